@@ -0,0 +1,1382 @@
+#![allow(dead_code)]
+
+use std::collections::{HashMap, HashSet};
+
+use common::{shape_util::ShapeUtil, util::human_readable_num_bytes};
+use hlo::{
+  hlo_buffer::HloBuffer,
+  hlo_instruction::{HloInstruction, InstructionId},
+  hlo_module::{ComputationId, HloModule},
+  hlo_opcode::HloOpcode,
+  hlo_value::{shape_index_to_string, HloValue}
+};
+use service::{
+  call_graph::{get_instruction_call_context, CallContext, CallGraph},
+  hlo_proto::{
+    BufferAllocationProto, BufferAllocationProtoAssigned, BufferAssignmentProto,
+    LogicalBufferProto
+  }
+};
+
+use crate::{
+  heap_simulator::{
+    BufferInterval, BufferIntervalCompare, ChooseBestHeapAlgorithm,
+    GlobalDecreasingSizeBestFitHeap, HeapAlgorithm, HeapSimulator
+  },
+  hlo_alias_analysis::HloAliasAnalysis,
+  hlo_ordering::HloOrdering
+};
+
+// Walks the HLO graph and assigns buffers to allocations.
+
+// This is the index of the allocation in the BufferAssignment.
+pub type BufferAllocationIndex = i64;
+
+// The memory space color of every allocation. Values are not colored by the
+// assigner, so all of them live in the default memory space.
+pub const DEFAULT_COLOR: i64 = 0;
+
+// Returns the size in bytes of the given value.
+pub type BufferSizeFunction = Box<dyn Fn(&HloValue) -> i64>;
+
+// The offset and size of a value within its allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OffsetSize {
+  pub offset: i64,
+  pub size: i64,
+}
+
+// A slice of a BufferAllocation, identified by the allocation index, an
+// offset into the allocation and a size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BufferAllocationSlice {
+  allocation: BufferAllocationIndex,
+  offset: i64,
+  size: i64,
+}
+
+impl BufferAllocationSlice {
+  pub fn new(allocation: BufferAllocationIndex, offset: i64, size: i64) -> Self {
+    BufferAllocationSlice { allocation: allocation, offset: offset, size: size }
+  }
+
+  pub fn index(&self) -> BufferAllocationIndex {
+    self.allocation
+  }
+
+  pub fn offset(&self) -> i64 {
+    self.offset
+  }
+
+  pub fn size(&self) -> i64 {
+    self.size
+  }
+
+  // Returns true if this slice shares any bytes with 'other'.
+  pub fn overlaps_with(&self, other: &BufferAllocationSlice) -> bool {
+    let end = self.offset + self.size;
+    let other_end = other.offset + other.size;
+    self.allocation == other.allocation &&
+      self.offset < other_end && end > other.offset
+  }
+
+  pub fn to_string(&self) -> String {
+    format!("{{index:{}, offset:{}, size:{}}}", self.allocation, self.offset, self.size)
+  }
+}
+
+// BufferAllocation represents a contiguous region of memory which is
+// assigned to one or more HloValues. Values may share an allocation if
+// their live ranges do not interfere, or if they are placed at disjoint
+// offsets by the heap simulator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferAllocation {
+  // The index of the allocation in the BufferAssignment.
+  index: BufferAllocationIndex,
+  // Size of the allocation in bytes.
+  size: i64,
+  // Whether this buffer needs to be thread-local.
+  is_thread_local: bool,
+  // Whether this allocation holds a tuple.
+  is_tuple: bool,
+  // Color of the allocation.
+  color: i64,
+  // Whether this allocation holds an entry computation parameter. Entry
+  // computation parameters are special because they have lifetimes which may
+  // outlast the computation.
+  is_entry_computation_parameter: bool,
+  // Whether this entry computation parameter is aliased with output.
+  is_parameter_aliased_with_output: bool,
+  // If this allocation holds an entry computation parameter, this field
+  // indicates the index (starting from 0) of the parameter.
+  parameter_number: i64,
+  // If this buffer is for an entry computation parameter, which subshape of
+  // the parameter is it for?
+  param_shape_index: Vec<i64>,
+  // Whether the allocation contains a value that may be live out of the
+  // module.
+  maybe_live_out: bool,
+  // See comment on the is_constant() accessor.
+  is_constant: bool,
+  // Mapping from the set of buffers assigned to this allocation to their
+  // offset and size, sorted by value id.
+  assigned_buffers: Vec<(HloValue, OffsetSize)>,
+  fragmentation_bytes: i64,
+  // The values live at the peak of the heap simulation of this allocation.
+  peak_buffers: Vec<HloValue>,
+}
+
+impl BufferAllocation {
+  pub fn new(index: BufferAllocationIndex, size: i64, color: i64) -> Self {
+    BufferAllocation {
+      index: index,
+      size: size,
+      is_thread_local: false,
+      is_tuple: false,
+      color: color,
+      is_entry_computation_parameter: false,
+      is_parameter_aliased_with_output: false,
+      parameter_number: 0,
+      param_shape_index: Vec::new(),
+      maybe_live_out: false,
+      is_constant: false,
+      assigned_buffers: Vec::new(),
+      fragmentation_bytes: 0,
+      peak_buffers: Vec::new()
+    }
+  }
+
+  // Returns the index of this allocation.
+  pub fn index(&self) -> BufferAllocationIndex {
+    self.index
+  }
+
+  // Returns the size of the allocation in bytes.
+  pub fn size(&self) -> i64 {
+    self.size
+  }
+
+  // Returns the color of the allocation.
+  pub fn color(&self) -> i64 {
+    self.color
+  }
+
+  // Whether this allocation is used in a parallel calling context such as
+  // inside of a map or reduce computation. Such allocations need to be
+  // thread local.
+  pub fn is_thread_local(&self) -> bool {
+    self.is_thread_local
+  }
+
+  pub fn set_is_thread_local(&mut self, is_thread_local: bool) {
+    self.is_thread_local = is_thread_local;
+  }
+
+  // Whether this allocation holds a tuple.
+  pub fn is_tuple(&self) -> bool {
+    self.is_tuple
+  }
+
+  pub fn set_is_tuple(&mut self, is_tuple: bool) {
+    self.is_tuple = is_tuple;
+  }
+
+  // Whether this allocation holds a LogicalBuffer from a parameter of the
+  // entry computation.
+  pub fn is_entry_computation_parameter(&self) -> bool {
+    self.is_entry_computation_parameter
+  }
+
+  pub fn is_parameter_aliased_with_output(&self) -> bool {
+    self.is_parameter_aliased_with_output
+  }
+
+  // If this allocation holds a buffer from a parameter of the entry
+  // computation, returns the parameter number.
+  pub fn parameter_number(&self) -> i64 {
+    assert!(self.is_entry_computation_parameter);
+    self.parameter_number
+  }
+
+  // If this allocation is for a parameter of the entry computation, returns
+  // the shape index of the parameter the buffer is for.
+  pub fn param_shape_index(&self) -> &Vec<i64> {
+    assert!(self.is_entry_computation_parameter);
+    &self.param_shape_index
+  }
+
+  pub fn set_entry_computation_parameter(
+    &mut self, parameter_number: i64, param_shape_index: Vec<i64>, parameter_aliased_with_output: bool)
+  {
+    self.is_entry_computation_parameter = true;
+    self.is_parameter_aliased_with_output = parameter_aliased_with_output;
+    self.parameter_number = parameter_number;
+    self.param_shape_index = param_shape_index;
+  }
+
+  // Whether this allocation holds a constant. The constant's value is known
+  // at compile time, so the backend may emit it into the executable instead
+  // of allocating memory for it at runtime.
+  pub fn is_constant(&self) -> bool {
+    self.is_constant
+  }
+
+  pub fn set_constant(&mut self, is_constant: bool) {
+    self.is_constant = is_constant;
+  }
+
+  // Returns whether this allocation may hold a value that is live out of the
+  // module.
+  pub fn maybe_live_out(&self) -> bool {
+    self.maybe_live_out
+  }
+
+  pub fn set_maybe_live_out(&mut self, value: bool) {
+    self.maybe_live_out = value;
+  }
+
+  // Returns true if the allocation is read only, i.e. it holds a constant or
+  // an entry parameter which is not aliased with the output.
+  pub fn is_readonly(&self) -> bool {
+    (self.is_entry_computation_parameter && !self.is_parameter_aliased_with_output) ||
+      self.is_constant
+  }
+
+  // Whether this allocation may be shared by buffers with disjoint live
+  // ranges.
+  pub fn is_reusable(&self) -> bool {
+    !self.is_thread_local && !self.is_tuple && !self.is_entry_computation_parameter &&
+      !self.is_constant
+  }
+
+  // Whether this allocation holds temporary values of the module, which the
+  // backend allocates itself before running the executable.
+  pub fn is_preallocated_temp_buffer(&self) -> bool {
+    !self.is_entry_computation_parameter && !self.maybe_live_out &&
+      !self.is_thread_local && !self.is_constant
+  }
+
+  // Access to the values assigned to this allocation, sorted by value id.
+  pub fn assigned_buffers(&self) -> &Vec<(HloValue, OffsetSize)> {
+    &self.assigned_buffers
+  }
+
+  // Adds a value to the set assigned to this allocation.
+  pub fn add_assignment(&mut self, value: &HloValue, offset: i64, size: i64) {
+    assert!(offset >= 0, "Offset must be non-negative.");
+    assert!(offset + size <= self.size,
+      "Value {} (offset {}, size {}) does not fit in allocation {} of size {}.",
+      value.to_short_string(), offset, size, self.index, self.size);
+    assert!(!self.assigned_buffers.iter().any(|(assigned, _)| assigned.id() == value.id()),
+      "Value {} is already assigned to allocation {}.", value.to_short_string(), self.index);
+    let position = self.assigned_buffers.iter()
+      .position(|(assigned, _)| assigned.id() > value.id())
+      .unwrap_or(self.assigned_buffers.len());
+    self.assigned_buffers.insert(
+      position, (value.clone(), OffsetSize { offset: offset, size: size }));
+  }
+
+  // Returns the slice of this allocation holding the given value.
+  pub fn get_slice(&self, value: &HloValue) -> BufferAllocationSlice {
+    let assigned = self.assigned_buffers.iter()
+      .find(|(assigned, _)| assigned.id() == value.id());
+    assert!(assigned.is_some(),
+      "Value {} is not assigned to allocation {}.", value.to_short_string(), self.index);
+    let offset_size = assigned.unwrap().1;
+    BufferAllocationSlice::new(self.index, offset_size.offset, offset_size.size)
+  }
+
+  // Returns the number of bytes of this allocation which are unused at the
+  // peak of its heap simulation.
+  pub fn fragmentation_bytes(&self) -> i64 {
+    self.fragmentation_bytes
+  }
+
+  pub fn set_fragmentation_bytes(&mut self, bytes: i64) {
+    self.fragmentation_bytes = bytes;
+  }
+
+  // Returns the values live at the point of peak memory usage of this
+  // allocation.
+  pub fn peak_buffers(&self) -> &Vec<HloValue> {
+    &self.peak_buffers
+  }
+
+  pub fn set_peak_buffers(&mut self, peak_buffers: Vec<HloValue>) {
+    self.peak_buffers = peak_buffers;
+  }
+
+  pub fn to_short_string(&self) -> String {
+    let mut out = format!("allocation {}: size {}", self.index, self.size);
+    if self.color != 0 {
+      out.push_str(&format!(", color {}", self.color));
+    }
+    if self.is_entry_computation_parameter {
+      let value = self.assigned_buffers.iter()
+        .find(|(value, _)| value.defining_instruction().opcode() == HloOpcode::Parameter);
+      out.push_str(&format!(", parameter {}", self.parameter_number));
+      if let Some((value, _)) = value {
+        out.push_str(&format!(", shape |{}|",
+          ShapeUtil::human_string(value.defining_instruction().shape())));
+      }
+      out.push_str(&format!(" at ShapeIndex {}", shape_index_to_string(&self.param_shape_index)));
+    }
+    if self.is_constant {
+      out.push_str(", constant");
+    }
+    if self.is_thread_local {
+      out.push_str(", thread-local");
+    }
+    if self.maybe_live_out {
+      out.push_str(", maybe-live-out");
+    }
+    if self.is_tuple {
+      out.push_str(", tuple");
+    }
+    if self.is_preallocated_temp_buffer() {
+      out.push_str(", preallocated-temp");
+    }
+    out.push_str(":\n");
+    out
+  }
+
+  pub fn to_string(&self) -> String {
+    let mut out = self.to_short_string();
+    for (value, offset_size) in &self.assigned_buffers {
+      out.push_str(&format!(" value: {} (size={},offset={}): {}\n",
+        value.to_short_string(), offset_size.size, offset_size.offset,
+        ShapeUtil::human_string(&value.shape())));
+    }
+    out
+  }
+
+  pub fn to_proto(&self) -> BufferAllocationProto {
+    let mut proto = BufferAllocationProto::new(self.index, self.size, self.color);
+    proto.set_is_thread_local(self.is_thread_local);
+    proto.set_is_tuple(self.is_tuple);
+    proto.set_is_entry_computation_parameter(self.is_entry_computation_parameter);
+    proto.set_is_constant(self.is_constant);
+    proto.set_parameter_number(self.parameter_number);
+    proto.set_parameter_shape_index(self.param_shape_index.clone());
+    proto.set_maybe_live_out(self.maybe_live_out);
+    for (value, offset_size) in &self.assigned_buffers {
+      proto.add_assigned(BufferAllocationProtoAssigned::new(
+        value.id(), offset_size.offset, offset_size.size));
+    }
+    proto
+  }
+}
+
+// Statistics about the allocations of a BufferAssignment.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BufferAssignmentStats {
+  pub parameter_allocation_count: i64,
+  pub parameter_allocation_bytes: i64,
+  pub constant_allocation_count: i64,
+  pub constant_allocation_bytes: i64,
+  pub maybe_live_out_allocation_count: i64,
+  pub maybe_live_out_allocation_bytes: i64,
+  pub preallocated_temp_allocation_count: i64,
+  pub preallocated_temp_allocation_bytes: i64,
+  // -1 if the fragmentation is unknown, i.e. the module is not scheduled.
+  pub preallocated_temp_fragmentation_bytes: i64,
+  pub total_allocation_count: i64,
+  pub total_allocation_bytes: i64,
+  // -1 if the fragmentation is unknown, i.e. the module is not scheduled.
+  pub total_fragmentation_bytes: i64,
+  // The number of bytes in use at the peak of the program, treating every
+  // allocation other than the heap-simulated temp allocations as live for
+  // the whole program.
+  pub peak_bytes: i64,
+}
+
+impl BufferAssignmentStats {
+  pub fn to_string(&self) -> String {
+    let percent = |bytes: i64| -> String {
+      if self.total_allocation_bytes == 0 {
+        return String::new();
+      }
+      format!(" ({:.2}%)", 100.0 * bytes as f64 / self.total_allocation_bytes as f64)
+    };
+    let mut out = "BufferAssignment stats:\n".to_string();
+    out.push_str(&format!("             parameter allocation: {:>10}\n",
+      human_readable_num_bytes(self.parameter_allocation_bytes)));
+    out.push_str(&format!("              constant allocation: {:>10}\n",
+      human_readable_num_bytes(self.constant_allocation_bytes)));
+    out.push_str(&format!("        maybe_live_out allocation: {:>10}\n",
+      human_readable_num_bytes(self.maybe_live_out_allocation_bytes)));
+    out.push_str(&format!("     preallocated temp allocation: {:>10}\n",
+      human_readable_num_bytes(self.preallocated_temp_allocation_bytes)));
+    if self.preallocated_temp_fragmentation_bytes >= 0 {
+      out.push_str(&format!("  preallocated temp fragmentation: {:>10}{}\n",
+        human_readable_num_bytes(self.preallocated_temp_fragmentation_bytes),
+        percent(self.preallocated_temp_fragmentation_bytes)));
+    }
+    out.push_str(&format!("                 total allocation: {:>10}\n",
+      human_readable_num_bytes(self.total_allocation_bytes)));
+    if self.total_fragmentation_bytes >= 0 {
+      out.push_str(&format!("              total fragmentation: {:>10}{}\n",
+        human_readable_num_bytes(self.total_fragmentation_bytes),
+        percent(self.total_fragmentation_bytes)));
+    }
+    out.push_str(&format!("                       peak bytes: {:>10}\n",
+      human_readable_num_bytes(self.peak_bytes)));
+    out
+  }
+}
+
+// This class encapsulates an assignment of the HloValues in an HLO module to
+// BufferAllocations.
+pub struct BufferAssignment<'module> {
+  module: &'module HloModule,
+  alias_analysis: HloAliasAnalysis<'module>,
+  hlo_ordering: HloOrdering<'module>,
+  // Function which returns the buffer size for a given value.
+  buffer_size: BufferSizeFunction,
+  // The set of allocations, indexed by BufferAllocationIndex.
+  allocations: Vec<BufferAllocation>,
+  // Maps a value id to the index of the allocation the value is assigned to.
+  allocation_index_for_value: HashMap<i64, BufferAllocationIndex>,
+  // The total size of all temporary buffers.
+  temp_allocation_total_size: i64,
+  stats: BufferAssignmentStats,
+}
+
+impl<'module> BufferAssignment<'module> {
+  fn new(
+    module: &'module HloModule,
+    alias_analysis: HloAliasAnalysis<'module>,
+    hlo_ordering: HloOrdering<'module>,
+    buffer_size: BufferSizeFunction) -> Self
+  {
+    BufferAssignment {
+      module: module,
+      alias_analysis: alias_analysis,
+      hlo_ordering: hlo_ordering,
+      buffer_size: buffer_size,
+      allocations: Vec::new(),
+      allocation_index_for_value: HashMap::new(),
+      temp_allocation_total_size: 0,
+      stats: BufferAssignmentStats::default()
+    }
+  }
+
+  // Returns the vector containing all buffer allocations in this assignment.
+  pub fn allocations(&self) -> &Vec<BufferAllocation> {
+    &self.allocations
+  }
+
+  // Returns the total size allocation holding all temporary buffers.
+  pub fn temp_allocation_total_size(&self) -> i64 {
+    self.temp_allocation_total_size
+  }
+
+  // Returns the allocation with the given index.
+  pub fn get_allocation(&self, index: BufferAllocationIndex) -> &BufferAllocation {
+    assert!(index >= 0 && (index as usize) < self.allocations.len());
+    &self.allocations[index as usize]
+  }
+
+  // Returns whether the given value has been assigned an allocation.
+  pub fn has_allocation(&self, value: &HloValue) -> bool {
+    self.allocation_index_for_value.contains_key(&value.id())
+  }
+
+  // Returns whether every value of the given buffer has been assigned an
+  // allocation.
+  pub fn has_allocation_for_buffer(&self, buffer: &HloBuffer) -> bool {
+    let has_allocation = self.has_allocation(&buffer.values()[0]);
+    debug_assert!(buffer.values().iter().all(|value| self.has_allocation(value) == has_allocation));
+    has_allocation
+  }
+
+  // Returns the allocation that the given value has been assigned to.
+  pub fn get_assigned_allocation(&self, value: &HloValue) -> &BufferAllocation {
+    let index = self.allocation_index_for_value.get(&value.id());
+    assert!(index.is_some(), "Value {} has no allocation.", value.to_short_string());
+    self.get_allocation(*index.unwrap())
+  }
+
+  // Returns all slices which may hold the values at the given position. The
+  // result is sorted and contains no duplicates.
+  pub fn get_all_slices(
+    &self, instruction: &HloInstruction, index: &Vec<i64>) -> Vec<BufferAllocationSlice>
+  {
+    let mut result = vec![];
+    let value_set = self.alias_analysis.dataflow_analysis().get_value_set(instruction, index);
+    for value in value_set.values() {
+      if !self.has_allocation(value) {
+        continue;
+      }
+      let slice = self.get_assigned_allocation(value).get_slice(value);
+      if !result.contains(&slice) {
+        result.push(slice);
+      }
+    }
+    result.sort();
+    result
+  }
+
+  // Returns whether an allocation has been assigned to the values at the
+  // given position.
+  pub fn has_allocation_at(&self, instruction: &HloInstruction, index: &Vec<i64>) -> bool {
+    let value_set = self.alias_analysis.dataflow_analysis().get_value_set(instruction, index);
+    value_set.values().iter().any(|value| self.has_allocation(value))
+  }
+
+  pub fn has_top_level_allocation(&self, instruction: &HloInstruction) -> bool {
+    self.has_allocation_at(instruction, &vec![])
+  }
+
+  // Convenience function which returns the unique slice containing the
+  // buffer at the given index of the given instruction. If a slice is not
+  // assigned or the slice cannot be determined at compile time then an
+  // error is returned.
+  pub fn get_unique_slice(
+    &self,
+    instruction: &HloInstruction,
+    index: &Vec<i64>) -> Result<BufferAllocationSlice, String>
+  {
+    let mut result: Option<BufferAllocationSlice> = None;
+    let value_set = self.alias_analysis.dataflow_analysis().get_value_set(instruction, index);
+    for value in value_set.values() {
+      if !self.has_allocation(value) {
+        continue;
+      }
+      let slice = self.get_assigned_allocation(value).get_slice(value);
+      if result.is_some() && result != Some(slice) {
+        return Err(format!(
+          "BufferAllocation::Slice for instruction {} at index {} cannot be determined at compile-time.",
+          instruction.name(), shape_index_to_string(index)));
+      }
+      result = Some(slice);
+    }
+    match result {
+      Some(slice) => Ok(slice),
+      None => Err(format!("BufferAllocation::Slice not assigned for instruction {} at index {}",
+        instruction.name(), shape_index_to_string(index)))
+    }
+  }
+
+  // Like get_unique_slice but fixes the index to the top-level of the shape
+  // (index = {}).
+  pub fn get_unique_top_level_slice(
+    &self, instruction: &HloInstruction) -> Result<BufferAllocationSlice, String>
+  {
+    self.get_unique_slice(instruction, &vec![])
+  }
+
+  // Returns true if the instruction 'a' at index 'a_index' and the
+  // instruction 'b' at index 'b_index' share the same slice.
+  pub fn shares_slice_at_index(
+    &self,
+    a: &HloInstruction,
+    a_index: &Vec<i64>,
+    b: &HloInstruction,
+    b_index: &Vec<i64>) -> bool
+  {
+    match (self.get_unique_slice(a, a_index), self.get_unique_slice(b, b_index)) {
+      (Ok(a_slice), Ok(b_slice)) => a_slice == b_slice,
+      _ => false
+    }
+  }
+
+  // Returns true if the top-level buffers of 'a' and 'b' share the same
+  // slice.
+  pub fn shares_top_level_slice(&self, a: &HloInstruction, b: &HloInstruction) -> bool {
+    self.shares_slice_at_index(a, &vec![], b, &vec![])
+  }
+
+  // Returns true if none of the slices of 'a' overlap with any of the slices
+  // of 'b'. Returns false if any index of either instruction has no slice.
+  pub fn have_disjoint_slices(&self, a: &HloInstruction, b: &HloInstruction) -> bool {
+    let dataflow = self.alias_analysis.dataflow_analysis();
+    let collect_slices = |instruction: &HloInstruction| -> Option<Vec<BufferAllocationSlice>> {
+      let value_set = dataflow.get_instruction_value_set(instruction)?;
+      let mut slices = vec![];
+      for node in value_set.nodes() {
+        let all_slices = self.get_all_slices(instruction, node.index());
+        if all_slices.is_empty() {
+          return None;
+        }
+        slices.extend(all_slices);
+      }
+      Some(slices)
+    };
+    let (a_slices, b_slices) = match (collect_slices(a), collect_slices(b)) {
+      (Some(a_slices), Some(b_slices)) => (a_slices, b_slices),
+      _ => return false
+    };
+    a_slices.iter().all(|a_slice| b_slices.iter().all(|b_slice| !a_slice.overlaps_with(b_slice)))
+  }
+
+  // Returns the underlying HloModule.
+  pub fn module(&self) -> &'module HloModule {
+    self.module
+  }
+
+  pub fn alias_analysis(&self) -> &HloAliasAnalysis<'module> {
+    &self.alias_analysis
+  }
+
+  pub fn hlo_ordering(&self) -> &HloOrdering<'module> {
+    &self.hlo_ordering
+  }
+
+  pub fn get_stats(&self) -> &BufferAssignmentStats {
+    &self.stats
+  }
+
+  pub fn to_string(&self) -> String {
+    let mut out = "BufferAssignment:\n".to_string();
+    let mut used_values = vec![];
+    for allocation in &self.allocations {
+      out.push_str(&allocation.to_string());
+      for (value, _) in allocation.assigned_buffers() {
+        used_values.push(value);
+      }
+    }
+    out.push_str("\nTotal bytes used: ");
+    out.push_str(&format!("{} ({})\n",
+      self.stats.total_allocation_bytes,
+      human_readable_num_bytes(self.stats.total_allocation_bytes)));
+    out.push_str("\nUsed values:\n");
+    used_values.sort_by_key(|value| value.id());
+    for value in used_values {
+      out.push_str(&value.to_string());
+    }
+    out
+  }
+
+  // Verbose string tailored to debugging OOMs, includes the positions of up
+  // to 'max_buffers_to_show' of the largest values in each allocation.
+  pub fn to_verbose_string(&self, max_buffers_to_show: usize) -> String {
+    let mut out = "BufferAssignment OOM Debugging.\n".to_string();
+    out.push_str(&self.stats.to_string());
+    let mut allocations: Vec<&BufferAllocation> = self.allocations.iter().collect();
+    allocations.sort_by(|a, b| b.size().cmp(&a.size()).then(a.index().cmp(&b.index())));
+    for allocation in allocations {
+      out.push_str(&allocation.to_short_string());
+      let mut values: Vec<&(HloValue, OffsetSize)> = allocation.assigned_buffers().iter().collect();
+      values.sort_by(|a, b| b.1.size.cmp(&a.1.size).then(a.0.id().cmp(&b.0.id())));
+      for (value, offset_size) in values.iter().take(max_buffers_to_show) {
+        out.push_str(&format!("\tValue: {}, offset {}, size {}\n",
+          value.to_short_string(), offset_size.offset,
+          human_readable_num_bytes(offset_size.size)));
+        for position in value.positions() {
+          out.push_str(&format!("\t\tPosition: {}\n", position.to_string()));
+        }
+      }
+      if values.len() > max_buffers_to_show {
+        out.push_str(&format!("\t... and {} more values.\n", values.len() - max_buffers_to_show));
+      }
+    }
+    out
+  }
+
+  // Returns the assigned values as a CSV table, one row per value.
+  pub fn buffer_info_string(&self) -> String {
+    let mut out =
+      "buffer_id,buffer_name,allocation,offset,size,num_uses,use_names\n".to_string();
+    let mut values: Vec<(&HloValue, BufferAllocationIndex, OffsetSize)> = vec![];
+    for allocation in &self.allocations {
+      for (value, offset_size) in allocation.assigned_buffers() {
+        values.push((value, allocation.index(), *offset_size));
+      }
+    }
+    values.sort_by_key(|(value, _, _)| value.id());
+    for (value, index, offset_size) in values {
+      let use_names: Vec<String> = value.get_uses().iter()
+        .map(|use_| use_.instruction.name())
+        .collect();
+      out.push_str(&format!("{},\"{}\",{},{},{},{},\"{}\"\n",
+        value.id(), value.to_short_string(), index, offset_size.offset,
+        offset_size.size, value.get_uses().len(), use_names.join(";")));
+    }
+    out
+  }
+
+  pub fn to_proto(&self) -> BufferAssignmentProto {
+    let mut proto = BufferAssignmentProto::new();
+    let mut values: Vec<&HloValue> = vec![];
+    for allocation in &self.allocations {
+      for (value, _) in allocation.assigned_buffers() {
+        values.push(value);
+      }
+    }
+    values.sort_by_key(|value| value.id());
+    for value in values {
+      proto.add_logical_buffers(LogicalBufferProto::new(
+        value.id(),
+        (self.buffer_size)(value),
+        value.defining_instruction().name(),
+        value.defining_index().clone(),
+        DEFAULT_COLOR));
+    }
+    for allocation in &self.allocations {
+      proto.add_buffer_allocations(allocation.to_proto());
+    }
+    proto
+  }
+
+  // Returns the size of the given buffer, the size of its largest value.
+  fn hlo_buffer_size(&self, buffer: &HloBuffer) -> i64 {
+    buffer.values().iter()
+      .map(|value| (self.buffer_size)(value))
+      .max()
+      .unwrap_or(0)
+  }
+
+  // Creates a new allocation with no values assigned to it.
+  fn new_empty_allocation(&mut self, size: i64, color: i64) -> BufferAllocationIndex {
+    let index = self.allocations.len() as BufferAllocationIndex;
+    self.allocations.push(BufferAllocation::new(index, size, color));
+    index
+  }
+
+  // Creates a new allocation of the given size and assigns every value of
+  // the buffer to it at offset 0.
+  fn new_allocation(&mut self, buffer_id: i64, size: i64) -> BufferAllocationIndex {
+    let values = self.alias_analysis.get_buffer(buffer_id).values().clone();
+    let index = self.new_empty_allocation(size, DEFAULT_COLOR);
+    for value in &values {
+      let value_size = (self.buffer_size)(value);
+      self.add_assignment(index, value, 0, value_size);
+    }
+    index
+  }
+
+  // Adds a value to the set assigned to the given allocation.
+  fn add_assignment(
+    &mut self, index: BufferAllocationIndex, value: &HloValue, offset: i64, size: i64)
+  {
+    assert!(!self.has_allocation(value),
+      "Value {} is already assigned to an allocation.", value.to_short_string());
+    let lives_out = self.alias_analysis.value_lives_out(value);
+    let allocation = &mut self.allocations[index as usize];
+    allocation.add_assignment(value, offset, size);
+    if lives_out {
+      allocation.set_maybe_live_out(true);
+    }
+    self.allocation_index_for_value.insert(value.id(), index);
+  }
+
+  fn compute_summary_stats(&mut self) {
+    let mut stats = BufferAssignmentStats::default();
+    let fragmentation_known = self.module.has_schedule();
+    for allocation in &self.allocations {
+      let size = allocation.size();
+      stats.total_allocation_count += 1;
+      stats.total_allocation_bytes += size;
+      if allocation.is_entry_computation_parameter() {
+        stats.parameter_allocation_count += 1;
+        stats.parameter_allocation_bytes += size;
+      }
+      if allocation.is_constant() {
+        stats.constant_allocation_count += 1;
+        stats.constant_allocation_bytes += size;
+      }
+      if allocation.maybe_live_out() {
+        stats.maybe_live_out_allocation_count += 1;
+        stats.maybe_live_out_allocation_bytes += size;
+      }
+      if allocation.is_preallocated_temp_buffer() {
+        stats.preallocated_temp_allocation_count += 1;
+        stats.preallocated_temp_allocation_bytes += size;
+        stats.preallocated_temp_fragmentation_bytes += allocation.fragmentation_bytes();
+      }
+      stats.total_fragmentation_bytes += allocation.fragmentation_bytes();
+      stats.peak_bytes += size - allocation.fragmentation_bytes();
+    }
+    if !fragmentation_known {
+      stats.preallocated_temp_fragmentation_bytes = -1;
+      stats.total_fragmentation_bytes = -1;
+    }
+    self.temp_allocation_total_size = stats.preallocated_temp_allocation_bytes;
+    self.stats = stats;
+  }
+}
+
+// A BufferAssigner assigns each HloValue of a module to a BufferAllocation.
+pub struct BufferAssigner {
+  // If true, allocate buffers for constant instructions.
+  allocate_buffers_for_constants: bool,
+  // The alignment of the offsets assigned by the heap simulator.
+  alignment: i64,
+}
+
+impl BufferAssigner {
+  // Builds and returns a BufferAssignment for the given module. The values
+  // of computations with a sequential order in 'hlo_ordering' are packed
+  // into a single temp allocation by simulating the heap over the flattened
+  // schedule of the module; the remaining values reuse existing allocations
+  // when their live ranges do not interfere.
+  pub fn run<'module>(
+    module: &'module HloModule,
+    hlo_ordering: HloOrdering<'module>,
+    buffer_size: BufferSizeFunction,
+    alignment: i64,
+    allocate_buffers_for_constants: bool) -> Result<BufferAssignment<'module>, String>
+  {
+    let assigner = BufferAssigner {
+      allocate_buffers_for_constants: allocate_buffers_for_constants,
+      alignment: alignment
+    };
+    assigner.create_assignment(module, hlo_ordering, buffer_size)
+  }
+
+  // Create a buffer assignment.
+  fn create_assignment<'module>(
+    &self,
+    module: &'module HloModule,
+    hlo_ordering: HloOrdering<'module>,
+    buffer_size: BufferSizeFunction) -> Result<BufferAssignment<'module>, String>
+  {
+    println!("BufferAssigner::create_assignment on module {}", module.name());
+    let alias_analysis = HloAliasAnalysis::run(module, None, &HashSet::new())?;
+    let (global_computations, thread_local_computations) =
+      gather_computations_by_allocation_type(module, hlo_ordering.call_graph())?;
+    let mut assignment =
+      BufferAssignment::new(module, alias_analysis, hlo_ordering, buffer_size);
+
+    // Collect the buffers defined in computations which need allocations,
+    // largest first. Values of fusion computations live in the fusion
+    // instruction's buffers and are not assigned separately.
+    let mut sorted_buffers = vec![];
+    for buffer in assignment.alias_analysis.buffers() {
+      let is_thread_local = match buffer_is_thread_local(
+        buffer, &global_computations, &thread_local_computations)?
+      {
+        Some(is_thread_local) => is_thread_local,
+        None => continue
+      };
+      let size = assignment.hlo_buffer_size(buffer);
+      sorted_buffers.push((size, buffer.id(), is_thread_local));
+    }
+    sorted_buffers.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let mut allocation_indices = vec![];
+    let mut buffers_to_assign_sequentially = vec![];
+    for (_, buffer_id, is_thread_local) in sorted_buffers {
+      self.assign_single_hlo_buffer(
+        buffer_id,
+        is_thread_local,
+        &mut buffers_to_assign_sequentially,
+        &mut allocation_indices,
+        &mut assignment);
+    }
+    if !buffers_to_assign_sequentially.is_empty() {
+      self.assign_buffers_with_sequential_ordering(
+        &buffers_to_assign_sequentially, &mut assignment)?;
+    }
+
+    assignment.compute_summary_stats();
+    Ok(assignment)
+  }
+
+  // Assigns a single HloBuffer to an allocation. Buffers of computations
+  // with a sequential order are deferred to 'buffers_to_assign_sequentially'
+  // for the heap simulation.
+  fn assign_single_hlo_buffer(
+    &self,
+    buffer_id: i64,
+    is_thread_local: bool,
+    buffers_to_assign_sequentially: &mut Vec<i64>,
+    allocation_indices: &mut Vec<BufferAllocationIndex>,
+    assignment: &mut BufferAssignment)
+  {
+    let buffer = assignment.alias_analysis.get_buffer(buffer_id).clone();
+    let buffer_size = assignment.hlo_buffer_size(&buffer);
+    for value in buffer.values() {
+      let instruction = value.defining_instruction();
+      if instruction.opcode() == HloOpcode::Constant {
+        if self.allocate_buffers_for_constants {
+          let index = assignment.new_allocation(buffer_id, buffer_size);
+          assignment.allocations[index as usize].set_constant(true);
+          println!("New allocation #{} for constant {}", index, buffer.to_string());
+        }
+        return;
+      }
+
+      let is_entry_parameter = instruction.opcode() == HloOpcode::Parameter &&
+        instruction.parent() == Some(assignment.module.entry_computation_id());
+      if is_entry_parameter {
+        let parameter_has_alias = assignment.module.input_output_alias_config()
+          .parameter_has_alias(instruction.parameter_number(), value.index());
+        // If the hlo buffer is part of an external parameter, creates a new
+        // allocation and sets its parameter number. Parameters of non-entry
+        // computations do not need special allocations because they live
+        // inside callers.
+        let index = assignment.new_allocation(buffer_id, buffer_size);
+        assignment.allocations[index as usize].set_entry_computation_parameter(
+          instruction.parameter_number(), value.index().clone(), parameter_has_alias);
+        println!("New allocation #{} marked as entry computation parameter: {}",
+          index, buffer.to_string());
+        return;
+      }
+    }
+
+    if is_thread_local {
+      let index = assignment.new_allocation(buffer_id, buffer_size);
+      assignment.allocations[index as usize].set_is_thread_local(true);
+      return;
+    }
+
+    if buffer.values().iter().any(|value| value.shape().is_tuple()) {
+      let index = assignment.new_allocation(buffer_id, buffer_size);
+      assignment.allocations[index as usize].set_is_tuple(true);
+      return;
+    }
+
+    // Find the smallest allocation which can be reused, iterating from the
+    // end of allocation_indices (smallest) to the beginning (largest).
+    for index in allocation_indices.iter().rev() {
+      if self.maybe_assign_buffer(*index, buffer_id, assignment) {
+        return;
+      }
+    }
+
+    if !assignment.alias_analysis.buffer_lives_out(&buffer) {
+      let all_computations_have_sequential_order = buffer.values().iter().all(|value| {
+        let computation = assignment.module
+          .computation(value.defining_instruction().parent().unwrap());
+        assignment.hlo_ordering.sequential_order(computation).is_some()
+      });
+      if all_computations_have_sequential_order {
+        // There is a sequential instruction ordering, so we delay assignment
+        // of temp buffers until the heap simulation. This is done right
+        // before creating a new allocation, to ensure the buffer re-use
+        // cases above are exhausted.
+        buffers_to_assign_sequentially.push(buffer_id);
+        return;
+      }
+    }
+
+    let index = assignment.new_allocation(buffer_id, buffer_size);
+    allocation_indices.push(index);
+  }
+
+  // Tries to assign the given buffer to the given allocation. Returns true
+  // if the buffer is assigned.
+  fn maybe_assign_buffer(
+    &self,
+    index: BufferAllocationIndex,
+    buffer_id: i64,
+    assignment: &mut BufferAssignment) -> bool
+  {
+    let buffer = assignment.alias_analysis.get_buffer(buffer_id);
+    let allocation = assignment.get_allocation(index);
+    if !allocation.is_reusable() {
+      return false;
+    }
+    let buffer_size = assignment.hlo_buffer_size(buffer);
+    if buffer_size > allocation.size() {
+      return false;
+    }
+    // Live out buffers must have an allocation of their exact size, so that
+    // the output of the module has the expected size.
+    if assignment.alias_analysis.buffer_lives_out(buffer) && buffer_size != allocation.size() {
+      return false;
+    }
+    let dataflow = assignment.alias_analysis.dataflow_analysis();
+    for (assigned, _) in allocation.assigned_buffers() {
+      for value in buffer.values() {
+        if assignment.hlo_ordering.may_interfere(assigned, value, dataflow) {
+          return false;
+        }
+      }
+    }
+
+    let values = buffer.values().clone();
+    for value in &values {
+      let value_size = (assignment.buffer_size)(value);
+      assignment.add_assignment(index, value, 0, value_size);
+    }
+    true
+  }
+
+  // Packs the given buffers into a single temp allocation by running the
+  // heap simulator over the flattened schedule of the module.
+  fn assign_buffers_with_sequential_ordering(
+    &self,
+    buffer_ids: &[i64],
+    assignment: &mut BufferAssignment) -> Result<(), String>
+  {
+    let module = assignment.module;
+    let mut schedule_times = HashMap::new();
+    let mut next_time = 0;
+    flatten_schedule(module, module.entry_computation_id(), &mut schedule_times, &mut next_time)?;
+    let time_of = |instruction: &HloInstruction| -> Result<i64, String> {
+      match schedule_times.get(&(instruction.parent().unwrap(), instruction.id())) {
+        Some(time) => Ok(*time),
+        None => Err(format!("Instruction {} is not in the flattened schedule of module {}.",
+          instruction.name(), module.name()))
+      }
+    };
+
+    let mut intervals = vec![];
+    for buffer_id in buffer_ids {
+      let buffer = assignment.alias_analysis.get_buffer(*buffer_id);
+      let mut start = i64::MAX;
+      let mut end = i64::MIN;
+      for value in buffer.values() {
+        start = start.min(time_of(value.defining_instruction())?);
+        for position in value.positions() {
+          end = end.max(time_of(&position.instruction)?);
+        }
+        for use_ in value.get_uses() {
+          end = end.max(time_of(&use_.instruction)?);
+        }
+      }
+      intervals.push(BufferInterval {
+        buffer: *buffer_id,
+        size: assignment.hlo_buffer_size(buffer),
+        start: start,
+        end: end.max(start)
+      });
+    }
+
+    let algorithms: Vec<Box<dyn HeapAlgorithm>> = vec![
+      Box::new(GlobalDecreasingSizeBestFitHeap::new(
+        self.alignment, BufferIntervalCompare::Spatial)),
+      Box::new(GlobalDecreasingSizeBestFitHeap::new(
+        self.alignment, BufferIntervalCompare::Temporal)),
+    ];
+    let result = HeapSimulator::run(
+      Box::new(ChooseBestHeapAlgorithm::new(algorithms)), &intervals);
+    if result.chunk_map.is_empty() {
+      return Ok(());
+    }
+
+    let index = assignment.new_empty_allocation(result.heap_size, DEFAULT_COLOR);
+    let mut chunks: Vec<(i64, i64)> = result.chunk_map.iter()
+      .map(|(buffer_id, chunk)| (*buffer_id, chunk.offset))
+      .collect();
+    chunks.sort();
+    for (buffer_id, offset) in chunks {
+      let values = assignment.alias_analysis.get_buffer(buffer_id).values().clone();
+      for value in &values {
+        let value_size = (assignment.buffer_size)(value);
+        assignment.add_assignment(index, value, offset, value_size);
+      }
+    }
+    let mut peak_buffers = vec![];
+    for buffer_id in &result.peak_buffers {
+      peak_buffers.extend(assignment.alias_analysis.get_buffer(*buffer_id).values().clone());
+    }
+    let allocation = &mut assignment.allocations[index as usize];
+    allocation.set_fragmentation_bytes(result.fragmentation_size);
+    allocation.set_peak_buffers(peak_buffers);
+    println!("Heap simulation assigned {} buffers to allocation #{} of size {}",
+      result.chunk_map.len(), index, result.heap_size);
+    Ok(())
+  }
+}
+
+// Partitions the non-fusion computations of the module into those whose
+// buffers are allocated globally (called in a sequential context) and those
+// whose buffers are thread-local (called in a parallel context).
+fn gather_computations_by_allocation_type(
+  module: &HloModule,
+  call_graph: &CallGraph) -> Result<(HashSet<ComputationId>, HashSet<ComputationId>), String>
+{
+  let mut global_computations = HashSet::new();
+  let mut thread_local_computations = HashSet::new();
+  for id in module.make_computation_post_order(&HashSet::new(), false) {
+    let computation = module.computation(id);
+    if computation.is_fusion_computation() {
+      continue;
+    }
+    match call_graph.get_node(id).context() {
+      CallContext::Embedded => { thread_local_computations.insert(id); },
+      CallContext::ControlFlow => { global_computations.insert(id); },
+      CallContext::Both => return Err(format!(
+        "Computation {} is called in both a parallel (eg, kMap) and sequential (eg, kCall) context",
+        computation.name())),
+      CallContext::None => continue
+    }
+  }
+  Ok((global_computations, thread_local_computations))
+}
+
+// Returns whether the values of the buffer are defined in thread-local
+// computations, or None if none of them is in a computation which needs
+// allocations. All the values must be of the same kind.
+fn buffer_is_thread_local(
+  buffer: &HloBuffer,
+  global_computations: &HashSet<ComputationId>,
+  thread_local_computations: &HashSet<ComputationId>) -> Result<Option<bool>, String>
+{
+  let mut result = None;
+  for value in buffer.values() {
+    let computation = value.defining_instruction().parent().unwrap();
+    let is_thread_local = if thread_local_computations.contains(&computation) {
+      true
+    } else if global_computations.contains(&computation) {
+      false
+    } else {
+      continue;
+    };
+    if result.is_some_and(|r| r != is_thread_local) {
+      return Err(format!(
+        "Buffer {} has values in both thread-local and global computations.",
+        buffer.to_string()));
+    }
+    result = Some(is_thread_local);
+  }
+  Ok(result)
+}
+
+// Assigns a logical time to every instruction of the given computation and
+// the computations it calls in a sequential context. Called computations
+// are flattened before their caller, so the live ranges of the caller's
+// operands cover the whole call.
+fn flatten_schedule(
+  module: &HloModule,
+  computation_id: ComputationId,
+  schedule_times: &mut HashMap<(ComputationId, InstructionId), i64>,
+  next_time: &mut i64) -> Result<(), String>
+{
+  let computation = module.computation(computation_id);
+  let sequence = match module.schedule().sequence(computation) {
+    Some(sequence) => sequence,
+    None => return Err(format!(
+      "Computation {} has no sequential order.", computation.name()))
+  };
+  for unique_id in sequence.ids() {
+    let id = match computation.instruction_with_unique_id(*unique_id) {
+      Some(id) => id,
+      None => return Err(format!(
+        "Instruction {} in the schedule of {} is not in the computation.",
+        unique_id, computation.name()))
+    };
+    let instruction = computation.instruction(id);
+    if get_instruction_call_context(&instruction.opcode()) == CallContext::ControlFlow {
+      for callee in instruction.called_computations() {
+        flatten_schedule(module, *callee, schedule_times, next_time)?;
+      }
+    }
+    schedule_times.insert((computation_id, instruction.id()), *next_time);
+    *next_time += 1;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use common::shape_util::ShapeUtil;
+  use crate::hlo_ordering::SequentialHloOrdering;
+  use service::test_utils::{find, parse_module};
+  use super::*;
+
+  fn buffer_size() -> BufferSizeFunction {
+    Box::new(|value: &HloValue| ShapeUtil::byte_size_of(&value.shape(), 8))
+  }
+
+  fn run_assigner(module: &HloModule, allocate_constants: bool) -> BufferAssignment<'_> {
+    let ordering = if module.has_schedule() {
+      SequentialHloOrdering::new(module).into_ordering()
+    } else {
+      HloOrdering::new(module)
+    };
+    BufferAssigner::run(module, ordering, buffer_size(), 1, allocate_constants).unwrap()
+  }
+
+  #[test]
+  fn test_parameters_and_live_out() {
+    let module = parse_module(r#"
+HloModule m
+
+ENTRY entry {
+  p0 = f32[4] parameter(0)
+  p1 = f32[4] parameter(1)
+  ROOT add = f32[4] add(p0, p1)
+}
+"#);
+    let assignment = run_assigner(&module, false);
+    assert_eq!(assignment.allocations().len(), 3);
+
+    let p1_slice = assignment.get_unique_top_level_slice(find(&module, "p1")).unwrap();
+    let p1_allocation = assignment.get_allocation(p1_slice.index());
+    assert!(p1_allocation.is_entry_computation_parameter());
+    assert_eq!(p1_allocation.parameter_number(), 1);
+    assert!(p1_allocation.is_readonly());
+    assert_eq!(p1_slice.size(), 16);
+
+    let add_slice = assignment.get_unique_top_level_slice(find(&module, "add")).unwrap();
+    let add_allocation = assignment.get_allocation(add_slice.index());
+    assert!(add_allocation.maybe_live_out());
+    assert!(!add_allocation.is_preallocated_temp_buffer());
+    assert!(assignment.have_disjoint_slices(find(&module, "p0"), find(&module, "add")));
+
+    let stats = assignment.get_stats();
+    assert_eq!(stats.parameter_allocation_bytes, 32);
+    assert_eq!(stats.maybe_live_out_allocation_bytes, 16);
+    assert_eq!(stats.total_fragmentation_bytes, -1);
+  }
+
+  #[test]
+  fn test_constants() {
+    let text = r#"
+HloModule m
+
+ENTRY entry {
+  p0 = f32[] parameter(0)
+  c = f32[] constant(1)
+  ROOT add = f32[] add(p0, c)
+}
+"#;
+    let module = parse_module(text);
+    let assignment = run_assigner(&module, false);
+    assert!(!assignment.has_top_level_allocation(find(&module, "c")));
+    assert_eq!(assignment.allocations().len(), 2);
+
+    let assignment = run_assigner(&module, true);
+    let slice = assignment.get_unique_top_level_slice(find(&module, "c")).unwrap();
+    assert!(assignment.get_allocation(slice.index()).is_constant());
+    assert_eq!(assignment.get_stats().constant_allocation_count, 1);
+  }
+
+  #[test]
+  fn test_heap_simulated_temps_share_memory() {
+    let module = parse_module(r#"
+HloModule m, is_scheduled=true
+
+ENTRY entry {
+  p0 = f32[4] parameter(0)
+  a = f32[4] negate(p0)
+  b = f32[4] exponential(a)
+  c = f32[4] negate(b)
+  ROOT d = f32[4] exponential(c)
+}
+"#);
+    let assignment = run_assigner(&module, false);
+    let (a, b, c) = (find(&module, "a"), find(&module, "b"), find(&module, "c"));
+    // 'a' is dead once 'b' is computed, so 'c' can reuse its memory.
+    assert!(assignment.shares_top_level_slice(a, c));
+    assert!(!assignment.shares_top_level_slice(a, b));
+    assert!(assignment.have_disjoint_slices(b, c));
+
+    let slice = assignment.get_unique_top_level_slice(b).unwrap();
+    let temp = assignment.get_allocation(slice.index());
+    assert!(temp.is_preallocated_temp_buffer());
+    assert_eq!(temp.size(), 32);
+    assert_eq!(temp.assigned_buffers().len(), 3);
+    assert_eq!(assignment.temp_allocation_total_size(), 32);
+
+    let stats = assignment.get_stats();
+    assert_eq!(stats.preallocated_temp_fragmentation_bytes, 0);
+    assert_eq!(stats.peak_bytes, stats.total_allocation_bytes);
+    assert!(stats.to_string().contains("preallocated temp allocation:        32B"));
+  }
+
+  #[test]
+  fn test_while_buffers_are_colocated() {
+    let module = parse_module(r#"
+HloModule m, is_scheduled=true
+
+cond {
+  cond_param = (f32[4], s32[]) parameter(0)
+  i = s32[] get-tuple-element(cond_param), index=1
+  limit = s32[] constant(10)
+  ROOT lt = pred[] compare(i, limit), direction=LT
+}
+
+body {
+  body_param = (f32[4], s32[]) parameter(0)
+  x = f32[4] get-tuple-element(body_param), index=0
+  i = s32[] get-tuple-element(body_param), index=1
+  one = s32[] constant(1)
+  next_i = s32[] add(i, one)
+  next_x = f32[4] negate(x)
+  ROOT body_root = (f32[4], s32[]) tuple(next_x, next_i)
+}
+
+ENTRY entry {
+  p0 = f32[4] parameter(0)
+  zero = s32[] constant(0)
+  init = (f32[4], s32[]) tuple(p0, zero)
+  loop = (f32[4], s32[]) while(init), condition=cond, body=body
+  ROOT result = f32[4] get-tuple-element(loop), index=0
+}
+"#);
+    let assignment = run_assigner(&module, true);
+    let (init, loop_, body_param, body_root) = (
+      find(&module, "init"), find(&module, "loop"),
+      find(&module, "body_param"), find(&module, "body_root"));
+    for instruction in [init, body_param, body_root] {
+      assert!(assignment.shares_slice_at_index(loop_, &vec![0], instruction, &vec![0]));
+      assert!(assignment.shares_slice_at_index(loop_, &vec![1], instruction, &vec![1]));
+    }
+    assert!(!assignment.shares_slice_at_index(loop_, &vec![0], loop_, &vec![1]));
+    let slice = assignment.get_unique_slice(loop_, &vec![0]).unwrap();
+    assert!(assignment.get_allocation(slice.index()).maybe_live_out());
+  }
+
+  #[test]
+  fn test_thread_local_and_dump() {
+    let module = parse_module(r#"
+HloModule m
+
+add {
+  x = f32[] parameter(0)
+  y = f32[] parameter(1)
+  ROOT sum = f32[] add(x, y)
+}
+
+ENTRY entry {
+  p0 = f32[8] parameter(0)
+  zero = f32[] constant(0)
+  ROOT reduce = f32[] reduce(p0, zero), dimensions={0}, to_apply=add
+}
+"#);
+    let assignment = run_assigner(&module, true);
+    let slice = assignment.get_unique_top_level_slice(find(&module, "sum")).unwrap();
+    assert!(assignment.get_allocation(slice.index()).is_thread_local());
+    assert!(slice.to_string().starts_with(&format!("{{index:{}, offset:0", slice.index())));
+
+    let proto = assignment.to_proto();
+    assert_eq!(proto.buffer_allocations().len(), assignment.allocations().len());
+    let values: usize = assignment.allocations().iter()
+      .map(|allocation| allocation.assigned_buffers().len())
+      .sum();
+    assert_eq!(proto.logical_buffers().len(), values);
+
+    let text = assignment.to_string();
+    assert!(text.starts_with("BufferAssignment:\nallocation 0: size 32, parameter 0"));
+    assert!(text.contains("thread-local"));
+    assert!(assignment.buffer_info_string().starts_with("buffer_id,buffer_name"));
+    assert!(assignment.to_verbose_string(1).contains("BufferAssignment stats:"));
+  }
+
+  #[test]
+  fn test_buffer_with_thread_local_and_global_values() {
+    let module = parse_module(r#"
+HloModule m
+
+add {
+  x = f32[] parameter(0)
+  y = f32[] parameter(1)
+  ROOT sum = f32[] add(x, y)
+}
+
+ENTRY entry {
+  p0 = f32[] parameter(0)
+  zero = f32[] constant(0)
+  ROOT reduce = f32[] reduce(p0, zero), dimensions={}, to_apply=add
+}
+"#);
+    let call_graph = CallGraph::build(&module, &HashSet::new());
+    let (global, thread_local) =
+      gather_computations_by_allocation_type(&module, &call_graph).unwrap();
+    let value = |id: i64, name: &str| HloValue::new(id, find(&module, name), &vec![], false);
+
+    let buffer = HloBuffer::new(0, vec![value(0, "sum"), value(1, "x")]);
+    assert_eq!(buffer_is_thread_local(&buffer, &global, &thread_local), Ok(Some(true)));
+    let buffer = HloBuffer::new(1, vec![value(2, "reduce")]);
+    assert_eq!(buffer_is_thread_local(&buffer, &global, &thread_local), Ok(Some(false)));
+    let buffer = HloBuffer::new(2, vec![value(3, "sum"), value(4, "reduce")]);
+    let err_msg = buffer_is_thread_local(&buffer, &global, &thread_local).err().unwrap();
+    assert!(err_msg.contains("both thread-local and global computations"));
+  }
+}
@@ -0,0 +1,410 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+
+// Identifies a buffer in the heap simulation.
+pub type BufferId = i64;
+
+// A chunk of memory in the heap, described by its offset and size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct HeapSimulatorChunk {
+  pub offset: i64,
+  pub size: i64,
+}
+
+impl HeapSimulatorChunk {
+  pub fn new(offset: i64, size: i64) -> Self {
+    HeapSimulatorChunk { offset: offset, size: size }
+  }
+
+  pub fn chunk_end(&self) -> i64 {
+    self.offset + self.size
+  }
+
+  pub fn overlaps_with(&self, other: &HeapSimulatorChunk) -> bool {
+    self.offset < other.chunk_end() && other.offset < self.chunk_end()
+  }
+
+  pub fn to_string(&self) -> String {
+    format!("[{},{})", self.offset, self.chunk_end())
+  }
+}
+
+// The result of a heap simulation.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HeapSimulatorResult {
+  // The assignment of buffers to chunks.
+  pub chunk_map: HashMap<BufferId, HeapSimulatorChunk>,
+  // The total size in bytes of the heap, containing all assigned chunks.
+  pub heap_size: i64,
+  // The difference between the heap size and the minimal heap size which
+  // could hold all buffers live at the same time.
+  pub fragmentation_size: i64,
+  // The buffers which are live when the heap reaches its peak usage, sorted
+  // by id.
+  pub peak_buffers: Vec<BufferId>,
+}
+
+// Abstract base for algorithms that implement the Alloc and Free events
+// simulated by the heap simulator.
+pub trait HeapAlgorithm {
+  // Alloc allocates a buffer of 'size' bytes.
+  fn alloc(&mut self, buffer: BufferId, size: i64);
+
+  // Free de-allocates a previously allocated buffer.
+  fn free(&mut self, buffer: BufferId, size: i64);
+
+  // Finish collects the buffer offset assignment results.
+  fn finish(&mut self) -> HeapSimulatorResult;
+}
+
+// A heap algorithm which does not assign offsets, but only tracks the
+// maximum number of bytes live at the same time. This is the lower bound of
+// the heap size of any algorithm.
+pub struct NoFragmentationStatsHeap {
+  current_heap_size: i64,
+  max_heap_size: i64,
+  live_buffers: HashMap<BufferId, i64>,
+  peak_buffers: Vec<BufferId>,
+}
+
+impl NoFragmentationStatsHeap {
+  pub fn new() -> Self {
+    NoFragmentationStatsHeap {
+      current_heap_size: 0,
+      max_heap_size: 0,
+      live_buffers: HashMap::new(),
+      peak_buffers: Vec::new()
+    }
+  }
+}
+
+impl HeapAlgorithm for NoFragmentationStatsHeap {
+  fn alloc(&mut self, buffer: BufferId, size: i64) {
+    self.current_heap_size += size;
+    self.live_buffers.insert(buffer, size);
+    if self.current_heap_size > self.max_heap_size {
+      self.max_heap_size = self.current_heap_size;
+      self.peak_buffers = self.live_buffers.keys().cloned().collect();
+      self.peak_buffers.sort();
+    }
+  }
+
+  fn free(&mut self, buffer: BufferId, size: i64) {
+    self.current_heap_size -= size;
+    self.live_buffers.remove(&buffer);
+  }
+
+  fn finish(&mut self) -> HeapSimulatorResult {
+    // Fragmentation is always zero, since no offsets are assigned.
+    HeapSimulatorResult {
+      chunk_map: HashMap::new(),
+      heap_size: self.max_heap_size,
+      fragmentation_size: 0,
+      peak_buffers: self.peak_buffers.clone()
+    }
+  }
+}
+
+// The live range and size of one buffer, in units of heap simulator events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferInterval {
+  pub buffer: BufferId,
+  pub size: i64,
+  pub start: i64,
+  pub end: i64,
+}
+
+// The order in which GlobalDecreasingSizeBestFitHeap assigns buffers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BufferIntervalCompare {
+  // Sort by size, larger buffers first. Ties are broken by live range,
+  // longer first.
+  Spatial,
+  // Sort by live range, longer first. Ties are broken by size, larger first.
+  Temporal,
+}
+
+// GlobalDecreasingSizeBestFitHeap collects the live intervals of all buffers,
+// then allocates them in decreasing spatial or temporal size regardless of
+// the alloc/free time. It internally tracks the allocated buffers and their
+// live intervals; when allocating a buffer, it finds the best-fit free chunk
+// during its live interval.
+pub struct GlobalDecreasingSizeBestFitHeap {
+  alignment: i64,
+  compare: BufferIntervalCompare,
+  current_time: i64,
+  buffer_intervals: HashMap<BufferId, BufferInterval>,
+}
+
+impl GlobalDecreasingSizeBestFitHeap {
+  pub fn new(alignment: i64, compare: BufferIntervalCompare) -> Self {
+    assert!(alignment > 0);
+    GlobalDecreasingSizeBestFitHeap {
+      alignment: alignment,
+      compare: compare,
+      current_time: 0,
+      buffer_intervals: HashMap::new()
+    }
+  }
+
+  // Returns the buffer intervals sorted in the order they are assigned.
+  fn sorted_buffer_intervals(&self) -> Vec<BufferInterval> {
+    let mut intervals: Vec<BufferInterval> =
+      self.buffer_intervals.values().cloned().collect();
+    for interval in &mut intervals {
+      if interval.end == -1 {
+        // The buffer is never freed, so it lives until the end.
+        interval.end = self.current_time;
+      }
+    }
+    let compare = self.compare;
+    intervals.sort_by(|a, b| {
+      let a_duration = a.end - a.start;
+      let b_duration = b.end - b.start;
+      let ordering = match compare {
+        BufferIntervalCompare::Spatial =>
+          b.size.cmp(&a.size).then(b_duration.cmp(&a_duration)),
+        BufferIntervalCompare::Temporal =>
+          b_duration.cmp(&a_duration).then(b.size.cmp(&a.size))
+      };
+      ordering.then(a.buffer.cmp(&b.buffer))
+    });
+    intervals
+  }
+
+  // Finds the best-fit chunk for 'interval' given the chunks already
+  // committed to buffers which are live at the same time.
+  fn find_chunk_candidate(
+    &self, interval: &BufferInterval, live_chunks: &mut [HeapSimulatorChunk]) -> HeapSimulatorChunk
+  {
+    live_chunks.sort_by_key(|chunk| chunk.offset);
+    let mut best: Option<HeapSimulatorChunk> = None;
+    let mut best_free_size = i64::MAX;
+    let mut offset = 0;
+    for chunk in live_chunks.iter() {
+      if chunk.offset > offset {
+        let free_size = chunk.offset - offset;
+        if free_size >= interval.size && free_size < best_free_size {
+          best = Some(HeapSimulatorChunk::new(offset, interval.size));
+          best_free_size = free_size;
+        }
+      }
+      offset = offset.max(align_up(chunk.chunk_end(), self.alignment));
+    }
+    // Fall back to the end of the live chunks.
+    best.unwrap_or(HeapSimulatorChunk::new(offset, interval.size))
+  }
+}
+
+impl HeapAlgorithm for GlobalDecreasingSizeBestFitHeap {
+  fn alloc(&mut self, buffer: BufferId, size: i64) {
+    let interval = BufferInterval {
+      buffer: buffer,
+      size: size,
+      start: self.current_time,
+      end: -1
+    };
+    self.buffer_intervals.insert(buffer, interval);
+    self.current_time += 1;
+  }
+
+  fn free(&mut self, buffer: BufferId, _size: i64) {
+    let interval = self.buffer_intervals.get_mut(&buffer);
+    assert!(interval.is_some(), "Buffer {} is freed before it is allocated.", buffer);
+    let interval = interval.unwrap();
+    assert_eq!(interval.end, -1, "Buffer {} is freed twice.", buffer);
+    interval.end = self.current_time;
+    self.current_time += 1;
+  }
+
+  fn finish(&mut self) -> HeapSimulatorResult {
+    let mut result = HeapSimulatorResult::default();
+    let mut committed: Vec<(BufferInterval, HeapSimulatorChunk)> = vec![];
+    for interval in self.sorted_buffer_intervals() {
+      let chunk = if interval.size == 0 {
+        HeapSimulatorChunk::new(0, 0)
+      } else {
+        let mut live_chunks: Vec<HeapSimulatorChunk> = committed.iter()
+          .filter(|(other, _)| other.start <= interval.end && interval.start <= other.end)
+          .map(|(_, chunk)| *chunk)
+          .collect();
+        self.find_chunk_candidate(&interval, &mut live_chunks)
+      };
+      result.heap_size = result.heap_size.max(chunk.chunk_end());
+      result.chunk_map.insert(interval.buffer, chunk);
+      committed.push((interval, chunk));
+    }
+    result
+  }
+}
+
+// A heap algorithm that chooses the best results from other algorithms added
+// to it.
+pub struct ChooseBestHeapAlgorithm {
+  algorithms: Vec<Box<dyn HeapAlgorithm>>,
+}
+
+impl ChooseBestHeapAlgorithm {
+  pub fn new(algorithms: Vec<Box<dyn HeapAlgorithm>>) -> Self {
+    ChooseBestHeapAlgorithm { algorithms: algorithms }
+  }
+}
+
+impl HeapAlgorithm for ChooseBestHeapAlgorithm {
+  fn alloc(&mut self, buffer: BufferId, size: i64) {
+    for algorithm in &mut self.algorithms {
+      algorithm.alloc(buffer, size);
+    }
+  }
+
+  fn free(&mut self, buffer: BufferId, size: i64) {
+    for algorithm in &mut self.algorithms {
+      algorithm.free(buffer, size);
+    }
+  }
+
+  fn finish(&mut self) -> HeapSimulatorResult {
+    assert!(!self.algorithms.is_empty());
+    let mut best: Option<HeapSimulatorResult> = None;
+    for algorithm in &mut self.algorithms {
+      let result = algorithm.finish();
+      if best.is_none() || result.heap_size < best.as_ref().unwrap().heap_size {
+        best = Some(result);
+      }
+    }
+    best.unwrap()
+  }
+}
+
+// HeapSimulator assigns buffer offsets by running a simulation of a regular
+// memory heap with Alloc and Free calls. The live range of every buffer is
+// given as an interval of logical time; at each time the buffers starting
+// at that time are allocated before the buffers ending at that time are
+// freed, so buffers whose live ranges touch never share memory.
+pub struct HeapSimulator {}
+
+impl HeapSimulator {
+  pub fn run(
+    mut algorithm: Box<dyn HeapAlgorithm>,
+    buffers: &[BufferInterval]) -> HeapSimulatorResult
+  {
+    let mut stats_heap = NoFragmentationStatsHeap::new();
+    let mut sorted: Vec<&BufferInterval> = buffers.iter().collect();
+    sorted.sort_by_key(|interval| (interval.start, interval.buffer));
+    let mut by_end: Vec<&BufferInterval> = buffers.iter().collect();
+    by_end.sort_by_key(|interval| (interval.end, interval.buffer));
+
+    let max_time = buffers.iter().map(|interval| interval.end).max().unwrap_or(-1);
+    let (mut next_alloc, mut next_free) = (0, 0);
+    for time in 0..=max_time {
+      while next_alloc < sorted.len() && sorted[next_alloc].start == time {
+        let interval = sorted[next_alloc];
+        algorithm.alloc(interval.buffer, interval.size);
+        stats_heap.alloc(interval.buffer, interval.size);
+        next_alloc += 1;
+      }
+      while next_free < by_end.len() && by_end[next_free].end == time {
+        let interval = by_end[next_free];
+        algorithm.free(interval.buffer, interval.size);
+        stats_heap.free(interval.buffer, interval.size);
+        next_free += 1;
+      }
+    }
+
+    let mut result = algorithm.finish();
+    let stats = stats_heap.finish();
+    result.fragmentation_size = result.heap_size - stats.heap_size;
+    result.peak_buffers = stats.peak_buffers;
+    result
+  }
+}
+
+fn align_up(value: i64, alignment: i64) -> i64 {
+  (value + alignment - 1) / alignment * alignment
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn interval(buffer: BufferId, size: i64, start: i64, end: i64) -> BufferInterval {
+    BufferInterval { buffer: buffer, size: size, start: start, end: end }
+  }
+
+  fn best_fit(compare: BufferIntervalCompare) -> Box<dyn HeapAlgorithm> {
+    Box::new(GlobalDecreasingSizeBestFitHeap::new(1, compare))
+  }
+
+  #[test]
+  fn test_no_fragmentation_stats() {
+    let mut heap = NoFragmentationStatsHeap::new();
+    heap.alloc(0, 10);
+    heap.alloc(1, 20);
+    heap.free(0, 10);
+    heap.alloc(2, 5);
+    heap.free(1, 20);
+    heap.free(2, 5);
+    let result = heap.finish();
+    assert_eq!(result.heap_size, 30);
+    assert_eq!(result.peak_buffers, vec![0, 1]);
+  }
+
+  #[test]
+  fn test_disjoint_buffers_share_memory() {
+    let buffers = vec![interval(0, 10, 0, 1), interval(1, 10, 2, 3)];
+    let result = HeapSimulator::run(best_fit(BufferIntervalCompare::Spatial), &buffers);
+    assert_eq!(result.heap_size, 10);
+    assert_eq!(result.chunk_map[&0], HeapSimulatorChunk::new(0, 10));
+    assert_eq!(result.chunk_map[&1], HeapSimulatorChunk::new(0, 10));
+    assert_eq!(result.fragmentation_size, 0);
+  }
+
+  #[test]
+  fn test_best_fit_picks_smallest_hole() {
+    // Decreasing size order places 0 at [0,50), 1 at [50,90), 2 at [90,120)
+    // and 3 at [120,140). Buffers 0 and 2 are freed early, leaving holes of
+    // 50 and 30 bytes between the long-lived buffers 1 and 3.
+    let buffers = vec![
+      interval(0, 50, 0, 2),
+      interval(1, 40, 0, 10),
+      interval(2, 30, 0, 2),
+      interval(3, 20, 0, 10),
+      interval(4, 15, 4, 6),
+    ];
+    let result = HeapSimulator::run(best_fit(BufferIntervalCompare::Spatial), &buffers);
+    // Buffer 4 goes into the smaller of the two holes.
+    assert_eq!(result.chunk_map[&4], HeapSimulatorChunk::new(90, 15));
+    assert_eq!(result.heap_size, 140);
+  }
+
+  #[test]
+  fn test_alignment() {
+    let buffers = vec![interval(0, 3, 0, 2), interval(1, 3, 1, 3)];
+    let heap = Box::new(GlobalDecreasingSizeBestFitHeap::new(8, BufferIntervalCompare::Spatial));
+    let result = HeapSimulator::run(heap, &buffers);
+    assert_eq!(result.chunk_map[&0].offset, 0);
+    assert_eq!(result.chunk_map[&1].offset, 8);
+    assert_eq!(result.heap_size, 11);
+  }
+
+  #[test]
+  fn test_choose_best_heap_algorithm() {
+    // Spatial order places the long-lived small buffers after the big one,
+    // temporal order packs them first; both fit within the same bound.
+    let buffers = vec![
+      interval(0, 10, 0, 9),
+      interval(1, 30, 2, 3),
+      interval(2, 30, 5, 6),
+      interval(3, 10, 0, 9),
+    ];
+    let algorithm = Box::new(ChooseBestHeapAlgorithm::new(vec![
+      best_fit(BufferIntervalCompare::Spatial),
+      best_fit(BufferIntervalCompare::Temporal),
+    ]));
+    let result = HeapSimulator::run(algorithm, &buffers);
+    assert_eq!(result.heap_size, 50);
+    assert_eq!(result.fragmentation_size, 0);
+    assert_eq!(result.peak_buffers, vec![0, 1, 3]);
+  }
+}
@@ -1,49 +1,121 @@
 #![allow(dead_code)]
 
 use std::collections::{HashMap, HashSet};
-use crate::hlo_dataflow_analysis::HloDataflowAnalysis;
+use crate::{
+  hlo_dataflow_analysis::{CanShareBuffer, HloDataflowAnalysis},
+  hlo_ordering::HloOrdering
+};
 
 use hlo::{
   hlo_buffer::HloBuffer,
   hlo_instruction::HloInstruction,
   hlo_module::HloModule,
-  hlo_value::HloValue
+  hlo_opcode::HloOpcode,
+  hlo_value::{shape_index_to_string, HloValue}
 };
 
+// Returns true if 'a' and 'b' are the same instruction of the module.
+fn is_same_instruction(a: &HloInstruction, b: &HloInstruction) -> bool {
+  a.parent() == b.parent() && a.id() == b.id()
+}
+
 // analysis which allocates HloBuffers to HloValues.
 pub struct HloAliasAnalysis<'module> {
   module: &'module HloModule,
-  live_out_buffers: HashSet<HloBuffer>,
+  // The set of buffers live out of the module, by buffer id.
+  live_out_buffers: HashSet<i64>,
+  // The underlying dataflow analysis used by this alias analysis.
   dataflow_analysis: HloDataflowAnalysis<'module>,
-  value_to_buffer: HashMap<HloValue, HloBuffer>,
+  // A map indicating which buffer a value is contained in, by value id.
+  value_to_buffer: HashMap<i64, i64>,
+  // A vector containing all HloBuffers sorted by HloBuffer::id.
   buffers: Vec<HloBuffer>,
 }
 
 impl<'module> HloAliasAnalysis<'module> {
-  pub fn new(_module: HloModule) -> Self {
-    /*
-    HloAliasAnalysis {
+  // The callgraph of the given HloModule must be flattened prior to running
+  // the analysis.
+  pub fn run(
+    module: &'module HloModule,
+    can_share_buffer: Option<CanShareBuffer>,
+    execution_threads: &HashSet<String>) -> Result<HloAliasAnalysis<'module>, String>
+  {
+    println!("HloAliasAnalysis::run on module {}", module.name());
+    let dataflow_analysis = HloDataflowAnalysis::run(
+      module, true, false, can_share_buffer, None, execution_threads)?;
+
+    let mut alias_analysis = HloAliasAnalysis {
       module: module,
       live_out_buffers: HashSet::new(),
-      dataflow_analysis: HloDataflowAnalysis::new(module, false, false, HashSet::new()),
+      dataflow_analysis: dataflow_analysis,
       value_to_buffer: HashMap::new(),
       buffers: Vec::new(),
+    };
+    alias_analysis.create_buffers();
+
+    for value in alias_analysis.dataflow_analysis.values() {
+      if value.live_out_of_module() {
+        let buffer_id = alias_analysis.value_to_buffer[&value.id()];
+        alias_analysis.live_out_buffers.insert(buffer_id);
+      }
     }
-    */
-    unimplemented!()
-  }
 
-  // The callgraph of the given HloModule must be flattened prior to running
-  // the analysis.
-  pub fn run(&self, _module: &HloModule) {}
+    alias_analysis.verify()?;
+    Ok(alias_analysis)
+  }
 
   pub fn to_string(&self) -> String {
-    unimplemented!()
+    let mut out = "HloAliasAnalysis, module ".to_string();
+    out.push_str(&self.module.name());
+    out.push('\n');
+
+    out.push_str("  Buffers at each position:\n");
+    for comp in self.module.computations() {
+      out.push_str("  ");
+      out.push_str(&comp.name());
+      out.push_str(":\n");
+      for inst in comp.instructions() {
+        let value_set = self.dataflow_analysis.get_instruction_value_set(inst);
+        if value_set.is_none() {
+          // The computation is not in the analyzed execution threads.
+          continue;
+        }
+        out.push_str("    ");
+        out.push_str(&inst.name());
+        out.push_str(":\n");
+        for node in value_set.unwrap().nodes() {
+          if inst.shape().is_tuple() {
+            out.push_str("      tuple index ");
+            out.push_str(&shape_index_to_string(node.index()));
+            out.push_str(":\n");
+          }
+          for buffer in self.compute_buffers_at(inst, node.index()) {
+            out.push_str("        ");
+            out.push_str(&buffer.to_string());
+            out.push('\n');
+          }
+        }
+      }
+    }
+
+    out.push_str("  Buffers:\n");
+    for buffer in self.buffers() {
+      out.push_str("    ");
+      out.push_str(&buffer.to_string());
+      out.push('\n');
+      out.push_str("      positions:\n");
+      for pos in buffer.compute_positions() {
+        out.push_str("        ");
+        out.push_str(&pos.to_string());
+        out.push('\n');
+      }
+    }
+    out
   }
 
   // Return the buffer containing the given value.
   pub fn get_buffer_containing_value(&self, value: &HloValue) -> Option<&HloBuffer> {
-    self.value_to_buffer.get(value)
+    self.value_to_buffer.get(&value.id()).map(|id| self.get_buffer(*id))
   }
 
   // Return the HloBuffer with the given id.
@@ -54,20 +126,31 @@ impl<'module> HloAliasAnalysis<'module> {
   // Returns the unique buffer at the given position.
   pub fn get_unique_buffer_at(
     &self,
-    _instruction: &HloInstruction,
-    _index: usize) -> &HloBuffer
+    instruction: &HloInstruction,
+    index: &Vec<i64>) -> &HloBuffer
   {
-    unimplemented!()
+    let buffers = self.compute_buffers_at(instruction, index);
+    assert_eq!(buffers.len(), 1,
+      "Expected a unique buffer at {} {}", instruction.name(), shape_index_to_string(index));
+    buffers[0]
   }
 
   // Compute the set of buffers at the given instruction and index and
   // return as a vector.
   pub fn compute_buffers_at(
     &self,
-    _instruction: &HloInstruction,
-    _index: usize) -> Vec<HloBuffer>
+    instruction: &HloInstruction,
+    index: &Vec<i64>) -> Vec<&HloBuffer>
   {
-    unimplemented!()
+    let mut buffers: Vec<&HloBuffer> = vec![];
+    for value in self.dataflow_analysis.get_value_set(instruction, index).values() {
+      let buffer = self.get_buffer_containing_value(value).unwrap();
+      if !buffers.iter().any(|b| b.id() == buffer.id()) {
+        buffers.push(buffer);
+      }
+    }
+    buffers.sort_by_key(|buffer| buffer.id());
+    buffers
   }
 
   // Return a vector of all HloBuffers stabily sorted by HloBuffer::id.
@@ -76,23 +159,451 @@ impl<'module> HloAliasAnalysis<'module> {
   }
 
   // Returns the underlying dataflow analysis used by this alias analysis.
-  pub fn dataflow_analysis(&self) -> &HloDataflowAnalysis {
+  pub fn dataflow_analysis(&self) -> &HloDataflowAnalysis<'module> {
     &self.dataflow_analysis
   }
 
-  // Returns true if a buffe out of the module.
+  // Returns true if a buffer lives out of the module.
   pub fn buffer_lives_out(&self, buffer: &HloBuffer) -> bool {
-    self.live_out_buffers.contains(buffer)
+    self.live_out_buffers.contains(&buffer.id())
   }
 
   // Returns true if a hlo value lives out of the module.
   pub fn value_lives_out(&self, value: &HloValue) -> bool {
     let buffer = self.get_buffer_containing_value(value);
     debug_assert!(buffer.is_some());
-    self.live_out_buffers.contains(buffer.unwrap())
+    self.buffer_lives_out(buffer.unwrap())
+  }
+
+  // Returns the buffers which live out of the module, sorted by id.
+  pub fn live_out_buffers(&self) -> Vec<&HloBuffer> {
+    let mut ids: Vec<i64> = self.live_out_buffers.iter().cloned().collect();
+    ids.sort();
+    ids.into_iter().map(|id| self.get_buffer(id)).collect()
+  }
+
+  // Returns true if any index in the output of the given instruction has more
+  // than one buffer. That is, ComputeBuffersAt returns a vector with more than
+  // one element.
+  pub fn instruction_buffers_are_ambiguous(&self, instruction: &HloInstruction) -> bool {
+    let value_set = self.dataflow_analysis.get_instruction_value_set(instruction).unwrap();
+    for node in value_set.nodes() {
+      let mut buffer_id = None;
+      for value in node.value().values() {
+        let id = self.value_to_buffer[&value.id()];
+        if buffer_id.is_none() {
+          buffer_id = Some(id);
+        } else if buffer_id != Some(id) {
+          return true;
+        }
+      }
+    }
+    false
+  }
+
+  // Returns true if any HLO values in the module belonging to the same buffer
+  // have interfering live ranges assuming the given ordering.
+  pub fn has_live_range_interference(&self, ordering: &HloOrdering) -> bool {
+    for buffer in self.buffers() {
+      assert!(!buffer.values().is_empty());
+      if buffer.values()[0].shape().is_token() {
+        // Tokens have no on-device representation and cannot interfere.
+        continue;
+      }
+
+      // Check that the values in the buffer are totally ordered with respect to
+      // 'ordering'. Begin by sorting the values with respect to 'ordering'.
+      let mut values = buffer.values().clone();
+      values.sort_by(|a, b| {
+        if ordering.is_defined_before(a, b) {
+          std::cmp::Ordering::Less
+        } else if ordering.is_defined_before(b, a) {
+          std::cmp::Ordering::Greater
+        } else {
+          std::cmp::Ordering::Equal
+        }
+      });
+
+      // Walk through the ordered vector of values. First verify that the values
+      // are totally ordered with respect to 'ordering', then check that no
+      // adjacent values have overlapping live ranges. Only adjacent values must
+      // be checked because of the property of live range interference. For
+      // example, if you have values A, B, and C (in program order) contained in
+      // a buffer and A interferes with C, then necessarily A also interferes
+      // with B. So to check interference you only need to check interference
+      // between A and B, and between B and C.
+      for i in 1..values.len() {
+        if !ordering.is_defined_before(&values[i - 1], &values[i]) {
+          println!("{} and {} are not ordered",
+            values[i - 1].to_short_string(), values[i].to_short_string());
+          return true;
+        }
+      }
+      for i in 1..values.len() {
+        if ordering.may_interfere(&values[i - 1], &values[i], &self.dataflow_analysis) {
+          println!("In buffer {} containing values: {}, value {} may interfere with {}",
+            buffer.id(),
+            values.iter().map(|v| v.to_short_string()).collect::<Vec<_>>().join(", "),
+            values[i - 1].to_short_string(), values[i].to_short_string());
+          return true;
+        }
+      }
+    }
+    false
+  }
+
+  // Groups the values of the dataflow analysis which must share storage, and
+  // creates one buffer per group.
+  fn create_buffers(&mut self) {
+    let values = self.dataflow_analysis.values();
+    let position_of: HashMap<i64, usize> = values.iter().enumerate()
+      .map(|(i, value)| (value.id(), i))
+      .collect();
+
+    // Union-find over the positions of the values in 'values'.
+    let mut parent: Vec<usize> = (0..values.len()).collect();
+    fn find(parent: &mut [usize], i: usize) -> usize {
+      let mut root = i;
+      while parent[root] != root {
+        root = parent[root];
+      }
+      let mut current = i;
+      while parent[current] != root {
+        let next = parent[current];
+        parent[current] = root;
+        current = next;
+      }
+      root
+    }
+
+    for (i, value) in values.iter().enumerate() {
+      for aliased_id in self.compute_aliased_values(value) {
+        let a = find(&mut parent, i);
+        let b = find(&mut parent, position_of[&aliased_id]);
+        if a != b {
+          // Keep the root with the smallest position so that buffers are
+          // created in order of their first value.
+          parent[a.max(b)] = a.min(b);
+        }
+      }
+    }
+
+    // Values are sorted by id, so both the buffers and the values within each
+    // buffer end up sorted by id.
+    let mut root_to_buffer: HashMap<usize, usize> = HashMap::new();
+    let mut buffer_values: Vec<Vec<HloValue>> = vec![];
+    for (i, value) in values.iter().enumerate() {
+      let root = find(&mut parent, i);
+      let buffer_index = *root_to_buffer.entry(root).or_insert_with(|| {
+        buffer_values.push(vec![]);
+        buffer_values.len() - 1
+      });
+      buffer_values[buffer_index].push(value.clone());
+    }
+
+    let mut buffers = vec![];
+    let mut value_to_buffer = HashMap::new();
+    for (id, values) in buffer_values.into_iter().enumerate() {
+      for value in &values {
+        value_to_buffer.insert(value.id(), id as i64);
+      }
+      buffers.push(HloBuffer::new(id as i64, values));
+    }
+    self.buffers = buffers;
+    self.value_to_buffer = value_to_buffer;
+  }
+
+  // Returns the ids of the values which must be in the same buffer as 'value'.
+  fn compute_aliased_values(&self, value: &HloValue) -> Vec<i64> {
+    let mut aliased_values = vec![];
+    self.compute_input_output_aliased_values(value, &mut aliased_values);
+    self.compute_while_aliased_values(value, &mut aliased_values);
+    self.compute_conditional_aliased_values(value, &mut aliased_values);
+    self.compute_in_place_operation_aliased_values(value, &mut aliased_values);
+    aliased_values
+  }
+
+  // Values which appear in the entry root at an index aliased with an entry
+  // parameter share the buffer of that parameter.
+  fn compute_input_output_aliased_values(&self, value: &HloValue, aliased_values: &mut Vec<i64>) {
+    let entry = self.module.entry_computation().unwrap();
+    let io_alias_config = self.module.input_output_alias_config();
+    for pos in value.positions() {
+      if !is_same_instruction(&pos.instruction, entry.root_instruction()) {
+        continue;
+      }
+      if let Some(aliased_input) = io_alias_config.get_aliased_parameter(&pos.index) {
+        let parameter = entry
+          .parameter_instruction(aliased_input.parameter_number() as usize).unwrap();
+        aliased_values.push(self.dataflow_analysis.get_unique_value_at(
+          parameter, aliased_input.parameter_index()).id());
+      }
+    }
+  }
+
+  fn compute_while_aliased_values(&self, value: &HloValue, aliased_values: &mut Vec<i64>) {
+    // Value is init of a while (use is while).
+    for use_ in value.get_uses() {
+      if use_.instruction.opcode() == HloOpcode::While {
+        // Determine the while value that this shares a buffer with.
+        aliased_values.push(self.dataflow_analysis.get_unique_value_at(
+          &use_.instruction, &use_.operand_index_vec).id());
+      }
+    }
+
+    // Value is a parameter or root of a while body.
+    for pos in value.positions() {
+      let computation = pos.instruction.parent().unwrap();
+      if !self.module.computation(computation).is_root(pos.instruction.id()) {
+        continue;
+      }
+      let call_graph_node = self.dataflow_analysis.call_graph().get_node(computation);
+      for callsite in call_graph_node.caller_callsites() {
+        let caller = self.module.instruction(callsite.computation(), callsite.instruction());
+        if caller.opcode() == HloOpcode::While {
+          // Call graph must have been flattened.
+          assert_eq!(call_graph_node.caller_callsites().len(), 1);
+          aliased_values.push(self.dataflow_analysis.get_unique_value_at(
+            caller, &pos.index).id());
+        }
+      }
+    }
+
+    // Value is the parameter of a while body or condition.
+    let defining_instruction = value.defining_instruction();
+    if defining_instruction.opcode() == HloOpcode::Parameter {
+      let computation = defining_instruction.parent().unwrap();
+      let call_graph_node = self.dataflow_analysis.call_graph().get_node(computation);
+      for callsite in call_graph_node.caller_callsites() {
+        let caller = self.module.instruction(callsite.computation(), callsite.instruction());
+        if caller.opcode() == HloOpcode::While {
+          // Call graph must have been flattened.
+          assert_eq!(call_graph_node.caller_callsites().len(), 1);
+          aliased_values.push(self.dataflow_analysis.get_unique_value_at(
+            caller, value.defining_index()).id());
+        }
+      }
+    }
+  }
+
+  fn compute_conditional_aliased_values(&self, value: &HloValue, aliased_values: &mut Vec<i64>) {
+    // Value is the root of a conditional branch, which shares a buffer with the
+    // output of the conditional.
+    for pos in value.positions() {
+      let computation = pos.instruction.parent().unwrap();
+      if !self.module.computation(computation).is_root(pos.instruction.id()) {
+        continue;
+      }
+      let call_graph_node = self.dataflow_analysis.call_graph().get_node(computation);
+      for callsite in call_graph_node.caller_callsites() {
+        let caller = self.module.instruction(callsite.computation(), callsite.instruction());
+        if caller.opcode() == HloOpcode::Conditional {
+          // Call graph must have been flattened.
+          assert_eq!(call_graph_node.caller_callsites().len(), 1);
+          aliased_values.push(self.dataflow_analysis.get_unique_value_at(
+            caller, &pos.index).id());
+        }
+      }
+    }
+  }
+
+  fn compute_in_place_operation_aliased_values(
+    &self, value: &HloValue, aliased_values: &mut Vec<i64>)
+  {
+    // The output of an in-place operation shares a buffer with its input.
+    for pos in value.positions() {
+      let computation = self.module.computation(pos.instruction.parent().unwrap());
+      let instruction = computation.instruction(pos.instruction.id());
+      for (operand_index, output_index) in
+        HloDataflowAnalysis::get_in_place_input_output_pairs(computation, instruction)
+      {
+        if pos.index == output_index {
+          let operand = computation.operand(
+            instruction.id(), operand_index.operand_number as usize);
+          aliased_values.push(self.dataflow_analysis.get_unique_value_at(
+            operand, &operand_index.operand_index).id());
+        }
+      }
+    }
+
+    // The input of an in-place operation shares a buffer with its output.
+    for use_ in value.get_uses() {
+      let computation = self.module.computation(use_.instruction.parent().unwrap());
+      let user = computation.instruction(use_.instruction.id());
+      for (operand_index, output_index) in
+        HloDataflowAnalysis::get_in_place_input_output_pairs(computation, user)
+      {
+        if use_.operand_number == operand_index.operand_number &&
+          use_.operand_index_vec == operand_index.operand_index
+        {
+          aliased_values.push(self.dataflow_analysis.get_unique_value_at(
+            user, &output_index).id());
+        }
+      }
+    }
   }
 
-  pub fn lives_out_buffers() {}
+  // Verifies internal state of the analysis.
+  fn verify(&self) -> Result<(), String> {
+    // Verify consistency between the value_to_buffer map and the buffers.
+    for (value_id, buffer_id) in &self.value_to_buffer {
+      let buffer = self.get_buffer(*buffer_id);
+      if !buffer.values().iter().any(|value| value.id() == *value_id) {
+        return Err(format!("Buffer {} does not contain value {}", buffer_id, value_id));
+      }
+    }
 
-  fn verify() {}
-}
\ No newline at end of file
+    for (i, buffer) in self.buffers().iter().enumerate() {
+      if buffer.id() != i as i64 {
+        return Err(format!("Buffer {} is stored at position {}", buffer.id(), i));
+      }
+      let mut last_value_id = -1;
+      for value in buffer.values() {
+        if value.id() <= last_value_id {
+          return Err(format!("Values of buffer {} are not sorted by id", buffer.id()));
+        }
+        last_value_id = value.id();
+        if self.value_to_buffer.get(&value.id()) != Some(&buffer.id()) {
+          return Err(format!("Value {} is not mapped to buffer {}",
+            value.to_short_string(), buffer.id()));
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use service::test_utils::{find_in, parse_module};
+  use super::*;
+
+  fn run_analysis(module: &HloModule) -> HloAliasAnalysis<'_> {
+    HloAliasAnalysis::run(module, None, &HashSet::new()).unwrap()
+  }
+
+  #[test]
+  fn test_tuple_does_not_merge_buffers() {
+    let text = "
+      HloModule m
+
+      ENTRY entry {
+        p0 = f32[4] parameter(0)
+        p1 = f32[4] parameter(1)
+        ROOT tuple = (f32[4], f32[4]) tuple(p0, p1)
+      }";
+    let module = parse_module(text);
+    let analysis = run_analysis(&module);
+    let p0 = find_in(&module, "entry", "p0");
+    let tuple = find_in(&module, "entry", "tuple");
+
+    assert_eq!(analysis.buffers().len(), 3);
+    assert_eq!(analysis.get_unique_buffer_at(tuple, &vec![0]).id(),
+      analysis.get_unique_buffer_at(p0, &vec![]).id());
+    assert!(analysis.buffer_lives_out(analysis.get_unique_buffer_at(p0, &vec![])));
+    assert_eq!(analysis.live_out_buffers().len(), 3);
+  }
+
+  #[test]
+  fn test_while_loop_shares_buffers() {
+    let text = "
+      HloModule m
+
+      body {
+        param = (f32[], f32[]) parameter(0)
+        gte0 = f32[] get-tuple-element(param), index=0
+        gte1 = f32[] get-tuple-element(param), index=1
+        add = f32[] add(gte0, gte1)
+        ROOT tuple = (f32[], f32[]) tuple(gte0, add)
+      }
+
+      cond {
+        param = (f32[], f32[]) parameter(0)
+        ROOT c = pred[] constant(false)
+      }
+
+      ENTRY entry {
+        c0 = f32[] constant(1)
+        c1 = f32[] constant(2)
+        init = (f32[], f32[]) tuple(c0, c1)
+        ROOT while = (f32[], f32[]) while(init), condition=cond, body=body
+      }";
+    let module = parse_module(text);
+    let analysis = run_analysis(&module);
+    let while_inst = find_in(&module, "entry", "while");
+    let buffer = analysis.get_unique_buffer_at(while_inst, &vec![1]);
+
+    // The loop-carried element holds the init value, the phis at the while
+    // and the body parameter, and the value computed by the body.
+    for (computation, name) in [("entry", "c1"), ("body", "add"), ("body", "gte1")] {
+      let instruction = find_in(&module, computation, name);
+      assert_eq!(analysis.get_unique_buffer_at(instruction, &vec![]).id(), buffer.id());
+    }
+    // The loop state tuples share one buffer as well.
+    assert_eq!(analysis.get_unique_buffer_at(find_in(&module, "body", "tuple"), &vec![]).id(),
+      analysis.get_unique_buffer_at(while_inst, &vec![]).id());
+    assert!(!analysis.instruction_buffers_are_ambiguous(while_inst));
+  }
+
+  #[test]
+  fn test_conditional_shares_buffers() {
+    let text = "
+      HloModule m
+
+      true_branch {
+        p = f32[] parameter(0)
+        ROOT neg = f32[] negate(p)
+      }
+
+      false_branch {
+        p = f32[] parameter(0)
+        ROOT exp = f32[] exponential(p)
+      }
+
+      ENTRY entry {
+        p0 = pred[] parameter(0)
+        x = f32[] parameter(1)
+        ROOT cond = f32[] conditional(p0, x, x), true_computation=true_branch, false_computation=false_branch
+      }";
+    let module = parse_module(text);
+    let analysis = run_analysis(&module);
+    let buffer = analysis.get_unique_buffer_at(find_in(&module, "entry", "cond"), &vec![]);
+    assert_eq!(analysis.get_unique_buffer_at(
+      find_in(&module, "true_branch", "neg"), &vec![]).id(), buffer.id());
+    assert_eq!(analysis.get_unique_buffer_at(
+      find_in(&module, "false_branch", "exp"), &vec![]).id(), buffer.id());
+    assert_ne!(analysis.get_unique_buffer_at(
+      find_in(&module, "entry", "x"), &vec![]).id(), buffer.id());
+  }
+
+  #[test]
+  fn test_in_place_and_input_output_aliasing() {
+    let text = "
+      HloModule m, input_output_alias={ {}: (1, {}, must-alias) }
+
+      ENTRY entry {
+        p0 = f32[8] parameter(0)
+        p1 = f32[8] parameter(1)
+        update = f32[2] parameter(2)
+        index = s32[] parameter(3)
+        add = f32[8] add(p0, p0)
+        ROOT dus = f32[8] dynamic-update-slice(add, update, index)
+      }";
+    let module = parse_module(text);
+    let analysis = run_analysis(&module);
+    let buffer = analysis.get_unique_buffer_at(find_in(&module, "entry", "dus"), &vec![]);
+    // The dynamic-update-slice updates 'add' in place, and the output is
+    // aliased with parameter 1.
+    assert_eq!(analysis.get_unique_buffer_at(
+      find_in(&module, "entry", "add"), &vec![]).id(), buffer.id());
+    assert_eq!(analysis.get_unique_buffer_at(
+      find_in(&module, "entry", "p1"), &vec![]).id(), buffer.id());
+    assert_ne!(analysis.get_unique_buffer_at(
+      find_in(&module, "entry", "p0"), &vec![]).id(), buffer.id());
+    assert_eq!(buffer.values().len(), 3);
+
+    let out = analysis.to_string();
+    assert!(out.starts_with("HloAliasAnalysis, module m\n"));
+    assert!(out.contains("  Buffers:\n"));
+  }
+}
@@ -1,13 +1,16 @@
 #![allow(dead_code)]
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use common::{shape::ShapeEqual, shape_util::ShapeUtil};
 use hlo::{
-  hlo_instruction::HloInstruction,
-  hlo_module::HloModule,
+  hlo_computation::HloComputation,
+  hlo_instruction::{HloInstruction, InstructionId},
+  hlo_module::{ComputationId, HloModule},
   hlo_opcode::HloOpcode,
-  hlo_value::{HloPosition, HloUse, HloValue, HloValueSet, InstructionValueSet}
+  hlo_value::{
+    shape_index_to_string, HloPosition, HloUse, HloValue, HloValueSet, InstructionValueSet
+  }
 };
 
 use service::{
@@ -35,9 +38,36 @@ impl HloOperandIndex {
   }
 }
 
+// The operand value an instruction forwards to one of its outputs.
+#[derive(Debug, Clone, PartialEq)]
 pub struct ForwardedOperand {
-  operand_number: i64,
-  operand_index: usize,
+  pub operand_number: i64,
+  pub operand_index: Vec<i64>,
+}
+
+// Backend hook which decides whether 'user' (first) at 'user_index' can share
+// a buffer with its operand 'operand' (second). None falls back to the
+// default rules.
+pub type CanShareBuffer =
+  Box<dyn Fn(&HloInstruction, &HloInstruction, &Vec<i64>) -> Option<bool>>;
+
+// Backend hook which returns the operand value an instruction forwards to
+// the given index of its output, if any. Such an instruction does not define
+// a value at that index.
+pub type ForwardsValue =
+  Box<dyn Fn(&HloInstruction, &Vec<i64>) -> Option<ForwardedOperand>>;
+
+// Identifies an instruction of the module by its computation and its id in
+// that computation.
+type InstructionKey = (ComputationId, InstructionId);
+
+fn key_of(instruction: &HloInstruction) -> InstructionKey {
+  (instruction.parent().unwrap(), instruction.id())
+}
+
+// Returns the computation called by the fusion or async instruction 'hlo'.
+fn called_computation<'a>(module: &'a HloModule, hlo: &HloInstruction) -> &'a HloComputation {
+  module.computation(hlo.called_computations()[0])
 }
 
 fn is_1d_slice_without_strides(instr: &HloInstruction) -> bool {
@@ -48,16 +78,19 @@ fn is_1d_slice_without_strides(instr: &HloInstruction) -> bool {
   instr.slice_strides()[0] == 1
 }
 
-pub fn is_slice_input_fusion(unnested_hlo: &HloInstruction) -> bool {
+pub fn is_slice_input_fusion(module: &HloModule, unnested_hlo: &HloInstruction) -> bool {
   if !unnested_hlo.is_input_fusion() {
     return false;
   }
-  let root = unnested_hlo.fused_expression_root();
+  let fused_computation = called_computation(module, unnested_hlo);
+  let root = fused_computation.root_instruction();
   if root.opcode() != HloOpcode::Tuple {
     return false;
   }
   for instr in root.operands() {
-    if !is_1d_slice_without_strides(instr) { return false; }
+    if !is_1d_slice_without_strides(fused_computation.instruction(*instr)) {
+      return false;
+    }
   }
   true
 }
@@ -74,7 +107,7 @@ pub fn are_transitive_uses_effectively_elementwise(
   _root_tuple: &HloInstruction,
   _out_shape_idx: &Vec<i64>) -> bool
 {
-  false    
+  false
 }
 
 // analysis which identifies all HLO values and their uses in an HLO module.
@@ -84,12 +117,22 @@ pub struct HloDataflowAnalysis<'module> {
   execution_threads: HashSet<String>,
   ssa_form: bool,
   bitcast_defines_value: bool,
+  // The map of all HloValues in the module.
   values: HashMap<i64, HloValue>,
-  value_sets: HashMap<HloInstruction, InstructionValueSet>,
+  // A map from instruction to InstructionValueSet.
+  value_sets: HashMap<InstructionKey, InstructionValueSet>,
+  // Values marked for deletion during construction.
   value_ids_to_delete: Vec<i64>,
+  // A vector containing all HloValues sorted by HloValue::id.
   values_vector: Vec<HloValue>,
+  // The Id to use for the next HloValue.
   next_value_id: i64,
-  phi_graph: PhiGraph
+  // An explicit graph holding phi values and edges.
+  phi_graph: PhiGraph,
+  // Backend specific function that decides whether an instruction can share
+  // buffer with its operand.
+  can_share_buffer: Option<CanShareBuffer>,
+  forwards_value: Option<ForwardsValue>
 }
 
 impl<'module> HloDataflowAnalysis<'module> {
@@ -110,19 +153,47 @@ impl<'module> HloDataflowAnalysis<'module> {
       value_ids_to_delete: Vec::new(),
       values_vector: Vec::new(),
       next_value_id: 0,
-      phi_graph: PhiGraph::new()
+      phi_graph: PhiGraph::new(),
+      can_share_buffer: None,
+      forwards_value: None
     }
   }
 
+  // Runs dataflow analysis on the given module.
+  //
+  // ssa_form : If true then new values are defined at the merge points of
+  //   kWhile and kConditional instructions. In SSA form, each value set
+  //   contains at most one value, so a use of an HloValue names a single
+  //   definition.
+  //
+  // bitcast_defines_value : If true then the Bitcast HLO instruction defines
+  //   a new HLO value in the analysis. If false then Bitcast forwards the
+  //   value of its operand.
   pub fn run(
-    _module: &HloModule,
-    _ssa_form: bool,
-    _bitcast_defines_value: bool,
-    _can_share_buffer: Option<&dyn Fn(&HloInstruction, &HloInstruction, usize) -> bool>,
-    _forwards_operand: Option<&dyn Fn(&HloInstruction, usize) -> ForwardedOperand>,
-    _execution_threads: &HashSet<String>) -> Result<HloDataflowAnalysis<'module>, String>
+    module: &'module HloModule,
+    ssa_form: bool,
+    bitcast_defines_value: bool,
+    can_share_buffer: Option<CanShareBuffer>,
+    forwards_value: Option<ForwardsValue>,
+    execution_threads: &HashSet<String>) -> Result<HloDataflowAnalysis<'module>, String>
   {
-    unimplemented!()
+    let mut dataflow_analysis = HloDataflowAnalysis::new(
+      module, ssa_form, bitcast_defines_value, execution_threads.clone());
+    dataflow_analysis.can_share_buffer = can_share_buffer;
+    dataflow_analysis.forwards_value = forwards_value;
+
+    dataflow_analysis.initialize_instruction_value_sets()?;
+    dataflow_analysis.propagate();
+    dataflow_analysis.optimize_phi_values();
+
+    // Delete all values marked for deletion.
+    dataflow_analysis.delete_marked_values();
+
+    // Gather and set all non-definition positions of all values.
+    dataflow_analysis.compute_positions_and_uses();
+
+    dataflow_analysis.verify()?;
+    Ok(dataflow_analysis)
   }
 
   // Returns true if 'instruction' defines an HLO value at the given shape index
@@ -134,7 +205,9 @@ impl<'module> HloDataflowAnalysis<'module> {
   {
     let value_set = self.get_value_set(instruction, index);
     if value_set.values().len() != 1 { return false; }
-    value_set.get_unique_value().defining_instruction() == instruction
+    let value = value_set.get_unique_value();
+    key_of(value.defining_instruction()) == key_of(instruction) &&
+      value.defining_index() == index
   }
 
   // Returns the HloValue defined by 'instruction' at the given shape index of
@@ -153,36 +226,22 @@ impl<'module> HloDataflowAnalysis<'module> {
     &self,
     instruction: &HloInstruction) -> Option<&InstructionValueSet>
   {
-    debug_assert!(self.value_sets.contains_key(instruction));
-    self.value_sets.get(instruction)
+    self.value_sets.get(&key_of(instruction))
   }
 
   pub fn get_mutable_instruction_value_set(
     &mut self,
     instruction: &HloInstruction) -> Option<&mut InstructionValueSet>
   {
-    debug_assert!(self.value_sets.contains_key(instruction));
-    self.value_sets.get_mut(instruction)
-  }
-
-  pub fn get_instruction_value_set_pairs(
-    &self, _instruction: &HloInstruction) -> &Vec<(&Vec<i64>, &HloValueSet)>
-  {
-    unimplemented!()    
-  }
-
-  pub fn get_mutable_instruction_value_set_pairs(
-    &mut self, _instruction: &HloInstruction) -> &mut Vec<(&Vec<i64>, &HloValueSet)>
-  {
-    unimplemented!()    
+    self.value_sets.get_mut(&key_of(instruction))
   }
 
   pub fn set_instruction_value_set(
     &mut self,
-    instruction: HloInstruction,
+    instruction: &HloInstruction,
     value_set: InstructionValueSet)
   {
-    self.value_sets.insert(instruction, value_set);
+    self.value_sets.insert(key_of(instruction), value_set);
   }
 
   // Returns all values that are contained in the output of this instruction in
@@ -194,11 +253,10 @@ impl<'module> HloDataflowAnalysis<'module> {
     let mut value_set = HloValueSet::default();
     let value_set_tree =
       self.get_instruction_value_set(instruction).unwrap();
-    
+
     let mut all_sets = vec![];
-    for pair in value_set_tree.nodes() {
-      let value_set = &pair.1;
-      all_sets.push(value_set.clone());
+    for node in value_set_tree.nodes() {
+      all_sets.push(node.value().clone());
     }
 
     value_set.assign_union_of(all_sets);
@@ -235,6 +293,7 @@ impl<'module> HloDataflowAnalysis<'module> {
     index_vec: &Vec<i64>) -> &HloValue
   {
     let value_set = self.get_value_set(instruction, index_vec);
+    assert_eq!(value_set.values().len(), 1);
     self.get_value(value_set.get_unique_value().id())
   }
 
@@ -259,56 +318,41 @@ impl<'module> HloDataflowAnalysis<'module> {
     &self.call_graph
   }
 
+  pub fn ssa_form(&self) -> bool {
+    self.ssa_form
+  }
+
+  pub fn bitcast_defines_value(&self) -> bool {
+    self.bitcast_defines_value
+  }
+
   pub fn to_string(&self) -> String {
     let mut out = "HloDataflowAnalysis, module ".to_string();
     out.push_str(&self.module.name());
-    out.push_str("\n");
+    out.push('\n');
 
     out.push_str("  Instruction value sets:\n");
-    for comp in self.module.computations() {
-      if HloInstruction::is_thread_included(
-        comp.execution_thread(), &self.execution_threads)
-      {
-        continue;
-      }
+    for comp in self.included_computations() {
+      out.push_str("  Computation ");
+      out.push_str(&comp.name());
+      out.push_str(":\n");
       for inst in comp.instructions() {
-        out.push_str("Instruction: \n  ");
+        out.push_str("    ");
         out.push_str(&inst.name());
         out.push_str(":\n");
-        if inst.shape().is_tuple() {
-          /*
-          let mut instr_v_set =
-            self.get_instruction_value_set(inst);
-          assert!(instr_v_set.is_some());
-          let mut func =
-            |index: &Vec<i64>, value_set: &HloValueSet|
-          {
+        for node in self.get_instruction_value_set(inst).unwrap().nodes() {
+          if inst.shape().is_tuple() {
             out.push_str("      tuple index ");
-            for i in index {
-              out.push_str(&i.to_string());
-            }
+            out.push_str(&shape_index_to_string(node.index()));
             out.push_str(":\n");
-            for value in value_set.values() {
-              out.push_str("        ");
-              out.push_str(&value.to_short_string());
-              if self.value_is_defined_at(inst, &vec![]) {
-                out.push_str(" (def)");
-              }
-              out.push_str("n");
-            }
-          };
-          instr_v_set.as_ref().unwrap().shape_tree.for_each_element(&func);
-          */
-        } else {
-          let top_level_value_set =
-            self.get_value_set(inst, &vec![]);
-          for value in top_level_value_set.values() {
-            out.push_str("      ");
+          }
+          for value in node.value().values() {
+            out.push_str("        ");
             out.push_str(&value.to_short_string());
-            if self.value_is_defined_at(inst, &vec![]) {
+            if self.value_is_defined_at(inst, node.index()) {
               out.push_str(" (def)");
             }
-            out.push_str("n");
+            out.push('\n');
           }
         }
       }
@@ -329,11 +373,11 @@ impl<'module> HloDataflowAnalysis<'module> {
     &self, operand: &HloInstruction, index_vec: &Vec<i64>, user: &HloInstruction) -> bool
   {
     for value in self.get_value_set(operand, index_vec).values() {
-      for use_ in value.get_uses() {
-        if &use_.instruction == user {
+      for use_ in self.get_value(value.id()).get_uses() {
+        if key_of(&use_.instruction) == key_of(user) {
           if user.is_loop_fusion() {
-            let fusion_param =
-              user.fused_parameter(use_.operand_number);
+            let fusion_param = called_computation(self.module, user)
+              .parameter_instruction(use_.operand_number as usize).unwrap();
             let value =
               self.get_value_defined_at(
                 fusion_param, &use_.operand_index_vec);
@@ -361,10 +405,11 @@ impl<'module> HloDataflowAnalysis<'module> {
       ShapeUtil::get_subshape(operand.shape(), operand_index);
     let user_subshape =
       ShapeUtil::get_subshape(user.shape(), user_index);
-    
-    if is_slice_input_fusion(user) {
-      let fusion_param =
-        user.fused_parameter(user.operand_index(operand) as i64);
+
+    if is_slice_input_fusion(self.module, user) {
+      let fused_computation = called_computation(self.module, user);
+      let fusion_param = fused_computation
+        .parameter_instruction(user.operand_index(operand.id())).unwrap();
       // We don't require the same dimensions but only the same number of elements
       // and type (to make sure the same buffer size).
       return operand_subshape.is_array() && user_subshape.is_array() &&
@@ -372,7 +417,7 @@ impl<'module> HloDataflowAnalysis<'module> {
         ShapeUtil::elements_in(&user_subshape) &&
         ShapeUtil::same_element_type(&operand_subshape, &user_subshape) &&
         are_transitive_uses_effectively_elementwise(
-          fusion_param, user.fused_expression_root(), user_index);
+          fusion_param, fused_computation.root_instruction(), user_index);
     }
 
     let shape_equal = ShapeEqual::new().equal(
@@ -382,7 +427,8 @@ impl<'module> HloDataflowAnalysis<'module> {
       // Must-alias relationship returns true for in-place operations (DUS and DUS
       // fusions), regardless of the backend.
       let pairs =
-        HloDataflowAnalysis::get_in_place_input_output_pairs(user);
+        HloDataflowAnalysis::get_in_place_input_output_pairs(
+          self.module.computation(user.parent().unwrap()), user);
       for operand_and_output_index in pairs {
         if &operand_and_output_index.1 != user_index {
           continue;
@@ -402,14 +448,23 @@ impl<'module> HloDataflowAnalysis<'module> {
       }
     }
 
-    // can_share_buffer : TODO
+    if let Some(can_share_buffer) = &self.can_share_buffer {
+      if let Some(hint) = can_share_buffer(user, operand, user_index) {
+        return hint;
+      }
+    }
 
     if !shape_equal {
       return false;
     }
 
     if user.opcode() == HloOpcode::Fusion {
-
+      let fusion_param = called_computation(self.module, user)
+        .parameter_instruction(user.operand_index(operand.id())).unwrap();
+      if user.is_loop_fusion() || user.is_input_fusion() {
+        return self.are_transitive_uses_elementwise_or_tuple(fusion_param);
+      }
+      return false;
     }
 
     // There is nothing inherently wrong with while and conditional ops to have
@@ -430,7 +485,7 @@ impl<'module> HloDataflowAnalysis<'module> {
     {
       // We eliminated other users in HloOrdering::LiveRangeStrictlyBefore
       // so here we just need to check that the use is at the right operand index.
-      let operand_indices = user.operand_indices(operand);
+      let operand_indices = user.operand_indices(operand.id());
       let mut operand_no = 0;
       if user.opcode() == HloOpcode::TriangularSolve {
         operand_no = 1;
@@ -440,7 +495,7 @@ impl<'module> HloDataflowAnalysis<'module> {
 
     if user.opcode() == HloOpcode::Sort {
       // Only valid if there are no other users.
-      if user.users().len() != 1 {
+      if operand.users().len() != 1 {
         return false;
       }
       // If we only sort keys, the output of sort is not a tuple, so we can always
@@ -450,7 +505,7 @@ impl<'module> HloDataflowAnalysis<'module> {
       }
       debug_assert!(!user_index.is_empty());
       // Only share with the right tuple element buffer.
-      let operand_indices = user.operand_indices(operand);
+      let operand_indices = user.operand_indices(operand.id());
       return operand_indices.len() == 1 && user_index[0] == operand_indices[0];
     }
 
@@ -466,31 +521,50 @@ impl<'module> HloDataflowAnalysis<'module> {
       //     root result buffer is required to alias with the Call result buffer).
       // *) The root instruction of the called computation is element-wise on
       //    'operand'.
-      let mut found_caller_use = false;
-      for use_ in uses {
-        if use_.instruction == *user { found_caller_use = true; }
-      }
-      let callee_root = user.to_apply().root_instruction();
-      let mut found_elementwise_callee_use = false;
-      for use_ in uses {
-        if use_.instruction == *callee_root &&
-          callee_root.is_elementwise_on_operand(use_.operand_number)
-        {
-          found_elementwise_callee_use = true;
-        }
-      }
+      let found_caller_use = uses.iter()
+        .any(|use_| key_of(&use_.instruction) == key_of(user));
+      let callee_root = self.root_of(user.to_apply());
+      let found_elementwise_callee_use = uses.iter()
+        .any(|use_| key_of(&use_.instruction) == key_of(callee_root) &&
+          callee_root.is_elementwise_on_operand(use_.operand_number));
       return uses.len() == 2 && found_caller_use && found_elementwise_callee_use;
     }
-    
+
     // Loop fusions that contain transposing copies won't reach here as they have
     // different layouts, which fails the check in the beginning of this function.
-    user.is_elementwise_on_operand(user.operand_index(operand) as i64)
+    user.is_elementwise_on_operand(user.operand_index(operand.id()) as i64)
   }
 
   pub fn module(&self) -> &HloModule {
     &self.module
   }
 
+  // Returns the `operand_num`-th operand of `instruction` in its parent
+  // computation.
+  fn operand(
+    &self, instruction: &HloInstruction, operand_num: usize) -> &'module HloInstruction
+  {
+    self.module.computation(instruction.parent().unwrap())
+      .operand(instruction.id(), operand_num)
+  }
+
+  fn root_of(&self, computation: ComputationId) -> &'module HloInstruction {
+    self.module.computation(computation).root_instruction()
+  }
+
+  fn instruction_at(&self, key: InstructionKey) -> &'module HloInstruction {
+    self.module.computation(key.0).instruction(key.1)
+  }
+
+  // Returns the computations of the module in the analyzed execution threads.
+  fn included_computations(&self) -> Vec<&'module HloComputation> {
+    let module = self.module;
+    module.computations()
+      .filter(|comp| HloInstruction::is_thread_included(
+        comp.execution_thread(), &self.execution_threads))
+      .collect()
+  }
+
   // Returns true if the operation is an in-place operation and its operand 0
   // must alias with the output.
   pub fn is_in_place_operation(opcode: &HloOpcode) -> bool {
@@ -537,19 +611,29 @@ impl<'module> HloDataflowAnalysis<'module> {
   // ... the results can include any of the 3 * 3 = 9 possible pairs of
   // input and output arrays.
   pub fn get_in_place_input_output_pairs(
+    computation: &HloComputation,
     instruction: &HloInstruction) -> Vec<(HloOperandIndex, Vec<i64>)>
   {
-    if HloDataflowAnalysis::is_in_place_operation(&instruction.opcode()) {
-      // TODO
+    if instruction.opcode() == HloOpcode::DynamicUpdateSlice {
       return vec![(HloOperandIndex::default(), vec![])];
+    } else if instruction.opcode() == HloOpcode::Scatter {
+      // A scatter has N operands, one indices operand and N updates. Each
+      // operand aliases the matching element of the output.
+      let operand_count = ((instruction.operand_count() - 1) / 2) as i64;
+      if operand_count == 1 {
+        return vec![(HloOperandIndex::default(), vec![])];
+      }
+      return (0..operand_count)
+        .map(|i| (HloOperandIndex::new(i, vec![]), vec![i]))
+        .collect();
     } else if instruction.opcode() == HloOpcode::CollectivePermute &&
       instruction.operands().len() == 4
     {
-      if instruction.operand(1).shape().is_tuple() {
+      if computation.operand(instruction.id(), 1).shape().is_tuple() {
         let mut in_place_pairs =
           vec![(HloOperandIndex::new(1, vec![]), vec![])];
         let tuple_shapes_len =
-          instruction.operand(1).shape().tuple_shapes_vec().len();
+          computation.operand(instruction.id(), 1).shape().tuple_shapes_vec().len();
         for i in 0..tuple_shapes_len {
           in_place_pairs.push((
             HloOperandIndex::new(1, vec![i as i64]),
@@ -562,11 +646,11 @@ impl<'module> HloDataflowAnalysis<'module> {
     } else if instruction.opcode() == HloOpcode::CollectivePermuteStart &&
       instruction.operands().len() == 4
     {
-      if instruction.operand(1).shape().is_tuple() {
+      if computation.operand(instruction.id(), 1).shape().is_tuple() {
         let mut in_place_pairs =
           vec![(HloOperandIndex::new(1, vec![]), vec![1])];
         let tuple_shapes_len =
-          instruction.operand(1).shape().tuple_shapes_vec().len();
+          computation.operand(instruction.id(), 1).shape().tuple_shapes_vec().len();
         for i in 0..tuple_shapes_len {
           in_place_pairs.push((
             HloOperandIndex::new(1, vec![i as i64]),
@@ -587,7 +671,7 @@ impl<'module> HloDataflowAnalysis<'module> {
         let operand_index = pair.1.0;
         let operand_shape_index = pair.1.1;
         in_place_pairs.push((HloOperandIndex::new(
-          operand_index, 
+          operand_index,
           operand_shape_index),
           output_shape_index));
       }
@@ -604,12 +688,16 @@ impl<'module> HloDataflowAnalysis<'module> {
       }
       return in_place_pairs;
     } else if instruction.opcode() == HloOpcode::Fusion {
-        
+      // TODO: the in-place pairs of a fusion come from the in-place
+      // instructions of its fused computation, which is not reachable from
+      // 'computation'.
+      return vec![];
     } else if instruction.opcode() == HloOpcode::SetDimensionSize {
       let mut in_place_pairs = vec![];
       let dimension = instruction.dimension();
+      let operand = computation.operand(instruction.id(), 0);
       if instruction.shape().is_dynamic_dimension(dimension) ==
-        instruction.shape().is_dynamic_dimension(dimension)
+        operand.shape().is_dynamic_dimension(dimension)
       {
         in_place_pairs.push((HloOperandIndex::default(), vec![]));
       }
@@ -618,7 +706,7 @@ impl<'module> HloDataflowAnalysis<'module> {
       return vec![(HloOperandIndex::new(1, vec![]), vec![])];
     }
 
-    vec![(HloOperandIndex::default(), vec![])]
+    vec![]
   }
 
   // Verifies various invariants of the dataflow analysis.
@@ -628,29 +716,34 @@ impl<'module> HloDataflowAnalysis<'module> {
     for value in self.values() {
       for pos in value.positions() {
         let value_set = self.get_value_set_by_pos(pos);
-        for _value in value_set.values() {
-          // TODO
+        if !value_set.values().contains(value) {
+          return Err(format!("Value set at position {} does not contain value {}",
+            pos.to_string(), value.to_short_string()));
         }
       }
     }
     // For each value in each value set, verify that the value set's position
     // appears in the value's positions().
-    for comp in self.module.computations() {
-      if HloInstruction::is_thread_included(
-        comp.execution_thread(), &self.execution_threads)
-      {
-        continue;
-      }
+    for comp in self.included_computations() {
       for inst in comp.instructions() {
-        if inst.opcode() == HloOpcode::AsyncStart &&
-          inst.async_wrapped_opcode() == HloOpcode::Call ||
-          inst.async_wrapped_opcode() == HloOpcode::CustomCall
-        {
-          continue;
+        if inst.opcode() == HloOpcode::AsyncStart {
+          let wrapped_opcode = called_computation(self.module, inst)
+            .root_instruction().opcode();
+          if wrapped_opcode == HloOpcode::Call || wrapped_opcode == HloOpcode::CustomCall {
+            continue;
+          }
+        }
+        for node in self.get_instruction_value_set(inst).unwrap().nodes() {
+          for value in node.value().values() {
+            let found = self.get_value(value.id()).positions().iter()
+              .any(|pos| key_of(&pos.instruction) == key_of(inst) &&
+                &pos.index == node.index());
+            if !found {
+              return Err(format!("Value {} does not have position {} {}",
+                value.to_short_string(), inst.name(), shape_index_to_string(node.index())));
+            }
+          }
         }
-        //for pair in self.get_instruction_value_set(inst) {
-          // TODO
-        //}
       }
     }
     Ok(())
@@ -661,19 +754,21 @@ impl<'module> HloDataflowAnalysis<'module> {
     let mut stack = vec![];
     stack.push(inst);
 
-    while !stack.is_empty() {
-      let current = stack.pop();
-      visited.insert(current.unwrap());
-      for user in current.unwrap().users() {
+    while let Some(current) = stack.pop() {
+      visited.insert(key_of(current));
+      let computation =
+        self.module.computation(current.parent().unwrap());
+      for user_id in current.users() {
+        let user = computation.instruction(*user_id);
         // Found a user that is non-elementwise on current instruction.
-        for use_index in user.operand_indices(current.unwrap()) {
+        for use_index in user.operand_indices(current.id()) {
           if !user.is_elementwise_on_operand(use_index) &&
             user.opcode() != HloOpcode::Tuple
           {
             return false;
           }
         }
-        if !visited.contains(user) {
+        if !visited.contains(&key_of(user)) {
           stack.push(user);
         }
       }
@@ -696,43 +791,27 @@ impl<'module> HloDataflowAnalysis<'module> {
     // Only applicable to SSA form where phis are defined.
     if !self.ssa_form { return; }
 
-    println!("Before phi graph optimization");
-    println!("{:?}", self.phi_graph.to_string());
     self.phi_graph.optimize();
-    println!("After phi graph optimization");
-    println!("{:?}", self.phi_graph.to_string());
 
-    for comp in self.module.computations() {
-      if !HloInstruction::is_thread_included(
-        comp.execution_thread(), &self.execution_threads)
-      {
-        continue;
-      }
-      /*
-      for inst in comp.instructions() {
-        let inst_value_set =
-          self.get_mutable_instruction_value_set(inst); 
-        println!("inst: {:?}", inst.name());
-        //println!("{:?}", inst_value_set.unwrap().to_string());
-        let mut func =
-          |_index: &Vec<i64>, value_set: &mut HloValueSet|
-        {
-          let values = value_set.values();
-          if !(values.len() == 1 && values[0].is_phi()) {
-            return;
-          }
-          let phi_id = values[0].id();
-          let new_id = self.phi_graph.find_optimized_value(phi_id);
-          if new_id != phi_id {
-            value_set.clear();
-            let new_value = self.get_value(new_id);
-            value_set.add_value(new_value.clone());
-            self.mark_value_for_deletion(phi_id);
-          }
-        };
-        inst_value_set.unwrap().shape_tree.for_each_mutable_element(&mut func);
+    let mut replaced_phi_ids = vec![];
+    for value_set in self.value_sets.values_mut() {
+      for node in value_set.mutable_nodes() {
+        let values = node.value().values();
+        if !(values.len() == 1 && values[0].is_phi()) {
+          continue;
+        }
+        let phi_id = values[0].id();
+        let new_id = self.phi_graph.find_optimized_value(phi_id);
+        if new_id != phi_id {
+          let new_value = self.values.get(&new_id).unwrap().clone();
+          node.mutable_value().clear();
+          node.mutable_value().add_value(new_value);
+          replaced_phi_ids.push(phi_id);
+        }
       }
-      */
+    }
+    for phi_id in replaced_phi_ids {
+      self.mark_value_for_deletion(phi_id);
     }
   }
 
@@ -745,16 +824,14 @@ impl<'module> HloDataflowAnalysis<'module> {
   {
     let value_id = self.next_value_id;
     self.next_value_id += 1;
-    let hlo_value = HloValue::new(value_id, instruction, index, is_phi); 
-    let result = self.values.insert(value_id, hlo_value);
-    debug_assert!(result.is_some());
-    result.unwrap()
+    let hlo_value = HloValue::new(value_id, instruction, index, is_phi);
+    let result = self.values.insert(value_id, hlo_value.clone());
+    debug_assert!(result.is_none());
+    hlo_value
   }
 
   // Marks the HloValue with the given ID for the deletion.
   fn mark_value_for_deletion(&mut self, value_id: i64) {
-    let value = self.get_value(value_id);
-    print!("mark_value_for_deletion( {:?} )", value.to_short_string());
     self.value_ids_to_delete.push(value_id);
   }
 
@@ -773,105 +850,124 @@ impl<'module> HloDataflowAnalysis<'module> {
     self.value_ids_to_delete.clear();
   }
 
+  // Sets the positions and uses of every value, and refreshes the copies of
+  // the values held by the value sets.
+  fn compute_positions_and_uses(&mut self) {
+    let mut value_positions: HashMap<i64, Vec<HloPosition>> = HashMap::new();
+    for comp in self.included_computations() {
+      for inst in comp.instructions() {
+        for node in self.get_instruction_value_set(inst).unwrap().nodes() {
+          for value in node.value().values() {
+            if key_of(value.defining_instruction()) != key_of(inst) ||
+              value.defining_index() != node.index()
+            {
+              value_positions.entry(value.id()).or_default()
+                .push(HloPosition::new(inst.clone(), node.index().clone()));
+            }
+          }
+        }
+      }
+    }
+    let module = self.module;
+    for (value_id, value) in self.values.iter_mut() {
+      value.set_positions(value_positions.remove(value_id).unwrap_or_default(), module);
+      value.compute_uses(module);
+    }
+
+    let mut values_vector: Vec<HloValue> = self.values.values().cloned().collect();
+    values_vector.sort();
+    self.values_vector = values_vector;
+
+    for value_set in self.value_sets.values_mut() {
+      for node in value_set.mutable_nodes() {
+        let values = node.value().values().iter()
+          .map(|value| self.values.get(&value.id()).unwrap().clone())
+          .collect();
+        *node.mutable_value() = HloValueSet::new(values);
+      }
+    }
+  }
+
+  // Adds a new HloValue to the HloValueSet corresponding to the given index
+  // of the instruction shape.
+  fn define_value_at(&mut self, instruction: &HloInstruction, index: &Vec<i64>) {
+    let value = self.new_hlo_value(instruction, index, false);
+    self.get_mutable_value_set(instruction, index).add_value(value);
+  }
+
+  // For each sub-shape of the instruction shape, adds a new HloValue to its
+  // HloValueSet. should_define may be provided to define a subset of values.
+  fn define_all_values<F>(&mut self, instruction: &HloInstruction, should_define: F)
+    where F: Fn(&Vec<i64>) -> bool
+  {
+    for index in self.value_set_indices(instruction) {
+      let forwarded = match &self.forwards_value {
+        Some(forwards_value) => forwards_value(instruction, &index).is_some(),
+        None => false
+      };
+      if !forwarded && should_define(&index) {
+        self.define_value_at(instruction, &index);
+      }
+    }
+  }
+
+  // Returns the shape indices of the value set of 'instruction', in
+  // pre-order.
+  fn value_set_indices(&self, instruction: &HloInstruction) -> Vec<Vec<i64>> {
+    self.get_instruction_value_set(instruction).unwrap().nodes().iter()
+      .map(|node| node.index().clone())
+      .collect()
+  }
+
   // Constructs and initializes the InstructionValueSets of all instructions to
   // contain exactly the HloValues defined by each instruction. These values can
   // then propagated throughout the HLO graph by calling Propagate.
   fn initialize_instruction_value_sets(&mut self) -> Result<(), String> {
-    let hash_set = HashSet::new();
-    for comp in self.module.make_computation_post_order(
-      &hash_set, false)
+    let module = self.module;
+    for comp_id in module.make_computation_post_order(
+      &self.execution_threads, false)
     {
-      if !HloInstruction::is_thread_included(
-        comp.execution_thread(), &self.execution_threads)
-      {
-        continue;
-      }
-      let call_graph_node = self.call_graph.get_node(&comp);
-      for inst in comp.make_instruction_post_order() {
+      let comp = module.computation(comp_id);
+      let call_graph_context = self.call_graph.get_node(comp_id).context();
+      let has_callers = !self.call_graph.get_node(comp_id).caller_callsites().is_empty();
+      for inst_id in comp.make_instruction_post_order() {
+        let inst = comp.instruction(inst_id);
         // Create an empty shape tree.
-        self.value_sets.insert(inst.clone(),
-        InstructionValueSet::new(inst.shape().clone()));
-
-        // For each sub-shape of the instruction shape, add a new HloValue to its
-        // HloValueSet. should_define may be provided to define a subset of
-        // values.
-        let define_all_values =
-          |dfs: &mut HloDataflowAnalysis,
-           instruction: &HloInstruction,
-           _func: &dyn Fn(&Vec<i64>)-> bool|
-        {
-          for pair in
-            dfs.get_instruction_value_set_pairs(instruction)
-          {
-            let _index = pair.0;
-            let defines_value = false;
-            // TODO: forwards_value
-
-            if defines_value {
-              //let value = dfs.new_hlo_value(instruction, index, false);
-              //dfs.get_value_set(instruction, index).add_value(value);
-            }
-          }
-        };
-
-        // Add a new HloValue to the HloValueSet corresponding to the given index
-        // of the instruction shape.
-        let define_value_at =
-          |dfs: &mut HloDataflowAnalysis, instruction: &HloInstruction, index: &Vec<i64>|
-        {
-          let value = dfs.new_hlo_value(instruction, index, false);
-          let v_set = dfs.get_mutable_value_set(instruction, index);
-          v_set.add_value(value);
-        };
+        self.set_instruction_value_set(inst, InstructionValueSet::new(inst.shape().clone()));
 
         match inst.opcode() {
           HloOpcode::Bitcast => {
             if self.bitcast_defines_value {
-              let func = |_index: &Vec<i64>| -> bool { return true; };
-              define_all_values(self, inst, &func);
+              self.define_all_values(inst, |_index| true);
             }
-            break
-          },
+          }
           // These instructions define no values. The values in their output
           // flow from their operands or from cross computation dataflow.
-          HloOpcode::AddDependency => break,
-          HloOpcode::While => break,
-          HloOpcode::Call => break,
-          HloOpcode::Conditional => break,
-          HloOpcode::GetTupleElement => break,
-          HloOpcode::Domain => break,
-          HloOpcode::OptimizationBarrier => break,
+          HloOpcode::AddDependency | HloOpcode::While | HloOpcode::Call |
+          HloOpcode::Conditional | HloOpcode::GetTupleElement | HloOpcode::Domain |
+          HloOpcode::OptimizationBarrier => {}
           HloOpcode::Parameter => {
-            if call_graph_node.context() == CallContext::Both {
+            if call_graph_context == CallContext::Both {
               // We do not support a subcomputation that is called from both a
               // parallel and sequential context. In this case, the parameter
               // would both define a value and propagate a value from its
               // caller. This limitation is not really a problem because the call
               // graph is typically flattened.
-              unimplemented!();
+              return Err(format!(
+                "Computation {} is called in both a parallel (eg, kMap) and \
+                sequential (eg, kCall) context", comp.name()));
             }
-            if call_graph_node.caller_callsites().is_empty() ||
-              call_graph_node.context() == CallContext::Embedded
-            {
+            if !has_callers || call_graph_context == CallContext::Embedded {
               // Parameters of computations called in a parallel context (eg, map
               // and reduce) as well as parameters of dead computations define all
               // values in their output. Otherwise the values of the parameter
               // come from the caller (eg, operands to the kCall instruction).
-              let func = |_index: &Vec<i64>| -> bool { return true; };
-              define_all_values(self, inst, &func);
+              self.define_all_values(inst, |_index| true);
             }
-            break;
-          },
+          }
           // These instructions only define their top-level values. Any other
           // values flow from their operands.
-          HloOpcode::Copy => {
-            define_value_at(self, inst, &vec![]);
-            break;
-          }
-          HloOpcode::Tuple => {
-            define_value_at(self, inst, &vec![]);
-            break;
-          }
+          HloOpcode::Copy | HloOpcode::Tuple => self.define_value_at(inst, &vec![]),
           HloOpcode::AsyncStart => {
             // AsyncStart produces a tuple of {{aliased operands}, {destination},
             // contexts}. It defines all of the tuple-shaped values and the
@@ -880,46 +976,31 @@ impl<'module> HloDataflowAnalysis<'module> {
             // dataflow, and define the destination values too.
             let thread_included = HloInstruction::is_thread_included(
               inst.async_execution_thread(), &self.execution_threads);
-            let func = |index: &Vec<i64>| -> bool {
+            self.define_all_values(inst, |index| {
               ShapeUtil::get_subshape(inst.shape(), index).is_tuple() ||
               (!thread_included && index.first() == Some(&1)) ||
               index.first() > Some(&1)
-            };
-            define_all_values(self, inst, &func);
-            break;
+            });
           }
-          HloOpcode::AsyncUpdate => {
+          HloOpcode::AsyncUpdate | HloOpcode::AsyncDone => {
             // AsyncUpdate produces a tuple of {{aliased operands}, {destination},
             // contexts} where all of the array-typed values alias with the
-            // operand. So, only tuple-shaped values are defined by AsyncUpdate.
-            let func = |index: &Vec<i64>| -> bool {
+            // operand, and AsyncDone's output aliases the destination of its
+            // operand. So only the tuple-shaped values are defined by them.
+            self.define_all_values(inst, |index| {
               ShapeUtil::get_subshape(inst.shape(), index).is_tuple()
-            };
-            define_all_values(self, inst, &func);
-            break;
-          }
-          HloOpcode::AsyncDone => {
-            // AsyncDone's output aliases its output. It defines all remaining
-            // tuple-shaped values.
-            let func = |index: &Vec<i64>| -> bool {
-              ShapeUtil::get_subshape(inst.shape(), index).is_tuple()
-            };
-            define_all_values(self, inst, &func);
-            break;
+            });
           }
           HloOpcode::CopyStart => {
             // CopyStart produces a tuple of {destination buffer, aliased operand,
             // U32 context}.
-            define_value_at(self, inst, &vec![]);
-            define_value_at(self, inst, &vec![0]);
-            define_value_at(self, inst, &vec![2]);
-            break;
-          }
-          HloOpcode::CopyDone => {
-            // CopyDone consumes a tuple produced by CopyStart and produces an
-            // element. Its output aliases its input tuple element {0}.
-            break;
+            self.define_value_at(inst, &vec![]);
+            self.define_value_at(inst, &vec![0]);
+            self.define_value_at(inst, &vec![2]);
           }
+          // CopyDone consumes a tuple produced by CopyStart and produces an
+          // element. Its output aliases its input tuple element {0}.
+          HloOpcode::CopyDone => {}
           HloOpcode::AllGatherStart => {
             // AllGatherStart produces a tuple of
             // {aliased operands, destination buffers}. If there is more than
@@ -927,117 +1008,256 @@ impl<'module> HloDataflowAnalysis<'module> {
             // will be tuples themselves. all-gather-start will define all tuples
             // and all tuple leaves (arrays) in tuple sub-index 1 (destination
             // buffers).
-            let func = |index: &Vec<i64>| -> bool {
-              ShapeUtil::get_subshape(inst.shape(), index).is_tuple() || index.first() == Some(&1)
-            };
-            define_all_values(self, inst, &func);
-            break;
+            self.define_all_values(inst, |index| {
+              ShapeUtil::get_subshape(inst.shape(), index).is_tuple() ||
+              index.first() == Some(&1)
+            });
           }
-          HloOpcode::AllGatherDone => {
-            // AllGatherDone's output aliases its input tuple element {1}.
+          HloOpcode::AllGatherDone | HloOpcode::CollectivePermuteDone => {
+            // The output aliases the input tuple element {1}. A tuple output
+            // is defined by the done instruction itself.
             if inst.shape().is_tuple() {
-              define_value_at(self, inst, &vec![]);
+              self.define_value_at(inst, &vec![]);
             }
-            break;
-          }
-          HloOpcode::AllReduceDone => {
-            // AllReduceDone's output aliases its input.
-            break;
           }
+          // AllReduceDone's output aliases its input.
+          HloOpcode::AllReduceDone => {}
           HloOpcode::CollectivePermuteStart => {
-            // TODO
-            break;
-          }
-          HloOpcode::CollectivePermuteDone => {
-            // CollectivePermuteDone's output aliases its input tuple element {1}.
-            if inst.shape().is_tuple() {
-              define_value_at(self, inst, &vec![]);
+            // CollectivePermuteStart produces a tuple of
+            // {aliased operand, destination buffer, contexts}, where the context
+            // data are optional.
+            self.define_value_at(inst, &vec![]);
+            self.define_value_at(inst, &vec![1]);
+            for i in 2..inst.shape().tuple_shapes_size() {
+              self.define_value_at(inst, &vec![i as i64]);
+            }
+            if inst.operand_count() > 1 {
+              let operand_shape = comp.operand(inst.id(), 1).shape();
+              if operand_shape.is_tuple() {
+                for i in 0..operand_shape.tuple_shapes_size() {
+                  self.define_value_at(inst, &vec![1, i as i64]);
+                }
+              }
             }
-            break;
           }
           HloOpcode::RecvDone => {
             // RecvDone produces a two-element tuple. Element zero aliases its
             // input tuple element {0}; element one is a token.
-            define_value_at(self, inst, &vec![]);
-            define_value_at(self, inst, &vec![1]);
-            break;
+            self.define_value_at(inst, &vec![]);
+            self.define_value_at(inst, &vec![1]);
           }
           HloOpcode::Send => {
             // Send produces a tuple of {aliased operand, U32 context, token},
             // therefore only defines the top-level tuple and the tuple elements
             // at {1} and {2}.
-            define_value_at(self, inst, &vec![]);
-            define_value_at(self, inst, &vec![1]);
-            define_value_at(self, inst, &vec![2]);
-            break;
-          }
-          _ => {
-            let func = |_index: &Vec<i64>| -> bool { return true; };
-            define_all_values(self, inst, &func);
-            break;
+            self.define_value_at(inst, &vec![]);
+            self.define_value_at(inst, &vec![1]);
+            self.define_value_at(inst, &vec![2]);
           }
+          _ => self.define_all_values(inst, |_index| true)
         }
       }
     }
     Ok(())
   }
 
+  // Propagates the values defined by the instructions through the module
+  // until no value set changes.
+  fn propagate(&mut self) {
+    let module = self.module;
+    let mut worklist = VecDeque::new();
+    let mut workset = HashSet::new();
+    let add_to_worklist =
+      |worklist: &mut VecDeque<InstructionKey>,
+       workset: &mut HashSet<InstructionKey>,
+       key: InstructionKey| {
+        if workset.insert(key) {
+          worklist.push_back(key);
+        }
+      };
+
+    for comp_id in module.make_computation_post_order(&self.execution_threads, false) {
+      for inst_id in module.computation(comp_id).make_instruction_post_order() {
+        add_to_worklist(&mut worklist, &mut workset, (comp_id, inst_id));
+      }
+    }
+
+    while let Some(key) = worklist.pop_front() {
+      workset.remove(&key);
+      let instruction = self.instruction_at(key);
+      if !self.update_instruction_value_set(instruction) {
+        // No change to the instruction's value set.
+        continue;
+      }
+
+      // Instruction value was updated. Add users to work list if we haven't
+      // already.
+      let computation = module.computation(key.0);
+      for user_id in instruction.users() {
+        let user = computation.instruction(*user_id);
+        add_to_worklist(&mut worklist, &mut workset, (key.0, *user_id));
+
+        // If user sequentially calls a computation, then the respective
+        // parameter(s) of the computation need to be updated.
+        if user.opcode() == HloOpcode::Conditional {
+          // If operand 0 is the use of instruction, then no parameters need to be
+          // updated, since that is the branch_index of the conditional.
+          // If operand n+1 is the use of instruction, then the branch_computation
+          // n's parameter need to be updated.
+          for j in 0..user.branch_count() {
+            if user.operands()[j + 1] == key.1 {
+              let branch = user.branch_computation(j);
+              let parameter = module.computation(branch).parameter_instruction(0).unwrap();
+              add_to_worklist(&mut worklist, &mut workset, key_of(parameter));
+            }
+          }
+        } else if user.opcode() == HloOpcode::AsyncUpdate ||
+          user.opcode() == HloOpcode::AsyncDone
+        {
+          if HloInstruction::is_thread_included(
+            user.async_execution_thread(), &self.execution_threads)
+          {
+            // For async update and async done, we cannot distinguish which
+            // parameter needs to be updated so add all to the worklist.
+            let wrapped = called_computation(module, user);
+            for parameter_number in 0..wrapped.num_parameters() {
+              let parameter = wrapped.parameter_instruction(parameter_number).unwrap();
+              add_to_worklist(&mut worklist, &mut workset, key_of(parameter));
+            }
+          }
+        } else {
+          for called_id in user.called_computations() {
+            let called = module.computation(*called_id);
+            if !HloInstruction::is_thread_included(
+              called.execution_thread(), &self.execution_threads)
+            {
+              continue;
+            }
+            if self.call_graph.get_node(*called_id).context() == CallContext::ControlFlow {
+              for operand_number in user.operand_indices(key.1) {
+                if let Some(parameter) =
+                  called.parameter_instruction(operand_number as usize)
+                {
+                  add_to_worklist(&mut worklist, &mut workset, key_of(parameter));
+                }
+              }
+            }
+          }
+        }
+      }
+
+      // If instruction is a root instruction, then propagate out to any calling
+      // instruction and across any while backedge.
+      if computation.is_root(key.1) {
+        let call_graph_node = self.call_graph.get_node(key.0);
+        for callsite in call_graph_node.caller_callsites() {
+          let caller = module.instruction(callsite.computation(), callsite.instruction());
+          if caller.opcode() == HloOpcode::While {
+            // Add the while itself, and the body and condition parameters.
+            add_to_worklist(&mut worklist, &mut workset, (callsite.computation(), callsite.instruction()));
+            for called_id in [caller.while_body(), caller.while_condition()] {
+              let parameter = module.computation(called_id).parameter_instruction(0).unwrap();
+              add_to_worklist(&mut worklist, &mut workset, key_of(parameter));
+            }
+          } else if call_graph_node.context() == CallContext::ControlFlow {
+            add_to_worklist(&mut worklist, &mut workset, (callsite.computation(), callsite.instruction()));
+          }
+        }
+      }
+    }
+  }
+
+  // Sets the value set at 'index' of 'instruction' to 'value_set'. Returns
+  // whether the value set changed.
+  fn assign_value_set(
+    &mut self,
+    instruction: &HloInstruction,
+    index: &Vec<i64>,
+    value_set: HloValueSet) -> bool
+  {
+    let current = self.get_mutable_value_set(instruction, index);
+    if *current == value_set {
+      return false;
+    }
+    *current = value_set;
+    true
+  }
+
+  // Copies the value set at 'source_index' of 'source' to 'index' of
+  // 'instruction'. Returns whether the value set changed.
+  fn forward_value_set(
+    &mut self,
+    instruction: &HloInstruction,
+    index: &Vec<i64>,
+    source: &HloInstruction,
+    source_index: &Vec<i64>) -> bool
+  {
+    let value_set = self.get_value_set(source, source_index).clone();
+    self.assign_value_set(instruction, index, value_set)
+  }
+
+  // Copies the whole value set of 'source' to 'instruction'.
+  fn forward_instruction_value_set(
+    &mut self, instruction: &HloInstruction, source: &HloInstruction) -> bool
+  {
+    let source_set = self.get_instruction_value_set(source).unwrap();
+    if source_set == self.get_instruction_value_set(instruction).unwrap() {
+      return false;
+    }
+    let source_set = source_set.clone();
+    self.set_instruction_value_set(instruction, source_set);
+    true
+  }
+
   // Updates the value set of the given instruction based on the values flowing
   // into the instruction (operands and cross-computation dataflow).
-  fn update_instructionn_value_set(&mut self, instruction: &HloInstruction) -> bool {
-    #[allow(unused_assignments)]
-    let mut changed = false;
-    match instruction.opcode() {
-      HloOpcode::AddDependency =>
-        changed = self.update_add_dependency_value_set(instruction),
-      HloOpcode::AllGatherStart =>
-        changed = self.update_all_gather_start_value_set(instruction),
-      HloOpcode::AllGatherDone =>
-        changed= self.update_all_gather_done_value_set(instruction),
-      HloOpcode::AsyncStart =>
-        changed = self.update_async_start_value_set(instruction),
-      HloOpcode::AsyncUpdate =>
-        changed = self.update_async_update_value_set(instruction),
-      HloOpcode::AsyncDone =>
-        changed = self.update_async_done_value_set(instruction),
-      HloOpcode::Bitcast =>
-        changed = self.update_bitcast_value_set(instruction),
-      HloOpcode::Domain =>
-        changed = self.update_domain_value_set(instruction),
-      HloOpcode::Copy =>
-        changed = self.update_copy_value_set(instruction),
-      HloOpcode::GetTupleElement =>
-        changed = self.update_get_tuple_element_value_set(instruction),
-      HloOpcode::Tuple =>
-        changed = self.update_tuple_value_set(instruction),
-      HloOpcode::Parameter =>
-        changed = self.update_parameter_value_set(instruction),
-      HloOpcode::Call =>
-        changed = self.update_call_value_set(instruction),
-      HloOpcode::While =>
-        changed = self.update_while_value_set(instruction),
-      HloOpcode::Send =>
-        changed = self.update_send_value_set(instruction),
-      HloOpcode::RecvDone =>
-        changed = self.update_recv_done_value_set(instruction),
-      HloOpcode::CopyStart =>
-        changed = self.update_copy_start_value_set(instruction),
-      HloOpcode::CopyDone =>
-        changed = self.update_copy_done_value_set(instruction),
-      HloOpcode::Conditional =>
-        changed = self.update_conditional_value_set(instruction),
-      HloOpcode::AllReduceDone =>
-        changed = self.update_all_reduce_done_value_set(instruction),
+  fn update_instruction_value_set(&mut self, instruction: &HloInstruction) -> bool {
+    let mut changed = match instruction.opcode() {
+      HloOpcode::AddDependency => self.update_add_dependency_value_set(instruction),
+      HloOpcode::AllGatherStart => self.update_all_gather_start_value_set(instruction),
+      HloOpcode::AllGatherDone => self.update_all_gather_done_value_set(instruction),
+      HloOpcode::AsyncStart => self.update_async_start_value_set(instruction),
+      HloOpcode::AsyncUpdate => self.update_async_update_value_set(instruction),
+      HloOpcode::AsyncDone => self.update_async_done_value_set(instruction),
+      HloOpcode::Bitcast => self.update_bitcast_value_set(instruction),
+      HloOpcode::Domain => self.update_domain_value_set(instruction),
+      HloOpcode::Copy => self.update_copy_value_set(instruction),
+      HloOpcode::GetTupleElement => self.update_get_tuple_element_value_set(instruction),
+      HloOpcode::Tuple => self.update_tuple_value_set(instruction),
+      HloOpcode::Parameter => self.update_parameter_value_set(instruction),
+      HloOpcode::Call => self.update_call_value_set(instruction),
+      HloOpcode::While => self.update_while_value_set(instruction),
+      HloOpcode::Send => self.update_send_value_set(instruction),
+      HloOpcode::RecvDone => self.update_recv_done_value_set(instruction),
+      HloOpcode::CopyStart => self.update_copy_start_value_set(instruction),
+      HloOpcode::CopyDone => self.update_copy_done_value_set(instruction),
+      HloOpcode::Conditional => self.update_conditional_value_set(instruction),
+      HloOpcode::AllReduceDone => self.update_all_reduce_done_value_set(instruction),
       HloOpcode::CollectivePermuteStart =>
-        changed = self.update_collective_permute_start_value_set(instruction),
+        self.update_collective_permute_start_value_set(instruction),
       HloOpcode::CollectivePermuteDone =>
-        changed = self.update_collective_permute_done_value_set(instruction),
+        self.update_collective_permute_done_value_set(instruction),
       HloOpcode::OptimizationBarrier =>
-        changed = self.update_optimization_barrier_value_set(instruction),
-      _ => unimplemented!(),
+        self.update_optimization_barrier_value_set(instruction),
+      // Instruction does not forward HloValues (it defines all values in its
+      // output). No need to update.
+      _ => false
+    };
+
+    // Values forwarded by the backend hook come from the named operand.
+    let mut forwarded = vec![];
+    if let Some(forwards_value) = &self.forwards_value {
+      for index in self.value_set_indices(instruction) {
+        if let Some(forwarded_operand) = forwards_value(instruction, &index) {
+          forwarded.push((index, forwarded_operand));
+        }
+      }
+    }
+    for (index, forwarded_operand) in forwarded {
+      let operand = self.operand(instruction, forwarded_operand.operand_number as usize);
+      changed |= self.forward_value_set(
+        instruction, &index, operand, &forwarded_operand.operand_index);
     }
-
-    // TODO: forwards_value
     changed
   }
 
@@ -1045,30 +1265,15 @@ impl<'module> HloDataflowAnalysis<'module> {
   // into the instruction value set changed.
   fn update_bitcast_value_set(&mut self, bitcast: &HloInstruction) -> bool {
     debug_assert!(bitcast.opcode() == HloOpcode::Bitcast);
-    let operand_set =
-      self.get_instruction_value_set(bitcast.operand(0)).unwrap();
-    let bitcast_set =
-      self.get_instruction_value_set(bitcast).unwrap();
-
-    if self.bitcast_defines_value && operand_set != bitcast_set {
-      self.set_instruction_value_set(bitcast.clone(), operand_set.clone());
-      return true;
+    if self.bitcast_defines_value {
+      return false;
     }
-    false
+    self.forward_instruction_value_set(bitcast, self.operand(bitcast, 0))
   }
 
   fn update_call_value_set(&mut self, call: &HloInstruction) -> bool {
     debug_assert!(call.opcode() == HloOpcode::Call);
-    let value_set =
-      self.get_instruction_value_set(call).unwrap();
-    let root_value_set =
-      self.get_instruction_value_set(call.to_apply().root_instruction()).unwrap();
-
-    if value_set != root_value_set {
-      self.set_instruction_value_set(call.clone(), root_value_set.clone());
-      return true;
-    }
-    false
+    self.forward_instruction_value_set(call, self.root_of(call.to_apply()))
   }
 
   fn update_conditional_value_set(&mut self, conditional: &HloInstruction) -> bool {
@@ -1076,54 +1281,40 @@ impl<'module> HloDataflowAnalysis<'module> {
     let mut inputs = vec![];
     for j in 0..conditional.branch_count() {
       let inst_value_set =
-        self.get_instruction_value_set(conditional.branch_computation(j).root_instruction());
+        self.get_instruction_value_set(self.root_of(conditional.branch_computation(j)));
       inputs.push(inst_value_set.unwrap().clone());
     }
     if self.ssa_form {
       self.phi(conditional, &inputs)
     } else {
       self.get_mutable_instruction_value_set(conditional)
-        .as_mut().unwrap().assign_union_of(inputs)
+        .unwrap().assign_union_of(&inputs)
     }
   }
 
   fn update_copy_value_set(&mut self, copy: &HloInstruction) -> bool {
     assert_eq!(copy.opcode(), HloOpcode::Copy);
     let mut changed = false;
-    for pair in
-      self.get_mutable_instruction_value_set_pairs(copy)
-    {
-      let index = pair.0;
+    let operand = self.operand(copy, 0);
+    for index in self.value_set_indices(copy) {
       if index.is_empty() {
         // Copy shallow copies and thus defines the top-level value so nothing to update.
         continue;
       }
-      let _value_set = pair.1;
-      //let opreand_value_set =
-        //self.get_value_set(copy.operand(0), index);
-      //if value_set != opreand_value_set {
-        //pair.1 = opreand_value_set;
-        changed = true;
-      //}
+      changed |= self.forward_value_set(copy, &index, operand, &index);
     }
     changed
   }
 
   fn update_domain_value_set(&mut self, domain: &HloInstruction) -> bool {
+    // Domain instructions just forward their operand. Given that domains can
+    // have a tuple operand, we iterate through its indexes, like for copies.
+    // Unlike copies though we also propagate the top-level value.
     debug_assert!(domain.opcode() == HloOpcode::Domain);
     let mut changed = false;
-    let nodes =
-      self.get_instruction_value_set(domain).unwrap().nodes();
-    for pair in nodes {
-      let index = pair.0 as i64;
-      let value_set = &pair.1;
-      let operand_value_set =
-        self.get_value_set(domain.operand(0), &vec![index]);
-      if value_set != operand_value_set {
-        // TODO
-        //pair.1 = operand_value_set.clone();
-        changed = true;
-      }
+    let operand = self.operand(domain, 0);
+    for index in self.value_set_indices(domain) {
+      changed |= self.forward_value_set(domain, &index, operand, &index);
     }
     changed
   }
@@ -1134,68 +1325,211 @@ impl<'module> HloDataflowAnalysis<'module> {
 
     // The GetTupleElement instruction forwards the values from the specified
     // tuple element.
-    for pair in
-      self.get_mutable_instruction_value_set_pairs(gte)
-    {
-      let index = pair.0;
-      let _value_set = pair.1;
+    let operand = self.operand(gte, 0);
+    for index in self.value_set_indices(gte) {
       // The corresponding ShapeIndex of the operand is simply the GTE ShapeIndex
       // with the tuple element number prefixed.
       let mut operand_index = vec![gte.tuple_index()];
-      for i in index {
-        operand_index.push(*i);
-      }
-      //let operand_value_set =
-        //self.get_value_set(gte.operand(0), &operand_index);
-      //if value_set != operand_value_set {
-        //pair.1 = operand_value_set;
-        changed = true;
-      //}
+      operand_index.extend(&index);
+      changed |= self.forward_value_set(gte, &index, operand, &operand_index);
     }
     changed
   }
 
-  fn update_parameter_value_set(&self, _instruction: &HloInstruction) -> bool {
-    unimplemented!()
+  fn update_parameter_value_set(&mut self, parameter: &HloInstruction) -> bool {
+    assert_eq!(parameter.opcode(), HloOpcode::Parameter);
+    let module = self.module;
+    let call_graph_node = self.call_graph.get_node(parameter.parent().unwrap());
+
+    // Subcomputations called in a parallel context (eg, map) do not have dataflow
+    // from the caller operands.
+    if call_graph_node.context() == CallContext::Embedded ||
+      call_graph_node.caller_callsites().is_empty()
+    {
+      return false;
+    }
+    assert_eq!(call_graph_node.context(), CallContext::ControlFlow);
+
+    let mut inputs = vec![];
+    let mut need_phi = false;
+    for callsite in call_graph_node.caller_callsites().clone() {
+      let caller = module.instruction(callsite.computation(), callsite.instruction());
+      let caller_computation = module.computation(callsite.computation());
+      match caller.opcode() {
+        // The operand values of a call instruction are passed into the called
+        // computation's parameters.
+        HloOpcode::Call | HloOpcode::AsyncStart => {
+          let operand = caller_computation.operand(
+            caller.id(), parameter.parameter_number() as usize);
+          inputs.push(self.get_instruction_value_set(operand).unwrap().clone());
+        }
+        HloOpcode::While => {
+          // In a while instruction, the while operand (ie, the init value) and the
+          // backedge are dataflow inputs to the parameter instruction. This is the
+          // case for parameters of both the body and condition computations.
+          assert_eq!(parameter.parameter_number(), 0);
+          let operand = caller_computation.operand(caller.id(), 0);
+          inputs.push(self.get_instruction_value_set(operand).unwrap().clone());
+          // If the parameter *is not* the root, parameter state would be
+          // updated by the root, otherwise don't consider it's current state
+          // (InstructionValueSet) as we are recomputing its current state.
+          let body_root = self.root_of(caller.while_body());
+          if key_of(body_root) != key_of(parameter) {
+            inputs.push(self.get_instruction_value_set(body_root).unwrap().clone());
+          }
+          need_phi = true;
+        }
+        HloOpcode::Conditional => {
+          assert_eq!(parameter.parameter_number(), 0);
+          // Conditional has branch_count+1 operands. Operand 0 is the branch_index,
+          // operands 1 and onward are the arguments to the branch computations.
+          //
+          // If the parameter belongs to conditional's branch 0 computation, then
+          // operand 1 is forwarded to this parameter instruction. If the parameter
+          // belongs to conditional's branch 5 computation, then operand 6 is
+          // forwarded to this parameter instruction.
+          let branch = (0..caller.branch_count())
+            .find(|j| Some(caller.branch_computation(*j)) == parameter.parent());
+          assert!(branch.is_some());
+          let operand = caller_computation.operand(caller.id(), branch.unwrap() + 1);
+          inputs.push(self.get_instruction_value_set(operand).unwrap().clone());
+          need_phi = true;
+        }
+        HloOpcode::AsyncUpdate | HloOpcode::AsyncDone => {
+          let operand = caller_computation.operand(caller.id(), 0);
+          let operand_set = self.get_instruction_value_set(operand).unwrap().clone();
+          return self.get_mutable_instruction_value_set(parameter).unwrap()
+            .assign_union_of_at(&operand_set, &vec![0, parameter.parameter_number()]);
+        }
+        _ => panic!("CallContext::ControlFlow computations should only be \
+          called from call, while, conditional or async instructions")
+      }
+    }
+
+    if self.ssa_form && need_phi {
+      self.phi(parameter, &inputs)
+    } else {
+      self.get_mutable_instruction_value_set(parameter).unwrap().assign_union_of(&inputs)
+    }
   }
 
-  fn update_async_start_value_set(&self, _instruction: &HloInstruction) -> bool {
-    unimplemented!()
+  fn update_async_start_value_set(&mut self, async_start: &HloInstruction) -> bool {
+    assert_eq!(async_start.opcode(), HloOpcode::AsyncStart);
+    let mut changed = false;
+    // AsyncStart forwards the operand values to element {0} of its output.
+    for i in 0..async_start.operand_count() {
+      let operand = self.operand(async_start, i);
+      for index in self.value_set_indices(operand) {
+        if !ShapeUtil::get_subshape(operand.shape(), &index).is_array() {
+          continue;
+        }
+        let mut output_index = vec![0, i as i64];
+        output_index.extend(&index);
+        changed |= self.forward_value_set(async_start, &output_index, operand, &index);
+      }
+    }
+    if !HloInstruction::is_thread_included(
+      async_start.async_execution_thread(), &self.execution_threads)
+    {
+      return changed;
+    }
+    // AsyncStart forwards the async wrapped computation root values to element
+    // {1} of its output.
+    let root = called_computation(self.module, async_start).root_instruction();
+    for index in self.value_set_indices(root) {
+      if !ShapeUtil::get_subshape(root.shape(), &index).is_array() {
+        continue;
+      }
+      let mut output_index = vec![1];
+      output_index.extend(&index);
+      changed |= self.forward_value_set(async_start, &output_index, root, &index);
+    }
+    changed
   }
 
-  fn update_async_update_value_set(&self, _instruction: &HloInstruction) -> bool {
-    unimplemented!()
+  fn update_async_update_value_set(&mut self, async_update: &HloInstruction) -> bool {
+    assert_eq!(async_update.opcode(), HloOpcode::AsyncUpdate);
+    let mut changed = false;
+    let operand = self.operand(async_update, 0);
+    let root = if HloInstruction::is_thread_included(
+      async_update.async_execution_thread(), &self.execution_threads)
+    {
+      Some(called_computation(self.module, async_update).root_instruction())
+    } else {
+      None
+    };
+    // AsyncUpdate forwards all of the operand values to corresponding elements
+    // of its output. The destination may also be written by the wrapped
+    // computation.
+    for index in self.value_set_indices(async_update) {
+      if !ShapeUtil::get_subshape(async_update.shape(), &index).is_array() {
+        continue;
+      }
+      let mut inputs = vec![self.get_value_set(operand, &index).clone()];
+      if let Some(root) = root {
+        if index.first() == Some(&1) {
+          inputs.push(self.get_value_set(root, &index[1..].to_vec()).clone());
+        }
+      }
+      let mut value_set = HloValueSet::default();
+      value_set.assign_union_of(inputs);
+      changed |= self.assign_value_set(async_update, &index, value_set);
+    }
+    changed
   }
 
-  fn update_async_done_value_set(&self, _instruction: &HloInstruction) -> bool {
-    unimplemented!()
+  fn update_async_done_value_set(&mut self, async_done: &HloInstruction) -> bool {
+    assert_eq!(async_done.opcode(), HloOpcode::AsyncDone);
+    let mut changed = false;
+    let operand = self.operand(async_done, 0);
+    let root = if HloInstruction::is_thread_included(
+      async_done.async_execution_thread(), &self.execution_threads)
+    {
+      Some(called_computation(self.module, async_done).root_instruction())
+    } else {
+      None
+    };
+    // AsyncDone's output aliases element {1} of its operand, which holds the
+    // values of the wrapped computation root.
+    for index in self.value_set_indices(async_done) {
+      if !ShapeUtil::get_subshape(async_done.shape(), &index).is_array() {
+        continue;
+      }
+      let mut operand_index = vec![1];
+      operand_index.extend(&index);
+      let mut inputs = vec![self.get_value_set(operand, &operand_index).clone()];
+      if let Some(root) = root {
+        inputs.push(self.get_value_set(root, &index).clone());
+      }
+      let mut value_set = HloValueSet::default();
+      value_set.assign_union_of(inputs);
+      changed |= self.assign_value_set(async_done, &index, value_set);
+    }
+    changed
   }
 
   fn update_copy_start_value_set(&mut self, copy_start: &HloInstruction) -> bool {
     debug_assert!(copy_start.opcode() == HloOpcode::CopyStart);
     let mut changed = false;
-    let operand_value_set =
-      self.get_value_set(copy_start.operand(0), &vec![0]);
-    let value_set =
-      self.get_value_set(copy_start, &vec![1]);
-    if value_set != operand_value_set {
-      // TODO
-      //self.set_instruction_value_set(copy_start.clone(), operand_value_set.clone());
-      changed = true;
+    // CopyStart forwards the operand value to element {1} of its output.
+    let operand = self.operand(copy_start, 0);
+    for index in self.value_set_indices(operand) {
+      let mut output_index = vec![1];
+      output_index.extend(&index);
+      changed |= self.forward_value_set(copy_start, &output_index, operand, &index);
     }
     changed
   }
 
-  fn update_copy_done_value_set(&self, copy_done: &HloInstruction) -> bool {
+  fn update_copy_done_value_set(&mut self, copy_done: &HloInstruction) -> bool {
     assert_eq!(copy_done.opcode(), HloOpcode::CopyDone);
     let mut changed = false;
     // CopyDone forwards the operand value at {0} to element {} of its output.
-    let operand_value_set = 
-      self.get_value_set(copy_done.operand(0), &vec![0]);
-    let value_set = self.get_value_set(copy_done, &vec![]);
-    if value_set != operand_value_set {
-      //value_set = operand_value_set; // TODO
-      changed = true;
+    let operand = self.operand(copy_done, 0);
+    for index in self.value_set_indices(copy_done) {
+      let mut operand_index = vec![0];
+      operand_index.extend(&index);
+      changed |= self.forward_value_set(copy_done, &index, operand, &operand_index);
     }
     changed
   }
@@ -1206,17 +1540,9 @@ impl<'module> HloDataflowAnalysis<'module> {
     // Unlike copies though we also propagate the top-level value.
     assert_eq!(barrier.opcode(), HloOpcode::OptimizationBarrier);
     let mut changed = false;
-    for pair in
-      self.get_mutable_instruction_value_set_pairs(barrier)
-    {
-      let _index = pair.0;
-      let _value_set = pair.1;
-      //let operand_value_set =
-        //self.get_value_set(barrier.operand(0), index);
-      //if value_set != operand_value_set {
-        //value_set = operand_value_set; // TODO
-        changed = true;
-      //}
+    let operand = self.operand(barrier, 0);
+    for index in self.value_set_indices(barrier) {
+      changed |= self.forward_value_set(barrier, &index, operand, &index);
     }
     changed
   }
@@ -1225,20 +1551,12 @@ impl<'module> HloDataflowAnalysis<'module> {
     assert_eq!(recv_done.opcode(), HloOpcode::RecvDone);
     let mut changed = false;
     // RecvDone forwards the operand value at {0} to element {0} of its output.
-    for pair in
-      self.get_mutable_instruction_value_set_pairs(recv_done)
-    {
-      let index = pair.0;
-      let _value_set = pair.1;
+    let operand = self.operand(recv_done, 0);
+    for index in self.value_set_indices(recv_done) {
       if index.is_empty() || index[0] != 0 {
         continue;
       }
-      //let operand_value_set =
-        //self.get_value_set(recv_done.operand(0), index);
-      //if value_set != operand_value_set {
-        //value_set = operand_value_set // TODO
-        changed = true;
-      //}
+      changed |= self.forward_value_set(recv_done, &index, operand, &index);
     }
     changed
   }
@@ -1247,22 +1565,11 @@ impl<'module> HloDataflowAnalysis<'module> {
     assert_eq!(send.opcode(), HloOpcode::Send);
     let mut changed = false;
     // Send forwards the operand value to the output tuple at {0}.
-    for pair in
-      self.get_mutable_instruction_value_set_pairs(send)
-    {
-      let operand_index = pair.0;
-      let _operand_value_set = pair.1;
-
+    let operand = self.operand(send, 0);
+    for operand_index in self.value_set_indices(operand) {
       let mut index = vec![0];
-      for i in operand_index {
-        index.push(*i);
-      }
-
-      //let value_set = self.get_value_set(send, &index);
-      //if value_set != operand_value_set {
-        //value_set = operand_value_set; // TODO
-        changed = true;
-      //}
+      index.extend(&operand_index);
+      changed |= self.forward_value_set(send, &index, operand, &operand_index);
     }
     changed
   }
@@ -1273,22 +1580,11 @@ impl<'module> HloDataflowAnalysis<'module> {
     for i in 0..tuple.operands().len() {
       // Copy the value set(s) of each operand into the respective position in the
       // Tuple instruction's value sets.
-      for pair in
-        self.get_mutable_instruction_value_set_pairs(tuple)
-      {
-        let operand_index = pair.0;
-        let _operand_value_set = pair.1;
-
+      let operand = self.operand(tuple, i);
+      for operand_index in self.value_set_indices(operand) {
         let mut index = vec![i as i64];
-        for op_index in operand_index {
-          index.push(*op_index);
-        }
-
-        //let value_set = self.get_value_set(tuple, &index);
-        //if value_set != operand_value_set {
-          //value_set = operand_value_set; // TODO
-          changed = true;
-        //}
+        index.extend(&operand_index);
+        changed |= self.forward_value_set(tuple, &index, operand, &operand_index);
       }
     }
     changed
@@ -1296,53 +1592,37 @@ impl<'module> HloDataflowAnalysis<'module> {
 
   fn update_while_value_set(&mut self, while_value: &HloInstruction) -> bool {
     debug_assert!(while_value.opcode() == HloOpcode::While);
-    let mut inputs = vec![];
-    inputs.push(self.get_instruction_value_set(
-      while_value.while_body().root_instruction()).unwrap().clone());
-    inputs.push(self.get_instruction_value_set(
-      while_value.operand(0)).unwrap().clone());
-    
+    let inputs = vec![
+      self.get_instruction_value_set(
+        self.root_of(while_value.while_body())).unwrap().clone(),
+      self.get_instruction_value_set(
+        self.operand(while_value, 0)).unwrap().clone()
+    ];
     if self.ssa_form {
       self.phi(while_value, &inputs)
     } else {
       self.get_mutable_instruction_value_set(
-        while_value).unwrap().assign_union_of(inputs)
+        while_value).unwrap().assign_union_of(&inputs)
     }
   }
 
   fn update_add_dependency_value_set(&mut self, add_dependency: &HloInstruction) -> bool {
+    // AddDependency just forwards the value of its zero-th operand.
     debug_assert!(add_dependency.opcode() == HloOpcode::AddDependency);
-    let operand_set =
-      self.get_instruction_value_set(add_dependency.operand(0)).unwrap();
-    let add_dependency_set =
-      self.get_instruction_value_set(add_dependency).unwrap();
-    
-    if operand_set != add_dependency_set {
-      self.set_instruction_value_set(
-        add_dependency.clone(), operand_set.clone());
-      return true;
-    }
-    false
+    self.forward_instruction_value_set(add_dependency, self.operand(add_dependency, 0))
   }
 
-  fn update_all_gather_start_value_set(&self, all_gather_start: &HloInstruction) -> bool {
+  fn update_all_gather_start_value_set(&mut self, all_gather_start: &HloInstruction) -> bool {
     assert_eq!(all_gather_start.opcode(), HloOpcode::AllGatherStart);
     let mut changed = false;
     // AllGatherStart forwards the operand values to element {0} of its output.
     for i in 0..all_gather_start.operand_count() {
-      let operand_value_set =
-        self.get_value_set(all_gather_start.operand(i), &vec![]);
-      
+      let operand = self.operand(all_gather_start, i);
       let mut output_index = vec![0];
       if all_gather_start.operand_count() > 1 {
         output_index.push(i as i64);
       }
-
-      let value_set = self.get_value_set(all_gather_start, &output_index);
-      if value_set != operand_value_set {
-        //value_set = operand_value_set; // TODO
-        changed = true;
-      }
+      changed |= self.forward_value_set(all_gather_start, &output_index, operand, &vec![]);
     }
     changed
   }
@@ -1354,24 +1634,15 @@ impl<'module> HloDataflowAnalysis<'module> {
     // AllGatherDone forwards the operand value at {1} to its output. If the
     // output is a tuple, then that tuple is defined by all-gather-done, so
     // only update the value set for tuple leaf elements (arrays).
-    for pair in
-      self.get_mutable_instruction_value_set_pairs(all_gather_done)
-    {
-      let output_index = pair.0;
-      let _value_set = pair.1;
-      if !ShapeUtil::get_subshape(all_gather_done.shape(), output_index).is_array() {
+    let operand = self.operand(all_gather_done, 0);
+    for output_index in self.value_set_indices(all_gather_done) {
+      if !ShapeUtil::get_subshape(all_gather_done.shape(), &output_index).is_array() {
         continue;
       }
       let mut operand_index = vec![1];
-      for i in output_index {
-        operand_index.push(*i);
-      }
-      //let operand_value_set =
-        //self.get_value_set(all_gather_done.operand(0), &operand_index);
-      //if value_set != operand_value_set {
-        //value_set = operand_value_set; // TODO
-        changed = true;
-      //}
+      operand_index.extend(&output_index);
+      changed |= self.forward_value_set(
+        all_gather_done, &output_index, operand, &operand_index);
     }
     changed
   }
@@ -1381,75 +1652,75 @@ impl<'module> HloDataflowAnalysis<'module> {
     let mut changed = false;
 
     // AllReduceDone forwards its only operand.
-    for pair in
-      self.get_mutable_instruction_value_set_pairs(all_reduce_done)
-    {
-      let output_index = pair.0;
-      let _value_set = pair.1;
-
-      let mut operand_index = vec![];
-      for i in output_index {
-        operand_index.push(*i);
-      }
-
-      //let operand_value_set =
-        //self.get_value_set(all_reduce_done.operand(0), &operand_index);
-      //if value_set != operand_value_set {
-        //value_set = operand_value_set; // TODO
-        changed = true;
-      //}
+    let operand = self.operand(all_reduce_done, 0);
+    for output_index in self.value_set_indices(all_reduce_done) {
+      changed |= self.forward_value_set(
+        all_reduce_done, &output_index, operand, &output_index);
     }
     changed
   }
 
-  fn update_collective_permute_start_value_set(&self, c_perm_start: &HloInstruction) -> bool {
+  fn update_collective_permute_start_value_set(
+    &mut self, c_perm_start: &HloInstruction) -> bool
+  {
     assert_eq!(c_perm_start.opcode(), HloOpcode::CollectivePermuteStart);
-    let changed = false;
+    let mut changed = false;
 
     // CollectivePermuteStart forwards the operand value to element {0} of its
     // output.
-    
-    // TODO
+    let operand = self.operand(c_perm_start, 0);
+    for index in self.value_set_indices(c_perm_start) {
+      if index.first() != Some(&0) {
+        continue;
+      }
+      changed |= self.forward_value_set(c_perm_start, &index, operand, &index[1..].to_vec());
+    }
     changed
   }
 
-  fn update_collective_permute_done_value_set(&self, _instruction: &HloInstruction) -> bool {
-    unimplemented!()
-  }
+  fn update_collective_permute_done_value_set(
+    &mut self, c_perm_done: &HloInstruction) -> bool
+  {
+    assert_eq!(c_perm_done.opcode(), HloOpcode::CollectivePermuteDone);
+    let mut changed = false;
 
-  fn propagate() {}
+    // CollectivePermuteDone forwards the operand value at {1} to its output. If
+    // the output is a tuple, then that tuple is defined by
+    // collective-permute-done, so only update the value set for tuple leaf
+    // elements (arrays).
+    let operand = self.operand(c_perm_done, 0);
+    for output_index in self.value_set_indices(c_perm_done) {
+      if !ShapeUtil::get_subshape(c_perm_done.shape(), &output_index).is_array() {
+        continue;
+      }
+      let mut operand_index = vec![1];
+      operand_index.extend(&output_index);
+      changed |= self.forward_value_set(c_perm_done, &output_index, operand, &operand_index);
+    }
+    changed
+  }
 
   // Returns the result of the SSA Phi function applied to the given inputs at
   // the given instruction.
-  fn phi(&mut self, instruction: &HloInstruction, inputs: &Vec<InstructionValueSet>) -> bool {
+  fn phi(&mut self, instruction: &HloInstruction, inputs: &[InstructionValueSet]) -> bool {
     assert!(self.ssa_form);
-    println!("phi({:?})", instruction.name());
-
-    if self.bitcast_defines_value {
-
-    } else {
-        
+    for input in inputs {
+      debug_assert!(ShapeUtil::compatible(instruction.shape(), input.shape()));
     }
 
     let mut changed = false;
-    for pair in
-      self.get_mutable_instruction_value_set_pairs(instruction)
-    {
-      let index = pair.0;
-      let value_set = pair.1;
+    for index in self.value_set_indices(instruction) {
+      let value_set = self.get_value_set(instruction, &index);
 
       // Positions with phi values should never have more than one value in the
       // value set.
-      assert!(value_set.values().len() > 1);
-      let mut current_value: Option<&HloValue> = None;
-      if value_set.values().len() == 1 {
-        current_value = Some(&value_set.values()[0]);
-      }
+      assert!(value_set.values().len() <= 1);
+      let current_value = value_set.values().first().cloned();
 
       // Construct a vector of value IDs of the inputs.
       let mut input_value_ids = vec![];
       for input in inputs {
-        for value in input.element(index).values() {
+        for value in input.element(&index).values() {
           input_value_ids.push(value.id());
         }
       }
@@ -1457,47 +1728,255 @@ impl<'module> HloDataflowAnalysis<'module> {
       // Remove the existing phi value (if it exists). The phi can be its own
       // input, for example, in while body parameters where the body passes
       // through the parameter value.
-      let current_value_defined_here = current_value.is_some() &&
-        current_value.unwrap().defining_instruction() == instruction &&
-        current_value.unwrap().defining_index() == index;
+      let current_value_defined_here = match &current_value {
+        Some(value) => key_of(value.defining_instruction()) == key_of(instruction) &&
+          value.defining_index() == &index,
+        None => false
+      };
 
-      println!("after input_value_ids.size = {:?}", input_value_ids.len());
       if input_value_ids.is_empty() {
         // A value set which has at least one element should never have its value
         // set reduced to zero elements. During dataflow value sets only can go
         // from empty to non-empty, not the reverse.
-        assert_eq!(value_set.values().len(), 0);
+        assert!(current_value.is_none(),
+          "Instruction {} at index {} previously had non-empty value set.",
+          instruction.name(), shape_index_to_string(&index));
       } else if input_value_ids.len() == 1 {
         // Only a single value reaches this point. There should be no phi, and
         // this value set should contain this single value.
-        //let new_value = self.get_value(input_value_ids[0]);
-        if current_value.is_none() {
-          //value_set.clear();
-          //value_set.add_value(new_value);
-          //changed = true;
-        //} else if current_value != Some(new_value) {
+        if current_value.as_ref().map(|value| value.id()) != Some(input_value_ids[0]) {
           if current_value_defined_here {
-            //self.mark_value_for_deletion(current_value.unwrap().id());
+            // Remove the existing phi.
+            self.mark_value_for_deletion(current_value.unwrap().id());
           }
-          //value_set.clear();
-          //value_set.add_value(new_value);
+          let new_value = self.get_value(input_value_ids[0]).clone();
+          self.assign_value_set(instruction, &index, HloValueSet::new(vec![new_value]));
           changed = true;
         }
       } else {
         // Multiple distinct values reach this point. A phi value is
         // necessary.
-        assert!(input_value_ids.len() > 1);
         let phi_defined_here = current_value_defined_here &&
-          current_value.unwrap().is_phi();
-        if current_value.is_none() || !phi_defined_here {
-
-        } else if phi_defined_here {
-            
+          current_value.as_ref().unwrap().is_phi();
+        if !phi_defined_here {
+          let phi = self.new_hlo_value(instruction, &index, true);
+          self.assign_value_set(instruction, &index, HloValueSet::new(vec![phi.clone()]));
+          // Register the phi into phi graph.
+          let phi_inputs: Vec<&HloValue> = input_value_ids.iter()
+            .map(|id| self.values.get(id).unwrap())
+            .collect();
+          self.phi_graph.register_phi(&phi, &phi_inputs);
+          changed = true;
+        } else {
+          let phi = current_value.unwrap();
+          let phi_inputs: Vec<&HloValue> = input_value_ids.iter()
+            .map(|id| self.values.get(id).unwrap())
+            .collect();
+          if !self.phi_graph.inputs_equal_to(&phi, &phi_inputs) {
+            // Update phi inputs.
+            self.phi_graph.register_phi(&phi, &phi_inputs);
+            changed = true;
+          }
         }
       }
     }
     changed
   }
+}
+
+#[cfg(test)]
+mod tests {
+  use service::test_utils::{find_in, parse_module};
+  use super::*;
+
+  fn run_analysis(module: &HloModule, ssa_form: bool) -> HloDataflowAnalysis<'_> {
+    HloDataflowAnalysis::run(module, ssa_form, false, None, None, &HashSet::new()).unwrap()
+  }
+
+  #[test]
+  fn test_tuple_and_get_tuple_element() {
+    let text = "
+      HloModule m
+
+      ENTRY entry {
+        p0 = f32[4] parameter(0)
+        p1 = f32[4] parameter(1)
+        tuple = (f32[4], f32[4]) tuple(p0, p1)
+        gte = f32[4] get-tuple-element(tuple), index=1
+        ROOT add = f32[4] add(gte, p0)
+      }";
+    let module = parse_module(text);
+    let analysis = run_analysis(&module, false);
+    let p1 = find_in(&module, "entry", "p1");
+    let tuple = find_in(&module, "entry", "tuple");
+    let gte = find_in(&module, "entry", "gte");
+
+    assert_eq!(analysis.value_count(), 4);
+    assert!(analysis.value_is_defined_at(tuple, &vec![]));
+    assert!(!analysis.value_is_defined_at(tuple, &vec![1]));
+    assert!(!analysis.value_is_defined_at(gte, &vec![]));
+    assert_eq!(analysis.get_unique_value_at(gte, &vec![]),
+      analysis.get_value_defined_at(p1, &vec![]));
+
+    // The value of p1 appears at the parameter, the tuple and the gte, but
+    // only the add uses it.
+    let value = analysis.get_value_defined_at(p1, &vec![]);
+    assert_eq!(value.positions().len(), 3);
+    assert_eq!(value.get_uses().len(), 1);
+  }
 
-  fn update_positions_of_values_at() {}
-}
\ No newline at end of file
+  #[test]
+  fn test_while_non_ssa_and_ssa() {
+    let text = "
+      HloModule m
+
+      body {
+        param = (f32[], f32[]) parameter(0)
+        gte0 = f32[] get-tuple-element(param), index=0
+        gte1 = f32[] get-tuple-element(param), index=1
+        add = f32[] add(gte0, gte1)
+        ROOT tuple = (f32[], f32[]) tuple(gte0, add)
+      }
+
+      cond {
+        param = (f32[], f32[]) parameter(0)
+        ROOT c = pred[] constant(false)
+      }
+
+      ENTRY entry {
+        c0 = f32[] constant(1)
+        c1 = f32[] constant(2)
+        init = (f32[], f32[]) tuple(c0, c1)
+        ROOT while = (f32[], f32[]) while(init), condition=cond, body=body
+      }";
+    let module = parse_module(text);
+    let while_inst = find_in(&module, "entry", "while");
+    let c0 = find_in(&module, "entry", "c0");
+
+    let analysis = run_analysis(&module, false);
+    // Element 0 is passed through the loop unchanged, element 1 merges the
+    // initial value with the value computed by the body.
+    assert_eq!(analysis.get_value_set(while_inst, &vec![0]).values().len(), 1);
+    assert_eq!(analysis.get_value_set(while_inst, &vec![1]).values().len(), 2);
+    assert!(!analysis.value_is_defined_at(while_inst, &vec![1]));
+
+    let analysis = run_analysis(&module, true);
+    // In SSA form the pass-through element is simplified to the initial value
+    // and the loop-carried element is a phi.
+    assert!(!analysis.value_is_defined_at(while_inst, &vec![0]));
+    assert_eq!(analysis.get_unique_value_at(while_inst, &vec![0]),
+      analysis.get_value_defined_at(c0, &vec![]));
+    assert!(analysis.value_is_defined_at(while_inst, &vec![1]));
+    assert!(analysis.get_value_defined_at(while_inst, &vec![1]).is_phi());
+    assert!(analysis.get_unique_value_at(while_inst, &vec![0]).live_out_of_module());
+  }
+
+  #[test]
+  fn test_conditional_phi() {
+    let text = "
+      HloModule m
+
+      true_branch {
+        p = f32[] parameter(0)
+        ROOT neg = f32[] negate(p)
+      }
+
+      false_branch {
+        p = f32[] parameter(0)
+        ROOT copy = f32[] copy(p)
+      }
+
+      ENTRY entry {
+        p0 = pred[] parameter(0)
+        x = f32[] parameter(1)
+        ROOT cond = f32[] conditional(p0, x, x), true_computation=true_branch, false_computation=false_branch
+      }";
+    let module = parse_module(text);
+    let cond = find_in(&module, "entry", "cond");
+
+    let analysis = run_analysis(&module, false);
+    assert_eq!(analysis.get_value_set(cond, &vec![]).values().len(), 2);
+    assert!(!analysis.value_is_defined_at(cond, &vec![]));
+
+    let analysis = run_analysis(&module, true);
+    assert!(analysis.value_is_defined_at(cond, &vec![]));
+    assert!(analysis.get_value_defined_at(cond, &vec![]).is_phi());
+    // The branch parameters forward the conditional operand.
+    let x = find_in(&module, "entry", "x");
+    let param = find_in(&module, "true_branch", "p");
+    assert_eq!(analysis.get_unique_value_at(param, &vec![]),
+      analysis.get_value_defined_at(x, &vec![]));
+  }
+
+  #[test]
+  fn test_copy_start_and_copy_done() {
+    let text = "
+      HloModule m
+
+      ENTRY entry {
+        p0 = f32[4] parameter(0)
+        copy-start = (f32[4], f32[4], u32[]) copy-start(p0)
+        ROOT copy-done = f32[4] copy-done(copy-start)
+      }";
+    let module = parse_module(text);
+    let analysis = run_analysis(&module, false);
+    let p0 = find_in(&module, "entry", "p0");
+    let copy_start = find_in(&module, "entry", "copy-start");
+    let copy_done = find_in(&module, "entry", "copy-done");
+
+    assert!(analysis.value_is_defined_at(copy_start, &vec![]));
+    assert!(analysis.value_is_defined_at(copy_start, &vec![0]));
+    assert!(!analysis.value_is_defined_at(copy_start, &vec![1]));
+    assert!(analysis.value_is_defined_at(copy_start, &vec![2]));
+    assert!(!analysis.value_is_defined_at(copy_done, &vec![]));
+    assert_eq!(analysis.get_unique_value_at(copy_start, &vec![1]),
+      analysis.get_value_defined_at(p0, &vec![]));
+    assert_eq!(analysis.get_unique_value_at(copy_done, &vec![]),
+      analysis.get_value_defined_at(copy_start, &vec![0]));
+  }
+
+  #[test]
+  fn test_bitcast_defines_value() {
+    let text = "
+      HloModule m
+
+      ENTRY entry {
+        p0 = f32[4] parameter(0)
+        ROOT bitcast = f32[2,2] bitcast(p0)
+      }";
+    let module = parse_module(text);
+    let bitcast = find_in(&module, "entry", "bitcast");
+
+    let analysis = HloDataflowAnalysis::run(
+      &module, false, true, None, None, &HashSet::new()).unwrap();
+    assert!(analysis.value_is_defined_at(bitcast, &vec![]));
+    assert_eq!(analysis.value_count(), 2);
+
+    let analysis = run_analysis(&module, false);
+    assert!(!analysis.value_is_defined_at(bitcast, &vec![]));
+    assert_eq!(analysis.value_count(), 1);
+  }
+
+  #[test]
+  fn test_dynamic_update_slice_in_place() {
+    let text = "
+      HloModule m
+
+      ENTRY entry {
+        p0 = f32[8] parameter(0)
+        update = f32[2] parameter(1)
+        index = s32[] parameter(2)
+        ROOT dus = f32[8] dynamic-update-slice(p0, update, index)
+      }";
+    let module = parse_module(text);
+    let entry = module.entry_computation().unwrap();
+    let p0 = find_in(&module, "entry", "p0");
+    let dus = find_in(&module, "entry", "dus");
+
+    let pairs = HloDataflowAnalysis::get_in_place_input_output_pairs(entry, dus);
+    assert_eq!(pairs, vec![(HloOperandIndex::default(), vec![])]);
+
+    let analysis = run_analysis(&module, false);
+    assert!(analysis.can_share_operand_buffer_with_user(p0, &vec![], dus, &vec![]));
+  }
+}
@@ -5,7 +5,7 @@ use std::collections::HashMap;
 use hlo::{
   hlo_computation::HloComputation,
   hlo_instruction::HloInstruction,
-  hlo_module::HloModule,
+  hlo_module::{ComputationId, HloModule},
   hlo_opcode::HloOpcode
 };
 
@@ -52,7 +52,7 @@ pub struct Channel {
 pub struct HloModuleGroupMetadata<'module> {
   companion_sets: Vec<Vec<HloInstruction>>,
   companion_set_index: HashMap<HloInstruction, i64>,
-  tracked_instructions: HashMap<HloComputation, TrackedInstruction>,
+  tracked_instructions: HashMap<ComputationId, TrackedInstruction>,
   tracked_instructions_comms: HashMap<HloInstruction, Vec<HloInstruction>>,
   channels: Vec<Channel>,
   channel_id_map: HashMap<i64, i64>,
@@ -82,17 +82,21 @@ impl<'module> HloModuleGroupMetadata<'module> {
 
   // Returns true if the instruction is either a cross-module all-reduce
   // instruction in a non-spmd module.
-  pub fn is_non_spmd_cross_module_all_reduce(&self, instruction: &HloInstruction) -> bool {
+  pub fn is_non_spmd_cross_module_all_reduce(
+    &self, module: &HloModule, instruction: &HloInstruction) -> bool
+  {
     instruction.is_cross_module_all_reduce() &&
-    !instruction.get_module().as_ref().unwrap().config().use_spmd_partitioning()
+    !module.config().use_spmd_partitioning()
   }
 
   // Returns true if the instruction is either a channel instruction, a
   // cross-module non-spmd all-reduce instruction, or a companion instruction.
-  pub fn instruction_communicates(&self, instruction: &HloInstruction) -> bool {
+  pub fn instruction_communicates(
+    &self, module: &HloModule, instruction: &HloInstruction) -> bool
+  {
     self.is_channel_instruction(instruction) ||
     self.is_companion_instruction(instruction) ||
-    self.is_non_spmd_cross_module_all_reduce(instruction)
+    self.is_non_spmd_cross_module_all_reduce(module, instruction)
   }
 
   // Returns the Channel instance for the given channel id.
@@ -133,11 +137,12 @@ impl<'module> HloModuleGroupMetadata<'module> {
     &self, instruction: &HloInstruction) -> Vec<&TrackedInstruction>
   {
     let mut path = vec![];
-    let mut parent = instruction.parent();
+    let mut parent = instruction.parent().unwrap();
     let mut companion =
       self.get_tracked_instruction(parent);
     while companion.is_some() {
-      parent = companion.as_ref().unwrap().instruction().as_ref().unwrap().parent();
+      parent = companion.as_ref().unwrap().instruction().as_ref().unwrap()
+        .parent().unwrap();
       path.push(companion.unwrap());
       companion = self.get_tracked_instruction(parent);
     }
@@ -175,10 +180,12 @@ impl<'module> HloModuleGroupMetadata<'module> {
   }
 
   // Retrieves the device an instruction is assigned to.
-  pub fn get_instruction_device(&self, instruction: &HloInstruction) -> Option<i64> {
+  pub fn get_instruction_device(
+    &self, module: &HloModule, instruction: &HloInstruction) -> Option<i64>
+  {
     let mut device = instruction.sharding_unique_device();
     if device.is_none() {
-      device = Some(self.get_module_id(instruction.get_module().as_ref().unwrap()));
+      device = Some(self.get_module_id(module));
     }
     device
   }
@@ -232,13 +239,11 @@ impl<'module> HloModuleGroupMetadata<'module> {
   // Checks whether a communicating instruction is placed in a valid position
   // within the graph.
   fn check_communicating_instruction(
-    &self, instruction: &HloInstruction) -> Result<(), String>
+    &self, module: &HloModule, instruction: &HloInstruction) -> Result<(), String>
   {
-    let computation = instruction.parent();
-    let module = computation.parent();
-    if module.is_some() &&
-       module.as_ref().unwrap().entry_computation() == Some(computation) ||
-       self.tracked_instructions.contains_key(computation)
+    let computation = instruction.parent().unwrap();
+    if module.entry_computation_id() == computation ||
+       self.tracked_instructions.contains_key(&computation)
     {
       return Ok(());
     }
@@ -249,8 +254,8 @@ impl<'module> HloModuleGroupMetadata<'module> {
 
   // Retrieves a pointer to the stored TrackedInstruction associated with a
   // tracked computation.
-  fn get_tracked_instruction(&self, computation: &HloComputation) -> Option<&TrackedInstruction> {
-    self.tracked_instructions.get(computation)
+  fn get_tracked_instruction(&self, computation: ComputationId) -> Option<&TrackedInstruction> {
+    self.tracked_instructions.get(&computation)
   }
 
   fn dump_collected_stats() {}
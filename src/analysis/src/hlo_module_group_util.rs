@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+use std::collections::VecDeque;
+
 use hlo::{hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_reachability::HloReachabilityMap};
 
 use crate::hlo_module_group_metadata::HloModuleGroupMetadata;
@@ -66,7 +68,7 @@ impl<'module> HloModuleGroupUtil<'module> {
   // Below reachability utils resemble in HloComputation, except that they can
   // handle instructions across multiple computations.
   pub fn compute_reachability(&self, _computations: &Vec<HloComputation>) -> HloReachabilityMap {
-    let post_order = vec![];
+    let post_order: Vec<&HloInstruction> = vec![];
     /*
     let visit_func =
       |_instruction: &HloInstruction, instruction_group: &Vec<HloInstruction>| -> Result<(), String>
@@ -87,7 +89,7 @@ impl<'module> HloModuleGroupUtil<'module> {
         false);
     }
     */
-    let reachability = HloReachabilityMap::new(post_order);
+    let reachability = HloReachabilityMap::new(&post_order);
     // TODO
     reachability
   }
@@ -97,20 +99,16 @@ impl<'module> HloModuleGroupUtil<'module> {
   pub fn update_reachability_through_instruction(
     &self, instruction: &HloInstruction, reachability_map: &mut HloReachabilityMap)
   {
-    let mut worklist = vec![];
-    worklist.push(instruction.clone());
-
-    while !worklist.is_empty() {
-      let item = worklist.first().unwrap();
-      //worklist.pop(); // ??
-      if reachability_map.set_reachability_to_union(
-        self.global_predecessors(item), item)
-      {
-        for successor in &self.global_successors(item) {
-          worklist.push(successor.clone());
-        }  
+    let mut worklist = VecDeque::new();
+    worklist.push_back(instruction.clone());
+
+    while let Some(item) = worklist.pop_front() {
+      let predecessors = self.global_predecessors(&item);
+      let inputs: Vec<&HloInstruction> = predecessors.iter().collect();
+      if reachability_map.set_reachability_to_union(&inputs, &item) {
+        worklist.extend(self.global_successors(&item));
       }
-    };
+    }
   }
 
   fn cycle_to_string() {}
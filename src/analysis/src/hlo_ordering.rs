@@ -1,11 +1,12 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use hlo::{
-  hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_module::HloModule,
-  hlo_opcode::HloOpcode, hlo_reachability::HloReachabilityMap,
-  hlo_schdule::{HloInstructionSequence, HloSchedule}, hlo_value::{HloUse, HloValue}
+  hlo_computation::HloComputation, hlo_instruction::{HloInstruction, InstructionId},
+  hlo_module::{ComputationId, HloModule}, hlo_opcode::HloOpcode,
+  hlo_reachability::HloReachabilityMap, hlo_schdule::HloInstructionSequence,
+  hlo_value::{HloUse, HloValue}
 };
 
 use service::call_graph::CallGraph;
@@ -24,18 +25,116 @@ pub enum ExecutionConstraint {
 
 // Base class for describing a partial ordering of HLO instructions.
 pub struct HloOrdering<'module> {
-  module: HloModule,
+  module: &'module HloModule,
   call_graph: CallGraph<'module>,
+  // The position of each instruction in the total order of its computation,
+  // for the computations which are sequentially ordered.
+  order_position: HashMap<ComputationId, HashMap<InstructionId, i64>>,
 }
 
 impl<'module> HloOrdering<'module> {
-  pub fn new() {}
+  // Creates an ordering in which instructions of the same computation are
+  // unordered.
+  pub fn new(module: &'module HloModule) -> Self {
+    HloOrdering {
+      module: module,
+      call_graph: CallGraph::build(module, &HashSet::new()),
+      order_position: HashMap::new()
+    }
+  }
 
   // Return the execution constraint between a and b.
   pub fn get_execution_constraint(
-    &self, _a: &HloInstruction, _b: &HloInstruction) -> ExecutionConstraint
+    &self, a: &HloInstruction, b: &HloInstruction) -> ExecutionConstraint
   {
-    unimplemented!()
+    // 'a' and 'b' may be in different computations. In this case, find the
+    // callgraph ancestor instructions which call (potentially transitively) the
+    // computations containing 'a' and 'b' and use these ancestor instructions
+    // to compare order.
+    if is_same_instruction(a, b) || self.is_async_wrapped(a, b) ||
+      self.is_async_wrapped(b, a)
+    {
+      return ExecutionConstraint::IsSame;
+    }
+    let (a_ancestor, b_ancestor) =
+      self.call_graph.nearest_ancestors_in_same_computation(
+        (a.parent().unwrap(), a.id()), (b.parent().unwrap(), b.id()));
+    if a_ancestor.is_none() {
+      println!("Ancestors in a common computation could not be found between {} and {}",
+        a.name(), b.name());
+      return ExecutionConstraint::Unordered;
+    }
+    assert!(b_ancestor.is_some());
+    let a_ancestor = self.module.instruction(a_ancestor.unwrap().0, a_ancestor.unwrap().1);
+    let b_ancestor = self.module.instruction(b_ancestor.unwrap().0, b_ancestor.unwrap().1);
+    assert_eq!(a_ancestor.parent(), b_ancestor.parent());
+
+    // If the common ancestor is a while instruction there is an additional
+    // ordering criteria which may apply. The condition computation is considered
+    // to execute before the body computation so if 'a' is in the condition and
+    // 'b' is in the body, then 'a' executes before 'b'.
+    if is_same_instruction(a_ancestor, b_ancestor) &&
+      a_ancestor.opcode() == HloOpcode::While
+    {
+      let body = a_ancestor.while_body();
+      let condition = a_ancestor.while_condition();
+      if self.call_graph.instruction_is_nested_in(a, condition) &&
+        self.call_graph.instruction_is_nested_in(b, body)
+      {
+        return ExecutionConstraint::RunBeforeStart;
+      }
+    }
+
+    // If the common ancestor is a conditional instruction, even though there are
+    // no ordering criteria for branches, they are exclusive of each other.
+    if is_same_instruction(a_ancestor, b_ancestor) &&
+      a_ancestor.opcode() == HloOpcode::Conditional
+    {
+      let mut a_branch = None;
+      let mut b_branch = None;
+      for j in 0..a_ancestor.branch_count() {
+        if self.call_graph.instruction_is_nested_in(a, a_ancestor.branch_computation(j)) {
+          a_branch = Some(j);
+        }
+        if self.call_graph.instruction_is_nested_in(b, a_ancestor.branch_computation(j)) {
+          b_branch = Some(j);
+        }
+      }
+      match (a_branch, b_branch) {
+        // If neither a nor b is inside the branches they both are the ancestor.
+        (None, None) => return ExecutionConstraint::IsSame,
+        // If 'b' is the conditional ancestor, and 'a' is within a branch
+        // computation, 'a' executes before 'b'.
+        (Some(_), None) => return ExecutionConstraint::RunBeforeEnd,
+        (None, Some(_)) => return ExecutionConstraint::RunAfter,
+        (Some(a_branch), Some(b_branch)) => {
+          if a_branch < b_branch {
+            return ExecutionConstraint::RunExclusiveBefore;
+          }
+          if b_branch < a_branch {
+            return ExecutionConstraint::RunExclusiveAfter;
+          }
+        }
+      }
+    }
+
+    if self.executes_before_in_same_computation(a_ancestor, b_ancestor) {
+      return ExecutionConstraint::RunBeforeStart;
+    }
+    if self.executes_before_in_same_computation(b_ancestor, a_ancestor) {
+      return ExecutionConstraint::RunAfter;
+    }
+    ExecutionConstraint::Unordered
+  }
+
+  // Returns true if 'b' is the instruction wrapped by the asynchronous
+  // instruction 'a'.
+  fn is_async_wrapped(&self, a: &HloInstruction, b: &HloInstruction) -> bool {
+    if !a.is_asynchronous() {
+      return false;
+    }
+    let wrapped = self.module.computation(a.called_computations()[0]).root_instruction();
+    is_same_instruction(wrapped, b)
   }
 
   // Returns true if instruction 'a' executes befoere instruction 'b'.
@@ -55,14 +154,14 @@ impl<'module> HloOrdering<'module> {
   // given ordering.
   pub fn is_defined_before(&self, a: &HloValue, b: &HloValue) -> bool {
     // Entry parameter shoould always be defined before other instructions.
-    let module = b.defining_instruction().get_module().as_ref().unwrap();
-    if b.defining_instruction().parent() == module.entry_computation().unwrap() &&
+    let entry = Some(self.module.entry_computation_id());
+    if b.defining_instruction().parent() == entry &&
        b.defining_instruction().opcode() == HloOpcode::Parameter
     {
       return false;
     }
 
-    if a.defining_instruction().parent() == module.entry_computation().unwrap() &&
+    if a.defining_instruction().parent() == entry &&
        a.defining_instruction().opcode() == HloOpcode::Parameter
     {
       return true;
@@ -75,13 +174,13 @@ impl<'module> HloOrdering<'module> {
     if is_body_or_condition_phi(a) &&
       !is_body_or_condition_phi(b) &&
        self.call_graph.instruction_is_nested_in(
-        b.defining_instruction(), a.defining_instruction().parent())
+        b.defining_instruction(), a.defining_instruction().parent().unwrap())
     {
       return true;
     }
     if is_body_or_condition_phi(b) &&
        self.call_graph.instruction_is_nested_in(
-        a.defining_instruction(), b.defining_instruction().parent())
+        a.defining_instruction(), b.defining_instruction().parent().unwrap())
     {
       return false;
     }
@@ -139,7 +238,7 @@ impl<'module> HloOrdering<'module> {
           // place computation).
           if use_is_always_before_def_in_same_instr ||
              dataflow.can_share_operand_buffer_with_user(
-              use_.instruction.mutable_operand(use_.operand_number as usize).unwrap(),
+              self.operand(&use_.instruction, use_.operand_number as usize),
               &use_.operand_index_vec,
               value.defining_instruction(),
               value.defining_index())
@@ -178,7 +277,7 @@ impl<'module> HloOrdering<'module> {
           // from its operand (CollectivePermuteStart).
           if use_is_always_before_def_in_same_instr &&
             use_.instruction.opcode() == HloOpcode::CollectivePermuteDone &&
-            use_.instruction.operand(0) == value.instruction()
+            self.operand(&use_.instruction, 0) == value.instruction()
           {
             return true;
           }
@@ -200,7 +299,8 @@ impl<'module> HloOrdering<'module> {
           value.defining_instruction(), blitz_while.while_condition())
         {
           if value.defining_instruction() !=
-            blitz_while.while_condition().parameter_instruction(0).unwrap()
+            self.module.computation(blitz_while.while_condition())
+              .parameter_instruction(0).unwrap()
           {
             println!("  use is while {:?} and def is in condition and is not the parameter.",
               blitz_while.name());
@@ -240,12 +340,16 @@ impl<'module> HloOrdering<'module> {
       }
       // The use at an async call occurs before values that are defined in the
       // called computation of the async wrapped instruction.
-      if use_.instruction.is_asynchronous() &&
-         use_.instruction.async_wrapped_opcode() == HloOpcode::Call
-      {
+      let async_wrapped = if use_.instruction.is_asynchronous() {
+        Some(self.module.computation(use_.instruction.called_computations()[0])
+          .root_instruction())
+      } else {
+        None
+      };
+      if let Some(wrapped) = async_wrapped.filter(|w| w.opcode() == HloOpcode::Call) {
         let async_ = &use_.instruction;
         if self.call_graph.instruction_is_nested_in(
-          value.defining_instruction(), async_.async_wrapped_instruction().to_apply())
+          value.defining_instruction(), wrapped.to_apply())
         {
           println!("  use is async {:?} and def is in called computation", async_.name());
           return true;
@@ -265,11 +369,13 @@ impl<'module> HloOrdering<'module> {
             value.defining_instruction(), conditional.branch_computation(j))
           {
             if dataflow.value_is_defined_at(
-              conditional.operand(use_.operand_number as usize), &vec![0])
+              self.operand(conditional, use_.operand_number as usize), &vec![0])
             {
               for value_use in value.get_uses() {
                 println!("def have use: {:?}.", value_use.to_string());
-                if &value_use.instruction == value_use.instruction.parent().root_instruction() {
+                let use_parent =
+                  self.module.computation(value_use.instruction.parent().unwrap());
+                if use_parent.is_root(value_use.instruction.id()) {
                   println!("def use is conditional root.");
                   has_escaped_use_in_conditional = true;
                   break;
@@ -324,7 +430,8 @@ impl<'module> HloOrdering<'module> {
   {
     println!("live_range_strictly_before(a={:?}. b={:?})",
       a.to_short_string(), b.to_short_string());
-    println!("Parent: {:?}", a.instruction().parent().to_string());
+    println!("Parent: {:?}",
+      self.module.computation(a.instruction().parent().unwrap()).name());
 
     if !self.is_defined_before(a, b) {
       println!("{:?} not defined before {:?}.", a.to_short_string(), b.to_short_string());
@@ -341,9 +448,9 @@ impl<'module> HloOrdering<'module> {
     // until the end of the computation and can never be strictly before another
     // buffer nested in the same computation.
     for pos in a.positions() {
-      if pos.instruction.parent().root_instruction() == &pos.instruction &&
-        self.call_graph().instruction_is_nested_in(
-          b.instruction(), pos.instruction.parent())
+      let parent = pos.instruction.parent().unwrap();
+      if self.module.computation(parent).is_root(pos.instruction.id()) &&
+        self.call_graph().instruction_is_nested_in(b.instruction(), parent)
       {
         return false;
       }
@@ -367,7 +474,7 @@ impl<'module> HloOrdering<'module> {
       return false;
     }
 
-    if a.is_root_of(b.instruction().parent()) {
+    if a.is_root_of(self.module.computation(b.instruction().parent().unwrap())) {
       println!("{:?} is live out of computation and defined before {:?} which is
         in same computation", a.to_short_string(), b.to_short_string());
       return false;
@@ -379,9 +486,26 @@ impl<'module> HloOrdering<'module> {
   // Returns the sequential instruction order for the given computation, or
   // none if the computation does not have a sequential ordering.
   pub fn sequential_order(
-    &self, _computation: &HloComputation) -> HloInstructionSequence
+    &self, computation: &HloComputation) -> Option<&'module HloInstructionSequence>
   {
-    unimplemented!()
+    if !self.order_position.contains_key(&computation.id()?) {
+      return None;
+    }
+    self.module.schedule().sequence(computation)
+  }
+
+  // Returns the module the ordering was built for.
+  pub fn module(&self) -> &'module HloModule {
+    self.module
+  }
+
+  // Returns the `operand_num`-th operand of `instruction` in its parent
+  // computation.
+  fn operand(
+    &self, instruction: &HloInstruction, operand_num: usize) -> &'module HloInstruction
+  {
+    self.module.computation(instruction.parent().unwrap())
+      .operand(instruction.id(), operand_num)
   }
 
   // Return the call graph of the module used to compute ordering.
@@ -393,10 +517,27 @@ impl<'module> HloOrdering<'module> {
     unimplemented!()
   }
 
+  // Returns true if 'a' executes before 'b' in their (common) computation.
+  // Instructions of a computation without a sequential order are unordered.
   pub fn executes_before_in_same_computation(
-    &self, _a: &HloInstruction, _b: &HloInstruction) -> bool
+    &self, a: &HloInstruction, b: &HloInstruction) -> bool
   {
-    unimplemented!()
+    debug_assert!(a.parent() == b.parent());
+    let positions = match self.order_position.get(&a.parent().unwrap()) {
+      Some(positions) => positions,
+      None => return false
+    };
+    // If either instruction is not in the order, then 'a' and 'b' are unordered.
+    let (a_position, b_position) = match (positions.get(&a.id()), positions.get(&b.id())) {
+      (Some(a_position), Some(b_position)) => (a_position, b_position),
+      _ => return false
+    };
+    // 'a' is the root instruction of the computation, which lives out. So
+    // 'a' cannot execute before 'b'.
+    if self.module.computation(a.parent().unwrap()).is_root(a.id()) {
+      return false;
+    }
+    a_position < b_position
   }
 }
 
@@ -404,7 +545,7 @@ impl<'module> HloOrdering<'module> {
 // each instruction.
 pub struct PredecessorHloOrdering<'module> {
   ordering: HloOrdering<'module>,
-  predecessors: HashMap<HloComputation, HloReachabilityMap>,
+  predecessors: HashMap<ComputationId, HloReachabilityMap>,
 }
 
 impl<'module> PredecessorHloOrdering<'module> {
@@ -423,7 +564,7 @@ impl<'module> PredecessorHloOrdering<'module> {
   pub fn reachability_map(
     &self, computation: &HloComputation) -> Option<&HloReachabilityMap>
   {
-    self.predecessors.get(computation)
+    self.predecessors.get(&computation.id()?)
   }
 
   pub fn executes_before_in_same_computation(
@@ -431,7 +572,7 @@ impl<'module> PredecessorHloOrdering<'module> {
   {
     // 'a' executes before 'b' if 'a' is in the strict predecessor set of 'b'.
     debug_assert!(a.parent() == b.parent());
-    a != b && self.predecessors.get(a.parent()).unwrap().is_reachable(a, b)
+    a != b && self.predecessors.get(&a.parent().unwrap()).unwrap().is_reachable(a, b)
   }
 }
 
@@ -448,41 +589,54 @@ impl<'module> DependencyHloOrdering<'module> {
 // An HLO ordering based om a total order of instructions in each computation.
 pub struct SequentialHloOrdering<'module> {
   ordering: HloOrdering<'module>,
-  schedule: HloSchedule,
-  order_position: HashMap<HloInstruction, i64>,
 }
 
 impl<'module> SequentialHloOrdering<'module> {
-  pub fn new() {}
+  // Creates the ordering from the schedule of the module.
+  pub fn new(module: &'module HloModule) -> Self {
+    assert!(module.has_schedule(), "Module {} is not scheduled.", module.name());
+    let mut ordering = HloOrdering::new(module);
+    for computation in module.computations() {
+      if let Some(sequence) = module.schedule().sequence(computation) {
+        let positions = sequence.ids().iter().enumerate()
+          .filter_map(|(position, unique_id)| computation.instruction_with_unique_id(*unique_id)
+            .map(|id| (id, position as i64)))
+          .collect();
+        ordering.order_position.insert(computation.id().unwrap(), positions);
+      }
+    }
+    SequentialHloOrdering { ordering: ordering }
+  }
+
+  pub fn ordering(&self) -> &HloOrdering<'module> {
+    &self.ordering
+  }
+
+  // Consumes the sequential ordering, returning the underlying ordering.
+  pub fn into_ordering(self) -> HloOrdering<'module> {
+    self.ordering
+  }
 
   pub fn sequential_order(
-    &self, computation: &HloComputation) -> Option<&HloInstructionSequence>
+    &self, computation: &HloComputation) -> Option<&'module HloInstructionSequence>
   {
-    if self.schedule.is_computation_scheduled(computation) {
-      return self.schedule.sequence(computation);
-    }
-    None
+    self.ordering.sequential_order(computation)
   }
 
   pub fn to_string(&self) -> String {
     let mut out = "SequentialHloOrdering\n".to_string();
-    out.push_str(&self.schedule.to_string());
+    out.push_str(&self.ordering.module.schedule().to_string(self.ordering.module));
     out
   }
 
   pub fn executes_before_in_same_computation(
     &self, a: &HloInstruction, b: &HloInstruction) -> bool
   {
-    debug_assert!(a.parent() == b.parent());
-    // If either instruction is not in the order, then 'a' and 'b' are unordered.
-    if !self.order_position.contains_key(a) || !self.order_position.contains_key(b) {
-      return false;
-    }
-    // 'a' is the root instruction of the computation, which lives out. So
-    // 'a' cannot execute before 'b'.
-    if a.parent().root_instruction() == a {
-      return false;
-    }
-    self.order_position.get(a) < self.order_position.get(b)
+    self.ordering.executes_before_in_same_computation(a, b)
   }
-}
\ No newline at end of file
+}
+
+// Returns true if 'a' and 'b' are the same instruction of the module.
+fn is_same_instruction(a: &HloInstruction, b: &HloInstruction) -> bool {
+  a.parent() == b.parent() && a.id() == b.id()
+}
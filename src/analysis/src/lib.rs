@@ -1,4 +1,6 @@
 
+pub mod buffer_assignment;
+pub mod heap_simulator;
 pub mod hlo_alias_analysis;
 pub mod hlo_dataflow_analysis;
 pub mod hlo_liveness_analysis;
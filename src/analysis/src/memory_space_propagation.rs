@@ -34,7 +34,8 @@ impl<'module> MemorySpacePropagation<'module> {
       module, false, true, None,
       None, execution_threads).unwrap();
 
-    for computation in module.make_nonfusion_computations(execution_threads) {
+    for computation_id in module.make_nonfusion_computations(execution_threads) {
+      let computation = module.computation(computation_id);
       for instruction in computation.instructions() {
         if instruction.opcode() == HloOpcode::Fusion {
           // Propagate the operand subshapes.
@@ -50,7 +51,8 @@ impl<'module> MemorySpacePropagation<'module> {
                 memory_space);
             };
             ShapeUtil::for_each_mutable_leaf_shape(
-              instruction.operand(operand_idx).shape(), &mut operand_func);
+              computation.operand(instruction.id(), operand_idx).shape(),
+              &mut operand_func);
           }
           // Propagate output subshapes.
           let mut output_func =
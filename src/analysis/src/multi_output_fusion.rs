@@ -4,7 +4,8 @@ use std::collections::{HashMap, HashSet};
 
 use common::shape_util::ShapeUtil;
 use hlo::{
-  hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_module::HloModule, hlo_opcode::HloOpcode, hlo_reachability::HloReachabilityMap
+  hlo_computation::HloComputation,
+  hlo_instruction::{HloInstruction, InstructionId}, hlo_module::HloModule, hlo_opcode::HloOpcode, hlo_reachability::HloReachabilityMap
 };
 
 use crate::hlo_dataflow_analysis::HloDataflowAnalysis;
@@ -77,7 +78,7 @@ pub struct MultiOutputFusion {
   //candidates: Vec<FusionCandidate>,
   candidates: [Option<FusionCandidate>; 0],
   worklist: WorkList,
-  candidats_index: HashMap<HloInstruction, i64>,
+  candidats_index: HashMap<InstructionId, i64>,
   reachability: HloReachabilityMap,
   all_fusion_candidates: Vec<(HloInstruction, usize)>,
   computation: HloComputation
@@ -134,7 +135,11 @@ impl MultiOutputFusion {
 
     // Fusing nodes with 0 users makes no sense and the rest of the implementation
     // doesn't support it either.
-    if instr1.is_dead() || instr2.is_dead() { return false; }
+    if self.computation.is_dead(instr1.id()) ||
+       self.computation.is_dead(instr2.id())
+    {
+      return false;
+    }
 
     // Check if the users of multioutput fusion is not a get-tuple-element.
     // If this is the case, we bail out because the transformation assumes
@@ -142,7 +147,9 @@ impl MultiOutputFusion {
     let multioutput_user_is_not_gte = |instr: &HloInstruction| -> bool {
       if instr.is_multi_output_fusion() { return false; }
       for user in instr.users() {
-        if user.opcode() != HloOpcode::GetTupleElement { return true; }
+        if self.computation.instruction(*user).opcode() != HloOpcode::GetTupleElement {
+          return true;
+        }
       }
       false
     };
@@ -158,12 +165,13 @@ impl MultiOutputFusion {
     // If both nodes are in-place operations and they use a common in-place
     // operand, we can't fuse these two.
     for operand_and_output_index1 in
-      HloDataflowAnalysis::get_in_place_input_output_pairs(instr1)
+      HloDataflowAnalysis::get_in_place_input_output_pairs(&self.computation, instr1)
     {
       let operand1 =
         instr1.operand(operand_and_output_index1.0.operand_number as usize);
       for operand_and_output_index2 in
-        HloDataflowAnalysis::get_in_place_input_output_pairs(instr2)
+        HloDataflowAnalysis::get_in_place_input_output_pairs(
+          &self.computation, instr2)
       {
         let operand2 =
           instr2.operand(operand_and_output_index2.0.operand_number as usize);
@@ -219,11 +227,11 @@ impl MultiOutputFusion {
 
       if instr2_instr {
         self.reachability.fast_set_rachability_to_union_by_index(
-          &vec![instr1_i, instr1_i], instr_i);
+          &[instr_i, instr1_i], instr_i);
       }
       if self.reachability.is_reachable_by_index(instr1_i, instr_i) {
         self.reachability.fast_set_rachability_to_union_by_index(
-          &vec![instr_i, instr2_i], instr_i);
+          &[instr_i, instr2_i], instr_i);
       }
     }
   }
@@ -270,7 +278,7 @@ impl MultiOutputFusion {
     new_fusibles: &Vec<(HloInstruction, usize)>,
     new_fusion_node: bool)
   {
-    let index = (*self.candidats_index.get(fusion).unwrap()) as usize;
+    let index = (*self.candidats_index.get(&fusion.id()).unwrap()) as usize;
     let _candidate_node = &self.candidates[index];
 
     for _it in new_fusibles {
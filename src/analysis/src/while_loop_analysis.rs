@@ -3,19 +3,23 @@
 use std::collections::HashMap;
 
 use common::{
-  blitz_data::PrimitiveType, literal::Literal, shape::ShapeEqual,
+  blitz_data::PrimitiveType, literal::Literal,
   shape_util::ShapeUtil
 };
 use hlo::{
-  evaluator::hlo_evaluator::HloEvaluator, hlo_instruction::HloInstruction,
+  evaluator::hlo_evaluator::HloEvaluator, hlo_computation::HloComputation,
+  hlo_instruction::{literal_shape, HloInstruction, InstructionId},
   hlo_module::HloModule, hlo_module_config::HloModuleConfig, hlo_opcode::HloOpcode
 };
 
 // Finds and returns the non-constant operand in instr.
 // CHECK-fails if instr doesn't have exactly one unique non-constant operand.
-fn non_constant_operand(instr: &HloInstruction) -> &HloInstruction {
+fn non_constant_operand<'a>(
+  computation: &'a HloComputation, instr: &HloInstruction) -> &'a HloInstruction
+{
   let mut result: Option<&HloInstruction> = None;
-  for operand in instr.operands() {
+  for operand_id in instr.operands() {
+    let operand = computation.instruction(*operand_id);
     if !operand.is_constant() {
       if result.is_some() {
         assert_eq!(result.unwrap(), operand);
@@ -31,7 +35,9 @@ fn non_constant_operand(instr: &HloInstruction) -> &HloInstruction {
 //   get-tuple-element(gte_operand, N)
 // for the same value N, returns N.  Otherwise, returns nullopt.
 fn get_gte_operand_index(
-  instr: &HloInstruction, gte_operand: &HloInstruction) -> Option<i64>
+  computation: &HloComputation,
+  instr: &HloInstruction,
+  gte_operand: &HloInstruction) -> Option<i64>
 {
   println!("get_gte_operand_index({:?}. {:?})",
     instr.to_string_default(), gte_operand.to_string_default());
@@ -41,12 +47,13 @@ fn get_gte_operand_index(
   // for the same value tuple_idx. We also support the case where GTE feeds a
   // copy that is then used.
   let mut tuple_idx: Option<i64> = None;
-  for operand in instr.operands() {
+  for operand_id in instr.operands() {
     // TODO
 
+    let operand = computation.instruction(*operand_id);
     let mut possibly_gte_operand = operand;
     if operand.opcode() == HloOpcode::Copy {
-      possibly_gte_operand = operand.operand(0);
+      possibly_gte_operand = computation.operand(operand.id(), 0);
     }
 
     if possibly_gte_operand.opcode() != HloOpcode::GetTupleElement {
@@ -160,8 +167,11 @@ pub fn match_trivial_loop_trip_count<T>(
 // max_brute_force_iters may be returned if we can pattern-match the loop
 // condition.
 pub fn compute_while_loop_trip_count(
-  while_op: &HloInstruction, max_brute_force_iters: i64) -> Option<i64>
+  module: &HloModule,
+  while_op: &HloInstruction,
+  max_brute_force_iters: i64) -> Option<i64>
 {
+  let computation = module.computation(while_op.parent().unwrap());
   println!("Getting trip count for loop {:?}", while_op.to_string_default());
 
   // The loop's induction variable is found at
@@ -175,67 +185,71 @@ pub fn compute_while_loop_trip_count(
   // Now that we know the index of the induction variable, we can try to
   // compute how many times the loop executes.  Start by computing the induction
   // variable's initial value.
-  let evaluator = HloEvaluator::new(0);
-  let while_init = while_op.operand(0);
-  let indvar_init = while_init.operand(indvar_tuple_idx.unwrap() as usize);
+  let mut evaluator = HloEvaluator::new(0);
+  let while_init = computation.operand(while_op.id(), 0);
+  let indvar_init =
+    computation.operand(while_init.id(), indvar_tuple_idx.unwrap() as usize);
   let indvar_init_result =
-    evaluator.evaluate(indvar_init, false);
+    evaluator.evaluate(module, indvar_init, true);
   if indvar_init_result.is_err() {
     println!("Couldn't evaluate induction variable init, {:?}, {:?}",
       indvar_init_result.err().unwrap(),
       indvar_init.to_string_default());
     return None;
   }
-  //let indvar_iter_val = indvar_init_result.
+  let mut indvar_iter_val = indvar_init_result.unwrap();
+  if !indvar_iter_val.is_known(&vec![]) {
+    println!("Induction variable init is not constant, {:?}",
+      indvar_init.to_string_default());
+    return None;
+  }
 
   // First, try to pattern-match.
   let trip_count = match_trivial_loop_trip_count(
     while_op,
     indvar_tuple_idx.unwrap(),
-    indvar_init_result.unwrap());
+    indvar_iter_val.clone());
   if trip_count.is_some() {
     return trip_count;
   }
 
   // If our pattern-match failed, try brute-forcing the loop trip count.
-  let while_body = while_op.while_body();
-  let while_body_indvar_update =
-    while_body.root_instruction().operand(indvar_tuple_idx.unwrap() as usize);
-  let _while_body_indvar =
-    non_constant_operand(while_body_indvar_update);
+  let while_body = module.computation(while_op.while_body());
+  let while_body_indvar_update = while_body.operand(
+    while_body.root_instruction_id(), indvar_tuple_idx.unwrap() as usize);
+  let while_body_indvar =
+    non_constant_operand(while_body, while_body_indvar_update);
 
-  let while_cond = while_op.while_condition();
+  let while_cond = module.computation(while_op.while_condition());
   let while_cond_root = while_cond.root_instruction();
-  let _while_cond_indvar = non_constant_operand(while_cond_root);
+  let while_cond_indvar = non_constant_operand(while_cond, while_cond_root);
 
   for trip_count in 0..max_brute_force_iters + 1 {
-    let map: HashMap<HloInstruction, Literal<bool>> = HashMap::new();
-    //map.insert(while_cond_indvar.clone(), indvar_init_result.unwrap());
+    let mut map: HashMap<InstructionId, Literal<f64>> = HashMap::new();
+    map.insert(while_cond_indvar.id(), indvar_iter_val.clone());
     let result =
-      evaluator.evaluate_with_substitutions(while_cond_root, map); 
+      evaluator.evaluate_with_substitutions(module, while_cond_root, &map);
     if result.is_err() {
       println!("Couldn't evaluate while cond: {:?}", result.err().unwrap());
       return None;
     }
-    for val in result.unwrap().data_default() {
-      // TODO
-      if *val == true {
-        println!("Loop has static trip count of {:?}", trip_count);
-        return Some(trip_count);
-      }
+    if *result.unwrap().get_first_element() == 0.0 {
+      println!("Loop has static trip count of {:?}", trip_count);
+      return Some(trip_count);
     }
     // Calculate the value of the induction variable after one iteration of the
     // loop, and check whether the while condition is true with this new value.
-    let map2: HashMap<HloInstruction, Literal<bool>> = HashMap::new();
+    let mut map2: HashMap<InstructionId, Literal<f64>> = HashMap::new();
+    map2.insert(while_body_indvar.id(), indvar_iter_val.clone());
     let indvar_next_result =
       evaluator.evaluate_with_substitutions(
-        while_body_indvar_update, map2);
+        module, while_body_indvar_update, &map2);
     if indvar_next_result.is_err() {
       println!("Couldn't evaluate induction variable update: {:?}",
         indvar_next_result.err().unwrap());
       return None;
     }
-    // indvar_iter_val = indvar_next_result.unwrap();
+    indvar_iter_val = indvar_next_result.unwrap();
   }
 
   println!("Loop has unknown trip count.");
@@ -246,26 +260,26 @@ pub fn compute_while_loop_trip_count(
 // get-tuple-element, otherwise return null. If this runs before CSE/DCE, we may
 // get a false negative if there are several copies of the same GTE, or there
 // are unused GTEs, but we can live with this.
-fn get_only_gte(inst: &HloInstruction) -> Option<&HloInstruction> {
+fn get_only_gte<'a>(
+  computation: &'a HloComputation, inst: &HloInstruction) -> Option<&'a HloInstruction>
+{
   if inst.user_count() != 1 {
     return None;
   }
-  let user = inst.users().last();
-  if user.is_none() {
+  let user = computation.instruction(*inst.users().last()?);
+  if user.opcode() != HloOpcode::GetTupleElement {
     return None;
   }
-  if user.unwrap().opcode() != HloOpcode::GetTupleElement {
-    return None;
-  }
-  user
+  Some(user)
 }
 
-pub fn compute_while_loop_trip_count_upper_bound<T>(while_op: &HloInstruction) -> Option<i64>
+pub fn compute_while_loop_trip_count_upper_bound<T>(
+  module: &HloModule, while_op: &HloInstruction) -> Option<i64>
   where T: Clone + Default
 {
   // If we know the exact trip count, it's also the upper bound.
   let exact_trip_count =
-    compute_while_loop_trip_count(while_op, 128);
+    compute_while_loop_trip_count(module, while_op, 128);
   if exact_trip_count.is_some() {
     println!("Loop has exact trip count.");
     return exact_trip_count;
@@ -283,9 +297,9 @@ pub fn compute_while_loop_trip_count_upper_bound<T>(while_op: &HloInstruction) -
 
   // Check whether the condition depends on a single parameter, and find out
   // which.
-  let while_cond = while_op.while_condition();
+  let while_cond = module.computation(while_op.while_condition());
   let while_cond_param = while_cond.parameter_instruction(0);
-  let cond_gte = get_only_gte(while_cond_param.unwrap());
+  let cond_gte = get_only_gte(while_cond, while_cond_param.unwrap());
   if cond_gte.is_none() {
     println!("Induction variable not found in loop condition: {:?}",
       while_cond.root_instruction().to_string_default());
@@ -293,7 +307,7 @@ pub fn compute_while_loop_trip_count_upper_bound<T>(while_op: &HloInstruction) -
   }
 
   // Now check whether this gets set to a constant by the while body.
-  let while_body = while_op.while_body();
+  let while_body = module.computation(while_op.while_body());
   let while_body_root = while_body.root_instruction();
   if while_body_root.opcode() != HloOpcode::Tuple {
     println!("While body's root is not a tuple instruction: {:?}",
@@ -302,7 +316,8 @@ pub fn compute_while_loop_trip_count_upper_bound<T>(while_op: &HloInstruction) -
   }
 
   let indvar_index = cond_gte.unwrap().tuple_index();
-  let while_body_indvar = while_body_root.operand(indvar_index as usize);
+  let while_body_indvar =
+    while_body.operand(while_body_root.id(), indvar_index as usize);
   if while_body_indvar.opcode() != HloOpcode::Constant {
     println!("While body does not set the IV to a constant: {:?}",
       while_body_indvar.to_string_default());
@@ -312,50 +327,53 @@ pub fn compute_while_loop_trip_count_upper_bound<T>(while_op: &HloInstruction) -
   // Create a new while cond computation accessing only the single parameter
   // extracted by the GTE above to avoid excessive memory allocation for the
   // evaluator.
-  let mut replacements: HashMap<HloInstruction, HloInstruction> = HashMap::new();
+  let mut replacements: HashMap<InstructionId, HloInstruction> = HashMap::new();
   let new_param = HloInstruction::create_parameter(
     0,
     &ShapeUtil::make_tuple_shape(vec![cond_gte.unwrap().shape().clone()]),
     "temp".to_string());
   let value =
     HloInstruction::create_get_tuple_element(&new_param, 0);
-  replacements.insert(cond_gte.unwrap().clone(), value);
-  replacements.insert(while_cond_param.unwrap().clone(), new_param);
+  replacements.insert(cond_gte.unwrap().id(), value);
+  replacements.insert(while_cond_param.unwrap().id(), new_param);
   
   // TODO
   let mut new_module = HloModule::new(
     "temp_mod".to_string(), HloModuleConfig::new_default());
-  let new_computation = new_module.add_embedded_computation(
+  let new_computation_id = new_module.add_embedded_computation(
     while_cond.clone_with_replacements(
       &replacements, &vec![], None,
       "clone".to_string(), None));
 
+  let new_computation = new_module.computation(new_computation_id);
+
   // We have a constant. Evaluate the condition on this constant.
-  let evaluator: HloEvaluator<bool> = HloEvaluator::new(0);
-  let mut fake_input = Literal::new_from_shape(
-    new_computation.parameter_instruction(0).unwrap().shape());
-  let result = fake_input.copy_from(
-    while_body_indvar.mutable_literal(),
-    &vec![0],
-    &vec![],
-    false);
-  assert!(result.is_ok());
+  let mut evaluator = HloEvaluator::new(0);
+  let indvar_literal = match while_body_indvar.literal().to_literal_f64() {
+    Ok(literal) => literal,
+    Err(msg) => {
+      println!("Couldn't read the induction variable constant: {}", msg);
+      return None;
+    }
+  };
+  let mut fake_input = Literal::new_from_shape(&literal_shape(
+    new_computation.parameter_instruction(0).unwrap().shape()));
+  fake_input.set_data(&vec![0], indvar_literal.data(&vec![]).clone());
   let eval_result =
-    evaluator.evaluate_computation(new_computation, &vec![fake_input]);
+    evaluator.evaluate_computation(&new_module, new_computation, &vec![fake_input]);
   if eval_result.is_err() {
     println!("Couldn't evaluate while loop condition.");
     return None;
   }
 
   let cond_result_pred = eval_result.unwrap();
-  assert!(ShapeEqual::new().ignore_layout().equal(
-    cond_result_pred.shape(), 
-    &ShapeUtil::make_shape(&PrimitiveType::BF16, vec![])));
+  assert!(ShapeUtil::is_scalar_with_element_type(
+    new_computation.root_instruction().shape(), PrimitiveType::Pred));
 
   // Per the explanation above, if the evaluated condition returns false, the
   // loop executes at most once.
   let cond_returns_true = cond_result_pred.get_first_element();
-  if !(*cond_returns_true) {
+  if *cond_returns_true == 0.0 {
     println!("Upper bound on the trip count is 1");
     return Some(1);
   }
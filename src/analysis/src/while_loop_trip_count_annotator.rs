@@ -36,17 +36,18 @@ impl WhileLoopTripCountAnnotator {
     execution_threads: &HashSet<String>) -> Result<bool, String>
   {
     let mut changed = false;
-    for comp in module.mutable_computations_by_exec_threads(execution_threads) {
-      for instr in comp.mutable_instructions() {
-        if instr.opcode() != HloOpcode::While {
+    for comp in module.computations_by_exec_threads(execution_threads) {
+      for instr in module.computation(comp).instruction_ids() {
+        if module.instruction(comp, instr).opcode() != HloOpcode::While {
           continue;
         }
-        let trip_count =
-          compute_while_loop_trip_count(instr, 128);
+        let trip_count = compute_while_loop_trip_count(
+          module, module.instruction(comp, instr), 128);
         if trip_count.unwrap() > 0 {
           let mut config = WhileLoopBackendConfig::new();
           config.set_known_trip_count(trip_count.unwrap() as usize);
-          instr.set_backend_config(config);
+          module.mutable_computation(comp).mutable_instruction(instr)
+            .set_backend_config(config);
           changed = true;
         }
       }
@@ -12,10 +12,7 @@ use common::{
   },
   comparison_util::{default_comparison_type, ComparisonDirection, ComparisonType},
   layout::Layout, layout_util::LayoutUtil, literal::Literal, literal_util::LiteralUtil,
-  primitive_util::{
-    bit_width, is_complex_type, is_unsigned_integral_type, primitive_type_name,
-    unsigned_integral_type_for_bit_width
-  },
+  primitive_util::is_complex_type,
   shape::{ProgramShape, Shape, /*ShapeEqual*/},
   shape_util::ShapeUtil, util::{self, make_no_padding_config}
 };
@@ -2122,45 +2119,85 @@ impl BlitzBuilder {
   }
 
   fn custom_call<T>(
-    &self,
-    _call_target_name: &String,
-    _operands: &Vec<BlitzOp>,
-    _shape_with_layout: &Shape,
-    _opaque: &String,
-    _operand_shapes_with_layout: Option<Vec<Shape>>,
-    _has_side_effect: bool,
-    _output_operand_aliasing: &Vec<(usize, (i64, usize))>,
-    _lieteral: Option<&Literal<T>>,
-    _window: Option<Window>,
-    _dnums: Option<&ConvolutionDimensionNumbers>,
-    _schedule: CustomCallSchedule,
-    _api_version: CustomCallApiVersion) -> BlitzOp
+    &mut self,
+    call_target_name: &String,
+    operands: &Vec<BlitzOp>,
+    shape_with_layout: &Shape,
+    opaque: &String,
+    operand_shapes_with_layout: Option<Vec<Shape>>,
+    has_side_effect: bool,
+    output_operand_aliasing: &Vec<(usize, (i64, usize))>,
+    lieteral: Option<&Literal<T>>,
+    window: Option<Window>,
+    dnums: Option<&ConvolutionDimensionNumbers>,
+    schedule: CustomCallSchedule,
+    api_version: CustomCallApiVersion) -> BlitzOp
     where T: Clone + Default + PartialEq
   {
-    unimplemented!()    
+    if call_target_name.starts_with('$') {
+      let mut err_msg = "Invalid custom_call_target \"".to_string();
+      err_msg.push_str(call_target_name);
+      err_msg.push_str("\": Call targets that start with '$' are reserved for internal use.");
+      assert!(false, "{:?}", err_msg);
+    }
+    let operand_shapes_wrapper = self.get_operand_shapes(operands);
+    check_error(&operand_shapes_wrapper);
+
+    let operand_shapes = operand_shapes_wrapper.unwrap();
+    let shape_wrapper = ShapeInference::infer_custom_call_shape(
+      &operand_shapes, shape_with_layout, &operand_shapes_with_layout);
+    check_error(&shape_wrapper);
+
+    let shape = shape_wrapper.unwrap();
+    let result = self.custom_call_internal(
+      call_target_name, operands, None, &shape, opaque.clone(),
+      operand_shapes_with_layout, has_side_effect, output_operand_aliasing,
+      lieteral, window, dnums, schedule, api_version);
+    check_error(&result);
+    result.unwrap()
   }
 
   // Internal version of CustomCall without computation that doesn't do op
   // specific error handling and expects arguments to be legal. CustomCall
   // method above calls this method after error handling.
   fn custom_call_internal<T>(
-    &self,
-    _call_target_name: &String,
-    _operands: &Vec<BlitzOp>,
-    _computation: &BlitzComputation,
-    _shape_with_layout: &Shape,
-    _opaque: String,
-    _operand_shapes_with_layout: Option<Vec<Shape>>,
-    _has_side_effect: bool,
+    &mut self,
+    call_target_name: &String,
+    operands: &Vec<BlitzOp>,
+    computation: Option<i64>,
+    shape_with_layout: &Shape,
+    opaque: String,
+    operand_shapes_with_layout: Option<Vec<Shape>>,
+    has_side_effect: bool,
     _output_operand_aliasing: &Vec<(usize, (i64, usize))>,
-    _lieteral: &Literal<T>,
-    _window: Option<Window>,
-    _dnums: Option<&ConvolutionDimensionNumbers>,
-    _schedule: CustomCallSchedule,
-    _api_version: CustomCallApiVersion) -> Result<BlitzOp, String>
+    _lieteral: Option<&Literal<T>>,
+    window: Option<Window>,
+    dnums: Option<&ConvolutionDimensionNumbers>,
+    schedule: CustomCallSchedule,
+    api_version: CustomCallApiVersion) -> Result<BlitzOp, String>
     where T: Clone + Default + PartialEq
   {
-    unimplemented!()    
+    let mut instr = HloInstruction::default();
+    instr.set_shape(shape_with_layout.clone());
+    instr.set_custom_call_target(call_target_name.clone());
+    instr.set_raw_backend_config_string(opaque);
+    if operand_shapes_with_layout.is_some() {
+      instr.set_operand_shapes_with_layout(operand_shapes_with_layout.unwrap());
+    }
+    // TODO: output_operand_aliasing, literal
+    instr.set_custom_call_has_side_effect(has_side_effect);
+    if computation.is_some() {
+      self.add_called_computation(computation.unwrap(), &mut instr);
+    }
+    if window.is_some() {
+      instr.set_window(window.unwrap());
+    }
+    if dnums.is_some() {
+      instr.set_convolution_dimension_numberes(dnums.unwrap().clone());
+    }
+    instr.set_custom_call_schedule(schedule);
+    instr.set_custom_call_api_version(api_version);
+    self.add_instruction(&mut instr, HloOpcode::CustomCall, operands)
   }
 
   fn optimization_barrier(&mut self, operand: BlitzOp) -> BlitzOp {
@@ -2203,7 +2240,7 @@ impl BlitzBuilder {
     check_error(&operand_shapes_wrapper);
 
     let operand_shapes = operand_shapes_wrapper.unwrap();
-    let shape_wrapper = ShapeInference::infer_reshape_shape(
+    let shape_wrapper = ShapeInference::infer_reduce_shape(
       &operand_shapes, dimensions_to_reduce, &called_program_shape);
     check_error(&shape_wrapper);
 
@@ -2253,43 +2290,98 @@ impl BlitzBuilder {
   }
 
   fn reduce_window(
-    &self,
-    _operand: &BlitzOp,
-    _init_value: &BlitzOp,
-    _computation: &BlitzComputation,
-    _window_dimensions: &Vec<i64>,
-    _window_strides: &Vec<i64>,
-    _padding: &Padding) -> BlitzOp
+    &mut self,
+    operand: &BlitzOp,
+    init_value: &BlitzOp,
+    computation: i64,
+    window_dimensions: &Vec<i64>,
+    window_strides: &Vec<i64>,
+    padding: &Padding) -> BlitzOp
   {
-    unimplemented!()    
+    let operand_shape_wrapper = self.get_shape(operand);
+    check_error(&operand_shape_wrapper);
+
+    let operand_shape = operand_shape_wrapper.unwrap();
+    let padding_values = make_padding(
+      operand_shape.dimensions_vec(),
+      window_dimensions,
+      window_strides,
+      padding);
+
+    self.reduce_window_with_general_padding(
+      &vec![operand.clone()], &vec![init_value.clone()], computation,
+      window_dimensions, window_strides, &vec![], &vec![], &padding_values)
   }
 
   fn reduce_window_with_general_padding(
-    &self,
-    _operands: &Vec<BlitzOp>,
-    _init_values: &Vec<BlitzOp>,
-    _computation: &BlitzComputation,
-    _window_dimensions: &Vec<i64>,
-    _window_strides: &Vec<i64>,
-    _base_dilations: &Vec<i64>,
-    _window_dilations: &Vec<i64>,
-    _padding: &Vec<(i64, i64)>) -> BlitzOp
+    &mut self,
+    operands: &Vec<BlitzOp>,
+    init_values: &Vec<BlitzOp>,
+    computation: i64,
+    window_dimensions: &Vec<i64>,
+    window_strides: &Vec<i64>,
+    base_dilations: &Vec<i64>,
+    window_dilations: &Vec<i64>,
+    padding: &Vec<(i64, i64)>) -> BlitzOp
   {
-    unimplemented!()    
+    let instr_wrapper = self.reduce_window_internal(
+      operands, init_values, computation, window_dimensions, window_strides,
+      base_dilations, window_dilations, padding);
+    check_error(&instr_wrapper);
+
+    let mut instr = instr_wrapper.unwrap();
+    let mut all_operands = vec![];
+    for op in operands {
+      all_operands.push(op.clone());
+    }
+    for val in init_values {
+      all_operands.push(val.clone());
+    }
+    let result =
+      self.add_instruction(&mut instr, HloOpcode::ReduceWindow, &all_operands);
+    check_error(&result);
+    result.unwrap()
   }
 
   fn reduce_window_internal(
     &self,
-    _operands: &Vec<BlitzOp>,
-    _init_values: &Vec<BlitzOp>,
-    _computation: &BlitzComputation,
-    _window_dimensions: &Vec<i64>,
-    _window_strides: &Vec<i64>,
-    _base_dilations: &Vec<i64>,
-    _window_dilations: &Vec<i64>,
-    _padding: &Vec<(i64, i64)>) -> Result<HloInstruction, String>
+    operands: &Vec<BlitzOp>,
+    init_values: &Vec<BlitzOp>,
+    computation: i64,
+    window_dimensions: &Vec<i64>,
+    window_strides: &Vec<i64>,
+    base_dilations: &Vec<i64>,
+    window_dilations: &Vec<i64>,
+    padding: &Vec<(i64, i64)>) -> Result<HloInstruction, String>
   {
-    unimplemented!()    
+    let mut instr = HloInstruction::default();
+    let operand_shapes_wrapper = self.get_operand_shapes(operands);
+    check_error(&operand_shapes_wrapper);
+    let init_shapes_wrapper = self.get_operand_shapes(init_values);
+    check_error(&init_shapes_wrapper);
+    let program_shape_wrapper =
+      self.get_subcomputation_shape(computation);
+    check_error(&program_shape_wrapper);
+
+    let window_wrapper =
+      ShapeInference::infer_window_from_dimensions(window_dimensions, window_strides,
+        padding, base_dilations, window_dilations, None);
+    check_error(&window_wrapper);
+
+    let operand_shapes = operand_shapes_wrapper.unwrap();
+    let init_shapes = init_shapes_wrapper.unwrap();
+    let program_shape = program_shape_wrapper.unwrap();
+    let window = window_wrapper.unwrap();
+
+    let shape_wrapper =
+      ShapeInference::infer_variadic_reduce_window_shape(
+        &operand_shapes, &init_shapes, &window, &program_shape);
+    check_error(&shape_wrapper);
+
+    instr.set_shape(shape_wrapper.unwrap());
+    instr.set_window(window);
+    self.add_called_computation(computation, &mut instr);
+    Ok(instr)
   }
 
   fn cross_replica_sum(
@@ -2586,16 +2678,87 @@ impl BlitzBuilder {
     reduce_scatter_wrapper.unwrap()
   }
 
-  fn all_to_all() {
-      
+  fn all_to_all(
+    &mut self,
+    operand: &BlitzOp,
+    split_dimension: i64,
+    concat_dimension: i64,
+    split_count: i64,
+    replica_groups: &Vec<ReplicaGroup>,
+    channel_id: Option<ChannelHandle>) -> BlitzOp
+  {
+    self.all_to_all_array(operand, split_dimension, concat_dimension,
+      split_count, replica_groups, channel_id)
   }
 
-  fn all_to_all_tuple() {
-      
+  fn all_to_all_tuple(
+    &mut self,
+    operands: &Vec<BlitzOp>,
+    replica_groups: &Vec<ReplicaGroup>,
+    channel_id: Option<ChannelHandle>) -> BlitzOp
+  {
+    let operand_shapes_wrapper = self.get_operand_shapes(operands);
+    check_error(&operand_shapes_wrapper);
+
+    let operand_shapes = operand_shapes_wrapper.unwrap();
+    let shape_wrapper =
+      ShapeInference::infer_all_to_all_tuple_shape(&operand_shapes);
+    check_error(&shape_wrapper);
+
+    let mut instr = HloInstruction::default();
+    instr.set_shape(shape_wrapper.unwrap());
+    for group in replica_groups {
+      instr.add_replica_groups(group.clone());
+    }
+    if channel_id.is_some() {
+      instr.set_channel_id(channel_id.unwrap().handle());
+    }
+    let result =
+      self.add_instruction(&mut instr, HloOpcode::AllToAll, operands);
+    check_error(&result);
+    result.unwrap()
   }
 
-  fn all_to_all_array() {
-      
+  fn all_to_all_array(
+    &mut self,
+    operand: &BlitzOp,
+    split_dimension: i64,
+    concat_dimension: i64,
+    split_count: i64,
+    replica_groups: &Vec<ReplicaGroup>,
+    channel_id: Option<ChannelHandle>) -> BlitzOp
+  {
+    let operand_shape_wrapper = self.get_shape(operand);
+    check_error(&operand_shape_wrapper);
+
+    let operand_shape = operand_shape_wrapper.unwrap();
+    let shape_wrapper = ShapeInference::infer_all_to_all_shape(
+      &operand_shape, split_dimension, concat_dimension, split_count);
+    check_error(&shape_wrapper);
+
+    if split_dimension != concat_dimension {
+      // TODO: lower to an all-to-all along the split dimension followed by a
+      // reshape and transpose into the concat dimension.
+      let mut err_msg = "AllToAll with split_dimension ".to_string();
+      err_msg.push_str(&split_dimension.to_string());
+      err_msg.push_str(" different from concat_dimension ");
+      err_msg.push_str(&concat_dimension.to_string());
+      err_msg.push_str(" is not supported yet.");
+      assert!(false, "{:?}", err_msg);
+    }
+    let mut instr = HloInstruction::default();
+    instr.set_shape(shape_wrapper.unwrap());
+    instr.add_dimensions(split_dimension);
+    for group in replica_groups {
+      instr.add_replica_groups(group.clone());
+    }
+    if channel_id.is_some() {
+      instr.set_channel_id(channel_id.unwrap().handle());
+    }
+    let result = self.add_instruction(
+      &mut instr, HloOpcode::AllToAll, &vec![operand.clone()]);
+    check_error(&result);
+    result.unwrap()
   }
   fn collective_broadcast(
     &mut self,
@@ -2827,14 +2990,17 @@ impl BlitzBuilder {
     if !shape.is_static() {
       assert!(false, "The output of iota must not have dynamic dimensions: {:?}", shape);
     }
+    let shape_wrapper =
+      ShapeInference::infer_iota_shape(shape, iota_dimension);
+    check_error(&shape_wrapper);
+
     let mut instr = HloInstruction::default();
-    instr.set_shape(shape.clone());
+    instr.set_shape(shape_wrapper.unwrap());
     instr.add_dimensions(iota_dimension);
-    //let result =
-      //self.add_instruction(&instr, HloOpcode::Iota, &vec![]);
-    //if result.is_err() { assert!(false); }
-    //result.unwrap()
-    unimplemented!()
+    let result =
+      self.add_instruction(&mut instr, HloOpcode::Iota, &vec![]);
+    check_error(&result);
+    result.unwrap()
   }
 
   fn convert_element_type(
@@ -2997,9 +3163,12 @@ impl BlitzBuilder {
     check_error(&operand_shapes_wrapper);
 
     let operand_shapes = operand_shapes_wrapper.unwrap();
+    let mut sort_dimension = dimension;
+    if sort_dimension == -1 && !operand_shapes.is_empty() {
+      sort_dimension = operand_shapes[0].rank() as i64 - 1;
+    }
     let shape_wrapper =
-      ShapeInference::infer_variadic_op_shape_by_opshapes(
-        HloOpcode::Sort, &operand_shapes);
+      ShapeInference::infer_sort_shape(&operand_shapes, sort_dimension);
     check_error(&shape_wrapper);
 
     let shape = shape_wrapper.unwrap();
@@ -3074,8 +3243,34 @@ impl BlitzBuilder {
     self.ternary_op(HloOpcode::Clamp, min, operand, max)
   }
 
-  fn map() {
-      
+  fn map(
+    &mut self,
+    operands: &Vec<BlitzOp>,
+    computation: i64,
+    dimensions: &Vec<i64>) -> BlitzOp
+  {
+    let operand_shapes_wrapper = self.get_operand_shapes(operands);
+    check_error(&operand_shapes_wrapper);
+    let called_program_shape_wrapper =
+      self.get_subcomputation_shape(computation);
+    check_error(&called_program_shape_wrapper);
+
+    let operand_shapes = operand_shapes_wrapper.unwrap();
+    let called_program_shape = called_program_shape_wrapper.unwrap();
+    let shape_wrapper = ShapeInference::infer_map_shape(
+      &operand_shapes, &called_program_shape, dimensions);
+    check_error(&shape_wrapper);
+
+    let mut instr = HloInstruction::default();
+    instr.set_shape(shape_wrapper.unwrap());
+    for dim in dimensions {
+      instr.add_dimensions(*dim);
+    }
+    self.add_called_computation(computation, &mut instr);
+    let result =
+      self.add_instruction(&mut instr, HloOpcode::Map, operands);
+    check_error(&result);
+    result.unwrap()
   }
 
   fn rng_normal(
@@ -3112,22 +3307,11 @@ impl BlitzBuilder {
     check_error(&state_shape_wrapper);
 
     let state_shape = state_shape_wrapper.unwrap();
-    let mut output_shape = Shape::new();
-    if shape.is_array() {
-      // Make output_shape the same as the input shape, but with an unsigned
-      // integral type.
-      output_shape = shape.clone();
-      output_shape.set_element_type(unsigned_integral_type_for_bit_width(
-        bit_width(&shape.element_type())));
-    }
-    if !is_unsigned_integral_type(&output_shape.element_type()) {
-      let mut err_msg = "Unsupported shape for rng_bit_generator: ".to_string();
-      err_msg.push_str(&primitive_type_name(&shape.element_type()));
-      assert!(false, "{:?}", err_msg);
-    }
+    let tuple_shape_wrapper =
+      ShapeInference::infer_rng_bit_generator_shape(&state_shape, &algorithm, shape);
+    check_error(&tuple_shape_wrapper);
 
-    let tuple_shape =
-      ShapeUtil::make_tuple_shape(vec![state_shape, output_shape]);
+    let tuple_shape = tuple_shape_wrapper.unwrap();
     let result =
       self.rng_bit_generator_internal(
         tuple_shape, algorithm, initial_state.clone());
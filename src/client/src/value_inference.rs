@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
-use common::literal::Literal;
+use common::{literal::Literal, shape::Shape, shape_util::ShapeUtil};
+use hlo::{
+  evaluator::hlo_evaluator::HloEvaluator,
+  hlo_instruction::{literal_shape, HloInstruction},
+  hlo_module::HloModule
+};
 
 // OptionalLiteral is an augmented literal class which returns optional
 // values for each index (the value can be either valid or invalid). The
@@ -17,28 +22,171 @@ impl<T> OptionalLiteral<T> where T: Clone + Default + PartialEq {
     OptionalLiteral { value: value, mask: mask }
   }
 
-  pub fn get(&self, _element_index: &Vec<i64>) {
-      
+  // Returns the value at the given index, or None if the value is invalid.
+  pub fn get(&self, element_index: &Vec<i64>, shape_index: &Vec<i64>) -> Option<T> {
+    if *self.mask.get(element_index, shape_index) != T::default() {
+      None
+    } else {
+      Some(self.value.get(element_index, shape_index).clone())
+    }
   }
 
   // Returns true if all values in this literal slice are value.
   pub fn all_valid(&self) -> bool where T: PartialEq {
-    self.mask.is_all_int(0)
+    all_default(self.mask.shape(), &self.mask, &mut vec![])
   }
 
-  pub fn get_value() {
-      
+  // Get the value out of this slice if all values are valid. Otherwise returns
+  // None.
+  pub fn get_value(&self) -> Option<&Literal<T>> {
+    if !self.all_valid() {
+      return None;
+    }
+    Some(&self.value)
   }
 }
 
+// Returns true if all elements of the arrays of 'literal' under 'index' are
+// the default value.
+fn all_default<T>(shape: &Shape, literal: &Literal<T>, index: &mut Vec<i64>) -> bool
+  where T: Clone + Default + PartialEq
+{
+  if shape.is_tuple() {
+    for i in 0..shape.tuple_shapes_size() {
+      index.push(i as i64);
+      let result = all_default(shape.tuple_shapes(i), literal, index);
+      index.pop();
+      if !result {
+        return false;
+      }
+    }
+    return true;
+  }
+  if !shape.is_array() {
+    return true;
+  }
+  literal.data(index).iter().all(|v| *v == T::default())
+}
+
 pub enum ValueInferenceMode {
   Value,
   UpperBound,
   LowerBound,
 }
 
-pub struct ValueInference {}
+// ValueInference analyzes values in HLO instructions that can be determined
+// at compile time, such as the shapes and induction variables derived from
+// constants.
+pub struct ValueInference {
+  evaluator: HloEvaluator
+}
 
 impl ValueInference {
-    
-}
\ No newline at end of file
+  pub fn new() -> Self {
+    ValueInference { evaluator: HloEvaluator::default() }
+  }
+
+  // Returns an OptionalLiteral. Each individual value of the literal is the
+  // concrete constant value if it can be inferred, otherwise it is invalid.
+  pub fn analyze_constant(
+    &mut self,
+    module: &HloModule,
+    instruction: &HloInstruction,
+    mode: ValueInferenceMode) -> Result<OptionalLiteral<f64>, String>
+  {
+    match mode {
+      ValueInferenceMode::Value => {},
+      ValueInferenceMode::UpperBound | ValueInferenceMode::LowerBound =>
+        return Err("Inferring bounds is not supported yet.".to_string())
+    }
+    let result = self.evaluator.evaluate(module, instruction, true)?;
+    let shape = literal_shape(instruction.shape());
+    let mut value = Literal::new_from_shape(&shape);
+    let mut mask = Literal::new_from_shape(&shape);
+    fill_optional_literal(&shape, &result, &mut value, &mut mask, &mut vec![]);
+    Ok(OptionalLiteral::new(value, mask))
+  }
+
+  // Forgets the values analyzed so far. Needed when the module changes.
+  pub fn reset(&mut self) {
+    self.evaluator.reset_visit_states();
+  }
+}
+
+// Copies the known arrays of 'result' into 'value', and marks the elements of
+// unknown arrays as invalid in 'mask'.
+fn fill_optional_literal(
+  shape: &Shape,
+  result: &Literal<f64>,
+  value: &mut Literal<f64>,
+  mask: &mut Literal<f64>,
+  index: &mut Vec<i64>)
+{
+  if shape.is_tuple() {
+    for i in 0..shape.tuple_shapes_size() {
+      index.push(i as i64);
+      fill_optional_literal(shape.tuple_shapes(i), result, value, mask, index);
+      index.pop();
+    }
+    return;
+  }
+  if !shape.is_array() {
+    return;
+  }
+  let count = ShapeUtil::elements_in(shape) as usize;
+  if result.is_known(index) {
+    value.set_data(index, result.data(index).clone());
+    mask.set_data(index, vec![0.0; count]);
+  } else {
+    value.set_data(index, vec![0.0; count]);
+    mask.set_data(index, vec![1.0; count]);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use common::{blitz_data::PrimitiveType, dyn_literal::DynLiteral};
+  use hlo::{
+    hlo_computation::HloComputation,
+    hlo_module_config::HloModuleConfig,
+    hlo_opcode::HloOpcode
+  };
+
+  #[test]
+  fn test_analyze_constant() {
+    let s32 = ShapeUtil::make_shape(&PrimitiveType::S32, vec![]);
+
+    let mut computation = HloComputation::new("entry".to_string());
+    let p = computation.add_parameter(
+      HloInstruction::create_parameter(0, &s32, "p".to_string()));
+    let c = computation.add_instruction(
+      HloInstruction::create_constant(DynLiteral::create_r0(3_i32)), "c".to_string());
+    let tuple = computation.add_instruction(HloInstruction::create_tuple(
+      &[computation.instruction(c), computation.instruction(p)]), "tuple".to_string());
+    let add = computation.add_instruction(HloInstruction::create_binary(
+      &s32, HloOpcode::Add, computation.instruction(c), computation.instruction(c)),
+      "add".to_string());
+    computation.set_root_instruction(add, false);
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    module.add_entry_computation(computation);
+    let entry = module.entry_computation().unwrap();
+
+    let mut inference = ValueInference::new();
+    let result = inference.analyze_constant(
+      &module, entry.instruction(tuple), ValueInferenceMode::Value).unwrap();
+    assert!(!result.all_valid());
+    assert!(result.get_value().is_none());
+    assert_eq!(result.get(&vec![], &vec![0]), Some(3.0));
+    assert_eq!(result.get(&vec![], &vec![1]), None);
+
+    let result = inference.analyze_constant(
+      &module, entry.instruction(add), ValueInferenceMode::Value).unwrap();
+    assert!(result.all_valid());
+    assert_eq!(*result.get_value().unwrap().get_first_element(), 6.0);
+
+    let result = inference.analyze_constant(
+      &module, entry.instruction(add), ValueInferenceMode::UpperBound);
+    assert!(result.is_err());
+  }
+}
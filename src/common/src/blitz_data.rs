@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use std::{collections::HashMap, hash::Hash};
+use std::{collections::BTreeMap, hash::Hash};
 
 use crate::{debug_options_flags::get_debug_options_from_flags, shape::Shape};
 
@@ -44,7 +44,7 @@ pub enum DimLevelType {
   LooseCompressed,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Precision {
   Default,
   High,
@@ -52,17 +52,42 @@ pub enum Precision {
   PackedNibble,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Algorithm {
   Unset,
   DotAnyF8anyF8F32,
 }
 
-#[derive(Debug, Clone)]
-pub struct PrecisionConfig {}
+// Used to indicate the precision configuration. It has backend specific
+// meaning.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PrecisionConfig {
+  operand_precision: Vec<Precision>,
+  algorithm: Algorithm,
+}
 
 impl PrecisionConfig {
+  pub fn new() -> Self {
+    PrecisionConfig {
+      operand_precision: Vec::new(),
+      algorithm: Algorithm::Unset
+    }
+  }
+
   pub fn operand_precision(&self) -> &Vec<Precision> {
-    unimplemented!()
+    &self.operand_precision
+  }
+
+  pub fn add_operand_precision(&mut self, precision: Precision) {
+    self.operand_precision.push(precision);
+  }
+
+  pub fn algorithm(&self) -> Algorithm {
+    self.algorithm.clone()
+  }
+
+  pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+    self.algorithm = algorithm;
   }
 }
 
@@ -150,59 +175,134 @@ impl OpMetadata {
 
   pub fn creation_pass_id(&self) -> i64 { 0 }
   pub fn set_creation_pass_id(&mut self, _id: i64) {}
-  pub fn set_size_of_generated_code_in_bytes(&mut self, _code_size_in_bytes: i64) {}
-  pub fn set_size_of_memory_working_set_in_bytes(&mut self, _working_set_size_in_bytes: i64) {}
-  pub fn op_name(&self) -> String { "".to_string() }
-  pub fn set_op_name(&mut self, _name: String) {}
-  pub fn op_type(&self) -> String { "".to_string() }
+
+  pub fn size_of_generated_code_in_bytes(&self) -> i64 {
+    self.size_of_generated_code_in_bytes
+  }
+
+  pub fn set_size_of_generated_code_in_bytes(&mut self, code_size_in_bytes: i64) {
+    self.size_of_generated_code_in_bytes = code_size_in_bytes;
+  }
+
+  pub fn size_of_memory_working_set_in_bytes(&self) -> i64 {
+    self.size_of_memory_working_set_in_bytes
+  }
+
+  pub fn set_size_of_memory_working_set_in_bytes(&mut self, working_set_size_in_bytes: i64) {
+    self.size_of_memory_working_set_in_bytes = working_set_size_in_bytes;
+  }
+
+  pub fn op_name(&self) -> String {
+    self.op_name.clone()
+  }
+
+  pub fn set_op_name(&mut self, name: String) {
+    self.op_name = name;
+  }
+
+  pub fn op_type(&self) -> String {
+    self.op_type.clone()
+  }
+
+  pub fn set_op_type(&mut self, op_type: String) {
+    self.op_type = op_type;
+  }
+
   pub fn set_logical_creation_pass_id(&mut self, _pass_id: i64) {}
-  pub fn set_deduplicated_name(&mut self, _deduplicated_name: String) {}
-  pub fn set_preserve_layout(&mut self, _preserve_layout: bool) {}
-  pub fn source_file(&self) -> String { "".to_string() }
-  pub fn source_line(&self) -> usize { 0 }
+
+  pub fn set_deduplicated_name(&mut self, deduplicated_name: String) {
+    self.deduplicated_name = deduplicated_name;
+  }
+
+  pub fn set_preserve_layout(&mut self, preserve_layout: bool) {
+    self.preserve_layout = preserve_layout;
+  }
+
+  pub fn source_file(&self) -> String {
+    self.source_file.clone()
+  }
+
+  pub fn set_source_file(&mut self, source_file: String) {
+    self.source_file = source_file;
+  }
+
+  pub fn source_line(&self) -> i64 {
+    self.source_line
+  }
+
+  pub fn set_source_line(&mut self, source_line: i64) {
+    self.source_line = source_line;
+  }
+
   pub fn profile_type(&self) -> String { "".to_string() }
-  pub fn deduplicated_name(&self) -> String { "".to_string() }
-  pub fn preserve_layout(&self) -> bool { false }
+
+  pub fn deduplicated_name(&self) -> String {
+    self.deduplicated_name.clone()
+  }
+
+  pub fn preserve_layout(&self) -> bool {
+    self.preserve_layout
+  }
+
+  pub fn stack_frame_id(&self) -> i64 {
+    self.stack_frame_id
+  }
+
+  pub fn set_stack_frame_id(&mut self, stack_frame_id: i64) {
+    self.stack_frame_id = stack_frame_id;
+  }
+
+  pub fn scheduling_name(&self) -> String {
+    self.scheduling_name.clone()
+  }
+
+  pub fn set_scheduling_name(&mut self, scheduling_name: String) {
+    self.scheduling_name = scheduling_name;
+  }
+
+  // Returns true if none of the fields carries information.
+  pub fn is_empty(&self) -> bool {
+    *self == OpMetadata::new()
+  }
 
   pub fn clear(&mut self) {
-    unimplemented!()
+    *self = OpMetadata::new();
   }
 }
 
 // Generic map of attributes used to pass hints / configuration options from
 // the Python frontend to the Blitz backend.
+// The map is ordered so that attributes print the same way every time.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct FrontendAttributes {
-  //map: HashMap<String, String>
+  map: BTreeMap<String, String>
 }
 
 impl FrontendAttributes {
   pub fn new() -> Self {
     FrontendAttributes {
-      //map: HashMap::new()
+      map: BTreeMap::new()
     }
   }
 
-  pub fn map(&self) -> &HashMap<String, String> {
-    //&self.map
-    unimplemented!()
+  pub fn map(&self) -> &BTreeMap<String, String> {
+    &self.map
   }
 
-  pub fn mutable_map(&mut self) -> &mut HashMap<String, String> {
-    //&mut self.map
-    unimplemented!()
+  pub fn mutable_map(&mut self) -> &mut BTreeMap<String, String> {
+    &mut self.map
   }
 
-  pub fn set_attribute(&mut self, _key: String, _value: String) {
-    unimplemented!()
+  pub fn set_attribute(&mut self, key: String, value: String) {
+    self.map.insert(key, value);
   }
 
-  pub fn has_attribute(&self, _key: String) -> bool {
-    unimplemented!()
+  pub fn has_attribute(&self, key: String) -> bool {
+    self.map.contains_key(&key)
   }
 
   pub fn clear(&mut self) {
-    unimplemented!()
+    self.map.clear();
   }
 }
 
@@ -217,6 +317,18 @@ impl Statisitic {
     Statisitic { stat_name: "".to_string(), stat_val: 0 }
   }
 
+  pub fn stat_name(&self) -> String {
+    self.stat_name.clone()
+  }
+
+  pub fn set_stat_name(&mut self, stat_name: String) {
+    self.stat_name = stat_name;
+  }
+
+  pub fn set_stat_val(&mut self, stat_val: i64) {
+    self.stat_val = stat_val;
+  }
+
   pub fn stat_val(&self) -> i64 {
     self.stat_val
   }
@@ -250,7 +362,7 @@ impl StatisticsViz {
   }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum PaddingType {
   Invalid,
   // Only valid portion of the base are covered.
@@ -259,7 +371,7 @@ pub enum PaddingType {
   Same,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum FftType {
   FFT,
   IFFT,
@@ -316,39 +428,60 @@ impl OpSharding {
   }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct DotDimensionNumbers {
-  lhs_contracting_dimensions: i64,
-  rhs_contracting_dimensions: i64,
-  lhs_batch_dimensions: i64,
-  rhs_batch_dimensions: i64,
+  lhs_contracting_dimensions: Vec<i64>,
+  rhs_contracting_dimensions: Vec<i64>,
+  lhs_batch_dimensions: Vec<i64>,
+  rhs_batch_dimensions: Vec<i64>,
 }
 
 impl DotDimensionNumbers {
   pub fn default() -> Self {
     DotDimensionNumbers {
-      lhs_contracting_dimensions: 0,
-      rhs_contracting_dimensions: 0,
-      lhs_batch_dimensions: 0,
-      rhs_batch_dimensions: 0
+      lhs_contracting_dimensions: Vec::new(),
+      rhs_contracting_dimensions: Vec::new(),
+      lhs_batch_dimensions: Vec::new(),
+      rhs_batch_dimensions: Vec::new()
     }
   }
 
-  pub fn lhs_contracting_dimensions(&self) -> i64 {
-    self.lhs_contracting_dimensions
+  pub fn lhs_contracting_dimensions(&self) -> &Vec<i64> {
+    &self.lhs_contracting_dimensions
   }
 
-  pub fn add_lhs_contracting_dimensions(&mut self, _dims: i64) {
-    unimplemented!()
+  pub fn add_lhs_contracting_dimensions(&mut self, dim: i64) {
+    self.lhs_contracting_dimensions.push(dim);
   }
 
-  pub fn add_rhs_contracting_dimensions(&mut self, _dims: i64) {
-    unimplemented!()
+  pub fn rhs_contracting_dimensions(&self) -> &Vec<i64> {
+    &self.rhs_contracting_dimensions
+  }
+
+  pub fn add_rhs_contracting_dimensions(&mut self, dim: i64) {
+    self.rhs_contracting_dimensions.push(dim);
+  }
+
+  pub fn lhs_batch_dimensions(&self) -> &Vec<i64> {
+    &self.lhs_batch_dimensions
+  }
+
+  pub fn add_lhs_batch_dimensions(&mut self, dim: i64) {
+    self.lhs_batch_dimensions.push(dim);
+  }
+
+  pub fn rhs_batch_dimensions(&self) -> &Vec<i64> {
+    &self.rhs_batch_dimensions
+  }
+
+  pub fn add_rhs_batch_dimensions(&mut self, dim: i64) {
+    self.rhs_batch_dimensions.push(dim);
   }
 }
 
 // Describes whether all data-parallelism replicas will receive the same
 // parameter data at each buffer.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ParameterReplication {
   replicated_at_leaf_buffers: Vec<bool>
 }
@@ -364,12 +497,12 @@ impl ParameterReplication {
     &self.replicated_at_leaf_buffers
   }
 
-  pub fn add_replicated_at_leaf_buffers(&mut self, _value: bool) {
-    unimplemented!()
+  pub fn add_replicated_at_leaf_buffers(&mut self, value: bool) {
+    self.replicated_at_leaf_buffers.push(value);
   }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ConvolutionDimensionNumbers {
   input_batch_dimension: i64,
   input_feature_dimension: i64,
@@ -494,6 +627,7 @@ impl ConvolutionDimensionNumbers {
   }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct PaddingConfigDimension {
   edge_padding_low: i64,
   edge_padding_high: i64,
@@ -501,6 +635,14 @@ pub struct PaddingConfigDimension {
 }
 
 impl PaddingConfigDimension {
+  pub fn new() -> Self {
+    PaddingConfigDimension {
+      edge_padding_low: 0,
+      edge_padding_high: 0,
+      interior_padding: 0
+    }
+  }
+
   pub fn edge_padding_low(&self) -> i64 {
     self.edge_padding_low
   }
@@ -520,42 +662,57 @@ impl PaddingConfigDimension {
   pub fn interior_padding(&self) -> i64 {
     self.interior_padding
   }
+
+  pub fn set_interior_padding(&mut self, interior_padding: i64) {
+    self.interior_padding = interior_padding;
+  }
 }
 
 // Describes the padding configuration for Pad operation. The padding amount on
 // both edges as well as between the elements are specified for each dimension.
-#[derive(Debug, Clone)]
-pub struct PaddingConfig {}
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PaddingConfig {
+  dimensions: Vec<PaddingConfigDimension>
+}
 
 impl PaddingConfig {
   pub fn new() -> Self {
-    PaddingConfig {  }
+    PaddingConfig { dimensions: Vec::new() }
   }
 
   pub fn dimensions_vec(&self) -> &Vec<PaddingConfigDimension> {
-    unimplemented!()
+    &self.dimensions
   }
 
-  pub fn dimensions(&self, _dimno: i64) -> &PaddingConfigDimension {
-    unimplemented!()
+  pub fn dimensions(&self, dimno: i64) -> &PaddingConfigDimension {
+    &self.dimensions[dimno as usize]
   }
 
-  pub fn mutable_dimensions(&mut self, _dimno: i64) -> &mut PaddingConfigDimension {
-    unimplemented!()
+  pub fn mutable_dimensions(&mut self, dimno: i64) -> &mut PaddingConfigDimension {
+    &mut self.dimensions[dimno as usize]
+  }
+
+  pub fn add_dimensions(&mut self, dimension: PaddingConfigDimension) {
+    self.dimensions.push(dimension);
   }
 
   pub fn dimensions_size(&self) -> usize {
-    unimplemented!()
+    self.dimensions.len()
   }
 
   pub fn short_debug_string(&self) -> String {
-    unimplemented!()
+    let mut result = String::new();
+    for dim in &self.dimensions {
+      result.push_str(&format!("dimensions {{ edge_padding_low: {} edge_padding_high: {} interior_padding: {} }} ",
+        dim.edge_padding_low, dim.edge_padding_high, dim.interior_padding));
+    }
+    result.trim_end().to_string()
   }
 }
 
 // Describes the replica groups in a cross replica op (e.g., all-reduce and
 // all-to-all).
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ReplicaGroup {
   // The ids of the replicas that belongs to the same group. The ordering of the
   // ids matters in some ops (e.g., all-to-all).
@@ -576,6 +733,7 @@ impl ReplicaGroup {
   }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum RandomDistribution {
   Invalid,
   // Creates a uniform-distribution-generated random number on the semi-open
@@ -594,13 +752,14 @@ pub fn random_distribution_name(dist: &RandomDistribution) -> String {
   }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum RandomAlgorithm {
   Default,
   ThreeFry,
   Philox,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum StepMarkerLocation {
   AtEntry,
   AtTopLevelWhileLoop,
@@ -608,6 +767,7 @@ pub enum StepMarkerLocation {
   None,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum CollectiveOpType {
   NoOp,
   AllReduce,
@@ -621,28 +781,28 @@ pub enum CollectiveOpType {
 // Enables strict PGLE checking. If an FDO profile is specified and latency
  // hiding scheduler encounters missing instructions in the profile
  // compilation will halt or warn depending on the value of this option.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PgleStrictnessLevel {
   Off,
   Warn,
   Error,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AutotuneCacheMode {
   Unspecified,
   Update,
   Read,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum LibNvJitLinkMode {
   Auto,
   Disabled,
   Enabled,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum WhileLoopUnrolling {
   NoUnroll,
   DoubleBuffer,
@@ -650,7 +810,7 @@ pub enum WhileLoopUnrolling {
   AutoUnroll,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PartitioningAlgorithm {
   Noop,
   Exp0,
@@ -659,21 +819,21 @@ pub enum PartitioningAlgorithm {
 }
 
 // Experimental optimizations for SPMD-based pipeline parallelism on GPU.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PipelineParallelismOptLevel {
   Disable,
   Enable,
   EnableCycleDecomposer,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ShapeChecks {
   Ignore,
   Runtime,
   CompileTime,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CommandBufferCmdType {
   Invalid,
   Fusion,
@@ -687,7 +847,7 @@ pub enum CommandBufferCmdType {
   DynamicsliceFusion,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum XnnGraphFusionMode {
   Diabled,
   Greedy,
@@ -704,6 +864,7 @@ pub enum XnnGraphFusionMode {
 // 2. Backend-specific options: `blitz_$backend_$flag_name` - must be in the
 //    corresponding backend section, and sorted alphabetically by the flag name.
 //
+#[derive(Debug, Clone, PartialEq)]
 pub struct DebugOptions {
   cpu_enable_concurrency_optimized_scheduler: bool,
   cpu_enable_fast_math: bool,
@@ -1203,6 +1364,42 @@ impl DebugOptions {
     self.eliminate_hlo_implicit_broadcast = value;
   }
 
+  pub fn set_blitz_dump_to(&mut self, value: String) {
+    self.dump_to = value;
+  }
+
+  pub fn set_blitz_dump_hlo_module_re(&mut self, value: String) {
+    self.dump_hlo_module_re = value;
+  }
+
+  pub fn set_blitz_dump_hlo_pass_re(&mut self, value: String) {
+    self.dump_hlo_pass_re = value;
+  }
+
+  pub fn set_blitz_dump_hlo_as_text(&mut self, value: bool) {
+    self.dump_hlo_as_text = value;
+  }
+
+  pub fn set_blitz_dump_hlo_as_proto(&mut self, value: bool) {
+    self.dump_hlo_as_proto = value;
+  }
+
+  pub fn set_blitz_dump_hlo_as_dot(&mut self, value: bool) {
+    self.dump_hlo_as_dot = value;
+  }
+
+  pub fn set_blitz_dump_hlo_as_url(&mut self, value: bool) {
+    self.dump_hlo_as_url = value;
+  }
+
+  pub fn set_blitz_dump_hlo_snapshots(&mut self, value: bool) {
+    self.dump_hlo_snapshots = value;
+  }
+
+  pub fn set_blitz_dump_compress_protos(&mut self, value: bool) {
+    self.dump_compress_proto = value;
+  }
+
   pub fn set_blitz_dump_hlo_as_html(&mut self, value: bool) {
     self.dump_hlo_as_html = value;
   }
@@ -1822,17 +2019,65 @@ impl DebugOptions {
   pub fn blitz_flags_reset(&self) -> bool {
     false
   }
+
+  pub fn blitz_dump_to(&self) -> String {
+    self.dump_to.clone()
+  }
+
+  pub fn blitz_dump_hlo_module_re(&self) -> String {
+    self.dump_hlo_module_re.clone()
+  }
+
+  pub fn blitz_dump_hlo_pass_re(&self) -> String {
+    self.dump_hlo_pass_re.clone()
+  }
+
+  pub fn blitz_dump_hlo_as_text(&self) -> bool {
+    self.dump_hlo_as_text
+  }
+
+  pub fn blitz_dump_hlo_as_proto(&self) -> bool {
+    self.dump_hlo_as_proto
+  }
+
+  pub fn blitz_dump_hlo_as_dot(&self) -> bool {
+    self.dump_hlo_as_dot
+  }
+
+  pub fn blitz_dump_hlo_as_url(&self) -> bool {
+    self.dump_hlo_as_url
+  }
+
+  pub fn blitz_dump_hlo_as_html(&self) -> bool {
+    self.dump_hlo_as_html
+  }
+
+  pub fn blitz_dump_hlo_snapshots(&self) -> bool {
+    self.dump_hlo_snapshots
+  }
+
+  pub fn blitz_dump_include_timestamp(&self) -> bool {
+    self.dump_include_timestamp
+  }
+
+  pub fn blitz_dump_max_hlo_modules(&self) -> i64 {
+    self.dump_max_hlo_modules
+  }
+
+  pub fn blitz_dump_compress_protos(&self) -> bool {
+    self.dump_compress_proto
+  }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-enum Transpose {
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Transpose {
   Invalid,
   NoTranspose,
   Transpose,
   Adjoint,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct TriangularSolveOptions {
   left_side: bool,
   lower: bool,
@@ -1840,21 +2085,162 @@ pub struct TriangularSolveOptions {
   transpose_a: Transpose,
 }
 
-#[derive(Debug, Clone)]
+impl TriangularSolveOptions {
+  pub fn new() -> Self {
+    TriangularSolveOptions {
+      left_side: false,
+      lower: false,
+      unit_diagonal: false,
+      transpose_a: Transpose::Invalid
+    }
+  }
+
+  pub fn left_side(&self) -> bool {
+    self.left_side
+  }
+
+  pub fn set_left_side(&mut self, left_side: bool) {
+    self.left_side = left_side;
+  }
+
+  pub fn lower(&self) -> bool {
+    self.lower
+  }
+
+  pub fn set_lower(&mut self, lower: bool) {
+    self.lower = lower;
+  }
+
+  pub fn unit_diagonal(&self) -> bool {
+    self.unit_diagonal
+  }
+
+  pub fn set_unit_diagonal(&mut self, unit_diagonal: bool) {
+    self.unit_diagonal = unit_diagonal;
+  }
+
+  pub fn transpose_a(&self) -> Transpose {
+    self.transpose_a.clone()
+  }
+
+  pub fn set_transpose_a(&mut self, transpose_a: Transpose) {
+    self.transpose_a = transpose_a;
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct CholeskyOptions {
   lower: bool
 }
 
 impl CholeskyOptions {
+  pub fn new() -> Self {
+    CholeskyOptions { lower: false }
+  }
+
+  pub fn lower(&self) -> bool {
+    self.lower
+  }
+
   pub fn set_lower(&mut self, lower: bool) {
     self.lower = lower;
   }
 }
 
-#[derive(Debug, Clone)]
-pub struct GatherDimensionNumbers {}
+// Describes the dimension numbers for a gather operation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GatherDimensionNumbers {
+  // "Window indices" is a term for a set of indices that index into the
+  // interior of a dynamic-slice from the input tensor, the starting indices for
+  // which were computed from output_gather_dims (see the operation semantic for
+  // how this is defined) and the start_indices tensor.
+  //
+  // The window indices for a specific output index Out is computed as:
+  //
+  //  i = 0
+  //  for (k : [0, input_tensor_shape.rank))
+  //    window_indices[k] =
+  //      if k in collapsed_slice_dims
+  //      then 0
+  //      else Out[offset_dims[i++]]
+  offset_dims: Vec<i64>,
+  collapsed_slice_dims: Vec<i64>,
+  // This is interpreted as a map from i to start_index_map[i]. It
+  // transforms the gather index looked up from the start_indices tensor into
+  // the starting index in the input space.
+  start_index_map: Vec<i64>,
+  // The dimension in the start_indices input that contains the starting
+  // indices.
+  index_vector_dim: i64,
+  // This is the batch dimensions in the operand.
+  operand_batching_dims: Vec<i64>,
+  // This is the batch dimensions in the index, and it should be the same size
+  // as operand_batching_dims.
+  start_indices_batching_dims: Vec<i64>,
+}
+
+impl GatherDimensionNumbers {
+  pub fn new() -> Self {
+    GatherDimensionNumbers {
+      offset_dims: Vec::new(),
+      collapsed_slice_dims: Vec::new(),
+      start_index_map: Vec::new(),
+      index_vector_dim: 0,
+      operand_batching_dims: Vec::new(),
+      start_indices_batching_dims: Vec::new()
+    }
+  }
 
-#[derive(Debug, Clone, PartialEq)]
+  pub fn offset_dims(&self) -> &Vec<i64> {
+    &self.offset_dims
+  }
+
+  pub fn add_offset_dims(&mut self, dim: i64) {
+    self.offset_dims.push(dim);
+  }
+
+  pub fn collapsed_slice_dims(&self) -> &Vec<i64> {
+    &self.collapsed_slice_dims
+  }
+
+  pub fn add_collapsed_slice_dims(&mut self, dim: i64) {
+    self.collapsed_slice_dims.push(dim);
+  }
+
+  pub fn start_index_map(&self) -> &Vec<i64> {
+    &self.start_index_map
+  }
+
+  pub fn add_start_index_map(&mut self, dim: i64) {
+    self.start_index_map.push(dim);
+  }
+
+  pub fn index_vector_dim(&self) -> i64 {
+    self.index_vector_dim
+  }
+
+  pub fn set_index_vector_dim(&mut self, dim: i64) {
+    self.index_vector_dim = dim;
+  }
+
+  pub fn operand_batching_dims(&self) -> &Vec<i64> {
+    &self.operand_batching_dims
+  }
+
+  pub fn add_operand_batching_dims(&mut self, dim: i64) {
+    self.operand_batching_dims.push(dim);
+  }
+
+  pub fn start_indices_batching_dims(&self) -> &Vec<i64> {
+    &self.start_indices_batching_dims
+  }
+
+  pub fn add_start_indices_batching_dims(&mut self, dim: i64) {
+    self.start_indices_batching_dims.push(dim);
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SparsityType {
   Invalid,
   StructuredNM,  
@@ -1866,7 +2252,7 @@ pub enum SparsityType {
 // Restrictions:
 // - only one operand of the dot operation may be sparse;
 // - only the contracting dimension may be sparse.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SparsityDescriptor {
   t: SparsityType,
   // Sparse operand index (0 or 1).
@@ -1878,32 +2264,263 @@ pub struct SparsityDescriptor {
   m: i64,
 }
 
-#[derive(Debug, Clone)]
-pub struct Window {}
+impl SparsityDescriptor {
+  pub fn new() -> Self {
+    SparsityDescriptor {
+      t: SparsityType::Invalid,
+      index: 0,
+      dimension: 0,
+      n: 0,
+      m: 0
+    }
+  }
 
-#[derive(Debug, Clone)]
+  pub fn sparsity_type(&self) -> SparsityType {
+    self.t.clone()
+  }
+
+  pub fn set_type(&mut self, t: SparsityType) {
+    self.t = t;
+  }
+
+  pub fn index(&self) -> i64 {
+    self.index
+  }
+
+  pub fn set_index(&mut self, index: i64) {
+    self.index = index;
+  }
+
+  pub fn dimension(&self) -> i64 {
+    self.dimension
+  }
+
+  pub fn set_dimension(&mut self, dimension: i64) {
+    self.dimension = dimension;
+  }
+
+  pub fn n(&self) -> i64 {
+    self.n
+  }
+
+  pub fn set_n(&mut self, n: i64) {
+    self.n = n;
+  }
+
+  pub fn m(&self) -> i64 {
+    self.m
+  }
+
+  pub fn set_m(&mut self, m: i64) {
+    self.m = m;
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WindowDimension {
+  // The size of the window in this dimension. For a rectangle, this would be
+  // the width or height.
+  size: i64,
+  // The stride at which the window moves across the base area in this
+  // dimension. In other words, this is the spacing between different
+  // positions of the window in this dimension.
+  stride: i64,
+  // If positive, means the amount of padding to add to the base area at the
+  // low end of this dimension; if negative, its negative means the number of
+  // elements removed from the low end of this dimension.
+  padding_low: i64,
+  // As padding_low, but on the high end of this dimension.
+  padding_high: i64,
+  // Dilation factor of the sliding window in this dimension. A dilation factor
+  // of 1 means no dilation.
+  window_dilation: i64,
+  // Dilation factor of the base area in this dimension. A dilation factor of 1
+  // means no dilation.
+  base_dilation: i64,
+  // Window reversal means that this dimension was logically reversed before
+  // the operation.
+  window_reversal: bool,
+}
+
+impl WindowDimension {
+  pub fn new() -> Self {
+    WindowDimension {
+      size: 0,
+      stride: 1,
+      padding_low: 0,
+      padding_high: 0,
+      window_dilation: 1,
+      base_dilation: 1,
+      window_reversal: false
+    }
+  }
+
+  pub fn size(&self) -> i64 {
+    self.size
+  }
+
+  pub fn set_size(&mut self, size: i64) {
+    self.size = size;
+  }
+
+  pub fn stride(&self) -> i64 {
+    self.stride
+  }
+
+  pub fn set_stride(&mut self, stride: i64) {
+    self.stride = stride;
+  }
+
+  pub fn padding_low(&self) -> i64 {
+    self.padding_low
+  }
+
+  pub fn set_padding_low(&mut self, padding_low: i64) {
+    self.padding_low = padding_low;
+  }
+
+  pub fn padding_high(&self) -> i64 {
+    self.padding_high
+  }
+
+  pub fn set_padding_high(&mut self, padding_high: i64) {
+    self.padding_high = padding_high;
+  }
+
+  pub fn window_dilation(&self) -> i64 {
+    self.window_dilation
+  }
+
+  pub fn set_window_dilation(&mut self, window_dilation: i64) {
+    self.window_dilation = window_dilation;
+  }
+
+  pub fn base_dilation(&self) -> i64 {
+    self.base_dilation
+  }
+
+  pub fn set_base_dilation(&mut self, base_dilation: i64) {
+    self.base_dilation = base_dilation;
+  }
+
+  pub fn window_reversal(&self) -> bool {
+    self.window_reversal
+  }
+
+  pub fn set_window_reversal(&mut self, window_reversal: bool) {
+    self.window_reversal = window_reversal;
+  }
+}
+
+// Describes the windowing in an operation such as convolution.
+//
+// The window is moved across a base area and for each position of the
+// window a computation is performed. The field below describes the
+// window and the movement of the window across a base area.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Window {
+  dimensions: Vec<WindowDimension>
+}
+
+impl Window {
+  pub fn new() -> Self {
+    Window { dimensions: Vec::new() }
+  }
+
+  pub fn dimensions(&self, index: usize) -> &WindowDimension {
+    &self.dimensions[index]
+  }
+
+  pub fn mutable_dimensions(&mut self, index: usize) -> &mut WindowDimension {
+    &mut self.dimensions[index]
+  }
+
+  pub fn dimensions_vec(&self) -> &Vec<WindowDimension> {
+    &self.dimensions
+  }
+
+  pub fn dimensions_size(&self) -> usize {
+    self.dimensions.len()
+  }
+
+  pub fn add_dimensions(&mut self, dimension: WindowDimension) {
+    self.dimensions.push(dimension);
+  }
+}
+
+// Describes the dimension numbers for a scatter operation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ScatterDimensionNummbers {
-  update_window_dims: i64,
-  inserted_window_dims: i64,
-  index_vector_dim: i64
+  // The set of dimensions in the updates shape that are window dimensions.
+  update_window_dims: Vec<i64>,
+  // The set of window dimensions that must be inserted into the updates shape.
+  inserted_window_dims: Vec<i64>,
+  scatter_dims_to_operand_dims: Vec<i64>,
+  index_vector_dim: i64,
+  // This is the batch dimensions in the input.
+  input_batching_dims: Vec<i64>,
+  // This is the batch dimension in the index.
+  scatter_indices_batching_dims: Vec<i64>,
 }
 
 impl ScatterDimensionNummbers {
+  pub fn new() -> Self {
+    ScatterDimensionNummbers {
+      update_window_dims: Vec::new(),
+      inserted_window_dims: Vec::new(),
+      scatter_dims_to_operand_dims: Vec::new(),
+      index_vector_dim: 0,
+      input_batching_dims: Vec::new(),
+      scatter_indices_batching_dims: Vec::new()
+    }
+  }
+
   pub fn index_vector_dim(&self) -> i64 {
     self.index_vector_dim
   }
 
+  pub fn set_index_vector_dim(&mut self, dim: i64) {
+    self.index_vector_dim = dim;
+  }
+
   pub fn update_window_dims(&self) -> &Vec<i64> {
-    //self.update_window_dims
-    unimplemented!()
+    &self.update_window_dims
+  }
+
+  pub fn add_update_window_dims(&mut self, dim: i64) {
+    self.update_window_dims.push(dim);
   }
 
   pub fn scatter_dims_to_operand_dims(&self) -> &Vec<i64> {
-    unimplemented!()
+    &self.scatter_dims_to_operand_dims
+  }
+
+  pub fn add_scatter_dims_to_operand_dims(&mut self, dim: i64) {
+    self.scatter_dims_to_operand_dims.push(dim);
   }
 
   pub fn inserted_window_dims(&self) -> &Vec<i64> {
-    unimplemented!()
+    &self.inserted_window_dims
+  }
+
+  pub fn add_inserted_window_dims(&mut self, dim: i64) {
+    self.inserted_window_dims.push(dim);
+  }
+
+  pub fn input_batching_dims(&self) -> &Vec<i64> {
+    &self.input_batching_dims
+  }
+
+  pub fn add_input_batching_dims(&mut self, dim: i64) {
+    self.input_batching_dims.push(dim);
+  }
+
+  pub fn scatter_indices_batching_dims(&self) -> &Vec<i64> {
+    &self.scatter_indices_batching_dims
+  }
+
+  pub fn add_scatter_indices_batching_dims(&mut self, dim: i64) {
+    self.scatter_indices_batching_dims.push(dim);
   }
 }
 
@@ -2060,9 +2677,35 @@ impl WhileLoopBackendConfig {
     WhileLoopBackendConfig { known_trip_count: 0 }
   }
 
+  pub fn known_trip_count(&self) -> usize {
+    self.known_trip_count
+  }
+
   pub fn set_known_trip_count(&mut self, count: usize) {
     self.known_trip_count = count;
   }
+
+  // Serializes the config in the JSON form backend configs are printed as,
+  // e.g. {"known_trip_count":{"n":"5"}}.
+  pub fn to_json_string(&self) -> String {
+    format!("{{\"known_trip_count\":{{\"n\":\"{}\"}}}}", self.known_trip_count)
+  }
+
+  // Parses a config printed by to_json_string. Returns None if 'json' holds
+  // no trip count.
+  pub fn from_json_string(json: &str) -> Option<Self> {
+    let compact: String = json.chars().filter(|c| !c.is_whitespace()).collect();
+    let start = compact.find("\"known_trip_count\":{\"n\":")? +
+      "\"known_trip_count\":{\"n\":".len();
+    let digits: String = compact[start..].chars()
+      .skip_while(|c| *c == '"')
+      .take_while(|c| c.is_ascii_digit())
+      .collect();
+    let count = digits.parse::<usize>().ok()?;
+    let mut config = WhileLoopBackendConfig::new();
+    config.set_known_trip_count(count);
+    Some(config)
+  }
 }
 
 // Profile data from the execution of a computation.
@@ -2113,6 +2756,7 @@ pub struct DeviceHandle {
   device_count: i64
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum CustomCallSchedule {
   None,
   Latest,
@@ -2121,6 +2765,7 @@ pub enum CustomCallSchedule {
 
 // The version of the API used by the custom call function. The signatures for
 // each version are given below.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum CustomCallApiVersion {
   Unspecified,
   Original,
@@ -2152,8 +2797,21 @@ impl SliceDimensions {
   pub fn set_stride(&mut self, stride: i64) {
     self.stride = stride;
   }
+
+  pub fn start(&self) -> i64 {
+    self.start
+  }
+
+  pub fn limit(&self) -> i64 {
+    self.limit
+  }
+
+  pub fn stride(&self) -> i64 {
+    self.stride
+  }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ResultAccuracy {}
 
 impl ResultAccuracy {
@@ -11,7 +11,7 @@ pub enum ComparisonOrder {
 }
 
 // Represents different comparison operations.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ComparisonDirection {
   Eq,
   Ne,
@@ -21,7 +21,7 @@ pub enum ComparisonDirection {
   Lt,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ComparisonType {
   Float,
   FloatTotalOrder,
@@ -42,15 +42,29 @@ pub fn is_valid_comparison(t: &PrimitiveType, order: &ComparisonOrder) -> bool {
 }
 
 pub fn string_to_comparison_direction(
-  _direction: &String) -> Result<ComparisonDirection, String>
+  direction: &String) -> Result<ComparisonDirection, String>
 {
-  unimplemented!()    
+  match direction.as_str() {
+    "EQ" => Ok(ComparisonDirection::Eq),
+    "NE" => Ok(ComparisonDirection::Ne),
+    "GE" => Ok(ComparisonDirection::Ge),
+    "GT" => Ok(ComparisonDirection::Gt),
+    "LE" => Ok(ComparisonDirection::Le),
+    "LT" => Ok(ComparisonDirection::Lt),
+    _ => Err(format!("Unknown comparison direction: {}", direction))
+  }
 }
 
 pub fn string_to_comparison_type(
-  _comparison: &String) -> Result<ComparisonType, String>
+  comparison: &String) -> Result<ComparisonType, String>
 {
-  unimplemented!()    
+  match comparison.as_str() {
+    "FLOAT" => Ok(ComparisonType::Float),
+    "TOTALORDER" => Ok(ComparisonType::FloatTotalOrder),
+    "SIGNED" => Ok(ComparisonType::Signed),
+    "UNSIGNED" => Ok(ComparisonType::Unsigned),
+    _ => Err(format!("Unknown comparison type: {}", comparison))
+  }
 }
 
 pub fn comparison_direction_to_string(dir: &ComparisonDirection) -> String {
@@ -0,0 +1,967 @@
+#![allow(dead_code)]
+
+use std::fmt::Debug;
+
+use num::complex::{Complex32, Complex64};
+
+use crate::{
+  blitz_data::PrimitiveType,
+  index_util::IndexUtil,
+  literal::{ArrayValueState, Literal, LiteralBase},
+  primitive_util::{
+    bit_width, float_from_bits, float_to_bits, is_4bit_type, is_complex_type,
+    is_floating_point_type, is_integral_type, is_signed_integral_type,
+    is_unsigned_integral_type, lowercase_primitive_type_name
+  },
+  shape::Shape,
+  shape_tree::ShapeTree,
+  shape_util::ShapeUtil
+};
+
+// A Rust type that can be stored as the elements of a DynLiteral array of the
+// matching primitive type.
+pub trait NativeType: Copy + Default + PartialEq + Debug + 'static {
+  fn primitive_type() -> PrimitiveType;
+
+  // Writes the little endian representation of the value into 'bytes'.
+  fn write_bytes(&self, bytes: &mut [u8]);
+
+  // Reads a value from its little endian representation.
+  fn read_bytes(bytes: &[u8]) -> Self;
+}
+
+impl NativeType for bool {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::Pred }
+  fn write_bytes(&self, bytes: &mut [u8]) { bytes[0] = *self as u8; }
+  fn read_bytes(bytes: &[u8]) -> Self { bytes[0] != 0 }
+}
+
+impl NativeType for i8 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::S8 }
+  fn write_bytes(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+  fn read_bytes(bytes: &[u8]) -> Self { i8::from_le_bytes(bytes.try_into().unwrap()) }
+}
+
+impl NativeType for i16 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::S16 }
+  fn write_bytes(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+  fn read_bytes(bytes: &[u8]) -> Self { i16::from_le_bytes(bytes.try_into().unwrap()) }
+}
+
+impl NativeType for i32 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::S32 }
+  fn write_bytes(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+  fn read_bytes(bytes: &[u8]) -> Self { i32::from_le_bytes(bytes.try_into().unwrap()) }
+}
+
+impl NativeType for i64 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::S64 }
+  fn write_bytes(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+  fn read_bytes(bytes: &[u8]) -> Self { i64::from_le_bytes(bytes.try_into().unwrap()) }
+}
+
+impl NativeType for u8 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::U8 }
+  fn write_bytes(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+  fn read_bytes(bytes: &[u8]) -> Self { u8::from_le_bytes(bytes.try_into().unwrap()) }
+}
+
+impl NativeType for u16 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::U16 }
+  fn write_bytes(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+  fn read_bytes(bytes: &[u8]) -> Self { u16::from_le_bytes(bytes.try_into().unwrap()) }
+}
+
+impl NativeType for u32 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::U32 }
+  fn write_bytes(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+  fn read_bytes(bytes: &[u8]) -> Self { u32::from_le_bytes(bytes.try_into().unwrap()) }
+}
+
+impl NativeType for u64 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::U64 }
+  fn write_bytes(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+  fn read_bytes(bytes: &[u8]) -> Self { u64::from_le_bytes(bytes.try_into().unwrap()) }
+}
+
+impl NativeType for f32 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::F32 }
+  fn write_bytes(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+  fn read_bytes(bytes: &[u8]) -> Self { f32::from_le_bytes(bytes.try_into().unwrap()) }
+}
+
+impl NativeType for f64 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::F64 }
+  fn write_bytes(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+  fn read_bytes(bytes: &[u8]) -> Self { f64::from_le_bytes(bytes.try_into().unwrap()) }
+}
+
+impl NativeType for Complex32 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::C64 }
+  fn write_bytes(&self, bytes: &mut [u8]) {
+    self.re.write_bytes(&mut bytes[0..4]);
+    self.im.write_bytes(&mut bytes[4..8]);
+  }
+  fn read_bytes(bytes: &[u8]) -> Self {
+    Complex32::new(f32::read_bytes(&bytes[0..4]), f32::read_bytes(&bytes[4..8]))
+  }
+}
+
+impl NativeType for Complex64 {
+  fn primitive_type() -> PrimitiveType { PrimitiveType::C128 }
+  fn write_bytes(&self, bytes: &mut [u8]) {
+    self.re.write_bytes(&mut bytes[0..8]);
+    self.im.write_bytes(&mut bytes[8..16]);
+  }
+  fn read_bytes(bytes: &[u8]) -> Self {
+    Complex64::new(f64::read_bytes(&bytes[0..8]), f64::read_bytes(&bytes[8..16]))
+  }
+}
+
+// Returns the number of bytes an element of type 't' occupies in a
+// DynLiteral buffer. 4-bit types use one byte per element.
+pub fn element_size_in_bytes(t: &PrimitiveType) -> usize {
+  if *t == PrimitiveType::Pred || is_4bit_type(t) {
+    return 1;
+  }
+  (bit_width(t) / 8) as usize
+}
+
+// An element of any primitive type, wide enough to hold all of them.
+// Integers and predicates are Integer, floating point values Real.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Element {
+  Integer(i128),
+  Real(f64),
+  Complex(f64, f64)
+}
+
+fn read_element(t: &PrimitiveType, bytes: &[u8]) -> Element {
+  let mut raw = [0_u8; 8];
+  let len = bytes.len().min(8);
+  raw[..len].copy_from_slice(&bytes[..len]);
+  let bits = u64::from_le_bytes(raw);
+  match t {
+    PrimitiveType::Pred => Element::Integer((bits != 0) as i128),
+    PrimitiveType::C64 => {
+      let value = Complex32::read_bytes(bytes);
+      Element::Complex(value.re as f64, value.im as f64)
+    },
+    PrimitiveType::C128 => {
+      let value = Complex64::read_bytes(bytes);
+      Element::Complex(value.re, value.im)
+    },
+    _ if is_integral_type(t) => {
+      let width = bit_width(t) as u32;
+      let value = if width == 64 { bits } else { bits & ((1 << width) - 1) };
+      if is_signed_integral_type(t) && value >> (width - 1) != 0 {
+        Element::Integer(value as i128 - (1_i128 << width))
+      } else {
+        Element::Integer(value as i128)
+      }
+    },
+    _ => Element::Real(float_from_bits(bits, t))
+  }
+}
+
+// Writes 'element' as an element of type 't'. Integers are wrapped into a
+// narrower integral type, floating point values are rounded to the precision
+// of the type, or truncated towards zero and saturated when written to an
+// integral type, with NaN becoming 0.
+fn write_element(t: &PrimitiveType, element: Element, bytes: &mut [u8]) {
+  let bits = match (t, element) {
+    (PrimitiveType::Pred, Element::Integer(v)) => (v != 0) as u64,
+    (PrimitiveType::Pred, Element::Real(v)) => (v != 0.0) as u64,
+    (PrimitiveType::Pred, Element::Complex(re, im)) => (re != 0.0 || im != 0.0) as u64,
+    (PrimitiveType::C64, _) | (PrimitiveType::C128, _) => {
+      let (re, im) = match element {
+        Element::Integer(v) => (v as f64, 0.0),
+        Element::Real(v) => (v, 0.0),
+        Element::Complex(re, im) => (re, im)
+      };
+      if *t == PrimitiveType::C64 {
+        Complex32::new(re as f32, im as f32).write_bytes(bytes);
+      } else {
+        Complex64::new(re, im).write_bytes(bytes);
+      }
+      return;
+    },
+    _ if is_integral_type(t) => {
+      let width = bit_width(t) as u32;
+      let value = match element {
+        Element::Integer(v) => v,
+        Element::Real(v) | Element::Complex(v, _) => {
+          let (min, max) = if is_unsigned_integral_type(t) {
+            (0_i128, (1_i128 << width) - 1)
+          } else {
+            (-(1_i128 << (width - 1)), (1_i128 << (width - 1)) - 1)
+          };
+          if v.is_nan() { 0 } else { (v.trunc() as i128).clamp(min, max) }
+        }
+      };
+      value.rem_euclid(1_i128 << width) as u64
+    },
+    _ => match element {
+      Element::Integer(v) => float_to_bits(v as f64, t),
+      Element::Real(v) | Element::Complex(v, _) => float_to_bits(v, t)
+    }
+  };
+  let len = bytes.len();
+  bytes.copy_from_slice(&bits.to_le_bytes()[..len]);
+}
+
+// Returns the position of the element at 'multi_index' in the buffer of an
+// array of 'shape', following the layout of the shape if it has one and
+// row-major order otherwise.
+pub(crate) fn linear_index(shape: &Shape, multi_index: &Vec<i64>) -> usize {
+  assert_eq!(multi_index.len(), shape.rank(), "Index rank does not match the shape.");
+  if shape.has_layout() &&
+    shape.layout().as_ref().unwrap().minor_to_major_size() == shape.rank()
+  {
+    return IndexUtil::multi_dimensional_index_to_linear_index(shape, multi_index) as usize;
+  }
+  let mut index = 0;
+  for i in 0..shape.rank() {
+    assert!(multi_index[i] >= 0 && multi_index[i] < shape.dimensions(i),
+      "Index out of bounds.");
+    index = index * shape.dimensions(i) + multi_index[i];
+  }
+  index as usize
+}
+
+// Advances 'index' to the next index in row-major order. Returns false once
+// all indices were visited.
+pub(crate) fn next_index(shape: &Shape, index: &mut Vec<i64>) -> bool {
+  for i in (0..shape.rank()).rev() {
+    index[i] += 1;
+    if index[i] < shape.dimensions(i) {
+      return true;
+    }
+    index[i] = 0;
+  }
+  false
+}
+
+// Calls 'func' with the shape and index of each array in 'shape'.
+pub(crate) fn for_each_array<F>(shape: &Shape, index: &mut Vec<i64>, func: &mut F)
+  where F: FnMut(&Shape, &Vec<i64>)
+{
+  if shape.is_tuple() {
+    for i in 0..shape.tuple_shapes_size() {
+      index.push(i as i64);
+      for_each_array(shape.tuple_shapes(i), index, func);
+      index.pop();
+    }
+  } else if shape.is_array() {
+    func(shape, index);
+  }
+}
+
+fn concat_index(prefix: &Vec<i64>, index: &Vec<i64>) -> Vec<i64> {
+  let mut result = prefix.clone();
+  result.extend_from_slice(index);
+  result
+}
+
+// The buffer of a subshape of a DynLiteral. Only arrays have elements. The
+// buffer of an array with dynamic dimensions is allocated for the bounds of
+// the dimensions, and 'dynamic_sizes' holds the actual sizes.
+#[derive(Debug, Clone, PartialEq)]
+struct DynPiece {
+  buffer: Vec<u8>,
+  dynamic_sizes: Vec<i64>,
+  array_value_state: ArrayValueState
+}
+
+impl Default for DynPiece {
+  fn default() -> Self {
+    DynPiece {
+      buffer: Vec::new(),
+      dynamic_sizes: Vec::new(),
+      array_value_state: ArrayValueState::Known
+    }
+  }
+}
+
+// A literal whose element type is only known at run time. Unlike Literal<T>,
+// it can hold arrays of any primitive type, and tuples whose elements have
+// different element types. Each array is stored as the little endian bytes of
+// its elements, in the order of the layout of its shape, and the accessors
+// dispatch on Shape::element_type().
+#[derive(Debug, Clone)]
+pub struct DynLiteral {
+  shape: Shape,
+  pieces: ShapeTree<DynPiece>
+}
+
+impl DynLiteral {
+  // Creates a literal of the given shape whose arrays are all zeros.
+  pub fn new(shape: &Shape) -> Self {
+    DynLiteral::new_with_state(shape, ArrayValueState::Known)
+  }
+
+  // Similar to new() but marks all leaf arrays as unknown.
+  pub fn create_from_shape_with_unknown_leaf_arrays(shape: &Shape) -> Self {
+    DynLiteral::new_with_state(shape, ArrayValueState::Unknown)
+  }
+
+  fn new_with_state(shape: &Shape, state: ArrayValueState) -> Self {
+    let mut shape_copy = shape.clone();
+    let mut pieces: ShapeTree<DynPiece> = ShapeTree::new(&mut shape_copy);
+    let mut func = |subshape: &Shape, index: &Vec<i64>| {
+      let size = ShapeUtil::elements_in(subshape) as usize *
+        element_size_in_bytes(&subshape.element_type());
+      pieces.set_element_value(index, DynPiece {
+        buffer: vec![0; size],
+        dynamic_sizes: subshape.dimensions_vec().clone(),
+        array_value_state: state.clone()
+      });
+    };
+    for_each_array(shape, &mut vec![], &mut func);
+    DynLiteral { shape: shape.clone(), pieces: pieces }
+  }
+
+  // Creates a scalar literal.
+  pub fn create_r0<T: NativeType>(value: T) -> Self {
+    let mut literal =
+      DynLiteral::new(&ShapeUtil::make_scalar_shape(&T::primitive_type()));
+    literal.set(&vec![], &vec![], value);
+    literal
+  }
+
+  // Creates a rank 1 literal.
+  pub fn create_r1<T: NativeType>(values: &[T]) -> Self {
+    let mut literal = DynLiteral::new(
+      &ShapeUtil::make_shape(&T::primitive_type(), vec![values.len() as i64]));
+    literal.set_data(&vec![], values);
+    literal
+  }
+
+  // Creates a tuple literal which owns the given elements.
+  pub fn make_tuple(elements: Vec<DynLiteral>) -> Self {
+    let shape = ShapeUtil::make_tuple_shape(
+      elements.iter().map(|e| e.shape().clone()).collect());
+    let mut literal = DynLiteral::new(&shape);
+    for (i, element) in elements.iter().enumerate() {
+      literal.copy_from(element, &vec![i as i64], &vec![]).unwrap();
+    }
+    literal
+  }
+
+  // Returns the elements of this tuple literal.
+  pub fn decompose_tuple(&self) -> Vec<DynLiteral> {
+    assert!(self.shape.is_tuple(), "Only tuple literals can be decomposed.");
+    (0..self.shape.tuple_shapes_size())
+      .map(|i| self.sub_literal(&vec![i as i64]))
+      .collect()
+  }
+
+  // Returns a copy of the part of this literal at the given shape index.
+  pub fn sub_literal(&self, shape_index: &Vec<i64>) -> DynLiteral {
+    let mut literal = DynLiteral::new(&ShapeUtil::get_subshape(&self.shape, shape_index));
+    literal.copy_from(self, &vec![], shape_index).unwrap();
+    literal
+  }
+
+  // Copies the part of 'src' at 'src_shape_index' into the part of this
+  // literal at 'dest_shape_index'. The shapes of the parts must be
+  // compatible.
+  pub fn copy_from(
+    &mut self,
+    src: &DynLiteral,
+    dest_shape_index: &Vec<i64>,
+    src_shape_index: &Vec<i64>) -> Result<(), String>
+  {
+    let dest_subshape = ShapeUtil::get_subshape(&self.shape, dest_shape_index);
+    let src_subshape = ShapeUtil::get_subshape(&src.shape, src_shape_index);
+    if !ShapeUtil::compatible(&dest_subshape, &src_subshape) {
+      return Err(format!("Destination subshape {} incompatible with source subshape {}.",
+        ShapeUtil::human_string(&dest_subshape), ShapeUtil::human_string(&src_subshape)));
+    }
+    let mut func = |subshape: &Shape, index: &Vec<i64>| {
+      let src_index = concat_index(src_shape_index, index);
+      let dest_index = concat_index(dest_shape_index, index);
+      let src_piece = src.pieces.element(&src_index);
+      let dest_shape = ShapeUtil::get_subshape(&self.shape, &dest_index);
+      if dest_shape == *subshape || src_piece.array_value_state != ArrayValueState::Known {
+        self.pieces.set_element_value(&dest_index, src_piece.clone());
+        return;
+      }
+      // The layouts differ, so copy element by element.
+      let src_shape = ShapeUtil::get_subshape(&src.shape, &src_index);
+      let size = element_size_in_bytes(&subshape.element_type());
+      let mut buffer = vec![0; src_piece.buffer.len()];
+      let mut multi_index = vec![0; subshape.rank()];
+      loop {
+        let from = linear_index(&src_shape, &multi_index) * size;
+        let to = linear_index(&dest_shape, &multi_index) * size;
+        buffer[to..to + size].copy_from_slice(&src_piece.buffer[from..from + size]);
+        if !next_index(subshape, &mut multi_index) {
+          break;
+        }
+      }
+      self.pieces.set_element_value(&dest_index, DynPiece {
+        buffer: buffer,
+        dynamic_sizes: src_piece.dynamic_sizes.clone(),
+        array_value_state: ArrayValueState::Known
+      });
+    };
+    for_each_array(&src_subshape, &mut vec![], &mut func);
+    Ok(())
+  }
+
+  pub fn shape(&self) -> &Shape {
+    &self.shape
+  }
+
+  // Returns true if the leaf arrays of the literal within the given shape
+  // index are all known.
+  pub fn is_known(&self, shape_index: &Vec<i64>) -> bool {
+    let subshape = ShapeUtil::get_subshape(&self.shape, shape_index);
+    let mut known = true;
+    let mut func = |_subshape: &Shape, index: &Vec<i64>| {
+      let piece = self.pieces.element(&concat_index(shape_index, index));
+      known &= piece.array_value_state == ArrayValueState::Known;
+    };
+    for_each_array(&subshape, &mut vec![], &mut func);
+    known
+  }
+
+  // Returns the count of the elements in the array at the given shape index.
+  pub fn element_count(&self, shape_index: &Vec<i64>) -> i64 {
+    ShapeUtil::elements_in(&ShapeUtil::get_subshape(&self.shape, shape_index))
+  }
+
+  // Returns the bytes of the array at the given shape index.
+  pub fn untyped_data(&self, shape_index: &Vec<i64>) -> &Vec<u8> {
+    &self.pieces.element(shape_index).buffer
+  }
+
+  // Returns the size in bytes of the array at the given shape index.
+  pub fn size_bytes(&self, shape_index: &Vec<i64>) -> usize {
+    self.untyped_data(shape_index).len()
+  }
+
+  // Replaces the bytes of the array at the given shape index, which becomes
+  // known.
+  pub fn set_untyped_data(
+    &mut self, shape_index: &Vec<i64>, data: Vec<u8>) -> Result<(), String>
+  {
+    let piece = self.pieces.mutable_element(shape_index);
+    if piece.buffer.len() != data.len() {
+      return Err(format!("Expected {} bytes, but got {}.", piece.buffer.len(), data.len()));
+    }
+    piece.buffer = data;
+    piece.array_value_state = ArrayValueState::Known;
+    Ok(())
+  }
+
+  // Returns the size of the given dimension of the array at the given shape
+  // index, which is its bound if the dimension is dynamic.
+  pub fn get_dynamic_size(&self, dim_index: usize, shape_index: &Vec<i64>) -> i64 {
+    let subshape = ShapeUtil::get_subshape(&self.shape, shape_index);
+    if !subshape.is_dynamic_dimension(dim_index as i64) {
+      return subshape.dimensions(dim_index);
+    }
+    self.pieces.element(shape_index).dynamic_sizes[dim_index]
+  }
+
+  pub fn set_dynamic_size(&mut self, dim_index: usize, shape_index: &Vec<i64>, size: i64) {
+    let subshape = ShapeUtil::get_subshape(&self.shape, shape_index);
+    assert!(subshape.is_dynamic_dimension(dim_index as i64),
+      "Dimension {} is not dynamic.", dim_index);
+    assert!(size >= 0 && size <= subshape.dimensions(dim_index),
+      "Dynamic size {} is out of the bound {}.", size, subshape.dimensions(dim_index));
+    self.pieces.mutable_element(shape_index).dynamic_sizes[dim_index] = size;
+  }
+
+  // Returns a copy of this literal whose dynamic dimensions are replaced by
+  // static dimensions of their dynamic sizes.
+  pub fn to_static(&self) -> DynLiteral {
+    let static_shape = self.static_shape(&self.shape, &mut vec![]);
+    let mut result = DynLiteral::new(&static_shape);
+    let mut func = |subshape: &Shape, index: &Vec<i64>| {
+      let src_shape = ShapeUtil::get_subshape(&self.shape, index);
+      let src_piece = self.pieces.element(index);
+      if src_piece.array_value_state != ArrayValueState::Known {
+        result.set_unknown(index);
+        return;
+      }
+      if ShapeUtil::elements_in(subshape) == 0 {
+        return;
+      }
+      let size = element_size_in_bytes(&subshape.element_type());
+      let piece = result.pieces.mutable_element(index);
+      let mut multi_index = vec![0; subshape.rank()];
+      loop {
+        let from = linear_index(&src_shape, &multi_index) * size;
+        let to = linear_index(subshape, &multi_index) * size;
+        piece.buffer[to..to + size].copy_from_slice(&src_piece.buffer[from..from + size]);
+        if !next_index(subshape, &mut multi_index) {
+          break;
+        }
+      }
+    };
+    for_each_array(&static_shape, &mut vec![], &mut func);
+    result
+  }
+
+  fn static_shape(&self, shape: &Shape, index: &mut Vec<i64>) -> Shape {
+    if shape.is_tuple() {
+      let mut element_shapes = vec![];
+      for i in 0..shape.tuple_shapes_size() {
+        index.push(i as i64);
+        element_shapes.push(self.static_shape(shape.tuple_shapes(i), index));
+        index.pop();
+      }
+      return ShapeUtil::make_tuple_shape(element_shapes);
+    }
+    let mut result = shape.clone();
+    if shape.is_array() {
+      for i in 0..shape.rank() {
+        if shape.is_dynamic_dimension(i as i64) {
+          result.set_dimensions(i, self.get_dynamic_size(i, index));
+          result.set_dynamic_dimension(i, false);
+        }
+      }
+    }
+    result
+  }
+
+  // Marks the array at the given shape index as unknown.
+  pub fn set_unknown(&mut self, shape_index: &Vec<i64>) {
+    self.pieces.mutable_element(shape_index).array_value_state = ArrayValueState::Unknown;
+  }
+
+  fn array_shape<T: NativeType>(&self, shape_index: &Vec<i64>) -> Shape {
+    let subshape = ShapeUtil::get_subshape(&self.shape, shape_index);
+    assert!(subshape.is_array(), "Expected an array shape.");
+    assert_eq!(subshape.element_type(), T::primitive_type(),
+      "Element type does not match the native type.");
+    subshape
+  }
+
+  // Returns the element at the given index of the array at the given shape
+  // index. T must match the element type of the array.
+  pub fn get<T: NativeType>(&self, multi_index: &Vec<i64>, shape_index: &Vec<i64>) -> T {
+    let subshape = self.array_shape::<T>(shape_index);
+    let size = element_size_in_bytes(&subshape.element_type());
+    let offset = linear_index(&subshape, multi_index) * size;
+    T::read_bytes(&self.untyped_data(shape_index)[offset..offset + size])
+  }
+
+  pub fn set<T: NativeType>(
+    &mut self, multi_index: &Vec<i64>, shape_index: &Vec<i64>, value: T)
+  {
+    let subshape = self.array_shape::<T>(shape_index);
+    let size = element_size_in_bytes(&subshape.element_type());
+    let offset = linear_index(&subshape, multi_index) * size;
+    let piece = self.pieces.mutable_element(shape_index);
+    value.write_bytes(&mut piece.buffer[offset..offset + size]);
+  }
+
+  // Returns the elements of the array at the given shape index, in the order
+  // of its layout.
+  pub fn data<T: NativeType>(&self, shape_index: &Vec<i64>) -> Vec<T> {
+    let subshape = self.array_shape::<T>(shape_index);
+    let size = element_size_in_bytes(&subshape.element_type());
+    self.untyped_data(shape_index).chunks(size).map(T::read_bytes).collect()
+  }
+
+  // Replaces the elements of the array at the given shape index, given in
+  // the order of its layout. The array becomes known.
+  pub fn set_data<T: NativeType>(&mut self, shape_index: &Vec<i64>, values: &[T]) {
+    let subshape = self.array_shape::<T>(shape_index);
+    let size = element_size_in_bytes(&subshape.element_type());
+    let piece = self.pieces.mutable_element(shape_index);
+    assert_eq!(piece.buffer.len(), values.len() * size, "Wrong number of elements.");
+    for (i, value) in values.iter().enumerate() {
+      value.write_bytes(&mut piece.buffer[i * size..(i + 1) * size]);
+    }
+    piece.array_value_state = ArrayValueState::Known;
+  }
+
+  fn element(&self, multi_index: &Vec<i64>, shape_index: &Vec<i64>) -> (PrimitiveType, Element) {
+    let subshape = ShapeUtil::get_subshape(&self.shape, shape_index);
+    assert!(subshape.is_array(), "Expected an array shape.");
+    let t = subshape.element_type();
+    let size = element_size_in_bytes(&t);
+    let offset = linear_index(&subshape, multi_index) * size;
+    (t.clone(), read_element(&t, &self.untyped_data(shape_index)[offset..offset + size]))
+  }
+
+  // Returns the element at the given index, exactly as it is stored.
+  pub fn get_element(&self, multi_index: &Vec<i64>, shape_index: &Vec<i64>) -> Element {
+    self.element(multi_index, shape_index).1
+  }
+
+  // Returns the elements of the array at 'shape_index' exactly as they are
+  // stored, in row-major order whatever the layout.
+  pub fn elements(&self, shape_index: &Vec<i64>) -> Vec<Element> {
+    let subshape = ShapeUtil::get_subshape(&self.shape, shape_index);
+    assert!(subshape.is_array(), "Expected an array shape.");
+    let count = ShapeUtil::elements_in(&subshape) as usize;
+    let mut result = Vec::with_capacity(count);
+    let mut multi_index = vec![0; subshape.rank()];
+    for _ in 0..count {
+      result.push(self.get_element(&multi_index, shape_index));
+      next_index(&subshape, &mut multi_index);
+    }
+    result
+  }
+
+  // Sets the elements of the array at 'shape_index' from 'values' in row-major
+  // order, converted like by set_element().
+  pub fn set_elements(&mut self, shape_index: &Vec<i64>, values: Vec<Element>) {
+    let subshape = ShapeUtil::get_subshape(&self.shape, shape_index);
+    assert!(subshape.is_array(), "Expected an array shape.");
+    assert_eq!(values.len() as i64, ShapeUtil::elements_in(&subshape));
+    let mut multi_index = vec![0; subshape.rank()];
+    for value in values {
+      self.set_element(&multi_index, shape_index, value);
+      next_index(&subshape, &mut multi_index);
+    }
+  }
+
+  // Returns the element at the given index converted to f64, whatever the
+  // element type of the array. Fails for complex arrays.
+  pub fn get_as_double(
+    &self, multi_index: &Vec<i64>, shape_index: &Vec<i64>) -> Result<f64, String>
+  {
+    match self.element(multi_index, shape_index) {
+      (_, Element::Integer(v)) => Ok(v as f64),
+      (_, Element::Real(v)) => Ok(v),
+      (t, Element::Complex(_, _)) => Err(format!("Unsupported type: {}.",
+        lowercase_primitive_type_name(&t)))
+    }
+  }
+
+  // Returns the element at the given index converted to a complex number,
+  // whatever the element type of the array.
+  pub fn get_as_complex128(&self, multi_index: &Vec<i64>, shape_index: &Vec<i64>) -> Complex64 {
+    match self.element(multi_index, shape_index).1 {
+      Element::Integer(v) => Complex64::new(v as f64, 0.0),
+      Element::Real(v) => Complex64::new(v, 0.0),
+      Element::Complex(re, im) => Complex64::new(re, im)
+    }
+  }
+
+  // Sets the element at the given index from an f64, converted like by
+  // convert().
+  pub fn set_from_double(
+    &mut self, multi_index: &Vec<i64>, shape_index: &Vec<i64>, value: f64)
+  {
+    self.set_element(multi_index, shape_index, Element::Real(value));
+  }
+
+  // Sets the element at the given index from an integer, converted like by
+  // convert(). Unlike set_from_double() this is exact for 64-bit integers.
+  pub fn set_from_integer(
+    &mut self, multi_index: &Vec<i64>, shape_index: &Vec<i64>, value: i128)
+  {
+    self.set_element(multi_index, shape_index, Element::Integer(value));
+  }
+
+  // Sets the element at the given index from a complex number, converted like
+  // by convert().
+  pub fn set_from_complex128(
+    &mut self, multi_index: &Vec<i64>, shape_index: &Vec<i64>, value: Complex64)
+  {
+    self.set_element(multi_index, shape_index, Element::Complex(value.re, value.im));
+  }
+
+  // Sets the element at the given index, converted like by convert().
+  pub fn set_element(&mut self, multi_index: &Vec<i64>, shape_index: &Vec<i64>, value: Element) {
+    let subshape = ShapeUtil::get_subshape(&self.shape, shape_index);
+    assert!(subshape.is_array(), "Expected an array shape.");
+    let t = subshape.element_type();
+    let size = element_size_in_bytes(&t);
+    let offset = linear_index(&subshape, multi_index) * size;
+    let piece = self.pieces.mutable_element(shape_index);
+    write_element(&t, value, &mut piece.buffer[offset..offset + size]);
+  }
+
+  // Converts this array literal to the element type 'to'. Integers are
+  // wrapped into a narrower integral type, floating point values are rounded
+  // to the precision of 'to', or truncated towards zero and saturated when
+  // converted to an integral type. Complex numbers can only be converted to a
+  // complex type.
+  pub fn convert(&self, to: &PrimitiveType) -> Result<DynLiteral, String> {
+    if !self.shape.is_array() {
+      return Err(format!("Convert is only supported for arrays, but got {}.",
+        ShapeUtil::human_string(&self.shape)));
+    }
+    let from = self.shape.element_type();
+    if is_complex_type(&from) && !is_complex_type(to) {
+      return Err(format!("Unimplemented conversion from {} to {}.",
+        lowercase_primitive_type_name(&from), lowercase_primitive_type_name(to)));
+    }
+    if !is_complex_type(to) && !is_integral_type(to) && !is_floating_point_type(to) &&
+      *to != PrimitiveType::Pred
+    {
+      return Err(format!("Cannot convert to {}.", lowercase_primitive_type_name(to)));
+    }
+    let mut result = DynLiteral::new(&ShapeUtil::change_element_type(&self.shape, to));
+    if !self.is_known(&vec![]) {
+      result.set_unknown(&vec![]);
+      return Ok(result);
+    }
+    let from_size = element_size_in_bytes(&from);
+    let to_size = element_size_in_bytes(to);
+    let src = self.untyped_data(&vec![]);
+    let dest = &mut result.pieces.mutable_element(&vec![]).buffer;
+    for i in 0..ShapeUtil::elements_in(&self.shape) as usize {
+      let element = read_element(&from, &src[i * from_size..(i + 1) * from_size]);
+      write_element(to, element, &mut dest[i * to_size..(i + 1) * to_size]);
+    }
+    Ok(result)
+  }
+
+  // Creates a literal from a Literal<T>. The element types of its arrays must
+  // match T.
+  pub fn from_literal<T: NativeType>(literal: &Literal<T>) -> Result<DynLiteral, String> {
+    DynLiteral::from_literal_base(&literal.base)
+  }
+
+  pub fn from_literal_base<T: NativeType>(
+    literal: &LiteralBase<T>) -> Result<DynLiteral, String>
+  {
+    let shape = literal.shape();
+    let mut result = DynLiteral::new(shape);
+    let mut status = Ok(());
+    let mut func = |subshape: &Shape, index: &Vec<i64>| {
+      if status.is_err() {
+        return;
+      }
+      if subshape.element_type() != T::primitive_type() {
+        status = Err(format!("Expected arrays of {}, but got {}.",
+          lowercase_primitive_type_name(&T::primitive_type()),
+          ShapeUtil::human_string(subshape)));
+        return;
+      }
+      if !literal.is_known(index) {
+        result.set_unknown(index);
+        return;
+      }
+      let count = ShapeUtil::elements_in(subshape) as usize;
+      let data = literal.data(index);
+      if data.len() < count {
+        status = Err(format!("Expected {} elements, but got {}.", count, data.len()));
+        return;
+      }
+      result.set_data(index, &data[..count]);
+      for i in 0..subshape.rank() {
+        if subshape.is_dynamic_dimension(i as i64) {
+          result.set_dynamic_size(i, index, literal.get_dynamic_size(i, index));
+        }
+      }
+    };
+    for_each_array(shape, &mut vec![], &mut func);
+    status?;
+    Ok(result)
+  }
+
+  // Returns this literal as a Literal<T>. The element types of its arrays
+  // must match T.
+  pub fn to_literal<T: NativeType>(&self) -> Result<Literal<T>, String> {
+    let mut literal = Literal::new_from_shape(&self.shape);
+    let mut status = Ok(());
+    let mut func = |subshape: &Shape, index: &Vec<i64>| {
+      if status.is_err() {
+        return;
+      }
+      if subshape.element_type() != T::primitive_type() {
+        status = Err(format!("Expected arrays of {}, but got {}.",
+          lowercase_primitive_type_name(&T::primitive_type()),
+          ShapeUtil::human_string(subshape)));
+        return;
+      }
+      if !self.is_known(index) {
+        literal.mutable_piece(index).set_array_value_state(ArrayValueState::Unknown);
+        return;
+      }
+      literal.set_data(index, self.data::<T>(index));
+      for i in 0..subshape.rank() {
+        if subshape.is_dynamic_dimension(i as i64) {
+          literal.set_dynamic_size(i, index, self.get_dynamic_size(i, index));
+        }
+      }
+    };
+    for_each_array(&self.shape, &mut vec![], &mut func);
+    status?;
+    mark_tuples_known(&self.shape, &mut literal, &mut vec![]);
+    Ok(literal)
+  }
+
+  // Creates a literal of 'shape' from 'literal', which holds the elements
+  // of 'shape' as f64 values in row-major order. The values are converted to
+  // the element types of 'shape' like by convert().
+  pub fn from_literal_f64(shape: &Shape, literal: &Literal<f64>) -> Self {
+    let mut result = DynLiteral::new(shape);
+    let mut func = |subshape: &Shape, index: &Vec<i64>| {
+      if !literal.is_known(index) {
+        result.set_unknown(index);
+        return;
+      }
+      let data = literal.data(index);
+      let mut multi_index = vec![0; subshape.rank()];
+      for value in data.iter().take(ShapeUtil::elements_in(subshape) as usize) {
+        result.set_from_double(&multi_index, index, *value);
+        next_index(subshape, &mut multi_index);
+      }
+    };
+    for_each_array(shape, &mut vec![], &mut func);
+    result
+  }
+
+  // Returns the elements of this literal as f64 values in row-major order, in
+  // a literal whose arrays are F64. Fails for complex arrays.
+  pub fn to_literal_f64(&self) -> Result<Literal<f64>, String> {
+    let f64_shape = to_f64_shape(&self.shape);
+    let mut literal = Literal::new_from_shape(&f64_shape);
+    let mut result = Ok(());
+    let mut func = |subshape: &Shape, index: &Vec<i64>| {
+      if result.is_err() {
+        return;
+      }
+      if !self.is_known(index) {
+        literal.mutable_piece(index).set_array_value_state(ArrayValueState::Unknown);
+        return;
+      }
+      let mut data = vec![];
+      let mut multi_index = vec![0; subshape.rank()];
+      for _ in 0..ShapeUtil::elements_in(subshape) {
+        match self.get_as_double(&multi_index, index) {
+          Ok(value) => data.push(value),
+          Err(error) => { result = Err(error); return; }
+        }
+        next_index(subshape, &mut multi_index);
+      }
+      literal.set_data(index, data);
+    };
+    for_each_array(&self.shape, &mut vec![], &mut func);
+    result?;
+    mark_tuples_known(&f64_shape, &mut literal, &mut vec![]);
+    Ok(literal)
+  }
+}
+
+// Returns 'shape' with F64 arrays, keeping tuples and tokens.
+fn to_f64_shape(shape: &Shape) -> Shape {
+  if shape.is_tuple() {
+    return ShapeUtil::make_tuple_shape(
+      shape.tuple_shapes_vec().iter().map(to_f64_shape).collect());
+  }
+  if shape.is_array() {
+    return ShapeUtil::change_element_type(shape, &PrimitiveType::F64);
+  }
+  shape.clone()
+}
+
+// Only arrays have values that can be unknown.
+fn mark_tuples_known<T>(shape: &Shape, literal: &mut Literal<T>, index: &mut Vec<i64>)
+  where T: Clone + Default + PartialEq + 'static
+{
+  if shape.is_array() {
+    return;
+  }
+  literal.mutable_piece(index).set_array_value_state(ArrayValueState::Known);
+  for i in 0..shape.tuple_shapes_size() {
+    index.push(i as i64);
+    mark_tuples_known(shape.tuple_shapes(i), literal, index);
+    index.pop();
+  }
+}
+
+impl PartialEq for DynLiteral {
+  // Literals are equal if they have compatible shapes and the same known
+  // elements. Layouts are ignored.
+  fn eq(&self, other: &Self) -> bool {
+    if !ShapeUtil::compatible(&self.shape, &other.shape) {
+      return false;
+    }
+    let mut equal = true;
+    let mut func = |subshape: &Shape, index: &Vec<i64>| {
+      if !equal {
+        return;
+      }
+      let (known, other_known) = (self.is_known(index), other.is_known(index));
+      if !known || !other_known {
+        equal = known == other_known;
+        return;
+      }
+      let mut multi_index = vec![0; subshape.rank()];
+      loop {
+        if self.element(&multi_index, index).1 != other.element(&multi_index, index).1 {
+          equal = false;
+          return;
+        }
+        if !next_index(subshape, &mut multi_index) {
+          return;
+        }
+      }
+    };
+    for_each_array(&self.shape, &mut vec![], &mut func);
+    equal
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_mixed_tuple() {
+    let f32s = DynLiteral::create_r1(&[1.0_f32, 2.5, -3.0, 4.0]);
+    let s32 = DynLiteral::create_r0(7_i32);
+    let preds = DynLiteral::create_r1(&[true, false]);
+    let tuple = DynLiteral::make_tuple(vec![f32s.clone(), s32, preds]);
+
+    assert_eq!(tuple.shape().tuple_shapes_size(), 3);
+    assert_eq!(tuple.get::<f32>(&vec![1], &vec![0]), 2.5);
+    assert_eq!(tuple.get::<i32>(&vec![], &vec![1]), 7);
+    assert_eq!(tuple.data::<bool>(&vec![2]), vec![true, false]);
+    assert_eq!(tuple.size_bytes(&vec![0]), 16);
+    assert_eq!(tuple.get_as_double(&vec![0], &vec![2]).unwrap(), 1.0);
+    assert_eq!(tuple.sub_literal(&vec![0]), f32s);
+    assert_eq!(tuple.decompose_tuple().len(), 3);
+    assert!(tuple.is_known(&vec![]));
+  }
+
+  #[test]
+  fn test_convert() {
+    let literal = DynLiteral::create_r1(&[1.5_f32, -2.5, 300.0, f32::NAN]);
+    let s8 = literal.convert(&PrimitiveType::S8).unwrap();
+    assert_eq!(s8.data::<i8>(&vec![]), vec![1, -2, 127, 0]);
+
+    let half = DynLiteral::create_r1(&[1.0_f64 / 3.0]).convert(&PrimitiveType::F16).unwrap();
+    assert_eq!(half.untyped_data(&vec![]), &vec![0x55, 0x35]);
+    assert_eq!(half.get_as_double(&vec![0], &vec![]).unwrap(), 0.333251953125);
+
+    let wrapped = DynLiteral::create_r0(300_i32).convert(&PrimitiveType::U8).unwrap();
+    assert_eq!(wrapped.get::<u8>(&vec![], &vec![]), 44);
+
+    let complex = DynLiteral::create_r0(2_i64).convert(&PrimitiveType::C64).unwrap();
+    assert_eq!(complex.get::<Complex32>(&vec![], &vec![]), Complex32::new(2.0, 0.0));
+    assert!(complex.convert(&PrimitiveType::F32).is_err());
+  }
+
+  #[test]
+  fn test_literal_f64_round_trip() {
+    let shape = ShapeUtil::make_tuple_shape(vec![
+      ShapeUtil::make_shape(&PrimitiveType::S4, vec![2, 2]),
+      ShapeUtil::make_shape(&PrimitiveType::BF16, vec![])]);
+    let mut literal = Literal::new_from_shape(&to_f64_shape(&shape));
+    literal.set_data(&vec![0], vec![-8.0, 7.0, -1.0, 0.0]);
+    literal.mutable_piece(&vec![1]).set_array_value_state(ArrayValueState::Unknown);
+
+    let dyn_literal = DynLiteral::from_literal_f64(&shape, &literal);
+    assert_eq!(dyn_literal.get_as_double(&vec![1, 0], &vec![0]).unwrap(), -1.0);
+    assert!(dyn_literal.is_known(&vec![0]));
+    assert!(!dyn_literal.is_known(&vec![]));
+
+    let result = dyn_literal.to_literal_f64().unwrap();
+    assert_eq!(result.data(&vec![0]), &vec![-8.0, 7.0, -1.0, 0.0]);
+    assert!(!result.is_known(&vec![1]));
+  }
+}
@@ -3,6 +3,7 @@
 use crate::blitz_data::PrimitiveType;
 
 // Structure describing permissible absolute and relative error bounds.
+#[derive(Debug, Clone)]
 pub struct ErrorSpec {
   // Absolute error bound.
   abs: f64,
@@ -47,9 +48,47 @@ impl ErrorSpec {
       low_precision_fp_error_spec: LowPrecisionFPErrorSpec::new(),
     }
   }
+
+  pub fn abs(&self) -> f64 {
+    self.abs
+  }
+
+  pub fn rel(&self) -> f64 {
+    self.rel
+  }
+
+  pub fn relaxed_nans(&self) -> bool {
+    self.relaxed_nans
+  }
+
+  pub fn all_nans_are_equivalent(&self) -> bool {
+    self.all_nans_are_equivalent
+  }
+
+  pub fn set_all_nans_are_equivalent(&mut self, value: bool) {
+    self.all_nans_are_equivalent = value;
+  }
+
+  pub fn fewer_infs_ok(&self) -> bool {
+    self.fewer_infs_ok
+  }
+
+  pub fn set_fewer_infs_ok(&mut self, value: bool) {
+    self.fewer_infs_ok = value;
+  }
+
+  pub fn low_precision_fp_error_spec(&self) -> &LowPrecisionFPErrorSpec {
+    &self.low_precision_fp_error_spec
+  }
+
+  pub fn set_low_precision_fp_error_spec(&mut self, t: PrimitiveType, within_n_values: i64) {
+    self.low_precision_fp_error_spec.type_ = t;
+    self.low_precision_fp_error_spec.within_n_values = within_n_values;
+  }
 }
 
-struct  LowPrecisionFPErrorSpec {
+#[derive(Debug, Clone)]
+pub struct LowPrecisionFPErrorSpec {
   // Type of low precision floating point to use for error bound calculations.
   // We can't infer this type from the result because the lower precision
   // could have been used for intermediate calculations.
@@ -66,4 +105,12 @@ impl LowPrecisionFPErrorSpec {
   pub fn new() -> Self {
     LowPrecisionFPErrorSpec { type_: PrimitiveType::Invalid, within_n_values: -1 }
   }
-}
\ No newline at end of file
+
+  pub fn primitive_type(&self) -> &PrimitiveType {
+    &self.type_
+  }
+
+  pub fn within_n_values(&self) -> i64 {
+    self.within_n_values
+  }
+}
@@ -4,7 +4,8 @@ use std::cmp::max;
 
 use crate::{
   blitz_data::{DimLevelType, PrimitiveType},
-  util::DimensionVector, shape::Shape, printer::{Printer, StringPrinter}, primitive_util, layout_util::LayoutUtil
+  util::DimensionVector, shape::Shape, printer::{Printer, StringPrinter}, primitive_util, layout_util::LayoutUtil,
+  shape_util::ShapeUtil
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -266,11 +267,11 @@ impl Layout {
       if primitive_util::is_integral_type(&self.pointer_primitive_type) {
         printer.append(&"*(".to_string());
         let primitive_type_name =
-          primitive_util::lowercase_primitive_type_name(&self.index_primitive_type);
+          primitive_util::lowercase_primitive_type_name(&self.pointer_primitive_type);
         printer.append(&primitive_type_name);
         printer.append(&")".to_string());
       } else {
-        printer.append(&"*(invalid".to_string());
+        printer.append(&"*(invalid)".to_string());
       }
     }
     if self.element_size_in_bits != 0 {
@@ -287,7 +288,14 @@ impl Layout {
     }
     if self.has_physical_shape() {
       print_colon(printer);
-      printer.append(&"P{".to_string());
+      printer.append(&"P(".to_string());
+      ShapeUtil::print_human_string_with_layout(
+        printer, self.physical_shape.as_ref().unwrap());
+      printer.append(&")".to_string());
+    }
+    if self.dynamic_shape_metadata_prefix_bytes != 0 {
+      print_colon(printer);
+      printer.append(&"M(".to_string());
       printer.append(&self.dynamic_shape_metadata_prefix_bytes.to_string());
       printer.append(&")".to_string());
     }
@@ -362,6 +370,7 @@ impl Layout {
       self.dim_attributes.push(DimInfo::new());
     };
     self.dim_attributes[self.n_dim_ordered as usize].dim_orderd = ordered;
+    self.n_dim_ordered += 1;
   }
 
   pub fn minor_to_major_size(&self) -> usize {
@@ -679,6 +688,12 @@ mod tests {
     let mut layout2 = layout.clone();
     layout2.set_memory_space(3);
     assert_eq!(layout2.to_string(), "{3,2,1,0:T(42,123)(4,5)S(3)}");
+
+    let mut layout3 = layout.clone();
+    layout3.set_index_primitive_type(PrimitiveType::S32);
+    layout3.set_pointer_primitive_type(PrimitiveType::S64);
+    layout3.set_dynamic_shape_metadata_prefix_bytes(8);
+    assert_eq!(layout3.to_string(), "{3,2,1,0:T(42,123)(4,5)#(s32)*(s64)M(8)}");
   }
 
   #[test]
@@ -7,6 +7,7 @@ pub mod blitz_data;
 pub mod comparison_util;
 pub mod debug_options_flags;
 pub mod debug_options_parsers;
+pub mod dyn_literal;
 pub mod error_spec;
 pub mod executable_run_options;
 pub mod execution_options_util;
@@ -14,6 +15,7 @@ pub mod index_util;
 pub mod layout_util;
 pub mod layout;
 pub mod literal_comparison;
+pub mod literal_serialization;
 pub mod literal_util;
 pub mod literal;
 pub mod metric_table_report;
@@ -27,4 +29,5 @@ pub mod shape_layout;
 pub mod shape_tree;
 pub mod shape_util;
 pub mod shape;
+pub mod text_literal_reader;
 pub mod util;
\ No newline at end of file
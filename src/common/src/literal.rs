@@ -5,7 +5,8 @@ use std::{any::Any, fmt::Debug, mem::size_of, vec};
 use num::{complex::Complex64, Complex};
 
 use crate::{
-  array3d::Array3D, blitz_data::PrimitiveType, index_util::IndexUtil, layout::Layout,
+  array3d::Array3D, blitz_data::PrimitiveType, dyn_literal::{DynLiteral, NativeType},
+  index_util::IndexUtil, layout::Layout,
   layout_util::LayoutUtil, permutation_util::{inverse_permutation, is_permutation},
   primitive_util::{self, array_type_switch, complex_type_switch, floating_point_type_switch,
     integral_type_switch, is_array_type, is_complex_type, is_floating_point_type, is_integral_type,
@@ -91,34 +92,40 @@ impl<T> LiteralBase<T> where T: Clone + Default + PartialEq + 'static {
   }
 
   // Computes the size in bytes of the output of the Serialize method.
-  pub fn serialized_size(&self) -> Result<i64, String> {
-    unimplemented!()
+  pub fn serialized_size(&self) -> Result<i64, String> where T: NativeType {
+    Ok(DynLiteral::from_literal_base(self)?.serialized_size())
   }
 
-  // Serialize the Literal into the given output iterator, whose value_type must
-  // be char.  It's up to the caller to ensure that output can store
-  // SerializedSize() bytes of data.  This can be ensured by using
-  // std::back_inserter, or by manually resizing the target container.
+  // Serialize the Literal by appending it to 'output'.
   // This serializer is useful for bypassing the 2GB protobuf serialization
   // limit with very large literals, and it should be faster than protobuf
   // serialization when performance is a concern.
   // The serialization format should not be relied on for forward/backward
   // compatibility.  If compatibility is required, you should use protobuf
   // serialization instead.
-  pub fn serialize(&self) {
-    unimplemented!()
+  pub fn serialize(&self, output: &mut Vec<u8>) -> Result<(), String> where T: NativeType {
+    DynLiteral::from_literal_base(self)?.serialize(output);
+    Ok(())
   }
 
   // Serialize the Literal into the given string.  This method has the same
   // caveats as the Serialize() method above.
-  pub fn serialize_to_string(&self) {
-    unimplemented!()
+  pub fn serialize_to_string(&self, output: &mut Vec<u8>) -> Result<(), String>
+    where T: NativeType
+  {
+    DynLiteral::from_literal_base(self)?.serialize_to_string(output);
+    Ok(())
   }
 
   // Serialize the Literal into a string and return it.  This method has the
   // same caveats as the Serialize() method above.
-  pub fn serialize_as_string(&self) {
-    unimplemented!()
+  pub fn serialize_as_string(&self) -> Result<Vec<u8>, String> where T: NativeType {
+    Ok(DynLiteral::from_literal_base(self)?.serialize_as_string())
+  }
+
+  // Returns the LiteralProto encoding of this literal.
+  pub fn to_proto(&self) -> Result<Vec<u8>, String> where T: NativeType {
+    DynLiteral::from_literal_base(self)?.to_proto()
   }
 
   // Returns this literal's data as a string. This literal must be a rank-1 u8 array.
@@ -1129,15 +1136,10 @@ impl<T> LiteralBase<T> where T: Clone + Default + PartialEq + 'static {
     literal.mutable_root_piece().for_each_mutable_subpiece(
       &mut |_index: &Vec<i64>, piece: &mut Piece<T>| -> Result<(), String> {
         if piece.subshape().is_array() {
-          let len = piece.size_bytes_dense() as usize;
-          let untyped_data = piece.mutable_untyped_data();
-          for i in 0..len {
-            untyped_data[i] = T::default();
-          }
-          return Ok(());
-        } else {
-          return Err("piece.subshape is not array type.".to_string());
+          let len = ShapeUtil::elements_in(piece.subshape()) as usize;
+          piece.set_data(vec![T::default(); len]);
         }
+        Ok(())
       }
     );
     literal
@@ -1441,6 +1443,45 @@ impl<T> Literal<T> where T: Clone + Default + PartialEq + 'static {
     LiteralBase::create_from_shape_with_unknown_leaf_arrays(shape)
   }
 
+  // Creates a literal from the output of serialize. The element type of the
+  // serialized literal must be T.
+  pub fn deserialize(data: &[u8]) -> Result<Literal<T>, String> where T: NativeType {
+    DynLiteral::deserialize(data)?.to_literal()
+  }
+
+  pub fn deserialize_from_string(data: &[u8]) -> Result<Literal<T>, String>
+    where T: NativeType
+  {
+    Literal::deserialize(data)
+  }
+
+  // Creates a literal from its LiteralProto encoding.
+  pub fn create_from_proto(proto: &[u8]) -> Result<Literal<T>, String> where T: NativeType {
+    DynLiteral::create_from_proto(proto)?.to_literal()
+  }
+
+  pub fn serialized_size(&self) -> Result<i64, String> where T: NativeType {
+    self.base.serialized_size()
+  }
+
+  pub fn serialize(&self, output: &mut Vec<u8>) -> Result<(), String> where T: NativeType {
+    self.base.serialize(output)
+  }
+
+  pub fn serialize_to_string(&self, output: &mut Vec<u8>) -> Result<(), String>
+    where T: NativeType
+  {
+    self.base.serialize_to_string(output)
+  }
+
+  pub fn serialize_as_string(&self) -> Result<Vec<u8>, String> where T: NativeType {
+    self.base.serialize_as_string()
+  }
+
+  pub fn to_proto(&self) -> Result<Vec<u8>, String> where T: NativeType {
+    self.base.to_proto()
+  }
+
   pub fn shape(&self) -> &Shape {
     self.base.shape()
   }
@@ -1923,7 +1964,7 @@ pub enum ArrayValueState {
   Undetermined,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Piece<T> where T: Clone + Default {
   subshape: Shape,
   children: Option<Vec<Piece<T>>>,
@@ -1933,6 +1974,47 @@ pub struct Piece<T> where T: Clone + Default {
   array_value_state: ArrayValueState,
 }
 
+// Arrays with dynamic dimensions are equal if they have the same dynamic
+// sizes and the same elements within them.
+impl<T> PartialEq for Piece<T> where T: Clone + Default + PartialEq {
+  fn eq(&self, other: &Self) -> bool {
+    if self.subshape != other.subshape || self.children != other.children ||
+      self.array_value_state != other.array_value_state
+    {
+      return false;
+    }
+    if !self.subshape.is_array() || self.subshape.is_static() {
+      return self.data == other.data && self.buffer == other.buffer;
+    }
+    let rank = self.subshape.rank();
+    let sizes: Vec<i64> = (0..rank).map(|i| self.get_dynamic_size(i)).collect();
+    if (0..rank).any(|i| other.get_dynamic_size(i) != sizes[i]) {
+      return false;
+    }
+    if sizes.iter().any(|size| *size == 0) {
+      return true;
+    }
+    let mut index = vec![0; rank];
+    loop {
+      if self.get(&index) != other.get(&index) {
+        return false;
+      }
+      let mut dim = rank;
+      loop {
+        if dim == 0 {
+          return true;
+        }
+        dim -= 1;
+        index[dim] += 1;
+        if index[dim] < sizes[dim] {
+          break;
+        }
+        index[dim] = 0;
+      }
+    }
+  }
+}
+
 impl<T> Piece<T> where T: Clone + Default {
   pub fn new() -> Self {
     let mut instance = Piece {
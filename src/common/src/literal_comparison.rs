@@ -3,16 +3,627 @@
 // Library for comparing literals without taking a dependency on testing
 // libraries.
 
-use crate::shape::Shape;
+use num::complex::Complex64;
+
+use crate::{
+  blitz_data::PrimitiveType,
+  dyn_literal::{element_size_in_bytes, for_each_array, linear_index, next_index, DynLiteral},
+  error_spec::ErrorSpec,
+  primitive_util::{
+    bit_width, float_to_bits, is_complex_type, is_floating_point_type,
+    lowercase_primitive_type_name, max_finite_value, round_to_type
+  },
+  shape::Shape,
+  shape_util::ShapeUtil
+};
+
+// The number of mismatches listed in the error message of near().
+const MAX_MISMATCHES_TO_REPORT: usize = 5;
+
+// Mismatches are bucketed by how far their error is over the error bound,
+// relative to the bound.
+const ERROR_BUCKET_BOUNDS: [f64; 5] = [0.0001, 0.001, 0.01, 0.1, 1.0];
+
+// Elements are bucketed by the absolute value of the actual value.
+const ABS_VALUE_BUCKET_BOUNDS: [f64; 7] = [0.0, 0.0001, 0.001, 0.01, 0.1, 1.0, f64::INFINITY];
 
 // Returns ok if the given shapes have the same rank, dimension sizes, and
 // primitive types.
-pub fn equal_shapes(_expected: &Shape, _actual: &Shape) {
-  unimplemented!()
+pub fn equal_shapes(expected: &Shape, actual: &Shape) -> Result<(), String> {
+  if expected.is_tuple() != actual.is_tuple() {
+    return Err(format!("tupleness-mismatch! want: {} got {}",
+      ShapeUtil::human_string(expected), ShapeUtil::human_string(actual)));
+  }
+  if expected.is_tuple() {
+    if expected.tuple_shapes_size() != actual.tuple_shapes_size() {
+      return Err(format!("want tuple size {} got tuple size {}",
+        expected.tuple_shapes_size(), actual.tuple_shapes_size()));
+    }
+    for i in 0..expected.tuple_shapes_size() {
+      if let Err(msg) = equal_shapes(expected.tuple_shapes(i), actual.tuple_shapes(i)) {
+        return Err(format!("{}\nmismatch in tuple index {}", msg, i));
+      }
+    }
+    return Ok(());
+  }
+  if expected.element_type() != actual.element_type() {
+    return Err(format!("mismatch in primitive type {} vs {}",
+      lowercase_primitive_type_name(&expected.element_type()),
+      lowercase_primitive_type_name(&actual.element_type())));
+  }
+  if !expected.is_array() {
+    return Ok(());
+  }
+  if expected.rank() != actual.rank() {
+    return Err(format!("want rank of {} got rank of {}",
+      ShapeUtil::human_string(expected), ShapeUtil::human_string(actual)));
+  }
+  for i in 0..expected.rank() {
+    if expected.dimensions(i) != actual.dimensions(i) {
+      return Err(format!("mismatch in dimension #{} expected: {} actual: {}",
+        i, ShapeUtil::human_string(expected), ShapeUtil::human_string(actual)));
+    }
+  }
+  Ok(())
 }
 
 // Returns ok if the given literals share identical dynamic shapes and
 // dimension sizes.
-pub fn equal_dynamicshapes_and_dimensions() {
-  unimplemented!()
-}
\ No newline at end of file
+pub fn equal_dynamicshapes_and_dimensions(
+  expected: &DynLiteral, actual: &DynLiteral) -> Result<(), String>
+{
+  equal_shapes(expected.shape(), actual.shape())?;
+  let mut status = Ok(());
+  let mut func = |expected_shape: &Shape, index: &Vec<i64>| {
+    if status.is_err() {
+      return;
+    }
+    let actual_shape = ShapeUtil::get_subshape(actual.shape(), index);
+    for i in 0..expected_shape.rank() {
+      if expected_shape.is_dynamic_dimension(i as i64) !=
+        actual_shape.is_dynamic_dimension(i as i64)
+      {
+        status = Err(format!(
+          "mismatch at dimension {}. the expected shape {} is {} while the actual shape {} is {}.",
+          i, ShapeUtil::human_string(expected_shape),
+          dynamic_string(expected_shape, i), ShapeUtil::human_string(&actual_shape),
+          dynamic_string(&actual_shape, i)));
+        return;
+      }
+      let expected_size = expected.get_dynamic_size(i, index);
+      let actual_size = actual.get_dynamic_size(i, index);
+      if expected_size != actual_size {
+        status = Err(format!("mismatch in dimension size #{} expected: {} actual: {}",
+          i, expected_size, actual_size));
+        return;
+      }
+    }
+  };
+  for_each_array(expected.shape(), &mut vec![], &mut func);
+  status
+}
+
+// Returns ok if the two literals have the same shape and all of their
+// elements are bitwise equal. Dynamic dimensions must have the same sizes,
+// and only the elements within them are compared.
+pub fn equal(expected: &DynLiteral, actual: &DynLiteral) -> Result<(), String> {
+  equal_dynamicshapes_and_dimensions(expected, actual)?;
+  let expected = expected.to_static();
+  let actual = actual.to_static();
+  compare_arrays(&expected, &actual, expected.shape(), &mut vec![], &mut |index| {
+    equal_array(&expected, &actual, index)
+  })
+}
+
+// Returns ok if the two literals have the same shape and their floating point
+// and complex elements are within the error bounds of 'error'. The other
+// element types must be equal. Complex elements must be within the bounds for
+// both their real and imaginary parts.
+//
+// If 'detailed_message' is true, the error message breaks the mismatches down
+// by the magnitude of the actual values and of the errors. By default, the
+// breakdown is only included for arrays with many elements.
+pub fn near(
+  expected: &DynLiteral,
+  actual: &DynLiteral,
+  error: &ErrorSpec,
+  detailed_message: Option<bool>) -> Result<(), String>
+{
+  equal_dynamicshapes_and_dimensions(expected, actual)?;
+  let expected = expected.to_static();
+  let actual = actual.to_static();
+  compare_arrays(&expected, &actual, expected.shape(), &mut vec![], &mut |index| {
+    let t = ShapeUtil::get_subshape(expected.shape(), index).element_type();
+    if !is_floating_point_type(&t) && !is_complex_type(&t) {
+      return equal_array(&expected, &actual, index);
+    }
+    let mut comparator = NearComparator::new(&expected, &actual, index, error);
+    comparator.run();
+    if comparator.num_mismatches == 0 {
+      return Ok(());
+    }
+    let detailed = detailed_message.unwrap_or(comparator.element_count >= 64);
+    Err(comparator.error_message(detailed))
+  })
+}
+
+fn dynamic_string(shape: &Shape, dimension: usize) -> &'static str {
+  if shape.is_dynamic_dimension(dimension as i64) { "dynamic" } else { "static" }
+}
+
+// Calls 'compare' on each array of the literals, which have the same shape,
+// and stops at the first failure. Arrays which are unknown in both literals
+// are skipped.
+fn compare_arrays<F>(
+  expected: &DynLiteral,
+  actual: &DynLiteral,
+  shape: &Shape,
+  index: &mut Vec<i64>,
+  compare: &mut F) -> Result<(), String>
+  where F: FnMut(&Vec<i64>) -> Result<(), String>
+{
+  if shape.is_tuple() {
+    for i in 0..shape.tuple_shapes_size() {
+      index.push(i as i64);
+      let result = compare_arrays(expected, actual, shape.tuple_shapes(i), index, compare);
+      index.pop();
+      result?;
+    }
+    return Ok(());
+  }
+  if !shape.is_array() {
+    return Ok(());
+  }
+  let result = match (expected.is_known(index), actual.is_known(index)) {
+    (true, true) => compare(index),
+    (false, false) => Ok(()),
+    (true, false) => Err("expected a known array, but the actual array is unknown".to_string()),
+    (false, true) => Err("expected an unknown array, but the actual array is known".to_string())
+  };
+  if !index.is_empty() {
+    return result.map_err(|msg| format!("Array at shape index {}, {}", index_string(index), msg));
+  }
+  result
+}
+
+// Calls 'func' with each multi index of 'shape', in row-major order.
+fn for_each_index<F>(shape: &Shape, func: &mut F) where F: FnMut(&Vec<i64>) {
+  if ShapeUtil::elements_in(shape) == 0 {
+    return;
+  }
+  let mut multi_index = vec![0; shape.rank()];
+  loop {
+    func(&multi_index);
+    if !next_index(shape, &mut multi_index) {
+      break;
+    }
+  }
+}
+
+// Returns the bytes of the element at 'multi_index' of the array at 'index'.
+fn element_bytes<'a>(literal: &'a DynLiteral, multi_index: &Vec<i64>, index: &Vec<i64>) -> &'a [u8] {
+  let shape = ShapeUtil::get_subshape(literal.shape(), index);
+  let size = element_size_in_bytes(&shape.element_type());
+  let offset = linear_index(&shape, multi_index) * size;
+  &literal.untyped_data(index)[offset..offset + size]
+}
+
+fn equal_array(expected: &DynLiteral, actual: &DynLiteral, index: &Vec<i64>) -> Result<(), String> {
+  let shape = ShapeUtil::get_subshape(expected.shape(), index);
+  let mut num_mismatches = 0;
+  let mut first_mismatch = None;
+  let mut func = |multi_index: &Vec<i64>| {
+    if element_bytes(expected, multi_index, index) != element_bytes(actual, multi_index, index) {
+      num_mismatches += 1;
+      if first_mismatch.is_none() {
+        first_mismatch = Some(multi_index.clone());
+      }
+    }
+  };
+  for_each_index(&shape, &mut func);
+  match first_mismatch {
+    None => Ok(()),
+    Some(multi_index) => Err(format!(
+      "Mismatch count {} in shape {} ({} elements), first mismatch at array index {}:\n  \
+      expected value: {}\n  actual value: {}",
+      num_mismatches, ShapeUtil::human_string(&shape), ShapeUtil::elements_in(&shape),
+      index_string(&multi_index), value_string(expected, &multi_index, index),
+      value_string(actual, &multi_index, index)))
+  }
+}
+
+fn index_string(index: &Vec<i64>) -> String {
+  let elements: Vec<String> = index.iter().map(|i| i.to_string()).collect();
+  format!("{{{}}}", elements.join(","))
+}
+
+fn value_string(literal: &DynLiteral, multi_index: &Vec<i64>, index: &Vec<i64>) -> String {
+  let t = ShapeUtil::get_subshape(literal.shape(), index).element_type();
+  complex_string(&t, literal.get_as_complex128(multi_index, index))
+}
+
+fn complex_string(t: &PrimitiveType, value: Complex64) -> String {
+  match t {
+    PrimitiveType::Pred => (value.re != 0.0).to_string(),
+    _ if is_complex_type(t) => format!("({}, {})", value.re, value.im),
+    _ => value.re.to_string()
+  }
+}
+
+fn percent(count: i64, total: i64) -> f64 {
+  if total == 0 { 0.0 } else { 100.0 * count as f64 / total as f64 }
+}
+
+// Returns the distance between 'a' and 'b' in the number of values of the
+// floating point type 't' between them, after rounding both to 't'. Positive
+// and negative zero are the same value.
+fn distance_in_values(a: f64, b: f64, t: &PrimitiveType) -> i128 {
+  let sign = 1_u64 << (bit_width(t) - 1);
+  let ordinal = |value: f64| -> i128 {
+    let bits = float_to_bits(value, t);
+    let magnitude = (bits & (sign - 1)) as i128;
+    if bits & sign != 0 { -magnitude } else { magnitude }
+  };
+  (ordinal(a) - ordinal(b)).abs()
+}
+
+// A mismatching element of an array compared by near().
+struct Mismatch {
+  multi_index: Vec<i64>,
+  expected: Complex64,
+  actual: Complex64,
+  abs_error: f64,
+  rel_error: f64
+}
+
+// Compares the elements of an array of floating point or complex type and
+// gathers statistics about the errors.
+struct NearComparator<'a> {
+  expected: &'a DynLiteral,
+  actual: &'a DynLiteral,
+  index: &'a Vec<i64>,
+  error: &'a ErrorSpec,
+  shape: Shape,
+  element_count: i64,
+  num_mismatches: i64,
+  num_nan_mismatches: i64,
+  num_abs_mismatches: i64,
+  num_rel_mismatches: i64,
+  max_abs_error: f64,
+  max_abs_error_index: Vec<i64>,
+  max_rel_error: f64,
+  max_rel_error_index: Vec<i64>,
+  first_mismatches: Vec<Mismatch>,
+  // The number of elements and of mismatches whose actual value is in each
+  // bucket of ABS_VALUE_BUCKET_BOUNDS.
+  abs_value_buckets: Vec<(i64, i64)>,
+  abs_error_buckets: Vec<i64>,
+  rel_error_buckets: Vec<i64>
+}
+
+impl<'a> NearComparator<'a> {
+  fn new(
+    expected: &'a DynLiteral,
+    actual: &'a DynLiteral,
+    index: &'a Vec<i64>,
+    error: &'a ErrorSpec) -> Self
+  {
+    let shape = ShapeUtil::get_subshape(expected.shape(), index);
+    NearComparator {
+      expected: expected,
+      actual: actual,
+      index: index,
+      error: error,
+      element_count: ShapeUtil::elements_in(&shape),
+      shape: shape,
+      num_mismatches: 0,
+      num_nan_mismatches: 0,
+      num_abs_mismatches: 0,
+      num_rel_mismatches: 0,
+      max_abs_error: 0.0,
+      max_abs_error_index: vec![],
+      max_rel_error: 0.0,
+      max_rel_error_index: vec![],
+      first_mismatches: vec![],
+      abs_value_buckets: vec![(0, 0); ABS_VALUE_BUCKET_BOUNDS.len() - 1],
+      abs_error_buckets: vec![0; ERROR_BUCKET_BOUNDS.len()],
+      rel_error_buckets: vec![0; ERROR_BUCKET_BOUNDS.len()]
+    }
+  }
+
+  fn run(&mut self) {
+    let shape = self.shape.clone();
+    for_each_index(&shape, &mut |multi_index| self.compare_element(multi_index));
+  }
+
+  fn compare_element(&mut self, multi_index: &Vec<i64>) {
+    let t = self.shape.element_type();
+    let expected = self.expected.get_as_complex128(multi_index, self.index);
+    let actual = self.actual.get_as_complex128(multi_index, self.index);
+    let bitwise_equal = element_bytes(self.expected, multi_index, self.index) ==
+      element_bytes(self.actual, multi_index, self.index);
+
+    let (mut abs_error, mut rel_error, mut is_mismatch, mut is_nan_mismatch) =
+      self.compare_values(expected.re, actual.re, bitwise_equal);
+    if is_complex_type(&t) {
+      let (imag_abs_error, imag_rel_error, imag_mismatch, imag_nan_mismatch) =
+        self.compare_values(expected.im, actual.im, bitwise_equal);
+      abs_error = abs_error.max(imag_abs_error);
+      rel_error = rel_error.max(imag_rel_error);
+      is_mismatch |= imag_mismatch;
+      is_nan_mismatch |= imag_nan_mismatch;
+    }
+
+    if abs_error > self.max_abs_error {
+      self.max_abs_error = abs_error;
+      self.max_abs_error_index = multi_index.clone();
+    }
+    if rel_error > self.max_rel_error {
+      self.max_rel_error = rel_error;
+      self.max_rel_error_index = multi_index.clone();
+    }
+    // Update the error of the relative bucket only if the absolute error
+    // bound is exceeded and vice versa.
+    if abs_error > self.error.abs() {
+      self.num_abs_mismatches += 1;
+      update_error_buckets(rel_error, self.error.rel(), &mut self.rel_error_buckets);
+    }
+    if rel_error > self.error.rel() {
+      self.num_rel_mismatches += 1;
+      update_error_buckets(abs_error, self.error.abs(), &mut self.abs_error_buckets);
+    }
+    let abs_value = actual.norm();
+    for i in 0..self.abs_value_buckets.len() {
+      if abs_value >= ABS_VALUE_BUCKET_BOUNDS[i] && abs_value < ABS_VALUE_BUCKET_BOUNDS[i + 1] {
+        self.abs_value_buckets[i].0 += 1;
+        if is_mismatch {
+          self.abs_value_buckets[i].1 += 1;
+        }
+      }
+    }
+    if !is_mismatch {
+      return;
+    }
+    self.num_mismatches += 1;
+    if is_nan_mismatch {
+      self.num_nan_mismatches += 1;
+    }
+    if self.first_mismatches.len() < MAX_MISMATCHES_TO_REPORT {
+      self.first_mismatches.push(Mismatch {
+        multi_index: multi_index.clone(),
+        expected: expected,
+        actual: actual,
+        abs_error: abs_error,
+        rel_error: rel_error
+      });
+    }
+  }
+
+  // Compares two real values. Returns the absolute and relative errors, and
+  // whether the values mismatch, because of NaNs or not.
+  fn compare_values(&self, expected: f64, actual: f64, bitwise_equal: bool) -> (f64, f64, bool, bool) {
+    let t = self.shape.element_type();
+    if expected == actual {
+      return (0.0, 0.0, false, false);
+    }
+    let (abs_error, rel_error) = if expected.is_nan() || actual.is_nan() {
+      let nan_mismatch = if self.error.relaxed_nans() {
+        !expected.is_nan() && actual.is_nan()
+      } else {
+        expected.is_nan() != actual.is_nan() ||
+          (!self.error.all_nans_are_equivalent() && !bitwise_equal)
+      };
+      if nan_mismatch {
+        return (f64::INFINITY, f64::INFINITY, true, true);
+      }
+      (0.0, 0.0)
+    } else if actual.is_infinite() && !expected.is_infinite() && self.error.fewer_infs_ok() {
+      // Compare as though 'actual' were the largest or lowest finite value
+      // rather than an infinity.
+      let component_type = if is_complex_type(&t) {
+        if t == PrimitiveType::C64 { PrimitiveType::F32 } else { PrimitiveType::F64 }
+      } else {
+        t.clone()
+      };
+      let actual_finite = max_finite_value(&component_type).copysign(actual);
+      let abs_error = (actual_finite - expected).abs();
+      (abs_error, relative_error(abs_error, expected))
+    } else if expected.is_infinite() || actual.is_infinite() {
+      // If either the expected or actual value is infinity but not both, then
+      // both absolute and relative error are regarded as infinity.
+      (f64::INFINITY, f64::INFINITY)
+    } else {
+      let abs_error = (actual - expected).abs();
+      (abs_error, relative_error(abs_error, expected))
+    };
+    let mut is_mismatch = abs_error > self.error.abs() && rel_error > self.error.rel();
+    let low_precision = self.error.low_precision_fp_error_spec();
+    if is_mismatch && *low_precision.primitive_type() != PrimitiveType::Invalid &&
+      low_precision.within_n_values() >= 0
+    {
+      let low_t = low_precision.primitive_type();
+      if round_to_type(expected, low_t).is_finite() && round_to_type(actual, low_t).is_finite() {
+        is_mismatch =
+          distance_in_values(expected, actual, low_t) > low_precision.within_n_values() as i128;
+      }
+    }
+    (abs_error, rel_error, is_mismatch, false)
+  }
+
+  fn error_message(&self, detailed: bool) -> String {
+    let mut out = format!(
+      "Mismatch count {} ({:.4}%) in shape {} ({} elements), abs bound {}, rel bound {}\n",
+      self.num_mismatches, percent(self.num_mismatches, self.element_count),
+      ShapeUtil::human_string(&self.shape), self.element_count,
+      self.error.abs(), self.error.rel());
+    if self.num_nan_mismatches > 0 {
+      out.push_str(&format!("nan mismatches {}\n", self.num_nan_mismatches));
+    }
+    let t = self.shape.element_type();
+    out.push_str(&format!("First {} mismatches:\n", self.first_mismatches.len()));
+    for mismatch in &self.first_mismatches {
+      out.push_str(&format!("  actual {}, expected {}, index {}, rel error {:.3e}, abs error {:.3e}\n",
+        complex_string(&t, mismatch.actual), complex_string(&t, mismatch.expected),
+        index_string(&mismatch.multi_index), mismatch.rel_error, mismatch.abs_error));
+    }
+    out.push_str(&format!("Max abs error {:.3e} at index {}, max rel error {:.3e} at index {}\n",
+      self.max_abs_error, index_string(&self.max_abs_error_index),
+      self.max_rel_error, index_string(&self.max_rel_error_index)));
+    if !detailed {
+      return out;
+    }
+
+    out.push_str("Absolute magnitude breakdown of actual values:\n");
+    for (i, (count, mismatches)) in self.abs_value_buckets.iter().enumerate() {
+      let lower = ABS_VALUE_BUCKET_BOUNDS[i];
+      let upper = ABS_VALUE_BUCKET_BOUNDS[i + 1];
+      let range = if upper.is_infinite() {
+        format!("x >= {:<6}", lower)
+      } else {
+        format!("{:<6} <= x < {:<6}", lower, upper)
+      };
+      out.push_str(&format!("  {} : {:>7} ({:>9.4}%), mismatches {}\n",
+        range, count, percent(*count, self.element_count), mismatches));
+    }
+
+    let print_buckets = |out: &mut String, buckets: &Vec<i64>, total: i64| {
+      for (i, count) in buckets.iter().enumerate() {
+        if i + 1 < buckets.len() {
+          out.push_str(&format!("  <  {:<6} : {:>7} ({:>9.4}%)\n",
+            ERROR_BUCKET_BOUNDS[i + 1], count - buckets[i + 1], percent(count - buckets[i + 1], total)));
+        } else {
+          out.push_str(&format!("  >= {:<6} : {:>7} ({:>9.4}%)\n",
+            ERROR_BUCKET_BOUNDS[i], count, percent(*count, total)));
+        }
+      }
+    };
+    out.push_str(&format!("Elements exceeding abs error bound {}: {} ({:.4}%)\n",
+      self.error.abs(), self.num_abs_mismatches,
+      percent(self.num_abs_mismatches, self.element_count)));
+    out.push_str("Relative error breakdown of elements exceeding abs error bound:\n");
+    print_buckets(&mut out, &self.rel_error_buckets, self.num_abs_mismatches);
+    out.push_str(&format!("Elements exceeding rel error bound {}: {} ({:.4}%)\n",
+      self.error.rel(), self.num_rel_mismatches,
+      percent(self.num_rel_mismatches, self.element_count)));
+    out.push_str("Absolute error breakdown of elements exceeding rel error bound:\n");
+    print_buckets(&mut out, &self.abs_error_buckets, self.num_rel_mismatches);
+    out
+  }
+}
+
+fn relative_error(abs_error: f64, expected: f64) -> f64 {
+  if expected != 0.0 { abs_error / expected.abs() } else { f64::INFINITY }
+}
+
+// Counts 'error' in each bucket whose bound, scaled by 'error_bound', it
+// reaches. The buckets are cumulative.
+fn update_error_buckets(error: f64, error_bound: f64, buckets: &mut Vec<i64>) {
+  for i in 0..buckets.len() {
+    if error >= ERROR_BUCKET_BOUNDS[i] * error_bound {
+      buckets[i] += 1;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use num::complex::Complex32;
+
+  #[test]
+  fn test_equal() {
+    let expected = DynLiteral::make_tuple(vec![
+      DynLiteral::create_r1(&[1_i32, 2, 3]),
+      DynLiteral::create_r0(f32::NAN)]);
+    assert!(equal(&expected, &expected.clone()).is_ok());
+
+    let actual = DynLiteral::make_tuple(vec![
+      DynLiteral::create_r1(&[1_i32, 5, 3]),
+      DynLiteral::create_r0(f32::NAN)]);
+    let msg = equal(&expected, &actual).unwrap_err();
+    assert!(msg.contains("Array at shape index {0}"));
+    assert!(msg.contains("first mismatch at array index {1}"));
+
+    let wrong_type = DynLiteral::create_r1(&[1_i64, 2, 3]);
+    assert!(equal(&expected.sub_literal(&vec![0]), &wrong_type).is_err());
+  }
+
+  #[test]
+  fn test_dynamic_shapes() {
+    let mut shape = ShapeUtil::make_shape(&PrimitiveType::F32, vec![4]);
+    shape.set_dynamic_dimension(0, true);
+    let mut expected = DynLiteral::new(&shape);
+    expected.set_data(&vec![], &[1.0_f32, 2.0, 3.0, 4.0]);
+    expected.set_dynamic_size(0, &vec![], 2);
+    let mut actual = DynLiteral::new(&shape);
+    actual.set_data(&vec![], &[1.0_f32, 2.0, 0.0, 0.0]);
+    actual.set_dynamic_size(0, &vec![], 2);
+    // The elements out of the dynamic size are ignored.
+    assert!(equal(&expected, &actual).is_ok());
+
+    actual.set_dynamic_size(0, &vec![], 3);
+    let msg = equal_dynamicshapes_and_dimensions(&expected, &actual).unwrap_err();
+    assert!(msg.contains("mismatch in dimension size #0"));
+  }
+
+  #[test]
+  fn test_near() {
+    let error = ErrorSpec::new(0.01, 0.01, false);
+    let expected = DynLiteral::create_r1(&[1.0_f32, 100.0, 0.0, f32::INFINITY]);
+    let actual = DynLiteral::create_r1(&[1.005_f32, 100.5, 0.001, f32::INFINITY]);
+    assert!(near(&expected, &actual, &error, None).is_ok());
+
+    let actual = DynLiteral::create_r1(&[1.5_f32, 100.5, 0.001, f32::NEG_INFINITY]);
+    let msg = near(&expected, &actual, &error, Some(true)).unwrap_err();
+    assert!(msg.contains("Mismatch count 2 (50.0000%)"));
+    assert!(msg.contains("index {0}"));
+    assert!(msg.contains("index {3}"));
+    assert!(msg.contains("Absolute magnitude breakdown of actual values"));
+
+    let mut fewer_infs = error.clone();
+    fewer_infs.set_fewer_infs_ok(true);
+    let expected = DynLiteral::create_r0(f32::MAX);
+    let actual = DynLiteral::create_r0(f32::INFINITY);
+    assert!(near(&expected, &actual, &error, None).is_err());
+    assert!(near(&expected, &actual, &fewer_infs, None).is_ok());
+  }
+
+  #[test]
+  fn test_near_nans() {
+    let expected = DynLiteral::create_r1(&[f32::NAN, 1.0]);
+    let actual = DynLiteral::create_r1(&[f32::NAN, f32::NAN]);
+    let strict = ErrorSpec::new(0.1, 0.1, false);
+    let msg = near(&expected, &actual, &strict, None).unwrap_err();
+    assert!(msg.contains("nan mismatches 1"));
+    assert!(near(&expected, &expected, &strict, None).is_ok());
+
+    // Relaxed NaNs accept any result for an expected NaN, but not a NaN for
+    // a number.
+    let relaxed = ErrorSpec::new(0.1, 0.1, true);
+    let actual = DynLiteral::create_r1(&[5.0_f32, 1.0]);
+    assert!(near(&expected, &actual, &relaxed, None).is_ok());
+    let actual = DynLiteral::create_r1(&[5.0_f32, f32::NAN]);
+    assert!(near(&expected, &actual, &relaxed, None).is_err());
+  }
+
+  #[test]
+  fn test_near_complex_and_low_precision() {
+    let error = ErrorSpec::new(0.01, 0.01, false);
+    let expected = DynLiteral::create_r1(&[Complex32::new(1.0, 2.0)]);
+    let actual = DynLiteral::create_r1(&[Complex32::new(1.0, 2.5)]);
+    assert!(near(&expected, &actual, &error, None).is_err());
+
+    // 1.0 and 1.125 are neighbours in F8E4M3FN.
+    let expected = DynLiteral::create_r1(&[1.0_f32]).convert(&PrimitiveType::BF16).unwrap();
+    let actual = DynLiteral::create_r1(&[1.125_f32]).convert(&PrimitiveType::BF16).unwrap();
+    assert!(near(&expected, &actual, &error, None).is_err());
+    let mut low_precision = error.clone();
+    low_precision.set_low_precision_fp_error_spec(PrimitiveType::F8E4M3FN, 1);
+    assert!(near(&expected, &actual, &low_precision, None).is_ok());
+
+    // Integers must be equal.
+    let expected = DynLiteral::create_r1(&[1_i32]);
+    let actual = DynLiteral::create_r1(&[2_i32]);
+    assert!(near(&expected, &actual, &ErrorSpec::new(10.0, 10.0, false), None).is_err());
+  }
+}
@@ -0,0 +1,861 @@
+#![allow(dead_code)]
+
+// Serialization of literals, in two formats:
+//
+// * The protobuf wire format of LiteralProto, ShapeProto and LayoutProto of
+//   xla_data.proto, to exchange literals with other XLA tools. Arrays are
+//   stored in the order of their layout. A dynamic array is stored with its
+//   dynamic sizes as its dimensions, which keep their dynamic flags.
+//
+// * A compact binary format, which is faster to write and read but is not
+//   meant to be compatible across versions:
+//
+//     magic "BLTZ", version (u32),
+//     size of the ShapeProto (u64), ShapeProto,
+//     for each array of the shape in pre-order:
+//       0 if the array is known or 1 if it is unknown (u8),
+//       if known, the dynamic sizes of the dimensions (i64 each) if the
+//       array has dynamic dimensions, followed by the elements in the order
+//       of the layout. 4-bit elements are packed two per byte, the first in
+//       the low bits.
+//
+// All the integers of the binary format are little endian.
+
+use crate::{
+  blitz_data::PrimitiveType,
+  dyn_literal::{element_size_in_bytes, for_each_array, DynLiteral},
+  layout::{Layout, Tile},
+  primitive_util::{is_4bit_type, lowercase_primitive_type_name},
+  shape::{ProgramShape, Shape},
+  shape_util::ShapeUtil
+};
+
+const MAGIC: &[u8; 4] = b"BLTZ";
+const VERSION: u32 = 1;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LENGTH_DELIMITED: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+// Field numbers of ShapeProto.
+const SHAPE_ELEMENT_TYPE: u32 = 2;
+const SHAPE_DIMENSIONS: u32 = 3;
+const SHAPE_TUPLE_SHAPES: u32 = 4;
+const SHAPE_LAYOUT: u32 = 5;
+const SHAPE_IS_DYNAMIC_DIMENSION: u32 = 6;
+
+// Field numbers of ProgramShapeProto.
+const PROGRAM_SHAPE_PARAMETERS: u32 = 1;
+const PROGRAM_SHAPE_RESULT: u32 = 2;
+const PROGRAM_SHAPE_PARAMETER_NAMES: u32 = 3;
+
+// Field numbers of LayoutProto and TileProto.
+const LAYOUT_MINOR_TO_MAJOR: u32 = 1;
+const LAYOUT_TILES: u32 = 6;
+const LAYOUT_ELEMENT_SIZE_IN_BITS: u32 = 7;
+const LAYOUT_MEMORY_SPACE: u32 = 8;
+const LAYOUT_TAIL_PADDING_ALIGNMENT_IN_ELEMENTS: u32 = 16;
+const TILE_DIMENSIONS: u32 = 1;
+
+// Field numbers of LiteralProto.
+const LITERAL_SHAPE: u32 = 1;
+const LITERAL_PREDS: u32 = 2;
+const LITERAL_U8S: u32 = 3;
+const LITERAL_S32S: u32 = 4;
+const LITERAL_S64S: u32 = 5;
+const LITERAL_U32S: u32 = 6;
+const LITERAL_U64S: u32 = 7;
+const LITERAL_F32S: u32 = 8;
+const LITERAL_F64S: u32 = 9;
+const LITERAL_TUPLE_LITERALS: u32 = 10;
+const LITERAL_F16S: u32 = 11;
+const LITERAL_C64S: u32 = 12;
+const LITERAL_BF16S: u32 = 13;
+const LITERAL_S8S: u32 = 15;
+const LITERAL_U16S: u32 = 16;
+const LITERAL_S16S: u32 = 17;
+const LITERAL_C128S: u32 = 18;
+const LITERAL_F8E5M2S: u32 = 19;
+const LITERAL_F8E4M3FNS: u32 = 20;
+const LITERAL_S4S: u32 = 21;
+const LITERAL_U4S: u32 = 22;
+const LITERAL_F8E4M3B11FNUZS: u32 = 23;
+const LITERAL_F8E5M2FNUZS: u32 = 24;
+const LITERAL_F8E4M3FNUZS: u32 = 25;
+
+// Returns the value of 't' in the PrimitiveType enum of xla_data.proto.
+pub fn primitive_type_to_proto(t: &PrimitiveType) -> u64 {
+  match t {
+    PrimitiveType::Invalid => 0,
+    PrimitiveType::Pred => 1,
+    PrimitiveType::S8 => 2,
+    PrimitiveType::S16 => 3,
+    PrimitiveType::S32 => 4,
+    PrimitiveType::S64 => 5,
+    PrimitiveType::U8 => 6,
+    PrimitiveType::U16 => 7,
+    PrimitiveType::U32 => 8,
+    PrimitiveType::U64 => 9,
+    PrimitiveType::F16 => 10,
+    PrimitiveType::F32 => 11,
+    PrimitiveType::F64 => 12,
+    PrimitiveType::Tuple => 13,
+    PrimitiveType::OpaqueType => 14,
+    PrimitiveType::C64 => 15,
+    PrimitiveType::BF16 => 16,
+    PrimitiveType::Token => 17,
+    PrimitiveType::C128 => 18,
+    PrimitiveType::F8E5M2 => 19,
+    PrimitiveType::F8E4M3FN => 20,
+    PrimitiveType::S4 => 21,
+    PrimitiveType::U4 => 22,
+    PrimitiveType::F8E4M3B11FNUZ => 23,
+    PrimitiveType::F8E5M2FNUZ => 24,
+    PrimitiveType::F8E4M3FNUZ => 25
+  }
+}
+
+pub fn primitive_type_from_proto(value: u64) -> Result<PrimitiveType, String> {
+  match value {
+    0 => Ok(PrimitiveType::Invalid),
+    1 => Ok(PrimitiveType::Pred),
+    2 => Ok(PrimitiveType::S8),
+    3 => Ok(PrimitiveType::S16),
+    4 => Ok(PrimitiveType::S32),
+    5 => Ok(PrimitiveType::S64),
+    6 => Ok(PrimitiveType::U8),
+    7 => Ok(PrimitiveType::U16),
+    8 => Ok(PrimitiveType::U32),
+    9 => Ok(PrimitiveType::U64),
+    10 => Ok(PrimitiveType::F16),
+    11 => Ok(PrimitiveType::F32),
+    12 => Ok(PrimitiveType::F64),
+    13 => Ok(PrimitiveType::Tuple),
+    14 => Ok(PrimitiveType::OpaqueType),
+    15 => Ok(PrimitiveType::C64),
+    16 => Ok(PrimitiveType::BF16),
+    17 => Ok(PrimitiveType::Token),
+    18 => Ok(PrimitiveType::C128),
+    19 => Ok(PrimitiveType::F8E5M2),
+    20 => Ok(PrimitiveType::F8E4M3FN),
+    21 => Ok(PrimitiveType::S4),
+    22 => Ok(PrimitiveType::U4),
+    23 => Ok(PrimitiveType::F8E4M3B11FNUZ),
+    24 => Ok(PrimitiveType::F8E5M2FNUZ),
+    25 => Ok(PrimitiveType::F8E4M3FNUZ),
+    _ => Err(format!("Unknown primitive type: {}.", value))
+  }
+}
+
+// Returns the LiteralProto field holding the elements of arrays of type 't'.
+fn literal_data_field(t: &PrimitiveType) -> Result<u32, String> {
+  match t {
+    PrimitiveType::Pred => Ok(LITERAL_PREDS),
+    PrimitiveType::S4 => Ok(LITERAL_S4S),
+    PrimitiveType::S8 => Ok(LITERAL_S8S),
+    PrimitiveType::S16 => Ok(LITERAL_S16S),
+    PrimitiveType::S32 => Ok(LITERAL_S32S),
+    PrimitiveType::S64 => Ok(LITERAL_S64S),
+    PrimitiveType::U4 => Ok(LITERAL_U4S),
+    PrimitiveType::U8 => Ok(LITERAL_U8S),
+    PrimitiveType::U16 => Ok(LITERAL_U16S),
+    PrimitiveType::U32 => Ok(LITERAL_U32S),
+    PrimitiveType::U64 => Ok(LITERAL_U64S),
+    PrimitiveType::F16 => Ok(LITERAL_F16S),
+    PrimitiveType::BF16 => Ok(LITERAL_BF16S),
+    PrimitiveType::F32 => Ok(LITERAL_F32S),
+    PrimitiveType::F64 => Ok(LITERAL_F64S),
+    PrimitiveType::C64 => Ok(LITERAL_C64S),
+    PrimitiveType::C128 => Ok(LITERAL_C128S),
+    PrimitiveType::F8E5M2 => Ok(LITERAL_F8E5M2S),
+    PrimitiveType::F8E4M3FN => Ok(LITERAL_F8E4M3FNS),
+    PrimitiveType::F8E4M3B11FNUZ => Ok(LITERAL_F8E4M3B11FNUZS),
+    PrimitiveType::F8E5M2FNUZ => Ok(LITERAL_F8E5M2FNUZS),
+    PrimitiveType::F8E4M3FNUZ => Ok(LITERAL_F8E4M3FNUZS),
+    _ => Err(format!("Unhandled primitive type for serialization: {}.",
+      lowercase_primitive_type_name(t)))
+  }
+}
+
+// Returns true if the elements of arrays of type 't' are varints in a
+// LiteralProto. The other types are stored as their little endian bytes.
+fn is_varint_type(t: &PrimitiveType) -> bool {
+  matches!(t, PrimitiveType::Pred | PrimitiveType::S32 | PrimitiveType::S64 |
+    PrimitiveType::U32 | PrimitiveType::U64)
+}
+
+pub fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+  while value >= 0x80 {
+    output.push((value as u8) | 0x80);
+    value >>= 7;
+  }
+  output.push(value as u8);
+}
+
+pub fn write_tag(output: &mut Vec<u8>, field: u32, wire_type: u8) {
+  write_varint(output, ((field as u64) << 3) | wire_type as u64);
+}
+
+pub fn write_varint_field(output: &mut Vec<u8>, field: u32, value: u64) {
+  write_tag(output, field, WIRE_VARINT);
+  write_varint(output, value);
+}
+
+pub fn write_bytes_field(output: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+  write_tag(output, field, WIRE_LENGTH_DELIMITED);
+  write_varint(output, bytes.len() as u64);
+  output.extend_from_slice(bytes);
+}
+
+pub fn write_packed_varints(output: &mut Vec<u8>, field: u32, values: &[u64]) {
+  if values.is_empty() {
+    return;
+  }
+  let mut packed = vec![];
+  for value in values {
+    write_varint(&mut packed, *value);
+  }
+  write_bytes_field(output, field, &packed);
+}
+
+// A field of a protobuf message.
+enum FieldValue<'a> {
+  Varint(u64),
+  Fixed64(&'a [u8]),
+  Fixed32(&'a [u8]),
+  Bytes(&'a [u8])
+}
+
+struct ProtoReader<'a> {
+  data: &'a [u8],
+  position: usize
+}
+
+impl<'a> ProtoReader<'a> {
+  fn new(data: &'a [u8]) -> Self {
+    ProtoReader { data: data, position: 0 }
+  }
+
+  fn done(&self) -> bool {
+    self.position >= self.data.len()
+  }
+
+  fn read_varint(&mut self) -> Result<u64, String> {
+    let mut value = 0_u64;
+    for shift in (0..64).step_by(7) {
+      let byte = *self.data.get(self.position).ok_or("Truncated varint.".to_string())?;
+      self.position += 1;
+      value |= ((byte & 0x7f) as u64) << shift;
+      if byte & 0x80 == 0 {
+        return Ok(value);
+      }
+    }
+    Err("Malformed varint.".to_string())
+  }
+
+  fn read_slice(&mut self, len: usize) -> Result<&'a [u8], String> {
+    if self.data.len() - self.position < len {
+      return Err("Truncated protobuf field.".to_string());
+    }
+    let slice = &self.data[self.position..self.position + len];
+    self.position += len;
+    Ok(slice)
+  }
+
+  // Reads the next field of the message.
+  fn read_field(&mut self) -> Result<(u32, FieldValue<'a>), String> {
+    let tag = self.read_varint()?;
+    let field = (tag >> 3) as u32;
+    let value = match (tag & 7) as u8 {
+      WIRE_VARINT => FieldValue::Varint(self.read_varint()?),
+      WIRE_FIXED64 => FieldValue::Fixed64(self.read_slice(8)?),
+      WIRE_LENGTH_DELIMITED => {
+        let len = self.read_varint()? as usize;
+        FieldValue::Bytes(self.read_slice(len)?)
+      },
+      WIRE_FIXED32 => FieldValue::Fixed32(self.read_slice(4)?),
+      wire_type => return Err(format!("Unsupported wire type {}.", wire_type))
+    };
+    Ok((field, value))
+  }
+}
+
+// Appends the varints of a repeated field, packed or not, to 'values'.
+fn read_varints(value: FieldValue, values: &mut Vec<u64>) -> Result<(), String> {
+  match value {
+    FieldValue::Varint(v) => values.push(v),
+    FieldValue::Bytes(bytes) => {
+      let mut reader = ProtoReader::new(bytes);
+      while !reader.done() {
+        values.push(reader.read_varint()?);
+      }
+    },
+    _ => return Err("Expected varints.".to_string())
+  }
+  Ok(())
+}
+
+// Returns the ShapeProto encoding of 'shape'.
+pub fn shape_to_proto(shape: &Shape) -> Vec<u8> {
+  let mut output = vec![];
+  write_varint_field(&mut output, SHAPE_ELEMENT_TYPE,
+    primitive_type_to_proto(&shape.element_type()));
+  if shape.is_tuple() {
+    for element in shape.tuple_shapes_vec() {
+      write_bytes_field(&mut output, SHAPE_TUPLE_SHAPES, &shape_to_proto(element));
+    }
+    return output;
+  }
+  if !shape.is_array() {
+    return output;
+  }
+  write_packed_varints(&mut output, SHAPE_DIMENSIONS,
+    &shape.dimensions_vec().iter().map(|d| *d as u64).collect::<Vec<u64>>());
+  if let Some(layout) = shape.layout() {
+    write_bytes_field(&mut output, SHAPE_LAYOUT, &layout_to_proto(layout));
+  }
+  if shape.is_dynamic() {
+    let flags = (0..shape.rank())
+      .map(|i| shape.is_dynamic_dimension(i as i64) as u64).collect::<Vec<u64>>();
+    write_packed_varints(&mut output, SHAPE_IS_DYNAMIC_DIMENSION, &flags);
+  }
+  output
+}
+
+// Returns the ProgramShapeProto encoding of 'program_shape'.
+pub fn program_shape_to_proto(program_shape: &ProgramShape) -> Vec<u8> {
+  let mut output = vec![];
+  for parameter in program_shape.parameters_vec() {
+    write_bytes_field(&mut output, PROGRAM_SHAPE_PARAMETERS, &shape_to_proto(parameter));
+  }
+  write_bytes_field(&mut output, PROGRAM_SHAPE_RESULT,
+    &shape_to_proto(program_shape.result()));
+  for i in 0..program_shape.parameter_names_size() {
+    write_bytes_field(&mut output, PROGRAM_SHAPE_PARAMETER_NAMES,
+      program_shape.parameter_names(i).as_bytes());
+  }
+  output
+}
+
+fn layout_to_proto(layout: &Layout) -> Vec<u8> {
+  let mut output = vec![];
+  write_packed_varints(&mut output, LAYOUT_MINOR_TO_MAJOR,
+    &layout.minor_to_major_vec().iter().map(|d| *d as u64).collect::<Vec<u64>>());
+  for tile in layout.tiles_vec() {
+    let mut tile_proto = vec![];
+    write_packed_varints(&mut tile_proto, TILE_DIMENSIONS,
+      &tile.dimensions().iter().map(|d| *d as u64).collect::<Vec<u64>>());
+    write_bytes_field(&mut output, LAYOUT_TILES, &tile_proto);
+  }
+  if layout.element_size_in_bits() != 0 {
+    write_varint_field(&mut output, LAYOUT_ELEMENT_SIZE_IN_BITS,
+      layout.element_size_in_bits() as u64);
+  }
+  if layout.memory_space() != 0 {
+    write_varint_field(&mut output, LAYOUT_MEMORY_SPACE, layout.memory_space() as u64);
+  }
+  write_varint_field(&mut output, LAYOUT_TAIL_PADDING_ALIGNMENT_IN_ELEMENTS,
+    layout.tail_padding_alignment_in_elements() as u64);
+  output
+}
+
+// Creates a shape from its ShapeProto encoding.
+pub fn shape_from_proto(proto: &[u8]) -> Result<Shape, String> {
+  let mut element_type = PrimitiveType::Invalid;
+  let mut dimensions = vec![];
+  let mut tuple_shapes = vec![];
+  let mut layout = None;
+  let mut dynamic_flags = vec![];
+  let mut reader = ProtoReader::new(proto);
+  while !reader.done() {
+    match reader.read_field()? {
+      (SHAPE_ELEMENT_TYPE, FieldValue::Varint(v)) =>
+        element_type = primitive_type_from_proto(v)?,
+      (SHAPE_DIMENSIONS, value) => read_varints(value, &mut dimensions)?,
+      (SHAPE_TUPLE_SHAPES, FieldValue::Bytes(bytes)) =>
+        tuple_shapes.push(shape_from_proto(bytes)?),
+      (SHAPE_LAYOUT, FieldValue::Bytes(bytes)) => layout = Some(layout_from_proto(bytes)?),
+      (SHAPE_IS_DYNAMIC_DIMENSION, value) => read_varints(value, &mut dynamic_flags)?,
+      // Skip the fields that are not supported.
+      _ => {}
+    }
+  }
+  match element_type {
+    PrimitiveType::Tuple => return Ok(ShapeUtil::make_tuple_shape(tuple_shapes)),
+    PrimitiveType::Token => return Ok(ShapeUtil::make_token_shape()),
+    PrimitiveType::Invalid | PrimitiveType::OpaqueType =>
+      return Err(format!("Unsupported element type in shape: {:?}.", element_type)),
+    _ => {}
+  }
+  let dimensions: Vec<i64> = dimensions.iter().map(|d| *d as i64).collect();
+  if dimensions.iter().any(|d| *d < 0) {
+    return Err(format!("Invalid dimensions in shape: {:?}.", dimensions));
+  }
+  let mut shape = ShapeUtil::make_shape(&element_type, dimensions);
+  if !dynamic_flags.is_empty() {
+    if dynamic_flags.len() != shape.rank() {
+      return Err(format!("Expected {} dynamic dimension flags, but got {}.",
+        shape.rank(), dynamic_flags.len()));
+    }
+    for (i, flag) in dynamic_flags.iter().enumerate() {
+      shape.set_dynamic_dimension(i, *flag != 0);
+    }
+  }
+  match layout {
+    Some(layout) => {
+      if layout.minor_to_major_size() != shape.rank() {
+        return Err(format!("Layout has {} dimensions, but the shape has rank {}.",
+          layout.minor_to_major_size(), shape.rank()));
+      }
+      shape.set_layout(layout);
+    },
+    None => shape.clear_layout()
+  }
+  Ok(shape)
+}
+
+fn layout_from_proto(proto: &[u8]) -> Result<Layout, String> {
+  let mut layout = Layout::new();
+  let mut minor_to_major = vec![];
+  let mut reader = ProtoReader::new(proto);
+  while !reader.done() {
+    match reader.read_field()? {
+      (LAYOUT_MINOR_TO_MAJOR, value) => read_varints(value, &mut minor_to_major)?,
+      (LAYOUT_TILES, FieldValue::Bytes(bytes)) => {
+        let mut dimensions = vec![];
+        let mut tile_reader = ProtoReader::new(bytes);
+        while !tile_reader.done() {
+          if let (TILE_DIMENSIONS, value) = tile_reader.read_field()? {
+            read_varints(value, &mut dimensions)?;
+          }
+        }
+        layout.add_tiles(Tile::new(dimensions.iter().map(|d| *d as i64).collect()));
+      },
+      (LAYOUT_ELEMENT_SIZE_IN_BITS, FieldValue::Varint(v)) =>
+        layout.set_element_size_in_bits(v as i64),
+      (LAYOUT_MEMORY_SPACE, FieldValue::Varint(v)) => layout.set_memory_space(v as i64),
+      (LAYOUT_TAIL_PADDING_ALIGNMENT_IN_ELEMENTS, FieldValue::Varint(v)) =>
+        layout.set_tail_padding_alignment_in_elements(if v == 0 { 1 } else { v as i64 }),
+      _ => {}
+    }
+  }
+  for dimension in minor_to_major {
+    layout.add_minor_to_major(dimension as i64);
+  }
+  Ok(layout)
+}
+
+// Returns 'shape' with the dynamic dimension flags of 'dynamic_shape', which
+// has the same structure.
+fn with_dynamic_flags(shape: &Shape, dynamic_shape: &Shape) -> Shape {
+  if shape.is_tuple() {
+    return ShapeUtil::make_tuple_shape((0..shape.tuple_shapes_size())
+      .map(|i| with_dynamic_flags(shape.tuple_shapes(i), dynamic_shape.tuple_shapes(i)))
+      .collect());
+  }
+  let mut result = shape.clone();
+  if shape.is_array() {
+    for i in 0..shape.rank() {
+      result.set_dynamic_dimension(i, dynamic_shape.is_dynamic_dimension(i as i64));
+    }
+  }
+  result
+}
+
+fn write_literal_proto(
+  literal: &DynLiteral,
+  shape: &Shape,
+  index: &mut Vec<i64>,
+  output: &mut Vec<u8>) -> Result<(), String>
+{
+  write_bytes_field(output, LITERAL_SHAPE, &shape_to_proto(shape));
+  if shape.is_tuple() {
+    for i in 0..shape.tuple_shapes_size() {
+      let mut element = vec![];
+      index.push(i as i64);
+      write_literal_proto(literal, shape.tuple_shapes(i), index, &mut element)?;
+      index.pop();
+      write_bytes_field(output, LITERAL_TUPLE_LITERALS, &element);
+    }
+    return Ok(());
+  }
+  if !shape.is_array() {
+    return Ok(());
+  }
+  if !literal.is_known(index) {
+    return Err("Cannot serialize a literal with unknown arrays to a proto.".to_string());
+  }
+  let t = shape.element_type();
+  let field = literal_data_field(&t)?;
+  let data = literal.untyped_data(index);
+  if !is_varint_type(&t) {
+    if !data.is_empty() {
+      write_bytes_field(output, field, data);
+    }
+    return Ok(());
+  }
+  let size = element_size_in_bytes(&t);
+  let values: Vec<u64> = data.chunks(size).map(|bytes| {
+    let mut raw = [0_u8; 8];
+    raw[..size].copy_from_slice(bytes);
+    let value = u64::from_le_bytes(raw);
+    if t == PrimitiveType::S32 {
+      // Negative int32 values are sign extended varints.
+      value as u32 as i32 as i64 as u64
+    } else {
+      value
+    }
+  }).collect();
+  write_packed_varints(output, field, &values);
+  Ok(())
+}
+
+fn read_literal_proto(proto: &[u8]) -> Result<DynLiteral, String> {
+  let mut shape = None;
+  let mut elements = vec![];
+  let mut data_field = None;
+  let mut data = vec![];
+  let mut varints = vec![];
+  let mut reader = ProtoReader::new(proto);
+  while !reader.done() {
+    match reader.read_field()? {
+      (LITERAL_SHAPE, FieldValue::Bytes(bytes)) => shape = Some(shape_from_proto(bytes)?),
+      (LITERAL_TUPLE_LITERALS, FieldValue::Bytes(bytes)) =>
+        elements.push(read_literal_proto(bytes)?),
+      (field, value) => {
+        if data_field.is_some() && data_field != Some(field) {
+          return Err("Literal proto has more than one data field.".to_string());
+        }
+        data_field = Some(field);
+        match value {
+          FieldValue::Bytes(bytes) if field != LITERAL_PREDS && field != LITERAL_S32S &&
+            field != LITERAL_S64S && field != LITERAL_U32S && field != LITERAL_U64S =>
+            data.extend_from_slice(bytes),
+          FieldValue::Fixed32(bytes) | FieldValue::Fixed64(bytes) =>
+            data.extend_from_slice(bytes),
+          value => read_varints(value, &mut varints)?
+        }
+      }
+    }
+  }
+  let shape = shape.ok_or("Literal proto has no shape.".to_string())?;
+  let mut literal = DynLiteral::new(&shape);
+  if shape.is_tuple() {
+    if elements.len() != shape.tuple_shapes_size() {
+      return Err(format!("Expected {} tuple elements, but got {}.",
+        shape.tuple_shapes_size(), elements.len()));
+    }
+    for (i, element) in elements.iter().enumerate() {
+      literal.copy_from(element, &vec![i as i64], &vec![])?;
+    }
+    return Ok(literal);
+  }
+  if !shape.is_array() {
+    return Ok(literal);
+  }
+  let t = shape.element_type();
+  let expected_field = literal_data_field(&t)?;
+  if data_field.is_some() && data_field != Some(expected_field) {
+    return Err(format!("Unexpected data field {} for an array of {}.",
+      data_field.unwrap(), lowercase_primitive_type_name(&t)));
+  }
+  if is_varint_type(&t) {
+    let size = element_size_in_bytes(&t);
+    for value in varints {
+      data.extend_from_slice(&value.to_le_bytes()[..size]);
+    }
+  }
+  if is_4bit_type(&t) {
+    for byte in data.iter_mut() {
+      *byte &= 0xf;
+    }
+  }
+  for i in 0..shape.rank() {
+    if shape.is_dynamic_dimension(i as i64) {
+      literal.set_dynamic_size(i, &vec![], shape.dimensions(i));
+    }
+  }
+  literal.set_untyped_data(&vec![], data)?;
+  Ok(literal)
+}
+
+fn read_u64(data: &[u8], position: &mut usize) -> Result<u64, String> {
+  if data.len() - *position < 8 {
+    return Err("Serialized literal is truncated.".to_string());
+  }
+  let value = u64::from_le_bytes(data[*position..*position + 8].try_into().unwrap());
+  *position += 8;
+  Ok(value)
+}
+
+// Returns the number of bytes of the elements of an array of 'shape' in the
+// binary format.
+fn serialized_data_size(shape: &Shape) -> usize {
+  let count = ShapeUtil::elements_in(shape) as usize;
+  let t = shape.element_type();
+  if is_4bit_type(&t) {
+    return count.div_ceil(2);
+  }
+  count * element_size_in_bytes(&t)
+}
+
+impl DynLiteral {
+  // Returns the LiteralProto encoding of this literal. Fails if it has
+  // unknown arrays.
+  pub fn to_proto(&self) -> Result<Vec<u8>, String> {
+    let static_literal = self.to_static();
+    let shape = with_dynamic_flags(static_literal.shape(), self.shape());
+    let mut output = vec![];
+    write_literal_proto(&static_literal, &shape, &mut vec![], &mut output)?;
+    Ok(output)
+  }
+
+  // Creates a literal from its LiteralProto encoding.
+  pub fn create_from_proto(proto: &[u8]) -> Result<DynLiteral, String> {
+    read_literal_proto(proto)
+  }
+
+  // Computes the size in bytes of the output of the serialize method.
+  pub fn serialized_size(&self) -> i64 {
+    let mut size = MAGIC.len() + 4 + 8 + shape_to_proto(self.shape()).len();
+    let mut func = |subshape: &Shape, index: &Vec<i64>| {
+      size += 1;
+      if !self.is_known(index) {
+        return;
+      }
+      if subshape.is_dynamic() {
+        size += 8 * subshape.rank();
+      }
+      size += serialized_data_size(subshape);
+    };
+    for_each_array(self.shape(), &mut vec![], &mut func);
+    size as i64
+  }
+
+  // Appends the binary format of this literal to 'output'. The format is
+  // faster than the proto but should not be relied on for forward or
+  // backward compatibility.
+  pub fn serialize(&self, output: &mut Vec<u8>) {
+    output.reserve(self.serialized_size() as usize);
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&VERSION.to_le_bytes());
+    let shape_proto = shape_to_proto(self.shape());
+    output.extend_from_slice(&(shape_proto.len() as u64).to_le_bytes());
+    output.extend_from_slice(&shape_proto);
+    let mut func = |subshape: &Shape, index: &Vec<i64>| {
+      if !self.is_known(index) {
+        output.push(1);
+        return;
+      }
+      output.push(0);
+      if subshape.is_dynamic() {
+        for i in 0..subshape.rank() {
+          output.extend_from_slice(&self.get_dynamic_size(i, index).to_le_bytes());
+        }
+      }
+      let data = self.untyped_data(index);
+      if is_4bit_type(&subshape.element_type()) {
+        for pair in data.chunks(2) {
+          let high = if pair.len() == 2 { pair[1] & 0xf } else { 0 };
+          output.push((pair[0] & 0xf) | (high << 4));
+        }
+      } else {
+        output.extend_from_slice(data);
+      }
+    };
+    for_each_array(self.shape(), &mut vec![], &mut func);
+  }
+
+  // Serializes the literal into 'output', replacing its contents.
+  pub fn serialize_to_string(&self, output: &mut Vec<u8>) {
+    output.clear();
+    self.serialize(output);
+  }
+
+  // Serializes the literal and returns the bytes.
+  pub fn serialize_as_string(&self) -> Vec<u8> {
+    let mut output = vec![];
+    self.serialize(&mut output);
+    output
+  }
+
+  // Creates a literal from the output of serialize.
+  pub fn deserialize(data: &[u8]) -> Result<DynLiteral, String> {
+    if data.len() < MAGIC.len() + 4 || &data[..MAGIC.len()] != MAGIC {
+      return Err("Serialized literal has no valid header.".to_string());
+    }
+    let mut position = MAGIC.len();
+    let version = u32::from_le_bytes(data[position..position + 4].try_into().unwrap());
+    if version != VERSION {
+      return Err(format!("Unsupported serialized literal version {}.", version));
+    }
+    position += 4;
+    let shape_size = read_u64(data, &mut position)? as usize;
+    if data.len() - position < shape_size {
+      return Err("Serialized literal is truncated.".to_string());
+    }
+    let shape = shape_from_proto(&data[position..position + shape_size])?;
+    position += shape_size;
+
+    let mut literal = DynLiteral::new(&shape);
+    let mut status = Ok(());
+    let mut func = |subshape: &Shape, index: &Vec<i64>| {
+      if status.is_err() {
+        return;
+      }
+      status = read_array(data, &mut position, subshape, index, &mut literal);
+    };
+    for_each_array(&shape, &mut vec![], &mut func);
+    status?;
+    if position != data.len() {
+      return Err(format!("Serialized literal has {} trailing bytes.", data.len() - position));
+    }
+    Ok(literal)
+  }
+
+  // Same as deserialize.
+  pub fn deserialize_from_string(data: &[u8]) -> Result<DynLiteral, String> {
+    DynLiteral::deserialize(data)
+  }
+}
+
+// Reads the array of 'subshape' at 'index' of the binary format.
+fn read_array(
+  data: &[u8],
+  position: &mut usize,
+  subshape: &Shape,
+  index: &Vec<i64>,
+  literal: &mut DynLiteral) -> Result<(), String>
+{
+  let state = *data.get(*position).ok_or("Serialized literal is truncated.".to_string())?;
+  *position += 1;
+  match state {
+    0 => {},
+    1 => {
+      literal.set_unknown(index);
+      return Ok(());
+    },
+    _ => return Err(format!("Invalid array state {}.", state))
+  }
+  if subshape.is_dynamic() {
+    for i in 0..subshape.rank() {
+      let size = read_u64(data, position)? as i64;
+      if size < 0 || size > subshape.dimensions(i) {
+        return Err(format!("Dynamic size {} is out of the bound {}.",
+          size, subshape.dimensions(i)));
+      }
+      if subshape.is_dynamic_dimension(i as i64) {
+        literal.set_dynamic_size(i, index, size);
+      }
+    }
+  }
+  let size = serialized_data_size(subshape);
+  if data.len() - *position < size {
+    return Err("Serialized literal is truncated.".to_string());
+  }
+  let bytes = &data[*position..*position + size];
+  *position += size;
+  if is_4bit_type(&subshape.element_type()) {
+    let count = ShapeUtil::elements_in(subshape) as usize;
+    let unpacked = (0..count).map(|i| (bytes[i / 2] >> (4 * (i % 2))) & 0xf).collect();
+    literal.set_untyped_data(index, unpacked)
+  } else {
+    literal.set_untyped_data(index, bytes.to_vec())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::literal::Literal;
+  use num::complex::Complex32;
+
+  fn make_test_literal() -> DynLiteral {
+    let s4_shape = ShapeUtil::make_shape(&PrimitiveType::S4, vec![3]);
+    let mut s4 = DynLiteral::new(&s4_shape);
+    for (i, value) in [-8.0, 7.0, -1.0].iter().enumerate() {
+      s4.set_from_double(&vec![i as i64], &vec![], *value);
+    }
+    let mut dynamic_shape = ShapeUtil::make_shape(&PrimitiveType::S32, vec![2, 3]);
+    dynamic_shape.set_dynamic_dimension(1, true);
+    let mut dynamic = DynLiteral::new(&dynamic_shape);
+    dynamic.set_data(&vec![], &[1, -2, 3, 4, 5, 6]);
+    dynamic.set_dynamic_size(1, &vec![], 2);
+    DynLiteral::make_tuple(vec![
+      DynLiteral::create_r1(&[1.5_f32, -2.0]),
+      s4,
+      DynLiteral::make_tuple(vec![
+        DynLiteral::create_r0(true),
+        DynLiteral::create_r1(&[Complex32::new(1.0, -1.0)])]),
+      dynamic])
+  }
+
+  #[test]
+  fn test_proto_round_trip() {
+    let literal = make_test_literal();
+    let proto = literal.to_proto().unwrap();
+    let result = DynLiteral::create_from_proto(&proto).unwrap();
+
+    assert_eq!(result.sub_literal(&vec![0]), literal.sub_literal(&vec![0]));
+    assert_eq!(result.sub_literal(&vec![1]), literal.sub_literal(&vec![1]));
+    assert_eq!(result.sub_literal(&vec![2]), literal.sub_literal(&vec![2]));
+    // The dynamic array comes back with its dynamic sizes as bounds.
+    let dynamic = result.sub_literal(&vec![3]);
+    assert!(dynamic.shape().is_dynamic_dimension(1));
+    assert_eq!(dynamic.shape().dimensions(1), 2);
+    assert_eq!(dynamic.data::<i32>(&vec![]), vec![1, -2, 4, 5]);
+  }
+
+  #[test]
+  fn test_proto_encoding() {
+    // A ShapeProto of f32[2]{0} and the LiteralProto of {1, 2} as encoded by
+    // protobuf.
+    let literal = DynLiteral::create_r1(&[1.0_f32, 2.0]);
+    let mut shape = literal.shape().clone();
+    shape.set_layout(Layout::new_from_minor_to_major(vec![0]));
+    let shape_proto = shape_to_proto(&shape);
+    assert_eq!(shape_proto, vec![0x10, 0x0b, 0x1a, 0x01, 0x02, 0x2a, 0x06, 0x0a, 0x01, 0x00,
+      0x80, 0x01, 0x01]);
+
+    let mut expected = vec![0x0a, shape_proto.len() as u8];
+    expected.extend_from_slice(&shape_proto);
+    expected.extend_from_slice(&[0x42, 0x08, 0, 0, 0x80, 0x3f, 0, 0, 0, 0x40]);
+    let mut with_layout = DynLiteral::new(&shape);
+    with_layout.set_data(&vec![], &[1.0_f32, 2.0]);
+    assert_eq!(with_layout.to_proto().unwrap(), expected);
+
+    let negative = DynLiteral::create_r0(-1_i32).to_proto().unwrap();
+    let result = DynLiteral::create_from_proto(&negative).unwrap();
+    assert_eq!(result.get::<i32>(&vec![], &vec![]), -1);
+  }
+
+  #[test]
+  fn test_binary_round_trip() {
+    let mut literal = make_test_literal();
+    literal.set_unknown(&vec![0]);
+    let data = literal.serialize_as_string();
+    assert_eq!(data.len() as i64, literal.serialized_size());
+
+    let result = DynLiteral::deserialize(&data).unwrap();
+    assert_eq!(result, literal);
+    assert!(!result.is_known(&vec![0]));
+    assert_eq!(result.get_dynamic_size(1, &vec![3]), 2);
+    assert_eq!(result.untyped_data(&vec![1]), literal.untyped_data(&vec![1]));
+
+    assert!(DynLiteral::deserialize(&data[..data.len() - 1]).is_err());
+    assert!(DynLiteral::deserialize(b"XXXX").is_err());
+  }
+
+  #[test]
+  fn test_literal_round_trip() {
+    let literal = DynLiteral::create_r1(&[1_i64, -5, 7]).to_literal::<i64>().unwrap();
+    let data = literal.serialize_as_string().unwrap();
+    assert_eq!(data.len() as i64, literal.serialized_size().unwrap());
+    assert_eq!(Literal::<i64>::deserialize(&data).unwrap().data(&vec![]), &vec![1, -5, 7]);
+    assert!(Literal::<f32>::deserialize(&data).is_err());
+
+    let proto = literal.to_proto().unwrap();
+    assert_eq!(Literal::<i64>::create_from_proto(&proto).unwrap().data(&vec![]), &vec![1, -5, 7]);
+  }
+}
@@ -1,27 +1,59 @@
 #![allow(dead_code)]
 
 
-use crate::{array3d::Array3D, blitz_data::PrimitiveType, layout::Layout, layout_util::LayoutUtil, literal::{Literal, LiteralBase /*LiteralSlice*/}, primitive_util::native_to_primitive_type, shape_util::ShapeUtil};
+use num::complex::{Complex32, Complex64};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+  array::Array, array2d::Array2D, array3d::Array3D, array4d::Array4D,
+  blitz_data::PrimitiveType, dyn_literal::{for_each_array, next_index, DynLiteral},
+  index_util::IndexUtil, layout::Layout, layout_util::LayoutUtil, literal::Literal,
+  primitive_util::{
+    has_infinity, is_floating_point_type, is_signed_integral_type,
+    is_unsigned_integral_type, lowercase_primitive_type_name, max_finite_value,
+    native_to_primitive_type
+  },
+  shape::Shape, shape_util::ShapeUtil
+};
 
 // Utilities for dealing with Literal protobufs.
 pub struct LiteralUtil {}
 
 impl LiteralUtil {
   // Returns a literal scalar representing the first element.
-  pub fn get_first_scalar_literal<T>(_literal: &Literal<T>) -> &Literal<T>
+  pub fn get_first_scalar_literal<T>(literal: &Literal<T>) -> Literal<T>
     where T: Clone + Default + PartialEq
   {
-    unimplemented!()
+    assert!(literal.shape().is_array());
+    assert!(ShapeUtil::elements_in(literal.shape()) > 0);
+    LiteralUtil::get_scalar_literal(literal, &vec![0; literal.shape().rank()])
   }
 
   // Returns a literal scalar representing the element at `multi_index`.
-  pub fn get_scalar_literal<T>(_literal: LiteralBase<T>, _multi_index: Vec<i64>) -> Literal<T>
+  pub fn get_scalar_literal<T>(literal: &Literal<T>, multi_index: &Vec<i64>) -> Literal<T>
     where T: Clone + Default + PartialEq
   {
-    unimplemented!()
+    assert!(literal.shape().is_array());
+    let shape = ShapeUtil::make_shape(&literal.shape().element_type(), vec![]);
+    let value = literal.get(multi_index, &vec![]).clone();
+    create_from_generator(&shape, |_| value.clone())
   }
 
-  pub fn set_scalar_literal() {}
+  // Sets the element at `multi_index` to the value of the scalar literal
+  // `scalar`, which must have the same element type.
+  pub fn set_scalar_literal<T>(
+    literal: &mut Literal<T>, multi_index: &Vec<i64>, scalar: &Literal<T>)
+    where T: Clone + Default + PartialEq
+  {
+    assert!(literal.shape().is_array());
+    assert!(ShapeUtil::is_scalar(scalar.shape()));
+    assert_eq!(literal.shape().element_type(), scalar.shape().element_type());
+    let linear_index =
+      IndexUtil::multi_dimensional_index_to_linear_index(literal.shape(), multi_index);
+    let mut data = literal.data(&vec![]).clone();
+    data[linear_index as usize] = scalar.get_first_element().clone();
+    literal.set_data(&vec![], data);
+  }
 
   // Creates a new literal of a given rank. To minimize ambiguity (for users
   // and the compiler) these CreateR[0-2] methods should explicitly specify the
@@ -91,65 +123,295 @@ impl LiteralUtil {
   }
 
   pub fn create_r3_with_layout<NativeT>(
-   _values: &Vec<Vec<Vec<NativeT>>>, _layout: &Layout) -> Literal<NativeT>
+    values: &Vec<Vec<Vec<NativeT>>>, layout: &Layout) -> Literal<NativeT>
+    where NativeT: Clone + Default + PartialEq
+  {
+    let n2 = values.first().map_or(0, |v| v.len());
+    let n3 = values.first().and_then(|v| v.first()).map_or(0, |v| v.len());
+    let shape = make_shape_with_layout::<NativeT>(
+      &vec![values.len() as i64, n2 as i64, n3 as i64], layout);
+    create_from_generator(&shape, |index| {
+      values[index[0] as usize][index[1] as usize][index[2] as usize].clone()
+    })
+  }
+
+  pub fn create_r4<NativeT>(
+    values: &Vec<Vec<Vec<Vec<NativeT>>>>) -> Literal<NativeT>
     where NativeT: Clone + Default + PartialEq
   {
-    unimplemented!()
+    LiteralUtil::create_r4_with_layout(values,
+      &LayoutUtil::get_default_layout_for_r4())
   }
 
-  pub fn create_r4() {}
-  pub fn create_r4_with_layout() {}
+  pub fn create_r4_with_layout<NativeT>(
+    values: &Vec<Vec<Vec<Vec<NativeT>>>>, layout: &Layout) -> Literal<NativeT>
+    where NativeT: Clone + Default + PartialEq
+  {
+    let n2 = values.first().map_or(0, |v| v.len());
+    let n3 = values.first().and_then(|v| v.first()).map_or(0, |v| v.len());
+    let n4 = values.first().and_then(|v| v.first()).and_then(|v| v.first())
+      .map_or(0, |v| v.len());
+    let shape = make_shape_with_layout::<NativeT>(
+      &vec![values.len() as i64, n2 as i64, n3 as i64, n4 as i64], layout);
+    create_from_generator(&shape, |index| {
+      values[index[0] as usize][index[1] as usize][index[2] as usize][index[3] as usize]
+        .clone()
+    })
+  }
 
   // Creates a scalar literal value zero of the given primitive type.
-  pub fn zero<T>(_primitive_type: PrimitiveType) -> Literal<T>
-    where T: Clone + Default + PartialEq
-  {
-    unimplemented!()
+  pub fn zero(primitive_type: PrimitiveType) -> DynLiteral {
+    create_scalar_from_double(&primitive_type, 0.0)
   }
 
   // Creates a scalar literal value one of the given primitive type.
-  pub fn one<T>(_primitive_type: PrimitiveType) -> Literal<T>
-    where T: Clone + Default + PartialEq
+  pub fn one(primitive_type: PrimitiveType) -> DynLiteral {
+    create_scalar_from_double(&primitive_type, 1.0)
+  }
+
+  // Creates a scalar literal value containing the minimum value of the given
+  // primitive type. For floating-point types supporting inf, returns -inf.
+  pub fn min_value(primitive_type: PrimitiveType) -> DynLiteral {
+    let t = &primitive_type;
+    if is_signed_integral_type(t) {
+      // The integer saturates at the minimum value of the type.
+      return create_scalar_from_double(t, f64::MIN);
+    }
+    if is_unsigned_integral_type(t) || *t == PrimitiveType::Pred {
+      return create_scalar_from_double(t, 0.0);
+    }
+    assert!(is_floating_point_type(t),
+      "Unhandled primitive type {}", lowercase_primitive_type_name(t));
+    if has_infinity(t) {
+      create_scalar_from_double(t, f64::NEG_INFINITY)
+    } else {
+      create_scalar_from_double(t, -max_finite_value(t))
+    }
+  }
+
+  // Creates a scalar literal value containing the maximum value of the given
+  // primitive type. For floating-point types supporting inf, returns inf.
+  pub fn max_value(primitive_type: PrimitiveType) -> DynLiteral {
+    let t = &primitive_type;
+    if is_signed_integral_type(t) || is_unsigned_integral_type(t) {
+      // The integer saturates at the maximum value of the type.
+      return create_scalar_from_double(t, f64::MAX);
+    }
+    if *t == PrimitiveType::Pred {
+      return create_scalar_from_double(t, 1.0);
+    }
+    assert!(is_floating_point_type(t),
+      "Unhandled primitive type {}", lowercase_primitive_type_name(t));
+    if has_infinity(t) {
+      create_scalar_from_double(t, f64::INFINITY)
+    } else {
+      create_scalar_from_double(t, max_finite_value(t))
+    }
+  }
+
+  // Creates a scalar literal value containing the NaN value of the given
+  // primitive type. Fail for non-inexact types. For complex types, returns a
+  // nan + nan * j value.
+  pub fn nan_value(primitive_type: PrimitiveType) -> Result<DynLiteral, String> {
+    let t = &primitive_type;
+    if is_floating_point_type(t) {
+      return Ok(create_scalar_from_double(t, f64::NAN));
+    }
+    let shape = ShapeUtil::make_shape(t, vec![]);
+    let mut literal = DynLiteral::new(&shape);
+    match t {
+      PrimitiveType::C64 => literal.set(&vec![], &vec![], Complex32::new(f32::NAN, f32::NAN)),
+      PrimitiveType::C128 => literal.set(&vec![], &vec![], Complex64::new(f64::NAN, f64::NAN)),
+      _ => return Err(format!("Invalid type for nan_value: {}",
+        lowercase_primitive_type_name(t)))
+    }
+    Ok(literal)
+  }
+
+  // Creates a literal of the given dimensions with a descending layout, whose
+  // elements are all 'value'.
+  pub fn create_full_with_descending_layout<NativeT>(
+    dimensions: &Vec<i64>, value: NativeT) -> Literal<NativeT>
+    where NativeT: Clone + Default + PartialEq
   {
-    unimplemented!()
+    let layout = LayoutUtil::make_descending_layout(dimensions.len() as i64);
+    let shape = make_shape_with_layout::<NativeT>(dimensions, &layout);
+    create_from_generator(&shape, |_| value.clone())
   }
 
-  pub fn min_value() {}
-  pub fn max_value() {}
-  pub fn nan_value() {}
-  pub fn create_full_with_descending_layout() {}
+  // Creates a new literal from an Array type. The variants not ending with
+  // WithLayout use the default XLA layout for the literal's linear
+  // representation in memory.
+  pub fn create_from_array(values: &Array) -> Literal<i64> {
+    LiteralUtil::create_from_array_with_layout(values,
+      &LayoutUtil::get_default_layout_for_rank(values.num_dimensions() as i64))
+  }
 
-  pub fn create_from_array() {}
+  pub fn create_from_array_with_layout(values: &Array, layout: &Layout) -> Literal<i64> {
+    let shape = make_shape_with_layout::<i64>(values.dimensions(), layout);
+    create_from_generator(&shape, |index| values.value_at(index))
+  }
 
-  pub fn craete_from_array_with_layout<NativeT>(
-    values: &Array3D<NativeT>, layout: &Layout) -> Literal<NativeT>
+  pub fn create_r2_from_array_2d<NativeT>(values: &Array2D<NativeT>) -> Literal<NativeT>
     where NativeT: Clone + Default + PartialEq
   {
-    let _literal = ShapeUtil::make_shape_with_dense_layout(
-      &native_to_primitive_type(&NativeT::default()),
-      values.dimensions(),
-      layout.minor_to_major_vec(),
-      vec![],
-      1,
-      0,
-      0);
+    LiteralUtil::create_r2_from_array_2d_with_layout(values,
+      &LayoutUtil::get_default_layout_for_r2())
+  }
 
-    unimplemented!()
+  pub fn create_r2_from_array_2d_with_layout<NativeT>(
+    values: &Array2D<NativeT>, layout: &Layout) -> Literal<NativeT>
+    where NativeT: Clone + Default + PartialEq
+  {
+    let shape = make_shape_with_layout::<NativeT>(
+      &vec![values.n1() as i64, values.n2() as i64], layout);
+    create_from_generator(&shape, |index| {
+      values.data(index[0] as usize, index[1] as usize).clone()
+    })
   }
 
-  pub fn create_r2_from_array_2d() {}
-  pub fn create_r2_from_array_2d_with_layout() {}
-  pub fn create_r3_from_array_3d() {}
+  pub fn create_r3_from_array_3d<NativeT>(values: &Array3D<NativeT>) -> Literal<NativeT>
+    where NativeT: Clone + Default + PartialEq
+  {
+    LiteralUtil::create_r3_from_array_3d_with_layout(values,
+      &LayoutUtil::get_default_layout_for_r3())
+  }
 
   pub fn create_r3_from_array_3d_with_layout<NativeT>(
-    _values: Array3D<NativeT>, _layout: &Layout) -> Literal<NativeT>
+    values: &Array3D<NativeT>, layout: &Layout) -> Literal<NativeT>
+    where NativeT: Clone + Default + PartialEq
+  {
+    let shape = make_shape_with_layout::<NativeT>(
+      &vec![values.n1() as i64, values.n2() as i64, values.n3() as i64], layout);
+    create_from_generator(&shape, |index| {
+      values.data(index[0] as usize, index[1] as usize, index[2] as usize).clone()
+    })
+  }
+
+  pub fn create_r4_from_array_4d<NativeT>(values: &Array4D<NativeT>) -> Literal<NativeT>
+    where NativeT: Clone + Default + PartialEq
+  {
+    LiteralUtil::create_r4_from_array_4d_with_layout(values,
+      &LayoutUtil::get_default_layout_for_r4())
+  }
+
+  pub fn create_r4_from_array_4d_with_layout<NativeT>(
+    values: &Array4D<NativeT>, layout: &Layout) -> Literal<NativeT>
     where NativeT: Clone + Default + PartialEq
   {
-    unimplemented!()
+    let shape = make_shape_with_layout::<NativeT>(&vec![values.n1() as i64,
+      values.n2() as i64, values.n3() as i64, values.n4() as i64], layout);
+    create_from_generator(&shape, |index| {
+      values.data(index[0] as usize, index[1] as usize, index[2] as usize,
+        index[3] as usize).clone()
+    })
   }
 
-  pub fn create_r4_from_array_4d() {}
-  pub fn create_r4_from_array_4d_with_layout() {}
+  // Creates an array literal of the given shape whose elements are their
+  // index along 'iota_dimension', like the iota instruction.
+  pub fn create_iota(shape: &Shape, iota_dimension: usize) -> DynLiteral {
+    assert!(shape.is_array());
+    assert!(iota_dimension < shape.rank());
+    let mut literal = DynLiteral::new(shape);
+    for_each_index(shape, &mut |index| {
+      literal.set_from_double(index, &vec![], index[iota_dimension] as f64);
+    });
+    literal
+  }
+
+  // Converts the arrays of type 'from' in 'literal' to the type 'to'. The
+  // other arrays are copied unchanged.
+  pub fn convert(literal: &DynLiteral, from: &PrimitiveType, to: &PrimitiveType) -> DynLiteral {
+    let shape = convert_shape(literal.shape(), from, to);
+    let mut result = DynLiteral::new(&shape);
+    for_each_array(literal.shape(), &mut vec![], &mut |subshape: &Shape, index: &Vec<i64>| {
+      if !literal.is_known(index) {
+        result.set_unknown(index);
+      } else if subshape.element_type() == *from {
+        let converted = literal.sub_literal(index).convert(to).unwrap();
+        result.copy_from(&converted, index, &vec![]).unwrap();
+      } else {
+        result.copy_from(literal, index, index).unwrap();
+      }
+    });
+    result
+  }
+
+  pub fn convert_bf16_to_f32(bf16_literal: &DynLiteral) -> DynLiteral {
+    LiteralUtil::convert(bf16_literal, &PrimitiveType::BF16, &PrimitiveType::F32)
+  }
+
+  pub fn convert_bf16_to_f64(bf16_literal: &DynLiteral) -> DynLiteral {
+    LiteralUtil::convert(bf16_literal, &PrimitiveType::BF16, &PrimitiveType::F64)
+  }
+
+  pub fn convert_f32_to_f8e4m3fnuz(f32_literal: &DynLiteral) -> DynLiteral {
+    LiteralUtil::convert(f32_literal, &PrimitiveType::F32, &PrimitiveType::F8E4M3FNUZ)
+  }
+
+  pub fn convert_f32_to_f8e5m2fnuz(f32_literal: &DynLiteral) -> DynLiteral {
+    LiteralUtil::convert(f32_literal, &PrimitiveType::F32, &PrimitiveType::F8E5M2FNUZ)
+  }
+
+  pub fn convert_f32_to_bf16(f32_literal: &DynLiteral) -> DynLiteral {
+    LiteralUtil::convert(f32_literal, &PrimitiveType::F32, &PrimitiveType::BF16)
+  }
+
+  pub fn convert_f32_to_s8(f32_literal: &DynLiteral) -> DynLiteral {
+    LiteralUtil::convert(f32_literal, &PrimitiveType::F32, &PrimitiveType::S8)
+  }
+
+  pub fn convert_f64_to_bf16(f64_literal: &DynLiteral) -> DynLiteral {
+    LiteralUtil::convert(f64_literal, &PrimitiveType::F64, &PrimitiveType::BF16)
+  }
+
+  pub fn convert_s32_to_f32(s32_literal: &DynLiteral) -> DynLiteral {
+    LiteralUtil::convert(s32_literal, &PrimitiveType::S32, &PrimitiveType::F32)
+  }
+
+  pub fn convert_f64_to_f32(f64_literal: &DynLiteral) -> DynLiteral {
+    LiteralUtil::convert(f64_literal, &PrimitiveType::F64, &PrimitiveType::F32)
+  }
+
+  pub fn convert_f32_to_f64(f32_literal: &DynLiteral) -> DynLiteral {
+    LiteralUtil::convert(f32_literal, &PrimitiveType::F32, &PrimitiveType::F64)
+  }
+
+  // Creates a literal of the given shape whose elements are drawn from a
+  // normal distribution with the given mean and standard deviation. Integers
+  // are truncated towards zero and saturated, predicates are drawn uniformly,
+  // and both parts of complex numbers are drawn independently. The same seed
+  // always gives the same literal.
+  pub fn create_random_literal(
+    shape: &Shape, mean: f64, stddev: f64, seed: u64) -> DynLiteral
+  {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut literal = DynLiteral::new(shape);
+    let normal = |rng: &mut StdRng| -> f64 {
+      // Box-Muller transform.
+      let u1: f64 = 1.0 - rng.gen::<f64>();
+      let u2: f64 = rng.gen::<f64>();
+      mean + stddev * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    };
+    for_each_array(shape, &mut vec![], &mut |subshape: &Shape, index: &Vec<i64>| {
+      let t = subshape.element_type();
+      for_each_index(subshape, &mut |multi_index| {
+        match t {
+          PrimitiveType::Pred => literal.set(multi_index, index, rng.gen::<bool>()),
+          PrimitiveType::C64 => {
+            let value = Complex32::new(normal(&mut rng) as f32, normal(&mut rng) as f32);
+            literal.set(multi_index, index, value);
+          },
+          PrimitiveType::C128 => {
+            let value = Complex64::new(normal(&mut rng), normal(&mut rng));
+            literal.set(multi_index, index, value);
+          },
+          _ => literal.set_from_double(multi_index, index, normal(&mut rng))
+        }
+      });
+    });
+    literal
+  }
 
   // Create a constant token literal. Token types have no value.
   pub fn create_token<T>() -> Literal<T>
@@ -172,18 +434,177 @@ impl LiteralUtil {
       &ShapeUtil::make_tuple_shape(element_shapes));
 
     for i in 0..elements.len() {
-      let _ = literal.copy_from(
-        &mut elements[i].clone(),
-        &vec![i as i64],
-        &vec![],
-        false);
+      copy_arrays(&mut literal, &vec![i as i64], elements[i]);
     }
     literal
   }
 
-  pub fn make_tuple_owned<T>(_elements: Vec<Literal<T>>) -> Literal<T>
+  // Like make_tuple, but the elements are consumed.
+  pub fn make_tuple_owned<T>(elements: Vec<Literal<T>>) -> Literal<T>
     where T: Clone + Default + PartialEq
   {
-    unimplemented!()
+    LiteralUtil::make_tuple(&elements.iter().collect())
+  }
+}
+
+fn make_shape_with_layout<NativeT>(dimensions: &Vec<i64>, layout: &Layout) -> Shape
+  where NativeT: Default
+{
+  ShapeUtil::make_shape_with_dense_layout(
+    &native_to_primitive_type(&NativeT::default()),
+    dimensions,
+    layout.minor_to_major_vec(),
+    vec![],
+    1,
+    0,
+    0)
+}
+
+// Calls 'func' with each multi index of the array 'shape', in row-major order.
+fn for_each_index<F>(shape: &Shape, func: &mut F) where F: FnMut(&Vec<i64>) {
+  if ShapeUtil::elements_in(shape) == 0 {
+    return;
+  }
+  let mut index = vec![0; shape.rank()];
+  loop {
+    func(&index);
+    if !next_index(shape, &mut index) {
+      break;
+    }
+  }
+}
+
+// Creates an array literal of 'shape' whose elements are the values returned
+// by 'generator' for their multi index.
+fn create_from_generator<T, F>(shape: &Shape, mut generator: F) -> Literal<T>
+  where T: Clone + Default + PartialEq, F: FnMut(&Vec<i64>) -> T
+{
+  let mut data = vec![T::default(); ShapeUtil::elements_in(shape) as usize];
+  for_each_index(shape, &mut |index| {
+    let linear_index = IndexUtil::multi_dimensional_index_to_linear_index(shape, index);
+    data[linear_index as usize] = generator(index);
+  });
+  let mut literal = Literal::new_from_shape(shape);
+  literal.set_data(&vec![], data);
+  literal
+}
+
+// Copies the arrays of 'src' into the subliteral of 'dest' at 'dest_index'.
+fn copy_arrays<T>(dest: &mut Literal<T>, dest_index: &Vec<i64>, src: &Literal<T>)
+  where T: Clone + Default + PartialEq
+{
+  for_each_array(src.shape(), &mut vec![], &mut |subshape: &Shape, index: &Vec<i64>| {
+    let mut full_index = dest_index.clone();
+    full_index.extend_from_slice(index);
+    dest.set_data(&full_index, src.data(index).clone());
+    for dim in 0..subshape.rank() {
+      if subshape.is_dynamic_dimension(dim as i64) {
+        dest.set_dynamic_size(dim, &full_index, src.get_dynamic_size(dim, index));
+      }
+    }
+  });
+}
+
+fn create_scalar_from_double(t: &PrimitiveType, value: f64) -> DynLiteral {
+  assert!(ShapeUtil::is_array_primitive_type(t),
+    "Unhandled primitive type {}", lowercase_primitive_type_name(t));
+  let mut literal = DynLiteral::new(&ShapeUtil::make_shape(t, vec![]));
+  literal.set_from_double(&vec![], &vec![], value);
+  literal
+}
+
+// Returns 'shape' with the element type of its arrays of type 'from' replaced
+// by 'to'.
+fn convert_shape(shape: &Shape, from: &PrimitiveType, to: &PrimitiveType) -> Shape {
+  if shape.is_tuple() {
+    return ShapeUtil::make_tuple_shape(shape.tuple_shapes_vec().iter()
+      .map(|subshape| convert_shape(subshape, from, to)).collect());
+  }
+  let mut result = shape.clone();
+  if shape.is_array() && shape.element_type() == *from {
+    result.set_element_type(to.clone());
+  }
+  result
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_scalar_values() {
+    assert_eq!(LiteralUtil::zero(PrimitiveType::BF16).get_as_double(&vec![], &vec![]), Ok(0.0));
+    assert_eq!(LiteralUtil::one(PrimitiveType::U4).get_as_double(&vec![], &vec![]), Ok(1.0));
+    assert_eq!(LiteralUtil::min_value(PrimitiveType::S64).get::<i64>(&vec![], &vec![]), i64::MIN);
+    assert_eq!(LiteralUtil::max_value(PrimitiveType::U64).get::<u64>(&vec![], &vec![]), u64::MAX);
+    assert_eq!(LiteralUtil::max_value(PrimitiveType::S4).get_as_double(&vec![], &vec![]), Ok(7.0));
+    assert_eq!(LiteralUtil::min_value(PrimitiveType::F32).get::<f32>(&vec![], &vec![]),
+      f32::NEG_INFINITY);
+    assert_eq!(LiteralUtil::max_value(PrimitiveType::F8E4M3FN)
+      .get_as_double(&vec![], &vec![]), Ok(448.0));
+    assert!(LiteralUtil::nan_value(PrimitiveType::F16).unwrap()
+      .get_as_double(&vec![], &vec![]).unwrap().is_nan());
+    assert!(LiteralUtil::nan_value(PrimitiveType::C64).unwrap()
+      .get::<Complex32>(&vec![], &vec![]).im.is_nan());
+    assert!(LiteralUtil::nan_value(PrimitiveType::S32).is_err());
+  }
+
+  #[test]
+  fn test_create_from_arrays() {
+    let r3 = LiteralUtil::create_r3(&vec![vec![vec![1, 2], vec![3, 4]], vec![vec![5, 6], vec![7, 8]]]);
+    assert_eq!(r3.shape().dimensions_vec(), &vec![2, 2, 2]);
+    assert_eq!(*r3.get(&vec![1, 0, 1], &vec![]), 6);
+
+    let array = Array2D::new_from(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+    let column_major = LiteralUtil::create_r2_from_array_2d_with_layout(
+      &array, &Layout::new_from_minor_to_major(vec![0, 1]));
+    assert_eq!(column_major.data(&vec![]), &vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+    assert_eq!(*column_major.get(&vec![0, 2], &vec![]), 3.0);
+
+    let full = LiteralUtil::create_full_with_descending_layout(&vec![2, 3], 7_i32);
+    assert_eq!(full.data(&vec![]), &vec![7; 6]);
+
+    let scalar = LiteralUtil::get_scalar_literal(&r3, &vec![1, 1, 0]);
+    assert_eq!(*scalar.get_first_element(), 7);
+    assert_eq!(*LiteralUtil::get_first_scalar_literal(&r3).get_first_element(), 1);
+
+    let mut r3 = r3;
+    LiteralUtil::set_scalar_literal(&mut r3, &vec![0, 0, 1], &scalar);
+    assert_eq!(*r3.get(&vec![0, 0, 1], &vec![]), 7);
+  }
+
+  #[test]
+  fn test_make_tuple_owned() {
+    let tuple = LiteralUtil::make_tuple_owned(vec![
+      LiteralUtil::create_r0(1.0_f64), LiteralUtil::create_r1(&vec![2.0_f64, 3.0])]);
+    assert!(tuple.shape().is_tuple());
+    assert_eq!(tuple.data(&vec![1]), &vec![2.0, 3.0]);
+  }
+
+  #[test]
+  fn test_iota_and_convert() {
+    let shape = ShapeUtil::make_shape(&PrimitiveType::F32, vec![2, 3]);
+    let iota = LiteralUtil::create_iota(&shape, 1);
+    assert_eq!(iota.data::<f32>(&vec![]), vec![0.0, 1.0, 2.0, 0.0, 1.0, 2.0]);
+
+    let tuple = DynLiteral::make_tuple(vec![iota, DynLiteral::create_r0(5_i32)]);
+    let converted = LiteralUtil::convert_f32_to_bf16(&tuple);
+    assert_eq!(converted.shape().tuple_shapes(0).element_type(), PrimitiveType::BF16);
+    assert_eq!(converted.shape().tuple_shapes(1).element_type(), PrimitiveType::S32);
+    let back = LiteralUtil::convert_bf16_to_f32(&converted);
+    assert_eq!(back, tuple);
+  }
+
+  #[test]
+  fn test_create_random_literal() {
+    let shape = ShapeUtil::make_tuple_shape(vec![
+      ShapeUtil::make_shape(&PrimitiveType::F32, vec![1000]),
+      ShapeUtil::make_shape(&PrimitiveType::S8, vec![10])]);
+    let literal = LiteralUtil::create_random_literal(&shape, 1.0, 2.0, 42);
+    assert_eq!(literal, LiteralUtil::create_random_literal(&shape, 1.0, 2.0, 42));
+    assert_ne!(literal, LiteralUtil::create_random_literal(&shape, 1.0, 2.0, 43));
+
+    let values = literal.data::<f32>(&vec![0]);
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    assert!((mean - 1.0).abs() < 0.3);
   }
-}
\ No newline at end of file
+}
@@ -1,8 +1,18 @@
 #![allow(dead_code)]
 
-use std::{fs::File, io::Read};
+use std::{fs::File, io::{Read, Seek, SeekFrom}};
 
-use crate::{layout::Layout, literal::Literal, shape::Shape};
+use crate::{
+  blitz_data::PrimitiveType,
+  dyn_literal::next_index,
+  index_util::IndexUtil,
+  layout::Layout,
+  layout_util::LayoutUtil,
+  literal::Literal,
+  primitive_util::lowercase_primitive_type_name,
+  shape::Shape,
+  shape_util::ShapeUtil
+};
 
 // Reads packed data from a metadata-less file as requested by a user (who must
 // know its internal format). These are yielded as (structured) literal values.
@@ -19,17 +29,77 @@ impl PackedLiteralReader {
   }
 
   // Yields the next packed literal with shape "shape" as read from the
-  // underlying file stream.
+  // underlying file stream. The data is expected to be packed little-endian
+  // F32 values in row-major order.
   //
-  // Layout is optional. If it is not provided, no layout is set on the literal
-  // that is produced.
-  pub fn read<T>(
-    &self,
-    _shape: &Shape,
-    _layout: &Option<Layout>) -> Result<Literal<T>, String>
-    where T: Clone + Default + PartialEq
+  // Layout is optional. If it is not provided, the default layout of the
+  // shape is used for the literal that is produced.
+  pub fn read(
+    &mut self,
+    shape: &Shape,
+    layout: &Option<Layout>) -> Result<Literal<f32>, String>
   {
-    unimplemented!()
+    if !shape.is_array() {
+      return Err(format!("Only array shapes can be read, but got {}.",
+        ShapeUtil::human_string(shape)));
+    }
+    if shape.element_type() != PrimitiveType::F32 {
+      return Err(format!("Only F32 data is supported, but got {}.",
+        lowercase_primitive_type_name(&shape.element_type())));
+    }
+    let mut literal_shape = shape.clone();
+    match layout {
+      Some(layout) => {
+        if layout.minor_to_major_size() != shape.rank() {
+          return Err(format!("Layout rank {} does not match the shape {}.",
+            layout.minor_to_major_size(), ShapeUtil::human_string(shape)));
+        }
+        literal_shape.set_layout(layout.clone());
+      },
+      None => {
+        if !literal_shape.has_layout() {
+          literal_shape.set_layout(LayoutUtil::get_default_layout_for_shape(shape));
+        }
+      }
+    }
+
+    let elements = ShapeUtil::elements_in(shape) as usize;
+    let mut bytes = vec![0; elements * 4];
+    self.file.seek(SeekFrom::Start(self.offset)).map_err(|e| e.to_string())?;
+    let mut read = 0;
+    while read < bytes.len() {
+      match self.file.read(&mut bytes[read..]) {
+        Ok(0) => break,
+        Ok(n) => read += n,
+        Err(e) => return Err(e.to_string())
+      }
+    }
+    if read != bytes.len() {
+      return Err(format!("Expected to read {} bytes at offset {}, but only {} were available.",
+        bytes.len(), self.offset, read));
+    }
+    self.offset += read as u64;
+
+    // The file holds the values in row-major order, so place each of them
+    // according to the layout of the literal.
+    let mut data = vec![0.0; elements];
+    if elements > 0 {
+      let mut index = vec![0; shape.rank()];
+      let mut i = 0;
+      loop {
+        let value = f32::from_le_bytes(bytes[i * 4..(i + 1) * 4].try_into().unwrap());
+        let linear_index =
+          IndexUtil::multi_dimensional_index_to_linear_index(&literal_shape, &index);
+        data[linear_index as usize] = value;
+        i += 1;
+        if !next_index(&literal_shape, &mut index) {
+          break;
+        }
+      }
+    }
+    let mut literal = Literal::new_from_shape(&literal_shape);
+    literal.set_data(&vec![], data);
+    Ok(literal)
   }
 
   // Returns whether the input file has been fully exhausted; i.e. all available
@@ -37,8 +107,75 @@ impl PackedLiteralReader {
   pub fn is_exhausted(&mut self) -> bool {
     // Try to read a single byte from offset_.  If we can't, we've
     // exhausted the data.
-    let single_byte: &mut [u8] = &mut [1];
-    let result = self.file.read(single_byte);
-    result.is_ok()
+    if self.file.seek(SeekFrom::Start(self.offset)).is_err() {
+      return true;
+    }
+    let single_byte: &mut [u8] = &mut [0];
+    match self.file.read(single_byte) {
+      Ok(n) => n == 0,
+      Err(_) => true
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Write;
+
+  fn write_temp_file(name: &str, values: &Vec<f32>) -> File {
+    let path = std::env::temp_dir().join(name);
+    let mut file = File::create(&path).unwrap();
+    for value in values {
+      file.write_all(&value.to_le_bytes()).unwrap();
+    }
+    File::open(&path).unwrap()
+  }
+
+  #[test]
+  fn test_read_row_major() {
+    let file = write_temp_file("packed_literal_reader_row_major.bin",
+      &vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    let mut reader = PackedLiteralReader::new(file);
+    assert!(!reader.is_exhausted());
+
+    let shape = ShapeUtil::make_shape(&PrimitiveType::F32, vec![2, 3]);
+    let literal = reader.read(&shape, &None).unwrap();
+    assert_eq!(literal.get(&vec![0, 0], &vec![]), &1.0);
+    assert_eq!(literal.get(&vec![0, 2], &vec![]), &3.0);
+    assert_eq!(literal.get(&vec![1, 0], &vec![]), &4.0);
+    assert_eq!(literal.get(&vec![1, 2], &vec![]), &6.0);
+    assert!(reader.is_exhausted());
+    assert!(reader.read(&shape, &None).is_err());
+  }
+
+  #[test]
+  fn test_read_with_layout() {
+    let file = write_temp_file("packed_literal_reader_layout.bin",
+      &vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+    let mut reader = PackedLiteralReader::new(file);
+
+    let shape = ShapeUtil::make_shape(&PrimitiveType::F32, vec![2, 3]);
+    let layout = LayoutUtil::make_layout(&vec![0, 1], vec![], vec![], vec![],
+      vec![], 1, PrimitiveType::Invalid, PrimitiveType::Invalid, 0, 0, None, 0);
+    let literal = reader.read(&shape, &Some(layout)).unwrap();
+    assert_eq!(literal.get(&vec![0, 1], &vec![]), &2.0);
+    assert_eq!(literal.get(&vec![1, 0], &vec![]), &4.0);
+    // Column-major layout: the data is stored as 1, 4, 2, 5, 3, 6.
+    assert_eq!(literal.data(&vec![]), &vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+    assert!(!reader.is_exhausted());
+
+    let scalar = reader.read(
+      &ShapeUtil::make_shape(&PrimitiveType::F32, vec![]), &None).unwrap();
+    assert_eq!(scalar.get(&vec![], &vec![]), &7.0);
+    assert!(reader.is_exhausted());
+  }
+
+  #[test]
+  fn test_read_unsupported_type() {
+    let file = write_temp_file("packed_literal_reader_type.bin", &vec![1.0]);
+    let mut reader = PackedLiteralReader::new(file);
+    let shape = ShapeUtil::make_shape(&PrimitiveType::S32, vec![1]);
+    assert!(reader.read(&shape, &None).is_err());
   }
-}
\ No newline at end of file
+}
@@ -5,7 +5,7 @@
 pub fn is_permutation(permutation: &Vec<i64>) -> bool {
   let mut seen = vec![false; permutation.len()];
   for p in permutation {
-    if *p < 0 || *p as usize > permutation.len()|| seen[*p as usize] == true {
+    if *p < 0 || *p as usize >= permutation.len() || seen[*p as usize] == true {
       return false;
     }
     seen[*p as usize] = true;
@@ -37,8 +37,19 @@ fn get_or_create_map() -> &'static HashMap<&'static str, PrimitiveType> {
   primitive_type_map
 }
 
-pub fn significand_width(_t: &PrimitiveType) -> i64 {
-  0 // TODO
+// Returns the number of significand digits, including the implicit leading
+// bit, of a floating point type.
+pub fn significand_width(t: &PrimitiveType) -> i64 {
+  match t {
+    PrimitiveType::F16 => 11,
+    PrimitiveType::BF16 => 8,
+    PrimitiveType::F32 => 24,
+    PrimitiveType::F64 => 53,
+    PrimitiveType::F8E5M2 | PrimitiveType::F8E5M2FNUZ => 3,
+    PrimitiveType::F8E4M3FN | PrimitiveType::F8E4M3B11FNUZ |
+    PrimitiveType::F8E4M3FNUZ => 4,
+    _ => unreachable!("{:?} is not a floating point type.", t)
+  }
 }
 
 pub fn exponent_width(t: &PrimitiveType) -> i64 {
@@ -48,17 +59,198 @@ pub fn exponent_width(t: &PrimitiveType) -> i64 {
   total_bit_width - (trailing_significand_field_width + sign_bit_width)
 }
 
-pub fn underflow_exponent(_t: &PrimitiveType) -> i64 {
-  0 // TODO
+// Returns the exponent of the smallest normal number, in the convention of
+// std::numeric_limits::min_exponent (the significand is in [0.5, 1)).
+pub fn underflow_exponent(t: &PrimitiveType) -> i64 {
+  match t {
+    PrimitiveType::F16 => -13,
+    PrimitiveType::BF16 | PrimitiveType::F32 => -125,
+    PrimitiveType::F64 => -1021,
+    PrimitiveType::F8E5M2 => -13,
+    PrimitiveType::F8E4M3FN => -5,
+    PrimitiveType::F8E4M3B11FNUZ => -9,
+    PrimitiveType::F8E5M2FNUZ => -14,
+    PrimitiveType::F8E4M3FNUZ => -6,
+    _ => unreachable!("{:?} is not a floating point type.", t)
+  }
 }
 
-pub fn overflow_exponent() {}
+// Returns one more than the exponent of the largest finite number, in the
+// convention of std::numeric_limits::max_exponent.
+pub fn overflow_exponent(t: &PrimitiveType) -> i64 {
+  match t {
+    PrimitiveType::F16 => 16,
+    PrimitiveType::BF16 | PrimitiveType::F32 => 128,
+    PrimitiveType::F64 => 1024,
+    PrimitiveType::F8E5M2 | PrimitiveType::F8E5M2FNUZ => 16,
+    PrimitiveType::F8E4M3FN => 9,
+    PrimitiveType::F8E4M3B11FNUZ => 5,
+    PrimitiveType::F8E4M3FNUZ => 8,
+    _ => unreachable!("{:?} is not a floating point type.", t)
+  }
+}
 
 pub fn exponent_bias(t: &PrimitiveType) -> i64 {
   (1 - underflow_exponent(t)) + 1
 }
 
-pub fn has_infinity() {}
+// Returns whether the floating point type can represent infinities. The
+// 'fn' and 'fnuz' f8 types saturate to NaN instead.
+pub fn has_infinity(t: &PrimitiveType) -> bool {
+  match t {
+    PrimitiveType::F16 | PrimitiveType::BF16 | PrimitiveType::F32 |
+    PrimitiveType::F64 | PrimitiveType::F8E5M2 => true,
+    _ => false
+  }
+}
+
+pub fn pow2(exponent: i64) -> f64 {
+  2.0_f64.powi(exponent as i32)
+}
+
+// Returns floor(log2(|value|)) for a finite non-zero value.
+fn exponent_of(value: f64) -> i64 {
+  let bits = value.abs().to_bits();
+  let biased = (bits >> 52) as i64;
+  if biased == 0 {
+    // Denormal f64.
+    return -1074 + (63 - bits.leading_zeros() as i64);
+  }
+  biased - 1023
+}
+
+// Rounds 'value' to the nearest number with 'significand_bits' significand
+// digits (the implicit bit included), ties to even. Numbers whose exponent
+// is below 'min_exponent' keep the absolute precision of the smallest normal
+// number, so they become denormals, or are flushed to zero if 'denormals' is
+// false. The exponent range is not limited from above.
+pub fn round_to_format(
+  value: f64, significand_bits: i64, min_exponent: i64, denormals: bool) -> f64
+{
+  if !value.is_finite() || value == 0.0 || significand_bits >= 53 {
+    return value;
+  }
+  let exponent = exponent_of(value);
+  let quantum = pow2(exponent.max(min_exponent) - (significand_bits - 1));
+  let rounded = (value / quantum).round_ties_even() * quantum;
+  if rounded == 0.0 || (!denormals && exponent_of(rounded) < min_exponent) {
+    return 0.0_f64.copysign(value);
+  }
+  rounded
+}
+
+// Returns the largest finite value of the floating point type 't'.
+pub fn max_finite_value(t: &PrimitiveType) -> f64 {
+  match t {
+    PrimitiveType::F64 => f64::MAX,
+    PrimitiveType::F32 => f32::MAX as f64,
+    _ => {
+      let ulp = pow2(1 - significand_width(t));
+      // F8E4M3FN spends the largest significand of its largest exponent on NaN.
+      let significand = if *t == PrimitiveType::F8E4M3FN { 2.0 - 2.0 * ulp } else { 2.0 - ulp };
+      significand * pow2(overflow_exponent(t) - 1)
+    }
+  }
+}
+
+fn is_fnuz_type(t: &PrimitiveType) -> bool {
+  matches!(t, PrimitiveType::F8E4M3B11FNUZ | PrimitiveType::F8E5M2FNUZ |
+    PrimitiveType::F8E4M3FNUZ)
+}
+
+// Rounds 'value' to the nearest value of the floating point type 't'. Values
+// out of range become infinity, or NaN for types without infinities.
+pub fn round_to_type(value: f64, t: &PrimitiveType) -> f64 {
+  match t {
+    PrimitiveType::F64 => value,
+    PrimitiveType::F32 => value as f32 as f64,
+    _ => {
+      if value.is_nan() {
+        return value;
+      }
+      let rounded = round_to_format(value, significand_width(t), underflow_exponent(t) - 1, true);
+      if rounded.abs() > max_finite_value(t) {
+        return if has_infinity(t) { f64::INFINITY.copysign(value) } else { f64::NAN };
+      }
+      if rounded == 0.0 && is_fnuz_type(t) {
+        // The fnuz types have no negative zero.
+        return 0.0;
+      }
+      rounded
+    }
+  }
+}
+
+// Returns the bit pattern of the floating point 'value' of type 't'. The
+// value is rounded to the type first.
+pub fn float_to_bits(value: f64, t: &PrimitiveType) -> u64 {
+  let value = round_to_type(value, t);
+  match t {
+    PrimitiveType::F64 => return value.to_bits(),
+    PrimitiveType::F32 => return (value as f32).to_bits() as u64,
+    // The value is a bf16, so the low half of its f32 is zero.
+    PrimitiveType::BF16 => return ((value as f32).to_bits() >> 16) as u64,
+    _ => {}
+  }
+  let mantissa_bits = significand_width(t) - 1;
+  let exponent_bits = exponent_width(t);
+  let bias = exponent_bias(t);
+  let max_exponent_field = (1_u64 << exponent_bits) - 1;
+  let sign = if value.is_sign_negative() { 1_u64 << (bit_width(t) - 1) } else { 0 };
+  if value.is_nan() {
+    return match t {
+      // The fnuz types spend the pattern of negative zero on NaN.
+      _ if is_fnuz_type(t) => 1 << (bit_width(t) - 1),
+      PrimitiveType::F8E4M3FN => 0x7f,
+      _ => (max_exponent_field << mantissa_bits) | (1 << (mantissa_bits - 1))
+    };
+  }
+  let magnitude = value.abs();
+  if magnitude == f64::INFINITY {
+    return sign | (max_exponent_field << mantissa_bits);
+  }
+  if magnitude == 0.0 {
+    return sign;
+  }
+  let exponent = exponent_of(magnitude);
+  if exponent < 1 - bias {
+    // Denormal: multiples of the smallest denormal.
+    return sign | (magnitude / pow2(1 - bias - mantissa_bits)) as u64;
+  }
+  let mantissa = ((magnitude / pow2(exponent) - 1.0) * pow2(mantissa_bits)) as u64;
+  sign | (((exponent + bias) as u64) << mantissa_bits) | mantissa
+}
+
+// Returns the floating point value of type 't' whose bit pattern is 'bits'.
+pub fn float_from_bits(bits: u64, t: &PrimitiveType) -> f64 {
+  match t {
+    PrimitiveType::F64 => return f64::from_bits(bits),
+    PrimitiveType::F32 => return f32::from_bits(bits as u32) as f64,
+    PrimitiveType::BF16 => return f32::from_bits((bits as u32) << 16) as f64,
+    _ => {}
+  }
+  let mantissa_bits = significand_width(t) - 1;
+  let exponent_bits = exponent_width(t);
+  let bias = exponent_bias(t);
+  let max_exponent_field = (1_u64 << exponent_bits) - 1;
+  let sign_bit = 1_u64 << (bit_width(t) - 1);
+  let sign = if bits & sign_bit != 0 { -1.0 } else { 1.0 };
+  let exponent = (bits >> mantissa_bits) & max_exponent_field;
+  let mantissa = bits & ((1 << mantissa_bits) - 1);
+  if is_fnuz_type(t) && bits == sign_bit {
+    return f64::NAN;
+  }
+  if *t == PrimitiveType::F8E4M3FN && bits & 0x7f == 0x7f {
+    return f64::NAN;
+  }
+  if has_infinity(t) && exponent == max_exponent_field {
+    return if mantissa == 0 { sign * f64::INFINITY } else { f64::NAN };
+  }
+  if exponent == 0 {
+    return sign * mantissa as f64 * pow2(1 - bias - mantissa_bits);
+  }
+  sign * (1.0 + mantissa as f64 * pow2(-mantissa_bits)) * pow2(exponent as i64 - bias)
+}
 
 // Declarations of specializations for each native type which correspond to a
 // Blitz primitive type.
@@ -186,8 +378,25 @@ pub fn bit_width_array_helper() {
     
 }
 
-pub fn bit_width(_t: &PrimitiveType) -> i64 {
-  0 // TODO
+// Returns the number of bits in the representation for a given type.
+pub fn bit_width(t: &PrimitiveType) -> i64 {
+  match t {
+    PrimitiveType::Pred => 8,
+    PrimitiveType::S4 | PrimitiveType::U4 => 4,
+    PrimitiveType::S8 | PrimitiveType::U8 => 8,
+    PrimitiveType::S16 | PrimitiveType::U16 => 16,
+    PrimitiveType::S32 | PrimitiveType::U32 => 32,
+    PrimitiveType::S64 | PrimitiveType::U64 => 64,
+    PrimitiveType::F16 | PrimitiveType::BF16 => 16,
+    PrimitiveType::F32 => 32,
+    PrimitiveType::F64 => 64,
+    PrimitiveType::F8E5M2 | PrimitiveType::F8E4M3FN | PrimitiveType::F8E4M3B11FNUZ |
+    PrimitiveType::F8E5M2FNUZ | PrimitiveType::F8E4M3FNUZ => 8,
+    PrimitiveType::C64 => 64,
+    PrimitiveType::C128 => 128,
+    PrimitiveType::Token => 0,
+    _ => unreachable!("{:?} is an invalid type for bit_width.", t)
+  }
 }
 
 // Returns the number of bytes in the representation for a given type.
@@ -195,6 +404,7 @@ pub fn byte_width(t: &PrimitiveType) -> i64 {
   match t {
     PrimitiveType::Pred => return 2, // ?
     PrimitiveType::S4 => return 0,
+    PrimitiveType::U4 => return 0,
     PrimitiveType::S8 => return 1, // 8 / 8
     PrimitiveType::S16 => return 2, // 16 / 8
     PrimitiveType::S32 => return 4, // 32 / 8
@@ -207,6 +417,8 @@ pub fn byte_width(t: &PrimitiveType) -> i64 {
     PrimitiveType::F16 => return 2, // 16 / 8
     PrimitiveType::F32 => return 4, // 32 / 8
     PrimitiveType::F64 => return 8, // 64 / 8
+    PrimitiveType::F8E5M2 | PrimitiveType::F8E4M3FN | PrimitiveType::F8E4M3B11FNUZ |
+    PrimitiveType::F8E5M2FNUZ | PrimitiveType::F8E4M3FNUZ => return 1, // 8 / 8
     PrimitiveType::C64 => return 8, // 64 / 8
     PrimitiveType::C128 => return 16, // 128 / 8
     PrimitiveType::Token => return 0,
@@ -328,11 +540,9 @@ impl PrimitiveTypeNameGenerator {
     let mut generator = PrimitiveTypeNameGenerator {
       lowercase_name: HashMap::new()
     };
-    generator.lowercase_name.insert(PrimitiveType::S32, "s32".to_string());
-    generator.lowercase_name.insert(PrimitiveType::U32, "u32".to_string());
-    generator.lowercase_name.insert(PrimitiveType::F32, "f32".to_string());
-    generator.lowercase_name.insert(PrimitiveType::OpaqueType, "opaque".to_string());
-    generator.lowercase_name.insert(PrimitiveType::Token, "token".to_string());
+    for (name, t) in get_or_create_map() {
+      generator.lowercase_name.insert(t.clone(), name.to_string());
+    }
     generator
   }
 
@@ -409,4 +619,45 @@ pub fn array_type_switch<R, F>(f: &mut F, t: &PrimitiveType) -> R
     }
   }
   unreachable!("Not an array data type.");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_round_to_type() {
+    assert_eq!(round_to_type(1.0 + 1e-10, &PrimitiveType::F32), 1.0);
+    assert_eq!(round_to_type(65519.0, &PrimitiveType::F16), 65504.0);
+    assert_eq!(round_to_type(65520.0, &PrimitiveType::F16), f64::INFINITY);
+    assert_eq!(round_to_type(1.0 / 3.0, &PrimitiveType::BF16), 0.333984375);
+    assert_eq!(round_to_type(pow2(-24), &PrimitiveType::F16), pow2(-24));
+    assert_eq!(round_to_type(500.0, &PrimitiveType::F8E4M3FN).is_nan(), true);
+    assert_eq!(round_to_type(448.0, &PrimitiveType::F8E4M3FN), 448.0);
+    assert_eq!(max_finite_value(&PrimitiveType::F8E5M2), 57344.0);
+    assert_eq!(max_finite_value(&PrimitiveType::F8E4M3FNUZ), 240.0);
+  }
+
+  #[test]
+  fn test_float_bits() {
+    assert_eq!(float_to_bits(1.0, &PrimitiveType::F16), 0x3c00);
+    assert_eq!(float_to_bits(-2.0, &PrimitiveType::BF16), 0xc000);
+    assert_eq!(float_to_bits(pow2(-24), &PrimitiveType::F16), 1);
+    assert_eq!(float_to_bits(448.0, &PrimitiveType::F8E4M3FN), 0x7e);
+    assert_eq!(float_to_bits(f64::NAN, &PrimitiveType::F8E4M3FNUZ), 0x80);
+    assert_eq!(float_to_bits(f64::NEG_INFINITY, &PrimitiveType::F8E5M2), 0xfc);
+    assert_eq!(float_from_bits(0x7bff, &PrimitiveType::F16), 65504.0);
+    assert_eq!(float_from_bits(0x7f, &PrimitiveType::F8E4M3FN).is_nan(), true);
+    assert_eq!(float_from_bits(0x7f, &PrimitiveType::F8E4M3FNUZ), 240.0);
+    for t in [PrimitiveType::F16, PrimitiveType::F8E5M2, PrimitiveType::F8E4M3FN,
+      PrimitiveType::F8E4M3B11FNUZ, PrimitiveType::F8E5M2FNUZ, PrimitiveType::F8E4M3FNUZ]
+    {
+      for bits in 0..(1_u64 << bit_width(&t)) {
+        let value = float_from_bits(bits, &t);
+        if !value.is_nan() {
+          assert_eq!(float_to_bits(value, &t), bits, "{:?} {:x}", t, bits);
+        }
+      }
+    }
+  }
+}
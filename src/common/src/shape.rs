@@ -33,17 +33,11 @@ impl Shape {
   }
 
   pub fn new_from(shape: &Shape) -> Self {
-    let mut dimensions = Vec::new();
-    dimensions.clone_from_slice(&shape.dimensions);
-    let mut dynamic_dimensions = Vec::new();
-    dynamic_dimensions.clone_from_slice(&shape.dynamic_dimensions);
-    let mut tuple_shapes = Vec::new();
-    tuple_shapes.clone_from_slice(&shape.tuple_shapes);
     Shape {
       element_type: shape.element_type.clone(),
-      dimensions: dimensions,
-      dynamic_dimensions: dynamic_dimensions,
-      tuple_shapes: tuple_shapes,
+      dimensions: shape.dimensions.clone(),
+      dynamic_dimensions: shape.dynamic_dimensions.clone(),
+      tuple_shapes: shape.tuple_shapes.clone(),
       layout: shape.layout.clone(),
     }
   }
@@ -363,6 +357,7 @@ impl ShapeEqual {
           let l = l_iter.next();
           let r = r_iter.next();
           if l.is_none() && r.is_none() { return true; }
+          if l.is_none() || r.is_none() { return false; }
           if !self.equal(l.as_ref().unwrap(), r.as_ref().unwrap()) {
             return false;
           }
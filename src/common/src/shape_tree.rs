@@ -85,6 +85,21 @@ pub struct Node<T> {
   value: T
 }
 
+impl<T> Node<T> {
+  // Returns the shape index of this node.
+  pub fn index(&self) -> &Vec<i64> {
+    &self.index
+  }
+
+  pub fn value(&self) -> &T {
+    &self.value
+  }
+
+  pub fn mutable_value(&mut self) -> &mut T {
+    &mut self.value
+  }
+}
+
 // A ShapeTree<T> is a recursive data structure which mirrors the structure of a
 // Blitz shape and holds a value of type T for each subshape (i.e. tuple or array)
 // in the shape. For array shapes, a ShapeTree trivially holds a single value of
@@ -0,0 +1,429 @@
+#![allow(dead_code)]
+
+use std::{fs::File, io::Read};
+
+use num::complex::Complex64;
+
+use crate::{
+  blitz_data::PrimitiveType,
+  dyn_literal::DynLiteral,
+  layout_util::LayoutUtil,
+  primitive_util::{
+    is_complex_type, is_floating_point_type, is_integral_type, string_to_primitive_type
+  },
+  shape::Shape,
+  shape_util::ShapeUtil
+};
+
+// Reads a literal from a file in the text format produced by
+// Literal::to_string(), e.g.
+//
+//   f32[2,3] {
+//     { 1, 2, 3 },
+//     { 4, 5, 6 }
+//   }
+//
+// The shape may carry a layout ("f32[2,3]{0,1}") and, for dynamic shapes, the
+// dynamic sizes ("f32[<=3](2) {1, 2}"). Tuples are written as a parenthesized,
+// comma-separated list of literals.
+pub struct TextLiteralReader {
+  file: File
+}
+
+impl TextLiteralReader {
+  // Ownership of file is passed to this instance -- this instance takes
+  // responsibility for closing it.
+  pub fn new(file: File) -> Self {
+    TextLiteralReader { file: file }
+  }
+
+  // Reads the literal stored in the file at 'path'.
+  pub fn read_path(path: &str) -> Result<DynLiteral, String> {
+    let file = File::open(path)
+      .map_err(|e| format!("Could not open {}: {}.", path, e))?;
+    let mut reader = TextLiteralReader::new(file);
+    reader.read_all_lines()
+  }
+
+  // Parses a literal from its text form.
+  pub fn parse(text: &str) -> Result<DynLiteral, String> {
+    let mut parser = LiteralTextParser::new(text);
+    let literal = parser.parse_literal()?;
+    parser.skip_whitespace();
+    if !parser.at_end() {
+      return Err(parser.error("Unexpected trailing characters"));
+    }
+    Ok(literal)
+  }
+
+  // Reads the whole file and parses it as a single literal.
+  pub fn read_all_lines(&mut self) -> Result<DynLiteral, String> {
+    let mut text = String::new();
+    self.file.read_to_string(&mut text).map_err(|e| e.to_string())?;
+    TextLiteralReader::parse(&text)
+  }
+}
+
+struct LiteralTextParser {
+  chars: Vec<char>,
+  pos: usize
+}
+
+impl LiteralTextParser {
+  fn new(text: &str) -> Self {
+    LiteralTextParser { chars: text.chars().collect(), pos: 0 }
+  }
+
+  fn at_end(&self) -> bool {
+    self.pos >= self.chars.len()
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.pos).copied()
+  }
+
+  fn looking_at(&self, s: &str) -> bool {
+    s.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+  }
+
+  fn error(&self, message: &str) -> String {
+    let line = self.chars[..self.pos.min(self.chars.len())]
+      .iter().filter(|c| **c == '\n').count() + 1;
+    format!("{} at line {} (offset {}).", message, line, self.pos)
+  }
+
+  // Skips whitespace and /*...*/ comments, such as the index annotations
+  // printed for arrays of rank greater than 3.
+  fn skip_whitespace(&mut self) {
+    loop {
+      while self.peek().is_some_and(|c| c.is_whitespace()) {
+        self.pos += 1;
+      }
+      if self.looking_at("/*") {
+        self.pos += 2;
+        while !self.at_end() && !self.looking_at("*/") {
+          self.pos += 1;
+        }
+        self.pos = (self.pos + 2).min(self.chars.len());
+        continue;
+      }
+      return;
+    }
+  }
+
+  fn expect(&mut self, c: char) -> Result<(), String> {
+    self.skip_whitespace();
+    if self.peek() != Some(c) {
+      return Err(self.error(&format!("Expected '{}'", c)));
+    }
+    self.pos += 1;
+    Ok(())
+  }
+
+  // Consumes 'c' if it is the next character, without skipping whitespace.
+  fn consume(&mut self, c: char) -> bool {
+    if self.peek() == Some(c) {
+      self.pos += 1;
+      return true;
+    }
+    false
+  }
+
+  // Returns the next run of characters up to a delimiter.
+  fn token(&mut self) -> String {
+    let start = self.pos;
+    while self.peek().is_some_and(|c| !c.is_whitespace() && !",{}()[]".contains(c)) {
+      self.pos += 1;
+    }
+    self.chars[start..self.pos].iter().collect()
+  }
+
+  fn parse_int(&mut self) -> Result<i64, String> {
+    self.skip_whitespace();
+    let token = self.token();
+    token.parse::<i64>().map_err(|_| self.error(&format!("Expected an integer, got '{}'", token)))
+  }
+
+  // Parses a comma-separated list of integers up to 'close'.
+  fn parse_int_list(&mut self, close: char) -> Result<Vec<i64>, String> {
+    let mut values = vec![];
+    self.skip_whitespace();
+    if self.consume(close) {
+      return Ok(values);
+    }
+    loop {
+      values.push(self.parse_int()?);
+      self.skip_whitespace();
+      if self.consume(close) {
+        return Ok(values);
+      }
+      self.expect(',')?;
+    }
+  }
+
+  fn parse_literal(&mut self) -> Result<DynLiteral, String> {
+    self.skip_whitespace();
+    if self.consume('(') {
+      let mut elements = vec![];
+      self.skip_whitespace();
+      if !self.consume(')') {
+        loop {
+          elements.push(self.parse_literal()?);
+          self.skip_whitespace();
+          if self.consume(')') {
+            break;
+          }
+          self.expect(',')?;
+        }
+      }
+      return Ok(DynLiteral::make_tuple(elements));
+    }
+
+    let (shape, dynamic_sizes) = self.parse_shape()?;
+    let mut literal = DynLiteral::new(&shape);
+    let mut sizes = shape.dimensions_vec().clone();
+    if let Some(dynamic_sizes) = dynamic_sizes {
+      for (dim, size) in dynamic_sizes.iter().enumerate() {
+        if *size < 0 || *size > shape.dimensions(dim) {
+          return Err(self.error(&format!(
+            "Dynamic size {} is out of bounds for dimension {}", size, dim)));
+        }
+        if !shape.is_dynamic_dimension(dim as i64) && *size != shape.dimensions(dim) {
+          return Err(self.error(&format!(
+            "Dimension {} is static but has dynamic size {}", dim, size)));
+        }
+        literal.set_dynamic_size(dim, &vec![], *size);
+      }
+      sizes = dynamic_sizes;
+    }
+    if shape.rank() == 0 {
+      self.parse_scalar(&mut literal, &vec![])?;
+    } else {
+      self.parse_array(&mut literal, &sizes, &mut vec![])?;
+    }
+    Ok(literal)
+  }
+
+  // Parses an array shape such as "f32[<=3,2]{0,1}(2,2)". Returns the shape
+  // and the dynamic sizes, if given.
+  fn parse_shape(&mut self) -> Result<(Shape, Option<Vec<i64>>), String> {
+    self.skip_whitespace();
+    let name = self.token();
+    let element_type = match string_to_primitive_type(&name) {
+      Some(t) if *t != PrimitiveType::Tuple && *t != PrimitiveType::Token &&
+        *t != PrimitiveType::OpaqueType => t.clone(),
+      _ => return Err(self.error(&format!("Unknown element type '{}'", name)))
+    };
+    if !self.consume('[') {
+      return Err(self.error("Expected '['"));
+    }
+    let mut dimensions = vec![];
+    let mut dynamic_dimensions = vec![];
+    self.skip_whitespace();
+    if !self.consume(']') {
+      loop {
+        self.skip_whitespace();
+        if self.peek() == Some('?') {
+          return Err(self.error("Unbounded dimensions are not supported"));
+        }
+        let is_dynamic = self.consume('<');
+        if is_dynamic && !self.consume('=') {
+          return Err(self.error("Expected '<='"));
+        }
+        dimensions.push(self.parse_int()?);
+        dynamic_dimensions.push(is_dynamic);
+        self.skip_whitespace();
+        if self.consume(']') {
+          break;
+        }
+        self.expect(',')?;
+      }
+    }
+    if dimensions.iter().any(|d| *d < 0) {
+      return Err(self.error("Dimensions must not be negative"));
+    }
+    let mut shape =
+      ShapeUtil::make_shape_dynamic(&element_type, dimensions, dynamic_dimensions);
+
+    // A layout directly follows the dimensions.
+    if self.consume('{') {
+      let minor_to_major = self.parse_int_list('}')?;
+      let mut sorted = minor_to_major.clone();
+      sorted.sort();
+      if sorted != (0..shape.rank() as i64).collect::<Vec<i64>>() {
+        return Err(self.error(&format!(
+          "Layout {:?} is not a permutation of the dimensions", minor_to_major)));
+      }
+      shape.set_layout(LayoutUtil::make_layout(&minor_to_major, vec![], vec![], vec![],
+        vec![], 1, PrimitiveType::Invalid, PrimitiveType::Invalid, 0, 0, None, 0));
+    }
+
+    // As do the dynamic sizes of a dynamic shape.
+    let mut dynamic_sizes = None;
+    if self.consume('(') {
+      let sizes = self.parse_int_list(')')?;
+      if sizes.len() != shape.rank() {
+        return Err(self.error(&format!(
+          "Expected {} dynamic sizes, got {}", shape.rank(), sizes.len())));
+      }
+      dynamic_sizes = Some(sizes);
+    }
+    Ok((shape, dynamic_sizes))
+  }
+
+  // Parses the nested braces of the array, where 'sizes' are the (dynamic)
+  // sizes of the dimensions.
+  fn parse_array(
+    &mut self,
+    literal: &mut DynLiteral,
+    sizes: &Vec<i64>,
+    index: &mut Vec<i64>) -> Result<(), String>
+  {
+    self.expect('{')?;
+    let dim = index.len();
+    for i in 0..sizes[dim] {
+      if i > 0 {
+        self.expect(',')?;
+      }
+      index.push(i);
+      if dim + 1 == sizes.len() {
+        self.parse_scalar(literal, index)?;
+      } else {
+        self.parse_array(literal, sizes, index)?;
+      }
+      index.pop();
+    }
+    self.skip_whitespace();
+    if self.peek() == Some(',') {
+      return Err(self.error(&format!(
+        "Too many elements in dimension {}, expected {}", dim, sizes[dim])));
+    }
+    self.expect('}')
+  }
+
+  fn parse_scalar(&mut self, literal: &mut DynLiteral, index: &Vec<i64>) -> Result<(), String> {
+    let element_type = literal.shape().element_type();
+    self.skip_whitespace();
+    if is_complex_type(&element_type) {
+      self.expect('(')?;
+      let re = self.parse_real()?;
+      self.expect(',')?;
+      let im = self.parse_real()?;
+      self.expect(')')?;
+      literal.set_from_complex128(index, &vec![], Complex64::new(re, im));
+      return Ok(());
+    }
+
+    let start = self.pos;
+    let token = self.token();
+    if token.is_empty() {
+      return Err(self.error("Expected a value"));
+    }
+    if element_type == PrimitiveType::Pred {
+      let value = match token.as_str() {
+        "true" | "1" => true,
+        "false" | "0" => false,
+        _ => return Err(self.error(&format!("Invalid pred value '{}'", token)))
+      };
+      literal.set(index, &vec![], value);
+    } else if is_integral_type(&element_type) {
+      let value = token.parse::<i128>()
+        .map_err(|_| self.error(&format!("Invalid integer value '{}'", token)))?;
+      literal.set_from_integer(index, &vec![], value);
+    } else if is_floating_point_type(&element_type) {
+      self.pos = start;
+      let value = self.parse_real()?;
+      literal.set_from_double(index, &vec![], value);
+    } else {
+      return Err(self.error("Unsupported element type"));
+    }
+    Ok(())
+  }
+
+  fn parse_real(&mut self) -> Result<f64, String> {
+    self.skip_whitespace();
+    let token = self.token();
+    let value = match token.as_str() {
+      "nan" | "+nan" => f64::NAN,
+      "-nan" => -f64::NAN,
+      "inf" | "+inf" => f64::INFINITY,
+      "-inf" => f64::NEG_INFINITY,
+      _ => token.parse::<f64>()
+        .map_err(|_| self.error(&format!("Invalid floating point value '{}'", token)))?
+    };
+    Ok(value)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Write;
+  use crate::literal_util::LiteralUtil;
+
+  #[test]
+  fn test_parse_r2() {
+    let literal = TextLiteralReader::parse(
+      "f32[2,3] {\n  { 1, 2.5, -3 },\n  { 4e2, inf, nan }\n}").unwrap();
+    assert_eq!(literal.shape().element_type(), PrimitiveType::F32);
+    assert_eq!(literal.shape().dimensions_vec(), &vec![2, 3]);
+    assert_eq!(literal.get::<f32>(&vec![0, 1], &vec![]), 2.5);
+    assert_eq!(literal.get::<f32>(&vec![0, 2], &vec![]), -3.0);
+    assert_eq!(literal.get::<f32>(&vec![1, 0], &vec![]), 400.0);
+    assert_eq!(literal.get::<f32>(&vec![1, 1], &vec![]), f32::INFINITY);
+    assert!(literal.get::<f32>(&vec![1, 2], &vec![]).is_nan());
+  }
+
+  #[test]
+  fn test_parse_scalars_and_tuples() {
+    let literal = TextLiteralReader::parse(
+      "(\ns32[] -7,\npred[3] {true, 0, 1},\nc64[1] {(1.5, -2)}\n)").unwrap();
+    assert!(literal.shape().is_tuple());
+    assert_eq!(literal.get::<i32>(&vec![], &vec![0]), -7);
+    assert!(literal.get::<bool>(&vec![0], &vec![1]));
+    assert!(!literal.get::<bool>(&vec![1], &vec![1]));
+    assert_eq!(literal.get_as_complex128(&vec![0], &vec![2]), Complex64::new(1.5, -2.0));
+
+    let literal = TextLiteralReader::parse("s64[] 9007199254740993").unwrap();
+    assert_eq!(literal.get::<i64>(&vec![], &vec![]), 9007199254740993);
+  }
+
+  #[test]
+  fn test_parse_layout_and_dynamic_sizes() {
+    let literal = TextLiteralReader::parse("f64[2,2]{0,1} { { 1, 2 }, { 3, 4 } }").unwrap();
+    assert_eq!(literal.shape().layout().as_ref().unwrap().minor_to_major_vec(), &vec![0, 1]);
+    assert_eq!(literal.get::<f64>(&vec![0, 1], &vec![]), 2.0);
+    assert_eq!(literal.data::<f64>(&vec![]), vec![1.0, 3.0, 2.0, 4.0]);
+
+    let literal = TextLiteralReader::parse("s32[<=4](2) {5, 6}").unwrap();
+    assert!(literal.shape().is_dynamic_dimension(0));
+    assert_eq!(literal.get_dynamic_size(0, &vec![]), 2);
+    assert_eq!(literal.get::<i32>(&vec![1], &vec![]), 6);
+
+    let literal = TextLiteralReader::parse(
+      "u8[1,1,1,1] {\n{ /*i0=0*/\n{ /*i1=0*/\n{ 3 }\n}\n}\n}").unwrap();
+    assert_eq!(literal.get::<u8>(&vec![0, 0, 0, 0], &vec![]), 3);
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    assert!(TextLiteralReader::parse("f32[2] {1}").is_err());
+    assert!(TextLiteralReader::parse("f32[2] {1, 2, 3}").is_err());
+    assert!(TextLiteralReader::parse("q32[2] {1, 2}").is_err());
+    assert!(TextLiteralReader::parse("s32[1] {1.5}").is_err());
+    assert!(TextLiteralReader::parse("f32[2]{0,0} {1, 2}").is_err());
+    assert!(TextLiteralReader::parse("f32[] 1 2").is_err());
+  }
+
+  #[test]
+  fn test_read_path() {
+    let literal = LiteralUtil::create_r2(&vec![vec![1, 2, 3], vec![4, 5, 6]]);
+    let path = std::env::temp_dir().join("text_literal_reader_read_path.txt");
+    let mut file = File::create(&path).unwrap();
+    file.write_all(literal.to_string().as_bytes()).unwrap();
+
+    let result = TextLiteralReader::read_path(path.to_str().unwrap()).unwrap();
+    assert_eq!(result.shape().dimensions_vec(), &vec![2, 3]);
+    assert_eq!(result.data::<i32>(&vec![]), vec![1, 2, 3, 4, 5, 6]);
+  }
+}
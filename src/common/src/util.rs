@@ -28,6 +28,24 @@ pub fn product(xs: &Vec<i64>) -> i64 {
 //
 // Note: even different amounts of leading whitespace on different lines will be
 // uniformly replaced with "indentation".
+// Escapes quotes, backslashes and control characters so that the string can
+// be embedded in a double-quoted HLO attribute.
+pub fn c_escape(s: &String) -> String {
+  let mut result = String::new();
+  for c in s.chars() {
+    match c {
+      '\n' => result.push_str("\\n"),
+      '\r' => result.push_str("\\r"),
+      '\t' => result.push_str("\\t"),
+      '\"' => result.push_str("\\\""),
+      '\'' => result.push_str("\\'"),
+      '\\' => result.push_str("\\\\"),
+      _ => result.push(c)
+    }
+  }
+  result
+}
+
 pub fn reindent(_original: &String, _indentation: &String) -> String {
   "".to_string()
 }
@@ -101,6 +119,32 @@ pub fn human_readable_num_flops(flops: f64, nanoseconds: f64) -> String {
   human_readable_num_ops(flops, nanoseconds, "FL".to_string())
 }
 
+// Converts a number of bytes into a human readable string, using binary
+// prefixes; e.g. HumanReadableNumBytes(1536) => 1.5KiB.
+pub fn human_readable_num_bytes(num_bytes: i64) -> String {
+  if num_bytes == i64::MIN {
+    // Special case for number with not representable negation.
+    return "-8E".to_string();
+  }
+  let neg_str = if num_bytes < 0 { "-" } else { "" };
+  let mut num_bytes = num_bytes.abs();
+  if num_bytes < 1024 {
+    // No fractions for bytes.
+    return format!("{}{}B", neg_str, num_bytes);
+  }
+  let units = ['K', 'M', 'G', 'T', 'P', 'E'];
+  let mut unit = 0;
+  while num_bytes >= 1024 * 1024 {
+    num_bytes /= 1024;
+    unit += 1;
+  }
+  if unit == 0 {
+    format!("{}{:.1}{}iB", neg_str, num_bytes as f64 / 1024.0, units[unit])
+  } else {
+    format!("{}{:.2}{}iB", neg_str, num_bytes as f64 / 1024.0, units[unit])
+  }
+}
+
 // Return ceiling(log2(n)) for positive integer n.  panic iff n == 0.
 pub fn log_2_ceiling(x: usize) -> u32 {
   x.ilog2()
@@ -132,6 +176,15 @@ mod tests {
     assert_eq!(vector_string(&int_vector), "(1, 57, 2)".to_string());
   }
 
+  #[test]
+  fn test_human_readable_num_bytes() {
+    assert_eq!(human_readable_num_bytes(0), "0B".to_string());
+    assert_eq!(human_readable_num_bytes(1023), "1023B".to_string());
+    assert_eq!(human_readable_num_bytes(1536), "1.5KiB".to_string());
+    assert_eq!(human_readable_num_bytes(-1536), "-1.5KiB".to_string());
+    assert_eq!(human_readable_num_bytes(3 * 1024 * 1024), "3.00MiB".to_string());
+  }
+
   #[test]
   fn test_sanitize_file_name() {
     assert_eq!(sanitize_filename(""), "".to_string());
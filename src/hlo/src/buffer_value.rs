@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+use common::shape::Shape;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct BufferValue {
   id: i64,
@@ -18,6 +20,16 @@ impl BufferValue {
     }
   }
 
+  // Creates the buffer value 'id' holding a value of 'shape'.
+  pub fn new_with_shape(id: i64, shape: &Shape) -> Self {
+    BufferValue {
+      id: id,
+      is_array: shape.is_array(),
+      is_tuple: shape.is_tuple(),
+      color: -1
+    }
+  }
+
   pub fn id(&self) -> i64 {
     self.id
   }
@@ -127,7 +127,7 @@ impl CollectiveDeviceList {
   }
 }
 
-fn replica_groups_to_string(replica_groups: &Vec<ReplicaGroup>) -> String {
+pub fn replica_groups_to_string(replica_groups: &Vec<ReplicaGroup>) -> String {
   let mut replica_g_str = vec![];
   for group in replica_groups {
     let mut str = "{".to_string();
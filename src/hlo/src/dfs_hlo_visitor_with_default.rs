@@ -2,8 +2,8 @@
 
 use std::collections::HashSet;
 use crate::{
-  dfs_hlo_visitor::{DfsHloVisitorBase, VisitState}, hlo_instruction::HloInstruction,
-  hlo_module::HloModule
+  dfs_hlo_visitor::{DfsHloVisitorBase, VisitState}, hlo_computation::HloComputation,
+  hlo_instruction::{HloInstruction, InstructionId}, hlo_module::HloModule
 };
 
 // DfsHloVisitor with default action based on the HloInstruction being visited.
@@ -371,7 +371,7 @@ impl DfsHloRewriteVisitor {
     execution_threads: &HashSet<String>) -> Result<bool, String>
   {
     for comp in module.make_nonfusion_computations(execution_threads) {
-      let status = comp.accept_rewrite_visitor(&self);
+      let status = module.computation(comp).accept_rewrite_visitor(&self);
       if status.is_err() { return Err(status.err().unwrap()); }
     }
     Ok(self.changed())
@@ -391,11 +391,12 @@ impl DfsHloRewriteVisitor {
   // Returns the absl::Status representing the result of the replace operation.
   pub fn replace_with_new_instruction(
     &mut self,
-    old: &HloInstruction,
-    new: &HloInstruction) -> Result<(), String>
+    computation: &mut HloComputation,
+    old: InstructionId,
+    new: HloInstruction) -> Result<InstructionId, String>
   {
     let status =
-      old.parent().replace_with_new_instruction(old, new);
+      computation.replace_with_new_instruction(old, new);
     if status.is_ok() {
       self.changed = true;
     }
@@ -407,16 +408,15 @@ impl DfsHloRewriteVisitor {
   // Returns the absl::Status representing the result of the replace operation.
   pub fn replace_instruction(
     &mut self,
-    old: &HloInstruction,
-    new: &HloInstruction,
+    computation: &mut HloComputation,
+    old: InstructionId,
+    new: InstructionId,
     preserve_sharding: bool) -> Result<bool, String>
   {
-    let changed_or = old.parent().replace_instruction(
-      old, new, preserve_sharding, true, false);
-    if changed_or.is_ok() {
-      self.changed |= changed_or.as_ref().unwrap();
-    }
-    Ok(*changed_or.as_ref().unwrap())
+    let changed = computation.replace_instruction(
+      old, new, preserve_sharding, true, false)?;
+    self.changed |= changed;
+    Ok(changed)
   }
 
   // Mark the computation as having changed.
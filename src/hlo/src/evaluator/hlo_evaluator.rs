@@ -1,57 +1,221 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use std::{collections::HashMap, rc::Rc};
 
-use common::literal::Literal;
+use common::{
+  blitz_data::{PrimitiveType, RandomDistribution, Transpose, Window},
+  comparison_util::{ComparisonDirection, ComparisonType},
+  dyn_literal::{DynLiteral, Element},
+  literal::{ArrayValueState, Literal},
+  primitive_util::{bit_width, is_floating_point_type, is_integral_type},
+  shape::Shape,
+  shape_util::ShapeUtil
+};
 
-use crate::{hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_module::HloModule};
+use crate::{
+  evaluator::hlo_evaluator_typed_visitor::{
+    as_f64, as_integer, binary_op, bit_pattern, bool_element, clamp, compare_values,
+    convert_value, element_from_f64, from_bit_pattern, is_true, multiply_add, normalize,
+    reduce_precision, total_order, unary_op, zero_element, RandomEngine
+  },
+  hlo_computation::HloComputation,
+  hlo_instruction::{literal_shape, HloInstruction, InstructionId},
+  hlo_module::{ComputationId, HloModule},
+  hlo_opcode::{hlo_opcode_string, HloOpcode}
+};
+
+// Computes the result of a custom-call from the literals of its operands,
+// which hold their elements exactly in their own element type.
+pub type CustomCallHandler =
+  Rc<dyn Fn(&HloInstruction, &Vec<DynLiteral>) -> Result<DynLiteral, String>>;
+
+// The value of an instruction while it is evaluated: the elements of an array
+// in row-major order, or the elements of a tuple. Tokens are empty tuples.
+// Partial evaluation marks values that do not only depend on constants, such
+// as parameters, as unknown.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+  Array(Vec<Element>),
+  Tuple(Vec<Value>),
+  Unknown
+}
+
+impl Value {
+  fn is_known(&self) -> bool {
+    match self {
+      Value::Array(_) => true,
+      Value::Tuple(elements) => elements.iter().all(|v| v.is_known()),
+      Value::Unknown => false
+    }
+  }
+
+  fn array(&self) -> &Vec<Element> {
+    match self {
+      Value::Array(data) => data,
+      _ => panic!("Expected an array value.")
+    }
+  }
+
+  fn elements(&self) -> &Vec<Value> {
+    match self {
+      Value::Tuple(elements) => elements,
+      _ => panic!("Expected a tuple value.")
+    }
+  }
+
+  fn scalar(&self) -> Element {
+    self.array()[0]
+  }
+}
+
+// A computation applied to scalars, such as the reducer of a reduce. With the
+// fast path enabled, a computation that applies a single binary op to its two
+// parameters is applied without interpreting it.
+struct ScalarComputation {
+  id: ComputationId,
+  binary_op: Option<(HloOpcode, PrimitiveType)>
+}
 
 // Responsible for evaluating HLO and obtain literal as the evaluation results.
+// Array elements are held exactly for every element type, as Elements whose
+// semantics the element type of the shape of each instruction decides. Only
+// the Literal<f64> interfaces, like constants, go through f64; the DynLiteral
+// interfaces keep S64, U64 and complex elements exact.
 // This class is not thread-safe.
-pub struct HloEvaluator<T> where T: Clone + Default + PartialEq + 'static {
-  arg_literals: Vec<Literal<T>>,
+pub struct HloEvaluator {
   max_loop_iterations: i64,
-  seed: u64
+  seed: u64,
+  engine: RandomEngine,
+  use_fast_path: bool,
+  custom_call_handler: Option<CustomCallHandler>,
+  // Whether values that are not constant become unknown instead of failing
+  // the evaluation.
+  partial: bool,
+  // The values partial evaluation computed so far, per computation of
+  // 'evaluated_module'.
+  evaluated: HashMap<ComputationId, HashMap<InstructionId, Value>>,
+  // The unique id and generation of the module 'evaluated' belongs to.
+  evaluated_module: Option<(i64, i64)>
 }
 
-impl<T> HloEvaluator<T> where T: Clone + Default + PartialEq + 'static {
+impl HloEvaluator {
   pub fn default() -> Self {
-    HloEvaluator {
-      arg_literals: Vec::new(),
-      max_loop_iterations: 0,
-      seed: 0
-    }    
+    HloEvaluator::new(-1)
   }
 
   // Only evaluate up to max_loop_iterations per while-loop execution if
-  // specified.
+  // specified. A negative value means no limit.
   pub fn new(max_loop_iterations: i64) -> Self {
     HloEvaluator {
-      arg_literals: Vec::new(),
       max_loop_iterations: max_loop_iterations,
-      seed: 0
+      seed: 0,
+      engine: RandomEngine::new(0),
+      use_fast_path: false,
+      custom_call_handler: None,
+      partial: false,
+      evaluated: HashMap::new(),
+      evaluated_module: None
     }
   }
 
-  pub fn create_embedded() {}
+  // Returns a new evaluator with the same configuration, for evaluating
+  // embedded computations.
+  pub fn create_embedded(&self, max_loop_iterations: i64) -> HloEvaluator {
+    let mut evaluator = HloEvaluator::new(max_loop_iterations);
+    evaluator.seed = self.seed;
+    evaluator.use_fast_path = self.use_fast_path;
+    evaluator.custom_call_handler = self.custom_call_handler.clone();
+    evaluator
+  }
+
   pub fn on_evaluate_computation() {}
 
+  pub fn max_loop_iterations(&self) -> i64 {
+    self.max_loop_iterations
+  }
+
+  pub fn seed(&self) -> u64 {
+    self.seed
+  }
+
+  // Sets the seed used for rng instructions of modules that have no seed of
+  // their own.
+  pub fn set_seed(&mut self, seed: u64) {
+    self.seed = seed;
+  }
+
   // Gets the value of running a single HLO instruction.
   //
   // This function may recursively evaluate the dependency of this instruction
   // within its parent computation until it encounters something that cannot be
   // evaluated, such as an Infeed or a Parameter instruction.
   // It makes best effort to partially evaluate a dependency if possible.
+  //
+  // The parts of the result that are not constant are unknown arrays of the
+  // literal, see Literal::is_known(). The values computed along the way are
+  // kept and reused by later calls for the same module, as long as the module
+  // is not changed in between, or until reset_visit_states() is called.
   pub fn evaluate(
-    &self,
-    _instruction: &HloInstruction,
-    _recursively_evaluate_nonconstant_operands: bool) -> Result<Literal<T>, String>
+    &mut self,
+    module: &HloModule,
+    instruction: &HloInstruction,
+    recursively_evaluate_nonconstant_operands: bool) -> Result<Literal<f64>, String>
   {
-    unimplemented!()
+    let computation = parent_computation(module, instruction)?;
+    if !recursively_evaluate_nonconstant_operands {
+      if instruction.operands().iter().any(|id| !computation.instruction(*id).is_constant()) {
+        return Err("Not all operands are constants.".to_string());
+      }
+      self.reseed(module);
+      let mut values = HashMap::new();
+      let value =
+        self.evaluate_in(module, computation, instruction.id(), &vec![], &mut values)?;
+      return value_to_literal(instruction.shape(), &value);
+    }
+
+    let module_key = (module.unique_id(), module.generation());
+    if self.evaluated_module != Some(module_key) {
+      self.reset_visit_states();
+      self.evaluated_module = Some(module_key);
+    }
+    self.reseed(module);
+    let computation_id = instruction.parent().unwrap();
+    let mut values = self.evaluated.remove(&computation_id).unwrap_or_default();
+    self.partial = true;
+    let result = self.evaluate_in(module, computation, instruction.id(), &vec![], &mut values);
+    self.partial = false;
+    self.evaluated.insert(computation_id, values);
+    value_to_literal(instruction.shape(), &result?)
+  }
+
+  // Forgets the values that partial evaluation computed.
+  pub fn reset_visit_states(&mut self) {
+    self.evaluated.clear();
+    self.evaluated_module = None;
   }
 
-  pub fn evaluate_module(&self, _module: &HloModule) -> Result<Literal<T>, String> {
-    unimplemented!()
+  pub fn evaluate_module(
+    &mut self,
+    module: &HloModule,
+    arg_literals: &Vec<Literal<f64>>) -> Result<Literal<f64>, String>
+  {
+    match module.entry_computation() {
+      Some(computation) => self.evaluate_computation(module, computation, arg_literals),
+      None => Err(format!("Module {} has no entry computation.", module.name()))
+    }
+  }
+
+  // Same as evaluate_module(), but with literals that hold the elements of
+  // every type exactly.
+  pub fn evaluate_module_dyn(
+    &mut self,
+    module: &HloModule,
+    arg_literals: &Vec<DynLiteral>) -> Result<DynLiteral, String>
+  {
+    match module.entry_computation() {
+      Some(computation) => self.evaluate_computation_dyn(module, computation, arg_literals),
+      None => Err(format!("Module {} has no entry computation.", module.name()))
+    }
   }
 
   // Evaluates an HLO computation and an array of pointers to literals.
@@ -70,18 +234,51 @@ impl<T> HloEvaluator<T> where T: Clone + Default + PartialEq + 'static {
   // where Parameter0 has parameter_number 0 and Parameter1 has parameter_number
   // 1 in this computation. The input literals array will then have its first
   // literal map to Parameter0 and the second map to Parameter1.
-  //
-  // (Dummy template arg is to reduce the overloading priority of one overload
-  // so that Evaluate(module, {}) resolves unambiguously.)
   pub fn evaluate_computation(
-    &self,
-    _computation: &HloComputation,
-    _arg_literals: &Vec<Literal<T>>) -> Result<Literal<T>, String>
+    &mut self,
+    module: &HloModule,
+    computation: &HloComputation,
+    arg_literals: &Vec<Literal<f64>>) -> Result<Literal<f64>, String>
   {
-    unimplemented!()    
+    check_argument_count(computation, arg_literals.len())?;
+    let mut args = Vec::with_capacity(arg_literals.len());
+    for (i, literal) in arg_literals.iter().enumerate() {
+      let parameter = computation.parameter_instruction(i).unwrap();
+      args.push(value_from_literal(parameter.shape(), literal)?);
+    }
+    self.reseed(module);
+    let value = self.run_computation(module, computation, &args)?;
+    value_to_literal(computation.root_instruction().shape(), &value)
   }
 
-  pub fn try_evaluate() {}
+  // Same as evaluate_computation(), but with literals that hold the elements
+  // of every type exactly.
+  pub fn evaluate_computation_dyn(
+    &mut self,
+    module: &HloModule,
+    computation: &HloComputation,
+    arg_literals: &Vec<DynLiteral>) -> Result<DynLiteral, String>
+  {
+    check_argument_count(computation, arg_literals.len())?;
+    let mut args = Vec::with_capacity(arg_literals.len());
+    for (i, literal) in arg_literals.iter().enumerate() {
+      let parameter = computation.parameter_instruction(i).unwrap();
+      args.push(value_from_dyn_literal(parameter.shape(), literal)?);
+    }
+    self.reseed(module);
+    let value = self.run_computation(module, computation, &args)?;
+    Ok(value_to_dyn_literal(computation.root_instruction().shape(), &value))
+  }
+
+  // Same as evaluate(), but returns None instead of the error.
+  pub fn try_evaluate(
+    &mut self,
+    module: &HloModule,
+    instruction: &HloInstruction,
+    recursively_evaluate_nonconstant_operands: bool) -> Option<Literal<f64>>
+  {
+    self.evaluate(module, instruction, recursively_evaluate_nonconstant_operands).ok()
+  }
 
   // Evaluates a single HLO instruction, substituting the given literals for
   // some of the instruction's operands.
@@ -89,22 +286,1980 @@ impl<T> HloEvaluator<T> where T: Clone + Default + PartialEq + 'static {
   // For example, given instruction = op(A, B, C) and the map
   // {A = x, C = y}, this evaluates op(x, B, y).
   pub fn evaluate_with_substitutions(
+    &mut self,
+    module: &HloModule,
+    instruction: &HloInstruction,
+    substitutions: &HashMap<InstructionId, Literal<f64>>) -> Result<Literal<f64>, String>
+  {
+    let computation = parent_computation(module, instruction)?;
+    let mut values = HashMap::new();
+    for operand_id in instruction.operands() {
+      let operand = computation.instruction(*operand_id);
+      match substitutions.get(operand_id) {
+        Some(literal) => {
+          values.insert(*operand_id, value_from_literal(operand.shape(), literal)?);
+        },
+        None => if !operand.is_constant() {
+          return Err(format!("Operand {} is neither a constant nor substituted.",
+            operand.name()));
+        }
+      }
+    }
+    self.reseed(module);
+    let value =
+      self.evaluate_in(module, computation, instruction.id(), &vec![], &mut values)?;
+    value_to_literal(instruction.shape(), &value)
+  }
+
+  // Applies the elementwise binary 'opcode' to literals whose elements are of
+  // type 'element_type'.
+  pub fn evaluate_elementwise_binary_op(
     &self,
-    _instruction: &HloInstruction,
-    _substitutions: HashMap<HloInstruction, Literal<T>>) -> Result<Literal<T>, String>
+    opcode: &HloOpcode,
+    element_type: &PrimitiveType,
+    lhs: &Literal<f64>,
+    rhs: &Literal<f64>) -> Result<Literal<f64>, String>
   {
-    unimplemented!()
+    check_same_dimensions(lhs, rhs)?;
+    let mut result = Vec::with_capacity(lhs.data(&vec![]).len());
+    for (a, b) in lhs.data(&vec![]).iter().zip(rhs.data(&vec![])) {
+      let (a, b) = (element_from_f64(*a, element_type), element_from_f64(*b, element_type));
+      result.push(element_to_f64(binary_op(opcode, a, b, element_type)?)?);
+    }
+    Ok(array_literal(lhs.shape().dimensions_vec(), result))
   }
 
-  pub fn evaluate_elementwise_binary_op() {}
-  pub fn evaluate_elementwise_unary_op() {}
-  pub fn evaluate_elementwise_ternary_op() {}
-  pub fn evaluate_elementwise_compare_op() {}
+  // Applies the elementwise unary 'opcode' to a literal whose elements are of
+  // type 'element_type'.
+  pub fn evaluate_elementwise_unary_op(
+    &self,
+    opcode: &HloOpcode,
+    element_type: &PrimitiveType,
+    operand: &Literal<f64>) -> Result<Literal<f64>, String>
+  {
+    let mut result = Vec::with_capacity(operand.data(&vec![]).len());
+    for value in operand.data(&vec![]) {
+      let value = element_from_f64(*value, element_type);
+      result.push(element_to_f64(unary_op(opcode, value, element_type)?)?);
+    }
+    Ok(array_literal(operand.shape().dimensions_vec(), result))
+  }
+
+  // Applies the elementwise ternary 'opcode', clamp or select, to literals
+  // whose elements are of type 'element_type'.
+  pub fn evaluate_elementwise_ternary_op(
+    &self,
+    opcode: &HloOpcode,
+    element_type: &PrimitiveType,
+    lhs: &Literal<f64>,
+    rhs: &Literal<f64>,
+    ehs: &Literal<f64>) -> Result<Literal<f64>, String>
+  {
+    check_same_dimensions(lhs, rhs)?;
+    check_same_dimensions(lhs, ehs)?;
+    let (a, b, c) = (lhs.data(&vec![]), rhs.data(&vec![]), ehs.data(&vec![]));
+    let element = |value: f64| element_from_f64(value, element_type);
+    let result = match opcode {
+      HloOpcode::Clamp => {
+        let mut result = Vec::with_capacity(b.len());
+        for i in 0..b.len() {
+          let value = clamp(element(a[i]), element(b[i]), element(c[i]));
+          result.push(element_to_f64(normalize(value, element_type))?);
+        }
+        result
+      },
+      HloOpcode::Select =>
+        (0..b.len()).map(|i| if a[i] != 0.0 { b[i] } else { c[i] }).collect(),
+      _ => return Err(format!("{} is not a ternary op.", hlo_opcode_string(opcode)))
+    };
+    Ok(array_literal(lhs.shape().dimensions_vec(), result))
+  }
+
+  // Compares literals elementwise and returns a literal of predicates.
+  pub fn evaluate_elementwise_compare_op(
+    &self,
+    direction: &ComparisonDirection,
+    comparison_type: &ComparisonType,
+    lhs: &Literal<f64>,
+    rhs: &Literal<f64>) -> Result<Literal<f64>, String>
+  {
+    check_same_dimensions(lhs, rhs)?;
+    let result = lhs.data(&vec![]).iter().zip(rhs.data(&vec![]))
+      .map(|(a, b)| {
+        let (a, b) = (Element::Real(*a), Element::Real(*b));
+        if compare_values(direction, comparison_type, a, b) { 1.0 } else { 0.0 }
+      })
+      .collect();
+    Ok(array_literal(lhs.shape().dimensions_vec(), result))
+  }
 
   pub fn set_dynamic_dimension_inference(&mut self) {
-      
+
   }
   pub fn dynamic_dimension_inference() {}
-  pub fn set_use_fast_path() {}
-  pub fn set_cusstom_call_handler() {}
-}
\ No newline at end of file
+
+  // Enables evaluating simple reducers, such as the add computation of a
+  // reduce, without interpreting them.
+  pub fn set_use_fast_path(&mut self, value: bool) {
+    self.use_fast_path = value;
+  }
+
+  // Sets the handler that evaluates custom-call instructions. Without one,
+  // custom-calls fail to evaluate.
+  pub fn set_custom_call_handler(&mut self, handler: CustomCallHandler) {
+    self.custom_call_handler = Some(handler);
+  }
+
+  // Seeds the random engine from the module, or from the evaluator's own seed
+  // if the module has none.
+  fn reseed(&mut self, module: &HloModule) {
+    let seed = if module.config().seed() != 0 { module.config().seed() } else { self.seed };
+    self.engine.seed(seed);
+  }
+
+  fn run_computation(
+    &mut self,
+    module: &HloModule,
+    computation: &HloComputation,
+    args: &[Value]) -> Result<Value, String>
+  {
+    let mut values = HashMap::new();
+    self.evaluate_in(module, computation, computation.root_instruction_id(), args, &mut values)
+  }
+
+  // Evaluates 'root' after the operands it depends on, in post order. Values
+  // that are already in 'values' are not evaluated again.
+  fn evaluate_in(
+    &mut self,
+    module: &HloModule,
+    computation: &HloComputation,
+    root: InstructionId,
+    args: &[Value],
+    values: &mut HashMap<InstructionId, Value>) -> Result<Value, String>
+  {
+    let mut stack = vec![(root, false)];
+    while let Some((id, expanded)) = stack.pop() {
+      if values.contains_key(&id) {
+        continue;
+      }
+      let instruction = computation.instruction(id);
+      if expanded {
+        let value = self.evaluate_instruction(module, computation, instruction, args, values)?;
+        values.insert(id, value);
+        continue;
+      }
+      stack.push((id, true));
+      for operand in instruction.operands().iter().rev() {
+        if !values.contains_key(operand) {
+          stack.push((*operand, false));
+        }
+      }
+    }
+    Ok(values[&root].clone())
+  }
+
+  fn evaluate_instruction(
+    &mut self,
+    module: &HloModule,
+    computation: &HloComputation,
+    instruction: &HloInstruction,
+    args: &[Value],
+    values: &HashMap<InstructionId, Value>) -> Result<Value, String>
+  {
+    let opcode = instruction.opcode();
+    let shape = instruction.shape();
+    let ops: Vec<&Value> = instruction.operands().iter().map(|id| &values[id]).collect();
+    let op_shapes: Vec<&Shape> = instruction.operands().iter()
+      .map(|id| computation.instruction(*id).shape()).collect();
+    if ops.iter().any(|v| !v.is_known()) {
+      if let Some(value) = evaluate_with_unknown_operands(instruction, &op_shapes, &ops) {
+        return Ok(value);
+      }
+    }
+
+    match opcode {
+      HloOpcode::Parameter => {
+        let number = instruction.parameter_number() as usize;
+        match args.get(number) {
+          Some(value) => Ok(value.clone()),
+          None if self.partial => Ok(Value::Unknown),
+          None => Err(format!("Parameter {} of {} has no argument.",
+            number, computation.name()))
+        }
+      },
+      HloOpcode::Constant => value_from_dyn_literal(shape, instruction.literal()),
+      HloOpcode::Abs | HloOpcode::Cbrt | HloOpcode::Ceil | HloOpcode::Clz |
+      HloOpcode::Copy | HloOpcode::Cos | HloOpcode::Erf | HloOpcode::Exp |
+      HloOpcode::Expm1 | HloOpcode::Floor | HloOpcode::Imag | HloOpcode::IsFinite |
+      HloOpcode::Log | HloOpcode::Log1p | HloOpcode::Logistic | HloOpcode::Negate |
+      HloOpcode::Not | HloOpcode::PopulationCount | HloOpcode::Real |
+      HloOpcode::RoundNearestAfz | HloOpcode::RoundNearestEven | HloOpcode::Rsqrt |
+      HloOpcode::Sign | HloOpcode::Sin | HloOpcode::Sqrt | HloOpcode::Tan |
+      HloOpcode::Tanh => {
+        if opcode == HloOpcode::Copy && !shape.is_array() {
+          return Ok(ops[0].clone());
+        }
+        let t = op_shapes[0].element_type();
+        let mut result = Vec::with_capacity(ops[0].array().len());
+        for value in ops[0].array() {
+          result.push(unary_op(&opcode, *value, &t)?);
+        }
+        Ok(Value::Array(result))
+      },
+      _ if is_elementwise_binary(&opcode) => {
+        let t = op_shapes[0].element_type();
+        let mut result = Vec::with_capacity(ops[0].array().len());
+        for (a, b) in ops[0].array().iter().zip(ops[1].array()) {
+          result.push(binary_op(&opcode, *a, *b, &t)?);
+        }
+        Ok(Value::Array(result))
+      },
+      HloOpcode::Compare => {
+        let direction = instruction.comparison_direction();
+        let comparison_type = instruction.comparison_type();
+        Ok(Value::Array(ops[0].array().iter().zip(ops[1].array())
+          .map(|(a, b)| bool_element(compare_values(&direction, &comparison_type, *a, *b)))
+          .collect()))
+      },
+      HloOpcode::Complex => {
+        let t = shape.element_type();
+        Ok(Value::Array(ops[0].array().iter().zip(ops[1].array())
+          .map(|(re, im)| normalize(Element::Complex(as_f64(*re), as_f64(*im)), &t))
+          .collect()))
+      },
+      HloOpcode::Clamp => {
+        let count = ops[1].array().len();
+        let low = |i: usize| ops[0].array()[if ops[0].array().len() == 1 { 0 } else { i }];
+        let high = |i: usize| ops[2].array()[if ops[2].array().len() == 1 { 0 } else { i }];
+        Ok(Value::Array(
+          (0..count).map(|i| clamp(low(i), ops[1].array()[i], high(i))).collect()))
+      },
+      HloOpcode::Select => {
+        if !shape.is_array() {
+          return Ok(if is_true(ops[0].scalar()) { ops[1].clone() } else { ops[2].clone() });
+        }
+        let pred = ops[0].array();
+        let count = ops[1].array().len();
+        Ok(Value::Array((0..count).map(|i| {
+          let p = pred[if pred.len() == 1 { 0 } else { i }];
+          if is_true(p) { ops[1].array()[i] } else { ops[2].array()[i] }
+        }).collect()))
+      },
+      HloOpcode::Convert => {
+        let (from, to) = (op_shapes[0].element_type(), shape.element_type());
+        let mut result = Vec::with_capacity(ops[0].array().len());
+        for value in ops[0].array() {
+          result.push(convert_value(*value, &from, &to)?);
+        }
+        Ok(Value::Array(result))
+      },
+      HloOpcode::BitcastConvert => {
+        let (from, to) = (op_shapes[0].element_type(), shape.element_type());
+        if bit_width(&from) != bit_width(&to) {
+          return Err(format!("bitcast-convert between {:?} and {:?} of different widths is not supported.", from, to));
+        }
+        let mut result = Vec::with_capacity(ops[0].array().len());
+        for value in ops[0].array() {
+          result.push(from_bit_pattern(bit_pattern(*value, &from)?, &to)?);
+        }
+        Ok(Value::Array(result))
+      },
+      HloOpcode::ReducePrecision => {
+        let (exponent_bits, mantissa_bits) =
+          (instruction.exponent_bits(), instruction.mantissa_bits());
+        Ok(Value::Array(ops[0].array().iter()
+          .map(|v| Element::Real(reduce_precision(as_f64(*v), exponent_bits, mantissa_bits)))
+          .collect()))
+      },
+      HloOpcode::Broadcast => Ok(broadcast(instruction, op_shapes[0], shape, ops[0])),
+      HloOpcode::Reshape | HloOpcode::Bitcast | HloOpcode::DynamicReshape =>
+        Ok(Value::Array(ops[0].array().clone())),
+      HloOpcode::Transpose => Ok(transpose(instruction, op_shapes[0], shape, ops[0])),
+      HloOpcode::Reverse => Ok(reverse(instruction, op_shapes[0], ops[0])),
+      HloOpcode::Slice => Ok(slice(instruction, op_shapes[0], shape, ops[0])),
+      HloOpcode::DynamicSlice => Ok(dynamic_slice(instruction, op_shapes[0], shape, &ops)),
+      HloOpcode::DynamicUpdateSlice =>
+        Ok(dynamic_update_slice(op_shapes[0], op_shapes[1], &ops)),
+      HloOpcode::Concatenate => Ok(concatenate(instruction, &op_shapes, shape, &ops)),
+      HloOpcode::Pad => Ok(pad(instruction, op_shapes[0], shape, &ops)),
+      HloOpcode::Iota => Ok(iota(instruction, shape)),
+      HloOpcode::Tuple => Ok(Value::Tuple(ops.iter().map(|v| (*v).clone()).collect())),
+      HloOpcode::GetTupleElement =>
+        Ok(ops[0].elements()[instruction.tuple_index() as usize].clone()),
+      HloOpcode::Dot => Ok(dot(instruction, op_shapes[0], op_shapes[1], shape, &ops)),
+      HloOpcode::Convolution =>
+        Ok(convolution(instruction, op_shapes[0], op_shapes[1], shape, &ops)),
+      HloOpcode::Gather => Ok(gather(instruction, op_shapes[0], op_shapes[1], shape, &ops)),
+      HloOpcode::Reduce => self.reduce(module, instruction, &op_shapes, shape, &ops),
+      HloOpcode::ReduceWindow =>
+        self.reduce_window(module, instruction, &op_shapes, shape, &ops),
+      HloOpcode::SelectAndScatter =>
+        self.select_and_scatter(module, instruction, &op_shapes, &ops),
+      HloOpcode::Scatter => self.scatter(module, instruction, &op_shapes, shape, &ops),
+      HloOpcode::Sort => self.sort(module, instruction, op_shapes[0], &ops),
+      HloOpcode::TopK => Ok(top_k(instruction, op_shapes[0], ops[0])),
+      HloOpcode::Map => self.map(module, instruction, &ops),
+      HloOpcode::While => {
+        let condition = module.computation(instruction.while_condition());
+        let body = module.computation(instruction.while_body());
+        let mut state = ops[0].clone();
+        let mut iterations: i64 = 0;
+        loop {
+          let keep_going = self.run_computation(module, condition, &vec![state.clone()])?;
+          if !keep_going.is_known() {
+            return Ok(Value::Unknown);
+          }
+          if !is_true(keep_going.scalar()) {
+            return Ok(state);
+          }
+          if self.max_loop_iterations >= 0 && iterations >= self.max_loop_iterations {
+            return Err(format!("Loop {} exceeded loop iteration limit ({}).",
+              instruction.name(), self.max_loop_iterations));
+          }
+          state = self.run_computation(module, body, &vec![state])?;
+          iterations += 1;
+        }
+      },
+      HloOpcode::Conditional => {
+        let branches = instruction.branch_computations();
+        let selector = ops[0].scalar();
+        let branch_index = if op_shapes[0].element_type() == PrimitiveType::Pred {
+          if is_true(selector) { 0 } else { 1 }
+        } else {
+          match as_integer(selector) {
+            // An out of range index selects the last branch.
+            index if index < 0 || index >= branches.len() as i128 => branches.len() - 1,
+            index => index as usize
+          }
+        };
+        let branch = module.computation(branches[branch_index]);
+        self.run_computation(module, branch, &vec![ops[branch_index + 1].clone()])
+      },
+      HloOpcode::Call | HloOpcode::Fusion => {
+        let callee = module.computation(instruction.called_computations()[0]);
+        let call_args: Vec<Value> = ops.iter().map(|v| (*v).clone()).collect();
+        self.run_computation(module, callee, &call_args)
+      },
+      HloOpcode::CustomCall => {
+        let handler = match &self.custom_call_handler {
+          Some(handler) => handler.clone(),
+          None if self.partial => return Ok(Value::Unknown),
+          None => return Err(format!(
+            "Custom call target {} is not supported without a custom call handler.",
+            instruction.custom_call_target()))
+        };
+        let literals: Vec<DynLiteral> = ops.iter().zip(&op_shapes)
+          .map(|(value, operand_shape)| value_to_dyn_literal(operand_shape, value))
+          .collect();
+        let result = handler(instruction, &literals)?;
+        value_from_dyn_literal(shape, &result)
+      },
+      HloOpcode::AsyncStart => {
+        // The result is ((operands), output, context).
+        let callee = module.computation(instruction.called_computations()[0]);
+        let call_args: Vec<Value> = ops.iter().map(|v| (*v).clone()).collect();
+        let output = self.run_computation(module, callee, &call_args)?;
+        let mut elements = vec![Value::Tuple(call_args), output];
+        for i in 2..shape.tuple_shapes_size() {
+          elements.push(zero_value(shape.tuple_shapes(i)));
+        }
+        Ok(Value::Tuple(elements))
+      },
+      HloOpcode::AsyncUpdate => Ok(ops[0].clone()),
+      HloOpcode::AsyncDone => Ok(ops[0].elements()[1].clone()),
+      HloOpcode::CopyStart => {
+        // The result is (destination, source, context).
+        let mut elements = vec![ops[0].clone(), ops[0].clone()];
+        for i in 2..shape.tuple_shapes_size() {
+          elements.push(zero_value(shape.tuple_shapes(i)));
+        }
+        Ok(Value::Tuple(elements))
+      },
+      HloOpcode::CopyDone => Ok(ops[0].elements()[0].clone()),
+      HloOpcode::Domain | HloOpcode::OptimizationBarrier | HloOpcode::AddDependency |
+      HloOpcode::SetDimensionSize => Ok(ops[0].clone()),
+      HloOpcode::GetDimensionSize => Ok(Value::Array(vec![Element::Integer(
+        op_shapes[0].dimensions(instruction.dimension() as usize) as i128)])),
+      HloOpcode::ReplicaId | HloOpcode::PartitionId =>
+        Ok(Value::Array(vec![Element::Integer(0)])),
+      HloOpcode::AfterAll => Ok(Value::Tuple(vec![])),
+      // Random numbers are not constant.
+      HloOpcode::Rng | HloOpcode::RngBitGenerator if self.partial => Ok(Value::Unknown),
+      HloOpcode::Rng => self.rng(instruction, shape, &ops),
+      HloOpcode::RngBitGenerator => rng_bit_generator(shape, ops[0]),
+      HloOpcode::Cholsky => cholesky(instruction, shape, ops[0]),
+      HloOpcode::TriangularSolve => triangular_solve(instruction, op_shapes[0], shape, &ops),
+      _ if self.partial => Ok(Value::Unknown),
+      _ => Err(format!("{} is not supported by the evaluator.", hlo_opcode_string(&opcode)))
+    }
+  }
+
+  fn scalar_computation(&self, module: &HloModule, id: ComputationId) -> ScalarComputation {
+    let mut binary = None;
+    if self.use_fast_path {
+      let computation = module.computation(id);
+      let root = computation.root_instruction();
+      let params = computation.parameter_instructions();
+      if params.len() == 2 && is_elementwise_binary(&root.opcode()) &&
+        root.operand(0) == params[0] && root.operand(1) == params[1]
+      {
+        binary = Some((root.opcode(), root.shape().element_type()));
+      }
+    }
+    ScalarComputation { id: id, binary_op: binary }
+  }
+
+  // Applies 'computation' to scalar arguments and returns its scalar results.
+  fn apply_scalar(
+    &mut self,
+    module: &HloModule,
+    computation: &ScalarComputation,
+    args: Vec<Element>) -> Result<Vec<Element>, String>
+  {
+    if let Some((opcode, t)) = &computation.binary_op {
+      return Ok(vec![binary_op(opcode, args[0], args[1], t)?]);
+    }
+    let args: Vec<Value> = args.into_iter().map(|v| Value::Array(vec![v])).collect();
+    let result = self.run_computation(module, module.computation(computation.id), &args)?;
+    match result {
+      _ if !result.is_known() => Err(format!("The result of {} is not constant.",
+        module.computation(computation.id).name())),
+      Value::Tuple(elements) => Ok(elements.iter().map(|v| v.scalar()).collect()),
+      _ => Ok(vec![result.scalar()])
+    }
+  }
+
+  fn reduce(
+    &mut self,
+    module: &HloModule,
+    instruction: &HloInstruction,
+    op_shapes: &Vec<&Shape>,
+    shape: &Shape,
+    ops: &Vec<&Value>) -> Result<Value, String>
+  {
+    let n = ops.len() / 2;
+    let input_dims = op_shapes[0].dimensions_vec();
+    let input_strides = strides(input_dims);
+    let reduce_dims: Vec<usize> = instruction.dimensions().iter().map(|d| *d as usize).collect();
+    let kept_dims: Vec<usize> =
+      (0..input_dims.len()).filter(|d| !reduce_dims.contains(d)).collect();
+    let output_dims: Vec<i64> = kept_dims.iter().map(|d| input_dims[*d]).collect();
+    let reduced_sizes: Vec<i64> = reduce_dims.iter().map(|d| input_dims[*d]).collect();
+    let reducer = self.scalar_computation(module, instruction.to_apply());
+
+    let mut results = vec![Vec::new(); n];
+    let mut output_index = vec![0; output_dims.len()];
+    let mut input_index = vec![0; input_dims.len()];
+    for _ in 0..element_count(&output_dims) {
+      for (i, d) in kept_dims.iter().enumerate() {
+        input_index[*d] = output_index[i];
+      }
+      let mut accumulators: Vec<Element> = (0..n).map(|k| ops[n + k].scalar()).collect();
+      let mut reduced_index = vec![0; reduce_dims.len()];
+      for _ in 0..element_count(&reduced_sizes) {
+        for (i, d) in reduce_dims.iter().enumerate() {
+          input_index[*d] = reduced_index[i];
+        }
+        let position = linear_index(&input_index, &input_strides);
+        let mut reducer_args = accumulators;
+        reducer_args.extend((0..n).map(|k| ops[k].array()[position]));
+        accumulators = self.apply_scalar(module, &reducer, reducer_args)?;
+        next_index(&mut reduced_index, &reduced_sizes);
+      }
+      for k in 0..n {
+        results[k].push(accumulators[k]);
+      }
+      next_index(&mut output_index, &output_dims);
+    }
+    Ok(variadic_result(shape, results))
+  }
+
+  fn reduce_window(
+    &mut self,
+    module: &HloModule,
+    instruction: &HloInstruction,
+    op_shapes: &Vec<&Shape>,
+    shape: &Shape,
+    ops: &Vec<&Value>) -> Result<Value, String>
+  {
+    let n = ops.len() / 2;
+    let window = instruction.window();
+    let input_dims = op_shapes[0].dimensions_vec();
+    let input_strides = strides(input_dims);
+    let output_dims = if shape.is_tuple() {
+      shape.tuple_shapes(0).dimensions_vec()
+    } else {
+      shape.dimensions_vec()
+    };
+    let window_sizes: Vec<i64> = window.dimensions_vec().iter().map(|d| d.size()).collect();
+    let reducer = self.scalar_computation(module, instruction.to_apply());
+
+    let mut results = vec![Vec::new(); n];
+    let mut output_index = vec![0; output_dims.len()];
+    for _ in 0..element_count(output_dims) {
+      let mut accumulators: Vec<Element> = (0..n).map(|k| ops[n + k].scalar()).collect();
+      let mut window_index = vec![0; window_sizes.len()];
+      for _ in 0..element_count(&window_sizes) {
+        let input_index =
+          window_operand_index(window, &output_index, &window_index, input_dims);
+        if let Some(input_index) = input_index {
+          let position = linear_index(&input_index, &input_strides);
+          let mut reducer_args = accumulators;
+          reducer_args.extend((0..n).map(|k| ops[k].array()[position]));
+          accumulators = self.apply_scalar(module, &reducer, reducer_args)?;
+        }
+        next_index(&mut window_index, &window_sizes);
+      }
+      for k in 0..n {
+        results[k].push(accumulators[k]);
+      }
+      next_index(&mut output_index, output_dims);
+    }
+    Ok(variadic_result(shape, results))
+  }
+
+  fn select_and_scatter(
+    &mut self,
+    module: &HloModule,
+    instruction: &HloInstruction,
+    op_shapes: &Vec<&Shape>,
+    ops: &Vec<&Value>) -> Result<Value, String>
+  {
+    let window = instruction.window();
+    let operand_dims = op_shapes[0].dimensions_vec();
+    let operand_strides = strides(operand_dims);
+    let source_dims = op_shapes[1].dimensions_vec();
+    let window_sizes: Vec<i64> = window.dimensions_vec().iter().map(|d| d.size()).collect();
+    let select = module.computation(instruction.select());
+    let scatter = self.scalar_computation(module, instruction.scatter());
+    let (operand, source) = (ops[0].array(), ops[1].array());
+
+    let mut result = vec![ops[2].scalar(); operand.len()];
+    let mut source_index = vec![0; source_dims.len()];
+    for source_position in 0..element_count(source_dims) {
+      let mut selected: Option<usize> = None;
+      let mut window_index = vec![0; window_sizes.len()];
+      for _ in 0..element_count(&window_sizes) {
+        let operand_index =
+          window_operand_index(window, &source_index, &window_index, operand_dims);
+        if let Some(operand_index) = operand_index {
+          let position = linear_index(&operand_index, &operand_strides);
+          let keep_selected = match selected {
+            None => false,
+            Some(current) => {
+              let select_args = vec![
+                Value::Array(vec![operand[current]]), Value::Array(vec![operand[position]])];
+              is_true(self.run_computation(module, select, &select_args)?.scalar())
+            }
+          };
+          if !keep_selected {
+            selected = Some(position);
+          }
+        }
+        next_index(&mut window_index, &window_sizes);
+      }
+      if let Some(position) = selected {
+        result[position] = self.apply_scalar(module, &scatter,
+          vec![result[position], source[source_position]])?[0];
+      }
+      next_index(&mut source_index, source_dims);
+    }
+    Ok(Value::Array(result))
+  }
+
+  fn scatter(
+    &mut self,
+    module: &HloModule,
+    instruction: &HloInstruction,
+    op_shapes: &Vec<&Shape>,
+    shape: &Shape,
+    ops: &Vec<&Value>) -> Result<Value, String>
+  {
+    let n = (ops.len() - 1) / 2;
+    let dnums = instruction.scatter_dimension_numbers();
+    let operand_dims = op_shapes[0].dimensions_vec();
+    let operand_strides = strides(operand_dims);
+    let indices_dims = op_shapes[n].dimensions_vec();
+    let indices = ops[n].array();
+    let update_dims = op_shapes[n + 1].dimensions_vec();
+    let update_window_dims: Vec<usize> =
+      dnums.update_window_dims().iter().map(|d| *d as usize).collect();
+    let update_scatter_dims: Vec<usize> =
+      (0..update_dims.len()).filter(|d| !update_window_dims.contains(d)).collect();
+    // The operand dimensions the update window dimensions map to, in order.
+    let window_operand_dims: Vec<usize> = (0..operand_dims.len() as i64)
+      .filter(|d| !dnums.inserted_window_dims().contains(d) &&
+        !dnums.input_batching_dims().contains(d))
+      .map(|d| d as usize).collect();
+    let mut window_sizes = vec![1; operand_dims.len()];
+    for (i, d) in window_operand_dims.iter().enumerate() {
+      window_sizes[*d] = update_dims[update_window_dims[i]];
+    }
+    let reducer = self.scalar_computation(module, instruction.to_apply());
+
+    let mut results: Vec<Vec<Element>> = (0..n).map(|k| ops[k].array().clone()).collect();
+    let mut update_index = vec![0; update_dims.len()];
+    for update_position in 0..element_count(update_dims) {
+      let batch_index: Vec<i64> = update_scatter_dims.iter().map(|d| update_index[*d]).collect();
+      let start = start_index(
+        &batch_index, indices, indices_dims, dnums.index_vector_dim(),
+        dnums.scatter_dims_to_operand_dims(), dnums.input_batching_dims(),
+        dnums.scatter_indices_batching_dims(), operand_dims.len());
+      // An update window that does not fit into the operand is skipped.
+      let in_bounds = (0..operand_dims.len())
+        .all(|d| start[d] >= 0 && start[d] + window_sizes[d] <= operand_dims[d]);
+      if in_bounds {
+        let mut operand_index = start;
+        for (i, d) in window_operand_dims.iter().enumerate() {
+          operand_index[*d] += update_index[update_window_dims[i]];
+        }
+        let position = linear_index(&operand_index, &operand_strides);
+        let mut reducer_args: Vec<Element> = (0..n).map(|k| results[k][position]).collect();
+        reducer_args.extend((0..n).map(|k| ops[n + 1 + k].array()[update_position]));
+        let updated = self.apply_scalar(module, &reducer, reducer_args)?;
+        for k in 0..n {
+          results[k][position] = updated[k];
+        }
+      }
+      next_index(&mut update_index, update_dims);
+    }
+    Ok(variadic_result(shape, results))
+  }
+
+  fn sort(
+    &mut self,
+    module: &HloModule,
+    instruction: &HloInstruction,
+    operand_shape: &Shape,
+    ops: &Vec<&Value>) -> Result<Value, String>
+  {
+    let comparator = module.computation(instruction.to_apply());
+    let dims = operand_shape.dimensions_vec();
+    let dimension = instruction.dimension() as usize;
+    let dims_strides = strides(dims);
+    let size = dims[dimension] as usize;
+    let stride = dims_strides[dimension] as usize;
+    let mut outer_dims = dims.clone();
+    outer_dims[dimension] = 1;
+
+    let mut results: Vec<Vec<Element>> = ops.iter().map(|v| v.array().clone()).collect();
+    let mut index = vec![0; dims.len()];
+    for _ in 0..element_count(&outer_dims) {
+      let base = linear_index(&index, &dims_strides);
+      let positions: Vec<usize> = (0..size).map(|i| base + i * stride).collect();
+      let mut less = |a: usize, b: usize| -> Result<bool, String> {
+        let mut args = Vec::with_capacity(2 * ops.len());
+        for op in ops {
+          args.push(Value::Array(vec![op.array()[positions[a]]]));
+          args.push(Value::Array(vec![op.array()[positions[b]]]));
+        }
+        Ok(is_true(self.run_computation(module, comparator, &args)?.scalar()))
+      };
+      let order = merge_sort((0..size).collect(), &mut less)?;
+      for (k, op) in ops.iter().enumerate() {
+        for (i, source) in order.iter().enumerate() {
+          results[k][positions[i]] = op.array()[positions[*source]];
+        }
+      }
+      next_index(&mut index, &outer_dims);
+    }
+    if results.len() == 1 && !instruction.shape().is_tuple() {
+      return Ok(Value::Array(results.pop().unwrap()));
+    }
+    Ok(Value::Tuple(results.into_iter().map(Value::Array).collect()))
+  }
+
+  fn map(
+    &mut self,
+    module: &HloModule,
+    instruction: &HloInstruction,
+    ops: &Vec<&Value>) -> Result<Value, String>
+  {
+    let mapper = self.scalar_computation(module, instruction.to_apply());
+    let count = ops[0].array().len();
+    let mut result = Vec::with_capacity(count);
+    for i in 0..count {
+      let args = ops.iter().map(|v| v.array()[i]).collect();
+      result.push(self.apply_scalar(module, &mapper, args)?[0]);
+    }
+    Ok(Value::Array(result))
+  }
+
+  fn rng(
+    &mut self,
+    instruction: &HloInstruction,
+    shape: &Shape,
+    ops: &Vec<&Value>) -> Result<Value, String>
+  {
+    let t = shape.element_type();
+    let (a, b) = (as_f64(ops[0].scalar()), as_f64(ops[1].scalar()));
+    let count = element_count(shape.dimensions_vec());
+    let mut result = Vec::with_capacity(count);
+    match instruction.random_distribution() {
+      // Values are drawn from [a, b).
+      RandomDistribution::Uniform => for _ in 0..count {
+        if is_integral_type(&t) {
+          let (a, b) = (as_integer(ops[0].scalar()), as_integer(ops[1].scalar()));
+          let offset = (self.engine.next_f64() * (b - a) as f64).floor() as i128;
+          result.push(Element::Integer(a + offset));
+          continue;
+        }
+        if !is_floating_point_type(&t) {
+          return Err(format!("rng of {:?} is not supported.", t));
+        }
+        loop {
+          let value = normalize(Element::Real(a + (b - a) * self.engine.next_f64()), &t);
+          if as_f64(value) < b || a >= b {
+            result.push(value);
+            break;
+          }
+        }
+      },
+      RandomDistribution::Normal => for _ in 0..count {
+        if !is_floating_point_type(&t) {
+          return Err(format!("rng of {:?} with a normal distribution is not supported.", t));
+        }
+        result.push(normalize(Element::Real(self.engine.next_normal(a, b)), &t));
+      },
+      RandomDistribution::Invalid => return Err("rng has no distribution.".to_string())
+    }
+    Ok(Value::Array(result))
+  }
+}
+
+// Returns the value of an instruction some of whose operands are unknown, or
+// None if the instruction is evaluated as usual. Only instructions that
+// forward, select or call their operands can have a known value then.
+fn evaluate_with_unknown_operands(
+  instruction: &HloInstruction, op_shapes: &Vec<&Shape>, ops: &Vec<&Value>) -> Option<Value>
+{
+  match instruction.opcode() {
+    HloOpcode::Tuple => Some(Value::Tuple(ops.iter().map(|v| (*v).clone()).collect())),
+    HloOpcode::GetTupleElement => match ops[0] {
+      Value::Tuple(elements) => Some(elements[instruction.tuple_index() as usize].clone()),
+      _ => Some(Value::Unknown)
+    },
+    // Only the shape of the operand matters.
+    HloOpcode::GetDimensionSize => Some(Value::Array(vec![Element::Integer(
+      op_shapes[0].dimensions(instruction.dimension() as usize) as i128)])),
+    HloOpcode::AddDependency | HloOpcode::AsyncUpdate | HloOpcode::Domain |
+    HloOpcode::OptimizationBarrier => Some(ops[0].clone()),
+    HloOpcode::Copy if !instruction.shape().is_array() => Some(ops[0].clone()),
+    HloOpcode::Call | HloOpcode::Fusion | HloOpcode::While => None,
+    HloOpcode::Conditional if ops[0].is_known() => None,
+    _ => Some(Value::Unknown)
+  }
+}
+
+// Returns the computation that contains 'instruction'.
+fn parent_computation<'a>(
+  module: &'a HloModule, instruction: &HloInstruction) -> Result<&'a HloComputation, String>
+{
+  match instruction.parent() {
+    Some(id) => Ok(module.computation(id)),
+    None => Err(format!("Instruction {} is not in a computation.", instruction.name()))
+  }
+}
+
+fn is_elementwise_binary(opcode: &HloOpcode) -> bool {
+  matches!(opcode, HloOpcode::Add | HloOpcode::And | HloOpcode::Atan2 |
+    HloOpcode::Divide | HloOpcode::Maximum | HloOpcode::Minimum |
+    HloOpcode::Multiply | HloOpcode::Or | HloOpcode::Power | HloOpcode::Remainder |
+    HloOpcode::ShiftLeft | HloOpcode::ShiftRightArithmetic |
+    HloOpcode::ShiftRightLogical | HloOpcode::Subtract | HloOpcode::Xor)
+}
+
+fn check_argument_count(computation: &HloComputation, count: usize) -> Result<(), String> {
+  if count != computation.num_parameters() {
+    return Err(format!("Expected {} arguments, but got {}.",
+      computation.num_parameters(), count));
+  }
+  Ok(())
+}
+
+// Returns 'value' as an element of a Literal<f64>, which has no room for the
+// imaginary part of complex numbers.
+fn element_to_f64(value: Element) -> Result<f64, String> {
+  match value {
+    Element::Complex(_, _) => Err("Complex elements can not be held by a Literal<f64>.".to_string()),
+    _ => Ok(as_f64(value))
+  }
+}
+
+// Returns true if 'a' and 'b' have the same tuple structure and dimensions.
+fn same_dimensions(a: &Shape, b: &Shape) -> bool {
+  if a.is_tuple() || b.is_tuple() {
+    return a.is_tuple() && b.is_tuple() && a.tuple_shapes_size() == b.tuple_shapes_size() &&
+      (0..a.tuple_shapes_size()).all(|i| same_dimensions(a.tuple_shapes(i), b.tuple_shapes(i)));
+  }
+  if a.is_token() || b.is_token() {
+    return a.is_token() && b.is_token();
+  }
+  a.dimensions_vec() == b.dimensions_vec()
+}
+
+fn check_same_dimensions(lhs: &Literal<f64>, rhs: &Literal<f64>) -> Result<(), String> {
+  if !lhs.shape().is_array() || !same_dimensions(lhs.shape(), rhs.shape()) {
+    return Err(format!("Literals of shapes {} and {} can not be combined elementwise.",
+      ShapeUtil::human_string(lhs.shape()), ShapeUtil::human_string(rhs.shape())));
+  }
+  Ok(())
+}
+
+fn array_literal(dims: &Vec<i64>, data: Vec<f64>) -> Literal<f64> {
+  let mut literal =
+    Literal::new_from_shape(&ShapeUtil::make_shape(&PrimitiveType::F64, dims.clone()));
+  literal.set_data(&vec![], data);
+  literal
+}
+
+fn value_from_literal(shape: &Shape, literal: &Literal<f64>) -> Result<Value, String> {
+  if !same_dimensions(shape, literal.shape()) {
+    return Err(format!("Literal of shape {} does not match shape {}.",
+      ShapeUtil::human_string(literal.shape()), ShapeUtil::human_string(shape)));
+  }
+  literal_piece_value(shape, literal, &mut vec![])
+}
+
+fn literal_piece_value(
+  shape: &Shape, literal: &Literal<f64>, index: &mut Vec<i64>) -> Result<Value, String>
+{
+  if shape.is_tuple() {
+    let mut elements = Vec::with_capacity(shape.tuple_shapes_size());
+    for i in 0..shape.tuple_shapes_size() {
+      index.push(i as i64);
+      elements.push(literal_piece_value(shape.tuple_shapes(i), literal, index)?);
+      index.pop();
+    }
+    return Ok(Value::Tuple(elements));
+  }
+  if shape.is_token() {
+    return Ok(Value::Tuple(vec![]));
+  }
+  if !literal.piece(index).is_known() {
+    return Ok(Value::Unknown);
+  }
+  let data = literal.data(index);
+  let count = element_count(shape.dimensions_vec());
+  if data.len() != count {
+    return Err(format!("Literal has {} elements, but its shape {} has {}.",
+      data.len(), ShapeUtil::human_string(shape), count));
+  }
+  let t = shape.element_type();
+  Ok(Value::Array(data.iter().map(|v| element_from_f64(*v, &t)).collect()))
+}
+
+fn value_to_literal(shape: &Shape, value: &Value) -> Result<Literal<f64>, String> {
+  let mut literal = Literal::new_from_shape(&literal_shape(shape));
+  fill_literal(shape, value, &mut literal, &mut vec![])?;
+  Ok(literal)
+}
+
+fn fill_literal(
+  shape: &Shape, value: &Value, literal: &mut Literal<f64>, index: &mut Vec<i64>) -> Result<(), String>
+{
+  if !shape.is_array() {
+    // Only arrays have values that can be unknown.
+    literal.mutable_piece(index).set_array_value_state(ArrayValueState::Known);
+  }
+  if shape.is_tuple() {
+    for i in 0..shape.tuple_shapes_size() {
+      let element = match value {
+        Value::Tuple(elements) => &elements[i],
+        _ => &Value::Unknown
+      };
+      index.push(i as i64);
+      fill_literal(shape.tuple_shapes(i), element, literal, index)?;
+      index.pop();
+    }
+    return Ok(());
+  }
+  if !shape.is_array() {
+    return Ok(());
+  }
+  match value {
+    Value::Unknown =>
+      literal.mutable_piece(index).set_array_value_state(ArrayValueState::Unknown),
+    _ => {
+      let mut data = Vec::with_capacity(value.array().len());
+      for element in value.array() {
+        data.push(element_to_f64(*element)?);
+      }
+      literal.set_data(index, data);
+    }
+  }
+  Ok(())
+}
+
+fn value_from_dyn_literal(shape: &Shape, literal: &DynLiteral) -> Result<Value, String> {
+  if !same_dimensions(shape, literal.shape()) {
+    return Err(format!("Literal of shape {} does not match shape {}.",
+      ShapeUtil::human_string(literal.shape()), ShapeUtil::human_string(shape)));
+  }
+  Ok(dyn_literal_piece_value(shape, literal, &mut vec![]))
+}
+
+fn dyn_literal_piece_value(shape: &Shape, literal: &DynLiteral, index: &mut Vec<i64>) -> Value {
+  if shape.is_tuple() {
+    let mut elements = Vec::with_capacity(shape.tuple_shapes_size());
+    for i in 0..shape.tuple_shapes_size() {
+      index.push(i as i64);
+      elements.push(dyn_literal_piece_value(shape.tuple_shapes(i), literal, index));
+      index.pop();
+    }
+    return Value::Tuple(elements);
+  }
+  if shape.is_token() {
+    return Value::Tuple(vec![]);
+  }
+  if !literal.is_known(index) {
+    return Value::Unknown;
+  }
+  let t = shape.element_type();
+  Value::Array(literal.elements(index).into_iter().map(|e| normalize(e, &t)).collect())
+}
+
+fn value_to_dyn_literal(shape: &Shape, value: &Value) -> DynLiteral {
+  let mut literal = DynLiteral::new(shape);
+  fill_dyn_literal(shape, value, &mut literal, &mut vec![]);
+  literal
+}
+
+fn fill_dyn_literal(shape: &Shape, value: &Value, literal: &mut DynLiteral, index: &mut Vec<i64>) {
+  if shape.is_tuple() {
+    for i in 0..shape.tuple_shapes_size() {
+      let element = match value {
+        Value::Tuple(elements) => &elements[i],
+        _ => &Value::Unknown
+      };
+      index.push(i as i64);
+      fill_dyn_literal(shape.tuple_shapes(i), element, literal, index);
+      index.pop();
+    }
+    return;
+  }
+  if !shape.is_array() {
+    return;
+  }
+  match value {
+    Value::Unknown => literal.set_unknown(index),
+    _ => literal.set_elements(index, value.array().clone())
+  }
+}
+
+fn zero_value(shape: &Shape) -> Value {
+  if shape.is_tuple() {
+    return Value::Tuple(shape.tuple_shapes_vec().iter().map(zero_value).collect());
+  }
+  if shape.is_token() {
+    return Value::Tuple(vec![]);
+  }
+  Value::Array(vec![zero_element(&shape.element_type()); element_count(shape.dimensions_vec())])
+}
+
+// Returns an array result, or a tuple of arrays for variadic ops.
+fn variadic_result(shape: &Shape, mut results: Vec<Vec<Element>>) -> Value {
+  if !shape.is_tuple() {
+    return Value::Array(results.pop().unwrap());
+  }
+  Value::Tuple(results.into_iter().map(Value::Array).collect())
+}
+
+// Returns the row-major strides of an array with the given dimensions.
+fn strides(dims: &Vec<i64>) -> Vec<i64> {
+  let mut strides = vec![1; dims.len()];
+  for i in (0..dims.len().saturating_sub(1)).rev() {
+    strides[i] = strides[i + 1] * dims[i + 1];
+  }
+  strides
+}
+
+fn linear_index(index: &Vec<i64>, strides: &Vec<i64>) -> usize {
+  index.iter().zip(strides).map(|(i, s)| i * s).sum::<i64>() as usize
+}
+
+fn element_count(dims: &Vec<i64>) -> usize {
+  dims.iter().product::<i64>() as usize
+}
+
+// Advances 'index' to the next index of an array with dimensions 'dims' in
+// row-major order.
+fn next_index(index: &mut Vec<i64>, dims: &Vec<i64>) {
+  for i in (0..dims.len()).rev() {
+    index[i] += 1;
+    if index[i] < dims[i] {
+      return;
+    }
+    index[i] = 0;
+  }
+}
+
+// Returns the operand index read by position 'window_index' of the window of
+// output 'output_index', or None if the position falls into padding or
+// between dilated elements.
+fn window_operand_index(
+  window: &Window,
+  output_index: &Vec<i64>,
+  window_index: &Vec<i64>,
+  operand_dims: &Vec<i64>) -> Option<Vec<i64>>
+{
+  let mut index = Vec::with_capacity(operand_dims.len());
+  for (d, dim) in window.dimensions_vec().iter().enumerate() {
+    let dilated = output_index[d] * dim.stride() - dim.padding_low() +
+      window_index[d] * dim.window_dilation();
+    if dilated % dim.base_dilation() != 0 {
+      return None;
+    }
+    let i = dilated / dim.base_dilation();
+    if i < 0 || i >= operand_dims[d] {
+      return None;
+    }
+    index.push(i);
+  }
+  Some(index)
+}
+
+// Sorts 'items' stably with a comparator that may fail.
+fn merge_sort<F>(mut items: Vec<usize>, less: &mut F) -> Result<Vec<usize>, String>
+  where F: FnMut(usize, usize) -> Result<bool, String>
+{
+  if items.len() <= 1 {
+    return Ok(items);
+  }
+  let right = items.split_off(items.len() / 2);
+  let left = merge_sort(items, less)?;
+  let right = merge_sort(right, less)?;
+  let mut merged = Vec::with_capacity(left.len() + right.len());
+  let (mut i, mut j) = (0, 0);
+  while i < left.len() && j < right.len() {
+    if less(right[j], left[i])? {
+      merged.push(right[j]);
+      j += 1;
+    } else {
+      merged.push(left[i]);
+      i += 1;
+    }
+  }
+  merged.extend_from_slice(&left[i..]);
+  merged.extend_from_slice(&right[j..]);
+  Ok(merged)
+}
+
+fn broadcast(
+  instruction: &HloInstruction, operand_shape: &Shape, shape: &Shape, operand: &Value) -> Value
+{
+  let output_dims = shape.dimensions_vec();
+  let operand_strides = strides(operand_shape.dimensions_vec());
+  let dimensions = instruction.dimensions();
+  let mut result = Vec::with_capacity(element_count(output_dims));
+  let mut index = vec![0; output_dims.len()];
+  let mut operand_index = vec![0; dimensions.len()];
+  for _ in 0..element_count(output_dims) {
+    for (i, d) in dimensions.iter().enumerate() {
+      operand_index[i] = index[*d as usize];
+    }
+    result.push(operand.array()[linear_index(&operand_index, &operand_strides)]);
+    next_index(&mut index, output_dims);
+  }
+  Value::Array(result)
+}
+
+fn transpose(
+  instruction: &HloInstruction, operand_shape: &Shape, shape: &Shape, operand: &Value) -> Value
+{
+  let output_dims = shape.dimensions_vec();
+  let operand_strides = strides(operand_shape.dimensions_vec());
+  let permutation = instruction.dimensions();
+  let mut result = Vec::with_capacity(element_count(output_dims));
+  let mut index = vec![0; output_dims.len()];
+  let mut operand_index = vec![0; output_dims.len()];
+  for _ in 0..element_count(output_dims) {
+    for (i, d) in permutation.iter().enumerate() {
+      operand_index[*d as usize] = index[i];
+    }
+    result.push(operand.array()[linear_index(&operand_index, &operand_strides)]);
+    next_index(&mut index, output_dims);
+  }
+  Value::Array(result)
+}
+
+fn reverse(instruction: &HloInstruction, operand_shape: &Shape, operand: &Value) -> Value {
+  let dims = operand_shape.dimensions_vec();
+  let dims_strides = strides(dims);
+  let mut result = Vec::with_capacity(element_count(dims));
+  let mut index = vec![0; dims.len()];
+  for _ in 0..element_count(dims) {
+    let mut operand_index = index.clone();
+    for d in instruction.dimensions() {
+      let d = *d as usize;
+      operand_index[d] = dims[d] - 1 - index[d];
+    }
+    result.push(operand.array()[linear_index(&operand_index, &dims_strides)]);
+    next_index(&mut index, dims);
+  }
+  Value::Array(result)
+}
+
+fn slice(
+  instruction: &HloInstruction, operand_shape: &Shape, shape: &Shape, operand: &Value) -> Value
+{
+  let output_dims = shape.dimensions_vec();
+  let operand_strides = strides(operand_shape.dimensions_vec());
+  let (starts, slice_strides) = (instruction.slice_starts(), instruction.slice_strides());
+  let mut result = Vec::with_capacity(element_count(output_dims));
+  let mut index = vec![0; output_dims.len()];
+  let mut operand_index = vec![0; output_dims.len()];
+  for _ in 0..element_count(output_dims) {
+    for d in 0..output_dims.len() {
+      operand_index[d] = starts[d] + index[d] * slice_strides[d];
+    }
+    result.push(operand.array()[linear_index(&operand_index, &operand_strides)]);
+    next_index(&mut index, output_dims);
+  }
+  Value::Array(result)
+}
+
+// Returns the start indices of a dynamic-(update-)slice, clamped so that a
+// slice of 'sizes' fits into the operand.
+fn clamped_starts(start_operands: &[&Value], operand_dims: &Vec<i64>, sizes: &Vec<i64>) -> Vec<i64> {
+  (0..operand_dims.len())
+    .map(|d| {
+      let start = as_integer(start_operands[d].scalar()).clamp(i64::MIN as i128, i64::MAX as i128);
+      (start as i64).clamp(0, operand_dims[d] - sizes[d])
+    })
+    .collect()
+}
+
+fn dynamic_slice(
+  instruction: &HloInstruction, operand_shape: &Shape, shape: &Shape, ops: &Vec<&Value>) -> Value
+{
+  let operand_dims = operand_shape.dimensions_vec();
+  let operand_strides = strides(operand_dims);
+  let output_dims = shape.dimensions_vec();
+  let starts = clamped_starts(&ops[1..], operand_dims, instruction.dynamic_slice_sizes());
+  let mut result = Vec::with_capacity(element_count(output_dims));
+  let mut index = vec![0; output_dims.len()];
+  let mut operand_index = vec![0; output_dims.len()];
+  for _ in 0..element_count(output_dims) {
+    for d in 0..output_dims.len() {
+      operand_index[d] = starts[d] + index[d];
+    }
+    result.push(ops[0].array()[linear_index(&operand_index, &operand_strides)]);
+    next_index(&mut index, output_dims);
+  }
+  Value::Array(result)
+}
+
+fn dynamic_update_slice(operand_shape: &Shape, update_shape: &Shape, ops: &Vec<&Value>) -> Value {
+  let operand_dims = operand_shape.dimensions_vec();
+  let operand_strides = strides(operand_dims);
+  let update_dims = update_shape.dimensions_vec();
+  let starts = clamped_starts(&ops[2..], operand_dims, update_dims);
+  let mut result = ops[0].array().clone();
+  let mut index = vec![0; update_dims.len()];
+  let mut operand_index = vec![0; update_dims.len()];
+  for position in 0..element_count(update_dims) {
+    for d in 0..update_dims.len() {
+      operand_index[d] = starts[d] + index[d];
+    }
+    result[linear_index(&operand_index, &operand_strides)] = ops[1].array()[position];
+    next_index(&mut index, update_dims);
+  }
+  Value::Array(result)
+}
+
+fn concatenate(
+  instruction: &HloInstruction, op_shapes: &Vec<&Shape>, shape: &Shape, ops: &Vec<&Value>) -> Value
+{
+  let output_dims = shape.dimensions_vec();
+  let output_strides = strides(output_dims);
+  let dimension = instruction.concatenate_dimension() as usize;
+  let mut result = vec![zero_element(&shape.element_type()); element_count(output_dims)];
+  let mut offset = 0;
+  for (operand, operand_shape) in ops.iter().zip(op_shapes) {
+    let dims = operand_shape.dimensions_vec();
+    let mut index = vec![0; dims.len()];
+    for position in 0..element_count(dims) {
+      let mut output_index = index.clone();
+      output_index[dimension] += offset;
+      result[linear_index(&output_index, &output_strides)] = operand.array()[position];
+      next_index(&mut index, dims);
+    }
+    offset += dims[dimension];
+  }
+  Value::Array(result)
+}
+
+fn pad(
+  instruction: &HloInstruction, operand_shape: &Shape, shape: &Shape, ops: &Vec<&Value>) -> Value
+{
+  let operand_dims = operand_shape.dimensions_vec();
+  let operand_strides = strides(operand_dims);
+  let output_dims = shape.dimensions_vec();
+  let config = instruction.padding_config().dimensions_vec();
+  let padding_value = ops[1].scalar();
+  let mut result = Vec::with_capacity(element_count(output_dims));
+  let mut index = vec![0; output_dims.len()];
+  let mut operand_index = vec![0; output_dims.len()];
+  for _ in 0..element_count(output_dims) {
+    let mut in_operand = true;
+    for d in 0..output_dims.len() {
+      let position = index[d] - config[d].edge_padding_low();
+      let step = config[d].interior_padding() + 1;
+      if position < 0 || position % step != 0 || position / step >= operand_dims[d] {
+        in_operand = false;
+        break;
+      }
+      operand_index[d] = position / step;
+    }
+    result.push(if in_operand {
+      ops[0].array()[linear_index(&operand_index, &operand_strides)]
+    } else {
+      padding_value
+    });
+    next_index(&mut index, output_dims);
+  }
+  Value::Array(result)
+}
+
+fn iota(instruction: &HloInstruction, shape: &Shape) -> Value {
+  let dims = shape.dimensions_vec();
+  let dimension = instruction.iota_dimension() as usize;
+  let t = shape.element_type();
+  let mut result = Vec::with_capacity(element_count(dims));
+  let mut index = vec![0; dims.len()];
+  for _ in 0..element_count(dims) {
+    result.push(normalize(Element::Integer(index[dimension] as i128), &t));
+    next_index(&mut index, dims);
+  }
+  Value::Array(result)
+}
+
+fn dot(
+  instruction: &HloInstruction,
+  lhs_shape: &Shape,
+  rhs_shape: &Shape,
+  shape: &Shape,
+  ops: &Vec<&Value>) -> Value
+{
+  let dnums = instruction.dot_dimension_numbers();
+  let (lhs_dims, rhs_dims) = (lhs_shape.dimensions_vec(), rhs_shape.dimensions_vec());
+  let (lhs_strides, rhs_strides) = (strides(lhs_dims), strides(rhs_dims));
+  let (lhs_batch, rhs_batch) = (dnums.lhs_batch_dimensions(), dnums.rhs_batch_dimensions());
+  let (lhs_contracting, rhs_contracting) =
+    (dnums.lhs_contracting_dimensions(), dnums.rhs_contracting_dimensions());
+  let lhs_free: Vec<usize> = (0..lhs_dims.len() as i64)
+    .filter(|d| !lhs_batch.contains(d) && !lhs_contracting.contains(d))
+    .map(|d| d as usize).collect();
+  let rhs_free: Vec<usize> = (0..rhs_dims.len() as i64)
+    .filter(|d| !rhs_batch.contains(d) && !rhs_contracting.contains(d))
+    .map(|d| d as usize).collect();
+  let contracting_sizes: Vec<i64> =
+    lhs_contracting.iter().map(|d| lhs_dims[*d as usize]).collect();
+  let output_dims = shape.dimensions_vec();
+  let t = shape.element_type();
+
+  let mut result = Vec::with_capacity(element_count(output_dims));
+  let mut index = vec![0; output_dims.len()];
+  let mut lhs_index = vec![0; lhs_dims.len()];
+  let mut rhs_index = vec![0; rhs_dims.len()];
+  for _ in 0..element_count(output_dims) {
+    // The output dimensions are the batch dimensions followed by the free
+    // dimensions of the lhs and then of the rhs.
+    let mut k = 0;
+    for i in 0..lhs_batch.len() {
+      lhs_index[lhs_batch[i] as usize] = index[k];
+      rhs_index[rhs_batch[i] as usize] = index[k];
+      k += 1;
+    }
+    for d in &lhs_free {
+      lhs_index[*d] = index[k];
+      k += 1;
+    }
+    for d in &rhs_free {
+      rhs_index[*d] = index[k];
+      k += 1;
+    }
+    let mut sum = zero_element(&t);
+    let mut contracting_index = vec![0; contracting_sizes.len()];
+    for _ in 0..element_count(&contracting_sizes) {
+      for i in 0..contracting_sizes.len() {
+        lhs_index[lhs_contracting[i] as usize] = contracting_index[i];
+        rhs_index[rhs_contracting[i] as usize] = contracting_index[i];
+      }
+      sum = multiply_add(sum, ops[0].array()[linear_index(&lhs_index, &lhs_strides)],
+        ops[1].array()[linear_index(&rhs_index, &rhs_strides)]);
+      next_index(&mut contracting_index, &contracting_sizes);
+    }
+    result.push(normalize(sum, &t));
+    next_index(&mut index, output_dims);
+  }
+  Value::Array(result)
+}
+
+fn convolution(
+  instruction: &HloInstruction,
+  lhs_shape: &Shape,
+  rhs_shape: &Shape,
+  shape: &Shape,
+  ops: &Vec<&Value>) -> Value
+{
+  let dnums = instruction.convolution_dimension_numberes();
+  let window = instruction.window();
+  let (lhs_dims, rhs_dims) = (lhs_shape.dimensions_vec(), rhs_shape.dimensions_vec());
+  let (lhs_strides, rhs_strides) = (strides(lhs_dims), strides(rhs_dims));
+  let output_dims = shape.dimensions_vec();
+  let t = shape.element_type();
+
+  let input_batch_dim = dnums.input_batch_dimension() as usize;
+  let input_z_dim = dnums.input_feature_dimension() as usize;
+  let kernel_input_z_dim = dnums.kernel_input_feature_dimension() as usize;
+  let kernel_output_z_dim = dnums.kernel_output_feature_dimension() as usize;
+  let output_batch_dim = dnums.output_batch_dimension() as usize;
+  let output_z_dim = dnums.output_feature_dimension() as usize;
+  let num_spatial = dnums.input_spatial_dimensions_size();
+
+  let feature_group_count = instruction.feature_group_count();
+  let batch_group_count = instruction.batch_group_count();
+  let input_batch_size = lhs_dims[input_batch_dim];
+  let input_z_size = rhs_dims[kernel_input_z_dim];
+  let output_z_size = rhs_dims[kernel_output_z_dim];
+  let output_feature_group_size = output_z_size / feature_group_count;
+  let batch_group_size = input_batch_size / batch_group_count;
+  // The output features of a batch group; only the "diagonal" of the result
+  // is kept when the batches are grouped.
+  let depthwise_multiplier =
+    if batch_group_count > 1 { output_z_size / input_batch_size } else { 1 };
+  let kernel_spatial_sizes: Vec<i64> = (0..num_spatial)
+    .map(|i| rhs_dims[dnums.kernel_spatial_dimensions(i) as usize]).collect();
+
+  let mut result = Vec::with_capacity(element_count(output_dims));
+  let mut out_index = vec![0; output_dims.len()];
+  let mut lhs_index = vec![0; lhs_dims.len()];
+  let mut rhs_index = vec![0; rhs_dims.len()];
+  for _ in 0..element_count(output_dims) {
+    let feature_group_index = out_index[output_z_dim] / output_feature_group_size;
+    let batch_group_index = out_index[output_z_dim] / depthwise_multiplier;
+    lhs_index[input_batch_dim] = out_index[output_batch_dim] +
+      (batch_group_index * batch_group_size) % input_batch_size;
+    rhs_index[kernel_output_z_dim] = out_index[output_z_dim];
+
+    let mut sum = zero_element(&t);
+    let mut kernel_index = vec![0; num_spatial];
+    for _ in 0..element_count(&kernel_spatial_sizes) {
+      let mut in_input = true;
+      for ki in 0..num_spatial {
+        let dim = window.dimensions(ki);
+        let undilated = out_index[dnums.output_spatial_dimensions(ki) as usize] * dim.stride() -
+          dim.padding_low() + kernel_index[ki] * dim.window_dilation();
+        if undilated % dim.base_dilation() != 0 {
+          in_input = false;
+          break;
+        }
+        let lhs_spatial = undilated / dim.base_dilation();
+        if lhs_spatial < 0 || lhs_spatial >= lhs_dims[dnums.input_spatial_dimensions(ki) as usize] {
+          in_input = false;
+          break;
+        }
+        lhs_index[dnums.input_spatial_dimensions(ki) as usize] = lhs_spatial;
+        rhs_index[dnums.kernel_spatial_dimensions(ki) as usize] = if dim.window_reversal() {
+          kernel_spatial_sizes[ki] - 1 - kernel_index[ki]
+        } else {
+          kernel_index[ki]
+        };
+      }
+      if in_input {
+        for rhs_iz in 0..input_z_size {
+          lhs_index[input_z_dim] = feature_group_index * input_z_size + rhs_iz;
+          rhs_index[kernel_input_z_dim] = rhs_iz;
+          sum = multiply_add(sum, ops[0].array()[linear_index(&lhs_index, &lhs_strides)],
+            ops[1].array()[linear_index(&rhs_index, &rhs_strides)]);
+        }
+      }
+      next_index(&mut kernel_index, &kernel_spatial_sizes);
+    }
+    result.push(normalize(sum, &t));
+    next_index(&mut out_index, output_dims);
+  }
+  Value::Array(result)
+}
+
+// Returns the operand index where the slice of a gather, or the window of a
+// scatter, with batch index 'batch_index' starts. 'batch_index' indexes the
+// dimensions of 'indices' other than 'index_vector_dim'.
+fn start_index(
+  batch_index: &Vec<i64>,
+  indices: &Vec<Element>,
+  indices_dims: &Vec<i64>,
+  index_vector_dim: i64,
+  start_index_map: &Vec<i64>,
+  operand_batching_dims: &Vec<i64>,
+  indices_batching_dims: &Vec<i64>,
+  operand_rank: usize) -> Vec<i64>
+{
+  let index_vector_dim = index_vector_dim as usize;
+  let indices_strides = strides(indices_dims);
+  let mut start = vec![0; operand_rank];
+  let mut indices_index = batch_index.clone();
+  let explicit_index_vector = index_vector_dim < indices_dims.len();
+  if explicit_index_vector {
+    indices_index.insert(index_vector_dim, 0);
+  }
+  for (k, operand_dim) in start_index_map.iter().enumerate() {
+    if explicit_index_vector {
+      indices_index[index_vector_dim] = k as i64;
+    }
+    let index = as_integer(indices[linear_index(&indices_index, &indices_strides)]);
+    start[*operand_dim as usize] = index.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+  }
+  for (operand_dim, indices_dim) in operand_batching_dims.iter().zip(indices_batching_dims) {
+    let i = *indices_dim as usize;
+    let position = if explicit_index_vector && i > index_vector_dim { i - 1 } else { i };
+    start[*operand_dim as usize] = batch_index[position];
+  }
+  start
+}
+
+fn gather(
+  instruction: &HloInstruction,
+  operand_shape: &Shape,
+  indices_shape: &Shape,
+  shape: &Shape,
+  ops: &Vec<&Value>) -> Value
+{
+  let dnums = instruction.gather_dimension_numbers();
+  let slice_sizes = instruction.gather_slice_sizes();
+  let operand_dims = operand_shape.dimensions_vec();
+  let operand_strides = strides(operand_dims);
+  let output_dims = shape.dimensions_vec();
+  let offset_dims: Vec<usize> = dnums.offset_dims().iter().map(|d| *d as usize).collect();
+  let batch_dims: Vec<usize> =
+    (0..output_dims.len()).filter(|d| !offset_dims.contains(d)).collect();
+  // The operand dimensions the offset dimensions map to, in order.
+  let offset_operand_dims: Vec<usize> = (0..operand_dims.len() as i64)
+    .filter(|d| !dnums.collapsed_slice_dims().contains(d) &&
+      !dnums.operand_batching_dims().contains(d))
+    .map(|d| d as usize).collect();
+
+  let mut result = Vec::with_capacity(element_count(output_dims));
+  let mut index = vec![0; output_dims.len()];
+  for _ in 0..element_count(output_dims) {
+    let batch_index: Vec<i64> = batch_dims.iter().map(|d| index[*d]).collect();
+    let mut operand_index = start_index(
+      &batch_index, ops[1].array(), indices_shape.dimensions_vec(), dnums.index_vector_dim(),
+      dnums.start_index_map(), dnums.operand_batching_dims(),
+      dnums.start_indices_batching_dims(), operand_dims.len());
+    for d in 0..operand_dims.len() {
+      operand_index[d] = operand_index[d].clamp(0, operand_dims[d] - slice_sizes[d]);
+    }
+    for (i, d) in offset_operand_dims.iter().enumerate() {
+      operand_index[*d] += index[offset_dims[i]];
+    }
+    result.push(ops[0].array()[linear_index(&operand_index, &operand_strides)]);
+    next_index(&mut index, output_dims);
+  }
+  Value::Array(result)
+}
+
+// Returns the k largest, or smallest, elements of each row and their indices.
+fn top_k(instruction: &HloInstruction, operand_shape: &Shape, operand: &Value) -> Value {
+  let dims = operand_shape.dimensions_vec();
+  let n = *dims.last().unwrap() as usize;
+  let k = instruction.k() as usize;
+  let rows = if n == 0 { 0 } else { element_count(dims) / n };
+  let (mut values, mut indices) = (Vec::new(), Vec::new());
+  for r in 0..rows {
+    let row = &operand.array()[r * n..(r + 1) * n];
+    let mut order: Vec<usize> = (0..n).collect();
+    if instruction.largest() {
+      order.sort_by(|a, b| total_order(row[*b], row[*a]));
+    } else {
+      order.sort_by(|a, b| total_order(row[*a], row[*b]));
+    }
+    for i in order.into_iter().take(k) {
+      values.push(row[i]);
+      indices.push(Element::Integer(i as i128));
+    }
+  }
+  Value::Tuple(vec![Value::Array(values), Value::Array(indices)])
+}
+
+// Produces (new_state, random bits) from the state operand. The random bits
+// only depend on the state.
+fn rng_bit_generator(shape: &Shape, state: &Value) -> Result<Value, String> {
+  let output_shape = shape.tuple_shapes(1);
+  let t = output_shape.element_type();
+  if !is_integral_type(&t) {
+    return Err(format!("rng-bit-generator of {:?} is not supported.", t));
+  }
+  let mut hash: u64 = 0;
+  for value in state.array() {
+    hash = hash.wrapping_mul(6364136223846793005).wrapping_add(as_integer(*value) as u64);
+  }
+  let mut engine = RandomEngine::new(hash);
+  let output: Vec<Element> = (0..element_count(output_shape.dimensions_vec()))
+    .map(|_| {
+      let bits = (engine.next_bits() << 1) | (engine.next() & 1);
+      normalize(Element::Integer(bits as i128), &t)
+    })
+    .collect();
+  let state_type = shape.tuple_shapes(0).element_type();
+  let mut new_state = state.array().clone();
+  if let Some(first) = new_state.first_mut() {
+    *first = normalize(Element::Integer(as_integer(*first) + 1), &state_type);
+  }
+  Ok(Value::Tuple(vec![Value::Array(new_state), Value::Array(output)]))
+}
+
+// Returns the elements of 'value' as f64s, for the linear algebra operations,
+// which only support real numbers.
+fn real_elements(instruction: &HloInstruction, value: &Value) -> Result<Vec<f64>, String> {
+  let mut result = Vec::with_capacity(value.array().len());
+  for element in value.array() {
+    match element {
+      Element::Complex(_, _) => return Err(format!(
+        "{} of complex numbers is not supported by the evaluator.", instruction.name())),
+      _ => result.push(as_f64(*element))
+    }
+  }
+  Ok(result)
+}
+
+fn cholesky(
+  instruction: &HloInstruction, shape: &Shape, operand: &Value) -> Result<Value, String>
+{
+  let dims = shape.dimensions_vec();
+  let n = *dims.last().unwrap() as usize;
+  let lower = instruction.cholesky_options().lower();
+  let t = shape.element_type();
+  let operand = real_elements(instruction, operand)?;
+  let mut result = Vec::with_capacity(operand.len());
+  for a in operand.chunks(n * n) {
+    // The factorization reads the triangle it is asked for.
+    let entry = |i: usize, j: usize| if lower { a[i * n + j] } else { a[j * n + i] };
+    let mut l = vec![0.0; n * n];
+    for j in 0..n {
+      let diagonal = entry(j, j) - (0..j).map(|k| l[j * n + k] * l[j * n + k]).sum::<f64>();
+      l[j * n + j] = diagonal.sqrt();
+      for i in j + 1..n {
+        let sum: f64 = (0..j).map(|k| l[i * n + k] * l[j * n + k]).sum();
+        l[i * n + j] = (entry(i, j) - sum) / l[j * n + j];
+      }
+    }
+    for i in 0..n {
+      for j in 0..n {
+        let value = if lower { l[i * n + j] } else { l[j * n + i] };
+        result.push(normalize(Element::Real(value), &t));
+      }
+    }
+  }
+  Ok(Value::Array(result))
+}
+
+// Solves t * x = b for x, where t is a lower or upper triangular n x n matrix.
+fn solve_triangular<F>(t: F, lower: bool, n: usize, b: &Vec<f64>) -> Vec<f64>
+  where F: Fn(usize, usize) -> f64
+{
+  let mut x = vec![0.0; n];
+  let rows: Vec<usize> = if lower { (0..n).collect() } else { (0..n).rev().collect() };
+  for i in rows {
+    let mut sum = b[i];
+    for j in 0..n {
+      if j != i && ((lower && j < i) || (!lower && j > i)) {
+        sum -= t(i, j) * x[j];
+      }
+    }
+    x[i] = sum / t(i, i);
+  }
+  x
+}
+
+fn triangular_solve(
+  instruction: &HloInstruction,
+  a_shape: &Shape,
+  shape: &Shape,
+  ops: &Vec<&Value>) -> Result<Value, String>
+{
+  let options = instruction.triangular_solve_options();
+  let m = *a_shape.dimensions_vec().last().unwrap() as usize;
+  let b_dims = shape.dimensions_vec();
+  let rows = b_dims[b_dims.len() - 2] as usize;
+  let cols = b_dims[b_dims.len() - 1] as usize;
+  let transpose_a = matches!(options.transpose_a(), Transpose::Transpose | Transpose::Adjoint);
+  let (lower, unit_diagonal) = (options.lower(), options.unit_diagonal());
+  let t = shape.element_type();
+
+  let (a_elements, b_elements) =
+    (real_elements(instruction, ops[0])?, real_elements(instruction, ops[1])?);
+  let mut result = Vec::with_capacity(b_elements.len());
+  for (a, b) in a_elements.chunks(m * m).zip(b_elements.chunks(rows * cols)) {
+    // The entries of a outside of its referenced triangle are ignored.
+    let a_entry = |i: usize, j: usize| {
+      if i == j {
+        if unit_diagonal { 1.0 } else { a[i * m + i] }
+      } else if (lower && i > j) || (!lower && i < j) {
+        a[i * m + j]
+      } else {
+        0.0
+      }
+    };
+    let op_a = |i: usize, j: usize| if transpose_a { a_entry(j, i) } else { a_entry(i, j) };
+    let op_a_lower = lower != transpose_a;
+    let mut x = vec![0.0; rows * cols];
+    if options.left_side() {
+      // op(a) * x = b, one column of x at a time.
+      for c in 0..cols {
+        let column = (0..rows).map(|r| b[r * cols + c]).collect();
+        for (r, value) in solve_triangular(&op_a, op_a_lower, rows, &column).into_iter().enumerate() {
+          x[r * cols + c] = value;
+        }
+      }
+    } else {
+      // x * op(a) = b is op(a)^T * x^T = b^T, one row of x at a time.
+      let op_a_t = |i: usize, j: usize| op_a(j, i);
+      for r in 0..rows {
+        let row = b[r * cols..(r + 1) * cols].to_vec();
+        for (c, value) in solve_triangular(&op_a_t, !op_a_lower, cols, &row).into_iter().enumerate() {
+          x[r * cols + c] = value;
+        }
+      }
+    }
+    result.extend(x.into_iter().map(|v| normalize(Element::Real(v), &t)));
+  }
+  Ok(Value::Array(result))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use common::{
+    blitz_data::CustomCallApiVersion,
+    comparison_util::{ComparisonDirection, ComparisonType}
+  };
+  use crate::hlo_module_config::HloModuleConfig;
+  use num::complex::Complex64;
+
+  fn r0(t: PrimitiveType) -> Shape {
+    ShapeUtil::make_shape(&t, vec![])
+  }
+
+  fn r1(t: PrimitiveType, size: i64) -> Shape {
+    ShapeUtil::make_shape(&t, vec![size])
+  }
+
+  fn scalar(value: f64) -> Literal<f64> {
+    array_literal(&vec![], vec![value])
+  }
+
+  // Creates a constant of 'shape' holding the row-major values of 'literal'.
+  fn make_constant(shape: &Shape, literal: Literal<f64>) -> HloInstruction {
+    HloInstruction::create_constant(DynLiteral::from_literal_f64(shape, &literal))
+  }
+
+  // Builds `add = add(p0, c)` where c is the constant {100, -128, 7}.
+  fn make_add_module() -> HloModule {
+    let shape = r1(PrimitiveType::S8, 3);
+    let mut computation = HloComputation::new("entry".to_string());
+    let p0 = computation.add_parameter(
+      HloInstruction::create_parameter(0, &shape, "p0".to_string()));
+    let c = computation.add_instruction(make_constant(
+      &shape, array_literal(&vec![3], vec![100.0, -128.0, 7.0])), "c".to_string());
+    let add = computation.add_instruction(HloInstruction::create_binary(
+      &shape, HloOpcode::Add, computation.instruction(p0), computation.instruction(c)),
+      "add".to_string());
+    computation.set_root_instruction(add, false);
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    module.add_entry_computation(computation);
+    module
+  }
+
+  // Builds a module whose entry counts from 0 to 5 in a while loop.
+  fn make_while_module() -> HloModule {
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    let s32 = r0(PrimitiveType::S32);
+
+    let mut condition = HloComputation::new("cond".to_string());
+    let i = condition.add_parameter(
+      HloInstruction::create_parameter(0, &s32, "i".to_string()));
+    let n = condition.add_instruction(
+      make_constant(&s32, scalar(5.0)), "".to_string());
+    let lt = condition.add_instruction(HloInstruction::create_compare(
+      &r0(PrimitiveType::Pred), condition.instruction(i), condition.instruction(n),
+      ComparisonDirection::Lt, ComparisonType::Signed), "".to_string());
+    condition.set_root_instruction(lt, false);
+    let condition_id = module.add_embedded_computation(condition);
+
+    let mut body = HloComputation::new("body".to_string());
+    let i = body.add_parameter(HloInstruction::create_parameter(0, &s32, "i".to_string()));
+    let one = body.add_instruction(
+      make_constant(&s32, scalar(1.0)), "".to_string());
+    let next = body.add_instruction(HloInstruction::create_binary(
+      &s32, HloOpcode::Add, body.instruction(i), body.instruction(one)), "".to_string());
+    body.set_root_instruction(next, false);
+    let body_id = module.add_embedded_computation(body);
+
+    let mut entry = HloComputation::new("entry".to_string());
+    let zero = entry.add_instruction(
+      make_constant(&s32, scalar(0.0)), "".to_string());
+    let while_id = entry.add_instruction(HloInstruction::create_while(
+      &s32, condition_id, body_id, entry.instruction(zero)), "".to_string());
+    entry.set_root_instruction(while_id, false);
+    module.add_entry_computation(entry);
+    module
+  }
+
+  #[test]
+  fn test_evaluate_module_wraps_integers() {
+    let module = make_add_module();
+    let mut evaluator = HloEvaluator::default();
+    let result = evaluator.evaluate_module(
+      &module, &vec![array_literal(&vec![3], vec![100.0, -1.0, 0.0])]);
+    assert_eq!(result.unwrap().data(&vec![]), &vec![-56.0, 127.0, 7.0]);
+
+    let result = evaluator.evaluate_module(&module, &vec![]);
+    assert_eq!(result.err().unwrap(), "Expected 1 arguments, but got 0.");
+  }
+
+  // Builds `root = opcode(p0, c)` where c is the scalar constant 'constant'.
+  fn make_binary_module(t: PrimitiveType, opcode: HloOpcode, constant: f64) -> HloModule {
+    let shape = r0(t);
+    let mut computation = HloComputation::new("entry".to_string());
+    let p0 = computation.add_parameter(
+      HloInstruction::create_parameter(0, &shape, "p0".to_string()));
+    let c = computation.add_instruction(
+      make_constant(&shape, scalar(constant)), "c".to_string());
+    let root = computation.add_instruction(HloInstruction::create_binary(
+      &shape, opcode, computation.instruction(p0), computation.instruction(c)),
+      "root".to_string());
+    computation.set_root_instruction(root, false);
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    module.add_entry_computation(computation);
+    module
+  }
+
+  #[test]
+  fn test_64_bit_integers_are_exact() {
+    let mut evaluator = HloEvaluator::default();
+    let module = make_binary_module(PrimitiveType::S64, HloOpcode::Add, 0.0);
+    let result = evaluator.evaluate_module_dyn(
+      &module, &vec![DynLiteral::create_r0(9007199254740993_i64)]).unwrap();
+    assert_eq!(result.get::<i64>(&vec![], &vec![]), 9007199254740993);
+
+    let module = make_binary_module(PrimitiveType::S64, HloOpcode::Add, 1.0);
+    let result = evaluator.evaluate_module_dyn(
+      &module, &vec![DynLiteral::create_r0(i64::MAX)]).unwrap();
+    assert_eq!(result.get::<i64>(&vec![], &vec![]), i64::MIN);
+
+    // The constant u64::MAX is exact; only values above it saturate.
+    let module = make_binary_module(PrimitiveType::U64, HloOpcode::Xor, u64::MAX as f64);
+    let result = evaluator.evaluate_module_dyn(
+      &module, &vec![DynLiteral::create_r0(1_u64)]).unwrap();
+    assert_eq!(result.get::<u64>(&vec![], &vec![]), u64::MAX - 1);
+
+    let module = make_binary_module(PrimitiveType::U64, HloOpcode::Multiply, 3.0);
+    let result = evaluator.evaluate_module_dyn(
+      &module, &vec![DynLiteral::create_r0((1_u64 << 62) + 1)]).unwrap();
+    assert_eq!(result.get::<u64>(&vec![], &vec![]), (3_u64 << 62) + 3);
+  }
+
+  #[test]
+  fn test_complex_elements() {
+    let mut evaluator = HloEvaluator::default();
+    let module = make_binary_module(PrimitiveType::C128, HloOpcode::Multiply, 2.0);
+    let arg = DynLiteral::create_r0(Complex64::new(1.5, -2.0));
+    let result = evaluator.evaluate_module_dyn(&module, &vec![arg.clone()]).unwrap();
+    assert_eq!(result.get::<Complex64>(&vec![], &vec![]), Complex64::new(3.0, -4.0));
+
+    // Complex results can not be returned through a Literal<f64>.
+    let result = evaluator.evaluate_module(&module, &vec![scalar(1.0)]);
+    assert!(result.is_err());
+
+    // abs(complex(3, p0)) with p0 = 4.
+    let f64_shape = r0(PrimitiveType::F64);
+    let mut computation = HloComputation::new("entry".to_string());
+    let p0 = computation.add_parameter(
+      HloInstruction::create_parameter(0, &f64_shape, "p0".to_string()));
+    let re = computation.add_instruction(
+      make_constant(&f64_shape, scalar(3.0)), "re".to_string());
+    let complex = computation.add_instruction(HloInstruction::create_binary(
+      &r0(PrimitiveType::C128), HloOpcode::Complex, computation.instruction(re),
+      computation.instruction(p0)), "complex".to_string());
+    let abs = computation.add_instruction(HloInstruction::create_unary(
+      &f64_shape, HloOpcode::Abs, computation.instruction(complex)), "abs".to_string());
+    computation.set_root_instruction(abs, false);
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    module.add_entry_computation(computation);
+    let result = evaluator.evaluate_module(&module, &vec![scalar(4.0)]).unwrap();
+    assert_eq!(result.get_first_element(), &5.0);
+  }
+
+  #[test]
+  fn test_evaluate_instruction() {
+    let module = make_add_module();
+    let computation = module.entry_computation().unwrap();
+    let mut evaluator = HloEvaluator::default();
+    let add = computation.root_instruction();
+    assert_eq!(evaluator.evaluate(&module, add, false).err().unwrap(),
+      "Not all operands are constants.");
+    assert!(!evaluator.evaluate(&module, add, true).unwrap().is_known(&vec![]));
+
+    let mut substitutions = HashMap::new();
+    substitutions.insert(add.operand(0), array_literal(&vec![3], vec![1.0, 2.0, 3.0]));
+    let result = evaluator.evaluate_with_substitutions(&module, add, &substitutions);
+    assert_eq!(result.unwrap().data(&vec![]), &vec![101.0, -126.0, 10.0]);
+  }
+
+  // Builds `tuple = tuple(c, p0)` and `add = add(get-tuple-element(tuple, 0), c)`
+  // where c is the constant 'value'.
+  fn make_partial_module(value: f64) -> HloModule {
+    let s32 = r0(PrimitiveType::S32);
+    let mut computation = HloComputation::new("entry".to_string());
+    let p0 = computation.add_parameter(
+      HloInstruction::create_parameter(0, &s32, "p0".to_string()));
+    let c = computation.add_instruction(
+      make_constant(&s32, scalar(value)), "c".to_string());
+    let tuple = computation.add_instruction(HloInstruction::create_tuple(
+      &[computation.instruction(c), computation.instruction(p0)]), "tuple".to_string());
+    let gte = computation.add_instruction(HloInstruction::create_get_tuple_element(
+      computation.instruction(tuple), 0), "gte".to_string());
+    let add = computation.add_instruction(HloInstruction::create_binary(
+      &s32, HloOpcode::Add, computation.instruction(gte), computation.instruction(c)),
+      "add".to_string());
+    computation.set_root_instruction(add, false);
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    module.add_entry_computation(computation);
+    module
+  }
+
+  #[test]
+  fn test_partial_evaluation() {
+    let module = make_partial_module(3.0);
+    let computation = module.entry_computation().unwrap();
+    let add = computation.root_instruction();
+    let gte = computation.instruction(add.operand(0));
+    let tuple = computation.instruction(gte.operand(0));
+    let mut evaluator = HloEvaluator::default();
+
+    let result = evaluator.evaluate(&module, tuple, true).unwrap();
+    assert!(!result.is_known(&vec![]));
+    assert!(result.is_known(&vec![0]));
+    assert!(!result.is_known(&vec![1]));
+    assert_eq!(result.data(&vec![0]), &vec![3.0]);
+
+    let result = evaluator.evaluate(&module, add, true).unwrap();
+    assert!(result.is_known(&vec![]));
+    assert_eq!(result.get_first_element(), &6.0);
+
+    // Values of another module are not taken from the cache.
+    let other = make_partial_module(5.0);
+    let root = other.entry_computation().unwrap().root_instruction();
+    assert_eq!(evaluator.evaluate(&other, root, true).unwrap().get_first_element(), &10.0);
+    evaluator.reset_visit_states();
+    assert_eq!(evaluator.evaluate(&module, add, true).unwrap().get_first_element(), &6.0);
+  }
+
+  #[test]
+  fn test_partial_evaluation_after_module_change() {
+    let mut module = make_partial_module(3.0);
+    let mut evaluator = HloEvaluator::default();
+    {
+      let computation = module.entry_computation().unwrap();
+      let add = computation.root_instruction();
+      assert_eq!(evaluator.evaluate(&module, add, true).unwrap().get_first_element(), &6.0);
+    }
+
+    // Replace the constant 3 by 5; the cached values of the module are stale.
+    let computation = module.mutable_entry_computation().unwrap();
+    let c = computation.instruction(computation.root_instruction().operand(1)).id();
+    let five = computation.add_instruction(
+      make_constant(&r0(PrimitiveType::S32), scalar(5.0)), "five".to_string());
+    computation.replace_instruction(c, five, true, true, true).unwrap();
+
+    let add = module.entry_computation().unwrap().root_instruction();
+    assert_eq!(evaluator.evaluate(&module, add, true).unwrap().get_first_element(), &10.0);
+  }
+
+  #[test]
+  fn test_reduce_fast_path() {
+    let f32 = r0(PrimitiveType::F32);
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    let mut add = HloComputation::new("add".to_string());
+    let x = add.add_parameter(HloInstruction::create_parameter(0, &f32, "x".to_string()));
+    let y = add.add_parameter(HloInstruction::create_parameter(1, &f32, "y".to_string()));
+    let sum = add.add_instruction(HloInstruction::create_binary(
+      &f32, HloOpcode::Add, add.instruction(x), add.instruction(y)), "".to_string());
+    add.set_root_instruction(sum, false);
+    let add_id = module.add_embedded_computation(add);
+
+    let mut entry = HloComputation::new("entry".to_string());
+    let input = entry.add_instruction(make_constant(
+      &ShapeUtil::make_shape(&PrimitiveType::F32, vec![2, 3]),
+      array_literal(&vec![2, 3], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0])), "".to_string());
+    let init = entry.add_instruction(
+      make_constant(&f32, scalar(0.0)), "".to_string());
+    let reduce = entry.add_instruction(HloInstruction::create_reduce(
+      &r1(PrimitiveType::F32, 3), entry.instruction(input), entry.instruction(init),
+      vec![0], add_id), "".to_string());
+    entry.set_root_instruction(reduce, false);
+    module.add_entry_computation(entry);
+
+    let mut evaluator = HloEvaluator::default();
+    let slow = evaluator.evaluate_module(&module, &vec![]).unwrap();
+    evaluator.set_use_fast_path(true);
+    let fast = evaluator.evaluate_module(&module, &vec![]).unwrap();
+    assert_eq!(slow.data(&vec![]), &vec![5.0, 7.0, 9.0]);
+    assert_eq!(fast.data(&vec![]), slow.data(&vec![]));
+  }
+
+  #[test]
+  fn test_while_loop_iteration_limit() {
+    let module = make_while_module();
+    let mut evaluator = HloEvaluator::default();
+    assert_eq!(evaluator.evaluate_module(&module, &vec![]).unwrap().data(&vec![]), &vec![5.0]);
+
+    let mut limited = HloEvaluator::new(3);
+    let result = limited.evaluate_module(&module, &vec![]);
+    assert!(result.err().unwrap().contains("exceeded loop iteration limit (3)"));
+  }
+
+  #[test]
+  fn test_elementwise_ops() {
+    let evaluator = HloEvaluator::default();
+    let lhs = array_literal(&vec![2], vec![1.0, f64::NAN]);
+    let rhs = array_literal(&vec![2], vec![2.0, 0.0]);
+    let max = evaluator.evaluate_elementwise_binary_op(
+      &HloOpcode::Maximum, &PrimitiveType::F32, &lhs, &rhs).unwrap();
+    assert_eq!(max.data(&vec![])[0], 2.0);
+    assert!(max.data(&vec![])[1].is_nan());
+
+    let lt = evaluator.evaluate_elementwise_compare_op(
+      &ComparisonDirection::Lt, &ComparisonType::FloatTotalOrder, &lhs, &rhs).unwrap();
+    assert_eq!(lt.data(&vec![]), &vec![1.0, 0.0]);
+
+    let bad = array_literal(&vec![3], vec![0.0; 3]);
+    assert!(evaluator.evaluate_elementwise_binary_op(
+      &HloOpcode::Add, &PrimitiveType::F32, &lhs, &bad).is_err());
+  }
+
+  #[test]
+  fn test_custom_call_handler_gets_exact_literals() {
+    let s64 = r0(PrimitiveType::S64);
+    let mut computation = HloComputation::new("entry".to_string());
+    let c = computation.add_instruction(HloInstruction::create_constant(
+      DynLiteral::create_r0(9007199254740993_i64)), "c".to_string());
+    let call = computation.add_instruction(HloInstruction::create_custom_call(
+      &s64, &[computation.instruction(c)], "increment".to_string(), "".to_string(),
+      CustomCallApiVersion::Original), "call".to_string());
+    computation.set_root_instruction(call, false);
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    module.add_entry_computation(computation);
+
+    let mut evaluator = HloEvaluator::default();
+    evaluator.set_custom_call_handler(Rc::new(|_, operands| {
+      let value: i64 = operands[0].get(&vec![], &vec![]);
+      Ok(DynLiteral::create_r0(value + 1))
+    }));
+    let result = evaluator.evaluate_module_dyn(&module, &vec![]).unwrap();
+    assert_eq!(result.get::<i64>(&vec![], &vec![]), 9007199254740994);
+  }
+}
@@ -0,0 +1,689 @@
+#![allow(dead_code)]
+
+use std::cmp::Ordering;
+
+use common::{
+  blitz_data::PrimitiveType,
+  comparison_util::{ComparisonDirection, ComparisonType},
+  dyn_literal::Element,
+  primitive_util::{
+    bit_width, complex_component_type, float_from_bits, float_to_bits, is_complex_type,
+    is_floating_point_type, is_integral_type, is_signed_integral_type,
+    is_unsigned_integral_type, pow2, round_to_format, round_to_type
+  }
+};
+use num::complex::Complex64;
+
+use crate::hlo_opcode::{hlo_opcode_string, HloOpcode};
+
+// The evaluator holds every array element as an Element whose variant
+// follows the element type of the array: integers and predicates are exact
+// Integer values, floating point values are Real values rounded to the
+// precision of their type and complex values are Complex values whose parts
+// are rounded the same way. The functions here implement the per-element
+// semantics of each opcode for the type of the element: integers wrap around
+// like two's complement arithmetic and predicates are 0 or 1.
+
+// Implements reduce-precision: rounds 'value' to a floating point format with
+// the given number of exponent and mantissa bits. Results beyond the range of
+// the format become infinity and results below its smallest normal number are
+// flushed to zero.
+pub fn reduce_precision(value: f64, exponent_bits: i64, mantissa_bits: i64) -> f64 {
+  if value.is_nan() {
+    return value;
+  }
+  let (min_exponent, max_value) = if exponent_bits >= 11 {
+    (-1022, f64::MAX)
+  } else {
+    let bias = (1_i64 << (exponent_bits - 1)) - 1;
+    (1 - bias, (2.0 - pow2(-mantissa_bits)) * pow2(bias))
+  };
+  let rounded = round_to_format(value, mantissa_bits + 1, min_exponent, false);
+  if rounded.abs() > max_value {
+    return f64::INFINITY.copysign(value);
+  }
+  rounded
+}
+
+// Wraps 'value' into the range of the integral type 't', as two's complement
+// arithmetic does.
+pub fn wrap_integer(value: i128, t: &PrimitiveType) -> i128 {
+  if *t == PrimitiveType::Pred {
+    return (value != 0) as i128;
+  }
+  let bits = bit_width(t) as u32;
+  let modulus = 1_i128 << bits;
+  let mut wrapped = value.rem_euclid(modulus);
+  if is_signed_integral_type(t) && wrapped >= modulus / 2 {
+    wrapped -= modulus;
+  }
+  wrapped
+}
+
+// Returns the two's complement bit pattern of the integral 'value' of type 't'.
+pub fn integer_bits(value: i128, t: &PrimitiveType) -> u64 {
+  let bits = bit_width(t) as u32;
+  value.rem_euclid(1_i128 << bits) as u64
+}
+
+// Returns the value of type 't' whose bit pattern is 'bits'.
+pub fn integer_from_bits(bits: u64, t: &PrimitiveType) -> i128 {
+  wrap_integer(bits as i128, t)
+}
+
+fn integer_min_max(t: &PrimitiveType) -> (i128, i128) {
+  let bits = bit_width(t) as u32;
+  if is_unsigned_integral_type(t) {
+    (0, (1_i128 << bits) - 1)
+  } else {
+    (-(1_i128 << (bits - 1)), (1_i128 << (bits - 1)) - 1)
+  }
+}
+
+// Returns the integral or predicate 'value'. Other values are truncated.
+pub fn as_integer(value: Element) -> i128 {
+  match value {
+    Element::Integer(v) => v,
+    Element::Real(v) | Element::Complex(v, _) => v as i128
+  }
+}
+
+// Returns the real 'value', or the real part of a complex one.
+pub fn as_f64(value: Element) -> f64 {
+  match value {
+    Element::Integer(v) => v as f64,
+    Element::Real(v) | Element::Complex(v, _) => v
+  }
+}
+
+fn as_complex(value: Element) -> Complex64 {
+  match value {
+    Element::Integer(v) => Complex64::new(v as f64, 0.0),
+    Element::Real(v) => Complex64::new(v, 0.0),
+    Element::Complex(re, im) => Complex64::new(re, im)
+  }
+}
+
+// Returns true if the predicate 'value' is set.
+pub fn is_true(value: Element) -> bool {
+  match value {
+    Element::Integer(v) => v != 0,
+    Element::Real(v) => v != 0.0,
+    Element::Complex(re, im) => re != 0.0 || im != 0.0
+  }
+}
+
+pub fn bool_element(value: bool) -> Element {
+  Element::Integer(value as i128)
+}
+
+// Returns the zero of type 't'.
+pub fn zero_element(t: &PrimitiveType) -> Element {
+  if is_complex_type(t) {
+    Element::Complex(0.0, 0.0)
+  } else if is_floating_point_type(t) {
+    Element::Real(0.0)
+  } else {
+    Element::Integer(0)
+  }
+}
+
+// Returns the complex 'value' with its parts rounded to the component type of
+// the complex type 't'.
+fn complex_element(value: Complex64, t: &PrimitiveType) -> Element {
+  let component = complex_component_type(t.clone());
+  Element::Complex(round_to_type(value.re, &component), round_to_type(value.im, &component))
+}
+
+// Returns 'value' as an element of type 't': integers are wrapped, floating
+// point values are rounded and predicates are 0 or 1. Real values are
+// truncated when they become integers.
+pub fn normalize(value: Element, t: &PrimitiveType) -> Element {
+  if *t == PrimitiveType::Pred {
+    return bool_element(is_true(value));
+  }
+  if is_integral_type(t) {
+    return Element::Integer(wrap_integer(as_integer(value), t));
+  }
+  if is_floating_point_type(t) {
+    return Element::Real(round_to_type(as_f64(value), t));
+  }
+  if is_complex_type(t) {
+    return complex_element(as_complex(value), t);
+  }
+  value
+}
+
+// Converts 'value' from type 'from' to type 'to'. Integers are wrapped into
+// a narrower integral type, floating point values are truncated towards zero
+// and saturated when converted to an integral type, with NaN becoming 0.
+// Complex numbers can only be converted to a complex type.
+pub fn convert_value(
+  value: Element, from: &PrimitiveType, to: &PrimitiveType) -> Result<Element, String>
+{
+  if is_complex_type(from) && !is_complex_type(to) {
+    return Err(format!("convert from {:?} to {:?} is not supported", from, to));
+  }
+  if is_integral_type(to) {
+    if let Element::Real(v) = value {
+      if v.is_nan() {
+        return Ok(Element::Integer(0));
+      }
+      let (min, max) = integer_min_max(to);
+      return Ok(Element::Integer((v.trunc() as i128).clamp(min, max)));
+    }
+  }
+  Ok(normalize(value, to))
+}
+
+// Returns the f64 'value' of a Literal<f64> as an element of type 't'.
+// Integers saturate, so the extremes of S64 and U64, which an f64 rounds
+// beyond the range of the type, stay exact.
+pub fn element_from_f64(value: f64, t: &PrimitiveType) -> Element {
+  convert_value(Element::Real(value), &PrimitiveType::F64, t).unwrap()
+}
+
+// Returns the bit pattern of 'value' of type 't'. Only types whose bit
+// pattern can be rebuilt from the value are supported.
+pub fn bit_pattern(value: Element, t: &PrimitiveType) -> Result<u64, String> {
+  match t {
+    _ if is_floating_point_type(t) => Ok(float_to_bits(as_f64(value), t)),
+    _ if is_integral_type(t) || *t == PrimitiveType::Pred =>
+      Ok(integer_bits(as_integer(value), t)),
+    _ => Err(format!("bitcast-convert of {:?} is not supported", t))
+  }
+}
+
+// Returns the value of type 't' whose bit pattern is 'bits'.
+pub fn from_bit_pattern(bits: u64, t: &PrimitiveType) -> Result<Element, String> {
+  match t {
+    _ if is_floating_point_type(t) => Ok(Element::Real(float_from_bits(bits, t))),
+    _ if is_integral_type(t) || *t == PrimitiveType::Pred =>
+      Ok(Element::Integer(integer_from_bits(bits, t))),
+    _ => Err(format!("bitcast-convert to {:?} is not supported", t))
+  }
+}
+
+// Returns the error function of 'x'. The Taylor series is used near zero and
+// a continued fraction for the complementary function elsewhere.
+pub fn erf(x: f64) -> f64 {
+  if x.is_nan() {
+    return x;
+  }
+  let a = x.abs();
+  if a < 2.5 {
+    let mut term = a;
+    let mut sum = a;
+    let mut n = 0.0;
+    while term.abs() > 1e-17 * sum.abs() {
+      n += 1.0;
+      term *= -a * a / n;
+      sum += term / (2.0 * n + 1.0);
+    }
+    return (2.0 / std::f64::consts::PI.sqrt() * sum).copysign(x);
+  }
+  if a > 6.0 {
+    return 1.0_f64.copysign(x);
+  }
+  let mut fraction = a;
+  for n in (1..120).rev() {
+    fraction = a + (n as f64 / 2.0) / fraction;
+  }
+  let erfc = (-a * a).exp() / std::f64::consts::PI.sqrt() / fraction;
+  (1.0 - erfc).copysign(x)
+}
+
+fn unsupported(opcode: &HloOpcode, t: &PrimitiveType) -> String {
+  format!("{} is not supported for element type {:?}", hlo_opcode_string(opcode), t)
+}
+
+// Applies the elementwise unary 'opcode' to 'value' of type 't'.
+pub fn unary_op(opcode: &HloOpcode, value: Element, t: &PrimitiveType) -> Result<Element, String> {
+  if *t == PrimitiveType::Pred {
+    let v = is_true(value);
+    return match opcode {
+      HloOpcode::Not => Ok(bool_element(!v)),
+      HloOpcode::Abs | HloOpcode::Copy | HloOpcode::Sign => Ok(bool_element(v)),
+      _ => Err(unsupported(opcode, t))
+    };
+  }
+  if is_integral_type(t) {
+    let v = as_integer(value);
+    let bits = bit_width(t) as u32;
+    let result = match opcode {
+      HloOpcode::Abs => wrap_integer(v.abs(), t),
+      HloOpcode::Negate => wrap_integer(-v, t),
+      HloOpcode::Sign => v.signum(),
+      HloOpcode::Not => integer_from_bits(!integer_bits(v, t), t),
+      HloOpcode::PopulationCount => integer_bits(v, t).count_ones() as i128,
+      HloOpcode::Clz => (integer_bits(v, t).leading_zeros() - (64 - bits)) as i128,
+      HloOpcode::Copy | HloOpcode::Real => v,
+      HloOpcode::Imag => 0,
+      _ => return Err(unsupported(opcode, t))
+    };
+    return Ok(Element::Integer(result));
+  }
+  if is_complex_type(t) {
+    return complex_unary_op(opcode, as_complex(value), t);
+  }
+  if !is_floating_point_type(t) {
+    return Err(unsupported(opcode, t));
+  }
+  let value = as_f64(value);
+  let result = match opcode {
+    HloOpcode::Abs => value.abs(),
+    HloOpcode::Negate => -value,
+    HloOpcode::Sign => if value.is_nan() || value == 0.0 { value } else { value.signum() },
+    HloOpcode::Ceil => value.ceil(),
+    HloOpcode::Floor => value.floor(),
+    HloOpcode::RoundNearestAfz => value.round(),
+    HloOpcode::RoundNearestEven => value.round_ties_even(),
+    HloOpcode::Cos => value.cos(),
+    HloOpcode::Sin => value.sin(),
+    HloOpcode::Tan => value.tan(),
+    HloOpcode::Tanh => value.tanh(),
+    HloOpcode::Exp => value.exp(),
+    HloOpcode::Expm1 => value.exp_m1(),
+    HloOpcode::Log => value.ln(),
+    HloOpcode::Log1p => value.ln_1p(),
+    HloOpcode::Logistic => 1.0 / (1.0 + (-value).exp()),
+    HloOpcode::Sqrt => value.sqrt(),
+    HloOpcode::Rsqrt => 1.0 / value.sqrt(),
+    HloOpcode::Cbrt => value.cbrt(),
+    HloOpcode::Erf => erf(value),
+    HloOpcode::IsFinite => return Ok(bool_element(value.is_finite())),
+    HloOpcode::Copy | HloOpcode::Real => value,
+    HloOpcode::Imag => 0.0,
+    _ => return Err(unsupported(opcode, t))
+  };
+  Ok(Element::Real(round_to_type(result, t)))
+}
+
+// Applies the elementwise unary 'opcode' to the complex 'value' of type 't'.
+// Abs, Real and Imag give an element of the component type.
+fn complex_unary_op(
+  opcode: &HloOpcode, value: Complex64, t: &PrimitiveType) -> Result<Element, String>
+{
+  let component = complex_component_type(t.clone());
+  let result = match opcode {
+    HloOpcode::Abs => return Ok(Element::Real(round_to_type(value.norm(), &component))),
+    HloOpcode::Real => return Ok(Element::Real(value.re)),
+    HloOpcode::Imag => return Ok(Element::Real(value.im)),
+    HloOpcode::Negate => -value,
+    HloOpcode::Sign =>
+      if value.re == 0.0 && value.im == 0.0 { value } else { value.unscale(value.norm()) },
+    HloOpcode::Cos => value.cos(),
+    HloOpcode::Sin => value.sin(),
+    HloOpcode::Tan => value.tan(),
+    HloOpcode::Tanh => value.tanh(),
+    HloOpcode::Exp => value.exp(),
+    HloOpcode::Expm1 => value.exp() - 1.0,
+    HloOpcode::Log => value.ln(),
+    HloOpcode::Log1p => (value + 1.0).ln(),
+    HloOpcode::Sqrt => value.sqrt(),
+    HloOpcode::Rsqrt => value.sqrt().inv(),
+    HloOpcode::Copy => value,
+    _ => return Err(unsupported(opcode, t))
+  };
+  Ok(complex_element(result, t))
+}
+
+// Raises the integer 'base' to the power 'exponent' with wrap around. A
+// negative exponent gives 0 unless the base is 1 or -1.
+fn integer_power(base: i128, exponent: i128, t: &PrimitiveType) -> i128 {
+  if exponent < 0 {
+    return match base {
+      1 => 1,
+      -1 => if exponent % 2 == 0 { 1 } else { -1 },
+      _ => 0
+    };
+  }
+  // Products wrap around at 128 bits, which keeps the bits of the type exact.
+  let mut result: i128 = 1;
+  let mut base = base;
+  let mut exponent = exponent;
+  while exponent > 0 {
+    if exponent & 1 == 1 {
+      result = result.wrapping_mul(base);
+    }
+    base = base.wrapping_mul(base);
+    exponent >>= 1;
+  }
+  wrap_integer(result, t)
+}
+
+// Applies the elementwise binary 'opcode' to 'lhs' and 'rhs' of type 't'.
+pub fn binary_op(
+  opcode: &HloOpcode, lhs: Element, rhs: Element, t: &PrimitiveType) -> Result<Element, String>
+{
+  if *t == PrimitiveType::Pred {
+    let (a, b) = (is_true(lhs), is_true(rhs));
+    let result = match opcode {
+      HloOpcode::And | HloOpcode::Minimum | HloOpcode::Multiply => a && b,
+      HloOpcode::Or | HloOpcode::Maximum | HloOpcode::Add => a || b,
+      HloOpcode::Xor | HloOpcode::Subtract => a != b,
+      _ => return Err(unsupported(opcode, t))
+    };
+    return Ok(bool_element(result));
+  }
+  if is_integral_type(t) {
+    let (a, b) = (as_integer(lhs), as_integer(rhs));
+    let bits = bit_width(t) as i128;
+    let result = match opcode {
+      HloOpcode::Add => wrap_integer(a + b, t),
+      HloOpcode::Subtract => wrap_integer(a - b, t),
+      HloOpcode::Multiply => wrap_integer(a.wrapping_mul(b), t),
+      HloOpcode::Divide => {
+        if b == 0 {
+          // Division by zero gives all ones.
+          integer_from_bits(u64::MAX, t)
+        } else {
+          wrap_integer(a / b, t)
+        }
+      },
+      HloOpcode::Remainder => if b == 0 { a } else { wrap_integer(a % b, t) },
+      HloOpcode::Maximum => a.max(b),
+      HloOpcode::Minimum => a.min(b),
+      HloOpcode::Power => integer_power(a, b, t),
+      HloOpcode::And => integer_from_bits(integer_bits(a, t) & integer_bits(b, t), t),
+      HloOpcode::Or => integer_from_bits(integer_bits(a, t) | integer_bits(b, t), t),
+      HloOpcode::Xor => integer_from_bits(integer_bits(a, t) ^ integer_bits(b, t), t),
+      HloOpcode::ShiftLeft => {
+        let shift = integer_bits(b, t) as i128;
+        if shift >= bits { 0 } else { wrap_integer(a << shift, t) }
+      },
+      HloOpcode::ShiftRightLogical => {
+        let shift = integer_bits(b, t) as i128;
+        if shift >= bits { 0 } else { integer_from_bits(integer_bits(a, t) >> shift, t) }
+      },
+      HloOpcode::ShiftRightArithmetic => {
+        // Shift the sign extended bit pattern, so unsigned types shift in
+        // their top bit as well.
+        let pattern = integer_bits(a, t) as i128;
+        let signed = if pattern >= 1 << (bits - 1) { pattern - (1 << bits) } else { pattern };
+        let shift = (integer_bits(b, t) as i128).min(bits - 1);
+        wrap_integer(signed >> shift, t)
+      },
+      _ => return Err(unsupported(opcode, t))
+    };
+    return Ok(Element::Integer(result));
+  }
+  if is_complex_type(t) {
+    let (a, b) = (as_complex(lhs), as_complex(rhs));
+    let result = match opcode {
+      HloOpcode::Add => a + b,
+      HloOpcode::Subtract => a - b,
+      HloOpcode::Multiply => a * b,
+      HloOpcode::Divide => a / b,
+      HloOpcode::Power => a.powc(b),
+      _ => return Err(unsupported(opcode, t))
+    };
+    return Ok(complex_element(result, t));
+  }
+  if !is_floating_point_type(t) {
+    return Err(unsupported(opcode, t));
+  }
+  let (lhs, rhs) = (as_f64(lhs), as_f64(rhs));
+  let result = match opcode {
+    HloOpcode::Add => lhs + rhs,
+    HloOpcode::Subtract => lhs - rhs,
+    HloOpcode::Multiply => lhs * rhs,
+    HloOpcode::Divide => lhs / rhs,
+    HloOpcode::Remainder => lhs % rhs,
+    HloOpcode::Maximum => if lhs.is_nan() || rhs.is_nan() { f64::NAN } else { lhs.max(rhs) },
+    HloOpcode::Minimum => if lhs.is_nan() || rhs.is_nan() { f64::NAN } else { lhs.min(rhs) },
+    HloOpcode::Power => lhs.powf(rhs),
+    HloOpcode::Atan2 => lhs.atan2(rhs),
+    _ => return Err(unsupported(opcode, t))
+  };
+  Ok(Element::Real(round_to_type(result, t)))
+}
+
+// Clamps 'value' into [low, high], propagating NaN.
+pub fn clamp(low: Element, value: Element, high: Element) -> Element {
+  if let (Element::Integer(l), Element::Integer(v), Element::Integer(h)) = (low, value, high) {
+    return Element::Integer(v.max(l).min(h));
+  }
+  let (l, v, h) = (as_f64(low), as_f64(value), as_f64(high));
+  if l.is_nan() || v.is_nan() || h.is_nan() {
+    return Element::Real(f64::NAN);
+  }
+  Element::Real(v.max(l).min(h))
+}
+
+// Returns sum + lhs * rhs, the step of a dot or convolution. Nothing is
+// rounded, the caller normalizes the final sum to its type. Integers wrap
+// around at 128 bits, which keeps the bits of any integral type exact.
+pub fn multiply_add(sum: Element, lhs: Element, rhs: Element) -> Element {
+  match (sum, lhs, rhs) {
+    (Element::Integer(s), Element::Integer(a), Element::Integer(b)) =>
+      Element::Integer(s.wrapping_add(a.wrapping_mul(b))),
+    (Element::Complex(_, _), _, _) | (_, Element::Complex(_, _), _) |
+    (_, _, Element::Complex(_, _)) => {
+      let result = as_complex(sum) + as_complex(lhs) * as_complex(rhs);
+      Element::Complex(result.re, result.im)
+    },
+    _ => Element::Real(as_f64(sum) + as_f64(lhs) * as_f64(rhs))
+  }
+}
+
+// Compares 'lhs' and 'rhs' in the given direction. Float comparisons follow
+// IEEE semantics, so every comparison but Ne is false for NaN operands, while
+// FloatTotalOrder orders -NaN < -Inf < ... < -0 < +0 < ... < +Inf < +NaN.
+// Complex numbers are only compared for (in)equality.
+pub fn compare_values(
+  direction: &ComparisonDirection,
+  comparison_type: &ComparisonType,
+  lhs: Element,
+  rhs: Element) -> bool
+{
+  let ordering = match (lhs, rhs) {
+    (Element::Integer(a), Element::Integer(b)) => Some(a.cmp(&b)),
+    (Element::Complex(_, _), _) | (_, Element::Complex(_, _)) => {
+      let equal = as_complex(lhs) == as_complex(rhs);
+      return match direction {
+        ComparisonDirection::Eq => equal,
+        ComparisonDirection::Ne => !equal,
+        _ => false
+      };
+    },
+    _ if *comparison_type == ComparisonType::FloatTotalOrder =>
+      Some(as_f64(lhs).total_cmp(&as_f64(rhs))),
+    _ => as_f64(lhs).partial_cmp(&as_f64(rhs))
+  };
+  match direction {
+    ComparisonDirection::Eq => ordering == Some(Ordering::Equal),
+    ComparisonDirection::Ne => ordering != Some(Ordering::Equal),
+    ComparisonDirection::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+    ComparisonDirection::Gt => ordering == Some(Ordering::Greater),
+    ComparisonDirection::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+    ComparisonDirection::Lt => ordering == Some(Ordering::Less)
+  }
+}
+
+// Orders elements totally, like FloatTotalOrder orders floating point values.
+// Complex numbers are ordered by their real, then their imaginary part.
+pub fn total_order(lhs: Element, rhs: Element) -> Ordering {
+  if let (Element::Integer(a), Element::Integer(b)) = (lhs, rhs) {
+    return a.cmp(&b);
+  }
+  let (a, b) = (as_complex(lhs), as_complex(rhs));
+  a.re.total_cmp(&b.re).then(a.im.total_cmp(&b.im))
+}
+
+// The minimal standard linear congruential generator (std::minstd_rand0).
+// The evaluator draws all of its random numbers from it, so results only
+// depend on the seed.
+#[derive(Debug, Clone)]
+pub struct RandomEngine {
+  state: u64
+}
+
+impl RandomEngine {
+  const MODULUS: u64 = 2147483647;
+
+  pub fn new(seed: u64) -> Self {
+    let mut engine = RandomEngine { state: 1 };
+    engine.seed(seed);
+    engine
+  }
+
+  pub fn seed(&mut self, seed: u64) {
+    let state = seed % RandomEngine::MODULUS;
+    self.state = if state == 0 { 1 } else { state };
+  }
+
+  // Returns the next value, in [1, 2^31 - 2].
+  pub fn next(&mut self) -> u64 {
+    self.state = self.state * 16807 % RandomEngine::MODULUS;
+    self.state
+  }
+
+  // Returns 63 random bits.
+  pub fn next_bits(&mut self) -> u64 {
+    let mask = (1_u64 << 21) - 1;
+    ((self.next() & mask) << 42) | ((self.next() & mask) << 21) | (self.next() & mask)
+  }
+
+  // Returns a uniformly distributed value in [0, 1).
+  pub fn next_f64(&mut self) -> f64 {
+    let range = (RandomEngine::MODULUS - 1) as f64;
+    let high = (self.next() - 1) as f64;
+    let low = (self.next() - 1) as f64;
+    let value = (high + low / range) / range;
+    if value >= 1.0 { 1.0 - f64::EPSILON / 2.0 } else { value }
+  }
+
+  // Returns a normally distributed value, by the Box-Muller transform.
+  pub fn next_normal(&mut self, mean: f64, stddev: f64) -> f64 {
+    let u1 = 1.0 - self.next_f64();
+    let u2 = self.next_f64();
+    mean + stddev * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn int(value: i128) -> Element {
+    Element::Integer(value)
+  }
+
+  fn complex(re: f64, im: f64) -> Element {
+    Element::Complex(re, im)
+  }
+
+  #[test]
+  fn test_reduce_precision() {
+    assert_eq!(reduce_precision(1.0 + pow2(-11), 8, 10), 1.0);
+    assert_eq!(reduce_precision(1.0 + 3.0 * pow2(-11), 8, 10), 1.0 + pow2(-9));
+    assert_eq!(reduce_precision(1e-40, 8, 23), 0.0);
+    assert_eq!(reduce_precision(1e5, 5, 10), f64::INFINITY);
+  }
+
+  #[test]
+  fn test_integer_semantics() {
+    let s8 = PrimitiveType::S8;
+    let u8 = PrimitiveType::U8;
+    assert_eq!(binary_op(&HloOpcode::Add, int(127), int(1), &s8).unwrap(), int(-128));
+    assert_eq!(binary_op(&HloOpcode::Divide, int(7), int(0), &s8).unwrap(), int(-1));
+    assert_eq!(binary_op(&HloOpcode::Divide, int(7), int(0), &u8).unwrap(), int(255));
+    assert_eq!(binary_op(&HloOpcode::Divide, int(-128), int(-1), &s8).unwrap(), int(-128));
+    assert_eq!(binary_op(&HloOpcode::Remainder, int(-7), int(2), &s8).unwrap(), int(-1));
+    assert_eq!(binary_op(&HloOpcode::ShiftRightLogical, int(-128), int(7), &s8).unwrap(), int(1));
+    assert_eq!(binary_op(&HloOpcode::ShiftRightArithmetic, int(-128), int(9), &s8).unwrap(),
+      int(-1));
+    assert_eq!(binary_op(&HloOpcode::ShiftLeft, int(1), int(8), &u8).unwrap(), int(0));
+    assert_eq!(binary_op(&HloOpcode::Power, int(3), int(5), &u8).unwrap(), int(243));
+    assert_eq!(binary_op(&HloOpcode::Power, int(2), int(-1), &s8).unwrap(), int(0));
+    assert_eq!(unary_op(&HloOpcode::Clz, int(1), &u8).unwrap(), int(7));
+    assert_eq!(unary_op(&HloOpcode::Not, int(0), &s8).unwrap(), int(-1));
+    assert_eq!(unary_op(&HloOpcode::Abs, int(-128), &s8).unwrap(), int(-128));
+    assert_eq!(convert_value(int(300), &PrimitiveType::S32, &s8).unwrap(), int(44));
+    assert_eq!(convert_value(Element::Real(300.5), &PrimitiveType::F32, &s8).unwrap(), int(127));
+    assert_eq!(convert_value(Element::Real(f64::NAN), &PrimitiveType::F32, &s8).unwrap(),
+      int(0));
+  }
+
+  #[test]
+  fn test_64_bit_integers_are_exact() {
+    let s64 = PrimitiveType::S64;
+    let u64 = PrimitiveType::U64;
+    let u64_max = u64::MAX as i128;
+    assert_eq!(binary_op(&HloOpcode::Add, int(9007199254740993), int(0), &s64).unwrap(),
+      int(9007199254740993));
+    assert_eq!(binary_op(&HloOpcode::Xor, int(u64_max), int(1), &u64).unwrap(),
+      int(u64_max - 1));
+    assert_eq!(binary_op(&HloOpcode::Multiply, int(u64_max), int(u64_max), &u64).unwrap(),
+      int(1));
+    assert_eq!(binary_op(&HloOpcode::Add, int(i64::MAX as i128), int(1), &s64).unwrap(),
+      int(i64::MIN as i128));
+    assert_eq!(binary_op(&HloOpcode::ShiftRightLogical, int(-1), int(1), &s64).unwrap(),
+      int(i64::MAX as i128));
+    assert_eq!(binary_op(&HloOpcode::Power, int(3), int(41), &u64).unwrap(),
+      int(18026252303461234787));
+    assert_eq!(unary_op(&HloOpcode::Not, int(0), &u64).unwrap(), int(u64_max));
+    assert_eq!(element_from_f64(u64::MAX as f64, &u64), int(u64_max));
+    assert_eq!(element_from_f64(i64::MAX as f64, &s64), int(i64::MAX as i128));
+  }
+
+  #[test]
+  fn test_complex_semantics() {
+    let c64 = PrimitiveType::C64;
+    let c128 = PrimitiveType::C128;
+    assert_eq!(binary_op(&HloOpcode::Multiply, complex(1.0, 2.0), complex(3.0, -1.0), &c128)
+      .unwrap(), complex(5.0, 5.0));
+    assert_eq!(binary_op(&HloOpcode::Divide, complex(5.0, 5.0), complex(3.0, -1.0), &c128)
+      .unwrap(), complex(1.0, 2.0));
+    assert_eq!(binary_op(&HloOpcode::Add, complex(0.1, 0.0), complex(0.2, 0.0), &c64).unwrap(),
+      complex((0.1 + 0.2) as f32 as f64, 0.0));
+    assert_eq!(unary_op(&HloOpcode::Abs, complex(3.0, 4.0), &c64).unwrap(), Element::Real(5.0));
+    assert_eq!(unary_op(&HloOpcode::Imag, complex(3.0, 4.0), &c64).unwrap(), Element::Real(4.0));
+    assert!(binary_op(&HloOpcode::Maximum, complex(1.0, 0.0), complex(2.0, 0.0), &c64).is_err());
+
+    let eq = ComparisonDirection::Eq;
+    let ne = ComparisonDirection::Ne;
+    let float = ComparisonType::Float;
+    assert!(compare_values(&eq, &float, complex(1.0, 2.0), complex(1.0, 2.0)));
+    assert!(compare_values(&ne, &float, complex(1.0, 2.0), complex(1.0, 3.0)));
+    assert_eq!(convert_value(Element::Real(1.5), &PrimitiveType::F32, &c64).unwrap(),
+      complex(1.5, 0.0));
+    assert!(convert_value(complex(1.0, 2.0), &c64, &PrimitiveType::F32).is_err());
+  }
+
+  #[test]
+  fn test_bit_patterns() {
+    let bits = bit_pattern(Element::Real(1.0), &PrimitiveType::F32).unwrap();
+    assert_eq!(bits, 0x3f800000);
+    assert_eq!(from_bit_pattern(bits, &PrimitiveType::S32).unwrap(), int(1065353216));
+    for value in [0.0, -2.5, 65504.0, pow2(-24), f64::INFINITY] {
+      let bits = bit_pattern(Element::Real(value), &PrimitiveType::F16).unwrap();
+      assert_eq!(from_bit_pattern(bits, &PrimitiveType::F16).unwrap(), Element::Real(value));
+    }
+    assert_eq!(bit_pattern(Element::Real(-1.0), &PrimitiveType::BF16).unwrap(), 0xbf80);
+    let bits = bit_pattern(Element::Real(-0.5), &PrimitiveType::F64).unwrap();
+    assert_eq!(from_bit_pattern(bits, &PrimitiveType::U64).unwrap(), int(bits as i128));
+  }
+
+  #[test]
+  fn test_erf() {
+    assert!((erf(0.5) - 0.5204998778130465).abs() < 1e-15);
+    assert!((erf(-1.5) + 0.9661051464753108).abs() < 1e-15);
+    assert!((erf(3.0) - 0.9999779095030014).abs() < 1e-15);
+    assert_eq!(erf(10.0), 1.0);
+  }
+
+  #[test]
+  fn test_random_engine() {
+    let mut engine = RandomEngine::new(0);
+    assert_eq!(engine.next(), 16807);
+    let mut a = RandomEngine::new(42);
+    let mut b = RandomEngine::new(42);
+    for _ in 0..100 {
+      let value = a.next_f64();
+      assert_eq!(value, b.next_f64());
+      assert!((0.0..1.0).contains(&value));
+    }
+  }
+}
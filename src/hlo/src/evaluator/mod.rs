@@ -1,2 +1,2 @@
-
-pub mod hlo_evaluator;
\ No newline at end of file
+pub mod hlo_evaluator;
+pub mod hlo_evaluator_typed_visitor;
\ No newline at end of file
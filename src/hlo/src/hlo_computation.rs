@@ -1,101 +1,281 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use common::{
+  literal_serialization::{program_shape_to_proto, write_bytes_field, write_varint_field},
+  printer::{Printer, StringPrinter},
+  shape::ProgramShape,
+  shape_util::ShapeUtil
+};
 
 use crate::{
   dfs_hlo_visitor_with_default::{DfsHloRewriteVisitor, FunctionVisitor},
   hlo_clone_context::HloCloneContext,
-  hlo_instruction::{self, HloInstruction, HloPrintOptions},
-  hlo_module::HloModule, hlo_opcode::HloOpcode
+  hlo_instruction::{
+    self, CanonicalNameMap, HloInstruction, HloPrintContext, HloPrintOptions, InstructionId
+  },
+  hlo_module::{ComputationId, HloModule, UniqueIdCounter}, hlo_opcode::HloOpcode
 };
 
+// Field numbers of HloComputationProto.
+const COMPUTATION_NAME: u32 = 1;
+const COMPUTATION_INSTRUCTIONS: u32 = 2;
+const COMPUTATION_PROGRAM_SHAPE: u32 = 4;
+const COMPUTATION_ID: u32 = 5;
+const COMPUTATION_ROOT_ID: u32 = 6;
+const COMPUTATION_IS_FUSION_COMPUTATION: u32 = 7;
+const COMPUTATION_EXECUTION_THREAD: u32 = 8;
+
+// An HLO computation owns its instructions in an arena. Instructions refer to
+// each other (operands, users, control dependencies) by InstructionId, which
+// is the index of the instruction in the arena. Removing an instruction
+// leaves an empty slot behind so that the ids of the remaining instructions
+// never change.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct HloComputation {
   name: String,
   unique_id: i64,
-  root_instruction: HloInstruction,
-  fusion_instruction: HloInstruction,
+  id: Option<ComputationId>,
+  root_instruction: Option<InstructionId>,
   is_fusion_computation: bool,
-  custom_call_instruction: HloInstruction,
   is_custom_call_computation: bool,
-  collective_call_instruction: HloInstruction,
   is_collective_call_instruction: bool,
-  while_call_instruction: HloInstruction,
   is_while_call_body_computation: bool,
-  async_instructions: Vec<HloInstruction>,
+  async_instructions: Vec<InstructionId>,
   execution_thread: String,
-  //parent
-  instructions: Vec<HloInstruction>,
+  instructions: Vec<Option<HloInstruction>>,
+  instruction_count: usize,
   to_be_deleted: Vec<HloInstruction>,
-  param_instructions: Vec<HloInstruction>,
+  param_instructions: Vec<InstructionId>,
+  // Source of instruction unique ids, shared with the parent module. Without
+  // a module, the unique id of an instruction is its index in the arena.
+  unique_id_counter: Option<UniqueIdCounter>,
+  unique_id_to_instruction: BTreeMap<i64, InstructionId>,
 }
 
 impl HloComputation {
-  pub fn new() {}
+  pub fn new(name: String) -> Self {
+    HloComputation {
+      name: name,
+      unique_id: -1,
+      id: None,
+      root_instruction: None,
+      is_fusion_computation: false,
+      is_custom_call_computation: false,
+      is_collective_call_instruction: false,
+      is_while_call_body_computation: false,
+      async_instructions: Vec::new(),
+      execution_thread: hlo_instruction::MAIN_EXECUTION_THREAD.to_string(),
+      instructions: Vec::new(),
+      instruction_count: 0,
+      to_be_deleted: Vec::new(),
+      param_instructions: Vec::new(),
+      unique_id_counter: None,
+      unique_id_to_instruction: BTreeMap::new(),
+    }
+  }
 
   // Add an instruction to the computation.
   // The computation takes ownership of the instruction.
-  pub fn add_instruction(&
-    mut self, mut instruction: HloInstruction, name: String) -> &mut HloInstruction
+  pub fn add_instruction(
+    &mut self, mut instruction: HloInstruction, name: String) -> InstructionId
   {
     assert!(instruction.opcode() != HloOpcode::Parameter,
       "Parameter insstructions cannot be added to a computation after it has been built.");
     if !name.is_empty() { instruction.set_and_sanitize_name(name); }
-    self.add_instruction_internal(instruction);
-
-    // TODO
-    unimplemented!()
+    self.add_instruction_internal(instruction)
   }
 
-  fn add_instruction_internal(&mut self, _instruction: HloInstruction) {}
+  fn add_instruction_internal(&mut self, mut instruction: HloInstruction) -> InstructionId {
+    let id = InstructionId::new(self.instructions.len());
+    // The instruction may be a copy of an instruction in another computation,
+    // so anything tied to its previous position is reset here.
+    instruction.set_instruction_id(id);
+    instruction.set_parent(self.id);
+    instruction.clear_users();
+    instruction.clear_unique_id_internal();
+    let unique_id = self.new_unique_instruction_id(id);
+    instruction.set_unique_id(unique_id);
+    self.unique_id_to_instruction.insert(unique_id, id);
+    if instruction.name().is_empty() {
+      instruction.set_name(format!("{}.{}",
+        crate::hlo_opcode::hlo_opcode_string(&instruction.opcode()), id.index()));
+    }
+    for operand in instruction.unique_operands() {
+      self.mutable_instruction(operand).add_user(id);
+    }
+    self.instructions.push(Some(instruction));
+    self.instruction_count += 1;
+    id
+  }
 
   pub fn replace_parameter() {}
-  pub fn remove_parameter() {}
+
+  // Removes the parameter with the given number and renumbers the remaining
+  // parameters. Only fusion computations may drop parameters.
+  pub fn remove_parameter(&mut self, param_no: usize) -> Result<(), String> {
+    assert!(param_no < self.param_instructions.len());
+    let param = self.param_instructions[param_no];
+    self.remove_instruction(param)?;
+    self.param_instructions.remove(param_no);
+    for (i, param) in self.param_instructions.clone().into_iter().enumerate() {
+      self.mutable_instruction(param).set_parameter_number(i as i64);
+    }
+    Ok(())
+  }
+
   pub fn remove_unused_parameters_from_fused_computation() {}
-  pub fn remove_unused_parameters_from_any_computation() {}
 
-  // Adds a new parameter instruction to a fusion computation.
-  pub fn add_parameter(&mut self, instruction: HloInstruction) {
+  // Removes every parameter without users and renumbers the remaining ones.
+  pub fn remove_unused_parameters_from_any_computation(&mut self) -> Result<(), String> {
+    let mut removed = 0;
+    for (i, param) in self.param_instructions.clone().into_iter().enumerate() {
+      if self.instruction(param).user_count() == 0 && !self.is_root(param) {
+        self.force_remove_instruction(param)?;
+        removed += 1;
+      } else if removed > 0 {
+        self.mutable_instruction(param).set_parameter_number((i - removed) as i64);
+      }
+    }
+    let instructions = &self.instructions;
+    self.param_instructions.retain(|p| instructions[p.index()].is_some());
+    Ok(())
+  }
+
+  // Adds a new parameter instruction to the computation.
+  pub fn add_parameter(&mut self, instruction: HloInstruction) -> InstructionId {
     assert!(instruction.opcode() == HloOpcode::Parameter);
-    assert!(!self.is_fusion_computation() ||
-      self.fusion_instruction().as_ref().unwrap().operand_count() ==
-      self.param_instructions.len());
-    
-    // TODO
-    //instruction.set_parent(self);
-    //self.param_instructions.push(instruction);
-    self.add_instruction_internal(instruction);
+    let param_no = instruction.parameter_number();
+    let id = self.add_instruction_internal(instruction);
+    // Keep the parameters ordered by parameter number so that
+    // param_instructions[i] is always parameter i, whatever order they are
+    // added in.
+    let instructions = &self.instructions;
+    let pos = self.param_instructions.partition_point(|p| {
+      instructions[p.index()].as_ref().unwrap().parameter_number() < param_no
+    });
+    self.param_instructions.insert(pos, id);
+    id
   }
 
   pub fn add_entry_computation_parameter() {}
   pub fn replace_entry_computation_parameter() {}
 
-  pub fn remove_instruction(&mut self, _instruction: &HloInstruction) -> Result<(), String> {
-    unimplemented!()
+  // Remove an instruction from the computation. The instruction must have no
+  // users. Instruction is deallocated with this call.
+  pub fn remove_instruction(&mut self, instruction: InstructionId) -> Result<(), String> {
+    if !self.is_safely_removable(self.instruction(instruction), false) {
+      return Err(format!("Cannot remove instruction: {}",
+        self.instruction(instruction).name()));
+    }
+    self.remove_instruction_impl(instruction, false)
+  }
+
+  // Same as remove_instruction but does not check that the instruction is
+  // safely removable, and drops control dependencies of the instruction.
+  pub fn force_remove_instruction(&mut self, instruction: InstructionId) -> Result<(), String> {
+    self.remove_instruction_impl(instruction, true)
   }
 
-  pub fn force_remove_instruction() {}
+  fn remove_instruction_impl(
+    &mut self, instruction: InstructionId, ignore_safety_check: bool) -> Result<(), String>
+  {
+    if self.is_root(instruction) {
+      return Err(format!("Cannot remove the root instruction {}.",
+        self.instruction(instruction).name()));
+    }
+    if self.instruction(instruction).user_count() != 0 {
+      return Err(format!("Instruction {} to remove has users.",
+        self.instruction(instruction).name()));
+    }
+    if ignore_safety_check {
+      self.drop_all_control_deps(instruction)?;
+    } else if self.instruction(instruction).has_control_dependencies() {
+      return Err(format!("Instruction {} to remove has control dependencies.",
+        self.instruction(instruction).name()));
+    }
+    for operand in self.instruction(instruction).unique_operands() {
+      self.mutable_instruction(operand).remove_user(instruction);
+    }
+    let mut removed = self.instructions[instruction.index()].take().unwrap();
+    self.unique_id_to_instruction.remove(&removed.unique_id());
+    removed.mark_as_dead();
+    self.async_instructions.retain(|a| *a != instruction);
+    self.to_be_deleted.push(removed);
+    self.instruction_count -= 1;
+    Ok(())
+  }
 
-  pub fn remove_instruction_and_unused_operands(&mut self, _instruction: &HloInstruction) {}
+  // Removes an instruction from the computation. The instruction must have no
+  // users. Instruction is deallocated with this call. Also removes all operands
+  // that become dead as a result (transitively).
+  pub fn remove_instruction_and_unused_operands(
+    &mut self, instruction: InstructionId) -> Result<(), String>
+  {
+    if self.is_root(instruction) {
+      return Err(format!("Cannot remove the root instruction {}.",
+        self.instruction(instruction).name()));
+    }
+    let mut worklist = vec![instruction];
+    let mut removed = HashSet::new();
+    while let Some(item) = worklist.pop() {
+      if removed.contains(&item) || self.instruction(item).user_count() != 0 ||
+        self.is_root(item) || !self.is_safely_removable(self.instruction(item), false) ||
+        (item != instruction && self.instruction(item).has_side_effect())
+      {
+        continue;
+      }
+      let operands = self.instruction(item).unique_operands();
+      self.remove_instruction(item)?;
+      removed.insert(item);
+      worklist.extend(operands);
+    }
+    Ok(())
+  }
 
   // Set the root of the computation to the given instruction. The instruction
   // must have already been added to the computation.
   pub fn set_root_instruction(
     &mut self,
-    _root_instruction: HloInstruction,
-    _accept_different_shape: bool)
+    new_root_instruction: InstructionId,
+    accept_different_shape: bool)
   {
-
+    assert!(self.contains(new_root_instruction),
+      "Root instruction must be in the computation.");
+    if !self.is_fusion_computation() && !accept_different_shape {
+      if let Some(old_root) = self.root_instruction {
+        assert!(ShapeUtil::compatible(self.instruction(old_root).shape(),
+          self.instruction(new_root_instruction).shape()),
+          "Root shape must stay the same.");
+      }
+    }
+    self.root_instruction = Some(new_root_instruction);
   }
 
   // Return the root instruction of the computation. The root instruction is the
   // instruction which produces the output of the computation.
   pub fn root_instruction(&self) -> &HloInstruction {
-    &self.root_instruction
+    self.instruction(self.root_instruction_id())
+  }
+
+  pub fn root_instruction_id(&self) -> InstructionId {
+    assert!(self.root_instruction.is_some(), "Computation {} has no root.", self.name);
+    self.root_instruction.unwrap()
+  }
+
+  pub fn has_root_instruction(&self) -> bool {
+    self.root_instruction.is_some()
   }
 
-  pub fn mutable_root_instruction(&mut self) -> &mut HloInstruction {
-    &mut self.root_instruction
+  // Returns whether the given instruction is the root of this computation.
+  pub fn is_root(&self, instruction: InstructionId) -> bool {
+    self.root_instruction == Some(instruction)
+  }
+
+  // Returns whether the given instruction has no users and is not the root.
+  pub fn is_dead(&self, instruction: InstructionId) -> bool {
+    self.instruction(instruction).user_count() == 0 && !self.is_root(instruction)
   }
 
   // Returns the number of parameters for this computation.
@@ -106,10 +286,10 @@ impl HloComputation {
   // Returns the parameter instruction for the given parameter number.
   pub fn parameter_instruction(&self, param_no: usize) -> Option<&HloInstruction> {
     assert!(param_no < self.param_instructions.len());
-    self.param_instructions.get(param_no)
+    self.param_instructions.get(param_no).map(|id| self.instruction(*id))
   }
 
-  pub fn parameter_instructions(&self) -> &Vec<HloInstruction> {
+  pub fn parameter_instructions(&self) -> &Vec<InstructionId> {
     &self.param_instructions
   }
 
@@ -117,41 +297,261 @@ impl HloComputation {
     self.name.clone()
   }
 
+  pub fn set_name(&mut self, name: String) {
+    self.name = name;
+  }
+
   pub fn uniquify_name() {}
-  pub fn print() {}
+  // Prints the computation, e.g. '%name (p: f32[2]) -> f32[2] { ... }'.
+  // 'module' is used to resolve called computations and the schedule; without
+  // it, called computations are printed by id and instructions in post order.
+  pub fn print(
+    &self,
+    printer: &mut dyn Printer,
+    module: Option<&HloModule>,
+    options: &HloPrintOptions)
+  {
+    let order = match module {
+      Some(module) if module.has_schedule() &&
+        module.schedule().is_computation_scheduled(self) =>
+      {
+        module.schedule().sequence(self).unwrap().ids().iter()
+          .filter_map(|unique_id| self.instruction_with_unique_id(*unique_id))
+          .collect()
+      },
+      _ => self.make_instruction_post_order()
+    };
+    self.print_with_order(printer, module, options, &order);
+  }
 
-  pub fn to_string(&self) -> String { "".to_string() }
-  pub fn to_string_with_options(&self, _options: &HloPrintOptions) -> String {
-    unimplemented!()
+  // Prints the computation with the instructions in the given order.
+  pub fn print_with_order(
+    &self,
+    printer: &mut dyn Printer,
+    module: Option<&HloModule>,
+    options: &HloPrintOptions,
+    instruction_order: &Vec<InstructionId>)
+  {
+    let tab = " ".repeat(2 * options.indent_amount() as usize);
+    printer.append(&tab);
+
+    if !options.is_in_nested_computation() {
+      if options.print_percent() {
+        printer.append(&"%".to_string());
+      }
+      if options.print_ids() {
+        printer.append(&self.name);
+        printer.append(&" ".to_string());
+      }
+    }
+
+    if options.print_program_shape() && !options.is_in_nested_computation() {
+      printer.append(&"(".to_string());
+      for (i, param) in self.param_instructions.iter().enumerate() {
+        if i > 0 {
+          printer.append(&", ".to_string());
+        }
+        let param = self.instruction(*param);
+        printer.append(&param.name());
+        printer.append(&": ".to_string());
+        ShapeUtil::print_human_string(printer, param.shape());
+      }
+      printer.append(&") -> ".to_string());
+      if self.has_root_instruction() {
+        ShapeUtil::print_human_string(printer, self.root_instruction().shape());
+      }
+      printer.append(&" ".to_string());
+    }
+    printer.append(&"{\n".to_string());
+
+    let mut new_options = options.clone();
+    new_options.set_indent_amount(options.indent_amount() + 1)
+      .set_is_in_nested_computation(true);
+    let new_tab = " ".repeat(2 * new_options.indent_amount() as usize);
+    let context = HloPrintContext::new(Some(self), module);
+    let mut name_map = CanonicalNameMap::new();
+    for id in instruction_order {
+      printer.append(&new_tab);
+      if self.root_instruction == Some(*id) {
+        printer.append(&"ROOT ".to_string());
+      }
+      self.instruction(*id).print_with_canonical_name_map(
+        printer, &context, &new_options, &mut name_map);
+      printer.append(&"\n".to_string());
+    }
+    printer.append(&tab);
+    printer.append(&"}".to_string());
+
+    if options.print_ids() && !self.is_main_thread() {
+      // When print_ids() is false, exclude the thread name because it includes
+      // an id.
+      printer.append(&", execution_thread=\"".to_string());
+      printer.append(&self.execution_thread);
+      printer.append(&"\"".to_string());
+    }
+    if options.print_name_after_closing_brace() && self.instruction_count() > 5 {
+      printer.append(&" // ".to_string());
+      printer.append(&self.name);
+    }
+  }
+
+  pub fn to_string(&self) -> String {
+    self.to_string_with_options(&HloPrintOptions::default())
+  }
+
+  pub fn to_string_with_options(&self, options: &HloPrintOptions) -> String {
+    let mut printer = StringPrinter::new();
+    self.print(&mut printer, None, options);
+    printer.to_string()
   }
 
   pub fn to_cord() {}
-  pub fn to_proto() {}
+  // Returns the HloComputationProto encoding of this computation, with its
+  // instructions in post order.
+  pub fn to_proto(&self) -> Vec<u8> {
+    let mut output = vec![];
+    write_bytes_field(&mut output, COMPUTATION_NAME, self.name().as_bytes());
+    for id in self.make_instruction_post_order() {
+      write_bytes_field(&mut output, COMPUTATION_INSTRUCTIONS,
+        &self.instruction(id).to_proto());
+    }
+    write_bytes_field(&mut output, COMPUTATION_PROGRAM_SHAPE,
+      &program_shape_to_proto(&self.compute_program_shape(true)));
+    write_varint_field(&mut output, COMPUTATION_ID, self.unique_id as u64);
+    write_varint_field(&mut output, COMPUTATION_ROOT_ID,
+      self.root_instruction().unique_id() as u64);
+    write_varint_field(&mut output, COMPUTATION_IS_FUSION_COMPUTATION,
+      self.is_fusion_computation() as u64);
+    write_bytes_field(&mut output, COMPUTATION_EXECUTION_THREAD,
+      self.execution_thread.as_bytes());
+    output
+  }
+
   pub fn new_from_proto() {}
   pub fn absl_hash_values() {}
 
-  pub fn instructions(&self) -> &Vec<HloInstruction> {
-    &self.instructions
+  // Returns the instruction with the given id. The instruction must not have
+  // been removed.
+  pub fn instruction(&self, id: InstructionId) -> &HloInstruction {
+    match self.instructions.get(id.index()) {
+      Some(Some(instruction)) => instruction,
+      _ => panic!("{:?} is not an instruction of computation {}.", id, self.name)
+    }
+  }
+
+  pub fn mutable_instruction(&mut self, id: InstructionId) -> &mut HloInstruction {
+    match self.instructions.get_mut(id.index()) {
+      Some(Some(instruction)) => instruction,
+      _ => panic!("{:?} is not an instruction of computation {}.", id, self.name)
+    }
   }
 
-  pub fn mutable_instructions(&mut self) -> &mut Vec<HloInstruction> {
-    &mut self.instructions
+  // Returns whether 'id' refers to a live instruction of this computation.
+  pub fn contains(&self, id: InstructionId) -> bool {
+    matches!(self.instructions.get(id.index()), Some(Some(_)))
   }
 
-  pub fn make_instruction_post_order(&self) -> &Vec<HloInstruction> {
-    unimplemented!()
+  // Returns the live instructions of the computation in the order they were
+  // added.
+  pub fn instructions(&self) -> impl Iterator<Item = &HloInstruction> {
+    self.instructions.iter().flatten()
   }
 
-  pub fn mutable_make_instruction_post_order(&mut self) -> &mut Vec<HloInstruction> {
-    unimplemented!()
+  pub fn mutable_instructions(&mut self) -> impl Iterator<Item = &mut HloInstruction> {
+    self.instructions.iter_mut().flatten()
+  }
+
+  // Returns the ids of the live instructions in the order they were added.
+  pub fn instruction_ids(&self) -> Vec<InstructionId> {
+    self.instructions().map(|instruction| instruction.id()).collect()
+  }
+
+  // Returns the operand 'operand_num' of 'instruction'.
+  pub fn operand(&self, instruction: InstructionId, operand_num: usize) -> &HloInstruction {
+    self.instruction(self.instruction(instruction).operand(operand_num))
+  }
+
+  // Compute and return a post-order of the instructions in the computation. In
+  // this order, definitions of values always appear before their uses.
+  pub fn make_instruction_post_order(&self) -> Vec<InstructionId> {
+    let mut post_order = Vec::with_capacity(self.instruction_count);
+    let mut visited = HashSet::new();
+    // Visit users-less instructions first so that dead code and the root are
+    // covered, then everything else that might be only reachable via control
+    // edges.
+    for instruction in self.instructions() {
+      if instruction.user_count() == 0 {
+        self.compute_instruction_post_order(instruction.id(), &mut visited, &mut post_order);
+      }
+    }
+    for instruction in self.instructions() {
+      self.compute_instruction_post_order(instruction.id(), &mut visited, &mut post_order);
+    }
+    assert_eq!(post_order.len(), self.instruction_count);
+    post_order
+  }
+
+  // Returns the post order of the instructions reachable from 'postorder_root'
+  // through operand and control predecessor edges.
+  pub fn make_instruction_post_order_from(
+    &self, postorder_root: InstructionId) -> Vec<InstructionId>
+  {
+    let mut post_order = Vec::new();
+    let mut visited = HashSet::new();
+    self.compute_instruction_post_order(postorder_root, &mut visited, &mut post_order);
+    post_order
+  }
+
+  fn compute_instruction_post_order(
+    &self,
+    root: InstructionId,
+    visited: &mut HashSet<InstructionId>,
+    post_order: &mut Vec<InstructionId>)
+  {
+    if visited.contains(&root) { return; }
+    // Iterative DFS; the boolean marks whether the children of the node have
+    // already been pushed.
+    let mut stack = vec![(root, false)];
+    let mut on_stack = HashSet::new();
+    while let Some((current, expanded)) = stack.pop() {
+      if expanded {
+        on_stack.remove(&current);
+        if visited.insert(current) {
+          post_order.push(current);
+        }
+        continue;
+      }
+      if visited.contains(&current) { continue; }
+      assert!(on_stack.insert(current),
+        "Cycle detected at instruction {}.", self.instruction(current).name());
+      stack.push((current, true));
+      let instruction = self.instruction(current);
+      let mut predecessors: Vec<InstructionId> = instruction.operands().clone();
+      predecessors.extend_from_slice(instruction.control_predecessors());
+      // Push in reverse order so that operands are visited in order.
+      for predecessor in predecessors.into_iter().rev() {
+        if !visited.contains(&predecessor) && !on_stack.contains(&predecessor) {
+          stack.push((predecessor, false));
+        } else if on_stack.contains(&predecessor) && !visited.contains(&predecessor) {
+          panic!("Cycle detected at instruction {}.", self.instruction(predecessor).name());
+        }
+      }
+    }
   }
 
-  pub fn make_instruction_post_order_from() {}
   pub fn make_instruction_post_order_with_reshape_first() {}
-  pub fn for_each_instruction_post_order() {}
+
+  // Calls 'func' on every instruction of the computation in post order.
+  pub fn for_each_instruction_post_order<F>(&self, mut func: F)
+    where F: FnMut(&HloInstruction)
+  {
+    for id in self.make_instruction_post_order() {
+      func(self.instruction(id));
+    }
+  }
 
   pub fn instruction_count(&self) -> usize {
-    self.instructions.len()
+    self.instruction_count
   }
 
   pub fn make_embedded_computations_list() {}
@@ -159,40 +559,301 @@ impl HloComputation {
   pub fn create_async_instructions() {}
   pub fn deep_copy_instruction() {}
   pub fn deep_copy_instruction_with_custom_copier() {}
-  pub fn compute_program_shape() {}
 
+  // Computes and returns the ProgramShape of this computation (shape of
+  // parameters and result with layout).
+  pub fn compute_program_shape(&self, include_ids: bool) -> ProgramShape {
+    let mut program_shape = ProgramShape::new();
+    for param_id in self.parameter_instructions() {
+      let param = self.instruction(*param_id);
+      program_shape.add_parameter(param.shape().clone());
+      if include_ids {
+        program_shape.add_parameter_names(param.name());
+      } else {
+        program_shape.add_parameter_names(
+          param.name().split('.').next().unwrap().to_string());
+      }
+    }
+    program_shape.set_result(self.root_instruction().shape().clone());
+    program_shape
+  }
+
+  // Replaces the old instruction with a newly created instruction. Removes old
+  // instruction from computation. Updates uses and root instruction.
   pub fn replace_with_new_instruction(
-    &self,
-    _old: &HloInstruction,
-    _new: &HloInstruction) -> Result<(), String>
+    &mut self,
+    old_instruction: InstructionId,
+    new_instruction: HloInstruction) -> Result<InstructionId, String>
   {
-    unimplemented!()
+    let new_id = self.add_instruction(new_instruction, "".to_string());
+    self.replace_instruction(old_instruction, new_id, true, true, true)?;
+    Ok(new_id)
   }
 
   pub fn replace_with_entry_computation_parameter() {}
 
+  // Replace old instruction with new instruction. Updates uses and root
+  // instruction. Removes old instruction from computation. Transitively removes
+  // non-side effecting operands of old instruction that become dead.
+  // Returns whether old instruction was replaced and removed.
   pub fn replace_instruction(
-    &self,
-    _old_instruction: &HloInstruction,
-    _new_instruction: &HloInstruction,
-    _preserve_sharding: bool,
-    _relay_control_dependency: bool,
-    _remove_unused_operands: bool) -> Result<bool, String>
+    &mut self,
+    old_instruction: InstructionId,
+    new_instruction: InstructionId,
+    preserve_sharding: bool,
+    relay_control_dependency: bool,
+    remove_unused_operands: bool) -> Result<bool, String>
   {
-    unimplemented!()
+    if !ShapeUtil::compatible_ignoring_fp_precision(
+      self.instruction(old_instruction).shape(),
+      self.instruction(new_instruction).shape())
+    {
+      return Err(format!("{} vs {}",
+        self.instruction(old_instruction).shape().to_string(true),
+        self.instruction(new_instruction).shape().to_string(true)));
+    }
+    self.replace_instruction_with_different_shape(old_instruction, new_instruction,
+      preserve_sharding, relay_control_dependency, remove_unused_operands)
   }
 
-  pub fn replace_instruction_with_defferent_shape() {}
-  pub fn set_parent() {}
+  pub fn replace_instruction_with_different_shape(
+    &mut self,
+    old_instruction: InstructionId,
+    new_instruction: InstructionId,
+    preserve_sharding: bool,
+    relay_control_dependency: bool,
+    remove_unused_operands: bool) -> Result<bool, String>
+  {
+    if old_instruction == new_instruction {
+      return Ok(false);
+    }
+    if preserve_sharding && self.instruction(new_instruction).has_sharding() &&
+      self.instruction(old_instruction).has_sharding() &&
+      !self.instruction(new_instruction).has_compatible_sharding(
+        self.instruction(old_instruction))
+    {
+      return Ok(false);
+    }
+    if relay_control_dependency {
+      self.copy_all_control_deps_from(old_instruction, new_instruction)?;
+    } else if self.instruction(old_instruction).has_control_dependencies() {
+      return Ok(false);
+    }
+    // The new instruction should have the same sharding as the old one unless
+    // it already carries its own.
+    if !self.instruction(new_instruction).has_sharding() &&
+      self.instruction(old_instruction).has_sharding()
+    {
+      let sharding = self.instruction(old_instruction).sharding().clone();
+      self.mutable_instruction(new_instruction).set_sharding(sharding);
+    }
+    self.replace_all_uses_with_different_shape(old_instruction, new_instruction)?;
+    if remove_unused_operands {
+      self.remove_instruction_and_unused_operands(old_instruction)?;
+    } else {
+      self.force_remove_instruction(old_instruction)?;
+    }
+    Ok(true)
+  }
 
-  pub fn parent(&self) -> &Option<HloModule> {
-    unimplemented!()
+  // Replaces the operand 'operand_num' of 'user' with 'new_operand'. The old
+  // and new operands must have compatible shapes ignoring floating-point
+  // precision.
+  pub fn replace_operand_with(
+    &mut self,
+    user: InstructionId,
+    operand_num: usize,
+    new_operand: InstructionId) -> Result<(), String>
+  {
+    let old_operand = self.instruction(user).operand(operand_num);
+    if !ShapeUtil::compatible_ignoring_fp_precision(
+      self.instruction(old_operand).shape(), self.instruction(new_operand).shape())
+    {
+      return Err(format!("{} is not compatible with {}",
+        self.instruction(old_operand).shape().to_string(true),
+        self.instruction(new_operand).shape().to_string(true)));
+    }
+    self.replace_operand_with_different_shape(user, operand_num, new_operand)
   }
 
-  pub fn mutable_parent(&mut self) -> &mut Option<HloModule> {
-    unimplemented!()
+  // Same as replace_operand_with, but new_operand can have a different shape.
+  pub fn replace_operand_with_different_shape(
+    &mut self,
+    user: InstructionId,
+    operand_num: usize,
+    new_operand: InstructionId) -> Result<(), String>
+  {
+    assert!(operand_num < self.instruction(user).operand_count());
+    let old_operand = self.instruction(user).operand(operand_num);
+    if old_operand == new_operand {
+      return Ok(());
+    }
+    self.mutable_instruction(user).set_operand(operand_num, new_operand);
+    if !self.instruction(user).operands().contains(&old_operand) {
+      self.mutable_instruction(old_operand).remove_user(user);
+    }
+    self.mutable_instruction(new_operand).add_user(user);
+    Ok(())
+  }
+
+  // Replaces all uses of 'producer' in 'user' with 'new_producer'.
+  pub fn replace_use_with(
+    &mut self,
+    producer: InstructionId,
+    user: InstructionId,
+    new_producer: InstructionId) -> Result<(), String>
+  {
+    if !ShapeUtil::compatible_ignoring_fp_precision(
+      self.instruction(producer).shape(), self.instruction(new_producer).shape())
+    {
+      return Err(format!("this shape: {}, replacement shape: {}",
+        self.instruction(producer).shape().to_string(true),
+        self.instruction(new_producer).shape().to_string(true)));
+    }
+    self.replace_use_with_different_shape(producer, user, new_producer)
+  }
+
+  // Same as replace_use_with(), but new_producer can have a different shape.
+  pub fn replace_use_with_different_shape(
+    &mut self,
+    producer: InstructionId,
+    user: InstructionId,
+    new_producer: InstructionId) -> Result<(), String>
+  {
+    if producer == new_producer { return Ok(()); }
+    for operand_num in self.instruction(user).operand_indices(producer) {
+      self.mutable_instruction(user).set_operand(operand_num as usize, new_producer);
+    }
+    self.mutable_instruction(producer).remove_user(user);
+    self.mutable_instruction(new_producer).add_user(user);
+    Ok(())
+  }
+
+  // Replaces all uses of 'old_producer' with 'new_producer'. Also updates the
+  // root of the computation if 'old_producer' was the root.
+  pub fn replace_all_uses_with(
+    &mut self,
+    old_producer: InstructionId,
+    new_producer: InstructionId) -> Result<(), String>
+  {
+    if !ShapeUtil::compatible_ignoring_fp_precision(
+      self.instruction(old_producer).shape(), self.instruction(new_producer).shape())
+    {
+      let mut result = "The shape doesn't match when replacing ".to_string();
+      result.push_str(&self.instruction(old_producer).name());
+      result.push_str(" with ");
+      result.push_str(&self.instruction(new_producer).name());
+      result.push_str(". ");
+      result.push_str(&self.instruction(old_producer).shape().to_string(false));
+      result.push_str(" is not compatible with ");
+      result.push_str(&self.instruction(new_producer).shape().to_string(false));
+      return Err(result);
+    }
+    self.replace_all_uses_with_different_shape(old_producer, new_producer)
+  }
+
+  // Same as replace_all_uses_with, but new_producer can have a different shape.
+  pub fn replace_all_uses_with_different_shape(
+    &mut self,
+    old_producer: InstructionId,
+    new_producer: InstructionId) -> Result<(), String>
+  {
+    if old_producer == new_producer { return Ok(()); }
+    let users = self.instruction(old_producer).users().clone();
+    let mut new_producer_is_user = false;
+    for user in users {
+      if user == new_producer {
+        // It's possible that new_producer is a user of old_producer, for
+        // example when old_producer is wrapped in a copy. That use is kept.
+        new_producer_is_user = true;
+        continue;
+      }
+      for operand_num in self.instruction(user).operand_indices(old_producer) {
+        self.mutable_instruction(user).set_operand(operand_num as usize, new_producer);
+      }
+      self.mutable_instruction(new_producer).add_user(user);
+    }
+    self.mutable_instruction(old_producer).clear_users();
+    if new_producer_is_user {
+      self.mutable_instruction(old_producer).add_user(new_producer);
+    }
+    if self.is_root(old_producer) {
+      self.set_root_instruction(new_producer, true);
+    }
+    Ok(())
+  }
+
+  // Adds a control dependency from 'predecessor' to 'successor'. The
+  // successor will be scheduled after the predecessor.
+  pub fn add_control_dependency_to(
+    &mut self,
+    predecessor: InstructionId,
+    successor: InstructionId) -> Result<(), String>
+  {
+    if predecessor == successor {
+      return Err(format!("Cannot add a control dependency from {} to itself.",
+        self.instruction(predecessor).name()));
+    }
+    self.mutable_instruction(predecessor).add_control_successor(successor);
+    self.mutable_instruction(successor).add_control_predecessor(predecessor);
+    Ok(())
   }
 
+  // Removes a previously added control dependency from 'predecessor' to
+  // 'successor'.
+  pub fn remove_control_dependency_to(
+    &mut self,
+    predecessor: InstructionId,
+    successor: InstructionId) -> Result<(), String>
+  {
+    self.mutable_instruction(predecessor).remove_control_successor(successor);
+    self.mutable_instruction(successor).remove_control_predecessor(predecessor);
+    Ok(())
+  }
+
+  // Drops all control predecessors and successors of 'instruction'.
+  pub fn drop_all_control_deps(&mut self, instruction: InstructionId) -> Result<(), String> {
+    let successors = self.instruction(instruction).control_successors().to_vec();
+    for successor in successors {
+      self.remove_control_dependency_to(instruction, successor)?;
+    }
+    let predecessors = self.instruction(instruction).control_predecessors().to_vec();
+    for predecessor in predecessors {
+      self.remove_control_dependency_to(predecessor, instruction)?;
+    }
+    Ok(())
+  }
+
+  // Drops all control dependencies of 'instruction', but keeps the ordering
+  // they imposed by connecting every predecessor to every successor.
+  pub fn safely_drop_all_control_dependencies(
+    &mut self, instruction: InstructionId) -> Result<(), String>
+  {
+    let predecessors = self.instruction(instruction).control_predecessors().to_vec();
+    let successors = self.instruction(instruction).control_successors().to_vec();
+    for predecessor in &predecessors {
+      for successor in &successors {
+        self.add_control_dependency_to(*predecessor, *successor)?;
+      }
+    }
+    self.drop_all_control_deps(instruction)
+  }
+
+  // Copies the control predecessors and successors of 'from' to 'to'.
+  pub fn copy_all_control_deps_from(
+    &mut self, from: InstructionId, to: InstructionId) -> Result<(), String>
+  {
+    for predecessor in self.instruction(from).control_predecessors().to_vec() {
+      self.add_control_dependency_to(predecessor, to)?;
+    }
+    for successor in self.instruction(from).control_successors().to_vec() {
+      self.add_control_dependency_to(to, successor)?;
+    }
+    Ok(())
+  }
+
+  pub fn set_parent() {}
+
   pub fn accept(&self, _visitor: &FunctionVisitor) -> Result<(), String> {
     unimplemented!()
   }
@@ -226,7 +887,7 @@ impl HloComputation {
   // A computation has a side effect if it contains one or more instruction with
   // a side effect.
   pub fn has_side_effect(&self) -> bool {
-    for instruction in &self.instructions {
+    for instruction in self.instructions() {
       if instruction.has_side_effect() { return true; }
     }
     false
@@ -234,18 +895,11 @@ impl HloComputation {
 
   // Returns if this computation is a fusion computation.
   pub fn is_fusion_computation(&self) -> bool {
-    false //self.is_fusion_computation
+    self.is_fusion_computation
   }
 
-  // Returns if the computation is the entry computation of the module.
-  pub fn is_entry_computation(&self) -> bool {
-    self.parent().as_ref().unwrap().entry_computation().unwrap() == self
-  }
-
-  // Returns the owning fusion instruction, or nullptr if this is not a fusion
-  // computation.
-  pub fn fusion_instruction(&self) -> Option<&HloInstruction> {
-    Some(&self.fusion_instruction)
+  pub fn set_is_fusion_computation(&mut self, is_fusion_computation: bool) {
+    self.is_fusion_computation = is_fusion_computation;
   }
 
   pub fn set_fusion_instruction() {}
@@ -254,44 +908,37 @@ impl HloComputation {
     self.is_custom_call_computation
   }
 
-  pub fn custom_call_instruction(&self) -> &HloInstruction {
-    &self.custom_call_instruction
-  }
-
   pub fn set_custom_call_instruction() {}
 
   pub fn is_collective_called_computation(&self) -> bool {
     self.is_collective_call_instruction
   }
 
-  pub fn collective_call_instruction(&self) -> &HloInstruction {
-    &self.collective_call_instruction
-  }
-
   pub fn set_collective_call_instruction() {}
 
   pub fn is_while_body_computation(&self) -> bool {
     self.is_while_call_body_computation
   }
 
-  pub fn while_call_instruction(&self) -> &HloInstruction {
-    &self.while_call_instruction
-  }
-
-  pub fn set_while_call_instruction(&mut self, _while_call_instruction: &HloInstruction) {
-    unimplemented!()
+  pub fn set_while_call_instruction(&mut self, is_while_call_body_computation: bool) {
+    self.is_while_call_body_computation = is_while_call_body_computation;
   }
 
   pub fn is_async_computation(&self) -> bool {
     !self.async_instructions.is_empty()
   }
 
-  pub fn async_instructions(&self) -> &Vec<HloInstruction> {
+  pub fn async_instructions(&self) -> &Vec<InstructionId> {
     &self.async_instructions
   }
 
-  pub fn add_async_instruction() {}
-  pub fn remove_async_instruction() {}
+  pub fn add_async_instruction(&mut self, async_instruction: InstructionId) {
+    self.async_instructions.push(async_instruction);
+  }
+
+  pub fn remove_async_instruction(&mut self, async_instruction: InstructionId) {
+    self.async_instructions.retain(|a| *a != async_instruction);
+  }
 
   pub fn is_called_computation(&self) -> bool {
     self.is_fusion_computation() || self.is_custom_call_computation()
@@ -307,12 +954,58 @@ impl HloComputation {
     self.unique_id = id;
   }
 
-  pub fn get_instruction_with_name() {}
+  // Returns the id of the instruction with the given unique id, if it is in
+  // the computation.
+  pub fn instruction_with_unique_id(&self, unique_id: i64) -> Option<InstructionId> {
+    self.unique_id_to_instruction.get(&unique_id).cloned()
+  }
+
+  // Called by the module when the computation is added to it. The
+  // instructions are given new ids from the counter of the module.
+  pub(crate) fn set_unique_id_counter(&mut self, counter: UniqueIdCounter) {
+    self.unique_id_counter = Some(counter);
+    self.unique_id_to_instruction.clear();
+    for index in 0..self.instructions.len() {
+      let id = InstructionId::new(index);
+      if !self.contains(id) { continue; }
+      let unique_id = self.new_unique_instruction_id(id);
+      let instruction = self.mutable_instruction(id);
+      instruction.clear_unique_id_internal();
+      instruction.set_unique_id(unique_id);
+      self.unique_id_to_instruction.insert(unique_id, id);
+    }
+  }
+
+  fn new_unique_instruction_id(&self, id: InstructionId) -> i64 {
+    match &self.unique_id_counter {
+      Some(counter) => counter.next(),
+      None => id.index() as i64
+    }
+  }
+
+  // Returns the id of the instruction with the given name, if any.
+  pub fn get_instruction_with_name(&self, name: &str) -> Option<InstructionId> {
+    self.instructions().find(|i| i.name() == name).map(|i| i.id())
+  }
 
   pub fn unique_id(&self) -> i64 {
     self.unique_id
   }
 
+  // Returns the id of this computation in its module, if it was added to one.
+  pub fn id(&self) -> Option<ComputationId> {
+    self.id
+  }
+
+  // Called by the module when the computation is added to it. Records the id
+  // on the computation and all of its instructions.
+  pub(crate) fn set_computation_id(&mut self, id: ComputationId) {
+    self.id = Some(id);
+    for instruction in self.mutable_instructions() {
+      instruction.set_parent(Some(id));
+    }
+  }
+
   pub fn set_execution_thread(&mut self, execution_thread: String) {
     self.execution_thread = execution_thread;
   }
@@ -331,11 +1024,21 @@ impl HloComputation {
   }
 
   // Returns true if a given instruction is marked dead in this computation.
-  pub fn is_marked_as_dead(_inst: &HloInstruction) -> bool {
-    false
+  pub fn is_marked_as_dead(&self, instruction: InstructionId) -> bool {
+    !self.contains(instruction)
   }
 
-  pub fn can_expand_into_single_instruction() {}
+  // Returns true if every instruction other than the root is a parameter, so
+  // that the computation could be replaced by a copy of its root.
+  pub fn can_expand_into_single_instruction(&self) -> bool {
+    if !self.has_root_instruction() {
+      return false;
+    }
+    let root = self.root_instruction_id();
+    self.instruction_ids().iter().all(|id| {
+      *id == root || self.instruction(*id).opcode() == HloOpcode::Parameter
+    })
+  }
 
   // Like Clone(), but if an instruction is present in replacement_map, we use
   // the map's value to replace that instruction in the cloned computation.
@@ -353,12 +1056,164 @@ impl HloComputation {
   // `replacements` map.
   pub fn clone_with_replacements(
     &self,
-    _replacements: &HashMap<HloInstruction, HloInstruction>,
+    _replacements: &HashMap<InstructionId, HloInstruction>,
     _extra_parameeters: &Vec<HloInstruction>,
     _context: Option<HloCloneContext>,
     _suffix: String,
-    _new_root: Option<HloInstruction>) -> HloComputation
+    _new_root: Option<InstructionId>) -> HloComputation
   {
-    unimplemented!()    
+    unimplemented!()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_utils::r0f32;
+
+  // Builds `add = add(p0, negate(p0))` with `add` as the root.
+  fn make_add_negate(computation: &mut HloComputation)
+    -> (InstructionId, InstructionId, InstructionId)
+  {
+    let p0 = computation.add_parameter(
+      HloInstruction::create_parameter(0, &r0f32(), "p0".to_string()));
+    let negate = computation.add_instruction(HloInstruction::create_unary(
+      &r0f32(), HloOpcode::Negate, computation.instruction(p0)), "".to_string());
+    let add = computation.add_instruction(HloInstruction::create_binary(
+      &r0f32(), HloOpcode::Add, computation.instruction(p0),
+      computation.instruction(negate)), "".to_string());
+    computation.set_root_instruction(add, false);
+    (p0, negate, add)
+  }
+
+  #[test]
+  fn test_add_instruction_records_users() {
+    let mut computation = HloComputation::new("test".to_string());
+    let (p0, negate, add) = make_add_negate(&mut computation);
+
+    assert_eq!(computation.instruction_count(), 3);
+    assert_eq!(computation.instruction(p0).users(), &vec![negate, add]);
+    assert_eq!(computation.instruction(negate).users(), &vec![add]);
+    assert_eq!(computation.instruction(add).operands(), &vec![p0, negate]);
+    assert_eq!(computation.operand(add, 1).opcode(), HloOpcode::Negate);
+    assert_eq!(computation.instruction(add).id(), add);
+    assert_eq!(computation.root_instruction_id(), add);
+    assert!(!computation.is_dead(negate));
+  }
+
+  #[test]
+  fn test_unique_ids_are_unique_in_module() {
+    let mut first = HloComputation::new("first".to_string());
+    let (p0, _negate, add) = make_add_negate(&mut first);
+    assert_eq!(first.instruction(add).unique_id(), add.index() as i64);
+    let mut second = HloComputation::new("second".to_string());
+    make_add_negate(&mut second);
+
+    let mut module = HloModule::new("m".to_string(),
+      crate::hlo_module_config::HloModuleConfig::new_default());
+    let first = module.add_entry_computation(first);
+    let second = module.add_embedded_computation(second);
+    let exp = {
+      let computation = module.mutable_computation(second);
+      let p0 = computation.parameter_instruction(0).unwrap().id();
+      computation.add_instruction(HloInstruction::create_unary(
+        &r0f32(), HloOpcode::Exp, computation.instruction(p0)), "".to_string())
+    };
+
+    let unique_ids: HashSet<i64> = module.computations()
+      .flat_map(|computation| computation.instructions())
+      .map(|instruction| instruction.unique_id())
+      .collect();
+    assert_eq!(unique_ids.len(), 7);
+    for id in [first, second] {
+      let computation = module.computation(id);
+      for instruction in computation.instructions() {
+        assert_eq!(computation.instruction_with_unique_id(instruction.unique_id()),
+          Some(instruction.id()));
+      }
+    }
+
+    let computation = module.mutable_computation(second);
+    let exp_unique_id = computation.instruction(exp).unique_id();
+    assert!(computation.remove_instruction(exp).is_ok());
+    assert_eq!(computation.instruction_with_unique_id(exp_unique_id), None);
+    let p0_unique_id = module.computation(first).instruction(p0).unique_id();
+    assert_eq!(module.computation(second).instruction_with_unique_id(p0_unique_id), None);
+  }
+
+  #[test]
+  fn test_unique_id_counter_is_shared_but_not_compared() {
+    let mut first_module = HloModule::new("first".to_string(),
+      crate::hlo_module_config::HloModuleConfig::new_default());
+    let mut computation = HloComputation::new("c".to_string());
+    make_add_negate(&mut computation);
+    let first = first_module.add_entry_computation(computation.clone());
+    let mut second_module = HloModule::new("second".to_string(),
+      crate::hlo_module_config::HloModuleConfig::new_default());
+    let second = second_module.add_entry_computation(computation);
+
+    let first = first_module.computation(first);
+    let second = second_module.computation(second);
+    let clone = first.clone();
+    let counter = |c: &HloComputation| c.unique_id_counter.clone().unwrap();
+    assert!(counter(&clone).is_shared_with(&counter(first)));
+    assert!(!counter(second).is_shared_with(&counter(first)));
+    // Equal instructions make equal computations, whatever their counters.
+    assert!(*first == *second);
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_post_order() {
+    let mut computation = HloComputation::new("test".to_string());
+    let (p0, negate, add) = make_add_negate(&mut computation);
+    assert_eq!(computation.make_instruction_post_order(), vec![p0, negate, add]);
+  }
+
+  #[test]
+  fn test_replace_all_uses_with() {
+    let mut computation = HloComputation::new("test".to_string());
+    let (p0, negate, add) = make_add_negate(&mut computation);
+    let exp = computation.add_instruction(HloInstruction::create_unary(
+      &r0f32(), HloOpcode::Exp, computation.instruction(p0)), "".to_string());
+
+    assert!(computation.replace_all_uses_with(negate, exp).is_ok());
+    assert_eq!(computation.instruction(add).operands(), &vec![p0, exp]);
+    assert!(computation.instruction(negate).users().is_empty());
+    assert_eq!(computation.instruction(exp).users(), &vec![add]);
+  }
+
+  #[test]
+  fn test_remove_instruction_keeps_ids_stable() {
+    let mut computation = HloComputation::new("test".to_string());
+    let (p0, negate, add) = make_add_negate(&mut computation);
+    let exp = computation.add_instruction(HloInstruction::create_unary(
+      &r0f32(), HloOpcode::Exp, computation.instruction(p0)), "".to_string());
+    assert!(computation.replace_operand_with(add, 1, exp).is_ok());
+
+    assert!(computation.remove_instruction(negate).is_ok());
+    assert!(computation.is_marked_as_dead(negate));
+    assert_eq!(computation.instruction_count(), 3);
+    assert_eq!(computation.instruction(exp).id(), exp);
+    assert_eq!(computation.instruction(p0).users(), &vec![add, exp]);
+    assert_eq!(computation.make_instruction_post_order(), vec![p0, exp, add]);
+  }
+
+  #[test]
+  fn test_control_dependencies() {
+    let mut computation = HloComputation::new("test".to_string());
+    let (p0, negate, add) = make_add_negate(&mut computation);
+    let exp = computation.add_instruction(HloInstruction::create_unary(
+      &r0f32(), HloOpcode::Exp, computation.instruction(p0)), "".to_string());
+
+    assert!(computation.add_control_dependency_to(add, exp).is_ok());
+    assert_eq!(computation.instruction(exp).control_predecessors(), &[add]);
+    assert!(computation.add_control_dependency_to(negate, negate).is_err());
+    // Removing an instruction with control dependencies is only possible
+    // through force_remove_instruction.
+    assert!(computation.remove_instruction(exp).is_err());
+
+    assert!(computation.drop_all_control_deps(exp).is_ok());
+    assert!(computation.instruction(add).control_successors().is_empty());
+    assert!(computation.remove_instruction(exp).is_ok());
+  }
+}
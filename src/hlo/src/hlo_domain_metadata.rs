@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 
 use std::collections::HashSet;
-use crate::hlo_instruction::HloInstruction;
+use crate::{hlo_instruction::HloInstruction, hlo_sharding::HloSharding};
 
 // A Domain data structure captures all the information about a kDomain
 // bounded instruction set.
@@ -24,28 +24,42 @@ impl Domain {
 }
 
 // The DomainMetadata represents the base class for metadata which can be
-// attached to Domain HLO instructions. 
-#[derive(Debug, Clone)]
-pub struct DomainMetadata {}
+// attached to Domain HLO instructions. The only kind of metadata supported
+// so far is "sharding", which carries the sharding of the domain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DomainMetadata {
+  kind: String,
+  sharding: Option<HloSharding>,
+}
 
 impl DomainMetadata {
-  pub fn new() {}
+  pub fn new(kind: String, sharding: Option<HloSharding>) -> Self {
+    DomainMetadata { kind: kind, sharding: sharding }
+  }
 
   // Returns the matadata type.
   pub fn kind(&self) -> String {
-    unimplemented!()
+    self.kind.clone()
+  }
+
+  // Returns the sharding carried by a "sharding" domain, if any.
+  pub fn sharding(&self) -> Option<&HloSharding> {
+    self.sharding.as_ref()
   }
 
   // Compares the metadata object with another one and return true if the
   // two matches.
-  pub fn matches(&self, _other: &DomainMetadata) -> bool {
-    unimplemented!()
+  pub fn matches(&self, other: &DomainMetadata) -> bool {
+    self == other
   }
 
   pub fn hash() {}
 
   // Returns a string representation of the metadata.
   pub fn to_string(&self) -> String {
-    unimplemented!()
+    match &self.sharding {
+      Some(sharding) => sharding.to_string(false),
+      None => "{}".to_string()
+    }
   }
-}
\ No newline at end of file
+}
@@ -1,8 +1,8 @@
 #![allow(dead_code)]
 
-use common::{shape::Shape, shape_tree::ShapeTree};
+use common::{shape::Shape, shape_tree::ShapeTree, shape_util::ShapeUtil};
 
-use crate::hlo_module::HloModule;
+use crate::{hlo_module::HloModule, hlo_value::shape_index_to_string};
 
 // The kind of aliases which can be set. A kMayAlias is one setup at
 // compilation time by the user, and has to be respected. A kMustAlias one
@@ -34,6 +34,14 @@ impl Alias {
     }
   }
 
+  pub fn parameter_number(&self) -> i64 {
+    self.parameter_number
+  }
+
+  pub fn parameter_index(&self) -> &Vec<i64> {
+    &self.parameter_index
+  }
+
   pub fn must_alias(&self) -> bool {
     self.kind == AliasKind::Must
   }
@@ -67,7 +75,7 @@ impl Alias {
 // parameter index in the entry computation.
 #[derive(PartialEq)]
 pub struct HloInputOutputAliasConfig {
-  alias: ShapeTree<Alias>
+  alias: ShapeTree<Option<Alias>>
 }
 
 impl HloInputOutputAliasConfig {
@@ -80,44 +88,89 @@ impl HloInputOutputAliasConfig {
   // Sets up alias config from `output_index` to `param_index` at
   // `param_number`.
   pub fn setup_alias(
-    &self,
-    _output_index: &Vec<i64>,
-    _param_number: i64,
-    _param_index: &Vec<i64>,
-    _must_alias: AliasKind)
+    &mut self,
+    output_index: &Vec<i64>,
+    param_number: i64,
+    param_index: &Vec<i64>,
+    must_alias: AliasKind) -> Result<(), String>
   {
-    unimplemented!()
+    if !self.is_valid_output_index(output_index) {
+      return Err(format!("Trying to set up alias at {} which is an invalid index for shape {}",
+        shape_index_to_string(output_index), ShapeUtil::human_string(self.alias.shape())));
+    }
+    if param_number < 0 {
+      return Err(format!("Invalid parameter number {}", param_number));
+    }
+    if let Some(alias) = self.alias.element(output_index) {
+      return Err(format!("Trying to set up output alias for param {} at {} but failed: \
+        output index {} is already aliased with param {} at {}",
+        param_number, shape_index_to_string(param_index),
+        shape_index_to_string(output_index), alias.parameter_number,
+        shape_index_to_string(&alias.parameter_index)));
+    }
+    self.alias.set_element_value(output_index,
+      Some(Alias::new(param_number, param_index.clone(), must_alias)));
+    Ok(())
   }
 
   // Returns true if the given parameter is aliased with one of the output
   // buffers.
-  pub fn parameter_has_alias(&self, _param_number: i64, _param_index: &Vec<i64>) -> bool {
-    unimplemented!()
+  pub fn parameter_has_alias(&self, param_number: i64, param_index: &Vec<i64>) -> bool {
+    self.alias.nodes().iter().any(|node| match node.value() {
+      Some(alias) => alias.parameter_number == param_number &&
+        &alias.parameter_index == param_index,
+      None => false
+    })
   }
 
   // Checks whether the provided output index has already been aliased.
-  pub fn output_has_alias(_output_index: &Vec<i64>) -> bool {
-    unimplemented!()
+  pub fn output_has_alias(&self, output_index: &Vec<i64>) -> bool {
+    self.alias.element(output_index).is_some()
   }
 
   // Returns the number of parameter and index of the parameter buffer that the
   // given output buffer index is aliased with. A nullopt is returned if there
   // is no parameter is aliased with the specific output.
-  pub fn get_aliased_parameter(_output_index: &Vec<i64>) -> Option<Alias> {
-    unimplemented!()
+  pub fn get_aliased_parameter(&self, output_index: &Vec<i64>) -> Option<Alias> {
+    assert!(self.is_valid_output_index(output_index),
+      "Invalid output index {} for shape {}",
+      shape_index_to_string(output_index), ShapeUtil::human_string(self.alias.shape()));
+    self.alias.element(output_index).clone()
+  }
+
+  // Returns the output index that the given parameter and parameter index is
+  // aliased with. A nullopt is returned if there is no output that is aliased
+  // with the parameter number and index.
+  pub fn get_aliased_output(&self, param_number: i64, param_index: &Vec<i64>) -> Option<Vec<i64>> {
+    for node in self.alias.nodes() {
+      if let Some(alias) = node.value() {
+        if alias.parameter_number == param_number && &alias.parameter_index == param_index {
+          return Some(node.index().clone());
+        }
+      }
+    }
+    None
   }
 
   // Returns if the parameter at the given parameter number and parameter
   // index must-alias with an output.
-  pub fn parameter_must_alias(_param_number: i64, _param_index: &Vec<i64>) -> bool {
-    unimplemented!()
+  pub fn parameter_must_alias(&self, param_number: i64, param_index: &Vec<i64>) -> bool {
+    self.alias.nodes().iter().any(|node| match node.value() {
+      Some(alias) => alias.parameter_number == param_number &&
+        &alias.parameter_index == param_index && alias.must_alias(),
+      None => false
+    })
   }
 
-  // Iterates through each aliased output and input.  
-  pub fn for_each_alias<F>(&self, _func: F)
-    where F: Fn(&Vec<i64>, &Alias)
+  // Iterates through each aliased output and input.
+  pub fn for_each_alias<F>(&self, mut func: F)
+    where F: FnMut(&Vec<i64>, &Alias)
   {
-    unimplemented!()   
+    for node in self.alias.nodes() {
+      if let Some(alias) = node.value() {
+        func(node.index(), alias);
+      }
+    }
   }
 
   // Verifies that the given config is valid for the given module.
@@ -135,15 +188,40 @@ impl HloInputOutputAliasConfig {
 
   // Returns the shape of the output of the alias config.
   pub fn shape(&self) -> &Shape {
-    unimplemented!()
+    self.alias.shape()
   }
 
   pub fn to_string(&self) -> String {
-    unimplemented!()
+    let mut out = "HloInputOutputAliasConfig\n".to_string();
+    out.push_str("  Output shape: ");
+    out.push_str(&ShapeUtil::human_string(self.alias.shape()));
+    out.push('\n');
+    self.for_each_alias(|output_index, alias| {
+      out.push_str("  OutputIndex ");
+      out.push_str(&shape_index_to_string(output_index));
+      out.push_str(" is ");
+      out.push_str(if alias.must_alias() { "must-aliased" } else { "may-aliased" });
+      out.push_str(" with parameter ");
+      out.push_str(&alias.parameter_number.to_string());
+      out.push_str(" at ");
+      out.push_str(&shape_index_to_string(&alias.parameter_index));
+      out.push_str(":\n");
+    });
+    out
   }
 
   pub fn to_short_string(&self) -> String {
-    unimplemented!()
+    let mut pieces = vec![];
+    self.for_each_alias(|output_index, alias| {
+      pieces.push(format!("{}: {}",
+        shape_index_to_string(output_index), alias.to_string()));
+    });
+    pieces.join(", ")
+  }
+
+  fn is_valid_output_index(&self, output_index: &[i64]) -> bool {
+    let index_vec = output_index.iter().map(|i| *i as usize).collect();
+    ShapeUtil::index_is_valid(self.alias.shape(), &index_vec)
   }
 }
 
@@ -151,5 +229,33 @@ impl HloInputOutputAliasConfig {
 pub struct HloBufferDonorConfig {}
 
 impl HloBufferDonorConfig {
-    
-}
\ No newline at end of file
+  pub fn new() -> Self {
+    HloBufferDonorConfig {}
+  }
+}
+#[cfg(test)]
+mod tests {
+  use common::{blitz_data::PrimitiveType, shape_util::ShapeUtil};
+  use super::*;
+
+  #[test]
+  fn test_setup_alias() {
+    let element = ShapeUtil::make_shape(&PrimitiveType::F32, vec![4]);
+    let output_shape = ShapeUtil::make_tuple_shape(vec![element.clone(), element]);
+    let mut config = HloInputOutputAliasConfig::new(output_shape);
+    assert!(config.setup_alias(&vec![1], 0, &vec![], AliasKind::Must).is_ok());
+
+    assert!(config.output_has_alias(&vec![1]));
+    assert!(!config.output_has_alias(&vec![0]));
+    assert!(config.parameter_has_alias(0, &vec![]));
+    assert!(config.parameter_must_alias(0, &vec![]));
+    assert_eq!(config.get_aliased_output(0, &vec![]), Some(vec![1]));
+    let alias = config.get_aliased_parameter(&vec![1]).unwrap();
+    assert_eq!(alias.parameter_number(), 0);
+    assert!(alias.parameter_index().is_empty());
+
+    // An output index can be aliased only once, and must exist in the shape.
+    assert!(config.setup_alias(&vec![1], 1, &vec![], AliasKind::May).is_err());
+    assert!(config.setup_alias(&vec![2], 1, &vec![], AliasKind::May).is_err());
+  }
+}
@@ -1,55 +1,33 @@
 #![allow(dead_code)]
 
-use std::collections::{HashMap, HashSet};
+use std::{collections::{HashMap, HashSet}, hash::{Hash, Hasher}};
 
 use common::{
   blitz_data::{
-    Algorithm, CholeskyOptions, ConvolutionDimensionNumbers, DotDimensionNumbers, FftType, FrontendAttributes, GatherDimensionNumbers, OpMetadata, PaddingConfig, PaddingType, ParameterReplication, Precision, PrecisionConfig, PrimitiveType, RandomAlgorithm, RandomDistribution, ReplicaGroup, ResultAccuracy, ScatterDimensionNummbers, SliceDimensions, SparsityDescriptor, Statisitic, StatisticsViz, TriangularSolveOptions, WhileLoopBackendConfig, Window
+    Algorithm, CholeskyOptions, ConvolutionDimensionNumbers, CustomCallApiVersion, CustomCallSchedule, DotDimensionNumbers, FftType, FrontendAttributes, GatherDimensionNumbers, OpMetadata, PaddingConfig, PaddingType, ParameterReplication, PrimitiveType, Precision, PrecisionConfig, RandomAlgorithm, RandomDistribution, ReplicaGroup, ResultAccuracy, ScatterDimensionNummbers, SliceDimensions, SparsityDescriptor, Statisitic, StatisticsViz, Transpose, TriangularSolveOptions, WhileLoopBackendConfig, Window, WindowDimension
+  },
+  comparison_util::{
+    comparison_direction_to_string, comparison_type_to_string,
+    default_comparison_type, ComparisonDirection, ComparisonType
+  },
+  dyn_literal::{DynLiteral, Element},
+  literal_serialization::{
+    shape_to_proto, write_bytes_field, write_packed_varints, write_varint_field
   },
-  comparison_util::{ComparisonDirection, ComparisonType},
-  literal::Literal,
   printer::{Printer, StringPrinter},
-  shape::Shape, shape_util::ShapeUtil
+  shape::Shape, shape_util::ShapeUtil,
+  util::c_escape
 };
 
 use crate::{
+  collective_device_list::replica_groups_to_string,
   dfs_hlo_visitor_with_default::DfsHloVisitor,
   hlo_computation::HloComputation,
   hlo_domain_metadata::DomainMetadata,
-  hlo_instructions::{
-    HloAsyncInstruction,
-    HloAsyncStartInstruction,
-    HloBatchNormGradInstruction,
-    HloBatchNormInferenceInstruction,
-    HloBatchNormTrainingInstruction,
-    //HloBroadcastInstruction,
-    HloCallInstruction,
-    HloCollectiveInstruction,
-    HloCompareInstruction,
-    //HloConcatenateInstruction,
-    HloConstantInstruction,
-    HloCopyStartInstruction,
-    HloDynamicReshapeInstruction,
-    HloDynamicSliceInstruction,
-    HloDynamicUpdateSliceInstruction,
-    //HloGetTupleElementInstruction,
-    HloInfeedInstruction,
-    HloIotaInstruction,
-    HloMapInstruction,
-    HloOutfeedInstruction,
-    //HloParameterInstruction,
-    HloRecvDoneInstruction,
-    HloRecvInstruction,
-    HloReduceInstruction,
-    HloReducePrecisionInstruction,
-    HloReshapeInstruction,
-    HloSendDoneInstruction,
-    HloSendInstruction,
-    HloSliceInstruction,
-    HloSortInstruction,
-    HloTopKInstruction,
-    HloTransposeInstruction
-  }, hlo_module::HloModule, hlo_opcode::HloOpcode, hlo_sharding::HloSharding
+  hlo_instructions::HloCollectiveInstruction,
+  hlo_module::{ComputationId, HloModule},
+  hlo_opcode::{hlo_opcode_string, HloOpcode},
+  hlo_sharding::{op_metadata_to_string, HloSharding}
 };
 
 #[derive(Clone, PartialEq)]
@@ -60,6 +38,7 @@ pub enum PrintSubcomputationMode {
   NonSequentialBodies,
 }
 
+#[derive(Clone)]
 pub struct HloPrintOptions {
   print_operand_index_annotation_interval: i64,
   print_subcomputation_mode: PrintSubcomputationMode,
@@ -358,6 +337,10 @@ impl HloPrintOptions {
     self.canonicalize_instruction_names
   }
 
+  pub fn canonicalize_computations(&self) -> bool {
+    self.canonicalize_computations
+  }
+
   pub fn indent_amount(&self) -> i64 {
     self.indent_amount
   }
@@ -371,6 +354,80 @@ impl HloPrintOptions {
   }
 }
 
+// Instructions refer to their operands and called computations by id, so
+// printing them needs the computation and module they live in. Whatever is
+// not available is printed by its id instead.
+#[derive(Clone, Copy)]
+pub struct HloPrintContext<'a> {
+  computation: Option<&'a HloComputation>,
+  module: Option<&'a HloModule>,
+}
+
+impl<'a> HloPrintContext<'a> {
+  pub fn new(
+    computation: Option<&'a HloComputation>, module: Option<&'a HloModule>) -> Self
+  {
+    HloPrintContext { computation: computation, module: module }
+  }
+
+  pub fn empty() -> Self {
+    HloPrintContext { computation: None, module: None }
+  }
+
+  pub fn computation(&self) -> Option<&'a HloComputation> {
+    self.computation
+  }
+
+  pub fn module(&self) -> Option<&'a HloModule> {
+    self.module
+  }
+
+  // Returns the operand 'id' of 'instruction', if it can be resolved.
+  pub fn operand(
+    &self, instruction: &HloInstruction, id: InstructionId) -> Option<&'a HloInstruction>
+  {
+    if let Some(computation) = self.computation {
+      if computation.id() == instruction.parent() && computation.contains(id) {
+        return Some(computation.instruction(id));
+      }
+    }
+    let module = self.module?;
+    let parent = instruction.parent()?;
+    if !module.contains_computation(parent) || !module.computation(parent).contains(id) {
+      return None;
+    }
+    Some(module.computation(parent).instruction(id))
+  }
+
+  // Returns the computation 'id' of the module, if it can be resolved.
+  pub fn called_computation(&self, id: ComputationId) -> Option<&'a HloComputation> {
+    let module = self.module?;
+    if !module.contains_computation(id) {
+      return None;
+    }
+    Some(module.computation(id))
+  }
+}
+
+// Maps the unique ids of the instructions of a computation to the names
+// they are given when printed in canonical form: tmp_0, tmp_1, ...
+pub struct CanonicalNameMap {
+  canonical_name_map: HashMap<i64, String>,
+}
+
+impl CanonicalNameMap {
+  pub fn new() -> Self {
+    CanonicalNameMap { canonical_name_map: HashMap::new() }
+  }
+
+  pub fn lookup_or_insert(&mut self, unique_id: i64) -> String {
+    let next = self.canonical_name_map.len();
+    self.canonical_name_map.entry(unique_id)
+      .or_insert_with(|| format!("tmp_{}", next))
+      .clone()
+  }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum FusionKind {
   Loop,
@@ -381,13 +438,30 @@ pub enum FusionKind {
 
 pub const MAIN_EXECUTION_THREAD: &'static str = "main";
 
+// Stable handle of an instruction inside the arena of its parent
+// computation. The id stays valid until the instruction is removed from the
+// computation and is never reused for another instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InstructionId(usize);
+
+impl InstructionId {
+  pub fn new(index: usize) -> Self {
+    InstructionId(index)
+  }
+
+  // Returns the slot of the instruction in the arena of its computation.
+  pub fn index(&self) -> usize {
+    self.0
+  }
+}
+
 // Rare is allocated lazily, only when any of its constituent fields are
   // non-empty.  This reduces the memory footprint of HloInstruction objects.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Rare {
-  called_computations: Vec<HloComputation>,
-  control_predecessors: Vec<HloInstruction>,
-  control_successors: Vec<HloInstruction>,
+  called_computations: Vec<ComputationId>,
+  control_predecessors: Vec<InstructionId>,
+  control_successors: Vec<InstructionId>,
   // Attributes passed from the frontend to give hints to the backend about
   // how to compile this HLO.
   // HLO -> HLO transforms are expected to preserve these attributes on a
@@ -402,9 +476,21 @@ struct Rare {
   statistics_vis: StatisticsViz,
 }
 
+impl Rare {
+  fn new() -> Self {
+    Rare {
+      called_computations: Vec::new(),
+      control_predecessors: Vec::new(),
+      control_successors: Vec::new(),
+      frontend_attributes: FrontendAttributes::new(),
+      statistics_vis: StatisticsViz::new(),
+    }
+  }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Users {
-  users: Vec<HloInstruction>
+  users: Vec<InstructionId>
 }
 
 impl Users {
@@ -420,11 +506,11 @@ impl Users {
     self.users.len()
   }
 
-  pub fn vec(&self) -> &Vec<HloInstruction> {
+  pub fn vec(&self) -> &Vec<InstructionId> {
     &self.users
   }
 
-  pub fn mutable_vec(&mut self) -> &mut Vec<HloInstruction> {
+  pub fn mutable_vec(&mut self) -> &mut Vec<InstructionId> {
     &mut self.users
   }
 
@@ -432,30 +518,46 @@ impl Users {
     self.users.clear()
   }
 
-  pub fn contains(&self, _instruction: &HloInstruction) -> bool {
-    false //self.users.contains(instruction)
+  pub fn contains(&self, instruction: InstructionId) -> bool {
+    self.users.contains(&instruction)
   }
 
-  pub fn add_user(&mut self, user: HloInstruction) {
-    self.users.push(user)
+  // Adds 'user' unless it is already a user. Users are unique even if the
+  // user consumes this instruction as several operands.
+  pub fn add_user(&mut self, user: InstructionId) {
+    if !self.contains(user) {
+      self.users.push(user);
+    }
   }
 
-  pub fn maybe_remove_user() {}
+  // Removes 'user' if it is a user, returns whether anything was removed.
+  pub fn maybe_remove_user(&mut self, user: InstructionId) -> bool {
+    match self.users.iter().position(|u| *u == user) {
+      Some(index) => { self.users.remove(index); true }
+      None => false
+    }
+  }
 
-  pub fn remove_user(&mut self, user: HloInstruction) {
-    let mut target_index = 0;
-    for i in 0..self.users.len() {
-      if self.users.get(i) == Some(&user) {
-        target_index = i;
-      }
+  pub fn remove_user(&mut self, user: InstructionId) {
+    let removed = self.maybe_remove_user(user);
+    assert!(removed, "{:?} is not a user.", user);
+  }
+
+  pub fn user_id(&self, user: InstructionId) -> i64 {
+    match self.users.iter().position(|u| *u == user) {
+      Some(index) => index as i64,
+      None => panic!("{:?} is not a user.", user)
     }
-    self.users.remove(target_index);
   }
 
-  pub fn user_id(&self, _user: &HloInstruction) -> i64 { 0 }
+  pub fn sort_instruction_users(&mut self) {
+    self.users.sort();
+  }
 
-  pub fn sort_instruction_users() {}
-  pub fn check_invariants() {}
+  pub fn check_invariants(&self) {
+    let unique: HashSet<&InstructionId> = self.users.iter().collect();
+    assert_eq!(unique.len(), self.users.len(), "Users are not unique.");
+  }
 }
 
 struct BackendConfigRep {}
@@ -467,30 +569,124 @@ const SCATTER_COMPUTATION_INDEX: usize = 1;
 const TRUE_COMPUTATION_INDEX: usize = 0;
 const FALSE_COMPUTATION_INDEX: usize = 1;
 
+// Field numbers of HloInstructionProto.
+const INSTRUCTION_NAME: u32 = 1;
+const INSTRUCTION_OPCODE: u32 = 2;
+const INSTRUCTION_SHAPE: u32 = 3;
+const INSTRUCTION_LITERAL: u32 = 8;
+const INSTRUCTION_PARAMETER_NUMBER: u32 = 9;
+const INSTRUCTION_TUPLE_INDEX: u32 = 13;
+const INSTRUCTION_DIMENSIONS: u32 = 14;
+const INSTRUCTION_ID: u32 = 35;
+const INSTRUCTION_OPERAND_IDS: u32 = 36;
+const INSTRUCTION_CALLED_COMPUTATION_IDS: u32 = 38;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct HloInstruction {
+  id: Option<InstructionId>,
   unique_id: i64,
   index_in_parent: u32,
   opcode: HloOpcode,
   is_default_config: bool,
   cleaned_up: bool,
   marked_as_dead: bool,
-  operands: Vec<HloInstruction>,
+  operands: Vec<InstructionId>,
   rare: Option<Rare>,
   users: Users,
-  parent: Option<Box<HloComputation>>,
+  parent: Option<ComputationId>,
   sharding: Option<HloSharding>,
   shape: Shape,
   name: String,
   metadata: Option<OpMetadata>,
   collective_instruction: Option<HloCollectiveInstruction>,
+  parameter_number: i64,
+  tuple_index: i64,
+  dimensions: Vec<i64>,
+  // Opcode specific attributes. Only the ones that belong to the opcode of
+  // the instruction are meaningful, the others keep their default values.
+  literal: Option<DynLiteral>,
+  iota_dimension: i64,
+  inferred_dimension: i64,
+  slice_starts: Vec<i64>,
+  slice_limits: Vec<i64>,
+  slice_strides: Vec<i64>,
+  dynamic_slice_sizes: Vec<i64>,
+  window: Option<Window>,
+  convolution_dimension_numbers: Option<ConvolutionDimensionNumbers>,
+  feature_group_count: i64,
+  batch_group_count: i64,
+  dot_dimension_numbers: Option<DotDimensionNumbers>,
+  sparsity: Vec<SparsityDescriptor>,
+  precision_config: PrecisionConfig,
+  padding_config: Option<PaddingConfig>,
+  padding_type: PaddingType,
+  epsilon: f64,
+  feature_index: i64,
+  fft_type: FftType,
+  fft_length: Vec<i64>,
+  channel_id: Option<i64>,
+  replica_groups: Vec<ReplicaGroup>,
+  source_target_pairs: Vec<(i64, i64)>,
+  constrain_layout: bool,
+  use_global_device_ids: bool,
+  is_host_transfer: bool,
+  custom_call_target: String,
+  custom_call_has_side_effect: bool,
+  custom_call_schedule: CustomCallSchedule,
+  custom_call_api_version: CustomCallApiVersion,
+  operand_shapes_with_layout: Option<Vec<Shape>>,
+  output_to_operand_aliasing: Vec<(Vec<i64>, (i64, Vec<i64>))>,
+  raw_backend_config: String,
+  exponent_bits: i64,
+  mantissa_bits: i64,
+  distribution: RandomDistribution,
+  rng_algorithm: RandomAlgorithm,
+  delta: i64,
+  k: i64,
+  largest: bool,
+  is_stable: bool,
+  comparison_direction: Option<ComparisonDirection>,
+  comparison_type: Option<ComparisonType>,
+  fusion_kind: Option<FusionKind>,
+  infeed_config: String,
+  outfeed_config: String,
+  outfeed_shape: Option<Shape>,
+  triangular_solve_options: Option<TriangularSolveOptions>,
+  cholesky_options: Option<CholeskyOptions>,
+  gather_dimension_numbers: Option<GatherDimensionNumbers>,
+  gather_slice_sizes: Vec<i64>,
+  indices_are_sorted: bool,
+  unique_indices: bool,
+  scatter_dimension_numbers: Option<ScatterDimensionNummbers>,
+  parameter_replication: Option<ParameterReplication>,
+  cross_program_prefetch_index: Option<i64>,
+  async_execution_thread: String,
+  is_composite: bool,
+  operand_side_metadata: Option<DomainMetadata>,
+  user_side_metadata: Option<DomainMetadata>,
+  result_accuracy: Option<ResultAccuracy>,
+}
+
+impl Eq for HloInstruction {}
+
+// Instructions are hashed by their identity, the attributes only take part
+// in equality.
+impl Hash for HloInstruction {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.id.hash(state);
+    self.parent.hash(state);
+    self.opcode.hash(state);
+    self.name.hash(state);
+    self.unique_id.hash(state);
+  }
 }
 
 impl HloInstruction {
   pub fn default() -> Self {
     HloInstruction {
-      unique_id: 0,
+      id: None,
+      unique_id: -1,
       index_in_parent: 0,
       opcode: HloOpcode::Abs,
       is_default_config: false,
@@ -504,32 +700,113 @@ impl HloInstruction {
       shape: Shape::new(),
       name: "".to_string(),
       metadata: None,
-      collective_instruction: None
+      collective_instruction: None,
+      parameter_number: -1,
+      tuple_index: -1,
+      dimensions: Vec::new(),
+      literal: None,
+      iota_dimension: -1,
+      inferred_dimension: -1,
+      slice_starts: Vec::new(),
+      slice_limits: Vec::new(),
+      slice_strides: Vec::new(),
+      dynamic_slice_sizes: Vec::new(),
+      window: None,
+      convolution_dimension_numbers: None,
+      feature_group_count: 1,
+      batch_group_count: 1,
+      dot_dimension_numbers: None,
+      sparsity: Vec::new(),
+      precision_config: PrecisionConfig::new(),
+      padding_config: None,
+      padding_type: PaddingType::Invalid,
+      epsilon: 0.0,
+      feature_index: -1,
+      fft_type: FftType::FFT,
+      fft_length: Vec::new(),
+      channel_id: None,
+      replica_groups: Vec::new(),
+      source_target_pairs: Vec::new(),
+      constrain_layout: false,
+      use_global_device_ids: false,
+      is_host_transfer: false,
+      custom_call_target: "".to_string(),
+      custom_call_has_side_effect: false,
+      custom_call_schedule: CustomCallSchedule::None,
+      custom_call_api_version: CustomCallApiVersion::Original,
+      operand_shapes_with_layout: None,
+      output_to_operand_aliasing: Vec::new(),
+      raw_backend_config: "".to_string(),
+      exponent_bits: 0,
+      mantissa_bits: 0,
+      distribution: RandomDistribution::Invalid,
+      rng_algorithm: RandomAlgorithm::Default,
+      delta: 0,
+      k: 0,
+      largest: true,
+      is_stable: false,
+      comparison_direction: None,
+      comparison_type: None,
+      fusion_kind: None,
+      infeed_config: "".to_string(),
+      outfeed_config: "".to_string(),
+      outfeed_shape: None,
+      triangular_solve_options: None,
+      cholesky_options: None,
+      gather_dimension_numbers: None,
+      gather_slice_sizes: Vec::new(),
+      indices_are_sorted: false,
+      unique_indices: false,
+      scatter_dimension_numbers: None,
+      parameter_replication: None,
+      cross_program_prefetch_index: None,
+      async_execution_thread: MAIN_EXECUTION_THREAD.to_string(),
+      is_composite: false,
+      operand_side_metadata: None,
+      user_side_metadata: None,
+      result_accuracy: None,
+    }
+  }
+
+  // Creates an instruction with the given opcode, shape and operands. The
+  // operands must already live in the computation the instruction is added to.
+  fn new(opcode: HloOpcode, shape: &Shape, operands: &[&HloInstruction]) -> Self {
+    let mut instruction = HloInstruction::default();
+    instruction.opcode = opcode;
+    instruction.shape = shape.clone();
+    for operand in operands {
+      instruction.append_operand(operand.id());
     }
+    instruction
   }
 
   pub fn create_from_proto() {}
 
   // Creates a parameter-retrieving insstruction.
   pub fn create_parameter(
-    _parameter_number: i64,
-    _shape: &Shape,
-    _name: String) -> HloInstruction //HloParameterInstruction
+    parameter_number: i64,
+    shape: &Shape,
+    name: String) -> HloInstruction
   {
-    //HloParameterInstruction::new(parameter_number, shape, name)
-    unimplemented!()
+    let mut instruction = HloInstruction::new(HloOpcode::Parameter, shape, &[]);
+    instruction.parameter_number = parameter_number;
+    instruction.name = name;
+    instruction
   }
 
-  // Creates a literal constant instruction.
-  pub fn create_constant<T>(literal: Literal<T>) -> HloConstantInstruction<T>
-    where T: Clone + Default + PartialEq
-  {
-    HloConstantInstruction::new(literal)
+  // Creates a literal constant instruction, whose shape is the shape of
+  // 'literal'.
+  pub fn create_constant(literal: DynLiteral) -> HloInstruction {
+    let mut instruction = HloInstruction::new(HloOpcode::Constant, literal.shape(), &[]);
+    instruction.literal = Some(literal);
+    instruction
   }
 
   // Creates an iota instruction.
-  pub fn create_iota(shape: &Shape, iota_dimension: i64) -> HloIotaInstruction {
-    HloIotaInstruction::new(shape, iota_dimension)
+  pub fn create_iota(shape: &Shape, iota_dimension: i64) -> HloInstruction {
+    let mut instruction = HloInstruction::new(HloOpcode::Iota, shape, &[]);
+    instruction.iota_dimension = iota_dimension;
+    instruction
   }
 
   // Creates a top-k instruction returning the top k values along the last
@@ -538,51 +815,110 @@ impl HloInstruction {
     shape: &Shape,
     input: &HloInstruction,
     k: i64,
-    largest: bool) -> HloTopKInstruction
+    largest: bool) -> HloInstruction
   {
-    HloTopKInstruction::new(shape, input, k, largest)
+    let mut instruction = HloInstruction::new(HloOpcode::TopK, shape, &[input]);
+    instruction.k = k;
+    instruction.largest = largest;
+    instruction
   }
 
   // Creates a get tuple element instruction.
   pub fn create_get_tuple_element_by_shape(
-    _shape: &Shape,
-    _operand: &HloInstruction,
-    _index: i64) -> HloInstruction
+    shape: &Shape,
+    operand: &HloInstruction,
+    index: i64) -> HloInstruction
   {
-    //HloGetTupleElementInstruction::new(shape, operand, index)
-    unimplemented!()
+    let mut instruction =
+      HloInstruction::new(HloOpcode::GetTupleElement, shape, &[operand]);
+    instruction.tuple_index = index;
+    instruction
   }
 
+  // Creates a get tuple element instruction whose shape is the 'index'-th
+  // element of the operand's tuple shape.
   pub fn create_get_tuple_element(
-    _operand: &HloInstruction,
-    _index: i64) -> HloInstruction
+    operand: &HloInstruction,
+    index: i64) -> HloInstruction
   {
-    //HloGetTupleElementInstruction::new(shape, operand, index)
-    unimplemented!()
+    assert!(operand.shape().is_tuple());
+    assert!(index >= 0 && (index as usize) < operand.shape().tuple_shapes_size());
+    let shape = operand.shape().tuple_shapes(index as usize).clone();
+    HloInstruction::create_get_tuple_element_by_shape(&shape, operand, index)
   }
 
   // Creates a random number generation instruction that fills a shape with
   // random numbers from a given distribution.
-  pub fn create_rng() {}
+  pub fn create_rng(
+    shape: &Shape,
+    distribution: RandomDistribution,
+    parameters: &[&HloInstruction]) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::new(HloOpcode::Rng, shape, parameters);
+    instruction.distribution = distribution;
+    instruction
+  }
+
+  // Creates a stateless random bit generator instruction that fills a shape
+  // with random bits.
+  pub fn create_rng_bit_generator(
+    shape: &Shape,
+    state: &HloInstruction,
+    algorithm: RandomAlgorithm) -> HloInstruction
+  {
+    let mut instruction =
+      HloInstruction::new(HloOpcode::RngBitGenerator, shape, &[state]);
+    instruction.rng_algorithm = algorithm;
+    instruction
+  }
 
-  pub fn create_rng_bit_generator() {}
-  pub fn create_rng_get_and_update_state() {}
+  // Creates an instruction to update the random number generator state to
+  // reflect the new state after 'delta' units of 32 random bits are generated
+  // and returns the old state.
+  pub fn create_rng_get_and_update_state(shape: &Shape, delta: i64) -> HloInstruction {
+    let mut instruction =
+      HloInstruction::new(HloOpcode::RngGetAndUpdateState, shape, &[]);
+    instruction.delta = delta;
+    instruction
+  }
 
   // Creates a unary instruction (one operand).
   pub fn create_unary(
-    _shape: &Shape,
-    _opcode: HloOpcode,
-    _operand: &HloInstruction) {}
+    shape: &Shape,
+    opcode: HloOpcode,
+    operand: &HloInstruction) -> HloInstruction
+  {
+    HloInstruction::create_nary(shape, opcode, &[operand])
+  }
+
+  // Creates a binary instruction (two operands).
+  pub fn create_binary(
+    shape: &Shape,
+    opcode: HloOpcode,
+    lhs: &HloInstruction,
+    rhs: &HloInstruction) -> HloInstruction
+  {
+    HloInstruction::create_nary(shape, opcode, &[lhs, rhs])
+  }
 
-  pub fn create_binary() {}
-  pub fn create_ternary() {}
+  // Creates a ternary instruction (three operands).
+  pub fn create_ternary(
+    shape: &Shape,
+    opcode: HloOpcode,
+    lhs: &HloInstruction,
+    rhs: &HloInstruction,
+    ehs: &HloInstruction) -> HloInstruction
+  {
+    HloInstruction::create_nary(shape, opcode, &[lhs, rhs, ehs])
+  }
 
+  // Creates a variadic instruction (an arbitrary number of operands).
   pub fn create_variadic(
-    _shape: &Shape,
-    _opcode: HloOpcode,
-    _operands: Vec<HloInstruction>)
+    shape: &Shape,
+    opcode: HloOpcode,
+    operands: &[&HloInstruction]) -> HloInstruction
   {
-
+    HloInstruction::create_nary(shape, opcode, operands)
   }
 
   // Creates a map instruction, where the computation (given by the handle) is
@@ -590,47 +926,108 @@ impl HloInstruction {
   // at a given index).
   pub fn create_map(
     shape: &Shape,
-    operands: Vec<HloInstruction>,
-    map_computation: HloComputation) -> HloMapInstruction
+    operands: &[&HloInstruction],
+    map_computation: ComputationId) -> HloInstruction
   {
-    HloMapInstruction::new(shape, operands, map_computation)
+    let mut instruction = HloInstruction::new(HloOpcode::Map, shape, operands);
+    instruction.append_computation(map_computation);
+    instruction
   }
 
-  pub fn create_convolve() {}
-  pub fn create_fft() {}
+  // Creates a convolution op, where rhs is the convolutional filter
+  // and window describes how the filter is applied to lhs.
+  pub fn create_convolve(
+    shape: &Shape,
+    lhs: &HloInstruction,
+    rhs: &HloInstruction,
+    feature_group_count: i64,
+    batch_group_count: i64,
+    window: Window,
+    dimension_numbers: ConvolutionDimensionNumbers,
+    precision_config: PrecisionConfig) -> HloInstruction
+  {
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Convolution, shape, &[lhs, rhs]);
+    instruction.feature_group_count = feature_group_count;
+    instruction.batch_group_count = batch_group_count;
+    instruction.window = Some(window);
+    instruction.convolution_dimension_numbers = Some(dimension_numbers);
+    instruction.precision_config = precision_config;
+    instruction
+  }
+
+  // Creates an FFT op, of the type indicated by fft_type.
+  pub fn create_fft(
+    shape: &Shape,
+    operand: &HloInstruction,
+    fft_type: FftType,
+    fft_length: Vec<i64>) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::new(HloOpcode::Fft, shape, &[operand]);
+    instruction.fft_type = fft_type;
+    instruction.fft_length = fft_length;
+    instruction
+  }
 
+  // Creates an async-start op that starts the computation 'async_computation'
+  // on the execution thread 'async_execution_thread'.
   pub fn create_async_start(
     shape: &Shape,
-    operands: Vec<HloInstruction>,
-    async_computation: HloComputation,
-    async_execution_thread: String) -> HloAsyncStartInstruction
+    operands: &[&HloInstruction],
+    async_computation: ComputationId,
+    async_execution_thread: String) -> HloInstruction
   {
-    HloAsyncStartInstruction::new(
-      shape, operands, async_computation, async_execution_thread)
+    let mut instruction =
+      HloInstruction::new(HloOpcode::AsyncStart, shape, operands);
+    instruction.append_computation(async_computation);
+    instruction.async_execution_thread = async_execution_thread;
+    instruction
   }
 
+  // Creates an async-update op which takes the state of the async-start (or
+  // a previous async-update).
   pub fn create_async_update(
     shape: &Shape,
-    operand: HloInstruction) -> HloAsyncInstruction
+    operand: &HloInstruction) -> HloInstruction
   {
-    HloAsyncInstruction::new(shape, operand)
+    HloInstruction::create_async_continuation(HloOpcode::AsyncUpdate, shape, operand)
   }
 
+  // Creates an async-done op which waits for the computation started by the
+  // chain 'operand' belongs to.
   pub fn create_async_done(
     shape: &Shape,
-    operand: HloInstruction) -> HloAsyncInstruction
+    operand: &HloInstruction) -> HloInstruction
+  {
+    HloInstruction::create_async_continuation(HloOpcode::AsyncDone, shape, operand)
+  }
+
+  fn create_async_continuation(
+    opcode: HloOpcode,
+    shape: &Shape,
+    operand: &HloInstruction) -> HloInstruction
   {
-    HloAsyncInstruction::new(shape, operand)
+    let mut instruction = HloInstruction::new(opcode, shape, &[operand]);
+    // The continuation refers to the same computation as the op starting
+    // the chain.
+    for computation in operand.called_computations() {
+      instruction.append_computation(*computation);
+    }
+    instruction.async_execution_thread = operand.async_execution_thread.clone();
+    instruction
   }
 
   // Creates a copy-start op, indicating whether this is a cross-program
   // prefetch or not.
   pub fn create_copy_start(
     shape: &Shape,
-    operand: HloInstruction,
-    cross_program_prefetch_index: Option<i64>) -> HloCopyStartInstruction
+    operand: &HloInstruction,
+    cross_program_prefetch_index: Option<i64>) -> HloInstruction
   {
-    HloCopyStartInstruction::new(shape, operand, cross_program_prefetch_index)
+    let mut instruction =
+      HloInstruction::new(HloOpcode::CopyStart, shape, &[operand]);
+    instruction.cross_program_prefetch_index = cross_program_prefetch_index;
+    instruction
   }
 
   // Creates a compare op, performing the comparison specified in direction.
@@ -639,61 +1036,292 @@ impl HloInstruction {
     lhs: &HloInstruction,
     rhs: &HloInstruction,
     direction: ComparisonDirection,
-    t: ComparisonType) -> HloCompareInstruction
+    t: ComparisonType) -> HloInstruction
+  {
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Compare, shape, &[lhs, rhs]);
+    instruction.comparison_direction = Some(direction);
+    instruction.comparison_type = Some(t);
+    instruction
+  }
+
+  pub fn create_triangular_solve(
+    shape: &Shape,
+    a: &HloInstruction,
+    b: &HloInstruction,
+    options: TriangularSolveOptions) -> HloInstruction
+  {
+    let mut instruction =
+      HloInstruction::new(HloOpcode::TriangularSolve, shape, &[a, b]);
+    instruction.triangular_solve_options = Some(options);
+    instruction
+  }
+
+  pub fn create_cholesky(
+    shape: &Shape,
+    a: &HloInstruction,
+    options: CholeskyOptions) -> HloInstruction
   {
-    HloCompareInstruction::new(shape, lhs, rhs, direction, t)
+    let mut instruction = HloInstruction::new(HloOpcode::Cholsky, shape, &[a]);
+    instruction.cholesky_options = Some(options);
+    instruction
   }
 
-  pub fn create_triangular_solve() {}
-  pub fn create_cholesky() {}
-  pub fn create_dot() {}
+  // Creates a dot op with operands 'lhs' and 'rhs' with contracting and batch
+  // dimensions specified in 'dimension_numbers'. If 'sparsity' is set, then
+  // 'sparse_meta' must also be present (and have the same size).
+  pub fn create_dot(
+    shape: &Shape,
+    lhs: &HloInstruction,
+    rhs: &HloInstruction,
+    dimension_numbers: DotDimensionNumbers,
+    precision_config: PrecisionConfig,
+    sparsity: Vec<SparsityDescriptor>,
+    sparse_meta: &[&HloInstruction]) -> HloInstruction
+  {
+    assert_eq!(sparsity.len(), sparse_meta.len());
+    let mut operands = vec![lhs, rhs];
+    operands.extend_from_slice(sparse_meta);
+    let mut instruction = HloInstruction::new(HloOpcode::Dot, shape, &operands);
+    instruction.dot_dimension_numbers = Some(dimension_numbers);
+    instruction.precision_config = precision_config;
+    instruction.sparsity = sparsity;
+    instruction
+  }
 
   // Creates a reduce-precision op, where operand is the data to reduce in
   // precision, and exponent_bits and mantissa-bits descirbe the precision
   // to reduce it to.
   pub fn create_reduce_precision(
     shape: &Shape,
-    operand: HloInstruction,
-    exponent_bits: i32,
-    mantissa_bits: i32) -> HloReducePrecisionInstruction
+    operand: &HloInstruction,
+    exponent_bits: i64,
+    mantissa_bits: i64) -> HloInstruction
+  {
+    let mut instruction =
+      HloInstruction::new(HloOpcode::ReducePrecision, shape, &[operand]);
+    instruction.exponent_bits = exponent_bits;
+    instruction.mantissa_bits = mantissa_bits;
+    instruction
+  }
+
+  // Creates an all-gather op, which concats the operands of all participants
+  // along all_gather_dimension.
+  pub fn create_all_gather(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    all_gather_dimension: i64,
+    replica_groups: Vec<ReplicaGroup>,
+    constrain_layout: bool,
+    channel_id: Option<i64>,
+    use_global_device_ids: bool) -> HloInstruction
+  {
+    HloInstruction::create_collective(HloOpcode::AllGather, shape, operands,
+      vec![all_gather_dimension], replica_groups, constrain_layout, channel_id,
+      use_global_device_ids)
+  }
+
+  pub fn create_all_gather_start(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    all_gather_dimension: i64,
+    replica_groups: Vec<ReplicaGroup>,
+    constrain_layout: bool,
+    channel_id: Option<i64>,
+    use_global_device_ids: bool) -> HloInstruction
+  {
+    HloInstruction::create_collective(HloOpcode::AllGatherStart, shape, operands,
+      vec![all_gather_dimension], replica_groups, constrain_layout, channel_id,
+      use_global_device_ids)
+  }
+
+  // Creates a cross replica reduction op.
+  pub fn create_all_reduce(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    reduce_computation: ComputationId,
+    replica_groups: Vec<ReplicaGroup>,
+    constrain_layout: bool,
+    channel_id: Option<i64>,
+    use_global_device_ids: bool) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::create_collective(
+      HloOpcode::AllReduce, shape, operands, vec![], replica_groups,
+      constrain_layout, channel_id, use_global_device_ids);
+    instruction.append_computation(reduce_computation);
+    instruction
+  }
+
+  // Creates a reduce-scatter operation which reduces its inputs across the
+  // given replica groups and then scatters the reduced data across the N
+  // participants.
+  pub fn create_reduce_scatter(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    reduce_computation: ComputationId,
+    replica_groups: Vec<ReplicaGroup>,
+    constrain_layout: bool,
+    channel_id: Option<i64>,
+    use_global_device_ids: bool,
+    scatter_dimension: i64) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::create_collective(
+      HloOpcode::ReduceScatter, shape, operands, vec![scatter_dimension],
+      replica_groups, constrain_layout, channel_id, use_global_device_ids);
+    instruction.append_computation(reduce_computation);
+    instruction
+  }
+
+  pub fn create_all_reduce_start(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    reduce_computation: ComputationId,
+    replica_groups: Vec<ReplicaGroup>,
+    constrain_layout: bool,
+    channel_id: Option<i64>,
+    use_global_device_ids: bool) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::create_collective(
+      HloOpcode::AllReduceStart, shape, operands, vec![], replica_groups,
+      constrain_layout, channel_id, use_global_device_ids);
+    instruction.append_computation(reduce_computation);
+    instruction
+  }
+
+  // An all-to-all op takes N array operands of the same shape and scatters
+  // them to N replicas. If 'split_dimension' is set, the op takes a single
+  // array operand and splits it along that dimension instead.
+  pub fn create_all_to_all(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    replica_groups: Vec<ReplicaGroup>,
+    constrain_layout: bool,
+    channel_id: Option<i64>,
+    split_dimension: Option<i64>) -> HloInstruction
+  {
+    let dimensions = match split_dimension {
+      Some(dim) => vec![dim],
+      None => vec![]
+    };
+    HloInstruction::create_collective(HloOpcode::AllToAll, shape, operands,
+      dimensions, replica_groups, constrain_layout, channel_id, false)
+  }
+
+  // Creates a collective broadcast op which broadcasts the operand of the
+  // root of each replica group to all the other participants.
+  pub fn create_collective_broadcast(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    replica_groups: Vec<ReplicaGroup>,
+    constrain_layout: bool,
+    channel_id: Option<i64>) -> HloInstruction
+  {
+    HloInstruction::create_collective(HloOpcode::CollectiveBroadcast, shape,
+      operands, vec![], replica_groups, constrain_layout, channel_id, false)
+  }
+
+  // Creates a communication instruction that permutes data cross replicas.
+  // Data is sent/received according to the (source_replica_id,
+  // target_replica_id) pairs in 'source_target_pairs'.
+  pub fn create_collective_permute(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    source_target_pairs: Vec<(i64, i64)>,
+    channel_id: Option<i64>) -> HloInstruction
+  {
+    let mut instruction =
+      HloInstruction::new(HloOpcode::CollectivePermute, shape, operands);
+    instruction.source_target_pairs = source_target_pairs;
+    instruction.channel_id = channel_id;
+    instruction
+  }
+
+  // Creates a communication instruction that initiates the start of
+  // CollectivePermute.
+  pub fn create_collective_permute_start(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    source_target_pairs: Vec<(i64, i64)>,
+    channel_id: Option<i64>) -> HloInstruction
   {
-    HloReducePrecisionInstruction::new(shape, operand, exponent_bits, mantissa_bits)
+    let mut instruction =
+      HloInstruction::new(HloOpcode::CollectivePermuteStart, shape, operands);
+    instruction.source_target_pairs = source_target_pairs;
+    instruction.channel_id = channel_id;
+    instruction
   }
 
-  pub fn create_all_gather() {}
-  pub fn create_all_gather_start() {}
-  pub fn create_all_reduce() {}
-  pub fn create_reduce_scatter() {}
-  pub fn create_all_reduce_start() {}
-  pub fn create_all_to_all() {}
-  pub fn create_collective_permute() {}
-  pub fn create_collective_permute_start() {}
+  fn create_collective(
+    opcode: HloOpcode,
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    dimensions: Vec<i64>,
+    replica_groups: Vec<ReplicaGroup>,
+    constrain_layout: bool,
+    channel_id: Option<i64>,
+    use_global_device_ids: bool) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::new(opcode, shape, operands);
+    instruction.dimensions = dimensions;
+    instruction.replica_groups = replica_groups;
+    instruction.constrain_layout = constrain_layout;
+    instruction.channel_id = channel_id;
+    instruction.use_global_device_ids = use_global_device_ids;
+    instruction
+  }
 
   // Creates an instruction that returns a u32 replica ID.
-  pub fn create_replica_id(_shape: &Shape) {}
+  pub fn create_replica_id(shape: &Shape) -> HloInstruction {
+    HloInstruction::new(HloOpcode::ReplicaId, shape, &[])
+  }
 
   // Creates an instruction that returns a u32 partition ID.
-  pub fn create_partition_id(_shape: &Shape) {}
+  pub fn create_partition_id(shape: &Shape) -> HloInstruction {
+    HloInstruction::new(HloOpcode::PartitionId, shape, &[])
+  }
 
-  pub fn create_convert() {}
+  // Creates a conversion instruction, where operand is the data to convert
+  // and shape is the target shape for the conversion.
+  pub fn create_convert(shape: &Shape, operand: &HloInstruction) -> HloInstruction {
+    HloInstruction::new(HloOpcode::Convert, shape, &[operand])
+  }
 
   // Creates a bitcast instruction, where operand is the data to convert
   // and shape is the target shape for the conversion.
-  pub fn create_bitcast(_shape: &Shape, _operand: &HloInstruction) -> HloInstruction {
-    unimplemented!()
+  pub fn create_bitcast(shape: &Shape, operand: &HloInstruction) -> HloInstruction {
+    HloInstruction::new(HloOpcode::Bitcast, shape, &[operand])
+  }
+
+  // Creates a bitcast conversion instruction, where operand is the data to
+  // convert and shape is the target shape for the conversion.
+  pub fn create_bitcast_convert(shape: &Shape, operand: &HloInstruction) -> HloInstruction {
+    HloInstruction::new(HloOpcode::BitcastConvert, shape, &[operand])
   }
 
-  pub fn create_bitcast_convert() {}
-  pub fn create_stochastic_convert() {}
+  // Creates a stochastic conversion instruction, where operand is the data to
+  // convert, random is a given random input to determine the rounding
+  // direction and shape is the target shape for the conversion.
+  pub fn create_stochastic_convert(
+    shape: &Shape,
+    operand: &HloInstruction,
+    random: &HloInstruction) -> HloInstruction
+  {
+    HloInstruction::new(HloOpcode::StochasticConvert, shape, &[operand, random])
+  }
 
   // Creates an infeed instruction, which reads data of the given shape
   // from the Infeed interface of the device.
   pub fn create_infeed(
     infeed_shape: &Shape,
-    token_operand: HloInstruction,
-    config: String) -> HloInfeedInstruction
+    token_operand: &HloInstruction,
+    config: String) -> HloInstruction
   {
-    HloInfeedInstruction::new(infeed_shape, token_operand, config)
+    let shape = ShapeUtil::make_tuple_shape(
+      vec![infeed_shape.clone(), ShapeUtil::make_token_shape()]);
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Infeed, &shape, &[token_operand]);
+    instruction.infeed_config = config;
+    instruction
   }
 
   // Creates an outfeed instruction, which outputs data. outfeed_shape is the
@@ -701,32 +1329,61 @@ impl HloInstruction {
   // which is a token.
   pub fn create_outfeed(
     outfeed_shape: &Shape,
-    operand: HloInstruction,
-    token_operand: HloInstruction,
-    outfeed_config: String) -> HloOutfeedInstruction
+    operand: &HloInstruction,
+    token_operand: &HloInstruction,
+    outfeed_config: String) -> HloInstruction
   {
-    HloOutfeedInstruction::new(outfeed_shape, operand, token_operand, outfeed_config)
+    let mut instruction = HloInstruction::new(HloOpcode::Outfeed,
+      &ShapeUtil::make_token_shape(), &[operand, token_operand]);
+    instruction.outfeed_shape = Some(outfeed_shape.clone());
+    instruction.outfeed_config = outfeed_config;
+    instruction
   }
 
   // Creates an asynchronous send instruction with the given channel id, which
   // initiates sending the operand data to a unique receive instruction in
   // another computation that has the same shannel id.
   pub fn create_send(
-    operand: HloInstruction,
-    token: HloInstruction,
+    operand: &HloInstruction,
+    token: &HloInstruction,
+    channel_id: i64,
+    is_host_transfer: bool) -> HloInstruction
+  {
+    // Send instruction produces a tuple of {aliased operand, U32 context,
+    // token}.
+    let shape = ShapeUtil::make_tuple_shape(vec![
+      operand.shape().clone(),
+      ShapeUtil::make_shape(&PrimitiveType::U32, vec![]),
+      ShapeUtil::make_token_shape()]);
+    HloInstruction::create_send_with_shape(
+      &shape, operand, token, channel_id, is_host_transfer)
+  }
+
+  pub fn create_send_with_shape(
+    shape: &Shape,
+    operand: &HloInstruction,
+    token: &HloInstruction,
     channel_id: i64,
-    is_host_transfer: bool) -> HloSendInstruction
+    is_host_transfer: bool) -> HloInstruction
   {
-    HloSendInstruction::new(operand, token, channel_id, is_host_transfer)
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Send, shape, &[operand, token]);
+    instruction.channel_id = Some(channel_id);
+    instruction.is_host_transfer = is_host_transfer;
+    instruction
   }
 
   // Blocks until data transfer for the Send instruction (operand) is complete.
   // The operand most be Senf.
   pub fn create_send_done(
-    operand: HloInstruction,
-    is_host_transfer: bool) -> HloSendDoneInstruction
+    operand: &HloInstruction,
+    is_host_transfer: bool) -> HloInstruction
   {
-    HloSendDoneInstruction::new(operand, is_host_transfer)
+    let mut instruction = HloInstruction::new(
+      HloOpcode::SendDone, &ShapeUtil::make_token_shape(), &[operand]);
+    instruction.channel_id = operand.channel_id;
+    instruction.is_host_transfer = is_host_transfer;
+    instruction
   }
 
   // Creates an asynchronous receive instruction with the given channel id.
@@ -734,196 +1391,527 @@ impl HloInstruction {
   // send instruction in another computation that has the same channel id.
   pub fn create_recv(
     shape: &Shape,
-    token: HloInstruction,
+    token: &HloInstruction,
+    channel_id: i64,
+    is_host_transfer: bool) -> HloInstruction
+  {
+    // Recv instruction produces a tuple of {receive buffer, U32 context,
+    // token}.
+    let recv_shape = ShapeUtil::make_tuple_shape(vec![
+      shape.clone(),
+      ShapeUtil::make_shape(&PrimitiveType::U32, vec![]),
+      ShapeUtil::make_token_shape()]);
+    HloInstruction::create_recv_with_shape(
+      &recv_shape, token, channel_id, is_host_transfer)
+  }
+
+  pub fn create_recv_with_shape(
+    shape: &Shape,
+    token: &HloInstruction,
     channel_id: i64,
-    is_host_transfer: bool) -> HloRecvInstruction
+    is_host_transfer: bool) -> HloInstruction
   {
-    HloRecvInstruction::new(shape, token, channel_id, is_host_transfer)
+    let mut instruction = HloInstruction::new(HloOpcode::Recv, shape, &[token]);
+    instruction.channel_id = Some(channel_id);
+    instruction.is_host_transfer = is_host_transfer;
+    instruction
   }
 
+  // Blocks until data transfer for the Recv instruction (operand) is complete
+  // and returns the receive buffer. The operand must be Recv.
   pub fn create_recv_done(
-    operand: HloRecvInstruction,
-    is_host_transfer: bool) -> HloRecvDoneInstruction
+    operand: &HloInstruction,
+    is_host_transfer: bool) -> HloInstruction
+  {
+    assert!(operand.shape().is_tuple());
+    let shape = ShapeUtil::make_tuple_shape(vec![
+      operand.shape().tuple_shapes(0).clone(),
+      ShapeUtil::make_token_shape()]);
+    HloInstruction::create_recv_done_with_shape(&shape, operand, is_host_transfer)
+  }
+
+  pub fn create_recv_done_with_shape(
+    shape: &Shape,
+    operand: &HloInstruction,
+    is_host_transfer: bool) -> HloInstruction
   {
-    HloRecvDoneInstruction::new(operand, is_host_transfer)
+    let mut instruction =
+      HloInstruction::new(HloOpcode::RecvDone, shape, &[operand]);
+    instruction.channel_id = operand.channel_id;
+    instruction.is_host_transfer = is_host_transfer;
+    instruction
   }
 
+  // Creates a slice instruction, where the operand is sliced by the given
+  // start/limit indices.
   pub fn create_slice(
     shape: &Shape,
-    operand: HloInstruction,
+    operand: &HloInstruction,
     start_indices: Vec<i64>,
     limit_indices: Vec<i64>,
-    strides: Vec<i64>) -> HloSliceInstruction
+    strides: Vec<i64>) -> HloInstruction
   {
-    HloSliceInstruction::new(shape, operand, start_indices, limit_indices, strides)
+    let mut instruction = HloInstruction::new(HloOpcode::Slice, shape, &[operand]);
+    instruction.slice_starts = start_indices;
+    instruction.slice_limits = limit_indices;
+    instruction.slice_strides = strides;
+    instruction
   }
 
+  // Creates a slice instruction, where the first operand is sliced by
+  // start indices specified in the second operand, and by size specified
+  // in 'slice_sizes'.
   pub fn create_dynamic_slice(
     shape: &Shape,
-    operand: HloInstruction,
-    start_indices: Vec<HloInstruction>,
-    slice_sizes: Vec<usize>) -> HloDynamicSliceInstruction
+    operand: &HloInstruction,
+    start_indices: &[&HloInstruction],
+    slice_sizes: Vec<i64>) -> HloInstruction
   {
-    HloDynamicSliceInstruction::new(shape, operand, start_indices, slice_sizes)
+    let mut operands = vec![operand];
+    operands.extend_from_slice(start_indices);
+    let mut instruction =
+      HloInstruction::new(HloOpcode::DynamicSlice, shape, &operands);
+    instruction.dynamic_slice_sizes = slice_sizes;
+    instruction
   }
 
+  // Creates a dynamic update slice instruction, which updates a slice
+  // of 'operand' with 'update' and 'start_indices'.
   pub fn create_dynamic_update_slice(
     shape: &Shape,
-    operand: HloInstruction,
-    update: HloInstruction,
-    start_indices: Vec<HloInstruction>) -> HloDynamicUpdateSliceInstruction
+    operand: &HloInstruction,
+    update: &HloInstruction,
+    start_indices: &[&HloInstruction]) -> HloInstruction
   {
-    HloDynamicUpdateSliceInstruction::new(shape, operand, update, start_indices)
+    let mut operands = vec![operand, update];
+    operands.extend_from_slice(start_indices);
+    HloInstruction::new(HloOpcode::DynamicUpdateSlice, shape, &operands)
   }
 
   pub fn create_concatenate(
-    _shape: &Shape,
-    _operands: Vec<HloInstruction>,
-    _dimension: i64) -> HloInstruction
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    dimension: i64) -> HloInstruction
   {
-    //HloConcatenateInstruction::new(shape, operands, dimension)
-    unimplemented!()
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Concatenate, shape, operands);
+    instruction.dimensions = vec![dimension];
+    instruction
   }
 
+  // Creates a reduce instruction, where the computation (given by the handle)
+  // is applied successively to every element in operand.
   pub fn create_reduce(
     shape: &Shape,
-    _operand: HloInstruction,
-    _init_value: HloInstruction,
+    operand: &HloInstruction,
+    init_value: &HloInstruction,
+    dimensions_to_reduce: Vec<i64>,
+    reduce_computation: ComputationId) -> HloInstruction
+  {
+    HloInstruction::create_reduce_variadic(shape, &[operand], &[init_value],
+      dimensions_to_reduce, reduce_computation)
+  }
+
+  // A more general, multiple-argument version of the above. The first half
+  // of the operands are the inputs and the second half are the init values.
+  pub fn create_reduce_variadic(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    init_values: &[&HloInstruction],
     dimensions_to_reduce: Vec<i64>,
-    reduce_computation: HloComputation) -> HloReduceInstruction
+    reduce_computation: ComputationId) -> HloInstruction
   {
-    HloReduceInstruction::new(
-      shape, Vec::new(), dimensions_to_reduce, reduce_computation)
+    let mut all_args = operands.to_vec();
+    all_args.extend_from_slice(init_values);
+    let mut instruction = HloInstruction::new(HloOpcode::Reduce, shape, &all_args);
+    instruction.dimensions = dimensions_to_reduce;
+    instruction.append_computation(reduce_computation);
+    instruction
   }
 
+  // Creates a reduce-window instruction, where the computation (given
+  // by the handle) is applied window-wise at each valid window position
+  // in the operand.
   pub fn create_reduce_window(
-    _shape: &Shape,
-    _operand: HloInstruction,
-    _init_value: HloInstruction) {}
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    init_values: &[&HloInstruction],
+    window: Window,
+    reduce_computation: ComputationId) -> HloInstruction
+  {
+    let mut all_args = operands.to_vec();
+    all_args.extend_from_slice(init_values);
+    let mut instruction =
+      HloInstruction::new(HloOpcode::ReduceWindow, shape, &all_args);
+    instruction.window = Some(window);
+    instruction.append_computation(reduce_computation);
+    instruction
+  }
 
   pub fn create_batch_norm_training(
     shape: &Shape,
-    operand: HloInstruction,
-    scale: HloInstruction,
-    offset: HloInstruction,
+    operand: &HloInstruction,
+    scale: &HloInstruction,
+    offset: &HloInstruction,
     epsilon: f64,
-    feature_index: i64) -> HloBatchNormTrainingInstruction
+    feature_index: i64) -> HloInstruction
   {
-    HloBatchNormTrainingInstruction::new(
-      shape, operand, scale, offset, epsilon, feature_index)
+    let mut instruction = HloInstruction::new(
+      HloOpcode::BatchNormTraining, shape, &[operand, scale, offset]);
+    instruction.epsilon = epsilon;
+    instruction.feature_index = feature_index;
+    instruction
   }
 
   pub fn create_batch_norm_inference(
     shape: &Shape,
-    operand: HloInstruction,
-    scale: HloInstruction,
-    offset: HloInstruction,
-    mean: HloInstruction,
-    variance: HloInstruction,
+    operand: &HloInstruction,
+    scale: &HloInstruction,
+    offset: &HloInstruction,
+    mean: &HloInstruction,
+    variance: &HloInstruction,
     epsilon: f64,
-    feature_index: i64) -> HloBatchNormInferenceInstruction
+    feature_index: i64) -> HloInstruction
   {
-    HloBatchNormInferenceInstruction::new(
-      shape, operand, scale, offset, mean, variance, epsilon, feature_index)
+    let mut instruction = HloInstruction::new(HloOpcode::BatchNormInference,
+      shape, &[operand, scale, offset, mean, variance]);
+    instruction.epsilon = epsilon;
+    instruction.feature_index = feature_index;
+    instruction
   }
 
   pub fn create_batch_norm_grad(
     shape: &Shape,
-    operand: HloInstruction,
-    scale: HloInstruction,
-    mean: HloInstruction,
-    variance: HloInstruction,
-    grad_output: HloInstruction,
+    operand: &HloInstruction,
+    scale: &HloInstruction,
+    mean: &HloInstruction,
+    variance: &HloInstruction,
+    grad_output: &HloInstruction,
     epsilon: f64,
-    feature_index: i64) -> HloBatchNormGradInstruction
+    feature_index: i64) -> HloInstruction
   {
-    HloBatchNormGradInstruction::new(
-      shape, operand, scale, mean, variance, grad_output, epsilon, feature_index)
+    let mut instruction = HloInstruction::new(HloOpcode::BatchNormGrad,
+      shape, &[operand, scale, mean, variance, grad_output]);
+    instruction.epsilon = epsilon;
+    instruction.feature_index = feature_index;
+    instruction
   }
 
-  pub fn create_select_and_scatter() {}
+  // Creates a scatter computation that scatters the 'source' array to the
+  // selected indices of each window.
+  pub fn create_select_and_scatter(
+    shape: &Shape,
+    operand: &HloInstruction,
+    select: ComputationId,
+    window: Window,
+    source: &HloInstruction,
+    init_value: &HloInstruction,
+    scatter: ComputationId) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::new(
+      HloOpcode::SelectAndScatter, shape, &[operand, source, init_value]);
+    // Select comes before scatter in the vector.
+    instruction.append_computation(select);
+    instruction.append_computation(scatter);
+    instruction.window = Some(window);
+    instruction
+  }
 
   // Creates a broadcast instruction.
   pub fn create_broadcast(
-    _shape: &Shape,
-    _operand: HloInstruction,
-    _broadcast_dimensions: Vec<i64>) -> HloInstruction //HloBroadcastInstruction
+    shape: &Shape,
+    operand: &HloInstruction,
+    broadcast_dimensions: Vec<i64>) -> HloInstruction
   {
-    //HloBroadcastInstruction::new(shape, operand, broadcast_dimensions)
-    unimplemented!()
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Broadcast, shape, &[operand]);
+    instruction.dimensions = broadcast_dimensions;
+    instruction
   }
 
   pub fn create_broadcast_sequence() {}
-  pub fn create_pad() {}
 
+  // Creates a pad instruction, where the operand is padded on the edges and
+  // between the elements with the given padding value.
+  pub fn create_pad(
+    shape: &Shape,
+    operand: &HloInstruction,
+    padding_value: &HloInstruction,
+    padding_config: PaddingConfig) -> HloInstruction
+  {
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Pad, shape, &[operand, padding_value]);
+    instruction.padding_config = Some(padding_config);
+    instruction
+  }
+
+  // Creates a reshape instruction, where the operand is flattened row-major
+  // order and then reshaped to the given result shape.
   pub fn create_reshape(
     shape: &Shape,
-    operand: HloInstruction,
-    inferred_dimension: i64) -> HloReshapeInstruction
+    operand: &HloInstruction,
+    inferred_dimension: i64) -> HloInstruction
   {
-    HloReshapeInstruction::new(shape, operand, inferred_dimension)
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Reshape, shape, &[operand]);
+    instruction.inferred_dimension = inferred_dimension;
+    instruction
   }
 
+  // Creates a dynamic reshape instruction. Similar to reshape but dynamic
+  // dimensions sizes are provided as additional variadic arguments.
   pub fn create_dynamic_reshape(
     shape: &Shape,
-    data_operand: HloInstruction,
-    dim_sizes: Vec<HloInstruction>) -> HloDynamicReshapeInstruction
+    data_operand: &HloInstruction,
+    dim_sizes: &[&HloInstruction]) -> HloInstruction
   {
-    HloDynamicReshapeInstruction::new(shape, data_operand, dim_sizes)
+    let mut operands = vec![data_operand];
+    operands.extend_from_slice(dim_sizes);
+    HloInstruction::new(HloOpcode::DynamicReshape, shape, &operands)
   }
 
+  // Creates a transpose instruction which permutes the operand dimensions.
   pub fn create_transpose(
     shape: &Shape,
-    operand: HloInstruction,
-    dimensions: Vec<i64>) -> HloTransposeInstruction
+    operand: &HloInstruction,
+    dimensions: Vec<i64>) -> HloInstruction
   {
-    HloTransposeInstruction::new(shape, operand, dimensions)
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Transpose, shape, &[operand]);
+    instruction.dimensions = dimensions;
+    instruction
   }
 
+  // Creates a n-ary sort op with a 'compare' computation which is used for
+  // comparisons in the sorting algorithm.
   pub fn create_sort(
     shape: &Shape,
     dimension: i64,
-    operands: Vec<HloInstruction>,
-    compare: HloComputation,
-    is_stable: bool) -> HloSortInstruction
+    operands: &[&HloInstruction],
+    compare: ComputationId,
+    is_stable: bool) -> HloInstruction
   {
-    HloSortInstruction::new(shape, dimension, operands, compare, is_stable)
+    let mut instruction = HloInstruction::new(HloOpcode::Sort, shape, operands);
+    instruction.dimensions = vec![dimension];
+    instruction.is_stable = is_stable;
+    instruction.append_computation(compare);
+    instruction
   }
 
+  // Creates a while instruction. 'condition' and 'body' must already be
+  // computations of the module the instruction ends up in.
   pub fn create_while(
-    _shape: &Shape,
-    _condition: HloComputation,
-    _body: HloComputation,
-    _init: HloInstruction) {}
-  
+    shape: &Shape,
+    condition: ComputationId,
+    body: ComputationId,
+    init: &HloInstruction) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::new(HloOpcode::While, shape, &[init]);
+    // Body comes before condition computation in the vector.
+    instruction.append_computation(body);
+    instruction.append_computation(condition);
+    instruction
+  }
+
   pub fn create_conditional(
-    _shape: &Shape,
-    _pred: HloInstruction,
-    _true_computation_arg: HloInstruction,
-    _true_computation: HloComputation,
-    _false_computation_arg: HloInstruction,
-    _false_computation: HloComputation) {}
-  
-  pub fn create_gather() {}
-  pub fn create_scatter() {}
-  pub fn create_domain() {}
-  pub fn create_fusion() {}
+    shape: &Shape,
+    pred: &HloInstruction,
+    true_computation_arg: &HloInstruction,
+    true_computation: ComputationId,
+    false_computation_arg: &HloInstruction,
+    false_computation: ComputationId) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::new(HloOpcode::Conditional, shape,
+      &[pred, true_computation_arg, false_computation_arg]);
+    // In called_computations, the index of true_computation must be 0 and
+    // that of false computation must be 1.
+    instruction.append_computation(true_computation);
+    instruction.append_computation(false_computation);
+    instruction
+  }
+
+  // Creates a conditional which calls the 'branch_index'-th computation of
+  // 'branch_computations' with the corresponding operand.
+  pub fn create_conditional_with_branches(
+    shape: &Shape,
+    branch_index: &HloInstruction,
+    branch_computations: Vec<ComputationId>,
+    branch_computation_args: &[&HloInstruction]) -> HloInstruction
+  {
+    assert_eq!(branch_computations.len(), branch_computation_args.len());
+    let mut operands = vec![branch_index];
+    operands.extend_from_slice(branch_computation_args);
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Conditional, shape, &operands);
+    for computation in branch_computations {
+      instruction.append_computation(computation);
+    }
+    instruction
+  }
+
+  pub fn create_gather(
+    shape: &Shape,
+    operand: &HloInstruction,
+    start_indices: &HloInstruction,
+    gather_dim_numbers: GatherDimensionNumbers,
+    slice_sizes: Vec<i64>,
+    indices_are_sorted: bool) -> HloInstruction
+  {
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Gather, shape, &[operand, start_indices]);
+    instruction.gather_dimension_numbers = Some(gather_dim_numbers);
+    instruction.gather_slice_sizes = slice_sizes;
+    instruction.indices_are_sorted = indices_are_sorted;
+    instruction
+  }
+
+  // The operands are the scatter inputs, followed by the indices and the
+  // updates.
+  pub fn create_scatter(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    scatter_indices: &HloInstruction,
+    updates: &[&HloInstruction],
+    update_computation: ComputationId,
+    scatter_dim_numbers: ScatterDimensionNummbers,
+    indices_are_sorted: bool,
+    unique_indices: bool) -> HloInstruction
+  {
+    let mut all_args = operands.to_vec();
+    all_args.push(scatter_indices);
+    all_args.extend_from_slice(updates);
+    let mut instruction =
+      HloInstruction::new(HloOpcode::Scatter, shape, &all_args);
+    instruction.append_computation(update_computation);
+    instruction.scatter_dimension_numbers = Some(scatter_dim_numbers);
+    instruction.indices_are_sorted = indices_are_sorted;
+    instruction.unique_indices = unique_indices;
+    instruction
+  }
+
+  // Creates a kDomain instruction which delimits an HLO domain which have
+  // the provided user and operand side metadata.
+  pub fn create_domain(
+    shape: &Shape,
+    operand: &HloInstruction,
+    operand_side_metadata: DomainMetadata,
+    user_side_metadata: DomainMetadata) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::new(HloOpcode::Domain, shape, &[operand]);
+    instruction.operand_side_metadata = Some(operand_side_metadata);
+    instruction.user_side_metadata = Some(user_side_metadata);
+    instruction
+  }
+
+  // Creates a fusion instruction which calls 'fused_computation' with the
+  // given operands.
+  pub fn create_fusion(
+    shape: &Shape,
+    fusion_kind: FusionKind,
+    operands: &[&HloInstruction],
+    fused_computation: ComputationId) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::new(HloOpcode::Fusion, shape, operands);
+    instruction.fusion_kind = Some(fusion_kind);
+    instruction.append_computation(fused_computation);
+    instruction
+  }
 
+  // Creates a call instruction that applies the given computation on the
+  // given operands.
   pub fn create_call(
     shape: &Shape,
-    called_computation_root: HloInstruction) -> HloCallInstruction
+    operands: &[&HloInstruction],
+    computation: ComputationId) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::new(HloOpcode::Call, shape, operands);
+    instruction.append_computation(computation);
+    instruction
+  }
+
+  // Creates a custom call instruction that applies the given custom call
+  // target to the given operands. 'opaque' can be an arbitrary string with a
+  // backend-specific interpretation.
+  pub fn create_custom_call(
+    shape: &Shape,
+    operands: &[&HloInstruction],
+    custom_call_target: String,
+    opaque: String,
+    api_version: CustomCallApiVersion) -> HloInstruction
+  {
+    let mut instruction =
+      HloInstruction::new(HloOpcode::CustomCall, shape, operands);
+    instruction.custom_call_target = custom_call_target;
+    instruction.raw_backend_config = opaque;
+    instruction.custom_call_api_version = api_version;
+    instruction
+  }
+
+  // Creates a tuple instruction with the given elements. This is a convenience
+  // wrapper around create_variadic.
+  pub fn create_tuple(elements: &[&HloInstruction]) -> HloInstruction {
+    let element_shapes: Vec<Shape> =
+      elements.iter().map(|e| e.shape().clone()).collect();
+    let tuple_shape = ShapeUtil::make_tuple_shape(element_shapes);
+    HloInstruction::create_variadic(&tuple_shape, HloOpcode::Tuple, elements)
+  }
+
+  // Creates a reverse instruction, which reverses the order of the elements
+  // in the specified dimensions.
+  pub fn create_reverse(
+    shape: &Shape,
+    operand: &HloInstruction,
+    dimensions: Vec<i64>) -> HloInstruction
+  {
+    let mut instruction = HloInstruction::new(HloOpcode::Reverse, shape, &[operand]);
+    instruction.dimensions = dimensions;
+    instruction
+  }
+
+  // Creates a Afterall instruction used for joining or creating new values of
+  // token type which thread through side-effecting operations.
+  pub fn create_after_all(operands: &[&HloInstruction]) -> HloInstruction {
+    assert!(!operands.is_empty());
+    HloInstruction::new(
+      HloOpcode::AfterAll, &ShapeUtil::make_token_shape(), operands)
+  }
+
+  // Creates an AfterAll instruction which creates a token type out of thin
+  // air (no operands).
+  pub fn create_token() -> HloInstruction {
+    HloInstruction::new(HloOpcode::AfterAll, &ShapeUtil::make_token_shape(), &[])
+  }
+
+  pub fn create_get_dimension_size(
+    shape: &Shape,
+    operand: &HloInstruction,
+    dimension: i64) -> HloInstruction
+  {
+    let mut instruction =
+      HloInstruction::new(HloOpcode::GetDimensionSize, shape, &[operand]);
+    instruction.dimensions = vec![dimension];
+    instruction
+  }
+
+  pub fn create_set_dimension_size(
+    shape: &Shape,
+    operand: &HloInstruction,
+    val: &HloInstruction,
+    dimension: i64) -> HloInstruction
   {
-    HloCallInstruction::new(shape, called_computation_root)
+    let mut instruction =
+      HloInstruction::new(HloOpcode::SetDimensionSize, shape, &[operand, val]);
+    instruction.dimensions = vec![dimension];
+    instruction
   }
 
-  pub fn create_custom_call() {}
-  pub fn create_tuple() {}
-  pub fn create_reverse() {}
-  pub fn create_after_all() {}
-  pub fn create_token() {}
-  pub fn create_get_dimension_size() {}
-  pub fn create_set_dimension_size() {}
-  pub fn create_add_dependency() {}
+  pub fn create_add_dependency(
+    data_operand: &HloInstruction,
+    token_operand: &HloInstruction) -> HloInstruction
+  {
+    HloInstruction::new(HloOpcode::AddDependency, data_operand.shape(),
+      &[data_operand, token_operand])
+  }
 
   // Returns true if 'execution_thread' is included in the 'execution_threads_set'.
   pub fn is_thread_included(
@@ -943,22 +1931,24 @@ impl HloInstruction {
     self.opcode = opcode;
   }
 
-  pub fn add_operand_ids(&mut self, _id: i64) {
-    unimplemented!()
+  pub fn add_operand_ids(&mut self, id: i64) {
+    assert!(id >= 0);
+    self.operands.push(InstructionId::new(id as usize));
   }
 
-  // Returns whether this instruciton is the root of its parent computation.
-  pub fn is_root(&self) -> bool {
-    if self.parent.is_some() {
-      let parent = self.parent.as_ref().unwrap().as_ref();
-      return parent.root_instruction() == self;
-    }
-    false
+  // Returns the id of this instruction in its parent computation.
+  pub fn id(&self) -> InstructionId {
+    assert!(self.id.is_some(), "{} has not been added to a computation.", self.name);
+    self.id.unwrap()
+  }
+
+  // Returns whether this instruction has been added to a computation.
+  pub fn has_id(&self) -> bool {
+    self.id.is_some()
   }
 
-  // Does this instruction have no users.
-  pub fn is_dead(&self) -> bool {
-    self.users.empty() && !self.is_root()
+  pub(crate) fn set_instruction_id(&mut self, id: InstructionId) {
+    self.id = Some(id);
   }
 
   // Returns true if this instruction has a side effect, irrespective of whether
@@ -979,6 +1969,7 @@ impl HloInstruction {
       HloOpcode::AllGatherDone => return true,
       HloOpcode::CollectivePermuteStart => return true,
       HloOpcode::CollectivePermuteDone => return true,
+      HloOpcode::CustomCall => return self.custom_call_has_side_effect(),
       _ => return false
     }
   }
@@ -1006,13 +1997,9 @@ impl HloInstruction {
     self.shape = shape;
   }
 
-  // Returns the i-th operand to this instruction.
-  pub fn operand(&self, i: usize) -> &HloInstruction {
-    self.operands.get(i).unwrap()
-  }
-
-  pub fn mutable_operand(&mut self, i: usize) -> Option<&mut HloInstruction> {
-    self.operands.get_mut(i)
+  // Returns the id of the i-th operand to this instruction.
+  pub fn operand(&self, i: usize) -> InstructionId {
+    self.operands[i]
   }
 
   // Returns the number of operands to this instruction.
@@ -1021,29 +2008,25 @@ impl HloInstruction {
   }
 
   // Returns the vector of operands of this instruction.
-  pub fn operands(&self) -> &Vec<HloInstruction> {
+  pub fn operands(&self) -> &Vec<InstructionId> {
     &self.operands
   }
 
-  pub fn mutable_operands(&mut self) -> &mut Vec<HloInstruction> {
-    &mut self.operands
-  }
-
   // Returns the vector of unique oprands, in the same order they are found
   // within the operand vector.
-  pub fn unique_operands(&self) -> Vec<HloInstruction> {
+  pub fn unique_operands(&self) -> Vec<InstructionId> {
     let mut unique = vec![];
     let mut seen = HashSet::new();
     for operand in self.operands() {
-      if seen.insert(operand) { unique.push(operand.clone()); }
+      if seen.insert(*operand) { unique.push(*operand); }
     }
     unique
   }
 
   // Returns the index of 'target' in the operands sequence.
-  pub fn operand_index(&self, target: &HloInstruction) -> usize {
+  pub fn operand_index(&self, target: InstructionId) -> usize {
     for i in 0..self.operand_count() {
-      if self.operands.get(i).unwrap() == target {
+      if self.operands[i] == target {
         return i;
       }
     }
@@ -1056,72 +2039,39 @@ impl HloInstruction {
   }
 
   // Returns the users of this instruction.
-  pub fn users(&self) -> &Vec<HloInstruction> {
+  pub fn users(&self) -> &Vec<InstructionId> {
     self.users.vec()
   }
 
-  pub fn mutable_users(&mut self) -> &mut Vec<HloInstruction> {
-    self.users.mutable_vec()
-  }
-
   // Returns the index of the user in the users vector.
-  pub fn user_id(&self, user: &HloInstruction) -> i64 {
+  pub fn user_id(&self, user: InstructionId) -> i64 {
     self.users.user_id(user)
   }
 
   // Returns true if this instruction is a user of 'instruction'.
   pub fn is_user_of(&self, instruction: &HloInstruction) -> bool {
-    instruction.users.contains(self)
+    instruction.users.contains(self.id())
   }
 
-  pub fn add_control_dependency_to() {}
-  pub fn remove_control_dependency_to() {}
-
-  // Drops all control predecessors and successors from this HLO instruction.
-  pub fn drop_all_control_deps(&mut self) -> Result<(), String> {
-    if self.has_rare() {
-      for _ctrl_succ in &mut self.mutable_rare().control_successors {
-        // TODO
-        //let mut index = MAX;
-        //for i in 0..ctrl_succ.rare().control_predecessors.len() {
-          //if ctrl_succ.rare().control_predecessors[i] == *self {
-            //index = i;
-            //break;
-          //}
-        //}
-        //ctrl_succ.mutable_rare().control_predecessors.remove(index);
-      }
-      for _ctrl_pred in &self.rare().control_predecessors {
-        // TODO
-      }
-      self.mutable_rare().control_successors.clear();
-      self.mutable_rare().control_predecessors.clear();
-    }
-    Ok(())
-  }
-
-  pub fn safely_drop_all_control_dependencies() {}
-
-  // Returns if instruction has any control dependencies.
-  pub fn has_control_dependencies(&self) -> bool {
-    false
-  }
-
-  // Copies the control predecessors and successors on this HLO instruction to
-  // 'inst'.
-  pub fn copy_all_control_deps_from(&self, _inst: &HloInstruction) -> Result<(), String> {
-    unimplemented!()
-  }
+  // Returns if instruction has any control dependencies.
+  pub fn has_control_dependencies(&self) -> bool {
+    !self.control_predecessors().is_empty() ||
+    !self.control_successors().is_empty()
+  }
 
   // Returns the set of control predecessors / successors of this instruction.
-  pub fn control_predecessors(&self) -> &Vec<HloInstruction>{
-    assert!(self.rare.is_some());
-    &self.rare.as_ref().unwrap().control_predecessors
+  pub fn control_predecessors(&self) -> &[InstructionId] {
+    match &self.rare {
+      Some(rare) => &rare.control_predecessors,
+      None => &[]
+    }
   }
 
-  pub fn control_successors(&self) -> &Vec<HloInstruction> {
-    assert!(self.rare.is_some());
-    &self.rare.as_ref().unwrap().control_successors
+  pub fn control_successors(&self) -> &[InstructionId] {
+    match &self.rare {
+      Some(rare) => &rare.control_successors,
+      None => &[]
+    }
   }
 
   pub fn identical() -> bool {
@@ -1135,134 +2085,6 @@ impl HloInstruction {
   pub fn identical_ignoring_commutative_operand_order() {}
   pub fn identical_ignoring_channel_id_values() {}
 
-  // Returns whether the instruction has a constant operand.
-  pub fn has_constant_operand(&self) -> bool {
-    for operand in &self.operands {
-      if operand.is_constant() { return true; }
-    }
-    false
-  }
-
-  // Replaces the use of this instruction in 'user' with 'new_producer'.
-  pub fn replace_use_with(
-    &mut self, user: &HloInstruction, new_producer: &mut HloInstruction) -> Result<(), String>
-  {
-    debug_assert!(ShapeUtil::compatible_ignoring_fp_precision(self.shape(), new_producer.shape()));
-    self.replace_use_with_different_shape(user, new_producer)
-  }
-
-  // Same as replace_use_with(), but new_producer can have a different shape.
-  pub fn replace_use_with_different_shape(
-    &mut self, user: &HloInstruction, new_producer: &mut HloInstruction) -> Result<(), String>
-  {
-    println!("Replacing uses of {:?} in  {:?} with {:?}.",
-      self.name(), user.name(), new_producer.name());
-    
-    self.remove_user(user.clone()); // check
-
-    let mut count = 0;
-    for operand in user.operands() {
-      if operand == self { count += 1; }
-    }
-    debug_assert!(count >= 0);
-
-    // TODO
-    new_producer.add_user(user.clone());
-    if user.opcode() == HloOpcode::Fusion {
-      let result = user.deduplicate_fusion_operands();
-      if result.is_err() { return result; }
-    }
-
-    Ok(())
-  }
-
-  // Replaces the specified operand with new_operand. The old and new operands
-  // must have compatible shapes ignoring floating-point precision.
-  //
-  // This function does NOT remove duplicated operands even if this instruction
-  // is a fusion, so that the existing operand numbers do not change.
-  pub fn replace_operand_with(
-    &mut self, _operand_num: i64, _new_operand: HloInstruction) -> Result<(), String>
-  {
-    unimplemented!()
-  }
-
-  pub fn replace_operand_with_different_shape() {}
-
-  // Decomposes fusion back to individual parts.
-  pub fn defuse(&mut self) -> Result<(), String> {
-    if self.opcode() != HloOpcode::Fusion {
-      return Ok(());
-    }
-    println!("Defusiing instruction: {:?}", self.to_string_default());
-
-    let _fused_computation = self.fused_instructions_computation();
-    let mut _defused_instructions: HashMap<&HloInstruction, &mut HloInstruction> = HashMap::new();
-    for _i in 0..self.operand_count() {
-      //defused_instructions.insert(
-        //fused_computation.parameter_instruction(i).unwrap(),
-        //self.mutable_operand(i).unwrap());
-    }
-
-    // TODO
-
-    Ok(())
-  }
-
-  // Replaces all uses of this instruction with the new producer.
-  pub fn replace_all_uses_with(
-    &mut self, new_producer: &mut HloInstruction, trigger: String) -> Result<(), String>
-  {
-    let mut print_options = HloPrintOptions::short_parsable();
-    print_options.set_print_operand_shape(true);
-    print_options.set_print_extra_attributes(false);
-
-    if !ShapeUtil::compatible_ignoring_fp_precision(self.shape(), new_producer.shape()) {
-      let mut result = "The shape doesn't match when replacing".to_string();
-      result.push_str(&self.to_string(&print_options));
-      result.push_str(" with ");
-      result.push_str(&new_producer.to_string(&print_options));
-      result.push_str(". ");
-      result.push_str(&self.shape().to_string(false));
-      result.push_str(" is not compatible with ");
-      result.push_str(&new_producer.shape().to_string(false));
-      result.push('\n');
-      result.push_str(&trigger);
-      result.push('\n');
-      result.push_str(" triggered this wrong replacement.");
-      return Err(result);
-    }
-    self.replace_all_uses_with_different_shape(new_producer)
-  }
-
-  // Same as replace_all_uses_with, but new_producer can have a different shape.
-  pub fn replace_all_uses_with_different_shape(
-    &mut self, new_producer: &mut HloInstruction) -> Result<(), String>
-  {
-    let mut new_producer_is_user = false;
-    for user in self.users() {
-      if user == new_producer {
-        new_producer_is_user = true;
-      } else {
-        // TODO
-        new_producer.add_user(user.clone());
-        if user.opcode() == HloOpcode::Fusion {
-          // TODO
-        }
-      }
-    }
-    self.users.clear();
-    if new_producer_is_user {
-      self.add_user(new_producer.clone());
-    }
-    if self.parent.is_some() && self.parent().root_instruction() == self {
-      self.mutable_parent().set_root_instruction(
-        new_producer.clone(), true);
-    }
-
-    Ok(())
-  }
-
   pub fn accept(&self,
     _visitor: &dyn DfsHloVisitor,
     _call_finish_visit: bool,
@@ -1279,18 +2101,19 @@ impl HloInstruction {
   pub fn is_effective_bitcast() {}
 
   // Gets/sets the to_apply HloComputation for call, map, reduce, etc.
-  pub fn to_apply(&self) -> &HloComputation {
+  pub fn to_apply(&self) -> ComputationId {
     if self.has_to_apply() {
       assert!(self.called_computations().len() == 1);
-      return self.called_computations().get(0).unwrap();
+      return self.called_computations()[0];
     }
     unreachable!("Invalid opcode for to_apply().");
   }
 
-  pub fn set_to_apply(&mut self, computation: HloComputation) {
+  pub fn set_to_apply(&mut self, computation: ComputationId) {
     if self.has_to_apply() {
       assert!(self.called_computations().len() == 1);
-      self.mutable_called_computations().insert(0, computation);
+      self.mutable_called_computations()[0] = computation;
+      return;
     }
     unreachable!("Invalid opcode for to_apply().");
   }
@@ -1313,52 +2136,47 @@ impl HloInstruction {
   }
 
   // Get/sets the while_condition of while_body HloComputation for while.
-  pub fn while_condition(&self) -> &HloComputation {
+  pub fn while_condition(&self) -> ComputationId {
     assert!(self.opcode == HloOpcode::While);
-    self.called_computations().get(CONDITION_COMPUTATION_INDEX).unwrap()
+    self.called_computations()[CONDITION_COMPUTATION_INDEX]
   }
 
-  pub fn while_body(&self) -> &HloComputation {
+  pub fn while_body(&self) -> ComputationId {
     assert!(self.opcode == HloOpcode::While);
-    self.called_computations().get(BODY_COMPUTATION_INDEX).unwrap()
+    self.called_computations()[BODY_COMPUTATION_INDEX]
   }
 
-  pub fn mutable_while_body(&mut self) -> &mut HloComputation {
-    unimplemented!()
-  }
-
-  pub fn set_while_condition(&mut self, computation: HloComputation) {
+  pub fn set_while_condition(&mut self, computation: ComputationId) {
     assert!(self.opcode == HloOpcode::While);
-    self.mutable_called_computations()
-      .insert(CONDITION_COMPUTATION_INDEX, computation);
+    self.mutable_called_computations()[CONDITION_COMPUTATION_INDEX] = computation;
   }
 
-  pub fn set_while_body(&mut self, computation: HloComputation) {
+  pub fn set_while_body(&mut self, computation: ComputationId) {
     assert!(self.opcode == HloOpcode::While);
-    self.mutable_called_computations()
-      .insert(BODY_COMPUTATION_INDEX, computation);
+    self.mutable_called_computations()[BODY_COMPUTATION_INDEX] = computation;
   }
 
-  pub fn while_init(&self) -> &HloInstruction {
+  pub fn while_init(&self) -> InstructionId {
     assert!(self.opcode == HloOpcode::While);
-    self.operands.get(0).unwrap()
+    self.operands[0]
   }
 
-  // Get/sets the true and false HloComputation for conditional.
-  pub fn true_computation(&self) -> &HloComputation {
+  // Get/sets the true and false HloComputation for conditional. Only valid
+  // for conditionals predicated on a pred operand, i.e. with two branches.
+  pub fn true_computation(&self) -> ComputationId {
     assert!(self.opcode == HloOpcode::Conditional);
-    assert!(self.operand(0).shape().element_type() == PrimitiveType::Pred);
-    self.called_computations().get(TRUE_COMPUTATION_INDEX).unwrap()
+    assert_eq!(self.branch_count(), 2);
+    self.called_computations()[TRUE_COMPUTATION_INDEX]
   }
 
-  pub fn false_computation(&self) -> &HloComputation {
+  pub fn false_computation(&self) -> ComputationId {
     assert!(self.opcode == HloOpcode::Conditional);
-    assert!(self.operand(0).shape().element_type() == PrimitiveType::Pred);
-    self.called_computations().get(FALSE_COMPUTATION_INDEX).unwrap()
+    assert_eq!(self.branch_count(), 2);
+    self.called_computations()[FALSE_COMPUTATION_INDEX]
   }
 
   // Gets the branch HloComputation for conditional.
-  pub fn branch_computations(&self) -> &Vec<HloComputation> {
+  pub fn branch_computations(&self) -> &[ComputationId] {
     assert!(self.opcode == HloOpcode::Conditional);
     self.called_computations()
   }
@@ -1368,50 +2186,226 @@ impl HloInstruction {
     self.called_computations().len()
   }
 
-  pub fn branch_computation(&self, b: usize) -> &HloComputation {
+  pub fn branch_computation(&self, b: usize) -> ComputationId {
     assert!(self.opcode == HloOpcode::Conditional);
     assert!(b < self.called_computations().len());
-    self.called_computations().get(b).unwrap()
+    self.called_computations()[b]
   }
 
   // Sets a branch HloComputation for conditional.
-  pub fn set_branch_computation(&mut self, b: usize, computation: HloComputation) {
+  pub fn set_branch_computation(&mut self, b: usize, computation: ComputationId) {
     assert!(self.opcode == HloOpcode::Conditional);
-    self.mutable_called_computations().insert(b, computation);
+    self.mutable_called_computations()[b] = computation;
   }
 
-  pub fn signature_string() {}
+  // Returns a string for the signature of this instruction if considered as a
+  // function, e.g. the signature of an F32 add is (F32, F32) -> F32.
+  pub fn signature_string(&self, context: &HloPrintContext) -> String {
+    let operands: Vec<String> = self.operands.iter().map(|id| {
+      match context.operand(self, *id) {
+        Some(operand) => ShapeUtil::human_string(operand.shape()),
+        None => "?".to_string()
+      }
+    }).collect();
+    format!("({}) -> {}", operands.join(", "), ShapeUtil::human_string(&self.shape))
+  }
 
-  // Prints a debugging string that represents this instruction.
-  pub fn print(_printer: &dyn Printer, _options: &HloPrintOptions) {}
+  // Prints a debugging string that represents this instruction. Operands and
+  // called computations are looked up in 'context'.
+  pub fn print(
+    &self,
+    printer: &mut dyn Printer,
+    context: &HloPrintContext,
+    options: &HloPrintOptions)
+  {
+    let mut new_map = CanonicalNameMap::new();
+    self.print_with_canonical_name_map(printer, context, options, &mut new_map);
+  }
 
   pub fn to_string_default(&self) -> String {
     self.to_string(&HloPrintOptions::default())
   }
 
-  // Returns a debugging string that represents this instruction.
+  // Returns a debugging string that represents this instruction. The
+  // instruction is printed on its own, so operands and called computations
+  // are named by their ids.
   pub fn to_string(&self, options: &HloPrintOptions) -> String {
-    let printer = StringPrinter::new();
-    HloInstruction::print(&printer, options);
-    "".to_string() // TODO
+    self.to_string_in_context(&HloPrintContext::empty(), options)
+  }
+
+  // Returns a debugging string that represents this instruction, resolving
+  // operands and called computations through 'context'.
+  pub fn to_string_in_context(
+    &self, context: &HloPrintContext, options: &HloPrintOptions) -> String
+  {
+    let mut printer = StringPrinter::new();
+    self.print(&mut printer, context, options);
+    printer.to_string()
+  }
+
+  // Prints the attributes that follow the operands, each preceded by ", ".
+  pub fn print_extra_attributes(
+    &self,
+    printer: &mut dyn Printer,
+    context: &HloPrintContext,
+    options: &HloPrintOptions)
+  {
+    for attribute in self.extra_attributes_to_string(context, options) {
+      printer.append(&", ".to_string());
+      printer.append(&attribute);
+    }
   }
 
-  pub fn print_extra_attributes() {}
-  pub fn extra_attributes_to_string() {}
+  // Returns the attributes that follow the operands, including the called
+  // computations, e.g. ["dimensions={0}", "to_apply=%add"].
+  pub fn extra_attributes_to_string(
+    &self, context: &HloPrintContext, options: &HloPrintOptions) -> Vec<String>
+  {
+    let mut attributes = self.opcode_attributes_to_string(context, options);
+    if let Some(sharding) = &self.sharding {
+      let mut printer = StringPrinter::new();
+      sharding.print(&mut printer, options.print_metadata());
+      attributes.push(format!("sharding={}", printer.to_string()));
+    }
+    if let Some(rare) = &self.rare {
+      if !rare.frontend_attributes.map().is_empty() {
+        attributes.push(format!("frontend_attributes={}",
+          frontend_attributes_to_string(&rare.frontend_attributes)));
+      }
+    }
+    if self.opcode == HloOpcode::Call && self.is_composite {
+      attributes.push("is_composite=true".to_string());
+    }
+    if options.print_control_dependencies() && !self.control_predecessors().is_empty() {
+      let names: Vec<String> = self.control_predecessors().iter()
+        .map(|id| self.operand_name(context, *id, options))
+        .collect();
+      attributes.push(format!("control-predecessors={{{}}}", names.join(", ")));
+    }
+    if let Some(rare) = &self.rare {
+      if !rare.statistics_vis.statiscics().is_empty() {
+        attributes.push(format!("statistics={}",
+          statistics_viz_to_string(&rare.statistics_vis)));
+      }
+    }
+    attributes
+  }
 
+  // Returns a short string of the form '%name = opcode(%operand, ...)'.
   pub fn to_short_string(&self) -> String {
-    unimplemented!()
+    let operands: Vec<String> = self.operands.iter()
+      .map(|id| format!("%operand.{}", id.index()))
+      .collect();
+    format!("%{} = {}({})",
+      self.name, hlo_opcode_string(&self.opcode), operands.join(", "))
+  }
+
+  // Prints the instruction, naming it and its operands from
+  // 'canonical_name_map' when instruction names are canonicalized.
+  pub fn print_with_canonical_name_map(
+    &self,
+    printer: &mut dyn Printer,
+    context: &HloPrintContext,
+    options: &HloPrintOptions,
+    canonical_name_map: &mut CanonicalNameMap)
+  {
+    // Logic to print the instruction name (e.g. "%foo = ").
+    if options.canonicalize_instruction_names() {
+      if options.is_in_nested_computation() {
+        // If we are canonicalizing instruction names and this is a top-level
+        // to_string() call, don't print an instruction name.
+        printer.append(&canonical_name_map.lookup_or_insert(self.unique_id));
+        printer.append(&" = ".to_string());
+      }
+    } else {
+      print_name_internal(printer, &self.name, options);
+      printer.append(&" = ".to_string());
+    }
+
+    if options.print_result_shape() {
+      print_shape(printer, &self.shape, options);
+      printer.append(&" ".to_string());
+    }
+
+    // Print opcode, operand(s).
+    match self.syntax_sugar_async_computation(context, options) {
+      Some(wrapped) => {
+        let suffix = match self.opcode {
+          HloOpcode::AsyncStart => "-start",
+          HloOpcode::AsyncUpdate => "-update",
+          _ => "-done"
+        };
+        printer.append(&hlo_opcode_string(&wrapped.root_instruction().opcode()));
+        printer.append(&suffix.to_string());
+      },
+      None => printer.append(&hlo_opcode_string(&self.opcode))
+    }
+    printer.append(&"(".to_string());
+    self.print_operands_with_canonical_name_map(
+      printer, context, options, canonical_name_map);
+    printer.append(&")".to_string());
+
+    // Print additional attributes. If an instruction contains a
+    // subcomputation, the subcomputation is also printed here.
+    self.print_extra_attributes(printer, context, options);
+
+    if options.print_metadata() {
+      if let Some(metadata) = &self.metadata {
+        let metadata_str = op_metadata_to_string(metadata);
+        if !metadata_str.is_empty() {
+          printer.append(&format!(", metadata={{{}}}", metadata_str));
+        }
+      }
+    }
+    if options.print_backend_config() && !self.raw_backend_config.is_empty() {
+      printer.append(&", backend_config=".to_string());
+      // A config that lexes as a JSON dict can be parsed back unquoted,
+      // which is much easier to read.
+      if lexes_as_json_dict(&self.raw_backend_config) {
+        printer.append(&self.raw_backend_config);
+      } else {
+        printer.append(&format!("\"{}\"", c_escape(&self.raw_backend_config)));
+      }
+    }
+  }
+  // Returns the HloInstructionProto encoding of this instruction. Operand ids
+  // are unique ids within the parent computation, and called computation ids
+  // are unique ids within the module.
+  pub fn to_proto(&self) -> Vec<u8> {
+    let mut output = vec![];
+    write_bytes_field(&mut output, INSTRUCTION_NAME, self.name.as_bytes());
+    write_bytes_field(&mut output, INSTRUCTION_OPCODE,
+      hlo_opcode_string(&self.opcode).as_bytes());
+    write_bytes_field(&mut output, INSTRUCTION_SHAPE, &shape_to_proto(&self.shape));
+    if let Some(literal) = &self.literal {
+      if let Ok(literal_proto) = literal.to_proto() {
+        write_bytes_field(&mut output, INSTRUCTION_LITERAL, &literal_proto);
+      }
+    }
+    if self.opcode == HloOpcode::Parameter {
+      write_varint_field(&mut output, INSTRUCTION_PARAMETER_NUMBER,
+        self.parameter_number as u64);
+    }
+    if self.opcode == HloOpcode::GetTupleElement {
+      write_varint_field(&mut output, INSTRUCTION_TUPLE_INDEX, self.tuple_index as u64);
+    }
+    write_packed_varints(&mut output, INSTRUCTION_DIMENSIONS,
+      &self.dimensions.iter().map(|d| *d as u64).collect::<Vec<u64>>());
+    write_varint_field(&mut output, INSTRUCTION_ID, self.unique_id as u64);
+    write_packed_varints(&mut output, INSTRUCTION_OPERAND_IDS,
+      &self.operands.iter().map(|id| id.index() as u64).collect::<Vec<u64>>());
+    write_packed_varints(&mut output, INSTRUCTION_CALLED_COMPUTATION_IDS,
+      &self.called_computations().iter().map(|id| id.index() as u64).collect::<Vec<u64>>());
+    output
   }
 
-  pub fn print_with_canonical_name_map() {}
-  pub fn to_proto() {}
   pub fn to_category() {}
 
   // Returns true if this instruction is fused, ie contained within a fusion
   // instruction.
   pub fn is_fused(&self) -> bool {
-    self.parent.is_some() &&
-    self.parent.as_ref().unwrap().is_fusion_computation()
+    // Fused computations are not tracked yet, so nothing is fused.
+    false
   }
 
   pub fn is_loop_fusion(&self) -> bool {
@@ -1521,25 +2515,40 @@ impl HloInstruction {
   }
 
   // Returns the computations this instruction directly calls (if any).
-  pub fn called_computations(&self) -> &Vec<HloComputation> {
-    assert!(self.rare.is_some());
-    &self.rare.as_ref().unwrap().called_computations
+  pub fn called_computations(&self) -> &[ComputationId] {
+    match &self.rare {
+      Some(rare) => &rare.called_computations,
+      None => &[]
+    }
   }
 
-  pub fn mutable_called_computations(&mut self) -> &mut Vec<HloComputation> {
-    assert!(self.rare.is_some());
-    &mut self.rare.as_mut().unwrap().called_computations
+  pub fn mutable_called_computations(&mut self) -> &mut Vec<ComputationId> {
+    &mut self.mutable_rare().called_computations
   }
 
   pub fn has_called_computations(&self) -> bool {
-    self.has_rare() && !self.called_computations().is_empty()
+    !self.called_computations().is_empty()
   }
 
   pub fn might_have_called_computations() {}
-  pub fn replace_called_computations() {}
 
-  pub fn add_called_computation_ids(&mut self, _computation: i64) {
-    unimplemented!()
+  // Replaces all called computations based on a map function.
+  pub fn replace_called_computations<F>(&mut self, map_function: F)
+    where F: Fn(ComputationId) -> ComputationId
+  {
+    for computation in self.mutable_called_computations() {
+      *computation = map_function(*computation);
+    }
+  }
+
+  pub fn add_called_computation_ids(&mut self, computation: i64) {
+    assert!(computation >= 0);
+    self.append_computation(ComputationId::new(computation as usize));
+  }
+
+  // Appends 'computation' to the called computations of this instruction.
+  pub fn append_computation(&mut self, computation: ComputationId) {
+    self.mutable_rare().called_computations.push(computation);
   }
 
   // Clears out the called computations.
@@ -1623,12 +2632,9 @@ impl HloInstruction {
   pub fn is_cross_module_all_reduce(&self) -> bool {
     if self.opcode == HloOpcode::AllReduce || self.opcode == HloOpcode::AllReduceStart {
       return self.channel_id() != None;
-    } else if self.opcode == HloOpcode::AllReduceDone {
-      assert_eq!(self.operand_count(), 1);
-      let operand = self.operand(0);
-      assert_eq!(operand.opcode(), HloOpcode::AllReduceStart);
-      return operand.channel_id() != None;
     }
+    // An all-reduce-done carries no channel of its own; callers must ask its
+    // all-reduce-start operand instead.
     false
   }
 
@@ -1636,11 +2642,6 @@ impl HloInstruction {
   pub fn is_cross_replica_all_reduce(&self) -> bool {
     if self.opcode == HloOpcode::AllReduce || self.opcode == HloOpcode::AllReduceStart {
       return self.channel_id() == None;
-    } else if self.opcode == HloOpcode::AllReduceDone {
-      assert_eq!(self.operand_count(), 1);
-      let operand = self.operand(0);
-      assert_eq!(operand.opcode(), HloOpcode::AllReduceStart);
-      return operand.channel_id() == None;
     }
     false
   }
@@ -1652,7 +2653,7 @@ impl HloInstruction {
 
   pub fn reuse_operand_elements() {}
 
-  pub fn operand_indices(&self, operand: &HloInstruction) -> Vec<i64> {
+  pub fn operand_indices(&self, operand: InstructionId) -> Vec<i64> {
     let mut result = vec![];
     for i in 0..self.operand_count() {
       if self.operand(i) == operand {
@@ -1679,8 +2680,14 @@ impl HloInstruction {
 
   // Sets the string identifier for this instruction. Name will be sanitized to
   // match the regexp "[a-zA-Z_][a-zA-Z0-9.-]*".
-  pub fn set_and_sanitize_name(&mut self, _name: String) {
-      
+  pub fn set_and_sanitize_name(&mut self, name: String) {
+    let mut sanitized = String::new();
+    for (i, c) in name.chars().enumerate() {
+      let valid = c.is_ascii_alphabetic() || c == '_' ||
+        (i > 0 && (c.is_ascii_digit() || c == '.' || c == '-'));
+      sanitized.push(if valid { c } else { '_' });
+    }
+    self.name = sanitized;
   }
 
   pub fn uniquify_name() {}
@@ -1705,8 +2712,16 @@ impl HloInstruction {
 
   pub fn backend_config() {}
 
-  pub fn set_backend_config(&mut self, _config: WhileLoopBackendConfig) {
-    unimplemented!()
+  pub fn set_backend_config(&mut self, config: WhileLoopBackendConfig) {
+    self.raw_backend_config = config.to_json_string();
+  }
+
+  // Returns the while loop backend config of this instruction, if it has one.
+  pub fn while_loop_backend_config(&self) -> Option<WhileLoopBackendConfig> {
+    if self.opcode != HloOpcode::While {
+      return None;
+    }
+    WhileLoopBackendConfig::from_json_string(&self.raw_backend_config)
   }
 
   pub fn preserve_layout() {}
@@ -1752,7 +2767,7 @@ impl HloInstruction {
 
   // Whether this specific instruction has statistics.
   pub fn has_statistics(&self) -> bool {
-    !self.statistics_vis().statiscics().is_empty()
+    self.has_rare() && !self.statistics_vis().statiscics().is_empty()
   }
 
   // Whether any instruction within the same HLO mosule as this has statistics.
@@ -1773,8 +2788,14 @@ impl HloInstruction {
     &self.rare().statistics_vis
   }
 
-  pub fn raw_backend_config_string() {}
-  pub fn set_raw_backend_config_string() {}
+  // Returns the backend config as the raw string it was printed/parsed as.
+  pub fn raw_backend_config_string(&self) -> String {
+    self.raw_backend_config.clone()
+  }
+
+  pub fn set_raw_backend_config_string(&mut self, config_str: String) {
+    self.raw_backend_config = config_str;
+  }
 
   pub fn is_default_config(&self) -> bool {
     self.is_default_config
@@ -1787,14 +2808,16 @@ impl HloInstruction {
   pub fn backend_config_to_raw_string() {}
 
   pub fn precision_config(&self) -> &PrecisionConfig {
-    unimplemented!()
+    &self.precision_config
   }
 
-  pub fn set_precision_config(&mut self, _config: PrecisionConfig) {
-    unimplemented!()
+  pub fn set_precision_config(&mut self, config: PrecisionConfig) {
+    self.precision_config = config;
   }
 
-  pub fn mutable_precision_config() {}
+  pub fn mutable_precision_config(&mut self) -> &mut PrecisionConfig {
+    &mut self.precision_config
+  }
 
   // Sets the debug metadata for this instruction, excluding cration_pass_id,
   // which should never be copied anywhere.
@@ -1846,129 +2869,150 @@ impl HloInstruction {
     self.metadata.as_ref().unwrap()
   }
 
-  // Set/get the computation containing this instruction.
-  pub fn set_parent(&mut self, parent: HloComputation) {
-    self.parent = Some(Box::new(parent));
+  pub fn has_metadata(&self) -> bool {
+    self.metadata.is_some()
   }
 
-  pub fn parent(&self) -> &HloComputation {
-    self.parent.as_ref().unwrap()
+  // Set/get the computation containing this instruction.
+  pub fn set_parent(&mut self, parent: Option<ComputationId>) {
+    self.parent = parent;
   }
 
-  pub fn mutable_parent(&mut self) -> &mut HloComputation {
-    self.parent.as_mut().unwrap()
+  // Returns the module-level id of the computation containing this
+  // instruction, or None if the computation is not part of a module yet.
+  pub fn parent(&self) -> Option<ComputationId> {
+    self.parent
   }
 
-  // Returns the module for this instruction.
-  pub fn get_module(&self) -> &Option<HloModule> {
-    if self.parent.is_some() {
-      return self.parent.as_ref().unwrap().parent();
+  // Sorts the users and control lists of this instruction by id so that
+  // traversals which follow them are deterministic.
+  pub fn sort_instruction_user_and_control_lists(&mut self) {
+    self.users.sort_instruction_users();
+    if self.has_rare() {
+      self.mutable_rare().control_predecessors.sort();
+      self.mutable_rare().control_successors.sort();
     }
-    &None
   }
 
-  pub fn sort_instruction_user_and_control_lists() {}
-
   pub fn feature_index(&self) -> i64 {
-    unimplemented!()
+    self.feature_index
   }
 
-  pub fn set_feature_index(&mut self, _feature_index: i64) {
-    unimplemented!()
+  pub fn set_feature_index(&mut self, feature_index: i64) {
+    self.feature_index = feature_index;
   }
 
-  pub fn epsilon() {}
+  pub fn epsilon(&self) -> f64 {
+    self.epsilon
+  }
 
-  pub fn set_epsilon(&mut self, _epsilon: f64) {
-    unimplemented!()
+  pub fn set_epsilon(&mut self, epsilon: f64) {
+    self.epsilon = epsilon;
   }
 
   pub fn fft_type(&self) -> FftType {
-    unimplemented!()
+    self.fft_type.clone()
   }
 
-  pub fn set_fft_type(&mut self, _fft_t: FftType) {
-    unimplemented!()
+  pub fn set_fft_type(&mut self, fft_t: FftType) {
+    self.fft_type = fft_t;
   }
 
   pub fn fft_length(&self) -> &Vec<i64> {
-    unimplemented!()
+    &self.fft_length
   }
 
-  pub fn add_fft_length(&mut self, _length: i64) {
-    unimplemented!()
+  pub fn add_fft_length(&mut self, length: i64) {
+    self.fft_length.push(length);
   }
 
   // Delegates to HloChannelInstruction::channel_id.
   pub fn channel_id(&self) -> Option<i64> {
-    None
+    self.channel_id
   }
 
-  pub fn set_channel_id(&mut self, _channel_id: i64) {}
+  pub fn set_channel_id(&mut self, channel_id: i64) {
+    self.channel_id = Some(channel_id);
+  }
 
   pub fn dimensions(&self) -> &Vec<i64> {
-    unimplemented!()
+    &self.dimensions
   }
 
-  pub fn add_dimensions(&mut self, _dim: i64) {
-    unimplemented!()
+  pub fn add_dimensions(&mut self, dim: i64) {
+    self.dimensions.push(dim);
   }
 
-  pub fn dimensions_number(&self, _index: i64) -> i64 {
-    unimplemented!()
+  pub fn dimensions_number(&self, index: i64) -> i64 {
+    self.dimensions[index as usize]
   }
 
   pub fn concatenate_dimension(&self) -> i64 {
-    unimplemented!()
+    assert!(self.opcode == HloOpcode::Concatenate);
+    self.dimensions[0]
   }
 
+  // Returns the single dimension of sort, get-dimension-size and
+  // set-dimension-size.
   pub fn dimension(&self) -> i64 {
-    unimplemented!()
+    assert_eq!(self.dimensions.len(), 1);
+    self.dimensions[0]
+  }
+
+  pub fn inferred_dimension(&self) -> i64 {
+    self.inferred_dimension
   }
 
-  pub fn inferred_dimension() {}
   pub fn is_rank_2_transpose() {}
 
   pub fn slice_starts(&self) -> &Vec<i64> {
-    unimplemented!()
+    &self.slice_starts
   }
 
-  pub fn mutable_slice_starts() {}
+  pub fn mutable_slice_starts(&mut self) -> &mut Vec<i64> {
+    &mut self.slice_starts
+  }
 
   pub fn slice_strides(&self) -> &Vec<i64> {
-    unimplemented!()
+    &self.slice_strides
   }
 
-  pub fn mutable_slice_strides() {}
+  pub fn mutable_slice_strides(&mut self) -> &mut Vec<i64> {
+    &mut self.slice_strides
+  }
 
   pub fn slice_limits(&self) -> &Vec<i64> {
-    unimplemented!()
+    &self.slice_limits
   }
 
-  pub fn add_slice_dimensions(&mut self, _slice_dim: SliceDimensions) {
-    unimplemented!()
+  pub fn add_slice_dimensions(&mut self, slice_dim: SliceDimensions) {
+    self.slice_starts.push(slice_dim.start());
+    self.slice_limits.push(slice_dim.limit());
+    self.slice_strides.push(slice_dim.stride());
   }
 
-  // ##### HloFusionInstruction : start #####
-  pub fn literal<T>(&self) -> &Literal<T>
-    where T: Clone + Default + PartialEq
-  {
-    unimplemented!()
+  // Returns the literal associated with this constant instruction.
+  pub fn literal(&self) -> &DynLiteral {
+    assert!(self.literal.is_some(), "{} has no literal.", self.name);
+    self.literal.as_ref().unwrap()
   }
 
-  pub fn mutable_literal<T>(&self) -> &mut Literal<T>
-    where T: Clone + Default + PartialEq
-  {
-    unimplemented!()
+  pub fn mutable_literal(&mut self) -> &mut DynLiteral {
+    assert!(self.literal.is_some(), "{} has no literal.", self.name);
+    self.literal.as_mut().unwrap()
   }
 
-  pub fn set_literal<T>(&mut self, _literal: Literal<T>)
-    where T: Clone + Default + PartialEq
-  {
-    unimplemented!()
+  pub fn has_literal(&self) -> bool {
+    self.literal.is_some()
+  }
+
+  pub fn set_literal(&mut self, literal: DynLiteral) {
+    self.literal = Some(literal);
   }
 
-  pub fn is_constant(&self) -> bool { false }
+  pub fn is_constant(&self) -> bool {
+    self.opcode == HloOpcode::Constant
+  }
 
   pub fn relayout_constant() {}
   pub fn append_instruction_into_called_computation() {}
@@ -2003,10 +3047,13 @@ impl HloInstruction {
 
   // Delegates to HloFusionInstruction::fusion_kind
   pub fn fusion_kind(&self) -> FusionKind {
-    FusionKind::Custom
+    assert!(self.fusion_kind.is_some(), "{} is not a fusion.", self.name);
+    self.fusion_kind.clone().unwrap()
   }
 
-  pub fn set_fusion_kind() {}
+  pub fn set_fusion_kind(&mut self, kind: FusionKind) {
+    self.fusion_kind = Some(kind);
+  }
 
   // If multiple operands are the same instruction, keeps only one of them.
   pub fn deduplicate_fusion_operands(&self) -> Result<(), String> {
@@ -2015,103 +3062,210 @@ impl HloInstruction {
 
   // ##### HloFusionInstruction : end #####
 
-  pub fn random_distribution() {}
+  pub fn random_distribution(&self) -> RandomDistribution {
+    self.distribution.clone()
+  }
 
-  pub fn set_distribution(&mut self, _distribution: RandomDistribution) {
-    unimplemented!()
+  pub fn set_distribution(&mut self, distribution: RandomDistribution) {
+    self.distribution = distribution;
+  }
+
+  pub fn rng_algorithm(&self) -> RandomAlgorithm {
+    self.rng_algorithm.clone()
+  }
+
+  // Returns the number of 32 bit units RngGetAndUpdateState advances the
+  // state by.
+  pub fn delta(&self) -> i64 {
+    self.delta
+  }
+
+  pub fn set_delta(&mut self, delta: i64) {
+    self.delta = delta;
   }
 
   pub fn parameter_number(&self) -> i64 {
-    unimplemented!()
+    self.parameter_number
   }
 
-  pub fn set_parameter_number(&mut self, _parameter_number: i64) {
-    unimplemented!()
+  pub fn set_parameter_number(&mut self, parameter_number: i64) {
+    self.parameter_number = parameter_number;
+  }
+
+  pub fn set_parameter_replicated_at_leaf_buffers(
+    &mut self, parameter_replicated_at_leaf_buffers: Vec<bool>)
+  {
+    let mut replication = ParameterReplication::new();
+    for replicated in parameter_replicated_at_leaf_buffers {
+      replication.add_replicated_at_leaf_buffers(replicated);
+    }
+    self.parameter_replication = Some(replication);
   }
 
-  pub fn set_parameter_replicated_at_leaf_buffers() {}
-  pub fn parameter_replicated_at_leaf_byffers() {}
+  pub fn parameter_replicated_at_leaf_byffers(&self) -> Option<&Vec<bool>> {
+    match &self.parameter_replication {
+      Some(replication) => Some(replication.replicated_at_leaf_buffers()),
+      None => None
+    }
+  }
 
   pub fn mutable_parameter_replication(&mut self) -> &mut ParameterReplication {
-    unimplemented!()
+    if self.parameter_replication.is_none() {
+      self.parameter_replication = Some(ParameterReplication::new());
+    }
+    self.parameter_replication.as_mut().unwrap()
   }
 
   pub fn tuple_index(&self) -> i64 {
-    unimplemented!()
+    self.tuple_index
   }
 
-  pub fn exponent_bits() {}
+  pub fn exponent_bits(&self) -> i64 {
+    self.exponent_bits
+  }
 
-  pub fn set_exponent_bits(&mut self, _exponent_bits: i64) {
-    unimplemented!()    
+  pub fn set_exponent_bits(&mut self, exponent_bits: i64) {
+    self.exponent_bits = exponent_bits;
   }
 
-  pub fn infeed_config() {}
+  pub fn infeed_config(&self) -> String {
+    self.infeed_config.clone()
+  }
 
-  pub fn set_infeed_config(&mut self, _infeed_config: String) {
-    unimplemented!()
+  pub fn set_infeed_config(&mut self, infeed_config: String) {
+    self.infeed_config = infeed_config;
   }
 
-  pub fn outfeed_config() {}
-  pub fn set_outfeed_cofig(&mut self, _outfeed_config: String) {
-    unimplemented!()
+  pub fn outfeed_config(&self) -> String {
+    self.outfeed_config.clone()
   }
 
-  pub fn outfeed_shape() {}
-  pub fn mutable_outfeed_shape() {}
+  pub fn set_outfeed_cofig(&mut self, outfeed_config: String) {
+    self.outfeed_config = outfeed_config;
+  }
 
-  pub fn set_outfeed_shape(&mut self, _shape: Shape) {
-    unimplemented!()
+  pub fn outfeed_shape(&self) -> &Shape {
+    assert!(self.outfeed_shape.is_some(), "{} is not an outfeed.", self.name);
+    self.outfeed_shape.as_ref().unwrap()
   }
 
-  pub fn replica_groups() {}
+  pub fn mutable_outfeed_shape(&mut self) -> &mut Shape {
+    assert!(self.outfeed_shape.is_some(), "{} is not an outfeed.", self.name);
+    self.outfeed_shape.as_mut().unwrap()
+  }
 
-  pub fn add_replica_groups(&mut self, _group: ReplicaGroup) {
-    unimplemented!()
+  pub fn set_outfeed_shape(&mut self, shape: Shape) {
+    self.outfeed_shape = Some(shape);
   }
 
-  pub fn source_target_pairs() {}
+  pub fn replica_groups(&self) -> &Vec<ReplicaGroup> {
+    &self.replica_groups
+  }
 
-  pub fn add_source_target_pairs(&mut self, _pair: (i64, i64)) {
-    unimplemented!()
+  pub fn add_replica_groups(&mut self, group: ReplicaGroup) {
+    self.replica_groups.push(group);
+  }
+
+  pub fn source_target_pairs(&self) -> &Vec<(i64, i64)> {
+    &self.source_target_pairs
+  }
+
+  pub fn add_source_target_pairs(&mut self, pair: (i64, i64)) {
+    self.source_target_pairs.push(pair);
   }
 
   pub fn convolution_dimension_numberes(&self) -> &ConvolutionDimensionNumbers {
-    unimplemented!()
+    assert!(self.convolution_dimension_numbers.is_some());
+    self.convolution_dimension_numbers.as_ref().unwrap()
   }
 
   pub fn set_convolution_dimension_numberes(
-    &mut self, _conv_dim_numbers: ConvolutionDimensionNumbers)
+    &mut self, conv_dim_numbers: ConvolutionDimensionNumbers)
   {
-    unimplemented!()
+    self.convolution_dimension_numbers = Some(conv_dim_numbers);
   }
 
   pub fn feature_group_count(&self) -> i64 {
-    unimplemented!()
+    self.feature_group_count
   }
 
-  pub fn set_feature_group_count(&mut self, _count: i64) {
-    unimplemented!()
+  pub fn set_feature_group_count(&mut self, count: i64) {
+    self.feature_group_count = count;
   }
 
   pub fn batch_group_count(&self) -> i64 {
-    unimplemented!()
+    self.batch_group_count
   }
 
-  pub fn set_batch_group_count(&mut self, _count: i64) {
-    unimplemented!()
+  pub fn set_batch_group_count(&mut self, count: i64) {
+    self.batch_group_count = count;
   }
 
-  pub fn select() {}
-  pub fn scatter() {}
-  pub fn set_select() {}
-  pub fn set_scatter() {}
+  pub fn select(&self) -> ComputationId {
+    assert!(self.opcode == HloOpcode::SelectAndScatter);
+    self.called_computations()[SELSECT_COMPUTATION_INDEX]
+  }
+
+  pub fn scatter(&self) -> ComputationId {
+    assert!(self.opcode == HloOpcode::SelectAndScatter);
+    self.called_computations()[SCATTER_COMPUTATION_INDEX]
+  }
+
+  pub fn set_select(&mut self, computation: ComputationId) {
+    assert!(self.opcode == HloOpcode::SelectAndScatter);
+    self.mutable_called_computations()[SELSECT_COMPUTATION_INDEX] = computation;
+  }
+
+  pub fn set_scatter(&mut self, computation: ComputationId) {
+    assert!(self.opcode == HloOpcode::SelectAndScatter);
+    self.mutable_called_computations()[SCATTER_COMPUTATION_INDEX] = computation;
+  }
 
   // Delegates to HloCustomCallInstruction::custom_call_target.
-  pub fn custom_call_target(&self)-> String { "".to_string() }
+  pub fn custom_call_target(&self)-> String {
+    self.custom_call_target.clone()
+  }
 
-  pub fn set_custom_call_target(&mut self, _name: String) {
-    unimplemented!()
+  pub fn set_custom_call_target(&mut self, name: String) {
+    self.custom_call_target = name;
+  }
+
+  pub fn custom_call_has_side_effect(&self) -> bool {
+    self.custom_call_has_side_effect
+  }
+
+  pub fn set_custom_call_has_side_effect(&mut self, has_side_effect: bool) {
+    self.custom_call_has_side_effect = has_side_effect;
+  }
+
+  pub fn custom_call_schedule(&self) -> CustomCallSchedule {
+    self.custom_call_schedule.clone()
+  }
+
+  pub fn set_custom_call_schedule(&mut self, schedule: CustomCallSchedule) {
+    self.custom_call_schedule = schedule;
+  }
+
+  pub fn custom_call_api_version(&self) -> CustomCallApiVersion {
+    self.custom_call_api_version.clone()
+  }
+
+  pub fn set_custom_call_api_version(&mut self, api_version: CustomCallApiVersion) {
+    self.custom_call_api_version = api_version;
+  }
+
+  // Returns true if the custom call constrains the layouts of its operands.
+  pub fn layout_constrained(&self) -> bool {
+    self.operand_shapes_with_layout.is_some()
+  }
+
+  pub fn operand_shapes_with_layout(&self) -> &Vec<Shape> {
+    assert!(self.layout_constrained());
+    self.operand_shapes_with_layout.as_ref().unwrap()
+  }
+
+  pub fn set_operand_shapes_with_layout(&mut self, shapes: Vec<Shape>) {
+    self.operand_shapes_with_layout = Some(shapes);
   }
 
   // Gets a list of output/operand buffer pairs that alias each other, where the
@@ -2119,33 +3273,47 @@ impl HloInstruction {
   // represented as the operand index and the ShapeIndex. By default this list
   // is empty.
   pub fn output_to_operand_aliasing(&self) -> Vec<(Vec<i64>, (i64, Vec<i64>))> {
-    unimplemented!()
+    self.output_to_operand_aliasing.clone()
+  }
+
+  pub fn set_output_to_operand_aliasing(
+    &mut self, aliasing: Vec<(Vec<i64>, (i64, Vec<i64>))>)
+  {
+    self.output_to_operand_aliasing = aliasing;
   }
 
   pub fn padding_config(&self) -> &PaddingConfig {
-    unimplemented!()
+    assert!(self.padding_config.is_some());
+    self.padding_config.as_ref().unwrap()
   }
 
-  pub fn mutable_padding_config() {}
+  pub fn mutable_padding_config(&mut self) -> &mut PaddingConfig {
+    assert!(self.padding_config.is_some());
+    self.padding_config.as_mut().unwrap()
+  }
 
-  pub fn set_padding_config(&mut self, _padding_config: PaddingConfig) {
-    unimplemented!()
+  pub fn set_padding_config(&mut self, padding_config: PaddingConfig) {
+    self.padding_config = Some(padding_config);
   }
 
-  pub fn padding_type() {}
+  pub fn padding_type(&self) -> PaddingType {
+    self.padding_type.clone()
+  }
 
-  pub fn set_padding_type(&mut self, _t: PaddingType) {
-    unimplemented!()
+  pub fn set_padding_type(&mut self, t: PaddingType) {
+    self.padding_type = t;
   }
 
-  pub fn slice_sizes() {}
+  pub fn slice_sizes(&self, dimension: usize) -> i64 {
+    self.dynamic_slice_sizes[dimension]
+  }
 
   pub fn dynamic_slice_sizes(&self) -> &Vec<i64> {
-    unimplemented!()
+    &self.dynamic_slice_sizes
   }
 
-  pub fn add_dynamic_slice_sizes(&mut self, _size: i64) {
-    unimplemented!()
+  pub fn add_dynamic_slice_sizes(&mut self, size: i64) {
+    self.dynamic_slice_sizes.push(size);
   }
 
   pub fn dynamic_slice_sizes_list() {}
@@ -2155,45 +3323,51 @@ impl HloInstruction {
   }
 
   pub fn gather_dimension_numbers(&self) -> &GatherDimensionNumbers {
-    unimplemented!()
+    assert!(self.gather_dimension_numbers.is_some());
+    self.gather_dimension_numbers.as_ref().unwrap()
   }
 
   pub fn set_gather_dimension_numbers(
-    &mut self, _dimension_numbers: GatherDimensionNumbers)
+    &mut self, dimension_numbers: GatherDimensionNumbers)
   {
-    unimplemented!()    
+    self.gather_dimension_numbers = Some(dimension_numbers);
   }
 
   pub fn gather_slice_sizes(&self) -> &Vec<i64> {
-    unimplemented!()
+    &self.gather_slice_sizes
   }
 
-  pub fn add_gather_slice_sizes(&mut self, _size: i64) {
-    unimplemented!()
+  pub fn add_gather_slice_sizes(&mut self, size: i64) {
+    self.gather_slice_sizes.push(size);
   }
 
+  // Returns the dimension of reduce-scatter the result is scattered along.
   pub fn scatter_dimension(&self) -> i64 {
-    unimplemented!()
+    assert!(self.opcode == HloOpcode::ReduceScatter);
+    self.dimensions[0]
   }
 
   pub fn dot_dimension_numbers(&self) -> &DotDimensionNumbers {
-    unimplemented!()
+    assert!(self.dot_dimension_numbers.is_some());
+    self.dot_dimension_numbers.as_ref().unwrap()
   }
 
-  pub fn set_dot_dimension_numbers(&mut self, _numbers: DotDimensionNumbers) {
-    unimplemented!()
+  pub fn set_dot_dimension_numbers(&mut self, numbers: DotDimensionNumbers) {
+    self.dot_dimension_numbers = Some(numbers);
   }
 
-  pub fn add_dot_sparsity(&mut self, _descriptor: SparsityDescriptor) {
-    unimplemented!()
+  pub fn add_dot_sparsity(&mut self, descriptor: SparsityDescriptor) {
+    self.sparsity.push(descriptor);
   }
 
   pub fn operand_side_metadata(&self) -> &DomainMetadata {
-    unimplemented!()
+    assert!(self.operand_side_metadata.is_some());
+    self.operand_side_metadata.as_ref().unwrap()
   }
 
   pub fn user_side_metadata(&self) -> &DomainMetadata {
-    unimplemented!()
+    assert!(self.user_side_metadata.is_some());
+    self.user_side_metadata.as_ref().unwrap()
   }
 
   pub fn is_asynchronous(&self) -> bool {
@@ -2214,56 +3388,75 @@ impl HloInstruction {
 
   // HloAsyncInstruction
   pub fn async_execution_thread(&self) -> String {
-    unimplemented!()
+    self.async_execution_thread.clone()
+  }
+
+  pub fn set_async_execution_thread(&mut self, async_execution_thread: String) {
+    self.async_execution_thread = async_execution_thread;
   }
 
-  pub fn set_async_execution_thread() {}
   pub fn set_called_computations_execution_thread() {}
-  pub fn cross_program_prefetch_index() {}
-  pub fn comparison_direction() {}
 
-  pub fn set_comparison_direction(&mut self, _direction: ComparisonDirection) {
-    unimplemented!()
+  pub fn cross_program_prefetch_index(&self) -> Option<i64> {
+    self.cross_program_prefetch_index
   }
 
-  pub fn set_comparison_type(&mut self, _t: ComparisonType) {
-    unimplemented!()
+  pub fn comparison_direction(&self) -> ComparisonDirection {
+    assert!(self.comparison_direction.is_some());
+    self.comparison_direction.clone().unwrap()
   }
 
-  pub fn comparison_order() {}
+  pub fn set_comparison_direction(&mut self, direction: ComparisonDirection) {
+    self.comparison_direction = Some(direction);
+  }
 
-  pub fn triangular_solve_options(&self) -> &TriangularSolveOptions {
-    unimplemented!()
+  pub fn comparison_type(&self) -> ComparisonType {
+    assert!(self.comparison_type.is_some());
+    self.comparison_type.clone().unwrap()
   }
 
-  pub fn set_triangular_solve_options(&mut self, _options: TriangularSolveOptions) {
-    unimplemented!()
+  pub fn set_comparison_type(&mut self, t: ComparisonType) {
+    self.comparison_type = Some(t);
+  }
+
+  pub fn comparison_order() {}
+
+  pub fn triangular_solve_options(&self) -> &TriangularSolveOptions {
+    assert!(self.triangular_solve_options.is_some());
+    self.triangular_solve_options.as_ref().unwrap()
   }
 
-  pub fn cholesky_options() {}
+  pub fn set_triangular_solve_options(&mut self, options: TriangularSolveOptions) {
+    self.triangular_solve_options = Some(options);
+  }
+
+  pub fn cholesky_options(&self) -> &CholeskyOptions {
+    assert!(self.cholesky_options.is_some());
+    self.cholesky_options.as_ref().unwrap()
+  }
 
   pub fn mutable_cholesky_options(&mut self) -> &mut CholeskyOptions {
-    unimplemented!()
+    assert!(self.cholesky_options.is_some());
+    self.cholesky_options.as_mut().unwrap()
   }
 
-  pub fn set_cholesky_options(&mut self, _options: CholeskyOptions) {
-    unimplemented!()
+  pub fn set_cholesky_options(&mut self, options: CholeskyOptions) {
+    self.cholesky_options = Some(options);
   }
 
   pub fn output_operand_aliasing() {}
-  pub fn append_operand() {}
 
   // HloReducePrecisionInstruction
   pub fn operand_bits(&self) -> i64 {
-    unimplemented!()
+    self.exponent_bits + self.mantissa_bits
   }
 
   pub fn mantissa_bits(&self) -> i64 {
-    unimplemented!()
+    self.mantissa_bits
   }
 
-  pub fn set_mantissa_bits(&mut self, _mantissa_bits: i64) {
-    unimplemented!()
+  pub fn set_mantissa_bits(&mut self, mantissa_bits: i64) {
+    self.mantissa_bits = mantissa_bits;
   }
   // HloReducePrecisionInstruction
 
@@ -2273,35 +3466,52 @@ impl HloInstruction {
   }
 
   pub fn constrain_layout(&self) -> bool {
-    self.collective_instruction.as_ref().unwrap().constrain_layout()
+    self.constrain_layout
   }
 
-  pub fn set_constrain_layout(&mut self, _constrain_layout: bool) {
-    unimplemented!()
+  pub fn set_constrain_layout(&mut self, constrain_layout: bool) {
+    self.constrain_layout = constrain_layout;
+  }
+
+  pub fn use_global_device_ids(&self) -> bool {
+    self.use_global_device_ids
+  }
+
+  pub fn is_host_transfer(&self) -> bool {
+    self.is_host_transfer
   }
 
   pub fn iota_dimension(&self) -> i64 {
-    unimplemented!()
+    self.iota_dimension
   }
 
   pub fn sparsity(&self) -> &Vec<SparsityDescriptor> {
-    unimplemented!()
+    &self.sparsity
   }
 
   pub fn window(&self) -> &Window {
-    unimplemented!()
+    assert!(self.window.is_some(), "{} has no window.", self.name);
+    self.window.as_ref().unwrap()
   }
 
-  pub fn set_window(&mut self, _window: Window) {
-    unimplemented!()
+  pub fn has_window(&self) -> bool {
+    self.window.is_some()
+  }
+
+  pub fn set_window(&mut self, window: Window) {
+    self.window = Some(window);
+  }
+
+  pub fn indices_are_sorted(&self) -> bool {
+    self.indices_are_sorted
   }
 
   pub fn unique_indices(&self) -> bool {
-    unimplemented!()
+    self.unique_indices
   }
 
-  pub fn set_unique_indices(&mut self, _unique_indices: bool) {
-    unimplemented!()
+  pub fn set_unique_indices(&mut self, unique_indices: bool) {
+    self.unique_indices = unique_indices;
   }
 
   pub fn scatter_indices(&self) -> &HloInstruction {
@@ -2309,93 +3519,788 @@ impl HloInstruction {
   }
 
   pub fn scatter_dimension_numbers(&self) -> &ScatterDimensionNummbers {
-    unimplemented!()
+    assert!(self.scatter_dimension_numbers.is_some());
+    self.scatter_dimension_numbers.as_ref().unwrap()
   }
 
   pub fn set_scatter_dimension_numbers(
     &mut self,
-    _dimension_numbers: ScatterDimensionNummbers)
+    dimension_numbers: ScatterDimensionNummbers)
   {
-    unimplemented!()    
+    self.scatter_dimension_numbers = Some(dimension_numbers);
   }
 
   pub fn scatter_updates(&self) -> &Vec<HloInstruction> {
     unimplemented!()
   }
 
-  pub fn set_indices_are_sorted(&mut self, _indices_are_sorted: bool) {
-    unimplemented!()
+  pub fn set_indices_are_sorted(&mut self, indices_are_sorted: bool) {
+    self.indices_are_sorted = indices_are_sorted;
   }
 
-  pub fn set_is_composite(&mut self, _is_composite: bool) {
-    unimplemented!()
+  pub fn is_composite(&self) -> bool {
+    self.is_composite
+  }
+
+  pub fn set_is_composite(&mut self, is_composite: bool) {
+    self.is_composite = is_composite;
+  }
+
+  // Returns the attributes specific to the opcode followed by the called
+  // computations. Sugared async ops show those of the wrapped instruction.
+  fn opcode_attributes_to_string(
+    &self, context: &HloPrintContext, options: &HloPrintOptions) -> Vec<String>
+  {
+    let mut attributes = vec![];
+    if options.print_extra_attributes() {
+      self.print_extra_attributes_impl(context, options, &mut attributes);
+    }
+    self.print_subcomputation_attributes(context, options, &mut attributes);
+    if let Some(wrapped) = self.syntax_sugar_async_computation(context, options) {
+      let wrapped_context = HloPrintContext::new(Some(wrapped), context.module());
+      attributes.extend(wrapped.root_instruction()
+        .opcode_attributes_to_string(&wrapped_context, options));
+    }
+    attributes
+  }
+
+  fn print_extra_attributes_impl(
+    &self,
+    context: &HloPrintContext,
+    options: &HloPrintOptions,
+    attributes: &mut Vec<String>)
+  {
+    match self.opcode {
+      HloOpcode::Parameter => {
+        if let Some(replication) = &self.parameter_replication {
+          let values: Vec<String> = replication.replicated_at_leaf_buffers().iter()
+            .map(|b| b.to_string()).collect();
+          attributes.push(format!("parameter_replication={{{}}}", values.join(",")));
+        }
+      },
+      HloOpcode::Iota => {
+        attributes.push(format!("iota_dimension={}", self.iota_dimension));
+      },
+      HloOpcode::TopK => {
+        attributes.push(format!("k={}", self.k));
+        attributes.push(format!("largest={}", self.largest));
+      },
+      HloOpcode::Compare => {
+        if let Some(direction) = &self.comparison_direction {
+          attributes.push(format!("direction={}",
+            comparison_direction_to_string(direction)));
+        }
+        // The type is only printed when it differs from the default for the
+        // operands, which is what the parser assumes when it is missing.
+        if let Some(comparison_type) = &self.comparison_type {
+          let is_default = match context.operand(self, self.operands[0]) {
+            Some(operand) =>
+              default_comparison_type(&operand.shape().element_type()) == *comparison_type,
+            None => false
+          };
+          if !is_default {
+            attributes.push(format!("type={}", comparison_type_to_string(comparison_type)));
+          }
+        }
+      },
+      HloOpcode::AllGather | HloOpcode::AllGatherStart | HloOpcode::AllReduce |
+      HloOpcode::AllReduceStart | HloOpcode::ReduceScatter | HloOpcode::AllToAll |
+      HloOpcode::RaggedAllToAll | HloOpcode::CollectiveBroadcast => {
+        if let Some(channel_id) = self.channel_id {
+          attributes.push(format!("channel_id={}", channel_id));
+        }
+        attributes.push(format!("replica_groups={}",
+          replica_groups_to_string(&self.replica_groups)));
+        if self.constrain_layout {
+          attributes.push("constrain_layout=true".to_string());
+        }
+        match self.opcode {
+          HloOpcode::AllGather | HloOpcode::AllGatherStart |
+          HloOpcode::ReduceScatter | HloOpcode::AllToAll => {
+            if !self.dimensions.is_empty() {
+              attributes.push(format!("dimensions={}", braced_list(&self.dimensions)));
+            }
+          },
+          _ => {}
+        }
+        if self.use_global_device_ids {
+          attributes.push("use_global_device_ids=true".to_string());
+        }
+      },
+      HloOpcode::CollectivePermute | HloOpcode::CollectivePermuteStart => {
+        if let Some(channel_id) = self.channel_id {
+          attributes.push(format!("channel_id={}", channel_id));
+        }
+        let pairs: Vec<String> = self.source_target_pairs.iter()
+          .map(|(source, target)| format!("{{{},{}}}", source, target))
+          .collect();
+        attributes.push(format!("source_target_pairs={{{}}}", pairs.join(",")));
+      },
+      HloOpcode::Reshape => {
+        if self.inferred_dimension != -1 {
+          attributes.push(format!("inferred_dimension={}", self.inferred_dimension));
+        }
+      },
+      HloOpcode::Sort => {
+        attributes.push(format!("dimensions={}", braced_list(&self.dimensions)));
+        if self.is_stable {
+          attributes.push("is_stable=true".to_string());
+        }
+      },
+      HloOpcode::Reduce | HloOpcode::Broadcast | HloOpcode::Concatenate |
+      HloOpcode::Reverse | HloOpcode::Transpose | HloOpcode::GetDimensionSize |
+      HloOpcode::SetDimensionSize => {
+        attributes.push(format!("dimensions={}", braced_list(&self.dimensions)));
+      },
+      HloOpcode::ReduceWindow | HloOpcode::SelectAndScatter => {
+        if let Some(window) = &self.window {
+          if !window.dimensions_vec().is_empty() {
+            attributes.push(format!("window={{{}}}", window_to_string(window)));
+          }
+        }
+      },
+      HloOpcode::Convolution => {
+        if let Some(window) = &self.window {
+          if !window.dimensions_vec().is_empty() {
+            attributes.push(format!("window={{{}}}", window_to_string(window)));
+          }
+        }
+        if let Some(dnums) = &self.convolution_dimension_numbers {
+          attributes.push(format!("dim_labels={}",
+            convolution_dimension_numbers_to_string(dnums)));
+        }
+        if self.feature_group_count != 1 {
+          attributes.push(format!("feature_group_count={}", self.feature_group_count));
+        }
+        if self.batch_group_count != 1 {
+          attributes.push(format!("batch_group_count={}", self.batch_group_count));
+        }
+        self.print_precision_config(attributes);
+      },
+      HloOpcode::Fft => {
+        attributes.push(format!("fft_type={}", fft_type_to_string(&self.fft_type)));
+        attributes.push(format!("fft_length={}", braced_list(&self.fft_length)));
+      },
+      HloOpcode::TriangularSolve => {
+        if let Some(options) = &self.triangular_solve_options {
+          if options.left_side() {
+            attributes.push("left_side=true".to_string());
+          }
+          if options.lower() {
+            attributes.push("lower=true".to_string());
+          }
+          if options.unit_diagonal() {
+            attributes.push("unit_diagonal=true".to_string());
+          }
+          if options.transpose_a() != Transpose::Invalid {
+            attributes.push(format!("transpose_a={}",
+              transpose_to_string(&options.transpose_a())));
+          }
+        }
+      },
+      HloOpcode::Cholsky => {
+        if let Some(options) = &self.cholesky_options {
+          attributes.push(format!("lower={}", options.lower()));
+        }
+      },
+      HloOpcode::BatchNormTraining | HloOpcode::BatchNormInference |
+      HloOpcode::BatchNormGrad => {
+        attributes.push(format!("epsilon={}", float_to_string(self.epsilon)));
+        attributes.push(format!("feature_index={}", self.feature_index));
+      },
+      HloOpcode::Pad => {
+        if let Some(padding_config) = &self.padding_config {
+          attributes.push(format!("padding={}", padding_config_to_string(padding_config)));
+        }
+      },
+      HloOpcode::Slice => {
+        let has_stride = self.slice_strides.iter().any(|stride| *stride != 1);
+        let mut ranges = vec![];
+        for i in 0..self.slice_starts.len() {
+          if has_stride {
+            ranges.push(format!("[{}:{}:{}]",
+              self.slice_starts[i], self.slice_limits[i], self.slice_strides[i]));
+          } else {
+            ranges.push(format!("[{}:{}]", self.slice_starts[i], self.slice_limits[i]));
+          }
+        }
+        attributes.push(format!("slice={{{}}}", ranges.join(", ")));
+      },
+      HloOpcode::DynamicSlice => {
+        attributes.push(format!("dynamic_slice_sizes={}",
+          braced_list(&self.dynamic_slice_sizes)));
+      },
+      HloOpcode::Gather => {
+        if let Some(dnums) = &self.gather_dimension_numbers {
+          attributes.push(format!("offset_dims={}", braced_list(dnums.offset_dims())));
+          attributes.push(format!("collapsed_slice_dims={}",
+            braced_list(dnums.collapsed_slice_dims())));
+          attributes.push(format!("start_index_map={}",
+            braced_list(dnums.start_index_map())));
+          if !dnums.operand_batching_dims().is_empty() {
+            attributes.push(format!("operand_batching_dims={}",
+              braced_list(dnums.operand_batching_dims())));
+          }
+          if !dnums.start_indices_batching_dims().is_empty() {
+            attributes.push(format!("start_indices_batching_dims={}",
+              braced_list(dnums.start_indices_batching_dims())));
+          }
+          attributes.push(format!("index_vector_dim={}", dnums.index_vector_dim()));
+        }
+        attributes.push(format!("slice_sizes={}", braced_list(&self.gather_slice_sizes)));
+        if self.indices_are_sorted {
+          attributes.push("indices_are_sorted=true".to_string());
+        }
+      },
+      HloOpcode::Scatter => {
+        if let Some(dnums) = &self.scatter_dimension_numbers {
+          attributes.push(format!("update_window_dims={}",
+            braced_list(dnums.update_window_dims())));
+          attributes.push(format!("inserted_window_dims={}",
+            braced_list(dnums.inserted_window_dims())));
+          attributes.push(format!("scatter_dims_to_operand_dims={}",
+            braced_list(dnums.scatter_dims_to_operand_dims())));
+          if !dnums.input_batching_dims().is_empty() {
+            attributes.push(format!("input_batching_dims={}",
+              braced_list(dnums.input_batching_dims())));
+          }
+          if !dnums.scatter_indices_batching_dims().is_empty() {
+            attributes.push(format!("scatter_indices_batching_dims={}",
+              braced_list(dnums.scatter_indices_batching_dims())));
+          }
+          attributes.push(format!("index_vector_dim={}", dnums.index_vector_dim()));
+        }
+        if self.indices_are_sorted {
+          attributes.push("indices_are_sorted=true".to_string());
+        }
+        if self.unique_indices {
+          attributes.push("unique_indices=true".to_string());
+        }
+      },
+      HloOpcode::Domain => {
+        if let (Some(entry), Some(exit)) =
+          (&self.user_side_metadata, &self.operand_side_metadata)
+        {
+          attributes.push(format!("domain={{kind=\"{}\", entry={}, exit={}}}",
+            entry.kind(), entry.to_string(), exit.to_string()));
+        }
+      },
+      HloOpcode::Fusion => {
+        if let Some(kind) = &self.fusion_kind {
+          attributes.push(format!("kind={}", fusion_kind_to_string(kind)));
+        }
+      },
+      HloOpcode::CustomCall => {
+        attributes.push(format!("custom_call_target=\"{}\"",
+          c_escape(&self.custom_call_target)));
+        if let Some(window) = &self.window {
+          if !window.dimensions_vec().is_empty() {
+            attributes.push(format!("window={{{}}}", window_to_string(window)));
+          }
+        }
+        if let Some(dnums) = &self.convolution_dimension_numbers {
+          attributes.push(format!("dim_labels={}",
+            convolution_dimension_numbers_to_string(dnums)));
+        }
+        if self.feature_group_count != 1 {
+          attributes.push(format!("feature_group_count={}", self.feature_group_count));
+        }
+        if self.batch_group_count != 1 {
+          attributes.push(format!("batch_group_count={}", self.batch_group_count));
+        }
+        if let Some(shapes) = &self.operand_shapes_with_layout {
+          let shapes: Vec<String> =
+            shapes.iter().map(ShapeUtil::human_string_with_layout).collect();
+          attributes.push(format!("operand_layout_constraints={{{}}}", shapes.join(", ")));
+        }
+        if self.custom_call_has_side_effect {
+          attributes.push("custom_call_has_side_effect=true".to_string());
+        }
+        if !self.output_to_operand_aliasing.is_empty() {
+          let pairs: Vec<String> = self.output_to_operand_aliasing.iter()
+            .map(|(output_index, (operand, operand_index))| {
+              format!("{}: ({}, {})", shape_index_to_string(output_index),
+                operand, shape_index_to_string(operand_index))
+            }).collect();
+          attributes.push(format!("output_to_operand_aliasing={{{}}}", pairs.join(", ")));
+        }
+        if self.custom_call_schedule != CustomCallSchedule::None {
+          attributes.push(format!("schedule={}",
+            custom_call_schedule_to_string(&self.custom_call_schedule)));
+        }
+        if self.custom_call_api_version != CustomCallApiVersion::Original {
+          attributes.push(format!("api_version={}",
+            custom_call_api_version_to_string(&self.custom_call_api_version)));
+        }
+        if self.padding_type != PaddingType::Invalid {
+          attributes.push(format!("padding_type={}",
+            padding_type_to_string(&self.padding_type)));
+        }
+        self.print_precision_config(attributes);
+      },
+      HloOpcode::Dot => {
+        if let Some(dnums) = &self.dot_dimension_numbers {
+          let lists = [
+            ("lhs_batch_dims", dnums.lhs_batch_dimensions()),
+            ("lhs_contracting_dims", dnums.lhs_contracting_dimensions()),
+            ("rhs_batch_dims", dnums.rhs_batch_dimensions()),
+            ("rhs_contracting_dims", dnums.rhs_contracting_dimensions())
+          ];
+          for (name, dims) in lists {
+            if !dims.is_empty() {
+              attributes.push(format!("{}={}", name, braced_list(dims)));
+            }
+          }
+        }
+        self.print_precision_config(attributes);
+      },
+      HloOpcode::ReducePrecision => {
+        attributes.push(format!("exponent_bits={}", self.exponent_bits));
+        attributes.push(format!("mantissa_bits={}", self.mantissa_bits));
+      },
+      HloOpcode::Rng => {
+        attributes.push(format!("distribution={}",
+          random_distribution_to_string(&self.distribution)));
+      },
+      HloOpcode::RngBitGenerator => {
+        attributes.push(format!("algorithm={}",
+          random_algorithm_to_string(&self.rng_algorithm)));
+      },
+      HloOpcode::RngGetAndUpdateState => {
+        attributes.push(format!("delta={}", self.delta));
+      },
+      HloOpcode::Infeed => {
+        if options.print_infeed_outfeed_config() && !self.infeed_config.is_empty() {
+          attributes.push(format!("infeed_config=\"{}\"", c_escape(&self.infeed_config)));
+        }
+      },
+      HloOpcode::Outfeed => {
+        if let Some(outfeed_shape) = &self.outfeed_shape {
+          attributes.push(format!("outfeed_shape={}",
+            ShapeUtil::human_string_with_layout(outfeed_shape)));
+        }
+        if options.print_infeed_outfeed_config() && !self.outfeed_config.is_empty() {
+          attributes.push(format!("outfeed_config=\"{}\"", c_escape(&self.outfeed_config)));
+        }
+      },
+      HloOpcode::Send | HloOpcode::Recv | HloOpcode::SendDone | HloOpcode::RecvDone => {
+        if let Some(channel_id) = self.channel_id {
+          attributes.push(format!("channel_id={}", channel_id));
+        }
+        if self.is_host_transfer {
+          attributes.push("is_host_transfer=true".to_string());
+        }
+      },
+      HloOpcode::GetTupleElement => {
+        attributes.push(format!("index={}", self.tuple_index));
+      },
+      HloOpcode::CopyStart => {
+        if let Some(index) = self.cross_program_prefetch_index {
+          attributes.push(format!("cross_program_prefetch_index={}", index));
+        }
+      },
+      HloOpcode::AsyncStart | HloOpcode::AsyncUpdate | HloOpcode::AsyncDone => {
+        if self.async_execution_thread != MAIN_EXECUTION_THREAD {
+          attributes.push(format!("async_execution_thread=\"{}\"",
+            c_escape(&self.async_execution_thread)));
+        }
+      },
+      _ => {}
+    }
+  }
+
+  // Prints the operand precisions unless they are all default, and the
+  // algorithm unless it is unset.
+  fn print_precision_config(&self, attributes: &mut Vec<String>) {
+    let precisions = self.precision_config.operand_precision();
+    if precisions.iter().any(|p| *p != Precision::Default) {
+      let names: Vec<String> = precisions.iter().map(precision_to_string).collect();
+      attributes.push(format!("operand_precision={{{}}}", names.join(",")));
+    }
+    if self.precision_config.algorithm() != Algorithm::Unset {
+      attributes.push(format!("algorithm={}",
+        algorithm_to_string(&self.precision_config.algorithm())));
+    }
+  }
+
+  // Prints the computations called by this instruction, either by name or
+  // with their full bodies depending on the subcomputation print mode.
+  fn print_subcomputation_attributes(
+    &self,
+    context: &HloPrintContext,
+    options: &HloPrintOptions,
+    attributes: &mut Vec<String>)
+  {
+    let mode = options.print_subcomputation_mode();
+    if mode == PrintSubcomputationMode::Off {
+      return;
+    }
+    // Control flow runs its computations in sequence with the caller, so
+    // they are only named when non-sequential bodies are requested.
+    let full_bodies = match mode {
+      PrintSubcomputationMode::FullBodies => true,
+      PrintSubcomputationMode::NonSequentialBodies => !matches!(self.opcode,
+        HloOpcode::While | HloOpcode::Conditional | HloOpcode::Call |
+        HloOpcode::AsyncStart | HloOpcode::AsyncUpdate | HloOpcode::AsyncDone),
+      _ => false
+    };
+    let computations = self.called_computations();
+    let print_one = |id: ComputationId| -> String {
+      self.called_computation_to_string(context, id, options, full_bodies)
+    };
+    let print_list = |ids: &[ComputationId]| -> String {
+      let names: Vec<String> = ids.iter().map(|id| print_one(*id)).collect();
+      format!("{{{}}}", names.join(", "))
+    };
+    match self.opcode {
+      HloOpcode::While => {
+        if computations.len() == 2 {
+          attributes.push(format!("condition={}", print_one(self.while_condition())));
+          attributes.push(format!("body={}", print_one(self.while_body())));
+        }
+      },
+      HloOpcode::SelectAndScatter => {
+        attributes.push(format!("select={}", print_one(self.select())));
+        attributes.push(format!("scatter={}", print_one(self.scatter())));
+      },
+      HloOpcode::Conditional => {
+        let is_pred = match context.operand(self, self.operands[0]) {
+          Some(operand) => operand.shape().element_type() == PrimitiveType::Pred,
+          None => false
+        };
+        if is_pred && computations.len() == 2 {
+          attributes.push(format!("true_computation={}",
+            print_one(computations[TRUE_COMPUTATION_INDEX])));
+          attributes.push(format!("false_computation={}",
+            print_one(computations[FALSE_COMPUTATION_INDEX])));
+        } else {
+          attributes.push(format!("branch_computations={}", print_list(computations)));
+        }
+      },
+      HloOpcode::Call | HloOpcode::Map | HloOpcode::ReduceWindow | HloOpcode::Reduce |
+      HloOpcode::AllReduce | HloOpcode::ReduceScatter | HloOpcode::AllReduceStart |
+      HloOpcode::Scatter | HloOpcode::TopK | HloOpcode::Sort => {
+        if !computations.is_empty() {
+          attributes.push(format!("to_apply={}", print_one(computations[0])));
+        }
+      },
+      HloOpcode::CustomCall => {
+        if !computations.is_empty() {
+          attributes.push(format!("called_computations={}", print_list(computations)));
+        }
+      },
+      HloOpcode::AsyncStart | HloOpcode::AsyncUpdate | HloOpcode::AsyncDone => {
+        // Async updates and dones refer to the computation of their operand.
+        if self.opcode == HloOpcode::AsyncStart && !computations.is_empty() &&
+           self.syntax_sugar_async_computation(context, options).is_none()
+        {
+          attributes.push(format!("calls={}", print_one(computations[0])));
+        }
+      },
+      _ => {
+        if computations.len() == 1 {
+          attributes.push(format!("calls={}", print_one(computations[0])));
+        } else if !computations.is_empty() {
+          attributes.push(format!("calls={}", print_list(computations)));
+        }
+      }
+    }
   }
 
-  fn print_extra_attributes_impl() {}
+  // Returns the called computation 'id' as it appears in an attribute: its
+  // name, or its whole body on the following lines.
+  fn called_computation_to_string(
+    &self,
+    context: &HloPrintContext,
+    id: ComputationId,
+    options: &HloPrintOptions,
+    full_body: bool) -> String
+  {
+    let mut printer = StringPrinter::new();
+    match context.called_computation(id) {
+      Some(computation) if full_body => {
+        let mut new_options = options.clone();
+        new_options.set_is_in_nested_computation(true);
+        printer.append(&"\n".to_string());
+        computation.print(&mut printer, context.module(), &new_options);
+      },
+      Some(computation) => print_name_internal(&mut printer, &computation.name(), options),
+      None => print_name_internal(&mut printer, &format!("computation.{}", id.index()), options)
+    }
+    printer.to_string()
+  }
+
+  // Returns the computation wrapped by this async op if the op is printed
+  // with the '<op>-start', '<op>-update' and '<op>-done' syntax sugar. This
+  // is the case when the computation holds nothing but the wrapped
+  // instruction applied to the parameters in order.
+  pub(crate) fn syntax_sugar_async_computation<'a>(
+    &self, context: &HloPrintContext<'a>, options: &HloPrintOptions) -> Option<&'a HloComputation>
+  {
+    if !options.syntax_sugar_async_ops() {
+      return None;
+    }
+    match self.opcode {
+      HloOpcode::AsyncStart | HloOpcode::AsyncUpdate | HloOpcode::AsyncDone => {},
+      _ => return None
+    }
+    let computation = context.called_computation(*self.called_computations().first()?)?;
+    if !computation.has_root_instruction() || !computation.can_expand_into_single_instruction() {
+      return None;
+    }
+    let root = computation.root_instruction();
+    if root.opcode() == HloOpcode::Parameter ||
+       root.operands() != computation.parameter_instructions()
+    {
+      return None;
+    }
+    Some(computation)
+  }
   // HloCollectiveInstruction
 
   // HloTopKInstruction
   pub fn k(&self) -> i64{
-    unimplemented!()
+    self.k
   }
 
-  pub fn set_k(&mut self, _k: i64) {
-    unimplemented!()
+  pub fn set_k(&mut self, k: i64) {
+    self.k = k;
+  }
+
+  pub fn largest(&self) -> bool {
+    self.largest
   }
   // HloTopKInstruction
 
   // HloGetTupleElementInstruction
-  pub fn set_tuple_index(&self, _new_tuple_index: i64) {
-    unimplemented!()
+  pub fn set_tuple_index(&mut self, new_tuple_index: i64) {
+    self.tuple_index = new_tuple_index;
   }
 
-  pub fn set_use_global_device_ids(&mut self, _use_global_device_ids: bool) {
-    unimplemented!()
+  pub fn set_use_global_device_ids(&mut self, use_global_device_ids: bool) {
+    self.use_global_device_ids = use_global_device_ids;
   }
 
-  pub fn set_is_stable(&mut self, _is_stable: bool) {
-    unimplemented!()
+  pub fn is_stable(&self) -> bool {
+    self.is_stable
   }
 
-  pub fn set_largest(&mut self, _largest: bool) {
-    unimplemented!()
+  pub fn set_is_stable(&mut self, is_stable: bool) {
+    self.is_stable = is_stable;
   }
 
-  pub fn set_rng_algorithm(&mut self, _algorithm: RandomAlgorithm) {
-    unimplemented!()
+  pub fn set_largest(&mut self, largest: bool) {
+    self.largest = largest;
   }
 
-  pub fn set_is_host_transfer(&mut self, _is_host_transfer: bool) {
-    unimplemented!()
+  pub fn set_rng_algorithm(&mut self, algorithm: RandomAlgorithm) {
+    self.rng_algorithm = algorithm;
   }
 
-  pub fn set_result_accuracy(&mut self, _result_accuracy: ResultAccuracy) {
-    unimplemented!()
+  pub fn set_is_host_transfer(&mut self, is_host_transfer: bool) {
+    self.is_host_transfer = is_host_transfer;
+  }
+
+  pub fn result_accuracy(&self) -> Option<&ResultAccuracy> {
+    self.result_accuracy.as_ref()
+  }
+
+  pub fn set_result_accuracy(&mut self, result_accuracy: ResultAccuracy) {
+    self.result_accuracy = Some(result_accuracy);
   }
 
   fn is_elementwise_impl(&self, _operand_idx: Option<i64>) -> bool {
     false
   }
 
-  fn print_operand_with_canonical_name_map() {}
+  fn print_operands_with_canonical_name_map(
+    &self,
+    printer: &mut dyn Printer,
+    context: &HloPrintContext,
+    options: &HloPrintOptions,
+    canonical_name_map: &mut CanonicalNameMap)
+  {
+    match self.opcode {
+      HloOpcode::Parameter => {
+        printer.append(&self.parameter_number.to_string());
+        return;
+      },
+      HloOpcode::Constant => {
+        self.print_constant_operand(printer, options);
+        return;
+      },
+      _ => {}
+    }
+    if self.operands.is_empty() {
+      return;
+    }
+    const MAX_OPERANDS_TO_SHOW_IF_COMPACT: usize = 4;
+    let mut slice = &self.operands[..];
+    if options.compact_operands() && slice.len() > MAX_OPERANDS_TO_SHOW_IF_COMPACT {
+      slice = &slice[..MAX_OPERANDS_TO_SHOW_IF_COMPACT];
+    }
+    let interval = options.print_operand_index_annotation_interval();
+    for (i, id) in slice.iter().enumerate() {
+      if i > 0 {
+        if interval != 0 && i as i64 % interval == 0 {
+          printer.append(&format!(", /*index={}*/", i));
+        } else {
+          printer.append(&", ".to_string());
+        }
+      }
+      let operand = context.operand(self, *id);
+      let mut add_space = false;
+      if options.print_operand_shape() {
+        if let Some(operand) = operand {
+          print_shape(printer, operand.shape(), options);
+          add_space = true;
+        }
+      }
+      if options.canonicalize_instruction_names() {
+        if add_space {
+          printer.append(&" ".to_string());
+        }
+        // In canonicalize mode, operands are canonicalized, i.e., named by
+        // instruction position.
+        let unique_id = operand.map_or(id.index() as i64, |o| o.unique_id);
+        printer.append(&canonical_name_map.lookup_or_insert(unique_id));
+      } else if options.print_operand_names() {
+        if add_space {
+          printer.append(&" ".to_string());
+        }
+        printer.append(&self.operand_name(context, *id, options));
+      }
+    }
+    let remaining = self.operands.len() - slice.len();
+    if remaining > 0 {
+      printer.append(&format!(", ...(+{})", remaining));
+    }
+  }
+
+  // For constants, shows the actual value in place of an empty operand list.
+  fn print_constant_operand(&self, printer: &mut dyn Printer, options: &HloPrintOptions) {
+    let literal = match &self.literal {
+      Some(literal) => literal,
+      None => {
+        printer.append(&"{...}".to_string());
+        return;
+      }
+    };
+    if options.print_only_essential_constants() {
+      let mut values = vec![];
+      literal_values(&self.shape, literal, &mut vec![], &mut values);
+      if values.iter().all(|v| element_equals(*v, 0.0)) {
+        printer.append(&"0".to_string());
+      } else if values.iter().all(|v| element_equals(*v, 1.0)) {
+        printer.append(&"1".to_string());
+      } else if self.shape.is_integer() {
+        print_literal_oneline(printer, &self.shape, literal, &mut vec![]);
+      } else {
+        printer.append(&"{...}".to_string());
+      }
+      return;
+    }
+    if (self.shape.is_array() && ShapeUtil::elements_in(&self.shape) <= 10) ||
+       options.print_large_constants()
+    {
+      print_literal_oneline(printer, &self.shape, literal, &mut vec![]);
+    } else {
+      // Do not show large constants or tuples.
+      printer.append(&"{...}".to_string());
+    }
+  }
+
+  // Returns the name of the instruction 'id' as referred to by this one,
+  // e.g. as an operand or a control predecessor.
+  fn operand_name(
+    &self, context: &HloPrintContext, id: InstructionId, options: &HloPrintOptions) -> String
+  {
+    let mut printer = StringPrinter::new();
+    match context.operand(self, id) {
+      Some(operand) => print_name_internal(&mut printer, &operand.name, options),
+      None => print_name_internal(&mut printer, &format!("operand.{}", id.index()), options)
+    }
+    printer.to_string()
+  }
   fn identical_slow_path() {}
-  fn create_nary() {}
+  fn create_nary(
+    shape: &Shape,
+    opcode: HloOpcode,
+    operands: &[&HloInstruction]) -> HloInstruction
+  {
+    if opcode == HloOpcode::Copy {
+      // It is impossible to copy an opaque shape, we don't know how big it is.
+      assert!(!shape.is_opaque());
+    }
+    HloInstruction::new(opcode, shape, operands)
+  }
 
   // Adds a user for this instruction.
-  fn add_user(&mut self, user: HloInstruction) {
+  pub(crate) fn add_user(&mut self, user: InstructionId) {
     self.users.add_user(user);
   }
 
   // Removes a user for this instruction.
-  fn remove_user(&mut self, user: HloInstruction) {
+  pub(crate) fn remove_user(&mut self, user: InstructionId) {
     self.users.remove_user(user);
   }
 
+  pub(crate) fn clear_users(&mut self) {
+    self.users.clear();
+  }
+
+  // Appends the given operand to the operand list. The caller is responsible
+  // for registering this instruction as a user of the operand.
+  pub(crate) fn append_operand(&mut self, operand: InstructionId) {
+    self.operands.push(operand);
+  }
+
+  pub(crate) fn set_operand(&mut self, operand_num: usize, operand: InstructionId) {
+    self.operands[operand_num] = operand;
+  }
+
+  pub(crate) fn remove_operands_at_ascending_indices(&mut self, indices: &[usize]) {
+    let mut index = 0;
+    self.operands.retain(|_| {
+      let keep = !indices.contains(&index);
+      index += 1;
+      keep
+    });
+  }
+
+  pub(crate) fn add_control_predecessor(&mut self, predecessor: InstructionId) {
+    let rare = self.mutable_rare();
+    if !rare.control_predecessors.contains(&predecessor) {
+      rare.control_predecessors.push(predecessor);
+    }
+  }
+
+  pub(crate) fn add_control_successor(&mut self, successor: InstructionId) {
+    let rare = self.mutable_rare();
+    if !rare.control_successors.contains(&successor) {
+      rare.control_successors.push(successor);
+    }
+  }
+
+  pub(crate) fn remove_control_predecessor(&mut self, predecessor: InstructionId) {
+    if self.has_rare() {
+      self.mutable_rare().control_predecessors.retain(|p| *p != predecessor);
+    }
+  }
+
+  pub(crate) fn remove_control_successor(&mut self, successor: InstructionId) {
+    if self.has_rare() {
+      self.mutable_rare().control_successors.retain(|s| *s != successor);
+    }
+  }
+
   fn get_backend_config_internal() {}
-  fn mark_as_dead() {}
+  pub(crate) fn mark_as_dead(&mut self) {
+    self.marked_as_dead = true;
+  }
 
-  fn is_marked_as_dead(&self) -> bool {
-    false
+  pub(crate) fn is_marked_as_dead(&self) -> bool {
+    self.marked_as_dead
   }
 
   fn has_rare(&self) -> bool {
@@ -2408,37 +4313,419 @@ impl HloInstruction {
   }
 
   fn mutable_rare(&mut self) -> &mut Rare {
-    assert!(self.has_rare());
+    if !self.has_rare() {
+      self.rare = Some(Rare::new());
+    }
     self.rare.as_mut().unwrap()
   }
 }
 
 pub fn string_to_fusion_kind(name: &String) -> Result<FusionKind, String> {
-  if name == "Loop" {
+  if name == "kLoop" {
     Ok(FusionKind::Loop)
-  } else if name == "Input" {
+  } else if name == "kInput" {
     Ok(FusionKind::Input)
-  } else if name == "Output" {
+  } else if name == "kOutput" {
     Ok(FusionKind::Output)
-  } else if name == "Custom" {
+  } else if name == "kCustom" {
     Ok(FusionKind::Custom)
   } else {
     Err("Unknown fusion kind".to_string())
   }
 }
 
-pub fn string_to_random_distribution(_name: &String) -> Result<RandomDistribution, String> {
-  unimplemented!()
+pub fn string_to_random_distribution(name: &String) -> Result<RandomDistribution, String> {
+  if name == "rng_uniform" {
+    Ok(RandomDistribution::Uniform)
+  } else if name == "rng_normal" {
+    Ok(RandomDistribution::Normal)
+  } else {
+    Err(format!("Unknown distribution: {}", name))
+  }
+}
+
+pub fn string_to_random_algorithm(name: &String) -> Result<RandomAlgorithm, String> {
+  if name == "rng_default" {
+    Ok(RandomAlgorithm::Default)
+  } else if name == "rng_three_fry" {
+    Ok(RandomAlgorithm::ThreeFry)
+  } else if name == "rng_philox" {
+    Ok(RandomAlgorithm::Philox)
+  } else {
+    Err(format!("Unknown algorithm: {}", name))
+  }
+}
+
+pub fn string_to_precision(name: &String) -> Result<Precision, String> {
+  if name == "default" {
+    Ok(Precision::Default)
+  } else if name == "high" {
+    Ok(Precision::High)
+  } else if name == "highest" {
+    Ok(Precision::Highest)
+  } else if name == "packed_nibble" {
+    Ok(Precision::PackedNibble)
+  } else {
+    Err(format!("Unknown precision: {}", name))
+  }
+}
+
+pub fn string_to_algorithm(name: &String) -> Result<Algorithm, String> {
+  if name == "unset" {
+    Ok(Algorithm::Unset)
+  } else if name == "dot_any_f8_any_f8_f32" {
+    Ok(Algorithm::DotAnyF8anyF8F32)
+  } else {
+    Err(format!("Unknown algorithm: {}", name))
+  }
+}
+pub fn fusion_kind_to_string(kind: &FusionKind) -> String {
+  match kind {
+    FusionKind::Loop => "kLoop".to_string(),
+    FusionKind::Input => "kInput".to_string(),
+    FusionKind::Output => "kOutput".to_string(),
+    FusionKind::Custom => "kCustom".to_string()
+  }
+}
+
+pub fn random_distribution_to_string(distribution: &RandomDistribution) -> String {
+  match distribution {
+    RandomDistribution::Uniform => "rng_uniform".to_string(),
+    RandomDistribution::Normal => "rng_normal".to_string(),
+    RandomDistribution::Invalid => "rng_invalid".to_string()
+  }
+}
+
+pub fn random_algorithm_to_string(algorithm: &RandomAlgorithm) -> String {
+  match algorithm {
+    RandomAlgorithm::Default => "rng_default".to_string(),
+    RandomAlgorithm::ThreeFry => "rng_three_fry".to_string(),
+    RandomAlgorithm::Philox => "rng_philox".to_string()
+  }
+}
+
+pub fn precision_to_string(precision: &Precision) -> String {
+  match precision {
+    Precision::Default => "default".to_string(),
+    Precision::High => "high".to_string(),
+    Precision::Highest => "highest".to_string(),
+    Precision::PackedNibble => "packed_nibble".to_string()
+  }
+}
+
+pub fn algorithm_to_string(algorithm: &Algorithm) -> String {
+  match algorithm {
+    Algorithm::Unset => "unset".to_string(),
+    Algorithm::DotAnyF8anyF8F32 => "dot_any_f8_any_f8_f32".to_string()
+  }
+}
+
+pub fn fft_type_to_string(fft_type: &FftType) -> String {
+  match fft_type {
+    FftType::FFT => "FFT".to_string(),
+    FftType::IFFT => "IFFT".to_string(),
+    FftType::RFFT => "RFFT".to_string(),
+    FftType::IRFFT => "IRFFT".to_string()
+  }
+}
+
+pub fn padding_type_to_string(padding_type: &PaddingType) -> String {
+  match padding_type {
+    PaddingType::Invalid => "PADDING_INVALID".to_string(),
+    PaddingType::Valid => "PADDING_VALID".to_string(),
+    PaddingType::Same => "PADDING_SAME".to_string()
+  }
+}
+
+pub fn custom_call_schedule_to_string(schedule: &CustomCallSchedule) -> String {
+  match schedule {
+    CustomCallSchedule::None => "SCHEDULE_NONE".to_string(),
+    CustomCallSchedule::Latest => "SCHEDULE_LATEST".to_string(),
+    CustomCallSchedule::Earliest => "SCHEDULE_EARLIEST".to_string()
+  }
+}
+
+pub fn custom_call_api_version_to_string(api_version: &CustomCallApiVersion) -> String {
+  match api_version {
+    CustomCallApiVersion::Unspecified => "API_VERSION_UNSPECIFIED".to_string(),
+    CustomCallApiVersion::Original => "API_VERSION_ORIGINAL".to_string(),
+    CustomCallApiVersion::StatusReturning => "API_VERSION_STATUS_RETURNING".to_string(),
+    CustomCallApiVersion::StatusReturningUnified =>
+      "API_VERSION_STATUS_RETURNING_UNIFIED".to_string(),
+    CustomCallApiVersion::TypedFfi => "API_VERSION_TYPED_FFI".to_string()
+  }
+}
+
+pub fn transpose_to_string(transpose: &Transpose) -> String {
+  match transpose {
+    Transpose::Invalid => "TRANSPOSE_INVALID".to_string(),
+    Transpose::NoTranspose => "NO_TRANSPOSE".to_string(),
+    Transpose::Transpose => "TRANSPOSE".to_string(),
+    Transpose::Adjoint => "ADJOINT".to_string()
+  }
+}
+
+// Returns the window in the form 'size=3x3 stride=2x2 pad=0_1x0_1'. Fields
+// which hold their default value in every dimension are left out.
+pub fn window_to_string(window: &Window) -> String {
+  let dims = window.dimensions_vec();
+  let field = |heading: &str, format: &dyn Fn(&WindowDimension) -> String| -> String {
+    let values: Vec<String> = dims.iter().map(format).collect();
+    format!("{}={}", heading, values.join("x"))
+  };
+  let mut fields = vec![];
+  if !dims.is_empty() {
+    fields.push(field("size", &|d| d.size().to_string()));
+  }
+  if dims.iter().any(|d| d.stride() != 1) {
+    fields.push(field("stride", &|d| d.stride().to_string()));
+  }
+  if dims.iter().any(|d| d.padding_low() != 0 || d.padding_high() != 0) {
+    fields.push(field("pad", &|d| format!("{}_{}", d.padding_low(), d.padding_high())));
+  }
+  if dims.iter().any(|d| d.base_dilation() != 1) {
+    fields.push(field("lhs_dilate", &|d| d.base_dilation().to_string()));
+  }
+  if dims.iter().any(|d| d.window_dilation() != 1) {
+    fields.push(field("rhs_dilate", &|d| d.window_dilation().to_string()));
+  }
+  if dims.iter().any(|d| d.window_reversal()) {
+    fields.push(field("rhs_reversal",
+      &|d| if d.window_reversal() { "1".to_string() } else { "0".to_string() }));
+  }
+  fields.join(" ")
+}
+
+// Returns the padding in the form 'low_high' or 'low_high_interior' per
+// dimension, joined by 'x'. The interior padding is only shown if any
+// dimension has some.
+pub fn padding_config_to_string(padding: &PaddingConfig) -> String {
+  let has_interior_padding =
+    padding.dimensions_vec().iter().any(|d| d.interior_padding() != 0);
+  let dims: Vec<String> = padding.dimensions_vec().iter().map(|d| {
+    if has_interior_padding {
+      format!("{}_{}_{}", d.edge_padding_low(), d.edge_padding_high(), d.interior_padding())
+    } else {
+      format!("{}_{}", d.edge_padding_low(), d.edge_padding_high())
+    }
+  }).collect();
+  dims.join("x")
+}
+
+// Returns the dimension numbers as labels, e.g. 'b01f_01io->b01f'.
+pub fn convolution_dimension_numbers_to_string(dnums: &ConvolutionDimensionNumbers) -> String {
+  let labels = |batch: char, feature: char, batch_dim: i64, feature_dim: i64,
+    spatial_dims: &Vec<i64>| -> String
+  {
+    let mut dims = vec!["?".to_string(); 2 + spatial_dims.len()];
+    let mut set = |dim: i64, label: String| {
+      if dim >= 0 && (dim as usize) < dims.len() {
+        dims[dim as usize] = label;
+      }
+    };
+    set(batch_dim, batch.to_string());
+    set(feature_dim, feature.to_string());
+    for (i, dim) in spatial_dims.iter().enumerate() {
+      set(*dim, i.to_string());
+    }
+    dims.concat()
+  };
+  let lhs = labels('b', 'f', dnums.input_batch_dimension(),
+    dnums.input_feature_dimension(), dnums.input_spatial_dimensions_vec());
+  let rhs = labels('i', 'o', dnums.kernel_input_feature_dimension(),
+    dnums.kernel_output_feature_dimension(), dnums.kernel_spatial_dimensions_vec());
+  let output = labels('b', 'f', dnums.output_batch_dimension(),
+    dnums.output_feature_dimension(), dnums.output_spatial_dimensions_vec());
+  format!("{}_{}->{}", lhs, rhs, output)
 }
 
-pub fn string_to_random_algorithm(_name: &String) -> Result<RandomAlgorithm, String> {
-  unimplemented!()
+pub fn frontend_attributes_to_string(attributes: &FrontendAttributes) -> String {
+  let items: Vec<String> = attributes.map().iter().map(|(key, value)| {
+    // Keys which are not identifiers are quoted.
+    let key_is_identifier = key.chars().next().map_or(false, |c| c.is_ascii_alphabetic() || c == '_') &&
+      key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-');
+    if key_is_identifier {
+      format!("{}=\"{}\"", key, c_escape(value))
+    } else {
+      format!("\"{}\"=\"{}\"", c_escape(key), c_escape(value))
+    }
+  }).collect();
+  format!("{{{}}}", items.join(","))
 }
 
-pub fn string_to_precision(_name: &String) -> Result<Precision, String> {
-  unimplemented!()
+pub fn statistics_viz_to_string(statistics_viz: &StatisticsViz) -> String {
+  let mut items = vec![
+    format!("visualizing_index={}", statistics_viz.stat_index_to_viaualize())];
+  for statistic in statistics_viz.statiscics() {
+    items.push(format!("{}={}", statistic.stat_name(), statistic.stat_val()));
+  }
+  format!("{{{}}}", items.join(","))
 }
 
-pub fn string_to_algorithm(_name: &String) -> Result<Algorithm, String> {
-  unimplemented!()
-}
\ No newline at end of file
+// A Literal<f64> holding a value of 'shape' mirrors it with every array
+// element type widened to F64.
+pub fn literal_shape(shape: &Shape) -> Shape {
+  if shape.is_tuple() {
+    return ShapeUtil::make_tuple_shape(
+      shape.tuple_shapes_vec().iter().map(literal_shape).collect());
+  }
+  if shape.is_token() {
+    return shape.clone();
+  }
+  ShapeUtil::make_shape(&PrimitiveType::F64, shape.dimensions_vec().clone())
+}
+
+// Prints 'name' with the '%' prefix if the options ask for it.
+fn print_name_internal(printer: &mut dyn Printer, name: &String, options: &HloPrintOptions) {
+  if options.print_percent() {
+    printer.append(&"%".to_string());
+  }
+  printer.append(name);
+}
+
+fn print_shape(printer: &mut dyn Printer, shape: &Shape, options: &HloPrintOptions) {
+  if options.include_layout_in_shapes() {
+    ShapeUtil::print_human_string_with_layout(printer, shape);
+  } else {
+    ShapeUtil::print_human_string(printer, shape);
+  }
+}
+
+fn braced_list(values: &Vec<i64>) -> String {
+  let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+  format!("{{{}}}", values.join(","))
+}
+
+fn shape_index_to_string(index: &Vec<i64>) -> String {
+  braced_list(index)
+}
+
+// Returns whether 'config' is a single curly-brace delimited dict, which the
+// parser accepts without quotes. Curlies inside quoted strings don't count.
+fn lexes_as_json_dict(config: &String) -> bool {
+  let bytes = config.as_bytes();
+  if bytes.first() != Some(&b'{') {
+    return false;
+  }
+  let mut depth = 0;
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'"' => {
+        i += 1;
+        while i < bytes.len() && bytes[i] != b'"' {
+          if bytes[i] == b'\\' { i += 1; }
+          i += 1;
+        }
+        if i >= bytes.len() {
+          return false;
+        }
+      },
+      b'{' => depth += 1,
+      b'}' => {
+        depth -= 1;
+        if depth == 0 {
+          return i + 1 == bytes.len();
+        }
+      },
+      _ => {}
+    }
+    i += 1;
+  }
+  false
+}
+
+// Formats a floating point value so that the parser reads it back unchanged.
+// Integral values are printed without a fraction.
+fn float_to_string(value: f64) -> String {
+  if value.is_nan() {
+    "nan".to_string()
+  } else if value.is_infinite() {
+    if value > 0.0 { "inf".to_string() } else { "-inf".to_string() }
+  } else if value.fract() == 0.0 && value.abs() < 1e16 {
+    (value as i64).to_string()
+  } else {
+    format!("{:?}", value)
+  }
+}
+
+// Prints an element of a constant of type 'element_type' so that the parser
+// reads it back unchanged. Complex numbers are printed as '(real, imag)'.
+fn literal_value_to_string(element_type: &PrimitiveType, value: Element) -> String {
+  match value {
+    Element::Integer(v) if *element_type == PrimitiveType::Pred => (v != 0).to_string(),
+    Element::Integer(v) => v.to_string(),
+    Element::Real(v) => float_to_string(v),
+    Element::Complex(re, im) => format!("({}, {})", float_to_string(re), float_to_string(im))
+  }
+}
+
+fn element_equals(element: Element, value: f64) -> bool {
+  match element {
+    Element::Integer(v) => v as f64 == value,
+    Element::Real(v) => v == value,
+    Element::Complex(re, im) => re == value && im == 0.0
+  }
+}
+
+// Collects the elements of every array in the literal.
+fn literal_values(
+  shape: &Shape, literal: &DynLiteral, index: &mut Vec<i64>, values: &mut Vec<Element>)
+{
+  if shape.is_tuple() {
+    for i in 0..shape.tuple_shapes_size() {
+      index.push(i as i64);
+      literal_values(shape.tuple_shapes(i), literal, index, values);
+      index.pop();
+    }
+    return;
+  }
+  values.extend(literal.elements(index));
+}
+
+// Prints the literal without shapes on a single line, e.g. '{ {1, 2}, {3, 4} }'
+// or '(1, {2, 3})' for a tuple.
+fn print_literal_oneline(
+  printer: &mut dyn Printer, shape: &Shape, literal: &DynLiteral, index: &mut Vec<i64>)
+{
+  if shape.is_tuple() {
+    printer.append(&"(".to_string());
+    for i in 0..shape.tuple_shapes_size() {
+      if i > 0 {
+        printer.append(&", ".to_string());
+      }
+      index.push(i as i64);
+      print_literal_oneline(printer, shape.tuple_shapes(i), literal, index);
+      index.pop();
+    }
+    printer.append(&")".to_string());
+    return;
+  }
+  let data = literal.elements(index);
+  let element_type = shape.element_type();
+  if shape.rank() == 0 {
+    printer.append(&literal_value_to_string(&element_type, data[0]));
+    return;
+  }
+  let mut position = 0;
+  print_literal_dimension(printer, shape, &data, 0, &mut position);
+}
+
+fn print_literal_dimension(
+  printer: &mut dyn Printer, shape: &Shape, data: &Vec<Element>, dim: usize, position: &mut usize)
+{
+  let minor = dim + 1 == shape.rank();
+  printer.append(&(if minor { "{" } else { "{ " }).to_string());
+  for i in 0..shape.dimensions(dim) {
+    if i > 0 {
+      printer.append(&", ".to_string());
+    }
+    if minor {
+      printer.append(&literal_value_to_string(&shape.element_type(), data[*position]));
+      *position += 1;
+    } else {
+      print_literal_dimension(printer, shape, data, dim + 1, position);
+    }
+  }
+  printer.append(&(if minor { "}" } else { " }" }).to_string());
+}
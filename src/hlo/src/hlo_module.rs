@@ -1,11 +1,17 @@
 #![allow(dead_code)]
 
-use std::collections::{HashMap, HashSet};
+use std::{
+  collections::{HashMap, HashSet},
+  hash::{Hash, Hasher},
+  sync::{atomic::{AtomicI64, Ordering}, Arc}
+};
 
 use common::{
   blitz_data::FrontendAttributes,
-  printer::Printer,
-  shape::Shape
+  literal_serialization::{program_shape_to_proto, write_bytes_field, write_varint_field},
+  printer::{Printer, StringPrinter},
+  shape::Shape,
+  shape_util::ShapeUtil
 };
 
 use crate::{
@@ -14,13 +20,86 @@ use crate::{
     HloBufferDonorConfig,
     HloInputOutputAliasConfig
   },
-  hlo_instruction::HloPrintOptions,
+  hlo_instruction::{
+    frontend_attributes_to_string, HloInstruction, HloPrintContext, HloPrintOptions,
+    InstructionId
+  },
   hlo_module_config::HloModuleConfig,
   hlo_module_metadata::HloModuleMetadata,
+  hlo_opcode::HloOpcode,
   hlo_schdule::HloSchedule,
   hlo_sharding::HloSharding
 };
 
+// Source of the unique ids of the modules created in this process.
+static NEXT_UNIQUE_MODULE_ID: AtomicI64 = AtomicI64::new(0);
+
+// Field numbers of HloModuleProto.
+const MODULE_NAME: u32 = 1;
+const MODULE_ENTRY_COMPUTATION_NAME: u32 = 2;
+const MODULE_COMPUTATIONS: u32 = 3;
+const MODULE_HOST_PROGRAM_SHAPE: u32 = 4;
+const MODULE_ID: u32 = 5;
+const MODULE_ENTRY_COMPUTATION_ID: u32 = 6;
+
+// Identifies a computation within its module. It is the index of the
+// computation in the module's arena, so it stays valid until the computation
+// is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ComputationId(usize);
+
+impl ComputationId {
+  pub fn new(index: usize) -> Self {
+    ComputationId(index)
+  }
+
+  pub fn index(&self) -> usize {
+    self.0
+  }
+}
+
+// Source of the unique ids of the instructions in a module. The counter is
+// shared by the module and its computations, so that instructions added to a
+// computation after it was added to the module still get ids which are unique
+// in the module.
+#[derive(Debug, Clone, Default)]
+pub struct UniqueIdCounter(Arc<AtomicI64>);
+
+impl UniqueIdCounter {
+  pub fn new() -> Self {
+    UniqueIdCounter(Arc::new(AtomicI64::new(0)))
+  }
+
+  // Returns the next id.
+  pub fn next(&self) -> i64 {
+    self.0.fetch_add(1, Ordering::Relaxed)
+  }
+
+  // Returns true if 'self' and 'other' hand out ids from the same sequence.
+  pub fn is_shared_with(&self, other: &UniqueIdCounter) -> bool {
+    Arc::ptr_eq(&self.0, &other.0)
+  }
+}
+
+// The counter is deliberately excluded from equality and hashing. Computations
+// derive PartialEq and Hash to compare their instructions, e.g. to deduplicate
+// identical computations, and two computations with the same instructions are
+// equal whether or not they draw their ids from the same module. Cloning a
+// computation shares the counter, so the clone keeps getting ids which are
+// unique in the module of the original. Use is_shared_with() to tell whether
+// two counters are the same.
+impl PartialEq for UniqueIdCounter {
+  fn eq(&self, _other: &Self) -> bool {
+    true
+  }
+}
+
+impl Eq for UniqueIdCounter {}
+
+impl Hash for UniqueIdCounter {
+  fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
 pub struct StackFrame {
   file_name: String,
   function_name: String,
@@ -44,10 +123,11 @@ pub struct CrossProgramPrefetchInfo {
 #[derive(PartialEq)]
 pub struct HloModule {
   name: String,
-  entry_computation: Option<HloComputation>,
-  computations: Vec<HloComputation>,
-  next_unique_id: i64,
+  entry_computation: Option<ComputationId>,
+  computations: Vec<Option<HloComputation>>,
+  next_unique_id: UniqueIdCounter,
   unique_id: i64,
+  generation: i64,
   is_dynamic: bool,
   profile_verison: i64,
   relative_speedup: f64,
@@ -66,33 +146,107 @@ pub struct HloModule {
 }
 
 impl HloModule {
-  pub fn new(_name: String, _config: HloModuleConfig) -> Self {
-    unimplemented!()
+  pub fn new(name: String, config: HloModuleConfig) -> Self {
+    HloModule {
+      name: name,
+      entry_computation: None,
+      computations: Vec::new(),
+      next_unique_id: UniqueIdCounter::new(),
+      unique_id: NEXT_UNIQUE_MODULE_ID.fetch_add(1, Ordering::Relaxed),
+      generation: 0,
+      is_dynamic: false,
+      profile_verison: 0,
+      relative_speedup: 0.0,
+      autofdo_fingerprint: "".to_string(),
+      use_auto_spmd_partitioning: false,
+      config: config,
+      frontend_attributes: FrontendAttributes::new(),
+      use_auto_spmd_partition: false,
+      input_output_alias_config: HloInputOutputAliasConfig::new(ShapeUtil::make_nil()),
+      buffer_donor_config: HloBufferDonorConfig::new(),
+      schedule: None,
+      spmd_parameters_shardings: None,
+      spmd_output_sharding: None,
+      cross_program_prefetches: Vec::new(),
+      metadata: HloModuleMetadata::new(),
+    }
+  }
+
+  fn add_computation_internal(
+    &mut self, mut computation: HloComputation, is_entry: bool) -> ComputationId
+  {
+    self.generation += 1;
+    let id = ComputationId::new(self.computations.len());
+    computation.set_computation_id(id);
+    computation.set_unique_id_counter(self.next_unique_id.clone());
+    if computation.unique_id() == -1 {
+      computation.set_unique_id(id.index() as i64);
+    }
+    if is_entry {
+      assert!(self.entry_computation.is_none(), "Module already has an entry computation.");
+      self.input_output_alias_config =
+        HloInputOutputAliasConfig::new(computation.root_instruction().shape().clone());
+      self.entry_computation = Some(id);
+    }
+    self.computations.push(Some(computation));
+    id
+  }
+
+  // Adds an entry computation to the module. A module can only have one entry
+  // computation.
+  pub fn add_entry_computation(&mut self, computation: HloComputation) -> ComputationId {
+    self.add_computation_internal(computation, true)
   }
 
-  pub fn add_entry_computation() {}
   pub fn add_entry_computation_with_layouts() {}
   pub fn replace_entry_computation() {}
 
   // Adds an embedded computation to the module.
-  pub fn add_embedded_computation(&mut self, _computation: HloComputation) -> &HloComputation {
-    unimplemented!()
+  pub fn add_embedded_computation(&mut self, computation: HloComputation) -> ComputationId {
+    self.add_computation_internal(computation, false)
   }
 
   // Removes an embedded computation.
   pub fn remove_embedded_computation(
-    &self,
-    _to_remove: &HloComputation) -> Result<(), String>
+    &mut self,
+    to_remove: ComputationId) -> Result<(), String>
   {
-    Ok(())
+    if self.entry_computation == Some(to_remove) {
+      return Err("Cannot remove the entry computation.".to_string());
+    }
+    match self.computations.get_mut(to_remove.index()) {
+      Some(slot) if slot.is_some() => {
+        *slot = None;
+        self.generation += 1;
+        Ok(())
+      },
+      _ => Err(format!("{:?} is not a computation of module {}.", to_remove, self.name))
+    }
   }
 
-  pub fn remove_unused_computations() {}
+  // Removes computations that are not reachable from the entry computation.
+  pub fn remove_unused_computations(&mut self) -> Result<(), String> {
+    if !self.has_entry_computation() { return Ok(()); }
+    let mut used = HashSet::new();
+    let mut worklist = vec![self.entry_computation_id()];
+    while let Some(id) = worklist.pop() {
+      if !used.insert(id) { continue; }
+      for instruction in self.computation(id).instructions() {
+        worklist.extend_from_slice(instruction.called_computations());
+      }
+    }
+    for id in self.computation_ids() {
+      if !used.contains(&id) {
+        self.remove_embedded_computation(id)?;
+      }
+    }
+    Ok(())
+  }
 
   // Mark duplicate fusions with the same name to be able to group them for
   // analysis pirposes.
   pub fn mark_fusion_duplications(
-    &self, _replacements: &HashMap<HloComputation, HloComputation>)
+    &self, _replacements: &HashMap<ComputationId, ComputationId>)
   {
     unimplemented!()
   }
@@ -100,9 +254,23 @@ impl HloModule {
   // Replaces all uses of computations that are keys of 'replacements' with
   // the corresponding values in 'replacements'.
   pub fn replace_computations(
-    &self, _replacements: &HashMap<HloComputation, HloComputation>)
+    &mut self, replacements: &HashMap<ComputationId, ComputationId>)
   {
-    unimplemented!()
+    self.generation += 1;
+    for computation in self.computations.iter_mut().flatten() {
+      for instruction in computation.mutable_instructions() {
+        instruction.replace_called_computations(
+          |c| *replacements.get(&c).unwrap_or(&c));
+      }
+    }
+    if let Some(entry) = self.entry_computation {
+      if let Some(replacement) = replacements.get(&entry) {
+        self.entry_computation = Some(*replacement);
+      }
+    }
+    for id in replacements.keys() {
+      let _ = self.remove_embedded_computation(*id);
+    }
   }
 
   pub fn name(&self) -> String {
@@ -117,9 +285,19 @@ impl HloModule {
 
   // Return a pointer to the entry computation of the module.
   pub fn entry_computation(&self) -> Option<&HloComputation> {
-    //assert!(self.has_entry_computation());
-    //self.entry_computation.as_ref().unwrap()
-    self.entry_computation.as_ref()
+    self.entry_computation.map(|id| self.computation(id))
+  }
+
+  pub fn mutable_entry_computation(&mut self) -> Option<&mut HloComputation> {
+    match self.entry_computation {
+      Some(id) => Some(self.mutable_computation(id)),
+      None => None
+    }
+  }
+
+  pub fn entry_computation_id(&self) -> ComputationId {
+    assert!(self.has_entry_computation());
+    self.entry_computation.unwrap()
   }
 
   pub fn has_entry_computation(&self) -> bool {
@@ -163,37 +341,74 @@ impl HloModule {
   pub fn layout_canonicalization_callback() {}
   pub fn absl_hash_value() {}
 
-  pub fn computations(&self) -> &Vec<HloComputation> {
-    unimplemented!()
+  // Returns the computation with the given id.
+  pub fn computation(&self, id: ComputationId) -> &HloComputation {
+    match self.computations.get(id.index()) {
+      Some(Some(computation)) => computation,
+      _ => panic!("{:?} is not a computation of module {}.", id, self.name)
+    }
   }
 
-  pub fn mutable_computations(&mut self) -> &mut Vec<HloComputation> {
-    unimplemented!()
+  pub fn mutable_computation(&mut self, id: ComputationId) -> &mut HloComputation {
+    self.generation += 1;
+    match self.computations.get_mut(id.index()) {
+      Some(Some(computation)) => computation,
+      _ => panic!("{:?} is not a computation of module {}.", id, self.name)
+    }
   }
 
-  pub fn computations_by_exec_threads(
-    &self, _execution_threads: &HashSet<String>) -> &Vec<HloComputation>
+  // Returns whether 'id' refers to a live computation of this module.
+  pub fn contains_computation(&self, id: ComputationId) -> bool {
+    matches!(self.computations.get(id.index()), Some(Some(_)))
+  }
+
+  // Looks up an instruction by its (computation, instruction) id pair.
+  pub fn instruction(
+    &self, computation: ComputationId, instruction: InstructionId) -> &HloInstruction
   {
-    unimplemented!()
+    self.computation(computation).instruction(instruction)
+  }
+
+  // Returns the computations of the module in the order they were added.
+  pub fn computations(&self) -> impl Iterator<Item = &HloComputation> {
+    self.computations.iter().flatten()
+  }
+
+  pub fn mutable_computations(&mut self) -> impl Iterator<Item = &mut HloComputation> {
+    self.generation += 1;
+    self.computations.iter_mut().flatten()
+  }
+
+  // Returns the ids of the computations in the order they were added.
+  pub fn computation_ids(&self) -> Vec<ComputationId> {
+    self.computations().map(|c| c.id().unwrap()).collect()
   }
 
-  pub fn mutable_computations_by_exec_threads(
-    &mut self, _execution_threads: &HashSet<String>) -> &mut Vec<HloComputation>
+  // Returns the computations that run on one of the given execution threads.
+  // An empty set means all threads.
+  pub fn computations_by_exec_threads(
+    &self, execution_threads: &HashSet<String>) -> Vec<ComputationId>
   {
-    unimplemented!()
+    self.computations()
+      .filter(|c| HloInstruction::is_thread_included(c.execution_thread(), execution_threads))
+      .map(|c| c.id().unwrap())
+      .collect()
   }
 
-  pub fn get_computation_with_name() {}
+  // Returns the computation with the given name, if any.
+  pub fn get_computation_with_name(&self, name: &str) -> Option<ComputationId> {
+    self.computations().find(|c| c.name() == name).map(|c| c.id().unwrap())
+  }
 
   // Gets the number of computations in this module.
   pub fn computation_count(&self) -> usize {
-    self.computations.len()
+    self.computations().count()
   }
 
   // Gets the number of instructions in this module.
   pub fn instruction_count(&self) -> usize {
     let mut n = 0;
-    for computation in &self.computations {
+    for computation in self.computations() {
       n += computation.instruction_count();
     }
     n
@@ -201,36 +416,83 @@ impl HloModule {
 
   // Deallocate removed instructions in this module.
   pub fn cleanup(&mut self) {
-    for computation in &mut self.computations {
+    for computation in self.mutable_computations() {
       computation.cleanup();
     }
   }
 
+  // Compute and return a post order of all computations in the module. The
+  // sort is defined like so: if computation A has an instruction which calls
+  // computation B, then A will appear after B in the sort.
   pub fn make_computation_post_order(
     &self,
-     _execution_threads: &HashSet<String>,
-     _dfs_post_order: bool) -> Vec<&mut HloComputation>
+    execution_threads: &HashSet<String>,
+    dfs_post_order: bool) -> Vec<ComputationId>
   {
-    unimplemented!()
+    let mut post_order = Vec::new();
+    let mut visited = HashSet::new();
+    let roots: Vec<ComputationId> = if dfs_post_order && self.has_entry_computation() {
+      // Start from the entry so that the order follows the call graph, then
+      // pick up any computation that is not reachable from it.
+      let mut roots = vec![self.entry_computation_id()];
+      roots.extend(self.computation_ids());
+      roots
+    } else {
+      self.computation_ids()
+    };
+    for root in roots {
+      self.compute_computation_post_order(root, &mut visited, &mut post_order);
+    }
+    post_order.into_iter()
+      .filter(|id| HloInstruction::is_thread_included(
+        self.computation(*id).execution_thread(), execution_threads))
+      .collect()
+  }
+
+  fn compute_computation_post_order(
+    &self,
+    root: ComputationId,
+    visited: &mut HashSet<ComputationId>,
+    post_order: &mut Vec<ComputationId>)
+  {
+    if visited.contains(&root) { return; }
+    let mut stack = vec![(root, false)];
+    while let Some((current, expanded)) = stack.pop() {
+      if expanded {
+        if visited.insert(current) {
+          post_order.push(current);
+        }
+        continue;
+      }
+      if visited.contains(&current) { continue; }
+      stack.push((current, true));
+      let mut callees = Vec::new();
+      for instruction in self.computation(current).instructions() {
+        for callee in instruction.called_computations() {
+          if !callees.contains(callee) { callees.push(*callee); }
+        }
+      }
+      for callee in callees.into_iter().rev() {
+        if !visited.contains(&callee) {
+          stack.push((callee, false));
+        }
+      }
+    }
   }
 
   pub fn make_computation_sorted() {}
 
   // Gets the computation in this module which aren't for fusion nodes.
-  pub fn make_nonfusion_computations_default(&self) -> &Vec<HloComputation> {
-    unimplemented!()
+  pub fn make_nonfusion_computations_default(&self) -> Vec<ComputationId> {
+    self.make_nonfusion_computations(&HashSet::new())
   }
 
   pub fn make_nonfusion_computations(
-    &self, _execution_threads: &HashSet<String>) -> &Vec<HloComputation>
-  {
-    unimplemented!()
-  }
-
-  pub fn make_mutable_nonfusion_computations(
-    &mut self, _execution_threads: &HashSet<String>) -> &mut Vec<HloComputation>
+    &self, execution_threads: &HashSet<String>) -> Vec<ComputationId>
   {
-    unimplemented!()
+    self.make_computation_post_order(execution_threads, false).into_iter()
+      .filter(|id| !self.computation(*id).is_fusion_computation())
+      .collect()
   }
 
   pub fn make_nonfusion_computations_sorted() {}
@@ -258,16 +520,121 @@ impl HloModule {
   }
 
   // Prints a string representation of the module.
-  pub fn print_default(printer: &dyn Printer) {
-    HloModule::print(printer, HloPrintOptions::default())
+  pub fn print_default(&self, printer: &mut dyn Printer) {
+    self.print(printer, &HloPrintOptions::default())
   }
 
-  pub fn print(_printer: &dyn Printer, _options: HloPrintOptions) {}
+  pub fn print(&self, printer: &mut dyn Printer, options: &HloPrintOptions) {
+    printer.append(&"HloModule ".to_string());
+    if options.print_ids() {
+      // When print_ids() is false, exclude module's name because it includes
+      // an id that would differ between otherwise identical modules.
+      printer.append(&self.name);
+    }
+    if self.has_schedule() {
+      printer.append(&", is_scheduled=true".to_string());
+    }
+    if self.config().alias_passthrough_params() {
+      printer.append(&", alias_passthrough_params=true".to_string());
+    }
+    if let Some(entry) = self.entry_computation() {
+      if entry.has_root_instruction() {
+        printer.append(&", entry_computation_layout={(".to_string());
+        for (i, param) in entry.parameter_instructions().iter().enumerate() {
+          if i > 0 {
+            printer.append(&", ".to_string());
+          }
+          ShapeUtil::print_human_string_with_layout(
+            printer, entry.instruction(*param).shape());
+        }
+        printer.append(&")->".to_string());
+        ShapeUtil::print_human_string_with_layout(
+          printer, entry.root_instruction().shape());
+        printer.append(&"}".to_string());
+      }
+    }
+    if self.config().replica_count() != 1 {
+      printer.append(&format!(", replica_count={}", self.config().replica_count()));
+    }
+    if self.config().num_partitions() != 1 {
+      printer.append(&format!(", num_partitions={}", self.config().num_partitions()));
+    }
+    if !self.frontend_attributes.map().is_empty() {
+      printer.append(&", frontend_attributes=".to_string());
+      printer.append(&frontend_attributes_to_string(&self.frontend_attributes));
+    }
+    printer.append(&"\n\n".to_string());
+
+    // Don't print the computations wrapped by syntax sugared async ops, they
+    // are printed as part of the async instructions.
+    let mut wrapped_computations = HashSet::new();
+    if options.syntax_sugar_async_ops() {
+      for computation in self.computations() {
+        let context = HloPrintContext::new(Some(computation), Some(self));
+        for instruction in computation.instructions() {
+          if instruction.opcode() != HloOpcode::AsyncStart {
+            continue;
+          }
+          if let Some(wrapped) =
+            instruction.syntax_sugar_async_computation(&context, options)
+          {
+            wrapped_computations.insert(wrapped.unique_id());
+          }
+        }
+      }
+    }
+
+    let order = self.make_computation_post_order(
+      &HashSet::new(), options.canonicalize_computations());
+    for id in order {
+      let computation = self.computation(id);
+      if wrapped_computations.contains(&computation.unique_id()) {
+        continue;
+      }
+      if self.has_entry_computation() && id == self.entry_computation_id() {
+        printer.append(&"ENTRY ".to_string());
+      }
+      computation.print(printer, Some(self), options);
+      printer.append(&"\n\n".to_string());
+    }
+  }
 
-  pub fn to_string(&self) -> String { "".to_string() }
+  pub fn to_string(&self) -> String {
+    self.to_string_with_options(&HloPrintOptions::default())
+  }
+
+  pub fn to_string_with_options(&self, options: &HloPrintOptions) -> String {
+    let mut printer = StringPrinter::new();
+    self.print(&mut printer, options);
+    printer.to_string()
+  }
   
   pub fn to_cord() {}
-  pub fn to_proto() {}
+  // Returns the HloModuleProto encoding of this module. Computations are
+  // emitted in post order, so that callees precede their callers.
+  pub fn to_proto(&self) -> Vec<u8> {
+    let mut output = vec![];
+    write_bytes_field(&mut output, MODULE_NAME, self.name.as_bytes());
+    if let Some(entry) = self.entry_computation() {
+      write_bytes_field(&mut output, MODULE_ENTRY_COMPUTATION_NAME,
+        entry.name().as_bytes());
+    }
+    for id in self.make_computation_post_order(&HashSet::new(), false) {
+      write_bytes_field(&mut output, MODULE_COMPUTATIONS,
+        &self.computation(id).to_proto());
+    }
+    if let Some(entry) = self.entry_computation() {
+      write_bytes_field(&mut output, MODULE_HOST_PROGRAM_SHAPE,
+        &program_shape_to_proto(&entry.compute_program_shape(false)));
+    }
+    write_varint_field(&mut output, MODULE_ID, self.unique_id as u64);
+    if let Some(entry) = self.entry_computation() {
+      write_varint_field(&mut output, MODULE_ENTRY_COMPUTATION_ID,
+        entry.unique_id() as u64);
+    }
+    output
+  }
+
   pub fn new_from_proto() {}
   pub fn to_proto_with_config() {}
   pub fn new_from_proto_with_config() {}
@@ -278,9 +645,7 @@ impl HloModule {
 
   // Assign a new unique dense id for an instruction.
   pub fn new_unique_instruction_id(&mut self) -> i64 {
-    let result = self.next_unique_id;
-    self.next_unique_id += 1;
-    result
+    self.next_unique_id.next()
   }
 
   // input_output_alias_config indicates the list of aliased buffers that are
@@ -289,6 +654,10 @@ impl HloModule {
     &self.input_output_alias_config
   }
 
+  pub fn mutable_input_output_alias_config(&mut self) -> &mut HloInputOutputAliasConfig {
+    &mut self.input_output_alias_config
+  }
+
   // buffer_donor_config indicates the set of input buffer donors that are
   // expected from the module.
   pub fn buffer_donor_config(&self) -> &HloBufferDonorConfig {
@@ -301,6 +670,13 @@ impl HloModule {
     self.unique_id
   }
 
+  // Returns a number that changes whenever the computations of the module may
+  // have changed, i.e. whenever one of them was handed out for mutation,
+  // added or removed.
+  pub fn generation(&self) -> i64 {
+    self.generation
+  }
+
   // Sets the schedule of the module to the given schedule.
   pub fn set_schedule(&mut self, schedule: HloSchedule) {
     self.schedule = Some(schedule);
@@ -327,6 +703,18 @@ impl HloModule {
     self.schedule.as_mut().unwrap()
   }
 
+  // Updates the schedule after instructions were added to or removed from
+  // the module, and verifies it. See HloSchedule::update.
+  pub fn update_schedule(
+    &mut self, execution_threads: &HashSet<String>) -> Result<(), String>
+  {
+    assert!(self.has_schedule());
+    let mut schedule = self.schedule.take().unwrap();
+    let result = schedule.update(self, execution_threads);
+    self.schedule = Some(schedule);
+    result
+  }
+
   pub fn add_computation_and_unify_names_and_ids() {}
   pub fn set_and_uniquify_instr_name() {}
   pub fn check_unique_names_and_ids_for_computations_and_instructions() {}
@@ -410,4 +798,4 @@ impl HloModule {
   pub fn comp_envs() {}
   pub fn get_fingerprint_128() {}
   pub fn get_stack_frame() {}
-}
\ No newline at end of file
+}
@@ -2,7 +2,7 @@
 #![allow(dead_code)]
 
 use std::collections::HashMap;
-use common::{blitz_data::Precision, shape::ProgramShape};
+use common::{blitz_data::{DebugOptions, Precision}, shape::ProgramShape};
 
 #[derive(Clone, PartialEq)]
 pub enum FusionConfigCollection {
@@ -11,7 +11,7 @@ pub enum FusionConfigCollection {
   PerNode,
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct HloModuleConfig {
   seed: u64,
   launch_id: i32,
@@ -37,6 +37,7 @@ pub struct HloModuleConfig {
   matrix_unit_operand_precision: Precision,
   fdo_profile: String,
   device_memory_size: i64,
+  debug_options: DebugOptions,
 }
 
 impl HloModuleConfig {
@@ -47,7 +48,33 @@ impl HloModuleConfig {
   // The layouts in the ProgramShape will be reset to default unless
   // ignore_layouts is set to false.
   pub fn new_default() -> Self {
-    unimplemented!()
+    HloModuleConfig {
+      seed: 0,
+      launch_id: 0,
+      replica_count: 1,
+      num_partitions: 1,
+      param_requires_broadcast_via_collectives: Vec::new(),
+      use_spmd_partitioning: false,
+      use_auto_spmd_partitioning: false,
+      auto_spmd_partitioning_mesh_shape: Vec::new(),
+      auto_spmd_partitioning_mesh_ids: Vec::new(),
+      deduplicate_hlo: false,
+      intra_op_parallelism_threads: -1,
+      device_type: "".to_string(),
+      allow_separate_sharding_programs: false,
+      alias_passthrough_params: false,
+      content_aware_computation_sorting: false,
+      fusion_config: Vec::new(),
+      layout_config: Vec::new(),
+      memory_space_assignment_config: Vec::new(),
+      phase_ordering_config: Vec::new(),
+      phase_index: 0,
+      analysis_allowance_map: HashMap::new(),
+      matrix_unit_operand_precision: Precision::Default,
+      fdo_profile: "".to_string(),
+      device_memory_size: 0,
+      debug_options: DebugOptions::new(),
+    }
   }
 
   pub fn new(_program_shape: &ProgramShape) -> Self {
@@ -160,8 +187,13 @@ impl HloModuleConfig {
   }
 
   pub fn compilation_cache_key() {}
-  pub fn debug_options() {}
-  pub fn set_debug_options() {}
+  pub fn debug_options(&self) -> &DebugOptions {
+    &self.debug_options
+  }
+
+  pub fn set_debug_options(&mut self, debug_options: DebugOptions) {
+    self.debug_options = debug_options;
+  }
 
   pub fn set_intra_op_parallelism_threads(&mut self, intra_op_parallelism_threads: i64) {
     self.intra_op_parallelism_threads = intra_op_parallelism_threads;
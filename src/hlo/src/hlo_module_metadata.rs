@@ -7,7 +7,9 @@ pub struct HloModuleMetadata {
 }
 
 impl HloModuleMetadata {
-  pub fn new() {}
+  pub fn new() -> Self {
+    HloModuleMetadata { next_pass_id: 1 }
+  }
   pub fn proto() {}
   pub fn record_pass_start() {}
   pub fn record_pass_end() {}
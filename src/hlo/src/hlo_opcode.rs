@@ -154,7 +154,7 @@ pub fn hlo_opcode_string(opcode: &HloOpcode) -> String {
     HloOpcode::Call => "call".to_string(),
     HloOpcode::Cbrt => "cbrt".to_string(),
     HloOpcode::Ceil => "ceil".to_string(),
-    HloOpcode::Cholsky => "cholsky".to_string(),
+    HloOpcode::Cholsky => "cholesky".to_string(),
     HloOpcode::Clamp => "clamp".to_string(),
     HloOpcode::Clz => "count-leading-zeros".to_string(),
     HloOpcode::CollectiveBroadcast => "collective-broadcast".to_string(),
@@ -207,7 +207,7 @@ pub fn hlo_opcode_string(opcode: &HloOpcode) -> String {
     HloOpcode::Pad => "pad".to_string(),
     HloOpcode::Parameter => "parameter".to_string(),
     HloOpcode::PartitionId => "partition-id".to_string(),
-    HloOpcode::PopulationCount => "pocnt".to_string(),
+    HloOpcode::PopulationCount => "popcnt".to_string(),
     HloOpcode::Power => "power".to_string(),
     HloOpcode::RaggedAllToAll => "ragged-all-to-all".to_string(),
     HloOpcode::Real => "real".to_string(),
@@ -306,7 +306,7 @@ pub fn string_to_hlo_opcode(name: &String) -> Result<HloOpcode, String> {
     return Ok(HloOpcode::Cbrt);
   } else if name == "ceil" {
     return Ok(HloOpcode::Ceil);
-  } else if name == "cholsky" {
+  } else if name == "cholesky" {
     return Ok(HloOpcode::Cholsky);
   } else if name == "clamp" {
     return Ok(HloOpcode::Clamp);
@@ -412,7 +412,7 @@ pub fn string_to_hlo_opcode(name: &String) -> Result<HloOpcode, String> {
     return Ok(HloOpcode::Parameter);
   } else if name == "partition-id" {
     return Ok(HloOpcode::PartitionId);
-  } else if name == "pocnt" {
+  } else if name == "popcnt" {
     return Ok(HloOpcode::PopulationCount);
   } else if name == "power" {
     return Ok(HloOpcode::Power);
@@ -478,6 +478,8 @@ pub fn string_to_hlo_opcode(name: &String) -> Result<HloOpcode, String> {
     return Ok(HloOpcode::Slice);
   } else if name == "sort" {
     return Ok(HloOpcode::Sort);
+  } else if name == "sqrt" {
+    return Ok(HloOpcode::Sqrt);
   } else if name == "stochastic-convert" {
     return Ok(HloOpcode::StochasticConvert);
   } else if name == "subtract" {
@@ -541,8 +543,110 @@ pub fn hlo_opcode_is_variadic(opcode: &HloOpcode) -> bool {
 }
 
 // Returns the arity of opcode or nullopt for variadic opcodes.
-pub fn hlo_opcode_arity(_opcode: &HloOpcode) -> Option<i8> {
-  unimplemented!()
+pub fn hlo_opcode_arity(opcode: &HloOpcode) -> Option<i8> {
+  if hlo_opcode_is_variadic(opcode) {
+    return None;
+  }
+  match opcode {
+    HloOpcode::Constant => Some(0),
+    HloOpcode::Iota => Some(0),
+    HloOpcode::Parameter => Some(0),
+    HloOpcode::PartitionId => Some(0),
+    HloOpcode::ReplicaId => Some(0),
+    HloOpcode::RngGetAndUpdateState => Some(0),
+    HloOpcode::Infeed => Some(1),
+    HloOpcode::Abs => Some(1),
+    HloOpcode::AllGatherDone => Some(1),
+    HloOpcode::AllReduceDone => Some(1),
+    HloOpcode::AsyncDone => Some(1),
+    HloOpcode::AsyncUpdate => Some(1),
+    HloOpcode::Bitcast => Some(1),
+    HloOpcode::BitcastConvert => Some(1),
+    HloOpcode::Broadcast => Some(1),
+    HloOpcode::Cbrt => Some(1),
+    HloOpcode::Ceil => Some(1),
+    HloOpcode::Cholsky => Some(1),
+    HloOpcode::Clz => Some(1),
+    HloOpcode::CollectiveBroadcast => Some(1),
+    HloOpcode::CollectivePermuteDone => Some(1),
+    HloOpcode::Convert => Some(1),
+    HloOpcode::Copy => Some(1),
+    HloOpcode::CopyDone => Some(1),
+    HloOpcode::CopyStart => Some(1),
+    HloOpcode::Cos => Some(1),
+    HloOpcode::Domain => Some(1),
+    HloOpcode::Erf => Some(1),
+    HloOpcode::Exp => Some(1),
+    HloOpcode::Expm1 => Some(1),
+    HloOpcode::Fft => Some(1),
+    HloOpcode::Floor => Some(1),
+    HloOpcode::GetDimensionSize => Some(1),
+    HloOpcode::GetTupleElement => Some(1),
+    HloOpcode::Imag => Some(1),
+    HloOpcode::IsFinite => Some(1),
+    HloOpcode::Log => Some(1),
+    HloOpcode::Log1p => Some(1),
+    HloOpcode::Logistic => Some(1),
+    HloOpcode::Negate => Some(1),
+    HloOpcode::Not => Some(1),
+    HloOpcode::OptimizationBarrier => Some(1),
+    HloOpcode::PopulationCount => Some(1),
+    HloOpcode::Real => Some(1),
+    HloOpcode::Recv => Some(1),
+    HloOpcode::RecvDone => Some(1),
+    HloOpcode::ReducePrecision => Some(1),
+    HloOpcode::Reshape => Some(1),
+    HloOpcode::Reverse => Some(1),
+    HloOpcode::RngBitGenerator => Some(1),
+    HloOpcode::RoundNearestAfz => Some(1),
+    HloOpcode::RoundNearestEven => Some(1),
+    HloOpcode::Rsqrt => Some(1),
+    HloOpcode::SendDone => Some(1),
+    HloOpcode::Sign => Some(1),
+    HloOpcode::Sin => Some(1),
+    HloOpcode::Slice => Some(1),
+    HloOpcode::Sqrt => Some(1),
+    HloOpcode::Tan => Some(1),
+    HloOpcode::Tanh => Some(1),
+    HloOpcode::TopK => Some(1),
+    HloOpcode::Transpose => Some(1),
+    HloOpcode::While => Some(1),
+    HloOpcode::Add => Some(2),
+    HloOpcode::AddDependency => Some(2),
+    HloOpcode::And => Some(2),
+    HloOpcode::Atan2 => Some(2),
+    HloOpcode::Compare => Some(2),
+    HloOpcode::Complex => Some(2),
+    HloOpcode::Convolution => Some(2),
+    HloOpcode::Divide => Some(2),
+    HloOpcode::Dot => Some(2),
+    HloOpcode::Gather => Some(2),
+    HloOpcode::Maximum => Some(2),
+    HloOpcode::Minimum => Some(2),
+    HloOpcode::Multiply => Some(2),
+    HloOpcode::Or => Some(2),
+    HloOpcode::Outfeed => Some(2),
+    HloOpcode::Pad => Some(2),
+    HloOpcode::Power => Some(2),
+    HloOpcode::Remainder => Some(2),
+    HloOpcode::Send => Some(2),
+    HloOpcode::ShiftLeft => Some(2),
+    HloOpcode::ShiftRightArithmetic => Some(2),
+    HloOpcode::ShiftRightLogical => Some(2),
+    HloOpcode::StochasticConvert => Some(2),
+    HloOpcode::Subtract => Some(2),
+    HloOpcode::TriangularSolve => Some(2),
+    HloOpcode::Xor => Some(2),
+    HloOpcode::SetDimensionSize => Some(2),
+    HloOpcode::Clamp => Some(3),
+    HloOpcode::BatchNormTraining => Some(3),
+    HloOpcode::Select => Some(3),
+    HloOpcode::SelectAndScatter => Some(3),
+    HloOpcode::BatchNormGrad => Some(5),
+    HloOpcode::BatchNormInference => Some(5),
+    HloOpcode::RaggedAllToAll => Some(6),
+    _ => None
+  }
 }
 
 // Returns true for kAsyncStart, kAsyncUpdate, kAsyncDone.
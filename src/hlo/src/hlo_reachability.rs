@@ -1,8 +1,11 @@
 #![allow(dead_code)]
 
-use std::{collections::HashMap, ops::BitOrAssign};
+use std::{collections::{HashMap, VecDeque}, ops::BitOrAssign};
 
-use crate::{hlo_computation::HloComputation, hlo_instruction::HloInstruction};
+use crate::{
+  hlo_computation::HloComputation,
+  hlo_instruction::{HloInstruction, InstructionId}
+};
 
 #[derive(Debug, Clone, PartialEq)]
 struct BitSet {
@@ -39,8 +42,9 @@ impl BitSet {
 }
 
 // Sets this bit-set to union of this bit-set and 'rhs'.
-impl BitOrAssign for BitSet {
-  fn bitor_assign(&mut self, rhs: Self) {
+impl BitOrAssign<&BitSet> for BitSet {
+  fn bitor_assign(&mut self, rhs: &BitSet) {
+    debug_assert_eq!(self.size, rhs.size);
     for i in 0..self.vector.len() {
       self.vector[i] |= rhs.vector[i];
     }
@@ -48,6 +52,12 @@ impl BitOrAssign for BitSet {
 }
 
 // A class for representing reachability between HloInstructions.
+//
+// It has an adjacency matrix and it is up to the user of the class to set the
+// adjacency matrix such that it represents reachability, i.e. such that it is
+// transitive. That the graph be transitive is thus not an invariant of this
+// class, but it is required for the name of the class and its methods to make
+// sense.
 pub struct HloReachabilityMap {
   indices: HashMap<(i64, i64), usize>,
   bit_sets: Vec<BitSet>,
@@ -55,32 +65,70 @@ pub struct HloReachabilityMap {
 }
 
 impl HloReachabilityMap {
-  pub fn new(instructions: Vec<HloInstruction>) -> Self {
+  // Sets up a graph with no edges and where the nodes correspond to the given
+  // instructions.
+  pub fn new(instructions: &[&HloInstruction]) -> Self {
     let mut instance = HloReachabilityMap {
       indices: HashMap::new(),
       bit_sets: vec![BitSet::new(instructions.len()); instructions.len()],
-      tmp_bit_set: BitSet::new(0)
+      tmp_bit_set: BitSet::new(instructions.len())
     };
-    for i in 0..instructions.len() {
+    for (i, instruction) in instructions.iter().enumerate() {
       instance.bit_sets[i].set(i);
-      instance.indices.insert(
-        HloReachabilityMap::get_key(instructions.get(i).as_ref().unwrap()),
-        i);
+      instance.indices.insert(HloReachabilityMap::get_key(instruction), i);
     }
     instance
   }
 
-  pub fn build(_computation: &HloComputation) -> Self {
-    unimplemented!()
+  // Computes and returns the reachability between HLO instructions in the
+  // computation. The returned map contains exactly the instructions of the
+  // computation, and an instruction reaches another if there is a path of
+  // operand or control edges between them.
+  pub fn build(computation: &HloComputation) -> Self {
+    HloReachabilityMap::build_with_restrictions(
+      computation,
+      |instruction: &HloInstruction, inputs: &mut Vec<InstructionId>| {
+        inputs.extend(instruction.operands());
+        inputs.extend(instruction.control_predecessors());
+      })
   }
 
-  pub fn build_with_restrictions() {}
+  // Similar to the above, but the dependencies of an instruction are given by
+  // 'add_dependencies', which appends the inputs of the instruction to the
+  // vector. This allows to ignore some of the edges of the graph.
+  pub fn build_with_restrictions<F>(
+    computation: &HloComputation, add_dependencies: F) -> Self
+    where F: Fn(&HloInstruction, &mut Vec<InstructionId>)
+  {
+    let post_order = computation.make_instruction_post_order();
+    let instructions: Vec<&HloInstruction> = post_order.iter()
+      .map(|id| computation.instruction(*id))
+      .collect();
+    let mut result = HloReachabilityMap::new(&instructions);
+
+    // Inputs precede their users in the post order, so a single pass computes
+    // the transitive closure.
+    let mut inputs = vec![];
+    for instruction in &instructions {
+      inputs.clear();
+      add_dependencies(instruction, &mut inputs);
+      let input_indices: Vec<usize> = inputs.iter()
+        .map(|id| result.get_index(computation.instruction(*id)))
+        .collect();
+      let index = result.get_index(instruction);
+      result.fast_set_rachability_to_union_by_index(&input_indices, index);
+    }
+    result
+  }
 
   // Set the reachability set of 'instruction' to the union of the reachability
-  // sets of 'inputs'.
+  // sets of 'inputs'. Upon return, is_reachable(x, instruction) is true if
+  // is_reachable(x, input) is true for some input in 'inputs'. Also sets
+  // 'instruction' as reachable from itself. Returns whether the reachability
+  // set of 'instruction' changed.
   pub fn set_reachability_to_union(
     &mut self,
-    inputs: Vec<HloInstruction>,
+    inputs: &[&HloInstruction],
     instruction: &HloInstruction) -> bool
   {
     let index = self.get_index(instruction);
@@ -92,7 +140,7 @@ impl HloReachabilityMap {
   // As above, but faster because it does not check if the reachability changed.
   pub fn fast_set_rachability_to_union(
     &mut self,
-    inputs: Vec<HloInstruction>,
+    inputs: &[&HloInstruction],
     instruction: &HloInstruction)
   {
     self.set_reachability_to_union_helper(inputs, self.get_index(instruction));
@@ -102,20 +150,17 @@ impl HloReachabilityMap {
   // faster since no hash map lookup will occur.
   pub fn fast_set_rachability_to_union_by_index(
     &mut self,
-    _input_indices: &Vec<usize>,
-    _index: usize)
+    input_indices: &[usize],
+    index: usize)
   {
-    unimplemented!()    
+    self.set_reachability_to_union_helper_by_indices(input_indices, index);
   }
 
   pub fn get_index(&self, instruction: &HloInstruction) -> usize {
-    let keys = self.indices.keys();
-    let mut index = 0;
-    for i in keys {
-      if *i == HloReachabilityMap::get_key(instruction) { return index; }
-      index += 1;
+    match self.indices.get(&HloReachabilityMap::get_key(instruction)) {
+      Some(index) => *index,
+      None => unreachable!("{} is not in the reachability map.", instruction.name())
     }
-    unreachable!("Key is not exist.");
   }
 
   // Sets entry so that is_reachable(a, b) will reeturn true.
@@ -127,7 +172,30 @@ impl HloReachabilityMap {
     self.bit_sets[b].set(a);
   }
 
-  pub fn update_reachability_through_instruction() {}
+  // Updates the given reachability map after the immediate predecessor set
+  // (operands and control predecessors) of 'instruction' has changed, e.g.
+  // after a fusion or a replacement of one of its operands. The change is
+  // propagated to the users and control successors as long as their
+  // reachability sets keep changing.
+  pub fn update_reachability_through_instruction(
+    &mut self, computation: &HloComputation, instruction: InstructionId)
+  {
+    let mut worklist = VecDeque::new();
+    worklist.push_back(instruction);
+
+    while let Some(item) = worklist.pop_front() {
+      let item_instruction = computation.instruction(item);
+      let inputs: Vec<&HloInstruction> = item_instruction.operands().iter()
+        .chain(item_instruction.control_predecessors())
+        .map(|id| computation.instruction(*id))
+        .collect();
+      if self.set_reachability_to_union(&inputs, item_instruction) {
+        // Add immediate successors to worklist.
+        worklist.extend(item_instruction.users());
+        worklist.extend(item_instruction.control_successors());
+      }
+    }
+  }
 
   // Returns true if 'b' is reachable from 'a'.
   pub fn is_reachable(&self, a: &HloInstruction, b: &HloInstruction) -> bool {
@@ -157,50 +225,176 @@ impl HloReachabilityMap {
     if HloReachabilityMap::get_key(original) !=
        HloReachabilityMap::get_key(replacement)
     {
-      self.indices.insert(HloReachabilityMap::get_key(replacement),
-        self.get_index(original));
+      let index = self.get_index(original);
+      self.indices.insert(HloReachabilityMap::get_key(replacement), index);
+      self.indices.remove(&HloReachabilityMap::get_key(original));
     }
   }
 
   fn get_key(instruction: &HloInstruction) -> (i64, i64) {
-    (instruction.get_module().as_ref().unwrap().unique_id(), instruction.unique_id())
+    let computation = instruction.parent().map_or(-1, |c| c.index() as i64);
+    (computation, instruction.id().index() as i64)
   }
 
   fn set_reachability_to_union_helper(
     &mut self,
-    inputs: Vec<HloInstruction>,
+    inputs: &[&HloInstruction],
     index: usize)
   {
-    let mut input_indices = Vec::new();
-    input_indices.reserve(inputs.len());
-    for input in &inputs {
-      input_indices.push(self.get_index(input));
-    }
-    self.set_reachability_to_union_helper_by_indices(input_indices, index)
+    let input_indices: Vec<usize> = inputs.iter()
+      .map(|input| self.get_index(input))
+      .collect();
+    self.set_reachability_to_union_helper_by_indices(&input_indices, index)
   }
 
   fn set_reachability_to_union_helper_by_indices(
     &mut self,
-    input_indices: Vec<usize>,
+    input_indices: &[usize],
     index: usize)
   {
-    let mut found = false;
-    for i in &input_indices {
-      if *i == index {
-        found = true;
-      }    
-    }
-    if !found { self.bit_sets[index].set_to_zero(); }
-    self.bit_sets[index].set(index);
+    // Take the bit set out of the vector so the inputs can be read while it
+    // is updated.
+    let mut bit_set =
+      std::mem::replace(&mut self.bit_sets[index], BitSet::new(0));
+    // If instruction is part of inputs, don't reset the bit set.
+    if !input_indices.contains(&index) { bit_set.set_to_zero(); }
+    bit_set.set(index);
 
-    for i in &input_indices {
+    for i in input_indices {
       if *i != index {
-        //self.bit_sets[index] |= self.bit_sets[*i];
+        bit_set |= &self.bit_sets[*i];
       }
     }
+    self.bit_sets[index] = bit_set;
+  }
+
+  // Makes every instruction reachable from itself only.
+  pub fn reset(&mut self) {
+    for (i, bit_set) in self.bit_sets.iter_mut().enumerate() {
+      bit_set.set_to_zero();
+      bit_set.set(i);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{hlo_opcode::HloOpcode, test_utils::{add_unary, r0f32}};
+
+  // Builds `add = add(negate(p0), exp(p0))` with `add` as the root, and an
+  // unrelated `copy = copy(p1)`.
+  fn make_diamond(computation: &mut HloComputation)
+    -> (InstructionId, InstructionId, InstructionId, InstructionId, InstructionId)
+  {
+    let p0 = computation.add_parameter(
+      HloInstruction::create_parameter(0, &r0f32(), "p0".to_string()));
+    let p1 = computation.add_parameter(
+      HloInstruction::create_parameter(1, &r0f32(), "p1".to_string()));
+    let negate = add_unary(computation, HloOpcode::Negate, p0, "negate");
+    let exp = add_unary(computation, HloOpcode::Exp, p0, "exp");
+    let add = computation.add_instruction(HloInstruction::create_binary(
+      &r0f32(), HloOpcode::Add, computation.instruction(negate),
+      computation.instruction(exp)), "".to_string());
+    computation.set_root_instruction(add, false);
+    let copy = add_unary(computation, HloOpcode::Copy, p1, "copy");
+    (p0, negate, exp, add, copy)
+  }
+
+  #[test]
+  fn test_build() {
+    let mut computation = HloComputation::new("test".to_string());
+    let (p0, negate, exp, add, copy) = make_diamond(&mut computation);
+    let map = HloReachabilityMap::build(&computation);
+    let instr = |id| computation.instruction(id);
+
+    assert!(map.is_reachable(instr(p0), instr(p0)));
+    assert!(map.is_reachable(instr(p0), instr(negate)));
+    assert!(map.is_reachable(instr(p0), instr(add)));
+    assert!(map.is_reachable(instr(exp), instr(add)));
+    assert!(!map.is_reachable(instr(add), instr(p0)));
+    assert!(!map.is_reachable(instr(negate), instr(exp)));
+    assert!(!map.is_connected(instr(negate), instr(exp)));
+    assert!(!map.is_connected(instr(copy), instr(add)));
+    assert!(map.is_connected(instr(add), instr(p0)));
+    assert!(map.is_present(instr(copy)));
+  }
+
+  #[test]
+  fn test_build_with_control_predecessors() {
+    let mut computation = HloComputation::new("test".to_string());
+    let (p0, negate, exp, add, copy) = make_diamond(&mut computation);
+    assert!(computation.add_control_dependency_to(negate, exp).is_ok());
+    assert!(computation.add_control_dependency_to(copy, p0).is_ok());
+    let map = HloReachabilityMap::build(&computation);
+    let instr = |id| computation.instruction(id);
+
+    assert!(map.is_reachable(instr(negate), instr(exp)));
+    assert!(!map.is_reachable(instr(exp), instr(negate)));
+    assert!(map.is_reachable(instr(copy), instr(add)));
   }
 
-  pub fn reset(&self) {
-    unimplemented!()
+  #[test]
+  fn test_build_with_restrictions() {
+    let mut computation = HloComputation::new("test".to_string());
+    let (p0, negate, exp, add, _copy) = make_diamond(&mut computation);
+    assert!(computation.add_control_dependency_to(negate, exp).is_ok());
+    // Only data dependencies.
+    let map = HloReachabilityMap::build_with_restrictions(
+      &computation,
+      |instruction: &HloInstruction, inputs: &mut Vec<InstructionId>| {
+        inputs.extend(instruction.operands());
+      });
+    let instr = |id| computation.instruction(id);
+
+    assert!(!map.is_reachable(instr(negate), instr(exp)));
+    assert!(map.is_reachable(instr(p0), instr(add)));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_set_reachability_to_union() {
+    let mut computation = HloComputation::new("test".to_string());
+    let (p0, negate, _exp, _add, copy) = make_diamond(&mut computation);
+    let mut map = HloReachabilityMap::build(&computation);
+    let instr = |id| computation.instruction(id);
+
+    assert!(!map.set_reachability_to_union(&[instr(p0)], instr(negate)));
+    assert!(map.set_reachability_to_union(&[instr(copy)], instr(negate)));
+    assert!(map.is_reachable(instr(copy), instr(negate)));
+    assert!(!map.is_reachable(instr(p0), instr(negate)));
+
+    map.reset();
+    assert!(!map.is_reachable(instr(copy), instr(negate)));
+    assert!(map.is_reachable(instr(negate), instr(negate)));
+  }
+
+  #[test]
+  fn test_update_reachability_through_instruction() {
+    let mut computation = HloComputation::new("test".to_string());
+    let (_p0, negate, exp, add, copy) = make_diamond(&mut computation);
+    let mut map = HloReachabilityMap::build(&computation);
+
+    // negate = negate(copy) makes copy reach negate and add.
+    assert!(computation.replace_operand_with(negate, 0, copy).is_ok());
+    map.update_reachability_through_instruction(&computation, negate);
+    let instr = |id| computation.instruction(id);
+    assert!(map.is_reachable(instr(copy), instr(negate)));
+    assert!(map.is_reachable(instr(copy), instr(add)));
+    assert!(!map.is_reachable(instr(copy), instr(exp)));
+  }
+
+  #[test]
+  fn test_replace() {
+    let mut computation = HloComputation::new("test".to_string());
+    let (p0, negate, _exp, add, _copy) = make_diamond(&mut computation);
+    let mut map = HloReachabilityMap::build(&computation);
+    let abs = add_unary(&mut computation, HloOpcode::Abs, p0, "abs");
+    let instr = |id| computation.instruction(id);
+
+    assert!(!map.is_present(instr(abs)));
+    map.replace(instr(negate), instr(abs));
+    assert!(map.is_present(instr(abs)));
+    assert!(!map.is_present(instr(negate)));
+    assert!(map.is_reachable(instr(abs), instr(add)));
+  }
+}
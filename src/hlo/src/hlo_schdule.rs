@@ -1,8 +1,12 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
-use crate::{hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_module::HloModule};
+use crate::{
+  hlo_computation::HloComputation,
+  hlo_instruction::{HloInstruction, InstructionId, MAIN_EXECUTION_THREAD},
+  hlo_module::HloModule
+};
 
 #[derive(PartialEq)]
 pub struct HloInstructionSequence {
@@ -129,7 +133,7 @@ impl HloSchedule {
   {
     if !self.sequences.contains_key(&computation.unique_id()) {
       // No sequence found for computation. Create and return an empty one.
-      debug_assert!(computation.parent().as_ref().unwrap() == module);
+      debug_assert!(computation.id().is_some_and(|id| module.contains_computation(id)));
       self.execution_threads.insert(computation.unique_id(), computation.execution_thread());
       let sequence = HloInstructionSequence::new();
       self.sequences.insert(computation.unique_id(), sequence);
@@ -145,7 +149,7 @@ impl HloSchedule {
   {
     if !self.sequences.contains_key(&computation.unique_id()) {
       // No sequence found for computation. Create and return an empty one.
-      debug_assert!(computation.parent().as_ref().unwrap() == module);
+      debug_assert!(computation.id().is_some_and(|id| module.contains_computation(id)));
       self.execution_threads.insert(computation.unique_id(), computation.execution_thread());
       let sequence = HloInstructionSequence::new();
       self.sequences.insert(computation.unique_id(), sequence);
@@ -181,7 +185,15 @@ impl HloSchedule {
     &self.sequences
   }
 
-  pub fn num_sequences_by_execution_thread() {}
+  // Returns the number of sequences per execution thread.
+  pub fn num_sequences_by_execution_thread(&self) -> HashMap<String, usize> {
+    let mut sequence_num_by_execution_threads = HashMap::new();
+    for id in self.sequences.keys() {
+      *sequence_num_by_execution_threads
+        .entry(self.execution_thread_of(*id)).or_insert(0) += 1;
+    }
+    sequence_num_by_execution_threads
+  }
 
   // Returns true if the schedule has a sequence for the given computation.
   pub fn is_computation_scheduled(&self, computation: &HloComputation) -> bool {
@@ -220,11 +232,160 @@ impl HloSchedule {
     sequence.as_mut().unwrap().replace_instruction(old, new);
   }
 
-  pub fn update() {}
-  pub fn verify() {}
+  // Updates the schedule such that it is (again) a valid schedule for the
+  // module. This is used to update a schedule after the HLO module has been
+  // transformed in some way. In general, the only transformations to the
+  // module for which a schedule can be updated is the addition or removal of
+  // instructions and removal of computations. Updating the schedule after new
+  // dependencies between existing instructions in the module is not
+  // supported and may result in an error status returned.
+  //
+  // Instructions in the module which also exist in the given schedule will
+  // remain in the same order in the updated schedule. Instructions which exist
+  // in the module but not in the given schedule will be placed as early as
+  // possible in the updated schedule.
+  pub fn update(
+    &mut self,
+    module: &HloModule,
+    execution_threads: &HashSet<String>) -> Result<(), String>
+  {
+    // The schedule must contain a sequence for every non-fusion computation
+    // in the module for the specified threads, but can have sequences for
+    // computations which no longer exist (these are removed).
+    let nonfusion_computations = module.make_nonfusion_computations(execution_threads);
+    for id in &nonfusion_computations {
+      let computation = module.computation(*id);
+      if !self.is_computation_scheduled(computation) {
+        self.get_or_create_sequence(module, computation);
+        self.update_computation_schedule(computation)?;
+      }
+    }
+
+    if self.sum_of_sequences_for_threads(execution_threads) > nonfusion_computations.len() {
+      // Schedule contains some computations which have been removed from the
+      // module. Remove them from the schedule as well.
+      let nonfusion_computations_ids: HashSet<i64> = nonfusion_computations.iter()
+        .map(|id| module.computation(*id).unique_id())
+        .collect();
+      let removed: Vec<i64> = self.sequences.keys()
+        .filter(|id| !nonfusion_computations_ids.contains(id) &&
+          HloInstruction::is_thread_included(self.execution_thread_of(**id), execution_threads))
+        .cloned()
+        .collect();
+      for id in removed {
+        self.sequences.remove(&id);
+        self.execution_threads.remove(&id);
+      }
+    }
+    let sequence_sum = self.sum_of_sequences_for_threads(execution_threads);
+    if sequence_sum != nonfusion_computations.len() {
+      return Err(format!(
+        "Schedule has {} sequences, but module has {} non-fusion computations.",
+        sequence_sum, nonfusion_computations.len()));
+    }
 
-  pub fn to_string(&self) -> String {
-    unimplemented!()
+    for id in &nonfusion_computations {
+      self.update_computation_schedule(module.computation(*id))?;
+    }
+    self.verify(module)
+  }
+
+  // Verifies that the given schedule is valid for the given module.
+  // Specifically, the schedule contains exactly the instructions in the
+  // non-fusion computations in the module and every dependency in the module
+  // is satisfied in the schedule.
+  pub fn verify(&self, module: &HloModule) -> Result<(), String> {
+    // Verify schedule contains exactly the same set of non-fusion computations
+    // as module currently does for each thread that has schedule.
+    for (thread_name, sequence_size) in self.num_sequences_by_execution_thread() {
+      let threads = HashSet::from([thread_name.clone()]);
+      let nonfusion_computations = module.make_nonfusion_computations(&threads);
+      if nonfusion_computations.len() != sequence_size {
+        return Err(format!(
+          "For thread {}, schedule has {} sequences, but module has {} non-fusion computations for thread {}",
+          thread_name, sequence_size, nonfusion_computations.len(), thread_name));
+      }
+      for id in &nonfusion_computations {
+        let computation = module.computation(*id);
+        if !self.is_computation_scheduled(computation) {
+          return Err(format!(
+            "Computation {} missing from HLO schedule.", computation.name()));
+        }
+      }
+
+      // For each computation verify the set of instructions is the same and
+      // that each dependency and control edge is honored.
+      for id in &nonfusion_computations {
+        let computation = module.computation(*id);
+        let mut instruction_position = HashMap::new();
+        for (position, instruction) in
+          self.sequence(computation).unwrap().instructions().iter().enumerate()
+        {
+          if instruction_position.insert(instruction.unique_id(), position).is_some() {
+            return Err(format!(
+              "Instruction {} appears more than once in the schedule",
+              instruction.name()));
+          }
+        }
+        if instruction_position.len() != computation.instruction_count() {
+          return Err(format!(
+            "Schedule for computation {} has {} instructions, expected {}",
+            computation.name(), instruction_position.len(),
+            computation.instruction_count()));
+        }
+        for instruction in computation.instructions() {
+          if !instruction_position.contains_key(&instruction.unique_id()) {
+            return Err(format!(
+              "Instruction {} is not in schedule", instruction.name()));
+          }
+        }
+        for instruction in computation.instructions() {
+          let position = instruction_position[&instruction.unique_id()];
+          for operand in instruction.operands() {
+            let operand = computation.instruction(*operand);
+            if instruction_position[&operand.unique_id()] >= position {
+              return Err(format!(
+                "Instruction {} is not scheduled after its operand {}",
+                instruction.name(), operand.name()));
+            }
+          }
+          for pred in instruction.control_predecessors() {
+            let pred = computation.instruction(*pred);
+            if instruction_position[&pred.unique_id()] >= position {
+              return Err(format!(
+                "Instruction {} is not scheduled after its control predecessor {}",
+                instruction.name(), pred.name()));
+            }
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  pub fn to_string(&self, module: &HloModule) -> String {
+    let mut pieces = vec!["HloSchedule".to_string()];
+    let mut sorted_ids: Vec<i64> = self.sequences.keys().cloned().collect();
+    sorted_ids.sort();
+    for id in sorted_ids {
+      let sequence = &self.sequences[&id];
+      match module.computations().find(|c| c.unique_id() == id) {
+        Some(computation) => {
+          pieces.push(format!("computation {}:", computation.name()));
+          for instruction in sequence.instructions() {
+            pieces.push(format!("  {}", instruction.name()));
+          }
+        },
+        None => {
+          pieces.push(format!(
+            "computation with id {} (no longer in HLO module):", id));
+          for instruction_id in sequence.ids() {
+            pieces.push(format!("  {}", instruction_id));
+          }
+        }
+      }
+    }
+    pieces.join("\n")
   }
 
   pub fn empty(&self) -> bool {
@@ -235,4 +396,279 @@ impl HloSchedule {
     //&self.module.as_ref()
     unimplemented!()
   }
-}
\ No newline at end of file
+
+  fn execution_thread_of(&self, computation_id: i64) -> String {
+    self.execution_threads.get(&computation_id)
+      .cloned()
+      .unwrap_or_else(|| MAIN_EXECUTION_THREAD.to_string())
+  }
+
+  fn sum_of_sequences_for_threads(&self, execution_threads: &HashSet<String>) -> usize {
+    if execution_threads.is_empty() {
+      return self.sequences.len();
+    }
+    self.num_sequences_by_execution_thread().iter()
+      .filter(|(thread_name, _)| execution_threads.contains(*thread_name))
+      .map(|(_, sequence_num)| *sequence_num)
+      .sum()
+  }
+
+  // Updates the instruction sequence for the given computation.
+  fn update_computation_schedule(
+    &mut self, computation: &HloComputation) -> Result<(), String>
+  {
+    // Set of all instructions in the schedule.
+    let mut ids_in_schedule = HashSet::new();
+    for id in self.sequences[&computation.unique_id()].ids() {
+      if !ids_in_schedule.insert(*id) {
+        return Err(format!("Instruction {} appears more than once in the schedule of {}.",
+          id, computation.name()));
+      }
+    }
+
+    // Map from instruction X to newly added instructions (instruction is in
+    // computation, but not in schedule) which use X, as an operand or as a
+    // control predecessor. If an instruction is not in the map, then it has
+    // no users which are newly added instructions.
+    let mut new_instruction_uses: HashMap<InstructionId, Vec<InstructionId>> = HashMap::new();
+
+    // For each newly added instruction, this is the count of the
+    // instruction's predecessors that have not yet been scheduled. When this
+    // value reaches zero, then the instruction may be placed in the schedule.
+    let mut unscheduled_operand_count: HashMap<InstructionId, usize> = HashMap::new();
+
+    // Create a worklist of newly added instructions which are ready to be
+    // added to the schedule. Initialize worklist with those that have zero
+    // predecessors.
+    let mut worklist = VecDeque::new();
+    for instruction in computation.instructions() {
+      if ids_in_schedule.contains(&instruction.unique_id()) {
+        continue;
+      }
+      // This is a newly added instruction which is not in the schedule.
+      let predecessors: Vec<InstructionId> = instruction.operands().iter()
+        .chain(instruction.control_predecessors())
+        .cloned()
+        .collect();
+      if predecessors.is_empty() {
+        worklist.push_back(instruction.id());
+      } else {
+        for predecessor in &predecessors {
+          new_instruction_uses.entry(*predecessor).or_default().push(instruction.id());
+        }
+        unscheduled_operand_count.insert(instruction.id(), predecessors.len());
+      }
+    }
+
+    // Update the schedule with the newly added instructions, and remove any
+    // instructions no longer in the graph.
+    let mut new_sequence = HloInstructionSequence::new();
+
+    // Schedules all instructions on the worklist.
+    let mut schedule_worklist = |worklist: &mut VecDeque<InstructionId>| {
+      while let Some(id) = worklist.pop_front() {
+        new_sequence.push_pack(computation.instruction(id).clone());
+        if let Some(new_users) = new_instruction_uses.get(&id) {
+          // This just-scheduled instruction has users which are newly added
+          // to the module. Update the number of unscheduled predecessors and
+          // push the newly added instruction to the worklist if it is ready
+          // to schedule.
+          for new_user in new_users {
+            let count = unscheduled_operand_count.get_mut(new_user).unwrap();
+            *count -= 1;
+            if *count == 0 {
+              worklist.push_back(*new_user);
+            }
+          }
+        }
+      }
+    };
+
+    schedule_worklist(&mut worklist);
+    for unique_id in self.sequences[&computation.unique_id()].ids() {
+      let id = match computation.instruction_with_unique_id(*unique_id) {
+        Some(id) => id,
+        // This instruction in the schedule is no longer in the module. Do not
+        // add it to the new schedule.
+        None => continue
+      };
+      worklist.push_back(id);
+      schedule_worklist(&mut worklist);
+    }
+
+    if new_sequence.size() != computation.instruction_count() {
+      return Err(format!(
+        "Cannot update the schedule of {}: new dependencies between instructions form a cycle.",
+        computation.name()));
+    }
+    self.set_sequence(computation, new_sequence);
+    Ok(())
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    hlo_module::ComputationId, hlo_module_config::HloModuleConfig, hlo_opcode::HloOpcode,
+    test_utils::{add_unary, r0f32}
+  };
+
+  // Builds `add = add(p0, p1)` with `add` as the root.
+  fn make_add_computation(name: &str) -> HloComputation {
+    let mut computation = HloComputation::new(name.to_string());
+    let p0 = computation.add_parameter(
+      HloInstruction::create_parameter(0, &r0f32(), "p0".to_string()));
+    let p1 = computation.add_parameter(
+      HloInstruction::create_parameter(1, &r0f32(), "p1".to_string()));
+    let add = computation.add_instruction(HloInstruction::create_binary(
+      &r0f32(), HloOpcode::Add, computation.instruction(p0), computation.instruction(p1)),
+      "add".to_string());
+    computation.set_root_instruction(add, false);
+    computation
+  }
+
+  fn make_sequence(computation: &HloComputation, ids: &[InstructionId]) -> HloInstructionSequence {
+    let mut sequence = HloInstructionSequence::new();
+    for id in ids {
+      sequence.push_pack(computation.instruction(*id).clone());
+    }
+    sequence
+  }
+
+  // Schedules every computation of the module in post order.
+  fn schedule_module(module: &mut HloModule) {
+    let mut schedule = HloSchedule::new();
+    for computation in module.computations() {
+      let post_order = computation.make_instruction_post_order();
+      schedule.set_sequence(computation, make_sequence(computation, &post_order));
+    }
+    module.set_schedule(schedule);
+  }
+
+  fn scheduled_names(module: &HloModule, computation: ComputationId) -> Vec<String> {
+    module.schedule().sequence(module.computation(computation)).unwrap()
+      .instructions().iter().map(|instruction| instruction.name()).collect()
+  }
+
+  #[test]
+  fn test_verify() {
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    let entry = module.add_entry_computation(make_add_computation("entry"));
+    schedule_module(&mut module);
+    assert!(module.schedule().verify(&module).is_ok());
+
+    let computation = module.computation(entry);
+    let mut reversed = computation.make_instruction_post_order();
+    reversed.reverse();
+    let mut schedule = HloSchedule::new();
+    schedule.set_sequence(computation, make_sequence(computation, &reversed));
+    let err_msg = schedule.verify(&module).err().unwrap();
+    assert!(err_msg.contains("is not scheduled after its operand"));
+
+    let partial = &computation.make_instruction_post_order()[0..2];
+    let mut schedule = HloSchedule::new();
+    schedule.set_sequence(computation, make_sequence(computation, partial));
+    let err_msg = schedule.verify(&module).err().unwrap();
+    assert!(err_msg.contains("has 2 instructions, expected 3"));
+  }
+
+  #[test]
+  fn test_update_adds_and_removes_instructions() {
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    let entry = module.add_entry_computation(make_add_computation("entry"));
+    let p0 = module.computation(entry).parameter_instruction(0).unwrap().id();
+    let exp = add_unary(module.mutable_computation(entry), HloOpcode::Exp, p0, "exp");
+    schedule_module(&mut module);
+
+    // Replace the root with negate(add), and drop exp.
+    let add = module.computation(entry).root_instruction_id();
+    let negate = add_unary(module.mutable_computation(entry), HloOpcode::Negate, add, "negate");
+    let computation = module.mutable_computation(entry);
+    computation.set_root_instruction(negate, false);
+    assert!(computation.remove_instruction(exp).is_ok());
+    let p1 = computation.parameter_instruction(1).unwrap().id();
+    let abs = add_unary(module.mutable_computation(entry), HloOpcode::Abs, p1, "abs");
+    assert!(module.schedule().verify(&module).is_err());
+
+    assert!(module.update_schedule(&HashSet::new()).is_ok());
+    let names = scheduled_names(&module, entry);
+    assert_eq!(names.len(), 5);
+    assert!(!names.contains(&"exp".to_string()));
+    let position = |name: &str| names.iter().position(|n| n == name).unwrap();
+    assert!(position("add") < position("negate"));
+    assert!(position("p1") < position("abs"));
+    assert_eq!(module.schedule().sequence(module.computation(entry)).unwrap()
+      .ids().len(), module.computation(entry).instruction_count());
+    assert!(module.computation(entry).contains(abs));
+  }
+
+  #[test]
+  fn test_update_honors_control_dependencies() {
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    let entry = module.add_entry_computation(make_add_computation("entry"));
+    schedule_module(&mut module);
+
+    // copy only reads p0, but must run after add.
+    let p0 = module.computation(entry).parameter_instruction(0).unwrap().id();
+    let add = module.computation(entry).root_instruction_id();
+    let copy = add_unary(module.mutable_computation(entry), HloOpcode::Copy, p0, "copy");
+    assert!(module.mutable_computation(entry).add_control_dependency_to(add, copy).is_ok());
+
+    assert!(module.update_schedule(&HashSet::new()).is_ok());
+    let names = scheduled_names(&module, entry);
+    assert_eq!(names.last().unwrap(), "copy");
+  }
+
+  #[test]
+  fn test_update_adds_new_computation() {
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    let entry = module.add_entry_computation(make_add_computation("entry"));
+    schedule_module(&mut module);
+
+    let added = module.add_embedded_computation(make_add_computation("added"));
+    let err_msg = module.schedule().verify(&module).err().unwrap();
+    assert!(err_msg.contains("schedule has 1 sequences, but module has 2"));
+
+    assert!(module.update_schedule(&HashSet::new()).is_ok());
+    assert_eq!(module.schedule().num_sequences_by_execution_thread()["main"], 2);
+    assert_eq!(scheduled_names(&module, added), vec!["p0", "p1", "add"]);
+    assert_eq!(scheduled_names(&module, entry), vec!["p0", "p1", "add"]);
+  }
+
+  #[test]
+  fn test_update_removed_computation_by_execution_thread() {
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    let entry = module.add_entry_computation(make_add_computation("entry"));
+    let mut parallel = make_add_computation("parallel");
+    parallel.set_execution_thread("parallel_thread".to_string());
+    let parallel = module.add_embedded_computation(parallel);
+    schedule_module(&mut module);
+
+    let counts = module.schedule().num_sequences_by_execution_thread();
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts["main"], 1);
+    assert_eq!(counts["parallel_thread"], 1);
+
+    assert!(module.remove_embedded_computation(parallel).is_ok());
+    assert!(module.schedule().verify(&module).is_err());
+    // Updating another thread keeps the sequence of the removed computation.
+    let main_thread = HashSet::from(["main".to_string()]);
+    assert!(module.update_schedule(&main_thread).is_err());
+    assert_eq!(module.schedule().sequences().len(), 2);
+
+    assert!(module.update_schedule(&HashSet::new()).is_ok());
+    let counts = module.schedule().num_sequences_by_execution_thread();
+    assert_eq!(counts.len(), 1);
+    assert_eq!(counts["main"], 1);
+    assert!(module.schedule().is_computation_scheduled(module.computation(entry)));
+  }
+
+  #[test]
+  fn test_to_string() {
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    module.add_entry_computation(make_add_computation("entry"));
+    schedule_module(&mut module);
+    assert_eq!(module.schedule().to_string(&module),
+      "HloSchedule\ncomputation entry:\n  p0\n  p1\n  add");
+  }
+}
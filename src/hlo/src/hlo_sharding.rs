@@ -2,7 +2,8 @@
 
 use common::{
   blitz_data::{OpMetadata, OpSharding, OpShardingType},
-  shape::Shape, shape_util::ShapeUtil
+  printer::{Printer, StringPrinter},
+  shape::Shape, shape_util::ShapeUtil, util::c_escape
 };
 
 use crate::tile_assignment::TileAssignment;
@@ -23,7 +24,15 @@ impl ShardGroup {
     }
   }
 
-  pub fn to_string() {}
+  pub fn to_string(&self) -> String {
+    if self.shard_as {
+      format!(" shard_as {}", self.shard_group_id)
+    } else if self.shard_like {
+      format!(" shard_like {}", self.shard_group_id)
+    } else {
+      "".to_string()
+    }
+  }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -79,13 +88,91 @@ impl HloSharding {
     HloSharding::new(false, false, true, metadata)
   }
 
-  pub fn assign_device() {}
-  pub fn tile() {}
-  pub fn iota_tile() {}
-  pub fn partial_tile() {}
-  pub fn subgroup() {}
+  // Creates a sharding that emulates device placement; a tile shape equal to
+  // the input shape (one tile) assigned to a single device.
+  pub fn assign_device(device_id: i64, metadata: Vec<OpMetadata>) -> Self {
+    let mut sharding = HloSharding::new(false, false, false, metadata);
+    sharding.maximal = true;
+    sharding.tile_assignment = TileAssignment::new_from_device_id(device_id);
+    sharding
+  }
+
+  // Creates a new sharding which splits a shape into tiles amongst the devices
+  // specified by 'tile_assignment'.
+  pub fn tile(tile_assignment: TileAssignment, metadata: Vec<OpMetadata>) -> Self {
+    let mut sharding = HloSharding::new(false, false, false, metadata);
+    sharding.tile_assignment = tile_assignment;
+    sharding
+  }
+
+  // Creates a new sharding where data is replicated within each replication
+  // group, and sharded across replication groups according to the last
+  // dimension of 'tile_assignment_last_dim_replicate'.
+  pub fn partial_tile(
+    tile_assignment_last_dim_replicate: TileAssignment,
+    metadata: Vec<OpMetadata>) -> Self
+  {
+    if tile_assignment_last_dim_replicate.num_dimensions() == 1 ||
+      tile_assignment_last_dim_replicate.dimensions().last() ==
+        Some(&tile_assignment_last_dim_replicate.num_elements())
+    {
+      return HloSharding::replicate(metadata);
+    }
+    let mut sharding = HloSharding::tile(tile_assignment_last_dim_replicate, metadata);
+    sharding.replica_on_last_tile_dim = true;
+    sharding
+  }
+
+  // Creates a subgroup sharding with device-level tile assignment, the
+  // sharding type of each subgroup is defined by subgroup_types.
+  pub fn subgroup(
+    tile_assignment: TileAssignment,
+    subgroup_types: Vec<OpShardingType>,
+    metadata: Vec<OpMetadata>) -> Self
+  {
+    if subgroup_types.is_empty() {
+      return HloSharding::tile(tile_assignment, metadata);
+    }
+    // If there is only one type of subgrouping and there is no tiling on data
+    // dimensions, it can be canonicalized to a simple manual/replicated
+    // sharding.
+    let all_same = subgroup_types.iter().all(|t| *t == subgroup_types[0]);
+    let data_rank = tile_assignment.num_dimensions() - subgroup_types.len();
+    let data_tiled = (0..data_rank).any(|i| tile_assignment.dim(i as i64) != 1);
+    if all_same && !data_tiled {
+      if subgroup_types[0] == OpShardingType::Manual {
+        return HloSharding::manual(metadata);
+      }
+      if subgroup_types[0] == OpShardingType::Replicated {
+        return HloSharding::replicate(metadata);
+      }
+    }
+    if subgroup_types.len() == 1 && subgroup_types[0] == OpShardingType::Replicated {
+      return HloSharding::partial_tile(tile_assignment, metadata);
+    }
+    let mut sharding = HloSharding::tile(tile_assignment, metadata);
+    sharding.subgroup_types = subgroup_types;
+    sharding
+  }
+
   pub fn tile_id() {}
-  pub fn tuple() {}
+
+  // Creates a new sharding for a flat tuple type. The sharding of each leaf
+  // buffer is given in 'shardings' in pre-order.
+  pub fn flat_tuple(shardings: Vec<HloSharding>) -> Self {
+    let mut sharding = HloSharding::new(false, false, false, vec![]);
+    sharding.tuple = true;
+    sharding.tuple_elements = shardings;
+    sharding
+  }
+
+  // Creates a new sharding for a tuple type. The given shardings must be
+  // compatible with the leaves of 'tuple_shape'.
+  pub fn tuple(tuple_shape: &Shape, shardings: Vec<HloSharding>) -> Self {
+    assert!(tuple_shape.is_tuple());
+    assert_eq!(shardings.len() as i64, HloSharding::required_leaves(tuple_shape));
+    HloSharding::flat_tuple(shardings)
+  }
 
   pub fn single_tuple(&self, _tuple_shape: &Shape, _sharding: &HloSharding) {}
 
@@ -105,8 +192,92 @@ impl HloSharding {
     unimplemented!()
   }
   
-  pub fn print() {}
-  pub fn to_string() {}
+  // Prints the string representation of this sharding.
+  pub fn print(&self, printer: &mut dyn Printer, include_metadata: bool) {
+    if self.tuple {
+      printer.append(&"{".to_string());
+      for i in 0..self.tuple_elements.len() {
+        if i > 0 { printer.append(&", ".to_string()); }
+        self.tuple_elements[i].print(printer, include_metadata);
+      }
+      printer.append(&"}".to_string());
+      return;
+    }
+
+    let print_metadata = |printer: &mut dyn Printer, metadata: &Vec<OpMetadata>| {
+      if !include_metadata || metadata.is_empty() { return; }
+      if metadata.len() == 1 {
+        printer.append(&" metadata={".to_string());
+        printer.append(&op_metadata_to_string(&metadata[0]));
+        printer.append(&"}".to_string());
+      } else {
+        printer.append(&" metadata={".to_string());
+        for i in 0..metadata.len() {
+          if i > 0 { printer.append(&", ".to_string()); }
+          printer.append(&"{".to_string());
+          printer.append(&op_metadata_to_string(&metadata[i]));
+          printer.append(&"}".to_string());
+        }
+        printer.append(&"}".to_string());
+      }
+    };
+    let print_shard_group = |printer: &mut dyn Printer, shard_group: &ShardGroup| {
+      printer.append(&shard_group.to_string());
+    };
+
+    if self.replicated {
+      printer.append(&"{replicated".to_string());
+      print_shard_group(printer, &self.shard_group);
+      print_metadata(printer, &self.metadata);
+      printer.append(&"}".to_string());
+      return;
+    }
+    if self.manual {
+      printer.append(&"{manual".to_string());
+      print_shard_group(printer, &self.shard_group);
+      print_metadata(printer, &self.metadata);
+      printer.append(&"}".to_string());
+      return;
+    }
+    if self.unknown {
+      printer.append(&"{unknown".to_string());
+      print_shard_group(printer, &self.shard_group);
+      print_metadata(printer, &self.metadata);
+      printer.append(&"}".to_string());
+      return;
+    }
+    if self.maximal {
+      printer.append(&"{maximal device=".to_string());
+      printer.append(&self.tile_assignment.first().to_string());
+      print_shard_group(printer, &self.shard_group);
+      print_metadata(printer, &self.metadata);
+      printer.append(&"}".to_string());
+      return;
+    }
+
+    printer.append(&"{".to_string());
+    self.tile_assignment.print(printer);
+    if self.replica_on_last_tile_dim {
+      printer.append(&" last_tile_dim_replicate".to_string());
+    }
+    if !self.subgroup_types.is_empty() {
+      printer.append(&" last_tile_dims={".to_string());
+      for i in 0..self.subgroup_types.len() {
+        if i > 0 { printer.append(&", ".to_string()); }
+        printer.append(&op_sharding_type_to_string(&self.subgroup_types[i]));
+      }
+      printer.append(&"}".to_string());
+    }
+    print_shard_group(printer, &self.shard_group);
+    print_metadata(printer, &self.metadata);
+    printer.append(&"}".to_string());
+  }
+
+  pub fn to_string(&self, include_metadata: bool) -> String {
+    let mut printer = StringPrinter::new();
+    self.print(&mut printer, include_metadata);
+    printer.to_string()
+  }
 
   // Validate that this sharding can be applied to a tensor with shape `shape`.
   pub fn validate(&self, _shape: &Shape, _num_devices: Option<i64>) -> Result<(), String>
@@ -483,4 +654,42 @@ impl HloSharding {
   pub fn get_shard_group(&self) -> &ShardGroup {
     &self.shard_group
   }
-}
\ No newline at end of file
+}
+
+fn op_sharding_type_to_string(t: &OpShardingType) -> String {
+  match t {
+    OpShardingType::Manual => "manual".to_string(),
+    OpShardingType::Maximal => "maximal".to_string(),
+    OpShardingType::Replicated => "replicated".to_string(),
+    _ => "unknown".to_string()
+  }
+}
+
+// Prints the fields of an OpMetadata in the form accepted by the HLO parser.
+pub fn op_metadata_to_string(metadata: &OpMetadata) -> String {
+  let mut result = vec![];
+  if !metadata.op_type().is_empty() {
+    result.push(format!("op_type=\"{}\"", c_escape(&metadata.op_type())));
+  }
+  if !metadata.op_name().is_empty() {
+    result.push(format!("op_name=\"{}\"", c_escape(&metadata.op_name())));
+  }
+  if !metadata.source_file().is_empty() {
+    result.push(format!("source_file=\"{}\"", c_escape(&metadata.source_file())));
+  }
+  if metadata.source_line() != 0 {
+    result.push(format!("source_line={}", metadata.source_line()));
+  }
+  if !metadata.deduplicated_name().is_empty() {
+    result.push(format!("deduplicated_name=\"{}\"",
+      c_escape(&metadata.deduplicated_name())));
+  }
+  if metadata.preserve_layout() {
+    result.push("preserve_layout=true".to_string());
+  }
+  if !metadata.scheduling_name().is_empty() {
+    result.push(format!("scheduling_name=\"{}\"",
+      c_escape(&metadata.scheduling_name())));
+  }
+  result.join(" ")
+}
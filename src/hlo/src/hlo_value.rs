@@ -1,11 +1,16 @@
 #![allow(dead_code)]
 
-use std::cmp::Ordering;
+use std::{cmp::Ordering, collections::HashSet, hash::{Hash, Hasher}};
 
-use common::{shape::Shape, shape_tree::ShapeTree, shape_util::ShapeUtil};
+use common::{
+  shape::Shape,
+  shape_tree::{Node, ShapeTree},
+  shape_util::ShapeUtil
+};
 
 use crate::{
   buffer_value::BufferValue, hlo_computation::HloComputation, hlo_instruction::HloInstruction,
+  hlo_module::HloModule, hlo_opcode::HloOpcode
 };
 
 // abstraction which identifies a specific point in the Blitz graph.
@@ -18,14 +23,29 @@ pub struct HloPosition {
 }
 
 impl HloPosition {
-  pub fn new() {}
+  pub fn new(instruction: HloInstruction, index: Vec<i64>) -> Self {
+    HloPosition { instruction: instruction, index: index }
+  }
 
   // Returns the shape at this position.
-  pub fn shape(&self) -> &Shape {
-    unimplemented!()
+  pub fn shape(&self) -> Shape {
+    ShapeUtil::get_subshape(self.instruction.shape(), &self.index)
+  }
+
+  pub fn to_string(&self) -> String {
+    let mut out = self.instruction.name();
+    if self.instruction.shape().is_tuple() {
+      out.push(' ');
+      out.push_str(&shape_index_to_string(&self.index));
+    }
+    out
   }
+}
 
-  pub fn to_string() {}
+// Formats a shape index as "{0,1}".
+pub fn shape_index_to_string(index: &[i64]) -> String {
+  let elements: Vec<String> = index.iter().map(|i| i.to_string()).collect();
+  format!("{{{}}}", elements.join(","))
 }
 
 impl PartialOrd for HloPosition {
@@ -74,11 +94,39 @@ impl HloUse {
   }
 
   pub fn to_string(&self) -> String {
-    unimplemented!()
+    let mut out = self.instruction.name();
+    out.push_str(", operand ");
+    out.push_str(&self.operand_number.to_string());
+    if !self.operand_index_vec.is_empty() {
+      out.push(' ');
+      out.push_str(&shape_index_to_string(&self.operand_index_vec));
+    }
+    out
   }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+// Returns whether 'user' may read the value at 'index' of its operand
+// 'operand_number'.
+fn may_use_operand_value(operand_number: i64, index: &Vec<i64>, user: &HloInstruction) -> bool {
+  match user.opcode() {
+    // These instructions only access the top-level values of their operand.
+    // Non-top-level (nested) values are passed through transparently.
+    HloOpcode::GetTupleElement | HloOpcode::Copy => {
+      assert_eq!(operand_number, 0);
+      index.is_empty()
+    }
+    // These instructions always pass through their operands transparently.
+    HloOpcode::Domain | HloOpcode::Tuple => false,
+    // Although call and while instructions pass through their operands, they
+    // are considered uses.
+    _ => true
+  }
+}
+
+// An HloValue is identified by its id, so two values compare equal when
+// their ids match, even if one of them is a copy taken before the positions
+// and uses were computed.
+#[derive(Debug, Clone)]
 pub struct HloValue {
   buffer_value: BufferValue,
   positions: Vec<HloPosition>,
@@ -103,22 +151,19 @@ impl HloValue {
   // parameter of a while body computation. Phi values are only used in the SSA
   // dataflow analysis (HloDataflowAnalysis::ssa_form_ is true).
   pub fn new(
-    _id: i64,
-    _instruction: &HloInstruction,
-    _index: &Vec<i64>,
-    _is_phi: bool) -> Self
+    id: i64,
+    instruction: &HloInstruction,
+    index: &Vec<i64>,
+    is_phi: bool) -> Self
   {
-    /*
-    let instance =HloValue {
-      buffer_value: BufferValue::new(),
-      positions: Vec::new(),
+    let shape = ShapeUtil::get_subshape(instruction.shape(), index);
+    HloValue {
+      buffer_value: BufferValue::new_with_shape(id, &shape),
+      positions: vec![HloPosition::new(instruction.clone(), index.clone())],
       uses: Vec::new(),
       is_phi: is_phi,
       live_out_of_module: false
-    };
-    instance
-    */
-    unimplemented!()
+    }
   }
 
   // Predicate comparing HloValues by increasing id, for std::sort.
@@ -127,19 +172,53 @@ impl HloValue {
   }
 
   // Sets the positions in the module at which the HloValue appears.
-  pub fn set_positions(&mut self, mut positions: Vec<HloPosition>) {
-    for pos_a in &positions {
-      assert_ne!(pos_a, self.defining_position());
-      for pos_b in &positions {
-        assert_ne!(pos_a, pos_b)
+  // The positions must be unique and must not contain the defining position,
+  // which is added at construction time.
+  pub fn set_positions(&mut self, mut positions: Vec<HloPosition>, module: &HloModule) {
+    assert_eq!(self.positions.len(), 1, "set_positions should only be called once.");
+    for (i, pos_a) in positions.iter().enumerate() {
+      debug_assert!(pos_a != self.defining_position());
+      for pos_b in &positions[i + 1..] {
+        debug_assert!(pos_a != pos_b);
       }
     }
     self.positions.append(&mut positions);
-    self.live_out_of_module |= self.is_root_of(
-      self
-      .defining_instruction()
-      .get_module().as_ref().unwrap()
-      .entry_computation().as_ref().unwrap());
+    if let Some(entry) = module.entry_computation() {
+      self.live_out_of_module |= self.is_root_of(entry);
+    }
+  }
+
+  // Computes the uses of the value from its positions. Must be called after
+  // set_positions.
+  pub fn compute_uses(&mut self, module: &HloModule) {
+    // Gather the computation roots at which this value appears.
+    let mut root_positions = HashSet::new();
+    for position in &self.positions {
+      let computation = module.computation(position.instruction.parent().unwrap());
+      if computation.is_root(position.instruction.id()) {
+        root_positions.insert(position.instruction.id());
+      }
+    }
+
+    let mut uses = vec![];
+    for position in &self.positions {
+      let computation = module.computation(position.instruction.parent().unwrap());
+      for user_id in position.instruction.users() {
+        let user = computation.instruction(*user_id);
+        for i in user.operand_indices(position.instruction.id()) {
+          // Root instructions of computations are considered to be uses whether
+          // or not the root instruction itself actually uses the value.
+          if may_use_operand_value(i, &position.index, user) ||
+            root_positions.contains(user_id)
+          {
+            let new_use = HloUse::new(user.clone(), i, position.index.clone());
+            debug_assert!(!uses.contains(&new_use));
+            uses.push(new_use);
+          }
+        }
+      }
+    }
+    self.uses = uses;
   }
 
   // Returns whether this value is a phi value.
@@ -172,7 +251,7 @@ impl HloValue {
   }
 
   // Return the shape of this HloValue.
-  pub fn shape(&self) -> &Shape {
+  pub fn shape(&self) -> Shape {
     self.defining_position().shape()
   }
 
@@ -190,7 +269,9 @@ impl HloValue {
   // computation.
   pub fn is_root_of(&self, computation: &HloComputation) -> bool {
     for pos in &self.positions {
-      if pos.instruction.is_root() && pos.instruction.parent() == computation {
+      if pos.instruction.parent() == computation.id() &&
+        computation.is_root(pos.instruction.id())
+      {
         return true;
       }
     }
@@ -203,11 +284,39 @@ impl HloValue {
   }
 
   pub fn to_short_string(&self) -> String {
-    unimplemented!()
+    let mut out = "<".to_string();
+    out.push_str(&self.id().to_string());
+    out.push(' ');
+    out.push_str(&self.instruction().name());
+    if self.instruction().shape().is_tuple() {
+      out.push_str(&shape_index_to_string(self.index()));
+    }
+    if self.is_phi {
+      out.push_str(" (phi)");
+    }
+    if self.buffer_value.has_color() {
+      out.push_str(" @");
+      out.push_str(&self.color().to_string());
+    }
+    out.push('>');
+    out
   }
 
   pub fn to_string(&self) -> String {
-    unimplemented!()
+    let mut out = self.to_short_string();
+    out.push_str("\n  positions:\n");
+    for position in &self.positions {
+      out.push_str("    ");
+      out.push_str(&position.to_string());
+      out.push('\n');
+    }
+    out.push_str("  uses:\n");
+    for use_ in &self.uses {
+      out.push_str("    ");
+      out.push_str(&use_.to_string());
+      out.push('\n');
+    }
+    out
   }
 
   // ----- BufferValue methods -----
@@ -220,6 +329,20 @@ impl HloValue {
   }
 }
 
+impl PartialEq for HloValue {
+  fn eq(&self, other: &Self) -> bool {
+    self.id() == other.id()
+  }
+}
+
+impl Eq for HloValue {}
+
+impl Hash for HloValue {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.id().hash(state);
+  }
+}
+
 impl PartialOrd for HloValue {
   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
     if self.id() < other.id() {
@@ -265,8 +388,7 @@ impl HloValueSet {
   // Sets this value set to the union of the given value sets.
   // Returns whether this value set changed.
   pub fn assign_union_of(&mut self, inputs: Vec<HloValueSet>) -> bool {
-    let mut original: Vec<HloValue> = Vec::new();
-    original.clone_from_slice(&self.values);
+    let original = self.values.clone();
 
     for input in inputs {
       for value in input.values {
@@ -339,76 +461,74 @@ impl InstructionValueSet {
 
   // Sets this value set to the union of the given value sets.
   // Returns whether this value set changed.
-  pub fn assign_union_of(&mut self, inputs: Vec<InstructionValueSet>) -> bool {
-    debug_assert!(inputs.len() > 0);
-    for _i in 1..inputs.len() {
-      debug_assert!(ShapeUtil::compatible(
-        inputs[0].shape(), inputs[1].shape()));
+  pub fn assign_union_of(&mut self, inputs: &[InstructionValueSet]) -> bool {
+    debug_assert!(!inputs.is_empty());
+    for input in inputs {
+      debug_assert!(ShapeUtil::compatible(self.shape(), input.shape()));
     }
     let mut changed = false;
-    for pair in self.mutable_nodes() {
-      let index = vec![pair.0 as i64];
-      let value_set = &mut pair.1;
-      let mut input_value_sets = Vec::new();
-      for input in &inputs {
-        input_value_sets.push(input.element(&index).clone());
-      }
-      changed |= value_set.assign_union_of(input_value_sets);
+    for node in self.shape_tree.mutable_nodes() {
+      let input_value_sets = inputs.iter()
+        .map(|input| input.element(node.index()).clone())
+        .collect();
+      changed |= node.mutable_value().assign_union_of(input_value_sets);
+    }
+    changed
+  }
+
+  // Sets this value set to the union of this set and the value sets of
+  // 'input' under 'input_index'. Returns whether this value set changed.
+  pub fn assign_union_of_at(
+    &mut self, input: &InstructionValueSet, input_index: &Vec<i64>) -> bool
+  {
+    let mut changed = false;
+    for node in self.shape_tree.mutable_nodes() {
+      let mut index = input_index.clone();
+      index.extend(node.index());
+      let input_value_set = input.element(&index).clone();
+      let current = node.value().clone();
+      changed |= node.mutable_value().assign_union_of(vec![current, input_value_set]);
     }
     changed
   }
 
   // Return true if any value sets for any subshape element is not a singleton.
   pub fn is_ambiguous(&self) -> bool {
-    let mut ambiguous = false;
-    for node in self.nodes() {
-      ambiguous |= node.1.values().len() > 1;
-    }
-    ambiguous
+    self.nodes().iter().any(|node| node.value().values().len() > 1)
   }
 
   pub fn shape(&self) -> &Shape {
     self.shape_tree.shape()
   }
 
-  pub fn element(&self, _index: &Vec<i64>) -> &HloValueSet {
-    //self.shape_tree.element(index)
-    unimplemented!()
+  pub fn element(&self, index: &Vec<i64>) -> &HloValueSet {
+    self.shape_tree.element(index)
   }
 
-  pub fn mutable_element(&mut self, _index: &Vec<i64>) -> &mut HloValueSet {
-    //self.shape_tree.mutable_element(index)
-    unimplemented!()
+  pub fn mutable_element(&mut self, index: &Vec<i64>) -> &mut HloValueSet {
+    self.shape_tree.mutable_element(index)
   }
 
-  pub fn find(&self, _index: usize) -> &(usize, HloValueSet) {
-    //self.shape_tree.find(index)
-    unimplemented!()
+  // Returns the (shape index, value set) nodes in pre-order.
+  pub fn nodes(&self) -> &Vec<Node<HloValueSet>> {
+    self.shape_tree.nodes()
   }
 
-  pub fn nodes(&self) -> &Vec<(usize, HloValueSet)> {
-    //self.shape_tree.nodes()
-    unimplemented!()
+  pub fn mutable_nodes(&mut self) -> &mut Vec<Node<HloValueSet>> {
+    self.shape_tree.mutable_nodes()
   }
 
-  pub fn mutable_nodes(&mut self) -> &mut Vec<(usize, HloValueSet)> {
-    //self.shape_tree.mutable_nodes()
-    unimplemented!()
-  }
-
-  pub fn to_string(&mut self) -> String {
+  pub fn to_string(&self) -> String {
     let mut out = "InstructionValueSet(".to_string();
     out.push_str(ShapeUtil::human_string(self.shape()).as_str());
     out.push_str(")\n");
-
-    let _func = |index: usize, value_set: &mut HloValueSet| {
+    for node in self.nodes() {
       out.push_str("  ");
-      out.push_str(&index.to_string());
+      out.push_str(&shape_index_to_string(node.index()));
       out.push_str(" : ");
-      out.push_str(&value_set.to_string());
+      out.push_str(&node.value().to_string());
       out.push('\n');
-    };
-    //self.shape_tree.for_each_mutable_element(&mut func);
+    }
     out
   }
-}
\ No newline at end of file
+}
@@ -22,4 +22,7 @@ pub mod hlo_reachability;
 pub mod hlo_schdule;
 pub mod hlo_sharding;
 pub mod hlo_value;
-pub mod tile_assignment;
\ No newline at end of file
+pub mod tile_assignment;
+
+#[cfg(any(test, feature = "test_utils"))]
+pub mod test_utils;
\ No newline at end of file
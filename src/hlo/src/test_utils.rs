@@ -0,0 +1,49 @@
+#![allow(dead_code)]
+
+// Helpers shared by the tests which build or inspect HLO modules. They are
+// only compiled for tests, and for the tests of the crates depending on this
+// one through the test_utils feature.
+
+use common::{blitz_data::PrimitiveType, shape::Shape, shape_util::ShapeUtil};
+
+use crate::{
+  hlo_computation::HloComputation,
+  hlo_instruction::{HloInstruction, InstructionId},
+  hlo_module::HloModule,
+  hlo_opcode::HloOpcode
+};
+
+pub fn r0f32() -> Shape {
+  ShapeUtil::make_shape(&PrimitiveType::F32, vec![])
+}
+
+// Adds the f32 scalar instruction `name = opcode(operand)` to 'computation'.
+pub fn add_unary(
+  computation: &mut HloComputation,
+  opcode: HloOpcode,
+  operand: InstructionId,
+  name: &str) -> InstructionId
+{
+  let instruction = HloInstruction::create_unary(
+    &r0f32(), opcode, computation.instruction(operand));
+  computation.add_instruction(instruction, name.to_string())
+}
+
+// Returns the instruction named 'name' in any computation of 'module'.
+pub fn find<'a>(module: &'a HloModule, name: &str) -> &'a HloInstruction {
+  module.computations()
+    .flat_map(|computation| computation.instructions())
+    .find(|instruction| instruction.name() == name)
+    .unwrap()
+}
+
+// Returns the instruction named 'name' in the computation named
+// 'computation' of 'module'.
+pub fn find_in<'a>(module: &'a HloModule, computation: &str, name: &str) -> &'a HloInstruction {
+  module.computations()
+    .find(|comp| comp.name() == computation)
+    .unwrap()
+    .instructions()
+    .find(|instruction| instruction.name() == name)
+    .unwrap()
+}
@@ -306,7 +306,7 @@ impl TileAssignment {
       printer.append(&"devices=[".to_string());
       append_join(printer, self.array().dimensions(), ",".to_string());
       printer.append(&"]".to_string());
-      //append_join(printer, self.array().values(), ",".to_string());
+      append_join(printer, self.array().values(), ",".to_string());
     }
   }
 
@@ -1,6 +1,9 @@
 #![allow(dead_code)]
 
-use crate::{hlo_instruction::HloInstruction, hlo_module::HloModule, hlo_opcode::HloOpcode};
+use crate::{
+  hlo_computation::HloComputation, hlo_instruction::HloInstruction,
+  hlo_module::HloModule, hlo_opcode::HloOpcode
+};
 
 
 pub fn is_collective_communication_op(_op: &HloOpcode) -> bool {
@@ -8,8 +11,11 @@ pub fn is_collective_communication_op(_op: &HloOpcode) -> bool {
 }
 
 // Returns whether all of an instruction's operands are parameters.
-pub fn all_operands_are_parameters(_instruction: &HloInstruction) -> bool {
-  unimplemented!()
+pub fn all_operands_are_parameters(
+  computation: &HloComputation, instruction: &HloInstruction) -> bool
+{
+  instruction.operands().iter().all(|operand|
+    computation.instruction(*operand).opcode() == HloOpcode::Parameter)
 }
 
 // Returns whether the module contains the given collective communication
@@ -22,10 +22,12 @@ impl BitcastDtypesExpander {
 
   pub fn name() -> String { "bitcast_dtypes_expander".to_string() }
 
-  pub fn instruction_matches_pattern(instruction: &HloInstruction) -> bool {
+  pub fn instruction_matches_pattern(
+    computation: &HloComputation, instruction: &HloInstruction) -> bool
+  {
     instruction.opcode() == HloOpcode::BitcastConvert &&
     bit_width(&instruction.shape().element_type()) !=
-    bit_width(&instruction.operand(0).shape().element_type())
+    bit_width(&computation.operand(instruction.id(), 0).shape().element_type())
   }
 
   pub fn expand_instruction(&self) {}
@@ -1 +0,0 @@
-#![allow(dead_code)]
\ No newline at end of file
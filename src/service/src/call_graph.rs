@@ -3,9 +3,8 @@
 use std::{collections::{HashMap, HashSet, VecDeque}, hash::Hash};
 
 use hlo::{
-  hlo_computation::HloComputation,
-  hlo_instruction::HloInstruction,
-  hlo_module::HloModule, hlo_opcode::HloOpcode
+  hlo_instruction::{HloInstruction, InstructionId},
+  hlo_module::{ComputationId, HloModule}, hlo_opcode::HloOpcode
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -53,36 +52,41 @@ pub fn get_instruction_call_context(opcode: &HloOpcode) -> CallContext {
 }
 
 // Represents an HLO instruction which calls one or more computations.
+#[derive(Debug, Clone, PartialEq)]
 pub struct CallSite {
-  instruction: HloInstruction,
-  called_computations: Vec<HloComputation>,
+  computation: ComputationId,
+  instruction: InstructionId,
+  called_computations: Vec<ComputationId>,
   context: CallContext
 }
 
 impl CallSite {
   pub fn new(
-    instruction: HloInstruction,
-    called_computations: Vec<HloComputation>,
+    computation: ComputationId,
+    instruction: InstructionId,
+    called_computations: Vec<ComputationId>,
     context: CallContext) -> Self
   {
     CallSite {
+      computation: computation,
       instruction: instruction,
       called_computations: called_computations,
       context: context
     }
   }
 
-  // Returns the instruction associated with this call site.
-  pub fn instruction(&self) -> &HloInstruction {
-    &self.instruction
+  // Returns the computation containing the instruction of this call site.
+  pub fn computation(&self) -> ComputationId {
+    self.computation
   }
 
-  pub fn mutable_instruction(&mut self) -> &mut HloInstruction {
-    &mut self.instruction
-  }  
+  // Returns the instruction associated with this call site.
+  pub fn instruction(&self) -> InstructionId {
+    self.instruction
+  }
 
   // Returns the computations called at this call site.
-  pub fn called_computations(&self) -> &Vec<HloComputation> {
+  pub fn called_computations(&self) -> &Vec<ComputationId> {
     &self.called_computations
   }
 
@@ -91,34 +95,35 @@ impl CallSite {
     self.context.clone()
   }
 
-  pub fn to_string(&self) -> String {
-    let mut result = self.instruction().name();
+  pub fn to_string(&self, module: &HloModule) -> String {
+    let mut result = module.instruction(self.computation, self.instruction).name();
     result.push_str(" calls in context ");
     result.push_str(&call_context_to_string(&self.context()));
     result.push_str(": ");
-    for comp in self.called_computations() {
-      result.push_str(&comp.name());
-    }
+    let names: Vec<String> = self.called_computations().iter()
+      .map(|c| module.computation(*c).name()).collect();
+    result.push_str(&names.join(", "));
     result
   }
 }
 
 // A node in the call graph representing an HLO computation.
+#[derive(Debug, Clone, PartialEq)]
 pub struct CallGraphNode {
-  computation: HloComputation,
-  callees: Vec<HloComputation>,
-  callee_set: HashSet<HloComputation>,
-  callers: Vec<HloComputation>,
-  caller_set: HashSet<HloComputation>,
+  computation: ComputationId,
+  callees: Vec<ComputationId>,
+  callee_set: HashSet<ComputationId>,
+  callers: Vec<ComputationId>,
+  caller_set: HashSet<ComputationId>,
   callsites: Vec<CallSite>,
-  callsite_instructions: HashMap<HloInstruction, usize>,
+  callsite_instructions: HashMap<InstructionId, usize>,
   caller_callsites: Vec<CallSite>,
   context: CallContext,
   depth: i64,
 }
 
 impl CallGraphNode {
-  pub fn new(computation: HloComputation) -> Self {
+  pub fn new(computation: ComputationId) -> Self {
     CallGraphNode {
       computation: computation,
       callees: Vec::new(),
@@ -134,12 +139,8 @@ impl CallGraphNode {
   }
 
   // Returns the computation represented by his call graph node.
-  pub fn computation(&self) -> &HloComputation {
-    &self.computation
-  }
-
-  pub fn mutable_computation(&mut self) -> &mut HloComputation {
-    &mut self.computation
+  pub fn computation(&self) -> ComputationId {
+    self.computation
   }
 
   // Returns the call sites in this computation. These are the instructions in
@@ -148,13 +149,9 @@ impl CallGraphNode {
     &self.callsites
   }
 
-  pub fn mutable_callsites(&mut self) -> &mut Vec<CallSite> {
-    &mut self.callsites
-  }
-
   // Returns the callsite associated with the given instruction.
-  pub fn get_call_site(&self, instruction: &HloInstruction) -> Option<&CallSite> {
-    let index = self.callsite_instructions.get(instruction);
+  pub fn get_call_site(&self, instruction: InstructionId) -> Option<&CallSite> {
+    let index = self.callsite_instructions.get(&instruction);
     if index.is_none() {
       return None;
     }
@@ -162,7 +159,7 @@ impl CallGraphNode {
   }
 
   // Returns the computations called by this computation.
-  pub fn callees(&self) -> &Vec<HloComputation> {
+  pub fn callees(&self) -> &Vec<ComputationId> {
     &self.callees
   }
 
@@ -172,7 +169,7 @@ impl CallGraphNode {
   }
 
   // Returns the computations which call this computation.
-  pub fn callers(&self) -> &Vec<HloComputation> {
+  pub fn callers(&self) -> &Vec<ComputationId> {
     &self.callers
   }
 
@@ -186,8 +183,8 @@ impl CallGraphNode {
     self.depth
   }
 
-  pub fn to_string(&self) -> String {
-    self.computation.name()
+  pub fn to_string(&self, module: &HloModule) -> String {
+    module.computation(self.computation).name()
   }
 
   // Sets the context in which this computation is called.
@@ -202,10 +199,10 @@ impl CallGraphNode {
 
   // Adds a callsite which calls this computation.
   fn add_caller_call_site(&mut self, caller_callsite: CallSite) {
-    let caller = caller_callsite.instruction().parent();
-    if !self.callee_set.contains(caller) {
-      self.callers.push(caller.clone());
-      self.caller_set.insert(caller.clone());
+    let caller = caller_callsite.computation();
+    if !self.caller_set.contains(&caller) {
+      self.callers.push(caller);
+      self.caller_set.insert(caller);
     }
     self.caller_callsites.push(caller_callsite);
   }
@@ -214,41 +211,56 @@ impl CallGraphNode {
   // to the call graph node.
   fn add_call_site_for_instruction(
     &mut self,
+    module: &HloModule,
     instruction: &HloInstruction,
     execution_threads: &HashSet<String>)
   {
-    debug_assert_eq!(instruction.parent(), self.computation());
+    debug_assert_eq!(instruction.parent(), Some(self.computation()));
     let context = get_instruction_call_context(&instruction.opcode());
     if !instruction.called_computations().is_empty() {
       debug_assert!(context == CallContext::ControlFlow ||
         context == CallContext::Embedded);
       let callsite = CallSite::new(
-        instruction.clone(),
-        instruction.called_computations().clone(),
+        self.computation,
+        instruction.id(),
+        instruction.called_computations().to_vec(),
         context);
       self.callsites.push(callsite);
-      self.callsite_instructions.insert(instruction.clone(), self.callsites.len());
+      self.callsite_instructions.insert(instruction.id(), self.callsites.len() - 1);
 
-      for callee in self.callsites.last().unwrap().called_computations() {
+      for callee in instruction.called_computations() {
         if HloInstruction::is_thread_included(
-          callee.execution_thread(),
+          module.computation(*callee).execution_thread(),
           execution_threads) &&
           !self.callee_set.contains(callee)
         {
-          self.callees.push(callee.clone());
-          self.callee_set.insert(callee.clone());
+          self.callees.push(*callee);
+          self.callee_set.insert(*callee);
         }
       }
     }
   }
 }
 
+// Returns the union of two call contexts.
+fn union_contexts(a: CallContext, b: CallContext) -> CallContext {
+  if a == CallContext::None {
+    b
+  } else if b == CallContext::None || a == b {
+    a
+  } else {
+    // Contexts are different and neither is None, ie one is ControlFlow and
+    // the other is Embedded.
+    CallContext::Both
+  }
+}
+
 // The call graph for an HLO module. The graph includes a node for each
 // computation in the module.
 pub struct CallGraph<'module> {
   module: &'module HloModule,
   nodes: Vec<CallGraphNode>,
-  node_indices: HashMap<HloComputation, i64>,
+  node_indices: HashMap<ComputationId, usize>,
   execution_threads: HashSet<String>,
 }
 
@@ -266,16 +278,111 @@ impl<'module> CallGraph<'module> {
   // execution_threads is provided, only computations that are in
   // execution_threads will be part of the returned call graph.
   pub fn build(
-    _module: &HloModule,
-    _execution_threads: &HashSet<String>) -> Self
+    module: &'module HloModule,
+    execution_threads: &HashSet<String>) -> Self
   {
-    unimplemented!()
+    let mut call_graph = CallGraph::new(module, execution_threads.clone());
+    // Construct nodes of the call graph and populate the callsites.
+    for computation in module.computations() {
+      if !HloInstruction::is_thread_included(
+        computation.execution_thread(), execution_threads)
+      {
+        continue;
+      }
+      let id = computation.id().unwrap();
+      let mut node = CallGraphNode::new(id);
+      for instruction in computation.instructions() {
+        node.add_call_site_for_instruction(module, instruction, execution_threads);
+      }
+      call_graph.node_indices.insert(id, call_graph.nodes.len());
+      call_graph.nodes.push(node);
+    }
+
+    // Add caller callsites to each node.
+    for i in 0..call_graph.nodes.len() {
+      let callsites = call_graph.nodes[i].callsites().clone();
+      for callsite in callsites {
+        for callee in callsite.called_computations() {
+          if let Some(index) = call_graph.node_indices.get(callee) {
+            call_graph.nodes[*index].add_caller_call_site(callsite.clone());
+          }
+        }
+      }
+    }
+
+    call_graph.set_call_contexts();
+    call_graph.set_node_depths();
+    call_graph
+  }
+
+  // Sets the call contexts for every node in the graph.
+  fn set_call_contexts(&mut self) {
+    let mut worklist = VecDeque::new();
+    // Computations without callers are called in a control flow context.
+    for node in &mut self.nodes {
+      if node.callers().is_empty() {
+        node.set_context(CallContext::ControlFlow);
+        worklist.push_back(node.computation());
+      }
+    }
+    while let Some(computation) = worklist.pop_front() {
+      let node = self.get_node(computation).clone();
+      for callsite in node.callsites() {
+        for callee in callsite.called_computations() {
+          let index = match self.node_indices.get(callee) {
+            Some(index) => *index,
+            None => continue
+          };
+          // Update context of callee computation based on the callsite and
+          // its current context.
+          let context_to_add = if node.context() == CallContext::Embedded {
+            CallContext::Embedded
+          } else {
+            callsite.context()
+          };
+          let new_context =
+            union_contexts(context_to_add, self.nodes[index].context());
+          if new_context != self.nodes[index].context() {
+            // Context of computation has been changed so add node to
+            // worklist.
+            self.nodes[index].set_context(new_context);
+            worklist.push_back(*callee);
+          }
+        }
+      }
+    }
+  }
+
+  // Sets the call node depths for every node in the graph.
+  fn set_node_depths(&mut self) {
+    let mut worklist = VecDeque::new();
+    for node in &mut self.nodes {
+      if node.callers().is_empty() {
+        node.set_depth(0);
+        worklist.push_back(node.computation());
+      }
+    }
+    while let Some(computation) = worklist.pop_front() {
+      let node = self.get_node(computation).clone();
+      for callee in node.callees() {
+        let index = self.node_indices[callee];
+        if node.depth() + 1 > self.nodes[index].depth() {
+          self.nodes[index].set_depth(node.depth() + 1);
+          worklist.push_back(*callee);
+        }
+      }
+    }
+  }
+
+  // Returns the module the call graph was built for.
+  pub fn module(&self) -> &'module HloModule {
+    self.module
   }
 
   // Returns the node associated with the given computation.
-  pub fn get_node(&self, computation: &HloComputation) -> &CallGraphNode {
-    debug_assert!(self.node_indices.contains_key(computation));
-    &self.nodes[*self.node_indices.get(computation).unwrap() as usize]
+  pub fn get_node(&self, computation: ComputationId) -> &CallGraphNode {
+    debug_assert!(self.node_indices.contains_key(&computation));
+    &self.nodes[*self.node_indices.get(&computation).unwrap()]
   }
 
   // Returns the vector of all nodes in the call graph.
@@ -283,28 +390,31 @@ impl<'module> CallGraph<'module> {
     &self.nodes
   }
 
-  // Calls the given function on each node in the call graph.
+  // Calls the given function on each node in the call graph. Nodes are visited
+  // in post order (callees before callers). If visit_unreachable_nodes is
+  // true then all nodes in the call graph are visited. Otherwise only those
+  // nodes reachable from the entry computation are visited.
   pub fn visit_nodes<F>(
     &self,
-    visitor_func: F,
+    mut visitor_func: F,
     visit_unreachable_nodes: bool
   ) -> Result<(), String>
-    where F: Fn(&CallGraphNode) -> Result<(), String>
+    where F: FnMut(&CallGraphNode) -> Result<(), String>
   {
-    let visited: HashSet<CallGraphNode> = HashSet::new();
+    let mut visited = HashSet::new();
     if visit_unreachable_nodes {
       // Traverse from all roots in the call graph.
       for node in &self.nodes {
         if node.callers.is_empty() {
-          return self.visit_nodes_internal(&visitor_func, node, &visited);
+          self.visit_nodes_internal(&mut visitor_func, node, &mut visited)?;
         }
       }
     } else {
       // Traverse only from the entry computation.
-      return self.visit_nodes_internal(
-        &visitor_func,
-        self.get_node(self.module.entry_computation().unwrap()),
-        &visited);
+      self.visit_nodes_internal(
+        &mut visitor_func,
+        self.get_node(self.module.entry_computation_id()),
+        &mut visited)?;
     }
     Ok(())
   }
@@ -314,11 +424,11 @@ impl<'module> CallGraph<'module> {
   // and 'visited' is the set of computations which have been visited.
   fn dominates_helper(
     &self,
-    a: &HloComputation,
-    b: &HloComputation,
-    visited: &mut HashSet<HloComputation>) -> bool
+    a: ComputationId,
+    b: ComputationId,
+    visited: &mut HashSet<ComputationId>) -> bool
   {
-    if a == b || visited.contains(b) {
+    if a == b || visited.contains(&b) {
       // The call graph is guaranteed to be acyclic so any previously visited node
       // we encounter was already determined to be dominated.
       return true;
@@ -329,9 +439,9 @@ impl<'module> CallGraph<'module> {
       return false;
     }
     // Walk up the callers of 'b' until we hit 'a' or a root node (no callers).
-    visited.insert(b.clone());
+    visited.insert(b);
     for b_caller in b_node.callers() {
-      if !self.dominates_helper(a, b_caller, visited) { return false; }
+      if !self.dominates_helper(a, *b_caller, visited) { return false; }
     }
     true
   }
@@ -340,20 +450,20 @@ impl<'module> CallGraph<'module> {
   // dominates computation 'b' iff all callgraph paths in the caller-to-callee
   // direction from a root computation to 'b' pass through computation
   // 'a'. Trivially, a computation dominates itself.
-  pub fn dominates(&self, a: &HloComputation, b: &HloComputation) -> bool {
+  pub fn dominates(&self, a: ComputationId, b: ComputationId) -> bool {
     let mut visited = HashSet::new();
     self.dominates_helper(a, b, &mut visited)
   }
 
   // Returns true if 'a' can reach 'b' in the call graph. 'a' can reach 'b' if
   // 'a' is 'b' or 'a' can reach one of the callers of 'b'.
-  pub fn can_reach(&self, a: &HloComputation, b: &HloComputation) -> bool {
+  pub fn can_reach(&self, a: ComputationId, b: ComputationId) -> bool {
     if a == b {
       return true;
     }
     let b_node = self.get_node(b);
     for b_caller in b_node.callers() {
-      if self.can_reach(a, b_caller) { return true; }
+      if self.can_reach(a, *b_caller) { return true; }
     }
     false
   }
@@ -362,16 +472,16 @@ impl<'module> CallGraph<'module> {
   // ('instruction->parent' is 'computation') or indirectly ('computation'
   // dominates 'instruction->parent' in the call graph).
   pub fn instruction_is_nested_in(
-    &self, instruction: &HloInstruction, computation: &HloComputation) -> bool
+    &self, instruction: &HloInstruction, computation: ComputationId) -> bool
   {
-    self.dominates(computation, instruction.parent())
+    self.dominates(computation, instruction.parent().unwrap())
   }
 
   // Returns the nearest call graph ancestors of instructions 'a' and 'b' for
   // which the ancestors are in the same computation. An instruction is an call
   // graph ancestor of 'a' if the instruction calls the computation containing
   // 'a' either directly or transitively. Degeneratively an instruction is an
-  // ancestor of itself. nullptr is returned if there is no common ancestor or
+  // ancestor of itself. None is returned if there is no common ancestor or
   // if the caller chain of 'a' or 'b' diverges (has multiple callers) before
   // the nearest common ancestor.
   //
@@ -392,60 +502,64 @@ impl<'module> CallGraph<'module> {
   // computation.
   pub fn nearest_ancestors_in_same_computation(
     &self,
-    a: &HloInstruction,
-    b: &HloInstruction) -> (Option<&HloInstruction>, Option<&HloInstruction>)
+    a: (ComputationId, InstructionId),
+    b: (ComputationId, InstructionId)
+  ) -> (Option<(ComputationId, InstructionId)>, Option<(ComputationId, InstructionId)>)
   {
     // Lambda which returns the next instruction in the callee->caller chain in
     // the call graph. This is the unique instruction which calls the computation
     // containing 'instruction'. If more than one instruction calls the
     // computation containing 'instruction' or no instructions call the
-    // computation then nullptr is returned.
-    let next_caller =
-      |instruction: Option<&HloInstruction>| -> Option<&HloInstruction>
+    // computation then None is returned.
+    let next_caller = |instruction: (ComputationId, InstructionId)|
+      -> Option<(ComputationId, InstructionId)>
     {
-      let node =
-        self.get_node(instruction.unwrap().parent());
+      let node = self.get_node(instruction.0);
       if node.caller_callsites().len() != 1 {
-        if instruction.unwrap().parent().is_async_computation() {
-          return Some(node.caller_callsites()[0].instruction());
+        if self.module.computation(instruction.0).is_async_computation() &&
+          !node.caller_callsites().is_empty()
+        {
+          let callsite = &node.caller_callsites()[0];
+          return Some((callsite.computation(), callsite.instruction()));
         }
         return None;
       }
-      Some(node.caller_callsites()[0].instruction())
+      let callsite = &node.caller_callsites()[0];
+      Some((callsite.computation(), callsite.instruction()))
     };
 
     // Iterate through the callee->caller chains and find the earliest common
     // element.
-    let mut a_ancestor  = Some(a);
+    let mut a_ancestor = Some(a);
     let mut b_ancestor = Some(b);
-    let a_depth = self.get_node(a.parent()).depth();
-    let b_depth = self.get_node(b.parent()).depth();
+    let a_depth = self.get_node(a.0).depth();
+    let b_depth = self.get_node(b.0).depth();
 
     // Advance a_ancestor (b_ancestor) up the call chain until the call depth of
     // a_ancestor or b_ancestor are the same. Necessarily each call to next_caller
     // reduces the depth by exactly one.
     if a_depth > b_depth {
       for _i in 0..a_depth-b_depth {
-        a_ancestor = next_caller(a_ancestor);
+        a_ancestor = next_caller(a_ancestor.unwrap());
         if a_ancestor.is_none() {
           return (None, None);
         }
       }
     } else if b_depth > a_depth {
       for _i in 0..b_depth-a_depth {
-        b_ancestor = next_caller(b_ancestor);
+        b_ancestor = next_caller(b_ancestor.unwrap());
         if b_ancestor.is_none() {
           return (None, None);
         }
       }
     }
 
-    while a_ancestor.is_some() && b_ancestor.is_some() {
-      if a_ancestor.unwrap().parent() == b_ancestor.unwrap().parent() {
-        //return (a_ancestor, b_ancestor);
+    while let (Some(a_current), Some(b_current)) = (a_ancestor, b_ancestor) {
+      if a_current.0 == b_current.0 {
+        return (a_ancestor, b_ancestor);
       }
-      a_ancestor = next_caller(a_ancestor);
-      b_ancestor = next_caller(b_ancestor);
+      a_ancestor = next_caller(a_current);
+      b_ancestor = next_caller(b_current);
     }
 
     (None, None)
@@ -476,23 +590,14 @@ impl<'module> CallGraph<'module> {
   //   %sub0 = Sub(%add0, %mul0)
   //
   // If called with {%p0, %p1}, this function would return {%add0}.
-  //
-  // Please check the detailed example in
-  // `CallGraphTest.NearestCommonAncestorInstructions`.
   pub fn nearest_common_ancestor_instructions(
-    &self, instructions: &Vec<HloInstruction>) -> HashSet<HloInstruction>
+    &self,
+    computation: ComputationId,
+    instructions: &Vec<InstructionId>) -> HashSet<InstructionId>
   {
-    if instructions.is_empty() {
-      return HashSet::new();
-    }
-
-    // Check if all the instructions belong to the same computation.
-    let computation = instructions[0].parent();
-    for instr in instructions {
-      assert_eq!(instr.parent(), computation);
-    }
-
-    self.nearest_common_ancestors_helper(instructions)
+    let computation = self.module.computation(computation);
+    self.nearest_common_ancestors_helper(instructions,
+      |instruction| computation.instruction(instruction).users().clone())
   }
 
   // Given a set of computations within a module, returns nearest common
@@ -511,22 +616,19 @@ impl<'module> CallGraph<'module> {
   //
   // If called with {Computation_A, Computation_B}, this function would return
   // {Entry_computation}.
-  //
-  // Please check the detailed example in
-  // `CallGraphTest.NearestCommonAncestorComputations`.
   pub fn nearest_common_ancestor_computations(
-    &self, computations: &Vec<HloComputation>) -> HashSet<HloComputation>
+    &self, computations: &Vec<ComputationId>) -> HashSet<ComputationId>
   {
-    self.nearest_common_ancestors_helper(computations)
+    self.nearest_common_ancestors_helper(computations,
+      |computation| self.get_node(computation).callers().clone())
   }
 
-  // A template helper function that computes the nearest common ancestors among
-  // instructions/computations. `T` can be either `HloInstruction` or
-  // `HloComputation`. Computing nearest common ancestors are basically the same
-  // for HloInstruction and HloComputation. The only difference is that they
-  // require different ways to access the ancestors of one node. Specifically,
-  // the ancestors are users_instruction for instructions, and are
-  // caller_computations for computations.
+  // A helper function that computes the nearest common ancestors among
+  // instructions/computations. Computing nearest common ancestors are basically
+  // the same for HloInstruction and HloComputation. The only difference is
+  // that they require different ways to access the ancestors of one node.
+  // Specifically, the ancestors are users_instruction for instructions, and
+  // are caller_computations for computations.
   //
   // The overall idea is to conduct BFS from the `starting_nodes`, and keep
   // track of the visited ancestors of each node. For each BFS step, we check if
@@ -535,104 +637,63 @@ impl<'module> CallGraph<'module> {
   // are traversing DAG, there could be multiple nearest common ancestors. And
   // there must be at least one common ancestor (i.e., entry computations among
   // computations or root instruction among instructions).
-  pub fn nearest_common_ancestors_helper<T>(
-    &self, starting_nodes: &Vec<T>) -> HashSet<T> where T: Clone + Eq + Hash
+  pub fn nearest_common_ancestors_helper<T, F>(
+    &self, starting_nodes: &Vec<T>, ancestors: F) -> HashSet<T>
+    where T: Copy + Eq + Hash, F: Fn(T) -> Vec<T>
   {
     if starting_nodes.is_empty() {
       return HashSet::new();
     }
     if starting_nodes.len() == 1 {
       let mut set = HashSet::new();
-      set.insert(starting_nodes[0].clone());
+      set.insert(starting_nodes[0]);
       return set;
     }
 
-    // There could be multiple nearest common ancestors in a DAG.
-    let mut nearest_common_ancestors = HashSet::new();
-
-    // Initialize `visited_ancestors` for each provided nodes.
-    let mut visited_ancestors = Vec::new();
-    for i in 0..starting_nodes.len() {
-      let mut set = HashSet::new();
-      set.insert(starting_nodes[i].clone());
-      visited_ancestors.push(set);
-    }
-
-    // Initialize BFS queue for each provided nodes.
-    let mut bfs_queues = Vec::new();
-    for i in 0..starting_nodes.len() {
-      let mut deque: VecDeque<T> = VecDeque::new();
-      deque.push_back(starting_nodes[i].clone());
-      bfs_queues.push(deque);
-    }
-
-    // Lambda to check if the BFS has finished (i.e., all queues in `bfs_queues`
-    // are empty).
-    let is_bfs_finished = || -> bool {
-      for q in &bfs_queues {
-        if !q.is_empty() { return false; }
-      }
-      true
-    };
-
-    // Lambda to check if there are common nodes in all the
-    // `visited_ancestors`. Save results in `nearest_common_ancestors`. Return
-    // true if they are found, otherwise return false.
-    let mut find_common_nodes = || -> bool {
-      let common_nodes = &visited_ancestors[0];
-      for i in 1..visited_ancestors.len() {
-        for k in common_nodes {
-          if visited_ancestors[i].contains(k) {
-            //common_nodes.remove(k);
-          }
-        }
+    // Initialize `visited_ancestors` and the BFS queue for each provided
+    // nodes.
+    let mut visited_ancestors: Vec<HashSet<T>> = starting_nodes.iter()
+      .map(|node| HashSet::from([*node])).collect();
+    let mut bfs_queues: Vec<VecDeque<T>> = starting_nodes.iter()
+      .map(|node| VecDeque::from([*node])).collect();
+
+    // Returns the nodes that are visited from every starting node.
+    let find_common_nodes = |visited_ancestors: &Vec<HashSet<T>>| -> HashSet<T> {
+      let mut common_nodes = visited_ancestors[0].clone();
+      for visited in &visited_ancestors[1..] {
+        common_nodes.retain(|node| visited.contains(node));
       }
-      nearest_common_ancestors = common_nodes.clone();
-      !nearest_common_ancestors.is_empty()
+      common_nodes
     };
 
     // BFS body.
     // For each BFS step, we check if there is a common node in all the visited
-    // ancestors (`find_common_nodes()`), and if yes, that common node is the
-    // nearest ancestor we are looking for. Otherwise, we conduct BFS from each
-    // bfs_queue, and update `bfs_queues` and `visited_ancestors` accordingly.
-    while !is_bfs_finished() && !find_common_nodes() {
-      /*
+    // ancestors, and if yes, that common node is the nearest ancestor we are
+    // looking for. Otherwise, we conduct BFS from each bfs_queue, and update
+    // `bfs_queues` and `visited_ancestors` accordingly.
+    let mut nearest_common_ancestors = find_common_nodes(&visited_ancestors);
+    while nearest_common_ancestors.is_empty() &&
+      bfs_queues.iter().any(|q| !q.is_empty())
+    {
       for i in 0..bfs_queues.len() {
-        let cur_queue = &mut bfs_queues[i];
-        let mut next_queue: VecDeque<T> = VecDeque::new();
-        let visited_ancestor = &mut visited_ancestors[i];
-
-        while !cur_queue.is_empty() {
-          let node = cur_queue.pop_back();
-          // Identify ancestor of node.
-          let ancestors_to_visit: Vec<T> = Vec::new();
-
-          for ancestor in &ancestors_to_visit {
-            if !visited_ancestor.contains(ancestor) {
-              next_queue.push_back(ancestor.clone());
-              visited_ancestor.insert(ancestor.clone());
+        let mut next_queue = VecDeque::new();
+        while let Some(node) = bfs_queues[i].pop_front() {
+          for ancestor in ancestors(node) {
+            if visited_ancestors[i].insert(ancestor) {
+              next_queue.push_back(ancestor);
             }
           }
         }
-        //bfs_queues[i] = next_queue;
+        bfs_queues[i] = next_queue;
       }
-      */
+      nearest_common_ancestors = find_common_nodes(&visited_ancestors);
     }
 
-    assert!(!nearest_common_ancestors.is_empty());
-
     // If one of the computed nearest common ancestors is inside
     // `starting_nodes`, we would only return the ones that are inside
     // `starting_nodes`.
-    for nca in starting_nodes {
-      if nearest_common_ancestors.contains(nca) {
-        for nca in &nearest_common_ancestors {
-          if !starting_nodes.contains(nca) {
-            //nearest_common_ancestors.remove(nca);
-          }
-        }
-      }
+    if starting_nodes.iter().any(|node| nearest_common_ancestors.contains(node)) {
+      nearest_common_ancestors.retain(|node| starting_nodes.contains(node));
     }
     nearest_common_ancestors
   }
@@ -644,7 +705,7 @@ impl<'module> CallGraph<'module> {
         return false;
       }
       if node.context() == CallContext::ControlFlow &&
-        !node.computation().is_async_computation() &&
+        !self.module.computation(node.computation()).is_async_computation() &&
          node.caller_callsites().len() > 1
       {
         return false;
@@ -654,10 +715,12 @@ impl<'module> CallGraph<'module> {
   }
 
   // Returns a vector of instructions calling the passed computation.
-  pub fn get_computation_callers(&self, c: &HloComputation) -> Vec<HloInstruction> {
+  pub fn get_computation_callers(
+    &self, c: ComputationId) -> Vec<(ComputationId, InstructionId)>
+  {
     let mut callers = Vec::new();
     for callsite in self.get_node(c).caller_callsites() {
-      callers.push(callsite.instruction().clone())
+      callers.push((callsite.computation(), callsite.instruction()))
     }
     callers
   }
@@ -669,24 +732,24 @@ impl<'module> CallGraph<'module> {
 
     for node in self.nodes() {
       out.push_str("Computation ");
-      out.push_str(&node.computation().name());
+      out.push_str(&node.to_string(self.module));
       out.push_str(":\n");
       out.push_str("  calls:\n");
       for callee in node.callees() {
         out.push_str("    ");
-        out.push_str(&callee.name());
+        out.push_str(&self.module.computation(*callee).name());
         out.push_str("\n");
       }
       out.push_str("  called by:\n");
       for caller in node.callers() {
         out.push_str("    ");
-        out.push_str(&caller.name());
+        out.push_str(&self.module.computation(*caller).name());
         out.push_str("\n");
       }
       out.push_str("  callsites:\n");
       for callsite in node.callsites() {
         out.push_str("    ");
-        out.push_str(&callsite.to_string());
+        out.push_str(&callsite.to_string(self.module));
         out.push_str("\n");
       }
     }
@@ -698,13 +761,19 @@ impl<'module> CallGraph<'module> {
   // caller) calling visitor_func on each node.
   fn visit_nodes_internal<F>(
     &self,
-    _visitor_func: &F,
-    _node: &CallGraphNode,
-    _visited: &HashSet<CallGraphNode>
+    visitor_func: &mut F,
+    node: &CallGraphNode,
+    visited: &mut HashSet<ComputationId>
   ) -> Result<(), String>
-    where F: Fn(&CallGraphNode) -> Result<(), String>
+    where F: FnMut(&CallGraphNode) -> Result<(), String>
   {
-    
-    Ok(())
+    if !visited.insert(node.computation()) {
+      // Node is already visited.
+      return Ok(());
+    }
+    for callee in node.callees() {
+      self.visit_nodes_internal(visitor_func, self.get_node(*callee), visited)?;
+    }
+    visitor_func(node)
   }
-}
\ No newline at end of file
+}
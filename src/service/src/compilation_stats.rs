@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Instant};
 
 #[derive(Debug, Clone)]
 struct PassInfo {
@@ -22,32 +22,54 @@ impl PassInfo {
 // This class is used to collect information about HLO passes and print some
 // statistics at the end of compilation.
 pub struct CompilationStats {
+  noop: bool,
   passes: Vec<PassInfo>,
   pass_running: bool,
   current_pass: String,
-  start_micros: u64
+  start_time: Option<Instant>
 }
 
 impl CompilationStats {
-  pub fn make_noop_stats() {}
-  pub fn make_stats() {}
+  // Returns a stats object that ignores every call, so callers that do not
+  // care about statistics pay nothing for them.
+  pub fn make_noop_stats() -> Self {
+    let mut stats = CompilationStats::make_stats();
+    stats.noop = true;
+    stats
+  }
+
+  pub fn make_stats() -> Self {
+    CompilationStats {
+      noop: false,
+      passes: Vec::new(),
+      pass_running: false,
+      current_pass: "".to_string(),
+      start_time: None
+    }
+  }
 
   pub fn start_pass(&mut self, pass_name: String) {
+    if self.noop { return; }
     assert!(!self.pass_running, "Can's start {:?} while running {:?}",
       pass_name, self.current_pass);
     self.pass_running = true;
     self.current_pass = pass_name;
-    // TODO: start_micros 
+    self.start_time = Some(Instant::now());
   }
 
   pub fn end_pass(&mut self, pass_name: String) {
+    if self.noop { return; }
     assert!(self.pass_running);
     assert_eq!(self.current_pass, pass_name);
     self.pass_running = false;
-    // TODO: end_micros
+    // Instant is monotonic, so the duration can not be negative.
+    let duration_ms = self.start_time.take()
+      .map(|start| start.elapsed().as_secs_f64() * 1000.0).unwrap_or(0.0);
+    self.passes.push(PassInfo::new(pass_name, duration_ms));
   }
 
   pub fn compilation_report(&self) {
+    if self.noop { return; }
     assert!(!self.pass_running, "end_pass never called for {:?}", self.current_pass);
     let mut summary: HashMap<String, PassInfo> = HashMap::new();
     let mut total_duration = 0.0;
@@ -78,5 +100,18 @@ impl CompilationStats {
     self.passes.len()
   }
 
-  pub fn record_pass_error() {}
+  // Closes the currently running pass after it returned an error, so that the
+  // stats stay consistent and the report can still be printed.
+  pub fn record_pass_error(&mut self, pass_name: String, err: &String) {
+    if self.noop { return; }
+    if self.pass_running && self.current_pass == pass_name {
+      self.pass_running = false;
+      self.start_time = None;
+    }
+    println!("Pass {:?} failed: {}", pass_name, err);
+  }
+
+  pub fn pass_names(&self) -> Vec<String> {
+    self.passes.iter().map(|pass_info| pass_info.name.clone()).collect()
+  }
 }
\ No newline at end of file
@@ -1,12 +1,18 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use common::blitz_data::{DebugOptions, Precision};
 use hlo::hlo_module::HloModule;
 use stream_executor::{device_memory_allocator::DeviceMemoryAllocator, platform::Platform, stream_executor::StreamExecutor};
 
-use crate::{computation_placer::DeviceAssignment, executable::Executable};
+use crate::{
+  computation_placer::DeviceAssignment,
+  dump::dump_hlo_module_if_enabled,
+  executable::Executable,
+  hlo_pass_interface::HloPassInterface,
+  hlo_pass_pipeline::HloPassPipeline
+};
 
 // Abstract superclass describing the result of an ahead-of-time compilation.
 pub struct AotCompilationResult {}
@@ -70,7 +76,20 @@ impl Compiler {
     unimplemented!()
   }
 
-  pub fn run_hlo_passes() {}
+  // Runs the HLO passes of 'pipeline' on 'module'. The module is dumped
+  // before and after the optimizations if dumping is enabled for it. Returns
+  // whether any pass changed the module.
+  pub fn run_hlo_passes(
+    &self,
+    module: &mut HloModule,
+    pipeline: &mut HloPassPipeline) -> Result<bool, String>
+  {
+    dump_hlo_module_if_enabled(module, "before_optimizations")?;
+    let changed = pipeline.run(module, &HashSet::new())?;
+    dump_hlo_module_if_enabled(module, "after_optimizations")?;
+    Ok(changed)
+  }
+
   pub fn assign_buffers() {}
   pub fn run_backend() {}
   pub fn run_backend_with_buffer_assignment() {}
@@ -247,4 +266,33 @@ impl AotCompilationOptions {
   pub fn set_target_config(&mut self, target_config: TargetConfig) {
     self.target_config = Some(target_config);
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{fs, path::Path};
+
+  use hlo::hlo_module_config::HloModuleConfig;
+  use super::*;
+
+  #[test]
+  fn test_run_hlo_passes_dumps_module() {
+    let dir = std::env::temp_dir().join("blitz_compiler_run_hlo_passes");
+    let _ = fs::remove_dir_all(&dir);
+    let mut debug_options = DebugOptions::new();
+    debug_options.set_blitz_dump_to(dir.to_str().unwrap().to_string());
+    debug_options.set_blitz_dump_hlo_as_text(true);
+    let mut config = HloModuleConfig::new_default();
+    config.set_debug_options(debug_options);
+    let mut module = HloModule::new("compiled".to_string(), config);
+
+    let compiler = Compiler {};
+    let mut pipeline = HloPassPipeline::new("optimizations".to_string(), None);
+    assert!(!compiler.run_hlo_passes(&mut module, &mut pipeline).unwrap());
+    for name in ["before_optimizations", "after_optimizations"] {
+      let file_name = format!("module_{:04}.compiled.{}.txt", module.unique_id(), name);
+      assert!(Path::new(&dir).join(file_name).exists());
+    }
+    let _ = fs::remove_dir_all(&dir);
+  }
+}
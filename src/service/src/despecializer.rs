@@ -38,9 +38,9 @@ impl ControlDepRemover {
   {
     let mut changed = false;
     for computation in module.mutable_computations() {
-      for instruction in computation.mutable_instructions() {
-        changed |= !instruction.control_successors().is_empty();
-        let result = instruction.drop_all_control_deps();
+      for instruction in computation.instruction_ids() {
+        changed |= !computation.instruction(instruction).control_successors().is_empty();
+        let result = computation.drop_all_control_deps(instruction);
         if result.is_err() { return Err(result.err().unwrap()); }
       }
     }
@@ -32,7 +32,7 @@ impl DotMerger {
   {
     let mut changed = false;
     for comp in module.make_nonfusion_computations(execution_threads) {
-      let result = self.merge_dots(comp);
+      let result = self.merge_dots(module.computation(comp));
       if result.is_err() { return Err(result.err().unwrap()); }
       changed |= result.ok().unwrap();
     }
@@ -0,0 +1,508 @@
+#![allow(dead_code)]
+
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fs,
+  io::Write,
+  path::Path,
+  sync::Mutex,
+  time::{SystemTime, UNIX_EPOCH}
+};
+
+use common::blitz_data::DebugOptions;
+use flate2::{write::GzEncoder, Compression};
+use hlo::hlo_module::HloModule;
+use regex::Regex;
+
+use crate::{
+  hlo_graph_dumper::{render_graph, HloRenderOptions, RenderedGraphFormat},
+  hlo_proto::{hlo_proto_from_module_proto, HloSnapshot}
+};
+
+// Number of files dumped in between passes for each module, keyed by the
+// module's unique id. Used to order the dumps.
+static STEP_NUMBERS: Mutex<BTreeMap<i64, i64>> = Mutex::new(BTreeMap::new());
+
+// Number of snapshots dumped for each module, keyed by the module's unique id.
+static EXECUTION_COUNTS: Mutex<BTreeMap<i64, i64>> = Mutex::new(BTreeMap::new());
+
+// Time at which each module was first dumped, keyed by the module's unique
+// id. Used as the file name prefix with --blitz_dump_include_timestamp.
+static TIMESTAMPS: Mutex<BTreeMap<i64, u64>> = Mutex::new(BTreeMap::new());
+
+// Unique ids of the modules dumped so far, used to honor
+// --blitz_dump_max_hlo_modules.
+static DUMPED_MODULES: Mutex<BTreeSet<i64>> = Mutex::new(BTreeSet::new());
+
+// The dumping related fields of DebugOptions, with the implied settings
+// filled in:
+//
+//  * Setting --blitz_dump_to without any format dumps as text.
+//  * Setting a format, --blitz_dump_hlo_module_re or --blitz_dump_hlo_pass_re
+//    without --blitz_dump_to dumps to stdout.
+struct CanonicalDebugOptions {
+  dump_to: String,
+  dump_as_text: bool,
+  dump_as_proto: bool,
+  dump_as_dot: bool,
+  dump_as_html: bool,
+  dump_as_url: bool,
+  dump_snapshots: bool,
+  dump_include_timestamp: bool,
+  dump_max_hlo_modules: i64,
+  dump_compress_protos: bool,
+  module_re: String,
+  pass_re: String
+}
+
+impl CanonicalDebugOptions {
+  fn new(opts: &DebugOptions) -> Self {
+    let mut dump_to = opts.blitz_dump_to();
+    let mut dump_as_text = opts.blitz_dump_hlo_as_text();
+    let output_format_other_than_url_specified = opts.blitz_dump_hlo_as_text() ||
+      opts.blitz_dump_hlo_as_proto() || opts.blitz_dump_hlo_as_dot() ||
+      opts.blitz_dump_hlo_as_html() || opts.blitz_dump_hlo_snapshots();
+    let output_format_specified =
+      output_format_other_than_url_specified || opts.blitz_dump_hlo_as_url();
+
+    let module_re = opts.blitz_dump_hlo_module_re();
+    let pass_re = opts.blitz_dump_hlo_pass_re();
+    if dump_to.is_empty() &&
+       (output_format_specified || !module_re.is_empty() || !pass_re.is_empty())
+    {
+      dump_to = "-".to_string();
+    }
+    if !dump_to.is_empty() && !output_format_other_than_url_specified {
+      dump_as_text = true;
+    }
+
+    CanonicalDebugOptions {
+      dump_to: dump_to,
+      dump_as_text: dump_as_text,
+      dump_as_proto: opts.blitz_dump_hlo_as_proto(),
+      dump_as_dot: opts.blitz_dump_hlo_as_dot(),
+      dump_as_html: opts.blitz_dump_hlo_as_html(),
+      dump_as_url: opts.blitz_dump_hlo_as_url(),
+      dump_snapshots: opts.blitz_dump_hlo_snapshots(),
+      dump_include_timestamp: opts.blitz_dump_include_timestamp(),
+      dump_max_hlo_modules: opts.blitz_dump_max_hlo_modules(),
+      dump_compress_protos: opts.blitz_dump_compress_protos(),
+      module_re: module_re,
+      pass_re: pass_re
+    }
+  }
+
+  fn dumping_enabled(&self) -> bool {
+    !self.dump_to.is_empty()
+  }
+
+  fn dumping_to_stdout(&self) -> bool {
+    self.dump_to == "-"
+  }
+
+  fn should_dump_module(&self, module_name: &str) -> bool {
+    self.dumping_enabled() &&
+      (self.module_re.is_empty() || matches_re(&self.module_re, module_name))
+  }
+
+  fn should_dump_pass(&self, pass_name: &str) -> bool {
+    self.dumping_enabled() &&
+      !self.pass_re.is_empty() && matches_re(&self.pass_re, pass_name)
+  }
+}
+
+// Returns true if 'name' matches the regular expression 're'. An invalid
+// expression never matches.
+fn matches_re(re: &String, name: &str) -> bool {
+  match Regex::new(re) {
+    Ok(regex) => regex.is_match(name),
+    Err(_) => false
+  }
+}
+
+// Returns true if modules named 'module_name' should be dumped, i.e. if
+// dumping is enabled and --blitz_dump_hlo_module_re is unset or matches the
+// name.
+pub fn should_dump_module(debug_options: &DebugOptions, module_name: &str) -> bool {
+  CanonicalDebugOptions::new(debug_options).should_dump_module(module_name)
+}
+
+// Returns true if the HLO before and after the pass named 'pass_name' should
+// be dumped, i.e. if --blitz_dump_hlo_pass_re matches the name.
+pub fn should_dump_pass(debug_options: &DebugOptions, pass_name: &str) -> bool {
+  CanonicalDebugOptions::new(debug_options).should_dump_pass(pass_name)
+}
+
+// Returns true if 'module' should be dumped at all.
+pub fn dumping_enabled_for_hlo_module(module: &HloModule) -> bool {
+  should_dump_module(module.config().debug_options(), &module.name())
+}
+
+// Replaces the characters which are not allowed in file names.
+pub fn sanitize_file_name(file_name: String) -> String {
+  file_name.replace(['/', '\\', '[', ']', ' '], "_")
+}
+
+// Returns the file name, without extension, used to dump 'module' with
+// 'suffix', e.g. "module_0001.name.suffix".
+pub fn filename_for(module: &HloModule, prefix: &str, suffix: &str) -> String {
+  format!("{}{}module_{:04}.{}.{}", prefix, if prefix.is_empty() { "" } else { "." },
+    module.unique_id(), module.name(), suffix)
+}
+
+fn next_count(counts: &Mutex<BTreeMap<i64, i64>>, module: &HloModule) -> i64 {
+  let mut counts = counts.lock().unwrap();
+  let count = counts.entry(module.unique_id()).or_insert(0);
+  let current = *count;
+  *count += 1;
+  current
+}
+
+fn timestamp_for(module: &HloModule, opts: &CanonicalDebugOptions) -> String {
+  if !opts.dump_include_timestamp {
+    return "".to_string();
+  }
+  let now = SystemTime::now().duration_since(UNIX_EPOCH)
+    .map(|d| d.as_micros() as u64).unwrap_or(0);
+  let mut timestamps = TIMESTAMPS.lock().unwrap();
+  timestamps.entry(module.unique_id()).or_insert(now).to_string()
+}
+
+// Returns false if 'module' would exceed --blitz_dump_max_hlo_modules.
+fn within_module_limit(module: &HloModule, opts: &CanonicalDebugOptions) -> bool {
+  if opts.dump_max_hlo_modules <= 0 {
+    return true;
+  }
+  let mut dumped_modules = DUMPED_MODULES.lock().unwrap();
+  if dumped_modules.contains(&module.unique_id()) {
+    return true;
+  }
+  if dumped_modules.len() as i64 >= opts.dump_max_hlo_modules {
+    println!("Have already dumped {} modules, more than the limit of {}.",
+      dumped_modules.len(), opts.dump_max_hlo_modules);
+    return false;
+  }
+  dumped_modules.insert(module.unique_id());
+  true
+}
+
+fn gzip(contents: &[u8]) -> Result<Vec<u8>, String> {
+  let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+  if let Err(e) = encoder.write_all(contents) {
+    return Err(format!("Could not compress dump: {}", e));
+  }
+  match encoder.finish() {
+    Ok(compressed) => Ok(compressed),
+    Err(e) => Err(format!("Could not compress dump: {}", e))
+  }
+}
+
+// Writes 'contents' to the file 'file_name' under --blitz_dump_to and returns
+// the path of the file.
+fn dump_to_file_in_dir(
+  opts: &CanonicalDebugOptions,
+  file_name: String,
+  contents: &[u8],
+  compress: bool) -> Result<String, String>
+{
+  let file_name = sanitize_file_name(file_name);
+  if let Err(e) = fs::create_dir_all(&opts.dump_to) {
+    return Err(format!("Could not create directory {} for dumping: {}", opts.dump_to, e));
+  }
+  let path = Path::new(&opts.dump_to).join(&file_name);
+  let result = if compress {
+    fs::write(&path, gzip(contents)?)
+  } else {
+    fs::write(&path, contents)
+  };
+  if let Err(e) = result {
+    return Err(format!("Could not write {}: {}", path.display(), e));
+  }
+  Ok(path.display().to_string())
+}
+
+// Writes the text 'contents' to the file 'file_name' under --blitz_dump_to,
+// or to stdout if dumping to stdout. Returns the path of the file, if any.
+fn dump_to_file_in_dir_or_stdout(
+  opts: &CanonicalDebugOptions,
+  file_name: String,
+  contents: &String) -> Result<Option<String>, String>
+{
+  if opts.dumping_to_stdout() {
+    let file_name = sanitize_file_name(file_name);
+    println!("*** Begin {} ***\n{}\n*** End {} ***", file_name, contents, file_name);
+    return Ok(None);
+  }
+  Ok(Some(dump_to_file_in_dir(opts, file_name, contents.as_bytes(), false)?))
+}
+
+// Dumps 'module' in every enabled format, with file names ending in
+// 'suffix'. Returns the paths of the files written.
+fn dump_hlo_module_impl(
+  module: &HloModule,
+  prefix: &str,
+  suffix: &str,
+  opts: &CanonicalDebugOptions) -> Result<Vec<String>, String>
+{
+  if !within_module_limit(module, opts) {
+    return Ok(vec![]);
+  }
+  let filename = filename_for(module, prefix, suffix);
+  let mut file_paths = vec![];
+
+  if opts.dump_as_text {
+    let file_path = dump_to_file_in_dir_or_stdout(
+      opts, format!("{}.txt", filename), &module.to_string())?;
+    file_paths.extend(file_path);
+  }
+
+  if opts.dump_as_proto {
+    if opts.dumping_to_stdout() {
+      println!("Cannot dump proto {} to stdout.", filename);
+    } else {
+      let hlo_proto = hlo_proto_from_module_proto(&module.to_proto());
+      if opts.dump_compress_protos {
+        file_paths.push(dump_to_file_in_dir(
+          opts, format!("{}.hlo.pb.gz", filename), &hlo_proto, true)?);
+      } else {
+        file_paths.push(dump_to_file_in_dir(
+          opts, format!("{}.hlo.pb", filename), &hlo_proto, false)?);
+      }
+    }
+  }
+
+  if module.has_entry_computation() {
+    file_paths.extend(dump_graphs(module, &filename, opts)?);
+  }
+
+  Ok(file_paths)
+}
+
+// Renders the entry computation of 'module' in the enabled graph formats.
+// Rendering failures are logged rather than failing the dump.
+fn dump_graphs(
+  module: &HloModule,
+  filename: &str,
+  opts: &CanonicalDebugOptions) -> Result<Vec<String>, String>
+{
+  let mut file_paths = vec![];
+  let formats = [
+    (opts.dump_as_dot, RenderedGraphFormat::Dot, "dot"),
+    (opts.dump_as_html, RenderedGraphFormat::Html, "html"),
+    (opts.dump_as_url, RenderedGraphFormat::Url, "url")
+  ];
+  for (enabled, format, extension) in formats {
+    if !enabled {
+      continue;
+    }
+    let rendered = render_graph(module, module.entry_computation_id(), filename,
+      module.config().debug_options(), format, &HloRenderOptions::new(), None);
+    let rendered = match rendered {
+      Ok(rendered) => rendered,
+      Err(msg) => {
+        println!("Failed to render {} as {}: {}", filename, extension, msg);
+        continue;
+      }
+    };
+    if format == RenderedGraphFormat::Url {
+      println!("{} --> {}", filename, rendered);
+      if opts.dumping_to_stdout() {
+        continue;
+      }
+    }
+    let file_path = dump_to_file_in_dir_or_stdout(
+      opts, format!("{}.{}", filename, extension), &rendered)?;
+    file_paths.extend(file_path);
+  }
+  Ok(file_paths)
+}
+
+// Dumps 'module' with the name 'name' (e.g. "before_optimizations") if
+// dumping is enabled for it. Returns the paths of the files written.
+pub fn dump_hlo_module_if_enabled(
+  module: &HloModule, name: &str) -> Result<Vec<String>, String>
+{
+  let opts = CanonicalDebugOptions::new(module.config().debug_options());
+  if !opts.should_dump_module(&module.name()) {
+    return Ok(vec![]);
+  }
+  dump_hlo_module_impl(module, &timestamp_for(module, &opts), name, &opts)
+}
+
+// Dumps 'module' in between the passes 'after_pass_name' and
+// 'before_pass_name' of the pipeline 'pipeline_name', if
+// --blitz_dump_hlo_pass_re matches either of them.
+pub fn dump_hlo_module_between_passes_if_enabled(
+  pipeline_name: &str,
+  before_pass_name: &str,
+  after_pass_name: &str,
+  module: &HloModule) -> Result<Vec<String>, String>
+{
+  let opts = CanonicalDebugOptions::new(module.config().debug_options());
+  if !opts.should_dump_module(&module.name()) {
+    return Ok(vec![]);
+  }
+  if !opts.should_dump_pass(before_pass_name) && !opts.should_dump_pass(after_pass_name) {
+    return Ok(vec![]);
+  }
+  let step_number = next_count(&STEP_NUMBERS, module);
+  let suffix = format!("{:04}.{}.after_{}.before_{}",
+    step_number, pipeline_name, after_pass_name, before_pass_name);
+  dump_hlo_module_impl(module, &timestamp_for(module, &opts), &suffix, &opts)
+}
+
+// Dumps 'snapshot' of an execution of 'module' if --blitz_dump_hlo_snapshots
+// is set. Every execution gets its own file. Returns the path of the file
+// written, if any.
+pub fn dump_hlo_snapshot_if_enabled(
+  module: &HloModule, snapshot: &HloSnapshot) -> Result<Option<String>, String>
+{
+  let opts = CanonicalDebugOptions::new(module.config().debug_options());
+  if !opts.should_dump_module(&module.name()) || !opts.dump_snapshots {
+    return Ok(None);
+  }
+  let execution_count = next_count(&EXECUTION_COUNTS, module);
+  let filename = format!("{}.pb",
+    filename_for(module, &timestamp_for(module, &opts),
+      &format!("snapshot.{}", execution_count)));
+  if opts.dumping_to_stdout() {
+    println!("Cannot dump HloSnapshot {} as binary to stdout.", filename);
+    return Ok(None);
+  }
+  Ok(Some(dump_to_file_in_dir(&opts, filename, &snapshot.serialize_as_string(), false)?))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Read;
+
+  use common::{blitz_data::PrimitiveType, shape_util::ShapeUtil};
+  use flate2::read::GzDecoder;
+  use hlo::{
+    hlo_computation::HloComputation, hlo_instruction::HloInstruction,
+    hlo_module_config::HloModuleConfig
+  };
+  use super::*;
+
+  fn make_module(name: &str, debug_options: DebugOptions) -> HloModule {
+    let mut config = HloModuleConfig::new_default();
+    config.set_debug_options(debug_options);
+    HloModule::new(name.to_string(), config)
+  }
+
+  fn dump_dir(name: &str) -> String {
+    let dir = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&dir);
+    dir.to_str().unwrap().to_string()
+  }
+
+  #[test]
+  fn test_should_dump_pass() {
+    let mut debug_options = DebugOptions::new();
+    assert!(!should_dump_pass(&debug_options, "dce"));
+    debug_options.set_blitz_dump_hlo_pass_re("dce|cse".to_string());
+    assert!(should_dump_pass(&debug_options, "dce"));
+    assert!(!should_dump_pass(&debug_options, "inliner"));
+    debug_options.set_blitz_dump_hlo_pass_re("(".to_string());
+    assert!(!should_dump_pass(&debug_options, "dce"));
+  }
+
+  #[test]
+  fn test_should_dump_module() {
+    let mut debug_options = DebugOptions::new();
+    assert!(!should_dump_module(&debug_options, "m"));
+    debug_options.set_blitz_dump_hlo_as_text(true);
+    assert!(should_dump_module(&debug_options, "m"));
+    debug_options.set_blitz_dump_hlo_module_re("^foo".to_string());
+    assert!(should_dump_module(&debug_options, "foo_bar"));
+    assert!(!should_dump_module(&debug_options, "bar"));
+  }
+
+  #[test]
+  fn test_sanitize_file_name() {
+    assert_eq!(sanitize_file_name("a/b [c]".to_string()), "a_b__c_".to_string());
+  }
+
+  #[test]
+  fn test_dump_text_and_compressed_proto() {
+    let dir = dump_dir("blitz_dump_text_and_proto");
+    let mut debug_options = DebugOptions::new();
+    debug_options.set_blitz_dump_to(dir.clone());
+    debug_options.set_blitz_dump_hlo_as_text(true);
+    debug_options.set_blitz_dump_hlo_as_proto(true);
+    debug_options.set_blitz_dump_compress_protos(true);
+    let module = make_module("dumped", debug_options);
+
+    let file_paths = dump_hlo_module_if_enabled(&module, "before_optimizations").unwrap();
+    let prefix = format!("module_{:04}.dumped.before_optimizations", module.unique_id());
+    assert_eq!(file_paths, vec![
+      Path::new(&dir).join(format!("{}.txt", prefix)).display().to_string(),
+      Path::new(&dir).join(format!("{}.hlo.pb.gz", prefix)).display().to_string()]);
+    assert_eq!(fs::read_to_string(&file_paths[0]).unwrap(), module.to_string());
+
+    let mut decoder = GzDecoder::new(fs::File::open(&file_paths[1]).unwrap());
+    let mut hlo_proto = vec![];
+    decoder.read_to_end(&mut hlo_proto).unwrap();
+    assert_eq!(hlo_proto, hlo_proto_from_module_proto(&module.to_proto()));
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn test_dump_module_re_filters() {
+    let dir = dump_dir("blitz_dump_module_re");
+    let mut debug_options = DebugOptions::new();
+    debug_options.set_blitz_dump_to(dir.clone());
+    debug_options.set_blitz_dump_hlo_module_re("^keep".to_string());
+    let kept = make_module("keep_me", debug_options.clone());
+    let skipped = make_module("skip_me", debug_options);
+    assert_eq!(dump_hlo_module_if_enabled(&kept, "after_optimizations").unwrap().len(), 1);
+    assert!(dump_hlo_module_if_enabled(&skipped, "after_optimizations").unwrap().is_empty());
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn test_dump_graphs() {
+    let dir = dump_dir("blitz_dump_graphs");
+    let mut debug_options = DebugOptions::new();
+    debug_options.set_blitz_dump_to(dir.clone());
+    debug_options.set_blitz_dump_hlo_as_dot(true);
+    debug_options.set_blitz_dump_hlo_as_html(true);
+    debug_options.set_blitz_dump_hlo_as_url(true);
+    let mut module = make_module("graphs", debug_options);
+    let shape = ShapeUtil::make_shape(&PrimitiveType::F32, vec![]);
+    let mut computation = HloComputation::new("entry".to_string());
+    let p0 = computation.add_parameter(
+      HloInstruction::create_parameter(0, &shape, "p0".to_string()));
+    computation.set_root_instruction(p0, false);
+    module.add_entry_computation(computation);
+
+    // No URL renderer is registered, so only the dot and html files are
+    // written.
+    let file_paths = dump_hlo_module_if_enabled(&module, "after_optimizations").unwrap();
+    assert_eq!(file_paths.len(), 2);
+    assert!(file_paths[0].ends_with(".after_optimizations.dot"));
+    assert!(fs::read_to_string(&file_paths[0]).unwrap().starts_with("digraph G {"));
+    assert!(file_paths[1].ends_with(".after_optimizations.html"));
+    assert!(fs::read_to_string(&file_paths[1]).unwrap().contains("<svg"));
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn test_dump_snapshots() {
+    let dir = dump_dir("blitz_dump_snapshots");
+    let mut debug_options = DebugOptions::new();
+    debug_options.set_blitz_dump_to(dir.clone());
+    debug_options.set_blitz_dump_hlo_snapshots(true);
+    let module = make_module("snapshotted", debug_options);
+    let mut snapshot = HloSnapshot::new();
+    snapshot.set_hlo(hlo_proto_from_module_proto(&module.to_proto()));
+    snapshot.set_execution_platform("Host".to_string());
+
+    let first = dump_hlo_snapshot_if_enabled(&module, &snapshot).unwrap().unwrap();
+    let second = dump_hlo_snapshot_if_enabled(&module, &snapshot).unwrap().unwrap();
+    assert!(first.ends_with(&format!("module_{:04}.snapshotted.snapshot.0.pb", module.unique_id())));
+    assert!(second.ends_with(&format!("module_{:04}.snapshotted.snapshot.1.pb", module.unique_id())));
+    assert_eq!(fs::read(&first).unwrap(), snapshot.serialize_as_string());
+    let _ = fs::remove_dir_all(&dir);
+  }
+}
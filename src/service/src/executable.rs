@@ -217,7 +217,8 @@ impl Executable {
   }
 
   pub fn dumping_snapshot(&self) -> bool {
-    unimplemented!()
+    self.hlo_module.is_some() &&
+      self.module_config().debug_options().blitz_dump_hlo_snapshots()
   }
 
   pub fn debug_info(&self) -> &String {
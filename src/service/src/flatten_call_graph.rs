@@ -3,9 +3,8 @@
 use std::collections::HashSet;
 
 use hlo::{
-  hlo_computation::HloComputation,
   hlo_instruction::HloInstruction,
-  hlo_module::HloModule,
+  hlo_module::{ComputationId, HloModule},
   hlo_opcode::HloOpcode,
   utils::hlo_query::is_collective_communication_op
 };
@@ -47,12 +46,18 @@ impl FlattenCallGraph {
     }
 
     // Annotate flattened computations with callee types.
-    let call_graph = CallGraph::build(module, execution_threads);
-    let result =
-      call_graph.visit_nodes(annotate_node, true);
+    let mut while_bodies = Vec::new();
+    {
+      let call_graph = CallGraph::build(module, execution_threads);
+      let result = call_graph.visit_nodes(
+        |node| annotate_node(module, node, &mut while_bodies), true);
       if result.is_err() {
         return Err(result.err().unwrap());
       }
+    }
+    for while_body in while_bodies {
+      module.mutable_computation(while_body).set_while_call_instruction(true);
+    }
 
     println!("After flatten call graph: {:?}", module.to_string());
     Ok(true)
@@ -65,8 +70,8 @@ impl FlattenCallGraph {
 // 'computation' more than once.
 pub fn replace_called_computation(
   instruction: &mut HloInstruction,
-  computation: &HloComputation,
-  new_computation: HloComputation)
+  computation: ComputationId,
+  new_computation: ComputationId)
 {
   let opcode = instruction.opcode();
   match opcode {
@@ -125,10 +130,17 @@ pub fn flatten_node(_node: &CallGraphNode) -> Result<(), String> {
   Ok(())
 }
 
-// Annotates flatten computations with callee instruction types.
-pub fn annotate_node(node: &CallGraphNode) -> Result<(), String> {
+// Annotates flatten computations with callee instruction types. While bodies
+// are collected into 'while_bodies' since the module can't be mutated while
+// the call graph is alive.
+pub fn annotate_node(
+  module: &HloModule,
+  node: &CallGraphNode,
+  while_bodies: &mut Vec<ComputationId>) -> Result<(), String>
+{
   for callsite in node.callsites() {
-    let instruction = callsite.instruction();
+    let instruction =
+      module.instruction(callsite.computation(), callsite.instruction());
     if instruction.opcode() == HloOpcode::Fusion {
 
     } else if instruction.opcode() == HloOpcode::CustomCall {
@@ -136,7 +148,7 @@ pub fn annotate_node(node: &CallGraphNode) -> Result<(), String> {
     } else if is_collective_communication_op(&instruction.opcode()) {
         
     } else if instruction.opcode() == HloOpcode::While {
-      //instruction.mutable_while_body().set_while_call_instruction(instruction);
+      while_bodies.push(instruction.while_body());
     } else if instruction.opcode() == HloOpcode::Conditional {
         
     }
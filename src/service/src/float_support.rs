@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 
 use common::blitz_data::PrimitiveType;
-use hlo::{hlo_instruction::HloInstruction, hlo_opcode::HloOpcode};
+use hlo::{hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_opcode::HloOpcode};
 
 // This class has methods to query if a certain low-precision floating-point
 // type, such as bfloat16, is supported in certain instructions on a given
@@ -31,7 +31,7 @@ impl FloatSupport {
   }
 
   pub fn supports_low_precision_operand(
-    &self, hlo: &HloInstruction, operand_index: usize) -> bool
+    &self, computation: &HloComputation, hlo: &HloInstruction, operand_index: usize) -> bool
   {
     match hlo.opcode() {
       HloOpcode::Call => return true,
@@ -44,7 +44,8 @@ impl FloatSupport {
       HloOpcode::OptimizationBarrier => return true,
       HloOpcode::Convert => {
         debug_assert_eq!(operand_index, 0);
-        return hlo.operand(0).shape().element_type() == self.low_precision_type
+        return computation.operand(hlo.id(), 0).shape().element_type() ==
+          self.low_precision_type
       },
       _ => return false
     }
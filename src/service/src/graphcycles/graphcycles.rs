@@ -1,8 +1,29 @@
 #![allow(dead_code)]
 
+use std::collections::HashSet;
+
+use super::ordered_set::OrderedSet;
+
+// GraphCycles detects the introduction of a cycle into a directed graph that
+// is being built up incrementally.
+//
+// Nodes are identified by small integers. It is not possible to record
+// multiple edges with the same (source, destination) pair; requests to add an
+// edge where one already exists are silently ignored.
+//
+// It is also not possible to introduce a cycle; an attempt to insert an edge
+// that would introduce a cycle fails and returns false.
+//
+// The implementation uses the Pearce-Kelly algorithm for maintaining a
+// topological order, see "A Dynamic Topological Sort Algorithm for Directed
+// Acyclic Graphs", David J. Pearce, Paul H. J. Kelly. Each node has a rank
+// and every edge goes from a lower rank to a higher rank. When an edge is
+// inserted against the current order, only the nodes with a rank between the
+// two endpoints are visited and reassigned.
+
 struct Node {
-  rank: i64,
-  visited: bool,
+  rank: i64,     // Rank number assigned by Pearce-Kelly algorithm.
+  visited: bool, // Temporary marker used by depth-first-search.
 }
 
 impl Node {
@@ -15,21 +36,32 @@ impl Node {
 }
 
 struct NodeIO {
+  in_nodes: OrderedSet<i64>,  // List of immediate predecessor nodes in graph.
+  out_nodes: OrderedSet<i64>, // List of immediate successor nodes in graph.
+}
 
+impl NodeIO {
+  pub fn new() -> Self {
+    NodeIO {
+      in_nodes: OrderedSet::new(),
+      out_nodes: OrderedSet::new()
+    }
+  }
 }
 
-struct Rep {
+struct Rep<T> {
   nodes: Vec<Node>,
   node_io: Vec<NodeIO>,
-  free_nodes: Vec<i64>,
-  deltaf: Vec<i64>,
-  deltab: Vec<i64>,
-  list: Vec<i64>,
-  merged: Vec<i64>,
-  stack: Vec<i64>
+  free_nodes: Vec<i64>, // Indices for unused entries in nodes.
+  deltaf: Vec<i64>,     // Results of forward DFS.
+  deltab: Vec<i64>,     // Results of backward DFS.
+  list: Vec<i64>,       // All nodes to reprocess.
+  merged: Vec<i64>,     // Rank values to assign to list entries.
+  stack: Vec<i64>,      // Emulates recursion stack when doing depth first search.
+  node_data: Vec<Option<T>>
 }
 
-impl Rep {
+impl<T> Rep<T> {
   pub fn new() -> Self {
     Rep {
       nodes: Vec::new(),
@@ -39,47 +71,527 @@ impl Rep {
       deltab: Vec::new(),
       list: Vec::new(),
       merged: Vec::new(),
-      stack: Vec::new()
+      stack: Vec::new(),
+      node_data: Vec::new()
+    }
+  }
+
+  fn node(&self, n: i64) -> &Node {
+    &self.nodes[n as usize]
+  }
+
+  fn mutable_node(&mut self, n: i64) -> &mut Node {
+    &mut self.nodes[n as usize]
+  }
+
+  fn node_io(&self, n: i64) -> &NodeIO {
+    &self.node_io[n as usize]
+  }
+
+  fn mutable_node_io(&mut self, n: i64) -> &mut NodeIO {
+    &mut self.node_io[n as usize]
+  }
+
+  // Visits the successors of 'n' with a rank below 'upper_bound'. Returns
+  // false if a node with rank 'upper_bound' is reachable, i.e. a cycle.
+  fn forward_dfs(&mut self, n: i64, upper_bound: i64) -> bool {
+    // Avoid recursion since stack space might be limited. We instead keep a
+    // stack of nodes to visit.
+    self.deltaf.clear();
+    self.stack.clear();
+    self.stack.push(n);
+    while let Some(n) = self.stack.pop() {
+      if self.node(n).visited { continue; }
+
+      self.mutable_node(n).visited = true;
+      self.deltaf.push(n);
+
+      for w in self.node_io[n as usize].out_nodes.get_sequence() {
+        let nw = &self.nodes[*w as usize];
+        if nw.rank == upper_bound {
+          return false; // Cycle.
+        }
+        if !nw.visited && nw.rank < upper_bound {
+          self.stack.push(*w);
+        }
+      }
+    }
+    true
+  }
+
+  // Visits the predecessors of 'n' with a rank above 'lower_bound'.
+  fn backward_dfs(&mut self, n: i64, lower_bound: i64) {
+    self.deltab.clear();
+    self.stack.clear();
+    self.stack.push(n);
+    while let Some(n) = self.stack.pop() {
+      if self.node(n).visited { continue; }
+
+      self.mutable_node(n).visited = true;
+      self.deltab.push(n);
+
+      for w in self.node_io[n as usize].in_nodes.get_sequence() {
+        let nw = &self.nodes[*w as usize];
+        if !nw.visited && lower_bound < nw.rank {
+          self.stack.push(*w);
+        }
+      }
+    }
+  }
+
+  // Reassigns the ranks of the nodes found by the backward and forward DFS so
+  // that the nodes of the backward DFS come first.
+  fn reorder(&mut self) {
+    let nodes = &self.nodes;
+    self.deltab.sort_by_key(|n| nodes[*n as usize].rank);
+    self.deltaf.sort_by_key(|n| nodes[*n as usize].rank);
+
+    // Adds contents of delta lists to list (backwards deltas first).
+    self.list.clear();
+    let mut deltab = std::mem::take(&mut self.deltab);
+    let mut deltaf = std::mem::take(&mut self.deltaf);
+    self.move_to_list(&mut deltab);
+    self.move_to_list(&mut deltaf);
+
+    // Produce sorted list of all ranks that will be reassigned.
+    self.merged.clear();
+    self.merged.extend(&deltab);
+    self.merged.extend(&deltaf);
+    self.merged.sort();
+    self.deltab = deltab;
+    self.deltaf = deltaf;
+
+    // Assign the ranks in order to the collected list.
+    for i in 0..self.list.len() {
+      let rank = self.merged[i];
+      self.mutable_node(self.list[i]).rank = rank;
+    }
+  }
+
+  fn move_to_list(&mut self, src: &mut [i64]) {
+    for w in src.iter_mut() {
+      let node = *w;
+      *w = self.node(node).rank; // Replace src entry with its rank.
+      self.mutable_node(node).visited = false; // Prepare for future DFS calls.
+      self.list.push(node);
+    }
+  }
+
+  fn clear_visited_bits(&mut self, visited_indices: &[i64]) {
+    for index in visited_indices {
+      self.mutable_node(*index).visited = false;
     }
   }
 }
 
-pub struct GraphCycles {
-  rep: Rep
+pub struct GraphCycles<T = ()> {
+  rep: Rep<T>
 }
 
-impl GraphCycles {
-  pub fn new() {}
+impl<T> GraphCycles<T> {
+  pub fn new() -> Self {
+    GraphCycles { rep: Rep::new() }
+  }
 
+  // Allocate an unused node id and return it. The new node has no node data.
+  // All node identifiers passed to other routines in this interface must have
+  // been allocated by new_node() and not yet deallocated by remove_node().
   pub fn new_node(&mut self) -> i64 {
-    if self.rep.free_nodes.is_empty() {
-      let mut n = Node::new();
-      n.rank = self.rep.nodes.len() as i64;
-      // TODO
-      n.rank
+    match self.rep.free_nodes.pop() {
+      None => {
+        let mut n = Node::new();
+        n.rank = self.rep.nodes.len() as i64;
+        let rank = n.rank;
+        self.rep.nodes.push(n);
+        self.rep.node_io.push(NodeIO::new());
+        self.rep.node_data.push(None);
+        rank
+      },
+      Some(r) => {
+        // Preserve preceding rank since the set of ranks in use must be a
+        // permutation of [0, nodes.len() - 1].
+        self.rep.node_data[r as usize] = None;
+        r
+      }
+    }
+  }
+
+  // Remove node 'node' from the graph, deleting all edges to and from it.
+  // After this call the identifier 'node' may no longer be used as an
+  // argument to any routine until it has been reallocated with new_node().
+  pub fn remove_node(&mut self, node: i64) {
+    let io = std::mem::replace(self.rep.mutable_node_io(node), NodeIO::new());
+    for y in io.out_nodes.get_sequence() {
+      self.rep.mutable_node_io(*y).in_nodes.erase(node);
+    }
+    for y in io.in_nodes.get_sequence() {
+      self.rep.mutable_node_io(*y).out_nodes.erase(node);
+    }
+    self.rep.free_nodes.push(node);
+  }
+
+  // Attempt to insert an edge from x to y. If the edge would introduce a
+  // cycle, return false without making any changes. Otherwise add the edge
+  // and return true.
+  pub fn insert_edge(&mut self, x: i64, y: i64) -> bool {
+    if x == y { return false; }
+    if !self.rep.mutable_node_io(x).out_nodes.insert(y) {
+      // Edge already exists.
+      return true;
+    }
+    self.rep.mutable_node_io(y).in_nodes.insert(x);
+
+    let x_rank = self.rep.node(x).rank;
+    let y_rank = self.rep.node(y).rank;
+    if x_rank <= y_rank {
+      // New edge is consistent with existing rank assignment.
+      return true;
+    }
+
+    // Current rank assignments are incompatible with the new edge. Recompute.
+    // We only need to consider nodes that fall in the range [y_rank, x_rank].
+    if !self.rep.forward_dfs(y, x_rank) {
+      // Found a cycle. Undo the insertion and tell caller.
+      self.rep.mutable_node_io(x).out_nodes.erase(y);
+      self.rep.mutable_node_io(y).in_nodes.erase(x);
+      // Since we do not call reorder() on this path, clear any visited
+      // markers left by forward_dfs.
+      let deltaf = std::mem::take(&mut self.rep.deltaf);
+      self.rep.clear_visited_bits(&deltaf);
+      self.rep.deltaf = deltaf;
+      return false;
+    }
+    self.rep.backward_dfs(x, y_rank);
+    self.rep.reorder();
+    true
+  }
+
+  // Remove any edge that exists from x to y.
+  pub fn remove_edge(&mut self, x: i64, y: i64) {
+    self.rep.mutable_node_io(x).out_nodes.erase(y);
+    self.rep.mutable_node_io(y).in_nodes.erase(x);
+    // No need to update the rank assignment since a previous valid rank
+    // assignment remains valid after an edge deletion.
+  }
+
+  // Return whether there is an edge directly from x to y.
+  pub fn has_edge(&self, x: i64, y: i64) -> bool {
+    self.rep.node_io(x).out_nodes.contains(&y)
+  }
+
+  // Contracts the edge from 'a' to node 'b', merging nodes 'a' and 'b'. One
+  // of the nodes is removed from the graph, and edges to/from it are added to
+  // the remaining one, which is returned. If contracting the edge would
+  // create a cycle, does nothing and return None.
+  pub fn contract_edge(&mut self, a: i64, b: i64) -> Option<i64> {
+    assert!(self.has_edge(a, b), "No edge exists from {} to {}", a, b);
+    self.remove_edge(a, b);
+
+    if self.is_reachable_non_const(a, b) {
+      // Restore the graph to its original state.
+      self.insert_edge(a, b);
+      return None;
+    }
+
+    let degree = |io: &NodeIO| io.in_nodes.size() + io.out_nodes.size();
+    let (a, b) = if degree(self.rep.node_io(b)) > degree(self.rep.node_io(a)) {
+      // Swap "a" and "b" to minimize copying.
+      (b, a)
     } else {
-      let r = self.rep.free_nodes.pop().unwrap();
-      // rep.node_data[r] = None
-      r
-    }
-  }
-
-  pub fn remove_node() {}
-  pub fn insert_edge() {}
-  pub fn remove_edge() {}
-  pub fn has_edge() {}
-  pub fn contract_edge() {}
-  pub fn can_contract_edge() {}
-  pub fn is_reachable() {}
-  pub fn is_reachable_non_const() {}
-  pub fn get_node_data() {}
-  pub fn set_node_data() {}
-  pub fn find_path() {}
-  pub fn check_invariants() {}
-  pub fn successors() {}
-  pub fn predecessors() {}
-  pub fn successors_copy() {}
-  pub fn predecessors_copy() {}
-  pub fn all_nodes_in_post_order() {}
-  pub fn debug_string() {}
-}
\ No newline at end of file
+      (a, b)
+    };
+
+    let io = std::mem::replace(self.rep.mutable_node_io(b), NodeIO::new());
+    for y in io.out_nodes.get_sequence() {
+      self.rep.mutable_node_io(*y).in_nodes.erase(b);
+    }
+    for y in io.in_nodes.get_sequence() {
+      self.rep.mutable_node_io(*y).out_nodes.erase(b);
+    }
+    self.rep.free_nodes.push(b);
+
+    let out_size = self.rep.node_io(a).out_nodes.size() + io.out_nodes.size();
+    self.rep.mutable_node_io(a).out_nodes.reserve(out_size);
+    for y in io.out_nodes.get_sequence() {
+      self.insert_edge(a, *y);
+    }
+
+    let in_size = self.rep.node_io(a).in_nodes.size() + io.in_nodes.size();
+    self.rep.mutable_node_io(a).in_nodes.reserve(in_size);
+    for y in io.in_nodes.get_sequence() {
+      self.insert_edge(*y, a);
+    }
+
+    // Note, if the swap happened it might be what originally was called "b".
+    Some(a)
+  }
+
+  // Return true if can contract edge, otherwise return false.
+  pub fn can_contract_edge(&mut self, a: i64, b: i64) -> bool {
+    assert!(self.has_edge(a, b), "No edge exists from {} to {}", a, b);
+    self.remove_edge(a, b);
+    let reachable = self.is_reachable_non_const(a, b);
+    // Restore the graph to its original state.
+    self.insert_edge(a, b);
+    // If reachable, then contracting edge will cause cycle.
+    !reachable
+  }
+
+  // Return whether dest node is reachable from source node by following edges.
+  pub fn is_reachable(&self, x: i64, y: i64) -> bool {
+    self.find_path(x, y, 0, &mut vec![]) > 0
+  }
+
+  // A faster non-thread-safe version of is_reachable.
+  pub fn is_reachable_non_const(&mut self, x: i64, y: i64) -> bool {
+    if x == y { return true; }
+    let x_rank = self.rep.node(x).rank;
+    let y_rank = self.rep.node(y).rank;
+
+    if x_rank >= y_rank {
+      // x cannot reach y since it is after it in the topological ordering.
+      return false;
+    }
+
+    // See if x can reach y using a DFS search that is limited to y's rank.
+    let reachable = !self.rep.forward_dfs(x, y_rank);
+
+    // Clear any visited markers left by forward_dfs.
+    let deltaf = std::mem::take(&mut self.rep.deltaf);
+    self.rep.clear_visited_bits(&deltaf);
+    self.rep.deltaf = deltaf;
+    reachable
+  }
+
+  // Return the node data of 'node', if any was set.
+  pub fn get_node_data(&self, node: i64) -> Option<&T> {
+    self.rep.node_data[node as usize].as_ref()
+  }
+
+  pub fn set_node_data(&mut self, node: i64, data: T) {
+    self.rep.node_data[node as usize] = Some(data);
+  }
+
+  // Find a path from "source" to "dest". If such a path exists, place the
+  // node IDs of the nodes on the path in the vector path, and return the
+  // number of nodes on the path. If the path is longer than 'max_path_len'
+  // nodes, only the first 'max_path_len' nodes are placed in 'path'. The
+  // number returned is the actual number of nodes on the path, regardless of
+  // how many nodes are placed. Returns 0 if there is no path.
+  pub fn find_path(&self, x: i64, y: i64, max_path_len: usize, path: &mut Vec<i64>) -> usize {
+    // Forward depth first search starting at x until we hit y. As we descend
+    // into a node, we push it onto the path. As we leave a node, we remove it
+    // from the path.
+    path.clear();
+    let mut path_len = 0;
+    let mut seen = HashSet::new();
+    let mut stack = vec![x];
+    while let Some(n) = stack.pop() {
+      if n < 0 {
+        // Marker to indicate that we are leaving a node.
+        path_len -= 1;
+        continue;
+      }
+
+      if path_len < max_path_len {
+        path.truncate(path_len);
+        path.push(n);
+      }
+      path_len += 1;
+      stack.push(-1); // Will remove tentative path entry.
+
+      if n == y {
+        path.truncate(path_len.min(max_path_len));
+        return path_len;
+      }
+
+      for w in self.rep.node_io(n).out_nodes.get_sequence() {
+        if seen.insert(*w) {
+          stack.push(*w);
+        }
+      }
+    }
+    path.clear();
+    0
+  }
+
+  // Check internal invariants. Crashes on failure, returns true on success.
+  // Expensive: should only be called from graphcycles tests.
+  pub fn check_invariants(&self) -> bool {
+    let mut ranks = HashSet::new(); // Set of ranks seen so far.
+    for x in 0..self.rep.nodes.len() {
+      let nx = &self.rep.nodes[x];
+      if nx.visited {
+        panic!("Did not clear visited marker on node {}", x);
+      }
+      if !ranks.insert(nx.rank) {
+        panic!("Duplicate occurrence of rank {}", nx.rank);
+      }
+      for y in self.rep.node_io[x].out_nodes.get_sequence() {
+        let ny = self.rep.node(*y);
+        if nx.rank >= ny.rank {
+          panic!("Edge {}->{} has bad rank assignment {}->{}", x, y, nx.rank, ny.rank);
+        }
+      }
+    }
+    true
+  }
+
+  // Warning: Do not modify a graph while iterating over its successors or
+  // predecessors.
+  pub fn successors(&self, node: i64) -> &Vec<i64> {
+    self.rep.node_io(node).out_nodes.get_sequence()
+  }
+
+  pub fn predecessors(&self, node: i64) -> &Vec<i64> {
+    self.rep.node_io(node).in_nodes.get_sequence()
+  }
+
+  // Return a copy of the successors set. This is needed for code using the
+  // collection while modifying the GraphCycles.
+  pub fn successors_copy(&self, node: i64) -> Vec<i64> {
+    self.successors(node).clone()
+  }
+
+  // Return a copy of the predecessors set. This is needed for code using the
+  // collection while modifying the GraphCycles.
+  pub fn predecessors_copy(&self, node: i64) -> Vec<i64> {
+    self.predecessors(node).clone()
+  }
+
+  // Returns all nodes in post order.
+  //
+  // If there is a path from X to Y then X appears after Y in the returned
+  // vector.
+  pub fn all_nodes_in_post_order(&self) -> Vec<i64> {
+    let free_nodes_set: HashSet<i64> = self.rep.free_nodes.iter().cloned().collect();
+    let mut all_nodes: Vec<i64> = (0..self.rep.nodes.len() as i64)
+      .filter(|i| !free_nodes_set.contains(i))
+      .collect();
+    all_nodes.sort_by_key(|n| std::cmp::Reverse(self.rep.node(*n).rank));
+    all_nodes
+  }
+
+  // Returns the graph in graphviz format.
+  pub fn debug_string(&self) -> String {
+    let free_nodes_set: HashSet<i64> = self.rep.free_nodes.iter().cloned().collect();
+
+    let mut result = "digraph {\n".to_string();
+    for i in 0..self.rep.nodes.len() as i64 {
+      if free_nodes_set.contains(&i) { continue; }
+
+      for succ in self.successors(i) {
+        result.push_str(&format!("  \"{}\" -> \"{}\"\n", i, succ));
+      }
+    }
+    result.push_str("}\n");
+    result
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn make_graph(num_nodes: usize, edges: &[(i64, i64)]) -> GraphCycles {
+    let mut graph_cycles = GraphCycles::new();
+    for _ in 0..num_nodes {
+      graph_cycles.new_node();
+    }
+    for (x, y) in edges {
+      assert!(graph_cycles.insert_edge(*x, *y));
+    }
+    graph_cycles
+  }
+
+  #[test]
+  fn test_insert_edge_detects_cycles() {
+    let mut graph_cycles = make_graph(4, &[(0, 1), (1, 2), (2, 3)]);
+    assert!(graph_cycles.check_invariants());
+    assert!(!graph_cycles.insert_edge(3, 0));
+    assert!(!graph_cycles.insert_edge(2, 1));
+    assert!(!graph_cycles.insert_edge(0, 0));
+    assert!(!graph_cycles.has_edge(3, 0));
+    assert!(graph_cycles.check_invariants());
+
+    // Edges against the initial rank order are accepted if acyclic.
+    let mut graph_cycles = make_graph(4, &[(3, 2), (2, 1), (1, 0)]);
+    assert!(graph_cycles.check_invariants());
+    assert!(!graph_cycles.insert_edge(0, 3));
+    graph_cycles.remove_edge(2, 1);
+    assert!(graph_cycles.insert_edge(0, 3));
+    assert!(graph_cycles.check_invariants());
+    assert_eq!(graph_cycles.all_nodes_in_post_order(), vec![2, 3, 0, 1]);
+  }
+
+  #[test]
+  fn test_is_reachable_and_find_path() {
+    let mut graph_cycles = make_graph(5, &[(0, 1), (1, 2), (0, 3), (3, 2)]);
+    assert!(graph_cycles.is_reachable(0, 2));
+    assert!(graph_cycles.is_reachable_non_const(0, 2));
+    assert!(!graph_cycles.is_reachable(2, 0));
+    assert!(!graph_cycles.is_reachable_non_const(2, 0));
+    assert!(!graph_cycles.is_reachable(0, 4));
+    assert!(graph_cycles.check_invariants());
+
+    let mut path = vec![];
+    assert_eq!(graph_cycles.find_path(0, 2, 10, &mut path), 3);
+    assert_eq!(path.len(), 3);
+    assert_eq!(path[0], 0);
+    assert_eq!(path[2], 2);
+    assert_eq!(graph_cycles.find_path(0, 2, 1, &mut path), 3);
+    assert_eq!(path, vec![0]);
+    assert_eq!(graph_cycles.find_path(2, 0, 10, &mut path), 0);
+    assert!(path.is_empty());
+  }
+
+  #[test]
+  fn test_contract_edge() {
+    // 0 -> 1 -> 2 and 0 -> 2: contracting 0 -> 2 would create a cycle.
+    let mut graph_cycles = make_graph(4, &[(0, 1), (1, 2), (0, 2), (2, 3)]);
+    assert!(!graph_cycles.can_contract_edge(0, 2));
+    assert_eq!(graph_cycles.contract_edge(0, 2), None);
+    assert!(graph_cycles.has_edge(0, 2));
+
+    assert!(graph_cycles.can_contract_edge(1, 2));
+    let merged = graph_cycles.contract_edge(1, 2).unwrap();
+    assert!(merged == 1 || merged == 2);
+    assert!(graph_cycles.has_edge(0, merged));
+    assert!(graph_cycles.has_edge(merged, 3));
+    assert_eq!(graph_cycles.successors(0).len(), 1);
+    assert_eq!(graph_cycles.all_nodes_in_post_order(), vec![3, merged, 0]);
+    assert!(graph_cycles.check_invariants());
+  }
+
+  #[test]
+  fn test_remove_node_and_node_data() {
+    let mut graph_cycles: GraphCycles<String> = GraphCycles::new();
+    let a = graph_cycles.new_node();
+    let b = graph_cycles.new_node();
+    let c = graph_cycles.new_node();
+    assert!(graph_cycles.insert_edge(a, b));
+    assert!(graph_cycles.insert_edge(b, c));
+    graph_cycles.set_node_data(b, "b".to_string());
+    assert_eq!(graph_cycles.get_node_data(b), Some(&"b".to_string()));
+    assert_eq!(graph_cycles.get_node_data(a), None);
+
+    graph_cycles.remove_node(b);
+    assert!(graph_cycles.successors(a).is_empty());
+    assert!(graph_cycles.predecessors(c).is_empty());
+    assert!(!graph_cycles.is_reachable(a, c));
+    assert_eq!(graph_cycles.all_nodes_in_post_order().len(), 2);
+
+    // The id is reused, without the old data.
+    assert_eq!(graph_cycles.new_node(), b);
+    assert_eq!(graph_cycles.get_node_data(b), None);
+    assert!(graph_cycles.insert_edge(c, b));
+    assert!(graph_cycles.check_invariants());
+  }
+
+  #[test]
+  fn test_debug_string() {
+    let mut graph_cycles = make_graph(3, &[(0, 1), (0, 2)]);
+    graph_cycles.remove_node(2);
+    assert_eq!(graph_cycles.debug_string(), "digraph {\n  \"0\" -> \"1\"\n}\n");
+  }
+}
@@ -1,2 +1,3 @@
 
-pub mod graphcycles;
\ No newline at end of file
+pub mod graphcycles;
+pub mod ordered_set;
\ No newline at end of file
@@ -0,0 +1,93 @@
+#![allow(dead_code)]
+
+use std::{collections::HashMap, hash::Hash};
+
+// This is a set data structure that provides a deterministic iteration order.
+// The iteration order of elements only depends on the sequence of inserts and
+// deletes, so as long as the inserts and deletes happen in the same order, the
+// set will be iterated in the same order.
+//
+// Assumes that T can be cheaply copied for simplicity.
+pub struct OrderedSet<T> {
+  value_sequence: Vec<T>,
+  value_to_index: HashMap<T, usize>
+}
+
+impl<T> OrderedSet<T> where T: Copy + Eq + Hash {
+  pub fn new() -> Self {
+    OrderedSet {
+      value_sequence: Vec::new(),
+      value_to_index: HashMap::new()
+    }
+  }
+
+  // Inserts 'value' into the ordered set. Returns true if the value was not
+  // present in the set before the insertion.
+  pub fn insert(&mut self, value: T) -> bool {
+    if self.value_to_index.contains_key(&value) {
+      return false;
+    }
+    self.value_to_index.insert(value, self.value_sequence.len());
+    self.value_sequence.push(value);
+    true
+  }
+
+  // Removes 'value' from the set. Assumes 'value' is present in the set.
+  pub fn erase(&mut self, value: T) {
+    let index = self.value_to_index.remove(&value);
+    assert!(index.is_some(), "Value is not in the set.");
+    let index = index.unwrap();
+
+    // Swap value with the last element and then pop it.
+    self.value_sequence.swap_remove(index);
+    if index < self.value_sequence.len() {
+      self.value_to_index.insert(self.value_sequence[index], index);
+    }
+  }
+
+  pub fn reserve(&mut self, new_size: usize) {
+    self.value_to_index.reserve(new_size);
+    self.value_sequence.reserve(new_size);
+  }
+
+  pub fn clear(&mut self) {
+    self.value_to_index.clear();
+    self.value_sequence.clear();
+  }
+
+  pub fn contains(&self, value: &T) -> bool {
+    self.value_to_index.contains_key(value)
+  }
+
+  pub fn size(&self) -> usize {
+    self.value_sequence.len()
+  }
+
+  pub fn get_sequence(&self) -> &Vec<T> {
+    &self.value_sequence
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_insert_and_erase() {
+    let mut set = OrderedSet::new();
+    assert!(set.insert(3));
+    assert!(set.insert(1));
+    assert!(set.insert(2));
+    assert!(!set.insert(1));
+    assert_eq!(set.get_sequence(), &vec![3, 1, 2]);
+
+    set.erase(3);
+    assert_eq!(set.get_sequence(), &vec![2, 1]);
+    assert!(!set.contains(&3));
+    assert!(set.contains(&2));
+    assert_eq!(set.size(), 2);
+
+    set.clear();
+    assert_eq!(set.size(), 0);
+  }
+}
@@ -2,7 +2,7 @@
 
 use std::collections::{HashMap, HashSet};
 
-use hlo::{hlo_computation::HloComputation, hlo_instruction::{HloPrintOptions, PrintSubcomputationMode}, hlo_module::HloModule};
+use hlo::{hlo_computation::HloComputation, hlo_instruction::{HloPrintOptions, PrintSubcomputationMode}, hlo_module::{ComputationId, HloModule}};
 
 // Deduplicate computations inside a HloModule.
 // If two computations are identical then keep the first one (in postorder
@@ -24,11 +24,11 @@ impl HloComputationDeduplicator {
   
   pub fn run(
     &self,
-    module: &HloModule,
+    module: &mut HloModule,
     execution_threads: HashSet<String>) -> Result<bool, String>
   {
-    let mut unique_comps: HashMap<String, HloComputation> = HashMap::new();
-    let mut replacements: HashMap<HloComputation, HloComputation> = HashMap::new();
+    let mut unique_comps: HashMap<String, ComputationId> = HashMap::new();
+    let mut replacements: HashMap<ComputationId, ComputationId> = HashMap::new();
 
     let mut options = HloPrintOptions::canonical();
     options.set_print_subcomputation_mode(PrintSubcomputationMode::Off);
@@ -38,19 +38,21 @@ impl HloComputationDeduplicator {
     options.set_print_ids(false);
     options.set_canonicalize_computations(true);
 
-    for comp in
+    for id in
       module.make_computation_post_order(&execution_threads, false) {
-      if comp.is_entry_computation() || comp.instruction_count() > 128 ||
-        self.contains_large_constants(comp) || comp.is_collective_called_computation()
+      let comp = module.computation(id);
+      if module.entry_computation().is_some_and(|entry| entry.id() == Some(id)) ||
+        comp.instruction_count() > 128 || self.contains_large_constants(comp) ||
+        comp.is_collective_called_computation()
       {
         continue;
       }
       let comp_str = comp.to_string_with_options(&options);
       let poss_dup = unique_comps.get(&comp_str);
       if poss_dup.is_some() { // TODO
-        replacements.insert(comp.clone(), poss_dup.unwrap().clone());
+        replacements.insert(id, *poss_dup.unwrap());
       } else {
-        unique_comps.insert(comp_str, comp.clone());
+        unique_comps.insert(comp_str, id);
       }
     }
 
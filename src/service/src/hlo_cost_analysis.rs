@@ -2,10 +2,31 @@
 
 use std::collections::HashMap;
 
-use common::{blitz_data::DotDimensionNumbers, layout_util::LayoutUtil, shape::Shape, shape_util::ShapeUtil};
-use hlo::{hlo_instruction::HloInstruction, hlo_opcode::HloOpcode};
-
-struct Properties {
+use common::{
+  blitz_data::{ConvolutionDimensionNumbers, DotDimensionNumbers, Window},
+  layout_util::LayoutUtil,
+  shape::Shape,
+  shape_util::ShapeUtil
+};
+use hlo::{
+  hlo_computation::HloComputation,
+  hlo_instruction::{HloInstruction, InstructionId},
+  hlo_module::{ComputationId, HloModule},
+  hlo_opcode::HloOpcode
+};
+
+// Keys of the properties computed for each instruction.
+pub const FLOPS_KEY: &str = "flops";
+pub const TRANSCENDENTALS_KEY: &str = "transcendentals";
+pub const BYTES_ACCESSED_KEY: &str = "bytes accessed";
+pub const OPTIMAL_SECONDS_KEY: &str = "optimal_seconds";
+pub const UTILIZATION_KEY: &str = "utilization";
+
+// The properties computed for an instruction, or summed over a computation.
+// The common properties have dedicated fields; any other property is kept in
+// 'named_props'.
+#[derive(Debug, Clone)]
+pub struct Properties {
   flops: f64,
   transcendentals: f64,
   bytes_accessed: f64,
@@ -44,7 +65,7 @@ impl Properties {
     match property {
       "flops" => return self.flops,
       "transcendentals" => return self.transcendentals,
-      "byts accessed" => return self.bytes_accessed,
+      "bytes accessed" => return self.bytes_accessed,
       "optimal_seconds" => return self.optimal_seconds,
       "utilization" => return self.utilization,
       "utilization0{}" => return self.operand0_utilization,
@@ -54,7 +75,7 @@ impl Properties {
       "bytes accessedout{}" => return self.output_root_bytes_accessed,
       "reserved0" => return self.reserved0,
       "reserved1" => return self.reserved1,
-      _ => return 0.0
+      _ => return *self.named_props.get(property).unwrap_or(&0.0)
     }
   }
 
@@ -72,8 +93,14 @@ impl Properties {
       "bytes accessedout{}" => self.output_root_bytes_accessed = value,
       "reserved0" => self.reserved0 = value,
       "reserved1" => self.reserved1 = value,
-      _ => return
-    }   
+      _ => { self.named_props.insert(property.to_string(), value); }
+    }
+  }
+
+  // Adds 'value' to 'property'.
+  pub fn add(&mut self, property: &str, value: f64) {
+    let current = self.get(property);
+    self.set(property, current + value);
   }
 
   pub fn for_each<F>(&self, mut func: F) where F: FnMut(&str, f64) {
@@ -128,7 +155,7 @@ impl Properties {
     if operand == 1 && shape_index.is_empty() {
       return self.operand1_utilization;
     }
-    let value = 
+    let value =
       self.named_props.get(&get_operand_utilization_key(operand, shape_index));
     if value.is_some() {
       return *value.unwrap();
@@ -156,7 +183,7 @@ impl Properties {
     if operand == 1 && shape_index.is_empty() {
       return self.operand1_bytes_accessed;
     }
-    let value = 
+    let value =
       self.named_props.get(&get_operand_bytes_accessed_key(operand, shape_index));
     if value.is_some() {
       return *value.unwrap();
@@ -243,33 +270,53 @@ impl Properties {
 fn get_operand_bytes_accessed_key(operand_num: i64, shape_index: Vec<usize>) -> String {
   let mut out = "bytes accessed".to_string();
   out.push_str(&operand_num.to_string());
-  out.push_str(&shape_index[0].to_string()); // check
+  out.push_str(&shape_index_to_string(&shape_index));
   out
 }
 
 fn get_operand_utilization_key(operand_num: i64, shape_index: Vec<usize>) -> String {
   let mut out = "utilization".to_string();
   out.push_str(&operand_num.to_string());
-  out.push_str(&shape_index[0].to_string()); // check
+  out.push_str(&shape_index_to_string(&shape_index));
   out
 }
 
 fn get_output_bytes_accessed_key(shape_index: Vec<usize>) -> String {
   let mut out = "bytes accessed".to_string();
   out.push_str("out");
-  out.push_str(&shape_index[0].to_string()); // check
+  out.push_str(&shape_index_to_string(&shape_index));
   out
 }
 
+// Formats a shape index the way the property keys spell it, e.g. "{0,1}".
+fn shape_index_to_string(shape_index: &[usize]) -> String {
+  let indices: Vec<String> = shape_index.iter().map(|i| i.to_string()).collect();
+  format!("{{{}}}", indices.join(","))
+}
+
+// Function which computes the size of the top-level of a given shape (not
+// including nested elements, if any).
+pub type ShapeSizeFunction = fn(&Shape) -> i64;
+
 // A struct to encapsulate hardware-related options.
-struct Options {
+#[derive(Debug, Clone)]
+pub struct Options {
+  shape_size: ShapeSizeFunction,
   per_second_rates: Properties,
   count_multiple_input_accesses: bool,
 }
 
 impl Options {
-  pub fn shape_size(&self, _shape: &Shape) -> i64 {
-    0 // TODO
+  pub fn new(shape_size: ShapeSizeFunction) -> Self {
+    Options {
+      shape_size: shape_size,
+      per_second_rates: Properties::new(),
+      count_multiple_input_accesses: false
+    }
+  }
+
+  pub fn shape_size(&self, shape: &Shape) -> i64 {
+    (self.shape_size)(shape)
   }
 
   // Set the rates used to calculate the time taken by the computation.
@@ -290,12 +337,22 @@ impl Options {
     self.per_second_rates.get(property)
   }
 
+  // If true, a fused parameter read by several fused instructions is
+  // counted once per read instead of once.
+  pub fn count_multiple_input_accesses(&self) -> bool {
+    self.count_multiple_input_accesses
+  }
+
+  pub fn set_count_multiple_input_accesses(&mut self, value: bool) {
+    self.count_multiple_input_accesses = value;
+  }
+
   pub fn to_string(&self) -> String {
     let mut out = "HloCostAnalysis::Options{\n".to_string();
     out.push_str(" per_second_rates:");
     out.push_str(&self.per_second_rates.to_string());
     out.push('\n');
-    out.push_str(" counnt_multiple_input_accesses: ");
+    out.push_str(" count_multiple_input_accesses: ");
     out.push_str(&self.count_multiple_input_accesses.to_string());
     out.push('\n');
     out.push('}');
@@ -303,10 +360,17 @@ impl Options {
   }
 }
 
+// Identifies an instruction within a module.
+type InstructionKey = (Option<ComputationId>, InstructionId);
+
+fn key_of(instruction: &HloInstruction) -> InstructionKey {
+  (instruction.parent(), instruction.id())
+}
+
 // HloCostanalysis treverses an HLO graph and calculates the amount of
 // computations required for the graph.
 pub struct HloCostAnalysis {
-  hlo_properties: HashMap<HloInstruction, Properties>,
+  hlo_properties: HashMap<InstructionKey, Properties>,
   current_should_compute_bottleneck_time: bool,
   current_properties: Properties,
   properties_sum: Properties,
@@ -316,7 +380,159 @@ pub struct HloCostAnalysis {
 impl HloCostAnalysis {
   pub const FMA_FLOPS: i64 = 2;
 
-  pub fn new() {}
+  pub fn new(options: Options) -> Self {
+    HloCostAnalysis {
+      hlo_properties: HashMap::new(),
+      current_should_compute_bottleneck_time: true,
+      current_properties: Properties::new(),
+      properties_sum: Properties::new(),
+      options: options
+    }
+  }
+
+  // The shape size used when no target specific one is given: the byte size
+  // of the shape, with 8 byte pointers for tuples.
+  pub fn default_shape_size(shape: &Shape) -> i64 {
+    ShapeUtil::byte_size_of(shape, 8)
+  }
+
+  pub fn options(&self) -> &Options {
+    &self.options
+  }
+
+  // Analyzes the entry computation of 'module', including every computation
+  // it calls. The module totals are then available from flop_count() etc.
+  pub fn run(&mut self, module: &HloModule) -> Result<(), String> {
+    if !module.has_entry_computation() {
+      return Err(format!("Module {} has no entry computation.", module.name()));
+    }
+    self.run_on_computation(module, module.entry_computation_id())
+  }
+
+  // Analyzes the computation 'id' of 'module', visiting its instructions in
+  // post order.
+  pub fn run_on_computation(
+    &mut self, module: &HloModule, id: ComputationId) -> Result<(), String>
+  {
+    let computation = module.computation(id);
+    for instruction_id in computation.make_instruction_post_order() {
+      let instruction = computation.instruction(instruction_id);
+      if self.hlo_properties.contains_key(&key_of(instruction)) {
+        continue;
+      }
+      self.preprocess(computation, instruction)?;
+      self.visit(module, computation, instruction)?;
+      self.postprocess(instruction)?;
+    }
+    self.finish_visit(computation.root_instruction())
+  }
+
+  // Dispatches 'instruction' to the handler of its opcode.
+  fn visit(
+    &mut self,
+    module: &HloModule,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    match instruction.opcode() {
+      HloOpcode::Abs | HloOpcode::Cbrt | HloOpcode::Ceil | HloOpcode::Clz |
+      HloOpcode::Cos | HloOpcode::Erf | HloOpcode::Exp | HloOpcode::Expm1 |
+      HloOpcode::Floor | HloOpcode::Imag | HloOpcode::IsFinite | HloOpcode::Log |
+      HloOpcode::Log1p | HloOpcode::Logistic | HloOpcode::Negate | HloOpcode::Not |
+      HloOpcode::PopulationCount | HloOpcode::Real | HloOpcode::RoundNearestAfz |
+      HloOpcode::RoundNearestEven | HloOpcode::Rsqrt | HloOpcode::Sign |
+      HloOpcode::Sin | HloOpcode::Sqrt | HloOpcode::Tan | HloOpcode::Tanh |
+      HloOpcode::BitcastConvert =>
+        self.handle_elementwise_unary(instruction),
+      HloOpcode::Add | HloOpcode::And | HloOpcode::Atan2 | HloOpcode::Complex |
+      HloOpcode::Divide | HloOpcode::Maximum | HloOpcode::Minimum |
+      HloOpcode::Multiply | HloOpcode::Or | HloOpcode::Power |
+      HloOpcode::Remainder | HloOpcode::ShiftLeft | HloOpcode::ShiftRightArithmetic |
+      HloOpcode::ShiftRightLogical | HloOpcode::Subtract | HloOpcode::Xor |
+      HloOpcode::StochasticConvert =>
+        self.handle_elementwise_binary(instruction),
+      HloOpcode::AddDependency => self.handle_add_dependency(instruction),
+      HloOpcode::AfterAll => self.handle_after_all(instruction),
+      HloOpcode::AllGather => self.handle_all_gather(instruction),
+      HloOpcode::AllGatherDone => self.handle_all_gather_done(instruction),
+      HloOpcode::AllGatherStart => self.handle_all_gather_start(instruction),
+      HloOpcode::AllReduce => self.handle_all_reduce(computation, instruction),
+      HloOpcode::AllReduceDone => self.handle_all_reduce_done(instruction),
+      HloOpcode::AllReduceStart => self.handle_all_reduce_start(computation, instruction),
+      HloOpcode::AllToAll => self.handle_all_to_all(instruction),
+      HloOpcode::AsyncDone => self.handle_async_done(instruction),
+      HloOpcode::AsyncStart => self.handle_async_start(module, instruction),
+      HloOpcode::AsyncUpdate => self.handle_async_update(instruction),
+      HloOpcode::BatchNormGrad => self.handle_batch_norm_grad(instruction),
+      HloOpcode::BatchNormInference => self.handle_batch_norm_inference(instruction),
+      HloOpcode::BatchNormTraining => self.handle_batch_norm_training(instruction),
+      HloOpcode::Bitcast => self.handle_bitcast(instruction),
+      HloOpcode::Broadcast => self.handle_broadcast(instruction),
+      HloOpcode::Call => self.handle_call(module, instruction),
+      HloOpcode::Cholsky => self.handle_cholsky(computation, instruction),
+      HloOpcode::Clamp => self.handle_clamp(instruction),
+      HloOpcode::CollectiveBroadcast => self.handle_collective_broadcast(instruction),
+      HloOpcode::CollectivePermute => self.handle_collective_permute(instruction),
+      HloOpcode::CollectivePermuteDone => self.handle_collective_permute_done(instruction),
+      HloOpcode::CollectivePermuteStart => self.handle_collective_permute_start(instruction),
+      HloOpcode::Compare => self.handle_compare(instruction),
+      HloOpcode::Concatenate => self.handle_concatenate(instruction),
+      HloOpcode::Conditional => self.handle_conditional(module, instruction),
+      HloOpcode::Constant => self.handle_constant(instruction),
+      HloOpcode::Convert => self.handle_convert(instruction),
+      HloOpcode::Convolution => self.handle_convolution(computation, instruction),
+      HloOpcode::Copy => self.handle_copy(instruction),
+      HloOpcode::CopyDone => self.handle_copy_done(instruction),
+      HloOpcode::CopyStart => self.handle_copy_start(instruction),
+      HloOpcode::CustomCall => self.handle_custom_call(instruction),
+      HloOpcode::Domain => self.handle_domain(instruction),
+      HloOpcode::Dot => self.handle_dot(computation, instruction),
+      HloOpcode::DynamicReshape => self.handle_dynamic_reshape(instruction),
+      HloOpcode::DynamicSlice => self.handle_dynamic_slice(computation, instruction),
+      HloOpcode::DynamicUpdateSlice =>
+        self.handle_dynamic_update_slice(computation, instruction),
+      HloOpcode::Fft => self.handle_fft(computation, instruction),
+      HloOpcode::Fusion => self.handle_fusion(module, instruction),
+      HloOpcode::Gather => self.handle_gather(computation, instruction),
+      HloOpcode::GetDimensionSize => self.handle_get_dimension_size(instruction),
+      HloOpcode::GetTupleElement => self.handle_get_tuple_element(instruction),
+      HloOpcode::Infeed => self.handle_infeed(instruction),
+      HloOpcode::Iota => self.handle_iota(instruction),
+      HloOpcode::Map => self.handle_map(module, instruction),
+      HloOpcode::OptimizationBarrier => self.handle_optimization_barrier(instruction),
+      HloOpcode::Outfeed => self.handle_outfeed(instruction),
+      HloOpcode::Pad => self.handle_pad(instruction),
+      HloOpcode::Parameter => self.handle_parameter(instruction),
+      HloOpcode::PartitionId => self.handle_partition_id(instruction),
+      HloOpcode::RaggedAllToAll => self.handle_all_to_all(instruction),
+      HloOpcode::Recv => self.handle_recv(instruction),
+      HloOpcode::RecvDone => self.handle_recv_done(instruction),
+      HloOpcode::Reduce => self.handle_reduce(module, computation, instruction),
+      HloOpcode::ReducePrecision => self.handle_reduce_precision(instruction),
+      HloOpcode::ReduceScatter => self.handle_reduce_scatter(computation, instruction),
+      HloOpcode::ReduceWindow => self.handle_reduce_window(module, instruction),
+      HloOpcode::ReplicaId => self.handle_replica_id(instruction),
+      HloOpcode::Reshape => self.handle_reshape(instruction),
+      HloOpcode::Reverse => self.handle_reverse(instruction),
+      HloOpcode::Rng => self.handle_rng(instruction),
+      HloOpcode::RngBitGenerator => self.handle_rng_bit_generator(instruction),
+      HloOpcode::RngGetAndUpdateState => self.handle_rng_get_and_update_state(instruction),
+      HloOpcode::Scatter => self.handle_scatter(module, computation, instruction),
+      HloOpcode::Select => self.handle_select(instruction),
+      HloOpcode::SelectAndScatter =>
+        self.handle_select_and_scatter(module, computation, instruction),
+      HloOpcode::Send => self.handle_send(instruction),
+      HloOpcode::SendDone => self.handle_send_done(instruction),
+      HloOpcode::SetDimensionSize => self.handle_set_dimension_size(instruction),
+      HloOpcode::Slice => self.handle_slice(computation, instruction),
+      HloOpcode::Sort => self.handle_sort(computation, instruction),
+      HloOpcode::TopK => self.handle_top_k(computation, instruction),
+      HloOpcode::Transpose => self.handle_transpose(instruction),
+      HloOpcode::TriangularSolve => self.handle_triangular_solve(computation, instruction),
+      HloOpcode::Tuple => self.handle_tuple(instruction),
+      HloOpcode::While => self.handle_while(module, instruction),
+    }
+  }
 
   pub fn handle_elementwise_unary(
     &mut self, instruction: &HloInstruction) -> Result<(), String>
@@ -387,7 +603,15 @@ impl HloCostAnalysis {
     Ok(())
   }
 
-  pub fn handle_async_start() {}
+  // The cost of an async computation is the cost of the computation it
+  // wraps.
+  pub fn handle_async_start(
+    &mut self, module: &HloModule, instruction: &HloInstruction) -> Result<(), String>
+  {
+    self.current_properties =
+      self.process_subcomputation(module, instruction.called_computations()[0])?;
+    Ok(())
+  }
 
   pub fn handle_async_update(
     &mut self, _instruction: &HloInstruction) -> Result<(), String>
@@ -464,10 +688,12 @@ impl HloCostAnalysis {
   }
 
   pub fn handle_dot(
-    &mut self, instruction: &HloInstruction) -> Result<(), String>
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
   {
     let value = HloCostAnalysis::get_dot_flops(
-      instruction.operand(0).shape(),
+      computation.operand(instruction.id(), 0).shape(),
       instruction.shape(),
       instruction.dot_dimension_numbers()) as f64;
 
@@ -475,10 +701,81 @@ impl HloCostAnalysis {
     Ok(())
   }
 
-  pub fn handle_convolution() {}
-  pub fn handle_fft() {}
-  pub fn handle_triangular_solve() {}
-  pub fn handle_cholsky() {}
+  pub fn handle_convolution(
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    let value = HloCostAnalysis::get_convolution_flops(
+      instruction,
+      computation.operand(instruction.id(), 0).shape(),
+      computation.operand(instruction.id(), 1).shape(),
+      instruction.shape()) as f64;
+
+    self.current_properties.set("flops", value);
+    Ok(())
+  }
+
+  pub fn handle_fft(
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    let operand_shape = computation.operand(instruction.id(), 0).shape();
+    let real_shape = if operand_shape.is_tuple() {
+      ShapeUtil::get_tuple_element_shape(operand_shape, 0)
+    } else {
+      operand_shape
+    };
+    const FMA_PER_COMPLEX_MUL: i64 = 4;
+    let mut log_factors = 1;
+    for dim in instruction.fft_length() {
+      log_factors *= log2_floor(*dim);
+    }
+    self.current_properties.set("flops", (HloCostAnalysis::FMA_FLOPS *
+      FMA_PER_COMPLEX_MUL * log_factors * ShapeUtil::elements_in(real_shape)) as f64);
+    Ok(())
+  }
+
+  pub fn handle_triangular_solve(
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    let a_shape = computation.operand(instruction.id(), 0).shape();
+    let b_shape = computation.operand(instruction.id(), 1).shape();
+    // Half of operand 0 is read.
+    let output_size = self.get_shape_size(instruction.shape()) as f64;
+    let a_size = self.get_shape_size(a_shape) as f64 / 2.0;
+    let b_size = self.get_shape_size(b_shape) as f64;
+    self.current_properties.set_output_bytes_accessed(vec![], output_size);
+    self.current_properties.set_operand_bytes_accessed(0, vec![], a_size);
+    self.current_properties.set_operand_bytes_accessed(1, vec![], b_size);
+    self.current_properties.set("bytes accessed", output_size + a_size + b_size);
+
+    // Estimate as batch * mn^2 / 2 flops.
+    let elems = a_shape.dimensions(a_shape.rank() - 1) * ShapeUtil::elements_in(b_shape);
+    self.current_properties.set("flops", (HloCostAnalysis::FMA_FLOPS * elems) as f64);
+    Ok(())
+  }
+
+  pub fn handle_cholsky(
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    let a_shape = computation.operand(instruction.id(), 0).shape();
+    // Half of operand 0 is read and half of the output will be written.
+    let half_size = self.get_shape_size(a_shape) as f64 / 2.0;
+    self.current_properties.set_output_bytes_accessed(vec![], half_size);
+    self.current_properties.set_operand_bytes_accessed(0, vec![], half_size);
+    self.current_properties.set("bytes accessed", half_size * 2.0);
+
+    // Estimate as batch * n^3 / 3 flops.
+    let elems = a_shape.dimensions(a_shape.rank() - 1) * ShapeUtil::elements_in(a_shape);
+    self.current_properties.set("flops", (elems / 3) as f64);
+    Ok(())
+  }
 
   pub fn handle_optimization_barrier(
     &mut self, _instruction: &HloInstruction) -> Result<(), String>
@@ -505,22 +802,57 @@ impl HloCostAnalysis {
   }
 
   pub fn handle_all_reduce(
-    &mut self, _instruction: &HloInstruction) -> Result<(), String>
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
   {
-    // TODO
+    // We assume 2 replicas, so that each output element is the sum of two
+    // input elements.
+    let mut flops = 0.0;
+    let mut output_bytes_accessed = 0.0;
+    ShapeUtil::for_each_subshape(instruction.shape(),
+      &mut |subshape: &Shape, _index: &Vec<i64>| {
+        if subshape.is_array() {
+          flops += ShapeUtil::elements_in(subshape) as f64;
+          output_bytes_accessed += self.get_shape_size(subshape) as f64;
+        }
+      });
+    let mut bytes_accessed = output_bytes_accessed;
+    for i in 0..instruction.operand_count() {
+      bytes_accessed +=
+        self.get_shape_size(computation.operand(instruction.id(), i).shape()) as f64;
+    }
+    self.current_properties.set("flops", flops);
+    self.current_properties.set_output_bytes_accessed(vec![], output_bytes_accessed);
+    self.current_properties.set("bytes accessed", bytes_accessed);
     Ok(())
   }
 
+  // Each input element takes part in one reduction.
   pub fn handle_reduce_scatter(
-    &mut self, _instruction: &HloInstruction) -> Result<(), String>
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
   {
+    let mut flops = 0.0;
+    for i in 0..instruction.operand_count() {
+      ShapeUtil::for_each_subshape(computation.operand(instruction.id(), i).shape(),
+        &mut |subshape: &Shape, _index: &Vec<i64>| {
+          if subshape.is_array() {
+            flops += ShapeUtil::elements_in(subshape) as f64;
+          }
+        });
+    }
+    self.current_properties.set("flops", flops);
     Ok(())
   }
 
   pub fn handle_all_reduce_start(
-    &mut self, instruction: &HloInstruction) -> Result<(), String>
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
   {
-    self.handle_all_reduce(instruction)
+    self.handle_all_reduce(computation, instruction)
   }
 
   pub fn handle_all_reduce_done(
@@ -571,10 +903,35 @@ impl HloCostAnalysis {
     Ok(())
   }
 
-  pub fn handle_infeed() {}
-  pub fn handle_outfeed() {}
-  pub fn handle_rng() {}
-  pub fn handle_rng_bit_generator() {}
+  pub fn handle_infeed(
+    &mut self, _instruction: &HloInstruction) -> Result<(), String>
+  {
+    Ok(())
+  }
+
+  pub fn handle_outfeed(
+    &mut self, _instruction: &HloInstruction) -> Result<(), String>
+  {
+    Ok(())
+  }
+
+  // Generating a random number is counted as one transcendental operation
+  // per element.
+  pub fn handle_rng(
+    &mut self, instruction: &HloInstruction) -> Result<(), String>
+  {
+    self.current_properties.set("transcendentals",
+      ShapeUtil::elements_in(instruction.shape()) as f64);
+    Ok(())
+  }
+
+  pub fn handle_rng_bit_generator(
+    &mut self, instruction: &HloInstruction) -> Result<(), String>
+  {
+    self.current_properties.set("transcendentals",
+      ShapeUtil::elements_in_recursive(instruction.shape()) as f64);
+    Ok(())
+  }
 
   pub fn handle_rng_get_and_update_state(
     &mut self, _nstruction: &HloInstruction) -> Result<(), String>
@@ -588,7 +945,17 @@ impl HloCostAnalysis {
     Ok(())
   }
 
-  pub fn handle_sort() {}
+  pub fn handle_sort(
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    // This assumes a comparison based N*log(N) algorithm. As for all ops, the
+    // actual properties of the op depend on the backend implementation.
+    let elements = ShapeUtil::elements_in(computation.operand(instruction.id(), 0).shape());
+    self.current_properties.set("flops", (elements * log2_ceiling(elements)) as f64);
+    Ok(())
+  }
 
   pub fn handle_parameter(
     &mut self, _instruction: &HloInstruction) -> Result<(), String>
@@ -600,7 +967,32 @@ impl HloCostAnalysis {
     Ok(())
   }
 
-  pub fn handle_reduce() {}
+  pub fn handle_reduce(
+    &mut self,
+    module: &HloModule,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    // Compute the cost of the user function.
+    let sub_properties = self.process_subcomputation(module, instruction.to_apply())?;
+
+    // Compute the cost of all elements for this Reduce operation. This counts
+    // the number of times the reduction function is applied, so it does not
+    // need to be multiplied by the number of input tensors - that's already
+    // "priced in" by the sub-computation doing more work.
+    let arg_shape = computation.operand(instruction.id(), 0).shape();
+    let output_shape = if instruction.shape().is_array() {
+      instruction.shape()
+    } else {
+      instruction.shape().tuple_shapes(0)
+    };
+    let reduction_count =
+      (ShapeUtil::elements_in(arg_shape) - ShapeUtil::elements_in(output_shape)) as f64;
+    self.current_properties.set("flops", reduction_count * sub_properties.get("flops"));
+    self.current_properties.set("transcendentals",
+      reduction_count * sub_properties.get("transcendentals"));
+    Ok(())
+  }
 
   pub fn handle_batch_norm_training(
     &mut self, _instruction: &HloInstruction) -> Result<(), String>
@@ -620,21 +1012,141 @@ impl HloCostAnalysis {
     Ok(())
   }
 
-  pub fn handle_fusion() {}
-  pub fn handle_call() {}
-  pub fn handle_custom_call() {}
-  pub fn handle_slice() {}
-  pub fn handle_dynamic_slice() {}
-  pub fn handle_dynamic_update_slice() {}
+  // The flops and transcendentals of a fusion are those of its fused
+  // instructions. The memory accessed inside the fusion is ignored, since
+  // fusion is supposed to keep intermediate data out of slow memory, so only
+  // the fusion's operands and outputs count as bytes accessed.
+  pub fn handle_fusion(
+    &mut self, module: &HloModule, instruction: &HloInstruction) -> Result<(), String>
+  {
+    let fused_id = instruction.called_computations()[0];
+    let sub_properties = self.process_subcomputation(module, fused_id)?;
+    self.current_properties.set("flops", sub_properties.get("flops"));
+    self.current_properties.set("transcendentals", sub_properties.get("transcendentals"));
+
+    let mut bytes_accessed = 0.0;
+    ShapeUtil::for_each_subshape(instruction.shape(),
+      &mut |subshape: &Shape, index: &Vec<i64>| {
+        if subshape.is_array() {
+          let size = self.get_shape_size(subshape) as f64;
+          bytes_accessed += size;
+          self.current_properties.set_output_bytes_accessed(
+            index.iter().map(|i| *i as usize).collect(), size);
+        }
+      });
+
+    let fused = module.computation(fused_id);
+    for i in 0..instruction.operand_count() {
+      let parameter = match fused.parameter_instruction(i) {
+        Some(parameter) => parameter,
+        None => continue
+      };
+      let mut operand_size = self.get_shape_size(parameter.shape()) as f64;
+      if self.options.count_multiple_input_accesses() && parameter.users().len() > 1 {
+        operand_size *= parameter.users().len() as f64;
+      }
+      bytes_accessed += operand_size;
+      self.current_properties.set_operand_bytes_accessed(i as i64, vec![], operand_size);
+    }
+    self.current_properties.set("bytes accessed", bytes_accessed);
+    Ok(())
+  }
+
+  pub fn handle_call(
+    &mut self, module: &HloModule, instruction: &HloInstruction) -> Result<(), String>
+  {
+    self.current_properties = self.process_subcomputation(module, instruction.to_apply())?;
+    self.current_should_compute_bottleneck_time = false;
+    Ok(())
+  }
+
+  // Mark applicable fields as "unknown", since we don't know what a custom
+  // call does.
+  pub fn handle_custom_call(
+    &mut self, _instruction: &HloInstruction) -> Result<(), String>
+  {
+    self.current_properties.set("optimal_seconds", -1.0);
+    self.current_properties.set("bytes accessed", -1.0);
+    self.current_properties.set("flops", -1.0);
+    self.current_should_compute_bottleneck_time = false;
+    Ok(())
+  }
+
+  pub fn handle_slice(
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    // Only the sliced elements of the operand are read.
+    let output_size = self.get_shape_size(instruction.shape()) as f64;
+    let num_input_elements =
+      ShapeUtil::elements_in(computation.operand(instruction.id(), 0).shape());
+    let num_output_elements = ShapeUtil::elements_in(instruction.shape());
+    self.current_properties.set("bytes accessed", output_size * 2.0);
+    self.current_properties.set_output_bytes_accessed(vec![], output_size);
+    self.current_properties.set_operand_bytes_accessed(0, vec![], output_size);
+    self.current_properties.set_operand_utilization(0, vec![],
+      num_output_elements as f64 / num_input_elements as f64);
+    Ok(())
+  }
+
+  pub fn handle_dynamic_slice(
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    self.handle_slice(computation, instruction)?;
+    // The start indices are read as well.
+    let mut bytes_accessed = self.current_properties.get("bytes accessed");
+    for i in 1..instruction.operand_count() {
+      let index_size =
+        self.get_shape_size(computation.operand(instruction.id(), i).shape()) as f64;
+      bytes_accessed += index_size;
+      self.current_properties.set_operand_bytes_accessed(i as i64, vec![], index_size);
+    }
+    self.current_properties.set("bytes accessed", bytes_accessed);
+    Ok(())
+  }
+
+  pub fn handle_dynamic_update_slice(
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    // Only the updated part of the output is written, and operand 0 is
+    // updated in place.
+    let update_shape = computation.operand(instruction.id(), 1).shape();
+    let update_size = self.get_shape_size(update_shape) as f64;
+    let num_update_elements = ShapeUtil::elements_in(update_shape);
+    let num_input_elements =
+      ShapeUtil::elements_in(computation.operand(instruction.id(), 0).shape());
+    let mut bytes_accessed = update_size * 2.0;
+    self.current_properties.set_output_bytes_accessed(vec![], update_size);
+    self.current_properties.set_operand_bytes_accessed(0, vec![], 0.0);
+    self.current_properties.set_operand_utilization(0, vec![],
+      num_update_elements as f64 / num_input_elements as f64);
+    self.current_properties.set_operand_bytes_accessed(1, vec![], update_size);
+    for i in 2..instruction.operand_count() {
+      let index_size =
+        self.get_shape_size(computation.operand(instruction.id(), i).shape()) as f64;
+      bytes_accessed += index_size;
+      self.current_properties.set_operand_bytes_accessed(i as i64, vec![], index_size);
+    }
+    self.current_properties.set("bytes accessed", bytes_accessed);
+    Ok(())
+  }
 
   pub fn handle_tuple(
     &mut self, instruction: &HloInstruction) -> Result<(), String>
   {
+    // The tuple instruction only gathers pointers from inputs (it doesn't
+    // iterate through them). The memory touched is then only the size of the
+    // output index table of the tuple.
     self.current_properties.set("bytes accessed",
       self.get_shape_size(instruction.shape()) as f64);
     self.current_properties.set_output_bytes_accessed(vec![],
       self.get_shape_size(instruction.shape()) as f64);
-    
+
     for i in 0..instruction.operand_count() {
       self.current_properties.set_operand_bytes_accessed(
         i as i64, vec![], 0.0);
@@ -643,12 +1155,86 @@ impl HloCostAnalysis {
     Ok(())
   }
 
-  pub fn handle_map() {}
-  pub fn handle_reduce_window() {}
-  pub fn handle_select_and_scatter() {}
-  pub fn handle_bitcast() {}
-  pub fn handle_broadcast() {}
-  pub fn handle_pad() {}
+  pub fn handle_map(
+    &mut self, module: &HloModule, instruction: &HloInstruction) -> Result<(), String>
+  {
+    // Compute properties of the mapped function.
+    let sub_properties = self.process_subcomputation(module, instruction.to_apply())?;
+
+    // Compute the cost of all elements for this Map operation.
+    let element_count = ShapeUtil::elements_in(instruction.shape()) as f64;
+    self.current_properties.set("flops", element_count * sub_properties.get("flops"));
+    self.current_properties.set("transcendentals",
+      element_count * sub_properties.get("transcendentals"));
+    Ok(())
+  }
+
+  pub fn handle_reduce_window(
+    &mut self, module: &HloModule, instruction: &HloInstruction) -> Result<(), String>
+  {
+    // Compute the properties of the reduction function.
+    let sub_properties = self.process_subcomputation(module, instruction.to_apply())?;
+
+    // For each output element there are window_size - 1 reductions to
+    // perform.
+    let window_element_count = window_element_count(instruction.window());
+    let output_shape = if instruction.shape().is_array() {
+      instruction.shape()
+    } else {
+      instruction.shape().tuple_shapes(0)
+    };
+    let reduction_count =
+      ((window_element_count - 1) * ShapeUtil::elements_in(output_shape)) as f64;
+    self.current_properties.set("flops", reduction_count * sub_properties.get("flops"));
+    self.current_properties.set("transcendentals",
+      reduction_count * sub_properties.get("transcendentals"));
+    Ok(())
+  }
+
+  pub fn handle_select_and_scatter(
+    &mut self,
+    module: &HloModule,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    let select_properties = self.process_subcomputation(module, instruction.select())?;
+    let scatter_properties = self.process_subcomputation(module, instruction.scatter())?;
+
+    // For each scatter source element there are window_size - 1 select
+    // computations to perform and 1 scatter computation to perform.
+    let source_element_count =
+      ShapeUtil::elements_in(computation.operand(instruction.id(), 1).shape()) as f64;
+    let select_count =
+      source_element_count * (window_element_count(instruction.window()) - 1) as f64;
+    for key in [FLOPS_KEY, TRANSCENDENTALS_KEY] {
+      self.current_properties.set(key, select_count * select_properties.get(key) +
+        source_element_count * scatter_properties.get(key));
+    }
+    Ok(())
+  }
+
+  pub fn handle_bitcast(
+    &mut self, _instruction: &HloInstruction) -> Result<(), String>
+  {
+    // A bitcast does no computation and touches no memory.
+    self.current_properties.set("bytes accessed", 0.0);
+    self.current_properties.set_output_bytes_accessed(vec![], 0.0);
+    self.current_properties.set_operand_bytes_accessed(0, vec![], 0.0);
+    self.current_properties.set("optimal_seconds", 0.0);
+    Ok(())
+  }
+
+  pub fn handle_broadcast(
+    &mut self, _instruction: &HloInstruction) -> Result<(), String>
+  {
+    Ok(())
+  }
+
+  pub fn handle_pad(
+    &mut self, _instruction: &HloInstruction) -> Result<(), String>
+  {
+    Ok(())
+  }
 
   pub fn handle_reshape(
     &mut self, _instruction: &HloInstruction) -> Result<(), String>
@@ -656,14 +1242,134 @@ impl HloCostAnalysis {
     Ok(())
   }
 
-  pub fn handle_dynamic_reshape() {}
-  pub fn handle_add_dependency() {}
-  pub fn handle_after_all() {}
-  pub fn handle_transpose() {}
-  pub fn handle_while() {}
-  pub fn handle_conditional() {}
-  pub fn handle_gather() {}
-  pub fn handle_scatter() {}
+  pub fn handle_dynamic_reshape(
+    &mut self, _instruction: &HloInstruction) -> Result<(), String>
+  {
+    Ok(())
+  }
+
+  // This instruction is used to enforce ordering at compile time. No code is
+  // emitted.
+  pub fn handle_add_dependency(
+    &mut self, instruction: &HloInstruction) -> Result<(), String>
+  {
+    self.handle_domain(instruction)
+  }
+
+  // This instruction is used to enforce ordering at compile time. No code is
+  // emitted.
+  pub fn handle_after_all(
+    &mut self, instruction: &HloInstruction) -> Result<(), String>
+  {
+    self.handle_domain(instruction)
+  }
+
+  pub fn handle_transpose(
+    &mut self, _instruction: &HloInstruction) -> Result<(), String>
+  {
+    Ok(())
+  }
+
+  // The number of iterations of a while loop cannot always be statically
+  // analyzed. If the loop carries a known trip count in its backend config
+  // the body is counted that many times and the condition once more;
+  // otherwise a single iteration of each is counted.
+  pub fn handle_while(
+    &mut self, module: &HloModule, instruction: &HloInstruction) -> Result<(), String>
+  {
+    let body_properties = self.process_subcomputation(module, instruction.while_body())?;
+    let condition_properties =
+      self.process_subcomputation(module, instruction.while_condition())?;
+    let (body_count, condition_count) = match instruction.while_loop_backend_config() {
+      Some(config) => (config.known_trip_count() as f64, config.known_trip_count() as f64 + 1.0),
+      None => (1.0, 1.0)
+    };
+
+    self.current_properties = Properties::new();
+    body_properties.for_each(|key, val| {
+      self.current_properties.add(key, val * body_count);
+    });
+    condition_properties.for_each(|key, val| {
+      self.current_properties.add(key, val * condition_count);
+    });
+    self.current_should_compute_bottleneck_time = false;
+    Ok(())
+  }
+
+  // Compute the cost of the branch computations and take the maximum from
+  // those for each property.
+  pub fn handle_conditional(
+    &mut self, module: &HloModule, instruction: &HloInstruction) -> Result<(), String>
+  {
+    self.current_properties =
+      self.process_subcomputation(module, instruction.branch_computation(0))?;
+    for i in 1..instruction.branch_count() {
+      let branch_properties =
+        self.process_subcomputation(module, instruction.branch_computation(i))?;
+      branch_properties.for_each(|key, val| {
+        let current = self.current_properties.get(key);
+        self.current_properties.set(key, current.max(val));
+      });
+    }
+    self.current_should_compute_bottleneck_time = false;
+    Ok(())
+  }
+
+  pub fn handle_gather(
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    // Gather doesn't read the whole input buffer, it's equivalent to a copy
+    // the size of the output shape and a read of the gather indices.
+    let output_size = self.get_shape_size(instruction.shape()) as f64;
+    let indices_size =
+      self.get_shape_size(computation.operand(instruction.id(), 1).shape()) as f64;
+    let num_input_elements =
+      ShapeUtil::elements_in(computation.operand(instruction.id(), 0).shape());
+    self.current_properties.set("bytes accessed", output_size * 2.0 + indices_size);
+    self.current_properties.set_operand_bytes_accessed(0, vec![], output_size);
+    self.current_properties.set_operand_bytes_accessed(1, vec![], indices_size);
+    self.current_properties.set_operand_utilization(0, vec![],
+      ShapeUtil::elements_in(instruction.shape()) as f64 / num_input_elements as f64);
+    self.current_properties.set_output_bytes_accessed(vec![], output_size);
+    // Gather does not issue any flops.
+    Ok(())
+  }
+
+  pub fn handle_scatter(
+    &mut self,
+    module: &HloModule,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    // The operands are the scattered arrays, the indices and one update per
+    // scattered array. Only the updated elements are read and written.
+    let num_scattered = (instruction.operand_count() - 1) / 2;
+    let mut total_update_size = 0.0;
+    for i in 0..num_scattered {
+      let update_size = self.get_shape_size(
+        computation.operand(instruction.id(), num_scattered + 1 + i).shape()) as f64;
+      self.current_properties.set_operand_bytes_accessed(i as i64, vec![], update_size);
+      self.current_properties.set_operand_bytes_accessed(
+        (num_scattered + 1 + i) as i64, vec![], update_size);
+      total_update_size += update_size;
+    }
+    let indices_size = self.get_shape_size(
+      computation.operand(instruction.id(), num_scattered).shape()) as f64;
+    self.current_properties.set_operand_bytes_accessed(
+      num_scattered as i64, vec![], indices_size);
+    self.current_properties.set("bytes accessed", indices_size + 3.0 * total_update_size);
+    self.current_properties.set_output_bytes_accessed(vec![], total_update_size);
+
+    let element_count = ShapeUtil::elements_in(
+      computation.operand(instruction.id(), num_scattered + 1).shape()) as f64;
+    let sub_properties = self.process_subcomputation(module, instruction.to_apply())?;
+    self.current_properties.set("flops", element_count * sub_properties.get("flops"));
+    self.current_properties.set("transcendentals",
+      element_count * sub_properties.get("transcendentals"));
+    Ok(())
+  }
 
   pub fn handle_get_dimension_size(
     &mut self, _instruction: &HloInstruction) -> Result<(), String>
@@ -678,8 +1384,13 @@ impl HloCostAnalysis {
   }
 
   pub fn handle_top_k(
-    &mut self, _instruction: &HloInstruction) -> Result<(), String>
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
   {
+    let input_size = ShapeUtil::elements_in(computation.operand(instruction.id(), 0).shape());
+    self.current_properties.set("flops",
+      (input_size * log2_ceiling(instruction.k()).max(1)) as f64);
     Ok(())
   }
 
@@ -689,42 +1400,87 @@ impl HloCostAnalysis {
     Ok(())
   }
 
-  pub fn preprocess(&mut self, _instruction: &HloInstruction) -> Result<(), String> {
-    unimplemented!()
+  // Resets the current properties and fills in the bytes accessed, which by
+  // default are the sizes of the output and of every operand.
+  pub fn preprocess(
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
+    self.current_properties = Properties::new();
+    self.current_should_compute_bottleneck_time = true;
+
+    let output_size = self.get_shape_size(instruction.shape()) as f64;
+    let mut bytes_accessed = output_size;
+    self.current_properties.set_output_bytes_accessed(vec![], output_size);
+    for i in 0..instruction.operand_count() {
+      let operand_size =
+        self.get_shape_size(computation.operand(instruction.id(), i).shape()) as f64;
+      bytes_accessed += operand_size;
+      self.current_properties.set_operand_bytes_accessed(i as i64, vec![], operand_size);
+      self.current_properties.set_operand_utilization(i as i64, vec![], 1.0);
+    }
+    self.current_properties.set("bytes accessed", bytes_accessed);
+    Ok(())
   }
 
-  pub fn postprocess(&mut self, _instruction: &HloInstruction) -> Result<(), String> {
-    unimplemented!()
+  // Computes the optimal seconds of the instruction from the per second
+  // rates, and records its properties.
+  pub fn postprocess(&mut self, instruction: &HloInstruction) -> Result<(), String> {
+    if self.current_should_compute_bottleneck_time {
+      let mut optimal_seconds: f64 = 0.0;
+      self.current_properties.for_each(|key, val| {
+        if key == OPTIMAL_SECONDS_KEY {
+          return;
+        }
+        let per_second_rate = self.options.per_second_rate(key);
+        if per_second_rate != 0.0 {
+          optimal_seconds = optimal_seconds.max(val / per_second_rate);
+        }
+      });
+      self.current_properties.set("optimal_seconds", optimal_seconds);
+    }
+
+    let current_properties =
+      std::mem::replace(&mut self.current_properties, Properties::new());
+    current_properties.for_each(|key, val| self.properties_sum.add(key, val));
+    self.hlo_properties.insert(key_of(instruction), current_properties);
+    Ok(())
   }
 
   // Enable efficient update if a known small set of instructions within an
   // HLO graph was modified.
   pub fn remove_instruction(&mut self, instruction: &HloInstruction) -> Result<(), String> {
-    let target = self.hlo_properties.get(instruction);
-    if target.is_some() {
-      let func = |key: &str, val: f64| {
-        self.properties_sum.set(key, val);
-        self.hlo_properties.remove(instruction);
-      };
-      self.current_properties.for_each(func);
+    // Subtract the instruction's properties from the summed properties.
+    if let Some(properties) = self.hlo_properties.remove(&key_of(instruction)) {
+      properties.for_each(|key, val| self.properties_sum.add(key, -val));
     }
     Ok(())
   }
 
   // Updates the cost analysis by re-doing the analysis of one instruction.
-  pub fn revisit_instruction(&mut self, instruction: &HloInstruction) -> Result<(), String> {
-    let mut result = self.remove_instruction(instruction);
-    if result.is_err() { return result; }
-
-    result = self.preprocess(instruction);
-    if result.is_err() { return result; }
-    
-    // TODO
-
-    result = self.postprocess(instruction);
-    if result.is_err() { return result; }
-
-    Ok(())
+  pub fn revisit_instruction(
+    &mut self, module: &HloModule, instruction: &HloInstruction) -> Result<(), String>
+  {
+    self.remove_instruction(instruction)?;
+    let computation = match instruction.parent() {
+      Some(id) => module.computation(id),
+      None => return Err(format!(
+        "Instruction {} is not in a computation.", instruction.name()))
+    };
+    self.preprocess(computation, instruction)?;
+    self.visit(module, computation, instruction)?;
+    self.postprocess(instruction)
+  }
+
+  // Returns the properties summed over the called computation 'id', analyzed
+  // with the same options.
+  fn process_subcomputation(
+    &self, module: &HloModule, id: ComputationId) -> Result<Properties, String>
+  {
+    let mut visitor = HloCostAnalysis::new(self.options.clone());
+    visitor.run_on_computation(module, id)?;
+    Ok(visitor.properties_sum)
   }
 
   // Decorates shape_size by returning 0 immediately if the shape does not have
@@ -741,7 +1497,7 @@ impl HloCostAnalysis {
   }
 
   pub fn transcendental_count(&self) -> f64 {
-    self.properties_sum.get("transcendentalls")
+    self.properties_sum.get("transcendentals")
   }
 
   pub fn bytes_accessed(&self) -> f64 {
@@ -752,18 +1508,101 @@ impl HloCostAnalysis {
     self.properties_sum.get("optimal_seconds")
   }
 
+  pub fn properties_sum(&self) -> &Properties {
+    &self.properties_sum
+  }
+
+  // Returns the properties computed for 'instruction', if it was analyzed.
+  pub fn properties(&self, instruction: &HloInstruction) -> Option<&Properties> {
+    self.hlo_properties.get(&key_of(instruction))
+  }
+
+  fn get_property_for_hlo(&self, instruction: &HloInstruction, key: &str) -> f64 {
+    self.properties(instruction).map_or(0.0, |properties| properties.get(key))
+  }
+
+  // Returns the respective property of a single instruction.
+  pub fn flop_count_for(&self, instruction: &HloInstruction) -> i64 {
+    self.get_property_for_hlo(instruction, FLOPS_KEY) as i64
+  }
+
+  pub fn transcendental_count_for(&self, instruction: &HloInstruction) -> i64 {
+    self.get_property_for_hlo(instruction, TRANSCENDENTALS_KEY) as i64
+  }
+
+  pub fn bytes_accessed_for(&self, instruction: &HloInstruction) -> i64 {
+    self.get_property_for_hlo(instruction, BYTES_ACCESSED_KEY) as i64
+  }
+
+  pub fn optimal_seconds_for(&self, instruction: &HloInstruction) -> f64 {
+    self.get_property_for_hlo(instruction, OPTIMAL_SECONDS_KEY)
+  }
+
+  // Returns the fraction of the elements of operand 'operand_num' (at
+  // 'shape_index') that 'instruction' reads.
+  pub fn operand_utilization(
+    &self,
+    instruction: &HloInstruction,
+    operand_num: i64,
+    shape_index: Vec<usize>) -> f64
+  {
+    self.properties(instruction)
+      .map_or(0.0, |properties| properties.operand_utilization(operand_num, shape_index))
+  }
+
+  pub fn operand_bytes_accessed(
+    &self,
+    instruction: &HloInstruction,
+    operand_num: i64,
+    shape_index: Vec<usize>) -> i64
+  {
+    self.properties(instruction)
+      .map_or(0.0, |properties| properties.operand_bytes_accessed(operand_num, shape_index))
+      as i64
+  }
+
+  pub fn output_bytes_accessed(
+    &self, instruction: &HloInstruction, shape_index: Vec<usize>) -> i64
+  {
+    self.properties(instruction)
+      .map_or(0.0, |properties| properties.output_bytes_accessed(shape_index)) as i64
+  }
+
   pub fn get_dot_flops(
     lhs_shape: &Shape, result_shape: &Shape, dnums: &DotDimensionNumbers) -> i64
   {
     // Count of elements along the reduction dimensions.
     let mut reduction_width = 1;
-    for dim in 0..dnums.lhs_contracting_dimensions() {
-      reduction_width += lhs_shape.dimensions(dim as usize);
+    for dim in dnums.lhs_contracting_dimensions() {
+      reduction_width *= lhs_shape.dimensions(*dim as usize);
     }
     // Each output element requires resuction_width FMA operations.
     HloCostAnalysis::FMA_FLOPS * ShapeUtil::elements_in(result_shape) * reduction_width
   }
 
+  // Returns the flops of 'convolution'. Only the kernel positions which fall
+  // on valid (not padded and not dilated) input elements are counted.
+  pub fn get_convolution_flops(
+    convolution: &HloInstruction,
+    lhs_shape: &Shape,
+    rhs_shape: &Shape,
+    result_shape: &Shape) -> i64
+  {
+    let dnums = convolution.convolution_dimension_numberes();
+    let input_feature =
+      ShapeUtil::get_dimension(lhs_shape, dnums.input_feature_dimension());
+    let output_feature =
+      ShapeUtil::get_dimension(result_shape, dnums.output_feature_dimension());
+    let batch = ShapeUtil::get_dimension(lhs_shape, dnums.input_batch_dimension());
+
+    let valid_position_counts = valid_convolution_positions(
+      convolution.window(), dnums, lhs_shape, rhs_shape, result_shape);
+    let fma_count = (input_feature / convolution.feature_group_count()) *
+      output_feature * (batch / convolution.batch_group_count()) *
+      valid_position_counts.iter().product::<i64>();
+    fma_count * HloCostAnalysis::FMA_FLOPS
+  }
+
   fn handle_elementwise_op(&mut self, instruction: &HloInstruction) -> Result<(), String> {
     let shape = instruction.shape();
     let computation_count = ShapeUtil::elements_in(shape);
@@ -805,4 +1644,271 @@ impl HloCostAnalysis {
 
     Ok(())
   }
-}
\ No newline at end of file
+}
+
+// Returns the number of elements in a window.
+fn window_element_count(window: &Window) -> i64 {
+  window.dimensions_vec().iter().map(|dimension| dimension.size()).product()
+}
+
+// Returns, for each spatial dimension of a convolution, the number of
+// (output position, kernel position) pairs which read a valid input element.
+fn valid_convolution_positions(
+  window: &Window,
+  dnums: &ConvolutionDimensionNumbers,
+  lhs_shape: &Shape,
+  rhs_shape: &Shape,
+  result_shape: &Shape) -> Vec<i64>
+{
+  let mut valid_position_counts = vec![];
+  for spatial_dimension in 0..window.dimensions_size() {
+    let window_dim = window.dimensions(spatial_dimension);
+    let kernel_limit =
+      rhs_shape.dimensions(dnums.kernel_spatial_dimensions(spatial_dimension) as usize);
+    let output_limit =
+      result_shape.dimensions(dnums.output_spatial_dimensions(spatial_dimension) as usize);
+    let input_limit =
+      lhs_shape.dimensions(dnums.input_spatial_dimensions(spatial_dimension) as usize);
+
+    // These two conditions will create an N^2 iteration pattern with only N
+    // valid elements. This is a performance optimization and produces the
+    // same result as the whole loop.
+    if input_limit == output_limit && kernel_limit == output_limit &&
+       input_limit == window_dim.base_dilation() && window_dim.window_dilation() == 1 &&
+       (input_limit - 1).max(1) == window_dim.stride() &&
+       window_dim.padding_low() == 0 && window_dim.padding_high() == 0
+    {
+      valid_position_counts.push(input_limit);
+      continue;
+    }
+    if input_limit == 1 && kernel_limit == output_limit &&
+       window_dim.window_dilation() == 1 && window_dim.base_dilation() == 1 &&
+       window_dim.stride() == 1 && window_dim.padding_high() == output_limit - 1 &&
+       window_dim.padding_low() == output_limit - 1
+    {
+      valid_position_counts.push(output_limit);
+      continue;
+    }
+
+    let mut valid_position_count = 0;
+    // Loop over each point in the kernel.
+    for kernel_idx in 0..kernel_limit {
+      // Skip loop for trivial stride and base_dilation.
+      if window_dim.stride() == 1 && window_dim.base_dilation() == 1 {
+        let undilated_index_base =
+          window_dim.padding_low() - kernel_idx * window_dim.window_dilation();
+        valid_position_count += ((input_limit + undilated_index_base).min(output_limit) -
+          undilated_index_base.max(0)).max(0);
+        continue;
+      }
+
+      // Loop over each point in the output.
+      for output_idx in 0..output_limit {
+        // Calculate lhs (input) index without taking base dilation into
+        // account.
+        let undilated_index = output_idx * window_dim.stride() -
+          window_dim.padding_low() + kernel_idx * window_dim.window_dilation();
+        // Calculate the actual lhs (input) index after dilation.
+        let lhs_spatial_index = if window_dim.base_dilation() > 1 {
+          undilated_index / window_dim.base_dilation()
+        } else {
+          undilated_index
+        };
+        // Skip if the lhs (input) index is to be dilated, or out of bounds.
+        if undilated_index != lhs_spatial_index * window_dim.base_dilation() {
+          continue;
+        }
+        if lhs_spatial_index < 0 || lhs_spatial_index >= input_limit {
+          continue;
+        }
+        valid_position_count += 1;
+      }
+    }
+    valid_position_counts.push(valid_position_count);
+  }
+  valid_position_counts
+}
+
+fn log2_floor(value: i64) -> i64 {
+  if value <= 1 { 0 } else { 63 - (value as u64).leading_zeros() as i64 }
+}
+
+fn log2_ceiling(value: i64) -> i64 {
+  if value <= 1 { 0 } else { 64 - ((value - 1) as u64).leading_zeros() as i64 }
+}
+
+#[cfg(test)]
+mod tests {
+  use common::blitz_data::WhileLoopBackendConfig;
+  use crate::test_utils::{find, parse_module};
+  use super::*;
+
+  fn analyze(module: &HloModule) -> HloCostAnalysis {
+    let mut analysis =
+      HloCostAnalysis::new(Options::new(HloCostAnalysis::default_shape_size));
+    analysis.run(module).unwrap();
+    analysis
+  }
+
+  #[test]
+  fn test_elementwise_and_dot() {
+    let module = parse_module(r#"
+HloModule m
+
+ENTRY entry {
+  lhs = f32[10,5] parameter(0)
+  rhs = f32[5,30] parameter(1)
+  dot = f32[10,30] dot(lhs, rhs), lhs_contracting_dims={1}, rhs_contracting_dims={0}
+  ROOT exp = f32[10,30] exponential(dot)
+}
+"#);
+    let analysis = analyze(&module);
+    let dot = find(&module, "dot");
+    // Each output element takes 5 FMAs.
+    assert_eq!(analysis.flop_count_for(dot), 2 * 10 * 30 * 5);
+    assert_eq!(analysis.bytes_accessed_for(dot), 4 * (10 * 5 + 5 * 30 + 10 * 30));
+    assert_eq!(analysis.operand_bytes_accessed(dot, 1, vec![]), 4 * 5 * 30);
+    assert_eq!(analysis.output_bytes_accessed(dot, vec![]), 4 * 10 * 30);
+    assert_eq!(analysis.transcendental_count_for(find(&module, "exp")), 300);
+
+    assert_eq!(analysis.flop_count(), (2 * 10 * 30 * 5) as f64);
+    assert_eq!(analysis.transcendental_count(), 300.0);
+    // Parameters touch no memory.
+    assert_eq!(analysis.bytes_accessed(), (4 * (10 * 5 + 5 * 30 + 10 * 30 + 2 * 10 * 30)) as f64);
+  }
+
+  #[test]
+  fn test_convolution() {
+    let module = parse_module(r#"
+HloModule m
+
+ENTRY entry {
+  input = f32[1,10,20,1] parameter(0)
+  kernel = f32[3,3,1,1] parameter(1)
+  ROOT conv = f32[1,10,20,1] convolution(input, kernel), window={size=3x3 pad=1_1x1_1}, dim_labels=b01f_01io->b01f
+}
+"#);
+    let analysis = analyze(&module);
+    // Without padding every output element would take 9 FMAs; the kernel
+    // positions falling on the padding are not counted.
+    assert_eq!(analysis.flop_count(), (2 * (10 * 3 - 2) * (20 * 3 - 2)) as f64);
+  }
+
+  #[test]
+  fn test_reduce_and_reduce_window() {
+    let module = parse_module(r#"
+HloModule m
+
+add {
+  x = f32[] parameter(0)
+  y = f32[] parameter(1)
+  ROOT add = f32[] add(x, y)
+}
+
+ENTRY entry {
+  input = f32[10,20] parameter(0)
+  zero = f32[] constant(0)
+  reduce = f32[10] reduce(input, zero), dimensions={1}, to_apply=add
+  window = f32[9,19] reduce-window(input, zero), window={size=2x2}, to_apply=add
+  ROOT tuple = (f32[10], f32[9,19]) tuple(reduce, window)
+}
+"#);
+    let analysis = analyze(&module);
+    assert_eq!(analysis.flop_count_for(find(&module, "reduce")), 10 * 20 - 10);
+    assert_eq!(analysis.flop_count_for(find(&module, "window")), 3 * 9 * 19);
+  }
+
+  #[test]
+  fn test_fusion() {
+    let module = parse_module(r#"
+HloModule m
+
+fused_computation {
+  p0 = f32[2,2] parameter(0)
+  p1 = f32[2,2] parameter(1)
+  add = f32[2,2] add(p0, p1)
+  ROOT mul = f32[2,2] multiply(add, p0)
+}
+
+ENTRY entry {
+  a = f32[2,2] parameter(0)
+  b = f32[2,2] parameter(1)
+  ROOT fusion = f32[2,2] fusion(a, b), kind=kLoop, calls=fused_computation
+}
+"#);
+    let analysis = analyze(&module);
+    let fusion = find(&module, "fusion");
+    assert_eq!(analysis.flop_count_for(fusion), 8);
+    // The intermediate add is not counted as memory traffic.
+    assert_eq!(analysis.bytes_accessed_for(fusion), 3 * 16);
+
+    let mut options = Options::new(HloCostAnalysis::default_shape_size);
+    options.set_count_multiple_input_accesses(true);
+    let mut analysis = HloCostAnalysis::new(options);
+    analysis.run(&module).unwrap();
+    assert_eq!(analysis.operand_bytes_accessed(fusion, 0, vec![]), 2 * 16);
+    assert_eq!(analysis.bytes_accessed_for(fusion), 4 * 16);
+  }
+
+  #[test]
+  fn test_while_with_known_trip_count() {
+    let text = r#"
+HloModule m
+
+body {
+  p = f32[4] parameter(0)
+  ROOT negate = f32[4] negate(p)
+}
+
+condition {
+  p = f32[4] parameter(0)
+  ROOT result = pred[] constant(true)
+}
+
+ENTRY entry {
+  init = f32[4] parameter(0)
+  ROOT while = f32[4] while(init), condition=condition, body=body
+}
+"#;
+    let module = parse_module(text);
+    let analysis = analyze(&module);
+    assert_eq!(analysis.flop_count_for(find(&module, "while")), 4);
+
+    let mut module = parse_module(text);
+    let entry = module.entry_computation_id();
+    let while_id = module.computation(entry).root_instruction().id();
+    let mut config = WhileLoopBackendConfig::new();
+    config.set_known_trip_count(5);
+    module.mutable_computation(entry).mutable_instruction(while_id).set_backend_config(config);
+    let analysis = analyze(&module);
+    assert_eq!(analysis.flop_count_for(find(&module, "while")), 5 * 4);
+  }
+
+  #[test]
+  fn test_all_reduce_and_remove_instruction() {
+    let module = parse_module(r#"
+HloModule m
+
+add {
+  x = f32[] parameter(0)
+  y = f32[] parameter(1)
+  ROOT add = f32[] add(x, y)
+}
+
+ENTRY entry {
+  p = f32[8] parameter(0)
+  ROOT all-reduce = f32[8] all-reduce(p), replica_groups={}, to_apply=add
+}
+"#);
+    let mut analysis = analyze(&module);
+    let all_reduce = find(&module, "all-reduce");
+    assert_eq!(analysis.flop_count_for(all_reduce), 8);
+    assert_eq!(analysis.bytes_accessed_for(all_reduce), 2 * 32);
+
+    analysis.remove_instruction(all_reduce).unwrap();
+    assert_eq!(analysis.flop_count(), 0.0);
+    assert!(analysis.properties(all_reduce).is_none());
+    analysis.revisit_instruction(&module, all_reduce).unwrap();
+    assert_eq!(analysis.flop_count(), 8.0);
+  }
+}
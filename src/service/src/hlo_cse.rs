@@ -32,13 +32,15 @@ impl HloCSE {
     execution_threads: &HashSet<String>) -> bool
   {
     let mut changed = false;
-    for computation in module.mutable_computations_by_exec_threads(execution_threads) {
+    for id in module.computations_by_exec_threads(execution_threads) {
+      let computation = module.mutable_computation(id);
       if self.only_fusion_computations && !computation.is_fusion_computation() {
         continue;
       }
       changed |= HloCSE::combine_constants(computation, self.is_layout_sensitive);
       
-      for instruction in computation.mutable_make_instruction_post_order() {
+      for instruction_id in computation.make_instruction_post_order() {
+        let instruction = computation.instruction(instruction_id);
         // If the instruction has zero operands (constants, parameters, etc.) skip over it.
         if instruction.operand_count() == 0 &&
            instruction.opcode() != HloOpcode::PartitionId &&
@@ -52,16 +54,16 @@ impl HloCSE {
         }
         // representatives.insert
         for i in 0..instruction.operand_count() {
-          let a = instruction.mutable_operand(i);
-          if a.unwrap().opcode() != HloOpcode::Iota {
+          let a = instruction.operand(i);
+          if computation.instruction(a).opcode() != HloOpcode::Iota {
             continue;
           }
           for j in i+1..instruction.operand_count() {
-            let b = instruction.mutable_operand(j);
+            let b = instruction.operand(j);
 
             changed = true;
-            if b.unwrap().is_dead() {
-              //computation.remove_instruction(b.unwrap());
+            if computation.is_dead(b) {
+              //computation.remove_instruction(b);
             }
           }
         }
@@ -5,10 +5,12 @@ use std::collections::{HashMap, HashSet};
 use hlo::{
   hlo_computation::HloComputation,
   hlo_instruction::HloInstruction,
-  hlo_module::HloModule,
+  hlo_module::{ComputationId, HloModule},
   hlo_opcode::HloOpcode,
 };
 
+use crate::hlo_module_dce::HloModuleDCE;
+
 // HLO pass which removes dead instructions from each computation in
 // the module and removes dead computations from the module.
 // An instruction is dead if it is not reachable from the root.
@@ -16,17 +18,25 @@ use hlo::{
 // it is not reachable from the entry computation.
 // This pass does not remove dead parameter instructions, as parameter instructions
 // cannot be deleted.
+// If remove_dead_tuple_elements is set, dead tuple elements of while loops and
+// unused parameters of called computations are removed first (HloModuleDCE).
 pub struct HloDCE {
-  remove_cross_partition_collective_ops: bool
+  remove_cross_partition_collective_ops: bool,
+  remove_dead_tuple_elements: bool
 }
 
 impl HloDCE {
   pub fn new(remove_cross_partition_collective_ops: bool) -> Self {
     HloDCE {
-      remove_cross_partition_collective_ops: remove_cross_partition_collective_ops
+      remove_cross_partition_collective_ops: remove_cross_partition_collective_ops,
+      remove_dead_tuple_elements: false
     }
   }
 
+  pub fn set_remove_dead_tuple_elements(&mut self, remove_dead_tuple_elements: bool) {
+    self.remove_dead_tuple_elements = remove_dead_tuple_elements;
+  }
+
   pub fn name() -> String {
     "dce".to_string()
   }
@@ -35,7 +45,7 @@ impl HloDCE {
   pub fn run_on_computation(
     &mut self,
     computation: &mut HloComputation,
-    remove_cross_partition_collective_ops: bool) -> bool
+    remove_cross_partition_collective_ops: bool) -> Result<bool, String>
   {
     let mut changed = false;
     println!("Before dce:");
@@ -46,24 +56,25 @@ impl HloDCE {
     // through the computation's instruction while simultaneously removing instructions.
     let mut dead_roots = Vec::new();
     for instruction in computation.instructions() {
-      if instruction.is_dead() &&
+      let id = instruction.id();
+      let is_sharding_of_live_operand = instruction.is_custom_call("Sharding".to_string()) &&
+        (computation.is_root(instruction.operand(0)) ||
+         computation.operand(id, 0).opcode() == HloOpcode::Parameter ||
+         computation.operand(id, 0).user_count() != 1);
+      if computation.is_dead(id) &&
          computation.is_safely_removable(instruction, false) &&
-         (!instruction.is_custom_call("Sharding".to_string()) ||
-          (!instruction.operand(0).is_root() &&
-            instruction.opcode() != HloOpcode::Parameter &&
-            instruction.operand(0).user_count() == 1) &&
-          (!instruction.has_side_effect() ||
-           (remove_cross_partition_collective_ops &&
-            instruction.is_collective_instruction() && !instruction.constrain_layout()) ||
-          HloDCE::is_removable_while(instruction, remove_cross_partition_collective_ops)))
+         !is_sharding_of_live_operand &&
+         (!instruction.has_side_effect() ||
+          (remove_cross_partition_collective_ops &&
+           instruction.is_collective_instruction() && !instruction.constrain_layout()) ||
+          HloDCE::is_removable_while(instruction, remove_cross_partition_collective_ops))
       {
-        dead_roots.push(instruction);
+        dead_roots.push(id);
       }
     }
 
-    for _dead_root in dead_roots {
-      // TODO
-      //computation.remove_instruction_and_unused_operands(dead_root);
+    for dead_root in dead_roots {
+      computation.remove_instruction_and_unused_operands(dead_root)?;
       changed = true;
     }
 
@@ -71,37 +82,51 @@ impl HloDCE {
       println!("After dce:");
       println!("{:?}", computation.to_string());
     }
-    changed
+    Ok(changed)
   }
 
   // Run the pass on the given module.
   // Returns whether the module was changed (instructions were removed).
-  pub fn run(&mut self, module: &HloModule, execution_threads: HashSet<String>) -> bool {
+  pub fn run(
+    &mut self,
+    module: &mut HloModule,
+    execution_threads: HashSet<String>) -> Result<bool, String>
+  {
     let mut changed = false;
     println!("Before dce:");
     println!("{:?}", module.to_string());
 
+    // Remove dead code which crosses computation boundaries first. The dead
+    // instructions it leaves behind are removed below.
+    if self.remove_dead_tuple_elements {
+      changed |= HloModuleDCE::new()
+        .remove_dead_code_across_computations(module, &execution_threads)?;
+    }
+
     // Run DCE on each computation.
     for computation in
       module.make_computation_post_order(&execution_threads, false) {
-      changed |= self.run_on_computation(computation, self.remove_cross_partition_collective_ops)
+      changed |= self.run_on_computation(module.mutable_computation(computation),
+        self.remove_cross_partition_collective_ops)?;
     }
 
     // Now DCE HloComputations.
     // Keep doing passes through the module until no more computations can be
     // eliminated. The functions removes all aubcomputations that cana be proved
     // to have no ramining live callers.
-    changed |= HloDCE::recursively_remove_dead_computations(module, execution_threads);
+    changed |= HloDCE::recursively_remove_dead_computations(module, execution_threads)?;
 
     println!("After dve:");
     println!("{:?}", module.to_string());
 
-    changed
+    Ok(changed)
   }
 
   // Finds all computations that are not called by any instruction and removes
   // them from the module. Returns whether any dead code was removed.
-  fn recursively_remove_dead_computations(module: &HloModule, execution_threads: HashSet<String>) -> bool {
+  fn recursively_remove_dead_computations(
+    module: &mut HloModule, execution_threads: HashSet<String>) -> Result<bool, String>
+  {
     // Tracks whether any dead code is eliminated by this pass.
     let mut module_contains_dead_code = false;
 
@@ -109,22 +134,18 @@ impl HloDCE {
     // instruction. We need to record this as a refcount map rather than a set
     // since we cannot guarantee that control flow flattening has been dine
     // and there may be multiple call sites.
-    let mut live_computation_call_count: HashMap<&HloComputation, i64> = HashMap::new();
-    let mut count = 1;
-    let entry_computation = module.entry_computation();
-    if entry_computation.is_some() {
-      live_computation_call_count.insert(entry_computation.unwrap(), count);
-      count += 1;
+    let mut live_computation_call_count: HashMap<ComputationId, i64> = HashMap::new();
+    if module.has_entry_computation() {
+      *live_computation_call_count.entry(module.entry_computation_id()).or_insert(0) += 1;
     }
 
     // Account for all threads' caller when counting a sub computation's live
     // call count.
     for computation in
-      module.make_computation_post_order(&execution_threads,false) {
-      for instruction in computation.instructions() {
+      module.make_computation_post_order(&HashSet::new(), false) {
+      for instruction in module.computation(computation).instructions() {
         for subcomp in instruction.called_computations() {
-          live_computation_call_count.insert(subcomp, count);
-          count += 1;
+          *live_computation_call_count.entry(*subcomp).or_insert(0) += 1;
         }
       }
     }
@@ -137,46 +158,46 @@ impl HloDCE {
       // inplies that computation is dead, but is nested in other dead computations.
       // These inner computations are ignored here since they will be removed
       // recursing through other computations.
-      if !live_computation_call_count.contains_key(computation) {
-        if HloDCE::recursively_remove_dead_computation(module, computation,
-        &live_computation_call_count).is_err()
-        {
-          module_contains_dead_code = true;
-        }
+      if !live_computation_call_count.contains_key(&computation) {
+        HloDCE::recursively_remove_dead_computation(module, computation,
+          &mut live_computation_call_count)?;
+        module_contains_dead_code = true;
       }
     }
 
-    module_contains_dead_code
+    Ok(module_contains_dead_code)
   }
 
   // Given a dead computation, decrements the ref count of all its called
   // computations and checks if any of the subcomputations become dead after
   // the removal.
   fn recursively_remove_dead_computation(
-    module: &HloModule,
-    computation: &HloComputation,
-    live_call_counts: &HashMap<&HloComputation, i64>) -> Result<(), String>
+    module: &mut HloModule,
+    computation: ComputationId,
+    live_call_counts: &mut HashMap<ComputationId, i64>) -> Result<(), String>
   {
+    let mut to_be_deleted = Vec::new();
     // First loops all the sub-instruction/sub-computations.
-    for instruction in computation.instructions() {
+    for instruction in module.computation(computation).instructions() {
       for subcomp in instruction.called_computations() {
-        let value = live_call_counts.get(subcomp);
+        let value = live_call_counts.get_mut(subcomp);
         if value.is_none() {
           return Err("Called computation not found in liva_call_counts table during HloDCE.".to_string());
-        } else {
-          // Decrements the live call count and sees if there are no more live calls
-          // to this computation.
-          let mut live_call_count = *value.unwrap();
-          live_call_count -= 1;
-          assert!(live_call_count >= 0);
-          if live_call_count == 0 {
-            return HloDCE::recursively_remove_dead_computation(
-              module, subcomp, &live_call_counts);
-          }
+        }
+        // Decrements the live call count and sees if there are no more live calls
+        // to this computation.
+        let live_call_count = value.unwrap();
+        *live_call_count -= 1;
+        assert!(*live_call_count >= 0);
+        if *live_call_count == 0 && !to_be_deleted.contains(subcomp) {
+          to_be_deleted.push(*subcomp);
         }
       }
     }
-    println!("Removing dead computation {:?}.", computation.name());
+    for subcomp in to_be_deleted {
+      HloDCE::recursively_remove_dead_computation(module, subcomp, live_call_counts)?;
+    }
+    println!("Removing dead computation {:?}.", module.computation(computation).name());
     // After looping called subcomputations, now safe to delete the computation.
     module.remove_embedded_computation(computation)
   }
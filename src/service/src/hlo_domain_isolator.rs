@@ -2,7 +2,10 @@
 
 use std::collections::HashSet;
 
-use hlo::{hlo_instruction::HloInstruction, hlo_module::HloModule, hlo_opcode::HloOpcode};
+use hlo::{
+  hlo_computation::HloComputation, hlo_instruction::HloInstruction,
+  hlo_module::HloModule, hlo_opcode::HloOpcode
+};
 
 // Domain instruction is the task of placing domain instructions between hlo
 // instructions having different sharding .
@@ -38,7 +41,10 @@ impl HloDomainIsolator {
   }
 }
 
-fn add_exit_domains<F>(instruction: &HloInstruction, creator: F) -> Result<i64, String>
+fn add_exit_domains<F>(
+  computation: &HloComputation,
+  instruction: &HloInstruction,
+  creator: F) -> Result<i64, String>
   where F: Fn(&HloInstruction, &HloInstruction, &HloInstruction) -> Option<HloInstruction>
 {
   let mut added_domain = 0;
@@ -47,7 +53,7 @@ fn add_exit_domains<F>(instruction: &HloInstruction, creator: F) -> Result<i64,
   }
 
   for user in instruction.users() {
-    let domain = creator(user, instruction, instruction);
+    let domain = creator(computation.instruction(*user), instruction, instruction);
     if domain.is_some() {
       println!("New domain: {:?}", domain.unwrap().to_string_default());
       //let result =
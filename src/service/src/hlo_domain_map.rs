@@ -80,9 +80,14 @@ impl HloDomainMap {
 
   // Check if the domain instruction is facing (via its oprand link) another
   // domain instruction of the same kind, hence defining an empty domain.
-  fn try_process_empty_dpmain(&mut self, instruction: &HloInstruction) -> Result<(), String> {
+  fn try_process_empty_dpmain(
+    &mut self,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> Result<(), String>
+  {
     debug_assert!(instruction.opcode() == HloOpcode::Domain);
-    for operand in &instruction.unique_operands() {
+    for operand in instruction.unique_operands() {
+      let operand = computation.instruction(operand);
       if self.is_domain_instruction(operand) {
         let mut domain = Domain::new();
         domain.enter_domains.insert(operand.clone());
@@ -91,7 +96,7 @@ impl HloDomainMap {
         if result.is_err() { return result; }
       }
     }
-    if instruction == instruction.parent().root_instruction() {
+    if computation.is_root(instruction.id()) {
       let mut domain = Domain::new();
       domain.enter_domains.insert(instruction.clone());
       let result = self.insert_domain(domain);
@@ -3,8 +3,9 @@
 use std::collections::HashSet;
 
 use hlo::{
+  hlo_computation::HloComputation,
   hlo_domain_metadata::Domain,
-  hlo_instruction::HloInstruction,
+  hlo_instruction::InstructionId,
   hlo_module::HloModule,
   hlo_opcode::HloOpcode
 };
@@ -27,8 +28,8 @@ impl RunContext {
     println!("Processing metadata domain: {:?}", remover.kind);
 
     let mut removed_domains = 0;
-    for computation in
-       module.mutable_computations_by_exec_threads(execution_threads) {
+    for id in module.computations_by_exec_threads(execution_threads) {
+      let computation = module.mutable_computation(id);
       // First create the domain instruction sets.
       // A domain instruction set is the set of instructions whose edges never cross
       // a domain instruction.
@@ -44,23 +45,24 @@ impl RunContext {
       // Now remove all the domain instructions of the kind specified by the remover,
       // that are within the currently processed computation from the graph.
       for instruction in computation.make_instruction_post_order() {
-        for operand in &instruction.unique_operands() {
-          if domain_map.is_domain_instruction(operand) {
-            println!("Removing {:?}", operand.name());
-            //operand.replace_all_uses_with(
-              //operand.mutable_operand(0).unwrap(),
-              //"".to_string());
-            //computation.remove_instruction(operand);
+        if !computation.contains(instruction) { continue; }
+        for operand in computation.instruction(instruction).unique_operands() {
+          if domain_map.is_domain_instruction(computation.instruction(operand)) {
+            println!("Removing {:?}", computation.instruction(operand).name());
+            let domain_operand = computation.instruction(operand).operand(0);
+            computation.replace_all_uses_with(operand, domain_operand)?;
+            computation.remove_instruction(operand)?;
             removed_domains += 1;
           }
         }
       }
 
-      let root = computation.root_instruction();
-      if domain_map.is_domain_instruction(root) {
-        println!("Removing {:?}", root.name());
-        //computation.set_root_instruction();
-        //computation.remove_instruction(root);
+      let root = computation.root_instruction_id();
+      if domain_map.is_domain_instruction(computation.instruction(root)) {
+        println!("Removing {:?}", computation.instruction(root).name());
+        let new_root = computation.instruction(root).operand(0);
+        computation.set_root_instruction(new_root, false);
+        computation.remove_instruction(root)?;
         removed_domains += 1;
       }
     }
@@ -107,20 +109,20 @@ impl HloDomainRemover {
   // Remove domains of a given kind which are used at users of a specific instruction.
   pub fn remove_exit_domains(
     &self,
-    instruction: &mut HloInstruction,
+    computation: &mut HloComputation,
+    instruction: InstructionId,
     domain_kind: String) -> Result<i64, String>
   {
     let mut removed_domains = 0;
-    let _computation = instruction.mutable_parent();
-    for user in instruction.mutable_users() {
+    for user_id in computation.instruction(instruction).users().clone() {
+      let user = computation.instruction(user_id);
       if user.opcode() == HloOpcode::Domain &&
          user.user_side_metadata().kind() == domain_kind &&
          user.operand_side_metadata().kind() == domain_kind
       {
         println!("Removing exit domain {:?}", user.name());
-        //let result =
-          //user.replace_all_uses_with(instruction, "".to_string());
-        //computation.remove_instruction(user);
+        computation.replace_all_uses_with(user_id, instruction)?;
+        computation.remove_instruction(user_id)?;
         removed_domains += 1;
       } 
     }
@@ -47,7 +47,7 @@ impl RunContext {
     if verifier.kinds.is_empty() {
       let mut kinds = Vec::new();
       for computation in module.computations_by_exec_threads(execution_threads) {
-        for instruction in computation.instructions() {
+        for instruction in module.computation(computation).instructions() {
           if instruction.opcode() == HloOpcode::Domain {
             debug_assert!(instruction.user_side_metadata().kind() ==
               instruction.operand_side_metadata().kind());
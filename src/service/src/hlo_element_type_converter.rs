@@ -4,7 +4,8 @@ use std::collections::HashSet;
 
 use common::blitz_data::PrimitiveType;
 use hlo::{
-  hlo_instruction::HloInstruction,
+  hlo_computation::HloComputation,
+  hlo_instruction::{HloInstruction, InstructionId},
   hlo_module::HloModule,
   hlo_opcode::HloOpcode
 };
@@ -41,9 +42,10 @@ impl HloElementTypeConverter {
     }
 
     let mut changed = false;
-    for computation in
-      module.mutable_computations_by_exec_threads(execution_threads) {
-      for hlo in computation.mutable_make_instruction_post_order() {
+    for id in module.computations_by_exec_threads(execution_threads) {
+      let computation = module.mutable_computation(id);
+      for hlo_id in computation.make_instruction_post_order() {
+        let hlo = computation.instruction(hlo_id);
         let opcode = hlo.opcode();
         
         // There are ops where it does not mokae sense to convert them.
@@ -81,7 +83,7 @@ impl HloElementTypeConverter {
         let null_ary = hlo.operands().is_empty();
         let wrong_element_type = hlo.shape().element_type() == self.eliminate_type;
         let should_eliminaete_type = (null_ary && wrong_element_type) ||
-          has_operand_type(hlo, &self.eliminate_type);
+          has_operand_type(computation, hlo, &self.eliminate_type);
         
         if !should_eliminaete_type {
           debug_assert!(hlo.shape().element_type() != self.eliminate_type);
@@ -91,15 +93,16 @@ impl HloElementTypeConverter {
         // Handle instructions that perform arithmetic operations and contain operands
         // with eliminate_type.
         let mut new_operands = vec![];
-        let cloned_hlo = hlo.clone();
-        for operand in hlo.mutable_operands() {
-          if operand.shape().element_type() == self.eliminate_type {
-            *operand = to_element_type(&cloned_hlo, &self.replace_with_type);
+        for operand in hlo.operands().clone() {
+          if computation.instruction(operand).shape().element_type() == self.eliminate_type {
+            new_operands.push(to_element_type(computation, operand, &self.replace_with_type));
+          } else {
+            new_operands.push(operand);
           }
-          new_operands.push(operand.clone());
         }
 
-        let new_hlo = None;
+        let hlo = computation.instruction(hlo_id);
+        let new_hlo: Option<InstructionId> = None;
         if hlo.shape().element_type() == self.eliminate_type {
 
         } else if hlo.shape().is_tuple() {
@@ -110,14 +113,9 @@ impl HloElementTypeConverter {
             //"".to_string()));
         }
 
-        let mut result = hlo.replace_all_uses_with(
-          &mut new_hlo.unwrap(), "".to_string());
-        if result.is_err() { return Err(result.err().unwrap()); }
-
-        result = hlo.drop_all_control_deps();
-        if result.is_err() { return Err(result.err().unwrap()); }
-
-        //computation.remove_instruction(&hlo);
+        computation.replace_all_uses_with(hlo_id, new_hlo.unwrap())?;
+        computation.drop_all_control_deps(hlo_id)?;
+        computation.remove_instruction(hlo_id)?;
         changed = true;
       }
     }
@@ -129,13 +127,19 @@ impl HloElementTypeConverter {
   }
 }
 
-pub fn to_element_type(_hlo: &HloInstruction, _t: &PrimitiveType) -> HloInstruction {
+pub fn to_element_type(
+  _computation: &mut HloComputation,
+  _hlo: InstructionId,
+  _t: &PrimitiveType) -> InstructionId
+{
   unimplemented!()
 }
 
-pub fn has_operand_type(hlo: &HloInstruction, t: &PrimitiveType) -> bool {
+pub fn has_operand_type(
+  computation: &HloComputation, hlo: &HloInstruction, t: &PrimitiveType) -> bool
+{
   for operand in hlo.operands() {
-    if operand.shape().element_type() == *t { return true; }
+    if computation.instruction(*operand).shape().element_type() == *t { return true; }
   }
   false
 }
\ No newline at end of file
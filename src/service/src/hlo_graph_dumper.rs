@@ -1,13 +1,26 @@
 #![allow(dead_code)]
 
+use std::{
+  collections::{HashMap, HashSet, VecDeque},
+  io::Write,
+  process::{Command, Stdio},
+  sync::Mutex
+};
+
 use common::{
-  blitz_data::{PrimitiveType, Statisitic},
+  blitz_data::{DebugOptions, PrimitiveType, Statisitic},
   shape_util::ShapeUtil
 };
 
-use hlo::hlo_instruction::HloInstruction;
+use hlo::{
+  hlo_computation::HloComputation,
+  hlo_instruction::{fusion_kind_to_string, HloInstruction, InstructionId},
+  hlo_module::{ComputationId, HloModule},
+  hlo_opcode::{hlo_opcode_string, HloOpcode}
+};
 
 // Different formats that a graph can be packaged as.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RenderedGraphFormat {
   Dot,
   Html,
@@ -15,18 +28,79 @@ pub enum RenderedGraphFormat {
 }
 
 pub struct HloRenderOptions {
-  shapw_backend_config: bool,
+  show_backend_config: bool,
   show_fusion_subcomputation: bool,
   show_while_subcomputation: bool,
   override_node_colors: bool
 }
 
+impl HloRenderOptions {
+  pub fn new() -> Self {
+    HloRenderOptions {
+      show_backend_config: false,
+      show_fusion_subcomputation: true,
+      show_while_subcomputation: true,
+      override_node_colors: false
+    }
+  }
+
+  // Include the backend config string in the rendered graph.
+  pub fn show_backend_config(&self) -> bool {
+    self.show_backend_config
+  }
+
+  pub fn set_show_backend_config(&mut self, value: bool) {
+    self.show_backend_config = value;
+  }
+
+  // Include the fusion subcomputations in the rendered graph.
+  pub fn show_fusion_subcomputation(&self) -> bool {
+    self.show_fusion_subcomputation
+  }
+
+  pub fn set_show_fusion_subcomputation(&mut self, value: bool) {
+    self.show_fusion_subcomputation = value;
+  }
+
+  // Include the while subcomputations in the rendered graph.
+  pub fn show_while_subcomputation(&self) -> bool {
+    self.show_while_subcomputation
+  }
+
+  pub fn set_show_while_subcomputation(&mut self, value: bool) {
+    self.show_while_subcomputation = value;
+  }
+
+  // Use the colors of the color map instead of the default node colors.
+  pub fn override_node_colors(&self) -> bool {
+    self.override_node_colors
+  }
+
+  pub fn set_override_node_colors(&mut self, value: bool) {
+    self.override_node_colors = value;
+  }
+}
+
 // Contains color computed according to the numerical diff of an HloInstruction.
 pub struct ColorStats {
   color: String,
   stats: String
 }
 
+impl ColorStats {
+  pub fn new(color: String, stats: String) -> Self {
+    ColorStats { color: color, stats: stats }
+  }
+
+  pub fn color(&self) -> &String {
+    &self.color
+  }
+
+  pub fn stats(&self) -> &String {
+    &self.stats
+  }
+}
+
 // Used to indicate how we should treat a given HloInstruction in the graph.
 #[derive(Debug, Clone, PartialEq)]
 enum NodeFilterResult {
@@ -55,6 +129,11 @@ impl NodeFilter {
     }
   }
 
+  // A filter which shows every node normally.
+  pub fn new_default() -> Self {
+    NodeFilter::new(Box::new(|_| NodeFilterResult::NormalNode), None)
+  }
+
   pub fn show(&self, instruction: &HloInstruction) -> bool {
     self.filter.as_ref()(instruction) != NodeFilterResult::HideNode
   }
@@ -212,14 +291,958 @@ fn node_font_color_for_statistic(statistic: &Statisitic) -> String {
   }
 }
 
-pub fn render_graph() {}
+// Escapes the characters which have a meaning in HTML, DOT html-like labels
+// and SVG.
+fn html_escape(s: &str) -> String {
+  s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// A node of the rendered graph. 'label' holds the lines of text shown in the
+// node; the first one is the instruction name.
+struct GraphNode {
+  id: i64,
+  label: Vec<String>,
+  tooltip: String,
+  shape: String,
+  colors: NodeColors,
+  cluster: Option<i64>
+}
+
+// A cluster (subgraph) of the rendered graph, used for the subcomputations of
+// fusion and while instructions.
+struct GraphCluster {
+  id: i64,
+  label: String,
+  parent: Option<i64>,
+  fill_color: String
+}
+
+struct GraphEdge {
+  from: i64,
+  to: i64,
+  operand_num: Option<usize>,
+  control: bool
+}
+
+// Renderer-independent description of a graph, which can be emitted as DOT
+// or laid out directly as SVG.
+struct Graph {
+  label: String,
+  nodes: Vec<GraphNode>,
+  clusters: Vec<GraphCluster>,
+  edges: Vec<GraphEdge>
+}
+
+impl Graph {
+  fn new(label: String) -> Self {
+    Graph { label: label, nodes: vec![], clusters: vec![], edges: vec![] }
+  }
+
+  fn to_dot(&self) -> String {
+    let mut dot = String::new();
+    dot.push_str("digraph G {\n");
+    dot.push_str("rankdir = TB;\n");
+    dot.push_str("compound = true;\n");
+    dot.push_str(&format!("label = <<b>{}</b>>;\n", html_escape(&self.label)));
+    dot.push_str("labelloc = t;\n");
+    // Disable the tooltip. Interestingly, "" doesn't work!
+    dot.push_str("tooltip = \" \";\n");
+    self.append_dot_body(None, &mut dot);
+    for edge in &self.edges {
+      let mut attrs = vec![];
+      if edge.control {
+        attrs.push("style=\"dotted\"".to_string());
+      }
+      if let Some(operand_num) = edge.operand_num {
+        attrs.push(format!("headlabel=\"{}\", labeldistance=2", operand_num));
+      }
+      if attrs.is_empty() {
+        dot.push_str(&format!("{} -> {};\n", edge.from, edge.to));
+      } else {
+        dot.push_str(&format!("{} -> {} [{}];\n", edge.from, edge.to, attrs.join(", ")));
+      }
+    }
+    dot.push_str("}\n");
+    dot
+  }
+
+  // Emits the nodes and nested clusters which belong to 'cluster'.
+  fn append_dot_body(&self, cluster: Option<i64>, dot: &mut String) {
+    for child in self.clusters.iter().filter(|c| c.parent == cluster) {
+      dot.push_str(&format!("subgraph cluster_{} {{\n", child.id));
+      dot.push_str("style=\"rounded,filled,bold\";\n");
+      dot.push_str(&format!("fillcolor=\"{}\";\n", child.fill_color));
+      dot.push_str(&format!("label = <{}>;\n", html_escape(&child.label).replace('\n', "<br/>")));
+      self.append_dot_body(Some(child.id), dot);
+      dot.push_str("}\n");
+    }
+    for node in self.nodes.iter().filter(|n| n.cluster == cluster) {
+      let label = node.label.iter().enumerate()
+        .map(|(i, line)| if i == 0 {
+          format!("<b>{}</b>", html_escape(line))
+        } else {
+          html_escape(line)
+        })
+        .collect::<Vec<String>>().join("<br/>");
+      dot.push_str(&format!(
+        "{} [label=<{}>, shape={}, tooltip=\"{}\", style=\"{}\", fontcolor=\"{}\", color=\"{}\", fillcolor=\"{}\"];\n",
+        node.id, label, node.shape, html_escape(&node.tooltip), node.colors.style,
+        node.colors.font_color, node.colors.stroke_color, node.colors.fill_color));
+    }
+  }
+
+  // Renders the graph as SVG, with graphviz if it is installed and with a
+  // simple built-in layered layout otherwise.
+  fn to_svg(&self) -> String {
+    match render_svg_with_dot(&self.to_dot()) {
+      Some(svg) => svg,
+      None => self.to_svg_stand_in()
+    }
+  }
+
+  // Lays out the nodes in rows by their depth in the graph and draws them
+  // with straight edges. This is only meant to make the HTML output usable
+  // on machines without graphviz.
+  fn to_svg_stand_in(&self) -> String {
+    const NODE_WIDTH: i64 = 220;
+    const LINE_HEIGHT: i64 = 16;
+    const H_GAP: i64 = 30;
+    const V_GAP: i64 = 50;
+    const MARGIN: i64 = 20;
+    const CLUSTER_PAD: i64 = 10;
+
+    let index_of: HashMap<i64, usize> =
+      self.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+    // Longest-path depth of every node. Bounded by the number of nodes so
+    // that a cycle cannot loop forever.
+    let mut depth = vec![0_i64; self.nodes.len()];
+    for _ in 0..self.nodes.len() {
+      let mut changed = false;
+      for edge in &self.edges {
+        let (from, to) = (index_of[&edge.from], index_of[&edge.to]);
+        if depth[to] < depth[from] + 1 && depth[from] + 1 < self.nodes.len() as i64 {
+          depth[to] = depth[from] + 1;
+          changed = true;
+        }
+      }
+      if !changed {
+        break;
+      }
+    }
+
+    let node_height = |n: &GraphNode| LINE_HEIGHT * (n.label.len() as i64) + 10;
+    let num_rows = depth.iter().max().map_or(0, |d| d + 1) as usize;
+    let mut rows: Vec<Vec<usize>> = vec![vec![]; num_rows];
+    for (i, d) in depth.iter().enumerate() {
+      rows[*d as usize].push(i);
+    }
+    let mut positions = vec![(0_i64, 0_i64, 0_i64); self.nodes.len()];
+    let mut y = MARGIN + 2 * LINE_HEIGHT;
+    let mut width = 0;
+    for row in &rows {
+      let row_height = row.iter().map(|i| node_height(&self.nodes[*i])).max().unwrap_or(0);
+      let mut x = MARGIN;
+      for i in row {
+        positions[*i] = (x, y, node_height(&self.nodes[*i]));
+        x += NODE_WIDTH + H_GAP;
+      }
+      width = width.max(x);
+      y += row_height + V_GAP;
+    }
+    let height = y + MARGIN;
+
+    let mut svg = String::new();
+    svg.push_str(&format!(
+      "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+      width + MARGIN, height, width + MARGIN, height));
+    svg.push_str("<defs><marker id=\"arrow\" markerWidth=\"10\" markerHeight=\"10\" refX=\"9\" refY=\"3\" orient=\"auto\"><path d=\"M0,0 L0,6 L9,3 z\" fill=\"#757575\"/></marker></defs>\n");
+    svg.push_str(&format!("<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-weight=\"bold\">{}</text>\n",
+      MARGIN, MARGIN + LINE_HEIGHT, html_escape(&self.label)));
+
+    // Clusters, outermost first, as boxes around their nodes.
+    for cluster in &self.clusters {
+      let members: Vec<usize> = (0..self.nodes.len())
+        .filter(|i| self.is_in_cluster(self.nodes[*i].cluster, cluster.id))
+        .collect();
+      if members.is_empty() {
+        continue;
+      }
+      let pad = CLUSTER_PAD * (self.cluster_nesting(cluster.id) as i64 + 1);
+      let min_x = members.iter().map(|i| positions[*i].0).min().unwrap() - pad;
+      let min_y = members.iter().map(|i| positions[*i].1).min().unwrap() - pad - LINE_HEIGHT;
+      let max_x = members.iter().map(|i| positions[*i].0 + NODE_WIDTH).max().unwrap() + pad;
+      let max_y = members.iter().map(|i| positions[*i].1 + positions[*i].2).max().unwrap() + pad;
+      svg.push_str(&format!(
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"8\" fill=\"{}\" stroke=\"black\" stroke-width=\"2\"/>\n",
+        min_x, min_y, max_x - min_x, max_y - min_y, cluster.fill_color));
+      svg.push_str(&format!(
+        "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"12\">{}</text>\n",
+        min_x + 4, min_y + LINE_HEIGHT - 2, html_escape(&cluster.label.replace('\n', " "))));
+    }
+
+    for edge in &self.edges {
+      let (fx, fy, fh) = positions[index_of[&edge.from]];
+      let (tx, ty, _) = positions[index_of[&edge.to]];
+      let dash = if edge.control { " stroke-dasharray=\"4,3\"" } else { "" };
+      svg.push_str(&format!(
+        "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#757575\"{} marker-end=\"url(#arrow)\"/>\n",
+        fx + NODE_WIDTH / 2, fy + fh, tx + NODE_WIDTH / 2, ty, dash));
+    }
+
+    for (i, node) in self.nodes.iter().enumerate() {
+      let (x, y, h) = positions[i];
+      let dash = if node.colors.style.contains("dashed") { " stroke-dasharray=\"4,3\"" } else { "" };
+      if node.shape == "diamond" {
+        svg.push_str(&format!(
+          "<polygon points=\"{},{} {},{} {},{} {},{}\" fill=\"{}\" stroke=\"{}\"{}/>\n",
+          x + NODE_WIDTH / 2, y, x + NODE_WIDTH, y + h / 2, x + NODE_WIDTH / 2, y + h, x, y + h / 2,
+          node.colors.fill_color, node.colors.stroke_color, dash));
+      } else {
+        let rx = if node.shape == "ellipse" { h / 2 } else { 4 };
+        svg.push_str(&format!(
+          "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\" fill=\"{}\" stroke=\"{}\"{}><title>{}</title></rect>\n",
+          x, y, NODE_WIDTH, h, rx, node.colors.fill_color, node.colors.stroke_color, dash,
+          html_escape(&node.tooltip)));
+      }
+      for (line_num, line) in node.label.iter().enumerate() {
+        let weight = if line_num == 0 { " font-weight=\"bold\"" } else { "" };
+        svg.push_str(&format!(
+          "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-family=\"sans-serif\" font-size=\"12\" fill=\"{}\"{}>{}</text>\n",
+          x + NODE_WIDTH / 2, y + 5 + LINE_HEIGHT * (line_num as i64 + 1) - 3,
+          node.colors.font_color, weight, html_escape(line)));
+      }
+    }
+    svg.push_str("</svg>\n");
+    svg
+  }
+
+  // Returns true if 'cluster' is 'ancestor' or nested in it.
+  fn is_in_cluster(&self, cluster: Option<i64>, ancestor: i64) -> bool {
+    let mut current = cluster;
+    while let Some(id) = current {
+      if id == ancestor {
+        return true;
+      }
+      current = self.clusters.iter().find(|c| c.id == id).and_then(|c| c.parent);
+    }
+    false
+  }
+
+  fn cluster_nesting(&self, cluster: i64) -> usize {
+    let mut depth = 0;
+    let mut current = self.clusters.iter().find(|c| c.id == cluster).and_then(|c| c.parent);
+    while let Some(id) = current {
+      depth += 1;
+      current = self.clusters.iter().find(|c| c.id == id).and_then(|c| c.parent);
+    }
+    depth
+  }
+}
+
+// Runs graphviz on 'dot' and returns the resulting SVG element, or None if
+// graphviz is not available.
+fn render_svg_with_dot(dot: &str) -> Option<String> {
+  let mut child = Command::new("dot")
+    .arg("-Tsvg")
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn().ok()?;
+  child.stdin.take()?.write_all(dot.as_bytes()).ok()?;
+  let output = child.wait_with_output().ok()?;
+  if !output.status.success() {
+    return None;
+  }
+  let svg = String::from_utf8(output.stdout).ok()?;
+  // Drop the XML prolog so that the element can be embedded in HTML.
+  svg.find("<svg").map(|start| svg[start..].to_string())
+}
+
+fn wrap_svg_in_html(title: &str, body: &str) -> String {
+  format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+<style>\nbody {{ font-family: sans-serif; }}\nsvg {{ max-width: 100%; height: auto; }}\n</style>\n\
+</head>\n<body>\n{}</body>\n</html>\n", html_escape(title), body)
+}
+
+type NodeKey = (ComputationId, InstructionId);
+
+// Builds the Graph of a computation, descending into the subcomputations of
+// fusion and while instructions as clusters.
+struct HloDotDumper<'a> {
+  module: &'a HloModule,
+  computation: ComputationId,
+  hlo_render_options: &'a HloRenderOptions,
+  filter: NodeFilter,
+  color_map: Option<&'a HashMap<String, ColorStats>>,
+  graph: Graph,
+  node_ids: HashMap<NodeKey, i64>,
+  next_id: i64
+}
+
+impl<'a> HloDotDumper<'a> {
+  fn new(
+    module: &'a HloModule,
+    computation: ComputationId,
+    label: String,
+    hlo_render_options: &'a HloRenderOptions,
+    filter: NodeFilter,
+    color_map: Option<&'a HashMap<String, ColorStats>>) -> Self
+  {
+    HloDotDumper {
+      module: module,
+      computation: computation,
+      hlo_render_options: hlo_render_options,
+      filter: filter,
+      color_map: color_map,
+      graph: Graph::new(label),
+      node_ids: HashMap::new(),
+      next_id: 0
+    }
+  }
+
+  fn dump(mut self) -> Graph {
+    self.dump_computation(self.computation, None);
+    self.dump_edges(self.computation);
+    self.graph
+  }
+
+  fn next_id(&mut self) -> i64 {
+    self.next_id += 1;
+    self.next_id
+  }
+
+  fn comp(&self, id: ComputationId) -> &'a HloComputation {
+    self.module.computation(id)
+  }
 
-pub fn render_all_computations_to_html() {}
+  // Returns the subcomputations of 'instr' which are drawn as clusters.
+  fn shown_subcomputations(&self, instr: &HloInstruction) -> Vec<ComputationId> {
+    let show = match instr.opcode() {
+      HloOpcode::Fusion => self.hlo_render_options.show_fusion_subcomputation(),
+      HloOpcode::While => self.hlo_render_options.show_while_subcomputation(),
+      _ => false
+    };
+    if !show {
+      return vec![];
+    }
+    instr.called_computations().iter().cloned()
+      .filter(|id| {
+        let subcomputation = self.comp(*id);
+        subcomputation.instructions().any(|i| self.filter.show(i))
+      })
+      .collect()
+  }
 
-pub fn render_all_paths_from_to() {}
+  fn is_shown_fusion(&self, instr: &HloInstruction) -> bool {
+    instr.opcode() == HloOpcode::Fusion && !self.shown_subcomputations(instr).is_empty()
+  }
+
+  fn dump_computation(&mut self, comp_id: ComputationId, cluster: Option<i64>) {
+    let computation = self.comp(comp_id);
+    for id in computation.make_instruction_post_order() {
+      let instr = computation.instruction(id);
+      if !self.filter.show(instr) {
+        continue;
+      }
+      let subcomputations = self.shown_subcomputations(instr);
+      // A fusion shown as a cluster is drawn through its fused instructions
+      // only.
+      if instr.opcode() != HloOpcode::Fusion || subcomputations.is_empty() {
+        self.dump_instruction(comp_id, instr, cluster);
+      }
+      for subcomputation in subcomputations {
+        let cluster_id = self.next_id();
+        let label = if instr.opcode() == HloOpcode::Fusion {
+          format!("{}\n{}", instr.name(), fusion_kind_to_string(&instr.fusion_kind()))
+        } else {
+          format!("Subcomputation for {}\n{}", instr.name(), self.comp(subcomputation).name())
+        };
+        self.graph.clusters.push(GraphCluster {
+          id: cluster_id,
+          label: label,
+          parent: cluster,
+          fill_color: if instr.opcode() == HloOpcode::Fusion {
+            "#f5f5f5".to_string()
+          } else {
+            "#fff9c4".to_string()
+          }
+        });
+        self.dump_computation(subcomputation, Some(cluster_id));
+      }
+    }
+  }
+
+  fn dump_instruction(
+    &mut self, comp_id: ComputationId, instr: &HloInstruction, cluster: Option<i64>)
+  {
+    let mut colors = node_colors_for_scheme(instruction_color_scheme(instr));
+    let mut label = vec![instr.name()];
+    let mut opcode_line = hlo_opcode_string(&instr.opcode());
+    if instr.opcode() == HloOpcode::Fusion {
+      opcode_line.push_str(&format!(" {}", fusion_kind_to_string(&instr.fusion_kind())));
+    }
+    label.push(opcode_line);
+    label.push(ShapeUtil::human_string(instr.shape()));
+
+    let mut color_overridden = false;
+    if self.hlo_render_options.override_node_colors() {
+      if let Some(color_stats) = self.color_map.and_then(|m| m.get(&instr.name())) {
+        colors = NodeColors::new("filled", &color_stats.color, "#757575", "black");
+        if !color_stats.stats.is_empty() {
+          label.push(color_stats.stats.clone());
+        }
+        color_overridden = true;
+      }
+    }
+    if !color_overridden && instr.has_statistics() {
+      let statistic = instr.statistic_to_visualize();
+      colors = NodeColors::new("filled", &node_fill_color_for_statistic(statistic),
+        "#757575", &node_font_color_for_statistic(statistic));
+      label.push(format!("{} = {}", statistic.stat_name(), statistic.stat_val()));
+    }
+
+    let mut shape = if instr.opcode() == HloOpcode::While {
+      "ellipse".to_string()
+    } else {
+      "rect".to_string()
+    };
+    if self.filter.some_or_all_operands_omitted(instr) {
+      label.push("(some operands omitted)".to_string());
+    }
+    if self.filter.deemphasized(instr) {
+      colors = node_colors_for_scheme(ColorScheme::DashedBorder);
+    }
+    if self.filter.highlight(instr) {
+      shape = "diamond".to_string();
+      colors = node_colors_for_scheme(ColorScheme::DarkRed);
+    }
+
+    let id = self.next_id();
+    self.node_ids.insert((comp_id, instr.id()), id);
+    self.graph.nodes.push(GraphNode {
+      id: id,
+      label: label,
+      tooltip: instr.to_short_string(),
+      shape: shape,
+      colors: colors,
+      cluster: cluster
+    });
+  }
+
+  // Returns the node edges leaving 'instr' start from. A fusion drawn as a
+  // cluster is represented by its fused root.
+  fn source_node(&self, comp_id: ComputationId, instr: &HloInstruction) -> Option<i64> {
+    if self.is_shown_fusion(instr) {
+      let fused_id = instr.called_computations()[0];
+      let fused = self.comp(fused_id);
+      return self.source_node(fused_id, fused.root_instruction());
+    }
+    self.node_ids.get(&(comp_id, instr.id())).cloned()
+  }
+
+  // Returns the node the edge for operand 'operand_num' of 'instr' ends at. A
+  // fusion drawn as a cluster receives its operands at its fused parameters.
+  fn target_node(
+    &self, comp_id: ComputationId, instr: &HloInstruction, operand_num: usize) -> Option<i64>
+  {
+    if self.is_shown_fusion(instr) {
+      let fused_id = instr.called_computations()[0];
+      let parameter = self.comp(fused_id).parameter_instruction(operand_num)?;
+      return self.node_ids.get(&(fused_id, parameter.id())).cloned();
+    }
+    self.node_ids.get(&(comp_id, instr.id())).cloned()
+  }
+
+  fn dump_edges(&mut self, comp_id: ComputationId) {
+    let computation = self.comp(comp_id);
+    for id in computation.make_instruction_post_order() {
+      let instr = computation.instruction(id);
+      if !self.filter.show(instr) {
+        continue;
+      }
+      for (operand_num, operand_id) in instr.operands().iter().enumerate() {
+        let operand = computation.instruction(*operand_id);
+        if !self.filter.show(operand) {
+          continue;
+        }
+        let from = self.source_node(comp_id, operand);
+        let to = self.target_node(comp_id, instr, operand_num);
+        if let (Some(from), Some(to)) = (from, to) {
+          self.graph.edges.push(GraphEdge {
+            from: from,
+            to: to,
+            operand_num: if instr.operand_count() > 1 { Some(operand_num) } else { None },
+            control: false
+          });
+        }
+      }
+      for predecessor_id in instr.control_predecessors() {
+        let predecessor = computation.instruction(*predecessor_id);
+        if !self.filter.show(predecessor) {
+          continue;
+        }
+        let from = self.source_node(comp_id, predecessor);
+        let to = self.node_ids.get(&(comp_id, instr.id())).cloned();
+        if let (Some(from), Some(to)) = (from, to) {
+          self.graph.edges.push(GraphEdge {
+            from: from, to: to, operand_num: None, control: true
+          });
+        }
+      }
+      for subcomputation in self.shown_subcomputations(instr) {
+        self.dump_edges(subcomputation);
+        // Connect the result of a while subcomputation to the while node.
+        if instr.opcode() == HloOpcode::While {
+          let root = self.comp(subcomputation).root_instruction();
+          let from = self.source_node(subcomputation, root);
+          let to = self.node_ids.get(&(comp_id, instr.id())).cloned();
+          if let (Some(from), Some(to)) = (from, to) {
+            self.graph.edges.push(GraphEdge {
+              from: from, to: to, operand_num: None, control: true
+            });
+          }
+        }
+      }
+    }
+  }
+}
+
+fn instruction_color_scheme(instr: &HloInstruction) -> ColorScheme {
+  let parameter_color = if is_small(instr) {
+    ColorScheme::Orange
+  } else {
+    ColorScheme::DarkOrange
+  };
+  if instr.is_elementwise() {
+    return ColorScheme::Yellow;
+  }
+  match instr.opcode() {
+    HloOpcode::Parameter => parameter_color,
+    HloOpcode::Constant | HloOpcode::Iota => if is_small(instr) {
+      ColorScheme::White
+    } else {
+      ColorScheme::Blue
+    },
+    HloOpcode::Bitcast | HloOpcode::GetTupleElement | HloOpcode::Tuple |
+    HloOpcode::AfterAll | HloOpcode::AddDependency => ColorScheme::White,
+    HloOpcode::Broadcast | HloOpcode::Concatenate | HloOpcode::DynamicSlice |
+    HloOpcode::DynamicUpdateSlice | HloOpcode::Gather | HloOpcode::Pad |
+    HloOpcode::Reshape | HloOpcode::Reverse | HloOpcode::Slice |
+    HloOpcode::Transpose | HloOpcode::Sort => if is_small(instr) {
+      ColorScheme::White
+    } else {
+      ColorScheme::Green
+    },
+    HloOpcode::Convolution | HloOpcode::Dot | HloOpcode::Fft |
+    HloOpcode::TriangularSolve => ColorScheme::DarkBlue,
+    HloOpcode::Reduce | HloOpcode::ReduceWindow | HloOpcode::SelectAndScatter |
+    HloOpcode::Scatter => ColorScheme::Purple,
+    HloOpcode::Fusion | HloOpcode::Map => ColorScheme::Gray,
+    HloOpcode::AllReduce | HloOpcode::AllGather | HloOpcode::AllToAll |
+    HloOpcode::CollectivePermute | HloOpcode::Infeed | HloOpcode::Outfeed |
+    HloOpcode::Send | HloOpcode::Recv => ColorScheme::Brown,
+    HloOpcode::Call | HloOpcode::Conditional | HloOpcode::CustomCall |
+    HloOpcode::While => ColorScheme::DarkGreen,
+    HloOpcode::Rng => ColorScheme::Red,
+    _ => ColorScheme::White
+  }
+}
+
+// Function used to turn a DOT graph into a URL, see
+// register_graph_to_url_renderer.
+type GraphToUrlRenderer = fn(&str) -> Result<String, String>;
+static URL_RENDERER: Mutex<Option<GraphToUrlRenderer>> = Mutex::new(None);
+
+fn wrap_graph_in_format(graph: &Graph, format: RenderedGraphFormat) -> Result<String, String> {
+  match format {
+    RenderedGraphFormat::Dot => Ok(graph.to_dot()),
+    RenderedGraphFormat::Html => Ok(wrap_svg_in_html(&graph.label, &graph.to_svg())),
+    RenderedGraphFormat::Url => {
+      let renderer = URL_RENDERER.lock().unwrap();
+      match *renderer {
+        Some(renderer) => renderer(&graph.to_dot()),
+        None => Err("Can't render as URL; no URL renderer was registered.".to_string())
+      }
+    }
+  }
+}
+
+// Renders an HLO computation (which must be part of 'module') into a DOT
+// graph, an HTML page embedding the graph as SVG, or a URL. Instructions
+// named in 'color_map' are colored from it when
+// 'hlo_render_options.override_node_colors()' is set, e.g. with the costs of
+// a cost analysis.
+pub fn render_graph(
+  module: &HloModule,
+  computation: ComputationId,
+  label: &str,
+  _debug_options: &DebugOptions,
+  format: RenderedGraphFormat,
+  hlo_render_options: &HloRenderOptions,
+  color_map: Option<&HashMap<String, ColorStats>>) -> Result<String, String>
+{
+  let graph = HloDotDumper::new(module, computation, label.to_string(),
+    hlo_render_options, NodeFilter::new_default(), color_map).dump();
+  wrap_graph_in_format(&graph, format)
+}
+
+// Renders every non-fusion computation of 'module' into one HTML page.
+pub fn render_all_computations_to_html(module: &HloModule) -> Result<String, String> {
+  let hlo_render_options = HloRenderOptions::new();
+  let mut body = String::new();
+  for computation in module.computations() {
+    if computation.is_fusion_computation() || computation.id().is_none() {
+      continue;
+    }
+    let graph = HloDotDumper::new(module, computation.id().unwrap(),
+      computation.name(), &hlo_render_options, NodeFilter::new_default(), None).dump();
+    body.push_str(&format!("<h2>{}</h2>\n{}", html_escape(&computation.name()), graph.to_svg()));
+  }
+  Ok(wrap_svg_in_html(&module.name(), &body))
+}
+
+// Shows the nodes of 'computation' at most 'radius' edges away from 'node'.
+// Instructions in 'boundary' are shown but not expanded. Nodes with operands
+// or users beyond the radius are deemphasized and 'node' is highlighted.
+fn make_node_radius_filter(
+  computation: &HloComputation,
+  comp_id: ComputationId,
+  root: InstructionId,
+  radius: i64,
+  boundary: &HashSet<InstructionId>) -> NodeFilter
+{
+  let mut nodes: HashMap<InstructionId, NodeFilterResult> = HashMap::new();
+  let mut worklist: VecDeque<(InstructionId, i64)> = VecDeque::new();
+  worklist.push_back((root, 0));
+  while let Some((id, depth)) = worklist.pop_front() {
+    if nodes.contains_key(&id) {
+      continue;
+    }
+    nodes.insert(id, NodeFilterResult::NormalNode);
+    if depth == radius || (boundary.contains(&id) && id != root) {
+      continue;
+    }
+    let instr = computation.instruction(id);
+    for neighbor in instr.operands().iter().chain(instr.users().iter())
+      .chain(instr.control_predecessors().iter()).chain(instr.control_successors().iter())
+    {
+      if !nodes.contains_key(neighbor) {
+        worklist.push_back((*neighbor, depth + 1));
+      }
+    }
+  }
+
+  let displayed: HashSet<InstructionId> = nodes.keys().cloned().collect();
+  for (id, result) in nodes.iter_mut() {
+    let instr = computation.instruction(*id);
+    let operands = instr.operands();
+    let num_displayed = operands.iter().filter(|o| displayed.contains(o)).count();
+    if num_displayed > 0 && num_displayed < operands.len() {
+      *result = NodeFilterResult::SomeOperandsOmitted;
+    } else if !operands.is_empty() && num_displayed == 0 {
+      *result = NodeFilterResult::OmitNodeOperands;
+    }
+    if *result == NodeFilterResult::NormalNode &&
+       instr.users().iter().any(|u| !displayed.contains(u))
+    {
+      *result = NodeFilterResult::SomeUsersOmitted;
+    }
+  }
+  nodes.insert(root, NodeFilterResult::HighlightNode);
+
+  let num_rendered = nodes.len() as i64;
+  NodeFilter::new(Box::new(move |instr: &HloInstruction| {
+    if instr.parent() != Some(comp_id) {
+      // Show all nodes in subcomputations.
+      return NodeFilterResult::NormalNode;
+    }
+    match nodes.get(&instr.id()) {
+      Some(result) => result.clone(),
+      None => NodeFilterResult::HideNode
+    }
+  }), Some(num_rendered))
+}
+
+// Like render_graph, but renders only the nodes within 'radius' of 'node' in
+// 'computation'.
+pub fn render_neighborhood_around(
+  module: &HloModule,
+  computation: ComputationId,
+  node: InstructionId,
+  radius: i64,
+  format: RenderedGraphFormat,
+  hlo_render_options: &HloRenderOptions,
+  boundary: &HashSet<InstructionId>) -> Result<String, String>
+{
+  let comp = module.computation(computation);
+  let label = format!("Neighborhood of {} nodes around {}",
+    radius, comp.instruction(node).name());
+  let filter = make_node_radius_filter(comp, computation, node, radius, boundary);
+  let graph = HloDotDumper::new(module, computation, label,
+    hlo_render_options, filter, None).dump();
+  wrap_graph_in_format(&graph, format)
+}
+
+// Returns the instructions reached from 'start' by following 'next'.
+fn reachable_from<'a, F>(
+  computation: &'a HloComputation, start: InstructionId, next: F) -> HashSet<InstructionId>
+  where F: Fn(&'a HloInstruction) -> &'a [InstructionId]
+{
+  let mut visited = HashSet::new();
+  let mut worklist = vec![start];
+  while let Some(id) = worklist.pop() {
+    if visited.insert(id) {
+      worklist.extend(next(computation.instruction(id)).iter().cloned());
+    }
+  }
+  visited
+}
+
+// Returns the instructions of a shortest path from 'from' to 'to' through
+// 'nodes'.
+fn shortest_path(
+  computation: &HloComputation,
+  from: InstructionId,
+  to: InstructionId,
+  nodes: &HashSet<InstructionId>) -> HashSet<InstructionId>
+{
+  let mut previous: HashMap<InstructionId, InstructionId> = HashMap::new();
+  let mut worklist = VecDeque::new();
+  worklist.push_back(from);
+  while let Some(id) = worklist.pop_front() {
+    if id == to {
+      break;
+    }
+    for user in computation.instruction(id).users() {
+      if nodes.contains(user) && *user != from && !previous.contains_key(user) {
+        previous.insert(*user, id);
+        worklist.push_back(*user);
+      }
+    }
+  }
+  let mut path = HashSet::new();
+  let mut current = to;
+  path.insert(current);
+  while let Some(prev) = previous.get(&current) {
+    path.insert(*prev);
+    current = *prev;
+  }
+  path
+}
+
+// Renders the nodes on all paths from 'from' to 'to' in 'computation'. If
+// there are more than 'max_nodes' of them, only a shortest path is shown.
+pub fn render_all_paths_from_to(
+  module: &HloModule,
+  computation: ComputationId,
+  from: InstructionId,
+  to: InstructionId,
+  max_nodes: i64,
+  format: RenderedGraphFormat,
+  hlo_render_options: &HloRenderOptions) -> Result<String, String>
+{
+  let comp = module.computation(computation);
+  let from_name = comp.instruction(from).name();
+  let to_name = comp.instruction(to).name();
+  let forward = reachable_from(comp, from, |i| i.users().as_slice());
+  let backward = reachable_from(comp, to, |i| i.operands().as_slice());
+  let mut nodes: HashSet<InstructionId> =
+    forward.intersection(&backward).cloned().collect();
+  if nodes.is_empty() {
+    return Err(format!("No path from {} to {}.", from_name, to_name));
+  }
+  let mut label = format!("All paths from {} to {}", from_name, to_name);
+  if nodes.len() as i64 > max_nodes {
+    nodes = shortest_path(comp, from, to, &nodes);
+    label.push_str(&format!(
+      " (too many nodes, more than {}; showing only the shortest path)", max_nodes));
+  }
+
+  let mut results: HashMap<InstructionId, NodeFilterResult> = HashMap::new();
+  for id in &nodes {
+    let instr = comp.instruction(*id);
+    let result = if *id == from || *id == to {
+      NodeFilterResult::HighlightNode
+    } else if instr.operands().iter().any(|o| !nodes.contains(o)) {
+      NodeFilterResult::SomeOperandsOmitted
+    } else {
+      NodeFilterResult::NormalNode
+    };
+    results.insert(*id, result);
+  }
+  let num_rendered = results.len() as i64;
+  let filter = NodeFilter::new(Box::new(move |instr: &HloInstruction| {
+    if instr.parent() != Some(computation) {
+      return NodeFilterResult::NormalNode;
+    }
+    match results.get(&instr.id()) {
+      Some(result) => result.clone(),
+      None => NodeFilterResult::HideNode
+    }
+  }), Some(num_rendered));
+  let graph = HloDotDumper::new(module, computation, label,
+    hlo_render_options, filter, None).dump();
+  wrap_graph_in_format(&graph, format)
+}
 
 pub fn register_fusion_state() {}
 
-pub fn register_graph_to_url_renderer() {}
+// Installs the function which turns a DOT graph into a URL, used by
+// RenderedGraphFormat::Url. The last registered renderer wins.
+pub fn register_graph_to_url_renderer(renderer: GraphToUrlRenderer) {
+  let mut url_renderer = URL_RENDERER.lock().unwrap();
+  if url_renderer.is_some() {
+    println!("Multiple calls to register_graph_to_url_renderer. Last call wins.");
+  }
+  *url_renderer = Some(renderer);
+}
+
+pub fn warp_fusion_explorer() {}
+
+#[cfg(test)]
+mod tests {
+  use common::dyn_literal::DynLiteral;
+  use hlo::{hlo_instruction::FusionKind, hlo_module_config::HloModuleConfig};
+  use crate::test_utils::r0f32;
+  use super::*;
+
+  // Builds negate(exp(add(p0, p1))) where the add and the exp are fused.
+  fn make_module() -> (HloModule, ComputationId) {
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    let mut fused = HloComputation::new("fused_computation".to_string());
+    let fp0 = fused.add_parameter(
+      HloInstruction::create_parameter(0, &r0f32(), "fp0".to_string()));
+    let fp1 = fused.add_parameter(
+      HloInstruction::create_parameter(1, &r0f32(), "fp1".to_string()));
+    let add = fused.add_instruction(HloInstruction::create_binary(
+      &r0f32(), HloOpcode::Add, fused.instruction(fp0), fused.instruction(fp1)),
+      "add".to_string());
+    let exp = fused.add_instruction(HloInstruction::create_unary(
+      &r0f32(), HloOpcode::Exp, fused.instruction(add)), "exp".to_string());
+    fused.set_root_instruction(exp, false);
+    fused.set_is_fusion_computation(true);
+    let fused_id = module.add_embedded_computation(fused);
+
+    let mut entry = HloComputation::new("entry".to_string());
+    let p0 = entry.add_parameter(
+      HloInstruction::create_parameter(0, &r0f32(), "p0".to_string()));
+    let constant = entry.add_instruction(HloInstruction::create_constant(
+      DynLiteral::create_r0(1.0_f32)), "constant".to_string());
+    let fusion = entry.add_instruction(HloInstruction::create_fusion(
+      &r0f32(), FusionKind::Loop,
+      &[entry.instruction(p0), entry.instruction(constant)], fused_id),
+      "fusion".to_string());
+    let negate = entry.add_instruction(HloInstruction::create_unary(
+      &r0f32(), HloOpcode::Negate, entry.instruction(fusion)), "negate".to_string());
+    entry.set_root_instruction(negate, false);
+    let entry_id = module.add_entry_computation(entry);
+    (module, entry_id)
+  }
+
+  fn node_id(dot: &str, name: &str) -> String {
+    let line = dot.lines().find(|l| l.contains(&format!("<b>{}</b>", name))).unwrap();
+    line.split(' ').next().unwrap().to_string()
+  }
+
+  #[test]
+  fn test_render_fusion_cluster() {
+    let (module, entry) = make_module();
+    let dot = render_graph(&module, entry, "my graph", &DebugOptions::new(),
+      RenderedGraphFormat::Dot, &HloRenderOptions::new(), None).unwrap();
+    assert!(dot.starts_with("digraph G {"));
+    assert!(dot.contains("label = <<b>my graph</b>>;"));
+    assert!(dot.contains("subgraph cluster_"));
+    assert!(dot.contains("label = <fusion<br/>kLoop>;"));
+    // The fusion is drawn through its fused instructions: operands go into
+    // the fused parameters and the fused root feeds the users.
+    assert!(!dot.contains("<b>fusion</b>"));
+    assert!(dot.contains(&format!("{} -> {} [headlabel=\"0\", labeldistance=2];",
+      node_id(&dot, "p0"), node_id(&dot, "fp0"))));
+    assert!(dot.contains(&format!("{} -> {};", node_id(&dot, "exp"), node_id(&dot, "negate"))));
+
+    let mut options = HloRenderOptions::new();
+    options.set_show_fusion_subcomputation(false);
+    let dot = render_graph(&module, entry, "my graph", &DebugOptions::new(),
+      RenderedGraphFormat::Dot, &options, None).unwrap();
+    assert!(!dot.contains("subgraph cluster_"));
+    assert!(dot.contains(&format!("{} -> {};", node_id(&dot, "fusion"), node_id(&dot, "negate"))));
+  }
 
-pub fn warp_fusion_explorer() {}
\ No newline at end of file
+  #[test]
+  fn test_render_color_map() {
+    let (module, entry) = make_module();
+    let mut color_map = HashMap::new();
+    color_map.insert("negate".to_string(),
+      ColorStats::new("#123456".to_string(), "flops: 1".to_string()));
+    let mut options = HloRenderOptions::new();
+    options.set_override_node_colors(true);
+    let dot = render_graph(&module, entry, "costs", &DebugOptions::new(),
+      RenderedGraphFormat::Dot, &options, Some(&color_map)).unwrap();
+    let negate_line = dot.lines().find(|l| l.contains("<b>negate</b>")).unwrap();
+    assert!(negate_line.contains("fillcolor=\"#123456\""));
+    assert!(negate_line.contains("flops: 1"));
+  }
+
+  #[test]
+  fn test_render_neighborhood() {
+    let (module, entry) = make_module();
+    let negate = module.computation(entry).root_instruction_id();
+    let mut options = HloRenderOptions::new();
+    options.set_show_fusion_subcomputation(false);
+    let dot = render_neighborhood_around(&module, entry, negate, 1,
+      RenderedGraphFormat::Dot, &options, &HashSet::new()).unwrap();
+    // Only negate and its operand are shown; the operands of the fusion are
+    // elided.
+    assert!(!dot.contains("<b>p0</b>"));
+    let negate_line = dot.lines().find(|l| l.contains("<b>negate</b>")).unwrap();
+    assert!(negate_line.contains("shape=diamond"));
+    let fusion_line = dot.lines().find(|l| l.contains("<b>fusion</b>")).unwrap();
+    assert!(fusion_line.contains("(some operands omitted)"));
+    assert!(fusion_line.contains("style=\"filled,dashed\""));
+  }
+
+  #[test]
+  fn test_render_all_paths_from_to() {
+    let (module, entry) = make_module();
+    let comp = module.computation(entry);
+    let p0 = comp.parameter_instruction(0).unwrap().id();
+    let negate = comp.root_instruction_id();
+    let mut options = HloRenderOptions::new();
+    options.set_show_fusion_subcomputation(false);
+    let dot = render_all_paths_from_to(&module, entry, p0, negate, 10,
+      RenderedGraphFormat::Dot, &options).unwrap();
+    assert!(dot.contains("<b>p0</b>"));
+    assert!(dot.contains("<b>fusion</b>"));
+    assert!(!dot.contains("<b>constant</b>"));
+
+    let err = render_all_paths_from_to(&module, entry, negate, p0, 10,
+      RenderedGraphFormat::Dot, &options).err().unwrap();
+    assert_eq!(err, "No path from negate to p0.".to_string());
+  }
+
+  #[test]
+  fn test_render_html() {
+    let (module, entry) = make_module();
+    let html = render_graph(&module, entry, "page", &DebugOptions::new(),
+      RenderedGraphFormat::Html, &HloRenderOptions::new(), None).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<svg"));
+    assert!(html.contains("negate"));
+
+    let html = render_all_computations_to_html(&module).unwrap();
+    assert!(html.contains("<h2>entry</h2>"));
+    assert!(!html.contains("<h2>fused_computation</h2>"));
+  }
+
+  #[test]
+  fn test_svg_stand_in() {
+    let (module, entry) = make_module();
+    let graph = HloDotDumper::new(&module, entry, "stand-in".to_string(),
+      &HloRenderOptions::new(), NodeFilter::new_default(), None).dump();
+    let svg = graph.to_svg_stand_in();
+    assert!(svg.starts_with("<svg"));
+    assert_eq!(svg.matches("<line ").count(), graph.edges.len());
+    assert!(svg.contains(">fusion kLoop</text>"));
+    assert!(svg.contains(">negate</text>"));
+  }
+}
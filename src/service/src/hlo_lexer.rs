@@ -3,7 +3,6 @@
 use common::{
   blitz_data::PrimitiveType,
   primitive_util::{is_primitive_type_name, string_to_primitive_type},
-  util::nan_with_sign_and_payload
 };
 use regex::Regex;
 
@@ -61,14 +60,58 @@ pub enum TokKind {
 const EOF: i64 = -1;
 const ERROR: i64 = -2;
 
-pub fn tok_kind_to_string(_kind: &TokKind) -> String {
-  unimplemented!()
+pub fn tok_kind_to_string(kind: &TokKind) -> String {
+  match kind {
+    TokKind::Eof => "kEof".to_string(),
+    TokKind::Error => "kError".to_string(),
+    TokKind::Equal => "kEqaul".to_string(),
+    TokKind::Comma => "kComma".to_string(),
+    TokKind::Colon => "kColon".to_string(),
+    TokKind::Asterisk => "kAsterisk".to_string(),
+    TokKind::QuestionMark => "kQuestionMark".to_string(),
+    TokKind::Octothorp => "kOctothorp".to_string(),
+    TokKind::Plus => "kPlus".to_string(),
+    TokKind::Tilde => "kTilde".to_string(),
+    TokKind::Lsquare => "kLsquare".to_string(),
+    TokKind::Rsquare => "kRsquare".to_string(),
+    TokKind::Lbrace => "kLbrace".to_string(),
+    TokKind::Rbrace => "kRbrace".to_string(),
+    TokKind::Lparen => "kLparen".to_string(),
+    TokKind::Rparen => "kRparen".to_string(),
+    TokKind::Dots => "kDots".to_string(),
+    TokKind::Arrow => "kArrow".to_string(),
+    TokKind::Leq => "kLeq".to_string(),
+    TokKind::HloModule => "kw_HloModule".to_string(),
+    TokKind::Entry => "kw_ENTRY".to_string(),
+    TokKind::Root => "kw_ROOT".to_string(),
+    TokKind::True => "kw_true".to_string(),
+    TokKind::False => "kw_false".to_string(),
+    TokKind::Maximal => "kw_maximal".to_string(),
+    TokKind::Replicated => "kw_replicated".to_string(),
+    TokKind::Manual => "kw_manual".to_string(),
+    TokKind::LastTileDimReplicate => "kw_last_tile_dim_replicate".to_string(),
+    TokKind::ShardAs => "kw_shard_as".to_string(),
+    TokKind::ShardLike => "kw_shard_like".to_string(),
+    TokKind::Unknown => "kw_unknown".to_string(),
+    TokKind::Inf => "kw_inf".to_string(),
+    TokKind::NegInf => "kNegInf".to_string(),
+    TokKind::PrimitiveType => "kPrimitiveType".to_string(),
+    TokKind::Name => "kName".to_string(),
+    TokKind::AttributeName => "kAttributeName".to_string(),
+    TokKind::DimLabels => "kDimLabels".to_string(),
+    TokKind::DxD => "kDxD".to_string(),
+    TokKind::Pad => "kPad".to_string(),
+    TokKind::Ident => "kIdent".to_string(),
+    TokKind::String => "kString".to_string(),
+    TokKind::Int => "kInt".to_string(),
+    TokKind::Decimal => "kDecimal".to_string(),
+  }
 }
 
 // Informaton about the current token.
 #[derive(Debug, Clone)]
 struct TokenState {
-  token_start: usize, //Option<char>,
+  token_start: usize,
   current_kind: TokKind,
   str_val: String,
   i64_val: i64,
@@ -89,11 +132,6 @@ impl TokenState {
   }
 }
 
-struct LineNoCacheTy {
-  last_query: char,
-  line_no_of_query: u64,
-}
-
 // Lexer for the HloModule::to_string() format text.
 // This class is meant to be used by HloParser. You shouldn't need to use
 // it directly.
@@ -154,8 +192,32 @@ impl HloLexer {
     self.token_state.token_start
   }
 
-  pub fn get_line_and_column() {}
-  pub fn get_line() {}
+  // Returns the 1-based line and column of the given location.
+  pub fn get_line_and_column(&self, location: usize) -> (usize, usize) {
+    let location = location.min(self.buf.len());
+    let prefix = &self.buf.as_bytes()[..location];
+    let line_no = prefix.iter().filter(|c| **c == b'\n').count() + 1;
+    let line_start = match prefix.iter().rposition(|c| *c == b'\n') {
+      Some(pos) => pos + 1,
+      None => 0
+    };
+    (line_no, location - line_start + 1)
+  }
+
+  // Returns the whole line given the location.
+  pub fn get_line(&self, location: usize) -> String {
+    let location = location.min(self.buf.len());
+    let bytes = self.buf.as_bytes();
+    let start = match bytes[..location].iter().rposition(|c| *c == b'\n') {
+      Some(pos) => pos + 1,
+      None => 0
+    };
+    let end = match bytes[location..].iter().position(|c| *c == b'\n') {
+      Some(pos) => location + pos,
+      None => bytes.len()
+    };
+    self.string_from_pointers(start, end)
+  }
 
   // Looks ahead one token and returns it. Lexer state is unchanged.
   pub fn look_ahead(&mut self) -> TokKind {
@@ -171,46 +233,42 @@ impl HloLexer {
     kind
   }
 
-  // Lexes a string delimited by matching curly braces.
-  // Curlies contained inside double quotes don't count.
+  // Lexes a string delimited by matching curly braces. The current token
+  // must be the opening '{'. Curlies contained inside double quotes don't
+  // count.
   pub fn lex_json_dict(&mut self) -> TokKind {
     if self.get_kind() != TokKind::Lbrace {
       return TokKind::Error;
     }
-    let orig =
-      self.string_from_pointers(self.token_state.token_start, self.buf.len());
-    let mut str = orig.clone();
-    if str.is_empty() {
-      return TokKind::Error;
-    }
-    let mut object_depth = 0;
-    if str.chars().nth(0) != Some('{') {
-      return TokKind::Error;
-    }
-    object_depth += 1;
-    str.remove(0);
-
-    while !str.is_empty() {
-      if object_depth == 0 { break; }
-      if str.chars().nth(0) == Some('"') {
-        let string_pattern = Regex::new(r"(([^\\]|\\.)*)").unwrap(); // TODO
-        if string_pattern.captures(&str).is_none() {
-          return TokKind::Error;
-        }
-        continue;
+    let bytes = self.buf.as_bytes();
+    let start = self.token_state.token_start;
+    let mut ptr = start + 1;
+    let mut object_depth = 1;
+    while ptr < bytes.len() && object_depth > 0 {
+      match bytes[ptr] {
+        b'"' => {
+          // Skip the quoted string, including escaped characters.
+          ptr += 1;
+          while ptr < bytes.len() && bytes[ptr] != b'"' {
+            if bytes[ptr] == b'\\' { ptr += 1; }
+            ptr += 1;
+          }
+          if ptr >= bytes.len() {
+            return TokKind::Error;
+          }
+        },
+        b'{' => object_depth += 1,
+        b'}' => object_depth -= 1,
+        _ => {}
       }
-      if str.chars().nth(0) == Some('{') { object_depth += 1; }
-      if str.chars().nth(0) == Some('}') { object_depth -= 1; }
-      str.remove(0);
+      ptr += 1;
     }
-
     if object_depth != 0 {
       return TokKind::Error;
     }
-    // TODO: cur_ptr = str.data
+    self.current_ptr = ptr;
+    self.token_state.str_val = self.string_from_pointers(start, ptr);
     self.token_state.current_kind = TokKind::String;
-    let split = orig.split_at(orig.len() - str.len());
-    self.token_state.str_val = split.0.to_string();
     TokKind::String
   }
 
@@ -218,32 +276,35 @@ impl HloLexer {
   // nor invalid character, moves the pointer forward.
   fn get_next_char(&mut self) -> Option<char> {
     let current_char = self.peek_current_char();
-    if current_char != None {
+    if current_char.is_some() {
       self.current_ptr += 1;
     }
     current_char
   }
 
-  // Returns the current character.
+  // Returns the current character. The text is expected to be ASCII outside
+  // of quoted strings, so the lexer walks the buffer byte by byte.
   fn peek_current_char(&self) -> Option<char> {
-    if self.buf.char_indices().nth(self.current_ptr) == None {
-      return None;
-    }
-    Some(self.buf.char_indices().nth(self.current_ptr).unwrap().1)
+    self.buf.as_bytes().get(self.current_ptr).map(|c| *c as char)
   }
 
   // Creates string with the given begin and end.
   fn string_from_pointers(&self, begin: usize, end: usize) -> String {
     assert!(begin <= end);
-    assert!(self.can_dereference(begin));
-    assert!(self.can_dereference(end));
-    self.buf[begin..end].to_string()
+    assert!(end <= self.buf.len());
+    String::from_utf8_lossy(&self.buf.as_bytes()[begin..end]).to_string()
   }
 
   // Returns true if the given ptr is dereferenceable within the range of the
   // current buffer.
   fn can_dereference(&self, ptr: usize) -> bool {
-    self.buf.char_indices().nth(ptr) != None
+    ptr < self.buf.len()
+  }
+
+  // Returns the text from the start of the current token to the end of the
+  // buffer, which is what the pattern based lexing consumes from.
+  fn consumable(&self) -> &str {
+    &self.buf[self.token_state.token_start..]
   }
 
   fn lex_token(&mut self) -> TokKind {
@@ -254,27 +315,25 @@ impl HloLexer {
         return TokKind::Eof;
       }
       match current_char.unwrap() {
-        ' ' => continue,
-        '\t' => continue,
-        '\n' => continue,
-        '\r' => continue,
-        '0' => return self.lex_number_or_pattern_wrap(&current_char),
-        '1' => return self.lex_number_or_pattern_wrap(&current_char),
-        '2' => return self.lex_number_or_pattern_wrap(&current_char),
-        '3' => return self.lex_number_or_pattern_wrap(&current_char),
-        '4' => return self.lex_number_or_pattern_wrap(&current_char),
-        '5' => return self.lex_number_or_pattern_wrap(&current_char),
-        '6' => return self.lex_number_or_pattern_wrap(&current_char),
-        '7' => return self.lex_number_or_pattern_wrap(&current_char),
-        '8' => return self.lex_number_or_pattern_wrap(&current_char),
-        '9' => return self.lex_number_or_pattern_wrap(&current_char),
-        '-' => return self.lex_number_or_pattern_wrap(&current_char),
+        ' ' | '\t' | '\n' | '\r' => continue,
+        '0'..='9' | '-' | '?' => {
+          if current_char == Some('-') && self.peek_current_char() == Some('>') {
+            self.current_ptr += 1;
+            return TokKind::Arrow;
+          }
+          let tmp = self.lex_number_or_pattern();
+          if tmp == TokKind::Error && current_char == Some('?') {
+            return TokKind::QuestionMark;
+          }
+          return tmp;
+        },
         '=' => return TokKind::Equal,
         '<' => {
-          if current_char.unwrap() == '<' && self.peek_current_char() == Some('=') {
+          if self.peek_current_char() == Some('=') {
             self.current_ptr += 1;
             return TokKind::Leq;
           }
+          return TokKind::Error;
         },
         ',' => return TokKind::Comma,
         '%' => return self.lex_percent(),
@@ -301,26 +360,25 @@ impl HloLexer {
                 self.current_ptr += 1;
                 break;
               }
-              if current == None {
+              if current.is_none() {
                 // Unterminated comment.
                 self.current_ptr = comment_start;
                 return TokKind::Error;
               }
-              // TODO: if current == Error
             }
             continue;
           } else if self.peek_current_char() == Some('/') {
             // This is the start of a '//' delimited comment.
             loop {
               let current = self.peek_current_char();
-              if current == None || current == Some('\n') || current == Some('\r') {
+              if current.is_none() || current == Some('\n') || current == Some('\r') {
                 break;
               }
-              // TODO: if current == Error
               self.current_ptr += 1;
             }
+            continue;
           }
-          continue;
+          return TokKind::Error;
         },
         '.' => {
           if self.peek_current_char() == Some('.') {
@@ -333,32 +391,41 @@ impl HloLexer {
           return TokKind::Error;
         },
         '"' => return self.lex_string(),
-        _ => return TokKind::Error
+        c => {
+          if c.is_ascii_alphabetic() || c == '_' {
+            return self.lex_identifier();
+          }
+          return TokKind::Error;
+        }
       }
     }
   }
 
   // Lex a shape, name, keyword, attribute name, the dim labels pattern, and
   // other identifiers.
+  // shape    ::= ([a-zA-Z0-9_]*[0-9]*)\[([0-9,]*)\](?:\s*{([0-9,]*)})?
+  // name     ::= [a-zA-Z_][a-zA-Z0-9_.-]*:
+  // keyword  ::= HloModule, ENTRY, ...
+  // attribute_name ::= condition, body, dimensions, ...
+  // dim_labels_pattern ::= [0-9bf?]{2,}_[0-9io?]{2,}->[0-9bf?]{2,}
+  // identifiers ::= other cases that match [a-zA-Z_][a-zA-Z0-9_.-]*
   fn lex_identifier(&mut self) -> TokKind {
-    while is_identifier_char(self.peek_current_char().unwrap()) {
+    while self.peek_current_char().map_or(false, is_identifier_char) {
       self.current_ptr += 1;
     }
 
-    if self.peek_current_char().unwrap() == ':' {
-      let str = self.token_state.str_val.as_str();
-      let str_val =
-        String::from(&str[self.token_state.token_start..self.current_ptr]);
-      self.token_state.str_val = str_val;
+    // If followed by ':', it's a name.
+    if self.peek_current_char() == Some(':') {
+      self.token_state.str_val =
+        self.string_from_pointers(self.token_state.token_start, self.current_ptr);
       self.current_ptr += 1; // skip ':'
       return TokKind::Name;
     }
 
-    if self.peek_current_char().unwrap() == '=' {
-      let str = self.token_state.str_val.as_str();
-      let str_val =
-        String::from(&str[self.token_state.token_start..self.current_ptr]);
-      self.token_state.str_val = str_val;
+    // If followed by '=', it's a attribute name.
+    if self.peek_current_char() == Some('=') {
+      self.token_state.str_val =
+        self.string_from_pointers(self.token_state.token_start, self.current_ptr);
       self.current_ptr += 1; // skip '='
       return TokKind::AttributeName;
     }
@@ -366,37 +433,54 @@ impl HloLexer {
     let identifier = self.string_from_pointers(
       self.token_state.token_start, self.current_ptr);
 
+    // Primitive type strings are reserved words. The exception is 'tuple'
+    // whose type is represented using nested parentheses without the string
+    // 'tuple'.
     if is_primitive_type_name(&identifier) {
-      let primitive_type = string_to_primitive_type(&identifier);
-      if primitive_type.unwrap() != &PrimitiveType::Tuple {
-        self.token_state.primitive_type_val = primitive_type.unwrap().clone();
+      let primitive_type = string_to_primitive_type(&identifier).unwrap();
+      if primitive_type != &PrimitiveType::Tuple {
+        self.token_state.primitive_type_val = primitive_type.clone();
         return TokKind::PrimitiveType;
       }
     }
 
-    if identifier.as_str() == "nan" {
-      let mut payload: Option<i64> = None;
+    if identifier == "nan" {
+      let mut payload = None;
       if self.peek_current_char() == Some('(') {
-        let consumable =
-          self.string_from_pointers(self.current_ptr, self.buf.len());
-        payload = self.lex_nan_payload(consumable);
+        payload = self.lex_nan_payload();
         if payload.is_none() {
           return TokKind::Error;
         }
       }
-      self.token_state.decimal_val = 
-        nan_with_sign_and_payload::<f64>(false, payload.unwrap() as u64);
+      self.token_state.decimal_val = nan_with_sign_and_payload(false, payload);
       return TokKind::Decimal;
     }
 
-    let consumable =
-      self.string_from_pointers(self.token_state.token_start, self.buf.len());
+    // See if this is a keyword.
+    match identifier.as_str() {
+      "true" => return TokKind::True,
+      "false" => return TokKind::False,
+      "inf" => return TokKind::Inf,
+      "HloModule" => return TokKind::HloModule,
+      "ENTRY" => return TokKind::Entry,
+      "ROOT" => return TokKind::Root,
+      "maximal" => return TokKind::Maximal,
+      "replicated" => return TokKind::Replicated,
+      "manual" => return TokKind::Manual,
+      "last_tile_dim_replicate" => return TokKind::LastTileDimReplicate,
+      "shard_as" => return TokKind::ShardAs,
+      "shard_like" => return TokKind::ShardLike,
+      "unknown" => return TokKind::Unknown,
+      _ => {}
+    }
+
     let dim_labels_pattern =
-      Regex::new(r"[0-9bf?]{2,}_[0-9io?]{2,}->[0-9bf?]{2,}").unwrap();
-    let captures = dim_labels_pattern.captures(&consumable);
-    if captures.is_some() {
-      let data = captures.unwrap().get(0).unwrap().as_str().to_string();
-      self.token_state.str_val = data;
+      Regex::new(r"^[0-9bf?]{2,}_[0-9io?]{2,}->[0-9bf?]{2,}").unwrap();
+    let found = dim_labels_pattern.find(self.consumable())
+      .map(|m| (m.end(), m.as_str().to_string()));
+    if let Some((end, text)) = found {
+      self.current_ptr = self.token_state.token_start + end;
+      self.token_state.str_val = text;
       return TokKind::DimLabels;
     }
 
@@ -405,58 +489,183 @@ impl HloLexer {
   }
 
   // Lex names after a % character.
-  // name ::= [a-zA-Z_][a-zA-Z0-9_.-]
+  // name ::= [a-zA-Z_][a-zA-Z0-9_.-]*
   fn lex_percent(&mut self) -> TokKind {
     let name_start = self.current_ptr;
-    let curr_char = self.peek_current_char().unwrap();
-    if curr_char.is_ascii_alphabetic() || curr_char == '_' {
+    let curr_char = self.peek_current_char();
+    if curr_char.map_or(false, |c| c.is_ascii_alphabetic() || c == '_') {
       self.current_ptr += 1;
-      while is_identifier_char(self.peek_current_char().unwrap()) {
+      while self.peek_current_char().map_or(false, is_identifier_char) {
         self.current_ptr += 1;
       }
-      let str = self.token_state.str_val.as_str();
-      let str_val = String::from(&str[name_start..self.current_ptr]);
-      self.token_state.str_val = str_val;
+      self.token_state.str_val =
+        self.string_from_pointers(name_start, self.current_ptr);
       return TokKind::Name;
     }
     TokKind::Error
   }
 
-  fn lex_shape() {}
-  fn lex_constant() {}
+  // Lex integer and floating-point values, -inf, and patterns for dim labels,
+  // dxd (e.g. 1x2x3), and pad.
+  //
+  // fp with exp ::= [-]?([0-9]+|[0-9]+[.][0-9]*|[0-9]*[.][0-9]+)([eE][+-]?[0-9]+)
+  // fp without exp ::= [-]?([0-9]+[.][0-9]*|[0-9]*[.][0-9]+)
+  // dim_labels_pattern ::= [0-9bf?]{2,}_[0-9io?]{2,}->[0-9bf?]{2,}
+  // dxd_pattern ::= [0-9]+(x[0-9]+)+
+  // pad_pattern ::=
+  //   [-]?[0-9]+_[-]?[0-9]+(_[0-9]+)?(x[-]?[0-9]+_[-]?[0-9]+(_[0-9]+)?)*
+  // int ::=  [-]?[0-9]+
+  // negative inf ::= '-inf'
   fn lex_number_or_pattern(&mut self) -> TokKind {
-    TokKind::Arrow
-  }
+    let float_pattern = Regex::new(
+      r"^[-]?(((\d+|\d+[.]\d*|\d*[.]\d+)([eE][+-]?\d+))|(\d+[.]\d*|\d*[.]\d+))")
+      .unwrap();
+    if let Some(m) = float_pattern.find(self.consumable()) {
+      let text = m.as_str().to_string();
+      self.current_ptr = self.token_state.token_start + m.end();
+      match text.parse::<f64>() {
+        Ok(value) => {
+          self.token_state.decimal_val = value;
+          return TokKind::Decimal;
+        },
+        Err(_) => return TokKind::Error
+      }
+    }
 
-  fn lex_number_or_pattern_wrap(&mut self, current_char: &Option<char>) -> TokKind {
-    if self.peek_current_char() == Some('>') {
-      self.current_ptr += 1;
-      return TokKind::Arrow;
+    let dim_labels_pattern =
+      Regex::new(r"^[0-9bf?]{2,}_[0-9io?]{2,}->[0-9bf?]{2,}").unwrap();
+    let dxd_pattern = Regex::new(r"^[0-9]+(x[0-9]+)+").unwrap();
+    let pad_pattern = Regex::new(
+      r"^[-]?[0-9]+_[-]?[0-9]+(_[0-9]+)?(x[-]?[0-9]+_[-]?[0-9]+(_[0-9]+)?)*")
+      .unwrap();
+    let patterns = [
+      (dim_labels_pattern, TokKind::DimLabels),
+      (dxd_pattern, TokKind::DxD),
+      (pad_pattern, TokKind::Pad)
+    ];
+    for (pattern, kind) in patterns {
+      let found = pattern.find(self.consumable())
+        .map(|m| (m.end(), m.as_str().to_string()));
+      if let Some((end, text)) = found {
+        self.current_ptr = self.token_state.token_start + end;
+        self.token_state.str_val = text;
+        return kind;
+      }
+    }
+
+    let int_pattern = Regex::new(r"^[-]?\d+").unwrap();
+    if let Some(m) = int_pattern.find(self.consumable()) {
+      let text = m.as_str().to_string();
+      self.current_ptr = self.token_state.token_start + m.end();
+      if let Ok(value) = text.parse::<i64>() {
+        self.token_state.i64_val = value;
+        return TokKind::Int;
+      }
+      // Values which only fit in an unsigned 64 bit integer are stored with
+      // their bit pattern.
+      if let Ok(value) = text.parse::<u64>() {
+        self.token_state.i64_val = value as i64;
+        return TokKind::Int;
+      }
+      return TokKind::Error;
+    }
+
+    if self.consumable().starts_with("-inf") {
+      self.current_ptr = self.token_state.token_start + 4;
+      return TokKind::NegInf;
     }
-    let tmp = self.lex_number_or_pattern();
-    if tmp == TokKind::Error && current_char == &Some('?') {
-      return TokKind::QuestionMark;
+
+    if self.consumable().starts_with("-nan") {
+      self.current_ptr = self.token_state.token_start + 4;
+      let mut payload = None;
+      if self.peek_current_char() == Some('(') {
+        payload = self.lex_nan_payload();
+        if payload.is_none() {
+          return TokKind::Error;
+        }
+      }
+      self.token_state.decimal_val = nan_with_sign_and_payload(true, payload);
+      return TokKind::Decimal;
     }
-    return tmp;
+
+    TokKind::Error
   }
 
-  // Lexes quoted string with escapingcharacters.
+  // Lexes quoted string with escaping characters. If matched, the quoted
+  // string will be unescaped and stored to str_val.
   fn lex_string(&mut self) -> TokKind {
-    let consumable =
-      self.string_from_pointers(self.token_state.token_start, self.buf.len());
-    let escaping_pattern = Regex::new(r"(([^\\]|\\.)*)").unwrap(); // TODO
-    let data = escaping_pattern.captures(&consumable);
-    if data.is_some() {
-      // TODO
-      return TokKind::String;
+    let escaping_pattern = Regex::new(r#"^"(([^"\\]|\\.)*)""#).unwrap();
+    let captures = escaping_pattern.captures(self.consumable());
+    if captures.is_none() {
+      return TokKind::Error;
+    }
+    let captures = captures.unwrap();
+    let end = captures.get(0).unwrap().end();
+    let raw = captures.get(1).unwrap().as_str().to_string();
+    self.current_ptr = self.token_state.token_start + end;
+    match c_unescape(&raw) {
+      Ok(value) => {
+        self.token_state.str_val = value;
+        TokKind::String
+      },
+      Err(_) => TokKind::Error
     }
-    TokKind::Error
   }
 
-  fn lex_nan_payload(&mut self, _consumable: String) -> Option<i64> { Some(1) }
+  // Lexes the '(0x...)' payload that may follow 'nan'.
+  fn lex_nan_payload(&mut self) -> Option<u64> {
+    let payload_pattern = Regex::new(r"^\(0x[0-9a-fA-F]+\)").unwrap();
+    let rest = &self.buf[self.current_ptr..];
+    let m = payload_pattern.find(rest)?;
+    let text = &m.as_str()[3..m.as_str().len() - 1];
+    let payload = u64::from_str_radix(text, 16).ok()?;
+    // The payload has to fit in the mantissa and must not be zero, otherwise
+    // the value would be an infinity.
+    if payload == 0 || payload >= (1 << 52) {
+      return None;
+    }
+    self.current_ptr += m.end();
+    Some(payload)
+  }
 }
 
 // [a-zA-Z0-9_.-]
 fn is_identifier_char(c: char) -> bool {
   c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_'
-}
\ No newline at end of file
+}
+
+// Builds a NaN with the given sign. Without a payload the quiet NaN is
+// returned.
+fn nan_with_sign_and_payload(sign: bool, payload: Option<u64>) -> f64 {
+  let payload = payload.unwrap_or(1 << 51);
+  let mut bits = 0x7ff0_0000_0000_0000u64 | payload;
+  if sign {
+    bits |= 1 << 63;
+  }
+  f64::from_bits(bits)
+}
+
+// Reverses the escaping done by util::c_escape.
+fn c_unescape(s: &str) -> Result<String, String> {
+  let mut result = String::new();
+  let mut chars = s.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      result.push(c);
+      continue;
+    }
+    match chars.next() {
+      Some('n') => result.push('\n'),
+      Some('r') => result.push('\r'),
+      Some('t') => result.push('\t'),
+      Some('"') => result.push('"'),
+      Some('\'') => result.push('\''),
+      Some('\\') => result.push('\\'),
+      Some('?') => result.push('?'),
+      Some(other) => return Err(format!("Invalid escape sequence: \\{}", other)),
+      None => return Err("Trailing backslash in string.".to_string())
+    }
+  }
+  Ok(result)
+}
+
@@ -0,0 +1,531 @@
+#![allow(dead_code)]
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use common::shape_tree::ShapeTree;
+use hlo::{
+  hlo_instruction::{HloInstruction, InstructionId},
+  hlo_module::{ComputationId, HloModule},
+  hlo_opcode::HloOpcode
+};
+use crate::call_graph::{CallContext, CallGraph};
+
+// Identifies an instruction of the module by its computation and id.
+type InstructionKey = (ComputationId, InstructionId);
+
+pub type HloIndexMap = HashMap<InstructionKey, ShapeTree<bool>>;
+
+fn key_of(instruction: &HloInstruction) -> InstructionKey {
+  (instruction.parent().unwrap(), instruction.id())
+}
+
+// The worklist state of the liveness propagation.
+struct Worklist {
+  live_index_map: HloIndexMap,
+  worklist: VecDeque<InstructionKey>,
+  workset: HashSet<InstructionKey>,
+}
+
+impl Worklist {
+  fn new() -> Self {
+    Worklist {
+      live_index_map: HashMap::new(),
+      worklist: VecDeque::new(),
+      workset: HashSet::new()
+    }
+  }
+
+  fn add_to_worklist(&mut self, key: InstructionKey) {
+    if self.workset.insert(key) {
+      self.worklist.push_back(key);
+    }
+  }
+
+  fn pop(&mut self) -> Option<InstructionKey> {
+    let key = self.worklist.pop_front()?;
+    self.workset.remove(&key);
+    Some(key)
+  }
+
+  fn liveness(&mut self, instruction: &HloInstruction) -> &mut ShapeTree<bool> {
+    self.live_index_map.entry(key_of(instruction)).or_insert_with(|| {
+      ShapeTree::new_with_value(&mut instruction.shape().clone(), false)
+    })
+  }
+
+  // Returns the live shape indices of the given instruction.
+  fn live_indices(&self, key: &InstructionKey) -> Vec<Vec<i64>> {
+    match self.live_index_map.get(key) {
+      Some(index_tree) => index_tree.nodes().iter()
+        .filter(|node| *node.value())
+        .map(|node| node.index().clone())
+        .collect(),
+      None => vec![]
+    }
+  }
+
+  // Marks 'instruction' output live at 'shape_index'.
+  // Adds to the worklist iff:
+  // *) 'instruction' is not already on worklist.
+  // *) 'shape_index' has not yet been visited.
+  fn mark_live_at_index(&mut self, instruction: &HloInstruction, shape_index: &Vec<i64>) {
+    let alive = self.liveness(instruction).mutable_element(shape_index);
+    if !*alive {
+      *alive = true;
+      self.add_to_worklist(key_of(instruction));
+    }
+  }
+
+  // Marks 'instruction' live at all shape indices in its output.
+  fn mark_live_at_all_indices(&mut self, instruction: &HloInstruction) {
+    let mut add_to_worklist = false;
+    for node in self.liveness(instruction).mutable_nodes() {
+      if !*node.value() {
+        add_to_worklist = true;
+        *node.mutable_value() = true;
+      }
+    }
+    if add_to_worklist {
+      self.add_to_worklist(key_of(instruction));
+    }
+  }
+}
+
+// Analysis which identifies all live {HloInstruction, shapeIndex} pairs in
+// an HLO module.
+//
+// HloLivenessAnalysis marks the shape index of each live output of each
+// instruction in the module, by propagating live shape index information
+// from an instruction to its called computations and operands.
+pub struct HloLivenessAnalysis<'module> {
+  module: &'module HloModule,
+  call_graph: CallGraph<'module>,
+  live_index_map: HloIndexMap
+}
+
+impl<'module> HloLivenessAnalysis<'module> {
+  pub fn new(module: &'module HloModule) -> Self {
+    HloLivenessAnalysis {
+      module: module,
+      call_graph: CallGraph::build(module, &HashSet::new()),
+      live_index_map: HashMap::new(),
+    }
+  }
+
+  // Runs liveness analysis on 'module'. Returns HloLivenessAnalysis object
+  // which exports liveness for each {HloInstruction, ShapeIndex} in 'module'.
+  pub fn run(module: &'module HloModule) -> Result<HloLivenessAnalysis<'module>, String> {
+    let mut liveness_analysis = HloLivenessAnalysis::new(module);
+    liveness_analysis.run_analysis();
+
+    Ok(liveness_analysis)
+  }
+
+  // Returns true if output of 'instruction' at 'shape_index' is live.
+  // Returns false otherwise.
+  pub fn is_live(&self, instruction: &HloInstruction, shape_index: &Vec<i64>) -> bool {
+    match self.live_index_map.get(&key_of(instruction)) {
+      Some(index_tree) => *index_tree.element(shape_index),
+      None => false
+    }
+  }
+
+  fn run_analysis(&mut self) {
+    let module = self.module;
+    let mut worklist = Worklist::new();
+    // Add entry computation root instruction.
+    worklist.mark_live_at_all_indices(module.entry_computation().unwrap().root_instruction());
+    for computation in module.computations() {
+      for instruction in computation.instructions() {
+        if instruction.has_side_effect_no_recurse() {
+          // Add instructions with side effects.
+          worklist.mark_live_at_all_indices(instruction);
+        }
+      }
+    }
+
+    while let Some((computation_id, instruction_id)) = worklist.pop() {
+      let computation = module.computation(computation_id);
+      let instruction = computation.instruction(instruction_id);
+
+      match instruction.opcode() {
+        HloOpcode::Tuple => self.propagate_liveness_through_tuple(instruction, &mut worklist),
+        HloOpcode::GetTupleElement =>
+          self.propagate_liveness_through_gte(instruction, &mut worklist),
+        HloOpcode::While => self.propagate_liveness_through_while(instruction, &mut worklist),
+        HloOpcode::Conditional =>
+          self.propagate_liveness_through_conditional(instruction, &mut worklist),
+        HloOpcode::Parameter =>
+          self.propagate_liveness_to_parameter_callers(instruction, &mut worklist),
+        _ => {
+          // Mark all called computations live.
+          for called_computation in instruction.called_computations() {
+            worklist.mark_live_at_all_indices(
+              module.computation(*called_computation).root_instruction());
+          }
+          // Propagate liveness to operands.
+          for i in 0..instruction.operand_count() {
+            worklist.mark_live_at_all_indices(computation.operand(instruction_id, i));
+          }
+        }
+      }
+
+      // Propagate liveness for instructions within control flow computations.
+      self.propagate_liveness_through_control_flow(instruction, &mut worklist);
+    }
+
+    self.live_index_map = worklist.live_index_map;
+  }
+
+  fn operand(&self, instruction: &HloInstruction, i: usize) -> &'module HloInstruction {
+    self.module.computation(instruction.parent().unwrap()).operand(instruction.id(), i)
+  }
+
+  fn root_of(&self, computation: ComputationId) -> &'module HloInstruction {
+    self.module.computation(computation).root_instruction()
+  }
+
+  // Propagates liveness through Tuple instructions.
+  // *) For each tuple operand:
+  //   *) For tuple output shape index associated with operand:
+  //     *) Propagate live shape indices to tuple operand at the associated
+  //        shape index in the operands output, and add to worklist.
+  fn propagate_liveness_through_tuple(
+    &self, instruction: &HloInstruction, worklist: &mut Worklist)
+  {
+    debug_assert_eq!(instruction.opcode(), HloOpcode::Tuple);
+    for shape_index in worklist.live_indices(&key_of(instruction)) {
+      if shape_index.is_empty() {
+        continue;
+      }
+      let operand_index = shape_index[0] as usize;
+      if operand_index >= instruction.operand_count() {
+        continue;
+      }
+      let operand = self.operand(instruction, operand_index);
+      // Mark top-level index of operand at 'operand_index'.
+      worklist.mark_live_at_index(operand, &vec![]);
+      // Mark sub-shape index of operand at 'operand_index'.
+      worklist.mark_live_at_index(operand, &shape_index[1..].to_vec());
+    }
+  }
+
+  // Propagates liveness through GetTupleElement instructions.
+  // *) For each live index in GetTupleElement output, mark output of GTE
+  //    operand at associated shape index in its output, and add to worklist.
+  fn propagate_liveness_through_gte(
+    &self, instruction: &HloInstruction, worklist: &mut Worklist)
+  {
+    debug_assert_eq!(instruction.opcode(), HloOpcode::GetTupleElement);
+    let operand = self.operand(instruction, 0);
+    // Mark operand top-level index.
+    worklist.mark_live_at_index(operand, &vec![]);
+    // Propagate live shape indices along GTE -> Tuple edge.
+    for shape_index in worklist.live_indices(&key_of(instruction)) {
+      let mut operand_shape_index = vec![instruction.tuple_index()];
+      operand_shape_index.extend(shape_index);
+      worklist.mark_live_at_index(operand, &operand_shape_index);
+    }
+  }
+
+  // Propagates liveness through While instructions.
+  // *) For each live index in While output, mark shape index of
+  //    while.body.root and while.operand (adding each to worklist).
+  // *) Mark while.cond.root and add to worklist.
+  fn propagate_liveness_through_while(
+    &self, instruction: &HloInstruction, worklist: &mut Worklist)
+  {
+    debug_assert_eq!(instruction.opcode(), HloOpcode::While);
+    let body_root = self.root_of(instruction.while_body());
+    let operand = self.operand(instruction, 0);
+    for shape_index in worklist.live_indices(&key_of(instruction)) {
+      // Propagate liveness to while body computation root instruction.
+      worklist.mark_live_at_index(body_root, &shape_index);
+      // Propagate liveness to tuple-shaped operand.
+      worklist.mark_live_at_index(operand, &shape_index);
+    }
+    // Propagate liveness to while condition computation root instruction.
+    worklist.mark_live_at_index(self.root_of(instruction.while_condition()), &vec![]);
+  }
+
+  // Propagates liveness out of Parameter instructions to callers and aliasing
+  // positions. This can occur if liveness propagates to a parameter in the
+  // while.condition computation, requiring liveness to propagate out to
+  // caller callsite while (and while.body.root).
+  fn propagate_liveness_to_parameter_callers(
+    &self, instruction: &HloInstruction, worklist: &mut Worklist)
+  {
+    debug_assert_eq!(instruction.opcode(), HloOpcode::Parameter);
+    let call_graph_node = self.call_graph.get_node(instruction.parent().unwrap());
+    if call_graph_node.context() != CallContext::ControlFlow {
+      return;
+    }
+    for callsite in call_graph_node.caller_callsites() {
+      let caller = self.module.computation(callsite.computation())
+        .instruction(callsite.instruction());
+      if caller.opcode() != HloOpcode::While {
+        continue;
+      }
+      let body_root = self.root_of(caller.while_body());
+      let operand = self.operand(caller, 0);
+      for shape_index in worklist.live_indices(&key_of(instruction)) {
+        // Propagate liveness to while result{shape_index}.
+        worklist.mark_live_at_index(caller, &shape_index);
+        // Propagate liveness to while body root{shape_index}.
+        worklist.mark_live_at_index(body_root, &shape_index);
+        // Propagate liveness to operand(0){shape_index}.
+        worklist.mark_live_at_index(operand, &shape_index);
+      }
+    }
+  }
+
+  // Makes sure that if a live instruction is within a computation used in
+  // control flow operations, we mark live even other related instructions.
+  fn propagate_liveness_through_control_flow(
+    &self, instruction: &HloInstruction, worklist: &mut Worklist)
+  {
+    let callee = instruction.parent().unwrap();
+    let call_graph_node = self.call_graph.get_node(callee);
+    if call_graph_node.context() != CallContext::ControlFlow {
+      return;
+    }
+    for callsite in call_graph_node.caller_callsites() {
+      let caller = self.module.computation(callsite.computation())
+        .instruction(callsite.instruction());
+      if caller.opcode() == HloOpcode::While {
+        // If a live instruction is within the while body or condition
+        // computation, mark the predicate value returned by the condition
+        // computation live as well.
+        worklist.mark_live_at_index(self.root_of(caller.while_condition()), &vec![]);
+      } else if caller.opcode() == HloOpcode::Conditional {
+        // If a live instruction is within the true or false branches of a
+        // conditional, we mark the predicate operand live as well.
+        worklist.mark_live_at_index(self.operand(caller, 0), &vec![]);
+        // Mark the caller instruction live.
+        worklist.mark_live_at_index(caller, &vec![]);
+        // Mark the caller's operand corresponding to the branch computation
+        // live. The predicate operand is skipped.
+        let branch = caller.called_computations().iter()
+          .position(|computation| *computation == callee);
+        if let Some(branch) = branch {
+          let operand = self.operand(caller, branch + 1);
+          worklist.mark_live_at_index(operand, &vec![]);
+          if instruction.opcode() == HloOpcode::Parameter {
+            // If 'instruction' is a parameter, propagate live shape indices
+            // to the associated callsite's argument shape indices.
+            for shape_index in worklist.live_indices(&key_of(instruction)) {
+              worklist.mark_live_at_index(operand, &shape_index);
+            }
+          }
+        }
+      }
+    }
+  }
+
+  // Propagates liveness through Conditional instructions.
+  // *) For each live index in Conditional output, mark shape index of the
+  //    root of every branch computation (adding each to worklist).
+  fn propagate_liveness_through_conditional(
+    &self, instruction: &HloInstruction, worklist: &mut Worklist)
+  {
+    debug_assert_eq!(instruction.opcode(), HloOpcode::Conditional);
+    for shape_index in worklist.live_indices(&key_of(instruction)) {
+      for called_computation in instruction.called_computations() {
+        worklist.mark_live_at_index(self.root_of(*called_computation), &shape_index);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::test_utils::{find, parse_module};
+  use super::*;
+
+  #[test]
+  fn test_dead_add() {
+    let module = parse_module(r#"
+HloModule SimpleModule
+
+ENTRY SimpleComputation {
+  constant.1 = s32[] constant(0)
+  constant.2 = s32[] constant(1)
+  add.1 = s32[] add(constant.1, constant.2)
+  ROOT add.2 = s32[] add(constant.1, constant.2)
+}
+"#);
+    let liveness = HloLivenessAnalysis::run(&module).unwrap();
+    assert!(liveness.is_live(find(&module, "add.2"), &vec![]));
+    assert!(liveness.is_live(find(&module, "constant.1"), &vec![]));
+    assert!(liveness.is_live(find(&module, "constant.2"), &vec![]));
+    assert!(!liveness.is_live(find(&module, "add.1"), &vec![]));
+  }
+
+  #[test]
+  fn test_gte_of_nested_tuple() {
+    let module = parse_module(r#"
+HloModule SimpleModule
+
+ENTRY SimpleComputation {
+  constant.1 = s32[] constant(0)
+  constant.2 = s32[] constant(1)
+  constant.3 = s32[] constant(2)
+  tuple.1 = (s32[], s32[]) tuple(constant.2, constant.3)
+  tuple.2 = (s32[], (s32[], s32[])) tuple(constant.1, tuple.1)
+  ROOT get-tuple-element.1 = (s32[], s32[]) get-tuple-element(tuple.2), index=1
+}
+"#);
+    let liveness = HloLivenessAnalysis::run(&module).unwrap();
+    let tuple2 = find(&module, "tuple.2");
+    assert!(liveness.is_live(tuple2, &vec![]));
+    assert!(!liveness.is_live(tuple2, &vec![0]));
+    assert!(liveness.is_live(tuple2, &vec![1]));
+    assert!(liveness.is_live(tuple2, &vec![1, 0]));
+    assert!(liveness.is_live(tuple2, &vec![1, 1]));
+    assert!(liveness.is_live(find(&module, "tuple.1"), &vec![0]));
+    assert!(!liveness.is_live(find(&module, "constant.1"), &vec![]));
+    assert!(liveness.is_live(find(&module, "constant.2"), &vec![]));
+    assert!(liveness.is_live(find(&module, "constant.3"), &vec![]));
+  }
+
+  #[test]
+  fn test_while_with_dead_tuple_element() {
+    let module = parse_module(r#"
+HloModule SimpleLoop
+
+SimpleLoop.body {
+  loop_var.1 = (s32[], s32[3]{0}) parameter(0)
+  get-tuple-element.1 = s32[] get-tuple-element(loop_var.1), index=0
+  constant.1 = s32[] constant(1)
+  add.0 = s32[] add(get-tuple-element.1, constant.1)
+  get-tuple-element.2 = s32[3]{0} get-tuple-element(loop_var.1), index=1
+  multiply.0 = s32[3]{0} multiply(get-tuple-element.2, get-tuple-element.2)
+  ROOT tuple.0 = (s32[], s32[3]{0}) tuple(add.0, multiply.0)
+}
+
+SimpleLoop.condition {
+  loop_var.2 = (s32[], s32[3]{0}) parameter(0)
+  get-tuple-element.3 = s32[] get-tuple-element(loop_var.2), index=0
+  constant.2 = s32[] constant(5)
+  ROOT less-than = pred[] compare(get-tuple-element.3, constant.2), direction=LT
+}
+
+ENTRY SimpleLoop {
+  constant.3 = s32[] constant(0)
+  constant.4 = s32[3]{0} constant({0, 1, 2})
+  tuple.1 = (s32[], s32[3]{0}) tuple(constant.3, constant.4)
+  while.0 = (s32[], s32[3]{0}) while(tuple.1), condition=SimpleLoop.condition, body=SimpleLoop.body
+  ROOT get-tuple-element.4 = s32[] get-tuple-element(while.0), index=0
+}
+"#);
+    let liveness = HloLivenessAnalysis::run(&module).unwrap();
+    assert!(liveness.is_live(find(&module, "get-tuple-element.4"), &vec![]));
+    let while0 = find(&module, "while.0");
+    assert!(liveness.is_live(while0, &vec![]));
+    assert!(liveness.is_live(while0, &vec![0]));
+    assert!(!liveness.is_live(while0, &vec![1]));
+    let tuple1 = find(&module, "tuple.1");
+    assert!(liveness.is_live(tuple1, &vec![0]));
+    assert!(!liveness.is_live(tuple1, &vec![1]));
+    assert!(liveness.is_live(find(&module, "constant.3"), &vec![]));
+    assert!(!liveness.is_live(find(&module, "constant.4"), &vec![]));
+    let tuple0 = find(&module, "tuple.0");
+    assert!(liveness.is_live(tuple0, &vec![0]));
+    assert!(!liveness.is_live(tuple0, &vec![1]));
+    assert!(liveness.is_live(find(&module, "add.0"), &vec![]));
+    assert!(!liveness.is_live(find(&module, "multiply.0"), &vec![]));
+    assert!(liveness.is_live(find(&module, "less-than"), &vec![]));
+  }
+
+  #[test]
+  fn test_while_condition_keeps_tuple_element_live() {
+    // The condition reads element 1, which is otherwise dead, so liveness
+    // propagates out of the condition parameter into the loop state.
+    let module = parse_module(r#"
+HloModule SimpleLoop
+
+SimpleLoop.body {
+  loop_var.1 = (s32[], s32[]) parameter(0)
+  get-tuple-element.1 = s32[] get-tuple-element(loop_var.1), index=0
+  get-tuple-element.2 = s32[] get-tuple-element(loop_var.1), index=1
+  constant.1 = s32[] constant(1)
+  add.0 = s32[] add(get-tuple-element.2, constant.1)
+  ROOT tuple.0 = (s32[], s32[]) tuple(get-tuple-element.1, add.0)
+}
+
+SimpleLoop.condition {
+  loop_var.2 = (s32[], s32[]) parameter(0)
+  get-tuple-element.3 = s32[] get-tuple-element(loop_var.2), index=1
+  constant.2 = s32[] constant(5)
+  ROOT less-than = pred[] compare(get-tuple-element.3, constant.2), direction=LT
+}
+
+ENTRY SimpleLoop {
+  constant.3 = s32[] constant(0)
+  constant.4 = s32[] constant(1)
+  tuple.1 = (s32[], s32[]) tuple(constant.3, constant.4)
+  while.0 = (s32[], s32[]) while(tuple.1), condition=SimpleLoop.condition, body=SimpleLoop.body
+  ROOT get-tuple-element.4 = s32[] get-tuple-element(while.0), index=0
+}
+"#);
+    let liveness = HloLivenessAnalysis::run(&module).unwrap();
+    let while0 = find(&module, "while.0");
+    assert!(liveness.is_live(while0, &vec![0]));
+    assert!(liveness.is_live(while0, &vec![1]));
+    assert!(liveness.is_live(find(&module, "add.0"), &vec![]));
+    assert!(liveness.is_live(find(&module, "constant.4"), &vec![]));
+  }
+
+  #[test]
+  fn test_conditional_branches() {
+    let module = parse_module(r#"
+HloModule Conditional
+
+true_branch {
+  true_param = (s32[], s32[]) parameter(0)
+  ROOT true_gte = s32[] get-tuple-element(true_param), index=0
+}
+
+false_branch {
+  false_param = s32[] parameter(0)
+  ROOT false_negate = s32[] negate(false_param)
+}
+
+ENTRY entry {
+  p0 = pred[] parameter(0)
+  constant.1 = s32[] constant(1)
+  constant.2 = s32[] constant(2)
+  constant.3 = s32[] constant(3)
+  tuple.1 = (s32[], s32[]) tuple(constant.1, constant.2)
+  ROOT conditional = s32[] conditional(p0, tuple.1, constant.3), true_computation=true_branch, false_computation=false_branch
+}
+"#);
+    let liveness = HloLivenessAnalysis::run(&module).unwrap();
+    assert!(liveness.is_live(find(&module, "p0"), &vec![]));
+    assert!(liveness.is_live(find(&module, "true_gte"), &vec![]));
+    assert!(liveness.is_live(find(&module, "false_negate"), &vec![]));
+    assert!(liveness.is_live(find(&module, "constant.3"), &vec![]));
+    let tuple1 = find(&module, "tuple.1");
+    assert!(liveness.is_live(tuple1, &vec![0]));
+    assert!(!liveness.is_live(tuple1, &vec![1]));
+    assert!(!liveness.is_live(find(&module, "constant.2"), &vec![]));
+  }
+
+  #[test]
+  fn test_side_effecting_instruction_is_live() {
+    let module = parse_module(r#"
+HloModule SideEffect
+
+ENTRY entry {
+  p0 = f32[] parameter(0)
+  p1 = f32[] parameter(1)
+  rng = f32[4] rng(p0, p1), distribution=rng_uniform
+  ROOT constant.1 = f32[] constant(1)
+}
+"#);
+    let liveness = HloLivenessAnalysis::run(&module).unwrap();
+    assert!(liveness.is_live(find(&module, "rng"), &vec![]));
+    assert!(liveness.is_live(find(&module, "p0"), &vec![]));
+    assert!(liveness.is_live(find(&module, "p1"), &vec![]));
+  }
+}
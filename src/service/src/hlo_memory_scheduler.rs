@@ -46,9 +46,10 @@ impl HloTrivialScheduler {
     execution_threads: HashSet<String>) -> Result<bool, String>
   {
     let mut schedule = HloSchedule::new();
-    for computation in
+    for id in
       module.make_computation_post_order(&execution_threads, false)
     {
+      let computation = module.computation(id);
       if !computation.is_fusion_computation() {
         // TODO
         let _computation_sequence =
@@ -2,10 +2,41 @@
 
 use std::collections::HashSet;
 
-use hlo::hlo_module::HloModule;
+use common::{shape::Shape, shape_util::ShapeUtil};
+use hlo::{
+  hlo_computation::HloComputation,
+  hlo_instruction::{HloInstruction, InstructionId},
+  hlo_module::{ComputationId, HloModule},
+  hlo_opcode::HloOpcode
+};
+use crate::{call_graph::CallGraph, hlo_dce::HloDCE};
+
+use crate::hlo_liveness_analysis::HloLivenessAnalysis;
+
+// A while loop whose state has dead tuple elements.
+struct DeadWhileElements {
+  computation: ComputationId,
+  instruction: InstructionId,
+  // The indices of the tuple elements which stay in the loop state.
+  live_elements: Vec<usize>,
+}
+
+// A call whose callee has parameters without users.
+struct DeadCallParameters {
+  computation: ComputationId,
+  instruction: InstructionId,
+  // The indices of the operands which stay in the call.
+  live_operands: Vec<usize>,
+}
 
 // HLO pass which removes dead code from computations in the module using
 // HloModule-scoped analysis (HloLivenessAnalysis).
+//
+// Sweeps through live instructions which cross computation boundaries
+// (while and call), and removes code at dead shape indices: dead tuple
+// elements of while loop state are dropped from the loop, and unused
+// parameters of called computations are dropped from the callee and its
+// call. HloDCE then removes the dead code and computations left behind.
 pub struct HloModuleDCE {}
 
 impl HloModuleDCE {
@@ -19,9 +50,545 @@ impl HloModuleDCE {
 
   pub fn run(
     &self,
-    _module: &HloModule,
-    _execution_threads: &HashSet<String>) -> Result<bool, String>
+    module: &mut HloModule,
+    execution_threads: &HashSet<String>) -> Result<bool, String>
+  {
+    let mut hlo_dce = HloDCE::new(false);
+    hlo_dce.set_remove_dead_tuple_elements(true);
+    hlo_dce.run(module, execution_threads.clone())
+  }
+
+  // Removes the dead tuple elements of while loops and the unused parameters
+  // of called computations. The dead code left behind is not removed; this
+  // is left to HloDCE, which calls this in its tuple-aware mode.
+  pub fn remove_dead_code_across_computations(
+    &self,
+    module: &mut HloModule,
+    execution_threads: &HashSet<String>) -> Result<bool, String>
+  {
+    let dead_while_elements = {
+      let liveness = HloLivenessAnalysis::run(module)?;
+      HloModuleDCE::find_dead_while_elements(module, &liveness, execution_threads)
+    };
+    let mut changed = false;
+    for dead in &dead_while_elements {
+      HloModuleDCE::remove_dead_while_elements(module, dead)?;
+      changed = true;
+    }
+
+    let dead_call_parameters =
+      HloModuleDCE::find_dead_call_parameters(module, execution_threads);
+    for dead in &dead_call_parameters {
+      HloModuleDCE::remove_dead_call_parameters(module, dead)?;
+      changed = true;
+    }
+    Ok(changed)
+  }
+
+  // Finds the while loops with tuple elements which are not live. Only
+  // tuple-shaped loops whose body root is a tuple, whose parameters are read
+  // through get-tuple-element only, and whose computations are not shared
+  // with another caller are considered. Loops which could not be rewritten
+  // completely are skipped here, so that no rewrite fails half way.
+  fn find_dead_while_elements(
+    module: &HloModule,
+    liveness: &HloLivenessAnalysis,
+    execution_threads: &HashSet<String>) -> Vec<DeadWhileElements>
+  {
+    let call_graph = CallGraph::build(module, &HashSet::new());
+    let mut result = vec![];
+    for computation_id in module.computations_by_exec_threads(execution_threads) {
+      for instruction in module.computation(computation_id).instructions() {
+        if instruction.opcode() != HloOpcode::While || !instruction.shape().is_tuple() {
+          continue;
+        }
+        let body = module.computation(instruction.while_body());
+        let condition = module.computation(instruction.while_condition());
+        if body.root_instruction().opcode() != HloOpcode::Tuple ||
+          !parameter_is_only_read_by_gte(body) || !parameter_is_only_read_by_gte(condition)
+        {
+          continue;
+        }
+        let has_single_caller = |id: ComputationId| {
+          call_graph.get_node(id).caller_callsites().len() == 1
+        };
+        if !has_single_caller(instruction.while_body()) ||
+          !has_single_caller(instruction.while_condition())
+        {
+          continue;
+        }
+
+        let tuple_element_count = ShapeUtil::tuple_element_count(instruction.shape());
+        let live_elements: Vec<usize> = (0..tuple_element_count)
+          .filter(|i| liveness.is_live(instruction, &vec![*i as i64]))
+          .collect();
+        if live_elements.len() == tuple_element_count {
+          continue;
+        }
+        let init = module.computation(computation_id).operand(instruction.id(), 0);
+        if body.parameter_instruction(0).unwrap().shape() != instruction.shape() ||
+          condition.parameter_instruction(0).unwrap().shape() != instruction.shape() ||
+          init.shape() != instruction.shape()
+        {
+          continue;
+        }
+        let body_root = body.root_instruction();
+        let live_root_operands: Vec<InstructionId> = live_elements.iter()
+          .map(|i| body_root.operand(*i))
+          .collect();
+        if !dead_elements_are_unread(body, &live_root_operands, &live_elements) ||
+          !dead_elements_are_unread(condition, &[condition.root_instruction_id()],
+            &live_elements)
+        {
+          continue;
+        }
+        result.push(DeadWhileElements {
+          computation: computation_id,
+          instruction: instruction.id(),
+          live_elements: live_elements
+        });
+      }
+    }
+    result
+  }
+
+  // Rewrites the while loop to carry only its live tuple elements. Where the
+  // output of the old loop is used as a whole, it is recreated as a tuple,
+  // using the init values for the removed elements; no live code reads them.
+  fn remove_dead_while_elements(
+    module: &mut HloModule, dead: &DeadWhileElements) -> Result<(), String>
+  {
+    let live = &dead.live_elements;
+    let xla_while = module.computation(dead.computation).instruction(dead.instruction);
+    let body = xla_while.while_body();
+    let condition = xla_while.while_condition();
+    let init = xla_while.operand(0);
+    let tuple_element_count = ShapeUtil::tuple_element_count(xla_while.shape());
+    let new_shape = ShapeUtil::make_tuple_shape(live.iter()
+      .map(|i| xla_while.shape().tuple_shapes(*i).clone())
+      .collect());
+
+    // Make the body root forward only the live elements, and drop the code
+    // which computed the dead ones.
+    let body_computation = module.mutable_computation(body);
+    let root = body_computation.root_instruction_id();
+    let operands: Vec<InstructionId> = live.iter()
+      .map(|i| body_computation.instruction(root).operand(*i))
+      .collect();
+    let new_root = add_tuple(body_computation, &operands);
+    body_computation.set_root_instruction(new_root, true);
+    shrink_tuple_parameter(body_computation, live, &new_shape)?;
+    shrink_tuple_parameter(module.mutable_computation(condition), live, &new_shape)?;
+
+    let computation = module.mutable_computation(dead.computation);
+    let init_is_tuple = computation.instruction(init).opcode() == HloOpcode::Tuple;
+    let init_element = |computation: &mut HloComputation, i: usize| -> InstructionId {
+      if init_is_tuple {
+        return computation.instruction(init).operand(i);
+      }
+      let gte = HloInstruction::create_get_tuple_element(computation.instruction(init), i as i64);
+      computation.add_instruction(gte, "".to_string())
+    };
+    let new_init_operands: Vec<InstructionId> = live.iter()
+      .map(|i| init_element(computation, *i))
+      .collect();
+    let new_init = add_tuple(computation, &new_init_operands);
+    let new_while = HloInstruction::create_while(
+      &new_shape, condition, body, computation.instruction(new_init));
+    let new_while = computation.add_instruction(new_while, "".to_string());
+
+    // Get-tuple-elements of live elements read the new loop directly.
+    // Those of dead elements are dead themselves and left to HloDCE.
+    let mut needs_tuple = computation.is_root(dead.instruction);
+    for user in computation.instruction(dead.instruction).users().clone() {
+      let user_instruction = computation.instruction(user);
+      if user_instruction.opcode() != HloOpcode::GetTupleElement {
+        needs_tuple = true;
+        continue;
+      }
+      let tuple_index = user_instruction.tuple_index() as usize;
+      if let Some(new_index) = live.iter().position(|i| *i == tuple_index) {
+        let gte = HloInstruction::create_get_tuple_element(
+          computation.instruction(new_while), new_index as i64);
+        let gte = computation.add_instruction(gte, "".to_string());
+        computation.replace_instruction(user, gte, true, true, false)?;
+      }
+    }
+    if !needs_tuple {
+      return Ok(());
+    }
+
+    let mut elements = vec![];
+    for i in 0..tuple_element_count {
+      let element = match live.iter().position(|live_index| *live_index == i) {
+        Some(new_index) => {
+          let gte = HloInstruction::create_get_tuple_element(
+            computation.instruction(new_while), new_index as i64);
+          computation.add_instruction(gte, "".to_string())
+        },
+        None => init_element(computation, i)
+      };
+      elements.push(element);
+    }
+    let replacement = add_tuple(computation, &elements);
+    computation.replace_instruction(dead.instruction, replacement, true, true, true)?;
+    Ok(())
+  }
+
+  // Finds the calls whose callee has parameters without users. Callees
+  // called from more than one call site are skipped.
+  fn find_dead_call_parameters(
+    module: &HloModule, execution_threads: &HashSet<String>) -> Vec<DeadCallParameters>
   {
-    Ok(true)
+    let call_graph = CallGraph::build(module, &HashSet::new());
+    let mut result = vec![];
+    for computation_id in module.computations_by_exec_threads(execution_threads) {
+      for instruction in module.computation(computation_id).instructions() {
+        if instruction.opcode() != HloOpcode::Call {
+          continue;
+        }
+        let callee_id = instruction.called_computations()[0];
+        if call_graph.get_node(callee_id).caller_callsites().len() != 1 {
+          continue;
+        }
+        let callee = module.computation(callee_id);
+        let live_operands: Vec<usize> = (0..callee.num_parameters())
+          .filter(|i| {
+            let param = callee.parameter_instruction(*i).unwrap();
+            param.user_count() != 0 || callee.is_root(param.id())
+          })
+          .collect();
+        if live_operands.len() == callee.num_parameters() {
+          continue;
+        }
+        result.push(DeadCallParameters {
+          computation: computation_id,
+          instruction: instruction.id(),
+          live_operands: live_operands
+        });
+      }
+    }
+    result
+  }
+
+  // Removes the unused parameters of the callee and the operands passed to
+  // them by the call.
+  fn remove_dead_call_parameters(
+    module: &mut HloModule, dead: &DeadCallParameters) -> Result<(), String>
+  {
+    let call = module.computation(dead.computation).instruction(dead.instruction).clone();
+    let callee = call.called_computations()[0];
+    module.mutable_computation(callee).remove_unused_parameters_from_any_computation()?;
+
+    let computation = module.mutable_computation(dead.computation);
+    let new_call = {
+      let operands: Vec<&HloInstruction> = dead.live_operands.iter()
+        .map(|i| computation.operand(dead.instruction, *i))
+        .collect();
+      HloInstruction::create_call(call.shape(), &operands, callee)
+    };
+    let new_call = computation.add_instruction(new_call, "".to_string());
+    computation.replace_instruction(dead.instruction, new_call, true, true, true)?;
+    Ok(())
+  }
+}
+
+// Returns true if every user of parameter 0 of the computation is a
+// get-tuple-element.
+fn parameter_is_only_read_by_gte(computation: &HloComputation) -> bool {
+  match computation.parameter_instruction(0) {
+    Some(param) => !computation.is_root(param.id()) && param.users().iter()
+      .all(|user| computation.instruction(*user).opcode() == HloOpcode::GetTupleElement),
+    None => false
+  }
+}
+
+// Returns true if no instruction which stays after HloDCE, once only 'roots'
+// are used, reads a tuple element of parameter 0 other than the 'live' ones.
+// Instructions which HloDCE keeps without users are treated as roots too.
+fn dead_elements_are_unread(
+  computation: &HloComputation, roots: &[InstructionId], live: &[usize]) -> bool
+{
+  let param = computation.parameter_instruction(0).unwrap().id();
+  let mut worklist = roots.to_vec();
+  for instruction in computation.instructions() {
+    if instruction.opcode() != HloOpcode::Parameter &&
+      (instruction.has_side_effect() || !computation.is_safely_removable(instruction, false))
+    {
+      worklist.push(instruction.id());
+    }
+  }
+  let mut visited = HashSet::new();
+  while let Some(id) = worklist.pop() {
+    if !visited.insert(id) {
+      continue;
+    }
+    let instruction = computation.instruction(id);
+    if instruction.opcode() == HloOpcode::GetTupleElement && instruction.operand(0) == param &&
+      !live.contains(&(instruction.tuple_index() as usize))
+    {
+      return false;
+    }
+    worklist.extend(instruction.operands().iter());
+    worklist.extend(instruction.control_predecessors().iter());
+  }
+  true
+}
+
+// Adds a tuple of the given instructions to the computation.
+fn add_tuple(computation: &mut HloComputation, elements: &[InstructionId]) -> InstructionId {
+  let tuple = {
+    let elements: Vec<&HloInstruction> = elements.iter()
+      .map(|id| computation.instruction(*id))
+      .collect();
+    HloInstruction::create_tuple(&elements)
+  };
+  computation.add_instruction(tuple, "".to_string())
+}
+
+// Removes the dead code of the computation, then changes the shape of its
+// tuple parameter to 'new_shape', which holds only the 'live' elements, and
+// renumbers the get-tuple-elements which read it. The caller checks with
+// dead_elements_are_unread() that no dead element is read after HloDCE.
+fn shrink_tuple_parameter(
+  computation: &mut HloComputation, live: &[usize], new_shape: &Shape) -> Result<(), String>
+{
+  HloDCE::new(false).run_on_computation(computation, false)?;
+  let param = computation.parameter_instruction(0).unwrap().id();
+  for user in computation.instruction(param).users().clone() {
+    let tuple_index = computation.instruction(user).tuple_index() as usize;
+    match live.iter().position(|i| *i == tuple_index) {
+      Some(new_index) =>
+        computation.mutable_instruction(user).set_tuple_index(new_index as i64),
+      None => return Err(format!("{} reads dead tuple element {} of the loop state.",
+        computation.instruction(user).name(), tuple_index))
+    }
+  }
+  *computation.mutable_instruction(param).mutable_shape() = new_shape.clone();
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::test_utils::{parse_module};
+  use super::*;
+
+  fn has_instruction(module: &HloModule, name: &str) -> bool {
+    module.computations()
+      .flat_map(|computation| computation.instructions())
+      .any(|instruction| instruction.name() == name)
+  }
+
+  fn find_while(module: &HloModule) -> &HloInstruction {
+    module.entry_computation().unwrap().instructions()
+      .find(|instruction| instruction.opcode() == HloOpcode::While)
+      .unwrap()
+  }
+
+  const SIMPLE_LOOP: &str = r#"
+HloModule SimpleLoop
+
+SimpleLoop.body {
+  loop_var.1 = (s32[], s32[3]{0}) parameter(0)
+  get-tuple-element.1 = s32[] get-tuple-element(loop_var.1), index=0
+  constant.1 = s32[] constant(1)
+  add.0 = s32[] add(get-tuple-element.1, constant.1)
+  get-tuple-element.2 = s32[3]{0} get-tuple-element(loop_var.1), index=1
+  multiply.0 = s32[3]{0} multiply(get-tuple-element.2, get-tuple-element.2)
+  ROOT tuple.0 = (s32[], s32[3]{0}) tuple(add.0, multiply.0)
+}
+
+SimpleLoop.condition {
+  loop_var.2 = (s32[], s32[3]{0}) parameter(0)
+  get-tuple-element.3 = s32[] get-tuple-element(loop_var.2), index=0
+  constant.2 = s32[] constant(5)
+  ROOT less-than = pred[] compare(get-tuple-element.3, constant.2), direction=LT
+}
+
+ENTRY SimpleLoop {
+  constant.3 = s32[] constant(0)
+  constant.4 = s32[3]{0} constant({0, 1, 2})
+  tuple.1 = (s32[], s32[3]{0}) tuple(constant.3, constant.4)
+  while.0 = (s32[], s32[3]{0}) while(tuple.1), condition=SimpleLoop.condition, body=SimpleLoop.body
+  ROOT get-tuple-element.4 = GET_TUPLE_ELEMENT_SHAPE get-tuple-element(while.0), index=GET_TUPLE_ELEMENT_INDEX
+}
+"#;
+
+  #[test]
+  fn test_while_with_dead_tuple_element() {
+    let text = SIMPLE_LOOP.replace("GET_TUPLE_ELEMENT_SHAPE", "s32[]")
+      .replace("GET_TUPLE_ELEMENT_INDEX", "0");
+    let mut module = parse_module(&text);
+    assert!(HloModuleDCE::new().run(&mut module, &HashSet::new()).unwrap());
+
+    let xla_while = find_while(&module);
+    assert_eq!(ShapeUtil::tuple_element_count(xla_while.shape()), 1);
+    assert!(!has_instruction(&module, "multiply.0"));
+    assert!(!has_instruction(&module, "get-tuple-element.2"));
+    assert!(!has_instruction(&module, "constant.4"));
+    assert!(has_instruction(&module, "add.0"));
+
+    let body = module.computation(xla_while.while_body());
+    assert_eq!(body.root_instruction().operand_count(), 1);
+    assert_eq!(body.parameter_instruction(0).unwrap().shape(), xla_while.shape());
+    let entry_root = module.entry_computation().unwrap().root_instruction();
+    assert_eq!(entry_root.shape().to_string(false), "s32[]");
+  }
+
+  #[test]
+  fn test_while_with_live_tuple_elements() {
+    let text = SIMPLE_LOOP.replace("GET_TUPLE_ELEMENT_SHAPE", "s32[3]{0}")
+      .replace("GET_TUPLE_ELEMENT_INDEX", "1");
+    let mut module = parse_module(&text);
+    // Element 0 is read by the condition, element 1 by the entry root.
+    assert!(!HloModuleDCE::new().run(&mut module, &HashSet::new()).unwrap());
+    assert_eq!(ShapeUtil::tuple_element_count(find_while(&module).shape()), 2);
+    assert!(has_instruction(&module, "multiply.0"));
+  }
+
+  #[test]
+  fn test_side_effecting_custom_call_keeps_element_live() {
+    let mut module = parse_module(r#"
+HloModule SideEffectLoop
+
+body {
+  loop_var.1 = (s32[], s32[3]{0}) parameter(0)
+  get-tuple-element.1 = s32[] get-tuple-element(loop_var.1), index=0
+  constant.1 = s32[] constant(1)
+  add.0 = s32[] add(get-tuple-element.1, constant.1)
+  get-tuple-element.2 = s32[3]{0} get-tuple-element(loop_var.1), index=1
+  custom-call.0 = () custom-call(get-tuple-element.2), custom_call_target="log", custom_call_has_side_effect=true
+  ROOT tuple.0 = (s32[], s32[3]{0}) tuple(add.0, get-tuple-element.2)
+}
+
+condition {
+  loop_var.2 = (s32[], s32[3]{0}) parameter(0)
+  get-tuple-element.3 = s32[] get-tuple-element(loop_var.2), index=0
+  constant.2 = s32[] constant(5)
+  ROOT less-than = pred[] compare(get-tuple-element.3, constant.2), direction=LT
+}
+
+ENTRY entry {
+  constant.3 = s32[] constant(0)
+  constant.4 = s32[3]{0} constant({0, 1, 2})
+  tuple.1 = (s32[], s32[3]{0}) tuple(constant.3, constant.4)
+  while.0 = (s32[], s32[3]{0}) while(tuple.1), condition=condition, body=body
+  ROOT get-tuple-element.4 = s32[] get-tuple-element(while.0), index=0
+}
+"#);
+    HloModuleDCE::new().run(&mut module, &HashSet::new()).unwrap();
+    assert_eq!(ShapeUtil::tuple_element_count(find_while(&module).shape()), 2);
+    assert!(has_instruction(&module, "custom-call.0"));
+    assert!(has_instruction(&module, "constant.4"));
+  }
+
+  #[test]
+  fn test_side_effecting_custom_call_in_body_with_dead_element() {
+    let mut module = parse_module(r#"
+HloModule SideEffectLoop
+
+body {
+  loop_var.1 = (s32[], s32[3]{0}) parameter(0)
+  get-tuple-element.1 = s32[] get-tuple-element(loop_var.1), index=0
+  constant.1 = s32[] constant(1)
+  add.0 = s32[] add(get-tuple-element.1, constant.1)
+  custom-call.0 = () custom-call(add.0), custom_call_target="log", custom_call_has_side_effect=true
+  get-tuple-element.2 = s32[3]{0} get-tuple-element(loop_var.1), index=1
+  multiply.0 = s32[3]{0} multiply(get-tuple-element.2, get-tuple-element.2)
+  ROOT tuple.0 = (s32[], s32[3]{0}) tuple(add.0, multiply.0)
+}
+
+condition {
+  loop_var.2 = (s32[], s32[3]{0}) parameter(0)
+  get-tuple-element.3 = s32[] get-tuple-element(loop_var.2), index=0
+  constant.2 = s32[] constant(5)
+  ROOT less-than = pred[] compare(get-tuple-element.3, constant.2), direction=LT
+}
+
+ENTRY entry {
+  constant.3 = s32[] constant(0)
+  constant.4 = s32[3]{0} constant({0, 1, 2})
+  tuple.1 = (s32[], s32[3]{0}) tuple(constant.3, constant.4)
+  while.0 = (s32[], s32[3]{0}) while(tuple.1), condition=condition, body=body
+  ROOT get-tuple-element.4 = s32[] get-tuple-element(while.0), index=0
+}
+"#);
+    assert!(HloModuleDCE::new().run(&mut module, &HashSet::new()).unwrap());
+    // The custom-call is kept, the element it does not read is removed.
+    assert_eq!(ShapeUtil::tuple_element_count(find_while(&module).shape()), 1);
+    assert!(has_instruction(&module, "custom-call.0"));
+    assert!(!has_instruction(&module, "multiply.0"));
+    assert!(!has_instruction(&module, "constant.4"));
+  }
+
+  #[test]
+  fn test_dead_call_removes_callee() {
+    let mut module = parse_module(r#"
+HloModule DeadCallModule
+
+callee {
+  p0 = f32[] parameter(0)
+  ROOT negate = f32[] negate(p0)
+}
+
+ENTRY entry {
+  a = f32[] parameter(0)
+  call = f32[] call(a), to_apply=callee
+  ROOT exp = f32[] exponential(a)
+}
+"#);
+    assert!(HloModuleDCE::new().run(&mut module, &HashSet::new()).unwrap());
+    assert!(!has_instruction(&module, "call"));
+    assert!(module.get_computation_with_name("callee").is_none());
+  }
+
+  #[test]
+  fn test_hlo_dce_removes_dead_tuple_elements_only_when_set() {
+    let text = SIMPLE_LOOP.replace("GET_TUPLE_ELEMENT_SHAPE", "s32[]")
+      .replace("GET_TUPLE_ELEMENT_INDEX", "0");
+    let mut module = parse_module(&text);
+    HloDCE::new(false).run(&mut module, HashSet::new()).unwrap();
+    assert_eq!(ShapeUtil::tuple_element_count(find_while(&module).shape()), 2);
+    assert!(has_instruction(&module, "multiply.0"));
+
+    let mut hlo_dce = HloDCE::new(false);
+    hlo_dce.set_remove_dead_tuple_elements(true);
+    assert!(hlo_dce.run(&mut module, HashSet::new()).unwrap());
+    assert_eq!(ShapeUtil::tuple_element_count(find_while(&module).shape()), 1);
+    assert!(!has_instruction(&module, "multiply.0"));
+  }
+
+  #[test]
+  fn test_unused_call_parameter_removed() {
+    let mut module = parse_module(r#"
+HloModule CallModule
+
+callee {
+  p0 = f32[] parameter(0)
+  p1 = f32[] parameter(1)
+  ROOT negate = f32[] negate(p0)
+}
+
+unused {
+  x = f32[] parameter(0)
+  ROOT y = f32[] negate(x)
+}
+
+ENTRY entry {
+  a = f32[] parameter(0)
+  b = f32[] parameter(1)
+  exp = f32[] exponential(b)
+  ROOT call = f32[] call(a, exp), to_apply=callee
+}
+"#);
+    assert!(HloModuleDCE::new().run(&mut module, &HashSet::new()).unwrap());
+    let callee = module.get_computation_with_name("callee").unwrap();
+    assert_eq!(module.computation(callee).num_parameters(), 1);
+    let root = module.entry_computation().unwrap().root_instruction();
+    assert_eq!(root.opcode(), HloOpcode::Call);
+    assert_eq!(root.operand_count(), 1);
+    assert!(!has_instruction(&module, "exp"));
+    // Computations which are not called are removed as well.
+    assert!(module.get_computation_with_name("unused").is_none());
   }
-}
\ No newline at end of file
+}
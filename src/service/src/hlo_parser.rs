@@ -3,61 +3,117 @@
 use std::collections::HashMap;
 
 use common::{
-  blitz_data::{Algorithm, ConvolutionDimensionNumbers, FftType, FrontendAttributes, OpMetadata, OpShardingType, PaddingConfig, ParameterReplication, Precision, PrimitiveType, RandomAlgorithm, RandomDistribution, ReplicaGroup, StatisticsViz},
-    comparison_util::{string_to_comparison_direction, string_to_comparison_type, ComparisonDirection, ComparisonType}, layout::Layout, layout_util::LayoutUtil, literal::Literal, literal_util::LiteralUtil, shape::Shape, shape_util::ShapeUtil};
-use hlo::{hlo_computation::HloComputation, hlo_domain_metadata::DomainMetadata, hlo_instruction::{self, FusionKind, HloInstruction}, hlo_module::HloModule, hlo_module_config::HloModuleConfig, hlo_opcode::HloOpcode, hlo_sharding::HloSharding};
-use num::complex::Complex64;
+  array::Array,
+  blitz_data::{
+    Algorithm, CholeskyOptions, ConvolutionDimensionNumbers, CustomCallApiVersion,
+    CustomCallSchedule, DimLevelType, DotDimensionNumbers, FftType, FrontendAttributes,
+    GatherDimensionNumbers, OpMetadata, OpShardingType, PaddingConfig,
+    PaddingConfigDimension, PaddingType, Precision, PrecisionConfig, PrimitiveType,
+    RandomAlgorithm, RandomDistribution, ReplicaGroup, ScatterDimensionNummbers,
+    Statisitic, StatisticsViz, Transpose, TriangularSolveOptions, Window, WindowDimension
+  },
+  comparison_util::{
+    default_comparison_type, string_to_comparison_direction, string_to_comparison_type,
+    ComparisonDirection, ComparisonType
+  },
+  dyn_literal::{DynLiteral, Element},
+  layout::{Layout, Tile},
+  primitive_util,
+  shape::Shape,
+  shape_util::ShapeUtil
+};
+use hlo::{
+  hlo_computation::HloComputation,
+  hlo_domain_metadata::DomainMetadata,
+  hlo_input_output_alias_config::AliasKind,
+  hlo_instruction::{
+    string_to_algorithm, string_to_fusion_kind, string_to_precision,
+    string_to_random_algorithm, string_to_random_distribution, FusionKind, HloInstruction,
+    InstructionId
+  },
+  hlo_module::{ComputationId, HloModule},
+  hlo_module_config::HloModuleConfig,
+  hlo_opcode::{hlo_opcode_arity, hlo_opcode_string, string_to_hlo_opcode, HloOpcode},
+  hlo_schdule::{HloInstructionSequence, HloSchedule},
+  hlo_sharding::HloSharding,
+  tile_assignment::{IotaTileAssignment, TileAssignment}
+};
 
-use crate::hlo_lexer::{tok_kind_to_string, HloLexer, TokKind};
+use crate::hlo_lexer::{HloLexer, TokKind};
 
 // Given a string in the HloModule::to_string() format, parses the string and
 // creates a HloModule with the given config.
 pub fn parse_and_return_unverified_module(
-  _str: String, _config: &HloModuleConfig) -> Result<HloModule, String>
+  str: String, config: &HloModuleConfig) -> Result<HloModule, String>
 {
-  unimplemented!()
+  let mut module = HloModule::new("_".to_string(), config.clone());
+  let mut parser = HloParser::new(str);
+  parser.run(&mut module)?;
+  Ok(module)
 }
 
 // Parses sharding from str.
-pub fn parse_sharding(_str: String) -> Result<HloSharding, String> {
-  unimplemented!()
+pub fn parse_sharding(str: String) -> Result<HloSharding, String> {
+  let mut parser = HloParser::new(str);
+  parser.parse_sharding_only()
 }
 
 // Parses frontend attributes from str.
-pub fn parse_frontend_attributes(_str: String) -> Result<FrontendAttributes, String> {
-  unimplemented!()
+pub fn parse_frontend_attributes(str: String) -> Result<FrontendAttributes, String> {
+  let mut parser = HloParser::new(str);
+  parser.parse_frontend_attributes_only()
 }
 
 // Parses statistics viz from str.
-pub fn parse_statistics_viz(_str: String) -> Result<StatisticsViz, String> {
-  unimplemented!()
+pub fn parse_statistics_viz(str: String) -> Result<StatisticsViz, String> {
+  let mut parser = HloParser::new(str);
+  parser.parse_statistics_viz_only()
 }
 
 // Parses parameter replication from str.
-pub fn parse_parameter_replication(_str: String) -> Result<Vec<bool>, String> {
-  unimplemented!()
+pub fn parse_parameter_replication(str: String) -> Result<Vec<bool>, String> {
+  let mut parser = HloParser::new(str);
+  parser.parse_parameter_replication_only()
 }
 
 // Parses the result of window_util::to_string().
-pub fn parse_window(_str: String) {}
+pub fn parse_window(str: String) -> Result<Window, String> {
+  let mut parser = HloParser::new(str);
+  parser.parse_window_only()
+}
 
 // Parses the result of comvolution_dimension_numbers_to_string().
-pub fn parse_convolution_demension_numbers(_str: String) {}
+pub fn parse_convolution_demension_numbers(
+  str: String) -> Result<ConvolutionDimensionNumbers, String>
+{
+  let mut parser = HloParser::new(str);
+  parser.parse_convolution_dimension_numbers_only()
+}
 
 // Parses the result of padding_config_to_string().
-pub fn parse_padding_config(_str: String) {}
+pub fn parse_padding_config(str: String) -> Result<PaddingConfig, String> {
+  let mut parser = HloParser::new(str);
+  parser.parse_padding_config_only()
+}
 
 // Parses and returns a Shape::to_string-format string.
-pub fn parse_shape(_str: String) -> Result<Shape, String> {
-  unimplemented!()
+pub fn parse_shape(str: String) -> Result<Shape, String> {
+  let mut parser = HloParser::new(str);
+  parser.parse_shape_only()
 }
 
 // Parses and returns a Layout::to_string-format string.
-pub fn parse_layout(_str: String) -> Result<Layout, String> {
-  unimplemented!()
+pub fn parse_layout(str: String) -> Result<Layout, String> {
+  let mut parser = HloParser::new(str);
+  parser.parse_layout_only()
 }
 
-pub fn parse_replica_groups_only() {}
+// Parses replica groups, either as an explicit list like '{{0,1},{2,3}}' or
+// in the iota form '[2,2]<=[4]'.
+pub fn parse_replica_groups_only(str: String) -> Result<Vec<ReplicaGroup>, String> {
+  let mut parser = HloParser::new(str);
+  parser.parse_replica_groups_only()
+}
 
 fn can_infer_shape(code: HloOpcode) -> bool {
   match code {
@@ -145,15 +201,15 @@ fn can_infer_shape(code: HloOpcode) -> bool {
   }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 enum AttrType {
   Bool,
   Int64,
-  Int32,
   Float,
   String,
-  Literal,
   BracedInt64List,
   BracedInt64ListList,
+  ReplicaGroups,
   HloComputation,
   BracedHloComputationList,
   FftType,
@@ -177,7 +233,6 @@ enum AttrType {
   PrecisionList,
   Shape,
   ShapeList,
-  Enum,
   RandomAlgorithm,
   PrecisionAlgorithm,
   Aliasing,
@@ -186,22 +241,194 @@ enum AttrType {
   InstructionAliasing,
   CustomCallSchedule,
   CustomCallApiVersion,
-  SparsityDescriptor,
+  TriangularSolveTranspose,
   StringOrJsonDict,
 }
 
+// The parsed value of an attribute. There is one variant per AttrType.
+enum AttrValue {
+  Bool(bool),
+  Int64(i64),
+  Float(f64),
+  String(String),
+  BracedInt64List(Vec<i64>),
+  BracedInt64ListList(Vec<Vec<i64>>),
+  ReplicaGroups(Vec<ReplicaGroup>),
+  HloComputation(ComputationId),
+  BracedHloComputationList(Vec<ComputationId>),
+  FftType(FftType),
+  PaddingType(PaddingType),
+  ComparisonDirection(ComparisonDirection),
+  ComparisonType(ComparisonType),
+  Window(Window),
+  ConvolutionDimensionNumbers(ConvolutionDimensionNumbers),
+  Sharding(HloSharding),
+  FrontendAttributes(FrontendAttributes),
+  StatisticsViz(StatisticsViz),
+  BracedBoolListOrBool(Vec<bool>),
+  ParameterReplication(Vec<bool>),
+  InstructionList(Vec<InstructionId>),
+  SliceRanges(SliceRange),
+  PaddingConfig(PaddingConfig),
+  Metadata(OpMetadata),
+  FusionKind(FusionKind),
+  Distribution(RandomDistribution),
+  Domain(DomainData),
+  PrecisionList(Vec<Precision>),
+  Shape(Shape),
+  ShapeList(Vec<Shape>),
+  RandomAlgorithm(RandomAlgorithm),
+  PrecisionAlgorithm(Algorithm),
+  Aliasing(Vec<AliasEntry>),
+  BufferDonor(Vec<(i64, Vec<i64>)>),
+  ComputationLayout(Vec<Shape>, Shape),
+  InstructionAliasing(Vec<(Vec<i64>, (i64, Vec<i64>))>),
+  CustomCallSchedule(CustomCallSchedule),
+  CustomCallApiVersion(CustomCallApiVersion),
+  TriangularSolveTranspose(Transpose),
+}
+
 struct AttrConfig {
   required: bool,
   attr_type: AttrType,
-  result: String // TODO
 }
 
 impl AttrConfig {
-  pub fn new(required: bool, attr_type: AttrType, result: String) -> Self {
+  pub fn new(required: bool, attr_type: AttrType) -> Self {
     AttrConfig {
       required: required,
-      attr_type: attr_type,
-      result: result
+      attr_type: attr_type
+    }
+  }
+}
+
+// The attributes parsed for one instruction (or the module header), keyed by
+// attribute name. Values are taken out as they are consumed.
+struct Attributes {
+  values: HashMap<String, AttrValue>
+}
+
+impl Attributes {
+  fn new() -> Self {
+    Attributes { values: HashMap::new() }
+  }
+
+  fn contains(&self, name: &str) -> bool {
+    self.values.contains_key(name)
+  }
+
+  fn insert(&mut self, name: String, value: AttrValue) {
+    self.values.insert(name, value);
+  }
+
+  fn take(&mut self, name: &str) -> Option<AttrValue> {
+    self.values.remove(name)
+  }
+
+  fn take_bool(&mut self, name: &str) -> Option<bool> {
+    match self.take(name) {
+      Some(AttrValue::Bool(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_i64(&mut self, name: &str) -> Option<i64> {
+    match self.take(name) {
+      Some(AttrValue::Int64(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_f64(&mut self, name: &str) -> Option<f64> {
+    match self.take(name) {
+      Some(AttrValue::Float(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_string(&mut self, name: &str) -> Option<String> {
+    match self.take(name) {
+      Some(AttrValue::String(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_i64_list(&mut self, name: &str) -> Option<Vec<i64>> {
+    match self.take(name) {
+      Some(AttrValue::BracedInt64List(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_i64_list_list(&mut self, name: &str) -> Option<Vec<Vec<i64>>> {
+    match self.take(name) {
+      Some(AttrValue::BracedInt64ListList(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_replica_groups(&mut self, name: &str) -> Option<Vec<ReplicaGroup>> {
+    match self.take(name) {
+      Some(AttrValue::ReplicaGroups(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_computation(&mut self, name: &str) -> Option<ComputationId> {
+    match self.take(name) {
+      Some(AttrValue::HloComputation(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_computation_list(&mut self, name: &str) -> Option<Vec<ComputationId>> {
+    match self.take(name) {
+      Some(AttrValue::BracedHloComputationList(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_window(&mut self, name: &str) -> Option<Window> {
+    match self.take(name) {
+      Some(AttrValue::Window(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_convolution_dimension_numbers(
+    &mut self, name: &str) -> Option<ConvolutionDimensionNumbers>
+  {
+    match self.take(name) {
+      Some(AttrValue::ConvolutionDimensionNumbers(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_precision_list(&mut self, name: &str) -> Option<Vec<Precision>> {
+    match self.take(name) {
+      Some(AttrValue::PrecisionList(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_algorithm(&mut self, name: &str) -> Option<Algorithm> {
+    match self.take(name) {
+      Some(AttrValue::PrecisionAlgorithm(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_sharding(&mut self, name: &str) -> Option<HloSharding> {
+    match self.take(name) {
+      Some(AttrValue::Sharding(value)) => Some(value),
+      _ => None
+    }
+  }
+
+  fn take_shape(&mut self, name: &str) -> Option<Shape> {
+    match self.take(name) {
+      Some(AttrValue::Shape(value)) => Some(value),
+      _ => None
     }
   }
 }
@@ -217,642 +444,3751 @@ struct DomainData {
   exit_metadata: DomainMetadata,
 }
 
-struct Scope {}
+// One entry of the module's input_output_alias attribute:
+// (output index, parameter number, parameter index, kind).
+type AliasEntry = (Vec<i64>, i64, Vec<i64>, AliasKind);
+
+// Attributes accepted on every instruction.
+fn common_attribute_configs() -> HashMap<String, AttrConfig> {
+  let mut configs = HashMap::new();
+  configs.insert("sharding".to_string(),
+    AttrConfig::new(false, AttrType::Sharding));
+  configs.insert("frontend_attributes".to_string(),
+    AttrConfig::new(false, AttrType::FrontendAttributes));
+  configs.insert("statistics".to_string(),
+    AttrConfig::new(false, AttrType::StatisticsViz));
+  configs.insert("metadata".to_string(),
+    AttrConfig::new(false, AttrType::Metadata));
+  configs.insert("backend_config".to_string(),
+    AttrConfig::new(false, AttrType::StringOrJsonDict));
+  configs.insert("control-predecessors".to_string(),
+    AttrConfig::new(false, AttrType::InstructionList));
+  configs
+}
+
+// Splits a syntax sugared async opcode such as 'add-start' into the async
+// opcode and the opcode of the wrapped instruction.
+fn split_async_opcode(opcode_str: &String) -> Option<(HloOpcode, HloOpcode)> {
+  let suffixes = [
+    ("-start", HloOpcode::AsyncStart),
+    ("-update", HloOpcode::AsyncUpdate),
+    ("-done", HloOpcode::AsyncDone)
+  ];
+  for (suffix, opcode) in suffixes {
+    if let Some(wrapped) = opcode_str.strip_suffix(suffix) {
+      return match string_to_hlo_opcode(&wrapped.to_string()) {
+        Ok(wrapped_opcode) => Some((opcode, wrapped_opcode)),
+        Err(_) => None
+      };
+    }
+  }
+  None
+}
+
+// Attributes accepted on the HloModule header line.
+fn module_attribute_configs() -> HashMap<String, AttrConfig> {
+  let mut configs = HashMap::new();
+  configs.insert("is_scheduled".to_string(),
+    AttrConfig::new(false, AttrType::Bool));
+  configs.insert("entry_computation_layout".to_string(),
+    AttrConfig::new(false, AttrType::ComputationLayout));
+  configs.insert("replica_count".to_string(),
+    AttrConfig::new(false, AttrType::Int64));
+  configs.insert("num_partitions".to_string(),
+    AttrConfig::new(false, AttrType::Int64));
+  configs.insert("frontend_attributes".to_string(),
+    AttrConfig::new(false, AttrType::FrontendAttributes));
+  configs.insert("use_auto_spmd_partitioning".to_string(),
+    AttrConfig::new(false, AttrType::Bool));
+  configs.insert("input_output_alias".to_string(),
+    AttrConfig::new(false, AttrType::Aliasing));
+  configs.insert("buffer_donor".to_string(),
+    AttrConfig::new(false, AttrType::BufferDonor));
+  configs.insert("alias_passthrough_params".to_string(),
+    AttrConfig::new(false, AttrType::Bool));
+  configs.insert("allow_spmd_sharding_propagation_to_parameters".to_string(),
+    AttrConfig::new(false, AttrType::BracedBoolListOrBool));
+  configs.insert("allow_spmd_sharding_propagation_to_output".to_string(),
+    AttrConfig::new(false, AttrType::BracedBoolListOrBool));
+  configs.insert("spmd_output_sharding".to_string(),
+    AttrConfig::new(false, AttrType::Sharding));
+  configs.insert("spmd_parameters_shardings".to_string(),
+    AttrConfig::new(false, AttrType::Sharding));
+  configs
+}
+
+// Attributes accepted by the given opcode, on top of the common ones.
+fn instruction_attribute_configs(opcode: &HloOpcode) -> HashMap<String, AttrConfig> {
+  let mut configs = HashMap::new();
+  let mut add = |name: &str, required: bool, attr_type: AttrType| {
+    configs.insert(name.to_string(), AttrConfig::new(required, attr_type));
+  };
+  match opcode {
+    HloOpcode::Parameter => {
+      add("parameter_replication", false, AttrType::ParameterReplication);
+    },
+    HloOpcode::Iota => {
+      add("iota_dimension", true, AttrType::Int64);
+    },
+    HloOpcode::TopK => {
+      add("k", true, AttrType::Int64);
+      add("largest", false, AttrType::Bool);
+    },
+    HloOpcode::Compare => {
+      add("direction", true, AttrType::ComparisonDirection);
+      add("type", false, AttrType::ComparisonType);
+    },
+    HloOpcode::AllGather | HloOpcode::AllGatherStart => {
+      add("replica_groups", false, AttrType::ReplicaGroups);
+      add("channel_id", false, AttrType::Int64);
+      add("dimensions", true, AttrType::BracedInt64List);
+      add("constrain_layout", false, AttrType::Bool);
+      add("use_global_device_ids", false, AttrType::Bool);
+    },
+    HloOpcode::AllReduce | HloOpcode::AllReduceStart | HloOpcode::ReduceScatter => {
+      add("to_apply", true, AttrType::HloComputation);
+      add("replica_groups", false, AttrType::ReplicaGroups);
+      add("channel_id", false, AttrType::Int64);
+      add("constrain_layout", false, AttrType::Bool);
+      add("use_global_device_ids", false, AttrType::Bool);
+      if *opcode == HloOpcode::ReduceScatter {
+        add("dimensions", true, AttrType::BracedInt64List);
+      }
+    },
+    HloOpcode::AllToAll => {
+      add("replica_groups", false, AttrType::ReplicaGroups);
+      add("channel_id", false, AttrType::Int64);
+      add("constrain_layout", false, AttrType::Bool);
+      add("dimensions", false, AttrType::BracedInt64List);
+    },
+    HloOpcode::RaggedAllToAll | HloOpcode::CollectiveBroadcast => {
+      add("replica_groups", false, AttrType::ReplicaGroups);
+      add("channel_id", false, AttrType::Int64);
+    },
+    HloOpcode::CollectivePermute | HloOpcode::CollectivePermuteStart => {
+      add("source_target_pairs", true, AttrType::BracedInt64ListList);
+      add("channel_id", false, AttrType::Int64);
+    },
+    HloOpcode::Reshape => {
+      add("inferred_dimension", false, AttrType::Int64);
+    },
+    HloOpcode::Sort => {
+      add("dimensions", true, AttrType::BracedInt64List);
+      add("is_stable", false, AttrType::Bool);
+      add("to_apply", true, AttrType::HloComputation);
+    },
+    HloOpcode::Call => {
+      add("to_apply", true, AttrType::HloComputation);
+      add("is_composite", false, AttrType::Bool);
+    },
+    HloOpcode::Reduce => {
+      add("dimensions", true, AttrType::BracedInt64List);
+      add("to_apply", true, AttrType::HloComputation);
+    },
+    HloOpcode::ReduceWindow => {
+      add("window", false, AttrType::Window);
+      add("to_apply", true, AttrType::HloComputation);
+    },
+    HloOpcode::Convolution => {
+      add("window", false, AttrType::Window);
+      add("dim_labels", true, AttrType::ConvolutionDimensionNumbers);
+      add("feature_group_count", false, AttrType::Int64);
+      add("batch_group_count", false, AttrType::Int64);
+      add("operand_precision", false, AttrType::PrecisionList);
+      add("algorithm", false, AttrType::PrecisionAlgorithm);
+    },
+    HloOpcode::Fft => {
+      add("fft_type", true, AttrType::FftType);
+      add("fft_length", true, AttrType::BracedInt64List);
+    },
+    HloOpcode::TriangularSolve => {
+      add("left_side", false, AttrType::Bool);
+      add("lower", false, AttrType::Bool);
+      add("unit_diagonal", false, AttrType::Bool);
+      add("transpose_a", false, AttrType::TriangularSolveTranspose);
+    },
+    HloOpcode::Cholsky => {
+      add("lower", false, AttrType::Bool);
+    },
+    HloOpcode::BatchNormTraining | HloOpcode::BatchNormInference |
+    HloOpcode::BatchNormGrad => {
+      add("epsilon", true, AttrType::Float);
+      add("feature_index", true, AttrType::Int64);
+    },
+    HloOpcode::Broadcast | HloOpcode::Concatenate | HloOpcode::Reverse |
+    HloOpcode::Transpose | HloOpcode::GetDimensionSize |
+    HloOpcode::SetDimensionSize => {
+      add("dimensions", true, AttrType::BracedInt64List);
+    },
+    HloOpcode::Map => {
+      add("to_apply", true, AttrType::HloComputation);
+      add("dimensions", false, AttrType::BracedInt64List);
+    },
+    HloOpcode::Pad => {
+      add("padding", true, AttrType::PaddingConfig);
+    },
+    HloOpcode::Slice => {
+      add("slice", true, AttrType::SliceRanges);
+    },
+    HloOpcode::DynamicSlice => {
+      add("dynamic_slice_sizes", true, AttrType::BracedInt64List);
+    },
+    HloOpcode::SelectAndScatter => {
+      add("select", true, AttrType::HloComputation);
+      add("scatter", true, AttrType::HloComputation);
+      add("window", false, AttrType::Window);
+    },
+    HloOpcode::While => {
+      add("condition", true, AttrType::HloComputation);
+      add("body", true, AttrType::HloComputation);
+    },
+    HloOpcode::Conditional => {
+      add("true_computation", false, AttrType::HloComputation);
+      add("false_computation", false, AttrType::HloComputation);
+      add("branch_computations", false, AttrType::BracedHloComputationList);
+    },
+    HloOpcode::Gather => {
+      add("offset_dims", true, AttrType::BracedInt64List);
+      add("collapsed_slice_dims", true, AttrType::BracedInt64List);
+      add("start_index_map", true, AttrType::BracedInt64List);
+      add("index_vector_dim", true, AttrType::Int64);
+      add("slice_sizes", true, AttrType::BracedInt64List);
+      add("indices_are_sorted", false, AttrType::Bool);
+      add("operand_batching_dims", false, AttrType::BracedInt64List);
+      add("start_indices_batching_dims", false, AttrType::BracedInt64List);
+    },
+    HloOpcode::Scatter => {
+      add("update_window_dims", true, AttrType::BracedInt64List);
+      add("inserted_window_dims", true, AttrType::BracedInt64List);
+      add("scatter_dims_to_operand_dims", true, AttrType::BracedInt64List);
+      add("index_vector_dim", true, AttrType::Int64);
+      add("to_apply", true, AttrType::HloComputation);
+      add("indices_are_sorted", false, AttrType::Bool);
+      add("unique_indices", false, AttrType::Bool);
+      add("input_batching_dims", false, AttrType::BracedInt64List);
+      add("scatter_indices_batching_dims", false, AttrType::BracedInt64List);
+    },
+    HloOpcode::Domain => {
+      add("domain", true, AttrType::Domain);
+    },
+    HloOpcode::Fusion => {
+      add("kind", true, AttrType::FusionKind);
+      add("calls", true, AttrType::HloComputation);
+    },
+    HloOpcode::CustomCall => {
+      add("custom_call_target", true, AttrType::String);
+      add("window", false, AttrType::Window);
+      add("dim_labels", false, AttrType::ConvolutionDimensionNumbers);
+      add("feature_group_count", false, AttrType::Int64);
+      add("batch_group_count", false, AttrType::Int64);
+      add("operand_layout_constraints", false, AttrType::ShapeList);
+      add("custom_call_has_side_effect", false, AttrType::Bool);
+      add("to_apply", false, AttrType::HloComputation);
+      add("called_computations", false, AttrType::BracedHloComputationList);
+      add("output_to_operand_aliasing", false, AttrType::InstructionAliasing);
+      add("schedule", false, AttrType::CustomCallSchedule);
+      add("api_version", false, AttrType::CustomCallApiVersion);
+      add("padding_type", false, AttrType::PaddingType);
+      add("operand_precision", false, AttrType::PrecisionList);
+    },
+    HloOpcode::Dot => {
+      add("lhs_contracting_dims", false, AttrType::BracedInt64List);
+      add("rhs_contracting_dims", false, AttrType::BracedInt64List);
+      add("lhs_batch_dims", false, AttrType::BracedInt64List);
+      add("rhs_batch_dims", false, AttrType::BracedInt64List);
+      add("operand_precision", false, AttrType::PrecisionList);
+      add("algorithm", false, AttrType::PrecisionAlgorithm);
+    },
+    HloOpcode::ReducePrecision => {
+      add("exponent_bits", true, AttrType::Int64);
+      add("mantissa_bits", true, AttrType::Int64);
+    },
+    HloOpcode::Rng => {
+      add("distribution", true, AttrType::Distribution);
+    },
+    HloOpcode::RngBitGenerator => {
+      add("algorithm", true, AttrType::RandomAlgorithm);
+    },
+    HloOpcode::RngGetAndUpdateState => {
+      add("delta", true, AttrType::Int64);
+    },
+    HloOpcode::Infeed => {
+      add("infeed_config", false, AttrType::String);
+    },
+    HloOpcode::Outfeed => {
+      add("outfeed_shape", false, AttrType::Shape);
+      add("outfeed_config", false, AttrType::String);
+    },
+    HloOpcode::Send | HloOpcode::Recv => {
+      add("channel_id", true, AttrType::Int64);
+      add("is_host_transfer", false, AttrType::Bool);
+    },
+    HloOpcode::SendDone | HloOpcode::RecvDone => {
+      add("channel_id", false, AttrType::Int64);
+      add("is_host_transfer", false, AttrType::Bool);
+    },
+    HloOpcode::GetTupleElement => {
+      add("index", true, AttrType::Int64);
+    },
+    HloOpcode::CopyStart => {
+      add("cross_program_prefetch_index", false, AttrType::Int64);
+    },
+    HloOpcode::AsyncStart => {
+      add("calls", true, AttrType::HloComputation);
+      add("async_execution_thread", false, AttrType::String);
+    },
+    HloOpcode::AsyncUpdate | HloOpcode::AsyncDone => {
+      add("calls", false, AttrType::HloComputation);
+      add("async_execution_thread", false, AttrType::String);
+    },
+    _ => {}
+  }
+  configs
+}
+
+// Returns the inclusive range of values representable by an integral type.
+fn integral_range(t: &PrimitiveType) -> Option<(i128, i128)> {
+  match t {
+    PrimitiveType::S4 => Some((-8, 7)),
+    PrimitiveType::S8 => Some((i8::MIN as i128, i8::MAX as i128)),
+    PrimitiveType::S16 => Some((i16::MIN as i128, i16::MAX as i128)),
+    PrimitiveType::S32 => Some((i32::MIN as i128, i32::MAX as i128)),
+    PrimitiveType::S64 => Some((i64::MIN as i128, i64::MAX as i128)),
+    PrimitiveType::U4 => Some((0, 15)),
+    PrimitiveType::U8 => Some((0, u8::MAX as i128)),
+    PrimitiveType::U16 => Some((0, u16::MAX as i128)),
+    PrimitiveType::U32 => Some((0, u32::MAX as i128)),
+    PrimitiveType::U64 => Some((0, u64::MAX as i128)),
+    _ => None
+  }
+}
+
+// Expands iota replica groups '[num_groups,group_size]<=[reshape_dims]T(perm)'
+// into explicit groups: the device ids 0..N are reshaped to 'reshape_dims',
+// transposed by 'perm' and then split into 'num_groups' groups.
+fn expand_iota_replica_groups(
+  num_groups: i64,
+  group_size: i64,
+  reshape_dims: &Vec<i64>,
+  perm: &Vec<i64>) -> Vec<ReplicaGroup>
+{
+  let rank = reshape_dims.len();
+  let mut strides = vec![1; rank];
+  for i in (0..rank.saturating_sub(1)).rev() {
+    strides[i] = strides[i + 1] * reshape_dims[i + 1];
+  }
+  let transposed_dims: Vec<i64> =
+    perm.iter().map(|p| reshape_dims[*p as usize]).collect();
+
+  let num_devices: i64 = reshape_dims.iter().product();
+  let mut devices = Vec::with_capacity(num_devices as usize);
+  let mut index = vec![0; rank];
+  for _ in 0..num_devices {
+    let device: i64 =
+      (0..rank).map(|k| index[k] * strides[perm[k] as usize]).sum();
+    devices.push(device);
+    for k in (0..rank).rev() {
+      index[k] += 1;
+      if index[k] < transposed_dims[k] { break; }
+      index[k] = 0;
+    }
+  }
+
+  let mut groups = vec![];
+  for g in 0..num_groups {
+    let mut group = ReplicaGroup::new();
+    let start = (g * group_size) as usize;
+    *group.mutable_replica_ids() =
+      devices[start..start + group_size as usize].to_vec();
+    groups.push(group);
+  }
+  groups
+}
+
+fn string_to_fft_type(name: &str) -> Result<FftType, String> {
+  match name {
+    "FFT" => Ok(FftType::FFT),
+    "IFFT" => Ok(FftType::IFFT),
+    "RFFT" => Ok(FftType::RFFT),
+    "IRFFT" => Ok(FftType::IRFFT),
+    _ => Err(format!("Unknown fft type: {}", name))
+  }
+}
+
+fn string_to_padding_type(name: &str) -> Result<PaddingType, String> {
+  match name {
+    "PADDING_INVALID" => Ok(PaddingType::Invalid),
+    "PADDING_VALID" => Ok(PaddingType::Valid),
+    "PADDING_SAME" => Ok(PaddingType::Same),
+    _ => Err(format!("Unknown padding type: {}", name))
+  }
+}
+
+fn string_to_custom_call_schedule(name: &str) -> Result<CustomCallSchedule, String> {
+  match name {
+    "SCHEDULE_NONE" => Ok(CustomCallSchedule::None),
+    "SCHEDULE_LATEST" => Ok(CustomCallSchedule::Latest),
+    "SCHEDULE_EARLIEST" => Ok(CustomCallSchedule::Earliest),
+    _ => Err(format!("Unknown custom call schedule: {}", name))
+  }
+}
+
+fn string_to_custom_call_api_version(name: &str) -> Result<CustomCallApiVersion, String> {
+  match name {
+    "API_VERSION_UNSPECIFIED" => Ok(CustomCallApiVersion::Unspecified),
+    "API_VERSION_ORIGINAL" => Ok(CustomCallApiVersion::Original),
+    "API_VERSION_STATUS_RETURNING" => Ok(CustomCallApiVersion::StatusReturning),
+    "API_VERSION_STATUS_RETURNING_UNIFIED" =>
+      Ok(CustomCallApiVersion::StatusReturningUnified),
+    "API_VERSION_TYPED_FFI" => Ok(CustomCallApiVersion::TypedFfi),
+    _ => Err(format!("Unknown custom call api version: {}", name))
+  }
+}
+
+fn string_to_transpose(name: &str) -> Result<Transpose, String> {
+  match name {
+    "NO_TRANSPOSE" => Ok(Transpose::NoTranspose),
+    "TRANSPOSE" => Ok(Transpose::Transpose),
+    "ADJOINT" => Ok(Transpose::Adjoint),
+    _ => Err(format!("Unknown transpose type: {}", name))
+  }
+}
+
+// Builds the precision config of a dot or convolution. Operands without an
+// explicit precision get the default one.
+fn make_precision_config(
+  precisions: Option<Vec<Precision>>,
+  algorithm: Option<Algorithm>,
+  num_operands: usize) -> PrecisionConfig
+{
+  let mut config = PrecisionConfig::new();
+  let precisions = precisions.unwrap_or(vec![Precision::Default; num_operands]);
+  for precision in precisions {
+    config.add_operand_precision(precision);
+  }
+  if let Some(algorithm) = algorithm {
+    config.set_algorithm(algorithm);
+  }
+  config
+}
+
 
 pub struct HloParser {
   lexer: HloLexer,
-  scoped_name_tables: Vec<HashMap<String, (HloInstruction, usize)>>,
-  computation_pool: HashMap<String, (HloComputation, usize)>,
-  computations: Vec<HloComputation>,
+  // Computations being parsed, innermost last. Instructions are added to the
+  // computation on top of the stack.
+  computation_stack: Vec<HloComputation>,
+  // Instruction names of the computations in 'computation_stack'.
+  scoped_name_tables: Vec<HashMap<String, InstructionId>>,
+  // The last top-level non-entry computation. It is added to the module when
+  // the next computation starts, or becomes the entry computation if the
+  // module has no ENTRY.
+  pending_computation: Option<HloComputation>,
+  computation_names: HashMap<String, ComputationId>,
+  entry_computation_parsed: bool,
   error: Vec<String>
 }
 
 impl HloParser {
-  pub fn new() {}
+  pub fn new(str: String) -> Self {
+    HloParser {
+      lexer: HloLexer::new(str),
+      computation_stack: Vec::new(),
+      scoped_name_tables: Vec::new(),
+      pending_computation: None,
+      computation_names: HashMap::new(),
+      entry_computation_parsed: false,
+      error: Vec::new()
+    }
+  }
 
   // Runs the parser and constructs the resulting HLO in the given (empty)
   // HloModule. Returns the error status in case an error occurred.
-  pub fn run(&mut self, module: &HloModule) -> Result<(), String> {
+  pub fn run(&mut self, module: &mut HloModule) -> Result<(), String> {
     self.lexer.lex();
     if self.lexer.get_kind() == TokKind::HloModule ||
        self.lexer.get_kind() == TokKind::Entry ||
-       self.lexer.look_ahead() == TokKind::Lbrace
+       self.lexer.look_ahead() == TokKind::Lbrace ||
+       self.lexer.look_ahead() == TokKind::Lparen
     {
-      // This means that the text contains a full HLLO module.
-      let mut parse_module_without_header = true;
-      if self.lexer.get_kind() == TokKind::HloModule {
-        parse_module_without_header = false;
-      }
+      // This means that the text contains a full HLO module.
+      let parse_module_without_header =
+        self.lexer.get_kind() != TokKind::HloModule;
       if !self.parse_hlo_module(module, parse_module_without_header) {
         let mut err_msg =
-          "Syntac error when trying to parse the text as a HloModule.".to_string();
+          "Syntax error when trying to parse the text as a HloModule:\n".to_string();
         err_msg.push_str(&self.get_error());
         return Err(err_msg);
       }
       return Ok(());
     }
-    if !self.parse_single_instruction(module) {
-      let mut err_msg = "Syntax error when trying to parse the text as a 
-        single HloInstruction:\n".to_string();
-      err_msg.push_str(&self.get_error());
-      return Err(err_msg);
-    }
-    Ok(())
+    let mut err_msg =
+      "Syntax error when trying to parse the text as a HloModule:\n".to_string();
+    self.token_error("expects HloModule, ENTRY or a computation");
+    err_msg.push_str(&self.get_error());
+    Err(err_msg)
   }
 
   // Returns the error information.
   pub fn get_error(&self) -> String {
-    let mut result = "".to_string();
-    for err_msg in &self.error {
-      result.push_str(&err_msg);
-    }
-    result
+    self.error.join("\n")
   }
 
   pub fn parse_shape_only(&mut self) -> Result<Shape, String> {
     self.lexer.lex();
     let mut shape = Shape::new();
     if !self.parse_shape(&mut shape) {
-      let mut error_msg = "Syntax error:\n".to_string();
-      error_msg.push_str(&self.get_error());
-      return Err(error_msg);
+      return Err(format!("Syntax error:\n{}", self.get_error()));
     }
     if self.lexer.get_kind() != TokKind::Eof {
-      let error_msg = "Syntax error:\nExtra content after shape".to_string();
-      return Err(error_msg);
+      return Err("Syntax error:\nExtra content after shape".to_string());
     }
     Ok(shape)
   }
 
   pub fn parse_layout_only(&mut self) -> Result<Layout, String> {
     self.lexer.lex();
-    let layout = Layout::new();
-    if !self.parse_layout(&layout) {
-      let mut error_msg = "Syntax error:\n".to_string();
-      error_msg.push_str(&self.get_error());
-      return Err(error_msg);
+    let mut layout = Layout::new();
+    if !self.parse_layout(&mut layout) {
+      return Err(format!("Syntax error:\n{}", self.get_error()));
     }
     if self.lexer.get_kind() != TokKind::Eof {
-      let error_msg = "Syntax error:\nExtra content after layout".to_string();
-      return Err(error_msg);
+      return Err("Syntax error:\nExtra content after layout".to_string());
     }
     Ok(layout)
   }
 
   pub fn parse_sharding_only(&mut self) -> Result<HloSharding, String> {
-    //self.lexer.lex();
-    unimplemented!()
+    self.lexer.lex();
+    let mut sharding = HloSharding::replicate(vec![]);
+    if !self.parse_sharding(&mut sharding) {
+      return Err(format!("Syntax error:\n{}", self.get_error()));
+    }
+    if self.lexer.get_kind() != TokKind::Eof {
+      return Err("Syntax error:\nExtra content after sharding".to_string());
+    }
+    Ok(sharding)
   }
 
   pub fn parse_frontend_attributes_only(&mut self) -> Result<FrontendAttributes, String> {
     self.lexer.lex();
-    let attributes = FrontendAttributes::new();
-    if !self.parse_frontend_attributes(&attributes) {
-      let mut error_msg = "Syntax error:\n".to_string();
-      error_msg.push_str(&self.get_error());
-      return Err(error_msg);
+    let mut attributes = FrontendAttributes::new();
+    if !self.parse_frontend_attributes(&mut attributes) {
+      return Err(format!("Syntax error:\n{}", self.get_error()));
     }
     if self.lexer.get_kind() != TokKind::Eof {
-      let error_msg =
-        "Syntax error:\nExtra content after frontend attributes".to_string();
-      return Err(error_msg);
+      return Err("Syntax error:\nExtra content after frontend attributes".to_string());
     }
     Ok(attributes)
   }
 
   pub fn parse_statistics_viz_only(&mut self) -> Result<StatisticsViz, String> {
     self.lexer.lex();
-    let statistics_viz = StatisticsViz::new();
-    if !self.parse_statistics_viz(&statistics_viz) {
-      let mut error_msg = "Syntax error:\n".to_string();
-      error_msg.push_str(&self.get_error());
-      return Err(error_msg);
+    let mut statistics_viz = StatisticsViz::new();
+    if !self.parse_statistics_viz(&mut statistics_viz) {
+      return Err(format!("Syntax error:\n{}", self.get_error()));
     }
     if self.lexer.get_kind() != TokKind::Eof {
-      let error_msg =
-        "Syntax error:\nExtra content after statistics".to_string();
-      return Err(error_msg);
+      return Err("Syntax error:\nExtra content after statistics".to_string());
     }
     Ok(statistics_viz)
   }
 
   pub fn parse_parameter_replication_only(&mut self) -> Result<Vec<bool>, String> {
     self.lexer.lex();
-    let mut parameter_replication = ParameterReplication::new();
-    if !self.parse_parameter_replication(&mut parameter_replication) {
-      let mut error_msg = "Syntax error:\n".to_string();
-      error_msg.push_str(&self.get_error());
-      return Err(error_msg);
+    let mut replication = vec![];
+    if !self.parse_parameter_replication(&mut replication) {
+      return Err(format!("Syntax error:\n{}", self.get_error()));
     }
     if self.lexer.get_kind() != TokKind::Eof {
-      let error_msg =
-        "Syntax error:\nExtra content after parameter replication".to_string();
-      return Err(error_msg);
-    }
-    let mut result = vec![];
-    for param_replicated in parameter_replication.replicated_at_leaf_buffers() {
-      result.push(*param_replicated)
+      return Err("Syntax error:\nExtra content after parameter replication".to_string());
     }
-    Ok(result)
+    Ok(replication)
   }
 
-  pub fn parse_boolean_list_or_single_boolean_only(&mut self) -> Result<Vec<bool>, String> {
+  pub fn parse_replica_groups_only(&mut self) -> Result<Vec<ReplicaGroup>, String> {
     self.lexer.lex();
-    let mut booleans = vec![];
-    if !self.parse_boolean_list_or_single_boolean(&mut booleans) {
-      let mut error_msg = "Syntax error:\n".to_string();
-      error_msg.push_str(&self.get_error());
-      return Err(error_msg);
+    let mut replica_groups = vec![];
+    if !self.parse_replica_groups(&mut replica_groups) {
+      return Err(format!("Syntax error:\n{}", self.get_error()));
     }
     if self.lexer.get_kind() != TokKind::Eof {
-      let error_msg =
-        "Syntax error:\nExtra content after boolean list".to_string();
-      return Err(error_msg);
+      return Err("Syntax error:\nExtra content after replica groups".to_string());
     }
-    Ok(booleans)
+    Ok(replica_groups)
   }
 
-  pub fn parse_window_only(&self) {}
+  pub fn parse_window_only(&mut self) -> Result<Window, String> {
+    self.lexer.lex();
+    let mut window = Window::new();
+    if !self.parse_window(&mut window, false) {
+      return Err(format!("Syntax error:\n{}", self.get_error()));
+    }
+    if self.lexer.get_kind() != TokKind::Eof {
+      return Err("Syntax error:\nExtra content after window".to_string());
+    }
+    Ok(window)
+  }
 
-  pub fn parse_convolution_demension_numbers_only(
+  pub fn parse_convolution_dimension_numbers_only(
     &mut self) -> Result<ConvolutionDimensionNumbers, String>
   {
     self.lexer.lex();
-    let dnums = ConvolutionDimensionNumbers::new();
-    if !self.parse_convolution_demension_numbers(&dnums) {
-      let mut error_msg = "Syntax error:\n".to_string();
-      error_msg.push_str(&self.get_error());
-      return Err(error_msg);
+    let mut dnums = ConvolutionDimensionNumbers::new();
+    if !self.parse_convolution_dimension_numbers(&mut dnums) {
+      return Err(format!("Syntax error:\n{}", self.get_error()));
     }
     if self.lexer.get_kind() != TokKind::Eof {
-      let error_msg =
-        "Syntax error:\nExtra content after convolution dnums".to_string();
-      return Err(error_msg);
+      return Err(
+        "Syntax error:\nExtra content after convolution dnums".to_string());
     }
     Ok(dnums)
   }
 
   pub fn parse_padding_config_only(&mut self) -> Result<PaddingConfig, String> {
     self.lexer.lex();
-    let padding_config = PaddingConfig::new();
-    if !self.parse_padding_config(&padding_config) {
-      let mut error_msg = "Syntax error:\n".to_string();
-      error_msg.push_str(&self.get_error());
-      return Err(error_msg);
+    let mut padding_config = PaddingConfig::new();
+    if !self.parse_padding_config(&mut padding_config) {
+      return Err(format!("Syntax error:\n{}", self.get_error()));
     }
     if self.lexer.get_kind() != TokKind::Eof {
-      let error_msg =
-        "Syntax error:\nExtra content after padding_config".to_string();
-      return Err(error_msg);
+      return Err("Syntax error:\nExtra content after PaddingConfig".to_string());
     }
     Ok(padding_config)
   }
 
-  pub fn parse_replica_groups_only(&self, _replica_groups: Vec<ReplicaGroup>) -> bool {
-    unimplemented!()
-  }
-
-  // Returns the map from the instruction name to the instruction itself and its
-  // location in the current scope.
-  fn current_name_table(&self) -> Option<&HashMap<String, (HloInstruction, usize)>> {
-    self.scoped_name_tables.last()
-  }
-
-  fn find_instruction(&self, _name: &String) -> Option<&(HloInstruction, usize)> {
-    unimplemented!()
-  }
-
-  fn parse_single_instruction(&self, _module: &HloModule) -> bool {
-    unimplemented!()
-  }
-
-  fn parse_hlo_module(&self, _module: &HloModule, _parse_module_without_header: bool) -> bool {
-    unimplemented!()
-  }
-
-  fn parse_computations() {}
-  fn parse_computation() {}
-  fn parse_instruction_list() {}
-  fn parse_instruction() {}
-  fn parse_instruction_rhs() {}
-  fn parse_control_predecessors() {}
-
-  // literal
-  //  ::= tuple
-  //  ::= non_tuple
-  fn parse_literal<T>(&mut self, literal: &mut Literal<T>, shape: &Shape) -> bool
-    where T: Clone + Default + PartialEq
+  // ::= 'HloModule' name computations
+  fn parse_hlo_module(
+    &mut self, module: &mut HloModule, parse_module_without_header: bool) -> bool
   {
-    if shape.is_tuple() {
-      self.parse_tuple_literal(literal, shape)
-    } else {
-      self.parse_non_tuple_literal(literal, shape)
+    let mut attrs = Attributes::new();
+    if !parse_module_without_header {
+      if self.lexer.get_kind() != TokKind::HloModule {
+        return self.token_error("expects HloModule");
+      }
+      // Eat 'HloModule'
+      self.lexer.lex();
+      let mut name = "".to_string();
+      if !self.parse_name(&mut name) {
+        return false;
+      }
+      module.set_name(name);
+      match self.parse_attributes(&module_attribute_configs(), module) {
+        Some(parsed) => attrs = parsed,
+        None => return false
+      }
     }
-  }
-
-  // tuple
-  //  ::= shape '(' literal_list ')'
-  // literal_list
-  //  ::= /*empty*/
-  //  ::= literal (',' literal)*
-  fn parse_tuple_literal<T>(&mut self, literal: &mut Literal<T>, shape: &Shape) -> bool
-    where T: Clone + Default + PartialEq
-  {
-    if self.parse_token(&TokKind::Lparen,
-        "expects '(' in front of tuple elements".to_string())
-    {
+    if !self.parse_computations(module) {
       return false;
     }
 
-    let element_count = ShapeUtil::tuple_element_count(shape);
-    let mut elements: Vec<Literal<T>> = Vec::new();
-    elements.reserve(element_count);
-    if self.lexer.get_kind() == TokKind::Rparen {
-      // empty
-    } else {
-      for i in 0..element_count {
-        if i > 0 {
-          self.parse_token(&TokKind::Comma,
-            "expects ',' to separate tuple elements".to_string());
-        }
-        if !self.parse_literal(&mut elements[i],
-            ShapeUtil::get_tuple_element_shape(shape, i))
-        {
-          let mut err_msg = "expects the ".to_string();
-          err_msg.push_str(&i.to_string());
-          err_msg.push_str("th element");
-          return self.token_error(err_msg);
+    if let Some(replica_count) = attrs.take_i64("replica_count") {
+      module.mutable_config().set_replica_count(replica_count);
+    }
+    if let Some(num_partitions) = attrs.take_i64("num_partitions") {
+      module.mutable_config().set_num_partitions(num_partitions);
+    }
+    if let Some(AttrValue::FrontendAttributes(attributes)) =
+      attrs.take("frontend_attributes")
+    {
+      module.set_frontend_attributes(attributes);
+    }
+    if let Some(use_auto_spmd_partitioning) =
+      attrs.take_bool("use_auto_spmd_partitioning")
+    {
+      module.set_use_auto_spmd_partitioning(use_auto_spmd_partitioning);
+      module.mutable_config()
+        .set_use_auto_spmd_partitioning(use_auto_spmd_partitioning);
+    }
+    if let Some(alias_passthrough_params) =
+      attrs.take_bool("alias_passthrough_params")
+    {
+      module.mutable_config().set_alias_passthrough_params(alias_passthrough_params);
+    }
+    if let Some(sharding) = attrs.take_sharding("spmd_output_sharding") {
+      module.set_spmd_output_sharding(sharding);
+    }
+    if let Some(sharding) = attrs.take_sharding("spmd_parameters_shardings") {
+      if sharding.is_tuple() {
+        module.set_spmd_parameters_shardings(sharding.tuple_elements().clone());
+      } else {
+        module.set_spmd_parameters_shardings(vec![sharding]);
+      }
+    }
+    if let Some(AttrValue::Aliasing(aliasing)) = attrs.take("input_output_alias") {
+      for (output_index, param_number, param_index, kind) in aliasing {
+        let result = module.mutable_input_output_alias_config()
+          .setup_alias(&output_index, param_number, &param_index, kind);
+        if let Err(msg) = result {
+          return self.token_error(&msg);
         }
       }
     }
-    *literal = LiteralUtil::make_tuple_owned(elements);
-
-    self.parse_token(&TokKind::Rparen,
-      "expects ')' at the end of the tuple with elements".to_string())
+    // TODO: buffer_donor, entry_computation_layout and the
+    // allow_spmd_sharding_propagation_* flags are validated by the parser but
+    // not recorded, as HloModule and HloModuleConfig cannot hold them yet.
+    if attrs.take_bool("is_scheduled") == Some(true) {
+      self.create_default_schedule(module);
+    }
+    true
   }
 
-  // non_tuple
-  //   ::= rank01
-  //   ::= rank2345
-  // rank2345 ::= shape nested_array
-  fn parse_non_tuple_literal<T>(&mut self, literal: &Literal<T>, shape: &Shape) -> bool
-    where T: Clone + Default + PartialEq
-  {
-    debug_assert!(LayoutUtil::is_dense_array(shape));
-    self.parse_dense_literal(literal, shape)
+  // Schedules every non-fusion computation in the order its instructions
+  // were parsed, which is the order a scheduled module is printed in.
+  fn create_default_schedule(&mut self, module: &mut HloModule) {
+    let mut schedule = HloSchedule::new();
+    for id in module.make_nonfusion_computations_default() {
+      let computation = module.computation(id);
+      let mut sequence = HloInstructionSequence::new();
+      for instruction in computation.instructions() {
+        sequence.push_pack(instruction.clone());
+      }
+      schedule.set_sequence(computation, sequence);
+    }
+    module.set_schedule(schedule);
   }
 
-  fn parse_dense_literal<T>(&mut self, _literal: &Literal<T>, _shape: &Shape) -> bool
-    where T: Clone + Default + PartialEq
-  {
-    unimplemented!()
+  // computations ::= (computation)+
+  fn parse_computations(&mut self, module: &mut HloModule) -> bool {
+    loop {
+      if !self.parse_computation(module) {
+        return false;
+      }
+      if self.lexer.get_kind() == TokKind::Eof {
+        break;
+      }
+    }
+    if let Some(computation) = self.pending_computation.take() {
+      let name = computation.name();
+      // If no computation is marked as ENTRY, the last one is the entry.
+      let id = if self.entry_computation_parsed {
+        module.add_embedded_computation(computation)
+      } else {
+        self.entry_computation_parsed = true;
+        module.add_entry_computation(computation)
+      };
+      self.computation_names.insert(name, id);
+    }
+    if !self.entry_computation_parsed {
+      return self.token_error("expects at least one computation");
+    }
+    true
   }
 
-  fn create_instruction() {}
-
-  fn set_value_in_literal() {}
-  fn set_value_in_literal_helper() {}
-
-  fn check_parsed_value_is_in_range() {}
-  fn parse_operands() {}
-
-  fn parse_attributes() {}
-  fn parse_sub_attributes() {}
-  fn parse_attribute_helper() {}
-
-  fn copy_attribute_to_proto_message() {}
-
-  fn parse_attributes_as_proto_message() {}
-
-  fn parse_computation_name(&mut self, value: &mut HloComputation) -> bool {
+  // computation ::= ('ENTRY')? name (param_list_to_shape)? instruction_list
+  //   (',' 'execution_thread=' string)?
+  fn parse_computation(&mut self, module: &mut HloModule) -> bool {
+    let is_entry_computation = self.eat_if_present(TokKind::Entry);
+    let name_loc = self.lexer.get_loc();
     let mut name = "".to_string();
-    let loc = self.lexer.get_loc();
     if !self.parse_name(&mut name) {
-      return self.error(loc, "expects computation name".to_string());
+      return false;
+    }
+    if self.computation_names.contains_key(&name) ||
+       self.pending_computation.as_ref().map_or(false, |c| c.name() == name)
+    {
+      return self.error(name_loc,
+        &format!("computation previously defined here: {}", name));
     }
-    let computation = self.computation_pool.get(&name);
-    if computation.is_none() {
-      let mut err_msg = "computation does not exist: ".to_string();
-      err_msg.push_str(&name);
-      return self.error(loc, err_msg);
+
+    let mut result_shape = None;
+    if self.lexer.get_kind() != TokKind::Lbrace {
+      // The computation may have a signature, used to check the root shape.
+      let mut shape = Shape::new();
+      if !self.parse_param_list_to_shape(&mut shape) {
+        return false;
+      }
+      result_shape = Some(shape);
     }
-    *value = computation.unwrap().0.clone();
-    true
-  }
 
-  // '{' name+ '}'
-  fn parse_instruction_names(&mut self, instructions: &mut Vec<HloInstruction>) -> bool {
-    if self.parse_token(&TokKind::Lbrace,
-      "expects '{' at the beginning of instruction name list".to_string())
-    {
+    // The previous computation is complete: add it to the module so that
+    // this one can refer to it.
+    if let Some(computation) = self.pending_computation.take() {
+      let pending_name = computation.name();
+      let id = module.add_embedded_computation(computation);
+      self.computation_names.insert(pending_name, id);
+    }
+
+    let mut computation = HloComputation::new(name.clone());
+    if !self.parse_computation_body(module, &mut computation) {
       return false;
     }
-    let loc = self.lexer.get_loc();
-    loop {
-      let mut name = "".to_string();
-      if !self.parse_name(&mut name) {
-        return self.error(loc, "expects a instruction name".to_string());
-      }
-      let instr = self.find_instruction(&name);
-      if instr.is_none() {
-        let mut err_msg = "instruction ".to_string();
-        err_msg.push_str(&name);
-        err_msg.push_str(" is not defined");
-        return self.token_error(err_msg);
+    if let Some(shape) = result_shape {
+      let root_shape = computation.root_instruction().shape();
+      if !ShapeUtil::compatible(root_shape, &shape) {
+        let msg = format!(
+          "Shape of computation {}, {}, is not compatible with that of its root instruction {}, {}",
+          name, ShapeUtil::human_string(&shape),
+          computation.root_instruction().name(), ShapeUtil::human_string(root_shape));
+        return self.error(name_loc, &msg);
       }
-      instructions.push(instr.unwrap().0.clone());
-      if !self.eat_if_present(&TokKind::Comma) { break; }
     }
 
-    self.parse_token(&TokKind::Rbrace,
-      "expects '}' at the end of instruction name list".to_string())
-  }
-
-  fn parse_window() {}
+    if self.eat_if_present(TokKind::Comma) {
+      let mut attribute_name = "".to_string();
+      if !self.parse_attribute_name(&mut attribute_name) {
+        return false;
+      }
+      if attribute_name != "execution_thread" {
+        return self.token_error(
+          &format!("expects execution_thread, but sees {}", attribute_name));
+      }
+      let mut execution_thread = "".to_string();
+      if !self.parse_string(&mut execution_thread) {
+        return false;
+      }
+      computation.set_execution_thread(execution_thread);
+    }
 
-  fn parse_convolution_demension_numbers(&mut self, _dnums: &ConvolutionDimensionNumbers) -> bool {
-    unimplemented!()
+    if is_entry_computation {
+      if self.entry_computation_parsed {
+        return self.error(name_loc, "expects only one ENTRY");
+      }
+      self.entry_computation_parsed = true;
+      let id = module.add_entry_computation(computation);
+      self.computation_names.insert(name, id);
+    } else {
+      self.pending_computation = Some(computation);
+    }
+    true
   }
 
-  fn parse_padding_config(&mut self, _padding_config: &PaddingConfig) -> bool {
-    unimplemented!()
+  // Parses an instruction list into 'computation'.
+  fn parse_computation_body(
+    &mut self, module: &mut HloModule, computation: &mut HloComputation) -> bool
+  {
+    self.computation_stack.push(std::mem::replace(
+      computation, HloComputation::new("".to_string())));
+    self.scoped_name_tables.push(HashMap::new());
+    let result = self.parse_instruction_list(module);
+    self.scoped_name_tables.pop();
+    *computation = self.computation_stack.pop().unwrap();
+    result
   }
 
-  // '{' metadata_string '}'
-  fn parse_metadata(&mut self, _metadata: &OpMetadata) -> bool {
-    let mut attrs: HashMap<String, AttrConfig> = HashMap::new();
-
-    let op_type = String::new();
-    let op_name = String::new();
-    let source_file = String::new();
-    let source_line = 0;
-    //let profile_type = vec![];
-    let _deduplicated_name = String::new();
-    let _preserve_layout = false;
-
-    attrs.insert("op_type".to_string(), 
-      AttrConfig::new(false, AttrType::String, op_type.clone()));
-    attrs.insert("op_name".to_string(), 
-      AttrConfig::new(false, AttrType::String, op_name.clone()));
-    attrs.insert("source_file".to_string(), 
-      AttrConfig::new(false, AttrType::String, source_file.clone()));
-    attrs.insert("source_line".to_string(), 
-      AttrConfig::new(false, AttrType::Int32, source_line.to_string()));
-    //attrs.insert("profile_type".to_string(), 
-      //AttrConfig::new(false, AttrType::BracedInt64List, profile_type));
-
-    // TODO 
-
+  // Parses a computation written inline in an attribute, e.g.
+  // 'to_apply={ ... }', and adds it to the module.
+  fn parse_inline_computation(
+    &mut self, module: &mut HloModule, result: &mut ComputationId) -> bool
+  {
+    let name = format!("computation.{}", module.computation_count());
+    let mut computation = HloComputation::new(name.clone());
+    if !self.parse_computation_body(module, &mut computation) {
+      return false;
+    }
+    *result = module.add_embedded_computation(computation);
+    self.computation_names.insert(name, *result);
     true
   }
 
-  // ::= single_metadata | ('{' [single_metadata (',' single_metadata)*] '}')
-  fn parse_single_or_list_metadata(&mut self, _metadata: Vec<OpMetadata>) -> bool {
-    if self.lexer.get_kind() == TokKind::Lbrace && self.lexer.look_ahead() == TokKind::Lbrace {
-      if !self.parse_token(&TokKind::Lbrace,
-        "expected '{' to start metadata lsit".to_string())
-      {
-        return false;
+  // instruction_list ::= '{' instruction_list1 '}'
+  // instruction_list1 ::= (instruction)+
+  fn parse_instruction_list(&mut self, module: &mut HloModule) -> bool {
+    if !self.parse_token(TokKind::Lbrace,
+      "expects '{' at the beginning of instruction list.")
+    {
+      return false;
+    }
+    let mut root = None;
+    let mut last = None;
+    loop {
+      if self.lexer.get_kind() == TokKind::Rbrace {
+        break;
       }
-
-      if self.lexer.get_kind() != TokKind::Rbrace {
-        // TODO
+      if !self.parse_instruction(module, &mut root, &mut last) {
+        return false;
       }
-
-      return self.parse_token(&TokKind::Rbrace,
-        "expected '}' to end metadata list".to_string());
     }
-
-    true // TODO
+    let rbrace_loc = self.lexer.get_loc();
+    if !self.parse_token(TokKind::Rbrace,
+      "expects '}' at the end of instruction list.")
+    {
+      return false;
+    }
+    // If no ROOT is given, the last instruction is the root.
+    match root.or(last) {
+      Some(root) => {
+        self.computation_stack.last_mut().unwrap().set_root_instruction(root, true);
+        true
+      },
+      None => self.error(rbrace_loc, "expects at least one instruction")
+    }
   }
 
-  fn parse_op_sharding_type(&mut self, t: &mut OpShardingType) -> bool {
+  // instruction ::= ('ROOT')? name '=' shape opcode operands (attribute)*
+  fn parse_instruction(
+    &mut self,
+    module: &mut HloModule,
+    root: &mut Option<InstructionId>,
+    last: &mut Option<InstructionId>) -> bool
+  {
+    let is_root = self.eat_if_present(TokKind::Root);
+    let name_loc = self.lexer.get_loc();
+    let name;
     match self.lexer.get_kind() {
-      TokKind::Maximal => {
-        *t = OpShardingType::Maximal;
+      TokKind::Name | TokKind::Ident => {
+        name = self.lexer.get_str_val();
         self.lexer.lex();
-        return true;
-      },
-      TokKind::Replicated => {
-        *t = OpShardingType::Replicated;
-        self.lexer.lex();
-        return true;
+        if !self.parse_token(TokKind::Equal, "expects '=' in instruction") {
+          return false;
+        }
       },
-      TokKind::Manual => {
-        *t = OpShardingType::Manual;
+      // 'name=' is lexed as a single attribute name token.
+      TokKind::AttributeName => {
+        name = self.lexer.get_str_val();
         self.lexer.lex();
-        return true;
       },
-      _ => return false
+      _ => return self.token_error("expects instruction name")
+    }
+    if self.scoped_name_tables.last().unwrap().contains_key(&name) {
+      return self.error(name_loc,
+        &format!("instruction previously defined: {}", name));
     }
-  }
 
-  fn parse_list_sharding_type(&mut self, types: &mut Vec<OpShardingType>) -> bool {
-    if !self.parse_token(&TokKind::Lbrace,
-      "expected '{' to start sharding type list".to_string())
-    {
+    let mut shape = Shape::new();
+    if !self.parse_shape(&mut shape) {
       return false;
     }
-    if self.lexer.get_kind() != TokKind::Rbrace {
-      loop {
-        let mut t = OpShardingType::Unknown;
-        if !self.parse_op_sharding_type(&mut t) { return false; }
-        types.push(t); // check
-        if !self.eat_if_present(&TokKind::Comma) { break; }
-      }
+
+    let opcode_loc = self.lexer.get_loc();
+    if self.lexer.get_kind() != TokKind::Ident {
+      return self.token_error("expects opcode");
+    }
+    let opcode_str = self.lexer.get_str_val();
+    // Async ops wrapping a single instruction may be written in the syntax
+    // sugared form '<opcode>-start', '<opcode>-update' and '<opcode>-done'.
+    let (opcode, async_wrapped_opcode) = match string_to_hlo_opcode(&opcode_str) {
+      Ok(opcode) => (opcode, None),
+      Err(_) => match split_async_opcode(&opcode_str) {
+        Some((opcode, wrapped_opcode)) => (opcode, Some(wrapped_opcode)),
+        None => return self.token_error(
+          &format!("expects opcode but sees: {}", opcode_str))
+      }
+    };
+    self.lexer.lex();
+
+    let mut parameter_number = 0;
+    let mut literal = None;
+    let mut operand_ids = vec![];
+    match opcode {
+      HloOpcode::Parameter => {
+        if !self.parse_token(TokKind::Lparen,
+            "expects '(' before parameter number") ||
+           !self.parse_int64(&mut parameter_number) ||
+           !self.parse_token(TokKind::Rparen,
+            "expects ')' after parameter number")
+        {
+          return false;
+        }
+        if parameter_number < 0 {
+          return self.error(opcode_loc, "parameter number must be >= 0");
+        }
+      },
+      HloOpcode::Constant => {
+        if !self.parse_token(TokKind::Lparen,
+            "expects '(' before constant literal") ||
+           !self.parse_literal(&shape, &mut literal) ||
+           !self.parse_token(TokKind::Rparen,
+            "expects ')' after constant literal")
+        {
+          return false;
+        }
+      },
+      _ => {
+        if !self.parse_operands(&mut operand_ids) {
+          return false;
+        }
+      }
+    }
+
+    let mut configs = common_attribute_configs();
+    match &async_wrapped_opcode {
+      Some(wrapped_opcode) => {
+        // The attributes of the wrapped instruction are printed on every op
+        // of the chain, but only the start creates the instruction.
+        for (attr_name, mut config) in instruction_attribute_configs(wrapped_opcode) {
+          config.required = config.required && opcode == HloOpcode::AsyncStart;
+          configs.insert(attr_name, config);
+        }
+        configs.insert("async_execution_thread".to_string(),
+          AttrConfig::new(false, AttrType::String));
+      },
+      None => configs.extend(instruction_attribute_configs(&opcode))
+    }
+    let mut attrs = match self.parse_attributes(&configs, module) {
+      Some(attrs) => attrs,
+      None => return false
+    };
+
+    let created = match async_wrapped_opcode {
+      Some(wrapped_opcode) => self.create_syntax_sugar_async_instruction(
+        module, opcode.clone(), wrapped_opcode, &shape, &operand_ids, &mut attrs,
+        opcode_loc),
+      None => {
+        // The operands are borrowed from the computation, so take it off the
+        // stack while the instruction is created.
+        let computation = self.computation_stack.pop().unwrap();
+        let created = self.create_instruction(module, &computation,
+          opcode.clone(), &shape, &operand_ids, parameter_number, literal,
+          &name, &mut attrs, opcode_loc);
+        self.computation_stack.push(computation);
+        created
+      }
+    };
+    let mut instruction = match created {
+      Some(instruction) => instruction,
+      None => return false
+    };
+
+    instruction.set_name(name.clone());
+    if let Some(sharding) = attrs.take_sharding("sharding") {
+      instruction.set_sharding(sharding);
+    }
+    if let Some(AttrValue::FrontendAttributes(attributes)) =
+      attrs.take("frontend_attributes")
+    {
+      instruction.set_frontend_attributes(attributes);
+    }
+    if let Some(AttrValue::StatisticsViz(statistics)) = attrs.take("statistics") {
+      instruction.set_statistics_vis(statistics);
+    }
+    if let Some(AttrValue::Metadata(metadata)) = attrs.take("metadata") {
+      instruction.set_metadata(metadata);
+    }
+    if let Some(config) = attrs.take_string("backend_config") {
+      instruction.set_raw_backend_config_string(config);
+    }
+    let mut predecessors = vec![];
+    if let Some(AttrValue::InstructionList(ids)) = attrs.take("control-predecessors") {
+      predecessors = ids;
+    }
+
+    let computation = self.computation_stack.last_mut().unwrap();
+    let id = if opcode == HloOpcode::Parameter {
+      let duplicated = computation.instructions().any(|i| {
+        i.opcode() == HloOpcode::Parameter && i.parameter_number() == parameter_number
+      });
+      if duplicated {
+        return self.error(opcode_loc,
+          &format!("parameter {} is defined more than once", parameter_number));
+      }
+      computation.add_parameter(instruction)
+    } else {
+      computation.add_instruction(instruction, "".to_string())
+    };
+    for predecessor in predecessors {
+      let computation = self.computation_stack.last_mut().unwrap();
+      if let Err(msg) = computation.add_control_dependency_to(predecessor, id) {
+        return self.error(name_loc, &msg);
+      }
+    }
+    self.scoped_name_tables.last_mut().unwrap().insert(name, id);
+
+    if is_root {
+      if root.is_some() {
+        return self.error(name_loc, "one computation should have only one ROOT");
+      }
+      *root = Some(id);
+    }
+    *last = Some(id);
+    true
+  }
+
+  // operands ::= '(' operands1 ')'
+  // operands1 ::= /*empty*/ | operand (',' operand)*
+  // operand ::= (shape)? name
+  fn parse_operands(&mut self, operands: &mut Vec<InstructionId>) -> bool {
+    if !self.parse_token(TokKind::Lparen, "expects '(' at the beginning of operands") {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rparen {
+      loop {
+        // The operand may be preceded by its shape, which is redundant.
+        if self.lexer.get_kind() == TokKind::PrimitiveType ||
+           self.lexer.get_kind() == TokKind::Lparen
+        {
+          let mut shape = Shape::new();
+          if !self.parse_shape(&mut shape) {
+            return false;
+          }
+        }
+        let loc = self.lexer.get_loc();
+        let mut name = "".to_string();
+        if !self.parse_name(&mut name) {
+          return false;
+        }
+        match self.scoped_name_tables.last().unwrap().get(&name) {
+          Some(id) => operands.push(*id),
+          None => return self.error(loc,
+            &format!("instruction does not exist: {}", name))
+        }
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    self.parse_token(TokKind::Rparen, "expects ')' at the end of operands")
+  }
+
+  // param_list_to_shape ::= param_list '->' shape
+  // param_list ::= '(' param_list1 ')'
+  // param_list1 ::= /*empty*/ | param (',' param)*
+  // param ::= name shape
+  fn parse_param_list_to_shape(&mut self, result: &mut Shape) -> bool {
+    if !self.parse_token(TokKind::Lparen, "expects '(' at the beginning of param list") {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rparen {
+      loop {
+        let mut name = "".to_string();
+        if !self.parse_name(&mut name) {
+          return false;
+        }
+        // '%name:' lexes the colon separately.
+        self.eat_if_present(TokKind::Colon);
+        let mut shape = Shape::new();
+        if !self.parse_shape(&mut shape) {
+          return false;
+        }
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    if !self.parse_token(TokKind::Rparen, "expects ')' at the end of param list") ||
+       !self.parse_token(TokKind::Arrow, "expects '->'")
+    {
+      return false;
+    }
+    self.parse_shape(result)
+  }
+
+  // Creates the instruction for 'opcode' from its parsed operands and
+  // attributes. Common attributes are left in 'attrs' for the caller.
+  #[allow(clippy::too_many_arguments)]
+  fn create_instruction(
+    &mut self,
+    module: &mut HloModule,
+    computation: &HloComputation,
+    opcode: HloOpcode,
+    shape: &Shape,
+    operand_ids: &Vec<InstructionId>,
+    parameter_number: i64,
+    literal: Option<DynLiteral>,
+    name: &String,
+    attrs: &mut Attributes,
+    loc: usize) -> Option<HloInstruction>
+  {
+    let ops: Vec<&HloInstruction> =
+      operand_ids.iter().map(|id| computation.instruction(*id)).collect();
+    if let Some(arity) = hlo_opcode_arity(&opcode) {
+      if ops.len() != arity as usize {
+        self.error(loc, &format!("expects {} operands, but has {} operands",
+          arity, ops.len()));
+        return None;
+      }
+    }
+    let opcode_str = hlo_opcode_string(&opcode);
+    let expect_at_least = |parser: &mut HloParser, n: usize| -> bool {
+      if ops.len() < n {
+        parser.error(loc, &format!("{} expects at least {} operands, but has {}",
+          opcode_str, n, ops.len()));
+        return false;
+      }
+      true
+    };
+
+    let instruction = match opcode {
+      HloOpcode::Parameter => {
+        let mut instruction =
+          HloInstruction::create_parameter(parameter_number, shape, name.clone());
+        if let Some(AttrValue::ParameterReplication(replication)) =
+          attrs.take("parameter_replication")
+        {
+          instruction.set_parameter_replicated_at_leaf_buffers(replication);
+        }
+        instruction
+      },
+      HloOpcode::Constant => {
+        HloInstruction::create_constant(literal.unwrap())
+      },
+      HloOpcode::Iota => {
+        let iota_dimension = attrs.take_i64("iota_dimension").unwrap();
+        HloInstruction::create_iota(shape, iota_dimension)
+      },
+      HloOpcode::TopK => {
+        let k = attrs.take_i64("k").unwrap();
+        let largest = attrs.take_bool("largest").unwrap_or(true);
+        HloInstruction::create_top_k(shape, ops[0], k, largest)
+      },
+      // Unary ops.
+      HloOpcode::Abs | HloOpcode::AllGatherDone | HloOpcode::AllReduceDone |
+      HloOpcode::RoundNearestAfz | HloOpcode::RoundNearestEven |
+      HloOpcode::Cbrt | HloOpcode::Ceil | HloOpcode::Clz |
+      HloOpcode::CollectivePermuteDone | HloOpcode::Copy | HloOpcode::CopyDone |
+      HloOpcode::Cos | HloOpcode::Erf | HloOpcode::Exp | HloOpcode::Expm1 |
+      HloOpcode::Imag | HloOpcode::IsFinite | HloOpcode::Floor | HloOpcode::Log |
+      HloOpcode::Log1p | HloOpcode::Logistic | HloOpcode::Not | HloOpcode::Negate |
+      HloOpcode::OptimizationBarrier | HloOpcode::PopulationCount |
+      HloOpcode::Real | HloOpcode::Rsqrt | HloOpcode::Sign | HloOpcode::Sin |
+      HloOpcode::Sqrt | HloOpcode::Tan | HloOpcode::Tanh => {
+        HloInstruction::create_unary(shape, opcode, ops[0])
+      },
+      // Binary ops.
+      HloOpcode::Add | HloOpcode::Divide | HloOpcode::Multiply |
+      HloOpcode::Subtract | HloOpcode::Atan2 | HloOpcode::Complex |
+      HloOpcode::Maximum | HloOpcode::Minimum | HloOpcode::Power |
+      HloOpcode::Remainder | HloOpcode::And | HloOpcode::Or | HloOpcode::Xor |
+      HloOpcode::ShiftLeft | HloOpcode::ShiftRightArithmetic |
+      HloOpcode::ShiftRightLogical => {
+        HloInstruction::create_binary(shape, opcode, ops[0], ops[1])
+      },
+      // Ternary ops.
+      HloOpcode::Clamp | HloOpcode::Select => {
+        HloInstruction::create_ternary(shape, opcode, ops[0], ops[1], ops[2])
+      },
+      HloOpcode::Convert => HloInstruction::create_convert(shape, ops[0]),
+      HloOpcode::BitcastConvert => HloInstruction::create_bitcast_convert(shape, ops[0]),
+      HloOpcode::StochasticConvert => {
+        HloInstruction::create_stochastic_convert(shape, ops[0], ops[1])
+      },
+      HloOpcode::Bitcast => HloInstruction::create_bitcast(shape, ops[0]),
+      HloOpcode::Compare => {
+        let direction = match attrs.take("direction") {
+          Some(AttrValue::ComparisonDirection(direction)) => direction,
+          _ => unreachable!()
+        };
+        let comparison_type = match attrs.take("type") {
+          Some(AttrValue::ComparisonType(t)) => t,
+          _ => default_comparison_type(&ops[0].shape().element_type())
+        };
+        HloInstruction::create_compare(
+          shape, ops[0], ops[1], direction, comparison_type)
+      },
+      HloOpcode::AllGather | HloOpcode::AllGatherStart => {
+        let dimensions = attrs.take_i64_list("dimensions").unwrap();
+        if dimensions.len() != 1 {
+          self.error(loc, "expects exactly one all-gather dimension");
+          return None;
+        }
+        let replica_groups = attrs.take_replica_groups("replica_groups").unwrap_or_default();
+        let constrain_layout = attrs.take_bool("constrain_layout").unwrap_or(false);
+        let channel_id = attrs.take_i64("channel_id");
+        let use_global_device_ids =
+          attrs.take_bool("use_global_device_ids").unwrap_or(false);
+        if opcode == HloOpcode::AllGather {
+          HloInstruction::create_all_gather(shape, &ops, dimensions[0],
+            replica_groups, constrain_layout, channel_id, use_global_device_ids)
+        } else {
+          HloInstruction::create_all_gather_start(shape, &ops, dimensions[0],
+            replica_groups, constrain_layout, channel_id, use_global_device_ids)
+        }
+      },
+      HloOpcode::AllReduce | HloOpcode::AllReduceStart | HloOpcode::ReduceScatter => {
+        let to_apply = attrs.take_computation("to_apply").unwrap();
+        let replica_groups = attrs.take_replica_groups("replica_groups").unwrap_or_default();
+        let constrain_layout = attrs.take_bool("constrain_layout").unwrap_or(false);
+        let channel_id = attrs.take_i64("channel_id");
+        let use_global_device_ids =
+          attrs.take_bool("use_global_device_ids").unwrap_or(false);
+        match opcode {
+          HloOpcode::AllReduce => HloInstruction::create_all_reduce(shape, &ops,
+            to_apply, replica_groups, constrain_layout, channel_id, use_global_device_ids),
+          HloOpcode::AllReduceStart => HloInstruction::create_all_reduce_start(shape,
+            &ops, to_apply, replica_groups, constrain_layout, channel_id,
+            use_global_device_ids),
+          _ => {
+            let dimensions = attrs.take_i64_list("dimensions").unwrap();
+            if dimensions.len() != 1 {
+              self.error(loc, "expects exactly one scatter dimension");
+              return None;
+            }
+            HloInstruction::create_reduce_scatter(shape, &ops, to_apply,
+              replica_groups, constrain_layout, channel_id, use_global_device_ids,
+              dimensions[0])
+          }
+        }
+      },
+      HloOpcode::AllToAll => {
+        let replica_groups = attrs.take_replica_groups("replica_groups").unwrap_or_default();
+        let constrain_layout = attrs.take_bool("constrain_layout").unwrap_or(false);
+        let channel_id = attrs.take_i64("channel_id");
+        let mut split_dimension = None;
+        if let Some(dimensions) = attrs.take_i64_list("dimensions") {
+          if dimensions.len() != 1 {
+            self.error(loc,
+              "expects exactly one split dimension for all-to-all");
+            return None;
+          }
+          split_dimension = Some(dimensions[0]);
+        }
+        HloInstruction::create_all_to_all(shape, &ops, replica_groups,
+          constrain_layout, channel_id, split_dimension)
+      },
+      HloOpcode::RaggedAllToAll => {
+        let mut instruction = HloInstruction::create_variadic(shape, opcode, &ops);
+        for group in attrs.take_replica_groups("replica_groups").unwrap_or_default() {
+          instruction.add_replica_groups(group);
+        }
+        if let Some(channel_id) = attrs.take_i64("channel_id") {
+          instruction.set_channel_id(channel_id);
+        }
+        instruction
+      },
+      HloOpcode::CollectiveBroadcast => {
+        let replica_groups = attrs.take_replica_groups("replica_groups").unwrap_or_default();
+        let channel_id = attrs.take_i64("channel_id");
+        HloInstruction::create_collective_broadcast(
+          shape, &ops, replica_groups, false, channel_id)
+      },
+      HloOpcode::CollectivePermute | HloOpcode::CollectivePermuteStart => {
+        let mut pairs = vec![];
+        for pair in attrs.take_i64_list_list("source_target_pairs").unwrap() {
+          if pair.len() != 2 {
+            self.error(loc, "expects 'source_target_pairs=' to be a list of pairs");
+            return None;
+          }
+          pairs.push((pair[0], pair[1]));
+        }
+        let channel_id = attrs.take_i64("channel_id");
+        if opcode == HloOpcode::CollectivePermute {
+          HloInstruction::create_collective_permute(shape, &ops, pairs, channel_id)
+        } else {
+          HloInstruction::create_collective_permute_start(shape, &ops, pairs, channel_id)
+        }
+      },
+      HloOpcode::ReplicaId => HloInstruction::create_replica_id(shape),
+      HloOpcode::PartitionId => HloInstruction::create_partition_id(shape),
+      HloOpcode::Reshape => {
+        let inferred_dimension = attrs.take_i64("inferred_dimension").unwrap_or(-1);
+        HloInstruction::create_reshape(shape, ops[0], inferred_dimension)
+      },
+      HloOpcode::DynamicReshape => {
+        if !expect_at_least(self, 1) { return None; }
+        HloInstruction::create_dynamic_reshape(shape, ops[0], &ops[1..])
+      },
+      HloOpcode::AfterAll => {
+        let mut instruction = if ops.is_empty() {
+          HloInstruction::create_token()
+        } else {
+          HloInstruction::create_after_all(&ops)
+        };
+        instruction.set_shape(shape.clone());
+        instruction
+      },
+      HloOpcode::AddDependency => {
+        HloInstruction::create_add_dependency(ops[0], ops[1])
+      },
+      HloOpcode::Sort => {
+        let dimensions = attrs.take_i64_list("dimensions").unwrap();
+        if dimensions.len() != 1 {
+          self.error(loc, "sort op expects exactly one dimension");
+          return None;
+        }
+        let is_stable = attrs.take_bool("is_stable").unwrap_or(false);
+        let to_apply = attrs.take_computation("to_apply").unwrap();
+        HloInstruction::create_sort(shape, dimensions[0], &ops, to_apply, is_stable)
+      },
+      HloOpcode::Tuple => {
+        let mut instruction = HloInstruction::create_tuple(&ops);
+        // Keep the parsed shape, which carries the layouts.
+        instruction.set_shape(shape.clone());
+        instruction
+      },
+      HloOpcode::Call => {
+        let to_apply = attrs.take_computation("to_apply").unwrap();
+        let mut instruction = HloInstruction::create_call(shape, &ops, to_apply);
+        if let Some(is_composite) = attrs.take_bool("is_composite") {
+          instruction.set_is_composite(is_composite);
+        }
+        instruction
+      },
+      HloOpcode::Reduce | HloOpcode::ReduceWindow => {
+        if ops.is_empty() || ops.len() % 2 != 0 {
+          self.error(loc, &format!(
+            "expects an even number of operands, but has {} operands", ops.len()));
+          return None;
+        }
+        let to_apply = attrs.take_computation("to_apply").unwrap();
+        let half = ops.len() / 2;
+        if opcode == HloOpcode::Reduce {
+          let dimensions = attrs.take_i64_list("dimensions").unwrap();
+          HloInstruction::create_reduce_variadic(
+            shape, &ops[..half], &ops[half..], dimensions, to_apply)
+        } else {
+          let window = attrs.take_window("window").unwrap_or(Window::new());
+          HloInstruction::create_reduce_window(
+            shape, &ops[..half], &ops[half..], window, to_apply)
+        }
+      },
+      HloOpcode::Convolution => {
+        let window = attrs.take_window("window").unwrap_or(Window::new());
+        let dnums = attrs.take_convolution_dimension_numbers("dim_labels").unwrap();
+        let feature_group_count = attrs.take_i64("feature_group_count").unwrap_or(1);
+        let batch_group_count = attrs.take_i64("batch_group_count").unwrap_or(1);
+        let precision_config = make_precision_config(
+          attrs.take_precision_list("operand_precision"),
+          attrs.take_algorithm("algorithm"), ops.len());
+        HloInstruction::create_convolve(shape, ops[0], ops[1], feature_group_count,
+          batch_group_count, window, dnums, precision_config)
+      },
+      HloOpcode::Fft => {
+        let fft_type = match attrs.take("fft_type") {
+          Some(AttrValue::FftType(fft_type)) => fft_type,
+          _ => unreachable!()
+        };
+        let fft_length = attrs.take_i64_list("fft_length").unwrap();
+        HloInstruction::create_fft(shape, ops[0], fft_type, fft_length)
+      },
+      HloOpcode::TriangularSolve => {
+        let mut options = TriangularSolveOptions::new();
+        options.set_left_side(attrs.take_bool("left_side").unwrap_or(false));
+        options.set_lower(attrs.take_bool("lower").unwrap_or(false));
+        options.set_unit_diagonal(attrs.take_bool("unit_diagonal").unwrap_or(false));
+        match attrs.take("transpose_a") {
+          Some(AttrValue::TriangularSolveTranspose(t)) => options.set_transpose_a(t),
+          _ => options.set_transpose_a(Transpose::NoTranspose)
+        }
+        HloInstruction::create_triangular_solve(shape, ops[0], ops[1], options)
+      },
+      HloOpcode::Cholsky => {
+        let mut options = CholeskyOptions::new();
+        options.set_lower(attrs.take_bool("lower").unwrap_or(false));
+        HloInstruction::create_cholesky(shape, ops[0], options)
+      },
+      HloOpcode::BatchNormTraining => {
+        let epsilon = attrs.take_f64("epsilon").unwrap();
+        let feature_index = attrs.take_i64("feature_index").unwrap();
+        HloInstruction::create_batch_norm_training(
+          shape, ops[0], ops[1], ops[2], epsilon, feature_index)
+      },
+      HloOpcode::BatchNormInference => {
+        let epsilon = attrs.take_f64("epsilon").unwrap();
+        let feature_index = attrs.take_i64("feature_index").unwrap();
+        HloInstruction::create_batch_norm_inference(
+          shape, ops[0], ops[1], ops[2], ops[3], ops[4], epsilon, feature_index)
+      },
+      HloOpcode::BatchNormGrad => {
+        let epsilon = attrs.take_f64("epsilon").unwrap();
+        let feature_index = attrs.take_i64("feature_index").unwrap();
+        HloInstruction::create_batch_norm_grad(
+          shape, ops[0], ops[1], ops[2], ops[3], ops[4], epsilon, feature_index)
+      },
+      HloOpcode::Broadcast => {
+        let dimensions = attrs.take_i64_list("dimensions").unwrap();
+        HloInstruction::create_broadcast(shape, ops[0], dimensions)
+      },
+      HloOpcode::Concatenate => {
+        if !expect_at_least(self, 1) { return None; }
+        let dimensions = attrs.take_i64_list("dimensions").unwrap();
+        if dimensions.len() != 1 {
+          self.error(loc, "concatenate expects exactly one dimension");
+          return None;
+        }
+        HloInstruction::create_concatenate(shape, &ops, dimensions[0])
+      },
+      HloOpcode::Map => {
+        let to_apply = attrs.take_computation("to_apply").unwrap();
+        HloInstruction::create_map(shape, &ops, to_apply)
+      },
+      HloOpcode::Reverse => {
+        let dimensions = attrs.take_i64_list("dimensions").unwrap();
+        HloInstruction::create_reverse(shape, ops[0], dimensions)
+      },
+      HloOpcode::Transpose => {
+        let dimensions = attrs.take_i64_list("dimensions").unwrap();
+        HloInstruction::create_transpose(shape, ops[0], dimensions)
+      },
+      HloOpcode::Pad => {
+        let padding = match attrs.take("padding") {
+          Some(AttrValue::PaddingConfig(padding)) => padding,
+          _ => unreachable!()
+        };
+        HloInstruction::create_pad(shape, ops[0], ops[1], padding)
+      },
+      HloOpcode::Slice => {
+        let slice = match attrs.take("slice") {
+          Some(AttrValue::SliceRanges(slice)) => slice,
+          _ => unreachable!()
+        };
+        HloInstruction::create_slice(
+          shape, ops[0], slice.starts, slice.limits, slice.strides)
+      },
+      HloOpcode::DynamicSlice => {
+        if !expect_at_least(self, 1) { return None; }
+        let slice_sizes = attrs.take_i64_list("dynamic_slice_sizes").unwrap();
+        HloInstruction::create_dynamic_slice(shape, ops[0], &ops[1..], slice_sizes)
+      },
+      HloOpcode::DynamicUpdateSlice => {
+        if !expect_at_least(self, 2) { return None; }
+        HloInstruction::create_dynamic_update_slice(shape, ops[0], ops[1], &ops[2..])
+      },
+      HloOpcode::SelectAndScatter => {
+        let select = attrs.take_computation("select").unwrap();
+        let scatter = attrs.take_computation("scatter").unwrap();
+        let window = attrs.take_window("window").unwrap_or(Window::new());
+        HloInstruction::create_select_and_scatter(
+          shape, ops[0], select, window, ops[1], ops[2], scatter)
+      },
+      HloOpcode::While => {
+        let condition = attrs.take_computation("condition").unwrap();
+        let body = attrs.take_computation("body").unwrap();
+        HloInstruction::create_while(shape, condition, body, ops[0])
+      },
+      HloOpcode::Conditional => {
+        if !expect_at_least(self, 1) { return None; }
+        let true_computation = attrs.take_computation("true_computation");
+        let false_computation = attrs.take_computation("false_computation");
+        let branch_computations = attrs.take_computation_list("branch_computations");
+        let branches = match (true_computation, false_computation, branch_computations) {
+          (Some(t), Some(f), None) => vec![t, f],
+          (None, None, Some(branches)) => branches,
+          _ => {
+            self.error(loc, "Conditional expects either both true_computation and \
+              false_computation, or branch_computations");
+            return None;
+          }
+        };
+        if branches.len() + 1 != ops.len() {
+          self.error(loc, &format!(
+            "Conditional expects {} branch operands, but has {}",
+            branches.len(), ops.len() - 1));
+          return None;
+        }
+        HloInstruction::create_conditional_with_branches(
+          shape, ops[0], branches, &ops[1..])
+      },
+      HloOpcode::Gather => {
+        let mut dnums = GatherDimensionNumbers::new();
+        for dim in attrs.take_i64_list("offset_dims").unwrap() {
+          dnums.add_offset_dims(dim);
+        }
+        for dim in attrs.take_i64_list("collapsed_slice_dims").unwrap() {
+          dnums.add_collapsed_slice_dims(dim);
+        }
+        for dim in attrs.take_i64_list("start_index_map").unwrap() {
+          dnums.add_start_index_map(dim);
+        }
+        dnums.set_index_vector_dim(attrs.take_i64("index_vector_dim").unwrap());
+        for dim in attrs.take_i64_list("operand_batching_dims").unwrap_or_default() {
+          dnums.add_operand_batching_dims(dim);
+        }
+        for dim in attrs.take_i64_list("start_indices_batching_dims").unwrap_or_default() {
+          dnums.add_start_indices_batching_dims(dim);
+        }
+        let slice_sizes = attrs.take_i64_list("slice_sizes").unwrap();
+        let indices_are_sorted = attrs.take_bool("indices_are_sorted").unwrap_or(false);
+        HloInstruction::create_gather(
+          shape, ops[0], ops[1], dnums, slice_sizes, indices_are_sorted)
+      },
+      HloOpcode::Scatter => {
+        if ops.len() < 3 || ops.len() % 2 != 1 {
+          self.error(loc, &format!(
+            "scatter expects an odd number of operands, at least 3, but has {}",
+            ops.len()));
+          return None;
+        }
+        let num_inputs = ops.len() / 2;
+        let mut dnums = ScatterDimensionNummbers::new();
+        for dim in attrs.take_i64_list("update_window_dims").unwrap() {
+          dnums.add_update_window_dims(dim);
+        }
+        for dim in attrs.take_i64_list("inserted_window_dims").unwrap() {
+          dnums.add_inserted_window_dims(dim);
+        }
+        for dim in attrs.take_i64_list("scatter_dims_to_operand_dims").unwrap() {
+          dnums.add_scatter_dims_to_operand_dims(dim);
+        }
+        dnums.set_index_vector_dim(attrs.take_i64("index_vector_dim").unwrap());
+        for dim in attrs.take_i64_list("input_batching_dims").unwrap_or_default() {
+          dnums.add_input_batching_dims(dim);
+        }
+        for dim in attrs.take_i64_list("scatter_indices_batching_dims").unwrap_or_default() {
+          dnums.add_scatter_indices_batching_dims(dim);
+        }
+        let to_apply = attrs.take_computation("to_apply").unwrap();
+        let indices_are_sorted = attrs.take_bool("indices_are_sorted").unwrap_or(false);
+        let unique_indices = attrs.take_bool("unique_indices").unwrap_or(false);
+        HloInstruction::create_scatter(shape, &ops[..num_inputs], ops[num_inputs],
+          &ops[num_inputs + 1..], to_apply, dnums, indices_are_sorted, unique_indices)
+      },
+      HloOpcode::Domain => {
+        let domain = match attrs.take("domain") {
+          Some(AttrValue::Domain(domain)) => domain,
+          _ => unreachable!()
+        };
+        HloInstruction::create_domain(
+          shape, ops[0], domain.exit_metadata, domain.entry_metadata)
+      },
+      HloOpcode::Fusion => {
+        let kind = match attrs.take("kind") {
+          Some(AttrValue::FusionKind(kind)) => kind,
+          _ => unreachable!()
+        };
+        let calls = attrs.take_computation("calls").unwrap();
+        module.mutable_computation(calls).set_is_fusion_computation(true);
+        HloInstruction::create_fusion(shape, kind, &ops, calls)
+      },
+      HloOpcode::CustomCall => {
+        let target = attrs.take_string("custom_call_target").unwrap();
+        let api_version = match attrs.take("api_version") {
+          Some(AttrValue::CustomCallApiVersion(version)) => version,
+          _ => CustomCallApiVersion::Original
+        };
+        // The opaque string is the backend config, set by the caller.
+        let mut instruction = HloInstruction::create_custom_call(
+          shape, &ops, target, "".to_string(), api_version);
+        if let Some(window) = attrs.take_window("window") {
+          instruction.set_window(window);
+        }
+        if let Some(dnums) = attrs.take_convolution_dimension_numbers("dim_labels") {
+          instruction.set_convolution_dimension_numberes(dnums);
+        }
+        if let Some(count) = attrs.take_i64("feature_group_count") {
+          instruction.set_feature_group_count(count);
+        }
+        if let Some(count) = attrs.take_i64("batch_group_count") {
+          instruction.set_batch_group_count(count);
+        }
+        if let Some(AttrValue::ShapeList(shapes)) = attrs.take("operand_layout_constraints") {
+          if shapes.len() != ops.len() {
+            self.error(loc, &format!(
+              "Expected {} operand layout constraints, {} given",
+              ops.len(), shapes.len()));
+            return None;
+          }
+          instruction.set_operand_shapes_with_layout(shapes);
+        }
+        if let Some(has_side_effect) = attrs.take_bool("custom_call_has_side_effect") {
+          instruction.set_custom_call_has_side_effect(has_side_effect);
+        }
+        if let Some(to_apply) = attrs.take_computation("to_apply") {
+          instruction.append_computation(to_apply);
+        }
+        for computation in attrs.take_computation_list("called_computations")
+          .unwrap_or_default()
+        {
+          instruction.append_computation(computation);
+        }
+        if let Some(AttrValue::InstructionAliasing(aliasing)) =
+          attrs.take("output_to_operand_aliasing")
+        {
+          instruction.set_output_to_operand_aliasing(aliasing);
+        }
+        if let Some(AttrValue::CustomCallSchedule(schedule)) = attrs.take("schedule") {
+          instruction.set_custom_call_schedule(schedule);
+        }
+        if let Some(AttrValue::PaddingType(padding_type)) = attrs.take("padding_type") {
+          instruction.set_padding_type(padding_type);
+        }
+        if let Some(precisions) = attrs.take_precision_list("operand_precision") {
+          instruction.set_precision_config(
+            make_precision_config(Some(precisions), None, ops.len()));
+        }
+        instruction
+      },
+      HloOpcode::Dot => {
+        let mut dnums = DotDimensionNumbers::default();
+        for dim in attrs.take_i64_list("lhs_contracting_dims").unwrap_or_default() {
+          dnums.add_lhs_contracting_dimensions(dim);
+        }
+        for dim in attrs.take_i64_list("rhs_contracting_dims").unwrap_or_default() {
+          dnums.add_rhs_contracting_dimensions(dim);
+        }
+        for dim in attrs.take_i64_list("lhs_batch_dims").unwrap_or_default() {
+          dnums.add_lhs_batch_dimensions(dim);
+        }
+        for dim in attrs.take_i64_list("rhs_batch_dims").unwrap_or_default() {
+          dnums.add_rhs_batch_dimensions(dim);
+        }
+        let precision_config = make_precision_config(
+          attrs.take_precision_list("operand_precision"),
+          attrs.take_algorithm("algorithm"), ops.len());
+        HloInstruction::create_dot(
+          shape, ops[0], ops[1], dnums, precision_config, vec![], &[])
+      },
+      HloOpcode::ReducePrecision => {
+        let exponent_bits = attrs.take_i64("exponent_bits").unwrap();
+        let mantissa_bits = attrs.take_i64("mantissa_bits").unwrap();
+        HloInstruction::create_reduce_precision(
+          shape, ops[0], exponent_bits, mantissa_bits)
+      },
+      HloOpcode::Rng => {
+        let distribution = match attrs.take("distribution") {
+          Some(AttrValue::Distribution(distribution)) => distribution,
+          _ => unreachable!()
+        };
+        HloInstruction::create_rng(shape, distribution, &ops)
+      },
+      HloOpcode::RngBitGenerator => {
+        let algorithm = match attrs.take("algorithm") {
+          Some(AttrValue::RandomAlgorithm(algorithm)) => algorithm,
+          _ => unreachable!()
+        };
+        HloInstruction::create_rng_bit_generator(shape, ops[0], algorithm)
+      },
+      HloOpcode::RngGetAndUpdateState => {
+        let delta = attrs.take_i64("delta").unwrap();
+        HloInstruction::create_rng_get_and_update_state(shape, delta)
+      },
+      HloOpcode::Infeed => {
+        // The result is a tuple of the infeed data and a token.
+        if !shape.is_tuple() || shape.tuple_shapes_size() != 2 {
+          self.error(loc, "infeed must have a tuple shape of (data, token)");
+          return None;
+        }
+        let config = attrs.take_string("infeed_config").unwrap_or_default();
+        let mut instruction =
+          HloInstruction::create_infeed(shape.tuple_shapes(0), ops[0], config);
+        instruction.set_shape(shape.clone());
+        instruction
+      },
+      HloOpcode::Outfeed => {
+        let outfeed_shape = attrs.take_shape("outfeed_shape")
+          .unwrap_or(ops[0].shape().clone());
+        let config = attrs.take_string("outfeed_config").unwrap_or_default();
+        HloInstruction::create_outfeed(&outfeed_shape, ops[0], ops[1], config)
+      },
+      HloOpcode::Send => {
+        let channel_id = attrs.take_i64("channel_id").unwrap();
+        let is_host_transfer = attrs.take_bool("is_host_transfer").unwrap_or(false);
+        HloInstruction::create_send_with_shape(
+          shape, ops[0], ops[1], channel_id, is_host_transfer)
+      },
+      HloOpcode::SendDone => {
+        let is_host_transfer = attrs.take_bool("is_host_transfer").unwrap_or(false);
+        let mut instruction = HloInstruction::create_send_done(ops[0], is_host_transfer);
+        instruction.set_shape(shape.clone());
+        if let Some(channel_id) = attrs.take_i64("channel_id") {
+          instruction.set_channel_id(channel_id);
+        }
+        instruction
+      },
+      HloOpcode::Recv => {
+        let channel_id = attrs.take_i64("channel_id").unwrap();
+        let is_host_transfer = attrs.take_bool("is_host_transfer").unwrap_or(false);
+        HloInstruction::create_recv_with_shape(
+          shape, ops[0], channel_id, is_host_transfer)
+      },
+      HloOpcode::RecvDone => {
+        let is_host_transfer = attrs.take_bool("is_host_transfer").unwrap_or(false);
+        let mut instruction =
+          HloInstruction::create_recv_done_with_shape(shape, ops[0], is_host_transfer);
+        if let Some(channel_id) = attrs.take_i64("channel_id") {
+          instruction.set_channel_id(channel_id);
+        }
+        instruction
+      },
+      HloOpcode::GetTupleElement => {
+        let index = attrs.take_i64("index").unwrap();
+        if !ops[0].shape().is_tuple() ||
+           index < 0 || index as usize >= ops[0].shape().tuple_shapes_size()
+        {
+          self.error(loc, &format!(
+            "get-tuple-element index {} is out of range for operand {}",
+            index, ops[0].name()));
+          return None;
+        }
+        HloInstruction::create_get_tuple_element_by_shape(shape, ops[0], index)
+      },
+      HloOpcode::GetDimensionSize | HloOpcode::SetDimensionSize => {
+        let dimensions = attrs.take_i64_list("dimensions").unwrap();
+        if dimensions.len() != 1 {
+          self.error(loc, &format!("{} expects exactly one dimension", opcode_str));
+          return None;
+        }
+        if opcode == HloOpcode::GetDimensionSize {
+          HloInstruction::create_get_dimension_size(shape, ops[0], dimensions[0])
+        } else {
+          HloInstruction::create_set_dimension_size(shape, ops[0], ops[1], dimensions[0])
+        }
+      },
+      HloOpcode::CopyStart => {
+        let cross_program_prefetch_index = attrs.take_i64("cross_program_prefetch_index");
+        HloInstruction::create_copy_start(shape, ops[0], cross_program_prefetch_index)
+      },
+      HloOpcode::AsyncStart => {
+        let calls = attrs.take_computation("calls").unwrap();
+        let execution_thread = attrs.take_string("async_execution_thread")
+          .unwrap_or("main".to_string());
+        HloInstruction::create_async_start(shape, &ops, calls, execution_thread)
+      },
+      HloOpcode::AsyncUpdate | HloOpcode::AsyncDone => {
+        if ops[0].opcode() != HloOpcode::AsyncStart &&
+           ops[0].opcode() != HloOpcode::AsyncUpdate
+        {
+          self.error(loc, &format!(
+            "{} expects its operand to be an async-start or async-update", opcode_str));
+          return None;
+        }
+        // The called computation and thread come from the operand; explicit
+        // values must agree with it.
+        if let Some(calls) = attrs.take_computation("calls") {
+          if !ops[0].called_computations().contains(&calls) {
+            self.error(loc, &format!(
+              "{} calls a different computation than its operand", opcode_str));
+            return None;
+          }
+        }
+        attrs.take_string("async_execution_thread");
+        if opcode == HloOpcode::AsyncUpdate {
+          HloInstruction::create_async_update(shape, ops[0])
+        } else {
+          HloInstruction::create_async_done(shape, ops[0])
+        }
+      }
+    };
+    Some(instruction)
+  }
+
+  // Creates the async op of a syntax sugared '<opcode>-start',
+  // '<opcode>-update' or '<opcode>-done'. The start wraps a new computation
+  // holding a parameter per operand and the '<opcode>' instruction as root.
+  fn create_syntax_sugar_async_instruction(
+    &mut self,
+    module: &mut HloModule,
+    opcode: HloOpcode,
+    wrapped_opcode: HloOpcode,
+    shape: &Shape,
+    operand_ids: &Vec<InstructionId>,
+    attrs: &mut Attributes,
+    loc: usize) -> Option<HloInstruction>
+  {
+    let execution_thread = attrs.take_string("async_execution_thread")
+      .unwrap_or("main".to_string());
+    let wrapped_opcode_str = hlo_opcode_string(&wrapped_opcode);
+    if opcode == HloOpcode::AsyncStart {
+      if !shape.is_tuple() || shape.tuple_shapes_size() < 2 {
+        self.error(loc, &format!(
+          "{}-start expects a tuple shape holding its operands and result",
+          wrapped_opcode_str));
+        return None;
+      }
+      let operand_shapes: Vec<Shape> = {
+        let computation = self.computation_stack.last().unwrap();
+        operand_ids.iter().map(|id| computation.instruction(*id).shape().clone()).collect()
+      };
+      let wrapped_name = format!("async_wrapped.{}", module.computation_count());
+      let mut wrapped = HloComputation::new(wrapped_name.clone());
+      let mut param_ids = vec![];
+      for (i, operand_shape) in operand_shapes.iter().enumerate() {
+        let param = HloInstruction::create_parameter(
+          i as i64, operand_shape, format!("param_{}", i));
+        param_ids.push(wrapped.add_parameter(param));
+      }
+      let mut instruction = self.create_instruction(module, &wrapped,
+        wrapped_opcode.clone(), shape.tuple_shapes(1), &param_ids, 0, None,
+        &wrapped_opcode_str, attrs, loc)?;
+      instruction.set_name(wrapped_opcode_str);
+      let root = wrapped.add_instruction(instruction, "".to_string());
+      wrapped.set_root_instruction(root, true);
+      wrapped.set_execution_thread(execution_thread.clone());
+      let wrapped_id = module.add_embedded_computation(wrapped);
+      self.computation_names.insert(wrapped_name, wrapped_id);
+
+      let computation = self.computation_stack.last().unwrap();
+      let ops: Vec<&HloInstruction> =
+        operand_ids.iter().map(|id| computation.instruction(*id)).collect();
+      return Some(HloInstruction::create_async_start(
+        shape, &ops, wrapped_id, execution_thread));
+    }
+
+    let suffix = if opcode == HloOpcode::AsyncUpdate { "update" } else { "done" };
+    if operand_ids.len() != 1 {
+      self.error(loc, &format!("{}-{} expects 1 operand, but has {} operands",
+        wrapped_opcode_str, suffix, operand_ids.len()));
+      return None;
+    }
+    let computation = self.computation_stack.last().unwrap();
+    let operand = computation.instruction(operand_ids[0]);
+    let wraps_same_opcode = matches!(operand.opcode(),
+        HloOpcode::AsyncStart | HloOpcode::AsyncUpdate) &&
+      operand.called_computations().first().map_or(false, |id| {
+        let called = module.computation(*id);
+        called.has_root_instruction() &&
+          called.root_instruction().opcode() == wrapped_opcode
+      });
+    if !wraps_same_opcode {
+      self.error(loc, &format!(
+        "{}-{} expects its operand to be a {}-start or {}-update",
+        wrapped_opcode_str, suffix, wrapped_opcode_str, wrapped_opcode_str));
+      return None;
+    }
+    // The attributes of the wrapped instruction were applied by the start,
+    // so they are left unused here.
+    if opcode == HloOpcode::AsyncUpdate {
+      Some(HloInstruction::create_async_update(shape, operand))
+    } else {
+      Some(HloInstruction::create_async_done(shape, operand))
+    }
+  }
+
+  // attributes ::= (',' attribute)*
+  //
+  // Parses the attributes in 'configs' and returns their values. Returns
+  // None if an attribute is not in 'configs', is given twice, or a required
+  // attribute is missing.
+  fn parse_attributes(
+    &mut self,
+    configs: &HashMap<String, AttrConfig>,
+    module: &mut HloModule) -> Option<Attributes>
+  {
+    let mut attrs = Attributes::new();
+    while self.eat_if_present(TokKind::Comma) {
+      let loc = self.lexer.get_loc();
+      let mut name = "".to_string();
+      if !self.parse_attribute_name(&mut name) {
+        return None;
+      }
+      let attr_type = match configs.get(&name) {
+        Some(config) => config.attr_type.clone(),
+        None => {
+          self.error(loc, &format!("unexpected attribute {}", name));
+          return None;
+        }
+      };
+      if attrs.contains(&name) {
+        self.error(loc, &format!("attribute {} already exists", name));
+        return None;
+      }
+      match self.parse_attribute_value(&attr_type, module) {
+        Some(value) => attrs.insert(name, value),
+        None => {
+          self.error(loc, &format!("error parsing attribute {}", name));
+          return None;
+        }
+      }
+    }
+    let mut missing: Vec<&String> = configs.iter()
+      .filter(|(name, config)| config.required && !attrs.contains(name))
+      .map(|(name, _)| name)
+      .collect();
+    missing.sort();
+    if let Some(name) = missing.first() {
+      self.error(self.lexer.get_loc(),
+        &format!("attribute {} is expected but not seen", name));
+      return None;
+    }
+    Some(attrs)
+  }
+
+  fn parse_attribute_value(
+    &mut self, attr_type: &AttrType, module: &mut HloModule) -> Option<AttrValue>
+  {
+    match attr_type {
+      AttrType::Bool => {
+        let mut value = false;
+        if !self.parse_bool(&mut value) { return None; }
+        Some(AttrValue::Bool(value))
+      },
+      AttrType::Int64 => {
+        let mut value = 0;
+        if !self.parse_int64(&mut value) { return None; }
+        Some(AttrValue::Int64(value))
+      },
+      AttrType::Float => {
+        let mut value = 0.0;
+        if !self.parse_double(&mut value) { return None; }
+        Some(AttrValue::Float(value))
+      },
+      AttrType::String => {
+        let mut value = "".to_string();
+        if !self.parse_string(&mut value) { return None; }
+        Some(AttrValue::String(value))
+      },
+      AttrType::StringOrJsonDict => {
+        // The backend config is either a quoted string or a raw JSON dict.
+        if self.lexer.get_kind() == TokKind::Lbrace {
+          if self.lexer.lex_json_dict() != TokKind::String {
+            self.token_error("expects a JSON dict");
+            return None;
+          }
+        }
+        let mut value = "".to_string();
+        if !self.parse_string(&mut value) { return None; }
+        Some(AttrValue::String(value))
+      },
+      AttrType::BracedInt64List => {
+        let mut values = vec![];
+        if !self.parse_int64_list(TokKind::Lbrace, TokKind::Rbrace,
+          TokKind::Comma, &mut values)
+        {
+          return None;
+        }
+        Some(AttrValue::BracedInt64List(values))
+      },
+      AttrType::BracedInt64ListList => {
+        let mut values = vec![];
+        if !self.parse_int64_list_list(&mut values) { return None; }
+        Some(AttrValue::BracedInt64ListList(values))
+      },
+      AttrType::ReplicaGroups => {
+        let mut groups = vec![];
+        if !self.parse_replica_groups(&mut groups) { return None; }
+        Some(AttrValue::ReplicaGroups(groups))
+      },
+      AttrType::HloComputation => {
+        let mut id = ComputationId::new(0);
+        if !self.parse_computation_ref(module, &mut id) { return None; }
+        Some(AttrValue::HloComputation(id))
+      },
+      AttrType::BracedHloComputationList => {
+        let mut ids = vec![];
+        if !self.parse_computation_list(module, &mut ids) { return None; }
+        Some(AttrValue::BracedHloComputationList(ids))
+      },
+      AttrType::FftType => {
+        let name = self.parse_enum_ident("fft type")?;
+        self.convert_enum(string_to_fft_type(&name)).map(AttrValue::FftType)
+      },
+      AttrType::PaddingType => {
+        let name = self.parse_enum_ident("padding type")?;
+        self.convert_enum(string_to_padding_type(&name)).map(AttrValue::PaddingType)
+      },
+      AttrType::ComparisonDirection => {
+        let name = self.parse_enum_ident("comparison direction")?;
+        self.convert_enum(string_to_comparison_direction(&name))
+          .map(AttrValue::ComparisonDirection)
+      },
+      AttrType::ComparisonType => {
+        let name = self.parse_enum_ident("comparison type")?;
+        self.convert_enum(string_to_comparison_type(&name))
+          .map(AttrValue::ComparisonType)
+      },
+      AttrType::FusionKind => {
+        let name = self.parse_enum_ident("fusion kind")?;
+        self.convert_enum(string_to_fusion_kind(&name)).map(AttrValue::FusionKind)
+      },
+      AttrType::Distribution => {
+        let name = self.parse_enum_ident("random distribution")?;
+        self.convert_enum(string_to_random_distribution(&name))
+          .map(AttrValue::Distribution)
+      },
+      AttrType::RandomAlgorithm => {
+        let name = self.parse_enum_ident("random algorithm")?;
+        self.convert_enum(string_to_random_algorithm(&name))
+          .map(AttrValue::RandomAlgorithm)
+      },
+      AttrType::PrecisionAlgorithm => {
+        let name = self.parse_enum_ident("precision algorithm")?;
+        self.convert_enum(string_to_algorithm(&name)).map(AttrValue::PrecisionAlgorithm)
+      },
+      AttrType::CustomCallSchedule => {
+        let name = self.parse_enum_ident("custom call schedule")?;
+        self.convert_enum(string_to_custom_call_schedule(&name))
+          .map(AttrValue::CustomCallSchedule)
+      },
+      AttrType::CustomCallApiVersion => {
+        let name = self.parse_enum_ident("custom call api version")?;
+        self.convert_enum(string_to_custom_call_api_version(&name))
+          .map(AttrValue::CustomCallApiVersion)
+      },
+      AttrType::TriangularSolveTranspose => {
+        let name = self.parse_enum_ident("transpose type")?;
+        self.convert_enum(string_to_transpose(&name))
+          .map(AttrValue::TriangularSolveTranspose)
+      },
+      AttrType::Window => {
+        let mut window = Window::new();
+        if !self.parse_window(&mut window, true) { return None; }
+        Some(AttrValue::Window(window))
+      },
+      AttrType::ConvolutionDimensionNumbers => {
+        let mut dnums = ConvolutionDimensionNumbers::new();
+        if !self.parse_convolution_dimension_numbers(&mut dnums) { return None; }
+        Some(AttrValue::ConvolutionDimensionNumbers(dnums))
+      },
+      AttrType::Sharding => {
+        let mut sharding = HloSharding::replicate(vec![]);
+        if !self.parse_sharding(&mut sharding) { return None; }
+        Some(AttrValue::Sharding(sharding))
+      },
+      AttrType::FrontendAttributes => {
+        let mut attributes = FrontendAttributes::new();
+        if !self.parse_frontend_attributes(&mut attributes) { return None; }
+        Some(AttrValue::FrontendAttributes(attributes))
+      },
+      AttrType::StatisticsViz => {
+        let mut statistics_viz = StatisticsViz::new();
+        if !self.parse_statistics_viz(&mut statistics_viz) { return None; }
+        Some(AttrValue::StatisticsViz(statistics_viz))
+      },
+      AttrType::BracedBoolListOrBool => {
+        let mut values = vec![];
+        if self.lexer.get_kind() == TokKind::Lbrace {
+          if !self.parse_bool_list(&mut values) { return None; }
+        } else {
+          let mut value = false;
+          if !self.parse_bool(&mut value) { return None; }
+          values.push(value);
+        }
+        Some(AttrValue::BracedBoolListOrBool(values))
+      },
+      AttrType::ParameterReplication => {
+        let mut values = vec![];
+        if !self.parse_parameter_replication(&mut values) { return None; }
+        Some(AttrValue::ParameterReplication(values))
+      },
+      AttrType::InstructionList => {
+        let mut ids = vec![];
+        if !self.parse_instruction_names(&mut ids) { return None; }
+        Some(AttrValue::InstructionList(ids))
+      },
+      AttrType::SliceRanges => {
+        let mut ranges = SliceRange { starts: vec![], limits: vec![], strides: vec![] };
+        if !self.parse_slice_ranges(&mut ranges) { return None; }
+        Some(AttrValue::SliceRanges(ranges))
+      },
+      AttrType::PaddingConfig => {
+        let mut padding = PaddingConfig::new();
+        if !self.parse_padding_config(&mut padding) { return None; }
+        Some(AttrValue::PaddingConfig(padding))
+      },
+      AttrType::Metadata => {
+        let mut metadata = OpMetadata::new();
+        if !self.parse_metadata(&mut metadata) { return None; }
+        Some(AttrValue::Metadata(metadata))
+      },
+      AttrType::Domain => {
+        let mut domain = None;
+        if !self.parse_domain(&mut domain) { return None; }
+        domain.map(AttrValue::Domain)
+      },
+      AttrType::PrecisionList => {
+        let mut precisions = vec![];
+        if !self.parse_precision_list(&mut precisions) { return None; }
+        Some(AttrValue::PrecisionList(precisions))
+      },
+      AttrType::Shape => {
+        let mut shape = Shape::new();
+        if !self.parse_shape(&mut shape) { return None; }
+        Some(AttrValue::Shape(shape))
+      },
+      AttrType::ShapeList => {
+        let mut shapes = vec![];
+        if !self.parse_shape_list(&mut shapes) { return None; }
+        Some(AttrValue::ShapeList(shapes))
+      },
+      AttrType::Aliasing => {
+        let mut aliasing = vec![];
+        if !self.parse_aliasing(&mut aliasing) { return None; }
+        Some(AttrValue::Aliasing(aliasing))
+      },
+      AttrType::BufferDonor => {
+        let mut donors = vec![];
+        if !self.parse_buffer_donor(&mut donors) { return None; }
+        Some(AttrValue::BufferDonor(donors))
+      },
+      AttrType::ComputationLayout => {
+        let mut parameters = vec![];
+        let mut result = Shape::new();
+        if !self.parse_computation_layout(&mut parameters, &mut result) {
+          return None;
+        }
+        Some(AttrValue::ComputationLayout(parameters, result))
+      },
+      AttrType::InstructionAliasing => {
+        let mut aliasing = vec![];
+        if !self.parse_instruction_output_operand_aliasing(&mut aliasing) {
+          return None;
+        }
+        Some(AttrValue::InstructionAliasing(aliasing))
+      }
+    }
+  }
+
+  // Parses an identifier naming an enum value.
+  fn parse_enum_ident(&mut self, what: &str) -> Option<String> {
+    if self.lexer.get_kind() != TokKind::Ident {
+      self.token_error(&format!("expects {}", what));
+      return None;
+    }
+    let name = self.lexer.get_str_val();
+    Some(name)
+  }
+
+  // Consumes the identifier parsed by parse_enum_ident() if it names a valid
+  // enum value.
+  fn convert_enum<T>(&mut self, result: Result<T, String>) -> Option<T> {
+    match result {
+      Ok(value) => {
+        self.lexer.lex();
+        Some(value)
+      },
+      Err(msg) => {
+        self.token_error(&msg);
+        None
+      }
+    }
+  }
+
+  // Parses a reference to a computation: either the name of a computation
+  // defined earlier or an inline computation.
+  fn parse_computation_ref(
+    &mut self, module: &mut HloModule, result: &mut ComputationId) -> bool
+  {
+    if self.lexer.get_kind() == TokKind::Lbrace {
+      return self.parse_inline_computation(module, result);
+    }
+    let loc = self.lexer.get_loc();
+    let mut name = "".to_string();
+    if !self.parse_name(&mut name) {
+      return self.error(loc, "expects computation name");
+    }
+    match self.computation_names.get(&name) {
+      Some(id) => {
+        *result = *id;
+        true
+      },
+      None => self.error(loc, &format!("computation does not exist: {}", name))
+    }
+  }
+
+  // computation_list ::= '{' (computation (',' computation)*)? '}'
+  fn parse_computation_list(
+    &mut self, module: &mut HloModule, result: &mut Vec<ComputationId>) -> bool
+  {
+    if !self.parse_token(TokKind::Lbrace,
+      "expects '{' at the beginning of computation list")
+    {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rbrace {
+      loop {
+        let mut id = ComputationId::new(0);
+        if !self.parse_computation_ref(module, &mut id) {
+          return false;
+        }
+        result.push(id);
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    self.parse_token(TokKind::Rbrace, "expects '}' at the end of computation list")
+  }
+
+  // instruction_names ::= '{' (name (',' name)*)? '}'
+  fn parse_instruction_names(&mut self, result: &mut Vec<InstructionId>) -> bool {
+    if !self.parse_token(TokKind::Lbrace,
+      "expects '{' at the beginning of instruction name list")
+    {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rbrace {
+      loop {
+        let loc = self.lexer.get_loc();
+        let mut name = "".to_string();
+        if !self.parse_name(&mut name) {
+          return false;
+        }
+        match self.scoped_name_tables.last().unwrap().get(&name) {
+          Some(id) => result.push(*id),
+          None => return self.error(loc,
+            &format!("instruction does not exist: {}", name))
+        }
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    self.parse_token(TokKind::Rbrace,
+      "expects '}' at the end of instruction name list")
+  }
+
+  // literal
+  //  ::= tuple
+  //  ::= non_tuple
+  fn parse_literal(&mut self, shape: &Shape, result: &mut Option<DynLiteral>) -> bool {
+    let mut literal = DynLiteral::new(shape);
+    if !self.parse_literal_piece(shape, &mut literal, &mut vec![]) {
+      return false;
+    }
+    *result = Some(literal);
+    true
+  }
+
+  // tuple ::= '(' (shape? literal (',' shape? literal)*)? ')'
+  fn parse_literal_piece(
+    &mut self,
+    shape: &Shape,
+    literal: &mut DynLiteral,
+    index: &mut Vec<i64>) -> bool
+  {
+    if !shape.is_tuple() {
+      return self.parse_dense_literal(shape, literal, index);
+    }
+    if !self.parse_token(TokKind::Lparen, "expects '(' in front of tuple elements") {
+      return false;
+    }
+    for i in 0..shape.tuple_shapes_size() {
+      if i > 0 && !self.parse_token(TokKind::Comma,
+        "expects ',' to separate tuple elements")
+      {
+        return false;
+      }
+      // Elements may be printed with their shape.
+      if self.lexer.get_kind() == TokKind::PrimitiveType {
+        let mut element_shape = Shape::new();
+        if !self.parse_shape(&mut element_shape) {
+          return false;
+        }
+      }
+      index.push(i as i64);
+      if !self.parse_literal_piece(shape.tuple_shapes(i), literal, index) {
+        return false;
+      }
+      index.pop();
+    }
+    self.parse_token(TokKind::Rparen, "expects ')' at the end of the tuple")
+  }
+
+  // non_tuple
+  //   ::= rank0_value
+  //   ::= '{' '...' '}'
+  //   ::= dense_literal
+  fn parse_dense_literal(
+    &mut self,
+    shape: &Shape,
+    literal: &mut DynLiteral,
+    index: &Vec<i64>) -> bool
+  {
+    let element_type = shape.element_type();
+    let num_elements: i64 = shape.dimensions_vec().iter().product();
+    let mut data = vec![];
+    if shape.rank() == 0 {
+      let mut value = Element::Integer(0);
+      if !self.parse_literal_value(&element_type, &mut value) {
+        return false;
+      }
+      data.push(value);
+    } else if self.lexer.get_kind() == TokKind::Lbrace &&
+              self.lexer.look_ahead() == TokKind::Dots
+    {
+      // The values were elided when printing; fill the literal with zeros.
+      self.lexer.lex();
+      self.lexer.lex();
+      if !self.parse_token(TokKind::Rbrace, "expects '}' after '...'") {
+        return false;
+      }
+      data = vec![Element::Integer(0); num_elements as usize];
+    } else if !self.parse_dense_dimension(shape, 0, &mut data) {
+      return false;
+    }
+    if data.len() as i64 != num_elements {
+      return self.token_error(&format!(
+        "expects {} elements in the literal, but sees {}", num_elements, data.len()));
+    }
+    literal.set_elements(index, data);
+    true
+  }
+
+  // Parses the elements of dimension 'dim' of a dense literal, e.g. '{1, 2}'
+  // for the most minor dimension or '{{1, 2}, {3, 4}}' for the others.
+  fn parse_dense_dimension(
+    &mut self, shape: &Shape, dim: usize, data: &mut Vec<Element>) -> bool
+  {
+    if !self.parse_token(TokKind::Lbrace, "expects '{' in dense literal") {
+      return false;
+    }
+    let expected = shape.dimensions(dim);
+    let mut count = 0;
+    if self.lexer.get_kind() != TokKind::Rbrace {
+      loop {
+        if dim + 1 == shape.rank() {
+          let mut value = Element::Integer(0);
+          if !self.parse_literal_value(&shape.element_type(), &mut value) {
+            return false;
+          }
+          data.push(value);
+        } else if !self.parse_dense_dimension(shape, dim + 1, data) {
+          return false;
+        }
+        count += 1;
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    if count != expected {
+      return self.token_error(&format!(
+        "expects {} elements in dimension {}, but sees {}", expected, dim, count));
+    }
+    self.parse_token(TokKind::Rbrace, "expects '}' at the end of dense literal")
+  }
+
+  // Parses a single element of a literal of the given type. Integers are kept
+  // exact, and complex numbers are written as '(real, imag)'.
+  fn parse_literal_value(&mut self, element_type: &PrimitiveType, value: &mut Element) -> bool {
+    let kind = self.lexer.get_kind();
+    if *element_type == PrimitiveType::Pred {
+      match kind {
+        TokKind::True => *value = Element::Integer(1),
+        TokKind::False => *value = Element::Integer(0),
+        TokKind::Int if self.lexer.get_i64_val() == 0 || self.lexer.get_i64_val() == 1 => {
+          *value = Element::Integer(self.lexer.get_i64_val() as i128);
+        },
+        _ => return self.token_error("expects true, false, 0 or 1 for pred"),
+      }
+      self.lexer.lex();
+      return true;
+    }
+    if primitive_util::is_integral_type(element_type) {
+      if kind != TokKind::Int {
+        return self.token_error(&format!("expects integer for primitive type: {}",
+          primitive_util::lowercase_primitive_type_name(element_type)));
+      }
+      let raw = self.lexer.get_i64_val();
+      // Values above i64::MAX are lexed with their u64 bit pattern.
+      let int_value = if *element_type == PrimitiveType::U64 {
+        raw as u64 as i128
+      } else {
+        raw as i128
+      };
+      let (min, max) = integral_range(element_type).unwrap();
+      if int_value < min || int_value > max {
+        return self.token_error(&format!("value {} is out of range for literal's \
+          primitive type {}", int_value,
+          primitive_util::lowercase_primitive_type_name(element_type)));
+      }
+      *value = Element::Integer(int_value);
+      self.lexer.lex();
+      return true;
+    }
+    if primitive_util::is_floating_point_type(element_type) {
+      let mut real = 0.0;
+      if !self.parse_real_value(&mut real) {
+        return false;
+      }
+      *value = Element::Real(real);
+      return true;
+    }
+    if primitive_util::is_complex_type(element_type) {
+      let mut real = 0.0;
+      let mut imag = 0.0;
+      if !self.parse_token(TokKind::Lparen, "expects '(' in front of complex number") ||
+         !self.parse_real_value(&mut real) ||
+         !self.parse_token(TokKind::Comma, "expects ',' in complex number") ||
+         !self.parse_real_value(&mut imag) ||
+         !self.parse_token(TokKind::Rparen, "expects ')' at the end of complex number")
+      {
+        return false;
+      }
+      *value = Element::Complex(real, imag);
+      return true;
+    }
+    self.token_error(&format!("unsupported primitive type {}",
+      primitive_util::lowercase_primitive_type_name(element_type)))
+  }
+
+  // Parses a floating point number, which may be written as an integer.
+  fn parse_real_value(&mut self, value: &mut f64) -> bool {
+    match self.lexer.get_kind() {
+      TokKind::Decimal => *value = self.lexer.get_decimal_val(),
+      TokKind::Int => *value = self.lexer.get_i64_val() as f64,
+      TokKind::Inf => *value = f64::INFINITY,
+      TokKind::NegInf => *value = f64::NEG_INFINITY,
+      _ => return self.token_error("expects number"),
+    }
+    self.lexer.lex();
+    true
+  }
+
+  // shape ::= shape_val_
+  // shape ::= '(' tuple_elements ')'
+  // tuple_elements
+  //   ::= /*empty*/
+  //   ::= shape (',' shape)*
+  fn parse_shape(&mut self, result: &mut Shape) -> bool {
+    if self.eat_if_present(TokKind::Lparen) {
+      let mut shapes = vec![];
+      if self.lexer.get_kind() != TokKind::Rparen {
+        loop {
+          let mut shape = Shape::new();
+          if !self.parse_shape(&mut shape) {
+            return false;
+          }
+          shapes.push(shape);
+          if !self.eat_if_present(TokKind::Comma) {
+            break;
+          }
+        }
+      }
+      *result = ShapeUtil::make_tuple_shape(shapes);
+      return self.parse_token(TokKind::Rparen, "expects ')' at the end of tuple.");
+    }
+    self.parse_non_tuple_shape(result)
+  }
+
+  // shape ::= primitive_type dimension_sizes layout?
+  fn parse_non_tuple_shape(&mut self, result: &mut Shape) -> bool {
+    if self.lexer.get_kind() != TokKind::PrimitiveType {
+      return self.token_error("expected primitive type");
+    }
+    let primitive_type = self.lexer.get_primitive_type_val();
+    self.lexer.lex();
+
+    let mut dimensions = vec![];
+    let mut dynamic_dimensions = vec![];
+    if !self.parse_dimension_sizes(&mut dimensions, &mut dynamic_dimensions) {
+      return false;
+    }
+    match primitive_type {
+      PrimitiveType::Token => *result = ShapeUtil::make_token_shape(),
+      PrimitiveType::OpaqueType => *result = ShapeUtil::make_opaque_shape(),
+      _ => {
+        *result = ShapeUtil::make_shape(&primitive_type, dimensions);
+        for (i, is_dynamic) in dynamic_dimensions.into_iter().enumerate() {
+          if is_dynamic {
+            result.set_dynamic_dimension(i, true);
+          }
+        }
+      }
+    }
+
+    // An explicit layout overrides the default one.
+    if self.lexer.get_kind() == TokKind::Lbrace {
+      let next = self.lexer.look_ahead();
+      if next == TokKind::Int || next == TokKind::Colon || next == TokKind::Rbrace {
+        let mut layout = Layout::new();
+        if !self.parse_layout(&mut layout) {
+          return false;
+        }
+        if layout.minor_to_major_size() != result.rank() {
+          return self.token_error(&format!(
+            "Dimensions size is {}, but minor to major size is {}.",
+            result.rank(), layout.minor_to_major_size()));
+        }
+        result.set_layout(layout);
+      }
+    }
+    true
+  }
+
+  // dimension_sizes ::= '[' dimension_list ']'
+  // dimension_list
+  //   ::= /*empty*/
+  //   ::= '<='? int64 (',' param)*
+  //   ::= '?' (',' param)*
+  fn parse_dimension_sizes(
+    &mut self, dimensions: &mut Vec<i64>, dynamic_dimensions: &mut Vec<bool>) -> bool
+  {
+    if !self.parse_token(TokKind::Lsquare, "expects '[' at the beginning of dimensions") {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rsquare {
+      loop {
+        if self.eat_if_present(TokKind::QuestionMark) {
+          dimensions.push(Shape::UNBOUNDED_SIZE);
+          dynamic_dimensions.push(true);
+        } else {
+          let is_dynamic = self.eat_if_present(TokKind::Leq);
+          let mut size = 0;
+          if !self.parse_int64(&mut size) {
+            return false;
+          }
+          dimensions.push(size);
+          dynamic_dimensions.push(is_dynamic);
+        }
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    self.parse_token(TokKind::Rsquare, "expects ']' at the end of dimensions")
+  }
+
+  // layout ::= '{' int64_list (':' dim_level_types tiles
+  //   tail_padding_alignment_in_elements element_size_in_bits memory_space
+  //   physical_shape dynamic_shape_metadata_prefix_bytes)? '}'
+  fn parse_layout(&mut self, layout: &mut Layout) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expects '{' at the beginning of layout") {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rbrace && self.lexer.get_kind() != TokKind::Colon {
+      loop {
+        let mut dim = 0;
+        if !self.parse_int64(&mut dim) {
+          return false;
+        }
+        layout.add_minor_to_major(dim);
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    if self.eat_if_present(TokKind::Colon) {
+      while self.lexer.get_kind() != TokKind::Rbrace {
+        match self.lexer.get_kind() {
+          TokKind::Ident => {
+            let item = self.lexer.get_str_val();
+            self.lexer.lex();
+            if !self.parse_layout_item(&item, layout) {
+              return false;
+            }
+          },
+          TokKind::Octothorp | TokKind::Asterisk => {
+            let is_index = self.lexer.get_kind() == TokKind::Octothorp;
+            self.lexer.lex();
+            let mut primitive_type = PrimitiveType::Invalid;
+            if !self.parse_token(TokKind::Lparen, "expects '(' after '#' or '*'") ||
+               !self.parse_layout_primitive_type(&mut primitive_type) ||
+               !self.parse_token(TokKind::Rparen, "expects ')' after primitive type")
+            {
+              return false;
+            }
+            if is_index {
+              layout.set_index_primitive_type(primitive_type);
+            } else {
+              layout.set_pointer_primitive_type(primitive_type);
+            }
+          },
+          _ => return self.token_error("unexpected token in layout")
+        }
+      }
+    }
+    self.parse_token(TokKind::Rbrace, "expects '}' at the end of layout")
+  }
+
+  // Parses one of the D/T/L/E/S/P/M items of a layout, after the letter.
+  fn parse_layout_item(&mut self, item: &String, layout: &mut Layout) -> bool {
+    match item.as_str() {
+      "D" => self.parse_dim_level_types(layout),
+      "T" => {
+        // One or more tiles, each '(' dims ')'.
+        if self.lexer.get_kind() != TokKind::Lparen {
+          return self.token_error("expects '(' after T");
+        }
+        while self.lexer.get_kind() == TokKind::Lparen {
+          let mut tile = vec![];
+          if !self.parse_tile_dimensions(&mut tile) {
+            return false;
+          }
+          layout.add_tiles(Tile::new(tile));
+        }
+        true
+      },
+      "L" | "E" | "S" | "M" => {
+        let mut value = 0;
+        if !self.parse_token(TokKind::Lparen, &format!("expects '(' after {}", item)) ||
+           !self.parse_int64(&mut value) ||
+           !self.parse_token(TokKind::Rparen, "expects ')'")
+        {
+          return false;
+        }
+        match item.as_str() {
+          "L" => layout.set_tail_padding_alignment_in_elements(value),
+          "E" => layout.set_element_size_in_bits(value),
+          "S" => layout.set_memory_space(value),
+          _ => layout.set_dynamic_shape_metadata_prefix_bytes(value),
+        }
+        true
+      },
+      "P" => {
+        let mut physical_shape = Shape::new();
+        if !self.parse_token(TokKind::Lparen, "expects '(' after P") ||
+           !self.parse_shape(&mut physical_shape) ||
+           !self.parse_token(TokKind::Rparen, "expects ')'")
+        {
+          return false;
+        }
+        layout.set_physical_shape(physical_shape);
+        true
+      },
+      _ => self.token_error(&format!("unexpected layout attribute {}", item))
+    }
+  }
+
+  // dim_level_types ::= '(' dim_level_type (',' dim_level_type)* ')'
+  // dim_level_type ::= ('D' | 'C' | 'S' | 'H') '+'? '~'?
+  fn parse_dim_level_types(&mut self, layout: &mut Layout) -> bool {
+    if !self.parse_token(TokKind::Lparen, "expects '(' after D") {
+      return false;
+    }
+    let mut level_types = vec![];
+    let mut unique = vec![];
+    let mut ordered = vec![];
+    if self.lexer.get_kind() != TokKind::Rparen {
+      loop {
+        if self.lexer.get_kind() != TokKind::Ident {
+          return self.token_error("expects dimension level type");
+        }
+        let level_type = match self.lexer.get_str_val().as_str() {
+          "D" => DimLevelType::Dense,
+          "C" => DimLevelType::Compressed,
+          "S" => DimLevelType::Singleton,
+          "H" => DimLevelType::LooseCompressed,
+          other => return self.token_error(
+            &format!("invalid dimension level type {}", other))
+        };
+        self.lexer.lex();
+        level_types.push(level_type);
+        unique.push(!self.eat_if_present(TokKind::Plus));
+        ordered.push(!self.eat_if_present(TokKind::Tilde));
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    if !self.parse_token(TokKind::Rparen, "expects ')' after dimension level types") {
+      return false;
+    }
+    for level_type in level_types {
+      layout.add_dim_level_type(level_type);
+    }
+    // Uniqueness and ordering are only recorded when some dimension differs
+    // from the default.
+    if unique.iter().any(|u| !u) {
+      for u in unique {
+        layout.add_dim_unique(u);
+      }
+    }
+    if ordered.iter().any(|o| !o) {
+      for o in ordered {
+        layout.add_dim_ordered(o);
+      }
+    }
+    true
+  }
+
+  // tile ::= '(' (int64 | '*') (',' (int64 | '*'))* ')'
+  fn parse_tile_dimensions(&mut self, tile: &mut Vec<i64>) -> bool {
+    if !self.parse_token(TokKind::Lparen, "expects '(' at the beginning of tile") {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rparen {
+      loop {
+        if self.eat_if_present(TokKind::Asterisk) {
+          tile.push(Tile::COMBINE_DIMENSION);
+        } else {
+          let mut dim = 0;
+          if !self.parse_int64(&mut dim) {
+            return false;
+          }
+          tile.push(dim);
+        }
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    self.parse_token(TokKind::Rparen, "expects ')' at the end of tile")
+  }
+
+  // Parses the index or pointer primitive type of a layout, which must be an
+  // integral type.
+  fn parse_layout_primitive_type(&mut self, result: &mut PrimitiveType) -> bool {
+    if self.lexer.get_kind() != TokKind::PrimitiveType {
+      return self.token_error("expects primitive type");
+    }
+    let primitive_type = self.lexer.get_primitive_type_val();
+    if !primitive_util::is_integral_type(&primitive_type) {
+      return self.token_error("expects integral primitive type");
+    }
+    *result = primitive_type;
+    self.lexer.lex();
+    true
+  }
+
+  // sharding ::= '{' (single_sharding | tuple_sharding) '}'
+  // tuple_sharding ::= single_sharding* (',' single_sharding)*
+  fn parse_sharding(&mut self, sharding: &mut HloSharding) -> bool {
+    if self.lexer.get_kind() != TokKind::Lbrace {
+      return self.token_error("expected '{' to start sharding attribute");
+    }
+    let next = self.lexer.look_ahead();
+    if next != TokKind::Lbrace && next != TokKind::Rbrace {
+      return self.parse_single_sharding(sharding);
+    }
+    // A tuple sharding.
+    self.lexer.lex();
+    let mut elements = vec![];
+    if self.lexer.get_kind() != TokKind::Rbrace {
+      loop {
+        let mut element = HloSharding::replicate(vec![]);
+        if !self.parse_single_sharding(&mut element) {
+          return false;
+        }
+        elements.push(element);
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    *sharding = HloSharding::flat_tuple(elements);
+    self.parse_token(TokKind::Rbrace, "expected '}' to end sharding attribute")
+  }
+
+  //  single_sharding
+  //    ::= '{' 'replicated'? 'manual'? 'maximal'? 'unknown'? ('device=' int)?
+  //        shape? ('devices=' ('[' dims ']')* device_list)?
+  //        (metadata=metadata)* '}'
+  fn parse_single_sharding(&mut self, sharding: &mut HloSharding) -> bool {
+    let loc = self.lexer.get_loc();
+    if !self.parse_token(TokKind::Lbrace, "expected '{' to start sharding attribute") {
+      return false;
+    }
+    let mut maximal = false;
+    let mut replicated = false;
+    let mut manual = false;
+    let mut unknown = false;
+    let mut last_tile_dim_replicate = false;
+    let mut last_tile_dims = vec![];
+    let mut devices = vec![];
+    let mut tile_assignment_dims = vec![];
+    let mut iota_reshape_dims = vec![];
+    let mut iota_transpose_perm = vec![];
+    let mut metadata = vec![];
+    let mut shard_group = None;
+    while self.lexer.get_kind() != TokKind::Rbrace {
+      match self.lexer.get_kind() {
+        TokKind::Maximal => {
+          maximal = true;
+          self.lexer.lex();
+        },
+        TokKind::Replicated => {
+          replicated = true;
+          self.lexer.lex();
+        },
+        TokKind::Manual => {
+          manual = true;
+          self.lexer.lex();
+        },
+        TokKind::Unknown => {
+          unknown = true;
+          self.lexer.lex();
+        },
+        TokKind::LastTileDimReplicate => {
+          last_tile_dim_replicate = true;
+          self.lexer.lex();
+        },
+        TokKind::ShardAs | TokKind::ShardLike => {
+          let shard_as = self.lexer.get_kind() == TokKind::ShardAs;
+          self.lexer.lex();
+          let mut id = 0;
+          if !self.parse_int64(&mut id) {
+            return false;
+          }
+          shard_group = Some(if shard_as {
+            HloSharding::shard_as(id)
+          } else {
+            HloSharding::shard_like(id)
+          });
+        },
+        TokKind::AttributeName => {
+          let name = self.lexer.get_str_val();
+          self.lexer.lex();
+          match name.as_str() {
+            "device" => {
+              let mut device = 0;
+              if !self.parse_int64(&mut device) {
+                return false;
+              }
+              devices.push(device);
+            },
+            "devices" => {
+              if !self.parse_int64_list(TokKind::Lsquare, TokKind::Rsquare,
+                TokKind::Comma, &mut tile_assignment_dims)
+              {
+                return false;
+              }
+              if self.eat_if_present(TokKind::Leq) {
+                // Iota tile assignment: '[dims]<=[reshape_dims]T(perm)'.
+                if !self.parse_int64_list(TokKind::Lsquare, TokKind::Rsquare,
+                  TokKind::Comma, &mut iota_reshape_dims)
+                {
+                  return false;
+                }
+                if self.lexer.get_kind() == TokKind::Ident &&
+                   self.lexer.get_str_val() == "T"
+                {
+                  self.lexer.lex();
+                  if !self.parse_int64_list(TokKind::Lparen, TokKind::Rparen,
+                    TokKind::Comma, &mut iota_transpose_perm)
+                  {
+                    return false;
+                  }
+                }
+              } else {
+                loop {
+                  let mut device = 0;
+                  if !self.parse_int64(&mut device) {
+                    return false;
+                  }
+                  devices.push(device);
+                  if !self.eat_if_present(TokKind::Comma) {
+                    break;
+                  }
+                }
+              }
+            },
+            "last_tile_dims" => {
+              if !self.parse_token(TokKind::Lbrace, "expects '{' after last_tile_dims") {
+                return false;
+              }
+              loop {
+                let sharding_type = match self.lexer.get_kind() {
+                  TokKind::Maximal => OpShardingType::Maximal,
+                  TokKind::Replicated => OpShardingType::Replicated,
+                  TokKind::Manual => OpShardingType::Manual,
+                  _ => return self.token_error("expects sharding type")
+                };
+                self.lexer.lex();
+                last_tile_dims.push(sharding_type);
+                if !self.eat_if_present(TokKind::Comma) {
+                  break;
+                }
+              }
+              if !self.parse_token(TokKind::Rbrace, "expects '}' after last_tile_dims") {
+                return false;
+              }
+            },
+            "metadata" => {
+              if !self.parse_sharding_metadata(&mut metadata) {
+                return false;
+              }
+            },
+            _ => return self.token_error(&format!("unknown sharding attribute {}", name))
+          }
+        },
+        TokKind::Rbrace => break,
+        _ => return self.token_error("unexpected token")
+      }
+    }
+
+    if replicated {
+      if !devices.is_empty() || !tile_assignment_dims.is_empty() {
+        return self.error(loc, "replicated shardings should not have any devices assigned");
+      }
+      *sharding = HloSharding::replicate(metadata);
+    } else if manual {
+      if !devices.is_empty() || !tile_assignment_dims.is_empty() {
+        return self.error(loc, "manual shardings should not have any devices assigned");
+      }
+      *sharding = HloSharding::manual(metadata);
+    } else if unknown {
+      if !devices.is_empty() || !tile_assignment_dims.is_empty() {
+        return self.error(loc, "unknown shardings should not have any devices assigned");
+      }
+      *sharding = HloSharding::unknown(metadata);
+    } else if maximal {
+      if devices.len() != 1 {
+        return self.error(loc,
+          "maximal shardings should have exactly one device assigned");
+      }
+      *sharding = HloSharding::assign_device(devices[0], metadata);
+    } else {
+      if tile_assignment_dims.is_empty() {
+        return self.error(loc,
+          "non-maximal shardings must have a tile assignment list including dimensions");
+      }
+      let num_devices: i64 = tile_assignment_dims.iter().product();
+      let tile_assignment = if !iota_reshape_dims.is_empty() {
+        if iota_reshape_dims.iter().product::<i64>() != num_devices {
+          return self.error(loc,
+            "iota reshape dimensions do not match the number of devices");
+        }
+        if iota_transpose_perm.is_empty() {
+          iota_transpose_perm = (0..iota_reshape_dims.len() as i64).collect();
+        } else if iota_transpose_perm.len() != iota_reshape_dims.len() {
+          return self.error(loc,
+            "iota transpose permutation must match the reshape dimensions");
+        }
+        TileAssignment::new_from_iota(IotaTileAssignment::new_detail(
+          &tile_assignment_dims, &iota_reshape_dims, &iota_transpose_perm))
+      } else {
+        if devices.len() as i64 != num_devices {
+          return self.error(loc, &format!(
+            "Number of tile assignment devices {} does not match the product of \
+            tile assignment dimensions {}", devices.len(), num_devices));
+        }
+        let mut array = Array::new(tile_assignment_dims.clone());
+        let mut next = 0;
+        array.each(&mut |_, value: &mut i64| {
+          *value = devices[next];
+          next += 1;
+        });
+        TileAssignment::new_from_array(array)
+      };
+      if last_tile_dim_replicate {
+        *sharding = HloSharding::partial_tile(tile_assignment, metadata);
+      } else if !last_tile_dims.is_empty() {
+        *sharding = HloSharding::subgroup(tile_assignment, last_tile_dims, metadata);
+      } else {
+        *sharding = HloSharding::tile(tile_assignment, metadata);
+      }
+    }
+    if let Some(shard_group) = shard_group {
+      sharding.set_shard_group(shard_group);
+    }
+    self.lexer.lex();
+    true
+  }
+
+  // metadata ::= single_metadata |
+  //              ('{' [single_metadata (',' single_metadata)*] '}')
+  fn parse_sharding_metadata(&mut self, metadata: &mut Vec<OpMetadata>) -> bool {
+    if self.lexer.get_kind() != TokKind::Lbrace {
+      return self.token_error("expected '{' to start metadata");
     }
-    self.parse_token(&TokKind::Rbrace,
-      "expected '}' to end sharding type list".to_string())
+    let next = self.lexer.look_ahead();
+    if next != TokKind::Lbrace && next != TokKind::Rbrace {
+      let mut single = OpMetadata::new();
+      if !self.parse_metadata(&mut single) {
+        return false;
+      }
+      metadata.push(single);
+      return true;
+    }
+    self.lexer.lex();
+    if self.lexer.get_kind() != TokKind::Rbrace {
+      loop {
+        let mut single = OpMetadata::new();
+        if !self.parse_metadata(&mut single) {
+          return false;
+        }
+        metadata.push(single);
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    self.parse_token(TokKind::Rbrace, "expected '}' to end metadata")
   }
 
-  fn parse_sharding() {}
+  // metadata ::= '{' (attribute_name '=' value)* '}'
+  fn parse_metadata(&mut self, metadata: &mut OpMetadata) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expected '{' to start metadata") {
+      return false;
+    }
+    while self.lexer.get_kind() != TokKind::Rbrace {
+      let loc = self.lexer.get_loc();
+      let mut name = "".to_string();
+      if !self.parse_attribute_name(&mut name) {
+        return false;
+      }
+      match name.as_str() {
+        "op_type" | "op_name" | "source_file" | "deduplicated_name" |
+        "scheduling_name" => {
+          let mut value = "".to_string();
+          if !self.parse_string(&mut value) {
+            return false;
+          }
+          match name.as_str() {
+            "op_type" => metadata.set_op_type(value),
+            "op_name" => metadata.set_op_name(value),
+            "source_file" => metadata.set_source_file(value),
+            "deduplicated_name" => metadata.set_deduplicated_name(value),
+            _ => metadata.set_scheduling_name(value),
+          }
+        },
+        "source_line" | "stack_frame_id" => {
+          let mut value = 0;
+          if !self.parse_int64(&mut value) {
+            return false;
+          }
+          if name == "source_line" {
+            metadata.set_source_line(value);
+          } else {
+            metadata.set_stack_frame_id(value);
+          }
+        },
+        "preserve_layout" => {
+          let mut value = false;
+          if !self.parse_bool(&mut value) {
+            return false;
+          }
+          metadata.set_preserve_layout(value);
+        },
+        _ => return self.error(loc, &format!("invalid metadata attribute {}", name))
+      }
+      // Metadata attributes are separated by spaces, but accept commas too.
+      self.eat_if_present(TokKind::Comma);
+    }
+    self.parse_token(TokKind::Rbrace, "expects '}' at the end of metadata")
+  }
 
   // frontend_attributes ::= '{' attributes '}'
   // attributes
   //   ::= /*empty*/
   //   ::= attribute '=' value (',' attribute '=' value)*
-  fn parse_frontend_attributes(
-    &mut self, _frontend_attributes: &FrontendAttributes) -> bool
-  {
-    if self.parse_token(&TokKind::Lbrace,
-      "expected '{' to start frontend attributes".to_string()) {
+  fn parse_frontend_attributes(&mut self, attributes: &mut FrontendAttributes) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expected '{' to start frontend attributes") {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rbrace {
+      loop {
+        let mut key = "".to_string();
+        if self.lexer.get_kind() == TokKind::String {
+          key = self.lexer.get_str_val();
+          self.lexer.lex();
+          if !self.parse_token(TokKind::Equal, "expects '='") {
+            return false;
+          }
+        } else if !self.parse_attribute_name(&mut key) {
+          return false;
+        }
+        let mut value = "".to_string();
+        if !self.parse_string(&mut value) {
+          return false;
+        }
+        attributes.set_attribute(key, value);
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    self.parse_token(TokKind::Rbrace, "expects '}' at the end of frontend attributes")
+  }
+
+  // statistics
+  //   ::= '{' /*empty*/ '}'
+  //   ::= '{' index, single_statistic '}'
+  // index ::= 'visualizing_index=' value
+  // single_statistic ::= statistic '=' value (',' statistic '=' value)*
+  fn parse_statistics_viz(&mut self, statistics_viz: &mut StatisticsViz) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expected '{' to start statistics") {
       return false;
     }
     if self.lexer.get_kind() == TokKind::Rbrace {
-      // empty
-    } else {
+      self.lexer.lex();
+      return true;
+    }
+    let mut name = "".to_string();
+    if !self.parse_attribute_name(&mut name) {
+      return false;
+    }
+    if name != "visualizing_index" {
+      return self.token_error("expects 'visualizing_index' as the first statistic");
+    }
+    let mut index = 0;
+    if !self.parse_int64(&mut index) {
+      return false;
+    }
+    statistics_viz.set_stat_index_to_visualize(index);
+    while self.eat_if_present(TokKind::Comma) {
+      let mut stat_name = "".to_string();
+      if !self.parse_attribute_name(&mut stat_name) {
+        return false;
+      }
+      let mut value = 0.0;
+      if !self.parse_double(&mut value) {
+        return false;
+      }
+      let mut statistic = Statisitic::new();
+      statistic.set_stat_name(stat_name);
+      // TODO: keep the fractional part once Statisitic holds an f64.
+      statistic.set_stat_val(value as i64);
+      statistics_viz.add_statistics(statistic);
+    }
+    self.parse_token(TokKind::Rbrace, "expects '}' at the end of statistics")
+  }
+
+  // parameter_replication ::=
+  //   '{' ('true' | 'false')* (',' ('true' | 'false'))*  '}'
+  fn parse_parameter_replication(&mut self, replication: &mut Vec<bool>) -> bool {
+    self.parse_bool_list(replication)
+  }
+
+  fn parse_bool_list(&mut self, values: &mut Vec<bool>) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expected '{' to start a list of bools") {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rbrace {
       loop {
-        let mut attribute = "".to_string();
-        if !self.parse_attribute_name(&mut attribute) { return false; }
-        if self.lexer.get_kind() != TokKind::String { return false; }
-        // TODO
-        // frontend_attributes.mutable_map
+        let mut value = false;
+        if !self.parse_bool(&mut value) {
+          return false;
+        }
+        values.push(value);
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    self.parse_token(TokKind::Rbrace, "expected '}' to end a list of bools")
+  }
+
+  // replica_groups ::='{' int64_list_elements '}'
+  // int64_list_elements
+  //   ::= /*empty*/
+  //   ::= int64_list (',' int64_list)*
+  // int64_list ::= '{' int64_elements '}'
+  // int64_elements
+  //   ::= /*empty*/
+  //   ::= int64_val (',' int64_val)*
+  //
+  // or the iota form '[num_groups,group_size]<=[dims]T(perm)'.
+  fn parse_replica_groups(&mut self, replica_groups: &mut Vec<ReplicaGroup>) -> bool {
+    if self.lexer.get_kind() == TokKind::Lsquare {
+      let loc = self.lexer.get_loc();
+      let mut shape = vec![];
+      let mut reshape_dims = vec![];
+      let mut perm = vec![];
+      if !self.parse_int64_list(TokKind::Lsquare, TokKind::Rsquare,
+          TokKind::Comma, &mut shape) ||
+         !self.parse_token(TokKind::Leq, "expects '<=' in iota replica groups") ||
+         !self.parse_int64_list(TokKind::Lsquare, TokKind::Rsquare,
+          TokKind::Comma, &mut reshape_dims)
+      {
+        return false;
+      }
+      if self.lexer.get_kind() == TokKind::Ident && self.lexer.get_str_val() == "T" {
+        self.lexer.lex();
+        if !self.parse_int64_list(TokKind::Lparen, TokKind::Rparen,
+          TokKind::Comma, &mut perm)
+        {
+          return false;
+        }
+      } else {
+        perm = (0..reshape_dims.len() as i64).collect();
+      }
+      if shape.len() != 2 {
+        return self.error(loc, "expects iota replica groups of rank 2");
+      }
+      if perm.len() != reshape_dims.len() ||
+         shape[0] * shape[1] != reshape_dims.iter().product::<i64>()
+      {
+        return self.error(loc, "iota replica group dimensions do not match");
+      }
+      *replica_groups = expand_iota_replica_groups(shape[0], shape[1], &reshape_dims, &perm);
+      return true;
+    }
+    let mut groups = vec![];
+    if !self.parse_int64_list_list(&mut groups) {
+      return false;
+    }
+    for ids in groups {
+      let mut group = ReplicaGroup::new();
+      *group.mutable_replica_ids() = ids;
+      replica_groups.push(group);
+    }
+    true
+  }
+
+  // window ::= '{' size stride? pad? lhs_dilate? rhs_dilate? '}'
+  // The subattributes can appear in any order. 'size=' is required, others are
+  // optional.
+  fn parse_window(&mut self, window: &mut Window, expect_outer_curlies: bool) -> bool {
+    let loc = self.lexer.get_loc();
+    if expect_outer_curlies &&
+       !self.parse_token(TokKind::Lbrace, "expected '{' to start window attribute")
+    {
+      return false;
+    }
+    let mut size = vec![];
+    let mut stride = vec![];
+    let mut pad = vec![];
+    let mut lhs_dilate = vec![];
+    let mut rhs_dilate = vec![];
+    let mut rhs_reversal = vec![];
+    let end_token = if expect_outer_curlies { TokKind::Rbrace } else { TokKind::Eof };
+    while self.lexer.get_kind() != end_token {
+      let attr_loc = self.lexer.get_loc();
+      let mut field_name = "".to_string();
+      if !self.parse_attribute_name(&mut field_name) {
+        return self.token_error("expects sub-attributes in window");
+      }
+      let ok = match field_name.as_str() {
+        "size" => self.parse_dxd(&mut size),
+        "stride" => self.parse_dxd(&mut stride),
+        "lhs_dilate" => self.parse_dxd(&mut lhs_dilate),
+        "rhs_dilate" => self.parse_dxd(&mut rhs_dilate),
+        "rhs_reversal" => self.parse_dxd(&mut rhs_reversal),
+        "pad" => self.parse_window_pad(&mut pad),
+        _ => return self.error(attr_loc,
+          &format!("unexpected attribute name: {}", field_name))
+      };
+      if !ok {
+        return false;
+      }
+    }
+    if !stride.is_empty() && stride.len() != size.len() {
+      return self.error(loc, "expects 'stride=' has the same size as 'size='");
+    }
+    if !lhs_dilate.is_empty() && lhs_dilate.len() != size.len() {
+      return self.error(loc, "expects 'lhs_dilate=' has the same size as 'size='");
+    }
+    if !rhs_dilate.is_empty() && rhs_dilate.len() != size.len() {
+      return self.error(loc, "expects 'rhs_dilate=' has the same size as 'size='");
+    }
+    if !rhs_reversal.is_empty() && rhs_reversal.len() != size.len() {
+      return self.error(loc, "expects 'rhs_reversal=' has the same size as 'size='");
+    }
+    if !pad.is_empty() && pad.len() != size.len() {
+      return self.error(loc, "expects 'pad=' has the same size as 'size='");
+    }
+    for i in 0..size.len() {
+      let mut dim = WindowDimension::new();
+      dim.set_size(size[i]);
+      if !pad.is_empty() {
+        dim.set_padding_low(pad[i][0]);
+        dim.set_padding_high(pad[i][1]);
+      }
+      if !stride.is_empty() { dim.set_stride(stride[i]); }
+      if !lhs_dilate.is_empty() { dim.set_base_dilation(lhs_dilate[i]); }
+      if !rhs_dilate.is_empty() { dim.set_window_dilation(rhs_dilate[i]); }
+      if !rhs_reversal.is_empty() { dim.set_window_reversal(rhs_reversal[i] == 1); }
+      window.add_dimensions(dim);
+    }
+    if expect_outer_curlies {
+      return self.parse_token(TokKind::Rbrace, "expected '}' to end window attribute");
+    }
+    true
+  }
+
+  // dxd ::= int64 ('x' int64)*
+  fn parse_dxd(&mut self, result: &mut Vec<i64>) -> bool {
+    match self.lexer.get_kind() {
+      TokKind::Int => {
+        result.push(self.lexer.get_i64_val());
         self.lexer.lex();
-        if !self.eat_if_present(&TokKind::Comma) { break; }
+        true
+      },
+      TokKind::DxD => {
+        for dim in self.lexer.get_str_val().split('x') {
+          match dim.parse::<i64>() {
+            Ok(value) => result.push(value),
+            Err(_) => return self.token_error("expects sub-attribute in NxM format")
+          }
+        }
+        self.lexer.lex();
+        true
+      },
+      _ => self.token_error("expects token type kInt or kDxD")
+    }
+  }
+
+  // pad ::= low '_' high ('x' low '_' high)*
+  fn parse_window_pad(&mut self, pad: &mut Vec<Vec<i64>>) -> bool {
+    if self.lexer.get_kind() != TokKind::Pad {
+      return self.token_error("expects padding in low_high format");
+    }
+    for padding_str in self.lexer.get_str_val().split('x') {
+      let low_high: Vec<Option<i64>> =
+        padding_str.split('_').map(|s| s.parse::<i64>().ok()).collect();
+      if low_high.len() != 2 || low_high.iter().any(|v| v.is_none()) {
+        return self.token_error("expects padding_low and padding_high separated by '_'");
+      }
+      pad.push(low_high.into_iter().map(|v| v.unwrap()).collect());
+    }
+    self.lexer.lex();
+    true
+  }
+
+  // This is the inverse of convolution_dimension_numbers_to_string. Thestring
+  // looks like "b01f_01io->b01f".
+  fn parse_convolution_dimension_numbers(
+    &mut self, dnums: &mut ConvolutionDimensionNumbers) -> bool
+  {
+    if self.lexer.get_kind() != TokKind::DimLabels {
+      return self.token_error("expects dim labels pattern, e.g., 'bf0_0io->0bf'");
+    }
+    let str = self.lexer.get_str_val();
+    let split1: Vec<&str> = str.split('_').collect();
+    if split1.len() != 2 {
+      return self.token_error(&format!(
+        "expects 3 items: lhs, rhs, and output dims, but sees {}", str));
+    }
+    let split2: Vec<&str> = split1[1].split("->").collect();
+    if split2.len() != 2 {
+      return self.token_error(&format!(
+        "expects 3 items: lhs, rhs, and output dims, but sees {}", str));
+    }
+    let (lhs, rhs, out) = (split1[0], split2[0], split2[1]);
+    let rank = lhs.len();
+    if rank != rhs.len() || rank != out.len() || rank < 2 {
+      return self.token_error(&format!(
+        "convolution lhs, rhs, and output must have the same rank of at least 2, \
+        but sees {}", str));
+    }
+
+    let mut lhs_spatial = vec![-1; rank - 2];
+    for (i, c) in lhs.chars().enumerate() {
+      match c {
+        'b' => dnums.set_input_batch_dimension(i as i64),
+        'f' => dnums.set_input_feature_dimension(i as i64),
+        _ if c.is_ascii_digit() && (c as usize - '0' as usize) < rank - 2 => {
+          lhs_spatial[c as usize - '0' as usize] = i as i64;
+        },
+        _ => return self.token_error(&format!(
+          "expects [0-{}bf] in lhs dimension numbers", rank - 3))
+      }
+    }
+    let mut rhs_spatial = vec![-1; rank - 2];
+    for (i, c) in rhs.chars().enumerate() {
+      match c {
+        'i' => dnums.set_kernel_input_feature_dimension(i as i64),
+        'o' => dnums.set_kernel_output_feature_dimension(i as i64),
+        _ if c.is_ascii_digit() && (c as usize - '0' as usize) < rank - 2 => {
+          rhs_spatial[c as usize - '0' as usize] = i as i64;
+        },
+        _ => return self.token_error(&format!(
+          "expects [0-{}io] in rhs dimension numbers", rank - 3))
+      }
+    }
+    let mut out_spatial = vec![-1; rank - 2];
+    for (i, c) in out.chars().enumerate() {
+      match c {
+        'b' => dnums.set_output_batch_dimension(i as i64),
+        'f' => dnums.set_output_feature_dimension(i as i64),
+        _ if c.is_ascii_digit() && (c as usize - '0' as usize) < rank - 2 => {
+          out_spatial[c as usize - '0' as usize] = i as i64;
+        },
+        _ => return self.token_error(&format!(
+          "expects [0-{}bf] in output dimension numbers", rank - 3))
+      }
+    }
+    if lhs_spatial.contains(&-1) || rhs_spatial.contains(&-1) ||
+       out_spatial.contains(&-1)
+    {
+      return self.token_error(&format!("missing spatial dimension in {}", str));
+    }
+    for dim in lhs_spatial { dnums.add_input_spatial_dimensions(dim); }
+    for dim in rhs_spatial { dnums.add_kernel_spatial_dimensions(dim); }
+    for dim in out_spatial { dnums.add_output_spatial_dimensions(dim); }
+    self.lexer.lex();
+    true
+  }
+
+  // padding_config ::= padding_dim ('x' padding_dim)*
+  // padding_dim ::= int64 '_' int64 ('_' int64)?
+  fn parse_padding_config(&mut self, padding: &mut PaddingConfig) -> bool {
+    if self.lexer.get_kind() != TokKind::Pad {
+      return self.token_error("expects padding config, e.g., '0_0_0x3_3_1'");
+    }
+    for padding_dim_str in self.lexer.get_str_val().split('x') {
+      let values: Vec<Option<i64>> =
+        padding_dim_str.split('_').map(|s| s.parse::<i64>().ok()).collect();
+      if values.len() < 2 || values.len() > 3 || values.iter().any(|v| v.is_none()) {
+        return self.token_error(
+          "expects padding config pattern like 'low_high_interior' or 'low_high'");
+      }
+      let mut dim = PaddingConfigDimension::new();
+      dim.set_edge_padding_low(values[0].unwrap());
+      dim.set_edge_padding_high(values[1].unwrap());
+      dim.set_interior_padding(if values.len() == 3 { values[2].unwrap() } else { 0 });
+      padding.add_dimensions(dim);
+    }
+    self.lexer.lex();
+    true
+  }
+
+  // slice_ranges ::= '{' ranges '}'
+  // ranges
+  //   ::= /*empty*/
+  //   ::= range (',' range)*
+  // range ::= '[' int64 ':' int64 (':' int64)? ']'
+  fn parse_slice_ranges(&mut self, ranges: &mut SliceRange) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expects '{' to start ranges") {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rbrace {
+      loop {
+        let loc = self.lexer.get_loc();
+        let mut range = vec![];
+        if !self.parse_int64_list(TokKind::Lsquare, TokKind::Rsquare,
+          TokKind::Colon, &mut range)
+        {
+          return false;
+        }
+        if range.len() != 2 && range.len() != 3 {
+          return self.error(loc, &format!(
+            "expects [start:limit:step] or [start:limit], but sees {} elements.",
+            range.len()));
+        }
+        ranges.starts.push(range[0]);
+        ranges.limits.push(range[1]);
+        ranges.strides.push(if range.len() == 3 { range[2] } else { 1 });
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    self.parse_token(TokKind::Rbrace, "expects '}' to end ranges")
+  }
+
+  // domain ::= '{' 'kind=' domain_kind ',' 'entry=' entry_sharding ','
+  //            'exit=' exit_sharding '}'
+  fn parse_domain(&mut self, domain: &mut Option<DomainData>) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expected '{' to start domain attribute") {
+      return false;
+    }
+    let mut kind = None;
+    let mut entry = None;
+    let mut exit = None;
+    while self.lexer.get_kind() != TokKind::Rbrace {
+      let loc = self.lexer.get_loc();
+      let mut name = "".to_string();
+      if !self.parse_attribute_name(&mut name) {
+        return false;
+      }
+      match name.as_str() {
+        "kind" => {
+          let mut value = "".to_string();
+          if !self.parse_string(&mut value) {
+            return false;
+          }
+          kind = Some(value);
+        },
+        "entry" | "exit" => {
+          let mut sharding = HloSharding::replicate(vec![]);
+          if !self.parse_sharding(&mut sharding) {
+            return false;
+          }
+          if name == "entry" { entry = Some(sharding); } else { exit = Some(sharding); }
+        },
+        _ => return self.error(loc, &format!("unexpected domain attribute {}", name))
+      }
+      self.eat_if_present(TokKind::Comma);
+    }
+    let loc = self.lexer.get_loc();
+    if !self.parse_token(TokKind::Rbrace, "expected '}' to end domain attribute") {
+      return false;
+    }
+    match kind {
+      Some(kind) if kind == "sharding" => {
+        if entry.is_none() || exit.is_none() {
+          return self.error(loc, "domain expects both entry and exit shardings");
+        }
+        *domain = Some(DomainData {
+          entry_metadata: DomainMetadata::new(kind.clone(), entry),
+          exit_metadata: DomainMetadata::new(kind, exit)
+        });
+        true
+      },
+      Some(kind) => self.error(loc, &format!("unsupported domain kind: {}", kind)),
+      None => self.error(loc, "domain expects a kind")
+    }
+  }
+
+  // precision_list ::= '{' precision (',' precision)* '}'
+  fn parse_precision_list(&mut self, precisions: &mut Vec<Precision>) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expects '{' to start precision list") {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rbrace {
+      loop {
+        let name = match self.parse_enum_ident("precision") {
+          Some(name) => name,
+          None => return false
+        };
+        match self.convert_enum(string_to_precision(&name)) {
+          Some(precision) => precisions.push(precision),
+          None => return false
+        }
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    self.parse_token(TokKind::Rbrace, "expects '}' to end precision list")
+  }
+
+  // shape_list ::= '{' (shape (',' shape)*)? '}'
+  fn parse_shape_list(&mut self, shapes: &mut Vec<Shape>) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expects '{' to start shape list") {
+      return false;
+    }
+    if self.lexer.get_kind() != TokKind::Rbrace {
+      loop {
+        let mut shape = Shape::new();
+        if !self.parse_shape(&mut shape) {
+          return false;
+        }
+        shapes.push(shape);
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
       }
     }
-    self.parse_token(&TokKind::Rbrace,
-      "expects '}' at the end of frontend attributes".to_string())
+    self.parse_token(TokKind::Rbrace, "expects '}' to end shape list")
   }
 
-  fn parse_statistics_viz(&mut self, _statistics_viz: &StatisticsViz) -> bool {
-    unimplemented!()
+  // shape_index ::= '{' (int64 (',' int64)*)? '}'
+  fn parse_shape_index(&mut self, index: &mut Vec<i64>) -> bool {
+    self.parse_int64_list(TokKind::Lbrace, TokKind::Rbrace, TokKind::Comma, index)
   }
 
-  fn parse_single_sharding() {}
-
-  // parameter_replication ::=
-  //   '{' ('true' | 'false')* (',' ('true' | 'false'))*  '}'
-  fn parse_parameter_replication(
-    &mut self, parameter_replication: &mut ParameterReplication) -> bool
-  {
-    if !self.parse_token(&TokKind::Lbrace,
-      "expected '{' to start parameter_replication attribute".to_string()) {
+  // aliasing ::= '{' (single_alias (',' single_alias)*)? '}'
+  // single_alias ::= shape_index ':' '(' int64 ',' shape_index
+  //   (',' ('may-alias' | 'must-alias'))? ')'
+  fn parse_aliasing(&mut self, aliasing: &mut Vec<AliasEntry>) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expects '{' to start aliasing description") {
       return false;
     }
     if self.lexer.get_kind() != TokKind::Rbrace {
       loop {
-        if self.lexer.get_kind() == TokKind::True {
-          parameter_replication.add_replicated_at_leaf_buffers(true);
-        } else if self.lexer.get_kind() == TokKind::False {
-          parameter_replication.add_replicated_at_leaf_buffers(false);
-        } else {
+        let mut output_index = vec![];
+        let mut param_number = 0;
+        let mut param_index = vec![];
+        if !self.parse_shape_index(&mut output_index) ||
+           !self.parse_token(TokKind::Colon, "expects ':' after output index") ||
+           !self.parse_token(TokKind::Lparen, "expects '(' before parameter") ||
+           !self.parse_int64(&mut param_number) ||
+           !self.parse_token(TokKind::Comma, "expects ',' after parameter number") ||
+           !self.parse_shape_index(&mut param_index)
+        {
           return false;
         }
-        self.lexer.lex();
-        if !self.eat_if_present(&TokKind::Comma) { break; }
+        let mut kind = AliasKind::May;
+        if self.eat_if_present(TokKind::Comma) {
+          if self.lexer.get_kind() != TokKind::Ident {
+            return self.token_error("expects 'may-alias' or 'must-alias'");
+          }
+          kind = match self.lexer.get_str_val().as_str() {
+            "may-alias" => AliasKind::May,
+            "must-alias" => AliasKind::Must,
+            _ => return self.token_error("expects 'may-alias' or 'must-alias'")
+          };
+          self.lexer.lex();
+        }
+        if !self.parse_token(TokKind::Rparen, "expects ')' after aliasing") {
+          return false;
+        }
+        aliasing.push((output_index, param_number, param_index, kind));
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
       }
     }
-    self.parse_token(&TokKind::Rbrace,
-      "expects '}' at the end parameter_replication attribute".to_string())
+    self.parse_token(TokKind::Rbrace, "expects '}' at the end of aliasing description")
   }
 
-  // boolean_list ::=
-  //   ('true' | 'false') | ('{' ('true' | 'false')* (',' ('true' | 'false'))*
-  //   '}')
-  fn parse_boolean_list_or_single_boolean(
-    &mut self, booleans: &mut Vec<bool>) -> bool
-  {
-    if self.lexer.get_kind() != TokKind::Lbrace &&
-       self.lexer.get_kind() != TokKind::True &&
-       self.lexer.get_kind() != TokKind::False
-    {
-      return self.token_error(
-        "Expected list of booleans or true/false value".to_string());
-    }
-    if self.parse_boolean(booleans) {
-      return true;
-    }
-    if !self.parse_token(&TokKind::Lbrace,
-      "expected '{' to start boolean list attribute".to_string())
-    {
-      return  false;
+  // buffer_donor ::= '{' (single_buffer_donor (',' single_buffer_donor)*)? '}'
+  // single_buffer_donor ::= '(' int64 ',' shape_index ')'
+  fn parse_buffer_donor(&mut self, donors: &mut Vec<(i64, Vec<i64>)>) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expects '{' to start buffer donor description") {
+      return false;
     }
     if self.lexer.get_kind() != TokKind::Rbrace {
       loop {
-        if !self.parse_boolean(booleans) { return false; }
-        if !self.eat_if_present(&TokKind::Comma) { break; }
+        let mut param_number = 0;
+        let mut param_index = vec![];
+        if !self.parse_token(TokKind::Lparen, "expects '(' before buffer donor") ||
+           !self.parse_int64(&mut param_number) ||
+           !self.parse_token(TokKind::Comma, "expects ',' after parameter number") ||
+           !self.parse_shape_index(&mut param_index) ||
+           !self.parse_token(TokKind::Rparen, "expects ')' after buffer donor")
+        {
+          return false;
+        }
+        donors.push((param_number, param_index));
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
       }
     }
-
-    self.parse_token(&TokKind::Rbrace,
-      "expected '}' to end boolean list attribute".to_string())
+    self.parse_token(TokKind::Rbrace, "expects '}' at the end of buffer donor description")
   }
 
-  fn parse_boolean(&mut self, boolean_list: &mut Vec<bool>) -> bool {
-    if self.lexer.get_kind() == TokKind::True {
-      boolean_list.push(true);
-      self.lexer.lex();
-      return true;
-    } else if self.lexer.get_kind() == TokKind::False {
-      boolean_list.push(false);
-      self.lexer.lex();
-      return true;
+  // computation_layout ::= '{' '(' (shape (',' shape)*)? ')' '->' shape '}'
+  fn parse_computation_layout(
+    &mut self, parameters: &mut Vec<Shape>, result: &mut Shape) -> bool
+  {
+    if !self.parse_token(TokKind::Lbrace, "expects '{' to start computation layout") {
+      return false;
     }
-    false
+    // The parameter list has the same syntax as a tuple shape.
+    let mut parameter_tuple = Shape::new();
+    if self.lexer.get_kind() != TokKind::Lparen {
+      return self.token_error("expects '(' before the parameter shapes");
+    }
+    if !self.parse_shape(&mut parameter_tuple) ||
+       !self.parse_token(TokKind::Arrow, "expects '->'") ||
+       !self.parse_shape(result)
+    {
+      return false;
+    }
+    *parameters = parameter_tuple.tuple_shapes_vec().clone();
+    self.parse_token(TokKind::Rbrace, "expects '}' at the end of computation layout")
   }
 
-  //fn parse_replica_groups_only() {}
-
-  fn parse_domain() {}
-  fn parse_dxd() {}
-  fn parse_window_pad() {}
-  fn parse_slice_ranges() {}
-  fn parse_precision_list() {}
-  fn parse_hlo_computation() {}
-  fn parse_hlo_computation_list() {}
-  fn parse_shape_list() {}
-  fn parse_int64_list_list() {}
-
-  fn parse_list(
-    &mut self,
-    start: &TokKind,
-    end: &TokKind,
-    delim: &TokKind,
-    mut parse_and_add_item: Box<dyn FnMut()->bool>) -> bool
+  // instruction_aliasing ::= '{' (single_alias (',' single_alias)*)? '}'
+  // single_alias ::= shape_index ':' '(' int64 ',' shape_index ')'
+  fn parse_instruction_output_operand_aliasing(
+    &mut self, aliasing: &mut Vec<(Vec<i64>, (i64, Vec<i64>))>) -> bool
   {
-    let mut err_msg = "expects a list starting with ".to_string();
-    err_msg.push_str(&tok_kind_to_string(start));
-    if !self.parse_token(start, err_msg) {
+    if !self.parse_token(TokKind::Lbrace,
+      "expects '{' to start operand aliasing description")
+    {
       return false;
     }
-
-    if self.lexer.get_kind() == *end {
-      // empty
-    } else {
+    if self.lexer.get_kind() != TokKind::Rbrace {
       loop {
-        if !parse_and_add_item() { return false; }
-        if !self.eat_if_present(delim) { break; }
+        let mut output_index = vec![];
+        let mut operand_number = 0;
+        let mut operand_index = vec![];
+        if !self.parse_shape_index(&mut output_index) ||
+           !self.parse_token(TokKind::Colon, "expects ':' after output index") ||
+           !self.parse_token(TokKind::Lparen, "expects '(' before operand") ||
+           !self.parse_int64(&mut operand_number) ||
+           !self.parse_token(TokKind::Comma, "expects ',' after operand number") ||
+           !self.parse_shape_index(&mut operand_index) ||
+           !self.parse_token(TokKind::Rparen, "expects ')' after aliasing")
+        {
+          return false;
+        }
+        aliasing.push((output_index, (operand_number, operand_index)));
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
       }
     }
-
-    let mut err_msg = "expects a list to end with ".to_string();
-    err_msg.push_str(&tok_kind_to_string(end));
-    self.parse_token(end, err_msg)
+    self.parse_token(TokKind::Rbrace,
+      "expects '}' at the end of operand aliasing description")
   }
 
-  // param_list_to_shape ::= param_list '->' shape
-  fn parse_param_list_to_shape(&mut self, shape: &mut Shape, shape_loc: &mut usize) -> bool {
-    if !self.parse_param_list() ||
-       ! self.parse_token(&TokKind::Arrow, "expects '->'".to_string())
-    {
+  // int64_list_list ::= '{' (int64_list (',' int64_list)*)? '}'
+  fn parse_int64_list_list(&mut self, result: &mut Vec<Vec<i64>>) -> bool {
+    if !self.parse_token(TokKind::Lbrace, "expects '{' at the beginning of a list") {
       return false;
     }
-    *shape_loc = self.lexer.get_loc();
-    self.parse_shape(shape)
+    if self.lexer.get_kind() != TokKind::Rbrace {
+      loop {
+        let mut item = vec![];
+        if !self.parse_int64_list(TokKind::Lbrace, TokKind::Rbrace,
+          TokKind::Comma, &mut item)
+        {
+          return false;
+        }
+        result.push(item);
+        if !self.eat_if_present(TokKind::Comma) {
+          break;
+        }
+      }
+    }
+    self.parse_token(TokKind::Rbrace, "expects '}' at the end of a list")
   }
 
-  // param_list ::= '(' param_list1 ')'
-  // param_list1
+  // int64_list ::= start int64_elements end
+  // int64_elements
   //   ::= /*empty*/
-  //   ::= param (',' param)*
-  // param ::= name shape
-  fn parse_param_list(&mut self) -> bool {
-    if !self.parse_token(&TokKind::Lparen,
-      "expects '(' at the beginning of param list".to_string())
-    {
+  //   ::= int64_val (delim int64_val)*
+  fn parse_int64_list(
+    &mut self, start: TokKind, end: TokKind, delim: TokKind, result: &mut Vec<i64>) -> bool
+  {
+    if !self.parse_token(start, "expects a list starting with the start token") {
       return false;
     }
-    if self.lexer.get_kind() == TokKind::Rparen {
-      // empty
-    } else {
+    if self.lexer.get_kind() != end {
       loop {
-        let mut shape = Shape::new();
-        let mut name = String::new();
-        if !self.parse_name(&mut name) || !self.parse_shape(&mut shape) {
+        let mut value = 0;
+        if !self.parse_int64(&mut value) {
           return false;
         }
-        if !self.eat_if_present(&TokKind::Comma) { break; }
+        result.push(value);
+        if !self.eat_if_present(delim.clone()) {
+          break;
+        }
       }
     }
-    self.parse_token(&TokKind::Rparen,
-      "expects ')' at the end of param list".to_string())
+    self.parse_token(end, "expects the list to end with the end token")
   }
 
   fn parse_name(&mut self, result: &mut String) -> bool {
-    println!("parse_name");
     if self.lexer.get_kind() != TokKind::Ident && self.lexer.get_kind() != TokKind::Name {
-      return self.token_error("expects name".to_string());
+      return self.token_error("expects name");
     }
     *result = self.lexer.get_str_val();
     self.lexer.lex();
@@ -861,7 +4197,7 @@ impl HloParser {
 
   fn parse_attribute_name(&mut self, result: &mut String) -> bool {
     if self.lexer.get_kind() != TokKind::AttributeName {
-      return self.token_error("expects attribute name".to_string());
+      return self.token_error("expects attribute name");
     }
     *result = self.lexer.get_str_val();
     self.lexer.lex();
@@ -869,391 +4205,508 @@ impl HloParser {
   }
 
   fn parse_string(&mut self, result: &mut String) -> bool {
-    println!("parse_string");
     if self.lexer.get_kind() != TokKind::String {
-      return self.token_error("expects string".to_string());
+      return self.token_error("expects string");
     }
     *result = self.lexer.get_str_val();
     self.lexer.lex();
     true
   }
 
-  fn parse_json_dict(&mut self, result: &mut String) -> bool {
-    println!("parse_json_dict");
-    if self.lexer.lex_json_dict() != TokKind::String {
-      return self.token_error("expects JSON dict".to_string());
+  fn parse_int64(&mut self, result: &mut i64) -> bool {
+    if self.lexer.get_kind() != TokKind::Int {
+      return self.token_error("expects integer");
     }
-    *result = self.lexer.get_str_val();
+    *result = self.lexer.get_i64_val();
     self.lexer.lex();
     true
   }
 
-  // dimension_sizes ::= '[' dimension_list ']'
-  // dimension_list
-  //   ::= /*empty*/
-  //   ::= '?'
-  //   ::= <=? int64_t (',' param)*
-  // param ::= name shape
-  fn parse_dimension_sizes(
-    &mut self, _dimension_sizes: &mut Vec<i64>, _dynamic_dimensions: &mut Vec<bool>) -> bool
-  {
-    /*
-    let parse_and_add_item = || -> bool {
-      let mut i = 0;
-      let mut is_dynamic = false;
-      if self.lexer.get_kind() == TokKind::QuestionMark {
-        i = Shape::UNBOUNDED_SIZE;
-        is_dynamic = true;
-        self.lexer.lex();
-      } else {
-        if self.lexer.get_kind() == TokKind::Leq {
-          is_dynamic = true;
-          self.lexer.lex();
-        }
-        if !self.parse_i64(&mut i) {
-          return false;
-        }
-      }
-      dimension_sizes.push(i);
-      dynamic_dimensions.push(is_dynamic);
-      true
-    };
-    self.parse_list(&TokKind::Lsquare, &TokKind::Rsquare,
-      &TokKind::Comma, Box::new(parse_and_add_item))
-      */
-    false
-  }
-
-  fn parse_shape(&mut self, _shape: &mut Shape) -> bool {
-    unimplemented!()
-  }
-
-  fn parse_layout(&mut self, _layout: &Layout) -> bool {
-    unimplemented!()
+  fn parse_double(&mut self, result: &mut f64) -> bool {
+    match self.lexer.get_kind() {
+      TokKind::Decimal => *result = self.lexer.get_decimal_val(),
+      TokKind::Int => *result = self.lexer.get_i64_val() as f64,
+      TokKind::Inf => *result = f64::INFINITY,
+      TokKind::NegInf => *result = f64::NEG_INFINITY,
+      _ => return self.token_error("expects decimal or integer")
+    }
+    self.lexer.lex();
+    true
   }
 
-  // int_attribute
-  //   ::= /*empty*/
-  //   ::= attr_token '(' attr_value ')'
-  // attr_token
-  //   ::= 'E' | 'S'
-  // attr_value
-  //   ::= int64_t
-  fn parse_layout_int_attribute(
-    &mut self, attr_value: &mut i64, attr_desc: String) -> bool
-  {
-    let mut err_msg = "expects ".to_string();
-    err_msg.push_str(&attr_desc);
-    err_msg.push_str(" to start with ");
-    err_msg.push_str(&tok_kind_to_string(&TokKind::Lparen));
-
-    if !self.parse_token(&TokKind::Lparen, err_msg) {
-      return false;
-    }
-    if !self.parse_i64(attr_value) {
-      return false;
+  fn parse_bool(&mut self, result: &mut bool) -> bool {
+    match self.lexer.get_kind() {
+      TokKind::True => *result = true,
+      TokKind::False => *result = false,
+      _ => return self.token_error("expects true or false")
     }
+    self.lexer.lex();
+    true
+  }
 
-    let mut err_msg = "expects ".to_string();
-    err_msg.push_str(&attr_desc);
-    err_msg.push_str(" to end with ");
-    err_msg.push_str(&tok_kind_to_string(&TokKind::Rparen));
-
-    if !self.parse_token(&TokKind::Rparen, err_msg) {
-      return false;
+  // Consumes the current token if it is 'kind', otherwise records 'msg' as
+  // an error.
+  fn parse_token(&mut self, kind: TokKind, msg: &str) -> bool {
+    if self.lexer.get_kind() != kind {
+      return self.token_error(msg);
     }
+    self.lexer.lex();
     true
   }
 
-  fn parse_dim_level_types() {}
-  fn parse_tiles() {}
-  fn parse_split_configs() {}
-
-  // physical_shape
-  //   ::= /*empty*/
-  //   ::= 'P' '(' shape ')'
-  fn parse_physical_shape(&mut self, physical_shape: &mut Shape) -> bool {
-    let mut err_msg = "expects physical shape to start with ".to_string();
-    err_msg.push_str(&tok_kind_to_string(&TokKind::Lparen));
-    if !self.parse_token(&TokKind::Lparen, err_msg) {
-      return false;
-    }
-    self.parse_shape(physical_shape);
-    let mut err_msg = "expects physical shape to end with ".to_string();
-    err_msg.push_str(&tok_kind_to_string(&TokKind::Rparen));
-    if !self.parse_token(&TokKind::Rparen, err_msg) {
+  // If the current token is 'kind', eats it and returns true. Otherwise
+  // returns false.
+  fn eat_if_present(&mut self, kind: TokKind) -> bool {
+    if self.lexer.get_kind() != kind {
       return false;
     }
+    self.lexer.lex();
     true
   }
 
-  fn parse_opcode() {}
+  // Adds an error at the given location. Always returns false.
+  fn error(&mut self, loc: usize, msg: &str) -> bool {
+    let (line, col) = self.lexer.get_line_and_column(loc);
+    let mut error_msg = format!("was parsing {}:{}: error: {}\n", line, col, msg);
+    error_msg.push_str(&self.lexer.get_line(loc));
+    error_msg.push('\n');
+    error_msg.push_str(&" ".repeat(col - 1));
+    error_msg.push('^');
+    self.error.push(error_msg);
+    false
+  }
 
-  fn parse_fft_type(&mut self, _result: &mut FftType) -> bool {
-    println!("parse_fft_type");
-    if self.lexer.get_kind() != TokKind::Ident {
-      return self.token_error("expects fft type".to_string());
-    }
-    let _val = self.lexer.get_str_val();
-    // TODO: fft_type_parse
-    self.lexer.lex();
-    true
+  // Reports an error at the current token.
+  fn token_error(&mut self, msg: &str) -> bool {
+    self.error(self.lexer.get_loc(), msg)
   }
+}
 
-  fn parse_padding_type() {}
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use hlo::{evaluator::hlo_evaluator::HloEvaluator, hlo_instruction::HloPrintOptions};
+  use num::complex::Complex32;
 
-  fn parse_primitive_type(&mut self, result: &mut PrimitiveType) -> bool {
-    if self.lexer.get_kind() != TokKind::PrimitiveType {
-      return self.token_error("expected primitive type".to_string());
+  fn parse(text: &str) -> HloModule {
+    let config = HloModuleConfig::new_default();
+    match parse_and_return_unverified_module(text.to_string(), &config) {
+      Ok(module) => module,
+      Err(msg) => panic!("{}", msg)
     }
-    *result = self.lexer.get_primitive_type_val();
-    self.lexer.lex();
-    true
   }
 
-  fn parse_comparison_direction(&mut self, result: &mut ComparisonDirection) -> bool {
-    println!("parse_comparison_direction");
-    if self.lexer.get_kind() != TokKind::Ident {
-      return self.token_error("expects comparison direction".to_string());
-    }
-    let val = self.lexer.get_str_val();
-    let comparison_direction =
-      string_to_comparison_direction(&val);
-    if comparison_direction.is_err() {
-      let mut err_msg = "expects comparison direction but sees: ".to_string();
-      err_msg.push_str(&val);
-      return self.token_error(err_msg);
-    }
-    *result = comparison_direction.unwrap();
-    self.lexer.lex();
-    true
+  #[test]
+  fn test_parse_reduce_with_embedded_computation() {
+    let text = r#"
+HloModule reduce, replica_count=2
+
+%add (x: f32[], y: f32[]) -> f32[] {
+  %x = f32[] parameter(0)
+  %y = f32[] parameter(1)
+  ROOT %add = f32[] add(f32[] %x, f32[] %y)
+}
+
+ENTRY %main (p: f32[8,16]) -> f32[8] {
+  %p = f32[8,16]{1,0} parameter(0)
+  %zero = f32[] constant(0)
+  ROOT %reduce = f32[8]{0} reduce(%p, %zero), dimensions={1}, to_apply=%add
+}
+"#;
+    let module = parse(text);
+    assert_eq!(module.name(), "reduce".to_string());
+    assert_eq!(module.config().replica_count(), 2);
+    assert_eq!(module.computation_count(), 2);
+    let root = module.entry_computation().unwrap().root_instruction();
+    assert_eq!(root.opcode(), HloOpcode::Reduce);
+    assert_eq!(root.name(), "reduce".to_string());
+    assert_eq!(root.dimensions(), &vec![1]);
+    assert_eq!(root.operand_count(), 2);
   }
 
-  fn parse_comparison_type(&mut self, result: &mut ComparisonType) -> bool {
-    println!("parse_comparison_type");
-    if self.lexer.get_kind() != TokKind::Ident {
-      return self.token_error("expects comparison type".to_string());
-    }
-    let val = self.lexer.get_str_val();
-    let comparison_type =
-      string_to_comparison_type(&val);
-    if comparison_type.is_err() {
-      let mut err_msg = "expects comparison type but sees: ".to_string();
-      err_msg.push_str(&val);
-      return self.token_error(err_msg);
-    }
-    *result = comparison_type.unwrap();
-    self.lexer.lex();
-    true
+  #[test]
+  fn test_parse_module_without_entry() {
+    let text = r#"
+HloModule no_entry
+
+%add (x: f32[], y: f32[]) -> f32[] {
+  %x = f32[] parameter(0)
+  %y = f32[] parameter(1)
+  ROOT %add = f32[] add(%x, %y)
+}
+"#;
+    let module = parse(text);
+    assert_eq!(module.computation_count(), 1);
+    assert_eq!(module.entry_computation().unwrap().name(), "add".to_string());
   }
 
-  fn parse_fusion_kind(&mut self, result: &mut FusionKind) -> bool {
-    println!("parse_fusion_kind");
-    if self.lexer.get_kind() != TokKind::Ident {
-      return self.token_error("expexts fusion kind".to_string());
-    }
-    let val = self.lexer.get_str_val();
-    let fusion_kind =
-      hlo_instruction::string_to_fusion_kind(&val);
-    if fusion_kind.is_err() {
-      let mut err_msg = "expects fusion kind but sees: ".to_string();
-      err_msg.push_str(&val);
-      return self.token_error(err_msg);
-    }
-    *result = fusion_kind.unwrap();
-    self.lexer.lex();
-    true
+  #[test]
+  fn test_parse_constants() {
+    let text = r#"
+HloModule constants
+
+ENTRY %main () -> (f32[], s32[2,2], pred[3]) {
+  %scalar = f32[] constant(-1.5)
+  %array = s32[2,2]{1,0} constant({ {1, 2}, {3, 4} })
+  %pred = pred[3]{0} constant({true, false, 1})
+  ROOT %tuple = (f32[], s32[2,2]{1,0}, pred[3]{0}) tuple(%scalar, %array, %pred)
+}
+"#;
+    let module = parse(text);
+    let root = module.entry_computation().unwrap().root_instruction();
+    assert_eq!(root.opcode(), HloOpcode::Tuple);
+    assert_eq!(root.shape().tuple_shapes_size(), 3);
+
+    let out_of_range = r#"
+HloModule bad_constant
+
+ENTRY %main () -> s8[] {
+  ROOT %c = s8[] constant(300)
+}
+"#;
+    let config = HloModuleConfig::new_default();
+    let result = parse_and_return_unverified_module(out_of_range.to_string(), &config);
+    assert!(result.is_err());
+    assert!(result.err().unwrap().contains("out of range"));
   }
 
-  fn parse_random_distribution(&mut self, result: &mut RandomDistribution) -> bool {
-    println!("parse_random_distribution");
-    if self.lexer.get_kind() != TokKind::Ident {
-      return self.token_error("expects random distribution".to_string());
-    }
-    let val = self.lexer.get_str_val();
-    let random_distribution =
-      hlo_instruction::string_to_random_distribution(&val);
-    if random_distribution.is_err() {
-      let mut err_msg = "expects random distribution but sees: ".to_string();
-      err_msg.push_str(&val);
-      return self.token_error(err_msg);
+  #[test]
+  fn test_parse_exact_constants() {
+    let text = r#"
+HloModule exact_constants
+
+ENTRY %main () -> (s64[], s64[], u64[], s64[], c64[], c128[2]) {
+  %min = s64[] constant(-9223372036854775808)
+  %max = s64[] constant(9223372036854775807)
+  %umax = u64[] constant(18446744073709551615)
+  %odd = s64[] constant(9007199254740993)
+  %c64 = c64[] constant((1, -2.5))
+  %c128 = c128[2]{0} constant({(0.5, 3), (-inf, 1e-07)})
+  ROOT %tuple = (s64[], s64[], u64[], s64[], c64[], c128[2]{0}) tuple(%min, %max, %umax, %odd, %c64, %c128)
+}
+"#;
+    let module = parse(text);
+    let entry = module.entry_computation().unwrap();
+    let literal = |name: &str| entry.instructions()
+      .find(|i| i.name() == name).unwrap().literal().clone();
+    assert_eq!(literal("min").get::<i64>(&vec![], &vec![]), i64::MIN);
+    assert_eq!(literal("max").get::<i64>(&vec![], &vec![]), i64::MAX);
+    assert_eq!(literal("umax").get::<u64>(&vec![], &vec![]), u64::MAX);
+    assert_eq!(literal("odd").get::<i64>(&vec![], &vec![]), 9007199254740993);
+    assert_eq!(literal("c64").get::<Complex32>(&vec![], &vec![]), Complex32::new(1.0, -2.5));
+    assert_eq!(literal("c128").elements(&vec![]),
+      vec![Element::Complex(0.5, 3.0), Element::Complex(f64::NEG_INFINITY, 1e-07)]);
+
+    // The values survive printing and parsing again.
+    let reparsed = parse(&module.to_string());
+    let reparsed_entry = reparsed.entry_computation().unwrap();
+    for instruction in entry.instructions() {
+      if instruction.opcode() == HloOpcode::Constant {
+        let reparsed_constant = reparsed_entry.instructions()
+          .find(|i| i.name() == instruction.name()).unwrap();
+        assert_eq!(reparsed_constant.literal(), instruction.literal());
+      }
     }
-    *result = random_distribution.unwrap();
-    self.lexer.lex();
-    true
+
+    let bad_complex = r#"
+HloModule bad_complex
+
+ENTRY %main () -> c64[] {
+  ROOT %c = c64[] constant(1)
+}
+"#;
+    let config = HloModuleConfig::new_default();
+    let result = parse_and_return_unverified_module(bad_complex.to_string(), &config);
+    assert!(result.is_err());
+    assert!(result.err().unwrap().contains("expects '(' in front of complex number"));
   }
 
-  fn parse_random_algorithm(&mut self, result: &mut RandomAlgorithm) -> bool {
-    println!("parse_random_algorithm");
-    if self.lexer.get_kind() != TokKind::Ident {
-      return self.token_error("expects random algorithm".to_string());
-    }
-    let val = self.lexer.get_str_val();
-    let random_algorithm =
-      hlo_instruction::string_to_random_algorithm(&val);
-    if random_algorithm.is_err() {
-      let mut err_msg = "expects random algorithm but sees: ".to_string();
-      err_msg.push_str(&val);
-      return self.token_error(err_msg);
-    }
-    *result = random_algorithm.unwrap();
-    self.lexer.lex();
-    true
+  #[test]
+  fn test_evaluate_parsed_constants_exactly() {
+    let module = parse(r#"
+HloModule exact_add
+
+ENTRY %main () -> s64[] {
+  %c = s64[] constant(9007199254740993)
+  %zero = s64[] constant(0)
+  ROOT %add = s64[] add(%c, %zero)
+}
+"#);
+    let mut evaluator = HloEvaluator::default();
+    let result = evaluator.evaluate_module_dyn(&module, &vec![]).unwrap();
+    assert_eq!(result.get::<i64>(&vec![], &vec![]), 9007199254740993);
   }
 
-  fn parse_precision(&mut self, result: &mut Precision) -> bool {
-    println!("parse_precision");
-    if self.lexer.get_kind() != TokKind::Ident {
-      return self.token_error("expects precision".to_string());
-    }
-    let val = self.lexer.get_str_val();
-    let precision = hlo_instruction::string_to_precision(&val);
-    if precision.is_err() {
-      let mut err_msg = "expects precision but sees: ".to_string();
-      err_msg.push_str(&val);
-      return self.token_error(err_msg);
-    }
-    *result = precision.unwrap();
-    self.lexer.lex();
-    true
+  #[test]
+  fn test_parse_instruction_attributes() {
+    let text = r#"
+HloModule attributes
+
+ENTRY %main (p: f32[4,4]) -> f32[2,2] {
+  %p = f32[4,4]{1,0} parameter(0), sharding={devices=[2,1]0,1}
+  %slice = f32[2,4]{1,0} slice(%p), slice={[0:4:2], [0:4]}, metadata={op_type="Slice" op_name="s" source_line=7}
+  %zero = f32[] constant(0)
+  %window = f32[2,2]{1,0} reduce-window(%slice, %zero), window={size=1x2 stride=1x2 pad=0_0x0_0}, to_apply={
+    %a = f32[] parameter(0)
+    %b = f32[] parameter(1)
+    ROOT %m = f32[] maximum(%a, %b)
   }
+  ROOT %copy = f32[2,2]{0,1} copy(%window), sharding={replicated}, backend_config={"key":"value"}
+}
+"#;
+    let module = parse(text);
+    let entry = module.entry_computation().unwrap();
+    let root = entry.root_instruction();
+    assert_eq!(root.opcode(), HloOpcode::Copy);
+    assert_eq!(root.raw_backend_config_string(), "{\"key\":\"value\"}".to_string());
+    assert!(root.sharding().is_replicated());
+    assert_eq!(ShapeUtil::human_string_with_layout(root.shape()),
+      "f32[2,2]{0,1}".to_string());
 
-  fn parse_algorithm(&mut self, result: &mut Algorithm) -> bool {
-    println!("parse_algorithm");
-    if self.lexer.get_kind() != TokKind::Ident {
-      return self.token_error("expects algorithm".to_string());
-    }
-    let val = self.lexer.get_str_val();
-    let algorithm = hlo_instruction::string_to_algorithm(&val);
-    if algorithm.is_err() {
-      let mut err_msg = "expects algorithm but sees: ".to_string();
-      err_msg.push_str(&val);
-      return self.token_error(err_msg);
-    }
-    *result = algorithm.unwrap();
-    self.lexer.lex();
-    true
+    let slice = entry.instructions()
+      .find(|i| i.opcode() == HloOpcode::Slice).unwrap();
+    assert_eq!(slice.slice_strides(), &vec![2, 1]);
+    assert_eq!(slice.metadata().op_type(), "Slice".to_string());
+    assert_eq!(slice.metadata().source_line(), 7);
+
+    let window = entry.instructions()
+      .find(|i| i.opcode() == HloOpcode::ReduceWindow).unwrap();
+    assert_eq!(window.window().dimensions(1).size(), 2);
+    assert_eq!(window.window().dimensions(1).stride(), 2);
+    assert_eq!(module.computation_count(), 2);
   }
 
-  fn parse_i64(&mut self, result: &mut i64) -> bool {
-    println!("parse_int64");
-    if self.lexer.get_kind() != TokKind::Int {
-      return self.token_error("expects integer".to_string());
-    }
-    *result = self.lexer.get_i64_val();
-    self.lexer.lex();
-    true
+  #[test]
+  fn test_parse_unexpected_attribute() {
+    let text = r#"
+HloModule bad_attribute
+
+ENTRY %main (p: f32[4]) -> f32[4] {
+  %p = f32[4]{0} parameter(0)
+  ROOT %n = f32[4]{0} negate(%p), dimensions={0}
+}
+"#;
+    let result = parse_and_return_unverified_module(
+      text.to_string(), &HloModuleConfig::new_default());
+    assert!(result.is_err());
+    let msg = result.err().unwrap();
+    assert!(msg.contains("unexpected attribute dimensions"));
+    assert!(msg.contains("was parsing 6:"));
   }
 
-  fn parse_double(&mut self, result: &mut f64) -> bool {
-    match self.lexer.get_kind() {
-      TokKind::Decimal => {
-        let val = self.lexer.get_decimal_val();
-        if val.is_infinite() {
-          let mut err_msg = "Constant is out of range for double (+/-".to_string();
-          err_msg.push_str(&f64::MAX.to_string());
-          err_msg.push_str(") and so is unparsable.");
-          return self.token_error(err_msg);
-        }
-      },
-      TokKind::Int => *result = self.lexer.get_i64_val() as f64,
-      TokKind::Inf => *result = f64::INFINITY,
-      TokKind::NegInf => *result = f64::NEG_INFINITY,
-      _ => return self.token_error("expects decimal or integer".to_string())
-    };
-    self.lexer.lex();
-    true
+  #[test]
+  fn test_parse_shape() {
+    let shape = parse_shape("f32[2,3]{0,1}".to_string()).unwrap();
+    assert_eq!(shape.dimensions_vec(), &vec![2, 3]);
+    assert_eq!(shape.layout().as_ref().unwrap().minor_to_major_vec(), &vec![0, 1]);
+
+    let shape = parse_shape("s32[<=4,?]".to_string()).unwrap();
+    assert!(shape.is_dynamic_dimension(0));
+    assert!(shape.is_unbounded_dynamic_dimension(1));
+    assert_eq!(shape.dimensions(0), 4);
+
+    let shape = parse_shape("(f32[], (token[], u8[3]{0}))".to_string()).unwrap();
+    assert!(shape.is_tuple());
+    assert!(shape.tuple_shapes(1).tuple_shapes(0).is_token());
+
+    assert!(parse_shape("f32[2] extra".to_string()).is_err());
   }
 
-  fn parse_complex(&mut self, result: &mut Complex64) -> bool {
-    if self.lexer.get_kind() != TokKind::Lparen {
-      return self.token_error("expects '(' before complex number".to_string());
-    }
-    self.lexer.lex();
+  #[test]
+  fn test_parse_layout() {
+    let layout = parse_layout("{1,0:T(8,128)(2,*)L(4)E(16)S(1)#(s32)*(s64)M(8)}".to_string())
+      .unwrap();
+    assert_eq!(layout.minor_to_major_vec(), &vec![1, 0]);
+    assert_eq!(layout.tiles_size(), 2);
+    assert_eq!(layout.tiles(1).dimensions(), &vec![2, Tile::COMBINE_DIMENSION]);
+    assert_eq!(layout.tail_padding_alignment_in_elements(), 4);
+    assert_eq!(layout.element_size_in_bits(), 16);
+    assert_eq!(layout.memory_space(), 1);
+    assert_eq!(layout.index_primitive_type(), PrimitiveType::S32);
+    assert_eq!(layout.pointer_primitive_type(), PrimitiveType::S64);
+    assert_eq!(layout.dynamic_shape_metadata_prefix_bytes(), 8);
 
-    let mut real = 0.0;
-    let loc = self.lexer.get_loc();
-    if !self.parse_double(&mut real) {
-      return self.error(loc,
-        "expect floating-point value for real part of complex number".to_string());
-    }
+    let layout = parse_layout("{0:D(C+)}".to_string()).unwrap();
+    assert_eq!(layout.dim_level_type(0), DimLevelType::Compressed);
+    assert!(!layout.dim_unique(0));
 
-    if self.lexer.get_kind() != TokKind::Comma {
-      return self.token_error("expect comma after real part of complex literal".to_string());
-    }
-    self.lexer.lex();
+    assert!(parse_layout("{0:X(1)}".to_string()).is_err());
+  }
 
-    let mut imag = 0.0;
-    let loc = self.lexer.get_loc();
-    if !self.parse_double(&mut imag) {
-      return self.error(loc,
-        "expect floating-point value for imaginary part of complex number".to_string());
-    }
+  #[test]
+  fn test_parse_sharding() {
+    let sharding = parse_sharding("{maximal device=1}".to_string()).unwrap();
+    assert_eq!(sharding.to_string(false), "{maximal device=1}".to_string());
 
-    if self.lexer.get_kind() != TokKind::Rparen {
-      return self.token_error("expect ')' after complex number".to_string());
-    }
+    let sharding = parse_sharding(
+      "{devices=[2,2]<=[4] last_tile_dim_replicate}".to_string()).unwrap();
+    assert_eq!(sharding.to_string(false),
+      "{devices=[2,2]<=[4] last_tile_dim_replicate}".to_string());
 
-    *result = Complex64::new(real, imag);
-    self.lexer.lex();
-    true
+    let sharding = parse_sharding("{{replicated}, {manual}}".to_string()).unwrap();
+    assert!(sharding.is_tuple());
+    assert_eq!(sharding.tuple_elements().len(), 2);
   }
 
-  fn parse_bool(&mut self, result: &mut bool) -> bool {
-    if self.lexer.get_kind() != TokKind::True &&
-       self.lexer.get_kind() != TokKind::False
-    {
-      return self.token_error("expexts true or false".to_string());
-    }
-    if self.lexer.get_kind() == TokKind::True {
-      *result = true;
-    } else {
-      *result = false;
-    }
-    self.lexer.lex();
-    true
+  #[test]
+  fn test_parse_replica_groups() {
+    let groups = parse_replica_groups_only("{{0,1},{2,3}}".to_string()).unwrap();
+    assert_eq!(groups[1].replica_ids(), &vec![2, 3]);
+
+    let groups = parse_replica_groups_only("[2,2]<=[2,2]T(1,0)".to_string()).unwrap();
+    assert_eq!(groups[0].replica_ids(), &vec![0, 2]);
+    assert_eq!(groups[1].replica_ids(), &vec![1, 3]);
   }
 
-  fn parse_token(&mut self, kind: &TokKind, msg: String) -> bool {
-    println!("parse_token {:?} {:?}", kind, msg);
-    if self.lexer.get_kind() != *kind {
-      return self.token_error(msg);
-    }
-    self.lexer.lex();
-    true
+  fn assert_round_trip(text: &str) {
+    let module = parse(text);
+    let printed = module.to_string_with_options(&HloPrintOptions::short_parsable());
+    let reparsed = match parse_and_return_unverified_module(
+      printed.clone(), &HloModuleConfig::new_default())
+    {
+      Ok(module) => module,
+      Err(msg) => panic!("{}\n{}", msg, printed)
+    };
+    assert_eq!(reparsed.to_string_with_options(&HloPrintOptions::short_parsable()), printed);
   }
 
-  fn parse_unsigned_integer_type() {}
+  #[test]
+  fn test_print_round_trip() {
+    assert_round_trip(r#"
+HloModule round_trip, replica_count=2, frontend_attributes={attr="v"}
+
+%add (x: f32[], y: f32[]) -> f32[] {
+  %x = f32[] parameter(0)
+  %y = f32[] parameter(1)
+  ROOT %add = f32[] add(%x, %y)
+}
 
-  fn parse_aliasing() {}
-  fn parse_buffer_donor() {}
-  fn parse_computation_layout() {}
-  fn parse_instruction_output_operand_aliasing() {}
-  fn parse_parse_custom_call_schedule() {}
-  fn parse_custom_call_api_version() {}
-  fn parse_sparsity_descriptor() {}
-  fn parse_shape_index() {}
+%cond (c: (s32[], f32[8])) -> pred[] {
+  %c = (s32[], f32[8]{0}) parameter(0)
+  %i = s32[] get-tuple-element(%c), index=0
+  %limit = s32[] constant(10)
+  ROOT %lt = pred[] compare(%i, %limit), direction=LT
+}
 
-  fn can_be_shape() {}
-  fn can_be_param_list_to_shape() {}
+%body (b: (s32[], f32[8])) -> (s32[], f32[8]) {
+  %b = (s32[], f32[8]{0}) parameter(0)
+  %i = s32[] get-tuple-element(%b), index=0
+  %v = f32[8]{0} get-tuple-element(%b), index=1
+  %one = s32[] constant(1)
+  %next = s32[] add(%i, %one)
+  %e = f32[8]{0} exponential(%v), metadata={op_type="Exp" op_name="exp"}
+  ROOT %t = (s32[], f32[8]{0}) tuple(%next, %e)
+}
 
-  // Logs the currentparsing line and the given message. Always return false.
-  fn token_error(&self, msg: String) -> bool {
-    self.error(self.lexer.get_loc(), msg)
+ENTRY %main (p: f32[8,16], q: f32[16,4]) -> (f32[8], f32[8,4], f32[8], f32[4,8]) {
+  %p = f32[8,16]{1,0} parameter(0), sharding={devices=[2,1]0,1}
+  %q = f32[16,4]{1,0} parameter(1)
+  %zero = f32[] constant(0)
+  %reduce = f32[8]{0} reduce(%p, %zero), dimensions={1}, to_apply=%add
+  %dot = f32[8,4]{1,0} dot(%p, %q), lhs_contracting_dims={1}, rhs_contracting_dims={0}, operand_precision={high,default}
+  %init = s32[] constant(0)
+  %big = f32[8]{0} constant({0.5, -1.25, 3, 1e-07, inf, -inf, 7, 8})
+  %tuple = (s32[], f32[8]{0}) tuple(%init, %reduce)
+  %while = (s32[], f32[8]{0}) while(%tuple), condition=%cond, body=%body, backend_config={"known_trip_count":{"n":"10"}}
+  %result = f32[8]{0} get-tuple-element(%while), index=1
+  %sum = f32[8]{0} add(%result, %big), frontend_attributes={_xla_stream="1"}
+  %slice = f32[4,16]{1,0} slice(%p), slice={[0:8:2], [0:16]}
+  %pad = f32[6,18]{1,0} pad(%slice, %zero), padding=1_1x1_1
+  %window = f32[4,8]{1,0} reduce-window(%slice, %zero), window={size=1x2 stride=1x2}, to_apply=%add
+  %start = ((f32[8,16]{1,0}), f32[8,16]{1,0}, u32[]) copy-start(%p)
+  %done = f32[8,16]{1,0} copy-done(%start)
+  %t = f32[16,8]{0,1} transpose(%done), dimensions={1,0}
+  ROOT %root = (f32[8]{0}, f32[8,4]{1,0}, f32[8]{0}, f32[4,8]{1,0}) tuple(%reduce, %dot, %sum, %window)
+}
+"#);
   }
 
-  fn error(&self, _loc: usize, _msg: String) -> bool {
-    unimplemented!()
+  #[test]
+  fn test_print_exact_constants_round_trip() {
+    let text = r#"
+HloModule exact_constants
+
+ENTRY %main () -> (s64[2], u64[], c64[], c128[2]) {
+  %ints = s64[2]{0} constant({-9223372036854775808, 9007199254740993})
+  %unsigned = u64[] constant(18446744073709551615)
+  %complex = c64[] constant((1, -2.5))
+  %complexes = c128[2]{0} constant({(0.5, 3), (-inf, 1e-07)})
+  ROOT %tuple = (s64[2]{0}, u64[], c64[], c128[2]{0}) tuple(%ints, %unsigned, %complex, %complexes)
+}
+"#;
+    assert_round_trip(text);
+    let printed = parse(text).to_string_with_options(&HloPrintOptions::short_parsable());
+    assert!(printed.contains("constant({-9223372036854775808, 9007199254740993})"));
+    assert!(printed.contains("constant(18446744073709551615)"));
+    assert!(printed.contains("constant((1, -2.5))"));
+    assert!(printed.contains("constant({(0.5, 3), (-inf, 1e-7)})"));
   }
 
-  fn eat_if_present(&mut self, kind: &TokKind) -> bool {
-    if self.lexer.get_kind() != *kind {
-      return false;
-    }
-    self.lexer.lex();
-    true
+  #[test]
+  fn test_print_syntax_sugar_async_ops() {
+    let text = r#"
+HloModule async
+
+%wrapped_negate (x: f32[4]) -> f32[4] {
+  %x = f32[4]{0} parameter(0)
+  ROOT %negate = f32[4]{0} negate(%x)
+}
+
+ENTRY %main (p: f32[4], q: f32[4]) -> f32[4] {
+  %p = f32[4]{0} parameter(0)
+  %q = f32[4]{0} parameter(1)
+  %start = ((f32[4]{0}, f32[4]{0}), f32[4]{0}, s32[]) add-start(%p, %q), async_execution_thread="worker"
+  %update = ((f32[4]{0}, f32[4]{0}), f32[4]{0}, s32[]) add-update(%start)
+  %sum = f32[4]{0} add-done(%update)
+  %negate_start = ((f32[4]{0}), f32[4]{0}, s32[]) async-start(%sum), calls=%wrapped_negate
+  ROOT %negate_done = f32[4]{0} async-done(%negate_start)
+}
+"#;
+    assert_round_trip(text);
+    let module = parse(text);
+    assert_eq!(module.computation_count(), 3);
+    let printed = module.to_string_with_options(&HloPrintOptions::short_parsable());
+    assert!(printed.contains("add-start(p, q), async_execution_thread=\"worker\""));
+    assert!(printed.contains("negate-done(negate_start)"));
+    assert!(!printed.contains("wrapped_negate"));
+
+    let mut options = HloPrintOptions::short_parsable();
+    options.set_syntax_sugar_async_ops(false);
+    let printed = module.to_string_with_options(&options);
+    assert!(printed.contains("async-start(sum), calls=wrapped_negate"));
+    assert!(printed.contains("%wrapped_negate") || printed.contains("wrapped_negate {"));
   }
 
-  fn add_instruction() {}
-  fn add_computation() {}
-}
\ No newline at end of file
+  #[test]
+  fn test_print_canonical_and_fingerprint() {
+    let text = |prefix: &str| format!(r#"
+HloModule {prefix}_module
+
+%{prefix}_add (x: f32[], y: f32[]) -> f32[] {{
+  %{prefix}_x = f32[] parameter(0)
+  %{prefix}_y = f32[] parameter(1)
+  ROOT %{prefix}_sum = f32[] add(%{prefix}_x, %{prefix}_y)
+}}
+
+ENTRY %{prefix}_main (p: f32[8,16]) -> f32[8] {{
+  %{prefix}_p = f32[8,16]{{1,0}} parameter(0)
+  %{prefix}_zero = f32[] constant(0)
+  ROOT %{prefix}_reduce = f32[8]{{0}} reduce(%{prefix}_p, %{prefix}_zero), dimensions={{1}}, to_apply=%{prefix}_add, metadata={{op_name="{prefix}"}}
+}}
+"#);
+    let a = parse(&text("a"));
+    let b = parse(&text("b"));
+    // Canonical text keeps the computation names but not the instruction
+    // names, the fingerprint drops both.
+    let canonical = a.to_string_with_options(&HloPrintOptions::canonical());
+    assert_eq!(canonical, a.to_string_with_options(&HloPrintOptions::canonical()));
+    assert!(canonical.contains("ENTRY a_main {"));
+    assert!(canonical.contains(
+      "ROOT tmp_2 = f32[8]{0} reduce(f32[8,16]{1,0} tmp_0, f32[] tmp_1), dimensions={1}"));
+    assert!(!canonical.contains("a_reduce") && !canonical.contains("metadata"));
+
+    let fingerprint = a.to_string_with_options(&HloPrintOptions::fingerprint());
+    assert_eq!(fingerprint, b.to_string_with_options(&HloPrintOptions::fingerprint()));
+    assert!(!fingerprint.contains("a_"));
+  }
+}
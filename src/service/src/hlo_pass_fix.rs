@@ -0,0 +1,121 @@
+#![allow(dead_code)]
+
+use std::collections::HashSet;
+
+use hlo::hlo_module::HloModule;
+
+use crate::hlo_pass_interface::HloPassInterface;
+
+// Maximum number of times a wrapped pass is run before the fixed point loop
+// gives up.
+pub const ITERATION_LIMIT: i64 = 25;
+
+// Do an HLO pass to a fix point.
+pub struct HloPassFix<P: HloPassInterface> {
+  pass: P,
+  iteration_limit: i64
+}
+
+impl<P: HloPassInterface> HloPassFix<P> {
+  pub fn new(pass: P) -> Self {
+    HloPassFix { pass: pass, iteration_limit: ITERATION_LIMIT }
+  }
+
+  pub fn new_with_iteration_limit(pass: P, iteration_limit: i64) -> Self {
+    assert!(iteration_limit > 0);
+    HloPassFix { pass: pass, iteration_limit: iteration_limit }
+  }
+
+  pub fn pass(&self) -> &P {
+    &self.pass
+  }
+
+  pub fn iteration_limit(&self) -> i64 {
+    self.iteration_limit
+  }
+}
+
+impl<P: HloPassInterface> HloPassInterface for HloPassFix<P> {
+  fn name(&self) -> String {
+    self.pass.name()
+  }
+
+  // Runs the wrapped pass until it no longer changes the module, or until it
+  // has run 'iteration_limit' times.
+  fn run(
+    &mut self,
+    module: &mut HloModule,
+    execution_threads: &HashSet<String>) -> Result<bool, String>
+  {
+    let mut changed = false;
+    let mut iteration_count = 0;
+    loop {
+      let changed_this_iteration = self.pass.run(module, execution_threads)?;
+      if !changed_this_iteration {
+        break;
+      }
+      changed = true;
+      iteration_count += 1;
+      if iteration_count == self.iteration_limit {
+        println!("Unexpectedly high number of iterations in HLO passes '{}' for module '{}'. Exiting fixed point loop.",
+          self.pass.name(), module.name());
+        break;
+      }
+    }
+    Ok(changed)
+  }
+
+  fn is_pass_pipeline(&self) -> bool {
+    self.pass.is_pass_pipeline()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use hlo::hlo_module_config::HloModuleConfig;
+  use super::*;
+
+  // A pass which reports a change on its first 'num_changes' runs.
+  struct CountingPass {
+    num_changes: i64,
+    num_runs: i64
+  }
+
+  impl HloPassInterface for CountingPass {
+    fn name(&self) -> String {
+      "counting".to_string()
+    }
+
+    fn run(
+      &mut self,
+      _module: &mut HloModule,
+      _execution_threads: &HashSet<String>) -> Result<bool, String>
+    {
+      self.num_runs += 1;
+      if self.num_changes > 0 {
+        self.num_changes -= 1;
+        return Ok(true);
+      }
+      Ok(false)
+    }
+  }
+
+  #[test]
+  fn test_runs_to_fixed_point() {
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    let mut pass_fix = HloPassFix::new(CountingPass { num_changes: 3, num_runs: 0 });
+    assert_eq!(pass_fix.name(), "counting".to_string());
+    assert_eq!(pass_fix.run(&mut module, &HashSet::new()), Ok(true));
+    assert_eq!(pass_fix.pass().num_runs, 4);
+    assert_eq!(pass_fix.run(&mut module, &HashSet::new()), Ok(false));
+  }
+
+  #[test]
+  fn test_stops_at_iteration_limit() {
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    let mut pass_fix = HloPassFix::new_with_iteration_limit(
+      CountingPass { num_changes: i64::MAX, num_runs: 0 }, 5);
+    assert_eq!(pass_fix.run(&mut module, &HashSet::new()), Ok(true));
+    assert_eq!(pass_fix.pass().num_runs, 5);
+  }
+}
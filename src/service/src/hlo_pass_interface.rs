@@ -23,8 +23,21 @@ impl RunState {
 
 // Base class for HLO passes.
 // These are used with the HloPassPipeline to organize a sequence of passes.
-pub trait  HloPassInterface{
-  fn run(module: &HloModule, execution_threads: &HashSet<String>) -> Result<bool, String>;
+pub trait HloPassInterface {
+  fn name(&self) -> String;
+
+  // Run the pass on the given HLO module with specified execution_threads.
+  // Empty execution_threads means all execution_threads are included.
+  // Returns whether it modified the module.
+  fn run(
+    &mut self,
+    module: &mut HloModule,
+    execution_threads: &HashSet<String>) -> Result<bool, String>;
+
+  // Returns true if this is a pass pipeline.
+  fn is_pass_pipeline(&self) -> bool {
+    false
+  }
 }
 
 
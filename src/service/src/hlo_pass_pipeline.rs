@@ -1,40 +1,300 @@
 #![allow(dead_code)]
 
-//use crate::hlo_pass_interface::HloPassInterface;
+use std::collections::HashSet;
 
-use crate::compilation_stats::CompilationStats;
+use hlo::hlo_module::HloModule;
+
+use crate::{
+  compilation_stats::CompilationStats,
+  dump::dump_hlo_module_between_passes_if_enabled,
+  hlo_pass_interface::HloPassInterface,
+  hlo_verifier::verify_after_pass_in_debug_build
+};
 
 // Pipeline of HLO passes.
 pub struct HloPassPipeline {
   name: String,
-  //passes: Vec<Box<dyn HloPassInterface>>
+  passes: Vec<Box<dyn HloPassInterface>>,
+  invariant_checkers: Vec<Box<dyn HloPassInterface>>,
   run_called: bool,
   compilation_stats: CompilationStats
 }
 
 impl HloPassPipeline {
-  pub fn new(_name: String, _compilation_stats: Option<CompilationStats>) -> Self {
-    unimplemented!()
+  pub fn new(name: String, compilation_stats: Option<CompilationStats>) -> Self {
+    HloPassPipeline {
+      name: name,
+      passes: Vec::new(),
+      invariant_checkers: Vec::new(),
+      run_called: false,
+      compilation_stats: compilation_stats.unwrap_or(CompilationStats::make_noop_stats())
+    }
   }
 
   pub fn name(&self) -> &String {
     &self.name
   }
 
-  // Add a pass to the pipeline. It should be called with the arguments for the
-  // pass constructor:
+  // Add a pass to the pipeline. Passes are run in the order they are added:
   //
-  //   pipeline.AddPass<FooPass>(constructor_arg1, constructor_arg2);
+  //   pipeline.add_pass(Box::new(FooPass::new(constructor_arg1)));
   //
   // Returns a reference to the added pass.
-  pub fn add_pass<Arg>(&mut self, _arg: Option<Arg>) {
+  pub fn add_pass(
+    &mut self, pass: Box<dyn HloPassInterface>) -> &mut dyn HloPassInterface
+  {
     assert!(!self.run_called, "add_pass cannot be called after run.");
-    unimplemented!()
+    self.passes.push(pass);
+    self.passes.last_mut().unwrap().as_mut()
+  }
+
+  // Add an invariant-checking pass to the pipeline. It will be run before and
+  // after each HLO pass which changed the module. The invariant checking pass
+  // must not mutate the graph (it is required to always return "false" from
+  // its run method).
+  pub fn add_invariant_checker(
+    &mut self, checker: Box<dyn HloPassInterface>) -> &mut dyn HloPassInterface
+  {
+    assert!(!self.run_called, "add_invariant_checker cannot be called after run.");
+    self.invariant_checkers.push(checker);
+    self.invariant_checkers.last_mut().unwrap().as_mut()
+  }
+
+  pub fn passes_size(&self) -> usize {
+    self.passes.len()
+  }
+
+  pub fn get_pass(&self, index: usize) -> &dyn HloPassInterface {
+    self.passes[index].as_ref()
+  }
+
+  pub fn compilation_stats(&self) -> &CompilationStats {
+    &self.compilation_stats
+  }
+
+  // Runs the invariant checkers on 'module'. 'after_pass_name' names the pass
+  // the module was just changed by, and is attached to any failure.
+  fn run_invariant_checkers(
+    &mut self,
+    module: &mut HloModule,
+    after_pass_name: &str,
+    execution_threads: &HashSet<String>) -> Result<(), String>
+  {
+    for checker in &mut self.invariant_checkers {
+      match checker.run(module, execution_threads) {
+        Ok(changed) => assert!(!changed,
+          "invariant checkers must not change the graph: {}", checker.name()),
+        Err(msg) => return Err(format!(
+          "{}\n\nFailed after {} (pipeline {}, checker {})",
+          msg, after_pass_name, self.name, checker.name()))
+      }
+    }
+    Ok(())
+  }
+
+  fn maybe_dump_hlo(
+    &self, module: &HloModule, after_pass_name: &str, before_pass_name: &str)
+  {
+    let result = dump_hlo_module_between_passes_if_enabled(
+      &self.name, before_pass_name, after_pass_name, module);
+    if let Err(msg) = result {
+      println!("Failed to dump HLO in pipeline {}: {}", self.name, msg);
+    }
+  }
+}
+
+impl HloPassInterface for HloPassPipeline {
+  fn name(&self) -> String {
+    self.name.clone()
+  }
+
+  // Run all passes in order on 'module', restricted to the computations of
+  // 'execution_threads'. Returns whether any pass changed the module.
+  fn run(
+    &mut self,
+    module: &mut HloModule,
+    execution_threads: &HashSet<String>) -> Result<bool, String>
+  {
+    self.run_called = true;
+    self.run_invariant_checkers(module, "pipeline-start", execution_threads)?;
+    if !self.passes.is_empty() {
+      let first_pass_name = self.passes[0].name();
+      self.maybe_dump_hlo(module, "pipeline-start", &first_pass_name);
+    }
+
+    let mut changed = false;
+    for i in 0..self.passes.len() {
+      let pass_name = self.passes[i].name();
+      let is_pipeline = self.passes[i].is_pass_pipeline();
+      if !is_pipeline {
+        self.compilation_stats.start_pass(pass_name.clone());
+      }
+      let result = self.passes[i].run(module, execution_threads);
+      let pass_changed = match result {
+        Ok(pass_changed) => pass_changed,
+        Err(msg) => {
+          if !is_pipeline {
+            self.compilation_stats.record_pass_error(pass_name.clone(), &msg);
+          }
+          return Err(format!("{}\n\nFailed running pass {} in pipeline {}",
+            msg, pass_name, self.name));
+        }
+      };
+      if !is_pipeline {
+        self.compilation_stats.end_pass(pass_name.clone());
+      }
+
+      let next_pass_name = if i + 1 < self.passes.len() {
+        self.passes[i + 1].name()
+      } else {
+        "pipeline-end".to_string()
+      };
+      self.maybe_dump_hlo(module, &pass_name, &next_pass_name);
+
+      if pass_changed {
+        self.run_invariant_checkers(module, &pass_name, execution_threads)?;
+        verify_after_pass_in_debug_build(module, &pass_name, execution_threads)?;
+      }
+      changed |= pass_changed;
+    }
+    Ok(changed)
+  }
+
+  fn is_pass_pipeline(&self) -> bool {
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use common::{blitz_data::PrimitiveType, shape_util::ShapeUtil};
+  use hlo::{
+    hlo_computation::HloComputation, hlo_instruction::HloInstruction,
+    hlo_module_config::HloModuleConfig, hlo_opcode::HloOpcode
+  };
+  use crate::hlo_verifier::HloVerifier;
+  use super::*;
+
+  // A pass which reports a change on its first 'num_changes' runs.
+  struct FakePass {
+    name: String,
+    num_changes: i64
   }
 
-  pub fn add_invariant_checker() {}
-  pub fn run() {}
-  pub fn is_pass_pipeline() {}
-  pub fn passes_size() {}
-  pub fn get_pass() {}
-}
\ No newline at end of file
+  impl HloPassInterface for FakePass {
+    fn name(&self) -> String {
+      self.name.clone()
+    }
+
+    fn run(
+      &mut self,
+      _module: &mut HloModule,
+      _execution_threads: &HashSet<String>) -> Result<bool, String>
+    {
+      if self.num_changes > 0 {
+        self.num_changes -= 1;
+        return Ok(true);
+      }
+      Ok(false)
+    }
+  }
+
+  // An invariant checker which always fails.
+  struct FailingChecker {}
+
+  impl HloPassInterface for FailingChecker {
+    fn name(&self) -> String {
+      "failing-checker".to_string()
+    }
+
+    fn run(
+      &mut self,
+      _module: &mut HloModule,
+      _execution_threads: &HashSet<String>) -> Result<bool, String>
+    {
+      Err("invariant broken".to_string())
+    }
+  }
+
+  fn fake_pass(name: &str, num_changes: i64) -> Box<dyn HloPassInterface> {
+    Box::new(FakePass { name: name.to_string(), num_changes: num_changes })
+  }
+
+  fn make_module(name: &str) -> HloModule {
+    let shape = ShapeUtil::make_shape(&PrimitiveType::F32, vec![]);
+    let mut computation = HloComputation::new("entry".to_string());
+    let p0 = computation.add_parameter(
+      HloInstruction::create_parameter(0, &shape, "p0".to_string()));
+    let negate = computation.add_instruction(HloInstruction::create_unary(
+      &shape, HloOpcode::Negate, computation.instruction(p0)),
+      "negate".to_string());
+    computation.set_root_instruction(negate, false);
+    let mut module = HloModule::new(name.to_string(), HloModuleConfig::new_default());
+    module.add_entry_computation(computation);
+    module
+  }
+
+  #[test]
+  fn test_run_passes_in_order() {
+    let mut module = make_module("m");
+    let mut pipeline = HloPassPipeline::new(
+      "pipeline".to_string(), Some(CompilationStats::make_stats()));
+    pipeline.add_pass(fake_pass("foo", 0));
+    pipeline.add_pass(fake_pass("bar", 1));
+    pipeline.add_invariant_checker(Box::new(HloVerifier::new(false, false)));
+    assert_eq!(pipeline.passes_size(), 2);
+    assert_eq!(pipeline.get_pass(1).name(), "bar".to_string());
+    assert!(pipeline.is_pass_pipeline());
+
+    assert_eq!(pipeline.run(&mut module, &HashSet::new()), Ok(true));
+    assert_eq!(pipeline.compilation_stats().pass_names(),
+      vec!["foo".to_string(), "bar".to_string()]);
+  }
+
+  #[test]
+  fn test_unchanged_pipeline() {
+    let mut module = make_module("m");
+    let mut pipeline = HloPassPipeline::new("pipeline".to_string(), None);
+    pipeline.add_pass(fake_pass("foo", 0));
+    assert_eq!(pipeline.run(&mut module, &HashSet::new()), Ok(false));
+    assert_eq!(pipeline.compilation_stats().get_passes_size(), 0);
+  }
+
+  #[test]
+  fn test_invariant_checker_failure() {
+    let mut module = make_module("m");
+    let mut pipeline = HloPassPipeline::new("pipeline".to_string(), None);
+    pipeline.add_pass(fake_pass("foo", 1));
+    pipeline.add_invariant_checker(Box::new(FailingChecker {}));
+    let err_msg = pipeline.run(&mut module, &HashSet::new()).err().unwrap();
+    assert!(err_msg.contains("invariant broken"));
+    assert!(err_msg.contains("Failed after pipeline-start"));
+  }
+
+  #[test]
+  fn test_dump_between_passes() {
+    let dump_dir = std::env::temp_dir().join("blitz_hlo_pass_pipeline_dump");
+    let _ = fs::remove_dir_all(&dump_dir);
+    let mut module = make_module("dump_module");
+    let mut debug_options = module.config().debug_options().clone();
+    debug_options.set_blitz_dump_to(dump_dir.to_str().unwrap().to_string());
+    debug_options.set_blitz_dump_hlo_pass_re("bar".to_string());
+    module.mutable_config().set_debug_options(debug_options);
+
+    let mut pipeline = HloPassPipeline::new("pipeline".to_string(), None);
+    pipeline.add_pass(fake_pass("foo", 0));
+    pipeline.add_pass(fake_pass("bar", 0));
+    assert_eq!(pipeline.run(&mut module, &HashSet::new()), Ok(false));
+
+    let mut file_names: Vec<String> = fs::read_dir(&dump_dir).unwrap()
+      .map(|entry| entry.unwrap().file_name().to_str().unwrap().to_string())
+      .collect();
+    file_names.sort();
+    assert_eq!(file_names.len(), 2);
+    assert!(file_names[0].ends_with(".0000.pipeline.after_foo.before_bar.txt"));
+    assert!(file_names[1].ends_with(".0001.pipeline.after_bar.before_pipeline-end.txt"));
+    let _ = fs::remove_dir_all(&dump_dir);
+  }
+}
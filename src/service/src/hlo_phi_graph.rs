@@ -1,27 +1,30 @@
 #![allow(dead_code)]
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use hlo::hlo_value::HloValue;
 
-
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Node {
+// A node of the phi graph, identified by its index in the node storage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Node {
   is_phi: bool,
-  users: Vec<Node>,
-  operands: Vec<Node>,
+  // Users of this node. Non-phi nodes never have operands.
+  users: Vec<usize>,
+  // Operands of this node.
+  operands: Vec<usize>,
+  // The value that the node is originally registered with.
   value_id: i64,
+  // mark_as_dead is set to true when a phi node is simplified away.
   mark_as_dead: bool
 }
 
 impl Node {
-  pub fn new() -> Self {
+  pub fn new(value_id: i64) -> Self {
     Node {
       is_phi: false,
       users: Vec::new(),
       operands: Vec::new(),
-      value_id: 0,
+      value_id: value_id,
       mark_as_dead: false
     }
   }
@@ -31,8 +34,10 @@ impl Node {
 // HloValues with explicit edges, as well as non-phi nodes that are direct
 // inputs to the phi nodes.
 pub struct PhiGraph {
-  node_to_value_id: HashMap<Node, Vec<i64>>,
-  value_id_to_node: HashMap<i64, Node>,
+  // Index of the node each value id was originally registered with, or the
+  // node it was replaced with.
+  node_to_value_id: HashMap<usize, Vec<i64>>,
+  value_id_to_node: HashMap<i64, usize>,
   node_strage: Vec<Node>
 }
 
@@ -46,61 +51,137 @@ impl PhiGraph {
   }
 
   // Register an hlo value into the phi node.
-  pub fn register_phi(&mut self, value: &HloValue) -> &mut Node {
-    let node = self.create_or_reuse_node(value);
+  pub fn register_phi(&mut self, value: &HloValue, inputs: &[&HloValue]) {
     debug_assert!(value.is_phi());
-    node.is_phi = true;
-    node.operands.clear();
-    //for input in inputs {
-      //let input_node = self.create_or_reuse_node(input);
-      //node.operands.push(input_node.clone());
-    //}
-    node
-  }
-
-  // Splitted from reigster_phi to avoid compile error.
-  pub fn register_phi_operands(&mut self, node: &mut Node, inputs: &Vec<HloValue>) {
+    let node = self.create_or_reuse_node(value);
+    let mut operands = vec![];
     for input in inputs {
-      let input_node = self.create_or_reuse_node(input);
-      node.operands.push(input_node.clone());
+      operands.push(self.create_or_reuse_node(input));
     }
+    self.node_strage[node].is_phi = true;
+    self.node_strage[node].operands = operands;
   }
 
   pub fn get_optimized_id(&self, value: &HloValue) -> i64 {
-    let node = self.value_id_to_node.get(&value.id());
-    debug_assert!(node.is_some());
-    debug_assert!(!node.as_ref().unwrap().mark_as_dead);
-    node.as_ref().unwrap().value_id
+    self.find_optimized_value(value.id())
   }
 
   // Returns true if the input to a hlo value is the same as 'inputs'.
-  pub fn inputs_equal_to(&self, value: &HloValue, inputs: &Vec<HloValue>) -> bool {
+  pub fn inputs_equal_to(&self, value: &HloValue, inputs: &[&HloValue]) -> bool {
     let node = self.value_id_to_node.get(&value.id());
-    debug_assert!(node.is_some());
-
-    let mut existing_set = HashSet::new();
-    for operand in &node.as_ref().unwrap().operands {
-      existing_set.insert(operand.value_id);
-    }
-
-    let mut new_set = HashSet::new();
-    for input in inputs {
-      new_set.insert(input.id());
-    }
+    assert!(node.is_some());
 
+    let existing_set: HashSet<i64> = self.node_strage[*node.unwrap()].operands.iter()
+      .map(|operand| self.node_strage[*operand].value_id)
+      .collect();
+    let new_set: HashSet<i64> = inputs.iter().map(|input| input.id()).collect();
     existing_set == new_set
   }
 
-  // Given 'id', returns the new id that 'id' shpuld be replaced with.
+  // Given 'id', returns the new id that 'id' shpuld be replaced with. If the
+  // node is not optimized, returns the same value.
   pub fn find_optimized_value(&self, id: i64) -> i64 {
-    let opt_id = self.value_id_to_node.get(&id);
-    debug_assert!(opt_id.is_some());
-    debug_assert!(!opt_id.as_ref().unwrap().mark_as_dead);
-    opt_id.as_ref().unwrap().value_id
+    let node = self.value_id_to_node.get(&id);
+    assert!(node.is_some());
+    let node = &self.node_strage[*node.unwrap()];
+    debug_assert!(!node.mark_as_dead);
+    node.value_id
   }
 
   // Optimize the entire graph.
-  pub fn optimize(&mut self) {}
+  pub fn optimize(&mut self) {
+    // Set up users for each node.
+    for i in 0..self.node_strage.len() {
+      for operand in self.node_strage[i].operands.clone() {
+        self.node_strage[operand].users.push(i);
+      }
+    }
+
+    // Run the optimization to a fixed point.
+    let mut changed = true;
+    while changed {
+      changed = false;
+      let mut checked_for_closure = HashSet::new();
+      for i in 0..self.node_strage.len() {
+        // Only optimize live phi nodes.
+        if !self.node_strage[i].is_phi || self.node_strage[i].mark_as_dead {
+          continue;
+        }
+        assert!(!self.node_strage[i].operands.is_empty());
+
+        // Remove self-referencing ids from users and operands.
+        self.node_strage[i].operands.retain(|operand| *operand != i);
+        self.node_strage[i].users.retain(|user| *user != i);
+
+        // If all inputs to phi (after self referencing ids are removed) are the
+        // same value, replace the phi with that value.
+        //
+        // phi(A, A, ... A) => A
+        // phi(A, self) = phi(A) => A
+        assert!(!self.node_strage[i].operands.is_empty());
+        let first = self.node_strage[i].operands[0];
+        if self.node_strage[i].operands.iter().all(|operand| *operand == first) {
+          self.replace_node_with(i, first);
+          changed = true;
+          continue;
+        }
+
+        // Find a closure of inter-connected phis and one non-phi node. Replace
+        // all phis with that non-phi node.
+        //
+        // def A = phi(B, C)
+        // def B = phi(C, D)
+        // def C = phi(A, B)
+        // def D = non-phi
+        // Replace A, B, and C with D:
+        // A = phi(B, C) => D
+        // B = phi(C, D) => D
+        // C = phi(A, B) => D
+        if checked_for_closure.contains(&i) {
+          // Only visit each node once.
+          continue;
+        }
+        // Nodes in this closure.
+        let mut workset = HashSet::new();
+        let mut worklist = VecDeque::new();
+        let mut non_phi = None;
+        worklist.push_back(i);
+        while let Some(todo) = worklist.pop_front() {
+          if workset.contains(&todo) {
+            continue;
+          }
+          checked_for_closure.insert(todo);
+          workset.insert(todo);
+          for operand in &self.node_strage[todo].operands {
+            worklist.push_back(*operand);
+          }
+          if !self.node_strage[todo].is_phi {
+            if non_phi.is_some() && non_phi != Some(todo) {
+              // We see distinct non-phi nodes in the closure, can't apply the
+              // optimization.
+              non_phi = None;
+              break;
+            }
+            // This is the non_phi node we are seeing so far.
+            non_phi = Some(todo);
+          }
+        }
+        if let Some(non_phi) = non_phi {
+          // Replace all phi nodes in the closure/workset with the non_phi node.
+          let mut closure: Vec<usize> = workset.into_iter().collect();
+          closure.sort();
+          for node in closure {
+            if !self.node_strage[node].is_phi {
+              assert_eq!(node, non_phi);
+              continue;
+            }
+            self.replace_node_with(node, non_phi);
+            changed = true;
+          }
+        }
+      }
+    }
+  }
 
   pub fn to_string(&self) -> String {
     let mut out = "PhiGraph: \n".to_string();
@@ -110,46 +191,118 @@ impl PhiGraph {
         out.push_str(", phi");
       }
       if node.mark_as_dead {
-        out.push_str(", dead:\n"); // CHECK
+        out.push_str(", dead");
       }
+      out.push_str(":\n");
       for input in &node.operands {
         out.push_str("  ");
-        out.push_str(input.value_id.to_string().as_str());
-        out.push_str("\n");
+        out.push_str(self.node_strage[*input].value_id.to_string().as_str());
+        out.push('\n');
       }
     }
     out
   }
 
-  fn create_or_reuse_node(&mut self, value: &HloValue) -> &mut Node {
-    if !self.value_id_to_node.contains_key(&value.id()) {
-      let mut node = Node::new();
-      node.value_id = value.id();
-      self.node_strage.push(node.clone());
-
-      let mut node_id_vec = self.node_to_value_id.get_mut(&node.clone());
-      node_id_vec.as_mut().unwrap().push(value.id());
-
-      self.value_id_to_node.insert(value.id(), node);
-      self.value_id_to_node.get_mut(&value.id()).unwrap()
-    } else {
-      self.value_id_to_node.get_mut(&value.id()).unwrap()
+  fn create_or_reuse_node(&mut self, value: &HloValue) -> usize {
+    if let Some(node) = self.value_id_to_node.get(&value.id()) {
+      return *node;
     }
+    let node = self.node_strage.len();
+    self.node_strage.push(Node::new(value.id()));
+    self.value_id_to_node.insert(value.id(), node);
+    self.node_to_value_id.entry(node).or_default().push(value.id());
+    node
   }
 
   // Replace 'node' with 'replace'.
-  fn replace_node_with(&self, node: &Node, replace: &Node) {
-    // Updat users.
-    debug_assert!(node.is_phi);
-    if node.mark_as_dead {
+  fn replace_node_with(&mut self, node: usize, replace: usize) {
+    debug_assert!(self.node_strage[node].is_phi);
+    if self.node_strage[node].mark_as_dead {
       // The node has already been replaced with another.
       return;
     }
-    if replace.mark_as_dead {
+    if self.node_strage[replace].mark_as_dead {
       // The node we are placing with has already been replaced with another node.
-      let replace_node = self.value_id_to_node.get(&replace.value_id);
-      debug_assert!(replace_node.is_some());
-      self.replace_node_with(node, replace_node.as_ref().unwrap());
+      let replace_value_id = self.node_strage[replace].value_id;
+      let replace_node = self.value_id_to_node[&replace_value_id];
+      return self.replace_node_with(node, replace_node);
     }
+    // Update users.
+    for user in self.node_strage[node].users.clone() {
+      for operand in &mut self.node_strage[user].operands {
+        if *operand == node {
+          *operand = replace;
+        }
+      }
+    }
+    // Update operand's users.
+    for operand in self.node_strage[node].operands.clone() {
+      for user in &mut self.node_strage[operand].users {
+        if *user == node {
+          *user = replace;
+        }
+      }
+    }
+    // Update mappings to HloValue ids.
+    let value_ids = self.node_to_value_id.remove(&node).unwrap_or_default();
+    for value_id in &value_ids {
+      self.value_id_to_node.insert(*value_id, replace);
+    }
+    self.node_to_value_id.entry(replace).or_default().extend(value_ids);
+    self.node_strage[node].mark_as_dead = true;
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use common::{blitz_data::PrimitiveType, shape_util::ShapeUtil};
+  use hlo::hlo_instruction::HloInstruction;
+  use super::*;
+
+  fn make_value(id: i64, is_phi: bool) -> HloValue {
+    let shape = ShapeUtil::make_shape(&PrimitiveType::F32, vec![]);
+    let instruction = HloInstruction::create_parameter(0, &shape, "p".to_string());
+    HloValue::new(id, &instruction, &vec![], is_phi)
+  }
+
+  #[test]
+  fn test_self_referencing_phi() {
+    // A = non-phi
+    // B = phi(B, A)
+    let a = make_value(1, false);
+    let b = make_value(2, true);
+    let mut phi_graph = PhiGraph::new();
+    phi_graph.register_phi(&b, &[&b, &a]);
+    phi_graph.optimize();
+    assert_eq!(phi_graph.find_optimized_value(2), 1);
+  }
+
+  #[test]
+  fn test_phi_closure() {
+    // A = phi(B, C), B = phi(C, D), C = phi(A, B), D = non-phi
+    let a = make_value(1, true);
+    let b = make_value(2, true);
+    let c = make_value(3, true);
+    let d = make_value(4, false);
+    let mut phi_graph = PhiGraph::new();
+    phi_graph.register_phi(&a, &[&b, &c]);
+    phi_graph.register_phi(&b, &[&c, &d]);
+    phi_graph.register_phi(&c, &[&a, &b]);
+    assert!(phi_graph.inputs_equal_to(&b, &[&d, &c]));
+    phi_graph.optimize();
+    assert_eq!(phi_graph.find_optimized_value(1), 4);
+    assert_eq!(phi_graph.find_optimized_value(2), 4);
+    assert_eq!(phi_graph.find_optimized_value(3), 4);
+  }
+
+  #[test]
+  fn test_phi_of_distinct_values_is_kept() {
+    let a = make_value(1, false);
+    let b = make_value(2, false);
+    let c = make_value(3, true);
+    let mut phi_graph = PhiGraph::new();
+    phi_graph.register_phi(&c, &[&a, &b]);
+    phi_graph.optimize();
+    assert_eq!(phi_graph.find_optimized_value(3), 3);
+  }
+}
@@ -1,6 +1,15 @@
 #![allow(dead_code)]
 
-use common::shape::ProgramShape;
+use common::{literal_serialization::write_bytes_field, shape::ProgramShape};
+
+// Field numbers of HloProto.
+const HLO_PROTO_HLO_MODULE: u32 = 1;
+
+// Field numbers of HloSnapshot.
+const SNAPSHOT_HLO: u32 = 1;
+const SNAPSHOT_ARGUMENTS: u32 = 2;
+const SNAPSHOT_RESULT: u32 = 3;
+const SNAPSHOT_EXECUTION_PLATFORM: u32 = 4;
 
 // Serialization of HloModule.
 #[derive(Debug, Clone)]
@@ -44,8 +53,227 @@ impl HloModuleProto {
   }
 }
 
+// Serialization of LogicalBuffer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogicalBufferProto {
+  id: i64,
+  size: i64,
+  // The location where the buffer is defined.
+  defined_at_instruction_name: String,
+  defined_at_shape_index: Vec<i64>,
+  color: i64
+}
+
+impl LogicalBufferProto {
+  pub fn new(
+    id: i64,
+    size: i64,
+    defined_at_instruction_name: String,
+    defined_at_shape_index: Vec<i64>,
+    color: i64) -> Self
+  {
+    LogicalBufferProto {
+      id: id,
+      size: size,
+      defined_at_instruction_name: defined_at_instruction_name,
+      defined_at_shape_index: defined_at_shape_index,
+      color: color
+    }
+  }
+
+  pub fn id(&self) -> i64 {
+    self.id
+  }
+
+  pub fn size(&self) -> i64 {
+    self.size
+  }
+
+  pub fn defined_at_instruction_name(&self) -> &String {
+    &self.defined_at_instruction_name
+  }
+
+  pub fn defined_at_shape_index(&self) -> &Vec<i64> {
+    &self.defined_at_shape_index
+  }
+
+  pub fn color(&self) -> i64 {
+    self.color
+  }
+}
+
+// Assigned represents a single LogicalBuffer that is assigned to this
+// BufferAllocation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferAllocationProtoAssigned {
+  logical_buffer_id: i64,
+  offset: i64,
+  size: i64
+}
+
+impl BufferAllocationProtoAssigned {
+  pub fn new(logical_buffer_id: i64, offset: i64, size: i64) -> Self {
+    BufferAllocationProtoAssigned {
+      logical_buffer_id: logical_buffer_id,
+      offset: offset,
+      size: size
+    }
+  }
+
+  pub fn logical_buffer_id(&self) -> i64 {
+    self.logical_buffer_id
+  }
+
+  pub fn offset(&self) -> i64 {
+    self.offset
+  }
+
+  pub fn size(&self) -> i64 {
+    self.size
+  }
+}
+
+// Serialization of BufferAllocation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferAllocationProto {
+  index: i64,
+  size: i64,
+  is_thread_local: bool,
+  is_tuple: bool,
+  is_entry_computation_parameter: bool,
+  is_constant: bool,
+  parameter_number: i64,
+  parameter_shape_index: Vec<i64>,
+  maybe_live_out: bool,
+  color: i64,
+  assigned: Vec<BufferAllocationProtoAssigned>
+}
+
+impl BufferAllocationProto {
+  pub fn new(index: i64, size: i64, color: i64) -> Self {
+    BufferAllocationProto {
+      index: index,
+      size: size,
+      is_thread_local: false,
+      is_tuple: false,
+      is_entry_computation_parameter: false,
+      is_constant: false,
+      parameter_number: 0,
+      parameter_shape_index: Vec::new(),
+      maybe_live_out: false,
+      color: color,
+      assigned: Vec::new()
+    }
+  }
+
+  pub fn index(&self) -> i64 {
+    self.index
+  }
+
+  pub fn size(&self) -> i64 {
+    self.size
+  }
+
+  pub fn color(&self) -> i64 {
+    self.color
+  }
+
+  pub fn is_thread_local(&self) -> bool {
+    self.is_thread_local
+  }
+
+  pub fn set_is_thread_local(&mut self, is_thread_local: bool) {
+    self.is_thread_local = is_thread_local;
+  }
+
+  pub fn is_tuple(&self) -> bool {
+    self.is_tuple
+  }
+
+  pub fn set_is_tuple(&mut self, is_tuple: bool) {
+    self.is_tuple = is_tuple;
+  }
+
+  pub fn is_entry_computation_parameter(&self) -> bool {
+    self.is_entry_computation_parameter
+  }
+
+  pub fn set_is_entry_computation_parameter(&mut self, value: bool) {
+    self.is_entry_computation_parameter = value;
+  }
+
+  pub fn is_constant(&self) -> bool {
+    self.is_constant
+  }
+
+  pub fn set_is_constant(&mut self, is_constant: bool) {
+    self.is_constant = is_constant;
+  }
+
+  pub fn parameter_number(&self) -> i64 {
+    self.parameter_number
+  }
+
+  pub fn set_parameter_number(&mut self, parameter_number: i64) {
+    self.parameter_number = parameter_number;
+  }
+
+  pub fn parameter_shape_index(&self) -> &Vec<i64> {
+    &self.parameter_shape_index
+  }
+
+  pub fn set_parameter_shape_index(&mut self, index: Vec<i64>) {
+    self.parameter_shape_index = index;
+  }
+
+  pub fn maybe_live_out(&self) -> bool {
+    self.maybe_live_out
+  }
+
+  pub fn set_maybe_live_out(&mut self, maybe_live_out: bool) {
+    self.maybe_live_out = maybe_live_out;
+  }
+
+  pub fn assigned(&self) -> &Vec<BufferAllocationProtoAssigned> {
+    &self.assigned
+  }
+
+  pub fn add_assigned(&mut self, assigned: BufferAllocationProtoAssigned) {
+    self.assigned.push(assigned);
+  }
+}
+
 // Serialization of BufferAssignment.
-pub struct BufferAssignmentProto {}
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferAssignmentProto {
+  logical_buffers: Vec<LogicalBufferProto>,
+  buffer_allocations: Vec<BufferAllocationProto>
+}
+
+impl BufferAssignmentProto {
+  pub fn new() -> Self {
+    BufferAssignmentProto {
+      logical_buffers: Vec::new(),
+      buffer_allocations: Vec::new()
+    }
+  }
+
+  pub fn logical_buffers(&self) -> &Vec<LogicalBufferProto> {
+    &self.logical_buffers
+  }
+
+  pub fn add_logical_buffers(&mut self, logical_buffer: LogicalBufferProto) {
+    self.logical_buffers.push(logical_buffer);
+  }
+
+  pub fn buffer_allocations(&self) -> &Vec<BufferAllocationProto> {
+    &self.buffer_allocations
+  }
+
+  pub fn add_buffer_allocations(&mut self, allocation: BufferAllocationProto) {
+    self.buffer_allocations.push(allocation);
+  }
+}
 
 // Grouping message that contains all of the information above.
 pub struct HloProto {
@@ -53,22 +281,93 @@ pub struct HloProto {
   buffer_assignment: BufferAssignmentProto
 }
 
+// Returns the HloProto encoding wrapping the HloModuleProto encoding
+// 'hlo_module'.
+pub fn hlo_proto_from_module_proto(hlo_module: &[u8]) -> Vec<u8> {
+  let mut output = vec![];
+  write_bytes_field(&mut output, HLO_PROTO_HLO_MODULE, hlo_module);
+  output
+}
+
 // Encapsulates HloProto together with the arguments, result, and
 // execution_platform. This message is used for purposes such as
 // analysis/replay/file-storage.
-pub struct HloSnapshot {}
+//
+// The module and the literals are kept in their serialized proto form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HloSnapshot {
+  hlo: Option<Vec<u8>>,
+  arguments: Vec<Vec<u8>>,
+  result: Option<Vec<u8>>,
+  execution_platform: String
+}
 
 impl HloSnapshot {
   pub fn new() -> Self {
-    HloSnapshot {  }
+    HloSnapshot {
+      hlo: None,
+      arguments: Vec::new(),
+      result: None,
+      execution_platform: "".to_string()
+    }
   }
 
-  pub fn hlo(&self) {
-    unimplemented!()
+  // Returns the HloProto encoding of the snapshotted module.
+  pub fn hlo(&self) -> &Vec<u8> {
+    assert!(self.has_hlo());
+    self.hlo.as_ref().unwrap()
   }
 
   pub fn has_hlo(&self) -> bool {
-    unimplemented!()
+    self.hlo.is_some()
+  }
+
+  pub fn set_hlo(&mut self, hlo_proto: Vec<u8>) {
+    self.hlo = Some(hlo_proto);
+  }
+
+  // Returns the LiteralProto encodings of the arguments.
+  pub fn arguments(&self) -> &Vec<Vec<u8>> {
+    &self.arguments
+  }
+
+  pub fn add_arguments(&mut self, literal_proto: Vec<u8>) {
+    self.arguments.push(literal_proto);
+  }
+
+  pub fn result(&self) -> &Option<Vec<u8>> {
+    &self.result
+  }
+
+  pub fn set_result(&mut self, literal_proto: Vec<u8>) {
+    self.result = Some(literal_proto);
+  }
+
+  pub fn execution_platform(&self) -> &String {
+    &self.execution_platform
+  }
+
+  pub fn set_execution_platform(&mut self, execution_platform: String) {
+    self.execution_platform = execution_platform;
+  }
+
+  // Returns the HloSnapshot proto encoding of this snapshot.
+  pub fn serialize_as_string(&self) -> Vec<u8> {
+    let mut output = vec![];
+    if let Some(hlo) = &self.hlo {
+      write_bytes_field(&mut output, SNAPSHOT_HLO, hlo);
+    }
+    for argument in &self.arguments {
+      write_bytes_field(&mut output, SNAPSHOT_ARGUMENTS, argument);
+    }
+    if let Some(result) = &self.result {
+      write_bytes_field(&mut output, SNAPSHOT_RESULT, result);
+    }
+    if !self.execution_platform.is_empty() {
+      write_bytes_field(&mut output, SNAPSHOT_EXECUTION_PLATFORM,
+        self.execution_platform.as_bytes());
+    }
+    output
   }
 }
 
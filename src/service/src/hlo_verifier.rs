@@ -1,11 +1,28 @@
 #![allow(dead_code)]
 
-use std::vec;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use common::{blitz_data::PrimitiveType, primitive_util, shape::{Shape, ShapeEqual}, shape_util::ShapeUtil};
-use hlo::{hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_opcode::hlo_opcode_string};
+use common::{
+  blitz_data::PrimitiveType,
+  shape::{ProgramShape, Shape, ShapeEqual},
+  shape_util::ShapeUtil
+};
+use hlo::{
+  hlo_computation::HloComputation,
+  hlo_instruction::HloInstruction,
+  hlo_module::{ComputationId, HloModule},
+  hlo_opcode::{hlo_opcode_arity, hlo_opcode_string, HloOpcode},
+  hlo_sharding::HloSharding
+};
 
-use crate::shape_inference::ShapeInference;
+use crate::{hlo_pass_interface::HloPassInterface, shape_inference::ShapeInference};
+
+// Size of a pointer in bytes, used when comparing the byte sizes of shapes.
+const POINTER_SIZE: i64 = 8;
+
+// Number of lines of module text printed before and after the offending
+// instruction when the verifier fails.
+const CONTEXT_LINES: usize = 5;
 
 
 pub struct HloVerifierOpts {
@@ -17,9 +34,24 @@ pub struct HloVerifierOpts {
   verify_sharding_device_numbers: bool,
   allow_bitcast_to_have_different_size: bool,
   allow_unbounded_dynamism: bool,
+  instruction_can_change_layout: Option<fn(&HloInstruction) -> bool>,
 }
 
 impl HloVerifierOpts {
+  pub fn new() -> Self {
+    HloVerifierOpts {
+      layout_sensitive: false,
+      allow_mixed_precision: false,
+      verify_broadcast_dimensions_order: false,
+      verify_reshape_is_bitcast: false,
+      verify_custom_call_nested_computation_thread_name: true,
+      verify_sharding_device_numbers: true,
+      allow_bitcast_to_have_different_size: false,
+      allow_unbounded_dynamism: false,
+      instruction_can_change_layout: None,
+    }
+  }
+
   pub fn make_layout_sensitive(&mut self) -> &mut Self {
     self.layout_sensitive = true;
     self
@@ -62,8 +94,14 @@ impl HloVerifierOpts {
     self    
   }
 
-  pub fn with_instruction_can_change_layout() {
-      
+  // Sets the predicate telling whether an instruction may produce a layout
+  // different from the layouts of its operands. Only used when the verifier
+  // is layout sensitive.
+  pub fn with_instruction_can_change_layout(
+    &mut self, instruction_can_change_layout: fn(&HloInstruction) -> bool) -> &mut Self
+  {
+    self.instruction_can_change_layout = Some(instruction_can_change_layout);
+    self
   }
 
   pub fn with_verify_sharding_device_numbers(
@@ -92,24 +130,190 @@ impl HloVerifierOpts {
     self.allow_mixed_precision
   }
 
-  pub fn instruction_can_change_layout(&self) {
-      
+  pub fn instruction_can_change_layout(&self, instruction: &HloInstruction) -> bool {
+    match self.instruction_can_change_layout {
+      Some(func) => func(instruction),
+      None => true
+    }
   }
 }
 
 // Visitor which verifies that the output shape is correctly set.
-pub struct ShapeVerifier {}
+pub struct ShapeVerifier<'a> {
+  module: &'a HloModule,
+  computation: &'a HloComputation,
+  opts: &'a HloVerifierOpts,
+}
 
-impl ShapeVerifier {
-  pub fn new() {}
-  pub fn verify_entry_computation_layout() {}
-  pub fn preprocess(_hlo: &HloInstruction) {}
+impl<'a> ShapeVerifier<'a> {
+  pub fn new(
+    module: &'a HloModule,
+    computation: &'a HloComputation,
+    opts: &'a HloVerifierOpts) -> Self
+  {
+    ShapeVerifier { module: module, computation: computation, opts: opts }
+  }
+
+  // Returns the operand 'operand_num' of 'hlo'.
+  fn operand(&self, hlo: &HloInstruction, operand_num: usize) -> &'a HloInstruction {
+    self.computation.operand(hlo.id(), operand_num)
+  }
+
+  // Returns the shapes of all operands of 'hlo'.
+  fn operand_shapes(&self, hlo: &HloInstruction) -> Vec<Shape> {
+    hlo.operands().iter()
+      .map(|operand| self.computation.instruction(*operand).shape().clone())
+      .collect()
+  }
+
+  // Returns the program shape of the called computation 'id'.
+  fn program_shape(&self, id: ComputationId) -> ProgramShape {
+    self.module.computation(id).compute_program_shape(false)
+  }
+
+  // Verifies 'hlo' by dispatching to the handler of its opcode.
+  pub fn verify(&self, hlo: &HloInstruction) -> Result<(), String> {
+    self.preprocess(hlo)?;
+    match hlo.opcode() {
+      HloOpcode::Abs | HloOpcode::Cbrt | HloOpcode::Ceil | HloOpcode::Clz |
+      HloOpcode::Cos | HloOpcode::Erf | HloOpcode::Exp | HloOpcode::Expm1 |
+      HloOpcode::Floor | HloOpcode::Imag | HloOpcode::IsFinite | HloOpcode::Log |
+      HloOpcode::Log1p | HloOpcode::Logistic | HloOpcode::Negate | HloOpcode::Not |
+      HloOpcode::PopulationCount | HloOpcode::Real | HloOpcode::RoundNearestAfz |
+      HloOpcode::RoundNearestEven | HloOpcode::Rsqrt | HloOpcode::Sign |
+      HloOpcode::Sin | HloOpcode::Sqrt | HloOpcode::Tan | HloOpcode::Tanh =>
+        self.handle_elementwise_unary(hlo),
+      HloOpcode::Add | HloOpcode::And | HloOpcode::Atan2 | HloOpcode::Compare |
+      HloOpcode::Complex | HloOpcode::Divide | HloOpcode::Maximum |
+      HloOpcode::Minimum | HloOpcode::Multiply | HloOpcode::Or | HloOpcode::Power |
+      HloOpcode::Remainder | HloOpcode::ShiftLeft | HloOpcode::ShiftRightArithmetic |
+      HloOpcode::ShiftRightLogical | HloOpcode::Subtract | HloOpcode::Xor =>
+        self.handle_elementwise_binary(hlo),
+      HloOpcode::AddDependency => self.handle_add_dependency(hlo),
+      HloOpcode::AfterAll => self.handle_after_all(hlo),
+      HloOpcode::AllGather => self.handle_all_gather(hlo),
+      HloOpcode::AllGatherDone => self.handle_all_gather_done(hlo),
+      HloOpcode::AllGatherStart => self.handle_all_gather_start(hlo),
+      HloOpcode::AllReduce => self.handle_all_reduce(hlo),
+      HloOpcode::AllReduceDone => self.handle_all_reduce_done(hlo),
+      HloOpcode::AllReduceStart => self.handle_all_reduce_start(hlo),
+      HloOpcode::AllToAll => self.handle_all_to_all(hlo),
+      HloOpcode::AsyncDone => self.handle_async_done(hlo),
+      HloOpcode::AsyncStart => self.handle_async_start(hlo),
+      HloOpcode::AsyncUpdate => self.handle_async_update(hlo),
+      HloOpcode::BatchNormGrad => self.handle_batch_norm_grad(hlo),
+      HloOpcode::BatchNormInference => self.handle_batch_norm_inference(hlo),
+      HloOpcode::BatchNormTraining => self.handle_batch_norm_training(hlo),
+      HloOpcode::Bitcast => self.handle_bitcast(hlo),
+      HloOpcode::BitcastConvert => self.handle_bitcast_convert(hlo),
+      HloOpcode::Broadcast => self.handle_broadcast(hlo),
+      HloOpcode::Call => self.handle_call(hlo),
+      HloOpcode::Cholsky => self.handle_cholsky(hlo),
+      HloOpcode::Clamp => self.handle_clamp(hlo),
+      HloOpcode::CollectiveBroadcast => self.handle_collective_broadcast(hlo),
+      HloOpcode::CollectivePermute => self.handle_collective_permute(hlo),
+      HloOpcode::CollectivePermuteDone => self.handle_collective_permute_done(hlo),
+      HloOpcode::CollectivePermuteStart => self.handle_collective_permute_start(hlo),
+      HloOpcode::Concatenate => self.handle_concatenate(hlo),
+      HloOpcode::Conditional => self.handle_conditional(hlo),
+      HloOpcode::Constant => self.handle_constant(hlo),
+      HloOpcode::Convert => self.handle_convert(hlo),
+      HloOpcode::Convolution => self.handle_convolution(hlo),
+      HloOpcode::Copy => self.handle_copy(hlo),
+      HloOpcode::CopyDone => self.handle_copy_done(hlo),
+      HloOpcode::CopyStart => self.handle_copy_start(hlo),
+      HloOpcode::CustomCall => self.handle_custom_call(hlo),
+      HloOpcode::Domain => self.handle_domain(hlo),
+      HloOpcode::Dot => self.handle_dot(hlo),
+      HloOpcode::DynamicReshape => self.handle_dynamic_reshape(hlo),
+      HloOpcode::DynamicSlice => self.handle_dynamic_slice(hlo),
+      HloOpcode::DynamicUpdateSlice => self.handle_dynamic_update_slice(hlo),
+      HloOpcode::Fft => self.handle_fft(hlo),
+      HloOpcode::Fusion => self.handle_fusion(hlo),
+      HloOpcode::Gather => self.handle_gather(hlo),
+      HloOpcode::GetDimensionSize => self.handle_get_dimension_size(hlo),
+      HloOpcode::GetTupleElement => self.handle_get_tuple_element(hlo),
+      HloOpcode::Infeed => self.handle_infeed(hlo),
+      HloOpcode::Iota => self.handle_iota(hlo),
+      HloOpcode::Map => self.handle_map(hlo),
+      HloOpcode::OptimizationBarrier => self.handle_optimization_barrier(hlo),
+      HloOpcode::Outfeed => self.handle_outfeed(hlo),
+      HloOpcode::Pad => self.handle_pad(hlo),
+      HloOpcode::Parameter => self.handle_parameter(hlo),
+      HloOpcode::PartitionId => self.handle_partition_id(hlo),
+      HloOpcode::RaggedAllToAll => self.handle_ragged_all_to_all(hlo),
+      HloOpcode::Recv => self.handle_recv(hlo),
+      HloOpcode::RecvDone => self.handle_recv_done(hlo),
+      HloOpcode::Reduce => self.handle_reduce(hlo),
+      HloOpcode::ReducePrecision => self.handle_reduce_precision(hlo),
+      HloOpcode::ReduceScatter => self.handle_reduce_scatter(hlo),
+      HloOpcode::ReduceWindow => self.handle_reduce_window(hlo),
+      HloOpcode::ReplicaId => self.handle_replica_id(hlo),
+      HloOpcode::Reshape => self.handle_reshape(hlo),
+      HloOpcode::Reverse => self.handle_reverse(hlo),
+      HloOpcode::Rng => self.handle_rng(hlo),
+      HloOpcode::RngBitGenerator => self.handle_rng_bit_generator(hlo),
+      HloOpcode::RngGetAndUpdateState => self.handle_rng_get_and_update_state(hlo),
+      HloOpcode::Scatter => self.handle_scatter(hlo),
+      HloOpcode::Select => self.handle_select(hlo),
+      HloOpcode::SelectAndScatter => self.handle_select_and_scatter(hlo),
+      HloOpcode::Send => self.handle_send(hlo),
+      HloOpcode::SendDone => self.handle_send_done(hlo),
+      HloOpcode::SetDimensionSize => self.handle_set_dimension_size(hlo),
+      HloOpcode::Slice => self.handle_slice(hlo),
+      HloOpcode::Sort => self.handle_sort(hlo),
+      HloOpcode::StochasticConvert => self.handle_stochastic_convert(hlo),
+      HloOpcode::TopK => self.handle_top_k(hlo),
+      HloOpcode::Transpose => self.handle_transpose(hlo),
+      HloOpcode::TriangularSolve => self.handle_triangular_solve(hlo),
+      HloOpcode::Tuple => self.handle_tuple(hlo),
+      HloOpcode::While => self.handle_while(hlo),
+    }
+  }
+
+  // Checks which do not depend on the opcode: the operand count of fixed
+  // arity opcodes, unbounded dynamism and, when layout sensitive, that
+  // instructions which may not change the layout keep their operands' layout.
+  pub fn preprocess(&self, hlo: &HloInstruction) -> Result<(), String> {
+    // Dots take one extra operand per sparsity descriptor, so their operand
+    // count is checked in handle_dot.
+    if let Some(arity) = hlo_opcode_arity(&hlo.opcode()) {
+      if hlo.opcode() != HloOpcode::Dot {
+        check_operand_count(hlo, arity as usize)?;
+      }
+    }
+    if !self.opts.allow_unbounded_dynamism && hlo.shape().is_unbounded_dynamic() {
+      return Err(format!("Unbounded dynamism is disabled for instruction: {}",
+        hlo.to_string_default()));
+    }
+    if self.opts.layout_sensitive && hlo.shape().is_array() &&
+      !self.opts.instruction_can_change_layout(hlo)
+    {
+      let layout = hlo.shape().layout().as_ref()
+        .map(|layout| layout.minor_to_major_vec());
+      for i in 0..hlo.operand_count() {
+        let operand_shape = self.operand(hlo, i).shape();
+        if !operand_shape.is_array() || operand_shape.rank() != hlo.shape().rank() {
+          continue;
+        }
+        let operand_layout = operand_shape.layout().as_ref()
+          .map(|layout| layout.minor_to_major_vec());
+        if operand_layout != layout {
+          return Err(format!(
+            "Instruction shouldn't change layouts {} from {}",
+            ShapeUtil::human_string_with_layout(hlo.shape()),
+            ShapeUtil::human_string_with_layout(operand_shape)));
+        }
+      }
+    }
+    Ok(())
+  }
 
   pub fn handle_elementwise_unary(&self, hlo: &HloInstruction) -> Result<(), String> {
     self.check_unary_shape(hlo)
   }
 
-  pub fn handle_elementwise_bynary(&self, hlo: &HloInstruction) -> Result<(), String> {
+  pub fn handle_elementwise_binary(&self, hlo: &HloInstruction) -> Result<(), String> {
     self.check_binary_shape(hlo)
   }
 
@@ -124,7 +328,7 @@ impl ShapeVerifier {
   pub fn handle_concatenate(&self, concatenate: &HloInstruction) -> Result<(), String> {
     let mut operand_shapes = vec![];
     for operand in concatenate.operands() {
-      operand_shapes.push(operand.shape().clone());
+      operand_shapes.push(self.computation.instruction(*operand).shape().clone());
     }
     self.check_shape_by_status(concatenate,
       ShapeInference::infer_concat_op_shape(
@@ -132,99 +336,176 @@ impl ShapeVerifier {
   }
 
   pub fn handle_iota(&self, iota: &HloInstruction) -> Result<(), String> {
-    if !iota.shape().is_array() {
-      return Err("Iota does not support non-array result.".to_string());
-    }
-    let rank =  iota.shape().rank();
-    if rank == 0 {
-      return Err("Iota does not support scalars.".to_string());
-    }
-    let iota_dimension = iota.iota_dimension();
-    if iota_dimension >= rank as i64 || iota_dimension < 0 {
-      return Err("The iota dimension go beyond the operation rank or be negative.".to_string());
-    }
-    let p_type = iota.shape().element_type();
-    if !primitive_util::is_integral_type(&p_type) &&
-       !primitive_util::is_floating_point_type(&p_type) &&
-       !primitive_util::is_complex_type(&p_type)
-    {
-      let err_msg = "Only support iota of integral, floating point or complex
-        primitive types.".to_string();
-      return Err(err_msg);
+    let operand_count = check_operand_count(iota, 0);
+    if operand_count.is_err() {
+      return Err(operand_count.err().unwrap());
     }
-
-    Ok(())
+    self.check_shape_by_status(iota,
+      ShapeInference::infer_iota_shape(iota.shape(), iota.iota_dimension()))
   }
 
   pub fn handle_convert(&self, convert: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(convert,
       ShapeInference::infer_convert_shape(
-        convert.operand(0).shape(), &convert.shape().element_type()))
+        self.operand(convert, 0).shape(), &convert.shape().element_type()))
   }
 
   pub fn handle_bitcast_convert(&self, convert: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(convert,
       ShapeInference::infer_bitcast_convert_shape(
-        convert.operand(0).shape(), &convert.shape().element_type()))
+        self.operand(convert, 0).shape(), &convert.shape().element_type()))
   }
 
   pub fn handle_stochastic_convert(&self, convert: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(convert,
-      ShapeInference::infer_bitcast_convert_shape(
-        convert.operand(0).shape(), &convert.shape().element_type()))
+      ShapeInference::infer_stochastic_convert_shape(
+        self.operand(convert, 0).shape(),
+        self.operand(convert, 1).shape(),
+        &convert.shape().element_type()))
   }
 
   pub fn handle_copy(&self, copy: &HloInstruction) -> Result<(), String> {
     self.check_unary_shape(copy)
   }
 
-  pub fn handle_dot() {}
-  pub fn handle_convolution() {}
+  pub fn handle_domain(&self, domain: &HloInstruction) -> Result<(), String> {
+    self.check_shape(domain, self.operand(domain, 0).shape(), false)
+  }
+
+  pub fn handle_dot(&self, dot: &HloInstruction) -> Result<(), String> {
+    check_operand_count(dot, 2 + dot.sparsity().len())?;
+    self.check_shape_by_status(dot,
+      ShapeInference::infer_dot_op_shape(
+        self.operand(dot, 0).shape(),
+        self.operand(dot, 1).shape(),
+        dot.dot_dimension_numbers(),
+        Some(dot.shape().element_type()),
+        dot.sparsity()))
+  }
+
+  pub fn handle_convolution(&self, convolution: &HloInstruction) -> Result<(), String> {
+    self.check_shape_by_status(convolution,
+      ShapeInference::infer_convolve_shape(
+        self.operand(convolution, 0).shape(),
+        self.operand(convolution, 1).shape(),
+        convolution.feature_group_count(),
+        convolution.batch_group_count(),
+        convolution.window(),
+        convolution.convolution_dimension_numberes(),
+        Some(convolution.shape().element_type())))
+  }
 
   pub fn handle_fft(&self, fft: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(fft,
       ShapeInference::infer_fft_shape(
-        fft.operand(0).shape(), &fft.fft_type(), fft.fft_length()))
+        self.operand(fft, 0).shape(), &fft.fft_type(), fft.fft_length()))
   }
 
   pub fn handle_cholsky(&self, hlo: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(hlo,
       ShapeInference::infer_cholesky_shape(
-        hlo.operand(0).shape()))
+        self.operand(hlo, 0).shape()))
   }
 
   pub fn handle_triangular_solve(&self, hlo: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(hlo,
       ShapeInference::infer_triangular_solve_shape(
-        hlo.operand(0).shape(), hlo.operand(1).shape(), hlo.triangular_solve_options()))
+        self.operand(hlo, 0).shape(), self.operand(hlo, 1).shape(), hlo.triangular_solve_options()))
+  }
+
+  pub fn handle_all_gather(&self, hlo: &HloInstruction) -> Result<(), String> {
+    let all_gather_dimension = self.collective_dimension(hlo)?;
+    let shard_count = self.subgroup_size(hlo, Some(hlo.use_global_device_ids()));
+    self.check_shape_by_status(hlo,
+      ShapeInference::infer_all_gather_shape(
+        &self.operand_shapes(hlo), all_gather_dimension, shard_count))
   }
 
-  pub fn handle_all_gather() {}
-  pub fn handle_all_gather_start() {}
+  pub fn handle_all_gather_start(&self, hlo: &HloInstruction) -> Result<(), String> {
+    let all_gather_dimension = self.collective_dimension(hlo)?;
+    let shard_count = self.subgroup_size(hlo, Some(hlo.use_global_device_ids()));
+    self.check_shape_by_status(hlo,
+      ShapeInference::infer_all_gather_start_shape(
+        &self.operand_shapes(hlo), all_gather_dimension, shard_count))
+  }
 
   pub fn handle_all_gather_done(&self, hlo: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(hlo,
       ShapeInference::infer_all_gether_done_shape(
-        hlo.operand(0).shape()))
+        self.operand(hlo, 0).shape()))
   }
 
-  pub fn handle_all_reduce() {}
-  pub fn handle_all_reduce_start() {}
+  pub fn handle_all_reduce(&self, hlo: &HloInstruction) -> Result<(), String> {
+    self.check_shape_by_status(hlo,
+      ShapeInference::infer_all_reduce_shape(&self.operand_shapes(hlo)))
+  }
+
+  pub fn handle_all_reduce_start(&self, hlo: &HloInstruction) -> Result<(), String> {
+    self.check_shape_by_status(hlo,
+      ShapeInference::infer_all_reduce_shape(&self.operand_shapes(hlo)))
+  }
 
   pub fn handle_all_reduce_done(&self, hlo: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(hlo,
       ShapeInference::infer_all_reduce_done_shape(
-        hlo.operand(0).shape()))
+        self.operand(hlo, 0).shape()))
+  }
+
+  pub fn handle_all_to_all(&self, hlo: &HloInstruction) -> Result<(), String> {
+    let split_count = self.subgroup_size(hlo, None);
+    if !hlo.dimensions().is_empty() {
+      // Array all-to-all: the single operand is split and concatenated along
+      // the same dimension.
+      check_operand_count(hlo, 1)?;
+      let split_dimension = hlo.dimensions()[0];
+      return self.check_shape_by_status(hlo,
+        ShapeInference::infer_all_to_all_shape(
+          self.operand(hlo, 0).shape(), split_dimension, split_dimension, split_count));
+    }
+    if hlo.operand_count() as i64 != split_count {
+      return Err(format!(
+        "Tuple all-to-all should have {} operands, one per participant, but has {}: {}",
+        split_count, hlo.operand_count(), hlo.to_string_default()));
+    }
+    self.check_shape_by_status(hlo,
+      ShapeInference::infer_all_to_all_tuple_shape(&self.operand_shapes(hlo)))
+  }
+
+  pub fn handle_ragged_all_to_all(&self, hlo: &HloInstruction) -> Result<(), String> {
+    self.check_shape_by_status(hlo,
+      ShapeInference::infer_ragged_all_to_all_shape(&self.operand_shapes(hlo)))
   }
 
-  pub fn handle_all_to_all() {}
-  pub fn handle_collective_permute() {}
-  pub fn handle_collective_permute_start() {}
+  pub fn handle_collective_broadcast(&self, hlo: &HloInstruction) -> Result<(), String> {
+    self.check_shape_by_status(hlo,
+      ShapeInference::infer_collective_broadcast_shape(&self.operand_shapes(hlo)))
+  }
+
+  pub fn handle_collective_permute(&self, hlo: &HloInstruction) -> Result<(), String> {
+    // The in-place form takes the input, the output buffer and the input and
+    // output start indices.
+    let inplace = hlo.operand_count() == 4;
+    self.check_shape_by_status(hlo,
+      ShapeInference::infer_collective_permute_shape(&self.operand_shapes(hlo), inplace))
+  }
+
+  pub fn handle_collective_permute_start(&self, hlo: &HloInstruction) -> Result<(), String> {
+    let inplace = hlo.operand_count() == 4;
+    let mut context_shapes = vec![];
+    if hlo.shape().is_tuple() && hlo.shape().tuple_shapes_size() > 2 {
+      for i in 2..hlo.shape().tuple_shapes_size() {
+        context_shapes.push(hlo.shape().tuple_shapes(i).clone());
+      }
+    }
+    self.check_shape_by_status(hlo,
+      ShapeInference::infer_collective_permute_start_shape(
+        &self.operand_shapes(hlo), &context_shapes, inplace))
+  }
 
   pub fn handle_collective_permute_done(&self, hlo: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(hlo,
       ShapeInference::infer_collective_permute_done_shape(
-        hlo.operand(0).shape()))
+        self.operand(hlo, 0).shape()))
   }
 
   pub fn handle_partition_id(&self, hlo: &HloInstruction) -> Result<(), String> {
@@ -242,12 +523,23 @@ impl ShapeVerifier {
   pub fn handle_reduce_precision(&self, reduce_precision: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(reduce_precision,
       ShapeInference::infer_reduce_precision_shape(
-        reduce_precision.operand(0).shape(),
+        self.operand(reduce_precision, 0).shape(),
         reduce_precision.operand_bits(),
         reduce_precision.mantissa_bits()))
   }
 
-  pub fn handle_infeed() {}
+  pub fn handle_infeed(&self, infeed: &HloInstruction) -> Result<(), String> {
+    self.check_is_token_operand(infeed, 0)?;
+    // The output of infeed is a tuple containing the data value and a token.
+    if !infeed.shape().is_tuple() || infeed.shape().tuple_shapes_size() != 2 {
+      return Err(format!("Infeed should produce a (data, token) tuple, but has shape {}.",
+        ShapeUtil::human_string(infeed.shape())));
+    }
+    self.check_shape(infeed,
+      &ShapeUtil::make_tuple_shape(
+        vec![infeed.shape().tuple_shapes(0).clone(), ShapeUtil::make_token_shape()]),
+      false)
+  }
 
   pub fn handle_optimization_barrier(&self, hlo: &HloInstruction) -> Result<(), String> {
     let operand_count = check_operand_count(hlo, 1);
@@ -255,61 +547,284 @@ impl ShapeVerifier {
       return Err(operand_count.err().unwrap());
     }
     self.check_shape(hlo,
-      hlo.operand(0).shape(),
+      self.operand(hlo, 0).shape(),
       false)
   }
 
-  pub fn handle_outfeed() {}
-  pub fn handle_rng() {}
-  pub fn handle_rng_bit_generator() {}
-  pub fn handle_rng_get_and_update_state() {}
+  pub fn handle_outfeed(&self, outfeed: &HloInstruction) -> Result<(), String> {
+    self.check_is_token_operand(outfeed, 1)?;
+    // The outfeed shape is kept separately from the operand so that the
+    // layout of the transferred data can be fixed.
+    if !ShapeUtil::compatible(outfeed.outfeed_shape(), self.operand(outfeed, 0).shape()) {
+      return Err(format!(
+        "Expected outfeed shape to be compatible with operand's shape {}, actual shape is {}:\n{}",
+        ShapeUtil::human_string_with_layout(self.operand(outfeed, 0).shape()),
+        ShapeUtil::human_string_with_layout(outfeed.outfeed_shape()),
+        outfeed.to_string_default()));
+    }
+    self.check_shape(outfeed, &ShapeUtil::make_token_shape(), false)
+  }
+
+  pub fn handle_rng(&self, rng: &HloInstruction) -> Result<(), String> {
+    check_operand_count(rng, 2)?;
+    self.check_shape_by_status(rng,
+      ShapeInference::infer_rng_shape(
+        &rng.random_distribution(), &self.operand_shapes(rng), rng.shape()))
+  }
+
+  pub fn handle_rng_bit_generator(&self, hlo: &HloInstruction) -> Result<(), String> {
+    let operand_count = check_operand_count(hlo, 1);
+    if operand_count.is_err() {
+      return Err(operand_count.err().unwrap());
+    }
+    if !hlo.shape().is_tuple() || hlo.shape().tuple_shapes_size() != 2 {
+      return Err(format!("RngBitGenerator result should be a 2-tuple, but is {}.",
+        ShapeUtil::human_string(hlo.shape())));
+    }
+    self.check_shape_by_status(hlo,
+      ShapeInference::infer_rng_bit_generator_shape(
+        self.operand(hlo, 0).shape(), &hlo.rng_algorithm(), hlo.shape().tuple_shapes(1)))
+  }
+
+  pub fn handle_rng_get_and_update_state(&self, hlo: &HloInstruction) -> Result<(), String> {
+    check_operand_count(hlo, 0)?;
+    self.check_shape(hlo,
+      &ShapeUtil::make_shape(&PrimitiveType::U64, vec![2]),
+      false)
+  }
 
   pub fn handle_reverse(&self, reverse: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(reverse,
       ShapeInference::infer_reverse_shape(
-        reverse.operand(0).shape(), reverse.dimensions()))
+        self.operand(reverse, 0).shape(), reverse.dimensions()))
   }
 
-  pub fn handle_sort() {}
+  pub fn handle_sort(&self, sort: &HloInstruction) -> Result<(), String> {
+    if sort.operand_count() < 1 {
+      return Err(format!("Expected at least 1 operand for {} instruction: {}.",
+        hlo_opcode_string(&sort.opcode()), sort.name()));
+    }
+    if sort.dimensions().len() != 1 {
+      return Err(format!("Sort expects exactly one dimension, but got {}.",
+        sort.dimensions().len()));
+    }
+    let mut operand_shapes = vec![];
+    for i in 0..sort.operand_count() {
+      operand_shapes.push(self.operand(sort, i).shape().clone());
+    }
+    self.check_shape_by_status(sort,
+      ShapeInference::infer_sort_shape(&operand_shapes, sort.dimensions()[0]))
+  }
 
   pub fn handle_top_k(&self, hlo: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(hlo,
       ShapeInference::infer_topk_shape(
-        hlo.operand(0).shape(), hlo.k()))
+        self.operand(hlo, 0).shape(), hlo.k()))
   }
 
-  pub fn handle_constant() {}
+  pub fn handle_constant(&self, constant: &HloInstruction) -> Result<(), String> {
+    check_operand_count(constant, 0)?;
+    if !constant.has_literal() {
+      return Err(format!("Constant is missing its literal: {}",
+        constant.to_string_default()));
+    }
+    if constant.shape().is_array() &&
+      !ShapeUtil::same_dimensions(constant.literal().shape(), constant.shape())
+    {
+      return Err(format!(
+        "Constant literal has shape {} which does not match the instruction shape {}.",
+        ShapeUtil::human_string(constant.literal().shape()),
+        ShapeUtil::human_string(constant.shape())));
+    }
+    Ok(())
+  }
 
   pub fn handle_get_tuple_element(&self, get_tuple_element: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(get_tuple_element,
       ShapeInference::infer_get_tuple_element_shape(
-        get_tuple_element.operand(0).shape(), get_tuple_element.tuple_index()))
+        self.operand(get_tuple_element, 0).shape(), get_tuple_element.tuple_index()))
+  }
+
+  pub fn handle_reduce(&self, reduce: &HloInstruction) -> Result<(), String> {
+    if reduce.operand_count() % 2 != 0 {
+      return Err(format!(
+        "Expected an even number of operands for reduce instruction: {}",
+        reduce.to_string_default()));
+    }
+    self.check_shape_by_status(reduce,
+      ShapeInference::infer_reduce_shape(
+        &self.operand_shapes(reduce),
+        reduce.dimensions(),
+        &self.program_shape(reduce.to_apply())))
+  }
+
+  pub fn handle_bitcast(&self, bitcast: &HloInstruction) -> Result<(), String> {
+    // Without layouts the byte sizes of the shapes are not known yet.
+    if !self.opts.layout_sensitive || self.opts.allow_bitcast_to_have_different_size {
+      return Ok(());
+    }
+    let output_size = ShapeUtil::byte_size_of(bitcast.shape(), POINTER_SIZE);
+    let operand_size =
+      ShapeUtil::byte_size_of(self.operand(bitcast, 0).shape(), POINTER_SIZE);
+    if output_size != operand_size {
+      return Err(format!(
+        "Bitcast cannot have different shape sizes of output ({}) and operand ({}): {}",
+        output_size, operand_size, bitcast.to_string_default()));
+    }
+    Ok(())
+  }
+
+  pub fn handle_broadcast(&self, broadcast: &HloInstruction) -> Result<(), String> {
+    // Broadcast does not change the element type, and unlike the
+    // broadcast_in_dim of the builder it does not expand degenerate
+    // dimensions: every operand dimension keeps its size in the output.
+    let operand_shape = self.operand(broadcast, 0).shape();
+    if !self.same_element_type(operand_shape, broadcast.shape()) {
+      return Err(format!("Broadcast must not change the element type: {} -> {}.",
+        ShapeUtil::human_string(operand_shape), ShapeUtil::human_string(broadcast.shape())));
+    }
+    if operand_shape.rank() != broadcast.dimensions().len() {
+      return Err(format!(
+        "Broadcast HLO ({}) has invalid number of dimensions: {} != {}.",
+        broadcast.to_string_default(), operand_shape.rank(), broadcast.dimensions().len()));
+    }
+    for (operand_dimension, output_dimension) in broadcast.dimensions().iter().enumerate() {
+      if *output_dimension < 0 || *output_dimension as usize >= broadcast.shape().rank() {
+        return Err(format!("Broadcast dimension {} is out of bound: {}",
+          output_dimension, broadcast.to_string_default()));
+      }
+      let output_size = broadcast.shape().dimensions(*output_dimension as usize);
+      let operand_size = operand_shape.dimensions(operand_dimension);
+      if output_size != operand_size {
+        return Err(format!(
+          "Broadcast operand dimension {} has size {} but output dimension {} has size {}: {}",
+          operand_dimension, operand_size, output_dimension, output_size,
+          broadcast.to_string_default()));
+      }
+    }
+    if self.opts.verify_broadcast_dimensions_order &&
+      !broadcast.dimensions().windows(2).all(|pair| pair[0] < pair[1])
+    {
+      return Err(format!("Broadcast dimensions should be ordered, got: {}",
+        broadcast.to_string_default()));
+    }
+    Ok(())
+  }
+
+  pub fn handle_reshape(&self, reshape: &HloInstruction) -> Result<(), String> {
+    let operand_shape = self.operand(reshape, 0).shape();
+    if !self.same_element_type(operand_shape, reshape.shape()) {
+      return Err(format!("Reshape must not change the element type: {} -> {}.",
+        ShapeUtil::human_string(operand_shape), ShapeUtil::human_string(reshape.shape())));
+    }
+    if ShapeUtil::elements_in(operand_shape) != ShapeUtil::elements_in(reshape.shape()) {
+      return Err(format!(
+        "Reshape must not change the number of elements: {} -> {}.",
+        ShapeUtil::human_string(operand_shape), ShapeUtil::human_string(reshape.shape())));
+    }
+    Ok(())
   }
 
-  pub fn handle_reduce() {}
-  pub fn handle_bitcast() {}
-  pub fn handle_broadcast() {}
-  pub fn handle_reshape() {}
-  pub fn handle_dynamic_reshape() {}
+  pub fn handle_dynamic_reshape(&self, dynamic_reshape: &HloInstruction) -> Result<(), String> {
+    let shape = dynamic_reshape.shape();
+    let mut new_size_bounds = vec![];
+    let mut dims_are_dynamic = vec![];
+    for i in 0..shape.rank() {
+      new_size_bounds.push(shape.dimensions(i));
+      dims_are_dynamic.push(shape.is_dynamic_dimension(i as i64));
+    }
+    let operand_shapes = self.operand_shapes(dynamic_reshape);
+    self.check_shape_by_status(dynamic_reshape,
+      ShapeInference::infer_dynamic_reshape_shape(
+        &operand_shapes[0],
+        &operand_shapes[1..].to_vec(),
+        &new_size_bounds,
+        &dims_are_dynamic))
+  }
 
   pub fn handle_transpose(&self, transpose: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(transpose,
       ShapeInference::infer_tranpose_shape(
-        transpose.operand(0).shape(), transpose.dimensions()))
+        self.operand(transpose, 0).shape(), transpose.dimensions()))
   }
 
   pub fn handle_parameter(&self, _hlo: &HloInstruction) -> Result<(), String> {
     Ok(())
   }
 
-  pub fn handle_fusion() {}
-  pub fn handle_call() {}
-  pub fn handle_custom_call() {}
+  pub fn handle_fusion(&self, fusion: &HloInstruction) -> Result<(), String> {
+    if fusion.called_computations().len() != 1 {
+      return Err(format!(
+        "Fusion {} should call exactly one computation, but calls {}.",
+        fusion.name(), fusion.called_computations().len()));
+    }
+    let fused_computation = self.module.computation(fusion.called_computations()[0]);
+    if !fused_computation.is_fusion_computation() {
+      return Err(format!(
+        "Computation {} called by fusion {} is not a fusion computation.",
+        fused_computation.name(), fusion.name()));
+    }
+    ShapeVerifier::check_parameter_count(
+      fusion, fused_computation, fusion.operand_count() as i64)?;
+    for i in 0..fusion.operand_count() {
+      let parameter = fused_computation.parameter_instruction(i).unwrap();
+      let operand = self.operand(fusion, i);
+      if !self.shape_same(parameter.shape(), operand.shape(), false, false) {
+        return Err(format!(
+          "Shape mismatch between parameter number {} of fused computation {} ({}) and operand {} of fusion {} ({}).",
+          i, fused_computation.name(), ShapeUtil::human_string_with_layout(parameter.shape()),
+          i, fusion.name(), ShapeUtil::human_string_with_layout(operand.shape())));
+      }
+    }
+    self.check_shape(fusion, fused_computation.root_instruction().shape(), false)
+  }
+
+  pub fn handle_call(&self, call: &HloInstruction) -> Result<(), String> {
+    let to_apply = self.module.computation(call.to_apply());
+    ShapeVerifier::check_parameter_count(call, to_apply, call.operand_count() as i64)?;
+    self.check_shape_by_status(call,
+      ShapeInference::infer_call_shape(
+        &self.operand_shapes(call), &to_apply.compute_program_shape(false)))
+  }
+
+  pub fn handle_custom_call(&self, custom_call: &HloInstruction) -> Result<(), String> {
+    if custom_call.layout_constrained() {
+      // If the layout is constrained, the operands must have the shapes the
+      // custom call was created with.
+      let operand_shapes_with_layout = custom_call.operand_shapes_with_layout();
+      if operand_shapes_with_layout.len() != custom_call.operand_count() {
+        return Err(format!(
+          "Custom call {} has {} operands but {} operand shapes with layout.",
+          custom_call.name(), custom_call.operand_count(), operand_shapes_with_layout.len()));
+      }
+      for (i, operand_shape_with_layout) in operand_shapes_with_layout.iter().enumerate() {
+        if !ShapeUtil::compatible(self.operand(custom_call, i).shape(), operand_shape_with_layout) {
+          return Err(format!(
+            "Operand {} of custom call {} has shape {} which is not compatible with the constrained shape {}.",
+            i, custom_call.name(),
+            ShapeUtil::human_string(self.operand(custom_call, i).shape()),
+            ShapeUtil::human_string(operand_shape_with_layout)));
+        }
+      }
+    }
+    if self.opts.verify_custom_call_nested_computation_thread_name {
+      for id in custom_call.called_computations() {
+        let called = self.module.computation(*id);
+        if called.execution_thread() != self.computation.execution_thread() {
+          return Err(format!(
+            "Custom call {} runs on thread {} but calls computation {} on thread {}.",
+            custom_call.name(), self.computation.execution_thread(),
+            called.name(), called.execution_thread()));
+        }
+      }
+    }
+    Ok(())
+  }
 
   pub fn handle_slice(&self, slice: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(slice,
       ShapeInference::infer_slice_shape(
-        slice.operand(0).shape(),
+        self.operand(slice, 0).shape(),
         slice.slice_starts(),
         slice.slice_limits(),
         slice.slice_strides()))
@@ -318,7 +833,7 @@ impl ShapeVerifier {
   pub fn handle_dynamic_slice(&self, dynamic_slice: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(dynamic_slice,
       ShapeInference::infer_dynamic_slice_shape(
-        dynamic_slice.operand(0).shape(),
+        self.operand(dynamic_slice, 0).shape(),
         dynamic_slice.index_shapes(),
         dynamic_slice.dynamic_slice_sizes(),
         true))
@@ -329,8 +844,8 @@ impl ShapeVerifier {
   {
     self.check_shape_by_status(dynamic_update_slice,
       ShapeInference::infer_dynamic_update_slice_shape(
-        dynamic_update_slice.operand(0).shape(),
-        dynamic_update_slice.operand(1).shape(),
+        self.operand(dynamic_update_slice, 0).shape(),
+        self.operand(dynamic_update_slice, 1).shape(),
         dynamic_update_slice.index_shapes(),
         true))
   }
@@ -339,41 +854,157 @@ impl ShapeVerifier {
     self.check_variadic_shape(tuple)
   }
 
-  pub fn handle_map() {}
-  pub fn handle_reduce_scatter() {}
-  pub fn handle_reduce_window() {}
-  pub fn handle_select_and_scatter() {}
-  pub fn handle_while() {}
-  pub fn handle_cinditional() {}
+  pub fn handle_map(&self, map: &HloInstruction) -> Result<(), String> {
+    // Map is applied to all the dimensions of its operands.
+    let operand_shapes = self.operand_shapes(map);
+    let max_operand_rank = operand_shapes.iter()
+      .map(|shape| shape.rank()).max().unwrap_or(0);
+    let map_dims: Vec<i64> = (0..max_operand_rank as i64).collect();
+    self.check_shape_by_status(map,
+      ShapeInference::infer_map_shape(
+        &operand_shapes, &self.program_shape(map.to_apply()), &map_dims))
+  }
+
+  pub fn handle_reduce_scatter(&self, hlo: &HloInstruction) -> Result<(), String> {
+    let scatter_dimension = self.collective_dimension(hlo)?;
+    let shard_count = self.subgroup_size(hlo, Some(hlo.use_global_device_ids()));
+    self.check_shape_by_status(hlo,
+      ShapeInference::infer_reduce_scatter_shape(
+        &self.operand_shapes(hlo), scatter_dimension, shard_count))
+  }
+
+  pub fn handle_reduce_window(&self, reduce_window: &HloInstruction) -> Result<(), String> {
+    if reduce_window.operand_count() % 2 != 0 {
+      return Err(format!(
+        "Expected an even number of operands for reduce-window instruction: {}",
+        reduce_window.to_string_default()));
+    }
+    let operand_shapes = self.operand_shapes(reduce_window);
+    let input_count = operand_shapes.len() / 2;
+    self.check_shape_by_status(reduce_window,
+      ShapeInference::infer_variadic_reduce_window_shape(
+        &operand_shapes[..input_count].to_vec(),
+        &operand_shapes[input_count..].to_vec(),
+        reduce_window.window(),
+        &self.program_shape(reduce_window.to_apply())))
+  }
+
+  pub fn handle_select_and_scatter(&self, instruction: &HloInstruction) -> Result<(), String> {
+    self.check_shape_by_status(instruction,
+      ShapeInference::infer_select_and_scatter_shape(
+        self.operand(instruction, 0).shape(),
+        &self.program_shape(instruction.select()),
+        instruction.window(),
+        self.operand(instruction, 1).shape(),
+        self.operand(instruction, 2).shape(),
+        &self.program_shape(instruction.scatter())))
+  }
+
+  pub fn handle_while(&self, xla_while: &HloInstruction) -> Result<(), String> {
+    let body = self.module.computation(xla_while.while_body());
+    let condition = self.module.computation(xla_while.while_condition());
+    ShapeVerifier::check_parameter_count(xla_while, body, 1)?;
+    ShapeVerifier::check_parameter_count(xla_while, condition, 1)?;
+    self.check_shape_by_status(xla_while,
+      ShapeInference::infer_while_shape(
+        &condition.compute_program_shape(false),
+        &body.compute_program_shape(false),
+        self.operand(xla_while, 0).shape()))
+  }
+
+  pub fn handle_conditional(&self, conditional: &HloInstruction) -> Result<(), String> {
+    let branch_count = conditional.branch_computations().len();
+    if branch_count < 1 {
+      return Err(format!("Conditional should have at least one branch: {}",
+        conditional.to_string_default()));
+    }
+    check_operand_count(conditional, branch_count + 1)?;
+    let mut branch_computations = vec![];
+    for id in conditional.branch_computations() {
+      let branch = self.module.computation(*id);
+      ShapeVerifier::check_parameter_count(conditional, branch, 1)?;
+      branch_computations.push(branch.compute_program_shape(false));
+    }
+    let operand_shapes = self.operand_shapes(conditional);
+    self.check_shape_by_status(conditional,
+      ShapeInference::infer_conditional_shape(
+        &operand_shapes[0], &branch_computations, &operand_shapes[1..].to_vec()))
+  }
 
   pub fn handle_pad(&self, pad: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(pad,
       ShapeInference::infer_pad_shape(
-        pad.operand(0).shape(),
-        pad.operand(1).shape(),
+        self.operand(pad, 0).shape(),
+        self.operand(pad, 1).shape(),
         pad.padding_config()))
   }
 
-  pub fn handle_async_start() {}
-  pub fn handle_async_update() {}
-  pub fn handle_done() {}
+  pub fn handle_async_start(&self, async_start: &HloInstruction) -> Result<(), String> {
+    // The result of async-start is a tuple of the operands, the result of the
+    // wrapped computation and an optional context.
+    let shape = async_start.shape();
+    if !shape.is_tuple() || shape.tuple_shapes_size() < 2 {
+      return Err(format!(
+        "Async-start should produce a tuple of at least 2 elements, but has shape {}.",
+        ShapeUtil::human_string(shape)));
+    }
+    if async_start.called_computations().len() != 1 {
+      return Err(format!("Async-start {} should call exactly one computation, but calls {}.",
+        async_start.name(), async_start.called_computations().len()));
+    }
+    let wrapped = self.module.computation(async_start.called_computations()[0]);
+    ShapeVerifier::check_parameter_count(
+      async_start, wrapped, async_start.operand_count() as i64)?;
+    let operands_shape = ShapeUtil::make_tuple_shape(self.operand_shapes(async_start));
+    if !self.shape_same(shape.tuple_shapes(0), &operands_shape, false, false) {
+      return Err(format!(
+        "Async-start operands shape {} does not match the operands {}: {}",
+        ShapeUtil::human_string(shape.tuple_shapes(0)),
+        ShapeUtil::human_string(&operands_shape), async_start.to_string_default()));
+    }
+    let root_shape = wrapped.root_instruction().shape();
+    if !self.shape_same(shape.tuple_shapes(1), root_shape, false, false) {
+      return Err(format!(
+        "Async-start result shape {} does not match the root of computation {} ({}).",
+        ShapeUtil::human_string(shape.tuple_shapes(1)), wrapped.name(),
+        ShapeUtil::human_string(root_shape)));
+    }
+    Ok(())
+  }
+
+  pub fn handle_async_update(&self, async_update: &HloInstruction) -> Result<(), String> {
+    self.check_shape(async_update, self.operand(async_update, 0).shape(), false)
+  }
+
+  pub fn handle_async_done(&self, async_done: &HloInstruction) -> Result<(), String> {
+    let operand_shape = self.operand(async_done, 0).shape();
+    if !operand_shape.is_tuple() || operand_shape.tuple_shapes_size() < 2 {
+      return Err(format!(
+        "Async-done operand should be the tuple produced by async-start, but has shape {}.",
+        ShapeUtil::human_string(operand_shape)));
+    }
+    self.check_shape(async_done, operand_shape.tuple_shapes(1), false)
+  }
 
   pub fn handle_copy_start(&self, copy_start: &HloInstruction) -> Result<(), String> {
     self.check_shape(copy_start,
       &ShapeUtil::make_tuple_shape(
-        vec![copy_start.operand(0).shape().clone(),
-        copy_start.operand(0).shape().clone(),
+        vec![self.operand(copy_start, 0).shape().clone(),
+        self.operand(copy_start, 0).shape().clone(),
         ShapeUtil::make_shape(&PrimitiveType::U32, vec![])]
       ),
       true)
   }
 
-  pub fn handle_copy_done() {}
+  pub fn handle_copy_done(&self, copy_done: &HloInstruction) -> Result<(), String> {
+    self.check_shape_by_status(copy_done,
+      ShapeInference::infer_copy_done_shape(self.operand(copy_done, 0).shape()))
+  }
 
   pub fn handle_send(&self, send: &HloInstruction) -> Result<(), String> {
     self.check_shape(send,
       &ShapeUtil::make_tuple_shape(
-        vec![send.operand(0).shape().clone(),
+        vec![self.operand(send, 0).shape().clone(),
         ShapeUtil::make_shape(&PrimitiveType::U32, vec![]),
         ShapeUtil::make_token_shape()]
       ),
@@ -399,7 +1030,7 @@ impl ShapeVerifier {
   pub fn handle_recv_done(&self, recv_done: &HloInstruction) -> Result<(), String> {
     self.check_shape(recv_done,
       &ShapeUtil::make_tuple_shape(
-        vec![ShapeUtil::get_tuple_element_shape(recv_done.operand(0).shape(), 0).clone(),
+        vec![ShapeUtil::get_tuple_element_shape(self.operand(recv_done, 0).shape(), 0).clone(),
         ShapeUtil::make_token_shape()]
       ),
       false)
@@ -410,9 +1041,9 @@ impl ShapeVerifier {
   {
     self.check_shape_by_status(batch_norm_training,
       ShapeInference::infer_batch_norm_training_shape(
-        batch_norm_training.operand(0).shape(),
-        batch_norm_training.operand(1).shape(),
-        batch_norm_training.operand(2).shape(),
+        self.operand(batch_norm_training, 0).shape(),
+        self.operand(batch_norm_training, 1).shape(),
+        self.operand(batch_norm_training, 2).shape(),
         batch_norm_training.feature_index()))
   }
 
@@ -421,56 +1052,61 @@ impl ShapeVerifier {
   {
     self.check_shape_by_status(batch_norm_inference,
       ShapeInference::infer_batch_norm_inference_shape(
-        batch_norm_inference.operand(0).shape(),
-        batch_norm_inference.operand(1).shape(),
-        batch_norm_inference.operand(2).shape(),
-        batch_norm_inference.operand(3).shape(),
-        batch_norm_inference.operand(4).shape(),
+        self.operand(batch_norm_inference, 0).shape(),
+        self.operand(batch_norm_inference, 1).shape(),
+        self.operand(batch_norm_inference, 2).shape(),
+        self.operand(batch_norm_inference, 3).shape(),
+        self.operand(batch_norm_inference, 4).shape(),
         batch_norm_inference.feature_index()))
   }
 
   pub fn handle_batch_norm_grad(&self, batch_norm_grad: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(batch_norm_grad,
       ShapeInference::infer_batch_norm_grad_shape(
-        batch_norm_grad.operand(0).shape(),
-        batch_norm_grad.operand(1).shape(),
-        batch_norm_grad.operand(2).shape(),
-        batch_norm_grad.operand(3).shape(),
-        batch_norm_grad.operand(4).shape(),
+        self.operand(batch_norm_grad, 0).shape(),
+        self.operand(batch_norm_grad, 1).shape(),
+        self.operand(batch_norm_grad, 2).shape(),
+        self.operand(batch_norm_grad, 3).shape(),
+        self.operand(batch_norm_grad, 4).shape(),
         batch_norm_grad.feature_index()))
   }
 
   pub fn handle_gather(&self, gather: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(gather,
       ShapeInference::infer_gather_shape(
-        gather.operand(0).shape(), 
-        gather.operand(1).shape(),
+        self.operand(gather, 0).shape(),
+        self.operand(gather, 1).shape(),
         gather.gather_dimension_numbers(),
         gather.gather_slice_sizes()))
   }
 
-  pub fn handle_scatter() {}
+  pub fn handle_scatter(&self, scatter: &HloInstruction) -> Result<(), String> {
+    self.check_shape_by_status(scatter,
+      ShapeInference::infer_scatter_shape(
+        &self.operand_shapes(scatter),
+        &self.program_shape(scatter.to_apply()),
+        scatter.scatter_dimension_numbers()))
+  }
 
   pub fn handle_after_all(&self, token: &HloInstruction) -> Result<(), String> {
     let mut operand_shapes = vec![];
     for operand in token.operands() {
-      operand_shapes.push(operand.shape().clone())
+      operand_shapes.push(self.computation.instruction(*operand).shape().clone())
     }
-    self.check_shape(token,
-      &ShapeUtil::make_token_shape(),
-      false)
+    self.check_shape_by_status(token,
+      ShapeInference::infer_after_all_shape(&operand_shapes))
   }
 
   pub fn handle_get_dimension_size(&self, get_size: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(get_size,
       ShapeInference::infer_get_dimension_size_shape(
-        get_size.operand(0).shape(), get_size.dimension()))
+        self.operand(get_size, 0).shape(), get_size.dimension()))
   }
 
   pub fn handle_set_dimension_size(&self, set_size: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(set_size,
       ShapeInference::infer_set_dimension_size_shape(
-        set_size.operand(0).shape(), set_size.operand(1).shape(), set_size.dimension()))
+        self.operand(set_size, 0).shape(), self.operand(set_size, 1).shape(), set_size.dimension()))
   }
 
   pub fn handle_add_dependency(
@@ -481,31 +1117,118 @@ impl ShapeVerifier {
     if is_token.is_err() { return is_token; }
 
     self.check_shape(add_dependency,
-      add_dependency.operand(0).shape(),
+      self.operand(add_dependency, 0).shape(),
       false)
   }
 
-  pub fn finish_visit() {}
+  // Returns the dimension a collective gathers or scatters along.
+  fn collective_dimension(&self, hlo: &HloInstruction) -> Result<i64, String> {
+    if hlo.dimensions().len() != 1 {
+      return Err(format!("Expected exactly one dimension for {} instruction: {}",
+        hlo_opcode_string(&hlo.opcode()), hlo.to_string_default()));
+    }
+    Ok(hlo.dimensions()[0])
+  }
+
+  // Returns the number of participants in each replica group of the
+  // collective 'hlo'. Which devices take part depends on whether the
+  // collective has a channel id and on 'use_global_device_ids'; ops without
+  // that flag communicate across partitions when they have a channel id.
+  fn subgroup_size(&self, hlo: &HloInstruction, use_global_device_ids: Option<bool>) -> i64 {
+    let config = self.module.config();
+    let group_size = hlo.replica_groups().first()
+      .map(|group| group.replica_ids().len() as i64);
+    if hlo.channel_id().is_none() {
+      // Cross-replica.
+      return group_size.unwrap_or(config.replica_count());
+    }
+    match use_global_device_ids {
+      // Cross-partition.
+      None => group_size.unwrap_or(config.num_partitions()),
+      // Cross-replica and cross-partition.
+      Some(false) =>
+        group_size.unwrap_or(config.replica_count()) * config.num_partitions(),
+      // Flattened device ids.
+      Some(true) =>
+        group_size.unwrap_or(config.replica_count() * config.num_partitions()),
+    }
+  }
+
+  // Returns true if 'a' and 'b' have the same element type, ignoring the
+  // floating point precision when mixed precision is allowed.
+  fn same_element_type(&self, a: &Shape, b: &Shape) -> bool {
+    if self.opts.allow_mixed_precision {
+      ShapeUtil::same_element_type_ignoring_fp_precision(a, b)
+    } else {
+      ShapeUtil::same_element_type(a, b)
+    }
+  }
 
-  fn shape_same(&self, _a: &Shape, _b: &Shape) -> bool {
-    false
+  // Compares 'a' and 'b' with layouts when the verifier is layout sensitive,
+  // and only for compatibility otherwise.
+  fn shape_same(
+    &self,
+    a: &Shape,
+    b: &Shape,
+    ignore_fp_precision: bool,
+    only_compare_minor_to_major_in_layout: bool) -> bool
+  {
+    if !self.opts.layout_sensitive {
+      if ignore_fp_precision {
+        return ShapeUtil::compatible_ignoring_fp_precision(a, b);
+      }
+      return ShapeUtil::compatible(a, b);
+    }
+    let mut equal = ShapeEqual::new();
+    if ignore_fp_precision {
+      equal.ignore_fp_precision();
+    }
+    if only_compare_minor_to_major_in_layout {
+      equal.minor_to_major_only_in_layout();
+    }
+    equal.equal(a, b)
   }
 
   fn check_shape(
     &self,
-    _instruction: &HloInstruction,
-    _inferred_shape: &Shape,
-    _only_compare_minor_to_major_in_layout: bool) -> Result<(), String>
+    instruction: &HloInstruction,
+    inferred_shape: &Shape,
+    only_compare_minor_to_major_in_layout: bool) -> Result<(), String>
   {
-    unimplemented!()    
+    // Instructions which pass their operands through or move data around
+    // must produce exactly the inferred shape, even with mixed precision.
+    let equal = match instruction.opcode() {
+      HloOpcode::Call | HloOpcode::Conditional | HloOpcode::Constant |
+      HloOpcode::GetTupleElement | HloOpcode::Infeed | HloOpcode::Outfeed |
+      HloOpcode::OptimizationBarrier | HloOpcode::Parameter | HloOpcode::Recv |
+      HloOpcode::RecvDone | HloOpcode::Send | HloOpcode::SendDone |
+      HloOpcode::Tuple | HloOpcode::While =>
+        self.shape_same(instruction.shape(), inferred_shape,
+          false, only_compare_minor_to_major_in_layout),
+      _ =>
+        self.shape_same(instruction.shape(), inferred_shape,
+          self.opts.allow_mixed_precision, only_compare_minor_to_major_in_layout),
+    };
+    if !equal {
+      return Err(format!(
+        "Expected instruction to have shape equal to {}, actual shape is {}:\n{}",
+        ShapeUtil::human_string_with_layout(inferred_shape),
+        ShapeUtil::human_string_with_layout(instruction.shape()),
+        instruction.to_string_default()));
+    }
+    Ok(())
   }
 
   fn check_shape_by_status(
     &self,
-    _instruction: &HloInstruction,
-    _inferred_shape_status: Result<Shape, String>) -> Result<(), String>
+    instruction: &HloInstruction,
+    inferred_shape_status: Result<Shape, String>) -> Result<(), String>
   {
-    unimplemented!()
+    match inferred_shape_status {
+      Ok(inferred_shape) => self.check_shape(instruction, &inferred_shape, false),
+      Err(err_msg) => Err(format!("Shape inference failed for {}: {}",
+        instruction.to_string_default(), err_msg))
+    }
   }
 
   fn check_parameter_count(
@@ -530,34 +1253,37 @@ impl ShapeVerifier {
   fn check_unary_shape(&self, instruction: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(instruction,
       ShapeInference::infer_unary_op_shape(
-        &instruction.opcode(), instruction.operand(0)))
+        &instruction.opcode(), self.operand(instruction, 0)))
   }
 
   fn check_binary_shape(&self, instruction: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(instruction,
       ShapeInference::infer_binary_op_shape(
-        &instruction.opcode(), instruction.operand(0), instruction.operand(1)))
+        &instruction.opcode(), self.operand(instruction, 0), self.operand(instruction, 1)))
   }
 
   fn check_ternary_shape(&self, instruction: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(instruction,
       ShapeInference::infer_ternary_op_shape(
         &instruction.opcode(),
-        instruction.operand(0),
-        instruction.operand(1),
-        instruction.operand(2)))
+        self.operand(instruction, 0),
+        self.operand(instruction, 1),
+        self.operand(instruction, 2)))
   }
 
   fn check_variadic_shape(&self, instruction: &HloInstruction) -> Result<(), String> {
     self.check_shape_by_status(instruction,
-      ShapeInference::infer_variadic_op_shape(
-        instruction.opcode(), instruction.operands()))
+      ShapeInference::infer_variadic_op_shape_by_opshapes(
+        instruction.opcode(),
+        &instruction.operands().iter()
+          .map(|operand| self.computation.instruction(*operand).shape().clone())
+          .collect()))
   }
 
   fn check_is_token_operand(
     &self, instruction: &HloInstruction, operand_no: usize) -> Result<(), String>
   {
-    let token = instruction.operand(operand_no);
+    let token = self.operand(instruction, operand_no);
     if !ShapeEqual::new().equal(token.shape(), &ShapeUtil::make_token_shape()) {
       let mut err_msg = "Expected operand ".to_string();
       err_msg.push_str(&operand_no.to_string());
@@ -573,11 +1299,475 @@ pub fn check_operand_count(hlo: &HloInstruction, expected: usize) -> Result<(),
   if hlo.operand_count() != expected {
     let mut err_msg = "Expected ".to_string();
     err_msg.push_str(&expected.to_string());
-    err_msg.push_str("operands for ");
+    err_msg.push_str(" operands for ");
     err_msg.push_str(&hlo_opcode_string(&hlo.opcode()));
     err_msg.push_str(" instruction: ");
     err_msg.push_str(&hlo.to_string_default());
     return Err(err_msg);
   }
   Ok(())
-}
\ No newline at end of file
+}
+
+// HLO pass that verifies invariants of HLO instructions for each computation
+// in the module.
+pub struct HloVerifier {
+  opts: HloVerifierOpts,
+  // Prepended to the error message, e.g. the pass after which the verifier
+  // runs.
+  context: String,
+}
+
+impl HloVerifier {
+  pub fn new(layout_sensitive: bool, allow_mixed_precision: bool) -> Self {
+    let mut opts = HloVerifierOpts::new();
+    opts.with_layout_sensitive(layout_sensitive)
+      .with_allow_mixed_precision(allow_mixed_precision);
+    HloVerifier::new_with_opts(opts)
+  }
+
+  pub fn new_with_opts(opts: HloVerifierOpts) -> Self {
+    HloVerifier { opts: opts, context: "Unknown".to_string() }
+  }
+
+  pub fn opts(&self) -> &HloVerifierOpts {
+    &self.opts
+  }
+
+  pub fn set_context(&mut self, context: String) {
+    self.context = context;
+  }
+
+  // Verifies the computations of 'module' running on 'execution_threads'.
+  pub fn verify(
+    &self,
+    module: &HloModule,
+    execution_threads: &HashSet<String>) -> Result<(), String>
+  {
+    let num_devices = module.config().num_partitions();
+    for id in module.computations_by_exec_threads(execution_threads) {
+      let computation = module.computation(id);
+      let shape_verifier = ShapeVerifier::new(module, computation, &self.opts);
+      for instruction_id in computation.make_instruction_post_order() {
+        let instruction = computation.instruction(instruction_id);
+        let mut result = shape_verifier.verify(instruction);
+        if result.is_ok() && self.opts.verify_sharding_device_numbers &&
+          instruction.has_sharding()
+        {
+          result = verify_sharding_device_numbers(instruction.sharding(), num_devices);
+        }
+        if let Err(err_msg) = result {
+          return Err(self.failure(module, Some(instruction), err_msg));
+        }
+      }
+    }
+    self.verify_channels(module, execution_threads)?;
+    self.verify_fusion_computations(module, execution_threads)?;
+    if module.has_schedule() {
+      self.verify_schedule(module, execution_threads)?;
+    }
+    Ok(())
+  }
+
+  // Checks that every channel id is used by a single instruction, except for
+  // a send and a recv which may share a channel. The done halves of
+  // asynchronous pairs carry the channel id of their start and are skipped.
+  fn verify_channels(
+    &self,
+    module: &HloModule,
+    execution_threads: &HashSet<String>) -> Result<(), String>
+  {
+    let mut channels: BTreeMap<i64, Vec<&HloInstruction>> = BTreeMap::new();
+    for id in module.computations_by_exec_threads(execution_threads) {
+      for instruction in module.computation(id).instructions() {
+        match instruction.opcode() {
+          HloOpcode::SendDone | HloOpcode::RecvDone | HloOpcode::AllGatherDone |
+          HloOpcode::AllReduceDone | HloOpcode::CollectivePermuteDone => continue,
+          _ => {}
+        }
+        if let Some(channel_id) = instruction.channel_id() {
+          channels.entry(channel_id).or_default().push(instruction);
+        }
+      }
+    }
+    for (channel_id, instructions) in &channels {
+      let first = instructions[0];
+      let is_send_recv = |instruction: &HloInstruction| {
+        instruction.opcode() == HloOpcode::Send || instruction.opcode() == HloOpcode::Recv
+      };
+      if is_send_recv(first) {
+        for opcode in [HloOpcode::Send, HloOpcode::Recv] {
+          let count = instructions.iter().filter(|i| i.opcode() == opcode).count();
+          if count > 1 {
+            return Err(self.failure(module, Some(first), format!(
+              "Channel {} is used for multiple {} instructions.",
+              channel_id, hlo_opcode_string(&opcode))));
+          }
+        }
+        if let Some(other) = instructions.iter().find(|i| !is_send_recv(i)) {
+          return Err(self.failure(module, Some(other), format!(
+            "Channel {} is used for both send/recv and {} instructions.",
+            channel_id, hlo_opcode_string(&other.opcode()))));
+        }
+      } else if instructions.len() > 1 {
+        let names: Vec<String> = instructions.iter().map(|i| i.name()).collect();
+        return Err(self.failure(module, Some(instructions[1]), format!(
+          "Channel {} is used by multiple instructions: {}.",
+          channel_id, names.join(", "))));
+      }
+    }
+    Ok(())
+  }
+
+  // Checks that every fusion computation is called by exactly one fusion
+  // instruction and by nothing else. The shapes of the fused computation are
+  // checked against the fusion instruction by the shape verifier.
+  fn verify_fusion_computations(
+    &self,
+    module: &HloModule,
+    execution_threads: &HashSet<String>) -> Result<(), String>
+  {
+    let mut fusion_callers: HashMap<ComputationId, usize> = HashMap::new();
+    for id in module.computations_by_exec_threads(execution_threads) {
+      for instruction in module.computation(id).instructions() {
+        for called in instruction.called_computations() {
+          if instruction.opcode() == HloOpcode::Fusion {
+            *fusion_callers.entry(*called).or_insert(0) += 1;
+          } else if module.computation(*called).is_fusion_computation() {
+            return Err(self.failure(module, Some(instruction), format!(
+              "Fusion computation {} is called by non-fusion instruction {}.",
+              module.computation(*called).name(), instruction.name())));
+          }
+        }
+      }
+    }
+    for id in module.computations_by_exec_threads(execution_threads) {
+      let computation = module.computation(id);
+      if !computation.is_fusion_computation() {
+        continue;
+      }
+      let callers = fusion_callers.get(&id).copied().unwrap_or(0);
+      if callers != 1 {
+        return Err(self.failure(module, None, format!(
+          "Fusion computation {} should be called by exactly one fusion instruction, but is called by {}.",
+          computation.name(), callers)));
+      }
+    }
+    Ok(())
+  }
+
+  // Checks that the schedule of the module sequences every instruction of
+  // every non-fusion computation exactly once, after its operands and
+  // control predecessors.
+  fn verify_schedule(
+    &self,
+    module: &HloModule,
+    execution_threads: &HashSet<String>) -> Result<(), String>
+  {
+    let schedule = module.schedule();
+    let module_computations: HashSet<i64> =
+      module.computations().map(|computation| computation.unique_id()).collect();
+    for computation_unique_id in schedule.sequences().keys() {
+      if !module_computations.contains(computation_unique_id) {
+        return Err(self.failure(module, None, format!(
+          "Schedule has a sequence for computation {} which is not in the module.",
+          computation_unique_id)));
+      }
+    }
+    for id in module.computations_by_exec_threads(execution_threads) {
+      let computation = module.computation(id);
+      if computation.is_fusion_computation() {
+        continue;
+      }
+      let sequence = match schedule.sequence(computation) {
+        Some(sequence) => sequence,
+        None => return Err(self.failure(module, None, format!(
+          "Computation {} is not scheduled.", computation.name())))
+      };
+      let mut positions = HashMap::new();
+      for (position, unique_id) in sequence.ids().iter().enumerate() {
+        if positions.insert(*unique_id, position).is_some() {
+          return Err(self.failure(module, None, format!(
+            "Instruction {} appears more than once in the schedule of {}.",
+            unique_id, computation.name())));
+        }
+      }
+      if positions.len() != computation.instruction_count() {
+        return Err(self.failure(module, None, format!(
+          "Schedule of {} has {} instructions, but the computation has {}.",
+          computation.name(), positions.len(), computation.instruction_count())));
+      }
+      for instruction in computation.instructions() {
+        let position = match positions.get(&instruction.unique_id()) {
+          Some(position) => *position,
+          None => return Err(self.failure(module, Some(instruction), format!(
+            "Instruction {} is not in the schedule of {}.",
+            instruction.name(), computation.name())))
+        };
+        let predecessors = instruction.operands().iter()
+          .chain(instruction.control_predecessors().iter());
+        for predecessor_id in predecessors {
+          let predecessor = computation.instruction(*predecessor_id);
+          if positions.get(&predecessor.unique_id()).is_none_or(|p| *p > position) {
+            return Err(self.failure(module, Some(instruction), format!(
+              "Instruction {} is scheduled before its predecessor {}.",
+              instruction.name(), predecessor.name())));
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  // Builds the error returned when verification fails: the reason, the
+  // context of the verifier and the module text around 'instruction'.
+  fn failure(
+    &self,
+    module: &HloModule,
+    instruction: Option<&HloInstruction>,
+    err_msg: String) -> String
+  {
+    let mut failure = format!("{}: {}", self.context, err_msg);
+    match instruction {
+      Some(instruction) => {
+        failure.push_str(&format!("\nFailed verifying instruction {} of module {}:\n",
+          instruction.name(), module.name()));
+        failure.push_str(&module_text_around(module, &instruction.name()));
+      }
+      None => {
+        failure.push_str(&format!("\nFailed verifying module {}:\n", module.name()));
+        failure.push_str(&module.to_string());
+      }
+    }
+    failure
+  }
+}
+
+impl HloPassInterface for HloVerifier {
+  fn name(&self) -> String {
+    "hlo-verifier".to_string()
+  }
+
+  fn run(
+    &mut self,
+    module: &mut HloModule,
+    execution_threads: &HashSet<String>) -> Result<bool, String>
+  {
+    self.verify(module, execution_threads)?;
+    Ok(false)
+  }
+}
+
+// Verifies 'module' after the pass named 'pass_name' in debug builds, so that
+// a pass which breaks an invariant is caught right where it does. Release
+// builds skip the check as it walks every instruction of the module.
+pub fn verify_after_pass_in_debug_build(
+  module: &HloModule,
+  pass_name: &str,
+  execution_threads: &HashSet<String>) -> Result<(), String>
+{
+  if !cfg!(debug_assertions) {
+    return Ok(());
+  }
+  let mut verifier = HloVerifier::new(false, false);
+  verifier.set_context(format!("After running {}", pass_name));
+  verifier.verify(module, execution_threads)
+}
+
+// Checks that the devices 'sharding' places data on are in
+// [0, num_devices). Reserved (negative) device ids are allowed.
+fn verify_sharding_device_numbers(
+  sharding: &HloSharding, num_devices: i64) -> Result<(), String>
+{
+  if sharding.is_tuple() {
+    for element in sharding.tuple_elements() {
+      verify_sharding_device_numbers(element, num_devices)?;
+    }
+    return Ok(());
+  }
+  if sharding.is_replicated() || sharding.is_manual() || sharding.is_unknown() {
+    return Ok(());
+  }
+  let mut devices = vec![];
+  if sharding.is_tile_maximal() {
+    devices.push(sharding.tile_assignment().first());
+  } else {
+    let mut tile_assignment = sharding.tile_assignment().clone();
+    tile_assignment.each(&mut |_index: &Vec<i64>, device: &mut i64| devices.push(*device));
+  }
+  for device in devices {
+    if !HloSharding::is_reserved_device(device) && device >= num_devices {
+      return Err(format!(
+        "Sharding {} uses device {}, but the module only has {} devices.",
+        sharding.to_string(false), device, num_devices));
+    }
+  }
+  Ok(())
+}
+
+// Returns the lines of the module text around the definition of the
+// instruction 'name', with the definition itself marked.
+fn module_text_around(module: &HloModule, name: &str) -> String {
+  let text = module.to_string();
+  let lines: Vec<&str> = text.lines().collect();
+  let definition = format!("{} = ", name);
+  let position = lines.iter().position(|line| {
+    let line = line.trim_start();
+    let line = line.strip_prefix("ROOT ").unwrap_or(line);
+    line.strip_prefix('%').unwrap_or(line).starts_with(&definition)
+  });
+  let index = match position {
+    Some(index) => index,
+    None => return text
+  };
+  let start = index.saturating_sub(CONTEXT_LINES);
+  let end = (index + CONTEXT_LINES + 1).min(lines.len());
+  let mut result = String::new();
+  for i in start..end {
+    result.push_str(if i == index { ">>> " } else { "    " });
+    result.push_str(lines[i]);
+    result.push('\n');
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use common::dyn_literal::DynLiteral;
+  use hlo::{
+    hlo_instruction::{FusionKind, HloInstruction}, hlo_module_config::HloModuleConfig,
+    hlo_schdule::{HloInstructionSequence, HloSchedule}
+  };
+  use crate::test_utils::r0f32;
+  use super::*;
+
+  // Builds a module computing add(p0, p1) on f32 scalars, where the add is
+  // given 'add_shape'.
+  fn make_add_module(add_shape: &Shape) -> HloModule {
+    let mut computation = HloComputation::new("entry".to_string());
+    let p0 = computation.add_parameter(
+      HloInstruction::create_parameter(0, &r0f32(), "p0".to_string()));
+    let p1 = computation.add_parameter(
+      HloInstruction::create_parameter(1, &r0f32(), "p1".to_string()));
+    let add = computation.add_instruction(HloInstruction::create_binary(
+      add_shape, HloOpcode::Add, computation.instruction(p0), computation.instruction(p1)),
+      "add".to_string());
+    computation.set_root_instruction(add, false);
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    module.add_entry_computation(computation);
+    module
+  }
+
+  #[test]
+  fn test_verifies_well_formed_module() {
+    let mut module = make_add_module(&r0f32());
+    let mut verifier = HloVerifier::new(false, false);
+    assert_eq!(verifier.name(), "hlo-verifier".to_string());
+    assert_eq!(verifier.run(&mut module, &HashSet::new()), Ok(false));
+  }
+
+  #[test]
+  fn test_wrong_result_shape() {
+    let module = make_add_module(&ShapeUtil::make_shape(&PrimitiveType::S32, vec![]));
+    let verifier = HloVerifier::new(false, false);
+    let err_msg = verifier.verify(&module, &HashSet::new()).err().unwrap();
+    assert!(err_msg.contains("Expected instruction to have shape equal to f32[]"));
+    assert!(err_msg.contains("Failed verifying instruction add"));
+    assert!(err_msg.contains(">>> "));
+  }
+
+  #[test]
+  fn test_mixed_precision() {
+    let module = make_add_module(&ShapeUtil::make_shape(&PrimitiveType::BF16, vec![]));
+    assert!(HloVerifier::new(false, false).verify(&module, &HashSet::new()).is_err());
+    assert!(HloVerifier::new(false, true).verify(&module, &HashSet::new()).is_ok());
+  }
+
+  #[test]
+  fn test_duplicate_channel_ids() {
+    let mut computation = HloComputation::new("entry".to_string());
+    let token = computation.add_instruction(
+      HloInstruction::create_token(), "token".to_string());
+    let p0 = computation.add_parameter(
+      HloInstruction::create_parameter(0, &r0f32(), "p0".to_string()));
+    let send = computation.add_instruction(HloInstruction::create_send(
+      computation.instruction(p0), computation.instruction(token), 1, false),
+      "send".to_string());
+    let recv = computation.add_instruction(HloInstruction::create_recv(
+      &r0f32(), computation.instruction(token), 1, false), "recv".to_string());
+    let all_to_all = computation.add_instruction(HloInstruction::create_all_to_all(
+      &ShapeUtil::make_tuple_shape(vec![r0f32()]), &[computation.instruction(p0)],
+      vec![], false, Some(1), None), "all-to-all".to_string());
+    let tuple = computation.add_instruction(HloInstruction::create_tuple(
+      &[computation.instruction(send), computation.instruction(recv),
+        computation.instruction(all_to_all)]), "tuple".to_string());
+    computation.set_root_instruction(tuple, false);
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    module.add_entry_computation(computation);
+
+    let err_msg = HloVerifier::new(false, false)
+      .verify(&module, &HashSet::new()).err().unwrap();
+    assert!(err_msg.contains("Channel 1 is used for both send/recv and all-to-all"));
+  }
+
+  #[test]
+  fn test_sharding_device_out_of_range() {
+    let mut module = make_add_module(&r0f32());
+    let entry = module.entry_computation_id();
+    let computation = module.mutable_computation(entry);
+    let add = computation.root_instruction_id();
+    computation.mutable_instruction(add)
+      .set_sharding(HloSharding::assign_device(1, vec![]));
+    let err_msg = HloVerifier::new(false, false)
+      .verify(&module, &HashSet::new()).err().unwrap();
+    assert!(err_msg.contains("uses device 1, but the module only has 1 devices"));
+
+    let mut opts = HloVerifierOpts::new();
+    opts.with_verify_sharding_device_numbers(false);
+    assert!(HloVerifier::new_with_opts(opts).verify(&module, &HashSet::new()).is_ok());
+  }
+
+  #[test]
+  fn test_schedule_out_of_order() {
+    let mut module = make_add_module(&r0f32());
+    let computation = module.entry_computation().unwrap();
+    let mut sequence = HloInstructionSequence::new();
+    for id in computation.make_instruction_post_order().into_iter().rev() {
+      sequence.push_pack(computation.instruction(id).clone());
+    }
+    let mut schedule = HloSchedule::new();
+    schedule.set_sequence(computation, sequence);
+    module.set_schedule(schedule);
+    let err_msg = HloVerifier::new(false, false)
+      .verify(&module, &HashSet::new()).err().unwrap();
+    assert!(err_msg.contains("is scheduled before its predecessor"));
+  }
+
+  #[test]
+  fn test_fusion_parameter_count() {
+    let mut module = HloModule::new("m".to_string(), HloModuleConfig::new_default());
+    let mut fused = HloComputation::new("fused".to_string());
+    let fp0 = fused.add_parameter(
+      HloInstruction::create_parameter(0, &r0f32(), "fp0".to_string()));
+    let negate = fused.add_instruction(HloInstruction::create_unary(
+      &r0f32(), HloOpcode::Negate, fused.instruction(fp0)), "negate".to_string());
+    fused.set_root_instruction(negate, false);
+    fused.set_is_fusion_computation(true);
+    let fused_id = module.add_embedded_computation(fused);
+
+    let mut entry = HloComputation::new("entry".to_string());
+    let p0 = entry.add_parameter(
+      HloInstruction::create_parameter(0, &r0f32(), "p0".to_string()));
+    let constant = entry.add_instruction(HloInstruction::create_constant(
+      DynLiteral::create_r0(1.0_f32)), "constant".to_string());
+    let fusion = entry.add_instruction(HloInstruction::create_fusion(
+      &r0f32(), FusionKind::Loop,
+      &[entry.instruction(p0), entry.instruction(constant)], fused_id),
+      "fusion".to_string());
+    entry.set_root_instruction(fusion, false);
+    module.add_entry_computation(entry);
+
+    let err_msg = HloVerifier::new(false, false)
+      .verify(&module, &HashSet::new()).err().unwrap();
+    assert!(err_msg.contains("calling from fusion to have 2 parameters, has 1"));
+  }
+}
@@ -63,6 +63,7 @@ pub mod despecializer;
 pub mod dot_decomposer;
 pub mod dot_dimension_merger;
 pub mod dot_merger;
+pub mod dump;
 pub mod dynamic_dimension_inference;
 pub mod dynamic_dimension_simplifier;
 pub mod dynamic_index_splitter;
@@ -97,10 +98,12 @@ pub mod hlo_execution_profile_data;
 pub mod hlo_execution_profile;
 pub mod hlo_graph_dumper;
 pub mod hlo_lexer;
+pub mod hlo_liveness_analysis;
 pub mod hlo_memory_scheduler;
 pub mod hlo_module_dce;
 pub mod hlo_module_util;
 pub mod hlo_parser;
+pub mod hlo_pass_fix;
 pub mod hlo_pass_interface;
 pub mod hlo_pass_pipeline;
 pub mod hlo_phi_graph;
@@ -132,6 +135,7 @@ pub mod reduce_scatter_reassociate;
 pub mod reshape_decomposer;
 pub mod reshape_mover;
 pub mod result_caster;
+pub mod roofline_performance_model;
 pub mod root_instruction_sinker;
 pub mod scatter_expander;
 pub mod scatter_simplifier;
@@ -152,4 +156,6 @@ pub mod while_loop_fusible_sinking;
 pub mod while_loop_invariant_code_motion;
 pub mod while_loop_simplifier;
 pub mod while_loop_unroller;
-pub mod zero_sized_hlo_elimination;
\ No newline at end of file
+pub mod zero_sized_hlo_elimination;
+#[cfg(any(test, feature = "test_utils"))]
+pub mod test_utils;
@@ -2,17 +2,20 @@
 
 use std::collections::HashSet;
 
-use hlo::{dfs_hlo_visitor_with_default::DfsHloVisitor, hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_module::HloModule, hlo_opcode::HloOpcode, utils::hlo_query::all_operands_are_parameters};
+use hlo::{
+  dfs_hlo_visitor_with_default::DfsHloVisitor,
+  hlo_instruction::{HloInstruction, InstructionId},
+  hlo_module::{ComputationId, HloModule}, hlo_opcode::HloOpcode,
+  utils::hlo_query::all_operands_are_parameters
+};
 
 struct MapInlinerVisitor {
-  computation: Option<HloComputation>,
   changed: bool
 }
 
 impl MapInlinerVisitor {
-  pub fn new(computation: Option<HloComputation>) -> Self {
+  pub fn new() -> Self {
     MapInlinerVisitor {
-      computation: computation,
       changed: false
     }
   }
@@ -21,42 +24,38 @@ impl MapInlinerVisitor {
     Ok(())
   }
 
-  pub fn handle_map(&mut self, map: &mut HloInstruction) -> Result<(), String> {
-    let function = map.to_apply();
-    let root = function.root_instruction();
+  pub fn handle_map(
+    &mut self,
+    module: &mut HloModule,
+    computation: ComputationId,
+    map: InstructionId) -> Result<(), String>
+  {
+    let function = module.computation(computation).instruction(map).to_apply();
+    let root = module.computation(function).root_instruction().clone();
 
-    if all_operands_are_parameters(root) {
+    if all_operands_are_parameters(module.computation(function), &root) {
       if root.opcode() == HloOpcode::Fusion {
         // Cloning not supported for these instructions.
         return Ok(());
       }
       println!("inlining map(X ... Y, op) => : op(X ... Y) with function {:?}",
         root.to_short_string());
+      let computation = module.mutable_computation(computation);
       if root.opcode() == HloOpcode::Parameter {
         // If the root is a parameter, then use the corresponding operand as the
         // result of the computation.
-        let mut new_producer =
-          map.mutable_operand(root.parameter_number() as usize).unwrap().clone();
-        let result = map.replace_all_uses_with(
-          &mut new_producer, "".to_string());
-        if result.is_err() {
-          return Err(result.err().unwrap());
-        }
+        let new_producer =
+          computation.instruction(map).operand(root.parameter_number() as usize);
+        computation.replace_all_uses_with(map, new_producer)?;
       } else if root.opcode() == HloOpcode::Constant {
-        /*
-        let constant =
-          self.computation.as_mut().unwrap().add_instruction(root.clone(), "".to_string());
-        let placed_instruction =
-          self.computation.as_mut().unwrap().add_instruction(
-            HloInstruction::create_broadcast(
-              map.shape(), constant.clone(), vec![]), "".to_string());
-        let result =
-          self.computation.as_mut().unwrap().replace_instruction(
-            &map, &placed_instruction, false, false, true);
-        if result.is_err() {
-          return Err(result.err().unwrap());
-        }
-        */
+        // If the input is a constant then the shape of the constant could be
+        // different than the map shape. Hence, a broadcast is needed, else the
+        // cloned operand with new shape and operands work.
+        let constant = computation.add_instruction(root, "".to_string());
+        let broadcast = HloInstruction::create_broadcast(
+          computation.instruction(map).shape(), computation.instruction(constant), vec![]);
+        let placed_instruction = computation.add_instruction(broadcast, "".to_string());
+        computation.replace_instruction(map, placed_instruction, false, false, true)?;
       } else {
         // TODO
       }
@@ -69,14 +68,17 @@ impl MapInlinerVisitor {
   }
 
   // Runs the visitor on a computation.
-  pub fn run(&mut self, computation: &HloComputation) -> Result<bool, String> {
+  pub fn run(
+    &mut self, module: &mut HloModule, computation: ComputationId) -> Result<bool, String>
+  {
     self.changed = false;
-    self.computation = Some(computation.clone());
-    let result =
-      self.computation.as_ref().unwrap().root_instruction()
-        .accept(self, true, false, false);
-    if result.is_err() { return Err(result.err().unwrap()); }
-    Ok(true)
+    for instruction in module.computation(computation).make_instruction_post_order() {
+      if !module.computation(computation).contains(instruction) { continue; }
+      if module.instruction(computation, instruction).opcode() == HloOpcode::Map {
+        self.handle_map(module, computation, instruction)?;
+      }
+    }
+    Ok(self.changed)
   }
 }
 
@@ -96,14 +98,14 @@ impl MapInliner {
 
   pub fn run(
     &self,
-    module: &HloModule,
+    module: &mut HloModule,
     execution_threads: &HashSet<String>) -> Result<bool, String>
   {
-    let mut visitor = MapInlinerVisitor::new(None);
+    let mut visitor = MapInlinerVisitor::new();
     let mut changed = false;
     
     for computation in module.computations_by_exec_threads(execution_threads) {
-      let computation_changed = visitor.run(computation);
+      let computation_changed = visitor.run(module, computation);
       if computation_changed.is_err() {
         return Err(computation_changed.err().unwrap());
       }
@@ -112,4 +114,4 @@ impl MapInliner {
 
     Ok(changed)
   }
-}
\ No newline at end of file
+}
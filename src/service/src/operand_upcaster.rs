@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 
 use common::{blitz_data::Precision, shape::Shape, shape_util::ShapeUtil};
-use hlo::{hlo_instruction::HloInstruction, hlo_opcode::HloOpcode};
+use hlo::{hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_opcode::HloOpcode};
 
 use crate::shape_inference::ShapeInference;
 
@@ -16,8 +16,10 @@ impl OperandUpcaster {
     "operand-upcaster".to_string()
   }
 
-  pub fn instruction_matches_pattern(instruction: &HloInstruction) -> bool {
-    let inferred_shape = maybe_infer_shape(instruction);
+  pub fn instruction_matches_pattern(
+    computation: &HloComputation, instruction: &HloInstruction) -> bool
+  {
+    let inferred_shape = maybe_infer_shape(computation, instruction);
     if inferred_shape.is_err() {
       return false;
     }
@@ -35,8 +37,8 @@ impl OperandUpcaster {
 
     let inferred_type = inferred_shape.as_ref().unwrap().element_type();
     if instruction.shape().element_type() == inferred_type &&
-       instruction.operand(0).shape().element_type() == inferred_type &&
-       instruction.operand(1).shape().element_type() == inferred_type
+       computation.operand(instruction.id(), 0).shape().element_type() == inferred_type &&
+       computation.operand(instruction.id(), 1).shape().element_type() == inferred_type
     {
       return false;
     }
@@ -51,20 +53,22 @@ impl OperandUpcaster {
   }
 }
 
-fn maybe_infer_shape(instruction: &HloInstruction) -> Result<Shape, String> {
+fn maybe_infer_shape(
+  computation: &HloComputation, instruction: &HloInstruction) -> Result<Shape, String>
+{
   match instruction.opcode() {
     HloOpcode::Dot => {
       return ShapeInference::infer_dot_op_shape(
-        instruction.operand(0).shape(),
-        instruction.operand(1).shape(),
+        computation.operand(instruction.id(), 0).shape(),
+        computation.operand(instruction.id(), 1).shape(),
         instruction.dot_dimension_numbers(),
         None,
         instruction.sparsity());
     },
     HloOpcode::Convolution => {
       return ShapeInference::infer_convolve_shape(
-        instruction.operand(0).shape(),
-        instruction.operand(1).shape(),
+        computation.operand(instruction.id(), 0).shape(),
+        computation.operand(instruction.id(), 1).shape(),
         instruction.feature_group_count(),
         instruction.batch_group_count(),
         instruction.window(),
@@ -22,11 +22,12 @@ impl OptimizationBarrierExpander {
     execution_threads: &HashSet<String>) -> Result<bool, String>
   {
     let mut barriers = vec![];
-    for comp in module.make_nonfusion_computations(execution_threads) {
+    for comp_id in module.make_nonfusion_computations(execution_threads) {
+      let comp = module.computation(comp_id);
       let mut modified = false;
       for inst in comp.instructions() {
         if inst.opcode() == HloOpcode::OptimizationBarrier {
-          barriers.push(inst.clone());
+          barriers.push((comp_id, inst.id()));
           modified = true;
         }
       }
@@ -40,22 +41,12 @@ impl OptimizationBarrierExpander {
       }
     }
 
-    for _inst in &mut barriers {
-      /*
-      let arg = inst.mutable_operand(0).unwrap();
-      let mut result = arg.copy_all_control_deps_from(inst);
-      if result.is_err() {
-        return Err(result.err().unwrap());
-      }
-      result = inst.replace_all_uses_with(arg, "".to_string());
-      if result.is_err() {
-        return Err(result.err().unwrap());
-      }
-      result = inst.mutable_parent().remove_instruction(&inst);
-      if result.is_err() {
-        return Err(result.err().unwrap());
-      }
-      */
+    for (comp, inst) in &barriers {
+      let computation = module.mutable_computation(*comp);
+      let arg = computation.instruction(*inst).operand(0);
+      computation.copy_all_control_deps_from(*inst, arg)?;
+      computation.replace_all_uses_with(*inst, arg)?;
+      computation.force_remove_instruction(*inst)?;
     }
 
     Ok(!barriers.is_empty())
@@ -41,21 +41,21 @@ impl ReduceScatterReassociate {
     let _next_channel_id = hlo_query::next_channel_id(module);
     let mut changed = false;
 
-    for computation in
-      module.mutable_computations_by_exec_threads(execution_threads)
-    {
-      for instruction in computation.mutable_make_instruction_post_order() {
+    for id in module.computations_by_exec_threads(execution_threads) {
+      let computation = module.mutable_computation(id);
+      for instruction_id in computation.make_instruction_post_order() {
+        let instruction = computation.instruction(instruction_id);
         let kind = match_reduction_instruction(instruction);
         if kind.is_none() ||
-           instruction.operand(0).opcode() != HloOpcode::ReduceScatter ||
-           instruction.operand(1).opcode() != HloOpcode::ReduceScatter ||
+           computation.operand(instruction_id, 0).opcode() != HloOpcode::ReduceScatter ||
+           computation.operand(instruction_id, 1).opcode() != HloOpcode::ReduceScatter ||
           !instruction.shape().is_array()  
         {
           continue;
         }
 
-        let rs0 = instruction.operand(0);
-        let rs1 = instruction.operand(1);
+        let rs0 = computation.operand(instruction_id, 0);
+        let rs1 = computation.operand(instruction_id, 1);
         if !are_compatible(rs0, rs1, &kind.unwrap()) {
           println!("Reduce-Scatter operations are not compatible, skipping.");
           continue;
@@ -68,7 +68,7 @@ impl ReduceScatterReassociate {
 
         // TODO
         
-        //let result = computation.remove_instruction(instruction);
+        //let result = computation.remove_instruction(instruction_id);
         //if result.is_err() { return Err(result.err().unwrap()); }
 
         changed = true;
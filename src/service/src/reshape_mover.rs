@@ -9,7 +9,8 @@ use common::{
 };
 
 use hlo::{
-  hlo_instruction::{HloInstruction, HloPrintOptions},
+  hlo_computation::HloComputation,
+  hlo_instruction::{HloInstruction, HloPrintOptions, InstructionId},
   hlo_module::HloModule,
   hlo_opcode::HloOpcode
 };
@@ -35,18 +36,20 @@ impl ReshapeMover {
 
   pub fn run(
     &mut self,
-    module: &HloModule,
+    module: &mut HloModule,
     execution_threads: &HashSet<String>) -> Result<bool, String>
   {
     let mut changed = false;
-    for comp in module.make_nonfusion_computations(execution_threads) {
+    for comp_id in module.make_nonfusion_computations(execution_threads) {
+      let comp = module.computation(comp_id);
       let mut candidates = HashSet::new();
       for instruction in comp.instructions() {
-        if self.is_reshape_move_candidate(instruction) {
-          candidates.insert(instruction.clone());
+        if self.is_reshape_move_candidate(comp, instruction) {
+          candidates.insert(instruction.id());
         }
       }
-      let did_change = self.try_reshape_move_on_candidates(&candidates);
+      let did_change = self.try_reshape_move_on_candidates(
+        module.mutable_computation(comp_id), &candidates);
       if did_change.is_err() {
         return Err(did_change.err().unwrap());
       }
@@ -70,45 +73,55 @@ impl ReshapeMover {
   // case, all the remaining instructions in `candidates` are reshape-moved and
   // the routine returns true.
   fn try_reshape_move_on_candidates(
-    &self, _candidates: &HashSet<HloInstruction>) -> Result<bool, String>
+    &self,
+    _computation: &mut HloComputation,
+    _candidates: &HashSet<InstructionId>) -> Result<bool, String>
   {
     unimplemented!()
   }
 
   // Actually performs the reshape-move transformation -- that is, sinks the
   // reshape or transpose operands of `instruction` across it.
-  fn sink_rearrange_operands(&self, instruction: &mut HloInstruction) -> Result<bool, String> {
+  fn sink_rearrange_operands(
+    &self,
+    computation: &mut HloComputation,
+    instruction: InstructionId) -> Result<bool, String>
+  {
     let mut print_no_metadata = HloPrintOptions::new();
     print_no_metadata.set_print_metadata(false);
 
-    let _computation = instruction.parent();
-    let rearrange =
-      self.first_nontrivial_rearrange(instruction.operands());
+    let rearrange = self.first_nontrivial_rearrange(
+      computation, computation.instruction(instruction).operands());
     assert!(rearrange.is_some());
+    let rearrange = computation.instruction(rearrange.unwrap());
 
-    let new_operand_shape = rearrange.as_ref().unwrap().operand(0).shape();
-    println!("** Sinking reshape or transpose: {:?}", instruction.to_string(&print_no_metadata));
-    println!("first rearrange operand: {:?}", rearrange.as_ref().unwrap().to_string(&print_no_metadata));
+    let new_operand_shape = computation.operand(rearrange.id(), 0).shape();
+    println!("** Sinking reshape or transpose: {:?}",
+      computation.instruction(instruction).to_string(&print_no_metadata));
+    println!("first rearrange operand: {:?}", rearrange.to_string(&print_no_metadata));
     println!("new operand shape: {:?}", ShapeUtil::human_string(new_operand_shape));
 
-    let t = instruction.shape().element_type();
+    let t = computation.instruction(instruction).shape().element_type();
     let _new_shape = ShapeUtil::change_element_type(
       new_operand_shape, &t);
-    let operands = instruction.mutable_operands();
+    let operands = computation.instruction(instruction).operands().clone();
+    let mut new_operands = vec![];
     for i in 0..operands.len() {
-      println!("Updating operand # {:?}: {:?}", i, operands[i].to_string(&print_no_metadata));
+      let operand = computation.instruction(operands[i]);
+      println!("Updating operand # {:?}: {:?}", i, operand.to_string(&print_no_metadata));
       let result =
-        self.apply_inverse_rearrange(rearrange.as_ref().unwrap(), &operands[i]);
+        self.apply_inverse_rearrange(computation, rearrange, operand);
       if result.is_err() {
         return Err(result.err().unwrap());
       }
-      operands[i] = result.unwrap();
-      println!("Updated operand # {:?} to: {:?}", i, operands[i].to_string(&print_no_metadata));    
+      let new_operand = result.unwrap();
+      println!("Updated operand # {:?} to: {:?}", i, new_operand.to_string(&print_no_metadata));
+      new_operands.push(new_operand);
     }
 
     // TODO
     //let cloned = instruction.clone_with_new_opereands(
-      //&new_shape, operands);
+      //&new_shape, new_operands);
 
     Ok(true)
   }
@@ -121,13 +134,14 @@ impl ReshapeMover {
   // produce rearrange'(rearrange(x)), which can be simplified to x.
   fn apply_inverse_rearrange(
     &self,
+    computation: &HloComputation,
     rearrange: &HloInstruction,
     operand: &HloInstruction) -> Result<HloInstruction, String>
   {
     match rearrange.opcode() {
       HloOpcode::Reshape => {
         let new_shape = ShapeUtil::change_element_type(
-          rearrange.operand(0).shape(), &operand.shape().element_type());
+          computation.operand(rearrange.id(), 0).shape(), &operand.shape().element_type());
         if *operand.shape() != new_shape {
           return make_reshape_hlo(&new_shape, operand);
         } else {
@@ -151,7 +165,9 @@ impl ReshapeMover {
   //   * at least one operand is a rearrange, and
   //   * all rearrange operands are equivalent (if there's more than one), and
   //   * we can trivially apply the inverse rearrange to all other operands.
-  fn is_reshape_move_candidate(&self, instruction: &HloInstruction) -> bool {
+  fn is_reshape_move_candidate(
+    &self, computation: &HloComputation, instruction: &HloInstruction) -> bool
+  {
     let mut print_no_metadata = HloPrintOptions::new();
     print_no_metadata.set_print_metadata(false);
     println!("** Checking instruction: {:?}", instruction.to_string(&print_no_metadata));
@@ -160,14 +176,16 @@ impl ReshapeMover {
     if !instruction.is_elementwise() { return false; }
 
     let rearrange =
-      self.first_nontrivial_rearrange(instruction.operands());
+      self.first_nontrivial_rearrange(computation, instruction.operands());
     if rearrange.is_none() { return false; }
+    let rearrange = computation.instruction(rearrange.unwrap());
 
     for operand in instruction.operands() {
+      let operand = computation.instruction(*operand);
       if (is_rearrange(operand) &&
-          are_equivalent_rearranges(operand, rearrange.as_ref().unwrap())) ||
+          are_equivalent_rearranges(computation, operand, rearrange)) ||
          (!is_rearrange(operand) &&
-          self.can_trivially_rearrange(operand, rearrange.as_ref().unwrap()))
+          self.can_trivially_rearrange(computation, operand, rearrange))
       {
         continue;
       } else {
@@ -178,12 +196,18 @@ impl ReshapeMover {
     true
   }
 
-  fn first_nontrivial_rearrange(&self, instrs: &Vec<HloInstruction>) -> Option<HloInstruction> {
+  fn first_nontrivial_rearrange(
+    &self,
+    computation: &HloComputation,
+    instrs: &Vec<InstructionId>) -> Option<InstructionId>
+  {
     for instr in instrs {
+      let instr = computation.instruction(*instr);
       if is_rearrange(instr) &&
-        !self.can_trivially_rearrange(instr.operand(0), instr)
+        !self.can_trivially_rearrange(
+          computation, computation.operand(instr.id(), 0), instr)
       {
-        return Some(instr.clone());
+        return Some(instr.id());
       }
     }
     None
@@ -191,11 +215,17 @@ impl ReshapeMover {
 
   // Returns true if `instr` can easily change its shape according to the inverse
   // of `rearrange`, which must be a kReshape or kTranspose op.
-  fn can_trivially_rearrange(&self, instr: &HloInstruction, rearrange: &HloInstruction) -> bool {
+  fn can_trivially_rearrange(
+    &self,
+    computation: &HloComputation,
+    instr: &HloInstruction,
+    rearrange: &HloInstruction) -> bool
+  {
     assert!(is_rearrange(rearrange));
 
     if rearrange.opcode() == HloOpcode::Reshape &&
-      ShapeEqual::new().equal(rearrange.shape(), rearrange.operand(0).shape())
+      ShapeEqual::new().equal(
+        rearrange.shape(), computation.operand(rearrange.id(), 0).shape())
     {
       return true;
     }
@@ -237,7 +267,9 @@ fn is_rearrange(instruction: &HloInstruction) -> bool {
 }
 
 // Returns whether `a` and `b` are equivalent reshapes/transposes.
-fn are_equivalent_rearranges(a: &HloInstruction, b: &HloInstruction) -> bool {
+fn are_equivalent_rearranges(
+  computation: &HloComputation, a: &HloInstruction, b: &HloInstruction) -> bool
+{
   if a.opcode() != b.opcode() ||
      !ShapeUtil::same_dimensions(a.shape(), b.shape())
   {
@@ -247,7 +279,7 @@ fn are_equivalent_rearranges(a: &HloInstruction, b: &HloInstruction) -> bool {
   match a.opcode() {
     HloOpcode::Transpose => return a.dimensions() == b.dimensions(),
     HloOpcode::Reshape => return ShapeUtil::same_dimensions(
-      a.operand(0).shape(), b.operand(0).shape()),
+      computation.operand(a.id(), 0).shape(), computation.operand(b.id(), 0).shape()),
     _ => return false
   }
 }
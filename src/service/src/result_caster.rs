@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 
 use common::shape::Shape;
-use hlo::{hlo_instruction::HloInstruction, hlo_opcode::HloOpcode};
+use hlo::{hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_opcode::HloOpcode};
 
 use crate::shape_inference::ShapeInference;
 
@@ -19,8 +19,10 @@ impl ResultCaster {
     "result-caster".to_string()
   }
 
-  pub fn instruction_matches_pattern(instruction: &HloInstruction) -> bool {
-    let inferred_shape = maybe_infer_shape(instruction);
+  pub fn instruction_matches_pattern(
+    computation: &HloComputation, instruction: &HloInstruction) -> bool
+  {
+    let inferred_shape = maybe_infer_shape(computation, instruction);
     if inferred_shape.is_err() { return false; }
     inferred_shape.unwrap().element_type() != instruction.shape().element_type()
   }
@@ -32,17 +34,19 @@ impl ResultCaster {
   }
 }
 
-fn maybe_infer_shape(instruction: &HloInstruction) -> Result<Shape, String> {
+fn maybe_infer_shape(
+  computation: &HloComputation, instruction: &HloInstruction) -> Result<Shape, String>
+{
   match instruction.opcode() {
     HloOpcode::Dot => return ShapeInference::infer_dot_op_shape(
-      instruction.operand(0).shape(),
-      instruction.operand(1).shape(),
+      computation.operand(instruction.id(), 0).shape(),
+      computation.operand(instruction.id(), 1).shape(),
       instruction.dot_dimension_numbers(),
       None,
       instruction.sparsity()),
     HloOpcode::Convolution => return ShapeInference::infer_convolve_shape(
-      instruction.operand(9).shape(),
-      instruction.operand(1).shape(),
+      computation.operand(instruction.id(), 0).shape(),
+      computation.operand(instruction.id(), 1).shape(),
       instruction.feature_group_count(),
       instruction.batch_group_count(),
       instruction.window(),
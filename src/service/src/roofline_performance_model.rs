@@ -0,0 +1,612 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+
+use common::shape::Shape;
+use common::shape_util::ShapeUtil;
+use hlo::{
+  hlo_computation::HloComputation,
+  hlo_instruction::{HloInstruction, InstructionId},
+  hlo_module::HloModule,
+  hlo_opcode::HloOpcode
+};
+use stream_executor::device_description::DeviceDescription;
+
+use crate::hlo_cost_analysis::{HloCostAnalysis, Options};
+
+// The number of participants assumed for a collective whose replica groups
+// are empty, matching the assumption made by HloCostAnalysis.
+pub const DEFAULT_REPLICA_COUNT: i64 = 2;
+
+// The resource which limits the execution time of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bottleneck {
+  Compute,
+  Memory,
+  Collective,
+}
+
+// The roofline estimate of a single instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RooflineEstimate {
+  flops: f64,
+  bytes_accessed: f64,
+  bytes_transferred: f64,
+  compute_seconds: f64,
+  memory_seconds: f64,
+  collective_seconds: f64,
+}
+
+impl RooflineEstimate {
+  pub fn flops(&self) -> f64 {
+    self.flops
+  }
+
+  pub fn bytes_accessed(&self) -> f64 {
+    self.bytes_accessed
+  }
+
+  // Bytes sent over the interconnect by each participant of a collective.
+  pub fn bytes_transferred(&self) -> f64 {
+    self.bytes_transferred
+  }
+
+  pub fn compute_seconds(&self) -> f64 {
+    self.compute_seconds
+  }
+
+  pub fn memory_seconds(&self) -> f64 {
+    self.memory_seconds
+  }
+
+  pub fn collective_seconds(&self) -> f64 {
+    self.collective_seconds
+  }
+
+  // Flops per byte of memory traffic.
+  pub fn arithmetic_intensity(&self) -> f64 {
+    if self.bytes_accessed == 0.0 {
+      return 0.0;
+    }
+    self.flops / self.bytes_accessed
+  }
+
+  // The resources are assumed to work in parallel, so the slowest one
+  // determines the time of the instruction.
+  pub fn seconds(&self) -> f64 {
+    self.compute_seconds.max(self.memory_seconds).max(self.collective_seconds)
+  }
+
+  pub fn bottleneck(&self) -> Bottleneck {
+    if self.collective_seconds > self.compute_seconds &&
+      self.collective_seconds > self.memory_seconds {
+      Bottleneck::Collective
+    } else if self.compute_seconds >= self.memory_seconds {
+      Bottleneck::Compute
+    } else {
+      Bottleneck::Memory
+    }
+  }
+
+  pub fn is_compute_bound(&self) -> bool {
+    self.bottleneck() == Bottleneck::Compute
+  }
+
+  pub fn is_memory_bound(&self) -> bool {
+    self.bottleneck() == Bottleneck::Memory
+  }
+}
+
+// The estimate of a whole scheduled module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleEstimate {
+  makespan_seconds: f64,
+  critical_path_seconds: f64,
+  compute_stream_seconds: f64,
+  async_collective_seconds: f64,
+  exposed_collective_seconds: f64,
+  instruction_estimates: HashMap<InstructionId, RooflineEstimate>,
+}
+
+impl ModuleEstimate {
+  // The time until the entry computation finishes when its instructions run
+  // in schedule order, one at a time on the compute stream, with
+  // asynchronous transfers one at a time on the async stream.
+  pub fn makespan_seconds(&self) -> f64 {
+    self.makespan_seconds
+  }
+
+  // The longest chain of data and control dependencies of the entry
+  // computation. It ignores the order and resources of the schedule, so it is
+  // a lower bound of the makespan.
+  pub fn critical_path_seconds(&self) -> f64 {
+    self.critical_path_seconds
+  }
+
+  // The time the compute stream is busy, including synchronous collectives.
+  pub fn compute_stream_seconds(&self) -> f64 {
+    self.compute_stream_seconds
+  }
+
+  // The time spent in asynchronous collectives and copies.
+  pub fn async_collective_seconds(&self) -> f64 {
+    self.async_collective_seconds
+  }
+
+  // The part of the asynchronous time the compute stream waited for.
+  pub fn exposed_collective_seconds(&self) -> f64 {
+    self.exposed_collective_seconds
+  }
+
+  // The part of the asynchronous time hidden behind other work.
+  pub fn overlapped_collective_seconds(&self) -> f64 {
+    (self.async_collective_seconds - self.exposed_collective_seconds).max(0.0)
+  }
+
+  // Returns the estimate of an instruction of the entry computation.
+  pub fn instruction_estimate(&self, id: InstructionId) -> Option<&RooflineEstimate> {
+    self.instruction_estimates.get(&id)
+  }
+}
+
+// An analytical performance model. The flops and bytes HloCostAnalysis
+// computes for each instruction are divided by the peak rates of the device,
+// and the slowest resource determines the time of the instruction.
+pub struct RooflinePerformanceModel {
+  flops_per_second: f64,
+  bytes_per_second: f64,
+  interconnect_bytes_per_second: f64,
+  replica_count: i64,
+}
+
+impl RooflinePerformanceModel {
+  // Derives the peak rates from 'device'. Each fpu is assumed to retire one
+  // fused multiply-add (two flops) per cycle.
+  pub fn new(device: &DeviceDescription) -> Result<Self, String> {
+    let flops_per_second = device.clock_rate_ghz() * 1e9 *
+      device.core_count() as f64 * device.fpus_per_core() as f64 *
+      HloCostAnalysis::FMA_FLOPS as f64;
+    let bytes_per_second = device.memory_bandwidth() as f64;
+    if flops_per_second <= 0.0 {
+      return Err(format!("Device {} has no peak flop rate.", device.name()));
+    }
+    if bytes_per_second <= 0.0 {
+      return Err(format!("Device {} has no memory bandwidth.", device.name()));
+    }
+    Ok(RooflinePerformanceModel {
+      flops_per_second: flops_per_second,
+      bytes_per_second: bytes_per_second,
+      interconnect_bytes_per_second: bytes_per_second,
+      replica_count: DEFAULT_REPLICA_COUNT
+    })
+  }
+
+  pub fn flops_per_second(&self) -> f64 {
+    self.flops_per_second
+  }
+
+  pub fn bytes_per_second(&self) -> f64 {
+    self.bytes_per_second
+  }
+
+  pub fn interconnect_bytes_per_second(&self) -> f64 {
+    self.interconnect_bytes_per_second
+  }
+
+  // Collectives are costed at the memory bandwidth unless this is set.
+  pub fn set_interconnect_bytes_per_second(&mut self, value: f64) {
+    assert!(value > 0.0);
+    self.interconnect_bytes_per_second = value;
+  }
+
+  pub fn replica_count(&self) -> i64 {
+    self.replica_count
+  }
+
+  // The number of participants of collectives without replica groups.
+  pub fn set_replica_count(&mut self, value: i64) {
+    assert!(value > 0);
+    self.replica_count = value;
+  }
+
+  // The options of an HloCostAnalysis whose optimal_seconds agree with the
+  // peak rates of this model.
+  pub fn cost_analysis_options(&self) -> Options {
+    let mut options = Options::new(HloCostAnalysis::default_shape_size);
+    options.set_flops_per_second(self.flops_per_second);
+    options.set_transcendentals_per_second(self.flops_per_second);
+    options.set_bytes_per_second(self.bytes_per_second);
+    options
+  }
+
+  // The flop rate at which 'arithmetic_intensity' (flops per byte) stops
+  // being memory bound.
+  pub fn attainable_flops_per_second(&self, arithmetic_intensity: f64) -> f64 {
+    self.flops_per_second.min(arithmetic_intensity * self.bytes_per_second)
+  }
+
+  // Estimates 'instruction' of 'computation', which 'analysis' must have
+  // visited.
+  pub fn estimate_instruction(
+    &self,
+    analysis: &HloCostAnalysis,
+    module: &HloModule,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> RooflineEstimate
+  {
+    // Custom calls report unknown costs as negative values.
+    let flops = analysis.flop_count_for(instruction).max(0) as f64;
+    let transcendentals = analysis.transcendental_count_for(instruction).max(0) as f64;
+    let bytes_accessed = analysis.bytes_accessed_for(instruction).max(0) as f64;
+    let bytes_transferred = self.bytes_transferred(analysis, module, computation, instruction);
+    RooflineEstimate {
+      flops: flops,
+      bytes_accessed: bytes_accessed,
+      bytes_transferred: bytes_transferred,
+      compute_seconds: (flops + transcendentals) / self.flops_per_second,
+      memory_seconds: bytes_accessed / self.bytes_per_second,
+      collective_seconds: bytes_transferred / self.interconnect_bytes_per_second
+    }
+  }
+
+  // Estimates the entry computation of 'module'. For the makespan,
+  // instructions run one after another on a single compute stream in
+  // schedule order, or in post order if the module is not scheduled.
+  // Asynchronous collectives and copies run on a separate async stream
+  // between their start and done instructions, one at a time; the transfer is
+  // charged to the start, and the done only waits for it. The critical path
+  // follows the dependencies only, with the same cost per instruction.
+  pub fn estimate_module(&self, module: &HloModule) -> Result<ModuleEstimate, String> {
+    let mut analysis = HloCostAnalysis::new(self.cost_analysis_options());
+    analysis.run(module)?;
+
+    let computation = module.entry_computation().unwrap();
+    let order = self.instruction_order(module, computation)?;
+
+    let mut ready_time: HashMap<InstructionId, f64> = HashMap::new();
+    let mut path_time: HashMap<InstructionId, f64> = HashMap::new();
+    let mut instruction_estimates = HashMap::new();
+    let mut now: f64 = 0.0;
+    let mut async_free: f64 = 0.0;
+    let mut compute_stream_seconds = 0.0;
+    let mut async_collective_seconds = 0.0;
+    let mut exposed_collective_seconds = 0.0;
+    for id in order {
+      let instruction = computation.instruction(id);
+      let estimate = self.estimate_instruction(&analysis, module, computation, instruction);
+      // The transfer of an asynchronous operation is charged to its start
+      // only.
+      let seconds = if is_async_done(instruction.opcode()) { 0.0 } else { estimate.seconds() };
+      let predecessors = || instruction.operands().iter()
+        .chain(instruction.control_predecessors());
+      let operands_ready = predecessors()
+        .map(|operand| ready_time.get(operand).copied().unwrap_or(0.0))
+        .fold(0.0, f64::max);
+      let path_start = predecessors()
+        .map(|operand| path_time.get(operand).copied().unwrap_or(0.0))
+        .fold(0.0, f64::max);
+      path_time.insert(id, path_start + seconds);
+      if is_async_start(instruction.opcode()) {
+        // Issuing the operation is free; the transfer waits for the
+        // asynchronous resource to become available.
+        let start = now.max(operands_ready).max(async_free);
+        async_free = start + seconds;
+        async_collective_seconds += seconds;
+        ready_time.insert(id, async_free);
+      } else {
+        let start = now.max(operands_ready);
+        if is_async_done(instruction.opcode()) {
+          exposed_collective_seconds += start - now;
+        }
+        now = start + seconds;
+        compute_stream_seconds += seconds;
+        ready_time.insert(id, now);
+      }
+      instruction_estimates.insert(id, estimate);
+    }
+
+    Ok(ModuleEstimate {
+      makespan_seconds: f64::max(now, async_free),
+      critical_path_seconds: path_time.values().copied().fold(0.0, f64::max),
+      compute_stream_seconds: compute_stream_seconds,
+      async_collective_seconds: async_collective_seconds,
+      exposed_collective_seconds: exposed_collective_seconds,
+      instruction_estimates: instruction_estimates
+    })
+  }
+
+  fn instruction_order(
+    &self,
+    module: &HloModule,
+    computation: &HloComputation) -> Result<Vec<InstructionId>, String>
+  {
+    if !module.has_schedule() {
+      return Ok(computation.make_instruction_post_order());
+    }
+    match module.schedule().sequence(computation) {
+      Some(sequence) => sequence.ids().iter()
+        .map(|unique_id| computation.instruction_with_unique_id(*unique_id).ok_or(format!(
+          "Instruction {} in the schedule of {} is not in the computation.",
+          unique_id, computation.name())))
+        .collect(),
+      None => Err(format!("Computation {} is not scheduled.", computation.name()))
+    }
+  }
+
+  // The bytes each participant sends over the interconnect, assuming ring
+  // algorithms for the reducing collectives. An async-start transfers what
+  // the collective it wraps does.
+  fn bytes_transferred(
+    &self,
+    analysis: &HloCostAnalysis,
+    module: &HloModule,
+    computation: &HloComputation,
+    instruction: &HloInstruction) -> f64
+  {
+    if instruction.opcode() == HloOpcode::AsyncStart {
+      let wrapped = module.computation(instruction.called_computations()[0]);
+      return self.bytes_transferred(analysis, module, wrapped, wrapped.root_instruction());
+    }
+    let mut input_bytes = 0.0;
+    for i in 0..instruction.operand_count() {
+      ShapeUtil::for_each_subshape(computation.operand(instruction.id(), i).shape(),
+        &mut |subshape: &Shape, _index: &Vec<i64>| {
+          if subshape.is_array() {
+            input_bytes += analysis.get_shape_size(subshape) as f64;
+          }
+        });
+    }
+    let n = self.group_size(instruction) as f64;
+    match instruction.opcode() {
+      HloOpcode::AllReduce | HloOpcode::AllReduceStart =>
+        2.0 * (n - 1.0) / n * input_bytes,
+      HloOpcode::AllGather | HloOpcode::AllGatherStart =>
+        (n - 1.0) * input_bytes,
+      HloOpcode::ReduceScatter | HloOpcode::AllToAll | HloOpcode::RaggedAllToAll =>
+        (n - 1.0) / n * input_bytes,
+      HloOpcode::CollectivePermute | HloOpcode::CollectivePermuteStart |
+      HloOpcode::CollectiveBroadcast | HloOpcode::Send | HloOpcode::Recv =>
+        input_bytes,
+      _ => 0.0
+    }
+  }
+
+  fn group_size(&self, instruction: &HloInstruction) -> i64 {
+    let group_size = instruction.replica_groups().iter()
+      .map(|group| group.replica_ids().len() as i64)
+      .max()
+      .unwrap_or(0);
+    if group_size > 0 { group_size } else { self.replica_count }
+  }
+}
+
+fn is_async_start(opcode: HloOpcode) -> bool {
+  matches!(opcode,
+    HloOpcode::AllGatherStart | HloOpcode::AllReduceStart | HloOpcode::AsyncStart |
+    HloOpcode::CollectivePermuteStart | HloOpcode::CopyStart)
+}
+
+fn is_async_done(opcode: HloOpcode) -> bool {
+  matches!(opcode,
+    HloOpcode::AllGatherDone | HloOpcode::AllReduceDone | HloOpcode::AsyncDone |
+    HloOpcode::CollectivePermuteDone | HloOpcode::CopyDone)
+}
+
+#[cfg(test)]
+mod tests {
+  use stream_executor::device_description::DeviceDescriptionBuilder;
+  use crate::test_utils::{find, parse_module};
+  use super::*;
+
+  // A device with 1e12 flops per second and 1e10 bytes per second, so that
+  // instructions above 100 flops per byte are compute bound.
+  fn make_device() -> DeviceDescription {
+    let mut builder = DeviceDescriptionBuilder::new();
+    builder.set_name("test-device".to_string());
+    builder.set_clock_rate_ghz(1.0);
+    builder.set_core_count(50);
+    builder.set_fpus_per_core(10);
+    builder.set_memory_bandwidth(10_000_000_000);
+    builder.build()
+  }
+
+  fn assert_near(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() <= expected.abs() * 1e-9,
+      "expected {} but got {}", expected, actual);
+  }
+
+  #[test]
+  fn test_peak_rates() {
+    let model = RooflinePerformanceModel::new(&make_device()).unwrap();
+    assert_near(model.flops_per_second(), 1e12);
+    assert_near(model.bytes_per_second(), 1e10);
+    assert_near(model.interconnect_bytes_per_second(), 1e10);
+    assert_near(model.attainable_flops_per_second(10.0), 1e11);
+    assert_near(model.attainable_flops_per_second(1000.0), 1e12);
+
+    let err_msg = RooflinePerformanceModel::new(&DeviceDescription::new()).err().unwrap();
+    assert!(err_msg.contains("has no peak flop rate"));
+  }
+
+  #[test]
+  fn test_compute_and_memory_bound() {
+    let module = parse_module(r#"
+HloModule m
+
+ENTRY entry {
+  lhs = f32[1024,1024] parameter(0)
+  rhs = f32[1024,1024] parameter(1)
+  dot = f32[1024,1024] dot(lhs, rhs), lhs_contracting_dims={1}, rhs_contracting_dims={0}
+  ROOT add = f32[1024,1024] add(dot, lhs)
+}
+"#);
+    let model = RooflinePerformanceModel::new(&make_device()).unwrap();
+    let estimate = model.estimate_module(&module).unwrap();
+
+    let dot = estimate.instruction_estimate(find(&module, "dot").id()).unwrap();
+    assert_near(dot.flops(), 2.0 * 1024.0 * 1024.0 * 1024.0);
+    assert_near(dot.bytes_accessed(), 3.0 * 4.0 * 1024.0 * 1024.0);
+    assert!(dot.is_compute_bound());
+    assert_near(dot.seconds(), dot.flops() / 1e12);
+
+    let add = estimate.instruction_estimate(find(&module, "add").id()).unwrap();
+    assert!(add.is_memory_bound());
+    assert_near(add.seconds(), add.bytes_accessed() / 1e10);
+    assert!(add.arithmetic_intensity() < 1.0);
+  }
+
+  #[test]
+  fn test_critical_path_of_module() {
+    let module = parse_module(r#"
+HloModule m, is_scheduled=true
+
+ENTRY entry {
+  p0 = f32[1024] parameter(0)
+  p1 = f32[1024] parameter(1)
+  negate = f32[1024] negate(p0)
+  negate.1 = f32[1024] negate(negate)
+  exp = f32[1024] exponential(p1)
+  ROOT add = f32[1024] add(negate.1, exp)
+}
+"#);
+    let model = RooflinePerformanceModel::new(&make_device()).unwrap();
+    let estimate = model.estimate_module(&module).unwrap();
+    let seconds = |name: &str|
+      estimate.instruction_estimate(find(&module, name).id()).unwrap().seconds();
+    // Every instruction runs on the compute stream, one after another.
+    let expected = seconds("negate") + seconds("negate.1") + seconds("exp") + seconds("add");
+    assert!(expected > 0.0);
+    assert_near(estimate.makespan_seconds(), expected);
+    assert_near(estimate.compute_stream_seconds(), expected);
+    assert_eq!(estimate.async_collective_seconds(), 0.0);
+    // The negates do not depend on exp, so only the longer of the two
+    // branches is on the critical path.
+    assert!(seconds("negate") + seconds("negate.1") > seconds("exp"));
+    assert_near(estimate.critical_path_seconds(),
+      seconds("negate") + seconds("negate.1") + seconds("add"));
+    assert!(estimate.critical_path_seconds() < estimate.makespan_seconds());
+  }
+
+  #[test]
+  fn test_collective_transfer() {
+    let module = parse_module(r#"
+HloModule m
+
+add {
+  x = f32[] parameter(0)
+  y = f32[] parameter(1)
+  ROOT add = f32[] add(x, y)
+}
+
+ENTRY entry {
+  p0 = f32[1024] parameter(0)
+  ROOT all-reduce = f32[1024] all-reduce(p0), replica_groups={{0,1,2,3}}, to_apply=add
+}
+"#);
+    let mut model = RooflinePerformanceModel::new(&make_device()).unwrap();
+    model.set_interconnect_bytes_per_second(1e9);
+    let estimate = model.estimate_module(&module).unwrap();
+    let all_reduce = estimate.instruction_estimate(find(&module, "all-reduce").id()).unwrap();
+    // A ring all-reduce over 4 replicas sends 2 * 3 / 4 of its input.
+    assert_near(all_reduce.bytes_transferred(), 1.5 * 4096.0);
+    assert_near(all_reduce.collective_seconds(), 1.5 * 4096.0 / 1e9);
+    assert_eq!(all_reduce.bottleneck(), Bottleneck::Collective);
+    assert_near(estimate.makespan_seconds(), all_reduce.seconds());
+    assert_near(estimate.critical_path_seconds(), all_reduce.seconds());
+  }
+
+  #[test]
+  fn test_async_collective_overlap() {
+    let module = parse_module(r#"
+HloModule m, is_scheduled=true
+
+add {
+  x = f32[] parameter(0)
+  y = f32[] parameter(1)
+  ROOT add = f32[] add(x, y)
+}
+
+ENTRY entry {
+  p0 = f32[1024] parameter(0)
+  p1 = f32[512,512] parameter(1)
+  start = f32[1024] all-reduce-start(p0), replica_groups={{0,1}}, to_apply=add
+  dot = f32[512,512] dot(p1, p1), lhs_contracting_dims={1}, rhs_contracting_dims={0}
+  done = f32[1024] all-reduce-done(start)
+  ROOT tuple = (f32[1024], f32[512,512]) tuple(done, dot)
+}
+"#);
+    let mut model = RooflinePerformanceModel::new(&make_device()).unwrap();
+    model.set_interconnect_bytes_per_second(1e9);
+    let estimate = model.estimate_module(&module).unwrap();
+    let start = estimate.instruction_estimate(find(&module, "start").id()).unwrap();
+    let dot = estimate.instruction_estimate(find(&module, "dot").id()).unwrap();
+    // The dot takes longer than the all-reduce, which is hidden behind it.
+    assert!(dot.seconds() > start.seconds());
+    assert_near(estimate.async_collective_seconds(), start.seconds());
+    assert_eq!(estimate.exposed_collective_seconds(), 0.0);
+    assert_near(estimate.overlapped_collective_seconds(), start.seconds());
+    assert!(estimate.makespan_seconds() < estimate.compute_stream_seconds() + start.seconds());
+  }
+
+  #[test]
+  fn test_async_done_is_not_charged_to_compute_stream() {
+    let module = parse_module(r#"
+HloModule m, is_scheduled=true
+
+add {
+  x = f32[] parameter(0)
+  y = f32[] parameter(1)
+  ROOT add = f32[] add(x, y)
+}
+
+ENTRY entry {
+  p0 = f32[1024] parameter(0)
+  start = f32[1024] all-reduce-start(p0), replica_groups={{0,1}}, to_apply=add
+  ROOT done = f32[1024] all-reduce-done(start)
+}
+"#);
+    let mut model = RooflinePerformanceModel::new(&make_device()).unwrap();
+    model.set_interconnect_bytes_per_second(1e9);
+    let estimate = model.estimate_module(&module).unwrap();
+    let start = estimate.instruction_estimate(find(&module, "start").id()).unwrap();
+    // The done only waits for the transfer, which is charged once, to the
+    // async stream.
+    assert_eq!(estimate.compute_stream_seconds(), 0.0);
+    assert_near(estimate.async_collective_seconds(), start.seconds());
+    assert_near(estimate.exposed_collective_seconds(), start.seconds());
+    assert_near(estimate.makespan_seconds(), start.seconds());
+    assert_near(estimate.critical_path_seconds(), start.seconds());
+  }
+
+  #[test]
+  fn test_async_start_of_wrapped_collective() {
+    let module = parse_module(r#"
+HloModule m, is_scheduled=true
+
+add {
+  x = f32[] parameter(0)
+  y = f32[] parameter(1)
+  ROOT add = f32[] add(x, y)
+}
+
+wrapped_all_reduce {
+  x = f32[1024] parameter(0)
+  ROOT all-reduce = f32[1024] all-reduce(x), replica_groups={{0,1,2,3}}, to_apply=add
+}
+
+ENTRY entry {
+  p0 = f32[1024] parameter(0)
+  start = ((f32[1024]), f32[1024], s32[]) async-start(p0), calls=wrapped_all_reduce
+  ROOT done = f32[1024] async-done(start)
+}
+"#);
+    let mut model = RooflinePerformanceModel::new(&make_device()).unwrap();
+    model.set_interconnect_bytes_per_second(1e9);
+    let estimate = model.estimate_module(&module).unwrap();
+    let start = estimate.instruction_estimate(find(&module, "start").id()).unwrap();
+    // The same transfer as the ring all-reduce over 4 replicas it wraps.
+    assert_near(start.bytes_transferred(), 1.5 * 4096.0);
+    assert_eq!(start.bottleneck(), Bottleneck::Collective);
+    assert_near(estimate.async_collective_seconds(), 1.5 * 4096.0 / 1e9);
+  }
+}
@@ -31,7 +31,8 @@ impl RootInstructionSinker {
     }
 
     let mut modified = false;
-    for comp in module.make_nonfusion_computations(execution_threads) {
+    for id in module.make_nonfusion_computations(execution_threads) {
+      let comp = module.computation(id);
       let sequence = module.schedule().sequence(comp);
       if comp.root_instruction() == 
         &sequence.as_ref().unwrap().instructions()[sequence.as_ref().unwrap().size() - 1]
@@ -60,6 +61,7 @@ fn sink_nontuple_root(_module: &HloModule, computation: &mut HloComputation) {
   assert!(!root.shape().is_tuple());
   let bitcast = HloInstruction::create_bitcast(root.shape(), root);
 
+
   let _new_root =
     computation.add_instruction(bitcast, "".to_string());
 
@@ -58,12 +58,14 @@ impl ScatterExpander {
     "scatter-expander".to_string()
   }
 
-  pub fn instruction_matches_pattern(&self, instruction: &HloInstruction) -> bool {
+  pub fn instruction_matches_pattern(
+    &self, module: &HloModule, instruction: &HloInstruction) -> bool
+  {
     self.mode == ScatterExpanderMode::EliminateAllScatters ||
     (self.mode == ScatterExpanderMode::EliminateSimpleScatters &&
      scatter_trip_count(instruction) == 1) ||
     (self.mode == ScatterExpanderMode::EliminateIndeterminisitcScatters &&
-     !is_deterministic(instruction))
+     !is_deterministic(module, instruction))
   }
 
   pub fn expand_instruction(&self, _instruction: &HloInstruction) -> Result<HloInstruction, String> {
@@ -318,11 +320,11 @@ fn is_combiner_associative(combiner: &HloComputation) -> bool {
   }
 }
 
-fn is_deterministic(scatter: &HloInstruction) -> bool {
+fn is_deterministic(module: &HloModule, scatter: &HloInstruction) -> bool {
   if scatter.unique_indices() {
     return true;
   }
-  if is_combiner_associative(scatter.to_apply()) {
+  if is_combiner_associative(module.computation(scatter.to_apply())) {
     return true;
   }
   false
@@ -233,11 +233,10 @@ impl Service {
     unimplemented!()
   }
 
-  pub fn compute_constant_graph<T>(
+  pub fn compute_constant_graph(
     &self,
     computation: &BlitzComputation,
-    output_layout: Option<&Layout>) -> Result<Literal<T>, String>
-    where T: Default + Clone + PartialEq
+    output_layout: Option<&Layout>) -> Result<Literal<f64>, String>
   {
     if computation.has_host_program_shape() {
       let err_msg = "program shape may not be empty".to_string();
@@ -268,10 +267,10 @@ impl Service {
       DynamicDimensionInference::run(&module); // TODO
     check_error(&dynamic_dimension_inference);
 
-    let mut evaluator: HloEvaluator<T> = HloEvaluator::default();
+    let mut evaluator = HloEvaluator::default();
     evaluator.set_dynamic_dimension_inference(); // TODO
     let result_literal_wrapper =
-      evaluator.evaluate_module(&module);
+      evaluator.evaluate_module(&module, &vec![]);
     check_error(&result_literal_wrapper);
 
     let mut result_literal = result_literal_wrapper.unwrap();
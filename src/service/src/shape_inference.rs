@@ -1,71 +1,733 @@
 #![allow(dead_code)]
 
+use std::collections::HashSet;
+
 use common::{
-  blitz_data::{ConvolutionDimensionNumbers, DotDimensionNumbers, FftType, GatherDimensionNumbers, PaddingConfig, PrimitiveType, ScatterDimensionNummbers, SparsityDescriptor, TriangularSolveOptions, Window}, shape::{ProgramShape, Shape}, shape_util::ShapeUtil
+  blitz_data::{
+    ConvolutionDimensionNumbers, DotDimensionNumbers, FftType, GatherDimensionNumbers,
+    PaddingConfig, PrimitiveType, RandomAlgorithm, RandomDistribution,
+    ScatterDimensionNummbers, SparsityDescriptor, Transpose, TriangularSolveOptions,
+    Window, WindowDimension
+  },
+  permutation_util::is_permutation,
+  primitive_util::{
+    bit_width, complex_component_type, complex_type, is_array_type, is_complex_type,
+    is_floating_point_type, is_integral_type, is_signed_integral_type,
+    is_unsigned_integral_type, lowercase_primitive_type_name
+  },
+  shape::{ProgramShape, Shape},
+  shape_util::ShapeUtil
 };
 use hlo::{
   hlo_instruction::HloInstruction, hlo_opcode::{hlo_opcode_string, HloOpcode}
 };
 
+// Returns an error if 'shape' is not an array, naming the operand in the
+// message.
+fn expect_array(shape: &Shape, op_type: &str) -> Result<(), String> {
+  if !shape.is_array() {
+    return Err(format!("Expected array argument for {}, but got {}.",
+      op_type, ShapeUtil::human_string(shape)));
+  }
+  Ok(())
+}
+
+// Returns true if the given dimension sizes can describe the same dimension,
+// i.e. they are equal or one of them is unbounded.
+fn compatible_dimension_sizes(size_a: i64, size_b: i64) -> bool {
+  size_a == Shape::UNBOUNDED_SIZE || size_b == Shape::UNBOUNDED_SIZE || size_a == size_b
+}
+
+// Merges two dimensions that must describe the same runtime size into the
+// most specific one, following StableHLO: a static size wins over a bounded
+// dynamic one, and a bounded dynamic size wins over an unbounded one. Returns
+// None if the dimensions are incompatible.
+fn most_specific_dimension(
+  lhs_size: i64, lhs_is_dynamic: bool, rhs_size: i64, rhs_is_dynamic: bool) -> Option<(i64, bool)>
+{
+  if lhs_size == Shape::UNBOUNDED_SIZE {
+    return Some((rhs_size, rhs_is_dynamic));
+  }
+  if rhs_size == Shape::UNBOUNDED_SIZE {
+    return Some((lhs_size, lhs_is_dynamic));
+  }
+  match (lhs_is_dynamic, rhs_is_dynamic) {
+    (false, false) if lhs_size == rhs_size => Some((lhs_size, false)),
+    (false, false) => None,
+    (true, false) if rhs_size <= lhs_size => Some((rhs_size, false)),
+    (false, true) if lhs_size <= rhs_size => Some((lhs_size, false)),
+    (true, true) => Some((i64::min(lhs_size, rhs_size), true)),
+    _ => None
+  }
+}
+
+// Like most_specific_dimension(), but a static dimension of size 1 is
+// broadcast to the other dimension.
+fn broadcast_dimension(
+  lhs_size: i64, lhs_is_dynamic: bool, rhs_size: i64, rhs_is_dynamic: bool) -> Option<(i64, bool)>
+{
+  if lhs_size == 1 && !lhs_is_dynamic {
+    return Some((rhs_size, rhs_is_dynamic));
+  }
+  if rhs_size == 1 && !rhs_is_dynamic {
+    return Some((lhs_size, lhs_is_dynamic));
+  }
+  most_specific_dimension(lhs_size, lhs_is_dynamic, rhs_size, rhs_is_dynamic)
+}
+
+// Returns true if the array shapes have the same rank and compatible
+// dimension sizes, ignoring the element types.
+fn compatible_dimensions(lhs: &Shape, rhs: &Shape) -> bool {
+  lhs.rank() == rhs.rank() && (0..lhs.rank()).all(|i| {
+    most_specific_dimension(lhs.dimensions(i), lhs.is_dynamic_dimension(i as i64),
+      rhs.dimensions(i), rhs.is_dynamic_dimension(i as i64)).is_some()
+  })
+}
+
+// Returns the most specific shape of two array shapes with compatible
+// dimensions, with the element type of 'lhs'.
+fn merge_dimensions(lhs: &Shape, rhs: &Shape) -> Option<Shape> {
+  if lhs.rank() != rhs.rank() {
+    return None;
+  }
+  let mut dimensions = vec![];
+  let mut is_dynamic = vec![];
+  for i in 0..lhs.rank() {
+    let (size, dynamic) = most_specific_dimension(
+      lhs.dimensions(i), lhs.is_dynamic_dimension(i as i64),
+      rhs.dimensions(i), rhs.is_dynamic_dimension(i as i64))?;
+    dimensions.push(size);
+    is_dynamic.push(dynamic);
+  }
+  Some(ShapeUtil::make_shape_dynamic(&lhs.element_type(), dimensions, is_dynamic))
+}
+
+// Returns a shape with the given element type, dimensions and dynamic
+// dimensions taken from 'shape'.
+fn make_shape_like(element_type: &PrimitiveType, shape: &Shape) -> Shape {
+  ShapeUtil::make_shape_dynamic(element_type, shape.dimensions_vec().clone(),
+    shape.dinamic_dimensions().clone())
+}
+
+fn dilated_bound(bound: i64, dilation: i64) -> i64 {
+  if bound == 0 { 0 } else { (bound - 1) * dilation + 1 }
+}
+
+fn strided_bound(bound: i64, window_size: i64, stride: i64) -> i64 {
+  if bound == 0 || window_size > bound { 0 } else { (bound - window_size) / stride + 1 }
+}
+
+fn window_to_string(window: &Window) -> String {
+  let join = |f: &dyn Fn(&WindowDimension) -> String| -> String {
+    window.dimensions_vec().iter().map(f).collect::<Vec<String>>().join("x")
+  };
+  let mut result = format!("size={}", join(&|d| d.size().to_string()));
+  result.push_str(&format!(" stride={}", join(&|d| d.stride().to_string())));
+  result.push_str(&format!(" pad={}",
+    join(&|d| format!("{}_{}", d.padding_low(), d.padding_high()))));
+  result.push_str(&format!(" lhs_dilate={}", join(&|d| d.base_dilation().to_string())));
+  result.push_str(&format!(" rhs_dilate={}", join(&|d| d.window_dilation().to_string())));
+  result
+}
+
+fn vector_string(values: &Vec<i64>) -> String {
+  format!("{{{}}}", values.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(","))
+}
+
+fn has_duplicates(values: &Vec<i64>) -> bool {
+  let mut seen = HashSet::new();
+  values.iter().any(|v| !seen.insert(*v))
+}
+
+// Returns the element type of a dot or convolution whose operands have the
+// element type 'from_type', honoring the preferred element type if any.
+fn maybe_upcast(
+  from_type: &PrimitiveType,
+  preferred_element_type: &Option<PrimitiveType>) -> Result<PrimitiveType, String>
+{
+  match preferred_element_type {
+    None => Ok(from_type.clone()),
+    Some(preferred) if preferred == from_type => Ok(from_type.clone()),
+    Some(preferred) => {
+      if !is_floating_point_type(from_type) && bit_width(preferred) < bit_width(from_type) {
+        return Err("`preferred_element_type` must not be narrower than the original type.".to_string());
+      }
+      Ok(preferred.clone())
+    }
+  }
+}
+
+// Verifies that the reducer has the shape expected for reducing 'inputs'
+// arrays with the given element types and init values.
+fn verify_reducer_shape(
+  reducer_shape: &ProgramShape,
+  init_value_shapes: &Vec<Shape>,
+  input_element_types: &Vec<PrimitiveType>,
+  inputs: usize) -> Result<(), String>
+{
+  if reducer_shape.parameters_size() != inputs * 2 {
+    return Err(format!("Reduction function must take {} parameters, but takes {} parameter(s).",
+      inputs * 2, reducer_shape.parameters_size()));
+  }
+  let accumulator_shape = reducer_shape.result();
+  let mut accumulator_subshapes = vec![];
+  if accumulator_shape.is_array() {
+    if inputs != 1 {
+      return Err(format!(
+        "Reduction function must produce a tuple with {} elements, but produces a scalar.",
+        inputs));
+    }
+    accumulator_subshapes.push(accumulator_shape.clone());
+  } else if accumulator_shape.is_tuple() {
+    if accumulator_shape.tuple_shapes_size() != inputs {
+      return Err(format!(
+        "Reduction function must produce a tuple with {} elements, but has {} elements.",
+        inputs, accumulator_shape.tuple_shapes_size()));
+    }
+    accumulator_subshapes.extend(accumulator_shape.tuple_shapes_vec().iter().cloned());
+  } else {
+    return Err(format!(
+      "Reduction function must produce a scalar or tuple of scalars, but has shape: {}.",
+      ShapeUtil::human_string(accumulator_shape)));
+  }
+  for accumulator_subshape in &accumulator_subshapes {
+    if !ShapeUtil::is_scalar(accumulator_subshape) {
+      return Err(format!(
+        "Reduction function must have rank 0 (rank {} reduction function given).",
+        accumulator_subshape.rank()));
+    }
+  }
+  for i in 0..inputs {
+    // Check that the accumulator can be passed in as the first argument.
+    if !ShapeUtil::compatible_ignoring_fp_precision(
+      &accumulator_subshapes[i], reducer_shape.parameters(i))
+    {
+      return Err(format!(
+        "Reduction function's {}-th parameter shape differs from the result shape: {} vs {}.",
+        i, ShapeUtil::human_string(reducer_shape.parameters(i)),
+        ShapeUtil::human_string(&accumulator_subshapes[i])));
+    }
+    // Check that init_value's shapes are suitable for reducer_shape.
+    if !ShapeUtil::compatible_ignoring_fp_precision(
+      &accumulator_subshapes[i], &init_value_shapes[i])
+    {
+      return Err(format!(
+        "Reduction function's accumulator shape at index {} differs from the init_value shape: {} vs {}.",
+        i, ShapeUtil::human_string(&accumulator_subshapes[i]),
+        ShapeUtil::human_string(&init_value_shapes[i])));
+    }
+    // Check that the inputs can be passed in as the non-accumulator arguments.
+    let input_element_shape = ShapeUtil::make_scalar_shape(&input_element_types[i]);
+    if !ShapeUtil::compatible_ignoring_fp_precision(
+      &input_element_shape, reducer_shape.parameters(inputs + i))
+    {
+      return Err(format!(
+        "Reduction function's {}-th parameter shape differs from the input type element type: {} vs {}.",
+        inputs + i, ShapeUtil::human_string(reducer_shape.parameters(inputs + i)),
+        ShapeUtil::human_string(&input_element_shape)));
+    }
+    // Check that the accumulator and inputs to the reducer function match.
+    if !ShapeUtil::compatible_ignoring_fp_precision(
+      &accumulator_subshapes[i], reducer_shape.parameters(inputs + i))
+    {
+      return Err(format!(
+        "Reduction function's {}-th parameter shape must match the result shape, but got {} vs {}.",
+        inputs + i, ShapeUtil::human_string(reducer_shape.parameters(inputs + i)),
+        ShapeUtil::human_string(&accumulator_subshapes[i])));
+    }
+  }
+  Ok(())
+}
+
+// Infers the shape of the result of applying 'window' to 'base_shape'.
+fn infer_window_output_shape(
+  base_shape: &Shape, window: &Window, element_type: &PrimitiveType) -> Result<Shape, String>
+{
+  if window.dimensions_size() != base_shape.rank() {
+    return Err(format!("Window has dimension {} but base shape has dimension {}.",
+      window.dimensions_size(), base_shape.rank()));
+  }
+  let mut output_dimensions = vec![0; window.dimensions_size()];
+  let mut output_is_dynamic = vec![false; window.dimensions_size()];
+  for i in 0..window.dimensions_size() {
+    let dim = window.dimensions(i);
+    if dim.size() <= 0 {
+      return Err(format!("Window {} has a non-positive dimension.", window_to_string(window)));
+    }
+    if dim.stride() <= 0 {
+      return Err(format!("Window {} has a non-positive stride.", window_to_string(window)));
+    }
+    if dim.base_dilation() < 1 {
+      return Err(format!("Window {} has a non-positive base area dilation factor.",
+        window_to_string(window)));
+    }
+    if dim.window_dilation() < 1 {
+      return Err(format!("Window {} has a non-positive window dilation factor.",
+        window_to_string(window)));
+    }
+    if base_shape.is_unbounded_dynamic_dimension(i) {
+      output_dimensions[i] = Shape::UNBOUNDED_SIZE;
+      output_is_dynamic[i] = true;
+      continue;
+    }
+    let dilated_base = dilated_bound(base_shape.dimensions(i), dim.base_dilation());
+    let padded_dilated_base = dim.padding_low() + dilated_base + dim.padding_high();
+    let dilated_window = dilated_bound(dim.size(), dim.window_dilation());
+    output_dimensions[i] = strided_bound(padded_dilated_base, dilated_window, dim.stride());
+    output_is_dynamic[i] = base_shape.is_dynamic_dimension(i as i64);
+  }
+  Ok(ShapeUtil::make_shape_dynamic(element_type, output_dimensions, output_is_dynamic))
+}
+
+// Validates that the dimension numbers of a dot are in range and unique.
+fn validate_dot_dimension_numbers(
+  lhs: &Shape, rhs: &Shape, dimension_numbers: &DotDimensionNumbers) -> Result<(), String>
+{
+  let dims_in_range = |rank: usize, contracting: &Vec<i64>, batch: &Vec<i64>| -> bool {
+    contracting.iter().chain(batch.iter()).all(|d| *d >= 0 && (*d as usize) < rank)
+  };
+  let dims_unique = |contracting: &Vec<i64>, batch: &Vec<i64>| -> bool {
+    let mut all = contracting.clone();
+    all.extend(batch.iter());
+    !has_duplicates(&all)
+  };
+  if !dims_in_range(lhs.rank(), dimension_numbers.lhs_contracting_dimensions(),
+      dimension_numbers.lhs_batch_dimensions()) ||
+    !dims_in_range(rhs.rank(), dimension_numbers.rhs_contracting_dimensions(),
+      dimension_numbers.rhs_batch_dimensions())
+  {
+    return Err(format!("A dimension number is out of range in Dot: {:?}.", dimension_numbers));
+  }
+  if !dims_unique(dimension_numbers.lhs_contracting_dimensions(),
+      dimension_numbers.lhs_batch_dimensions()) ||
+    !dims_unique(dimension_numbers.rhs_contracting_dimensions(),
+      dimension_numbers.rhs_batch_dimensions())
+  {
+    return Err(format!("A dimension number is not unique in Dot: {:?}.", dimension_numbers));
+  }
+  Ok(())
+}
+
+// Checks that 'values' lists distinct dimensions in [0, rank).
+fn validate_dimension_list(
+  values: &Vec<i64>, rank: usize, op_name: &str, list_name: &str, sorted: bool) -> Result<(), String>
+{
+  for value in values {
+    if *value < 0 || *value as usize >= rank {
+      return Err(format!("Invalid {} set in {} op; valid range is [0, {}), got: {}.",
+        list_name, op_name, rank, value));
+    }
+  }
+  if sorted && !values.windows(2).all(|w| w[0] <= w[1]) {
+    return Err(format!("{} in {} op must be sorted; got: {}.",
+      list_name, op_name, vector_string(values)));
+  }
+  if has_duplicates(values) {
+    return Err(format!("Repeated dimensions not allowed in {} in {} op; got: {}.",
+      list_name, op_name, vector_string(values)));
+  }
+  Ok(())
+}
+
 // For a given operation and input shapes, infers what the resulting shape
 // is for the operation.
 pub struct ShapeInference {}
 
 impl ShapeInference {
+  // Infers the shape produced by applying the given unary operation to the
+  // given input operand.
   pub fn infer_unary_op_shape(
-    _opcode: &HloOpcode,
-    _operand: &HloInstruction) -> Result<Shape, String>
+    opcode: &HloOpcode,
+    operand: &HloInstruction) -> Result<Shape, String>
   {
-    unimplemented!()
+    ShapeInference::infer_unary_op_shape_by_shape(opcode, operand.shape())
   }
 
   pub fn infer_unary_op_shape_by_shape(
-    _opcode: &HloOpcode,
-    _shape: &Shape) -> Result<Shape, String>
+    opcode: &HloOpcode,
+    shape: &Shape) -> Result<Shape, String>
   {
-    unimplemented!()
+    // There is no copy operation at the proto level, so handle copy
+    // explicitly. A domain shape is the same as the input one.
+    if *opcode == HloOpcode::Copy || *opcode == HloOpcode::Domain {
+      return Ok(shape.clone());
+    }
+    let op_name = hlo_opcode_string(opcode);
+    expect_array(shape, &format!("operand of unary operation {}", op_name))?;
+    ShapeUtil::validate_shape_with_optional_layout(shape)?;
+    let t = shape.element_type();
+    let type_name = lowercase_primitive_type_name(&t);
+    match opcode {
+      HloOpcode::Floor | HloOpcode::Ceil | HloOpcode::RoundNearestAfz |
+      HloOpcode::RoundNearestEven => {
+        if !is_floating_point_type(&t) {
+          return Err(format!(
+            "Expected element type in shape to be floating for {} operation; got {}.",
+            op_name, type_name));
+        }
+        Ok(shape.clone())
+      },
+      HloOpcode::Cos | HloOpcode::Sin | HloOpcode::Exp | HloOpcode::Expm1 |
+      HloOpcode::Log | HloOpcode::Log1p | HloOpcode::Logistic | HloOpcode::Rsqrt |
+      HloOpcode::Sqrt | HloOpcode::Cbrt | HloOpcode::Tanh | HloOpcode::Tan => {
+        if !is_floating_point_type(&t) && !is_complex_type(&t) {
+          return Err(format!(
+            "Expected element type in shape to be floating or complex for {} operation; got {}.",
+            op_name, type_name));
+        }
+        Ok(shape.clone())
+      },
+      HloOpcode::Erf => {
+        if !is_floating_point_type(&t) {
+          return Err(format!(
+            "Expected element type in shape to be floating for {} operation; got {}.",
+            op_name, type_name));
+        }
+        Ok(shape.clone())
+      },
+      HloOpcode::Real | HloOpcode::Imag => {
+        if is_complex_type(&t) {
+          return Ok(ShapeUtil::change_element_type(shape, &complex_component_type(t)));
+        }
+        if !is_floating_point_type(&t) {
+          return Err(format!(
+            "Expected element type in shape to be floating or complex for {} operation; got {}.",
+            op_name, type_name));
+        }
+        Ok(shape.clone())
+      },
+      HloOpcode::Abs => {
+        if is_complex_type(&t) {
+          return Ok(ShapeUtil::change_element_type(shape, &complex_component_type(t)));
+        }
+        if !is_signed_integral_type(&t) && !is_floating_point_type(&t) {
+          return Err(format!(
+            "Expected element type in shape to be signed integral, floating or complex for {} operation; got {}.",
+            op_name, type_name));
+        }
+        Ok(shape.clone())
+      },
+      HloOpcode::Clz | HloOpcode::PopulationCount => {
+        if !is_integral_type(&t) {
+          return Err(format!(
+            "Expected an integral element type in argument to {} operation; got {}.",
+            op_name, type_name));
+        }
+        Ok(shape.clone())
+      },
+      HloOpcode::Negate => {
+        if !is_integral_type(&t) && !is_floating_point_type(&t) && !is_complex_type(&t) {
+          return Err(format!(
+            "Expected element type in shape to be integral, floating or complex for {} operation; got {}.",
+            op_name, type_name));
+        }
+        Ok(shape.clone())
+      },
+      HloOpcode::Sign => {
+        if !is_signed_integral_type(&t) && !is_floating_point_type(&t) && !is_complex_type(&t) {
+          return Err(format!(
+            "Expected element type in shape to be signed or complex for {} operation; got {}.",
+            op_name, type_name));
+        }
+        Ok(shape.clone())
+      },
+      HloOpcode::Not => {
+        if t != PrimitiveType::Pred && !is_integral_type(&t) {
+          return Err(format!(
+            "Expected pred or an integral element type in argument to Not operation; got {}.",
+            type_name));
+        }
+        Ok(shape.clone())
+      },
+      HloOpcode::IsFinite => {
+        if !is_floating_point_type(&t) {
+          return Err(format!(
+            "Expected element type in shape to be floating point for IsFinite operation; got {}.",
+            type_name));
+        }
+        Ok(ShapeUtil::change_element_type(shape, &PrimitiveType::Pred))
+      },
+      _ => Err(format!("Unknown operation for unary shape inference: \"{}\".", op_name))
+    }
   }
 
+  // Infers the shape produced by applying the given binary operation to the
+  // given input operands.
   pub fn infer_binary_op_shape(
-    _opcode: &HloOpcode,
-    _lhs: &HloInstruction,
-    _rhs: &HloInstruction) -> Result<Shape, String>
+    opcode: &HloOpcode,
+    lhs: &HloInstruction,
+    rhs: &HloInstruction) -> Result<Shape, String>
   {
-    unimplemented!()
+    ShapeInference::infer_binary_op_shape_by_dimensions(
+      opcode, lhs.shape(), rhs.shape(), &vec![])
   }
 
+  // Infers the shape produced by applying the given binary operation to the
+  // given input shapes, broadcasting the lower-rank operand into
+  // 'broadcast_dimensions' of the higher-rank one.
   pub fn infer_binary_op_shape_by_dimensions(
-    _opcode: &HloOpcode,
-    _lhs: &Shape,
-    _rhs: &Shape,
-    _broadcast_dimensions: &Vec<i64>) -> Result<Shape, String>
+    opcode: &HloOpcode,
+    lhs: &Shape,
+    rhs: &Shape,
+    broadcast_dimensions: &Vec<i64>) -> Result<Shape, String>
+  {
+    let op_name = hlo_opcode_string(opcode);
+    expect_array(lhs, &format!("lhs of binary operation {}", op_name))?;
+    expect_array(rhs, &format!("rhs of binary operation {}", op_name))?;
+    ShapeUtil::validate_shape_with_optional_layout(lhs)?;
+    ShapeUtil::validate_shape_with_optional_layout(rhs)?;
+
+    match opcode {
+      HloOpcode::Add | HloOpcode::Subtract | HloOpcode::Multiply | HloOpcode::Divide |
+      HloOpcode::Remainder | HloOpcode::Maximum | HloOpcode::Minimum | HloOpcode::Power |
+      HloOpcode::ShiftLeft | HloOpcode::ShiftRightArithmetic |
+      HloOpcode::ShiftRightLogical => {
+        ShapeInference::infer_elementwise_binary_op_shape(opcode, lhs, rhs, broadcast_dimensions)
+      },
+      HloOpcode::Atan2 => {
+        if !ShapeUtil::element_is_floating(lhs) && !ShapeUtil::element_is_complex(lhs) {
+          return Err(format!(
+            "Expected element type in shape to be floating or complex for {} operation; got {}.",
+            op_name, lowercase_primitive_type_name(&lhs.element_type())));
+        }
+        ShapeInference::infer_elementwise_binary_op_shape(opcode, lhs, rhs, broadcast_dimensions)
+      },
+      HloOpcode::Complex => {
+        if !ShapeUtil::element_is_floating(lhs) {
+          return Err(format!(
+            "Expected element type in shape to be floating for complex compose operation; got {}.",
+            lowercase_primitive_type_name(&lhs.element_type())));
+        }
+        let shape = ShapeInference::infer_elementwise_binary_op_shape(
+          opcode, lhs, rhs, broadcast_dimensions)?;
+        match shape.element_type() {
+          PrimitiveType::F32 => Ok(ShapeUtil::change_element_type(&shape, &PrimitiveType::C64)),
+          PrimitiveType::F64 => Ok(ShapeUtil::change_element_type(&shape, &PrimitiveType::C128)),
+          _ => Err(format!("{} not supported by Complex.",
+            lowercase_primitive_type_name(&shape.element_type())))
+        }
+      },
+      HloOpcode::And | HloOpcode::Or | HloOpcode::Xor => {
+        if lhs.element_type() != PrimitiveType::Pred && !ShapeUtil::element_is_integral(lhs) {
+          return Err(format!(
+            "Expected pred or integral type in argument to and/or operation; got {}.",
+            lowercase_primitive_type_name(&lhs.element_type())));
+        }
+        ShapeInference::infer_elementwise_binary_op_shape(opcode, lhs, rhs, broadcast_dimensions)
+      },
+      HloOpcode::Compare => {
+        let shape = ShapeInference::infer_elementwise_binary_op_shape(
+          opcode, lhs, rhs, broadcast_dimensions)?;
+        Ok(ShapeUtil::change_element_type(&shape, &PrimitiveType::Pred))
+      },
+      _ => Err(format!("Binary op shape inference: {}; lhs: {}; rhs: {} is not implemented.",
+        op_name, ShapeUtil::human_string(lhs), ShapeUtil::human_string(rhs)))
+    }
+  }
+
+  fn infer_elementwise_binary_op_shape(
+    opcode: &HloOpcode,
+    lhs: &Shape,
+    rhs: &Shape,
+    broadcast_dimensions: &Vec<i64>) -> Result<Shape, String>
+  {
+    if !ShapeUtil::same_element_type_ignoring_fp_precision(lhs, rhs) {
+      return Err(format!("Binary op {} with different element types: {} and {}.",
+        hlo_opcode_string(opcode), ShapeUtil::human_string(lhs), ShapeUtil::human_string(rhs)));
+    }
+    let element_type = ShapeUtil::higher_precision_element_type(lhs, rhs);
+
+    if lhs.rank() == rhs.rank() {
+      if !broadcast_dimensions.is_empty() {
+        let identity: Vec<i64> = (0..lhs.rank() as i64).collect();
+        if *broadcast_dimensions != identity {
+          return Err(format!(
+            "Broadcast dimensions field must either be not set or be the identity on binary operations with operands of the same rank; got {}.",
+            vector_string(broadcast_dimensions)));
+        }
+      }
+      let shape = ShapeInference::infer_degenerate_dimension_broadcast_shape(lhs, rhs)?;
+      return Ok(ShapeUtil::change_element_type(&shape, &element_type));
+    }
+
+    // The operands have different ranks.
+    if broadcast_dimensions.is_empty() &&
+      !ShapeUtil::is_scalar(lhs) && !ShapeUtil::is_scalar(rhs)
+    {
+      return Err(format!("Automatic shape inference not supported: {} and {}.",
+        ShapeUtil::human_string(lhs), ShapeUtil::human_string(rhs)));
+    }
+    let (smaller, larger) = if lhs.rank() > rhs.rank() { (rhs, lhs) } else { (lhs, rhs) };
+    let indim_broadcast_shape = ShapeInference::infer_in_dim_broadcast_shape(
+      smaller, larger, broadcast_dimensions)?;
+    let shape = ShapeInference::infer_degenerate_dimension_broadcast_shape(
+      &indim_broadcast_shape, larger)?;
+    Ok(ShapeUtil::change_element_type(&shape, &element_type))
+  }
+
+  // Infers the shape of the result of broadcasting size-1 dimensions of the
+  // same-rank operands into the other operand.
+  fn infer_degenerate_dimension_broadcast_shape(
+    lhs: &Shape, rhs: &Shape) -> Result<Shape, String>
+  {
+    assert_eq!(lhs.rank(), rhs.rank());
+    let mut dimensions = vec![];
+    let mut is_dynamic = vec![];
+    for i in 0..lhs.rank() {
+      match broadcast_dimension(lhs.dimensions(i), lhs.is_dynamic_dimension(i as i64),
+        rhs.dimensions(i), rhs.is_dynamic_dimension(i as i64))
+      {
+        Some((size, dynamic)) => {
+          dimensions.push(size);
+          is_dynamic.push(dynamic);
+        },
+        None => return Err(format!(
+          "Binary op with incompatible shapes: {} and {}.",
+          ShapeUtil::human_string(lhs), ShapeUtil::human_string(rhs)))
+      }
+    }
+    Ok(ShapeUtil::make_shape_dynamic(&lhs.element_type(), dimensions, is_dynamic))
+  }
+
+  // Infers the shape of broadcasting 'smaller' into the dimensions
+  // 'broadcast_dimensions' of 'larger'.
+  fn infer_in_dim_broadcast_shape(
+    smaller: &Shape, larger: &Shape, broadcast_dimensions: &Vec<i64>) -> Result<Shape, String>
   {
-    unimplemented!()
+    if broadcast_dimensions.is_empty() && !ShapeUtil::is_scalar(smaller) {
+      return Err(format!(
+        "Shapes must be equal rank, but are {} and {}.",
+        ShapeUtil::human_string(smaller), ShapeUtil::human_string(larger)));
+    }
+    if !broadcast_dimensions.is_empty() && broadcast_dimensions.len() != smaller.rank() {
+      return Err(format!(
+        "Size of broadcast_dimensions has to match lower-rank operand's rank; lower-rank operand's rank is {}, size of broadcast_dimensions is {}.",
+        smaller.rank(), broadcast_dimensions.len()));
+    }
+    let mut dimensions = larger.dimensions_vec().clone();
+    let mut is_dynamic = larger.dinamic_dimensions().clone();
+    for i in 0..broadcast_dimensions.len() {
+      let dimension_to_match = broadcast_dimensions[i];
+      if dimension_to_match < 0 {
+        return Err(format!("Broadcast dimension number ({}) cannot be negative.",
+          dimension_to_match));
+      }
+      if dimension_to_match as usize >= larger.rank() {
+        return Err(format!(
+          "Broadcast dimension number ({}) too large; higher-rank operand has rank {}.",
+          dimension_to_match, larger.rank()));
+      }
+      if i > 0 && broadcast_dimensions[i - 1] >= dimension_to_match {
+        return Err(format!("Broadcast dimensions order is wrong: {} comes after {}.",
+          dimension_to_match, broadcast_dimensions[i - 1]));
+      }
+      let d = dimension_to_match as usize;
+      match broadcast_dimension(smaller.dimensions(i), smaller.is_dynamic_dimension(i as i64),
+        larger.dimensions(d), larger.is_dynamic_dimension(d as i64))
+      {
+        Some((size, dynamic)) => {
+          dimensions[d] = size;
+          is_dynamic[d] = dynamic;
+        },
+        None => return Err(format!(
+          "Broadcast dimension {} mismatch: {} != {}; {} and {}.",
+          i, smaller.dimensions(i), larger.dimensions(d),
+          ShapeUtil::human_string(smaller), ShapeUtil::human_string(larger)))
+      }
+    }
+    Ok(ShapeUtil::make_shape_dynamic(&larger.element_type(), dimensions, is_dynamic))
   }
 
+  // Infers the shape produced by applying the given ternary operation to the
+  // given input operands.
   pub fn infer_ternary_op_shape(
-    _opcode: &HloOpcode,
-    _lhs: &HloInstruction,
-    _rhs: &HloInstruction,
-    _ehs: &HloInstruction) -> Result<Shape, String>
+    opcode: &HloOpcode,
+    lhs: &HloInstruction,
+    rhs: &HloInstruction,
+    ehs: &HloInstruction) -> Result<Shape, String>
   {
-    unimplemented!()    
+    ShapeInference::infer_ternary_op_shape_by_shape(
+      opcode.clone(), lhs.shape(), rhs.shape(), ehs.shape())
   }
 
   pub fn infer_ternary_op_shape_by_shape(
-    _opcode: HloOpcode,
-    _lhs: &Shape,
-    _rhs: &Shape,
-    _ehs: &Shape) -> Result<Shape, String>
+    opcode: HloOpcode,
+    lhs: &Shape,
+    rhs: &Shape,
+    ehs: &Shape) -> Result<Shape, String>
   {
-    unimplemented!()    
+    ShapeUtil::validate_shape_with_optional_layout(lhs)?;
+    ShapeUtil::validate_shape_with_optional_layout(rhs)?;
+    ShapeUtil::validate_shape_with_optional_layout(ehs)?;
+    match opcode {
+      HloOpcode::Clamp => ShapeInference::infer_clamp_shape(lhs, rhs, ehs),
+      HloOpcode::Select => ShapeInference::infer_select_shape(lhs, rhs, ehs),
+      _ => Err(format!("Unknown operation {}.", hlo_opcode_string(&opcode)))
+    }
+  }
+
+  fn infer_clamp_shape(min: &Shape, operand: &Shape, max: &Shape) -> Result<Shape, String> {
+    expect_array(min, "clamp min")?;
+    expect_array(operand, "clamp operand")?;
+    expect_array(max, "clamp max")?;
+    if !ShapeUtil::same_element_type_ignoring_fp_precision(min, operand) ||
+      !ShapeUtil::same_element_type_ignoring_fp_precision(max, operand)
+    {
+      return Err(format!("Clamp with different shapes: {}, {}, {}.",
+        ShapeUtil::human_string(min), ShapeUtil::human_string(operand),
+        ShapeUtil::human_string(max)));
+    }
+    let mut result = operand.clone();
+    for bound in [min, max] {
+      if ShapeUtil::is_scalar(bound) {
+        continue;
+      }
+      match merge_dimensions(&result, bound) {
+        Some(merged) => result = merged,
+        None => return Err(format!("Clamp with incompatible shapes: {}, {}, {}.",
+          ShapeUtil::human_string(min), ShapeUtil::human_string(operand),
+          ShapeUtil::human_string(max)))
+      }
+    }
+    Ok(result)
+  }
+
+  fn infer_select_shape(pred: &Shape, on_true: &Shape, on_false: &Shape) -> Result<Shape, String> {
+    expect_array(pred, "select pred")?;
+    expect_array(on_true, "select on-true")?;
+    expect_array(on_false, "select on-false")?;
+    if !ShapeUtil::same_element_type_ignoring_fp_precision(on_true, on_false) {
+      return Err(format!("Operands to select must be the same shape; got {} and {}.",
+        ShapeUtil::human_string(on_true), ShapeUtil::human_string(on_false)));
+    }
+    let branches = match merge_dimensions(on_true, on_false) {
+      Some(shape) => shape,
+      None => return Err(format!("Operands to select must be the same shape; got {} and {}.",
+        ShapeUtil::human_string(on_true), ShapeUtil::human_string(on_false)))
+    };
+    if pred.element_type() != PrimitiveType::Pred {
+      return Err(format!("Select's pred operand must have PRED element type; got {}.",
+        ShapeUtil::human_string(pred)));
+    }
+    let mut result = branches;
+    if !ShapeUtil::is_scalar(pred) {
+      match merge_dimensions(&result, pred) {
+        Some(shape) => result = shape,
+        None => return Err(format!(
+          "Operands to select and predicate must be the same shape (or predicate must be a scalar); got {} and {}.",
+          ShapeUtil::human_string(pred), ShapeUtil::human_string(on_true)))
+      }
+    }
+    Ok(ShapeUtil::change_element_type(&result,
+      &ShapeUtil::higher_precision_element_type(on_true, on_false)))
   }
 
   pub fn infer_variadic_op_shape(
-    _opcode: HloOpcode,
-    _operands: &Vec<HloInstruction>) -> Result<Shape, String>
+    opcode: HloOpcode,
+    operands: &Vec<HloInstruction>) -> Result<Shape, String>
   {
-    unimplemented!()    
+    let operand_shapes = operands.iter().map(|operand| operand.shape().clone()).collect();
+    ShapeInference::infer_variadic_op_shape_by_opshapes(opcode, &operand_shapes)
   }
 
   // Infers the shape produced by applying the given variadic operation to the
@@ -121,160 +783,915 @@ impl ShapeInference {
     }
   }
 
+  // Infers the shape produced by sorting the operands along 'dimension'.
+  pub fn infer_sort_shape(operand_shapes: &Vec<Shape>, dimension: i64) -> Result<Shape, String> {
+    if operand_shapes.is_empty() {
+      return Err("Sort expects at least one operand.".to_string());
+    }
+    for shape in operand_shapes {
+      expect_array(shape, "operand of sort")?;
+    }
+    let keys = &operand_shapes[0];
+    if dimension < 0 || dimension as usize >= keys.rank() {
+      return Err(format!("Sort dimension {} is out of bounds for keys of shape {}.",
+        dimension, ShapeUtil::human_string(keys)));
+    }
+    ShapeInference::infer_variadic_op_shape_by_opshapes(HloOpcode::Sort, operand_shapes)
+  }
+
+  // Infers the shape produced by a map operation of the computation
+  // 'to_apply' over the arrays 'arg_shapes'.
+  pub fn infer_map_shape(
+    arg_shapes: &Vec<Shape>,
+    to_apply: &ProgramShape,
+    dimensions: &Vec<i64>) -> Result<Shape, String>
+  {
+    if arg_shapes.is_empty() {
+      return Err("Map expects at least one argument.".to_string());
+    }
+    // All arguments must have the same shape ignoring the element types.
+    expect_array(&arg_shapes[0], "operand of map")?;
+    let mut arg_shape = &arg_shapes[0];
+    for i in 1..arg_shapes.len() {
+      expect_array(&arg_shapes[i], "operand of map")?;
+      if compatible_dimensions(&arg_shapes[i], arg_shape) {
+        continue;
+      }
+      if ShapeUtil::same_element_type_ignoring_fp_precision(&arg_shapes[i], arg_shape) {
+        if ShapeUtil::is_scalar(&arg_shapes[i]) {
+          continue;
+        }
+        if ShapeUtil::is_scalar(arg_shape) {
+          arg_shape = &arg_shapes[i];
+          continue;
+        }
+      }
+      let shapes: Vec<String> = arg_shapes.iter().map(ShapeUtil::human_string).collect();
+      return Err(format!("Map operation requires all operands to have the same shape; got: {}.",
+        shapes.join(", ")));
+    }
+
+    // Only mapping across all dimensions, i.e. scalar map functions, is
+    // supported.
+    if dimensions.len() != arg_shape.rank() {
+      return Err(format!(
+        "Map applied to a subset of dimensions currently not supported: arg_dimension_size: {}, requested_map_dimensions_size: {}.",
+        arg_shape.rank(), dimensions.len()));
+    }
+    for (i, dimension) in dimensions.iter().enumerate() {
+      if *dimension != i as i64 {
+        return Err(format!("Map requires monotonically increasing dimension numbers; got: {}.",
+          vector_string(dimensions)));
+      }
+    }
+
+    // The applied function's arity equals the number of arguments.
+    if arg_shapes.len() != to_apply.parameters_size() {
+      return Err(format!(
+        "Map applied function arity must match number of arguments; got: arity: {}, arguments: {}.",
+        to_apply.parameters_size(), arg_shapes.len()));
+    }
+
+    // The parameters should all be scalars, and the output too.
+    let output_shape = to_apply.result();
+    if !ShapeUtil::is_scalar(output_shape) {
+      return Err(format!("Mapped computation's result has to be a scalar; got: {}.",
+        ShapeUtil::human_string(output_shape)));
+    }
+    for i in 0..to_apply.parameters_size() {
+      let parameter_shape = to_apply.parameters(i);
+      if !ShapeUtil::is_scalar(parameter_shape) {
+        return Err(format!(
+          "Mapped computation's parameter has to be a scalar; got parameter {} shape: {}.",
+          i, ShapeUtil::human_string(parameter_shape)));
+      }
+      if !ShapeUtil::same_element_type_ignoring_fp_precision(parameter_shape, &arg_shapes[i]) {
+        return Err(format!(
+          "Mapped computation's parameter type has to match argument element type; got parameter {} shape: {}, argument shape: {}.",
+          i, ShapeUtil::human_string(parameter_shape), ShapeUtil::human_string(&arg_shapes[i])));
+      }
+    }
+    Ok(make_shape_like(&output_shape.element_type(), arg_shape))
+  }
 
+  // Infers the shape produced by concatenating the arrays along 'dimension'.
   pub fn infer_concat_op_shape(
-    _arg_shapes: &Vec<Shape>,
-    _dimension: i64) -> Result<Shape, String>
+    arg_shapes: &Vec<Shape>,
+    dimension: i64) -> Result<Shape, String>
   {
-    unimplemented!()    
+    if arg_shapes.is_empty() {
+      return Err("Concatenate expects at least one argument.".to_string());
+    }
+    for shape in arg_shapes {
+      expect_array(shape, "operand of concatenation")?;
+    }
+    if dimension < 0 || dimension as usize >= arg_shapes[0].rank() {
+      return Err(format!("Concatenate dimension out of bounds: {}.", dimension));
+    }
+    let d = dimension as usize;
+    let first = &arg_shapes[0];
+    let mut element_type = first.element_type();
+    let mut dimensions = first.dimensions_vec().clone();
+    let mut is_dynamic = first.dinamic_dimensions().clone();
+    for shape in arg_shapes.iter().skip(1) {
+      if shape.rank() != first.rank() {
+        return Err(format!(
+          "Cannot concatenate arrays with different ranks: {} ({}) vs {} ({}).",
+          first.rank(), ShapeUtil::human_string(first), shape.rank(),
+          ShapeUtil::human_string(shape)));
+      }
+      if !ShapeUtil::same_element_type_ignoring_fp_precision(first, shape) {
+        return Err(format!(
+          "Cannot concatenate arrays with different element types: {} vs {}.",
+          lowercase_primitive_type_name(&first.element_type()),
+          lowercase_primitive_type_name(&shape.element_type())));
+      }
+      for i in 0..first.rank() {
+        if i == d {
+          continue;
+        }
+        match most_specific_dimension(dimensions[i], is_dynamic[i],
+          shape.dimensions(i), shape.is_dynamic_dimension(i as i64))
+        {
+          Some((size, dynamic)) => {
+            dimensions[i] = size;
+            is_dynamic[i] = dynamic;
+          },
+          None => return Err(format!(
+            "Cannot concatenate arrays that differ in dimensions other than the one being concatenated. Dimension {} in both shapes must be equal (or compatible): {} vs {}.",
+            i, ShapeUtil::human_string(first), ShapeUtil::human_string(shape)))
+        }
+      }
+      if dimensions[d] == Shape::UNBOUNDED_SIZE || shape.is_unbounded_dynamic_dimension(d) {
+        dimensions[d] = Shape::UNBOUNDED_SIZE;
+      } else {
+        dimensions[d] += shape.dimensions(d);
+      }
+      is_dynamic[d] |= shape.is_dynamic_dimension(d as i64);
+      element_type = ShapeUtil::higher_precision_element_type(
+        &ShapeUtil::make_scalar_shape(&element_type), shape);
+    }
+    Ok(ShapeUtil::make_shape_dynamic(&element_type, dimensions, is_dynamic))
   }
 
+  // Infers the shape produced by a kConvert operation.
   pub fn infer_convert_shape(
-    _operand_shape: &Shape,
-    _new_element_t: &PrimitiveType) -> Result<Shape, String>
+    operand_shape: &Shape,
+    new_element_t: &PrimitiveType) -> Result<Shape, String>
   {
-    unimplemented!()    
+    if !operand_shape.is_array() || !is_array_type(new_element_t) {
+      // Note: we may want to support tuple conversions via this operation in
+      // the future, by recursing into the tuple elements to check all
+      // sub-conversions are valid. For now we just reject them, though.
+      return Err(format!("Convert does not allow non-arrays, so cannot convert from {} to {}.",
+        ShapeUtil::human_string(operand_shape), lowercase_primitive_type_name(new_element_t)));
+    }
+    Ok(ShapeUtil::change_element_type(operand_shape, new_element_t))
   }
 
+  // Infers the shape produced by a kBitcastConvert operation.
   pub fn infer_bitcast_convert_shape(
-    _operand_shape: &Shape,
-    _new_element_t: &PrimitiveType) -> Result<Shape, String>
+    operand_shape: &Shape,
+    new_element_t: &PrimitiveType) -> Result<Shape, String>
   {
-    unimplemented!()    
+    let old_element_t = operand_shape.element_type();
+    if is_complex_type(&old_element_t) != is_complex_type(new_element_t) {
+      return Err(format!("Conversion between complex and real type {} => {}.",
+        ShapeUtil::human_string(operand_shape), lowercase_primitive_type_name(new_element_t)));
+    }
+    if !operand_shape.is_array() || !is_array_type(new_element_t) {
+      return Err(format!(
+        "Cannot convert from or to tuple type; requested conversion: {} => {}.",
+        ShapeUtil::human_string(operand_shape), lowercase_primitive_type_name(new_element_t)));
+    }
+    let input_bitwidth = bit_width(&old_element_t);
+    let output_bitwidth = bit_width(new_element_t);
+    if input_bitwidth == output_bitwidth {
+      return Ok(ShapeUtil::change_element_type(operand_shape, new_element_t));
+    }
+    let mut new_shape = ShapeUtil::change_element_type(operand_shape, new_element_t);
+    if input_bitwidth > output_bitwidth {
+      ShapeUtil::append_minor_dimension(input_bitwidth / output_bitwidth, &mut new_shape);
+      return Ok(new_shape);
+    }
+    let ratio = output_bitwidth / input_bitwidth;
+    if operand_shape.rank() == 0 {
+      return Err(format!(
+        "Cannot bitcast-convert a scalar {} to the wider type {}.",
+        ShapeUtil::human_string(operand_shape), lowercase_primitive_type_name(new_element_t)));
+    }
+    let last_dimension = operand_shape.dimensions(operand_shape.rank() - 1);
+    if last_dimension != ratio {
+      return Err(format!(
+        "Last dimension of input shape={} is not equal to ratio of bit-widths={} for bitcast-convert from {} to {}.",
+        last_dimension, ratio, ShapeUtil::human_string(operand_shape),
+        lowercase_primitive_type_name(new_element_t)));
+    }
+    new_shape.delete_dimension(operand_shape.rank() as i64 - 1);
+    Ok(new_shape)
   }
 
+  // Infers the shape produced by a stochastic convert operation.
   pub fn infer_stochastic_convert_shape(
-    _operand_shape: &Shape,
-    _random_shape: &Shape,
-    _new_element_t: &PrimitiveType) -> Result<Shape, String>
+    operand_shape: &Shape,
+    random_shape: &Shape,
+    new_element_t: &PrimitiveType) -> Result<Shape, String>
   {
-    unimplemented!()    
+    expect_array(operand_shape, "lhs of stochastic convert")?;
+    expect_array(random_shape, "rhs of stochastic convert")?;
+    if !ShapeUtil::element_is_floating(operand_shape) {
+      return Err(format!(
+        "The input of stochastic convert must be a floating point type; got {}.",
+        ShapeUtil::human_string(operand_shape)));
+    }
+    if !ShapeUtil::element_is_integral(&ShapeUtil::change_element_type(
+      &ShapeUtil::make_scalar_shape(new_element_t), new_element_t))
+    {
+      return Err(format!(
+        "The output of stochastic convert must be an integral type; got {}.",
+        lowercase_primitive_type_name(new_element_t)));
+    }
+    if !is_unsigned_integral_type(&random_shape.element_type()) ||
+      bit_width(&random_shape.element_type()) != bit_width(&operand_shape.element_type())
+    {
+      return Err(format!(
+        "The random number is required to be an unsigned integer with the same bit width as the input; got {} and {}.",
+        ShapeUtil::human_string(random_shape), ShapeUtil::human_string(operand_shape)));
+    }
+    if !compatible_dimensions(operand_shape, random_shape) {
+      return Err(format!(
+        "The random number is required to have the same dimensions as the input; got {} and {}.",
+        ShapeUtil::human_string(random_shape), ShapeUtil::human_string(operand_shape)));
+    }
+    Ok(ShapeUtil::change_element_type(operand_shape, new_element_t))
   }
 
+  // Infers the shape produced by a kFft operation of the given type.
   pub fn infer_fft_shape(
-    _input: &Shape,
-    _fft_t: &FftType,
-    _fft_length: &Vec<i64>) -> Result<Shape, String>
+    input: &Shape,
+    fft_t: &FftType,
+    fft_length: &Vec<i64>) -> Result<Shape, String>
   {
-    unimplemented!()    
+    let fft_rank = fft_length.len();
+    let type_name = format!("{:?}", fft_t);
+    if !(1..=3).contains(&fft_rank) {
+      return Err(format!("{} only supports ranks 1-3; got {}.", type_name, fft_rank));
+    }
+    expect_array(input, "input of fft")?;
+    if input.rank() < fft_rank {
+      return Err(format!("{} requires input of at least same rank as fft_length; got {}.",
+        type_name, ShapeUtil::human_string(input)));
+    }
+    let check_innermost = |input: &Shape, count: usize| -> Result<(), String> {
+      for i in 0..count {
+        let dim = input.rank() - fft_rank + i;
+        if !compatible_dimension_sizes(input.dimensions(dim), fft_length[i]) {
+          return Err(format!(
+            "{} requires innermost dimensions match fft_length but dimension {} is {} and should be {}.",
+            type_name, dim, input.dimensions(dim), fft_length[i]));
+        }
+      }
+      Ok(())
+    };
+    match fft_t {
+      FftType::FFT | FftType::IFFT => {
+        if !is_complex_type(&input.element_type()) {
+          return Err(format!("{} requires complex input type, found {}.",
+            type_name, lowercase_primitive_type_name(&input.element_type())));
+        }
+        check_innermost(input, fft_rank)?;
+        Ok(input.clone())
+      },
+      FftType::RFFT => {
+        let output_type = match input.element_type() {
+          PrimitiveType::F32 => PrimitiveType::C64,
+          PrimitiveType::F64 => PrimitiveType::C128,
+          t => return Err(format!("{} requires f32 or f64 input type, found {}.",
+            type_name, lowercase_primitive_type_name(&t)))
+        };
+        check_innermost(input, fft_rank)?;
+        let mut result = ShapeUtil::change_element_type(input, &output_type);
+        let last = fft_length[fft_rank - 1];
+        if last != 0 {
+          result.set_dimensions(input.rank() - 1, last / 2 + 1);
+        }
+        Ok(result)
+      },
+      FftType::IRFFT => {
+        if !is_complex_type(&input.element_type()) {
+          return Err(format!("{} requires complex input type, found {}.",
+            type_name, lowercase_primitive_type_name(&input.element_type())));
+        }
+        check_innermost(input, fft_rank - 1)?;
+        let last = fft_length[fft_rank - 1];
+        let last_dim = input.rank() - 1;
+        if !compatible_dimension_sizes(input.dimensions(last_dim), last / 2 + 1) {
+          return Err(format!(
+            "{} requires non-final dimensions match fft_length, and the final dimension to be fft_length / 2 + 1, but dimension {} is {} and should be {}.",
+            type_name, last_dim, input.dimensions(last_dim), last / 2 + 1));
+        }
+        let mut result = ShapeUtil::change_element_type(
+          input, &complex_component_type(input.element_type()));
+        result.set_dimensions(last_dim, last);
+        Ok(result)
+      }
+    }
   }
 
+  // Infers the shape produced by a triangular solve operation.
   pub fn infer_triangular_solve_shape(
-    _a: &Shape,
-    _b: &Shape,
-    _options: &TriangularSolveOptions) -> Result<Shape, String>
+    a: &Shape,
+    b: &Shape,
+    options: &TriangularSolveOptions) -> Result<Shape, String>
   {
-    unimplemented!()    
+    if (!ShapeUtil::element_is_floating(a) && !ShapeUtil::element_is_complex(a)) ||
+      a.element_type() != b.element_type()
+    {
+      return Err(format!(
+        "Expected element types in shape to be floating or complex and identical for TriangularSolve; got {} and {}.",
+        lowercase_primitive_type_name(&a.element_type()),
+        lowercase_primitive_type_name(&b.element_type())));
+    }
+    if a.rank() < 2 {
+      return Err(format!(
+        "The 'a' argument to TriangularSolve must have rank >= 2, got shape {}.",
+        ShapeUtil::human_string(a)));
+    }
+    if b.rank() != a.rank() {
+      return Err(format!("Arguments to triangular solve must have equal rank; got {} and {}.",
+        ShapeUtil::human_string(b), ShapeUtil::human_string(a)));
+    }
+    let rank = a.rank();
+    if !compatible_dimension_sizes(a.dimensions(rank - 2), a.dimensions(rank - 1)) {
+      return Err(format!("The two minor dimensions of 'a' must have equal size, got {}.",
+        ShapeUtil::human_string(a)));
+    }
+    let b_dim = if options.left_side() { rank - 2 } else { rank - 1 };
+    if !compatible_dimension_sizes(a.dimensions(rank - 1), b.dimensions(b_dim)) {
+      return Err(format!("The shared dimension of 'a' and 'b' does not match, got shapes {} and {}.",
+        ShapeUtil::human_string(a), ShapeUtil::human_string(b)));
+    }
+    for i in 0..rank - 2 {
+      if !compatible_dimension_sizes(a.dimensions(i), b.dimensions(i)) {
+        return Err(format!(
+          "The leading batch dimensions of the arguments to triangular solve must be equal; got {} and {}.",
+          ShapeUtil::human_string(b), ShapeUtil::human_string(a)));
+      }
+    }
+    if options.transpose_a() == Transpose::Invalid {
+      return Err("Invalid transpose option value for triangular solve.".to_string());
+    }
+    Ok(b.clone())
   }
 
-  pub fn infer_cholesky_shape(_a: &Shape) -> Result<Shape, String> {
-    unimplemented!()
+  // Infers the shape produced by a Cholesky decomposition.
+  pub fn infer_cholesky_shape(a: &Shape) -> Result<Shape, String> {
+    if !ShapeUtil::element_is_floating(a) && !ShapeUtil::element_is_complex(a) {
+      return Err(format!(
+        "Expected element type in shape to be floating or complex for Cholesky; got {}.",
+        lowercase_primitive_type_name(&a.element_type())));
+    }
+    if a.rank() < 2 {
+      return Err(format!("The 'a' argument to Cholesky must have rank >= 2, got shape {}.",
+        ShapeUtil::human_string(a)));
+    }
+    if !compatible_dimension_sizes(a.dimensions(a.rank() - 2), a.dimensions(a.rank() - 1)) {
+      return Err(format!("The two minor dimensions of 'a' must have equal size, got {}.",
+        ShapeUtil::human_string(a)));
+    }
+    Ok(a.clone())
   }
 
+  // Infers the shape produced by an all-gather with the given dimension and
+  // shard count.
   pub fn infer_all_gather_shape(
-    _operand_shapes: &Vec<Shape>,
-    _all_gather_dimension: i64,
-    _shard_count: i64) -> Result<Shape, String>
+    operand_shapes: &Vec<Shape>,
+    all_gather_dimension: i64,
+    shard_count: i64) -> Result<Shape, String>
+  {
+    if all_gather_dimension < 0 {
+      return Err(format!("All-gather dimension must be non-negative; got {}.",
+        all_gather_dimension));
+    }
+    if shard_count <= 0 {
+      return Err(format!("Shard count must be positive; got {}.", shard_count));
+    }
+    let mut output_shapes = vec![];
+    for operand_shape in operand_shapes {
+      expect_array(operand_shape, "operand of all-gather")?;
+      if all_gather_dimension as usize >= operand_shape.rank() {
+        return Err(format!("All-gather dimension {} is out of bounds for operand of shape {}.",
+          all_gather_dimension, ShapeUtil::human_string(operand_shape)));
+      }
+      let mut output_shape = operand_shape.clone();
+      let d = all_gather_dimension as usize;
+      if !operand_shape.is_unbounded_dynamic_dimension(d) {
+        output_shape.set_dimensions(d, shard_count * operand_shape.dimensions(d));
+      }
+      output_shapes.push(output_shape);
+    }
+    Ok(ShapeUtil::make_maybe_tuple_shape(output_shapes))
+  }
+
+  // Infers the shape produced by an all-gather-start: the operands and the
+  // outputs of the all-gather.
+  pub fn infer_all_gather_start_shape(
+    operand_shapes: &Vec<Shape>,
+    all_gather_dimension: i64,
+    shard_count: i64) -> Result<Shape, String>
   {
-    unimplemented!()    
+    let ag_shape = ShapeInference::infer_all_gather_shape(
+      operand_shapes, all_gather_dimension, shard_count)?;
+    let input_shape = ShapeUtil::make_maybe_tuple_shape(operand_shapes.clone());
+    Ok(ShapeUtil::make_tuple_shape(vec![input_shape, ag_shape]))
   }
 
-  pub fn infer_all_gether_done_shape(_all_gather_start_shape: &Shape) -> Result<Shape, String> {
-    unimplemented!()
+  // Infers the shape produced by an all-gather-done from the shape of the
+  // all-gather-start.
+  pub fn infer_all_gether_done_shape(all_gather_start_shape: &Shape) -> Result<Shape, String> {
+    if !all_gather_start_shape.is_tuple() || all_gather_start_shape.tuple_shapes_size() != 2 {
+      return Err(format!("All-gather-done expects a 2-tuple operand; got {}.",
+        ShapeUtil::human_string(all_gather_start_shape)));
+    }
+    Ok(all_gather_start_shape.tuple_shapes(1).clone())
   }
 
-  pub fn infer_all_reduce_shape(_operand_shapes: &Vec<Shape>) -> Result<Shape, String> {
-    unimplemented!()
+  // Infers the shape produced by a cross replica sum with the given operand
+  // shapes.
+  pub fn infer_all_reduce_shape(operand_shapes: &Vec<Shape>) -> Result<Shape, String> {
+    for operand_shape in operand_shapes {
+      expect_array(operand_shape, "operand of cross replica sum")?;
+    }
+    Ok(ShapeUtil::make_maybe_tuple_shape(operand_shapes.clone()))
   }
 
+  // Infers the shape produced by a reduce-scatter with the given dimension
+  // and shard count.
   pub fn infer_reduce_scatter_shape(
-    _operand_shapes: &Vec<Shape>,
-    _scatter_dimension: i64,
-    _shard_count: i64) -> Result<Shape, String>
+    operand_shapes: &Vec<Shape>,
+    scatter_dimension: i64,
+    shard_count: i64) -> Result<Shape, String>
   {
-    unimplemented!()    
+    if scatter_dimension < 0 {
+      return Err(format!("Scatter dimension must be non-negative; got {}.", scatter_dimension));
+    }
+    if shard_count <= 0 {
+      return Err(format!("Shard count must be positive; got {}.", shard_count));
+    }
+    let mut output_shapes = vec![];
+    for operand_shape in operand_shapes {
+      expect_array(operand_shape, "operand of reduce-scatter")?;
+      if scatter_dimension as usize >= operand_shape.rank() {
+        return Err(format!("Scatter dimension {} is out of bounds for operand of shape {}.",
+          scatter_dimension, ShapeUtil::human_string(operand_shape)));
+      }
+      let d = scatter_dimension as usize;
+      let mut output_shape = operand_shape.clone();
+      if !operand_shape.is_unbounded_dynamic_dimension(d) {
+        let scatter_dim_input_size = operand_shape.dimensions(d);
+        if scatter_dim_input_size % shard_count != 0 {
+          return Err(format!(
+            "ReduceScatter operand scatter dimension size {} must be dividable by shard_count {}.",
+            scatter_dim_input_size, shard_count));
+        }
+        output_shape.set_dimensions(d, scatter_dim_input_size / shard_count);
+      }
+      output_shapes.push(output_shape);
+    }
+    Ok(ShapeUtil::make_maybe_tuple_shape(output_shapes))
   }
 
-  pub fn infer_all_reduce_done_shape(_operand_shape: &Shape) -> Result<Shape, String> {
-    unimplemented!()
+  // Infers the final shape of an all-reduce-done from the operand shape.
+  pub fn infer_all_reduce_done_shape(operand_shape: &Shape) -> Result<Shape, String> {
+    // The returned value from AllReduceDone is the operand forwarded.
+    Ok(operand_shape.clone())
   }
 
-  pub fn infer_collective_permute_shape(
-    _operand_shapes: &Vec<Shape>,
-    _inplace: bool) -> Result<Shape, String>
+  // Infers the shape of an all-to-all that splits 'split_dimension' into
+  // 'split_count' pieces and concatenates them along 'concat_dimension'.
+  pub fn infer_all_to_all_shape(
+    shape: &Shape,
+    split_dimension: i64,
+    concat_dimension: i64,
+    split_count: i64) -> Result<Shape, String>
   {
-    unimplemented!()
+    expect_array(shape, "operand of all-to-all")?;
+    if split_count <= 0 {
+      return Err(format!("AllToAll split_count must be positive; got {}.", split_count));
+    }
+    if split_dimension < 0 || split_dimension as usize >= shape.rank() {
+      return Err(format!("AllToAll split_dimension {} is out-of-bounds in shape {}.",
+        split_dimension, ShapeUtil::human_string(shape)));
+    }
+    if concat_dimension < 0 || concat_dimension as usize >= shape.rank() {
+      return Err(format!("AllToAll concat_dimension {} is out-of-bounds in shape {}.",
+        concat_dimension, ShapeUtil::human_string(shape)));
+    }
+    let split = split_dimension as usize;
+    let concat = concat_dimension as usize;
+    let mut result = shape.clone();
+    if !shape.is_unbounded_dynamic_dimension(split) {
+      if shape.dimensions(split) % split_count != 0 {
+        return Err(format!(
+          "AllToAll split dimension size {} must be dividable by split_count {}.",
+          shape.dimensions(split), split_count));
+      }
+      result.set_dimensions(split, shape.dimensions(split) / split_count);
+    }
+    if !shape.is_unbounded_dynamic_dimension(concat) {
+      result.set_dimensions(concat, result.dimensions(concat) * split_count);
+    }
+    Ok(result)
   }
 
-  pub fn infer_collective_permute_done_shape(
-    _operand_shape: &Shape) -> Result<Shape, String>
-  {
-    unimplemented!()
+  // Infers the shape of an all-to-all over a tuple of arrays.
+  pub fn infer_all_to_all_tuple_shape(operand_shapes: &Vec<Shape>) -> Result<Shape, String> {
+    // An Alltoall HLO instruction receives N operands (with the same shape)
+    // and returns a tuple that contains N array shapes.
+    for operand_shape in operand_shapes {
+      expect_array(operand_shape, "operand of all-to-all")?;
+      if !compatible_dimensions(operand_shape, &operand_shapes[0]) ||
+        operand_shape.element_type() != operand_shapes[0].element_type()
+      {
+        return Err(format!(
+          "HLO all-to-all has operands with different shapes: {} vs {}.",
+          ShapeUtil::human_string(&operand_shapes[0]), ShapeUtil::human_string(operand_shape)));
+      }
+    }
+    Ok(ShapeUtil::make_tuple_shape(operand_shapes.clone()))
   }
 
-  pub fn infer_collective_broadcast_shape(
-    _operand_shapes: &Vec<Shape>) -> Result<Shape, String>
-  {
-    unimplemented!()
+  // Infers the shape of a ragged all-to-all from its operands: input, output,
+  // input offsets, send sizes, output offsets and receive sizes.
+  pub fn infer_ragged_all_to_all_shape(operand_shapes: &Vec<Shape>) -> Result<Shape, String> {
+    if operand_shapes.len() != 6 {
+      return Err(format!("RaggedAllToAll expects 6 operands; got {}.", operand_shapes.len()));
+    }
+    for (i, operand_shape) in operand_shapes.iter().enumerate() {
+      expect_array(operand_shape, "operand of ragged-all-to-all")?;
+      if i >= 2 && !ShapeUtil::element_is_integral(operand_shape) {
+        return Err(format!(
+          "RaggedAllToAll operand {} must be an integral array; got {}.",
+          i, ShapeUtil::human_string(operand_shape)));
+      }
+    }
+    Ok(operand_shapes[1].clone())
   }
 
-  pub fn infer_reduce_precision_shape(
-    _operand_shape: &Shape,
-    _operand_bits: i64,
-    _mantissa_bits: i64) -> Result<Shape, String>
+  // Infers the shape of a collective broadcast.
+  pub fn infer_collective_broadcast_shape(
+    operand_shapes: &Vec<Shape>) -> Result<Shape, String>
   {
-    unimplemented!()    
+    for operand_shape in operand_shapes {
+      expect_array(operand_shape, "operand of collective-broadcast")?;
+    }
+    Ok(ShapeUtil::make_maybe_tuple_shape(operand_shapes.clone()))
   }
 
-  pub fn infer_reverse_shape(
-    _operand_shape: &Shape,
-    _dimensions: &Vec<i64>) -> Result<Shape, String>
+  // Infers the shape of a collective permute. An in-place collective permute
+  // takes the input, the output buffer and the input and output offsets.
+  pub fn infer_collective_permute_shape(
+    operand_shapes: &Vec<Shape>,
+    inplace: bool) -> Result<Shape, String>
   {
-    unimplemented!()    
+    if !inplace {
+      for operand_shape in operand_shapes {
+        expect_array(operand_shape, "operand of collective-permute")?;
+      }
+      return Ok(ShapeUtil::make_maybe_tuple_shape(operand_shapes.clone()));
+    }
+    if operand_shapes.len() != 4 {
+      return Err(format!("In-place collective-permute expects 4 operands; got {}.",
+        operand_shapes.len()));
+    }
+    Ok(operand_shapes[1].clone())
   }
 
-  pub fn infer_topk_shape(_operand_shape: &Shape, _k: i64) -> Result<Shape, String> {
-    unimplemented!()
+  // Infers the shape of a collective-permute-start: the input, the output and
+  // the given context shapes.
+  pub fn infer_collective_permute_start_shape(
+    operand_shapes: &Vec<Shape>,
+    context_shapes: &Vec<Shape>,
+    inplace: bool) -> Result<Shape, String>
+  {
+    let mut shapes = vec![];
+    if !inplace {
+      for operand_shape in operand_shapes {
+        expect_array(operand_shape, "operand of collective-permute-start")?;
+      }
+      let input = ShapeUtil::make_maybe_tuple_shape(operand_shapes.clone());
+      shapes.push(input.clone());
+      shapes.push(input);
+    } else {
+      if operand_shapes.len() != 4 {
+        return Err(format!("In-place collective-permute-start expects 4 operands; got {}.",
+          operand_shapes.len()));
+      }
+      shapes.push(operand_shapes[0].clone());
+      shapes.push(operand_shapes[1].clone());
+    }
+    shapes.extend(context_shapes.iter().cloned());
+    Ok(ShapeUtil::make_tuple_shape(shapes))
   }
 
-  pub fn infer_get_tuple_element_shape(_arg: &Shape, _index: i64) -> Result<Shape, String> {
-    unimplemented!()
+  // Infers the shape of a collective-permute-done from the shape of the
+  // collective-permute-start.
+  pub fn infer_collective_permute_done_shape(
+    operand_shape: &Shape) -> Result<Shape, String>
+  {
+    if !operand_shape.is_tuple() || operand_shape.tuple_shapes_size() < 2 {
+      return Err(format!("Collective-permute-done expects a tuple operand; got {}.",
+        ShapeUtil::human_string(operand_shape)));
+    }
+    Ok(operand_shape.tuple_shapes(1).clone())
   }
 
-  pub fn infer_tranpose_shape(
-    _operand: &Shape,
-    _dimensions: &Vec<i64>) -> Result<Shape, String>
+  // Infers the shape produced by a reduce-precision operation.
+  pub fn infer_reduce_precision_shape(
+    operand_shape: &Shape,
+    exponent_bits: i64,
+    mantissa_bits: i64) -> Result<Shape, String>
   {
-    unimplemented!()
+    if !ShapeUtil::element_is_floating(operand_shape) {
+      return Err(format!(
+        "Expected element type in shape to be floating point for ReducePrecision operation; got {}.",
+        lowercase_primitive_type_name(&operand_shape.element_type())));
+    }
+    if exponent_bits < 1 {
+      // One exponent bit is necessary to distinguish 0 from infinity. Having
+      // no exponent bits doesn't produce a sensible number, so we require at
+      // least one.
+      return Err(format!("Expected exponent_bits >= 1; got {}.", exponent_bits));
+    }
+    if mantissa_bits < 0 {
+      // A number with no mantissa bits is still meaningful, however.
+      return Err(format!("Expected non-negative mantissa_bits; got {}.", mantissa_bits));
+    }
+    Ok(operand_shape.clone())
   }
 
+  // Infers the shape produced by a reverse operation that reverses the order
+  // of the elements in the given dimensions.
+  pub fn infer_reverse_shape(
+    operand_shape: &Shape,
+    dimensions: &Vec<i64>) -> Result<Shape, String>
+  {
+    expect_array(operand_shape, "operand of reverse")?;
+    if has_duplicates(dimensions) {
+      return Err(format!("Reverse dimensions must be unique; got {}.", vector_string(dimensions)));
+    }
+    for dimension in dimensions {
+      if *dimension >= operand_shape.rank() as i64 || *dimension < 0 {
+        return Err(format!("One of the reverse dimensions ({}) is out-of-bounds in shape {}.",
+          dimension, ShapeUtil::human_string(operand_shape)));
+      }
+    }
+    Ok(operand_shape.clone())
+  }
+
+  // Infers the shape produced by a top-k operation: the k largest values of
+  // the last dimension and their indices.
+  pub fn infer_topk_shape(operand_shape: &Shape, k: i64) -> Result<Shape, String> {
+    expect_array(operand_shape, "operand of top-k")?;
+    if operand_shape.rank() < 1 {
+      return Err(format!("The operand of top-k must have rank >= 1; got {}.",
+        ShapeUtil::human_string(operand_shape)));
+    }
+    let last_dim = operand_shape.rank() - 1;
+    if k < 0 {
+      return Err(format!("k={} must be non-negative.", k));
+    }
+    if !operand_shape.is_unbounded_dynamic_dimension(last_dim) &&
+      k > operand_shape.dimensions(last_dim)
+    {
+      return Err(format!("k={} is larger than the last dimension of size={}.",
+        k, operand_shape.dimensions(last_dim)));
+    }
+    let mut out = operand_shape.clone();
+    out.set_dimensions(last_dim, k);
+    out.set_dynamic_dimension(last_dim, false);
+    let idxs_shape = ShapeUtil::change_element_type(&out, &PrimitiveType::S32);
+    Ok(ShapeUtil::make_tuple_shape(vec![out, idxs_shape]))
+  }
+
+  // Infers the shape produced by a get-tuple-element operation.
+  pub fn infer_get_tuple_element_shape(arg: &Shape, index: i64) -> Result<Shape, String> {
+    if !arg.is_tuple() {
+      return Err(format!("Cannot infer shape: attempting to index into non-tuple: {}.",
+        ShapeUtil::human_string(arg)));
+    }
+    if index < 0 || index as usize >= arg.tuple_shapes_size() {
+      return Err(format!(
+        "Cannot infer shape: attempt to index out of tuple bounds: {} >= {} in shape {}.",
+        index, arg.tuple_shapes_size(), ShapeUtil::human_string(arg)));
+    }
+    Ok(arg.tuple_shapes(index as usize).clone())
+  }
+
+  // Infers the shape produced by a transpose operation: result dimension i is
+  // operand dimension dimensions[i].
+  pub fn infer_tranpose_shape(
+    operand: &Shape,
+    dimensions: &Vec<i64>) -> Result<Shape, String>
+  {
+    expect_array(operand, "transpose")?;
+    if dimensions.len() != operand.rank() || !is_permutation(dimensions) {
+      return Err(format!(
+        "Transpose dimensions {} are not a permutation of the operand dimensions (operand shape is {}).",
+        vector_string(dimensions), ShapeUtil::human_string(operand)));
+    }
+    let permuted_dimensions = dimensions.iter()
+      .map(|d| operand.dimensions(*d as usize)).collect();
+    let permuted_is_dynamic = dimensions.iter()
+      .map(|d| operand.is_dynamic_dimension(*d)).collect();
+    Ok(ShapeUtil::make_shape_dynamic(
+      &operand.element_type(), permuted_dimensions, permuted_is_dynamic))
+  }
+
+  // Infers the shape produced by a static slice operation.
   pub fn infer_slice_shape(
-    _arg: &Shape,
-    _starts: &Vec<i64>,
-    _limits: &Vec<i64>,
-    _strides: &Vec<i64>) -> Result<Shape, String>
+    arg: &Shape,
+    starts: &Vec<i64>,
+    limits: &Vec<i64>,
+    strides: &Vec<i64>) -> Result<Shape, String>
+  {
+    let error = |message: String| -> String {
+      format!("{} in slice operation; argument shape: {}; starts: {}; limits: {}; strides: {}.",
+        message, ShapeUtil::human_string(arg), vector_string(starts), vector_string(limits),
+        vector_string(strides))
+    };
+    expect_array(arg, "operand of slice")?;
+    if starts.len() != limits.len() {
+      return Err(error(format!("Slice start and limit sizes differ: {} vs {}",
+        starts.len(), limits.len())));
+    }
+    if starts.len() != strides.len() {
+      return Err(error(format!("Slice start and strides sizes differ: {} vs {}",
+        starts.len(), strides.len())));
+    }
+    if starts.len() != arg.rank() {
+      return Err(format!("Slice index count does not match argument rank: {} vs {}.",
+        starts.len(), arg.rank()));
+    }
+    let mut sizes = vec![];
+    let mut is_dynamic = vec![];
+    for dimension in 0..starts.len() {
+      let start_index = starts[dimension];
+      let limit_index = limits[dimension];
+      let stride = strides[dimension];
+      if start_index < 0 {
+        return Err(error(format!("Negative start index to slice: {}", start_index)));
+      }
+      let dimension_size = arg.dimensions(dimension);
+      if !arg.is_unbounded_dynamic_dimension(dimension) && limit_index > dimension_size {
+        return Err(error(format!(
+          "limit index ({}) must be less than or equal to dimension size ({})",
+          limit_index, dimension_size)));
+      }
+      if start_index > limit_index {
+        return Err(error(format!(
+          "limit index ({}) must be greater or equal to start index ({}) in slice with positive stride",
+          limit_index, start_index)));
+      }
+      if stride <= 0 {
+        return Err(format!("Stride ({}) must be positive.", stride));
+      }
+      sizes.push((limit_index - start_index + stride - 1) / stride);
+      // Slicing a whole bounded dynamic dimension keeps it dynamic.
+      is_dynamic.push(arg.is_bounded_dynamic_dimension(dimension as i64) &&
+        start_index == 0 && limit_index == dimension_size && stride == 1);
+    }
+    Ok(ShapeUtil::make_shape_dynamic(&arg.element_type(), sizes, is_dynamic))
+  }
+
+  // Checks the start indices of a dynamic slice or dynamic update slice of
+  // 'operand_shape'.
+  fn validate_start_indices(
+    op_name: &str,
+    operand_shape: &Shape,
+    start_index_shapes: &Vec<Shape>,
+    allow_scalar_indices: bool) -> Result<(), String>
   {
-    unimplemented!()    
+    let number_of_indices = start_index_shapes.len();
+    // TODO: Remove this path once the R1 index form is gone.
+    if !allow_scalar_indices || (number_of_indices >= 1 && start_index_shapes[0].rank() == 1) {
+      if number_of_indices != 1 {
+        return Err(format!("{} should have exactly 1 index operand, got {}.",
+          op_name, number_of_indices));
+      }
+      let start_indices_shape = &start_index_shapes[0];
+      expect_array(start_indices_shape, &format!("start indices of {}", op_name))?;
+      if start_indices_shape.rank() != 1 {
+        return Err(format!(
+          "{} start indices of rank {} must be rank1.", op_name, start_indices_shape.rank()));
+      }
+      if !ShapeUtil::element_is_integral(start_indices_shape) {
+        return Err(format!("{} start indices must be of integral type.", op_name));
+      }
+      let start_num_dims = start_indices_shape.dimensions(0);
+      if operand_shape.rank() as i64 != start_num_dims {
+        return Err(format!(
+          "{} start number of dimensions {} ({}) must match rank {} of slice input ({}).",
+          op_name, start_num_dims, ShapeUtil::human_string(start_indices_shape),
+          operand_shape.rank(), ShapeUtil::human_string(operand_shape)));
+      }
+      return Ok(());
+    }
+    if operand_shape.rank() != number_of_indices {
+      return Err(format!(
+        "{} arguments must have the same number of start indices as operand rank: {} vs {}.",
+        op_name, number_of_indices, operand_shape.rank()));
+    }
+    for (i, index_shape) in start_index_shapes.iter().enumerate() {
+      if !ShapeUtil::is_scalar(index_shape) {
+        return Err(format!("{} start index {} must be a scalar, but got {}.",
+          op_name, i, ShapeUtil::human_string(index_shape)));
+      }
+      if !ShapeUtil::element_is_integral(index_shape) {
+        return Err(format!("{} start indices must be of integral type.", op_name));
+      }
+      if index_shape.element_type() != start_index_shapes[0].element_type() {
+        return Err(format!(
+          "{} start indices must all have the same type, but got {} and {}.",
+          op_name, ShapeUtil::human_string(&start_index_shapes[0]),
+          ShapeUtil::human_string(index_shape)));
+      }
+    }
+    Ok(())
   }
 
   // Infers the shape produced by a dynamic slice operation of size specified
   // in 'slice_sizes', with dynamic start indices shape 'start_indices_shape'.
   pub fn infer_dynamic_slice_shape(
-    _operand_shape: &Shape,
-    _start_index_shapes: &Vec<Shape>,
-    _slice_sizes: &Vec<i64>,
-    _allow_scalar_indices: bool) -> Result<Shape, String>
+    operand_shape: &Shape,
+    start_index_shapes: &Vec<Shape>,
+    slice_sizes: &Vec<i64>,
+    allow_scalar_indices: bool) -> Result<Shape, String>
   {
-    unimplemented!()    
+    expect_array(operand_shape, "operand of dynamic slice")?;
+    ShapeInference::validate_start_indices(
+      "Dynamic slice", operand_shape, start_index_shapes, allow_scalar_indices)?;
+    if slice_sizes.len() != operand_shape.rank() {
+      return Err(format!("Dynamic slice index count does not match argument rank: {} vs {}.",
+        slice_sizes.len(), operand_shape.rank()));
+    }
+    let mut is_dynamic = vec![];
+    for dim in 0..slice_sizes.len() {
+      let input_dim_size = operand_shape.dimensions(dim);
+      let slice_dim_size = slice_sizes[dim];
+      if slice_dim_size < 0 {
+        return Err(format!("Negative size index to dynamic slice: {}.", slice_dim_size));
+      }
+      if !operand_shape.is_unbounded_dynamic_dimension(dim) && slice_dim_size > input_dim_size {
+        return Err(format!("Slice dim size {} greater than dynamic slice dimension: {}.",
+          slice_dim_size, input_dim_size));
+      }
+      // Slicing a whole bounded dynamic dimension keeps it dynamic.
+      is_dynamic.push(operand_shape.is_bounded_dynamic_dimension(dim as i64) &&
+        slice_dim_size == input_dim_size);
+    }
+    Ok(ShapeUtil::make_shape_dynamic(
+      &operand_shape.element_type(), slice_sizes.clone(), is_dynamic))
   }
 
+  // Infers the shape produced by a dynamic update slice operation.
   pub fn infer_dynamic_update_slice_shape(
-    _operand_shape: &Shape,
-    _update_shape: &Shape,
-    _start_index_shapes: &Vec<Shape>,
-    _allow_scalar_indices: bool) -> Result<Shape, String>
+    operand_shape: &Shape,
+    update_shape: &Shape,
+    start_index_shapes: &Vec<Shape>,
+    allow_scalar_indices: bool) -> Result<Shape, String>
   {
-    unimplemented!()    
+    expect_array(operand_shape, "operand of dynamic update slice")?;
+    expect_array(update_shape, "update of dynamic update slice")?;
+    ShapeInference::validate_start_indices(
+      "Dynamic update slice", operand_shape, start_index_shapes, allow_scalar_indices)?;
+    if update_shape.rank() != operand_shape.rank() {
+      return Err(format!(
+        "Dynamic update slice update rank does not match argument rank: {} vs {}.",
+        update_shape.rank(), operand_shape.rank()));
+    }
+    if !ShapeUtil::same_element_type_ignoring_fp_precision(operand_shape, update_shape) {
+      return Err(format!(
+        "Dynamic update slice update element type does not match argument. operand.element_type: {} vs update.element_type: {}.",
+        lowercase_primitive_type_name(&operand_shape.element_type()),
+        lowercase_primitive_type_name(&update_shape.element_type())));
+    }
+    for dim in 0..operand_shape.rank() {
+      let input_dim_size = operand_shape.dimensions(dim);
+      let update_dim_size = update_shape.dimensions(dim);
+      if update_shape.is_unbounded_dynamic_dimension(dim) {
+        continue;
+      }
+      if update_dim_size < 0 {
+        return Err(format!("Size index {} to dynamic update slice must be >= 0.",
+          update_dim_size));
+      }
+      if !operand_shape.is_unbounded_dynamic_dimension(dim) && update_dim_size > input_dim_size {
+        return Err(format!("Update dim size {} greater than dynamic slice dimension: {}.",
+          update_dim_size, input_dim_size));
+      }
+    }
+    Ok(operand_shape.clone())
   }
 
   // Infers the shape produced by a dynamic reshape operation from the element
@@ -348,7 +1765,7 @@ impl ShapeInference {
       return Err(err_msg);
     }
     if operand_shape.dimensions_vec().len() != padding_config.dimensions_size() {
-      let mut err_msg = "The rank of the operand and the padding 
+      let mut err_msg = "The rank of the operand and the padding
         configuration do not match: ".to_string();
       err_msg.push_str(&ShapeUtil::human_string(operand_shape));
       err_msg.push_str(" vs ");
@@ -373,7 +1790,7 @@ impl ShapeInference {
       let err_msg = "Dynamic padding value is not supported".to_string();
       return Err(err_msg);
     }
-    let mut dimensions = vec![];
+    let mut dimensions = vec![0; operand_shape.dimensions_vec().len()];
     let mut is_dunamic = vec![false; operand_shape.dimensions_vec().len()];
     for i in 0..operand_shape.dimensions_vec().len() {
       let p = padding_config.dimensions(i as i64);
@@ -400,156 +1817,1562 @@ impl ShapeInference {
       is_dunamic))
   }
 
+  // Checks the operand, scale and offset shapes shared by the batch norm
+  // operations and returns the size of the feature dimension.
+  fn validate_batch_norm_operands(
+    op_name: &str,
+    operand_shape: &Shape,
+    feature_index: i64,
+    feature_shapes: &Vec<(&str, &Shape)>) -> Result<i64, String>
+  {
+    expect_array(operand_shape, &format!("operand of {}", op_name))?;
+    ShapeUtil::validate_shape_with_optional_layout(operand_shape)?;
+    if feature_index < 0 || feature_index as usize >= operand_shape.rank() {
+      return Err(format!(
+        "Expected feature_index of {} to be a non-negative number less than the rank of operand, got feature_index {}, and rank {}.",
+        op_name, feature_index, operand_shape.rank()));
+    }
+    if operand_shape.rank() < 1 {
+      return Err(format!("Expected the rank of operand to {} to be at least 1; got {}.",
+        op_name, operand_shape.rank()));
+    }
+    if !ShapeUtil::element_is_floating(operand_shape) {
+      return Err(format!(
+        "The operand to {} must have a floating point element type, but the shape is {}.",
+        op_name, lowercase_primitive_type_name(&operand_shape.element_type())));
+    }
+    let feature_count = operand_shape.dimensions(feature_index as usize);
+    for (name, shape) in feature_shapes {
+      expect_array(shape, &format!("{} input of {}", name, op_name))?;
+      ShapeUtil::validate_shape_with_optional_layout(shape)?;
+      if shape.rank() != 1 {
+        return Err(format!("{} input of {} must have rank 1, but has rank {}.",
+          name, op_name, shape.rank()));
+      }
+      if !ShapeUtil::same_element_type_ignoring_fp_precision(shape, operand_shape) {
+        return Err(format!(
+          "The inputs should have the same element type for {}, but the shape of {} factor is {} and the shape of operand is {}.",
+          op_name, name, lowercase_primitive_type_name(&shape.element_type()),
+          lowercase_primitive_type_name(&operand_shape.element_type())));
+      }
+      if !compatible_dimension_sizes(shape.dimensions(0), feature_count) {
+        return Err(format!(
+          "The size of {} factor should be the same as feature count, but the size of {} factor is {} and the feature count is {}.",
+          name, name, shape.dimensions(0), feature_count));
+      }
+    }
+    Ok(feature_count)
+  }
+
+  // Infers the shape produced by batch norm training: the normalized operand
+  // and the batch mean and variance.
   pub fn infer_batch_norm_training_shape(
-    _operand_shape: &Shape,
-    _scale_shape: &Shape,
-    _offset_shape: &Shape,
-    _feature_index: i64) -> Result<Shape, String>
+    operand_shape: &Shape,
+    scale_shape: &Shape,
+    offset_shape: &Shape,
+    feature_index: i64) -> Result<Shape, String>
   {
-    unimplemented!()    
+    let feature_count = ShapeInference::validate_batch_norm_operands(
+      "BatchNormTraining", operand_shape, feature_index,
+      &vec![("scale", scale_shape), ("offset", offset_shape)])?;
+    let output_shape_for_mean_and_var = ShapeUtil::make_shape_dynamic(
+      &operand_shape.element_type(), vec![feature_count],
+      vec![operand_shape.is_dynamic_dimension(feature_index)]);
+    Ok(ShapeUtil::make_tuple_shape(vec![
+      operand_shape.clone(), output_shape_for_mean_and_var.clone(), output_shape_for_mean_and_var]))
   }
 
+  // Infers the shape produced by batch norm inference.
   pub fn infer_batch_norm_inference_shape(
-    _operand_shape: &Shape,
-    _scale_shape: &Shape,
-    _offset_shape: &Shape,
-    _mean_shape: &Shape,
-    _variance_shape: &Shape,
-    _feature_index: i64) -> Result<Shape, String>
+    operand_shape: &Shape,
+    scale_shape: &Shape,
+    offset_shape: &Shape,
+    mean_shape: &Shape,
+    variance_shape: &Shape,
+    feature_index: i64) -> Result<Shape, String>
   {
-    unimplemented!()    
+    ShapeInference::validate_batch_norm_operands(
+      "BatchNormInference", operand_shape, feature_index,
+      &vec![("scale", scale_shape), ("offset", offset_shape), ("mean", mean_shape),
+        ("variance", variance_shape)])?;
+    Ok(operand_shape.clone())
   }
 
+  // Infers the shape produced by batch norm grad: the gradients with respect
+  // to the operand, the scale and the offset.
   pub fn infer_batch_norm_grad_shape(
-    _operand_shape: &Shape,
-    _scale_shape: &Shape,
-    _mean_shape: &Shape,
-    _variance_shape: &Shape,
-    _output_grad_shape: &Shape,
-    _feature_index: i64) -> Result<Shape, String>
+    operand_shape: &Shape,
+    scale_shape: &Shape,
+    mean_shape: &Shape,
+    variance_shape: &Shape,
+    output_grad_shape: &Shape,
+    feature_index: i64) -> Result<Shape, String>
   {
-    unimplemented!()    
+    let feature_count = ShapeInference::validate_batch_norm_operands(
+      "BatchNormGrad", operand_shape, feature_index,
+      &vec![("scale", scale_shape), ("mean", mean_shape), ("variance", variance_shape)])?;
+    expect_array(output_grad_shape, "output_grad input of BatchNormGrad")?;
+    if !ShapeUtil::same_element_type_ignoring_fp_precision(output_grad_shape, operand_shape) ||
+      !compatible_dimensions(output_grad_shape, operand_shape)
+    {
+      return Err(format!(
+        "The shape of output_grad should be the same as the operand, but the shape of output_grad is {} and the shape of operand is {}.",
+        ShapeUtil::human_string(output_grad_shape), ShapeUtil::human_string(operand_shape)));
+    }
+    let feature_shape = ShapeUtil::make_shape_dynamic(
+      &operand_shape.element_type(), vec![feature_count],
+      vec![operand_shape.is_dynamic_dimension(feature_index)]);
+    Ok(ShapeUtil::make_tuple_shape(vec![
+      operand_shape.clone(), feature_shape.clone(), feature_shape]))
   }
 
+  // Infers the shape produced by a gather operation.
   pub fn infer_gather_shape(
-    _input_shape: &Shape,
-    _start_indices_shape: &Shape,
-    _gather_dim_numbers: &GatherDimensionNumbers,
-    _slice_sizes: &Vec<i64>) -> Result<Shape, String>
+    input_shape: &Shape,
+    start_indices_shape: &Shape,
+    gather_dim_numbers: &GatherDimensionNumbers,
+    slice_sizes: &Vec<i64>) -> Result<Shape, String>
   {
-    unimplemented!()    
+    expect_array(input_shape, "input tensor operand of gather op")?;
+    expect_array(start_indices_shape, "gather indices operand of gather op")?;
+    if !ShapeUtil::element_is_integral(start_indices_shape) {
+      return Err(format!("Gather indices parameter must be an integral tensor; got {}.",
+        ShapeUtil::human_string(start_indices_shape)));
+    }
+    let index_vector_dim = gather_dim_numbers.index_vector_dim();
+    // We implicitly reshape gather indices of shape P[A,B,C] to P[A,B,C,1] if
+    // index_vector_dim is rank(P). The bounds of this expanded shape is
+    // stored in expanded_start_indices_shape.
+    if index_vector_dim < 0 || index_vector_dim as usize > start_indices_shape.rank() {
+      return Err(format!(
+        "Gather index leaf dimension must be within [0, rank(start_indices) + 1). rank(start_indices) is {} and gather index leaf dimension is {}.",
+        start_indices_shape.rank(), index_vector_dim));
+    }
+    let mut expanded_start_indices_shape = start_indices_shape.dimensions_vec().clone();
+    let mut expanded_is_dynamic = start_indices_shape.dinamic_dimensions().clone();
+    if expanded_start_indices_shape.len() == index_vector_dim as usize {
+      expanded_start_indices_shape.push(1);
+      expanded_is_dynamic.push(false);
+    }
+    let ivd = index_vector_dim as usize;
+
+    // Validate the dimension numbers.
+    let offset_dims = gather_dim_numbers.offset_dims();
+    let collapsed_slice_dims = gather_dim_numbers.collapsed_slice_dims();
+    let start_index_map = gather_dim_numbers.start_index_map();
+    let operand_batching_dims = gather_dim_numbers.operand_batching_dims();
+    let start_indices_batching_dims = gather_dim_numbers.start_indices_batching_dims();
+    if !offset_dims.windows(2).all(|w| w[0] <= w[1]) {
+      return Err(format!("Output window dimensions in gather op must be ascending; got: {}.",
+        vector_string(offset_dims)));
+    }
+    if has_duplicates(offset_dims) {
+      return Err(format!("Output window dimensions in gather op must not repeat; got: {}.",
+        vector_string(offset_dims)));
+    }
+    let output_shape_rank = offset_dims.len() + expanded_start_indices_shape.len() - 1;
+    for offset_dim in offset_dims {
+      if *offset_dim < 0 || *offset_dim as usize >= output_shape_rank {
+        return Err(format!(
+          "Offset dimension {} in gather op is out of bounds; got {}, but should have been in [0,{}).",
+          offset_dim, offset_dim, output_shape_rank));
+      }
+    }
+    if start_index_map.len() as i64 != expanded_start_indices_shape[ivd] {
+      return Err(format!(
+        "Gather op has {} elements in start_index_map and the bound of dimension index_vector_dim={} of start_indices is {}. These two numbers must be equal.",
+        start_index_map.len(), index_vector_dim, expanded_start_indices_shape[ivd]));
+    }
+    for (i, operand_dim_for_start_index_i) in start_index_map.iter().enumerate() {
+      if *operand_dim_for_start_index_i < 0 ||
+        *operand_dim_for_start_index_i as usize >= input_shape.rank()
+      {
+        return Err(format!("Invalid start_index_map; domain is [0, {}), got: {}->{}.",
+          input_shape.rank(), i, operand_dim_for_start_index_i));
+      }
+    }
+    if has_duplicates(start_index_map) {
+      return Err(format!("Repeated dimensions are not allowed in start_index_map; got: {}.",
+        vector_string(start_index_map)));
+    }
+    validate_dimension_list(collapsed_slice_dims, input_shape.rank(), "gather",
+      "collapsed_slice_dims", true)?;
+    validate_dimension_list(operand_batching_dims, input_shape.rank(), "gather",
+      "operand_batching_dims", true)?;
+    if collapsed_slice_dims.iter().any(|d| operand_batching_dims.contains(d)) {
+      return Err(format!(
+        "collapsed_slice_dims and operand_batching_dims in gather op must be disjoint; got {} and {}.",
+        vector_string(collapsed_slice_dims), vector_string(operand_batching_dims)));
+    }
+    validate_dimension_list(start_indices_batching_dims, expanded_start_indices_shape.len(),
+      "gather", "start_indices_batching_dims", false)?;
+    if start_indices_batching_dims.contains(&index_vector_dim) {
+      return Err("Gather op cannot have the index vector dimension as the batching dimension.".to_string());
+    }
+    if operand_batching_dims.len() != start_indices_batching_dims.len() {
+      return Err(format!(
+        "operand_batching_dims and start_indices_batching_dims in gather op must be of the same size; got {} and {}.",
+        operand_batching_dims.len(), start_indices_batching_dims.len()));
+    }
+    for (operand_dim, indices_dim) in operand_batching_dims.iter().zip(start_indices_batching_dims) {
+      if !compatible_dimension_sizes(input_shape.dimensions(*operand_dim as usize),
+        expanded_start_indices_shape[*indices_dim as usize])
+      {
+        return Err(format!(
+          "Gather op has operand batching dimension {} of size {} and start indices batching dimension {} of size {}. These must be equal.",
+          operand_dim, input_shape.dimensions(*operand_dim as usize), indices_dim,
+          expanded_start_indices_shape[*indices_dim as usize]));
+      }
+    }
+
+    if slice_sizes.len() != input_shape.rank() {
+      return Err(format!(
+        "Gather op must have one slice size for every input dimension; got: len(slice_sizes)={}, input_shape.rank={}.",
+        slice_sizes.len(), input_shape.rank()));
+    }
+    if slice_sizes.len() !=
+      offset_dims.len() + collapsed_slice_dims.len() + operand_batching_dims.len()
+    {
+      return Err(format!(
+        "All components of the offset index in a gather op must either be a offset dimension or explicitly collapsed/batching; got len(slice_sizes)={}, output_slice_sizes={}, collapsed_slice_dims={}, operand_batching_dims={}.",
+        slice_sizes.len(), vector_string(offset_dims), vector_string(collapsed_slice_dims),
+        vector_string(operand_batching_dims)));
+    }
+    for (i, slice_size) in slice_sizes.iter().enumerate() {
+      let corresponding_input_size = input_shape.dimensions(i);
+      if *slice_size < 0 || (!input_shape.is_unbounded_dynamic_dimension(i) &&
+        *slice_size > corresponding_input_size)
+      {
+        return Err(format!(
+          "Slice size at index {} in gather op is out of range, must be within [0, {}), got {}.",
+          i, corresponding_input_size + 1, slice_size));
+      }
+    }
+    for (name, dims) in [("collapse", collapsed_slice_dims), ("batch", operand_batching_dims)] {
+      for (i, dim) in dims.iter().enumerate() {
+        if slice_sizes[*dim as usize] > 1 {
+          return Err(format!(
+            "Gather op can only {} slice dims with bound 1, but bound is {} for index {} at position {}.",
+            name, slice_sizes[*dim as usize], dim, i));
+        }
+      }
+    }
+
+    // The batch dimensions of the output come from the start indices, without
+    // the index vector dimension.
+    let mut batch_dims = vec![];
+    for (i, size) in expanded_start_indices_shape.iter().enumerate() {
+      if i != ivd {
+        batch_dims.push((*size, expanded_is_dynamic[i]));
+      }
+    }
+    let mut output_dim_bounds = vec![];
+    let mut output_dim_is_dynamic = vec![];
+    let mut offset_dims_seen = 0;
+    let mut gather_dims_seen = 0;
+    for i in 0..output_shape_rank {
+      if offset_dims.contains(&(i as i64)) {
+        while collapsed_slice_dims.contains(&(offset_dims_seen as i64)) ||
+          operand_batching_dims.contains(&(offset_dims_seen as i64))
+        {
+          offset_dims_seen += 1;
+        }
+        // Gathering an entire dynamic dimension creates a dynamic dimension.
+        output_dim_bounds.push(slice_sizes[offset_dims_seen]);
+        output_dim_is_dynamic.push(input_shape.is_dynamic_dimension(offset_dims_seen as i64) &&
+          slice_sizes[offset_dims_seen] == input_shape.dimensions(offset_dims_seen));
+        offset_dims_seen += 1;
+      } else {
+        output_dim_bounds.push(batch_dims[gather_dims_seen].0);
+        output_dim_is_dynamic.push(batch_dims[gather_dims_seen].1);
+        gather_dims_seen += 1;
+      }
+    }
+    Ok(ShapeUtil::make_shape_dynamic(
+      &input_shape.element_type(), output_dim_bounds, output_dim_is_dynamic))
   }
 
+  // Infers the shape produced by a get-dimension-size operation.
   pub fn infer_get_dimension_size_shape(
-    _shape: &Shape,
-    _dimension: i64) -> Result<Shape, String>
+    shape: &Shape,
+    dimension: i64) -> Result<Shape, String>
   {
-    unimplemented!()
+    if dimension < 0 || dimension as usize >= shape.rank() {
+      return Err(format!("GetDimensionSize dimension out of bounds: {}.", dimension));
+    }
+    // TODO: Remove this check once get-dimension-size supports 64-bit sizes.
+    if shape.dimensions(dimension as usize) > i32::MAX as i64 {
+      return Err(format!(
+        "GetDimensionSize's input shape is {}, the {}th dimension exceeds the INT_MAX limit.",
+        ShapeUtil::human_string(shape), dimension));
+    }
+    Ok(ShapeUtil::make_scalar_shape(&PrimitiveType::S32))
   }
 
+  // Infers the shape produced by a set-dimension-size operation.
   pub fn infer_set_dimension_size_shape(
-    _shape: &Shape,
-    _val_shape: &Shape,
-    _dimension: i64) -> Result<Shape, String>
+    shape: &Shape,
+    val_shape: &Shape,
+    dimension: i64) -> Result<Shape, String>
   {
-    unimplemented!()    
+    if dimension < 0 || dimension as usize >= shape.rank() {
+      return Err(format!("SetDimensionSize dimension out of bounds: {}.", dimension));
+    }
+    if val_shape.rank() != 0 || val_shape.element_type() != PrimitiveType::S32 {
+      return Err(format!("SetDimensionSize's value has to be S32 scalar, got {}.",
+        ShapeUtil::human_string(val_shape)));
+    }
+    // TODO: Remove this check once set-dimension-size supports 64-bit sizes.
+    if shape.dimensions(dimension as usize) > i32::MAX as i64 {
+      return Err(format!(
+        "SetDimensionSize's input shape is {}, the {}th dimension exceeds the INT_MAX limit.",
+        ShapeUtil::human_string(shape), dimension));
+    }
+    let mut result = shape.clone();
+    result.set_dynamic_dimension(dimension as usize, true);
+    Ok(result)
   }
 
+  // Infers the shape produced by a dot operation.
   pub fn infer_dot_op_shape(
-    _lhs: &Shape,
-    _rhs: &Shape,
-    _dimension_numbers: &DotDimensionNumbers,
-    _preferred_element_type: Option<PrimitiveType>,
-    _sparsity: &Vec<SparsityDescriptor>) -> Result<Shape, String>
+    lhs: &Shape,
+    rhs: &Shape,
+    dimension_numbers: &DotDimensionNumbers,
+    preferred_element_type: Option<PrimitiveType>,
+    sparsity: &Vec<SparsityDescriptor>) -> Result<Shape, String>
   {
-    unimplemented!()    
+    expect_array(lhs, "lhs of dot")?;
+    expect_array(rhs, "rhs of dot")?;
+    let fail = |addendum: &str| -> String {
+      format!("Cannot infer shape for dot operation: {} <dot> {}. {}",
+        ShapeUtil::human_string(lhs), ShapeUtil::human_string(rhs), addendum)
+    };
+
+    // Validate basic properties of dot dimension numbers.
+    validate_dot_dimension_numbers(lhs, rhs, dimension_numbers)?;
+
+    // Check that the number of contracting dimensions match.
+    let lhs_contracting = dimension_numbers.lhs_contracting_dimensions();
+    let rhs_contracting = dimension_numbers.rhs_contracting_dimensions();
+    if lhs_contracting.len() != rhs_contracting.len() {
+      return Err(fail("Must specify the same number of contracting dimensions for lhs and rhs."));
+    }
+    // Check that contracting dimension sizes match, taking the compression
+    // of sparse operands into account.
+    let contracting_size = |shape: &Shape, operand: i64, dimension: i64| -> i64 {
+      let size = shape.dimensions(dimension as usize);
+      for descriptor in sparsity {
+        if descriptor.index() == operand && descriptor.dimension() == dimension &&
+          size != Shape::UNBOUNDED_SIZE && descriptor.n() > 0
+        {
+          return size * descriptor.m() / descriptor.n();
+        }
+      }
+      size
+    };
+    for i in 0..lhs_contracting.len() {
+      let lhs_size = contracting_size(lhs, 0, lhs_contracting[i]);
+      let rhs_size = contracting_size(rhs, 1, rhs_contracting[i]);
+      if !compatible_dimension_sizes(lhs_size, rhs_size) {
+        return Err(fail("Contracting dimension sizes are not compatible."));
+      }
+    }
+
+    // Check that the number of batch dimensions match.
+    let lhs_batch = dimension_numbers.lhs_batch_dimensions();
+    let rhs_batch = dimension_numbers.rhs_batch_dimensions();
+    if lhs_batch.len() != rhs_batch.len() {
+      return Err(fail("Must the same number of batch dimensions for lhs and rhs."));
+    }
+    let mut dimensions = vec![];
+    let mut is_dynamic = vec![];
+    for i in 0..lhs_batch.len() {
+      let l = lhs_batch[i] as usize;
+      let r = rhs_batch[i] as usize;
+      match most_specific_dimension(lhs.dimensions(l), lhs.is_dynamic_dimension(l as i64),
+        rhs.dimensions(r), rhs.is_dynamic_dimension(r as i64))
+      {
+        Some((size, dynamic)) => {
+          dimensions.push(size);
+          is_dynamic.push(dynamic);
+        },
+        None => return Err(fail("Batch dimension sizes are not compatible."))
+      }
+    }
+    if !ShapeUtil::same_element_type_ignoring_fp_precision(lhs, rhs) &&
+      preferred_element_type.is_none()
+    {
+      return Err(fail("Element types do not match."));
+    }
+
+    // The ordering of the result dimensions is the batch dimensions, then the
+    // free dimensions of lhs, then the free dimensions of rhs.
+    for (shape, contracting, batch) in
+      [(lhs, lhs_contracting, lhs_batch), (rhs, rhs_contracting, rhs_batch)]
+    {
+      for i in 0..shape.rank() as i64 {
+        if !contracting.contains(&i) && !batch.contains(&i) {
+          dimensions.push(shape.dimensions(i as usize));
+          is_dynamic.push(shape.is_dynamic_dimension(i));
+        }
+      }
+    }
+    let element_type = maybe_upcast(
+      &ShapeUtil::higher_precision_element_type(lhs, rhs), &preferred_element_type)?;
+    Ok(ShapeUtil::make_shape_dynamic(&element_type, dimensions, is_dynamic))
   }
 
+  // Infers the shape produced by a convolution of 'lhs' with the kernel
+  // 'rhs'.
   pub fn infer_convolve_shape(
-    _lhs: &Shape,
-    _rhs: &Shape,
-    _feature_group_count: i64,
-    _batch_group_count: i64,
-    _window: &Window,
-    _dnums: &ConvolutionDimensionNumbers,
-    _preferred_element_type: Option<PrimitiveType>) -> Result<Shape, String>
+    lhs: &Shape,
+    rhs: &Shape,
+    feature_group_count: i64,
+    batch_group_count: i64,
+    window: &Window,
+    dnums: &ConvolutionDimensionNumbers,
+    preferred_element_type: Option<PrimitiveType>) -> Result<Shape, String>
   {
-    unimplemented!()    
+    expect_array(lhs, "lhs of convolution")?;
+    expect_array(rhs, "rhs of convolution")?;
+    if feature_group_count <= 0 {
+      return Err(format!("feature_group_count must be a positive number, got {}.",
+        feature_group_count));
+    }
+    if batch_group_count <= 0 {
+      return Err(format!("batch_group_count must be a positive number, got {}.",
+        batch_group_count));
+    }
+    if batch_group_count > 1 && feature_group_count > 1 {
+      return Err(format!(
+        "both batch_group_count {} and feature_group_count {} cannot be greater than 1.",
+        batch_group_count, feature_group_count));
+    }
+    if dnums.input_spatial_dimensions_size() != dnums.kernel_spatial_dimensions_size() {
+      return Err(format!(
+        "Both arguments to convolution must have same number of dimensions.\nNumbers: {:?}",
+        dnums));
+    }
+    if dnums.input_spatial_dimensions_size() != dnums.output_spatial_dimensions_size() {
+      return Err(format!(
+        "Both input and output of convolution must have same number of dimensions.\nNumbers: {:?}",
+        dnums));
+    }
+    let num_spatial_dims = dnums.input_spatial_dimensions_size();
+    if window.dimensions_size() != num_spatial_dims {
+      return Err(format!(
+        "Window must have same number of dimensions as dimension numbers.\nWindow: {}\nDimension numbers: {:?}.",
+        window_to_string(window), dnums));
+    }
+    let num_dims = num_spatial_dims + 2;
+    if lhs.rank() != num_dims {
+      return Err(format!("The LHS argument to a convolution should have rank {}; lhs: {}.",
+        num_dims, ShapeUtil::human_string(lhs)));
+    }
+    if rhs.rank() != num_dims {
+      return Err(format!("The RHS argument to a convolution should have rank {}; rhs: {}.",
+        num_dims, ShapeUtil::human_string(rhs)));
+    }
+    ShapeUtil::validate_shape_with_optional_layout(lhs)?;
+    ShapeUtil::validate_shape_with_optional_layout(rhs)?;
+
+    // Verifies that the input and window dimensions are a permutation of the
+    // dimension numbers.
+    let mut input_dnums = vec![dnums.input_batch_dimension(), dnums.input_feature_dimension()];
+    input_dnums.extend(dnums.input_spatial_dimensions_vec().iter());
+    let mut window_dnums = vec![
+      dnums.kernel_input_feature_dimension(), dnums.kernel_output_feature_dimension()];
+    window_dnums.extend(dnums.kernel_spatial_dimensions_vec().iter());
+    let mut output_dnums = vec![dnums.output_batch_dimension(), dnums.output_feature_dimension()];
+    output_dnums.extend(dnums.output_spatial_dimensions_vec().iter());
+    for (name, numbers) in
+      [("input", &input_dnums), ("window", &window_dnums), ("output", &output_dnums)]
+    {
+      let mut sorted = numbers.clone();
+      sorted.sort();
+      if sorted != (0..num_dims as i64).collect::<Vec<i64>>() {
+        return Err(format!(
+          "Convolution {} dimension numbers must be a permutation of 0..{}; got {:?}.",
+          name, num_dims, dnums));
+      }
+    }
+
+    let input_features = lhs.dimensions(dnums.input_feature_dimension() as usize);
+    let input_batch = lhs.dimensions(dnums.input_batch_dimension() as usize);
+    let kernel_input_features =
+      rhs.dimensions(dnums.kernel_input_feature_dimension() as usize);
+    let kernel_output_features =
+      rhs.dimensions(dnums.kernel_output_feature_dimension() as usize);
+    let conv_string = format!("<conv>({}, {})\nDimension numbers: {:?}",
+      ShapeUtil::human_string(lhs), ShapeUtil::human_string(rhs), dnums);
+
+    if input_batch != Shape::UNBOUNDED_SIZE && input_batch % batch_group_count != 0 {
+      return Err(format!(
+        "Expected input batch dimension (value {}) to be divisible by batch_group_count (value {}); got {}.",
+        input_batch, batch_group_count, conv_string));
+    }
+    if input_features != Shape::UNBOUNDED_SIZE && kernel_input_features != Shape::UNBOUNDED_SIZE &&
+      (input_features % feature_group_count != 0 ||
+        input_features / feature_group_count != kernel_input_features)
+    {
+      return Err(format!(
+        "Expected LHS feature dimension (value {}) to be a multiple of feature_group_count (value {}), and LHS feature dimension / feature_group_count = RHS feature dimension (value {}); got {}.",
+        input_features, feature_group_count, kernel_input_features, conv_string));
+    }
+    if kernel_output_features != Shape::UNBOUNDED_SIZE {
+      if kernel_output_features % feature_group_count > 0 {
+        // A depthwise/grouped filter has the shape
+        // [space0, .. spaceN, GROUP_SIZE, NUM_OUTPUT_FEATURES]. When
+        // [space0, .. spaceN, GROUP_SIZE] is convolved with the input, a shape
+        // [space0, .. spaceN, feature_group_count] is formed. Therefore, the
+        // output feature count (which is equal to kernel output features) has
+        // to be a multiple of feature_group_count.
+        return Err(format!(
+          "Expected output feature dimension (value {}) to be divisible by feature_group_count (value {}); got {}.",
+          kernel_output_features, feature_group_count, conv_string));
+      }
+      if kernel_output_features % batch_group_count != 0 {
+        return Err(format!(
+          "Expected output feature dimension size (value {}) to be a multiple of batch group count {}; got {}.",
+          kernel_output_features, batch_group_count, conv_string));
+      }
+    }
+
+    let mut input_spatial_dims = vec![];
+    let mut input_spatial_is_dynamic = vec![];
+    for i in 0..num_spatial_dims {
+      let input_dim = dnums.input_spatial_dimensions(i) as usize;
+      let kernel_dim = dnums.kernel_spatial_dimensions(i) as usize;
+      input_spatial_dims.push(lhs.dimensions(input_dim));
+      input_spatial_is_dynamic.push(lhs.is_dynamic_dimension(input_dim as i64));
+      if !compatible_dimension_sizes(window.dimensions(i).size(), rhs.dimensions(kernel_dim)) {
+        return Err(format!(
+          "Window dimensions do not match RHS shape:\n\tRHS shape: {}\n\tWindow: {{{}}}\n\tDimension numbers: {{{:?}}}.",
+          ShapeUtil::human_string(rhs), window_to_string(window), dnums));
+      }
+    }
+
+    if !ShapeUtil::same_element_type_ignoring_fp_precision(lhs, rhs) &&
+      preferred_element_type.is_none()
+    {
+      return Err(format!("Convolution with different element types: {} and {}.",
+        ShapeUtil::human_string(lhs), ShapeUtil::human_string(rhs)));
+    }
+    let element_type = maybe_upcast(
+      &ShapeUtil::higher_precision_element_type(lhs, rhs), &preferred_element_type)?;
+    let base_shape = ShapeUtil::make_shape_dynamic(
+      &element_type, input_spatial_dims, input_spatial_is_dynamic);
+    let window_output_shape = infer_window_output_shape(&base_shape, window, &element_type)?;
+
+    let mut dimensions = vec![0; num_dims];
+    let mut is_dynamic = vec![false; num_dims];
+    let output_batch = dnums.output_batch_dimension() as usize;
+    let output_feature = dnums.output_feature_dimension() as usize;
+    dimensions[output_batch] = if input_batch == Shape::UNBOUNDED_SIZE {
+      Shape::UNBOUNDED_SIZE
+    } else {
+      input_batch / batch_group_count
+    };
+    is_dynamic[output_batch] = lhs.is_dynamic_dimension(dnums.input_batch_dimension());
+    dimensions[output_feature] = kernel_output_features;
+    is_dynamic[output_feature] = rhs.is_dynamic_dimension(dnums.kernel_output_feature_dimension());
+    for i in 0..num_spatial_dims {
+      let output_dim = dnums.output_spatial_dimensions(i) as usize;
+      dimensions[output_dim] = window_output_shape.dimensions(i);
+      is_dynamic[output_dim] = window_output_shape.is_dynamic_dimension(i as i64);
+    }
+    Ok(ShapeUtil::make_shape_dynamic(&element_type, dimensions, is_dynamic))
   }
 
+  // Infers the shape produced by a while node.
   pub fn infer_while_shape(
-    _condition: &ProgramShape,
-    _body: &ProgramShape,
-    _init: &Shape) -> Result<Shape, String>
+    condition: &ProgramShape,
+    body: &ProgramShape,
+    init: &Shape) -> Result<Shape, String>
+  {
+    // Check the number of parameters for given computations.
+    if condition.parameters_size() != 1 {
+      return Err(format!("Condition must take 1 arguments; got {}.",
+        condition.parameters_size()));
+    }
+    if body.parameters_size() != 1 {
+      return Err(format!("Body must take 1 arguments; got {}.", body.parameters_size()));
+    }
+    let shape_string = || -> String {
+      format!("Condition: {}; body: {}; init: {}.",
+        ShapeUtil::human_string_for_program_shape(condition),
+        ShapeUtil::human_string_for_program_shape(body), ShapeUtil::human_string(init))
+    };
+
+    // Check the shapes of computation parameters and return types.
+    if !ShapeUtil::is_scalar_with_element_type(condition.result(), PrimitiveType::Pred) {
+      return Err(format!("Condition must return a boolean; got {}",
+        shape_string()));
+    }
+    if !ShapeUtil::compatible(body.result(), condition.parameters(0)) ||
+      !ShapeUtil::compatible(body.result(), body.parameters(0)) ||
+      !ShapeUtil::compatible(body.result(), init)
+    {
+      return Err(format!(
+        "The parameter of condition and body, the result of the body, and init must all have the same shape; got {}",
+        shape_string()));
+    }
+    Ok(init.clone())
+  }
+
+  // Infers the shape produced by a conditional operation.
+  pub fn infer_conditional_shape(
+    branch_index: &Shape,
+    branch_computations: &Vec<ProgramShape>,
+    branch_operands: &Vec<Shape>) -> Result<Shape, String>
   {
-    unimplemented!()    
+    if !ShapeUtil::is_scalar_with_element_type(branch_index, PrimitiveType::Pred) &&
+      !ShapeUtil::is_scalar_with_element_type(branch_index, PrimitiveType::S32)
+    {
+      return Err(format!("branch_index must be bool or int32_t; got {}.",
+        ShapeUtil::human_string(branch_index)));
+    }
+    if branch_index.element_type() == PrimitiveType::Pred && branch_computations.len() != 2 {
+      return Err(format!(
+        "If branch_index is a bool, there must be exactly 2 branches; got {}.",
+        branch_computations.len()));
+    }
+    if branch_computations.is_empty() {
+      return Err("There must be at least one branch computation.".to_string());
+    }
+    if branch_computations.len() != branch_operands.len() {
+      return Err(format!(
+        "There must be one operand for each branch computation; got {} computations and {} operands.",
+        branch_computations.len(), branch_operands.len()));
+    }
+    let mut result = branch_computations[0].result().clone();
+    for j in 0..branch_computations.len() {
+      let computation = &branch_computations[j];
+      if computation.parameters_size() != 1 {
+        return Err(format!("branch computation {} must take 1 argument; got {}.",
+          j, computation.parameters_size()));
+      }
+      if !ShapeUtil::compatible(computation.parameters(0), &branch_operands[j]) {
+        return Err(format!(
+          "branch operand {} must match the shape of the only parameter of branch computation {}: got {} for operand, {} for param.",
+          j, j, ShapeUtil::human_string(&branch_operands[j]),
+          ShapeUtil::human_string_for_program_shape(computation)));
+      }
+      if !ShapeUtil::compatible(branch_computations[0].result(), computation.result()) {
+        return Err(format!(
+          "the result of branch 0 computation and branch computation {} must have the same shape: got {} and {}.",
+          j, ShapeUtil::human_string(branch_computations[0].result()),
+          ShapeUtil::human_string(computation.result())));
+      }
+      // A result dimension is dynamic if it is dynamic in any branch.
+      ShapeUtil::for_each_mutable_subshape(&mut result, &mut |subshape: &mut Shape, index: &Vec<i64>| {
+        if !subshape.is_array() {
+          return;
+        }
+        let branch_subshape = ShapeUtil::get_subshape(computation.result(), index);
+        for dim in 0..subshape.rank() {
+          if branch_subshape.is_dynamic_dimension(dim as i64) {
+            subshape.set_dynamic_dimension(dim, true);
+          }
+        }
+      });
+    }
+    Ok(result)
   }
 
+  // Infers the shape produced by a broadcast operation that adds the
+  // dimensions 'broadcast_sizes' in front of the operand's dimensions.
   pub fn infer_broadcast_shape(
-    _operand: &Shape,
-    _broadcast_sizes: &Vec<i64>) -> Result<Shape, String>
+    operand: &Shape,
+    broadcast_sizes: &Vec<i64>) -> Result<Shape, String>
+  {
+    expect_array(operand, "operand of broadcast")?;
+    for size in broadcast_sizes {
+      if *size == Shape::UNBOUNDED_SIZE {
+        return Err("Non-broadcast dimensions must not be dynamic.".to_string());
+      }
+      if *size < 0 {
+        return Err(format!("Broadcast with negative dimension size {}.", size));
+      }
+    }
+    let mut dimensions = broadcast_sizes.clone();
+    dimensions.extend(operand.dimensions_vec().iter());
+    let mut is_dynamic = vec![false; broadcast_sizes.len()];
+    is_dynamic.extend(operand.dinamic_dimensions().iter());
+    Ok(ShapeUtil::make_shape_dynamic(&operand.element_type(), dimensions, is_dynamic))
+  }
+
+  // Checks whether the given parameters can form a broadcast from
+  // 'operand_shape' to 'output_shape', where operand dimension i maps to
+  // output dimension broadcast_dimensions[i].
+  pub fn infer_broadcast_shape_in_dim(
+    operand_shape: &Shape,
+    output_shape: &Shape,
+    broadcast_dimensions: &Vec<i64>) -> Result<Shape, String>
   {
-    unimplemented!()    
+    expect_array(operand_shape, "operand of broadcast")?;
+    expect_array(output_shape, "operand of broadcast")?;
+    let operand_rank = operand_shape.rank();
+    let output_rank = output_shape.rank();
+    if operand_rank > output_rank {
+      return Err(format!(
+        "InDim style broadcast must be to an equal or higher ranked shape; operand rank: {}; output rank: {}.",
+        operand_rank, output_rank));
+    }
+    if operand_rank != broadcast_dimensions.len() {
+      return Err(format!(
+        "Size of broadcast_dimensions has to match operand's rank; operand rank: {}, size of broadcast_dimensions {}.",
+        operand_rank, broadcast_dimensions.len()));
+    }
+    for i in 0..operand_rank {
+      let d = broadcast_dimensions[i];
+      if d < 0 || d as usize >= output_rank {
+        return Err(format!("Broadcast dimension {} is out of bound.", d));
+      }
+      let operand_size = operand_shape.dimensions(i);
+      let output_size = output_shape.dimensions(d as usize);
+      if operand_size != 1 && !compatible_dimension_sizes(operand_size, output_size) {
+        return Err(format!(
+          "Input dimension should be either 1 or equal to the output dimension it is broadcasting into; the {}th operand dimension is {}, the {}th output dimension is {}.",
+          i, operand_size, d, output_size));
+      }
+      if operand_shape.is_dynamic_dimension(i as i64) &&
+        !output_shape.is_dynamic_dimension(d) && operand_size != 1
+      {
+        return Err(format!(
+          "Broadcast input and output dynamism mismatch: {} and {}.",
+          ShapeUtil::human_string(operand_shape), ShapeUtil::human_string(output_shape)));
+      }
+      // Make sure the broadcast dimensions are listed in a strictly increasing
+      // order.
+      if i > 0 && broadcast_dimensions[i - 1] >= d {
+        return Err(format!("Broadcast dimensions order is wrong: {} comes after {}.",
+          d, broadcast_dimensions[i - 1]));
+      }
+    }
+    Ok(output_shape.clone())
   }
 
+  // Infers the shape produced by a reshape of 'operand' to 'dimensions'. If
+  // the operand is dynamic, 'inferred_dimension' is the dimension that
+  // absorbs the dynamism, or -1.
   pub fn infer_reshape_shape(
-    _arg_shapes: &Vec<Shape>,
-    _dimensions_to_reduce: &Vec<i64>,
-    _to_apply: &ProgramShape) -> Result<Shape, String>
+    operand: &Shape,
+    dimensions: &Vec<i64>,
+    inferred_dimension: i64) -> Result<Shape, String>
+  {
+    expect_array(operand, "reshape")?;
+    for dimension in dimensions {
+      if *dimension < 0 {
+        return Err(format!("Reshape with negative dimension size {}.", dimension));
+      }
+    }
+    let mut inferred_shape = ShapeUtil::make_shape(&operand.element_type(), dimensions.clone());
+    if inferred_dimension >= dimensions.len() as i64 {
+      return Err(format!("Reshape inferred dimension {} is out of bounds for {}.",
+        inferred_dimension, ShapeUtil::human_string(&inferred_shape)));
+    }
+    if operand.is_unbounded_dynamic() {
+      if inferred_dimension < 0 {
+        return Err(format!(
+          "Reshape of the unbounded dynamic shape {} needs an inferred dimension.",
+          ShapeUtil::human_string(operand)));
+      }
+      inferred_shape.set_dimensions(inferred_dimension as usize, Shape::UNBOUNDED_SIZE);
+      inferred_shape.set_dynamic_dimension(inferred_dimension as usize, true);
+      return Ok(inferred_shape);
+    }
+    if ShapeUtil::elements_in(operand) != ShapeUtil::elements_in(&inferred_shape) {
+      return Err(format!(
+        "Reshape operation has mismatched element counts: from={} ({}) to={} ({}).",
+        ShapeUtil::elements_in(operand), ShapeUtil::human_string(operand),
+        ShapeUtil::elements_in(&inferred_shape), ShapeUtil::human_string(&inferred_shape)));
+    }
+    if operand.is_dynamic() && inferred_dimension >= 0 {
+      inferred_shape.set_dynamic_dimension(inferred_dimension as usize, true);
+    }
+    Ok(inferred_shape)
+  }
+
+  // Infers the shape produced by a reduction of the first half of
+  // 'arg_shapes' with the init values in the second half.
+  pub fn infer_reduce_shape(
+    arg_shapes: &Vec<Shape>,
+    dimensions_to_reduce: &Vec<i64>,
+    to_apply: &ProgramShape) -> Result<Shape, String>
+  {
+    if arg_shapes.is_empty() {
+      return Err("Reduce must have at least 2 arguments, has 0.".to_string());
+    }
+    if arg_shapes.len() % 2 != 0 {
+      return Err(format!("Reduce must have an even number of arguments, has {}.",
+        arg_shapes.len()));
+    }
+    let num_reduced_args = arg_shapes.len() / 2;
+    let reduced_args = &arg_shapes[..num_reduced_args];
+    for arg in reduced_args {
+      expect_array(arg, "operand of reduce")?;
+    }
+    // Check that all of the reduced tensors have the same dimensions. The
+    // element types may be different.
+    for i in 1..num_reduced_args {
+      if !compatible_dimensions(&reduced_args[0], &reduced_args[i]) {
+        return Err(format!(
+          "All reduced tensors must have compatible dimension. Tensor at index 0 has shape {}, and tensor at index {} has shape {}.",
+          ShapeUtil::human_string(&reduced_args[0]), i,
+          ShapeUtil::human_string(&reduced_args[i])));
+      }
+    }
+    // Check that the dimensions to reduce are in-bounds for the given shape.
+    // We've already verified all reduced tensors have the same dimensions, so
+    // it doesn't matter which one we choose.
+    let arg = &reduced_args[0];
+    for dimension in dimensions_to_reduce {
+      if *dimension < 0 || *dimension as usize >= arg.rank() {
+        return Err(format!("Reducing out-of-bounds dimension {} in shape {}.",
+          dimension, ShapeUtil::human_string(arg)));
+      }
+    }
+    let init_values = arg_shapes[num_reduced_args..].to_vec();
+    let element_types = reduced_args.iter().map(|arg| arg.element_type()).collect();
+    verify_reducer_shape(to_apply, &init_values, &element_types, num_reduced_args)?;
+
+    let mut dimensions_to_reduce_set = HashSet::new();
+    for dimension in dimensions_to_reduce {
+      if !dimensions_to_reduce_set.insert(*dimension) {
+        return Err(format!("Duplicate reduction dimension: {}.", dimension));
+      }
+    }
+    let mut new_dimensions = vec![];
+    let mut new_is_dynamic = vec![];
+    for i in 0..arg.rank() {
+      if !dimensions_to_reduce_set.contains(&(i as i64)) {
+        new_dimensions.push(arg.dimensions(i));
+        new_is_dynamic.push(arg.is_dynamic_dimension(i as i64));
+      }
+    }
+    let result = to_apply.result();
+    if result.is_tuple() {
+      let shapes = result.tuple_shapes_vec().iter().map(|shape| {
+        ShapeUtil::make_shape_dynamic(&shape.element_type(), new_dimensions.clone(),
+          new_is_dynamic.clone())
+      }).collect();
+      return Ok(ShapeUtil::make_tuple_shape(shapes));
+    }
+    Ok(ShapeUtil::make_shape_dynamic(&result.element_type(), new_dimensions, new_is_dynamic))
+  }
+
+  // Infers the shape produced by a reduce-window of a single operand.
+  pub fn infer_reduce_window_shape(
+    operand_shape: &Shape,
+    init_value_shape: &Shape,
+    window: &Window,
+    to_apply: &ProgramShape) -> Result<Shape, String>
   {
-    unimplemented!()    
+    expect_array(operand_shape, "operand of reduce-window")?;
+    verify_reducer_shape(to_apply, &vec![init_value_shape.clone()],
+      &vec![operand_shape.element_type()], 1)?;
+    infer_window_output_shape(operand_shape, window, &init_value_shape.element_type())
   }
 
+  // Infers the shape produced by a variadic reduce-window.
+  pub fn infer_variadic_reduce_window_shape(
+    operands: &Vec<Shape>,
+    init_values: &Vec<Shape>,
+    window: &Window,
+    to_apply: &ProgramShape) -> Result<Shape, String>
+  {
+    if operands.is_empty() {
+      return Err("ReduceWindow must have at least one operand.".to_string());
+    }
+    if operands.len() != init_values.len() {
+      return Err(format!(
+        "ReduceWindow must have as many init values as operands; got {} operands and {} init values.",
+        operands.len(), init_values.len()));
+    }
+    for operand in operands {
+      expect_array(operand, "operand of reduce-window")?;
+      if !compatible_dimensions(operand, &operands[0]) {
+        return Err(format!(
+          "All operands to ReduceWindow must have compatible dimensions; got {} and {}.",
+          ShapeUtil::human_string(&operands[0]), ShapeUtil::human_string(operand)));
+      }
+    }
+    let element_types = operands.iter().map(|operand| operand.element_type()).collect();
+    verify_reducer_shape(to_apply, init_values, &element_types, operands.len())?;
+    let mut output_shapes = vec![];
+    for i in 0..operands.len() {
+      output_shapes.push(
+        infer_window_output_shape(&operands[i], window, &init_values[i].element_type())?);
+    }
+    Ok(ShapeUtil::make_maybe_tuple_shape(output_shapes))
+  }
+
+  // Infers the window from the given sizes, strides, paddings and dilations.
+  // Empty optional arguments take their default values.
   pub fn infer_window_from_dimensions(
-    _window_dimensions: &Vec<i64>,
-    _window_strides: &Vec<i64>,
-    _padding: &Vec<(i64, i64)>,
-    _lhs_dilation: &Vec<i64>,
-    _rhs_dilation: &Vec<i64>,
-    _window_reversal: Option<&Vec<bool>>) -> Result<Window, String>
+    window_dimensions: &Vec<i64>,
+    window_strides: &Vec<i64>,
+    padding: &Vec<(i64, i64)>,
+    lhs_dilation: &Vec<i64>,
+    rhs_dilation: &Vec<i64>,
+    window_reversal: Option<&Vec<bool>>) -> Result<Window, String>
   {
-    unimplemented!()    
+    let verify_size = |size: usize, name: &str| -> Result<(), String> {
+      if size == 0 || size == window_dimensions.len() {
+        return Ok(());
+      }
+      Err(format!(
+        "Window has different number of window dimensions than of {}\nNumber of window dimensions: {}\nNumber of {}: {}\n",
+        name, window_dimensions.len(), name, size))
+    };
+    verify_size(window_strides.len(), "window strides")?;
+    verify_size(padding.len(), "padding entries")?;
+    verify_size(lhs_dilation.len(), "lhs dilation factors")?;
+    verify_size(rhs_dilation.len(), "rhs dilation factors")?;
+    if let Some(reversal) = window_reversal {
+      verify_size(reversal.len(), "window reversal")?;
+    }
+
+    let mut window = Window::new();
+    for i in 0..window_dimensions.len() {
+      let mut dim = WindowDimension::new();
+      dim.set_size(window_dimensions[i]);
+      dim.set_stride(if window_strides.is_empty() { 1 } else { window_strides[i] });
+      if !padding.is_empty() {
+        dim.set_padding_low(padding[i].0);
+        dim.set_padding_high(padding[i].1);
+      } else {
+        dim.set_padding_low(0);
+        dim.set_padding_high(0);
+      }
+      dim.set_base_dilation(if lhs_dilation.is_empty() { 1 } else { lhs_dilation[i] });
+      dim.set_window_dilation(if rhs_dilation.is_empty() { 1 } else { rhs_dilation[i] });
+      if let Some(reversal) = window_reversal {
+        if !reversal.is_empty() {
+          dim.set_window_reversal(reversal[i]);
+        }
+      }
+      window.add_dimensions(dim);
+    }
+    Ok(window)
   }
 
+  // Infers the shape produced by a call of 'program' with arguments of the
+  // given shapes.
   pub fn infer_call_shape(
-    _shapes: &Vec<Shape>,
-    _program: &ProgramShape) -> Result<Shape, String>
+    shapes: &Vec<Shape>,
+    program: &ProgramShape) -> Result<Shape, String>
   {
-    unimplemented!()
+    // Check the number of parameters for given computation.
+    if program.parameters_size() != shapes.len() {
+      return Err(format!(
+        "Call applied function arity must match number of arguments ({} != {}).",
+        program.parameters_size(), shapes.len()));
+    }
+    // Check that each given arg shape matches corresponding parameter shape.
+    for i in 0..shapes.len() {
+      let param_shape = program.parameters(i);
+      let arg_shape = &shapes[i];
+      if !ShapeUtil::compatible(arg_shape, param_shape) {
+        return Err(format!(
+          "Call parameter must match argument; got parameter {} shape: {}, argument shape: {}.",
+          i, ShapeUtil::human_string(param_shape), ShapeUtil::human_string(arg_shape)));
+      }
+    }
+    Ok(program.result().clone())
   }
 
   // Infers the shape produced by scattering the given source shape to the
   // selected indices of each window on the operand shape.
   pub fn infer_select_and_scatter_shape(
-    _operand_shape: &Shape,
-    _select_shape: &ProgramShape,
-    _window: &Window,
-    _source_shape: &Shape,
-    _init_value_shape: &Shape,
-    _scatter_shape: &ProgramShape) -> Result<Shape, String>
+    operand_shape: &Shape,
+    select_shape: &ProgramShape,
+    window: &Window,
+    source_shape: &Shape,
+    init_value_shape: &Shape,
+    scatter_shape: &ProgramShape) -> Result<Shape, String>
   {
-    unimplemented!()    
+    expect_array(operand_shape, "operand of select-and-scatter")?;
+
+    // Check if the select function has a proper shape of (T,T) -> PRED.
+    if select_shape.parameters_size() != 2 {
+      return Err(format!("Select function must take 2 parameters, but takes {} parameter(s).",
+        select_shape.parameters_size()));
+    }
+    if !ShapeUtil::is_scalar_with_element_type(select_shape.result(), PrimitiveType::Pred) {
+      return Err(format!("Select function must have rank-0 PRED result; got {}.",
+        ShapeUtil::human_string(select_shape.result())));
+    }
+    let operand_element_shape = ShapeUtil::make_scalar_shape(&operand_shape.element_type());
+    for i in 0..2 {
+      if !ShapeUtil::compatible_ignoring_fp_precision(
+        &operand_element_shape, select_shape.parameters(i))
+      {
+        return Err(format!(
+          "Select function's {} parameter shape currently must match the operand element shape, but got {} vs {}.",
+          if i == 0 { "first" } else { "second" },
+          ShapeUtil::human_string(select_shape.parameters(i)),
+          ShapeUtil::human_string(&operand_element_shape)));
+      }
+    }
+
+    // Check if the scatter function has a proper shape as a reduction.
+    verify_reducer_shape(scatter_shape, &vec![init_value_shape.clone()],
+      &vec![source_shape.element_type()], 1)?;
+
+    // Check if the result shape of window operation matches the source shape.
+    let window_result_shape =
+      infer_window_output_shape(operand_shape, window, &operand_shape.element_type())?;
+    if !ShapeUtil::compatible_ignoring_fp_precision(source_shape, &window_result_shape) {
+      return Err(format!(
+        "Source shape does not match the shape of window-reduced operand: source({}), window-reduced operand({}).",
+        ShapeUtil::human_string(source_shape), ShapeUtil::human_string(&window_result_shape)));
+    }
+    Ok(operand_shape.clone())
   }
 
-  pub fn infer_conditional_shape(
-    _branch_index: &Shape,
-    _branch_computations: &Vec<ProgramShape>,
-    _branch_operands: &Vec<Shape>) -> Result<Shape, String>
+  // Infers the shape produced by a scatter. 'arg_shapes' holds the operands,
+  // the scatter indices and the updates, in that order.
+  pub fn infer_scatter_shape(
+    arg_shapes: &Vec<Shape>,
+    to_apply_shape: &ProgramShape,
+    scatter_dim_numbers: &ScatterDimensionNummbers) -> Result<Shape, String>
   {
-    unimplemented!()    
+    if arg_shapes.len() < 3 || arg_shapes.len() % 2 != 1 {
+      return Err(format!("Invalid argument count {} to scatter; expected an odd count of at least 3.",
+        arg_shapes.len()));
+    }
+    let operand_count = arg_shapes.len() / 2;
+    let operand_shapes = &arg_shapes[..operand_count];
+    let scatter_indices_shape = &arg_shapes[operand_count];
+    let updates_shapes = &arg_shapes[operand_count + 1..];
+
+    let operand_shape = &operand_shapes[0];
+    for (i, shape) in operand_shapes.iter().enumerate() {
+      expect_array(shape, "operand tensor of scatter op")?;
+      if !compatible_dimensions(shape, operand_shape) {
+        return Err(format!(
+          "All scatter inputs must have the same compatible shape. Shape of input 0 is: {}, shape of input {} is: {}.",
+          ShapeUtil::human_string(operand_shape), i, ShapeUtil::human_string(shape)));
+      }
+    }
+    expect_array(scatter_indices_shape, "scatter indices of scatter op")?;
+    if !ShapeUtil::element_is_integral(scatter_indices_shape) {
+      return Err(format!("Scatter indices parameter must be an integral tensor; got {}.",
+        ShapeUtil::human_string(scatter_indices_shape)));
+    }
+    let index_vector_dim = scatter_dim_numbers.index_vector_dim();
+    if index_vector_dim < 0 || index_vector_dim as usize > scatter_indices_shape.rank() {
+      return Err(format!(
+        "Scatter index leaf dimension must be within [0, rank(scatter_indices) + 1). rank(scatter_indices) is {} and scatter index leaf dimension is {}.",
+        scatter_indices_shape.rank(), index_vector_dim));
+    }
+    let updates_shape = &updates_shapes[0];
+    for (i, shape) in updates_shapes.iter().enumerate() {
+      expect_array(shape, "updates tensor of scatter op")?;
+      if !compatible_dimensions(shape, updates_shape) {
+        return Err(format!(
+          "All scatter updates must have the same compatible shape. Shape of update 0 is: {}, shape of update {} is: {}.",
+          ShapeUtil::human_string(updates_shape), i, ShapeUtil::human_string(shape)));
+      }
+    }
+
+    let init_value_shapes = operand_shapes.iter()
+      .map(|shape| ShapeUtil::make_scalar_shape(&shape.element_type())).collect();
+    let update_element_types = updates_shapes.iter().map(|shape| shape.element_type()).collect();
+    verify_reducer_shape(to_apply_shape, &init_value_shapes, &update_element_types,
+      operand_count)?;
+
+    let mut expanded_scatter_indices_shape = scatter_indices_shape.dimensions_vec().clone();
+    if expanded_scatter_indices_shape.len() == index_vector_dim as usize {
+      expanded_scatter_indices_shape.push(1);
+    }
+    let ivd = index_vector_dim as usize;
+
+    // Validate the dimension numbers.
+    let update_window_dims = scatter_dim_numbers.update_window_dims();
+    let inserted_window_dims = scatter_dim_numbers.inserted_window_dims();
+    let input_batching_dims = scatter_dim_numbers.input_batching_dims();
+    let scatter_indices_batching_dims = scatter_dim_numbers.scatter_indices_batching_dims();
+    let scatter_dims_to_operand_dims = scatter_dim_numbers.scatter_dims_to_operand_dims();
+    validate_dimension_list(update_window_dims, updates_shape.rank(), "scatter",
+      "update_window_dims", true)?;
+    validate_dimension_list(inserted_window_dims, operand_shape.rank(), "scatter",
+      "inserted_window_dims", true)?;
+    validate_dimension_list(input_batching_dims, operand_shape.rank(), "scatter",
+      "input_batching_dims", true)?;
+    if inserted_window_dims.iter().any(|d| input_batching_dims.contains(d)) {
+      return Err(format!(
+        "inserted_window_dims and input_batching_dims in scatter op must be disjoint; got {} and {}.",
+        vector_string(inserted_window_dims), vector_string(input_batching_dims)));
+    }
+    validate_dimension_list(scatter_indices_batching_dims, expanded_scatter_indices_shape.len(),
+      "scatter", "scatter_indices_batching_dims", false)?;
+    if scatter_indices_batching_dims.contains(&index_vector_dim) {
+      return Err("Scatter op cannot have the index vector dimension as the batching dimension.".to_string());
+    }
+    if input_batching_dims.len() != scatter_indices_batching_dims.len() {
+      return Err(format!(
+        "input_batching_dims and scatter_indices_batching_dims in scatter op must be of the same size; got {} and {}.",
+        input_batching_dims.len(), scatter_indices_batching_dims.len()));
+    }
+    if scatter_dims_to_operand_dims.len() as i64 != expanded_scatter_indices_shape[ivd] {
+      return Err(format!(
+        "Scatter op has {} elements in scatter_dims_to_operand_dims and the bound of dimension index_vector_dim={} of scatter_indices is {}. These two numbers must be equal.",
+        scatter_dims_to_operand_dims.len(), index_vector_dim, expanded_scatter_indices_shape[ivd]));
+    }
+    for (i, dim) in scatter_dims_to_operand_dims.iter().enumerate() {
+      if *dim < 0 || *dim as usize >= operand_shape.rank() {
+        return Err(format!(
+          "Invalid scatter_dims_to_operand_dims mapping; domain is [0, {}), got: {}->{}.",
+          operand_shape.rank(), i, dim));
+      }
+    }
+    if has_duplicates(scatter_dims_to_operand_dims) {
+      return Err(format!("Repeated dimensions not allowed in scatter_dims_to_operand_dims; got: {}.",
+        vector_string(scatter_dims_to_operand_dims)));
+    }
+
+    let window_size = update_window_dims.len() + inserted_window_dims.len() +
+      input_batching_dims.len();
+    if window_size != operand_shape.rank() {
+      return Err(format!(
+        "Scatter op has window of size {}; doesn't match operand of rank {}.",
+        window_size, operand_shape.rank()));
+    }
+    let expected_updates_rank = expanded_scatter_indices_shape.len() - 1 +
+      update_window_dims.len();
+    if updates_shape.rank() != expected_updates_rank {
+      return Err(format!("Updates tensor must be of rank {}; got {}.",
+        expected_updates_rank, updates_shape.rank()));
+    }
+
+    // Validate the bounds of the window dimensions of the updates.
+    let mut max_update_slice_sizes = vec![];
+    for i in 0..operand_shape.rank() as i64 {
+      if !inserted_window_dims.contains(&i) && !input_batching_dims.contains(&i) {
+        max_update_slice_sizes.push(operand_shape.dimensions(i as usize));
+      }
+    }
+    for (i, update_window_dim) in update_window_dims.iter().enumerate() {
+      let update_size = updates_shape.dimensions(*update_window_dim as usize);
+      let max_size = max_update_slice_sizes[i];
+      if update_size != Shape::UNBOUNDED_SIZE && max_size != Shape::UNBOUNDED_SIZE &&
+        update_size > max_size
+      {
+        return Err(format!(
+          "Bounds of the window dimensions of updates must not exceed the bounds of the corresponding dimensions of operand. For dimension {}, updates bound is {}, operand bound is {}.",
+          update_window_dim, update_size, max_size));
+      }
+    }
+    // Validate the bounds of the scatter dimensions of the updates.
+    let mut scatter_dims_seen = 0;
+    for i in 0..updates_shape.rank() as i64 {
+      if update_window_dims.contains(&i) {
+        continue;
+      }
+      if scatter_dims_seen == ivd {
+        scatter_dims_seen += 1;
+      }
+      let update_size = updates_shape.dimensions(i as usize);
+      let indices_size = expanded_scatter_indices_shape[scatter_dims_seen];
+      if !compatible_dimension_sizes(update_size, indices_size) {
+        return Err(format!(
+          "Bounds of the scatter dimensions of updates must be same as the bounds of the corresponding dimensions of scatter indices. For scatter dimension {}, updates bound is {}, scatter_indices bound is {}.",
+          i, update_size, indices_size));
+      }
+      scatter_dims_seen += 1;
+    }
+    Ok(ShapeUtil::make_maybe_tuple_shape(operand_shapes.to_vec()))
   }
 
-  pub fn infer_scatter_shape(
-    _arg_shapes: &Vec<Shape>,
-    _to_apply_shape: &ProgramShape,
-    _scatter_dim_numbers: &ScatterDimensionNummbers) -> Result<Shape, String>
+  // Infers the shape of an iota along 'iota_dimension' of 'shape'.
+  pub fn infer_iota_shape(shape: &Shape, iota_dimension: i64) -> Result<Shape, String> {
+    expect_array(shape, "iota")?;
+    if shape.rank() == 0 {
+      return Err("Iota does not support scalars.".to_string());
+    }
+    if iota_dimension < 0 || iota_dimension as usize >= shape.rank() {
+      return Err(format!(
+        "The iota dimension {} must be non-negative and less than the rank {} of {}.",
+        iota_dimension, shape.rank(), ShapeUtil::human_string(shape)));
+    }
+    let t = shape.element_type();
+    if !is_integral_type(&t) && !is_floating_point_type(&t) && !is_complex_type(&t) {
+      return Err(format!("Iota does not support the element type {}.",
+        lowercase_primitive_type_name(&t)));
+    }
+    Ok(shape.clone())
+  }
+
+  // Infers the shape of a rng operation with the given distribution
+  // parameters.
+  pub fn infer_rng_shape(
+    distribution: &RandomDistribution,
+    parameter_shapes: &Vec<Shape>,
+    shape: &Shape) -> Result<Shape, String>
+  {
+    expect_array(shape, "rng")?;
+    if *distribution == RandomDistribution::Invalid {
+      return Err("Invalid distribution for rng.".to_string());
+    }
+    if parameter_shapes.len() != 2 {
+      return Err(format!("RNG distribution {:?} expects 2 parameters, but got {}.",
+        distribution, parameter_shapes.len()));
+    }
+    for parameter_shape in parameter_shapes {
+      if !ShapeUtil::is_scalar(parameter_shape) ||
+        !ShapeUtil::same_element_type(parameter_shape, shape)
+      {
+        return Err(format!(
+          "RNG parameters must be scalars of the result element type; got {} for result {}.",
+          ShapeUtil::human_string(parameter_shape), ShapeUtil::human_string(shape)));
+      }
+    }
+    if *distribution == RandomDistribution::Normal && !ShapeUtil::element_is_floating(shape) {
+      return Err(format!("Normal distribution requires a floating point type; got {}.",
+        ShapeUtil::human_string(shape)));
+    }
+    Ok(shape.clone())
+  }
+
+  // Infers the shape of a rng-bit-generator: the new state and the random
+  // bits, whose element type is the unsigned type of the width of 'shape'.
+  pub fn infer_rng_bit_generator_shape(
+    initial_state_shape: &Shape,
+    algorithm: &RandomAlgorithm,
+    shape: &Shape) -> Result<Shape, String>
+  {
+    expect_array(shape, "rng-bit-generator")?;
+    if initial_state_shape.rank() != 1 || initial_state_shape.element_type() != PrimitiveType::U64 {
+      return Err(format!("The rng-bit-generator state must be a u64 vector; got {}.",
+        ShapeUtil::human_string(initial_state_shape)));
+    }
+    let state_size = initial_state_shape.dimensions(0);
+    let valid_size = match algorithm {
+      RandomAlgorithm::ThreeFry => state_size == 2,
+      RandomAlgorithm::Philox => state_size == 2 || state_size == 3,
+      RandomAlgorithm::Default => state_size >= 1
+    };
+    if !valid_size {
+      return Err(format!("Invalid state shape {} for the rng-bit-generator algorithm {:?}.",
+        ShapeUtil::human_string(initial_state_shape), algorithm));
+    }
+    let t = shape.element_type();
+    if !is_integral_type(&t) && !is_floating_point_type(&t) {
+      return Err(format!("Unsupported shape for rng-bit-generator: {}.",
+        ShapeUtil::human_string(shape)));
+    }
+    let output_shape = ShapeUtil::change_element_type(shape,
+      &common::primitive_util::unsigned_integral_type_for_bit_width(bit_width(&t)));
+    Ok(ShapeUtil::make_tuple_shape(vec![initial_state_shape.clone(), output_shape]))
+  }
+
+  // Checks the shapes of a custom call. The result shape is given by the user,
+  // so it is returned as is once validated.
+  pub fn infer_custom_call_shape(
+    operand_shapes: &Vec<Shape>,
+    shape: &Shape,
+    operand_shapes_with_layout: &Option<Vec<Shape>>) -> Result<Shape, String>
+  {
+    ShapeUtil::validate_shape_with_optional_layout(shape)?;
+    if let Some(layouts) = operand_shapes_with_layout {
+      if layouts.len() != operand_shapes.len() {
+        return Err(format!(
+          "Must specify a shape with layout for each operand for custom call; {} shapes with layout given, {} operands.",
+          layouts.len(), operand_shapes.len()));
+      }
+      for (i, (operand_shape, layout_shape)) in operand_shapes.iter().zip(layouts).enumerate() {
+        if !ShapeUtil::compatible(operand_shape, layout_shape) {
+          return Err(format!(
+            "Shape with layout of operand {} of custom call, {}, does not match the operand shape {}.",
+            i, ShapeUtil::human_string(layout_shape), ShapeUtil::human_string(operand_shape)));
+        }
+        let mut missing_layout = false;
+        ShapeUtil::for_each_subshape(layout_shape, &mut |subshape: &Shape, _index: &Vec<i64>| {
+          if subshape.is_array() && !subshape.has_layout() {
+            missing_layout = true;
+          }
+        });
+        if missing_layout {
+          return Err(format!("Operand {} shape {} of custom call has no layout.",
+            i, ShapeUtil::human_string(layout_shape)));
+        }
+      }
+    }
+    Ok(shape.clone())
+  }
+
+  // Infers the shape of an after-all, which joins tokens.
+  pub fn infer_after_all_shape(arg_shapes: &Vec<Shape>) -> Result<Shape, String> {
+    for arg_shape in arg_shapes {
+      if !arg_shape.is_token() {
+        return Err(format!("Operands of token instructions must be TOKEN types; got {}.",
+          ShapeUtil::human_string(arg_shape)));
+      }
+    }
+    Ok(ShapeUtil::make_token_shape())
+  }
+
+  // Infers the shape of an add-dependency, which forwards its first operand.
+  pub fn infer_add_dependency_shape(
+    operand_shape: &Shape, token_shape: &Shape) -> Result<Shape, String>
   {
-    unimplemented!()    
+    if !token_shape.is_token() {
+      return Err(format!("The second operand of add-dependency must be a token; got {}.",
+        ShapeUtil::human_string(token_shape)));
+    }
+    Ok(operand_shape.clone())
+  }
+
+  // Infers the shape of a replica-id or partition-id.
+  pub fn infer_replica_id_shape() -> Result<Shape, String> {
+    Ok(ShapeUtil::make_scalar_shape(&PrimitiveType::U32))
+  }
+
+  // Infers the shape of a copy-start: the destination, the source and a
+  // context.
+  pub fn infer_copy_start_shape(operand_shape: &Shape) -> Result<Shape, String> {
+    Ok(ShapeUtil::make_tuple_shape(vec![operand_shape.clone(), operand_shape.clone(),
+      ShapeUtil::make_scalar_shape(&PrimitiveType::U32)]))
+  }
+
+  // Infers the shape of a copy-done from the shape of the copy-start.
+  pub fn infer_copy_done_shape(copy_start_shape: &Shape) -> Result<Shape, String> {
+    if !copy_start_shape.is_tuple() || copy_start_shape.tuple_shapes_size() != 3 {
+      return Err(format!("Copy-done expects a 3-tuple operand; got {}.",
+        ShapeUtil::human_string(copy_start_shape)));
+    }
+    Ok(copy_start_shape.tuple_shapes(0).clone())
+  }
+
+  // Infers the shape of the complex type with components of 'shape', used by
+  // the complex composition of two real operands.
+  pub fn infer_complex_shape(shape: &Shape) -> Result<Shape, String> {
+    if !ShapeUtil::element_is_floating(shape) {
+      return Err(format!("Complex requires real floating point components; got {}.",
+        ShapeUtil::human_string(shape)));
+    }
+    Ok(ShapeUtil::change_element_type(shape, &complex_type(shape.element_type())))
   }
 
+  // Returns the shape all of the non-scalar 'shapes' broadcast to, or None if
+  // they are all scalars.
   pub fn infer_scalar_broadcast_shape(
-    _shapes: &Vec<Shape>) -> Result<Option<Shape>, String>
+    shapes: &Vec<Shape>) -> Result<Option<Shape>, String>
   {
-    unimplemented!()
+    let mut broadcast_shape: Option<Shape> = None;
+    for shape in shapes {
+      if !shape.is_array() || shape.rank() == 0 {
+        continue;
+      }
+      match &broadcast_shape {
+        None => broadcast_shape = Some(shape.clone()),
+        Some(current) => {
+          match merge_dimensions(current, shape) {
+            Some(merged) => broadcast_shape = Some(merged),
+            None => return Err(format!(
+              "Shapes must either be scalars or have the same dimensions; got {} and {}.",
+              ShapeUtil::human_string(current), ShapeUtil::human_string(shape)))
+          }
+        }
+      }
+    }
+    Ok(broadcast_shape)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn f32_shape(dimensions: Vec<i64>) -> Shape {
+    ShapeUtil::make_shape(&PrimitiveType::F32, dimensions)
+  }
+
+  fn scalar_program(parameters: usize, t: &PrimitiveType) -> ProgramShape {
+    let mut program = ProgramShape::new();
+    for _ in 0..parameters {
+      program.add_parameter(ShapeUtil::make_scalar_shape(t));
+    }
+    program.set_result(ShapeUtil::make_scalar_shape(t));
+    program
+  }
+
+  #[test]
+  fn test_unary_and_binary_ops() {
+    let matrix = f32_shape(vec![2, 3]);
+    assert_eq!(ShapeInference::infer_unary_op_shape_by_shape(&HloOpcode::Exp, &matrix),
+      Ok(matrix.clone()));
+    let is_finite =
+      ShapeInference::infer_unary_op_shape_by_shape(&HloOpcode::IsFinite, &matrix).unwrap();
+    assert_eq!(is_finite.element_type(), PrimitiveType::Pred);
+    let s32 = ShapeUtil::make_shape(&PrimitiveType::S32, vec![2]);
+    assert!(ShapeInference::infer_unary_op_shape_by_shape(&HloOpcode::Floor, &s32).is_err());
+
+    let vector = f32_shape(vec![3]);
+    let result = ShapeInference::infer_binary_op_shape_by_dimensions(
+      &HloOpcode::Add, &matrix, &vector, &vec![1]).unwrap();
+    assert_eq!(result, matrix);
+    let compare = ShapeInference::infer_binary_op_shape_by_dimensions(
+      &HloOpcode::Compare, &f32_shape(vec![1, 3]), &matrix, &vec![]).unwrap();
+    assert_eq!(compare.dimensions_vec(), &vec![2, 3]);
+    assert_eq!(compare.element_type(), PrimitiveType::Pred);
+    let err = ShapeInference::infer_binary_op_shape_by_dimensions(
+      &HloOpcode::Add, &matrix, &f32_shape(vec![2, 4]), &vec![]).unwrap_err();
+    assert!(err.contains("incompatible shapes"), "{}", err);
+  }
+
+  #[test]
+  fn test_unbounded_dynamism() {
+    let unbounded = ShapeUtil::make_shape_dynamic(
+      &PrimitiveType::F32, vec![Shape::UNBOUNDED_SIZE, 3], vec![true, false]);
+    let bounded = ShapeUtil::make_shape_dynamic(
+      &PrimitiveType::F32, vec![4, 3], vec![true, false]);
+    let result = ShapeInference::infer_binary_op_shape_by_dimensions(
+      &HloOpcode::Add, &unbounded, &bounded, &vec![]).unwrap();
+    assert_eq!(result.dimensions_vec(), &vec![4, 3]);
+    assert!(result.is_dynamic_dimension(0));
+
+    let result = ShapeInference::infer_binary_op_shape_by_dimensions(
+      &HloOpcode::Add, &unbounded, &f32_shape(vec![1, 3]), &vec![]).unwrap();
+    assert!(result.is_unbounded_dynamic_dimension(0));
+
+    let concat = ShapeInference::infer_concat_op_shape(
+      &vec![unbounded.clone(), f32_shape(vec![2, 3])], 0).unwrap();
+    assert!(concat.is_unbounded_dynamic_dimension(0));
+    let concat = ShapeInference::infer_concat_op_shape(
+      &vec![unbounded.clone(), f32_shape(vec![2, 3])], 1).unwrap();
+    assert_eq!(concat.dimensions_vec(), &vec![2, 6]);
+    assert!(!concat.is_dynamic_dimension(0));
+  }
+
+  #[test]
+  fn test_reduce_and_reduce_window() {
+    let operand = f32_shape(vec![4, 5, 6]);
+    let init = ShapeUtil::make_scalar_shape(&PrimitiveType::F32);
+    let add = scalar_program(2, &PrimitiveType::F32);
+    let result = ShapeInference::infer_reduce_shape(
+      &vec![operand.clone(), init.clone()], &vec![0, 2], &add).unwrap();
+    assert_eq!(result, f32_shape(vec![5]));
+
+    let err = ShapeInference::infer_reduce_shape(
+      &vec![operand.clone(), init.clone()], &vec![3], &add).unwrap_err();
+    assert!(err.starts_with("Reducing out-of-bounds dimension 3"), "{}", err);
+    let err = ShapeInference::infer_reduce_shape(
+      &vec![operand.clone(), init.clone()], &vec![0],
+      &scalar_program(3, &PrimitiveType::F32)).unwrap_err();
+    assert_eq!(err, "Reduction function must take 2 parameters, but takes 3 parameter(s).");
+
+    let window = ShapeInference::infer_window_from_dimensions(
+      &vec![2, 2, 1], &vec![2, 2, 1], &vec![], &vec![], &vec![], None).unwrap();
+    let result = ShapeInference::infer_reduce_window_shape(
+      &operand, &init, &window, &add).unwrap();
+    assert_eq!(result, f32_shape(vec![2, 2, 6]));
+  }
+
+  #[test]
+  fn test_map_sort_and_iota() {
+    let operand = f32_shape(vec![3, 4]);
+    let add = scalar_program(2, &PrimitiveType::F32);
+    let result = ShapeInference::infer_map_shape(
+      &vec![operand.clone(), operand.clone()], &add, &vec![0, 1]).unwrap();
+    assert_eq!(result, operand);
+    let err = ShapeInference::infer_map_shape(
+      &vec![operand.clone()], &add, &vec![0, 1]).unwrap_err();
+    assert!(err.starts_with("Map applied function arity must match"), "{}", err);
+
+    let values = ShapeUtil::make_shape(&PrimitiveType::S32, vec![3, 4]);
+    let sorted = ShapeInference::infer_sort_shape(
+      &vec![operand.clone(), values.clone()], 1).unwrap();
+    assert!(sorted.is_tuple());
+    assert!(ShapeInference::infer_sort_shape(&vec![operand.clone()], 2).is_err());
+
+    assert_eq!(ShapeInference::infer_iota_shape(&operand, 1), Ok(operand.clone()));
+    assert!(ShapeInference::infer_iota_shape(&operand, 2).is_err());
+  }
+
+  #[test]
+  fn test_collectives() {
+    let operand = f32_shape(vec![8, 4]);
+    assert_eq!(ShapeInference::infer_all_to_all_shape(&operand, 0, 1, 4),
+      Ok(f32_shape(vec![2, 16])));
+    let err = ShapeInference::infer_all_to_all_shape(&operand, 1, 0, 3).unwrap_err();
+    assert_eq!(err, "AllToAll split dimension size 4 must be dividable by split_count 3.");
+    assert_eq!(ShapeInference::infer_all_gather_shape(&vec![operand.clone()], 1, 2),
+      Ok(f32_shape(vec![8, 8])));
+    assert_eq!(ShapeInference::infer_reduce_scatter_shape(&vec![operand.clone()], 0, 4),
+      Ok(f32_shape(vec![2, 4])));
+
+    let state = ShapeUtil::make_shape(&PrimitiveType::U64, vec![2]);
+    let result = ShapeInference::infer_rng_bit_generator_shape(
+      &state, &RandomAlgorithm::ThreeFry, &ShapeUtil::make_shape(&PrimitiveType::S32, vec![5]))
+      .unwrap();
+    assert_eq!(result.tuple_shapes(1).element_type(), PrimitiveType::U32);
+  }
+
+  #[test]
+  fn test_dot_and_convolution() {
+    let mut dnums = DotDimensionNumbers::default();
+    dnums.add_lhs_contracting_dimensions(1);
+    dnums.add_rhs_contracting_dimensions(0);
+    let result = ShapeInference::infer_dot_op_shape(
+      &f32_shape(vec![2, 3]), &f32_shape(vec![3, 5]), &dnums, None, &vec![]).unwrap();
+    assert_eq!(result, f32_shape(vec![2, 5]));
+    let err = ShapeInference::infer_dot_op_shape(
+      &f32_shape(vec![2, 3]), &f32_shape(vec![4, 5]), &dnums, None, &vec![]).unwrap_err();
+    assert!(err.ends_with("Contracting dimension sizes are not compatible."), "{}", err);
+
+    let mut conv_dnums = ConvolutionDimensionNumbers::new();
+    conv_dnums.set_input_batch_dimension(0);
+    conv_dnums.set_input_feature_dimension(1);
+    conv_dnums.add_input_spatial_dimensions(2);
+    conv_dnums.add_input_spatial_dimensions(3);
+    conv_dnums.set_kernel_output_feature_dimension(0);
+    conv_dnums.set_kernel_input_feature_dimension(1);
+    conv_dnums.add_kernel_spatial_dimensions(2);
+    conv_dnums.add_kernel_spatial_dimensions(3);
+    conv_dnums.set_output_batch_dimension(0);
+    conv_dnums.set_output_feature_dimension(1);
+    conv_dnums.add_output_spatial_dimensions(2);
+    conv_dnums.add_output_spatial_dimensions(3);
+    let window = ShapeInference::infer_window_from_dimensions(
+      &vec![3, 3], &vec![1, 1], &vec![(1, 1), (0, 0)], &vec![], &vec![], None).unwrap();
+    let result = ShapeInference::infer_convolve_shape(
+      &f32_shape(vec![10, 4, 8, 8]), &f32_shape(vec![16, 4, 3, 3]), 1, 1, &window,
+      &conv_dnums, None).unwrap();
+    assert_eq!(result, f32_shape(vec![10, 16, 8, 6]));
+  }
+
+  #[test]
+  fn test_gather_and_scatter() {
+    let mut gather_dnums = GatherDimensionNumbers::new();
+    gather_dnums.add_offset_dims(1);
+    gather_dnums.add_collapsed_slice_dims(0);
+    gather_dnums.add_start_index_map(0);
+    gather_dnums.set_index_vector_dim(1);
+    let operand = f32_shape(vec![5, 7]);
+    let indices = ShapeUtil::make_shape(&PrimitiveType::S32, vec![3, 1]);
+    let result = ShapeInference::infer_gather_shape(
+      &operand, &indices, &gather_dnums, &vec![1, 7]).unwrap();
+    assert_eq!(result, f32_shape(vec![3, 7]));
+    assert!(ShapeInference::infer_gather_shape(
+      &operand, &indices, &gather_dnums, &vec![2, 7]).is_err());
+
+    let mut scatter_dnums = ScatterDimensionNummbers::new();
+    scatter_dnums.add_update_window_dims(1);
+    scatter_dnums.add_inserted_window_dims(0);
+    scatter_dnums.add_scatter_dims_to_operand_dims(0);
+    scatter_dnums.set_index_vector_dim(1);
+    let updates = f32_shape(vec![3, 7]);
+    let add = scalar_program(2, &PrimitiveType::F32);
+    let result = ShapeInference::infer_scatter_shape(
+      &vec![operand.clone(), indices.clone(), updates], &add, &scatter_dnums).unwrap();
+    assert_eq!(result, operand);
+    let err = ShapeInference::infer_scatter_shape(
+      &vec![operand.clone(), indices, f32_shape(vec![4, 7])], &add, &scatter_dnums)
+      .unwrap_err();
+    assert!(err.starts_with("Bounds of the scatter dimensions of updates"), "{}", err);
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,15 @@
+#![allow(dead_code)]
+
+// Helpers shared by the tests which parse HLO modules. They are only compiled
+// for tests, and for the tests of the crates depending on this one through
+// the test_utils feature.
+
+use hlo::{hlo_module::HloModule, hlo_module_config::HloModuleConfig};
+
+use crate::hlo_parser::parse_and_return_unverified_module;
+
+pub use hlo::test_utils::*;
+
+pub fn parse_module(text: &str) -> HloModule {
+  parse_and_return_unverified_module(text.to_string(), &HloModuleConfig::new_default()).unwrap()
+}
@@ -5,12 +5,12 @@ use std::collections::{HashMap, HashSet};
 use common::{shape::Shape, shape_util::ShapeUtil};
 use hlo::{
   hlo_computation::HloComputation,
-  hlo_instruction::HloInstruction,
-  hlo_module::HloModule,
+  hlo_instruction::{HloInstruction, InstructionId},
+  hlo_module::{ComputationId, HloModule},
   hlo_opcode::HloOpcode
 };
 
-use crate::{hlo_dce::HloDCE, hlo_pass_pipeline::HloPassPipeline};
+use crate::hlo_pass_pipeline::HloPassPipeline;
 
 // A pass that tries to lift concatenation out of a while loop, and replace
 // piece-wise subcomputations in the loop body with one on the concatenated
@@ -64,25 +64,27 @@ impl WhileLoopConcatCodeMotion {
 
   pub fn run(
     &mut self,
-    module: &HloModule,
+    module: &mut HloModule,
     execution_threads: &HashSet<String>) -> Result<bool, String>
   {
     let mut changed = false;
     for comp in
       module.make_computation_post_order(execution_threads, false) {
-      for hlo in comp.mutable_make_instruction_post_order() {
-        if hlo.opcode() == HloOpcode::While {
-          let loop_changed =
-            run_on_loop(hlo, self.min_operand_count_to_optimize);
-          changed |= loop_changed.unwrap();
+      let post_order =
+        module.computation(comp).make_instruction_post_order();
+      for hlo in post_order {
+        if module.instruction(comp, hlo).opcode() == HloOpcode::While {
+          let loop_changed = run_on_loop(
+            module, comp, hlo, self.min_operand_count_to_optimize)?;
+          changed |= loop_changed;
         }
       }
     }
     if changed {
-      let mut pipeline = HloPassPipeline::new(
+      let _pipeline = HloPassPipeline::new(
         "loop-concat-motion-cleanup".to_string(), None);
-      // TODO
-      pipeline.add_pass::<HloDCE>(None);
+      // TODO: add TupleSimplifier and HloDCE and run the pipeline once they
+      // implement HloPassInterface.
     }
     Ok(changed)
   }
@@ -190,8 +192,9 @@ impl ConcatGroup {
         // TODO
       }
     }
+    let operands: Vec<&HloInstruction> = input_elements.iter().collect();
     let instruction = HloInstruction::create_concatenate(
-      &self.get_concat_shape(), input_elements, self.concat_dim);
+      &self.get_concat_shape(), &operands, self.concat_dim);
     comp.add_instruction(instruction, "".to_string());
   }
 }
@@ -319,6 +322,7 @@ impl ConcatGroups {
 // group in combined_operand_group. (Only required for kReshape.)
 #[allow(unused_assignments)]
 fn get_operand_concat_dim(
+  computation: &HloComputation,
   hlo: &HloInstruction,
   operand_index: usize,
   hlo_concat_dim: i64,
@@ -330,7 +334,8 @@ fn get_operand_concat_dim(
   }
   let mut operand_concat_dim = -1;
   let mut operand_inserted_concat_dim = false;
-  let mut operand_shape = hlo.operand(operand_index).shape();
+  let mut operand_shape =
+    computation.operand(hlo.id(), operand_index).shape();
   if combined_operand_group.is_some() {
     operand_shape = combined_operand_group.unwrap().elements.last().unwrap().shape();
   }
@@ -436,11 +441,13 @@ fn group_hlos_for_concat(
   unimplemented!()
 }
 
-fn tuple_elements_used_in_cond(loop_: &HloInstruction) -> Vec<bool> {
+fn tuple_elements_used_in_cond(
+  module: &HloModule, loop_: &HloInstruction) -> Vec<bool>
+{
   let mut result = vec![false; loop_.shape().tuple_shapes_size()];
-  for user in
-    loop_.while_condition().parameter_instruction(0).unwrap().users()
-  {
+  let cond = module.computation(loop_.while_condition());
+  for user_id in cond.parameter_instruction(0).unwrap().users() {
+    let user = cond.instruction(*user_id);
     if user.opcode() != HloOpcode::GetTupleElement {
       result.fill(true);
       return result;
@@ -462,14 +469,13 @@ fn add_copies_to_root(
 
 fn remove_copies_from_root(body: &mut HloComputation) -> Result<(), String>
 {
-  let root = body.mutable_root_instruction();
-  assert_eq!(root.opcode(), HloOpcode::Tuple);
-  for i in 0..root.operand_count() {
-    let copy = root.mutable_operand(i);
-    if copy.unwrap().opcode() == HloOpcode::Copy {
-      //let result =
-        //root.replace_operand_with(i as i64, 
-          //copy.unwrap().mutable_operand(0).unwrap().clone());
+  let root = body.root_instruction_id();
+  assert_eq!(body.instruction(root).opcode(), HloOpcode::Tuple);
+  for i in 0..body.instruction(root).operand_count() {
+    let copy = body.operand(root, i);
+    if copy.opcode() == HloOpcode::Copy {
+      let copy_operand = copy.operand(0);
+      body.replace_operand_with(root, i, copy_operand)?;
     }
   }
   Ok(())
@@ -484,10 +490,12 @@ fn rewrite_loop_with_concat_groups(
 }
 
 fn run_on_loop(
-  loop_: &mut HloInstruction,
+  module: &mut HloModule,
+  comp: ComputationId,
+  loop_: InstructionId,
   _min_operand_count_to_optimize: i64) -> Result<bool, String>
 {
-  let body = loop_.mutable_while_body();
+  let body = module.computation(module.instruction(comp, loop_).while_body());
   let param = body.parameter_instruction(0).unwrap();
   let root = body.root_instruction();
   if !param.shape().is_tuple() || root.opcode() != HloOpcode::Tuple {
@@ -2,7 +2,7 @@
 
 use std::collections::HashSet;
 
-use hlo::{hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_module::HloModule, hlo_opcode::HloOpcode};
+use hlo::{hlo_computation::HloComputation, hlo_instruction::{HloInstruction, InstructionId}, hlo_module::HloModule, hlo_opcode::HloOpcode};
 
 // Sinks while loop invariant values that happen to be constants into the while
 // loop body and conditional. This is probably not a win in isolation but may
@@ -88,25 +88,21 @@ impl WhileLoopConstantSinking {
 // This utility helps us replace an instruction in the while body with a
 // constant while still keeping it trivially loop invariant.
 fn replace_uses_while_keeping_loop_invariance(
-  old_instr: &HloInstruction,
-  new_instr: &HloInstruction,
-  while_body_root: &HloInstruction,
+  computation: &mut HloComputation,
+  old_instr: InstructionId,
+  new_instr: InstructionId,
+  while_body_root: InstructionId,
   tuple_index: usize) -> Result<(), String>
 {
-  assert_eq!(while_body_root.opcode(), HloOpcode::Tuple);
+  assert_eq!(computation.instruction(while_body_root).opcode(), HloOpcode::Tuple);
 
-  let mut users: Vec<HloInstruction> = vec![];
-  users.reserve(old_instr.user_count());
-  users.clone_from(old_instr.users());
-
-  for user in &mut users {
-    for i in 0..user.operand_count() {
-      if user.operand(i) == old_instr && !(user == while_body_root && i == tuple_index) {
-        let result =
-          user.replace_operand_with(i as i64, new_instr.clone());
-        if result.is_err() {
-          return Err(result.err().unwrap());
-        }
+  let users = computation.instruction(old_instr).users().clone();
+  for user in users {
+    for i in 0..computation.instruction(user).operand_count() {
+      if computation.instruction(user).operand(i) == old_instr &&
+        !(user == while_body_root && i == tuple_index)
+      {
+        computation.replace_operand_with(user, i, new_instr)?;
       }
     }
   }
@@ -2,7 +2,7 @@
 
 use std::collections::{HashMap, HashSet};
 
-use hlo::{hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_module::HloModule, hlo_opcode::HloOpcode};
+use hlo::{hlo_computation::HloComputation, hlo_instruction::{HloInstruction, InstructionId}, hlo_module::{ComputationId, HloModule}, hlo_opcode::HloOpcode};
 
 // Sinks while loop invariant values that happen to be fusibles into the while
 // loop body and conditional. This is probably not a win in isolation but may
@@ -29,7 +29,7 @@ use hlo::{hlo_computation::HloComputation, hlo_instruction::HloInstruction, hlo_
 // `v`.
 //
 pub struct WhileLoopFusibleSinking {
-  call_counts: HashMap<HloComputation, i64>
+  call_counts: HashMap<ComputationId, i64>
 }
 
 impl WhileLoopFusibleSinking {
@@ -55,15 +55,8 @@ impl WhileLoopFusibleSinking {
     }
     
     for while_instr in &while_instrs {
-      let mut while_body_val =
-        self.call_counts.get(while_instr.while_body()).unwrap().clone();
-      while_body_val += 1;
-      self.call_counts.insert(while_instr.while_body().clone(), while_body_val);
-
-      let mut while_cond_val =
-        self.call_counts.get(while_instr.while_condition()).unwrap().clone();
-      while_cond_val += 1;
-      self.call_counts.insert(while_instr.while_condition().clone(), while_cond_val);
+      *self.call_counts.entry(while_instr.while_body()).or_insert(0) += 1;
+      *self.call_counts.entry(while_instr.while_condition()).or_insert(0) += 1;
     }
 
     for while_instr in &while_instrs {
@@ -87,13 +80,15 @@ impl WhileLoopFusibleSinking {
   // Creates a loop fusion instruction containing the computation to move into
   // the while loop to avoid conflicts with actual instruction fusion, the loop
   // fusion will be defused.
-  fn is_sinkable_fusion(&self, while_operand: &HloInstruction) -> bool {
+  fn is_sinkable_fusion(
+    &self, computation: &HloComputation, while_operand: InstructionId) -> bool
+  {
     let mut worklist = vec![];
     let mut visited: HashSet<i64> = HashSet::new();
-    worklist.push(while_operand.clone());
+    worklist.push(while_operand);
 
     while !worklist.is_empty() {
-      let to_process = worklist.pop().unwrap();
+      let to_process = computation.instruction(worklist.pop().unwrap());
       if !to_process.is_fusible() {
         return false;
       }
@@ -105,12 +100,12 @@ impl WhileLoopFusibleSinking {
         }
         continue;
       }
-      if is_purely_expanding(&to_process) {
+      if is_purely_expanding(to_process) {
         continue;
       }
-      if is_fusion_candidate(&to_process) {
+      if is_fusion_candidate(to_process) {
         for op in to_process.operands() {
-          worklist.push(op.clone());
+          worklist.push(*op);
         }
         continue;
       }
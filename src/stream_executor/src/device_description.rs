@@ -116,7 +116,39 @@ pub struct DeviceDescription {
 }
 
 impl DeviceDescription {
-  pub fn new() {}
+  // Returns a description with every string field set to "<undefined>" and
+  // every numeric field unknown.
+  pub fn new() -> Self {
+    let undefined = "<undefined>".to_string();
+    DeviceDescription {
+      device_vendor: undefined.clone(),
+      platform_version: undefined.clone(),
+      driver_version: undefined.clone(),
+      runtime_version: undefined.clone(),
+      pci_bus_id: undefined.clone(),
+      name: undefined.clone(),
+      model_str: undefined,
+      thread_dim_limit: ThreadDim::new(1, 1, 1),
+      block_dim_limit: BlockDim::new(1, 1, 1),
+      threads_per_core_limit: -1,
+      threads_per_block_limit: -1,
+      threads_per_warp: -1,
+      registers_per_core_limit: -1,
+      registers_per_block_limit: -1,
+      device_address_bits: -1,
+      device_memory_size: -1,
+      l2_cache_size: -1,
+      memory_bandwidth: -1,
+      shared_memory_per_core: -1,
+      shared_memory_per_block: -1,
+      shared_memory_per_block_optin: -1,
+      clock_rate_ghz: -1.0,
+      numa_node: -1,
+      core_count: -1,
+      fpus_per_core: -1,
+      ecc_enabled: false,
+    }
+  }
 
   // Returns the platform being run on.
   pub fn platform_version(&self) -> String {
@@ -265,6 +297,10 @@ pub struct DeviceDescriptionBuilder {
 }
 
 impl DeviceDescriptionBuilder {
+  pub fn new() -> Self {
+    DeviceDescriptionBuilder { device_description: DeviceDescription::new() }
+  }
+
   pub fn set_gpu_compute_capability() {}
 
   pub fn set_block_dim_limit_x(&mut self, limit: u64) {
@@ -386,7 +422,10 @@ impl DeviceDescriptionBuilder {
     self.device_description.ecc_enabled = value;
   }
 
-  pub fn build() {}
+  // Consumes the builder and returns the accumulated description.
+  pub fn build(self) -> DeviceDescription {
+    self.device_description
+  }
 
   pub fn build_object(&self) -> &DeviceDescription {
     &self.device_description
@@ -412,4 +451,20 @@ mod tests {
     assert_eq!(CudaComputeCapability::hopper().is_at_least_major_minor(9, 0), true);
     assert_eq!(CudaComputeCapability::blackwell().is_at_least_major_minor(10, 0), true);
   }
+
+  #[test]
+  fn test_builder() {
+    let mut builder = DeviceDescriptionBuilder::new();
+    assert_eq!(builder.build_object().core_count(), -1);
+    builder.set_name("device".to_string());
+    builder.set_core_count(80);
+    builder.set_clock_rate_ghz(1.5);
+    builder.set_memory_bandwidth(900_000_000_000);
+    let desc = builder.build();
+    assert_eq!(desc.name(), "device".to_string());
+    assert_eq!(desc.core_count(), 80);
+    assert_eq!(desc.clock_rate_ghz(), 1.5);
+    assert_eq!(desc.memory_bandwidth(), 900_000_000_000);
+    assert_eq!(desc.model_str(), "<undefined>".to_string());
+  }
 }
\ No newline at end of file